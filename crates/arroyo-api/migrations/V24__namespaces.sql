@@ -0,0 +1,25 @@
+CREATE TABLE namespaces (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+    max_parallelism INT,
+    max_task_slots INT,
+    max_state_bytes BIGINT,
+
+    UNIQUE(organization_id, name)
+);
+
+ALTER TABLE pipelines
+ADD COLUMN namespace TEXT NOT NULL DEFAULT 'default';
+
+ALTER TABLE connection_profiles
+ADD COLUMN namespace TEXT NOT NULL DEFAULT 'default';
+
+ALTER TABLE connection_tables
+ADD COLUMN namespace TEXT NOT NULL DEFAULT 'default';
+
+ALTER TABLE udfs
+ADD COLUMN namespace TEXT NOT NULL DEFAULT 'default';
@@ -0,0 +1,7 @@
+-- API keys now carry a role and an optional scope restricting them to a set of pipelines or
+-- namespaces. New keys are stored as argon2 hashes; keys created before this migration hold
+-- plaintext secrets, can no longer be used to authenticate and must be rotated (the API logs
+-- their ids at startup)
+ALTER TABLE api_keys ADD COLUMN role TEXT NOT NULL DEFAULT 'admin';
+ALTER TABLE api_keys ADD COLUMN scope_pipelines JSONB;
+ALTER TABLE api_keys ADD COLUMN scope_namespaces JSONB;
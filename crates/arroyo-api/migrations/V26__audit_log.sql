@@ -0,0 +1,14 @@
+CREATE TABLE audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    organization_id VARCHAR NOT NULL,
+    user_id VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    action TEXT NOT NULL,
+    resource_type TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    diff JSONB
+);
+
+CREATE INDEX audit_log_organization_id_idx ON audit_log (organization_id, id);
+CREATE INDEX audit_log_resource_idx ON audit_log (resource_id);
@@ -0,0 +1,28 @@
+CREATE TABLE pipeline_schedules (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    pipeline_id BIGINT NOT NULL UNIQUE REFERENCES pipelines(id) ON DELETE CASCADE,
+    cron TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    next_run_at TIMESTAMPTZ
+);
+
+-- runs reference the pipeline rather than the schedule so that history is retained if the
+-- schedule is removed or replaced
+CREATE TABLE scheduled_runs (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    pipeline_id BIGINT NOT NULL REFERENCES pipelines(id) ON DELETE CASCADE,
+    job_id VARCHAR NOT NULL,
+    run_id BIGINT NOT NULL,
+    state TEXT NOT NULL,
+    scheduled_for TIMESTAMPTZ NOT NULL,
+    started_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    finished_at TIMESTAMPTZ,
+    failure_message TEXT
+);
+
+CREATE INDEX scheduled_runs_pipeline_id_idx ON scheduled_runs (pipeline_id, id);
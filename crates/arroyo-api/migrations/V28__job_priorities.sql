@@ -0,0 +1,7 @@
+ALTER TABLE job_configs
+ADD COLUMN priority INT NOT NULL DEFAULT 0;
+
+-- set by the controller when a job is stopped to make room for a higher-priority job, to the
+-- number of task slots it needs to be resumed
+ALTER TABLE job_configs
+ADD COLUMN preempted_slots INT;
@@ -0,0 +1,2 @@
+ALTER TABLE job_configs
+ADD COLUMN worker_pod JSONB;
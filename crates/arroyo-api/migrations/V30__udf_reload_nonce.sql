@@ -0,0 +1,2 @@
+ALTER TABLE job_configs
+ADD COLUMN udf_reload_nonce INT NOT NULL DEFAULT 0;
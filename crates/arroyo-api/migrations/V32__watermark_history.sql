@@ -0,0 +1,11 @@
+-- per-operator watermarks, sampled periodically by the controller while the job is running
+CREATE TABLE watermark_history (
+    id BIGSERIAL PRIMARY KEY,
+    job_id VARCHAR(8) NOT NULL REFERENCES job_configs(id) ON DELETE CASCADE,
+    operator_id TEXT NOT NULL,
+    time TIMESTAMPTZ NOT NULL,
+    watermark TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX watermark_history_job_id_idx ON watermark_history (job_id, time);
+CREATE INDEX watermark_history_time_idx ON watermark_history (time);
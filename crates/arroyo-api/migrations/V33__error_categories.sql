@@ -0,0 +1,2 @@
+ALTER TABLE job_log_messages ADD COLUMN error_category TEXT;
+ALTER TABLE job_statuses ADD COLUMN failure_category TEXT;
@@ -0,0 +1,22 @@
+CREATE TABLE udf_versions (
+    id BIGSERIAL PRIMARY KEY,
+    udf_pub_id VARCHAR NOT NULL REFERENCES udfs(pub_id) ON DELETE CASCADE,
+    version INT NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT,
+    dylib_url TEXT NOT NULL,
+
+    UNIQUE(udf_pub_id, version)
+);
+
+ALTER TABLE udfs
+ADD COLUMN version INT NOT NULL DEFAULT 1;
+
+INSERT INTO udf_versions (udf_pub_id, version, created_by, created_at, definition, description, dylib_url)
+SELECT pub_id, 1, created_by, updated_at, definition, description, dylib_url
+FROM udfs;
+
+ALTER TABLE pipelines
+ADD COLUMN udf_versions JSONB NOT NULL DEFAULT '{}';
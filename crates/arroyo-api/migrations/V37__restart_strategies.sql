@@ -0,0 +1,16 @@
+ALTER TABLE job_configs
+ADD COLUMN restart_strategy JSONB;
+
+-- restarts of jobs after failures, recorded by the controller
+CREATE TABLE job_restarts (
+    id BIGSERIAL PRIMARY KEY,
+    job_id VARCHAR(8) NOT NULL REFERENCES job_configs(id) ON DELETE CASCADE,
+    run_id BIGINT NOT NULL,
+    time TIMESTAMPTZ NOT NULL,
+    attempt INT NOT NULL,
+    delay_micros BIGINT NOT NULL,
+    reason TEXT NOT NULL
+);
+
+CREATE INDEX job_restarts_job_id_idx ON job_restarts (job_id, time);
+CREATE INDEX job_restarts_time_idx ON job_restarts (time);
@@ -0,0 +1,39 @@
+-- shared tables and views that pipelines can reference by name
+CREATE TABLE catalog_tables (
+    pub_id VARCHAR PRIMARY KEY,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT,
+    namespace TEXT NOT NULL DEFAULT 'default',
+    version INT NOT NULL DEFAULT 1,
+
+    UNIQUE(organization_id, name)
+);
+
+CREATE TABLE catalog_table_versions (
+    id BIGSERIAL PRIMARY KEY,
+    catalog_table_pub_id VARCHAR NOT NULL REFERENCES catalog_tables(pub_id) ON DELETE CASCADE,
+    version INT NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT,
+
+    UNIQUE(catalog_table_pub_id, version)
+);
+
+-- the catalog tables each pipeline was planned with, and the versions it used
+CREATE TABLE catalog_table_pipelines (
+    id BIGSERIAL PRIMARY KEY,
+    catalog_table_pub_id VARCHAR NOT NULL REFERENCES catalog_tables(pub_id) ON DELETE CASCADE,
+    pipeline_id BIGINT NOT NULL REFERENCES pipelines(id) ON DELETE CASCADE,
+    version INT NOT NULL,
+
+    UNIQUE(catalog_table_pub_id, pipeline_id)
+);
+
+CREATE INDEX catalog_table_pipelines_pipeline_id_idx ON catalog_table_pipelines (pipeline_id);
@@ -0,0 +1,28 @@
+-- pipeline definitions with declared parameters, which are instantiated into pipelines
+CREATE TABLE pipeline_templates (
+    pub_id VARCHAR PRIMARY KEY,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+    query TEXT NOT NULL,
+    description TEXT,
+    parameters JSONB NOT NULL,
+    udfs JSONB NOT NULL,
+    namespace TEXT NOT NULL DEFAULT 'default',
+
+    UNIQUE(organization_id, name)
+);
+
+-- the pipelines created from each template, and the parameters they were created with
+CREATE TABLE pipeline_template_instances (
+    id BIGSERIAL PRIMARY KEY,
+    template_pub_id VARCHAR NOT NULL REFERENCES pipeline_templates(pub_id) ON DELETE CASCADE,
+    pipeline_id BIGINT NOT NULL REFERENCES pipelines(id) ON DELETE CASCADE,
+    parameters JSONB NOT NULL,
+
+    UNIQUE(pipeline_id)
+);
+
+CREATE INDEX pipeline_template_instances_template_pub_id_idx ON pipeline_template_instances (template_pub_id);
@@ -0,0 +1,18 @@
+-- webhooks, Slack channels, and PagerDuty services that the controller alerts of job state
+-- changes
+CREATE TABLE notification_targets (
+    pub_id VARCHAR PRIMARY KEY,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+    config JSONB NOT NULL,
+    events JSONB NOT NULL,
+    -- if set, only events for this pipeline are sent to the target
+    pipeline_id BIGINT REFERENCES pipelines(id) ON DELETE CASCADE,
+    payload_template TEXT,
+
+    UNIQUE(organization_id, name)
+);
+
+CREATE INDEX notification_targets_organization_id_idx ON notification_targets (organization_id);
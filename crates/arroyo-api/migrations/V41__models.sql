@@ -0,0 +1,31 @@
+-- ONNX models that pipelines can call as SQL functions
+CREATE TABLE models (
+    pub_id VARCHAR PRIMARY KEY,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    namespace TEXT NOT NULL DEFAULT 'default',
+    version INT NOT NULL DEFAULT 1,
+    url TEXT NOT NULL,
+    inputs INT NOT NULL,
+    outputs INT NOT NULL,
+
+    UNIQUE(organization_id, name)
+);
+
+CREATE TABLE model_versions (
+    id BIGSERIAL PRIMARY KEY,
+    model_pub_id VARCHAR NOT NULL REFERENCES models(pub_id) ON DELETE CASCADE,
+    version INT NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    description TEXT,
+    url TEXT NOT NULL,
+    inputs INT NOT NULL,
+    outputs INT NOT NULL,
+
+    UNIQUE(model_pub_id, version)
+);
@@ -0,0 +1,15 @@
+-- per-operator metrics, sampled periodically by the controller while the job is running and
+-- rolled up into coarser resolutions; resolution_secs is the width of the bucket that each
+-- value covers
+CREATE TABLE metric_history (
+    id BIGSERIAL PRIMARY KEY,
+    job_id VARCHAR(8) NOT NULL REFERENCES job_configs(id) ON DELETE CASCADE,
+    operator_id TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    resolution_secs INTEGER NOT NULL,
+    time TIMESTAMPTZ NOT NULL,
+    value DOUBLE PRECISION NOT NULL
+);
+
+CREATE INDEX metric_history_job_id_idx ON metric_history (job_id, resolution_secs, time);
+CREATE INDEX metric_history_time_idx ON metric_history (resolution_secs, time);
@@ -1,13 +1,46 @@
 ----------- api keys -------------------
---! get_api_key
-SELECT user_id, organization_id
+--: DbApiKey (scope_pipelines?, scope_namespaces?)
+
+--! get_api_key : DbApiKey
+SELECT pub_id, user_id, organization_id, name, api_key, role, scope_pipelines, scope_namespaces, created_at
+FROM api_keys
+WHERE pub_id = :pub_id;
+
+--! get_api_keys : DbApiKey
+SELECT pub_id, user_id, organization_id, name, api_key, role, scope_pipelines, scope_namespaces, created_at
+FROM api_keys
+WHERE organization_id = :organization_id
+ORDER BY created_at;
+
+--! count_api_keys
+SELECT count(*) FROM api_keys;
+
+--! get_api_key_by_digest : DbApiKey
+SELECT pub_id, user_id, organization_id, name, api_key, role, scope_pipelines, scope_namespaces, created_at
 FROM api_keys
 WHERE api_key = :api_key;
 
+--! get_legacy_api_keys
+SELECT pub_id, api_key FROM api_keys
+WHERE api_key NOT LIKE '$argon2%' AND api_key NOT LIKE '$sha256$%';
+
+--! hash_legacy_api_key
+UPDATE api_keys
+SET api_key = :api_key
+WHERE pub_id = :pub_id;
+
+--! create_api_key(scope_pipelines?, scope_namespaces?)
+INSERT INTO api_keys (pub_id, user_id, organization_id, created_by, name, api_key, role, scope_pipelines, scope_namespaces)
+VALUES (:pub_id, :user_id, :organization_id, :created_by, :name, :api_key, :role, :scope_pipelines, :scope_namespaces);
+
+--! delete_api_key
+DELETE FROM api_keys
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
 ----------- connection profiles ----------------
 --! create_connection_profile
-INSERT INTO connection_profiles (pub_id, organization_id, created_by, name, type, config)
-VALUES (:pub_id, :organization_id, :created_by, :name, :type, :config);
+INSERT INTO connection_profiles (pub_id, organization_id, created_by, name, type, config, namespace)
+VALUES (:pub_id, :organization_id, :created_by, :name, :type, :config, :namespace);
 
 --! get_connection_profiles : DbConnectionProfile()
 SELECT
@@ -15,7 +48,8 @@ SELECT
     pub_id,
     name,
     type,
-    config
+    config,
+    namespace
 FROM connection_profiles
 WHERE connection_profiles.organization_id = :organization_id
 ORDER BY COALESCE(connection_profiles.updated_at, connection_profiles.created_at) DESC;
@@ -26,7 +60,8 @@ SELECT
     pub_id,
     name,
     type,
-    config
+    config,
+    namespace
 FROM connection_profiles
 WHERE connection_profiles.organization_id = :organization_id AND connection_profiles.pub_id = :pub_id
 ORDER BY COALESCE(connection_profiles.updated_at, connection_profiles.created_at) DESC;
@@ -39,10 +74,10 @@ WHERE organization_id = :organization_id AND pub_id = :pub_id;
 ------- connection tables -------------
 --! create_connection_table(profile_id?, schema?)
 INSERT INTO connection_tables
-(pub_id, organization_id, created_by, name, table_type, connector, connection_id, config, schema)
-VALUES (:pub_id, :organization_id, :created_by, :name, :table_type, :connector, :profile_id, :config, :schema);
+(pub_id, organization_id, created_by, name, table_type, connector, connection_id, config, schema, namespace)
+VALUES (:pub_id, :organization_id, :created_by, :name, :table_type, :connector, :profile_id, :config, :schema, :namespace);
 
---: DbConnectionTable (profile_id?, profile_name?, profile_type?, profile_config?, schema?)
+--: DbConnectionTable (profile_id?, profile_name?, profile_type?, profile_config?, profile_namespace?, schema?)
 
 --! get_connection_tables: DbConnectionTable
 SELECT connection_tables.id as id,
@@ -53,10 +88,12 @@ SELECT connection_tables.id as id,
     connection_tables.table_type as table_type,
     connection_tables.config as config,
     connection_tables.schema as schema,
+    connection_tables.namespace as namespace,
     connection_profiles.pub_id as profile_id,
     connection_profiles.name as profile_name,
     connection_profiles.type as profile_type,
     connection_profiles.config as profile_config,
+    connection_profiles.namespace as profile_namespace,
     (SELECT count(*) as pipeline_count
         FROM connection_table_pipelines
         WHERE connection_table_pipelines.connection_table_id = connection_tables.id
@@ -80,10 +117,12 @@ SELECT connection_tables.id as id,
     connection_tables.table_type as table_type,
     connection_tables.config as config,
     connection_tables.schema as schema,
+    connection_tables.namespace as namespace,
     connection_profiles.pub_id as profile_id,
     connection_profiles.name as profile_name,
     connection_profiles.type as profile_type,
     connection_profiles.config as profile_config,
+    connection_profiles.namespace as profile_namespace,
     (SELECT count(*) as pipeline_count
         FROM connection_table_pipelines
         WHERE connection_table_pipelines.connection_table_id = connection_tables.id
@@ -102,10 +141,12 @@ SELECT connection_tables.id as id,
     connection_tables.table_type as table_type,
     connection_tables.config as config,
     connection_tables.schema as schema,
+    connection_tables.namespace as namespace,
     connection_profiles.pub_id as profile_id,
     connection_profiles.name as profile_name,
     connection_profiles.type as profile_type,
     connection_profiles.config as profile_config,
+    connection_profiles.namespace as profile_namespace,
     (SELECT count(*) as pipeline_count
         FROM connection_table_pipelines
         WHERE connection_table_pipelines.connection_table_id = connection_tables.id
@@ -121,14 +162,14 @@ WHERE organization_id = :organization_id AND pub_id = :pub_id;
 
 ----------- pipelines -------------------
 
---: DbPipeline (state?, ttl_micros?)
+--: DbPipeline (state?, ttl_micros?, worker_pod?, freshness_slo?, restart_strategy?)
 
 --! create_pipeline(textual_repr?)
-INSERT INTO pipelines (pub_id, organization_id, created_by, name, type, textual_repr, udfs, program, proto_version)
-VALUES (:pub_id, :organization_id, :created_by, :name, :type, :textual_repr, :udfs, :program, :proto_version);
+INSERT INTO pipelines (pub_id, organization_id, created_by, name, type, textual_repr, udfs, udf_versions, program, proto_version, namespace)
+VALUES (:pub_id, :organization_id, :created_by, :name, :type, :textual_repr, :udfs, :udf_versions, :program, :proto_version, :namespace);
 
 --! get_pipelines : DbPipeline
-SELECT pipelines.id, pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros
+SELECT pipelines.id, pipelines.pub_id, name, type, textual_repr, udfs, udf_versions, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros, namespace, priority, worker_pod, freshness_slo, restart_strategy
 FROM pipelines
     INNER JOIN job_configs on pipelines.id = job_configs.pipeline_id
     LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
@@ -143,12 +184,21 @@ ORDER BY pipelines.created_at DESC
 LIMIT cast(:limit as integer);
 
 --! get_pipeline: DbPipeline
-SELECT pipelines.id, pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros
+SELECT pipelines.id, pipelines.pub_id, name, type, textual_repr, udfs, udf_versions, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros, namespace, priority, worker_pod, freshness_slo, restart_strategy
 FROM pipelines
     INNER JOIN job_configs on pipelines.id = job_configs.pipeline_id
     LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
 WHERE pipelines.pub_id = :pub_id AND pipelines.organization_id = :organization_id;
 
+--! get_result_pipelines
+SELECT pipelines.pub_id, pipelines.name, namespace, program, job_configs.id as job_id
+FROM pipelines
+    INNER JOIN job_configs on pipelines.id = job_configs.pipeline_id
+WHERE pipelines.organization_id = :organization_id
+    AND pipelines.pub_id IS NOT NULL
+    AND ttl_micros IS NULL
+ORDER BY pipelines.created_at DESC;
+
 --! get_pipeline_id
 SELECT id, pub_id
 FROM pipelines
@@ -158,6 +208,13 @@ WHERE pub_id = :pub_id AND organization_id = :organization_id;
 INSERT INTO connection_table_pipelines(pub_id, pipeline_id, connection_table_id)
 VALUES (:pub_id, :pipeline_id, :connection_table_id);
 
+--! get_pipeline_connection_tables
+SELECT connection_tables.pub_id as pub_id
+FROM connection_table_pipelines
+INNER JOIN connection_tables ON connection_tables.id = connection_table_pipelines.connection_table_id
+INNER JOIN pipelines ON pipelines.id = connection_table_pipelines.pipeline_id
+WHERE pipelines.pub_id = :pipeline_pub_id AND pipelines.organization_id = :organization_id;
+
 --! delete_pipeline
 DELETE FROM pipelines
 WHERE pub_id = :pub_id AND organization_id = :organization_id;
@@ -165,13 +222,17 @@ WHERE pub_id = :pub_id AND organization_id = :organization_id;
 
 ----------- jobs -----------------------
 
---! update_job(checkpoint_interval_micros?, stop?, parallelism_overrides?)
+--! update_job(checkpoint_interval_micros?, stop?, priority?, worker_pod?, freshness_slo?, restart_strategy?, parallelism_overrides?)
 UPDATE job_configs
 SET
    updated_at = :updated_at,
    updated_by = :updated_by,
 
    stop = COALESCE(:stop, stop),
+   priority = COALESCE(:priority, priority),
+   worker_pod = COALESCE(:worker_pod, worker_pod),
+   freshness_slo = COALESCE(:freshness_slo, freshness_slo),
+   restart_strategy = COALESCE(:restart_strategy, restart_strategy),
    checkpoint_interval_micros = COALESCE(:checkpoint_interval_micros, checkpoint_interval_micros),
    parallelism_overrides = COALESCE(:parallelism_overrides, parallelism_overrides)
 WHERE id = :job_id AND organization_id = :organization_id;
@@ -185,10 +246,23 @@ SET
    restart_mode = :mode
 WHERE id = :job_id AND organization_id = :organization_id;
 
---! create_job(ttl_micros?)
+--! update_pipeline_udfs
+UPDATE pipelines
+SET udfs = :udfs, udf_versions = :udf_versions, program = :program
+WHERE id = :pipeline_id;
+
+--! reload_job_udfs
+UPDATE job_configs
+SET
+   updated_at = :updated_at,
+   updated_by = :updated_by,
+   udf_reload_nonce = udf_reload_nonce + 1
+WHERE id = :job_id AND organization_id = :organization_id;
+
+--! create_job(ttl_micros?, worker_pod?, freshness_slo?, restart_strategy?)
 INSERT INTO job_configs
-(id, organization_id, pipeline_name, created_by, pipeline_id, checkpoint_interval_micros, ttl_micros)
-VALUES (:id, :organization_id, :pipeline_name, :created_by, :pipeline_id, :checkpoint_interval_micros, :ttl_micros);
+(id, organization_id, pipeline_name, created_by, pipeline_id, checkpoint_interval_micros, ttl_micros, priority, worker_pod, freshness_slo, restart_strategy, stop)
+VALUES (:id, :organization_id, :pipeline_name, :created_by, :pipeline_id, :checkpoint_interval_micros, :ttl_micros, :priority, :worker_pod, :freshness_slo, :restart_strategy, :stop);
 
 --! create_job_status
 INSERT INTO job_statuses (pub_id, id, organization_id) VALUES (:pub_id, :id, :organization_id);
@@ -201,24 +275,24 @@ FROM job_configs
 WHERE job_configs.organization_id = :organization_id AND ttl_micros IS NULL
 ORDER BY COALESCE(job_configs.updated_at, job_configs.created_at) DESC;
 
---! get_pipeline_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?)
-SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at
+--! get_pipeline_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, failure_category?, run_id?, preempted_slots?)
+SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, failure_category, run_id, checkpoint_interval_micros, job_configs.created_at, pipelines.pub_id as pipeline_pub_id, pipelines.namespace as namespace, preempted_slots
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
 WHERE job_configs.organization_id = :organization_id AND pipelines.pub_id = :pub_id
 ORDER BY job_configs.created_at DESC;
 
---! get_all_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?)
-SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at
+--! get_all_jobs : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, failure_category?, run_id?, preempted_slots?)
+SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, failure_category, run_id, checkpoint_interval_micros, job_configs.created_at, pipelines.pub_id as pipeline_pub_id, pipelines.namespace as namespace, preempted_slots
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
 WHERE job_configs.organization_id = :organization_id AND ttl_micros IS NULL
 ORDER BY job_configs.created_at DESC;
 
---! get_pipeline_job : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, run_id?)
-SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, run_id, checkpoint_interval_micros, job_configs.created_at
+--! get_pipeline_job : DbPipelineJob(start_time?, finish_time?, state?, tasks?, failure_message?, failure_category?, run_id?, preempted_slots?)
+SELECT job_configs.id, stop, start_time, finish_time, state, tasks, failure_message, failure_category, run_id, checkpoint_interval_micros, job_configs.created_at, pipelines.pub_id as pipeline_pub_id, pipelines.namespace as namespace, preempted_slots
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipelines.id = job_configs.pipeline_id
@@ -260,16 +334,37 @@ WHERE job_id = :job_id
     AND epoch = :epoch
     AND state != 'failed';
 
+--! get_last_checkpoint_epoch : (epoch?)
+SELECT MAX(epoch) as epoch FROM checkpoints
+WHERE job_id = :job_id;
+
+--! get_last_completed_checkpoint_epoch : (epoch?)
+SELECT MAX(epoch) as epoch FROM checkpoints
+WHERE job_id = :job_id
+    AND organization_id = :organization_id
+    AND state != 'inprogress'
+    AND state != 'failed';
+
+--! create_migrated_checkpoint
+INSERT INTO checkpoints
+(pub_id, organization_id, job_id, state_backend, epoch, min_epoch, start_time, finish_time, state, savepoint)
+VALUES (:pub_id, :organization_id, :job_id, :state_backend, :epoch, :epoch, :start_time, :start_time, 'ready', false);
+
+--! mark_checkpoint_savepoint
+UPDATE checkpoints
+SET savepoint = true
+WHERE job_id = :job_id AND organization_id = :organization_id AND epoch = :epoch;
+
 --! delete_pipeline_for_job
 DELETE FROM pipelines WHERE pipelines.id = (
     SELECT pipeline_id
     FROM job_configs
     WHERE job_configs.id = :job_id AND job_configs.organization_id = :organization_id);
 
---: DbLogMessage (operator_id?, task_index?)
+--: DbLogMessage (operator_id?, task_index?, error_category?)
 
 --! get_operator_errors : DbLogMessage
-SELECT jlm.pub_id, jlm.job_id, jlm.operator_id, jlm.task_index, jlm.created_at, jlm.log_level, jlm.message, jlm.details
+SELECT jlm.pub_id, jlm.job_id, jlm.operator_id, jlm.task_index, jlm.created_at, jlm.log_level, jlm.message, jlm.details, jlm.error_category
 FROM job_log_messages jlm
 JOIN job_configs ON job_configs.id = jlm.job_id
 WHERE job_configs.organization_id = :organization_id AND job_configs.id = :job_id
@@ -281,29 +376,397 @@ WHERE job_configs.organization_id = :organization_id AND job_configs.id = :job_i
 ORDER BY jlm.created_at DESC
 LIMIT cast(:limit as integer);
 
+--! get_watermark_history
+SELECT wh.operator_id, wh.time, wh.watermark
+FROM watermark_history wh
+JOIN job_configs ON job_configs.id = wh.job_id
+WHERE job_configs.organization_id = :organization_id AND job_configs.id = :job_id
+  AND wh.time >= :start_time AND wh.time <= :end_time
+ORDER BY wh.time;
+
+--! get_metric_history
+SELECT mh.operator_id, mh.metric, mh.time, mh.value
+FROM metric_history mh
+JOIN job_configs ON job_configs.id = mh.job_id
+WHERE job_configs.organization_id = :organization_id AND job_configs.id = :job_id
+  AND mh.resolution_secs = :resolution_secs
+  AND mh.time >= :start_time AND mh.time <= :end_time
+ORDER BY mh.time;
+
+--! get_job_restarts
+SELECT jr.run_id, jr.time, jr.attempt, jr.delay_micros, jr.reason
+FROM job_restarts jr
+JOIN job_configs ON job_configs.id = jr.job_id
+WHERE job_configs.organization_id = :organization_id AND job_configs.id = :job_id
+ORDER BY jr.time DESC
+LIMIT cast(:limit as integer);
+
 ----------- udfs -----------------------
 
 --: DbUdf (description?)
 
 --! create_udf
-INSERT INTO udfs (pub_id, organization_id, created_by, prefix, name, definition, description, dylib_url)
-VALUES (:pub_id, :organization_id, :created_by, :prefix, :name, :definition, :description, :dylib_url);
+INSERT INTO udfs (pub_id, organization_id, created_by, prefix, name, definition, description, dylib_url, namespace)
+VALUES (:pub_id, :organization_id, :created_by, :prefix, :name, :definition, :description, :dylib_url, :namespace);
+
+--! update_udf
+UPDATE udfs
+SET definition = :definition, description = :description, dylib_url = :dylib_url, version = :version, updated_at = :updated_at
+WHERE organization_id = :organization_id AND pub_id = :pub_id AND version = :previous_version;
 
 --! get_udf: DbUdf
-SELECT pub_id, prefix, name, definition, created_at, updated_at, description, dylib_url
+SELECT pub_id, prefix, name, definition, created_at, updated_at, description, dylib_url, namespace, version
 FROM udfs
 WHERE organization_id = :organization_id AND pub_id = :pub_id;
 
 --! get_udf_by_name: DbUdf
-SELECT pub_id, prefix, name, definition, created_at, updated_at, description, dylib_url
+SELECT pub_id, prefix, name, definition, created_at, updated_at, description, dylib_url, namespace, version
 FROM udfs
 WHERE organization_id = :organization_id AND name = :name;
 
 --! get_udfs: DbUdf
-SELECT pub_id, prefix, name, definition, created_at, updated_at, description, dylib_url
+SELECT pub_id, prefix, name, definition, created_at, updated_at, description, dylib_url, namespace, version
 FROM udfs
 WHERE organization_id = :organization_id;
 
 --! delete_udf
 DELETE FROM udfs
 WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+--: DbUdfVersion (description?)
+
+--! create_udf_version
+INSERT INTO udf_versions (udf_pub_id, version, created_by, definition, description, dylib_url)
+VALUES (:udf_pub_id, :version, :created_by, :definition, :description, :dylib_url);
+
+--! get_udf_version: DbUdfVersion
+SELECT udf_versions.version, udf_versions.created_at, udf_versions.definition, udf_versions.description, udf_versions.dylib_url
+FROM udf_versions
+    INNER JOIN udfs ON udfs.pub_id = udf_versions.udf_pub_id
+WHERE udfs.organization_id = :organization_id AND udfs.pub_id = :udf_pub_id AND udf_versions.version = :version;
+
+--! get_udf_versions: DbUdfVersion
+SELECT udf_versions.version, udf_versions.created_at, udf_versions.definition, udf_versions.description, udf_versions.dylib_url
+FROM udf_versions
+    INNER JOIN udfs ON udfs.pub_id = udf_versions.udf_pub_id
+WHERE udfs.organization_id = :organization_id AND udfs.pub_id = :udf_pub_id
+ORDER BY udf_versions.version DESC;
+
+----------- catalog --------------------
+
+--: DbCatalogTable (description?)
+
+--! create_catalog_table
+INSERT INTO catalog_tables (pub_id, organization_id, created_by, name, definition, description, namespace)
+VALUES (:pub_id, :organization_id, :created_by, :name, :definition, :description, :namespace);
+
+--! update_catalog_table
+UPDATE catalog_tables
+SET definition = :definition, description = :description, version = :version, updated_at = :updated_at
+WHERE organization_id = :organization_id AND pub_id = :pub_id AND version = :previous_version;
+
+--! get_catalog_table: DbCatalogTable
+SELECT pub_id, name, definition, description, namespace, version, created_at, updated_at,
+    (SELECT count(*) FROM catalog_table_pipelines
+        WHERE catalog_table_pipelines.catalog_table_pub_id = catalog_tables.pub_id
+    ) as pipeline_count
+FROM catalog_tables
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+--! get_catalog_tables: DbCatalogTable
+SELECT pub_id, name, definition, description, namespace, version, created_at, updated_at,
+    (SELECT count(*) FROM catalog_table_pipelines
+        WHERE catalog_table_pipelines.catalog_table_pub_id = catalog_tables.pub_id
+    ) as pipeline_count
+FROM catalog_tables
+WHERE organization_id = :organization_id
+ORDER BY name;
+
+--! delete_catalog_table
+DELETE FROM catalog_tables
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+--: DbCatalogTableVersion (description?)
+
+--! create_catalog_table_version
+INSERT INTO catalog_table_versions (catalog_table_pub_id, version, created_by, definition, description)
+VALUES (:catalog_table_pub_id, :version, :created_by, :definition, :description);
+
+--! get_catalog_table_version: DbCatalogTableVersion
+SELECT catalog_table_versions.version, catalog_table_versions.created_at, catalog_table_versions.definition, catalog_table_versions.description
+FROM catalog_table_versions
+    INNER JOIN catalog_tables ON catalog_tables.pub_id = catalog_table_versions.catalog_table_pub_id
+WHERE catalog_tables.organization_id = :organization_id AND catalog_tables.pub_id = :catalog_table_pub_id
+    AND catalog_table_versions.version = :version;
+
+--! get_catalog_table_versions: DbCatalogTableVersion
+SELECT catalog_table_versions.version, catalog_table_versions.created_at, catalog_table_versions.definition, catalog_table_versions.description
+FROM catalog_table_versions
+    INNER JOIN catalog_tables ON catalog_tables.pub_id = catalog_table_versions.catalog_table_pub_id
+WHERE catalog_tables.organization_id = :organization_id AND catalog_tables.pub_id = :catalog_table_pub_id
+ORDER BY catalog_table_versions.version DESC;
+
+--! add_pipeline_catalog_table
+INSERT INTO catalog_table_pipelines (catalog_table_pub_id, pipeline_id, version)
+SELECT pub_id, :pipeline_id, :version
+FROM catalog_tables
+WHERE organization_id = :organization_id AND name = :name;
+
+--! get_pipeline_catalog_versions
+SELECT catalog_tables.name, catalog_table_pipelines.version
+FROM catalog_table_pipelines
+    INNER JOIN catalog_tables ON catalog_tables.pub_id = catalog_table_pipelines.catalog_table_pub_id
+WHERE catalog_table_pipelines.pipeline_id = :pipeline_id;
+
+--! get_catalog_table_pipelines
+SELECT pipelines.pub_id, pipelines.name, catalog_table_pipelines.version
+FROM catalog_table_pipelines
+    INNER JOIN pipelines ON pipelines.id = catalog_table_pipelines.pipeline_id
+    INNER JOIN catalog_tables ON catalog_tables.pub_id = catalog_table_pipelines.catalog_table_pub_id
+WHERE catalog_tables.organization_id = :organization_id AND catalog_tables.pub_id = :catalog_table_pub_id
+ORDER BY pipelines.created_at DESC;
+
+----------- pipeline templates --------------------
+
+--: DbPipelineTemplate (description?)
+
+--! create_pipeline_template
+INSERT INTO pipeline_templates (pub_id, organization_id, created_by, name, query, description, parameters, udfs, namespace)
+VALUES (:pub_id, :organization_id, :created_by, :name, :query, :description, :parameters, :udfs, :namespace);
+
+--! get_pipeline_template: DbPipelineTemplate
+SELECT pub_id, name, query, description, parameters, udfs, namespace, created_at, updated_at,
+    (SELECT count(*) FROM pipeline_template_instances
+        WHERE pipeline_template_instances.template_pub_id = pipeline_templates.pub_id
+    ) as pipeline_count
+FROM pipeline_templates
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+--! get_pipeline_templates: DbPipelineTemplate
+SELECT pub_id, name, query, description, parameters, udfs, namespace, created_at, updated_at,
+    (SELECT count(*) FROM pipeline_template_instances
+        WHERE pipeline_template_instances.template_pub_id = pipeline_templates.pub_id
+    ) as pipeline_count
+FROM pipeline_templates
+WHERE organization_id = :organization_id
+ORDER BY name;
+
+--! delete_pipeline_template
+DELETE FROM pipeline_templates
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+--! add_pipeline_template_instance
+INSERT INTO pipeline_template_instances (template_pub_id, pipeline_id, parameters)
+VALUES (:template_pub_id, :pipeline_id, :parameters);
+
+--! get_pipeline_template_instances
+SELECT pipelines.pub_id, pipelines.name, pipeline_template_instances.parameters
+FROM pipeline_template_instances
+    INNER JOIN pipelines ON pipelines.id = pipeline_template_instances.pipeline_id
+    INNER JOIN pipeline_templates ON pipeline_templates.pub_id = pipeline_template_instances.template_pub_id
+WHERE pipeline_templates.organization_id = :organization_id AND pipeline_templates.pub_id = :template_pub_id
+ORDER BY pipelines.created_at DESC;
+
+----------- namespaces -----------------
+
+--: DbNamespace (max_parallelism?, max_task_slots?, max_state_bytes?)
+
+--! create_namespace(max_parallelism?, max_task_slots?, max_state_bytes?)
+INSERT INTO namespaces (pub_id, organization_id, created_by, name, max_parallelism, max_task_slots, max_state_bytes)
+VALUES (:pub_id, :organization_id, :created_by, :name, :max_parallelism, :max_task_slots, :max_state_bytes);
+
+--! get_namespaces: DbNamespace
+SELECT pub_id, name, created_at, max_parallelism, max_task_slots, max_state_bytes
+FROM namespaces
+WHERE organization_id = :organization_id
+ORDER BY name;
+
+--! get_namespace: DbNamespace
+SELECT pub_id, name, created_at, max_parallelism, max_task_slots, max_state_bytes
+FROM namespaces
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+--! get_namespace_by_name: DbNamespace
+SELECT pub_id, name, created_at, max_parallelism, max_task_slots, max_state_bytes
+FROM namespaces
+WHERE organization_id = :organization_id AND name = :name;
+
+--! delete_namespace
+DELETE FROM namespaces
+WHERE organization_id = :organization_id AND pub_id = :pub_id
+    AND NOT EXISTS (
+        SELECT 1 FROM pipelines
+        WHERE pipelines.organization_id = namespaces.organization_id
+            AND pipelines.namespace = namespaces.name
+    )
+    AND NOT EXISTS (
+        SELECT 1 FROM connection_profiles
+        WHERE connection_profiles.organization_id = namespaces.organization_id
+            AND connection_profiles.namespace = namespaces.name
+    )
+    AND NOT EXISTS (
+        SELECT 1 FROM connection_tables
+        WHERE connection_tables.organization_id = namespaces.organization_id
+            AND connection_tables.namespace = namespaces.name
+    )
+    AND NOT EXISTS (
+        SELECT 1 FROM udfs
+        WHERE udfs.organization_id = namespaces.organization_id
+            AND udfs.namespace = namespaces.name
+    )
+    AND NOT EXISTS (
+        SELECT 1 FROM catalog_tables
+        WHERE catalog_tables.organization_id = namespaces.organization_id
+            AND catalog_tables.namespace = namespaces.name
+    )
+    AND NOT EXISTS (
+        SELECT 1 FROM pipeline_templates
+        WHERE pipeline_templates.organization_id = namespaces.organization_id
+            AND pipeline_templates.namespace = namespaces.name
+    )
+    AND NOT EXISTS (
+        SELECT 1 FROM models
+        WHERE models.organization_id = namespaces.organization_id
+            AND models.namespace = namespaces.name
+    );
+
+----------- audit log -----------------
+
+--: DbAuditLogEntry (diff?)
+
+--! begin_transaction
+BEGIN;
+
+--! commit_transaction
+COMMIT;
+
+--! create_audit_log_entry(diff?)
+INSERT INTO audit_log (pub_id, organization_id, user_id, action, resource_type, resource_id, diff)
+VALUES (:pub_id, :organization_id, :user_id, :action, :resource_type, :resource_id, :diff);
+
+--! get_audit_log: DbAuditLogEntry
+SELECT pub_id, user_id, action, resource_type, resource_id, diff, created_at
+FROM audit_log
+WHERE organization_id = :organization_id
+    AND (resource_type = :resource_type OR :resource_type = '')
+    AND (resource_id = :resource_id OR :resource_id = '')
+    AND (id < (
+        SELECT id FROM audit_log
+        WHERE pub_id = :starting_after
+    ) OR :starting_after = '')
+ORDER BY id DESC
+LIMIT cast(:limit as integer);
+
+----------- pipeline schedules -----------------
+
+--: DbPipelineSchedule (next_run_at?)
+
+--! upsert_pipeline_schedule
+INSERT INTO pipeline_schedules (pub_id, organization_id, created_by, pipeline_id, cron, enabled)
+VALUES (:pub_id, :organization_id, :created_by, :pipeline_id, :cron, :enabled)
+ON CONFLICT (pipeline_id) DO UPDATE
+SET cron = excluded.cron, enabled = excluded.enabled, next_run_at = NULL;
+
+--! get_pipeline_schedule: DbPipelineSchedule
+SELECT pipeline_schedules.pub_id, cron, enabled, next_run_at, pipeline_schedules.created_at
+FROM pipeline_schedules
+    INNER JOIN pipelines ON pipelines.id = pipeline_schedules.pipeline_id
+WHERE pipelines.pub_id = :pipeline_pub_id AND pipeline_schedules.organization_id = :organization_id;
+
+--! delete_pipeline_schedule
+DELETE FROM pipeline_schedules
+WHERE organization_id = :organization_id AND pipeline_id = (
+    SELECT id FROM pipelines WHERE pub_id = :pipeline_pub_id
+);
+
+--: DbScheduledRun (finished_at?, failure_message?)
+
+--! get_scheduled_runs: DbScheduledRun
+SELECT scheduled_runs.pub_id, job_id, scheduled_runs.state, scheduled_for, started_at, finished_at, failure_message
+FROM scheduled_runs
+    INNER JOIN pipelines ON pipelines.id = scheduled_runs.pipeline_id
+WHERE pipelines.pub_id = :pipeline_pub_id
+    AND pipelines.organization_id = :organization_id
+    AND (scheduled_runs.id < (
+        SELECT id FROM scheduled_runs
+        WHERE pub_id = :starting_after
+    ) OR :starting_after = '')
+ORDER BY scheduled_runs.id DESC
+LIMIT cast(:limit as integer);
+
+----------- notification targets -----------------
+
+--: DbNotificationTarget (pipeline_pub_id?, payload_template?)
+
+--! create_notification_target (pipeline_id?, payload_template?)
+INSERT INTO notification_targets (pub_id, organization_id, created_by, name, config, events, pipeline_id, payload_template)
+VALUES (:pub_id, :organization_id, :created_by, :name, :config, :events, :pipeline_id, :payload_template);
+
+--! get_notification_target: DbNotificationTarget
+SELECT notification_targets.pub_id, notification_targets.name, config, events,
+    pipelines.pub_id as pipeline_pub_id, payload_template, notification_targets.created_at
+FROM notification_targets
+    LEFT JOIN pipelines ON pipelines.id = notification_targets.pipeline_id
+WHERE notification_targets.organization_id = :organization_id AND notification_targets.pub_id = :pub_id;
+
+--! get_notification_targets: DbNotificationTarget
+SELECT notification_targets.pub_id, notification_targets.name, config, events,
+    pipelines.pub_id as pipeline_pub_id, payload_template, notification_targets.created_at
+FROM notification_targets
+    LEFT JOIN pipelines ON pipelines.id = notification_targets.pipeline_id
+WHERE notification_targets.organization_id = :organization_id
+ORDER BY notification_targets.name;
+
+--! delete_notification_target
+DELETE FROM notification_targets
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+----------- models --------------------
+
+--: DbModel (description?)
+
+--! create_model
+INSERT INTO models (pub_id, organization_id, created_by, name, description, namespace, url, inputs, outputs)
+VALUES (:pub_id, :organization_id, :created_by, :name, :description, :namespace, :url, :inputs, :outputs);
+
+--! update_model
+UPDATE models
+SET description = :description, version = :version, url = :url, inputs = :inputs, outputs = :outputs, updated_at = :updated_at
+WHERE organization_id = :organization_id AND pub_id = :pub_id AND version = :previous_version;
+
+--! get_model: DbModel
+SELECT pub_id, name, description, namespace, version, url, inputs, outputs, created_at, updated_at
+FROM models
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+--! get_models: DbModel
+SELECT pub_id, name, description, namespace, version, url, inputs, outputs, created_at, updated_at
+FROM models
+WHERE organization_id = :organization_id
+ORDER BY name;
+
+--! delete_model
+DELETE FROM models
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+--: DbModelVersion (description?)
+
+--! create_model_version
+INSERT INTO model_versions (model_pub_id, version, created_by, description, url, inputs, outputs)
+VALUES (:model_pub_id, :version, :created_by, :description, :url, :inputs, :outputs);
+
+--! get_model_version: DbModelVersion
+SELECT model_versions.version, model_versions.created_at, model_versions.description,
+    model_versions.url, model_versions.inputs, model_versions.outputs
+FROM model_versions
+    INNER JOIN models ON models.pub_id = model_versions.model_pub_id
+WHERE models.organization_id = :organization_id AND models.pub_id = :model_pub_id
+    AND model_versions.version = :version;
+
+--! get_model_versions: DbModelVersion
+SELECT model_versions.version, model_versions.created_at, model_versions.description,
+    model_versions.url, model_versions.inputs, model_versions.outputs
+FROM model_versions
+    INNER JOIN models ON models.pub_id = model_versions.model_pub_id
+WHERE models.organization_id = :organization_id AND models.pub_id = :model_pub_id
+ORDER BY model_versions.version DESC;
@@ -0,0 +1,11 @@
+CREATE TABLE watermark_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    job_id TEXT NOT NULL,
+    operator_id TEXT NOT NULL,
+    time TIMESTAMP NOT NULL,
+    watermark TIMESTAMP NOT NULL,
+    FOREIGN KEY (job_id) references job_configs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX watermark_history_job_id_idx ON watermark_history (job_id, time);
+CREATE INDEX watermark_history_time_idx ON watermark_history (time);
@@ -0,0 +1,20 @@
+CREATE TABLE udf_versions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    udf_pub_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT,
+    dylib_url TEXT NOT NULL,
+    UNIQUE (udf_pub_id, version),
+    FOREIGN KEY (udf_pub_id) REFERENCES udfs(pub_id) ON DELETE CASCADE
+);
+
+ALTER TABLE udfs ADD COLUMN version INTEGER DEFAULT 1 NOT NULL;
+
+INSERT INTO udf_versions (udf_pub_id, version, created_by, created_at, definition, description, dylib_url)
+SELECT pub_id, 1, created_by, updated_at, definition, description, dylib_url
+FROM udfs;
+
+ALTER TABLE pipelines ADD COLUMN udf_versions TEXT DEFAULT '{}' NOT NULL;
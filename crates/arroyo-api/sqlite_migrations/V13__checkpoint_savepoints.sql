@@ -0,0 +1,2 @@
+-- the checkpoints that jobs were stopped at, which are kept by checkpoint retention
+ALTER TABLE checkpoints ADD COLUMN savepoint BOOLEAN NOT NULL DEFAULT false;
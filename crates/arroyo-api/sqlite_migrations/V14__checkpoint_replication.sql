@@ -0,0 +1,2 @@
+-- whether a checkpoint has been copied to the checkpoint replica, and can be restored from it
+ALTER TABLE checkpoints ADD COLUMN replicated BOOLEAN NOT NULL DEFAULT false;
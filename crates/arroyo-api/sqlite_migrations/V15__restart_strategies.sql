@@ -0,0 +1,15 @@
+ALTER TABLE job_configs ADD COLUMN restart_strategy TEXT;
+
+CREATE TABLE job_restarts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    job_id TEXT NOT NULL,
+    run_id BIGINT NOT NULL,
+    time TIMESTAMP NOT NULL,
+    attempt INTEGER NOT NULL,
+    delay_micros BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    FOREIGN KEY (job_id) references job_configs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX job_restarts_job_id_idx ON job_restarts (job_id, time);
+CREATE INDEX job_restarts_time_idx ON job_restarts (time);
@@ -0,0 +1,37 @@
+CREATE TABLE catalog_tables (
+    pub_id TEXT PRIMARY KEY,
+    organization_id TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT,
+    namespace TEXT DEFAULT 'default' NOT NULL,
+    version INTEGER DEFAULT 1 NOT NULL,
+    UNIQUE (organization_id, name)
+);
+
+CREATE TABLE catalog_table_versions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    catalog_table_pub_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT,
+    UNIQUE (catalog_table_pub_id, version),
+    FOREIGN KEY (catalog_table_pub_id) REFERENCES catalog_tables(pub_id) ON DELETE CASCADE
+);
+
+CREATE TABLE catalog_table_pipelines (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    catalog_table_pub_id TEXT NOT NULL,
+    pipeline_id INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    UNIQUE (catalog_table_pub_id, pipeline_id),
+    FOREIGN KEY (catalog_table_pub_id) REFERENCES catalog_tables(pub_id) ON DELETE CASCADE,
+    FOREIGN KEY (pipeline_id) REFERENCES pipelines(id) ON DELETE CASCADE
+);
+
+CREATE INDEX catalog_table_pipelines_pipeline_id_idx ON catalog_table_pipelines (pipeline_id);
@@ -0,0 +1,26 @@
+CREATE TABLE pipeline_templates (
+    pub_id TEXT PRIMARY KEY,
+    organization_id TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+    query TEXT NOT NULL,
+    description TEXT,
+    parameters TEXT NOT NULL,
+    udfs TEXT NOT NULL,
+    namespace TEXT DEFAULT 'default' NOT NULL,
+    UNIQUE (organization_id, name)
+);
+
+CREATE TABLE pipeline_template_instances (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    template_pub_id TEXT NOT NULL,
+    pipeline_id INTEGER NOT NULL,
+    parameters TEXT NOT NULL,
+    UNIQUE (pipeline_id),
+    FOREIGN KEY (template_pub_id) REFERENCES pipeline_templates(pub_id) ON DELETE CASCADE,
+    FOREIGN KEY (pipeline_id) REFERENCES pipelines(id) ON DELETE CASCADE
+);
+
+CREATE INDEX pipeline_template_instances_template_pub_id_idx ON pipeline_template_instances (template_pub_id);
@@ -0,0 +1,15 @@
+CREATE TABLE notification_targets (
+    pub_id TEXT PRIMARY KEY,
+    organization_id TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+    config TEXT NOT NULL,
+    events TEXT NOT NULL,
+    pipeline_id INTEGER,
+    payload_template TEXT,
+    UNIQUE (organization_id, name),
+    FOREIGN KEY (pipeline_id) REFERENCES pipelines(id) ON DELETE CASCADE
+);
+
+CREATE INDEX notification_targets_organization_id_idx ON notification_targets (organization_id);
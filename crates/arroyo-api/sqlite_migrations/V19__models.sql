@@ -0,0 +1,29 @@
+CREATE TABLE models (
+    pub_id TEXT PRIMARY KEY,
+    organization_id TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    namespace TEXT DEFAULT 'default' NOT NULL,
+    version INTEGER DEFAULT 1 NOT NULL,
+    url TEXT NOT NULL,
+    inputs INTEGER NOT NULL,
+    outputs INTEGER NOT NULL,
+    UNIQUE (organization_id, name)
+);
+
+CREATE TABLE model_versions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    model_pub_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    description TEXT,
+    url TEXT NOT NULL,
+    inputs INTEGER NOT NULL,
+    outputs INTEGER NOT NULL,
+    UNIQUE (model_pub_id, version),
+    FOREIGN KEY (model_pub_id) REFERENCES models(pub_id) ON DELETE CASCADE
+);
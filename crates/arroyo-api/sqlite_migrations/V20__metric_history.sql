@@ -0,0 +1,13 @@
+CREATE TABLE metric_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    job_id TEXT NOT NULL,
+    operator_id TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    resolution_secs INTEGER NOT NULL,
+    time TIMESTAMP NOT NULL,
+    value REAL NOT NULL,
+    FOREIGN KEY (job_id) references job_configs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX metric_history_job_id_idx ON metric_history (job_id, resolution_secs, time);
+CREATE INDEX metric_history_time_idx ON metric_history (resolution_secs, time);
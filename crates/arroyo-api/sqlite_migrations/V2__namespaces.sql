@@ -0,0 +1,20 @@
+CREATE TABLE namespaces (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pub_id TEXT NOT NULL UNIQUE,
+    organization_id TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+    max_parallelism INTEGER,
+    max_task_slots INTEGER,
+    max_state_bytes INTEGER,
+    UNIQUE (organization_id, name)
+);
+
+ALTER TABLE pipelines ADD COLUMN namespace TEXT DEFAULT 'default' NOT NULL;
+
+ALTER TABLE connection_profiles ADD COLUMN namespace TEXT DEFAULT 'default' NOT NULL;
+
+ALTER TABLE connection_tables ADD COLUMN namespace TEXT DEFAULT 'default' NOT NULL;
+
+ALTER TABLE udfs ADD COLUMN namespace TEXT DEFAULT 'default' NOT NULL;
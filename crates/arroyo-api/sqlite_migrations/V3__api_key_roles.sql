@@ -0,0 +1,5 @@
+ALTER TABLE api_keys ADD COLUMN role TEXT DEFAULT 'admin' NOT NULL;
+
+ALTER TABLE api_keys ADD COLUMN scope_pipelines TEXT;
+
+ALTER TABLE api_keys ADD COLUMN scope_namespaces TEXT;
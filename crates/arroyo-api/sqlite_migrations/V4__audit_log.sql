@@ -0,0 +1,14 @@
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pub_id TEXT NOT NULL UNIQUE,
+    organization_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    action TEXT NOT NULL,
+    resource_type TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    diff TEXT
+);
+
+CREATE INDEX audit_log_organization_id_idx ON audit_log (organization_id, id);
+CREATE INDEX audit_log_resource_idx ON audit_log (resource_id);
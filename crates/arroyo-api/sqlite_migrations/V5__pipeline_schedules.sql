@@ -0,0 +1,28 @@
+CREATE TABLE pipeline_schedules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pub_id TEXT NOT NULL UNIQUE,
+    organization_id TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    pipeline_id INTEGER NOT NULL UNIQUE,
+    cron TEXT NOT NULL,
+    enabled BOOLEAN DEFAULT TRUE NOT NULL,
+    next_run_at TIMESTAMP,
+    FOREIGN KEY (pipeline_id) references pipelines(id) ON DELETE CASCADE
+);
+
+CREATE TABLE scheduled_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pub_id TEXT NOT NULL UNIQUE,
+    pipeline_id INTEGER NOT NULL,
+    job_id TEXT NOT NULL,
+    run_id INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    scheduled_for TIMESTAMP NOT NULL,
+    started_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    finished_at TIMESTAMP,
+    failure_message TEXT,
+    FOREIGN KEY (pipeline_id) references pipelines(id) ON DELETE CASCADE
+);
+
+CREATE INDEX scheduled_runs_pipeline_id_idx ON scheduled_runs (pipeline_id, id);
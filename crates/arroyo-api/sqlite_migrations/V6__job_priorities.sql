@@ -0,0 +1,3 @@
+ALTER TABLE job_configs ADD COLUMN priority INTEGER DEFAULT 0 NOT NULL;
+
+ALTER TABLE job_configs ADD COLUMN preempted_slots INTEGER;
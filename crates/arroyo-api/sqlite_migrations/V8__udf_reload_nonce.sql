@@ -0,0 +1 @@
+ALTER TABLE job_configs ADD COLUMN udf_reload_nonce INTEGER DEFAULT 0 NOT NULL;
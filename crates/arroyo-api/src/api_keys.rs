@@ -0,0 +1,376 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use cornucopia_async::DatabaseSource;
+use once_cell::sync::Lazy;
+use rand::distributions::{Alphanumeric, DistString};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use subtle::ConstantTimeEq;
+use tracing::{info, warn};
+
+use arroyo_rpc::api_types::api_keys::{ApiKey, ApiKeyPost, ApiKeyScope, Role};
+use arroyo_rpc::api_types::ApiKeyCollection;
+use arroyo_rpc::config::config;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+
+use crate::queries::api_queries;
+use crate::queries::api_queries::DbApiKey;
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, forbidden, log_and_map, map_insert_err, not_found, required_field,
+    ApiError, BearerAuth, ErrorResp,
+};
+use crate::to_micros;
+
+const SECRET_LENGTH: usize = 32;
+const MAX_VERIFIED_KEYS: usize = 1024;
+const LEGACY_PREFIX: &str = "$sha256$";
+
+/// Digests of secrets that have already been verified against a stored hash, so that argon2
+/// (which is deliberately slow) only runs the first time a key is presented
+static VERIFIED_KEYS: Lazy<Mutex<HashMap<String, [u8; 32]>>> = Lazy::new(Default::default);
+
+/// API keys are presented as `<id>.<secret>`; only an argon2 hash of the secret is stored
+fn generate_key(pub_id: &str) -> Result<(String, String), ErrorResp> {
+    let secret = Alphanumeric.sample_string(&mut rand::thread_rng(), SECRET_LENGTH);
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>()).map_err(log_and_map)?;
+
+    let hash = Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .map_err(log_and_map)?
+        .to_string();
+
+    Ok((format!("{}.{}", pub_id, secret), hash))
+}
+
+/// Splits a bearer token into its key id and secret
+pub(crate) fn parse_key(token: &str) -> Option<(&str, &str)> {
+    token
+        .split_once('.')
+        .filter(|(id, secret)| !id.is_empty() && !secret.is_empty())
+}
+
+fn is_hashed(stored: &str) -> bool {
+    stored.starts_with("$argon2")
+}
+
+/// Compares a presented secret to an expected one in constant time
+pub(crate) fn secrets_match(presented: &str, expected: &str) -> bool {
+    presented.as_bytes().ct_eq(expected.as_bytes()).into()
+}
+
+/// Keys created before keys were hashed are presented as the whole of their stored value rather
+/// than as `<id>.<secret>`, so they're stored as a SHA-256 digest of it, which (unlike an argon2
+/// hash) can be looked up. The keys were randomly generated, so a fast digest is safe.
+pub(crate) fn legacy_digest(token: &str) -> String {
+    format!("{}{:x}", LEGACY_PREFIX, Sha256::digest(token.as_bytes()))
+}
+
+pub(crate) fn verify_secret(secret: &str, hash: &str) -> bool {
+    let digest: [u8; 32] = Sha256::digest(secret.as_bytes()).into();
+    if VERIFIED_KEYS
+        .lock()
+        .unwrap()
+        .get(hash)
+        .is_some_and(|d| bool::from(d[..].ct_eq(&digest[..])))
+    {
+        return true;
+    }
+
+    let Ok(parsed) = PasswordHash::new(hash) else {
+        return false;
+    };
+
+    if Argon2::default()
+        .verify_password(secret.as_bytes(), &parsed)
+        .is_err()
+    {
+        return false;
+    }
+
+    let mut verified = VERIFIED_KEYS.lock().unwrap();
+    if verified.len() >= MAX_VERIFIED_KEYS {
+        verified.clear();
+    }
+    verified.insert(hash.to_string(), digest);
+
+    true
+}
+
+/// Verifies a secret presented as `<id>.<secret>` against the key with that id; legacy keys are
+/// instead found by [`legacy_digest`]
+pub(crate) fn verify_key(secret: &str, key: &DbApiKey) -> bool {
+    is_hashed(&key.api_key) && verify_secret(secret, &key.api_key)
+}
+
+/// Replaces any API keys that are stored in plaintext, from before keys were hashed, with their
+/// [`legacy_digest`], and warns if no request could authenticate
+pub(crate) async fn check_keys(db: &DatabaseSource) -> anyhow::Result<()> {
+    let client = db.client().await?;
+    let legacy = api_queries::fetch_get_legacy_api_keys(&client).await?;
+
+    for key in &legacy {
+        api_queries::execute_hash_legacy_api_key(
+            &client,
+            &legacy_digest(&key.api_key),
+            &key.pub_id,
+        )
+        .await?;
+    }
+
+    if !legacy.is_empty() {
+        info!(
+            "Hashed {} API keys created before API keys were hashed",
+            legacy.len()
+        );
+    }
+
+    let api = &config().api;
+    if api.require_api_key && api.admin_key.is_none() {
+        let keys = api_queries::fetch_count_api_keys(&client)
+            .await?
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+
+        if keys == 0 {
+            warn!(
+                "api.require-api-key is enabled, but no API keys exist and no api.admin-key is \
+                set, so every request will be rejected; set api.admin-key to create the first key"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn parse_scope(
+    pipelines: Option<serde_json::Value>,
+    namespaces: Option<serde_json::Value>,
+) -> Result<ApiKeyScope, String> {
+    let parse = |v: Option<serde_json::Value>| {
+        v.map(serde_json::from_value::<Vec<String>>)
+            .transpose()
+            .map_err(|e| format!("invalid API key scope: {}", e))
+    };
+
+    Ok(ApiKeyScope {
+        pipelines: parse(pipelines)?,
+        namespaces: parse(namespaces)?,
+    })
+}
+
+impl TryFrom<DbApiKey> for ApiKey {
+    type Error = String;
+
+    fn try_from(val: DbApiKey) -> Result<Self, Self::Error> {
+        Ok(ApiKey {
+            id: val.pub_id,
+            name: val.name,
+            role: Role::from_str(&val.role)?,
+            scope: parse_scope(val.scope_pipelines, val.scope_namespaces)?,
+            created_at: to_micros(val.created_at),
+            key: None,
+        })
+    }
+}
+
+/// Create an API key
+///
+/// The key's secret is only returned in the response to this request.
+#[utoipa::path(
+    post,
+    path = "/v1/api_keys",
+    tag = "api_keys",
+    request_body = ApiKeyPost,
+    responses(
+        (status = 200, description = "Created API key", body = ApiKey),
+    ),
+)]
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(req), _): WithRejection<Json<ApiKeyPost>, ApiError>,
+) -> Result<Json<ApiKey>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Admin)?;
+
+    // a scoped key could otherwise be used to mint keys with broader access
+    if !auth_data.scope.is_unrestricted() {
+        return Err(forbidden("Scoped API keys cannot create other API keys"));
+    }
+
+    if req.name.trim().is_empty() {
+        return Err(required_field("name"));
+    }
+
+    let scope = req.scope.unwrap_or_default();
+    if scope
+        .pipelines
+        .as_ref()
+        .map(|p| p.is_empty())
+        .unwrap_or(false)
+        || scope
+            .namespaces
+            .as_ref()
+            .map(|n| n.is_empty())
+            .unwrap_or(false)
+    {
+        return Err(bad_request(
+            "Scopes must list at least one pipeline or namespace",
+        ));
+    }
+
+    let pub_id = generate_id(IdTypes::ApiKey);
+    let (key, hash) = generate_key(&pub_id)?;
+
+    let client = state.database.client().await?;
+
+    api_queries::execute_create_api_key(
+        &client,
+        &pub_id,
+        &auth_data.user_id,
+        &auth_data.organization_id,
+        &auth_data.user_id,
+        &req.name,
+        &hash,
+        &req.role.to_string(),
+        &scope
+            .pipelines
+            .as_ref()
+            .map(|p| serde_json::to_value(p).unwrap()),
+        &scope
+            .namespaces
+            .as_ref()
+            .map(|n| serde_json::to_value(n).unwrap()),
+    )
+    .await
+    .map_err(|e| map_insert_err("api key", e))?;
+
+    let mut api_key: ApiKey = api_queries::fetch_get_api_key(&client, &pub_id)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found("API key"))?
+        .try_into()
+        .map_err(log_and_map)?;
+
+    api_key.key = Some(key);
+
+    Ok(Json(api_key))
+}
+
+/// List all API keys
+#[utoipa::path(
+    get,
+    path = "/v1/api_keys",
+    tag = "api_keys",
+    responses(
+        (status = 200, description = "Got API keys collection", body = ApiKeyCollection),
+    ),
+)]
+pub async fn get_api_keys(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+) -> Result<Json<ApiKeyCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Admin)?;
+
+    let keys = api_queries::fetch_get_api_keys(
+        &state.database.client().await?,
+        &auth_data.organization_id,
+    )
+    .await?;
+
+    Ok(Json(ApiKeyCollection {
+        data: keys
+            .into_iter()
+            .map(|k| k.try_into())
+            .collect::<Result<_, _>>()
+            .map_err(log_and_map)?,
+    }))
+}
+
+/// Delete an API key
+#[utoipa::path(
+    delete,
+    path = "/v1/api_keys/{id}",
+    tag = "api_keys",
+    params(
+        ("id" = String, Path, description = "API key id")
+    ),
+    responses(
+        (status = 200, description = "Deleted API key"),
+    ),
+)]
+pub async fn delete_api_key(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Admin)?;
+
+    let deleted = api_queries::execute_delete_api_key(
+        &state.database.client().await?,
+        &auth_data.organization_id,
+        &pub_id,
+    )
+    .await?;
+
+    if deleted == 0 {
+        return Err(not_found("API key"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_verify_key() {
+        let (key, hash) = generate_key("ak_test").unwrap();
+
+        let (id, secret) = parse_key(&key).unwrap();
+        assert_eq!(id, "ak_test");
+        assert!(verify_secret(secret, &hash));
+        assert!(!verify_secret("not the secret", &hash));
+        assert!(!verify_secret(secret, "not a hash"));
+
+        // served from the cache of verified keys
+        assert!(verify_secret(secret, &hash));
+        assert!(!verify_secret("not the secret", &hash));
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("ak_1.abc"), Some(("ak_1", "abc")));
+        assert_eq!(parse_key("ak_1"), None);
+        assert_eq!(parse_key("ak_1."), None);
+        assert_eq!(parse_key(".abc"), None);
+    }
+
+    #[test]
+    fn test_legacy_digest() {
+        let digest = legacy_digest("an old key");
+        assert!(digest.starts_with(LEGACY_PREFIX));
+        assert_eq!(digest, legacy_digest("an old key"));
+        assert_ne!(digest, legacy_digest("another old key"));
+        assert!(!is_hashed(&digest));
+    }
+
+    #[test]
+    fn test_secrets_match() {
+        assert!(secrets_match("s3cret", "s3cret"));
+        assert!(!secrets_match("s3cret", "s3cres"));
+        assert!(!secrets_match("s3cret", "s3cret!"));
+        assert!(!secrets_match("", "s3cret"));
+    }
+}
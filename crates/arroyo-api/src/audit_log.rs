@@ -0,0 +1,387 @@
+use anyhow::anyhow;
+use axum::extract::{Query, State};
+use axum::Json;
+use cornucopia_async::{Database, DatabaseSource};
+use deadpool_postgres::{PoolConfig, Runtime};
+use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio_postgres::NoTls;
+
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{
+    AuditAction, AuditLogEntry, AuditLogQueryParams, AuditResourceType,
+};
+use arroyo_rpc::api_types::catalog::CatalogTable;
+use arroyo_rpc::api_types::models::Model;
+use arroyo_rpc::api_types::notifications::{NotificationTarget, NotificationTargetConfig};
+use arroyo_rpc::api_types::pipelines::Pipeline;
+use arroyo_rpc::api_types::templates::PipelineTemplate;
+use arroyo_rpc::api_types::udfs::GlobalUdf;
+use arroyo_rpc::api_types::AuditLogCollection;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+
+use crate::queries::api_queries;
+use crate::queries::api_queries::DbAuditLogEntry;
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, forbidden, log_and_map, paginate_results, validate_pagination_params, BearerAuth,
+    ErrorResp,
+};
+use crate::{postgres_pool_config, to_micros, AuthData};
+
+const REDACTED: &str = "<redacted>";
+
+/// Config keys whose values are never written to the audit log
+const SENSITIVE_KEYS: &[&str] = &[
+    "password",
+    "secret",
+    "token",
+    "credential",
+    "api_key",
+    "apikey",
+    "private",
+    "authorization",
+    "header",
+];
+
+/// Replaces the values of credential-like fields in a connection config so that they are not
+/// persisted in the audit log
+pub(crate) fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(obj) => Value::Object(
+            obj.iter()
+                .map(|(k, v)| {
+                    let key = k.to_lowercase().replace('-', "_");
+                    if SENSITIVE_KEYS.iter().any(|s| key.contains(s)) && !v.is_null() {
+                        (k.clone(), Value::String(REDACTED.to_string()))
+                    } else {
+                        (k.clone(), redact(v))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(arr) => Value::Array(arr.iter().map(redact).collect()),
+        v => v.clone(),
+    }
+}
+
+/// Computes a field-level diff of two resource states, as
+/// `{"field": {"before": .., "after": ..}}`; only fields that differ are included
+pub(crate) fn diff(before: Option<&Value>, after: Option<&Value>) -> Option<Value> {
+    let empty = Map::new();
+    let fields = |v: Option<&Value>| v.and_then(|v| v.as_object()).unwrap_or(&empty).clone();
+
+    let before = fields(before);
+    let after = fields(after);
+
+    let mut diff = Map::new();
+    for key in before
+        .keys()
+        .chain(after.keys().filter(|k| !before.contains_key(*k)))
+    {
+        let b = before.get(key);
+        let a = after.get(key);
+        if b == a {
+            continue;
+        }
+
+        let mut change = Map::new();
+        if let Some(b) = b {
+            change.insert("before".to_string(), b.clone());
+        }
+        if let Some(a) = a {
+            change.insert("after".to_string(), a.clone());
+        }
+        diff.insert(key.clone(), Value::Object(change));
+    }
+
+    (!diff.is_empty()).then_some(Value::Object(diff))
+}
+
+/// The parts of a pipeline that are tracked in the audit log
+pub(crate) fn pipeline_state(pipeline: &Pipeline) -> Value {
+    json!({
+        "name": pipeline.name,
+        "namespace": pipeline.namespace,
+        "query": pipeline.query,
+        "udfs": pipeline.udfs,
+        "udfVersions": pipeline.udf_versions,
+        "checkpointIntervalMicros": pipeline.checkpoint_interval_micros,
+        "stop": pipeline.stop,
+        "priority": pipeline.priority,
+        "workerPod": pipeline.worker_pod,
+        "freshnessSlo": pipeline.freshness_slo,
+        "restartStrategy": pipeline.restart_strategy,
+        "parallelism": pipeline.graph.nodes.iter().map(|n| n.parallelism).max(),
+    })
+}
+
+pub(crate) fn udf_state(udf: &GlobalUdf) -> Value {
+    json!({
+        "prefix": udf.prefix,
+        "name": udf.name,
+        "definition": udf.definition,
+        "description": udf.description,
+        "namespace": udf.namespace,
+        "version": udf.version,
+    })
+}
+
+pub(crate) fn catalog_table_state(table: &CatalogTable) -> Value {
+    json!({
+        "name": table.name,
+        "definition": table.definition,
+        "description": table.description,
+        "namespace": table.namespace,
+        "version": table.version,
+    })
+}
+
+pub(crate) fn pipeline_template_state(template: &PipelineTemplate) -> Value {
+    json!({
+        "name": template.name,
+        "query": template.query,
+        "description": template.description,
+        "parameters": template.parameters,
+        "udfs": template.udfs,
+        "namespace": template.namespace,
+    })
+}
+
+/// Notification target URLs and routing keys act as credentials, so only the type of the target
+/// is recorded
+pub(crate) fn notification_target_state(target: &NotificationTarget) -> Value {
+    let target_type = match &target.config {
+        NotificationTargetConfig::Webhook { .. } => "webhook",
+        NotificationTargetConfig::Slack { .. } => "slack",
+        NotificationTargetConfig::PagerDuty { .. } => "pagerDuty",
+    };
+
+    json!({
+        "name": target.name,
+        "type": target_type,
+        "events": target.events,
+        "pipelineId": target.pipeline_id,
+        "payloadTemplate": target.payload_template,
+    })
+}
+
+pub(crate) fn model_state(model: &Model) -> Value {
+    json!({
+        "name": model.name,
+        "description": model.description,
+        "namespace": model.namespace,
+        "version": model.version,
+        "url": model.url,
+        "inputs": model.inputs,
+        "outputs": model.outputs,
+    })
+}
+
+/// Opens a connection to the database of its own, which no other request's queries can run on
+fn dedicated_connection(db: &DatabaseSource) -> anyhow::Result<DatabaseSource> {
+    Ok(match db {
+        DatabaseSource::Postgres(_) => {
+            let mut cfg = postgres_pool_config();
+            cfg.pool = Some(PoolConfig::new(1));
+            DatabaseSource::Postgres(cfg.create_pool(Some(Runtime::Tokio1), NoTls)?)
+        }
+        DatabaseSource::Sqlite(shared) => {
+            let path = shared
+                .lock()
+                .unwrap()
+                .path()
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow!("sqlite database has no path"))?;
+            let conn = rusqlite::Connection::open(path)?;
+            conn.pragma_update(None, "foreign_keys", "ON")?;
+            DatabaseSource::Sqlite(Arc::new(Mutex::new(conn)))
+        }
+    })
+}
+
+/// Runs `mutation` in a transaction, which is committed if it succeeds and rolled back otherwise.
+/// Changes are made in the same transaction as their [`record`], so that a change is never made
+/// without being recorded, or recorded without being made; `mutation` must therefore only use the
+/// client it's given.
+///
+/// The transaction runs on a connection of its own, so that other requests can't interleave their
+/// queries with it, and which is closed (rolling back the transaction) if the transaction is
+/// dropped before it's committed, for example because the request was cancelled.
+pub(crate) async fn transaction<T>(
+    db: &DatabaseSource,
+    mutation: impl AsyncFnOnce(&Database<'_>) -> Result<T, ErrorResp>,
+) -> Result<T, ErrorResp> {
+    let connection = dedicated_connection(db).map_err(log_and_map)?;
+    let client = connection.client().await?;
+
+    api_queries::execute_begin_transaction(&client)
+        .await
+        .map_err(log_and_map)?;
+
+    let result = mutation(&client).await?;
+
+    api_queries::execute_commit_transaction(&client)
+        .await
+        .map_err(log_and_map)?;
+
+    Ok(result)
+}
+
+/// Records a change to a resource made by the authenticated user; this should be called within
+/// the [`transaction`] that makes the change
+pub(crate) async fn record(
+    db: &Database<'_>,
+    auth: &AuthData,
+    action: AuditAction,
+    resource_type: AuditResourceType,
+    resource_id: &str,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<(), ErrorResp> {
+    api_queries::execute_create_audit_log_entry(
+        db,
+        &generate_id(IdTypes::AuditLogEntry),
+        &auth.organization_id,
+        &auth.user_id,
+        &action.to_string(),
+        &resource_type.to_string(),
+        &resource_id,
+        &diff(before.as_ref(), after.as_ref()),
+    )
+    .await
+    .map_err(log_and_map)?;
+
+    Ok(())
+}
+
+impl TryFrom<DbAuditLogEntry> for AuditLogEntry {
+    type Error = String;
+
+    fn try_from(val: DbAuditLogEntry) -> Result<Self, Self::Error> {
+        Ok(AuditLogEntry {
+            id: val.pub_id,
+            created_at: to_micros(val.created_at),
+            user_id: val.user_id,
+            action: AuditAction::from_str(&val.action)
+                .map_err(|_| format!("invalid audit action '{}'", val.action))?,
+            resource_type: AuditResourceType::from_str(&val.resource_type)
+                .map_err(|_| format!("invalid audit resource type '{}'", val.resource_type))?,
+            resource_id: val.resource_id,
+            diff: val.diff,
+        })
+    }
+}
+
+/// List audit log entries
+///
+/// Entries are returned from newest to oldest, and may be filtered to a single resource type or
+/// resource.
+#[utoipa::path(
+    get,
+    path = "/v1/audit_log",
+    tag = "audit_log",
+    params(
+        AuditLogQueryParams
+    ),
+    responses(
+        (status = 200, description = "Got audit log entries", body = AuditLogCollection),
+    ),
+)]
+pub async fn get_audit_log(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    query_params: Query<AuditLogQueryParams>,
+) -> Result<Json<AuditLogCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Admin)?;
+
+    if !auth_data.scope.is_unrestricted() {
+        return Err(forbidden("Scoped API keys cannot read the audit log"));
+    }
+
+    let (starting_after, limit) =
+        validate_pagination_params(query_params.starting_after.clone(), query_params.limit)?;
+
+    let entries = api_queries::fetch_get_audit_log(
+        &state.database.client().await?,
+        &auth_data.organization_id,
+        &query_params
+            .resource_type
+            .map(|t| t.to_string())
+            .unwrap_or_default(),
+        &query_params.resource_id.clone().unwrap_or_default(),
+        &starting_after.unwrap_or_default(),
+        &(limit as i32),
+    )
+    .await?;
+
+    let (entries, has_more) = paginate_results(entries, limit);
+
+    Ok(Json(AuditLogCollection {
+        has_more,
+        data: entries
+            .into_iter()
+            .map(|e| e.try_into())
+            .collect::<Result<_, _>>()
+            .map_err(log_and_map)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let before = json!({"name": "a", "stop": "none", "parallelism": 1});
+        let after = json!({"name": "a", "stop": "checkpoint", "parallelism": 1, "namespace": "x"});
+
+        assert_eq!(
+            diff(Some(&before), Some(&after)),
+            Some(json!({
+                "stop": {"before": "none", "after": "checkpoint"},
+                "namespace": {"after": "x"},
+            }))
+        );
+
+        assert_eq!(
+            diff(None, Some(&json!({"name": "a"}))),
+            Some(json!({"name": {"after": "a"}}))
+        );
+
+        assert_eq!(diff(Some(&before), Some(&before)), None);
+    }
+
+    #[test]
+    fn test_redact() {
+        let config = json!({
+            "bootstrapServers": "localhost:9092",
+            "authentication": {
+                "username": "arroyo",
+                "password": "hunter2",
+                "mechanism": "PLAIN",
+            },
+            "headers": "Authorization: Bearer abc",
+            "properties": [{"X-Api-Key": "abc"}],
+            "token": null,
+        });
+
+        assert_eq!(
+            redact(&config),
+            json!({
+                "bootstrapServers": "localhost:9092",
+                "authentication": {
+                    "username": "arroyo",
+                    "password": REDACTED,
+                    "mechanism": "PLAIN",
+                },
+                "headers": REDACTED,
+                "properties": [{"X-Api-Key": REDACTED}],
+                "token": null,
+            })
+        );
+    }
+}
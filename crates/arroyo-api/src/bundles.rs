@@ -0,0 +1,1220 @@
+//! Export and apply of declarative pipeline bundles, which describe a set of pipelines along with
+//! the connection profiles, connection tables, and global UDFs they use, for git-driven deployment.
+//!
+//! Applying a bundle is idempotent: resources are matched by name, and only those that differ from
+//! the bundle are changed. All changes are planned before any are made, so a bundle that conflicts
+//! with the cluster fails without modifying it. Changes are made in the background, and the
+//! progress of each apply is tracked in memory.
+
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::bundles::{
+    BundleAction, BundleApplyQueryParams, BundleApplyResult, BundleApplyStatus, BundleChange,
+    BundleExportQueryParams, BundleResourceType, ConnectionProfileSpec, ConnectionTableSpec,
+    GlobalUdfSpec, PipelineBundle, PipelineSpec,
+};
+use arroyo_rpc::api_types::checkpoints::StateMigrationPost;
+use arroyo_rpc::api_types::connections::{
+    ConnectionProfile, ConnectionProfilePost, ConnectionTable, ConnectionTablePost,
+};
+use arroyo_rpc::api_types::pipelines::{
+    Pipeline, PipelinePatch, PipelinePost, PipelineUdfsPut, StopType,
+};
+use arroyo_rpc::api_types::udfs::{GlobalUdf, UdfPost, UdfVersionPost};
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use cornucopia_async::Database;
+use http::header;
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+use crate::connection_profiles::{create_connection_profile, get_all_connection_profiles};
+use crate::connection_tables::{create_connection_table, get_all_connection_tables};
+use crate::jobs::migrate_state_int;
+use crate::namespaces::DEFAULT_NAMESPACE;
+use crate::pipelines::{
+    create_pipeline_from_post, delete_pipeline, patch_pipeline, put_pipeline_udfs,
+};
+use crate::queries::api_queries;
+use crate::queries::api_queries::DbPipeline;
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, internal_server_error, log_and_map, not_found, BearerAuth, ErrorResp,
+};
+use crate::udfs::{create_udf, create_udf_version};
+use crate::AuthData;
+
+/// How long to wait for a replaced pipeline to take its final checkpoint and stop
+const REPLACE_STOP_TIMEOUT: Duration = Duration::from_secs(300);
+const REPLACE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many applies to keep the status of; finished ones are dropped beyond this
+const MAX_TRACKED_APPLIES: usize = 256;
+
+static APPLIES: Lazy<Mutex<HashMap<String, TrackedApply>>> = Lazy::new(Default::default);
+
+fn is_terminal(state: &str) -> bool {
+    matches!(state, "Stopped" | "Finished" | "Failed")
+}
+
+fn is_stopped(stop: &StopType) -> bool {
+    !matches!(stop, StopType::None)
+}
+
+fn parallelism(pipeline: &Pipeline) -> u64 {
+    pipeline
+        .graph
+        .nodes
+        .iter()
+        .map(|n| n.parallelism as u64)
+        .max()
+        .unwrap_or(1)
+}
+
+fn json_eq<T: serde::Serialize>(a: &T, b: &T) -> bool {
+    serde_json::to_value(a).unwrap() == serde_json::to_value(b).unwrap()
+}
+
+impl From<&ConnectionProfile> for ConnectionProfileSpec {
+    fn from(val: &ConnectionProfile) -> Self {
+        ConnectionProfileSpec {
+            name: val.name.clone(),
+            connector: val.connector.clone(),
+            config: val.config.clone(),
+            namespace: Some(val.namespace.clone()),
+        }
+    }
+}
+
+impl From<&ConnectionTable> for ConnectionTableSpec {
+    fn from(val: &ConnectionTable) -> Self {
+        ConnectionTableSpec {
+            name: val.name.clone(),
+            connector: val.connector.clone(),
+            connection_profile: val.connection_profile.as_ref().map(|p| p.name.clone()),
+            config: val.config.clone(),
+            schema: Some(val.schema.clone()),
+            namespace: Some(val.namespace.clone()),
+        }
+    }
+}
+
+impl From<&GlobalUdf> for GlobalUdfSpec {
+    fn from(val: &GlobalUdf) -> Self {
+        GlobalUdfSpec {
+            name: val.name.clone(),
+            prefix: val.prefix.clone(),
+            definition: val.definition.clone(),
+            description: val.description.clone(),
+            namespace: Some(val.namespace.clone()),
+        }
+    }
+}
+
+impl From<&Pipeline> for PipelineSpec {
+    fn from(val: &Pipeline) -> Self {
+        PipelineSpec {
+            name: val.name.clone(),
+            query: val.query.clone(),
+            udfs: val.udfs.clone(),
+            udf_versions: val.udf_versions.clone(),
+            parallelism: parallelism(val),
+            checkpoint_interval_micros: Some(val.checkpoint_interval_micros),
+            namespace: Some(val.namespace.clone()),
+            priority: Some(val.priority),
+            worker_pod: val.worker_pod.clone(),
+            freshness_slo: val.freshness_slo.clone(),
+            restart_strategy: val.restart_strategy.clone(),
+            batching: (val.batching != Default::default()).then(|| val.batching.clone()),
+            resource_classes: val.resource_classes.clone(),
+            stopped: is_stopped(&val.stop),
+        }
+    }
+}
+
+/// All non-preview pipelines visible to the caller, newest first
+async fn get_all_pipelines(
+    auth_data: &AuthData,
+    db: &Database<'_>,
+) -> Result<Vec<Pipeline>, ErrorResp> {
+    let pipelines: Vec<DbPipeline> =
+        api_queries::fetch_get_pipelines(db, &auth_data.organization_id, &String::new(), &i32::MAX)
+            .await?;
+
+    Ok(pipelines
+        .into_iter()
+        .filter(|p| auth_data.scope.allows_pipeline(&p.pub_id, &p.namespace))
+        .filter_map(|p| {
+            let id = p.pub_id.clone();
+            match TryInto::<Pipeline>::try_into(p) {
+                Ok(p) => Some(p),
+                Err(e) => {
+                    warn!("Failed to map pipeline {} from database: {:?}", id, e);
+                    None
+                }
+            }
+        })
+        .filter(|p| !p.preview)
+        .collect())
+}
+
+async fn get_all_udfs(
+    auth_data: &AuthData,
+    db: &Database<'_>,
+) -> Result<Vec<GlobalUdf>, ErrorResp> {
+    Ok(api_queries::fetch_get_udfs(db, &auth_data.organization_id)
+        .await?
+        .into_iter()
+        .map(|u| u.into())
+        .collect())
+}
+
+/// Export a pipeline bundle
+///
+/// Returns a YAML bundle describing the pipelines and the connection profiles, connection tables,
+/// and global UDFs they use. If a pipeline is given, only it and the resources it references are
+/// exported. Connection configs are exported as stored, so secrets should be referenced by name
+/// rather than stored inline in bundles that are checked into version control.
+#[utoipa::path(
+    get,
+    path = "/v1/bundles/export",
+    tag = "bundles",
+    params(BundleExportQueryParams),
+    responses(
+        (status = 200, description = "Exported bundle", body = PipelineBundle, content_type = "application/yaml"),
+    ),
+)]
+pub async fn export_bundle(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Query(query_params): Query<BundleExportQueryParams>,
+) -> Result<Response, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let db = state.database.client().await?;
+
+    let mut pipelines = get_all_pipelines(&auth_data, &db).await?;
+    let mut tables = get_all_connection_tables(&auth_data, &db).await?;
+    let mut profiles = get_all_connection_profiles(&auth_data, &db).await?;
+    let mut udfs = get_all_udfs(&auth_data, &db).await?;
+
+    if let Some(pipeline_id) = &query_params.pipeline_id {
+        pipelines.retain(|p| &p.id == pipeline_id);
+        let pipeline = pipelines.first().ok_or_else(|| not_found("Pipeline"))?;
+
+        let table_ids: HashSet<String> = api_queries::fetch_get_pipeline_connection_tables(
+            &db,
+            pipeline_id,
+            &auth_data.organization_id,
+        )
+        .await?
+        .into_iter()
+        .collect();
+        tables.retain(|t| table_ids.contains(&t.pub_id));
+
+        let profile_ids: HashSet<&str> = tables
+            .iter()
+            .filter_map(|t| t.connection_profile.as_ref())
+            .map(|p| p.id.as_str())
+            .collect();
+        profiles.retain(|p| profile_ids.contains(p.id.as_str()));
+
+        udfs.retain(|u| pipeline.udf_versions.contains_key(&u.name));
+    }
+
+    // export in creation order, so that the bundle is stable as resources are added
+    pipelines.reverse();
+
+    let bundle = PipelineBundle {
+        version: PipelineBundle::VERSION,
+        connection_profiles: profiles.iter().map(|p| p.into()).collect(),
+        connection_tables: tables.iter().map(|t| t.into()).collect(),
+        udfs: udfs.iter().map(|u| u.into()).collect(),
+        pipelines: pipelines.iter().map(|p| p.into()).collect(),
+    };
+
+    let yaml = serde_yaml::to_string(&bundle).map_err(log_and_map)?;
+
+    Ok(([(header::CONTENT_TYPE, "application/yaml")], yaml).into_response())
+}
+
+fn check_unique<'a>(kind: &str, names: impl Iterator<Item = &'a String>) -> Result<(), ErrorResp> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            return Err(bad_request(format!(
+                "Bundle contains multiple {}s named '{}'",
+                kind, name
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn change(
+    resource_type: BundleResourceType,
+    name: &str,
+    action: BundleAction,
+    id: Option<&str>,
+) -> BundleChange {
+    BundleChange {
+        resource_type,
+        name: name.to_string(),
+        action,
+        id: id.map(|s| s.to_string()),
+        message: None,
+    }
+}
+
+fn pipeline_post(spec: &PipelineSpec) -> PipelinePost {
+    PipelinePost {
+        name: spec.name.clone(),
+        query: spec.query.clone(),
+        udfs: Some(spec.udfs.clone()),
+        udf_versions: Some(spec.udf_versions.clone()),
+        preview: None,
+        parallelism: spec.parallelism,
+        checkpoint_interval_micros: spec.checkpoint_interval_micros,
+        namespace: spec.namespace.clone(),
+        priority: spec.priority,
+        worker_pod: spec.worker_pod.clone(),
+        freshness_slo: spec.freshness_slo.clone(),
+        restart_strategy: spec.restart_strategy.clone(),
+        batching: spec.batching.clone(),
+        recording: None,
+        replay: None,
+        bootstrap: None,
+        resource_classes: Some(spec.resource_classes.clone()),
+    }
+}
+
+/// The namespace that a pipeline spec is created in
+fn spec_namespace(spec: &PipelineSpec) -> &str {
+    spec.namespace
+        .as_deref()
+        .map(|n| n.trim())
+        .filter(|n| !n.is_empty())
+        .unwrap_or(DEFAULT_NAMESPACE)
+}
+
+/// Whether the pipeline must be replaced by a new one to match the spec, because it differs in a
+/// way that can't be changed in place
+fn needs_replace(spec: &PipelineSpec, existing: &Pipeline) -> bool {
+    spec.query != existing.query
+        || spec.batching.clone().unwrap_or_default() != existing.batching
+        || spec.resource_classes != existing.resource_classes
+}
+
+fn udfs_put(spec: &PipelineSpec, existing: &Pipeline) -> Option<PipelineUdfsPut> {
+    let udfs_changed = !json_eq(&spec.udfs, &existing.udfs);
+    // global UDFs that aren't listed in the spec are pinned to whatever version the pipeline
+    // was created with
+    let versions_changed = spec
+        .udf_versions
+        .iter()
+        .any(|(name, version)| existing.udf_versions.get(name) != Some(version));
+
+    (udfs_changed || versions_changed).then(|| PipelineUdfsPut {
+        udfs: spec.udfs.clone(),
+        udf_versions: Some(spec.udf_versions.clone()),
+    })
+}
+
+fn pipeline_patch(spec: &PipelineSpec, existing: &Pipeline) -> Option<PipelinePatch> {
+    let mut patch = PipelinePatch {
+        parallelism: None,
+        checkpoint_interval_micros: None,
+        stop: None,
+        priority: None,
+        worker_pod: None,
+        freshness_slo: None,
+        restart_strategy: None,
+    };
+    let mut changed = false;
+
+    if spec.parallelism != parallelism(existing) {
+        patch.parallelism = Some(spec.parallelism);
+        changed = true;
+    }
+
+    if let Some(interval) = spec.checkpoint_interval_micros {
+        if interval != existing.checkpoint_interval_micros {
+            patch.checkpoint_interval_micros = Some(interval);
+            changed = true;
+        }
+    }
+
+    if spec.priority.unwrap_or_default() != existing.priority {
+        patch.priority = Some(spec.priority.unwrap_or_default());
+        changed = true;
+    }
+
+    // these can't be unset by a patch, so they're only changed when the spec sets them
+    if spec.worker_pod.is_some() && !json_eq(&spec.worker_pod, &existing.worker_pod) {
+        patch.worker_pod = spec.worker_pod.clone();
+        changed = true;
+    }
+
+    if spec.freshness_slo.is_some() && !json_eq(&spec.freshness_slo, &existing.freshness_slo) {
+        patch.freshness_slo = spec.freshness_slo.clone();
+        changed = true;
+    }
+
+    if spec.restart_strategy.is_some()
+        && !json_eq(&spec.restart_strategy, &existing.restart_strategy)
+    {
+        patch.restart_strategy = spec.restart_strategy.clone();
+        changed = true;
+    }
+
+    if spec.stopped != is_stopped(&existing.stop) {
+        patch.stop = Some(if spec.stopped {
+            StopType::Checkpoint
+        } else {
+            StopType::None
+        });
+        changed = true;
+    }
+
+    changed.then_some(patch)
+}
+
+enum PipelinePlan<'a> {
+    Create,
+    Update {
+        existing: &'a Pipeline,
+        udfs: Option<PipelineUdfsPut>,
+        patch: Option<PipelinePatch>,
+    },
+    Replace {
+        existing: &'a Pipeline,
+    },
+    Unchanged {
+        existing: &'a Pipeline,
+    },
+}
+
+/// Apply a pipeline bundle
+///
+/// Makes the cluster match the YAML bundle in the request body. Connection profiles, connection
+/// tables, and global UDFs that don't exist are created, and global UDFs whose definitions differ
+/// get a new version; connection profiles and tables can't be modified, so a bundle that changes
+/// one is rejected. Pipelines that don't exist are created, and those whose configuration differs
+/// are updated in place. Pipelines whose query changed are replaced: the old pipeline takes a
+/// final checkpoint and stops, and a new pipeline is started from its state. Pipelines are
+/// matched by namespace and name, so moving one to another namespace creates a new pipeline. With
+/// `prune`, pipelines that aren't in the bundle (including replaced ones) are stopped and deleted,
+/// but only in the namespaces that the bundle's pipelines are in.
+///
+/// The bundle is planned and checked with the request, but its changes are made in the
+/// background, as replacing pipelines waits for them to stop; the response contains an id whose
+/// progress can be polled at `/v1/bundles/applies/{id}`. Dry runs report their changes directly.
+#[utoipa::path(
+    post,
+    path = "/v1/bundles/apply",
+    tag = "bundles",
+    params(BundleApplyQueryParams),
+    request_body(content = PipelineBundle, content_type = "application/yaml"),
+    responses(
+        (status = 200, description = "Started applying bundle", body = BundleApplyResult),
+        (status = 400, description = "Invalid or conflicting bundle", body = ErrorResp),
+    ),
+)]
+pub async fn apply_bundle(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Query(query_params): Query<BundleApplyQueryParams>,
+    body: String,
+) -> Result<Json<BundleApplyResult>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth.clone()).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let bundle = parse_bundle(&body)?;
+    let prune = query_params.prune;
+
+    // planning makes no changes, so a bundle that's invalid or conflicts with the cluster is
+    // rejected by the request rather than failing the apply
+    let mut changes = vec![];
+    execute_bundle(
+        &state,
+        &bearer_auth,
+        &auth_data,
+        &bundle,
+        prune,
+        true,
+        &mut |c| changes.push(c),
+    )
+    .await?;
+
+    if query_params.dry_run {
+        return Ok(Json(BundleApplyResult {
+            id: None,
+            dry_run: true,
+            status: BundleApplyStatus::Succeeded,
+            error: None,
+            changes,
+        }));
+    }
+
+    let result = BundleApplyResult {
+        id: Some(generate_id(IdTypes::BundleApply)),
+        dry_run: false,
+        status: BundleApplyStatus::Running,
+        error: None,
+        changes: vec![],
+    };
+    let id = track_apply(&auth_data.organization_id, result.clone())?;
+
+    tokio::spawn(async move {
+        let applied = execute_bundle(
+            &state,
+            &bearer_auth,
+            &auth_data,
+            &bundle,
+            prune,
+            false,
+            &mut |c| update_apply(&id, |r| r.changes.push(c)),
+        )
+        .await;
+
+        update_apply(&id, |r| match applied {
+            Ok(()) => r.status = BundleApplyStatus::Succeeded,
+            Err(e) => {
+                warn!("Failed to apply bundle {}: {}", id, e.message);
+                r.status = BundleApplyStatus::Failed;
+                r.error = Some(e.message);
+            }
+        });
+    });
+
+    Ok(Json(result))
+}
+
+/// Get the status of a bundle apply
+///
+/// Applies are tracked in memory by the API server that started them, so this must be routed to
+/// the same server, and the status is lost if it restarts.
+#[utoipa::path(
+    get,
+    path = "/v1/bundles/applies/{id}",
+    tag = "bundles",
+    params(
+        ("id" = String, Path, description = "Bundle apply id")
+    ),
+    responses(
+        (status = 200, description = "Bundle apply status", body = BundleApplyResult),
+        (status = 404, description = "Bundle apply not found", body = ErrorResp),
+    ),
+)]
+pub async fn get_bundle_apply(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(id): Path<String>,
+) -> Result<Json<BundleApplyResult>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    APPLIES
+        .lock()
+        .unwrap()
+        .get(&id)
+        .filter(|a| a.organization_id == auth_data.organization_id)
+        .map(|a| Json(a.result.clone()))
+        .ok_or_else(|| not_found("Bundle apply"))
+}
+
+struct TrackedApply {
+    organization_id: String,
+    result: BundleApplyResult,
+}
+
+/// Registers a new apply, returning its id. Only one apply may run at a time per organization, as
+/// concurrent applies would plan against each other's partial changes.
+fn track_apply(organization_id: &str, result: BundleApplyResult) -> Result<String, ErrorResp> {
+    let mut applies = APPLIES.lock().unwrap();
+
+    if let Some((id, _)) = applies.iter().find(|(_, a)| {
+        a.organization_id == organization_id && a.result.status == BundleApplyStatus::Running
+    }) {
+        return Err(bad_request(format!(
+            "Bundle apply {} is still running; wait for it to finish before applying another",
+            id
+        )));
+    }
+
+    if applies.len() >= MAX_TRACKED_APPLIES {
+        applies.retain(|_, a| a.result.status == BundleApplyStatus::Running);
+    }
+
+    let id = result.id.clone().unwrap();
+    applies.insert(
+        id.clone(),
+        TrackedApply {
+            organization_id: organization_id.to_string(),
+            result,
+        },
+    );
+    Ok(id)
+}
+
+fn update_apply(id: &str, f: impl FnOnce(&mut BundleApplyResult)) {
+    if let Some(apply) = APPLIES.lock().unwrap().get_mut(id) {
+        f(&mut apply.result);
+    }
+}
+
+fn parse_bundle(body: &str) -> Result<PipelineBundle, ErrorResp> {
+    let bundle: PipelineBundle =
+        serde_yaml::from_str(body).map_err(|e| bad_request(format!("Invalid bundle: {}", e)))?;
+
+    if bundle.version != PipelineBundle::VERSION {
+        return Err(bad_request(format!(
+            "Unsupported bundle version {}; expected {}",
+            bundle.version,
+            PipelineBundle::VERSION
+        )));
+    }
+
+    check_unique(
+        "connection profile",
+        bundle.connection_profiles.iter().map(|p| &p.name),
+    )?;
+    check_unique(
+        "connection table",
+        bundle.connection_tables.iter().map(|t| &t.name),
+    )?;
+    check_unique("UDF", bundle.udfs.iter().map(|u| &u.name))?;
+    let pipeline_keys: Vec<_> = bundle
+        .pipelines
+        .iter()
+        .map(|p| format!("{}/{}", spec_namespace(p), p.name))
+        .collect();
+    check_unique("pipeline", pipeline_keys.iter())?;
+
+    Ok(bundle)
+}
+
+/// Plans the changes needed to make the cluster match the bundle and, unless `dry_run`, makes
+/// them, reporting each change once it has been made
+async fn execute_bundle(
+    state: &AppState,
+    bearer_auth: &BearerAuth,
+    auth_data: &AuthData,
+    bundle: &PipelineBundle,
+    prune: bool,
+    dry_run: bool,
+    report: &mut (dyn FnMut(BundleChange) + Send),
+) -> Result<(), ErrorResp> {
+    let db = state.database.client().await?;
+
+    let profiles = get_all_connection_profiles(auth_data, &db).await?;
+    let tables = get_all_connection_tables(auth_data, &db).await?;
+    let udfs = get_all_udfs(auth_data, &db).await?;
+    let pipelines = get_all_pipelines(auth_data, &db).await?;
+
+    // plan every change before making any, so that conflicts leave the cluster untouched
+    let mut profile_ids: HashMap<String, String> = HashMap::new();
+    let mut profile_plan = vec![];
+    for spec in &bundle.connection_profiles {
+        match profiles.iter().find(|p| p.name == spec.name) {
+            Some(existing) => {
+                if existing.connector != spec.connector || existing.config != spec.config {
+                    return Err(bad_request(format!(
+                        "Connection profile '{}' already exists with a different configuration; \
+                        connection profiles can't be modified, so it must be deleted or the \
+                        profile in the bundle renamed",
+                        spec.name
+                    )));
+                }
+                profile_ids.insert(spec.name.clone(), existing.id.clone());
+                profile_plan.push((spec, Some(existing)));
+            }
+            None => profile_plan.push((spec, None)),
+        }
+    }
+
+    let mut table_plan = vec![];
+    for spec in &bundle.connection_tables {
+        if let Some(profile) = &spec.connection_profile {
+            if !profile_ids.contains_key(profile)
+                && !bundle
+                    .connection_profiles
+                    .iter()
+                    .any(|p| &p.name == profile)
+            {
+                return Err(bad_request(format!(
+                    "Connection table '{}' uses connection profile '{}', which isn't in the bundle",
+                    spec.name, profile
+                )));
+            }
+        }
+
+        match tables.iter().find(|t| t.name == spec.name) {
+            Some(existing) => {
+                if existing.connector != spec.connector
+                    || existing.config != spec.config
+                    || existing.connection_profile.as_ref().map(|p| &p.name)
+                        != spec.connection_profile.as_ref()
+                {
+                    return Err(bad_request(format!(
+                        "Connection table '{}' already exists with a different configuration; \
+                        connection tables can't be modified, so it must be deleted or the table \
+                        in the bundle renamed",
+                        spec.name
+                    )));
+                }
+                table_plan.push((spec, Some(existing)));
+            }
+            None => table_plan.push((spec, None)),
+        }
+    }
+
+    let udf_plan: Vec<_> = bundle
+        .udfs
+        .iter()
+        .map(|spec| (spec, udfs.iter().find(|u| u.name == spec.name)))
+        .collect();
+
+    // pipelines are matched by namespace and name; if several match, the newest is the one the
+    // bundle manages, and the others are left over from earlier replacements
+    let mut current: HashMap<(&str, &str), &Pipeline> = HashMap::new();
+    for pipeline in &pipelines {
+        current
+            .entry((pipeline.namespace.as_str(), pipeline.name.as_str()))
+            .or_insert(pipeline);
+    }
+
+    let mut pipeline_plan = vec![];
+    for spec in &bundle.pipelines {
+        let plan = match current.get(&(spec_namespace(spec), spec.name.as_str())) {
+            None => PipelinePlan::Create,
+            Some(existing) if needs_replace(spec, existing) => PipelinePlan::Replace { existing },
+            Some(existing) => {
+                let udfs = udfs_put(spec, existing);
+                let patch = pipeline_patch(spec, existing);
+                if udfs.is_none() && patch.is_none() {
+                    PipelinePlan::Unchanged { existing }
+                } else {
+                    PipelinePlan::Update {
+                        existing,
+                        udfs,
+                        patch,
+                    }
+                }
+            }
+        };
+        pipeline_plan.push((spec, plan));
+    }
+
+    // only namespaces that the bundle has pipelines in are pruned, so that bundles for different
+    // namespaces can be applied to the same cluster
+    let namespaces: HashSet<&str> = bundle.pipelines.iter().map(spec_namespace).collect();
+    let keys: HashSet<(&str, &str)> = bundle
+        .pipelines
+        .iter()
+        .map(|p| (spec_namespace(p), p.name.as_str()))
+        .collect();
+    let pruned: Vec<&Pipeline> = if prune {
+        pipelines
+            .iter()
+            .filter(|p| namespaces.contains(p.namespace.as_str()))
+            .filter(|p| {
+                let key = (p.namespace.as_str(), p.name.as_str());
+                !keys.contains(&key) || current[&key].id != p.id
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+
+    for (spec, existing) in profile_plan {
+        let id = match existing {
+            Some(existing) => {
+                report(change(
+                    BundleResourceType::ConnectionProfile,
+                    &spec.name,
+                    BundleAction::Unchanged,
+                    Some(&existing.id),
+                ));
+                continue;
+            }
+            None if dry_run => None,
+            None => {
+                let Json(profile) = create_connection_profile(
+                    State(state.clone()),
+                    bearer_auth.clone(),
+                    WithRejection(
+                        Json(ConnectionProfilePost {
+                            name: spec.name.clone(),
+                            connector: spec.connector.clone(),
+                            config: spec.config.clone(),
+                            namespace: spec.namespace.clone(),
+                        }),
+                        PhantomData,
+                    ),
+                )
+                .await?;
+                profile_ids.insert(spec.name.clone(), profile.id.clone());
+                Some(profile.id)
+            }
+        };
+        report(change(
+            BundleResourceType::ConnectionProfile,
+            &spec.name,
+            BundleAction::Create,
+            id.as_deref(),
+        ));
+    }
+
+    for (spec, existing) in table_plan {
+        let id = match existing {
+            Some(existing) => {
+                report(change(
+                    BundleResourceType::ConnectionTable,
+                    &spec.name,
+                    BundleAction::Unchanged,
+                    Some(&existing.pub_id),
+                ));
+                continue;
+            }
+            None if dry_run => None,
+            None => {
+                let Json(table) = create_connection_table(
+                    State(state.clone()),
+                    bearer_auth.clone(),
+                    WithRejection(
+                        Json(ConnectionTablePost {
+                            name: spec.name.clone(),
+                            connector: spec.connector.clone(),
+                            connection_profile_id: spec
+                                .connection_profile
+                                .as_ref()
+                                .and_then(|p| profile_ids.get(p).cloned()),
+                            config: spec.config.clone(),
+                            schema: spec.schema.clone(),
+                            namespace: spec.namespace.clone(),
+                        }),
+                        PhantomData,
+                    ),
+                )
+                .await?;
+                Some(table.pub_id)
+            }
+        };
+        report(change(
+            BundleResourceType::ConnectionTable,
+            &spec.name,
+            BundleAction::Create,
+            id.as_deref(),
+        ));
+    }
+
+    for (spec, existing) in udf_plan {
+        match existing {
+            Some(existing) if existing.definition == spec.definition => {
+                report(change(
+                    BundleResourceType::Udf,
+                    &spec.name,
+                    BundleAction::Unchanged,
+                    Some(&existing.id),
+                ));
+            }
+            Some(existing) => {
+                if !dry_run {
+                    create_udf_version(
+                        State(state.clone()),
+                        bearer_auth.clone(),
+                        Path(existing.id.clone()),
+                        WithRejection(
+                            Json(UdfVersionPost {
+                                definition: spec.definition.clone(),
+                                description: spec.description.clone(),
+                            }),
+                            PhantomData,
+                        ),
+                    )
+                    .await?;
+                }
+                let mut c = change(
+                    BundleResourceType::Udf,
+                    &spec.name,
+                    BundleAction::Update,
+                    Some(&existing.id),
+                );
+                c.message = Some("published a new version".to_string());
+                report(c);
+            }
+            None => {
+                let id = if dry_run {
+                    None
+                } else {
+                    let Json(udf) = create_udf(
+                        State(state.clone()),
+                        bearer_auth.clone(),
+                        WithRejection(
+                            Json(UdfPost {
+                                prefix: spec.prefix.clone(),
+                                definition: spec.definition.clone(),
+                                description: spec.description.clone(),
+                                namespace: spec.namespace.clone(),
+                            }),
+                            PhantomData,
+                        ),
+                    )
+                    .await?;
+                    if udf.name != spec.name {
+                        warn!(
+                            "UDF '{}' in bundle was created with name '{}'",
+                            spec.name, udf.name
+                        );
+                    }
+                    Some(udf.id)
+                };
+                report(change(
+                    BundleResourceType::Udf,
+                    &spec.name,
+                    BundleAction::Create,
+                    id.as_deref(),
+                ));
+            }
+        }
+    }
+
+    for (spec, plan) in pipeline_plan {
+        let c = match plan {
+            PipelinePlan::Create => {
+                let id = if dry_run {
+                    None
+                } else {
+                    Some(
+                        create_pipeline_from_post(
+                            state,
+                            auth_data.clone(),
+                            pipeline_post(spec),
+                            spec.stopped,
+                        )
+                        .await?
+                        .id,
+                    )
+                };
+                change(
+                    BundleResourceType::Pipeline,
+                    &spec.name,
+                    BundleAction::Create,
+                    id.as_deref(),
+                )
+            }
+            PipelinePlan::Unchanged { existing } => change(
+                BundleResourceType::Pipeline,
+                &spec.name,
+                BundleAction::Unchanged,
+                Some(&existing.id),
+            ),
+            PipelinePlan::Update {
+                existing,
+                udfs,
+                patch,
+            } => {
+                if !dry_run {
+                    if let Some(udfs) = udfs {
+                        put_pipeline_udfs(
+                            State(state.clone()),
+                            bearer_auth.clone(),
+                            Path(existing.id.clone()),
+                            WithRejection(Json(udfs), PhantomData),
+                        )
+                        .await?;
+                    }
+                    if let Some(patch) = patch {
+                        patch_pipeline(
+                            State(state.clone()),
+                            bearer_auth.clone(),
+                            Path(existing.id.clone()),
+                            WithRejection(Json(patch), PhantomData),
+                        )
+                        .await?;
+                    }
+                }
+                change(
+                    BundleResourceType::Pipeline,
+                    &spec.name,
+                    BundleAction::Update,
+                    Some(&existing.id),
+                )
+            }
+            PipelinePlan::Replace { existing } => {
+                let mut c = change(
+                    BundleResourceType::Pipeline,
+                    &spec.name,
+                    BundleAction::Replace,
+                    None,
+                );
+                c.message = Some(format!("replaces pipeline {}", existing.id));
+                if !dry_run {
+                    let id =
+                        replace_pipeline(state, bearer_auth, auth_data, spec, existing).await?;
+                    c.id = Some(id);
+                }
+                c
+            }
+        };
+        report(c);
+    }
+
+    for pipeline in pruned {
+        let mut c = change(
+            BundleResourceType::Pipeline,
+            &pipeline.name,
+            BundleAction::Delete,
+            Some(&pipeline.id),
+        );
+        if !dry_run {
+            let job_state = pipeline_job_state(&db, auth_data, &pipeline.id).await?;
+            if job_state.as_deref().map_or(true, is_terminal) {
+                delete_pipeline(
+                    State(state.clone()),
+                    bearer_auth.clone(),
+                    Path(pipeline.id.clone()),
+                )
+                .await?;
+            } else {
+                if !is_stopped(&pipeline.stop) {
+                    patch_pipeline(
+                        State(state.clone()),
+                        bearer_auth.clone(),
+                        Path(pipeline.id.clone()),
+                        WithRejection(Json(stop_patch(StopType::Checkpoint)), PhantomData),
+                    )
+                    .await?;
+                }
+                c.message =
+                    Some("stopping; it will be deleted by a later apply once stopped".to_string());
+            }
+        }
+        report(c);
+    }
+
+    Ok(())
+}
+
+fn stop_patch(stop: StopType) -> PipelinePatch {
+    PipelinePatch {
+        parallelism: None,
+        checkpoint_interval_micros: None,
+        stop: Some(stop),
+        priority: None,
+        worker_pod: None,
+        freshness_slo: None,
+        restart_strategy: None,
+    }
+}
+
+async fn pipeline_job(
+    db: &Database<'_>,
+    auth_data: &AuthData,
+    pipeline_id: &str,
+) -> Result<(String, Option<String>), ErrorResp> {
+    let job = api_queries::fetch_get_pipeline_jobs(
+        db,
+        &auth_data.organization_id,
+        &pipeline_id.to_string(),
+    )
+    .await?
+    .into_iter()
+    .next()
+    .ok_or_else(|| internal_server_error(format!("Pipeline {} has no jobs", pipeline_id)))?;
+
+    Ok((job.id, job.state))
+}
+
+async fn pipeline_job_state(
+    db: &Database<'_>,
+    auth_data: &AuthData,
+    pipeline_id: &str,
+) -> Result<Option<String>, ErrorResp> {
+    Ok(pipeline_job(db, auth_data, pipeline_id).await?.1)
+}
+
+async fn last_completed_epoch(
+    db: &Database<'_>,
+    auth_data: &AuthData,
+    job_id: &str,
+) -> Result<Option<u32>, ErrorResp> {
+    Ok(api_queries::fetch_get_last_completed_checkpoint_epoch(
+        db,
+        &job_id.to_string(),
+        &auth_data.organization_id,
+    )
+    .await?
+    .into_iter()
+    .next()
+    .flatten()
+    .map(|e| e as u32))
+}
+
+/// Replaces a pipeline with a new one created from the spec that starts from its state. The new
+/// pipeline is created stopped, and the migration of the old pipeline's state into it is checked
+/// before the old pipeline is stopped, so that an incompatible query leaves the old pipeline
+/// running. Returns the id of the new pipeline.
+///
+/// If the replacement fails at any point, the new pipeline is deleted and the old one restarted;
+/// otherwise the new pipeline, as the newest, would be taken as current by the next apply, and the
+/// old one pruned.
+async fn replace_pipeline(
+    state: &AppState,
+    bearer_auth: &BearerAuth,
+    auth_data: &AuthData,
+    spec: &PipelineSpec,
+    existing: &Pipeline,
+) -> Result<String, ErrorResp> {
+    let new =
+        create_pipeline_from_post(state, auth_data.clone(), pipeline_post(spec), true).await?;
+
+    if let Err(e) = replace_with(state, bearer_auth, auth_data, spec, existing, &new.id).await {
+        // the new pipeline has never run, so it can be removed regardless of its job's state
+        let db = state.database.client().await?;
+        if let Err(delete_err) =
+            api_queries::execute_delete_pipeline(&db, &new.id, &auth_data.organization_id).await
+        {
+            warn!(
+                "Failed to delete pipeline {} after failing to replace pipeline {}: {:?}",
+                new.id, existing.id, delete_err
+            );
+        }
+        return Err(e);
+    }
+
+    Ok(new.id)
+}
+
+async fn replace_with(
+    state: &AppState,
+    bearer_auth: &BearerAuth,
+    auth_data: &AuthData,
+    spec: &PipelineSpec,
+    existing: &Pipeline,
+    new_id: &str,
+) -> Result<(), ErrorResp> {
+    let db = state.database.client().await?;
+
+    let (new_job_id, _) = pipeline_job(&db, auth_data, new_id).await?;
+    let (old_job_id, _) = pipeline_job(&db, auth_data, &existing.id).await?;
+
+    let migration = |source_epoch, dry_run| StateMigrationPost {
+        source_job_id: old_job_id.clone(),
+        source_epoch,
+        mappings: vec![],
+        dry_run,
+    };
+
+    if let Some(epoch) = last_completed_epoch(&db, auth_data, &old_job_id).await? {
+        migrate_state_int(&db, auth_data, &new_job_id, &migration(epoch, true)).await?;
+    }
+
+    let was_running = !is_stopped(&existing.stop);
+
+    // take a final checkpoint, so that no data is lost in the replacement
+    if was_running {
+        patch_pipeline(
+            State(state.clone()),
+            bearer_auth.clone(),
+            Path(existing.id.clone()),
+            WithRejection(Json(stop_patch(StopType::Checkpoint)), PhantomData),
+        )
+        .await?;
+    }
+
+    let result = async {
+        let deadline = tokio::time::Instant::now() + REPLACE_STOP_TIMEOUT;
+        loop {
+            let job_state = pipeline_job_state(&db, auth_data, &existing.id).await?;
+            if job_state.as_deref().map_or(true, is_terminal) {
+                break;
+            }
+            if tokio::time::Instant::now() > deadline {
+                return Err(internal_server_error(format!(
+                    "Timed out waiting for pipeline {} to stop",
+                    existing.id
+                )));
+            }
+            tokio::time::sleep(REPLACE_POLL_INTERVAL).await;
+        }
+
+        if let Some(epoch) = last_completed_epoch(&db, auth_data, &old_job_id).await? {
+            migrate_state_int(&db, auth_data, &new_job_id, &migration(epoch, false)).await?;
+        }
+
+        if !spec.stopped {
+            patch_pipeline(
+                State(state.clone()),
+                bearer_auth.clone(),
+                Path(new_id.to_string()),
+                WithRejection(Json(stop_patch(StopType::None)), PhantomData),
+            )
+            .await?;
+        }
+
+        Ok::<_, ErrorResp>(())
+    }
+    .await;
+
+    if result.is_err() && was_running {
+        if let Err(e) = patch_pipeline(
+            State(state.clone()),
+            bearer_auth.clone(),
+            Path(existing.id.clone()),
+            WithRejection(Json(stop_patch(StopType::None)), PhantomData),
+        )
+        .await
+        {
+            warn!(
+                "Failed to restart pipeline {} after failing to replace it: {}",
+                existing.id, e.message
+            );
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_bundle_defaults() {
+        let bundle: PipelineBundle = serde_yaml::from_str(
+            r#"
+version: 1
+pipelines:
+  - name: orders
+    query: SELECT * FROM orders
+    parallelism: 2
+"#,
+        )
+        .unwrap();
+
+        assert!(bundle.connection_profiles.is_empty());
+        let spec = &bundle.pipelines[0];
+        assert!(!spec.stopped);
+        assert!(spec.udf_versions.is_empty());
+
+        let post = pipeline_post(spec);
+        assert_eq!(post.parallelism, 2);
+        assert_eq!(post.resource_classes, Some(BTreeMap::new()));
+        assert_eq!(spec_namespace(spec), DEFAULT_NAMESPACE);
+    }
+
+    #[test]
+    fn test_check_unique() {
+        let names = ["a".to_string(), "b".to_string(), "a".to_string()];
+        assert!(check_unique("pipeline", names[..2].iter()).is_ok());
+        assert!(check_unique("pipeline", names.iter()).is_err());
+    }
+}
@@ -0,0 +1,505 @@
+use crate::audit_log;
+use crate::audit_log::catalog_table_state;
+use crate::namespaces::{resolve_namespace, visible_in};
+use crate::pipelines::schema_provider;
+use crate::queries::api_queries;
+use crate::queries::api_queries::{
+    DbCatalogTable, DbCatalogTableVersion, GetCatalogTablePipelines,
+};
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, internal_server_error, map_insert_err, not_found, ApiError,
+    BearerAuth, ErrorResp,
+};
+use crate::{to_micros, AuthData};
+use arroyo_df::ArroyoSchemaProvider;
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{AuditAction, AuditResourceType};
+use arroyo_rpc::api_types::catalog::{
+    CatalogTable, CatalogTablePipeline, CatalogTablePost, CatalogTableVersion,
+    CatalogTableVersionPost,
+};
+use arroyo_rpc::api_types::{
+    CatalogTableCollection, CatalogTablePipelineCollection, CatalogTableVersionCollection,
+};
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use cornucopia_async::{Database, DatabaseSource};
+use http::StatusCode;
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+use tracing::warn;
+
+impl From<DbCatalogTable> for CatalogTable {
+    fn from(val: DbCatalogTable) -> Self {
+        CatalogTable {
+            id: val.pub_id,
+            name: val.name,
+            definition: val.definition,
+            description: val.description,
+            namespace: val.namespace,
+            version: val.version,
+            pipeline_count: val.pipeline_count as u32,
+            created_at: to_micros(val.created_at),
+            updated_at: to_micros(val.updated_at),
+        }
+    }
+}
+
+impl From<DbCatalogTableVersion> for CatalogTableVersion {
+    fn from(val: DbCatalogTableVersion) -> Self {
+        CatalogTableVersion {
+            version: val.version,
+            created_at: to_micros(val.created_at),
+            definition: val.definition,
+            description: val.description,
+        }
+    }
+}
+
+impl From<GetCatalogTablePipelines> for CatalogTablePipeline {
+    fn from(val: GetCatalogTablePipelines) -> Self {
+        CatalogTablePipeline {
+            pipeline_id: val.pub_id,
+            pipeline_name: val.name,
+            version: val.version,
+        }
+    }
+}
+
+/// Adds the catalog tables visible in `namespace` to the schema provider, at the versions pinned
+/// in `catalog_versions` or otherwise at their latest versions, returning the version of each
+/// table that was added
+pub(crate) async fn add_catalog_tables(
+    db: &Database<'_>,
+    organization_id: &str,
+    namespace: &str,
+    catalog_versions: &BTreeMap<String, i32>,
+    schema_provider: &mut ArroyoSchemaProvider,
+) -> Result<BTreeMap<String, i32>, ErrorResp> {
+    let mut versions = BTreeMap::new();
+
+    for table in api_queries::fetch_get_catalog_tables(db, organization_id)
+        .await?
+        .into_iter()
+        .filter(|t| visible_in(&t.namespace, namespace))
+    {
+        let (version, definition) = match catalog_versions.get(&table.name) {
+            Some(version) if *version != table.version => {
+                let pinned = api_queries::fetch_get_catalog_table_version(
+                    db,
+                    organization_id,
+                    &table.pub_id,
+                    version,
+                )
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| {
+                    bad_request(format!(
+                        "Version {} of catalog table {} does not exist",
+                        version, table.name
+                    ))
+                })?;
+                (pinned.version, pinned.definition)
+            }
+            _ => (table.version, table.definition),
+        };
+
+        match schema_provider.add_catalog_table(&definition) {
+            Ok(name) => {
+                versions.insert(name, version);
+            }
+            Err(e) => warn!("Invalid catalog table {}: {}", table.name, e),
+        }
+    }
+
+    Ok(versions)
+}
+
+/// Checks that a catalog definition plans against the rest of the catalog, returning the name
+/// of the table it defines
+async fn validate_definition(
+    definition: &str,
+    namespace: &str,
+    auth_data: &AuthData,
+    db: &DatabaseSource,
+) -> Result<String, ErrorResp> {
+    let (mut schema_provider, _) = schema_provider(
+        &vec![],
+        &mut BTreeMap::new(),
+        &BTreeMap::new(),
+        namespace,
+        auth_data,
+        true,
+        db,
+    )
+    .await?;
+
+    let name = schema_provider
+        .add_catalog_table(definition)
+        .map_err(|e| bad_request(format!("Invalid catalog table: {}", e)))?;
+
+    arroyo_df::catalog::plan_catalog_table(&name, schema_provider)
+        .map_err(|e| bad_request(format!("Invalid catalog table {}: {}", name, e)))?;
+
+    Ok(name)
+}
+
+async fn get_catalog_table(
+    db: &Database<'_>,
+    organization_id: &str,
+    pub_id: &str,
+) -> Result<CatalogTable, ErrorResp> {
+    Ok(
+        api_queries::fetch_get_catalog_table(db, organization_id, &pub_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_found("Catalog table"))?
+            .into(),
+    )
+}
+
+/// Create a catalog table
+///
+/// Adds a `CREATE TABLE` or `CREATE VIEW` definition to the catalog, so that pipelines in the
+/// namespace can read the table by name without defining it in their queries.
+#[utoipa::path(
+    post,
+    path = "/v1/catalog",
+    tag = "catalog",
+    request_body = CatalogTablePost,
+    responses(
+        (status = 200, description = "Created catalog table", body = CatalogTable),
+    ),
+)]
+pub async fn create_catalog_table(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(req), _): WithRejection<Json<CatalogTablePost>, ApiError>,
+) -> Result<Json<CatalogTable>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let client = state.database.client().await?;
+
+    let (namespace, _) = resolve_namespace(&auth_data, &client, req.namespace.as_deref()).await?;
+    auth_data.require_namespace(&namespace)?;
+
+    let name =
+        validate_definition(&req.definition, &namespace, &auth_data, &state.database).await?;
+    let description = req.description.unwrap_or_default();
+
+    let pub_id = generate_id(IdTypes::CatalogTable);
+    let created = audit_log::transaction(&state.database, async |client| {
+        api_queries::execute_create_catalog_table(
+            client,
+            &pub_id,
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &name,
+            &req.definition,
+            &description,
+            &namespace,
+        )
+        .await
+        .map_err(|e| map_insert_err("catalog table", e))?;
+
+        api_queries::execute_create_catalog_table_version(
+            client,
+            &pub_id,
+            &1,
+            &auth_data.user_id,
+            &req.definition,
+            &description,
+        )
+        .await?;
+
+        let created = get_catalog_table(client, &auth_data.organization_id, &pub_id)
+            .await
+            .map_err(|_| internal_server_error("Failed to fetch created catalog table"))?;
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Create,
+            AuditResourceType::CatalogTable,
+            &pub_id,
+            None,
+            Some(catalog_table_state(&created)),
+        )
+        .await?;
+
+        Ok(created)
+    })
+    .await?;
+
+    Ok(Json(created))
+}
+
+/// List the tables in the catalog
+#[utoipa::path(
+    get,
+    path = "/v1/catalog",
+    tag = "catalog",
+    responses(
+        (status = 200, description = "List of catalog tables", body = CatalogTableCollection),
+    ),
+)]
+pub async fn get_catalog_tables(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+) -> Result<Json<CatalogTableCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let tables = api_queries::fetch_get_catalog_tables(
+        &state.database.client().await?,
+        &auth_data.organization_id,
+    )
+    .await?;
+
+    Ok(Json(CatalogTableCollection {
+        data: tables.into_iter().map(|t| t.into()).collect(),
+    }))
+}
+
+/// Publish a new version of a catalog table
+///
+/// The new definition becomes the latest version of the table, which new pipelines use.
+/// Existing pipelines keep the version they were created with.
+#[utoipa::path(
+    post,
+    path = "/v1/catalog/{id}/versions",
+    tag = "catalog",
+    params(
+        ("id" = String, Path, description = "Catalog table id")
+    ),
+    request_body = CatalogTableVersionPost,
+    responses(
+        (status = 200, description = "Updated catalog table", body = CatalogTable),
+    ),
+)]
+pub async fn create_catalog_table_version(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+    WithRejection(Json(req), _): WithRejection<Json<CatalogTableVersionPost>, ApiError>,
+) -> Result<Json<CatalogTable>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let client = state.database.client().await?;
+
+    let table = get_catalog_table(&client, &auth_data.organization_id, &pub_id).await?;
+    auth_data.require_namespace(&table.namespace)?;
+
+    let name = validate_definition(
+        &req.definition,
+        &table.namespace,
+        &auth_data,
+        &state.database,
+    )
+    .await?;
+
+    // queries reference catalog tables by name, so it can't change between versions
+    if name != table.name {
+        return Err(bad_request(format!(
+            "New versions of catalog table {} must keep its name, but the definition is for {}",
+            table.name, name
+        )));
+    }
+
+    let description = req
+        .description
+        .or_else(|| table.description.clone())
+        .unwrap_or_default();
+    let version = table.version + 1;
+
+    let updated_table = audit_log::transaction(&state.database, async |client| {
+        let updated = api_queries::execute_update_catalog_table(
+            client,
+            &req.definition,
+            &description,
+            &version,
+            &OffsetDateTime::now_utc(),
+            &auth_data.organization_id,
+            &pub_id,
+            &table.version,
+        )
+        .await?;
+
+        if updated != 1 {
+            return Err(ErrorResp {
+                status_code: StatusCode::CONFLICT,
+                message: format!(
+                    "Catalog table {} was modified concurrently; retry the request",
+                    table.name
+                ),
+            });
+        }
+
+        api_queries::execute_create_catalog_table_version(
+            client,
+            &pub_id,
+            &version,
+            &auth_data.user_id,
+            &req.definition,
+            &description,
+        )
+        .await?;
+
+        let updated_table = get_catalog_table(client, &auth_data.organization_id, &pub_id)
+            .await
+            .map_err(|_| internal_server_error("Failed to fetch updated catalog table"))?;
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Update,
+            AuditResourceType::CatalogTable,
+            &pub_id,
+            Some(catalog_table_state(&table)),
+            Some(catalog_table_state(&updated_table)),
+        )
+        .await?;
+
+        Ok(updated_table)
+    })
+    .await?;
+
+    Ok(Json(updated_table))
+}
+
+/// Get the versions of a catalog table
+#[utoipa::path(
+    get,
+    path = "/v1/catalog/{id}/versions",
+    tag = "catalog",
+    params(
+        ("id" = String, Path, description = "Catalog table id")
+    ),
+    responses(
+        (status = 200, description = "Versions of the table, newest first", body = CatalogTableVersionCollection),
+    ),
+)]
+pub async fn get_catalog_table_versions(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<Json<CatalogTableVersionCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let versions = api_queries::fetch_get_catalog_table_versions(
+        &state.database.client().await?,
+        &auth_data.organization_id,
+        &pub_id,
+    )
+    .await?;
+
+    if versions.is_empty() {
+        return Err(not_found("Catalog table"));
+    }
+
+    Ok(Json(CatalogTableVersionCollection {
+        data: versions.into_iter().map(|v| v.into()).collect(),
+    }))
+}
+
+/// Get the pipelines that read a catalog table
+#[utoipa::path(
+    get,
+    path = "/v1/catalog/{id}/pipelines",
+    tag = "catalog",
+    params(
+        ("id" = String, Path, description = "Catalog table id")
+    ),
+    responses(
+        (status = 200, description = "Pipelines that read the table", body = CatalogTablePipelineCollection),
+    ),
+)]
+pub async fn get_catalog_table_pipelines(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<Json<CatalogTablePipelineCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let client = state.database.client().await?;
+
+    // check that the table exists
+    get_catalog_table(&client, &auth_data.organization_id, &pub_id).await?;
+
+    let pipelines = api_queries::fetch_get_catalog_table_pipelines(
+        &client,
+        &auth_data.organization_id,
+        &pub_id,
+    )
+    .await?;
+
+    Ok(Json(CatalogTablePipelineCollection {
+        data: pipelines.into_iter().map(|p| p.into()).collect(),
+    }))
+}
+
+/// Delete a catalog table
+///
+/// Tables that are still read by pipelines can't be deleted.
+#[utoipa::path(
+    delete,
+    path = "/v1/catalog/{id}",
+    tag = "catalog",
+    params(
+        ("id" = String, Path, description = "Catalog table id")
+    ),
+    responses(
+        (status = 200, description = "Deleted catalog table"),
+    ),
+)]
+pub async fn delete_catalog_table(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let client = state.database.client().await?;
+
+    let table = get_catalog_table(&client, &auth_data.organization_id, &pub_id).await?;
+    auth_data.require_namespace(&table.namespace)?;
+
+    if table.pipeline_count > 0 {
+        return Err(bad_request(format!(
+            "Cannot delete catalog table {}; it is still being used by {} pipelines",
+            table.name, table.pipeline_count
+        )));
+    }
+
+    audit_log::transaction(&state.database, async |client| {
+        let count =
+            api_queries::execute_delete_catalog_table(client, &auth_data.organization_id, &pub_id)
+                .await?;
+
+        if count != 1 {
+            return Err(not_found("Catalog table"));
+        }
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Delete,
+            AuditResourceType::CatalogTable,
+            &pub_id,
+            Some(catalog_table_state(&table)),
+            None,
+        )
+        .await?;
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(())
+}
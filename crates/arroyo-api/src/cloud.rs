@@ -1,24 +1,95 @@
+use crate::api_keys::{legacy_digest, parse_key, parse_scope, secrets_match, verify_key};
+use crate::queries::api_queries;
+use crate::rest_utils::unauthorized;
 use crate::{rest_utils::ErrorResp, AuthData, OrgMetadata};
+use arroyo_rpc::api_types::api_keys::{ApiKeyScope, Role};
+use arroyo_rpc::config::config;
 use axum::headers::authorization::{Authorization, Bearer};
 use axum::TypedHeader;
 use cornucopia_async::Database;
+use std::str::FromStr;
+use tracing::warn;
+
+fn unlimited_org_metadata() -> OrgMetadata {
+    OrgMetadata {
+        can_create_programs: true,
+        max_nexmark_qps: f64::MAX,
+        max_impulse_qps: f64::MAX,
+        max_parallelism: u32::MAX,
+        max_operators: u32::MAX,
+        max_running_jobs: u32::MAX,
+        kafka_qps: u32::MAX,
+    }
+}
+
+fn admin(user_id: &str) -> AuthData {
+    AuthData {
+        user_id: user_id.to_string(),
+        organization_id: "org".to_string(),
+        role: Role::Admin,
+        scope: ApiKeyScope::default(),
+        org_metadata: unlimited_org_metadata(),
+    }
+}
 
 pub(crate) async fn authenticate(
-    _client: &Database<'_>,
-    _bearer_auth: Option<TypedHeader<Authorization<Bearer>>>,
+    client: &Database<'_>,
+    bearer_auth: Option<TypedHeader<Authorization<Bearer>>>,
 ) -> Result<AuthData, ErrorResp> {
+    let Some(TypedHeader(Authorization(bearer))) = bearer_auth else {
+        // unauthenticated requests bypass the roles and scopes of API keys, so installations
+        // that rely on those must opt in to requiring a key
+        if config().api.require_api_key {
+            return Err(unauthorized("An API key is required"));
+        }
+
+        return Ok(admin("user"));
+    };
+
+    let token = bearer.token();
+
+    if let Some(admin_key) = &config().api.admin_key {
+        if secrets_match(token, admin_key) {
+            return Ok(admin("admin"));
+        }
+    }
+
+    let key = match parse_key(token) {
+        Some((pub_id, secret)) => api_queries::fetch_get_api_key(client, &pub_id)
+            .await?
+            .into_iter()
+            .next()
+            .filter(|k| verify_key(secret, k)),
+        None => None,
+    };
+
+    let key = match key {
+        Some(key) => key,
+        // keys created before keys were hashed are presented whole, and stored as a digest
+        None => api_queries::fetch_get_api_key_by_digest(client, &legacy_digest(token))
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| unauthorized("Invalid API key"))?,
+    };
+
+    let (role, scope) = Role::from_str(&key.role)
+        .and_then(|role| {
+            Ok((
+                role,
+                parse_scope(key.scope_pipelines, key.scope_namespaces)?,
+            ))
+        })
+        .map_err(|e| {
+            warn!("API key {} is invalid: {}", key.pub_id, e);
+            unauthorized("Invalid API key")
+        })?;
+
     Ok(AuthData {
-        user_id: "user".to_string(),
-        organization_id: "org".to_string(),
-        role: "admin".to_string(),
-        org_metadata: OrgMetadata {
-            can_create_programs: true,
-            max_nexmark_qps: f64::MAX,
-            max_impulse_qps: f64::MAX,
-            max_parallelism: u32::MAX,
-            max_operators: u32::MAX,
-            max_running_jobs: u32::MAX,
-            kafka_qps: u32::MAX,
-        },
+        user_id: key.user_id,
+        organization_id: key.organization_id,
+        role,
+        scope,
+        org_metadata: unlimited_org_metadata(),
     })
 }
@@ -1,9 +1,12 @@
 use axum::extract::{Path, State};
 use axum::Json;
 use axum_extra::extract::WithRejection;
+use serde_json::json;
 use std::collections::BTreeMap;
 
 use arroyo_connectors::connector_for_type;
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{AuditAction, AuditResourceType};
 use arroyo_rpc::api_types::connections::{
     ConnectionAutocompleteResp, ConnectionProfile, ConnectionProfilePost, TestSourceMessage,
 };
@@ -11,7 +14,11 @@ use arroyo_rpc::api_types::ConnectionProfileCollection;
 use tracing::warn;
 
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_rpc::secrets::resolve_secrets;
 
+use crate::audit_log;
+use crate::audit_log::redact;
+use crate::namespaces::resolve_namespace;
 use crate::queries::api_queries;
 use crate::queries::api_queries::DbConnectionProfile;
 use crate::rest::AppState;
@@ -43,6 +50,7 @@ impl TryFrom<DbConnectionProfile> for ConnectionProfile {
             connector: val.r#type,
             config: val.config,
             description,
+            namespace: val.namespace,
         })
     }
 }
@@ -67,6 +75,10 @@ pub async fn test_connection_profile(
     let connector = connector_for_type(&req.connector)
         .ok_or_else(|| bad_request("Unknown connector type".to_string()))?;
 
+    resolve_secrets(&req.config.to_string())
+        .await
+        .map_err(|e| bad_request(format!("{:#}", e)))?;
+
     let Some(rx) = connector
         .test_profile(&req.config)
         .map_err(|e| bad_request(format!("Invalid config: {:?}", e)))?
@@ -97,36 +109,65 @@ pub async fn create_connection_profile(
     WithRejection(Json(req), _): WithRejection<Json<ConnectionProfilePost>, ApiError>,
 ) -> Result<Json<ConnectionProfile>, ErrorResp> {
     let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
 
     connector_for_type(&req.connector)
         .ok_or_else(|| bad_request("Unknown connector type".to_string()))?
         .validate_config(&req.config)
         .map_err(|e| bad_request(format!("Invalid config: {:?}", e)))?;
 
+    let client = state.database.client().await?;
+
+    let (namespace, _) = resolve_namespace(&auth_data, &client, req.namespace.as_deref()).await?;
+    auth_data.require_namespace(&namespace)?;
+
     let pub_id = generate_id(IdTypes::ConnectionProfile);
-    api_queries::execute_create_connection_profile(
-        &state.database.client().await?,
-        &pub_id,
-        &auth_data.organization_id,
-        &auth_data.user_id,
-        &req.name,
-        &req.connector,
-        &req.config,
-    )
+    let connection_profile = audit_log::transaction(&state.database, async |client| {
+        api_queries::execute_create_connection_profile(
+            client,
+            &pub_id,
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &req.name,
+            &req.connector,
+            &req.config,
+            &namespace,
+        )
+        .await?;
+
+        let connection_profile: ConnectionProfile =
+            api_queries::fetch_get_connection_profile_by_pub_id(
+                client,
+                &auth_data.organization_id,
+                &pub_id,
+            )
+            .await?
+            .into_iter()
+            .next()
+            .unwrap()
+            .try_into()
+            .map_err(log_and_map)?;
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Create,
+            AuditResourceType::ConnectionProfile,
+            &pub_id,
+            None,
+            Some(json!({
+                "name": req.name,
+                "connector": req.connector,
+                "config": redact(&req.config),
+                "namespace": namespace,
+            })),
+        )
+        .await?;
+
+        Ok(connection_profile)
+    })
     .await?;
 
-    let connection_profile = api_queries::fetch_get_connection_profile_by_pub_id(
-        &state.database.client().await?,
-        &auth_data.organization_id,
-        &pub_id,
-    )
-    .await?
-    .into_iter()
-    .next()
-    .unwrap()
-    .try_into()
-    .map_err(log_and_map)?;
-
     Ok(Json(connection_profile))
 }
 
@@ -145,7 +186,12 @@ pub async fn get_connection_profiles(
 ) -> Result<Json<ConnectionProfileCollection>, ErrorResp> {
     let auth_data = authenticate(&state.database, bearer_auth).await?;
 
-    let data = get_all_connection_profiles(&auth_data, &state.database.client().await?).await?;
+    let data = get_all_connection_profiles(&auth_data, &state.database.client().await?)
+        .await?
+        .into_iter()
+        // profiles outside of an API key's scope are treated as though they don't exist
+        .filter(|p| auth_data.scope.allows_namespace(&p.namespace))
+        .collect();
 
     Ok(Json(ConnectionProfileCollection { data }))
 }
@@ -168,18 +214,53 @@ pub(crate) async fn delete_connection_profile(
     Path(pub_id): Path<String>,
 ) -> Result<(), ErrorResp> {
     let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
 
-    let deleted = api_queries::execute_delete_connection_profile(
-        &state.database.client().await?,
+    let client = state.database.client().await?;
+
+    let profile = api_queries::fetch_get_connection_profile_by_pub_id(
+        &client,
         &auth_data.organization_id,
         &pub_id,
     )
-    .await
-    .map_err(|e| map_delete_err("connection_profile", "connection tables", e))?;
-
-    if deleted == 0 {
-        return Err(not_found("Connection profile"));
-    }
+    .await?
+    .into_iter()
+    .next()
+    .ok_or_else(|| not_found("Connection profile"))?;
+    auth_data.require_namespace(&profile.namespace)?;
+
+    audit_log::transaction(&state.database, async |client| {
+        let deleted = api_queries::execute_delete_connection_profile(
+            client,
+            &auth_data.organization_id,
+            &pub_id,
+        )
+        .await
+        .map_err(|e| map_delete_err("connection_profile", "connection tables", e))?;
+
+        if deleted == 0 {
+            return Err(not_found("Connection profile"));
+        }
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Delete,
+            AuditResourceType::ConnectionProfile,
+            &pub_id,
+            Some(json!({
+                "name": profile.name,
+                "connector": profile.r#type,
+                "config": redact(&profile.config),
+                "namespace": profile.namespace,
+            })),
+            None,
+        )
+        .await?;
+
+        Ok(())
+    })
+    .await?;
 
     Ok(())
 }
@@ -17,6 +17,8 @@ use arroyo_connectors::connector_for_type;
 use arroyo_connectors::kafka::{KafkaConfig, KafkaTable, SchemaRegistry};
 use arroyo_formats::{avro, json};
 use arroyo_operator::connector::ErasedConnector;
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{AuditAction, AuditResourceType};
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionTable, ConnectionTablePost, ConnectionType,
     SchemaDefinition,
@@ -27,8 +29,12 @@ use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_rpc::schema_resolver::{
     ConfluentSchemaRegistry, ConfluentSchemaSubjectResponse, ConfluentSchemaType,
 };
+use arroyo_rpc::secrets::resolve_secrets;
 use arroyo_types::raw_schema;
 
+use crate::audit_log;
+use crate::audit_log::redact;
+use crate::namespaces::{resolve_namespace, DEFAULT_NAMESPACE};
 use crate::rest::AppState;
 use crate::rest_utils::{
     authenticate, bad_request, internal_server_error, log_and_map, map_delete_err, map_insert_err,
@@ -141,18 +147,51 @@ pub(crate) async fn delete_connection_table(
     Path(pub_id): Path<String>,
 ) -> Result<(), ErrorResp> {
     let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
 
-    let deleted = api_queries::execute_delete_connection_table(
-        &state.database.client().await?,
-        &auth_data.organization_id,
-        &pub_id,
-    )
-    .await
-    .map_err(|e| map_delete_err("connection_table", "pipelines", e))?;
+    let client = state.database.client().await?;
 
-    if deleted == 0 {
-        return Err(not_found("Connection table"));
-    }
+    let table =
+        api_queries::fetch_get_connection_table(&client, &auth_data.organization_id, &pub_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_found("Connection table"))?;
+    auth_data.require_namespace(&table.namespace)?;
+
+    audit_log::transaction(&state.database, async |client| {
+        let deleted = api_queries::execute_delete_connection_table(
+            client,
+            &auth_data.organization_id,
+            &pub_id,
+        )
+        .await
+        .map_err(|e| map_delete_err("connection_table", "pipelines", e))?;
+
+        if deleted == 0 {
+            return Err(not_found("Connection table"));
+        }
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Delete,
+            AuditResourceType::ConnectionTable,
+            &pub_id,
+            Some(json!({
+                "name": table.name,
+                "connector": table.connector,
+                "connectionProfileId": table.profile_id,
+                "config": redact(&table.config),
+                "namespace": table.namespace,
+            })),
+            None,
+        )
+        .await?;
+
+        Ok(())
+    })
+    .await?;
 
     Ok(())
 }
@@ -178,6 +217,12 @@ pub(crate) async fn test_connection_table(
     let (connector, _, profile, schema) =
         get_and_validate_connector(&req, &auth_data, &state.database).await?;
 
+    for config in [&profile, &req.config] {
+        resolve_secrets(&config.to_string())
+            .await
+            .map_err(|e| bad_request(format!("{:#}", e)))?;
+    }
+
     let (tx, rx) = channel(8);
 
     connector
@@ -217,6 +262,10 @@ fn get_connection_profile(
                 .config_description(&config)
                 .map_err(|e| anyhow!("invalid config for connector {}: {:?}", id, e))?,
             config,
+            namespace: c
+                .profile_namespace
+                .clone()
+                .unwrap_or_else(|| DEFAULT_NAMESPACE.to_string()),
         }))
     } else {
         Ok(None)
@@ -262,12 +311,7 @@ pub async fn create_connection_table(
     WithRejection(Json(req), _): WithRejection<Json<ConnectionTablePost>, ApiError>,
 ) -> Result<Json<ConnectionTable>, ErrorResp> {
     let auth_data = authenticate(&state.database, bearer_auth).await?;
-
-    // let transaction = client.transaction().await.map_err(log_and_map)?;
-    // transaction
-    //     .execute("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE", &[])
-    //     .await
-    //     .map_err(log_and_map)?;
+    auth_data.require_role(Role::Editor)?;
 
     let (connector, connection_id, profile, schema) =
         get_and_validate_connector(&req, &auth_data, &state.database).await?;
@@ -286,31 +330,55 @@ pub async fn create_connection_table(
 
     let client = state.database.client().await?;
 
-    api_queries::execute_create_connection_table(
-        &client,
-        &pub_id,
-        &auth_data.organization_id,
-        &auth_data.user_id,
-        &req.name,
-        &table_type.to_string(),
-        &req.connector,
-        &connection_id,
-        &req.config,
-        &schema,
-    )
-    .await
-    .map_err(|err| map_insert_err("connection_table", err))?;
-
-    // transaction.commit().await.map_err(log_and_map)?;
+    let (namespace, _) = resolve_namespace(&auth_data, &client, req.namespace.as_deref()).await?;
+    auth_data.require_namespace(&namespace)?;
+
+    let table = audit_log::transaction(&state.database, async |client| {
+        api_queries::execute_create_connection_table(
+            client,
+            &pub_id,
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &req.name,
+            &table_type.to_string(),
+            &req.connector,
+            &connection_id,
+            &req.config,
+            &schema,
+            &namespace,
+        )
+        .await
+        .map_err(|err| map_insert_err("connection_table", err))?;
+
+        let table: ConnectionTable =
+            api_queries::fetch_get_connection_table(client, &auth_data.organization_id, &pub_id)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| internal_server_error("Could not create connection table"))?
+                .try_into()
+                .map_err(log_and_map)?;
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Create,
+            AuditResourceType::ConnectionTable,
+            &pub_id,
+            None,
+            Some(json!({
+                "name": req.name,
+                "connector": req.connector,
+                "connectionProfileId": req.connection_profile_id,
+                "config": redact(&req.config),
+                "namespace": namespace,
+            })),
+        )
+        .await?;
 
-    let table =
-        api_queries::fetch_get_connection_table(&client, &auth_data.organization_id, &pub_id)
-            .await?
-            .into_iter()
-            .next()
-            .ok_or_else(|| internal_server_error("Could not create connection table"))?
-            .try_into()
-            .map_err(log_and_map)?;
+        Ok(table)
+    })
+    .await?;
 
     Ok(Json(table))
 }
@@ -373,6 +441,7 @@ impl TryInto<ConnectionTable> for DbConnectionTable {
             config: self.config,
             schema,
             consumers: self.consumer_count as u32,
+            namespace: self.namespace,
         })
     }
 }
@@ -419,6 +488,8 @@ pub(crate) async fn get_connection_tables(
             result
         })
         .filter_map(Result::ok)
+        // tables outside of an API key's scope are treated as though they don't exist
+        .filter(|t: &ConnectionTable| auth_data.scope.allows_namespace(&t.namespace))
         .collect();
 
     Ok(Json(ConnectionTableCollection {
@@ -628,6 +699,10 @@ async fn get_schema(
         ));
     };
 
+    resolve_secrets(&profile_config.to_string())
+        .await
+        .map_err(|e| bad_request(format!("{:#}", e)))?;
+
     let resolver = ConfluentSchemaRegistry::new(
         &endpoint,
         &table.subject(),
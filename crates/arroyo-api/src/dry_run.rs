@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use arrow_schema::Schema;
+use arroyo_connectors::connector_for_type;
+use arroyo_datastream::logical::{LogicalProgram, OperatorName};
+use arroyo_df::CompiledSql;
+use arroyo_formats::ser::ArrowSerializer;
+use arroyo_rpc::api_types::connections::{ConnectionSchema, TestSourceMessage};
+use arroyo_rpc::api_types::pipelines::{
+    DiagnosticSeverity, DryRunDiagnostic, DryRunStage, PipelineDryRunPost, PipelineDryRunResult,
+};
+use arroyo_rpc::api_types::udfs::{Udf, UdfLanguage};
+use arroyo_rpc::formats::Format;
+use arroyo_rpc::grpc::api::ConnectorOp;
+use arroyo_rpc::schema_resolver::ConfluentSchemaType;
+use arroyo_rpc::secrets::resolve_secrets;
+use arroyo_rpc::{OperatorConfig, TIMESTAMP_FIELD};
+use arroyo_udf_host::ParsedUdfFile;
+use axum::extract::State;
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use futures::future::join_all;
+use petgraph::Direction;
+use prost::Message;
+use tokio::sync::mpsc::channel;
+
+use crate::compiler_service;
+use crate::namespaces::resolve_namespace;
+use crate::pipelines::{compile_sql, confluent_schema_registry};
+use crate::rest::AppState;
+use crate::rest_utils::{authenticate, log_and_map, ApiError, BearerAuth, ErrorResp};
+use crate::udfs::build_udf;
+
+/// How long to wait for a connector to finish testing its connection
+const CONNECTIVITY_TIMEOUT: Duration = Duration::from_secs(15);
+
+fn diagnostic(
+    stage: DryRunStage,
+    severity: DiagnosticSeverity,
+    subject: Option<&str>,
+    message: impl Into<String>,
+) -> DryRunDiagnostic {
+    DryRunDiagnostic {
+        stage,
+        severity,
+        subject: subject.map(|s| s.to_string()),
+        message: message.into(),
+    }
+}
+
+fn error(
+    stage: DryRunStage,
+    subject: Option<&str>,
+    message: impl Into<String>,
+) -> DryRunDiagnostic {
+    diagnostic(stage, DiagnosticSeverity::Error, subject, message)
+}
+
+/// Compiles each of the Rust UDFs, without saving the results
+async fn check_udfs(udfs: &[Udf]) -> Vec<DryRunDiagnostic> {
+    let rust_udfs: Vec<_> = udfs
+        .iter()
+        .filter(|u| u.language == UdfLanguage::Rust)
+        .collect();
+
+    if rust_udfs.is_empty() {
+        return vec![];
+    }
+
+    let mut compiler = match compiler_service().await {
+        Ok(c) => c,
+        Err(e) => {
+            return vec![error(
+                DryRunStage::Udfs,
+                None,
+                format!("Failed to compile UDFs: {}", e.message),
+            )]
+        }
+    };
+
+    let mut diagnostics = vec![];
+    for udf in rust_udfs {
+        let name = match ParsedUdfFile::try_parse(&udf.definition) {
+            Ok(parsed) => parsed.udf.name,
+            Err(e) => {
+                diagnostics.push(error(
+                    DryRunStage::Udfs,
+                    None,
+                    format!("Invalid UDF: {}", e),
+                ));
+                continue;
+            }
+        };
+
+        match build_udf(&mut compiler, &udf.definition, false).await {
+            Ok(resp) => diagnostics.extend(
+                resp.errors
+                    .into_iter()
+                    .map(|e| error(DryRunStage::Udfs, Some(&name), e)),
+            ),
+            Err(e) => diagnostics.push(error(DryRunStage::Udfs, Some(&name), e.message)),
+        }
+    }
+
+    diagnostics
+}
+
+/// Runs the connector's connection test for the source or sink, which checks things like
+/// authentication and whether the topic or table exists
+async fn check_connectivity(
+    operator_id: &str,
+    op: &ConnectorOp,
+    config: &OperatorConfig,
+    schema: &Schema,
+) -> Vec<DryRunDiagnostic> {
+    let subject = Some(operator_id);
+
+    let Some(connector) = connector_for_type(&op.connector) else {
+        return vec![error(
+            DryRunStage::Connectivity,
+            subject,
+            format!("Unknown connector '{}'", op.connector),
+        )];
+    };
+
+    for value in [&config.connection, &config.table] {
+        if let Err(e) = resolve_secrets(&value.to_string()).await {
+            return vec![error(
+                DryRunStage::Connectivity,
+                subject,
+                format!("{:#}", e),
+            )];
+        }
+    }
+
+    let connection_schema = ConnectionSchema {
+        format: config.format.clone(),
+        bad_data: config.bad_data.clone(),
+        framing: config.framing.clone(),
+        struct_name: None,
+        fields: schema
+            .fields()
+            .iter()
+            .filter(|f| f.name() != TIMESTAMP_FIELD)
+            .filter_map(|f| (**f).clone().try_into().ok())
+            .collect(),
+        definition: None,
+        inferred: None,
+    };
+
+    let (tx, mut rx) = channel(8);
+    if let Err(e) = connector.test(
+        operator_id,
+        &config.connection,
+        &config.table,
+        Some(&connection_schema),
+        tx,
+    ) {
+        return vec![error(
+            DryRunStage::Connectivity,
+            subject,
+            format!("Failed to parse connector config: {}", e),
+        )];
+    }
+
+    let mut diagnostics = vec![];
+    let mut last_step = None;
+    let result = tokio::time::timeout(CONNECTIVITY_TIMEOUT, async {
+        while let Some(TestSourceMessage {
+            error: is_error,
+            done,
+            message,
+        }) = rx.recv().await
+        {
+            if is_error {
+                diagnostics.push(error(DryRunStage::Connectivity, subject, message));
+            } else {
+                last_step = Some(message);
+            }
+
+            if done {
+                break;
+            }
+        }
+    })
+    .await;
+
+    if result.is_err() {
+        diagnostics.push(diagnostic(
+            DryRunStage::Connectivity,
+            DiagnosticSeverity::Warning,
+            subject,
+            format!(
+                "Connection test did not finish within {} seconds{}",
+                CONNECTIVITY_TIMEOUT.as_secs(),
+                last_step
+                    .map(|s| format!("; last step: {}", s))
+                    .unwrap_or_default()
+            ),
+        ));
+    }
+
+    diagnostics
+}
+
+/// Checks that the schema a sink writes is compatible with the latest version of its subject in
+/// the schema registry, for sinks that register their schemas when the pipeline is created
+async fn check_schema(
+    operator_id: &str,
+    config: &OperatorConfig,
+    schema: &Schema,
+) -> Option<DryRunDiagnostic> {
+    let subject = Some(operator_id);
+
+    let (schema, schema_type) = match &config.format {
+        Some(Format::Avro(avro)) if avro.confluent_schema_registry && avro.schema_id.is_none() => (
+            ArrowSerializer::avro_schema(schema).canonical_form(),
+            ConfluentSchemaType::Avro,
+        ),
+        Some(Format::Json(json)) if json.confluent_schema_registry && json.schema_id.is_none() => (
+            ArrowSerializer::json_schema(schema).to_string(),
+            ConfluentSchemaType::Json,
+        ),
+        _ => return None,
+    };
+
+    let registry = match confluent_schema_registry(config).await {
+        Ok(Some(registry)) => registry,
+        Ok(None) => return None,
+        Err(e) => return Some(error(DryRunStage::Schema, subject, format!("{:#}", e))),
+    };
+
+    match registry.is_compatible(schema, schema_type).await {
+        Ok(true) => None,
+        Ok(false) => Some(error(
+            DryRunStage::Schema,
+            subject,
+            "Schema is not compatible with the latest version registered for its subject",
+        )),
+        Err(e) => Some(error(
+            DryRunStage::Schema,
+            subject,
+            format!("Failed to check schema compatibility: {:#}", e),
+        )),
+    }
+}
+
+/// Checks the connectivity of every source and sink in the program, and the schema compatibility
+/// of its sinks, concurrently
+async fn check_connectors(program: &LogicalProgram, connectivity: bool) -> Vec<DryRunDiagnostic> {
+    let checks = program.graph.node_indices().filter_map(|idx| {
+        let node = program.graph.node_weight(idx).unwrap();
+        let direction = match node.operator_name {
+            OperatorName::ConnectorSource => Direction::Outgoing,
+            OperatorName::ConnectorSink => Direction::Incoming,
+            _ => return None,
+        };
+
+        let op = ConnectorOp::decode(&node.operator_config[..]).ok()?;
+        // previews are written back to the controller, so there's nothing to check
+        if op.connector == "preview" {
+            return None;
+        }
+
+        let schema = program
+            .graph
+            .edges_directed(idx, direction)
+            .next()
+            .map(|e| e.weight().schema.schema.clone())?;
+
+        Some(async move {
+            let config: OperatorConfig = match serde_json::from_str(&op.config) {
+                Ok(config) => config,
+                Err(e) => {
+                    return vec![error(
+                        DryRunStage::Connectivity,
+                        Some(&node.operator_id),
+                        format!("Invalid connector config: {}", e),
+                    )]
+                }
+            };
+
+            let mut diagnostics = vec![];
+            if connectivity {
+                diagnostics
+                    .extend(check_connectivity(&node.operator_id, &op, &config, &schema).await);
+            }
+
+            if node.operator_name == OperatorName::ConnectorSink {
+                diagnostics.extend(check_schema(&node.operator_id, &config, &schema).await);
+            }
+
+            diagnostics
+        })
+    });
+
+    join_all(checks).await.into_iter().flatten().collect()
+}
+
+/// Dry-run a pipeline
+///
+/// Checks everything needed to create and run the pipeline without starting a job: that its UDFs
+/// compile, that its query plans, that its sources and sinks can connect to their external
+/// systems, and that the schemas of its sinks are compatible with their schema registry
+/// subjects. Problems are returned as diagnostics rather than errors.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/dry_run",
+    tag = "pipelines",
+    request_body = PipelineDryRunPost,
+    responses(
+        (status = 200, description = "Dry run results", body = PipelineDryRunResult),
+    ),
+)]
+pub async fn dry_run_pipeline(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(req), _): WithRejection<Json<PipelineDryRunPost>, ApiError>,
+) -> Result<Json<PipelineDryRunResult>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let udfs = req.udfs.unwrap_or_default();
+
+    let (namespace, _) = resolve_namespace(
+        &auth_data,
+        &state.database.client().await?,
+        req.namespace.as_deref(),
+    )
+    .await?;
+
+    let mut diagnostics = check_udfs(&udfs).await;
+
+    let program = match compile_sql(
+        req.query,
+        &udfs,
+        &mut BTreeMap::new(),
+        &mut BTreeMap::new(),
+        1,
+        &namespace,
+        &auth_data,
+        true,
+        &state.database,
+    )
+    .await
+    {
+        Ok(CompiledSql { program, .. }) => program,
+        Err(e) => {
+            diagnostics.push(error(DryRunStage::Planning, None, e.message));
+            return Ok(Json(PipelineDryRunResult {
+                valid: false,
+                graph: None,
+                diagnostics,
+            }));
+        }
+    };
+
+    diagnostics.extend(check_connectors(&program, req.check_connectivity.unwrap_or(true)).await);
+
+    Ok(Json(PipelineDryRunResult {
+        valid: !diagnostics
+            .iter()
+            .any(|d| d.severity == DiagnosticSeverity::Error),
+        graph: Some(program.try_into().map_err(log_and_map)?),
+        diagnostics,
+    }))
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use tonic::Code;
+
+use crate::pipelines::query_job_by_pub_id;
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, log_and_map, not_found, ApiError, BearerAuth, ErrorResp,
+};
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::faults::{FaultKind, JobFaultInjection, JobFaultPost};
+use arroyo_rpc::grpc;
+use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
+use arroyo_rpc::grpc::InjectJobFaultReq;
+use arroyo_rpc::Compressed;
+use arroyo_server_common::tls;
+
+const DEFAULT_FAULT_DURATION: Duration = Duration::from_secs(10);
+
+async fn inject(
+    state: &AppState,
+    job_id: String,
+    fault: Option<grpc::FaultSpec>,
+) -> Result<u32, ErrorResp> {
+    let mut controller = tls::connect_grpc(state.controller_addr.clone())
+        .await
+        .map(|channel| ControllerGrpcClient::new(channel).compressed())
+        .map_err(log_and_map)?;
+
+    match controller
+        .inject_job_fault(InjectJobFaultReq { job_id, fault })
+        .await
+    {
+        Ok(resp) => Ok(resp.into_inner().workers),
+        Err(e) if e.code() == Code::NotFound => Err(not_found("Running workers for job")),
+        Err(e) if e.code() == Code::FailedPrecondition => Err(bad_request(e.message())),
+        Err(e) => Err(log_and_map(e)),
+    }
+}
+
+/// Inject a fault into a job
+///
+/// Registers a fault with every running worker of the job, for testing how the pipeline
+/// recovers from failures. Each worker fires the fault up to `count` times, with the given
+/// probability each time a matching subtask checks for it. Requires `fault-injection.enabled`
+/// to be set in the cluster's configuration.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/faults",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+    ),
+    request_body = JobFaultPost,
+    responses(
+        (status = 200, description = "Injected fault", body = JobFaultInjection),
+    ),
+)]
+pub async fn create_job_fault(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+    WithRejection(Json(req), _): WithRejection<Json<JobFaultPost>, ApiError>,
+) -> Result<Json<JobFaultInjection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Admin)?;
+
+    let job = query_job_by_pub_id(
+        &pipeline_pub_id,
+        &job_pub_id,
+        &state.database.client().await?,
+        &auth_data,
+    )
+    .await?;
+
+    let probability = req.probability.unwrap_or(1.0);
+    if !(probability > 0.0 && probability <= 1.0) {
+        return Err(bad_request(
+            "probability must be greater than 0 and at most 1",
+        ));
+    }
+
+    if req.count == Some(0) {
+        return Err(bad_request("count must be greater than 0"));
+    }
+
+    if req.kind == FaultKind::DropNetworkBatch && req.operator_id.is_some() {
+        return Err(bad_request(
+            "operatorId is not supported for drop_network_batch faults",
+        ));
+    }
+
+    let duration = req
+        .duration_millis
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_FAULT_DURATION);
+
+    let fault = grpc::FaultSpec {
+        kind: grpc::FaultKind::from(req.kind) as i32,
+        operator_id: req.operator_id,
+        subtask_index: req.subtask_index,
+        probability,
+        count: Some(req.count.unwrap_or(1)),
+        duration_micros: duration.as_micros() as u64,
+    };
+
+    let workers = inject(&state, job.id, Some(fault)).await?;
+
+    Ok(Json(JobFaultInjection { workers }))
+}
+
+/// Clear a job's faults
+///
+/// Removes the faults registered with every running worker of the job, including those from
+/// the workers' configuration.
+#[utoipa::path(
+    delete,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/faults",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+    ),
+    responses(
+        (status = 200, description = "Cleared faults", body = JobFaultInjection),
+    ),
+)]
+pub async fn delete_job_faults(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+) -> Result<Json<JobFaultInjection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Admin)?;
+
+    let job = query_job_by_pub_id(
+        &pipeline_pub_id,
+        &job_pub_id,
+        &state.database.client().await?,
+        &auth_data,
+    )
+    .await?;
+
+    let workers = inject(&state, job.id, None).await?;
+
+    Ok(Json(JobFaultInjection { workers }))
+}
@@ -0,0 +1,278 @@
+//! An Arrow Flight SQL endpoint that serves the results materialized by pipelines' flight sinks,
+//! so that BI tools and Flight SQL clients can query them directly.
+//!
+//! See [`crate::results`] for how results are exposed. Clients authenticate with an API key,
+//! passed as a bearer token in the `authorization` header.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use arrow::datatypes::Schema;
+use arrow::record_batch::RecordBatch;
+use arrow_flight::encode::FlightDataEncoderBuilder;
+use arrow_flight::error::FlightError;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::sql::metadata::{SqlInfoData, SqlInfoDataBuilder};
+use arrow_flight::sql::server::FlightSqlService;
+use arrow_flight::sql::{
+    Any as FlightAny, CommandGetCatalogs, CommandGetDbSchemas, CommandGetSqlInfo, CommandGetTables,
+    CommandStatementQuery, ProstMessageExt, SqlInfo, TicketStatementQuery,
+};
+use arrow_flight::{FlightDescriptor, FlightEndpoint, FlightInfo, Ticket};
+use arroyo_rpc::config::config;
+use axum::http::StatusCode;
+use cornucopia_async::DatabaseSource;
+use datafusion::prelude::DataFrame;
+use futures::{stream, Stream, TryStreamExt};
+use once_cell::sync::Lazy;
+use prost::Message;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+use crate::rest_utils::ErrorResp;
+use crate::results::{ResultCatalog, ResultPipeline, CATALOG};
+use crate::AuthData;
+
+static SQL_INFO: Lazy<SqlInfoData> = Lazy::new(|| {
+    let mut builder = SqlInfoDataBuilder::new();
+    builder.append(SqlInfo::FlightSqlServerName, "Arroyo");
+    builder.append(SqlInfo::FlightSqlServerVersion, env!("CARGO_PKG_VERSION"));
+    builder.append(SqlInfo::FlightSqlServerArrowVersion, "1.3");
+    builder.append(SqlInfo::FlightSqlServerReadOnly, true);
+    builder.build().unwrap()
+});
+
+type DoGetStream = Pin<Box<dyn Stream<Item = Result<arrow_flight::FlightData, Status>> + Send>>;
+
+fn to_status(e: ErrorResp) -> Status {
+    match e.status_code {
+        StatusCode::UNAUTHORIZED => Status::unauthenticated(e.message),
+        StatusCode::FORBIDDEN => Status::permission_denied(e.message),
+        StatusCode::NOT_FOUND => Status::not_found(e.message),
+        StatusCode::BAD_REQUEST => Status::invalid_argument(e.message),
+        _ => Status::internal(e.message),
+    }
+}
+
+fn internal(e: impl std::fmt::Display) -> Status {
+    Status::internal(e.to_string())
+}
+
+#[derive(Clone)]
+pub struct FlightSqlServer {
+    results: ResultCatalog,
+}
+
+impl FlightSqlServer {
+    pub fn new(database: DatabaseSource) -> Self {
+        Self {
+            results: ResultCatalog::new(database),
+        }
+    }
+
+    async fn authenticate<T>(&self, request: &Request<T>) -> Result<AuthData, Status> {
+        let token = match request.metadata().get("authorization") {
+            Some(value) => Some(
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|v| v.strip_prefix("Bearer "))
+                    .ok_or_else(|| Status::unauthenticated("expected a bearer token"))?,
+            ),
+            None => None,
+        };
+
+        self.results.authenticate(token).await.map_err(to_status)
+    }
+
+    async fn plan(&self, auth: &AuthData, query: &str) -> Result<DataFrame, Status> {
+        self.results.plan(auth, query).await.map_err(to_status)
+    }
+
+    async fn pipelines(&self, auth: &AuthData) -> Result<Vec<ResultPipeline>, Status> {
+        self.results.pipelines(auth).await.map_err(to_status)
+    }
+
+    fn flight_info(
+        ticket: FlightAny,
+        schema: &Schema,
+        descriptor: FlightDescriptor,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let endpoint = FlightEndpoint::new().with_ticket(Ticket::new(ticket.encode_to_vec()));
+        let info = FlightInfo::new()
+            .try_with_schema(schema)
+            .map_err(internal)?
+            .with_endpoint(endpoint)
+            .with_descriptor(descriptor);
+        Ok(Response::new(info))
+    }
+
+    fn batch_response(batch: RecordBatch) -> Response<DoGetStream> {
+        let stream = FlightDataEncoderBuilder::new()
+            .with_schema(batch.schema())
+            .build(stream::once(async { Ok(batch) }))
+            .map_err(Status::from);
+        Response::new(Box::pin(stream))
+    }
+}
+
+#[tonic::async_trait]
+impl FlightSqlService for FlightSqlServer {
+    type FlightService = Self;
+
+    async fn get_flight_info_statement(
+        &self,
+        query: CommandStatementQuery,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let df = self.plan(&auth, &query.query).await?;
+
+        // the query is re-planned when its results are fetched, against the latest results
+        let ticket = TicketStatementQuery {
+            statement_handle: query.query.into_bytes().into(),
+        };
+        Self::flight_info(
+            ticket.as_any(),
+            &Schema::from(df.schema()),
+            request.into_inner(),
+        )
+    }
+
+    async fn do_get_statement(
+        &self,
+        ticket: TicketStatementQuery,
+        request: Request<Ticket>,
+    ) -> Result<Response<<Self as FlightService>::DoGetStream>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let query = String::from_utf8(ticket.statement_handle.to_vec())
+            .map_err(|_| Status::invalid_argument("invalid statement handle"))?;
+
+        let df = self.plan(&auth, &query).await?;
+        let schema = Arc::new(Schema::from(df.schema()));
+        let batches = df
+            .execute_stream()
+            .await
+            .map_err(internal)?
+            .map_err(|e| FlightError::ExternalError(Box::new(e)));
+
+        let stream = FlightDataEncoderBuilder::new()
+            .with_schema(schema)
+            .build(batches)
+            .map_err(Status::from);
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_flight_info_catalogs(
+        &self,
+        query: CommandGetCatalogs,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        self.authenticate(&request).await?;
+        Self::flight_info(
+            query.as_any(),
+            &query.into_builder().schema(),
+            request.into_inner(),
+        )
+    }
+
+    async fn do_get_catalogs(
+        &self,
+        query: CommandGetCatalogs,
+        request: Request<Ticket>,
+    ) -> Result<Response<<Self as FlightService>::DoGetStream>, Status> {
+        self.authenticate(&request).await?;
+        let mut builder = query.into_builder();
+        builder.append(CATALOG);
+        Ok(Self::batch_response(builder.build().map_err(internal)?))
+    }
+
+    async fn get_flight_info_schemas(
+        &self,
+        query: CommandGetDbSchemas,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        self.authenticate(&request).await?;
+        Self::flight_info(
+            query.as_any(),
+            &query.into_builder().schema(),
+            request.into_inner(),
+        )
+    }
+
+    async fn do_get_schemas(
+        &self,
+        query: CommandGetDbSchemas,
+        request: Request<Ticket>,
+    ) -> Result<Response<<Self as FlightService>::DoGetStream>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let mut builder = query.into_builder();
+        for pipeline in self.pipelines(&auth).await? {
+            builder.append(CATALOG, &pipeline.id);
+        }
+        Ok(Self::batch_response(builder.build().map_err(internal)?))
+    }
+
+    async fn get_flight_info_tables(
+        &self,
+        query: CommandGetTables,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        self.authenticate(&request).await?;
+        Self::flight_info(
+            query.as_any(),
+            &query.into_builder().schema(),
+            request.into_inner(),
+        )
+    }
+
+    async fn do_get_tables(
+        &self,
+        query: CommandGetTables,
+        request: Request<Ticket>,
+    ) -> Result<Response<<Self as FlightService>::DoGetStream>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let mut builder = query.into_builder();
+        for pipeline in self.pipelines(&auth).await? {
+            for (name, schema) in &pipeline.tables {
+                builder
+                    .append(CATALOG, &pipeline.id, name, "TABLE", schema)
+                    .map_err(internal)?;
+            }
+        }
+        Ok(Self::batch_response(builder.build().map_err(internal)?))
+    }
+
+    async fn get_flight_info_sql_info(
+        &self,
+        query: CommandGetSqlInfo,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        Self::flight_info(query.as_any(), &SqlInfoData::schema(), request.into_inner())
+    }
+
+    async fn do_get_sql_info(
+        &self,
+        query: CommandGetSqlInfo,
+        _request: Request<Ticket>,
+    ) -> Result<Response<<Self as FlightService>::DoGetStream>, Status> {
+        let batch = query.into_builder(&SQL_INFO).build().map_err(internal)?;
+        Ok(Self::batch_response(batch))
+    }
+
+    async fn register_sql_info(&self, _id: i32, _result: &SqlInfo) {}
+}
+
+pub async fn start_flight_server(database: DatabaseSource, port: u16) -> anyhow::Result<()> {
+    let addr = SocketAddr::new(config().api.bind_address, port);
+
+    info!("Starting Arrow Flight SQL server on {:?}", addr);
+    Server::builder()
+        .add_service(FlightServiceServer::new(FlightSqlServer::new(database)))
+        .serve(addr)
+        .await
+        .map_err(|e| anyhow!("Failed to start Arrow Flight SQL server on {}: {}", addr, e))
+}
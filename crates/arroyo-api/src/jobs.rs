@@ -1,49 +1,69 @@
 use crate::queries::api_queries::{DbCheckpoint, DbLogMessage, DbPipelineJob};
+use arroyo_rpc::api_types::api_keys::Role;
 use arroyo_rpc::api_types::checkpoints::{
-    Checkpoint, CheckpointEventSpan, CheckpointSpanType, OperatorCheckpointGroup,
-    SubtaskCheckpointGroup,
+    Checkpoint, CheckpointEventSpan, CheckpointSpanType, OperatorCheckpointGroup, OperatorState,
+    OperatorStateMapping, StateDumpFormat, StateMigration, StateMigrationPost, StateTable,
+    StateTableQueryParams, SubtaskCheckpointGroup,
+};
+use arroyo_rpc::api_types::pipelines::{
+    JobLogLevel, JobLogMessage, JobRestart, OutputData, StopType,
 };
-use arroyo_rpc::api_types::pipelines::{JobLogLevel, JobLogMessage, OutputData, StopType};
 use arroyo_rpc::api_types::{
-    CheckpointCollection, JobCollection, JobLogMessageCollection,
-    OperatorCheckpointGroupCollection, PaginationQueryParams,
+    CheckpointCollection, JobCollection, JobLogMessageCollection, JobRestartCollection,
+    OperatorCheckpointGroupCollection, OperatorStateCollection, PaginationQueryParams,
 };
 use arroyo_rpc::grpc;
 use arroyo_rpc::grpc::api::{
-    OperatorCheckpointDetail, TaskCheckpointDetail, TaskCheckpointEventType,
+    ArrowProgram, OperatorCheckpointDetail, TaskCheckpointDetail, TaskCheckpointEventType,
 };
 use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_rpc::Compressed;
+use arroyo_server_common::tls;
+use arroyo_state::{inspect, migrate, BackingStore, StateBackend};
+use axum::body::StreamBody;
 use axum::extract::{Path, Query, State};
 use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
 use axum::Json;
+use axum_extra::extract::WithRejection;
 use futures_util::stream::Stream;
+use http::header;
+use prost::Message;
 use std::convert::Infallible;
 use std::{collections::HashMap, time::Duration};
+use time::OffsetDateTime;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt as _;
 use tonic::Request;
 use tracing::info;
 
 const PREVIEW_TTL: Duration = Duration::from_secs(60);
+const MAX_RESTART_HISTORY: i32 = 100;
 
 use crate::pipelines::{query_job_by_pub_id, query_pipeline_by_pub_id};
 use crate::rest::AppState;
 use crate::rest_utils::{
     authenticate, bad_request, log_and_map, not_found, paginate_results,
-    validate_pagination_params, BearerAuth, ErrorResp,
+    validate_pagination_params, ApiError, BearerAuth, ErrorResp,
 };
 use crate::types::public::LogLevel;
 use crate::{queries::api_queries, to_micros, types::public, AuthData};
-use cornucopia_async::DatabaseSource;
+use cornucopia_async::Database;
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn create_job<'a>(
     pipeline_name: &str,
     pipeline_id: i64,
     checkpoint_interval: Duration,
+    priority: i32,
+    worker_pod: &Option<serde_json::Value>,
+    freshness_slo: &Option<serde_json::Value>,
+    restart_strategy: &Option<serde_json::Value>,
     preview: bool,
+    stop: public::StopMode,
     auth: &AuthData,
-    db: &DatabaseSource,
+    db: &Database<'_>,
 ) -> Result<String, ErrorResp> {
     let checkpoint_interval = if preview {
         Duration::from_secs(24 * 60 * 60)
@@ -59,7 +79,7 @@ pub(crate) async fn create_job<'a>(
         ));
     }
 
-    let running_jobs = api_queries::fetch_get_jobs(&db.client().await?, &auth.organization_id)
+    let running_jobs = api_queries::fetch_get_jobs(db, &auth.organization_id)
         .await?
         .iter()
         .filter(|j| {
@@ -84,7 +104,7 @@ pub(crate) async fn create_job<'a>(
 
     // TODO: handle chance of collision in ids
     api_queries::execute_create_job(
-        &db.client().await?,
+        db,
         &job_id,
         &auth.organization_id,
         &pipeline_name,
@@ -96,11 +116,16 @@ pub(crate) async fn create_job<'a>(
         } else {
             None
         }),
+        &priority,
+        worker_pod,
+        freshness_slo,
+        restart_strategy,
+        &stop,
     )
     .await?;
 
     api_queries::execute_create_job_status(
-        &db.client().await?,
+        db,
         &generate_id(IdTypes::JobStatus),
         &job_id,
         &auth.organization_id,
@@ -220,6 +245,53 @@ pub async fn get_job_errors(
     }))
 }
 
+/// List a job's restarts
+///
+/// Returns the most recent restarts of the job after failures, latest first, along with the
+/// delay the restart strategy waited before each of them.
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/restarts",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+    ),
+    responses(
+        (status = 200, description = "Got job's restarts", body = JobRestartCollection),
+    ),
+)]
+pub async fn get_job_restarts(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+) -> Result<Json<JobRestartCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let db = state.database.client().await?;
+
+    let job = query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &db, &auth_data).await?;
+
+    let restarts = api_queries::fetch_get_job_restarts(
+        &db,
+        &auth_data.organization_id,
+        &job.id,
+        &MAX_RESTART_HISTORY,
+    )
+    .await
+    .map_err(log_and_map)?
+    .into_iter()
+    .map(|r| JobRestart {
+        run_id: r.run_id as u64,
+        time: to_micros(r.time),
+        attempt: r.attempt as u32,
+        delay_micros: r.delay_micros as u64,
+        reason: r.reason,
+    })
+    .collect();
+
+    Ok(Json(JobRestartCollection { data: restarts }))
+}
+
 impl From<DbLogMessage> for JobLogMessage {
     fn from(val: DbLogMessage) -> Self {
         let level: JobLogLevel = match val.log_level {
@@ -236,6 +308,7 @@ impl From<DbLogMessage> for JobLogMessage {
             level,
             message: val.message,
             details: val.details,
+            category: val.error_category.and_then(|c| c.parse().ok()),
         }
     }
 }
@@ -418,6 +491,405 @@ pub async fn get_checkpoint_details(
     Ok(Json(OperatorCheckpointGroupCollection { data: operators }))
 }
 
+async fn describe_checkpoint_state(
+    state: &AppState,
+    bearer_auth: BearerAuth,
+    pipeline_pub_id: &str,
+    job_pub_id: &str,
+    epoch: u32,
+) -> Result<Vec<inspect::OperatorSummary>, ErrorResp> {
+    let db = state.database.client().await?;
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    query_job_by_pub_id(pipeline_pub_id, job_pub_id, &db, &auth_data).await?;
+
+    api_queries::fetch_get_checkpoint_details(
+        &db,
+        &job_pub_id,
+        &auth_data.organization_id,
+        &(epoch as i32),
+    )
+    .await
+    .map_err(log_and_map)?
+    .into_iter()
+    .next()
+    .ok_or_else(|| {
+        not_found(&format!(
+            "Checkpoint with epoch {} for job '{}'",
+            epoch, job_pub_id
+        ))
+    })?;
+
+    inspect::describe_checkpoint(job_pub_id, epoch)
+        .await
+        .map_err(log_and_map)
+}
+
+/// List the state tables of each operator in a checkpoint
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/checkpoints/{epoch}/state",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+        ("epoch" = u32, Path, description = "Epoch")
+    ),
+    responses(
+        (status = 200, description = "Got checkpoint's state tables", body = OperatorStateCollection),
+    ),
+)]
+pub async fn get_checkpoint_state(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id, epoch)): Path<(String, String, u32)>,
+) -> Result<Json<OperatorStateCollection>, ErrorResp> {
+    let operators =
+        describe_checkpoint_state(&state, bearer_auth, &pipeline_pub_id, &job_pub_id, epoch)
+            .await?;
+
+    Ok(Json(OperatorStateCollection {
+        data: operators
+            .into_iter()
+            .map(|op| OperatorState {
+                operator_id: op.operator_id,
+                tables: op
+                    .tables
+                    .into_iter()
+                    .map(|t| StateTable {
+                        name: t.name,
+                        table_type: t.table_type,
+                        description: t.description,
+                        files: t.files,
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }))
+}
+
+/// Dump the contents of a state table in a checkpoint
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/checkpoints/{epoch}/state/{operator_id}/{table}",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+        ("epoch" = u32, Path, description = "Epoch"),
+        ("operator_id" = String, Path, description = "Operator id"),
+        ("table" = String, Path, description = "State table name"),
+        StateTableQueryParams
+    ),
+    responses(
+        (status = 200, description = "The table's rows as newline-delimited JSON, or as a Parquet file"),
+    ),
+)]
+pub async fn get_checkpoint_state_table(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id, epoch, operator_id, table)): Path<(
+        String,
+        String,
+        u32,
+        String,
+        String,
+    )>,
+    query_params: Query<StateTableQueryParams>,
+) -> Result<Response, ErrorResp> {
+    let operators =
+        describe_checkpoint_state(&state, bearer_auth, &pipeline_pub_id, &job_pub_id, epoch)
+            .await?;
+
+    let table_summary = operators
+        .iter()
+        .find(|op| op.operator_id == operator_id)
+        .ok_or_else(|| not_found(&format!("Operator '{}' in checkpoint", operator_id)))?
+        .tables
+        .iter()
+        .find(|t| t.name == table)
+        .ok_or_else(|| not_found(&format!("State table '{}'", table)))?;
+
+    if query_params.key.is_some() && table_summary.table_type != "expiring_keyed_time" {
+        return Err(bad_request(
+            "Filtering by key is only supported for keyed time tables",
+        ));
+    }
+
+    let batches = inspect::read_table(
+        &job_pub_id,
+        epoch,
+        &operator_id,
+        &table,
+        query_params.key.as_deref(),
+    )
+    .await
+    .map_err(log_and_map)?;
+
+    match query_params.format.unwrap_or_default() {
+        StateDumpFormat::Json => {
+            let rows = tokio_stream::iter(batches).map(|batch| {
+                inspect::to_json_lines(&batch).map_err(|e| std::io::Error::other(e.to_string()))
+            });
+
+            Ok((
+                [(header::CONTENT_TYPE, "application/x-ndjson")],
+                StreamBody::new(rows),
+            )
+                .into_response())
+        }
+        StateDumpFormat::Parquet => {
+            if batches.is_empty() {
+                return Err(not_found(&format!("Data in state table '{}'", table)));
+            }
+
+            let data = inspect::to_parquet(&batches).map_err(log_and_map)?;
+            Ok((
+                [
+                    (
+                        header::CONTENT_TYPE,
+                        "application/vnd.apache.parquet".to_string(),
+                    ),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}.parquet\"", table),
+                    ),
+                ],
+                data,
+            )
+                .into_response())
+        }
+    }
+}
+
+async fn plan_operators(
+    db: &Database<'_>,
+    auth_data: &AuthData,
+    job_id: &str,
+) -> Result<Vec<migrate::PlanOperator>, ErrorResp> {
+    let details = api_queries::fetch_get_job_details(db, &auth_data.organization_id, &job_id)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found(&format!("Job '{}'", job_id)))?;
+
+    Ok(ArrowProgram::decode(&details.program[..])
+        .map_err(log_and_map)?
+        .nodes
+        .into_iter()
+        .map(|node| migrate::PlanOperator {
+            operator_id: node.node_id,
+            operator_name: node.operator_name,
+            description: node.description,
+        })
+        .collect())
+}
+
+/// Migrate state from a checkpoint of another pipeline
+///
+/// Rewrites a checkpoint of an earlier version of a query as a checkpoint of this job, moving each
+/// operator's state to the matching operator of the new query. The job must be stopped, and
+/// restores from the migrated checkpoint when it's next started. The source checkpoint is marked
+/// as a savepoint, as the migrated checkpoint refers to its data.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/state_migrations",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id")
+    ),
+    request_body = StateMigrationPost,
+    responses(
+        (status = 200, description = "Migrated state", body = StateMigration),
+        (status = 400, description = "Bad request", body = ErrorResp),
+    ),
+)]
+pub async fn migrate_state(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+    WithRejection(Json(req), _): WithRejection<Json<StateMigrationPost>, ApiError>,
+) -> Result<Json<StateMigration>, ErrorResp> {
+    let db = state.database.client().await?;
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let job = query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &db, &auth_data).await?;
+
+    if !req.dry_run && !matches!(job.state.as_str(), "Stopped" | "Finished" | "Failed") {
+        return Err(bad_request(
+            "The job must be stopped before state can be migrated into it",
+        ));
+    }
+
+    Ok(Json(
+        migrate_state_int(&db, &auth_data, &job_pub_id, &req).await?,
+    ))
+}
+
+/// Trigger a savepoint
+///
+/// Starts a checkpoint of the running job that's marked as a savepoint, so that it's retained
+/// by checkpoint cleanup. The checkpoint is taken once any checkpoint that's already in progress
+/// completes; it appears in the job's checkpoints, and the job keeps running.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/savepoints",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id")
+    ),
+    responses(
+        (status = 200, description = "Triggered savepoint"),
+        (status = 400, description = "Job is not running", body = ErrorResp),
+    ),
+)]
+pub async fn trigger_savepoint(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+) -> Result<(), ErrorResp> {
+    let db = state.database.client().await?;
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let job = query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &db, &auth_data).await?;
+
+    if job.state != "Running" {
+        return Err(bad_request("Savepoints can only be taken of running jobs"));
+    }
+
+    let mut controller = tls::connect_grpc(state.controller_addr.clone())
+        .await
+        .map(|channel| ControllerGrpcClient::new(channel).compressed())
+        .map_err(log_and_map)?;
+
+    controller
+        .trigger_savepoint(grpc::TriggerSavepointReq { job_id: job.id })
+        .await
+        .map_err(|e| bad_request(format!("Failed to trigger savepoint: {}", e.message())))?;
+
+    Ok(())
+}
+
+/// Migrates state from a checkpoint of another job into a job, which the caller must ensure
+/// isn't running
+pub(crate) async fn migrate_state_int(
+    db: &Database<'_>,
+    auth_data: &AuthData,
+    job_pub_id: &str,
+    req: &StateMigrationPost,
+) -> Result<StateMigration, ErrorResp> {
+    let source_checkpoint = api_queries::fetch_get_checkpoint_details(
+        db,
+        &req.source_job_id,
+        &auth_data.organization_id,
+        &(req.source_epoch as i32),
+    )
+    .await
+    .map_err(log_and_map)?
+    .into_iter()
+    .next()
+    .filter(|c| c.finish_time.is_some())
+    .ok_or_else(|| {
+        not_found(&format!(
+            "Completed checkpoint {} for job '{}'",
+            req.source_epoch, req.source_job_id
+        ))
+    })?;
+
+    let old = plan_operators(db, auth_data, &req.source_job_id).await?;
+    let new = plan_operators(db, auth_data, job_pub_id).await?;
+
+    let stateful = migrate::stateful_operators(&req.source_job_id, req.source_epoch)
+        .await
+        .map_err(log_and_map)?;
+
+    let explicit: Vec<_> = req
+        .mappings
+        .iter()
+        .map(|m| migrate::OperatorMapping {
+            from: m.from.clone(),
+            to: m.to.clone(),
+            tables: m.tables.clone(),
+        })
+        .collect();
+
+    let (mut mappings, orphaned) = migrate::match_operators(&old, &new, &stateful, &explicit)
+        .map_err(|e| bad_request(e.to_string()))?;
+    // operators without state have nothing to migrate
+    mappings.retain(|m| stateful.contains(&m.from));
+
+    let epoch = if req.dry_run {
+        None
+    } else {
+        let epoch = api_queries::fetch_get_last_checkpoint_epoch(db, &job_pub_id)
+            .await
+            .map_err(log_and_map)?
+            .into_iter()
+            .next()
+            .flatten()
+            .unwrap_or(0) as u32
+            + 1;
+
+        migrate::migrate_checkpoint(
+            &req.source_job_id,
+            source_checkpoint.epoch as u32,
+            job_pub_id,
+            epoch,
+            &mappings,
+        )
+        .await
+        .map_err(|e| bad_request(format!("Failed to migrate state: {:#}", e)))?;
+
+        api_queries::execute_mark_checkpoint_savepoint(
+            db,
+            &req.source_job_id,
+            &auth_data.organization_id,
+            &source_checkpoint.epoch,
+        )
+        .await
+        .map_err(log_and_map)?;
+
+        api_queries::execute_create_migrated_checkpoint(
+            db,
+            &generate_id(IdTypes::Checkpoint),
+            &auth_data.organization_id,
+            &job_pub_id,
+            &StateBackend::name().to_string(),
+            &(epoch as i32),
+            &OffsetDateTime::now_utc(),
+        )
+        .await
+        .map_err(log_and_map)?;
+
+        info!(
+            message = "migrated state",
+            job_id = job_pub_id,
+            source_job_id = req.source_job_id,
+            source_epoch = req.source_epoch,
+            epoch
+        );
+
+        Some(epoch)
+    };
+
+    Ok(StateMigration {
+        epoch,
+        mappings: mappings
+            .into_iter()
+            .map(|m| OperatorStateMapping {
+                from: m.from,
+                to: m.to,
+                tables: m.tables,
+            })
+            .collect(),
+        orphaned,
+    })
+}
+
 /// Subscribe to a job's output
 #[utoipa::path(
     get,
@@ -454,8 +926,9 @@ pub async fn get_job_output(
     }
     let (tx, rx) = tokio::sync::mpsc::channel(32);
 
-    let mut controller = ControllerGrpcClient::connect(state.controller_addr.clone())
+    let mut controller = tls::connect_grpc(state.controller_addr.clone())
         .await
+        .map(|channel| ControllerGrpcClient::new(channel).compressed())
         .unwrap();
 
     let mut stream = controller
@@ -522,7 +995,15 @@ pub async fn get_jobs(
     .await?;
 
     Ok(Json(JobCollection {
-        data: jobs.into_iter().map(|p| p.into()).collect(),
+        data: jobs
+            .into_iter()
+            .filter(|j| {
+                auth_data
+                    .scope
+                    .allows_pipeline(&j.pipeline_pub_id, &j.namespace)
+            })
+            .map(|p| p.into())
+            .collect(),
     }))
 }
 
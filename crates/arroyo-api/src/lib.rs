@@ -11,6 +11,14 @@ use tonic::transport::Channel;
 use tracing::{error, info};
 use utoipa::OpenApi;
 
+use crate::api_keys::{__path_create_api_key, __path_delete_api_key, __path_get_api_keys};
+use crate::audit_log::__path_get_audit_log;
+use crate::bundles::{__path_apply_bundle, __path_export_bundle, __path_get_bundle_apply};
+use crate::catalog::{
+    __path_create_catalog_table, __path_create_catalog_table_version, __path_delete_catalog_table,
+    __path_get_catalog_table_pipelines, __path_get_catalog_table_versions,
+    __path_get_catalog_tables,
+};
 use crate::connection_profiles::{
     __path_create_connection_profile, __path_delete_connection_profile,
     __path_get_connection_profile_autocomplete, __path_get_connection_profiles,
@@ -21,34 +29,86 @@ use crate::connection_tables::{
     __path_test_connection_table, __path_test_schema,
 };
 use crate::connectors::__path_get_connectors;
+use crate::dry_run::__path_dry_run_pipeline;
+use crate::faults::{__path_create_job_fault, __path_delete_job_faults};
 use crate::jobs::{
-    __path_get_checkpoint_details, __path_get_job_checkpoints, __path_get_job_errors,
-    __path_get_job_output, __path_get_jobs,
+    __path_get_checkpoint_details, __path_get_checkpoint_state, __path_get_checkpoint_state_table,
+    __path_get_job_checkpoints, __path_get_job_errors, __path_get_job_output,
+    __path_get_job_restarts, __path_get_jobs, __path_migrate_state, __path_trigger_savepoint,
+};
+use crate::metrics::{
+    __path_get_job_analysis, __path_get_metric_history, __path_get_operator_metric_groups,
+    __path_get_watermark_history,
+};
+use crate::models::{
+    __path_create_model, __path_create_model_version, __path_delete_model,
+    __path_get_model_versions, __path_get_models,
 };
-use crate::metrics::__path_get_operator_metric_groups;
+use crate::namespaces::{__path_create_namespace, __path_delete_namespace, __path_get_namespaces};
+use crate::notifications::{
+    __path_create_notification_target, __path_delete_notification_target,
+    __path_get_notification_target, __path_get_notification_targets,
+};
+use crate::parameters::__path_update_operator_parameters;
 use crate::pipelines::__path_get_pipelines;
 use crate::pipelines::{
     __path_create_pipeline, __path_delete_pipeline, __path_get_pipeline, __path_get_pipeline_jobs,
-    __path_patch_pipeline, __path_restart_pipeline, __path_validate_query,
+    __path_patch_pipeline, __path_put_pipeline_udfs, __path_restart_pipeline, __path_test_pipeline,
+    __path_validate_query,
 };
+use crate::profiles::__path_create_job_profile;
 use crate::rest::__path_ping;
 use crate::rest_utils::{service_unavailable, ErrorResp};
-use crate::udfs::{__path_create_udf, __path_delete_udf, __path_get_udfs, __path_validate_udf};
-use arroyo_rpc::api_types::{checkpoints::*, connections::*, metrics::*, pipelines::*, udfs::*, *};
+use crate::schedules::{
+    __path_delete_pipeline_schedule, __path_get_pipeline_schedule, __path_get_scheduled_runs,
+    __path_put_pipeline_schedule,
+};
+use crate::templates::{
+    __path_create_pipeline_template, __path_delete_pipeline_template, __path_get_pipeline_template,
+    __path_get_pipeline_template_instances, __path_get_pipeline_templates,
+    __path_instantiate_pipeline_template,
+};
+use crate::udfs::{
+    __path_create_udf, __path_create_udf_version, __path_delete_udf, __path_get_udf_versions,
+    __path_get_udfs, __path_upload_udf_artifact, __path_validate_udf,
+};
+use arroyo_rpc::api_types::{
+    api_keys::*, audit_log::*, bundles::*, catalog::*, checkpoints::*, connections::*, faults::*,
+    metrics::*, models::*, namespaces::*, notifications::*, parameters::*, pipelines::*,
+    profiles::*, templates::*, udfs::*, *,
+};
 use arroyo_rpc::config::config;
 use arroyo_rpc::formats::*;
 use arroyo_rpc::grpc::compiler_grpc_client::CompilerGrpcClient;
+use arroyo_rpc::secrets::start_secret_refresher;
+use arroyo_rpc::Compressed;
 
+mod api_keys;
+mod audit_log;
+mod bundles;
+mod catalog;
 mod cloud;
 mod connection_profiles;
 mod connection_tables;
 mod connectors;
+mod dry_run;
+mod faults;
+mod flight;
 mod jobs;
 mod metrics;
+mod models;
+mod namespaces;
+mod notifications;
+mod parameters;
 mod pipelines;
+mod postgres_wire;
+mod profiles;
 pub mod rest;
 mod rest_utils;
+mod results;
+mod schedules;
 pub mod sql;
+mod templates;
 mod udfs;
 
 include!(concat!(env!("OUT_DIR"), "/api-sql.rs"));
@@ -100,7 +160,8 @@ pub struct OrgMetadata {
 pub struct AuthData {
     pub user_id: String,
     pub organization_id: String,
-    pub role: String,
+    pub role: Role,
+    pub scope: ApiKeyScope,
     pub org_metadata: OrgMetadata,
 }
 
@@ -108,10 +169,26 @@ pub(crate) fn to_micros(dt: OffsetDateTime) -> u64 {
     (dt.unix_timestamp_nanos() / 1_000) as u64
 }
 
+/// Connection settings for the configured postgres database
+pub fn postgres_pool_config() -> deadpool_postgres::Config {
+    let config = &config().database.postgres;
+    let mut cfg = deadpool_postgres::Config::new();
+    cfg.dbname = Some(config.database_name.clone());
+    cfg.host = Some(config.host.clone());
+    cfg.port = Some(config.port);
+    cfg.user = Some(config.user.clone());
+    cfg.password = Some((*config.password).clone());
+    cfg.manager = Some(deadpool_postgres::ManagerConfig {
+        recycling_method: deadpool_postgres::RecyclingMethod::Fast,
+    });
+    cfg
+}
+
 pub async fn compiler_service() -> Result<CompilerGrpcClient<Channel>, ErrorResp> {
     // TODO: cache this
     CompilerGrpcClient::connect(config().compiler_endpoint().to_string())
         .await
+        .map(Compressed::compressed)
         .map_err(|e| {
             error!("Failed to connect to compiler service: {}", e);
             service_unavailable("compiler-service")
@@ -122,6 +199,29 @@ pub async fn start_server(database: DatabaseSource) -> anyhow::Result<()> {
     let config = config();
     let addr = SocketAddr::new(config.api.bind_address, config.api.http_port);
 
+    if let Some(port) = config.api.flight_port {
+        let database = database.clone();
+        tokio::spawn(async move {
+            if let Err(e) = flight::start_flight_server(database, port).await {
+                error!("{}", e);
+            }
+        });
+    }
+
+    if let Some(port) = config.api.postgres_port {
+        let database = database.clone();
+        tokio::spawn(async move {
+            if let Err(e) = postgres_wire::start_postgres_server(database, port).await {
+                error!("{}", e);
+            }
+        });
+    }
+
+    api_keys::check_keys(&database).await?;
+
+    // secrets are resolved when connections are tested and pipelines validated
+    start_secret_refresher();
+
     let app = rest::create_rest_app(database, &config.controller_endpoint());
 
     info!("Starting API server on {:?}", addr);
@@ -208,19 +308,30 @@ impl IntoResponse for HttpError {
     paths(
         ping,
         validate_query,
+        test_pipeline,
+        dry_run_pipeline,
         validate_udf,
         create_pipeline,
         patch_pipeline,
         restart_pipeline,
+        put_pipeline_udfs,
         get_pipeline,
         delete_pipeline,
         get_pipelines,
         get_jobs,
         get_pipeline_jobs,
         get_job_errors,
+        get_job_restarts,
         get_job_checkpoints,
         get_job_output,
         get_operator_metric_groups,
+        get_job_analysis,
+        get_watermark_history,
+        get_metric_history,
+        create_job_profile,
+        create_job_fault,
+        update_operator_parameters,
+        delete_job_faults,
         get_connectors,
         get_connection_profiles,
         test_connection_profile,
@@ -233,15 +344,58 @@ impl IntoResponse for HttpError {
         test_connection_table,
         test_schema,
         get_checkpoint_details,
+        get_checkpoint_state,
+        get_checkpoint_state_table,
+        migrate_state,
+        trigger_savepoint,
         create_udf,
         get_udfs,
-        delete_udf
+        delete_udf,
+        create_udf_version,
+        get_udf_versions,
+        upload_udf_artifact,
+        create_catalog_table,
+        get_catalog_tables,
+        delete_catalog_table,
+        create_catalog_table_version,
+        get_catalog_table_versions,
+        get_catalog_table_pipelines,
+        create_model,
+        get_models,
+        delete_model,
+        create_model_version,
+        get_model_versions,
+        create_pipeline_template,
+        get_pipeline_templates,
+        get_pipeline_template,
+        delete_pipeline_template,
+        instantiate_pipeline_template,
+        get_pipeline_template_instances,
+        create_notification_target,
+        get_notification_targets,
+        get_notification_target,
+        delete_notification_target,
+        create_namespace,
+        get_namespaces,
+        delete_namespace,
+        create_api_key,
+        get_api_keys,
+        delete_api_key,
+        get_audit_log,
+        put_pipeline_schedule,
+        get_pipeline_schedule,
+        delete_pipeline_schedule,
+        get_scheduled_runs,
+        export_bundle,
+        apply_bundle,
+        get_bundle_apply
     ),
     components(schemas(
         ErrorResp,
         PipelinePost,
         PipelinePatch,
         PipelineRestart,
+        PipelineUdfsPut,
         Pipeline,
         PipelineGraph,
         PipelineNode,
@@ -253,6 +407,9 @@ impl IntoResponse for HttpError {
         JobLogMessage,
         JobLogMessageCollection,
         JobLogLevel,
+        JobRestart,
+        JobRestartCollection,
+        ErrorCategory,
         Checkpoint,
         CheckpointCollection,
         OutputData,
@@ -261,6 +418,14 @@ impl IntoResponse for HttpError {
         SubtaskMetrics,
         MetricGroup,
         OperatorMetricGroup,
+        OperatorRuntimeStats,
+        OperatorAnalysis,
+        JobAnalysis,
+        WatermarkSample,
+        OperatorWatermarkHistory,
+        OperatorWatermarkHistoryCollection,
+        OperatorMetricHistory,
+        OperatorMetricHistoryCollection,
         ConnectorCollection,
         Connector,
         ConnectionProfile,
@@ -295,23 +460,133 @@ impl IntoResponse for HttpError {
         OperatorCheckpointGroupCollection,
         SubtaskCheckpointGroup,
         OperatorCheckpointGroup,
+        StateTable,
+        OperatorState,
+        OperatorStateCollection,
+        StateDumpFormat,
+        StateTableQueryParams,
+        OperatorStateMapping,
+        StateMigrationPost,
+        StateMigration,
         ValidateQueryPost,
         QueryValidationResult,
+        IntrospectionResult,
+        PipelineTestPost,
+        PipelineTestResult,
+        SinkTestResult,
+        PipelineDryRunPost,
+        DryRunStage,
+        DiagnosticSeverity,
+        DryRunDiagnostic,
+        PipelineDryRunResult,
         ValidateUdfPost,
+        UdfLanguage,
         UdfValidationResult,
         Udf,
         UdfPost,
         GlobalUdf,
         GlobalUdfCollection,
+        UdfArtifactPost,
+        UdfArtifact,
+        UdfVersionPost,
+        UdfVersion,
+        UdfVersionCollection,
+        CatalogTablePost,
+        CatalogTable,
+        CatalogTableCollection,
+        CatalogTableVersionPost,
+        CatalogTableVersion,
+        CatalogTableVersionCollection,
+        CatalogTablePipeline,
+        CatalogTablePipelineCollection,
+        ModelPost,
+        Model,
+        ModelCollection,
+        ModelVersionPost,
+        ModelVersion,
+        ModelVersionCollection,
+        TemplateParameterType,
+        TemplateParameter,
+        PipelineTemplatePost,
+        PipelineTemplate,
+        PipelineTemplateCollection,
+        PipelineTemplateInstancePost,
+        PipelineTemplateInstance,
+        PipelineTemplateInstanceCollection,
+        NotificationEvent,
+        NotificationTargetConfig,
+        NotificationTargetPost,
+        NotificationTarget,
+        NotificationTargetCollection,
+        NotificationPayload,
+        NotificationError,
+        Namespace,
+        NamespacePost,
+        NamespaceCollection,
+        Role,
+        ApiKeyScope,
+        ApiKeyPost,
+        ApiKey,
+        ApiKeyCollection,
+        AuditAction,
+        AuditResourceType,
+        AuditLogEntry,
+        AuditLogCollection,
+        PipelineSchedulePost,
+        PipelineSchedule,
+        ScheduledRunState,
+        ScheduledRun,
+        ScheduledRunCollection,
+        WorkerPodConfig,
+        WorkerGroupPlacement,
+        FreshnessSlo,
+        RestartStrategy,
+        RestartStrategyType,
+        PipelineBatching,
+        BatchingOverride,
+        PipelineRecording,
+        RecordingMode,
+        PipelineReplay,
+        StateBootstrap,
+        ProfileKind,
+        ProfileFormat,
+        JobProfilePost,
+        WorkerProfile,
+        WorkerProfileCollection,
+        FaultKind,
+        JobFaultPost,
+        JobFaultInjection,
+        OperatorParametersPost,
+        OperatorParametersUpdate,
         BadData,
+        PipelineBundle,
+        ConnectionProfileSpec,
+        ConnectionTableSpec,
+        GlobalUdfSpec,
+        PipelineSpec,
+        BundleExportQueryParams,
+        BundleApplyQueryParams,
+        BundleResourceType,
+        BundleAction,
+        BundleChange,
+        BundleApplyResult,
+        BundleApplyStatus,
     )),
     tags(
         (name = "ping", description = "Ping endpoint"),
         (name = "connection_profiles", description = "Connection profiles management endpoints"),
         (name = "connection_tables", description = "Connection tables management endpoints"),
+        (name = "catalog", description = "Catalog of shared tables and views"),
+        (name = "models", description = "ONNX models for in-pipeline inference"),
         (name = "pipelines", description = "Pipeline management endpoints"),
+        (name = "pipeline_templates", description = "Parameterized pipeline templates"),
+        (name = "bundles", description = "Declarative export and apply of pipelines"),
+        (name = "notification_targets", description = "Alerting of job state changes"),
         (name = "jobs", description = "Job management endpoints"),
         (name = "connectors", description = "Connector management endpoints"),
+        (name = "namespaces", description = "Namespace management endpoints"),
+        (name = "api_keys", description = "API key management endpoints"),
+        (name = "audit_log", description = "Audit log endpoints"),
     )
 )]
 pub struct ApiDoc;
@@ -1,13 +1,35 @@
-use axum::extract::{Path, State};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use axum::extract::{Path, Query, State};
 use axum::Json;
+use time::OffsetDateTime;
 
 use crate::pipelines::query_job_by_pub_id;
+use crate::queries::api_queries;
 use crate::rest::AppState;
-use crate::rest_utils::{authenticate, log_and_map, BearerAuth, ErrorResp};
-use arroyo_rpc::api_types::metrics::OperatorMetricGroup;
-use arroyo_rpc::api_types::OperatorMetricGroupCollection;
+use crate::rest_utils::{authenticate, bad_request, log_and_map, not_found, BearerAuth, ErrorResp};
+use crate::to_micros;
+use arroyo_datastream::logical::LogicalProgram;
+use arroyo_rpc::api_types::metrics::{
+    JobAnalysis, Metric, MetricHistoryQueryParams, MetricName, OperatorAnalysis,
+    OperatorMetricGroup, OperatorMetricHistory, OperatorRuntimeStats, OperatorWatermarkHistory,
+    WatermarkHistoryQueryParams, WatermarkSample,
+};
+use arroyo_rpc::api_types::{
+    OperatorMetricGroupCollection, OperatorMetricHistoryCollection,
+    OperatorWatermarkHistoryCollection,
+};
+use arroyo_rpc::config::config;
+use arroyo_rpc::grpc::api::{ArrowProgram, OperatorCheckpointDetail};
 use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::grpc::JobMetricsReq;
+use arroyo_rpc::Compressed;
+use arroyo_server_common::tls;
+use arroyo_types::from_micros;
+use petgraph::Direction;
+use prost::Message;
 use tonic::Code;
 
 /// Get a job's metrics
@@ -38,8 +60,9 @@ pub async fn get_operator_metric_groups(
     )
     .await?;
 
-    let mut controller = ControllerGrpcClient::connect(state.controller_addr)
+    let mut controller = tls::connect_grpc(state.controller_addr)
         .await
+        .map(|channel| ControllerGrpcClient::new(channel).compressed())
         .map_err(log_and_map)?;
 
     let data = match controller
@@ -63,3 +86,496 @@ pub async fn get_operator_metric_groups(
 
     Ok(Json(OperatorMetricGroupCollection { data }))
 }
+
+const DEFAULT_WATERMARK_HISTORY_RANGE: Duration = Duration::from_secs(24 * 60 * 60);
+const MIN_WATERMARK_HISTORY_RESOLUTION: Duration = Duration::from_secs(60);
+const TARGET_WATERMARK_SAMPLES: u64 = 300;
+
+/// Keeps the latest sample for each operator within each `resolution`-wide bucket of time
+fn downsample(
+    samples: impl IntoIterator<Item = (String, u64, u64)>,
+    resolution: Duration,
+) -> Vec<OperatorWatermarkHistory> {
+    let resolution = (resolution.as_micros() as u64).max(1);
+
+    let mut operators: HashMap<String, BTreeMap<u64, WatermarkSample>> = HashMap::new();
+    for (operator_id, time, watermark) in samples {
+        operators
+            .entry(operator_id)
+            .or_default()
+            .entry(time / resolution)
+            .and_modify(|s| {
+                if time >= s.time {
+                    *s = WatermarkSample { time, watermark };
+                }
+            })
+            .or_insert(WatermarkSample { time, watermark });
+    }
+
+    let mut history: Vec<_> = operators
+        .into_iter()
+        .map(|(operator_id, buckets)| OperatorWatermarkHistory {
+            operator_id,
+            watermarks: buckets.into_values().collect(),
+        })
+        .collect();
+
+    history.sort_by(|a, b| a.operator_id.cmp(&b.operator_id));
+    history
+}
+
+/// Get a job's watermark history
+///
+/// Returns the watermark of each operator over time, as sampled every minute by the
+/// controller while the job is running. Samples are downsampled to the requested resolution,
+/// keeping the latest sample in each interval.
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/watermark_history",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+        WatermarkHistoryQueryParams,
+    ),
+    responses(
+        (status = 200, description = "Got watermark history", body = OperatorWatermarkHistoryCollection),
+    ),
+)]
+pub async fn get_watermark_history(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+    query_params: Query<WatermarkHistoryQueryParams>,
+) -> Result<Json<OperatorWatermarkHistoryCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let db = state.database.client().await?;
+
+    let job = query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &db, &auth_data).await?;
+
+    let end_time = query_params
+        .end_time
+        .map(from_micros)
+        .unwrap_or_else(SystemTime::now);
+    let start_time = query_params
+        .start_time
+        .map(from_micros)
+        .unwrap_or_else(|| end_time - DEFAULT_WATERMARK_HISTORY_RANGE);
+
+    let range = end_time
+        .duration_since(start_time)
+        .map_err(|_| bad_request("start_time must be before end_time"))?;
+
+    let resolution = match query_params.resolution_secs {
+        Some(0) => return Err(bad_request("resolution_secs must be greater than 0")),
+        Some(secs) => Duration::from_secs(secs),
+        None => (range / TARGET_WATERMARK_SAMPLES as u32).max(MIN_WATERMARK_HISTORY_RESOLUTION),
+    };
+
+    let samples = api_queries::fetch_get_watermark_history(
+        &db,
+        &auth_data.organization_id,
+        &job.id,
+        &OffsetDateTime::from(start_time),
+        &OffsetDateTime::from(end_time),
+    )
+    .await
+    .map_err(log_and_map)?;
+
+    Ok(Json(OperatorWatermarkHistoryCollection {
+        data: downsample(
+            samples
+                .into_iter()
+                .map(|s| (s.operator_id, to_micros(s.time), to_micros(s.watermark))),
+            resolution,
+        ),
+    }))
+}
+
+const DEFAULT_METRIC_HISTORY_RANGE: Duration = Duration::from_secs(24 * 60 * 60);
+const TARGET_METRIC_SAMPLES: u64 = 300;
+
+/// Picks the finest of the stored `resolutions` (with their retention periods, finest first)
+/// that still covers the whole range and returns at most `TARGET_METRIC_SAMPLES` values,
+/// falling back to the coarsest
+fn choose_resolution(
+    resolutions: &[(u64, Duration)],
+    now: SystemTime,
+    start_time: SystemTime,
+    range: Duration,
+) -> u64 {
+    let age = now.duration_since(start_time).unwrap_or_default();
+
+    resolutions
+        .iter()
+        .find(|(secs, retention)| {
+            age <= *retention && range.as_secs() / secs <= TARGET_METRIC_SAMPLES
+        })
+        .or(resolutions.last())
+        .map(|(secs, _)| *secs)
+        .unwrap()
+}
+
+/// Get a job's metric history
+///
+/// Returns each operator's metrics over time, aggregated across its subtasks, as recorded in
+/// the database by the controller while the job is running. Raw samples are rolled up into
+/// per-minute and per-hour averages, each of which is kept for a configurable period, so
+/// that long time ranges can be read at a coarser resolution.
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/metric_history",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+        MetricHistoryQueryParams,
+    ),
+    responses(
+        (status = 200, description = "Got metric history", body = OperatorMetricHistoryCollection),
+    ),
+)]
+pub async fn get_metric_history(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+    query_params: Query<MetricHistoryQueryParams>,
+) -> Result<Json<OperatorMetricHistoryCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let db = state.database.client().await?;
+
+    let job = query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &db, &auth_data).await?;
+
+    let now = SystemTime::now();
+    let end_time = query_params.end_time.map(from_micros).unwrap_or(now);
+    let start_time = query_params
+        .start_time
+        .map(from_micros)
+        .unwrap_or_else(|| end_time - DEFAULT_METRIC_HISTORY_RANGE);
+
+    let range = end_time
+        .duration_since(start_time)
+        .map_err(|_| bad_request("start_time must be before end_time"))?;
+
+    let resolutions = config().controller.metrics_history.resolutions();
+    let resolution_secs = match query_params.resolution_secs {
+        Some(secs) if resolutions.iter().any(|(r, _)| *r == secs) => secs,
+        Some(_) => {
+            return Err(bad_request(format!(
+                "resolution_secs must be one of {}",
+                resolutions
+                    .iter()
+                    .map(|(r, _)| r.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )))
+        }
+        None => choose_resolution(&resolutions, now, start_time, range),
+    };
+
+    let samples = api_queries::fetch_get_metric_history(
+        &db,
+        &auth_data.organization_id,
+        &job.id,
+        &(resolution_secs as i32),
+        &OffsetDateTime::from(start_time),
+        &OffsetDateTime::from(end_time),
+    )
+    .await
+    .map_err(log_and_map)?;
+
+    let mut history: BTreeMap<(String, String), Vec<Metric>> = BTreeMap::new();
+    for s in samples {
+        history
+            .entry((s.operator_id, s.metric))
+            .or_default()
+            .push(Metric {
+                time: to_micros(s.time),
+                value: s.value,
+            });
+    }
+
+    Ok(Json(OperatorMetricHistoryCollection {
+        data: history
+            .into_iter()
+            .filter_map(|((operator_id, metric), metrics)| {
+                let metric = MetricName::from_str(&metric).ok()?;
+                query_params
+                    .metric
+                    .map_or(true, |m| m == metric)
+                    .then_some(OperatorMetricHistory {
+                        operator_id,
+                        metric,
+                        resolution_secs,
+                        metrics,
+                    })
+            })
+            .collect(),
+    }))
+}
+
+/// Annotates each operator of the program with its runtime statistics and state size, in
+/// topological order
+fn analyze(
+    program: &LogicalProgram,
+    stats: Vec<OperatorRuntimeStats>,
+    state_bytes: &HashMap<String, u64>,
+) -> Vec<OperatorAnalysis> {
+    let graph = &program.graph;
+    let total_busy_secs: f64 = stats.iter().map(|s| s.busy_secs).sum();
+    let mut stats: HashMap<_, _> = stats
+        .into_iter()
+        .map(|s| (s.operator_id.clone(), s))
+        .collect();
+
+    let neighbors = |index, direction| {
+        graph
+            .neighbors_directed(index, direction)
+            .map(|n| graph[n].operator_id.clone())
+            .collect::<Vec<_>>()
+    };
+
+    petgraph::algo::toposort(graph, None)
+        .unwrap_or_else(|_| graph.node_indices().collect())
+        .into_iter()
+        .map(|index| {
+            let node = &graph[index];
+            let stats = stats.remove(&node.operator_id);
+            OperatorAnalysis {
+                operator_id: node.operator_id.clone(),
+                operator: node.operator_name.to_string(),
+                description: node.description.clone(),
+                parallelism: node.parallelism as u32,
+                inputs: neighbors(index, Direction::Incoming),
+                outputs: neighbors(index, Direction::Outgoing),
+                busy_fraction: stats
+                    .as_ref()
+                    .filter(|_| total_busy_secs > 0.0)
+                    .map(|s| s.busy_secs / total_busy_secs),
+                stats,
+                state_bytes: state_bytes.get(&node.operator_id).copied(),
+            }
+        })
+        .collect()
+}
+
+/// Analyze a job's plan
+///
+/// Returns the operators of the job's plan annotated with what each has processed since the
+/// job's workers started (rows, bytes, and time spent processing), along with the size of its
+/// state in the latest completed checkpoint. This is the equivalent of `EXPLAIN ANALYZE`, and
+/// shows which parts of the query dominate its cost. Runtime statistics are only available while
+/// the job is running.
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/analyze",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+    ),
+    responses(
+        (status = 200, description = "Analyzed plan", body = JobAnalysis),
+    ),
+)]
+pub async fn get_job_analysis(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+) -> Result<Json<JobAnalysis>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let db = state.database.client().await?;
+
+    let job = query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &db, &auth_data).await?;
+
+    let pipeline =
+        api_queries::fetch_get_pipeline(&db, &pipeline_pub_id, &auth_data.organization_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_found("Pipeline"))?;
+
+    let program: LogicalProgram = ArrowProgram::decode(&pipeline.program[..])
+        .map_err(log_and_map)?
+        .try_into()
+        .map_err(log_and_map)?;
+
+    let mut controller = tls::connect_grpc(state.controller_addr)
+        .await
+        .map(|channel| ControllerGrpcClient::new(channel).compressed())
+        .map_err(log_and_map)?;
+
+    let stats: Vec<OperatorRuntimeStats> = match controller
+        .job_metrics(JobMetricsReq {
+            job_id: job.id.clone(),
+        })
+        .await
+    {
+        Ok(resp) => serde_json::from_str(&resp.into_inner().operator_stats).unwrap_or_default(),
+        Err(e) if e.code() == Code::NotFound => vec![],
+        Err(e) => return Err(log_and_map(e)),
+    };
+
+    let checkpoint_epoch = api_queries::fetch_get_last_completed_checkpoint_epoch(
+        &db,
+        &job.id,
+        &auth_data.organization_id,
+    )
+    .await?
+    .into_iter()
+    .next()
+    .flatten();
+
+    let mut state_bytes = HashMap::new();
+    if let Some(epoch) = checkpoint_epoch {
+        let operators = api_queries::fetch_get_checkpoint_details(
+            &db,
+            &job.id,
+            &auth_data.organization_id,
+            &epoch,
+        )
+        .await
+        .map_err(log_and_map)?
+        .into_iter()
+        .next()
+        .and_then(|c| c.operators)
+        .and_then(|o| serde_json::from_value::<HashMap<String, OperatorCheckpointDetail>>(o).ok())
+        .unwrap_or_default();
+
+        for (operator_id, detail) in operators {
+            state_bytes.insert(
+                operator_id,
+                detail.tasks.values().filter_map(|t| t.bytes).sum(),
+            );
+        }
+    }
+
+    Ok(Json(JobAnalysis {
+        checkpoint_epoch: checkpoint_epoch.map(|e| e as u32),
+        operators: analyze(&program, stats, &state_bytes),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_datastream::logical::{LogicalNode, OperatorName};
+
+    #[test]
+    fn test_analyze() {
+        let mut graph = petgraph::graph::DiGraph::new();
+        for (operator_id, operator_name) in [
+            ("source_1", OperatorName::ConnectorSource),
+            ("aggregate_2", OperatorName::TumblingWindowAggregate),
+        ] {
+            graph.add_node(LogicalNode {
+                operator_id: operator_id.to_string(),
+                description: operator_id.to_string(),
+                operator_name,
+                operator_config: vec![],
+                parallelism: 2,
+            });
+        }
+        let program = LogicalProgram::new(graph, Default::default());
+
+        let stats = vec![
+            OperatorRuntimeStats {
+                operator_id: "source_1".to_string(),
+                rows_sent: 100,
+                busy_secs: 1.0,
+                ..Default::default()
+            },
+            OperatorRuntimeStats {
+                operator_id: "aggregate_2".to_string(),
+                rows_received: 100,
+                busy_secs: 3.0,
+                ..Default::default()
+            },
+        ];
+        let state_bytes = HashMap::from([("aggregate_2".to_string(), 1024)]);
+
+        let operators = analyze(&program, stats, &state_bytes);
+        assert_eq!(operators.len(), 2);
+
+        let source = operators
+            .iter()
+            .find(|o| o.operator_id == "source_1")
+            .unwrap();
+        assert_eq!(source.stats.as_ref().unwrap().rows_sent, 100);
+        assert_eq!(source.busy_fraction, Some(0.25));
+        assert_eq!(source.state_bytes, None);
+
+        let aggregate = operators
+            .iter()
+            .find(|o| o.operator_id == "aggregate_2")
+            .unwrap();
+        assert_eq!(aggregate.busy_fraction, Some(0.75));
+        assert_eq!(aggregate.state_bytes, Some(1024));
+        assert_eq!(aggregate.parallelism, 2);
+
+        // without runtime statistics, only the plan and state are described
+        let operators = analyze(&program, vec![], &state_bytes);
+        assert!(operators
+            .iter()
+            .all(|o| o.stats.is_none() && o.busy_fraction.is_none()));
+    }
+
+    #[test]
+    fn test_choose_resolution() {
+        let hour = Duration::from_secs(60 * 60);
+        let day = 24 * hour;
+        let resolutions = [(5, 6 * hour), (60, 7 * day), (3600, 90 * day)];
+        let now = SystemTime::now();
+
+        // the last 15 minutes fit in 300 raw samples
+        let range = Duration::from_secs(15 * 60);
+        assert_eq!(choose_resolution(&resolutions, now, now - range, range), 5);
+
+        // the last four hours need minute averages
+        assert_eq!(
+            choose_resolution(&resolutions, now, now - 4 * hour, 4 * hour),
+            60
+        );
+
+        // raw samples from yesterday have expired, even for a short range
+        assert_eq!(choose_resolution(&resolutions, now, now - day, range), 60);
+
+        // a week needs hourly averages
+        assert_eq!(
+            choose_resolution(&resolutions, now, now - 7 * day, 7 * day),
+            3600
+        );
+
+        // falls back to the coarsest resolution
+        assert_eq!(
+            choose_resolution(&resolutions, now, now - 365 * day, 365 * day),
+            3600
+        );
+    }
+
+    #[test]
+    fn test_downsample() {
+        let minute = 60 * 1_000_000;
+        let samples = vec![
+            ("b".to_string(), 0, 10),
+            ("a".to_string(), minute, 20),
+            ("a".to_string(), 2 * minute, 30),
+            ("a".to_string(), 6 * minute, 40),
+            ("a".to_string(), 4 * minute, 35),
+        ];
+
+        let history = downsample(samples, Duration::from_secs(5 * 60));
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].operator_id, "a");
+        let a: Vec<_> = history[0]
+            .watermarks
+            .iter()
+            .map(|s| (s.time, s.watermark))
+            .collect();
+        assert_eq!(a, vec![(4 * minute, 35), (6 * minute, 40)]);
+
+        assert_eq!(history[1].operator_id, "b");
+        assert_eq!(history[1].watermarks.len(), 1);
+    }
+}
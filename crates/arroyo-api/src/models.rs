@@ -0,0 +1,468 @@
+use crate::audit_log;
+use crate::audit_log::model_state;
+use crate::namespaces::{resolve_namespace, visible_in};
+use crate::queries::api_queries;
+use crate::queries::api_queries::{DbModel, DbModelVersion};
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, internal_server_error, map_insert_err, not_found, ApiError,
+    BearerAuth, ErrorResp,
+};
+use crate::templates::is_identifier;
+use crate::to_micros;
+use arroyo_datastream::logical::ModelConfig;
+use arroyo_df::ArroyoSchemaProvider;
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{AuditAction, AuditResourceType};
+use arroyo_rpc::api_types::models::{Model, ModelPost, ModelVersion, ModelVersionPost};
+use arroyo_rpc::api_types::{ModelCollection, ModelVersionCollection};
+use arroyo_rpc::config::config;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_storage::StorageProvider;
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use base64::Engine;
+use cornucopia_async::Database;
+use http::StatusCode;
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+use tracing::{error, warn};
+
+impl From<DbModel> for Model {
+    fn from(val: DbModel) -> Self {
+        Model {
+            id: val.pub_id,
+            name: val.name,
+            description: val.description,
+            namespace: val.namespace,
+            version: val.version,
+            url: val.url,
+            inputs: val.inputs as u32,
+            outputs: val.outputs as u32,
+            created_at: to_micros(val.created_at),
+            updated_at: to_micros(val.updated_at),
+        }
+    }
+}
+
+impl From<DbModelVersion> for ModelVersion {
+    fn from(val: DbModelVersion) -> Self {
+        ModelVersion {
+            version: val.version,
+            created_at: to_micros(val.created_at),
+            description: val.description,
+            url: val.url,
+            inputs: val.inputs as u32,
+            outputs: val.outputs as u32,
+        }
+    }
+}
+
+/// Adds the models visible in `namespace` to the schema provider as functions, at the versions
+/// pinned in `udf_versions`. Models share their names with UDFs, so they are pinned in the same
+/// map; any that aren't pinned yet are pinned to their latest version.
+pub(crate) async fn add_models(
+    db: &Database<'_>,
+    organization_id: &str,
+    namespace: &str,
+    udf_versions: &mut BTreeMap<String, i32>,
+    schema_provider: &mut ArroyoSchemaProvider,
+) -> Result<(), ErrorResp> {
+    for model in api_queries::fetch_get_models(db, organization_id)
+        .await?
+        .into_iter()
+        .filter(|m| visible_in(&m.namespace, namespace))
+    {
+        let version = *udf_versions
+            .entry(model.name.clone())
+            .or_insert(model.version);
+
+        let config = if version == model.version {
+            ModelConfig {
+                url: model.url,
+                version,
+                inputs: model.inputs as u32,
+                outputs: model.outputs as u32,
+            }
+        } else {
+            let pinned =
+                api_queries::fetch_get_model_version(db, organization_id, &model.pub_id, &version)
+                    .await?
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| {
+                        bad_request(format!(
+                            "Version {} of model {} does not exist",
+                            version, model.name
+                        ))
+                    })?;
+
+            ModelConfig {
+                url: pinned.url,
+                version,
+                inputs: pinned.inputs as u32,
+                outputs: pinned.outputs as u32,
+            }
+        };
+
+        if let Err(e) = schema_provider.add_model(&model.name, config) {
+            warn!("Invalid model {}: {}", model.name, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a version of a model to the artifact store, returning its url
+async fn store_model(
+    organization_id: &str,
+    pub_id: &str,
+    version: i32,
+    model: &str,
+) -> Result<String, ErrorResp> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(model)
+        .map_err(|e| bad_request(format!("model is not valid base64: {}", e)))?;
+
+    if bytes.is_empty() {
+        return Err(bad_request("model is empty"));
+    }
+
+    let storage = StorageProvider::for_url(&config().compiler.artifact_url)
+        .await
+        .map_err(|e| {
+            error!("unable to construct artifact storage provider: {:?}", e);
+            internal_server_error("Failed to store model")
+        })?;
+
+    // the suffix keeps concurrent uploads of the same version from overwriting each other
+    let path = format!(
+        "models/{}/{}/v{}-{:016x}.onnx",
+        organization_id,
+        pub_id,
+        version,
+        rand::random::<u64>()
+    );
+
+    storage.put(path, bytes).await.map_err(|e| {
+        error!("failed to write model to artifact storage: {:?}", e);
+        internal_server_error("Failed to store model")
+    })
+}
+
+async fn get_model(
+    db: &Database<'_>,
+    organization_id: &str,
+    pub_id: &str,
+) -> Result<Model, ErrorResp> {
+    Ok(api_queries::fetch_get_model(db, organization_id, &pub_id)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found("Model"))?
+        .into())
+}
+
+/// Create a model
+///
+/// Stores an ONNX model in the artifact store and makes it available to pipelines in the
+/// namespace as a SQL function with the model's name, which takes the model's input features as
+/// arguments and returns its scores. Rows are scored in batches inside the pipeline.
+#[utoipa::path(
+    post,
+    path = "/v1/models",
+    tag = "models",
+    request_body = ModelPost,
+    responses(
+        (status = 200, description = "Created model", body = Model),
+    ),
+)]
+pub async fn create_model(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(req), _): WithRejection<Json<ModelPost>, ApiError>,
+) -> Result<Json<Model>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let client = state.database.client().await?;
+
+    let (namespace, _) = resolve_namespace(&auth_data, &client, req.namespace.as_deref()).await?;
+    auth_data.require_namespace(&namespace)?;
+
+    if !is_identifier(&req.name) {
+        return Err(bad_request(format!(
+            "Model name '{}' is not a valid function name",
+            req.name
+        )));
+    }
+
+    if req.inputs == 0 || req.outputs == 0 {
+        return Err(bad_request(
+            "Models must have at least one input and one output",
+        ));
+    }
+
+    let pub_id = generate_id(IdTypes::Model);
+    let url = store_model(&auth_data.organization_id, &pub_id, 1, &req.model).await?;
+    let description = req.description.unwrap_or_default();
+
+    let created = audit_log::transaction(&state.database, async |client| {
+        api_queries::execute_create_model(
+            client,
+            &pub_id,
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &req.name,
+            &description,
+            &namespace,
+            &url,
+            &(req.inputs as i32),
+            &(req.outputs as i32),
+        )
+        .await
+        .map_err(|e| map_insert_err("model", e))?;
+
+        api_queries::execute_create_model_version(
+            client,
+            &pub_id,
+            &1,
+            &auth_data.user_id,
+            &description,
+            &url,
+            &(req.inputs as i32),
+            &(req.outputs as i32),
+        )
+        .await?;
+
+        let created = get_model(client, &auth_data.organization_id, &pub_id)
+            .await
+            .map_err(|_| internal_server_error("Failed to fetch created model"))?;
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Create,
+            AuditResourceType::Model,
+            &pub_id,
+            None,
+            Some(model_state(&created)),
+        )
+        .await?;
+
+        Ok(created)
+    })
+    .await?;
+
+    Ok(Json(created))
+}
+
+/// List models
+#[utoipa::path(
+    get,
+    path = "/v1/models",
+    tag = "models",
+    responses(
+        (status = 200, description = "List of models", body = ModelCollection),
+    ),
+)]
+pub async fn get_models(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+) -> Result<Json<ModelCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let models =
+        api_queries::fetch_get_models(&state.database.client().await?, &auth_data.organization_id)
+            .await?;
+
+    Ok(Json(ModelCollection {
+        data: models.into_iter().map(|m| m.into()).collect(),
+    }))
+}
+
+/// Publish a new version of a model
+///
+/// The new version becomes the latest version of the model, which new pipelines use. Existing
+/// pipelines keep the version they were created with, and can be moved to the new version
+/// without restarting through `PUT /v1/pipelines/{id}/udfs`.
+#[utoipa::path(
+    post,
+    path = "/v1/models/{id}/versions",
+    tag = "models",
+    params(
+        ("id" = String, Path, description = "Model id")
+    ),
+    request_body = ModelVersionPost,
+    responses(
+        (status = 200, description = "Updated model", body = Model),
+    ),
+)]
+pub async fn create_model_version(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+    WithRejection(Json(req), _): WithRejection<Json<ModelVersionPost>, ApiError>,
+) -> Result<Json<Model>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let client = state.database.client().await?;
+
+    let model = get_model(&client, &auth_data.organization_id, &pub_id).await?;
+    auth_data.require_namespace(&model.namespace)?;
+
+    let version = model.version + 1;
+    let url = store_model(&auth_data.organization_id, &pub_id, version, &req.model).await?;
+    let description = req
+        .description
+        .or_else(|| model.description.clone())
+        .unwrap_or_default();
+
+    let updated_model = audit_log::transaction(&state.database, async |client| {
+        let updated = api_queries::execute_update_model(
+            client,
+            &description,
+            &version,
+            &url,
+            &(model.inputs as i32),
+            &(model.outputs as i32),
+            &OffsetDateTime::now_utc(),
+            &auth_data.organization_id,
+            &pub_id,
+            &model.version,
+        )
+        .await?;
+
+        if updated != 1 {
+            return Err(ErrorResp {
+                status_code: StatusCode::CONFLICT,
+                message: format!(
+                    "Model {} was modified concurrently; retry the request",
+                    model.name
+                ),
+            });
+        }
+
+        api_queries::execute_create_model_version(
+            client,
+            &pub_id,
+            &version,
+            &auth_data.user_id,
+            &description,
+            &url,
+            &(model.inputs as i32),
+            &(model.outputs as i32),
+        )
+        .await?;
+
+        let updated_model = get_model(client, &auth_data.organization_id, &pub_id)
+            .await
+            .map_err(|_| internal_server_error("Failed to fetch updated model"))?;
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Update,
+            AuditResourceType::Model,
+            &pub_id,
+            Some(model_state(&model)),
+            Some(model_state(&updated_model)),
+        )
+        .await?;
+
+        Ok(updated_model)
+    })
+    .await?;
+
+    Ok(Json(updated_model))
+}
+
+/// Get the versions of a model
+#[utoipa::path(
+    get,
+    path = "/v1/models/{id}/versions",
+    tag = "models",
+    params(
+        ("id" = String, Path, description = "Model id")
+    ),
+    responses(
+        (status = 200, description = "Versions of the model, newest first", body = ModelVersionCollection),
+    ),
+)]
+pub async fn get_model_versions(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<Json<ModelVersionCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let versions = api_queries::fetch_get_model_versions(
+        &state.database.client().await?,
+        &auth_data.organization_id,
+        &pub_id,
+    )
+    .await?;
+
+    if versions.is_empty() {
+        return Err(not_found("Model"));
+    }
+
+    Ok(Json(ModelVersionCollection {
+        data: versions.into_iter().map(|v| v.into()).collect(),
+    }))
+}
+
+/// Delete a model
+///
+/// Running pipelines keep the model they loaded, but will fail to start again once it is deleted.
+#[utoipa::path(
+    delete,
+    path = "/v1/models/{id}",
+    tag = "models",
+    params(
+        ("id" = String, Path, description = "Model id")
+    ),
+    responses(
+        (status = 200, description = "Deleted model"),
+    ),
+)]
+pub async fn delete_model(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let client = state.database.client().await?;
+
+    let model = get_model(&client, &auth_data.organization_id, &pub_id).await?;
+    auth_data.require_namespace(&model.namespace)?;
+
+    audit_log::transaction(&state.database, async |client| {
+        let count =
+            api_queries::execute_delete_model(client, &auth_data.organization_id, &pub_id).await?;
+
+        if count != 1 {
+            return Err(not_found("Model"));
+        }
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Delete,
+            AuditResourceType::Model,
+            &pub_id,
+            Some(model_state(&model)),
+            None,
+        )
+        .await?;
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::namespaces::{Namespace, NamespacePost};
+use arroyo_rpc::api_types::NamespaceCollection;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use cornucopia_async::Database;
+
+use crate::queries::api_queries;
+use crate::queries::api_queries::DbNamespace;
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, internal_server_error, map_insert_err, not_found, required_field,
+    ApiError, BearerAuth, ErrorResp,
+};
+use crate::{to_micros, AuthData};
+
+/// The namespace that resources belong to when none is specified. It always exists and has no
+/// quotas unless a namespace with this name is explicitly created.
+pub(crate) const DEFAULT_NAMESPACE: &str = "default";
+
+impl From<DbNamespace> for Namespace {
+    fn from(val: DbNamespace) -> Self {
+        Namespace {
+            id: val.pub_id,
+            name: val.name,
+            created_at: to_micros(val.created_at),
+            max_parallelism: val.max_parallelism.map(|v| v as u32),
+            max_task_slots: val.max_task_slots.map(|v| v as u32),
+            max_state_bytes: val.max_state_bytes.map(|v| v as u64),
+        }
+    }
+}
+
+/// Returns true if a resource in namespace `resource` should be visible to a pipeline running in
+/// namespace `namespace`; resources in the default namespace are shared across all namespaces
+pub(crate) fn visible_in(resource: &str, namespace: &str) -> bool {
+    resource == namespace || resource == DEFAULT_NAMESPACE
+}
+
+/// Resolves the requested namespace (or the default namespace if none was requested) into its
+/// name and, if it has been configured, its quotas
+pub(crate) async fn resolve_namespace(
+    auth: &AuthData,
+    db: &Database<'_>,
+    namespace: Option<&str>,
+) -> Result<(String, Option<Namespace>), ErrorResp> {
+    let name = namespace
+        .map(|n| n.trim())
+        .filter(|n| !n.is_empty())
+        .unwrap_or(DEFAULT_NAMESPACE);
+
+    let namespace: Option<Namespace> =
+        api_queries::fetch_get_namespace_by_name(db, &auth.organization_id, &name)
+            .await?
+            .into_iter()
+            .next()
+            .map(|n| n.into());
+
+    if namespace.is_none() && name != DEFAULT_NAMESPACE {
+        return Err(bad_request(format!("Namespace '{}' does not exist", name)));
+    }
+
+    Ok((name.to_string(), namespace))
+}
+
+/// Create a namespace
+#[utoipa::path(
+    post,
+    path = "/v1/namespaces",
+    tag = "namespaces",
+    request_body = NamespacePost,
+    responses(
+        (status = 200, description = "Created namespace", body = Namespace),
+    ),
+)]
+pub async fn create_namespace(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(req), _): WithRejection<Json<NamespacePost>, ApiError>,
+) -> Result<Json<Namespace>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Admin)?;
+
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(required_field("name"));
+    }
+    auth_data.require_namespace(name)?;
+
+    if req.max_parallelism == Some(0) || req.max_task_slots == Some(0) {
+        return Err(bad_request(
+            "maxParallelism and maxTaskSlots must be greater than 0",
+        ));
+    }
+
+    let client = state.database.client().await?;
+    let pub_id = generate_id(IdTypes::Namespace);
+
+    api_queries::execute_create_namespace(
+        &client,
+        &pub_id,
+        &auth_data.organization_id,
+        &auth_data.user_id,
+        &name,
+        &req.max_parallelism.map(|v| v as i32),
+        &req.max_task_slots.map(|v| v as i32),
+        &req.max_state_bytes.map(|v| v as i64),
+    )
+    .await
+    .map_err(|e| map_insert_err("namespace", e))?;
+
+    let namespace = api_queries::fetch_get_namespace(&client, &auth_data.organization_id, &pub_id)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| internal_server_error("Failed to fetch created namespace"))?
+        .into();
+
+    Ok(Json(namespace))
+}
+
+/// List all namespaces
+#[utoipa::path(
+    get,
+    path = "/v1/namespaces",
+    tag = "namespaces",
+    responses(
+        (status = 200, description = "Got namespaces collection", body = NamespaceCollection),
+    ),
+)]
+pub async fn get_namespaces(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+) -> Result<Json<NamespaceCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let namespaces = api_queries::fetch_get_namespaces(
+        &state.database.client().await?,
+        &auth_data.organization_id,
+    )
+    .await?;
+
+    Ok(Json(NamespaceCollection {
+        data: namespaces.into_iter().map(|n| n.into()).collect(),
+    }))
+}
+
+/// Delete a namespace
+///
+/// A namespace can only be deleted once it no longer contains any pipelines, connection
+/// profiles, connection tables, UDFs, catalog tables, pipeline templates, or models.
+#[utoipa::path(
+    delete,
+    path = "/v1/namespaces/{id}",
+    tag = "namespaces",
+    params(
+        ("id" = String, Path, description = "Namespace id")
+    ),
+    responses(
+        (status = 200, description = "Deleted namespace"),
+    ),
+)]
+pub async fn delete_namespace(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Admin)?;
+    let client = state.database.client().await?;
+
+    let namespace = api_queries::fetch_get_namespace(&client, &auth_data.organization_id, &pub_id)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found("Namespace"))?;
+    auth_data.require_namespace(&namespace.name)?;
+
+    let deleted =
+        api_queries::execute_delete_namespace(&client, &auth_data.organization_id, &pub_id).await?;
+
+    if deleted == 0 {
+        return Err(bad_request(format!(
+            "Cannot delete namespace '{}'; it still contains pipelines, connection profiles, \
+            connection tables, UDFs, catalog tables, pipeline templates, or models",
+            namespace.name
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{AuditAction, AuditResourceType};
+use arroyo_rpc::api_types::notifications::{
+    NotificationEvent, NotificationPayload, NotificationTarget, NotificationTargetConfig,
+    NotificationTargetPost,
+};
+use arroyo_rpc::api_types::NotificationTargetCollection;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use cornucopia_async::Database;
+
+use crate::audit_log;
+use crate::audit_log::notification_target_state;
+use crate::queries::api_queries;
+use crate::queries::api_queries::DbNotificationTarget;
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, internal_server_error, map_insert_err, not_found, ApiError,
+    BearerAuth, ErrorResp,
+};
+use crate::to_micros;
+
+impl From<DbNotificationTarget> for NotificationTarget {
+    fn from(val: DbNotificationTarget) -> Self {
+        NotificationTarget {
+            id: val.pub_id,
+            name: val.name,
+            config: serde_json::from_value(val.config).expect("invalid notification target config"),
+            events: serde_json::from_value(val.events).expect("invalid notification target events"),
+            pipeline_id: val.pipeline_pub_id,
+            payload_template: val.payload_template,
+            created_at: to_micros(val.created_at),
+        }
+    }
+}
+
+fn validate_http_url(field: &str, url: &str) -> Result<(), ErrorResp> {
+    reqwest::Url::parse(url)
+        .ok()
+        .filter(|u| u.scheme() == "http" || u.scheme() == "https")
+        .ok_or_else(|| bad_request(format!("{} '{}' is not a valid HTTP URL", field, url)))?;
+    Ok(())
+}
+
+fn validate_target(req: &NotificationTargetPost) -> Result<(), ErrorResp> {
+    if req.name.trim().is_empty() {
+        return Err(bad_request("Notification target name cannot be empty"));
+    }
+
+    match &req.config {
+        NotificationTargetConfig::Webhook { url, .. } => validate_http_url("url", url)?,
+        NotificationTargetConfig::Slack { webhook_url } => {
+            validate_http_url("webhookUrl", webhook_url)?
+        }
+        NotificationTargetConfig::PagerDuty { routing_key } => {
+            if routing_key.trim().is_empty() {
+                return Err(bad_request("PagerDuty routing key cannot be empty"));
+            }
+        }
+    }
+
+    if req.events.as_ref().is_some_and(|e| e.is_empty()) {
+        return Err(bad_request(
+            "Notification targets must subscribe to at least one event",
+        ));
+    }
+
+    if let Some(template) = &req.payload_template {
+        NotificationPayload::validate_template(template)
+            .map_err(|e| bad_request(format!("Invalid payload template: {}", e)))?;
+    }
+
+    Ok(())
+}
+
+async fn get_target(
+    db: &Database<'_>,
+    organization_id: &str,
+    pub_id: &str,
+) -> Result<NotificationTarget, ErrorResp> {
+    Ok(
+        api_queries::fetch_get_notification_target(db, organization_id, &pub_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_found("Notification target"))?
+            .into(),
+    )
+}
+
+/// Create a notification target
+///
+/// The controller sends notifications to the target when jobs fail, recover, repeatedly fail to
+/// checkpoint, or time out while being scheduled.
+#[utoipa::path(
+    post,
+    path = "/v1/notification_targets",
+    tag = "notification_targets",
+    request_body = NotificationTargetPost,
+    responses(
+        (status = 200, description = "Created notification target", body = NotificationTarget),
+        (status = 400, description = "Bad request", body = ErrorResp),
+    ),
+)]
+pub async fn create_notification_target(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(req), _): WithRejection<Json<NotificationTargetPost>, ApiError>,
+) -> Result<Json<NotificationTarget>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    validate_target(&req)?;
+
+    let client = state.database.client().await?;
+
+    let pipeline_id = match &req.pipeline_id {
+        Some(pipeline_pub_id) => Some(
+            api_queries::fetch_get_pipeline_id(
+                &client,
+                pipeline_pub_id,
+                &auth_data.organization_id,
+            )
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_found("Pipeline"))?
+            .id,
+        ),
+        None => None,
+    };
+
+    let events = req.events.clone().unwrap_or_else(NotificationEvent::all);
+
+    let pub_id = generate_id(IdTypes::NotificationTarget);
+    let created = audit_log::transaction(&state.database, async |client| {
+        api_queries::execute_create_notification_target(
+            client,
+            &pub_id,
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &req.name,
+            &serde_json::to_value(&req.config).unwrap(),
+            &serde_json::to_value(&events).unwrap(),
+            &pipeline_id,
+            &req.payload_template,
+        )
+        .await
+        .map_err(|e| map_insert_err("notification target", e))?;
+
+        let created = get_target(client, &auth_data.organization_id, &pub_id)
+            .await
+            .map_err(|_| internal_server_error("Failed to fetch created notification target"))?;
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Create,
+            AuditResourceType::NotificationTarget,
+            &pub_id,
+            None,
+            Some(notification_target_state(&created)),
+        )
+        .await?;
+
+        Ok(created)
+    })
+    .await?;
+
+    Ok(Json(created))
+}
+
+/// List notification targets
+#[utoipa::path(
+    get,
+    path = "/v1/notification_targets",
+    tag = "notification_targets",
+    responses(
+        (status = 200, description = "List of notification targets", body = NotificationTargetCollection),
+    ),
+)]
+pub async fn get_notification_targets(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+) -> Result<Json<NotificationTargetCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let targets = api_queries::fetch_get_notification_targets(
+        &state.database.client().await?,
+        &auth_data.organization_id,
+    )
+    .await?;
+
+    Ok(Json(NotificationTargetCollection {
+        data: targets.into_iter().map(|t| t.into()).collect(),
+    }))
+}
+
+/// Get a notification target
+#[utoipa::path(
+    get,
+    path = "/v1/notification_targets/{id}",
+    tag = "notification_targets",
+    params(
+        ("id" = String, Path, description = "Notification target id")
+    ),
+    responses(
+        (status = 200, description = "Notification target", body = NotificationTarget),
+    ),
+)]
+pub async fn get_notification_target(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<Json<NotificationTarget>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    Ok(Json(
+        get_target(
+            &state.database.client().await?,
+            &auth_data.organization_id,
+            &pub_id,
+        )
+        .await?,
+    ))
+}
+
+/// Delete a notification target
+#[utoipa::path(
+    delete,
+    path = "/v1/notification_targets/{id}",
+    tag = "notification_targets",
+    params(
+        ("id" = String, Path, description = "Notification target id")
+    ),
+    responses(
+        (status = 200, description = "Deleted notification target"),
+    ),
+)]
+pub async fn delete_notification_target(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let client = state.database.client().await?;
+
+    let target = get_target(&client, &auth_data.organization_id, &pub_id).await?;
+
+    audit_log::transaction(&state.database, async |client| {
+        let count = api_queries::execute_delete_notification_target(
+            client,
+            &auth_data.organization_id,
+            &pub_id,
+        )
+        .await?;
+
+        if count != 1 {
+            return Err(not_found("Notification target"));
+        }
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Delete,
+            AuditResourceType::NotificationTarget,
+            &pub_id,
+            Some(notification_target_state(&target)),
+            None,
+        )
+        .await?;
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(())
+}
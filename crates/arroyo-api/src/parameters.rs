@@ -0,0 +1,190 @@
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use petgraph::graph::NodeIndex;
+use prost::Message;
+use serde_json::json;
+use tonic::Code;
+
+use crate::audit_log;
+use crate::pipelines::query_job_by_pub_id;
+use crate::queries::api_queries;
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, log_and_map, not_found, ApiError, BearerAuth, ErrorResp,
+};
+use arroyo_datastream::logical::LogicalProgram;
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{AuditAction, AuditResourceType};
+use arroyo_rpc::api_types::parameters::{OperatorParametersPost, OperatorParametersUpdate};
+use arroyo_rpc::grpc::api::ArrowProgram;
+use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
+use arroyo_rpc::grpc::UpdateJobParametersReq;
+use arroyo_rpc::Compressed;
+use arroyo_server_common::tls;
+
+/// Checks that the operator is in the program and supports all of the parameters
+fn validate(program: &LogicalProgram, req: &OperatorParametersPost) -> Result<(), ErrorResp> {
+    if req.parameters.is_empty() {
+        return Err(bad_request("parameters must not be empty"));
+    }
+
+    let node = program
+        .operator_indices
+        .get(&req.operator_id)
+        .and_then(|idx| program.graph.node_weight(NodeIndex::new(*idx as usize)))
+        .ok_or_else(|| {
+            bad_request(format!(
+                "operator '{}' is not in the pipeline",
+                req.operator_id
+            ))
+        })?;
+
+    node.operator_name
+        .validate_runtime_parameters(&req.parameters)
+        .map_err(|e| {
+            bad_request(format!(
+                "invalid parameters for '{}': {}",
+                req.operator_id, e
+            ))
+        })
+}
+
+/// Update an operator's parameters
+///
+/// Changes runtime parameters of an operator in a running job without restarting it. Each
+/// subtask applies the update between batches. Updates are not persisted, so parameters return
+/// to their configured values when the job restarts.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/parameters",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+    ),
+    request_body = OperatorParametersPost,
+    responses(
+        (status = 200, description = "Updated parameters", body = OperatorParametersUpdate),
+    ),
+)]
+pub async fn update_operator_parameters(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+    WithRejection(Json(req), _): WithRejection<Json<OperatorParametersPost>, ApiError>,
+) -> Result<Json<OperatorParametersUpdate>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let db = state.database.client().await?;
+    let job = query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &db, &auth_data).await?;
+
+    let pipeline =
+        api_queries::fetch_get_pipeline(&db, &pipeline_pub_id, &auth_data.organization_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_found("Pipeline"))?;
+
+    let program: LogicalProgram = ArrowProgram::decode(&pipeline.program[..])
+        .map_err(log_and_map)?
+        .try_into()
+        .map_err(log_and_map)?;
+
+    validate(&program, &req)?;
+
+    let mut controller = tls::connect_grpc(state.controller_addr.clone())
+        .await
+        .map(|channel| ControllerGrpcClient::new(channel).compressed())
+        .map_err(log_and_map)?;
+
+    let subtasks = match controller
+        .update_job_parameters(UpdateJobParametersReq {
+            job_id: job.id,
+            operator_id: req.operator_id.clone(),
+            parameters: req.parameters.clone(),
+        })
+        .await
+    {
+        Ok(resp) => resp.into_inner().subtasks,
+        Err(e) if e.code() == Code::NotFound => return Err(not_found("Running workers for job")),
+        Err(e) => return Err(log_and_map(e)),
+    };
+
+    audit_log::record(
+        &db,
+        &auth_data,
+        AuditAction::Update,
+        AuditResourceType::Pipeline,
+        &pipeline_pub_id,
+        None,
+        Some(json!({
+            "operatorParameters": {
+                req.operator_id.clone(): req.parameters,
+            }
+        })),
+    )
+    .await?;
+
+    Ok(Json(OperatorParametersUpdate { subtasks }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_datastream::logical::{LogicalNode, OperatorName};
+    use std::collections::HashMap;
+
+    fn program() -> LogicalProgram {
+        let mut graph = petgraph::graph::DiGraph::new();
+        graph.add_node(LogicalNode {
+            operator_id: "source_1".to_string(),
+            description: "source".to_string(),
+            operator_name: OperatorName::ConnectorSource,
+            operator_config: vec![],
+            parallelism: 1,
+        });
+        graph.add_node(LogicalNode {
+            operator_id: "value_2".to_string(),
+            description: "projection".to_string(),
+            operator_name: OperatorName::ArrowValue,
+            operator_config: vec![],
+            parallelism: 1,
+        });
+
+        LogicalProgram::new(graph, Default::default())
+    }
+
+    fn post(operator_id: &str, parameters: &[(&str, f64)]) -> OperatorParametersPost {
+        OperatorParametersPost {
+            operator_id: operator_id.to_string(),
+            parameters: parameters
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let program = program();
+
+        assert!(validate(
+            &program,
+            &post("source_1", &[("max_rows_per_second", 100.0)])
+        )
+        .is_ok());
+        assert!(validate(&program, &post("source_1", &[("max_rows_per_second", 0.0)])).is_ok());
+
+        assert!(validate(&program, &post("source_1", &[])).is_err());
+        assert!(validate(
+            &program,
+            &post("source_1", &[("max_rows_per_second", -1.0)])
+        )
+        .is_err());
+        assert!(validate(&program, &post("source_1", &[("flush_interval_ms", 100.0)])).is_err());
+        assert!(validate(&program, &post("value_2", &[("max_rows_per_second", 1.0)])).is_err());
+        assert!(validate(&program, &post("sink_3", &[("max_rows_per_second", 1.0)])).is_err());
+    }
+}
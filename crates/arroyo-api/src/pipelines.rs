@@ -1,4 +1,7 @@
 use anyhow::anyhow;
+use arrow::array::{Array, RecordBatch};
+use arrow::error::ArrowError;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
 use arrow_schema::SchemaRef;
 use arroyo_connectors::connector_for_type;
 use axum::extract::{Path, Query, State};
@@ -7,32 +10,41 @@ use axum_extra::extract::WithRejection;
 use http::StatusCode;
 
 use petgraph::{Direction, EdgeDirection};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use petgraph::visit::NodeRef;
 use std::time::Duration;
 
-use crate::{compiler_service, connection_profiles, jobs, types};
+use crate::audit_log::pipeline_state;
+use crate::namespaces::{resolve_namespace, visible_in};
+use crate::{audit_log, catalog, compiler_service, connection_profiles, jobs, models, types};
 use arroyo_datastream::preview_sink;
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{AuditAction, AuditResourceType};
 use arroyo_rpc::api_types::pipelines::{
-    Job, Pipeline, PipelinePatch, PipelinePost, PipelineRestart, QueryValidationResult, StopType,
-    ValidateQueryPost,
+    FreshnessSlo, IntrospectionResult, Job, Pipeline, PipelineBatching, PipelinePatch,
+    PipelinePost, PipelineRecording, PipelineReplay, PipelineRestart, PipelineTestPost,
+    PipelineTestResult, PipelineUdfsPut, QueryValidationResult, RecordingMode, RestartStrategy,
+    RestartStrategyType, StateBootstrap, StopType, ValidateQueryPost, WorkerPodConfig,
 };
-use arroyo_rpc::api_types::udfs::{GlobalUdf, Udf};
+use arroyo_rpc::api_types::udfs::{GlobalUdf, Udf, UdfLanguage};
 use arroyo_rpc::api_types::{JobCollection, PaginationQueryParams, PipelineCollection};
 use arroyo_rpc::grpc::api::{ArrowProgram, ConnectorOp};
 
 use arroyo_connectors::kafka::{KafkaConfig, KafkaTable, SchemaRegistry};
-use arroyo_datastream::logical::{LogicalProgram, OperatorName};
+use arroyo_datastream::logical::{LogicalProgram, OperatorName, ProgramConfig};
 use arroyo_df::{has_duplicate_udf_names, ArroyoSchemaProvider, CompiledSql, SqlConfig};
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_rpc::formats::Format;
 use arroyo_rpc::grpc::compiler_grpc_client::CompilerGrpcClient;
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_rpc::quantities::validate_resource_quantity;
 use arroyo_rpc::schema_resolver::{ConfluentSchemaRegistry, ConfluentSchemaType};
+use arroyo_rpc::secrets::resolve_secrets;
 use arroyo_rpc::{error_chain, OperatorConfig};
 use arroyo_server_common::log_event;
 use arroyo_udf_host::ParsedUdfFile;
+use arroyo_worker::fixture_test::{self, FixtureTest};
 use prost::Message;
 use serde_json::json;
 use time::OffsetDateTime;
@@ -43,31 +55,42 @@ use crate::queries::api_queries;
 use crate::queries::api_queries::{fetch_get_udfs, DbPipeline, DbPipelineJob};
 use crate::rest::AppState;
 use crate::rest_utils::{
-    authenticate, bad_request, log_and_map, not_found, paginate_results, required_field,
+    authenticate, bad_request, forbidden, log_and_map, not_found, paginate_results, required_field,
     validate_pagination_params, ApiError, BearerAuth, ErrorResp,
 };
 use crate::types::public::{PipelineType, RestartMode, StopMode};
-use crate::udfs::build_udf;
+use crate::udfs::{build_udf, pinned_version};
 use crate::AuthData;
 use crate::{connection_tables, to_micros};
-use arroyo_rpc::config::config;
+use arroyo_rpc::config::{config, Scheduler};
 use cornucopia_async::{Database, DatabaseSource};
 
-async fn compile_sql<'a>(
-    query: String,
+/// Builds the schema provider that queries in `namespace` are planned against, with the global
+/// UDFs and models at the versions in `udf_versions` (any that aren't pinned there yet are pinned
+/// to their latest version) and the catalog tables at the versions in `catalog_versions`. Returns the
+/// provider along with the version of each catalog table it includes.
+pub(crate) async fn schema_provider(
     local_udfs: &Vec<Udf>,
-    parallelism: usize,
+    udf_versions: &mut BTreeMap<String, i32>,
+    catalog_versions: &BTreeMap<String, i32>,
+    namespace: &str,
     auth_data: &AuthData,
     validate_only: bool,
     db: &DatabaseSource,
-) -> Result<CompiledSql, ErrorResp> {
+) -> Result<(ArroyoSchemaProvider, BTreeMap<String, i32>), ErrorResp> {
     let mut schema_provider = ArroyoSchemaProvider::new();
 
-    let global_udfs = fetch_get_udfs(&db.client().await?, &auth_data.organization_id)
+    let client = db.client().await?;
+    let mut global_udfs = vec![];
+    for udf in fetch_get_udfs(&client, &auth_data.organization_id)
         .await?
         .into_iter()
         .map(|u| u.into())
-        .collect::<Vec<GlobalUdf>>();
+        .filter(|u: &GlobalUdf| visible_in(&u.namespace, namespace))
+    {
+        global_udfs
+            .push(pinned_version(&client, &auth_data.organization_id, udf, udf_versions).await?);
+    }
 
     // error if there are duplicate local or duplicate global UDF names,
     // but allow  global UDFs to override local ones
@@ -76,7 +99,11 @@ async fn compile_sql<'a>(
         return Err(bad_request("Global UDFs have duplicate function names"));
     }
 
-    if has_duplicate_udf_names(local_udfs.iter().map(|u| &u.definition)) {
+    let (js_udfs, rust_udfs): (Vec<_>, Vec<_>) = local_udfs
+        .iter()
+        .partition(|u| u.language == UdfLanguage::JavaScript);
+
+    if has_duplicate_udf_names(rust_udfs.iter().map(|u| &u.definition)) {
         return Err(bad_request("Local UDFs have duplicate function names"));
     }
 
@@ -86,10 +113,30 @@ async fn compile_sql<'a>(
         }
     }
 
-    if !local_udfs.is_empty() {
+    models::add_models(
+        &client,
+        &auth_data.organization_id,
+        namespace,
+        udf_versions,
+        &mut schema_provider,
+    )
+    .await?;
+
+    let mut js_names = HashSet::new();
+    for udf in js_udfs {
+        let name = schema_provider
+            .add_js_udf(&udf.definition)
+            .map_err(|e| bad_request(format!("Invalid JavaScript UDF: {e}")))?;
+
+        if !js_names.insert(name) {
+            return Err(bad_request("Local UDFs have duplicate function names"));
+        }
+    }
+
+    if !rust_udfs.is_empty() {
         let mut compiler_service: CompilerGrpcClient<_> = compiler_service().await?;
 
-        for udf in local_udfs {
+        for udf in rust_udfs {
             let parsed = ParsedUdfFile::try_parse(&udf.definition)
                 .map_err(|e| bad_request(format!("invalid UDF: {e}")))?;
 
@@ -117,7 +164,10 @@ async fn compile_sql<'a>(
     let tables =
         connection_tables::get_all_connection_tables(auth_data, &db.client().await?).await?;
 
-    for table in tables {
+    for table in tables
+        .into_iter()
+        .filter(|t| visible_in(&t.namespace, namespace))
+    {
         let Some(connector) = connector_for_type(&table.connector) else {
             warn!(
                 "Saved table found with unknown connector {}",
@@ -144,11 +194,53 @@ async fn compile_sql<'a>(
     let profiles =
         connection_profiles::get_all_connection_profiles(auth_data, &db.client().await?).await?;
 
-    for profile in profiles {
+    for profile in profiles
+        .into_iter()
+        .filter(|p| visible_in(&p.namespace, namespace))
+    {
         schema_provider.add_connection_profile(profile);
     }
 
-    arroyo_df::parse_and_get_program(
+    let available_catalog_versions = catalog::add_catalog_tables(
+        &db.client().await?,
+        &auth_data.organization_id,
+        namespace,
+        catalog_versions,
+        &mut schema_provider,
+    )
+    .await?;
+
+    Ok((schema_provider, available_catalog_versions))
+}
+
+/// Compiles the query against the global UDFs at the versions in `udf_versions` and the catalog
+/// tables at the versions in `catalog_versions`; any global UDFs that aren't pinned yet are pinned
+/// to their latest version, and `catalog_versions` is replaced by the versions of the catalog
+/// tables the query reads
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn compile_sql<'a>(
+    query: String,
+    local_udfs: &Vec<Udf>,
+    udf_versions: &mut BTreeMap<String, i32>,
+    catalog_versions: &mut BTreeMap<String, i32>,
+    parallelism: usize,
+    namespace: &str,
+    auth_data: &AuthData,
+    validate_only: bool,
+    db: &DatabaseSource,
+) -> Result<CompiledSql, ErrorResp> {
+    let (schema_provider, available_catalog_versions) = schema_provider(
+        local_udfs,
+        udf_versions,
+        catalog_versions,
+        namespace,
+        auth_data,
+        validate_only,
+        db,
+    )
+    .await?;
+
+    let compiled = arroyo_df::parse_and_get_program(
         &query,
         schema_provider,
         SqlConfig {
@@ -159,7 +251,15 @@ async fn compile_sql<'a>(
     .map_err(|err| {
         warn!("{:?}", err);
         bad_request(err.to_string())
-    })
+    })?;
+
+    *catalog_versions = compiled
+        .catalog_tables
+        .iter()
+        .filter_map(|name| Some((name.clone(), *available_catalog_versions.get(name)?)))
+        .collect();
+
+    Ok(compiled)
 }
 
 fn set_parallelism(program: &mut LogicalProgram, parallelism: usize) {
@@ -168,19 +268,16 @@ fn set_parallelism(program: &mut LogicalProgram, parallelism: usize) {
     }
 }
 
-#[allow(unused)]
-async fn try_register_confluent_schema(
-    sink: &mut ConnectorOp,
-    schema: &SchemaRef,
-) -> anyhow::Result<()> {
-    let mut config: OperatorConfig = serde_json::from_str(&sink.config).unwrap();
-
+/// The Confluent schema registry that a Kafka sink registers its schema with, if it has one
+pub(crate) async fn confluent_schema_registry(
+    config: &OperatorConfig,
+) -> anyhow::Result<Option<ConfluentSchemaRegistry>> {
     let Ok(profile) = serde_json::from_value::<KafkaConfig>(config.connection.clone()) else {
-        return Ok(());
+        return Ok(None);
     };
 
     let Ok(table) = serde_json::from_value::<KafkaTable>(config.table.clone()) else {
-        return Ok(());
+        return Ok(None);
     };
 
     let Some(SchemaRegistry::ConfluentSchemaRegistry {
@@ -189,11 +286,29 @@ async fn try_register_confluent_schema(
         api_secret,
     }) = profile.schema_registry_enum
     else {
-        return Ok(());
+        return Ok(None);
     };
 
-    let schema_registry =
-        ConfluentSchemaRegistry::new(&endpoint, &table.subject(), api_key, api_secret)?;
+    resolve_secrets(&config.connection.to_string()).await?;
+
+    Ok(Some(ConfluentSchemaRegistry::new(
+        &endpoint,
+        &table.subject(),
+        api_key,
+        api_secret,
+    )?))
+}
+
+#[allow(unused)]
+async fn try_register_confluent_schema(
+    sink: &mut ConnectorOp,
+    schema: &SchemaRef,
+) -> anyhow::Result<()> {
+    let mut config: OperatorConfig = serde_json::from_str(&sink.config).unwrap();
+
+    let Some(schema_registry) = confluent_schema_registry(&config).await? else {
+        return Ok(());
+    };
 
     match config.format.clone() {
         Some(Format::Avro(mut avro)) => {
@@ -265,26 +380,59 @@ async fn register_schemas(compiled_sql: &mut CompiledSql) -> anyhow::Result<()>
     Ok(())
 }
 
-pub(crate) async fn create_pipeline_int<'a>(
+/// A pipeline that has been compiled and validated by [`compile_pipeline`], ready to be stored
+struct CompiledPipeline {
+    namespace: String,
+    compiled: CompiledSql,
+    udf_versions: BTreeMap<String, i32>,
+    catalog_versions: BTreeMap<String, i32>,
+}
+
+/// Compiles and validates a new pipeline, without yet storing it
+async fn compile_pipeline(
     req: &PipelinePost,
-    pub_id: &str,
-    auth: AuthData,
+    auth: &AuthData,
     db: &DatabaseSource,
-) -> Result<(i64, LogicalProgram), ErrorResp> {
+) -> Result<CompiledPipeline, ErrorResp> {
     let is_preview = req.preview.unwrap_or(false);
 
-    if req.parallelism > auth.org_metadata.max_parallelism as u64 {
-        return Err(bad_request(format!(
-            "Your plan allows you to run pipelines up to parallelism {};
+    let (namespace, ns_quota) =
+        resolve_namespace(&auth, &db.client().await?, req.namespace.as_deref()).await?;
+    auth.require_namespace(&namespace)?;
+
+    let ns_max_parallelism = ns_quota.and_then(|n| n.max_parallelism);
+    let check_parallelism = |parallelism: u64| {
+        if parallelism > auth.org_metadata.max_parallelism as u64 {
+            return Err(bad_request(format!(
+                "Your plan allows you to run pipelines up to parallelism {};
             contact support@arroyo.systems for an increase",
-            auth.org_metadata.max_parallelism
-        )));
-    }
+                auth.org_metadata.max_parallelism
+            )));
+        }
+
+        if let Some(max_parallelism) = ns_max_parallelism {
+            if parallelism > max_parallelism as u64 {
+                return Err(bad_request(format!(
+                    "Namespace '{}' allows pipelines up to parallelism {}",
+                    namespace, max_parallelism
+                )));
+            }
+        }
+
+        Ok(())
+    };
+
+    check_parallelism(req.parallelism)?;
 
+    let mut udf_versions = req.udf_versions.clone().unwrap_or_default();
+    let mut catalog_versions = BTreeMap::new();
     let mut compiled = compile_sql(
         req.query.clone(),
         req.udfs.as_ref().unwrap_or(&vec![]),
+        &mut udf_versions,
+        &mut catalog_versions,
         req.parallelism as usize,
+        &namespace,
         &auth,
         false,
         db,
@@ -297,7 +445,50 @@ pub(crate) async fn create_pipeline_int<'a>(
                 contact support@arroyo.systems for an increase", auth.org_metadata.max_operators)));
     }
 
-    set_parallelism(&mut compiled.program, 1);
+    // parallelism set in the query takes precedence over the default of 1
+    if let Some(parallelism) = compiled.settings.parallelism {
+        check_parallelism(parallelism as u64)?;
+    }
+    set_parallelism(
+        &mut compiled.program,
+        compiled.settings.parallelism.unwrap_or(1),
+    );
+
+    // as does the parallelism hinted for individual operators
+    for parallelism in compiled.parallelism_overrides.values() {
+        check_parallelism(*parallelism as u64)?;
+    }
+    compiled
+        .program
+        .update_parallelism(&compiled.parallelism_overrides);
+
+    if let Some(batching) = &req.batching {
+        validate_batching(batching, &compiled.program)?;
+        compiled.program.program_config.batching = batching.clone();
+    }
+
+    if let Some(recording) = &req.recording {
+        validate_recording(recording)?;
+        compiled.program.program_config.recording = recording.clone();
+    }
+
+    if let Some(replay) = &req.replay {
+        validate_replay(replay, &auth, db).await?;
+        compiled.program.program_config.replay = Some(replay.clone());
+    }
+
+    if let Some(bootstrap) = &req.bootstrap {
+        validate_bootstrap(bootstrap, &compiled.program)?;
+        compiled.program.program_config.bootstrap = bootstrap.clone();
+    }
+
+    if let Some(resource_classes) = &req.resource_classes {
+        validate_resource_classes(resource_classes, &compiled.program)?;
+        compiled.program.program_config.resource_classes = resource_classes
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+    }
 
     if is_preview && !config().sinks_in_preview {
         for node in compiled.program.graph.node_weights_mut() {
@@ -319,18 +510,34 @@ pub(crate) async fn create_pipeline_int<'a>(
             ),
         })?;
 
-    let proto_program: ArrowProgram = compiled.program.clone().into();
-
-    let program_bytes = proto_program.encode_to_vec();
-
     if req.name.is_empty() {
         return Err(required_field("name"));
     }
 
+    Ok(CompiledPipeline {
+        namespace,
+        compiled,
+        udf_versions,
+        catalog_versions,
+    })
+}
+
+/// Stores a compiled pipeline with the given id, returning its database id
+async fn store_pipeline(
+    req: &PipelinePost,
+    pipeline: &CompiledPipeline,
+    pub_id: &str,
+    auth: &AuthData,
+    client: &Database<'_>,
+) -> Result<i64, ErrorResp> {
+    let proto_program: ArrowProgram = pipeline.compiled.program.clone().into();
+    let program_bytes = proto_program.encode_to_vec();
+
     let udfs = serde_json::to_value(req.udfs.as_ref().unwrap_or(&vec![])).unwrap();
+    let udf_versions = serde_json::to_value(&pipeline.udf_versions).unwrap();
 
     api_queries::execute_create_pipeline(
-        &db.client().await?,
+        client,
         &pub_id,
         &auth.organization_id,
         &auth.user_id,
@@ -338,32 +545,44 @@ pub(crate) async fn create_pipeline_int<'a>(
         &PipelineType::sql,
         &Some(req.query.clone()),
         &udfs,
+        &udf_versions,
         &program_bytes,
         &2,
+        &pipeline.namespace,
     )
     .await?;
 
-    let pipeline_id =
-        api_queries::fetch_get_pipeline_id(&db.client().await?, &pub_id, &auth.organization_id)
-            .await
-            .map_err(log_and_map)?
-            .get(0)
-            .unwrap()
-            .id;
+    let pipeline_id = api_queries::fetch_get_pipeline_id(client, &pub_id, &auth.organization_id)
+        .await
+        .map_err(log_and_map)?
+        .first()
+        .ok_or_else(|| internal_server_error("Failed to fetch created pipeline"))?
+        .id;
 
-    if !is_preview {
-        for connection in compiled.connection_ids {
+    if !req.preview.unwrap_or(false) {
+        for connection in &pipeline.compiled.connection_ids {
             api_queries::execute_add_pipeline_connection_table(
-                &db.client().await?,
+                client,
                 &generate_id(IdTypes::ConnectionTablePipeline),
                 &pipeline_id,
-                &connection,
+                connection,
+            )
+            .await?;
+        }
+
+        for (name, version) in &pipeline.catalog_versions {
+            api_queries::execute_add_pipeline_catalog_table(
+                client,
+                &pipeline_id,
+                version,
+                &auth.organization_id,
+                name,
             )
             .await?;
         }
     }
 
-    Ok((pipeline_id, compiled.program))
+    Ok(pipeline_id)
 }
 
 impl TryInto<Pipeline> for DbPipeline {
@@ -389,6 +608,17 @@ impl TryInto<Pipeline> for DbPipeline {
                 .collect(),
         );
 
+        let batching = program.program_config.batching.clone();
+        let recording = program.program_config.recording.clone();
+        let replay = program.program_config.replay.clone();
+        let bootstrap = program.program_config.bootstrap.clone();
+        let resource_classes = program
+            .program_config
+            .resource_classes
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+
         let stop = match self.stop {
             StopMode::none => StopType::None,
             StopMode::checkpoint => StopType::Checkpoint,
@@ -402,6 +632,7 @@ impl TryInto<Pipeline> for DbPipeline {
             name: self.name,
             query: self.textual_repr,
             udfs: serde_json::from_value(self.udfs).map_err(log_and_map)?,
+            udf_versions: serde_json::from_value(self.udf_versions).map_err(log_and_map)?,
             checkpoint_interval_micros: self.checkpoint_interval_micros as u64,
             stop,
             created_at: to_micros(self.created_at),
@@ -410,6 +641,28 @@ impl TryInto<Pipeline> for DbPipeline {
             action_text,
             action_in_progress,
             preview: self.ttl_micros.is_some(),
+            namespace: self.namespace,
+            priority: self.priority,
+            worker_pod: self
+                .worker_pod
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(log_and_map)?,
+            freshness_slo: self
+                .freshness_slo
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(log_and_map)?,
+            restart_strategy: self
+                .restart_strategy
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(log_and_map)?,
+            batching,
+            recording,
+            replay,
+            bootstrap,
+            resource_classes,
         })
     }
 }
@@ -425,12 +678,49 @@ impl From<DbPipelineJob> for Job {
             finish_time: val.finish_time.map(to_micros),
             tasks: val.tasks.map(|t| t as u64),
             failure_message: val.failure_message,
+            failure_category: val.failure_category.and_then(|c| c.parse().ok()),
             created_at: to_micros(val.created_at),
+            preempted: val.preempted_slots.is_some(),
         }
     }
 }
 
+fn introspection_result(batch: &RecordBatch) -> Result<IntrospectionResult, ArrowError> {
+    let options = FormatOptions::default();
+    let formatters = batch
+        .columns()
+        .iter()
+        .map(|c| ArrayFormatter::try_new(c.as_ref(), &options))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let rows = (0..batch.num_rows())
+        .map(|i| {
+            batch
+                .columns()
+                .iter()
+                .zip(&formatters)
+                .map(|(column, formatter)| {
+                    (!column.is_null(i)).then(|| formatter.value(i).to_string())
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(IntrospectionResult {
+        columns: batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect(),
+        rows,
+    })
+}
+
 /// Validate a query and return pipeline graph
+///
+/// Introspection statements (`SHOW TABLES`, `SHOW CONNECTIONS`, `DESCRIBE <table>`, and queries
+/// over `information_schema`) are evaluated, and their results returned instead of a graph.
 #[utoipa::path(
     post,
     path = "/v1/pipelines/validate_query",
@@ -449,10 +739,56 @@ pub async fn validate_query(
 
     let udfs = validate_query_post.udfs.unwrap_or(vec![]);
 
+    let (namespace, _) = resolve_namespace(
+        &auth_data,
+        &state.database.client().await?,
+        validate_query_post.namespace.as_deref(),
+    )
+    .await?;
+
+    if arroyo_df::introspection::is_introspection_query(&validate_query_post.query) {
+        let (schema_provider, _) = schema_provider(
+            &udfs,
+            &mut BTreeMap::new(),
+            &BTreeMap::new(),
+            &namespace,
+            &auth_data,
+            true,
+            &state.database,
+        )
+        .await?;
+
+        let result =
+            arroyo_df::introspection::introspect(&validate_query_post.query, schema_provider)
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|batch| {
+                    batch
+                        .map(|b| introspection_result(&b).map_err(|e| e.to_string()))
+                        .transpose()
+                });
+
+        return Ok(Json(match result {
+            Ok(introspection) => QueryValidationResult {
+                graph: None,
+                errors: vec![],
+                introspection,
+            },
+            Err(e) => QueryValidationResult {
+                graph: None,
+                errors: vec![e],
+                introspection: None,
+            },
+        }));
+    }
+
     let pipeline_graph_validation_result = match compile_sql(
         validate_query_post.query,
         &udfs,
+        &mut BTreeMap::new(),
+        &mut BTreeMap::new(),
         1,
+        &namespace,
         &auth_data,
         true,
         &state.database,
@@ -462,16 +798,420 @@ pub async fn validate_query(
         Ok(CompiledSql { program, .. }) => QueryValidationResult {
             graph: Some(program.try_into().map_err(log_and_map)?),
             errors: vec![],
+            introspection: None,
         },
         Err(e) => QueryValidationResult {
             graph: None,
             errors: vec![e.message],
+            introspection: None,
         },
     };
 
     Ok(Json(pipeline_graph_validation_result))
 }
 
+/// Run a query against fixture inputs and compare its output with the expected rows
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/test",
+    tag = "pipelines",
+    request_body = PipelineTestPost,
+    responses(
+        (status = 200, description = "Test results", body = PipelineTestResult),
+    ),
+)]
+pub async fn test_pipeline(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(test_post), _): WithRejection<Json<PipelineTestPost>, ApiError>,
+) -> Result<Json<PipelineTestResult>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let udfs = test_post.udfs.unwrap_or(vec![]);
+
+    let (namespace, _) = resolve_namespace(
+        &auth_data,
+        &state.database.client().await?,
+        test_post.namespace.as_deref(),
+    )
+    .await?;
+
+    let CompiledSql { program, .. } = compile_sql(
+        test_post.query,
+        &udfs,
+        &mut BTreeMap::new(),
+        &mut BTreeMap::new(),
+        1,
+        &namespace,
+        &auth_data,
+        false,
+        &state.database,
+    )
+    .await?;
+
+    let result = fixture_test::run(FixtureTest {
+        program,
+        inputs: test_post.inputs,
+        expected: test_post.expected,
+        ordered: test_post.ordered.unwrap_or(false),
+        timeout: fixture_test::DEFAULT_TEST_TIMEOUT,
+        simulated_time: test_post.simulated_time.unwrap_or(false),
+    })
+    .await
+    .map_err(|e| bad_request(format!("Failed to run test: {:#}", e)))?;
+
+    Ok(Json(result))
+}
+
+fn validate_freshness_slo(slo: &FreshnessSlo) -> Result<(), ErrorResp> {
+    if slo.target_millis == 0 {
+        return Err(bad_request("freshness_slo.target_millis must be positive"));
+    }
+
+    let objective = slo.objective();
+    if !(objective > 0.0 && objective < 1.0) {
+        return Err(bad_request(
+            "freshness_slo.objective must be between 0 and 1 (exclusive)",
+        ));
+    }
+
+    if let Some(url) = &slo.alert_webhook_url {
+        reqwest::Url::parse(url)
+            .ok()
+            .filter(|u| u.scheme() == "http" || u.scheme() == "https")
+            .ok_or_else(|| {
+                bad_request(format!(
+                    "freshness_slo.alert_webhook_url '{}' is not a valid HTTP URL",
+                    url
+                ))
+            })?;
+    }
+
+    Ok(())
+}
+
+// only the node scheduler manages a fixed pool of task slots, so it's the only one that can make
+// room for a pipeline by preempting lower-priority ones; elsewhere a priority would be ignored
+fn validate_priority(priority: Option<i32>) -> Result<(), ErrorResp> {
+    let scheduler = &config().controller.scheduler;
+    if priority.unwrap_or(0) != 0 && *scheduler != Scheduler::Node {
+        return Err(bad_request(format!(
+            "priority can only be set when using the node scheduler, as the {:?} scheduler does not manage a fixed pool of task slots",
+            scheduler
+        )));
+    }
+
+    Ok(())
+}
+
+// worker pod overrides are only applied by the kubernetes scheduler, and let a pipeline size and
+// place pods anywhere on the cluster, so editors may only use the keys allowed by the config
+fn validate_worker_pod(worker_pod: &WorkerPodConfig, role: Role) -> Result<(), ErrorResp> {
+    let scheduler = &config().controller.scheduler;
+    if *scheduler != Scheduler::Kubernetes {
+        return Err(bad_request(format!(
+            "worker_pod can only be set when using the kubernetes scheduler, as the {:?} scheduler does not run workers in pods",
+            scheduler
+        )));
+    }
+
+    check_worker_pod(
+        worker_pod,
+        role,
+        &config().kubernetes_scheduler.editor_worker_pod_keys,
+    )
+}
+
+fn check_worker_pod(
+    worker_pod: &WorkerPodConfig,
+    role: Role,
+    editor_keys: &[String],
+) -> Result<(), ErrorResp> {
+    check_pod_placement(
+        "worker_pod",
+        PodPlacement {
+            requests: &worker_pod.requests,
+            limits: &worker_pod.limits,
+            annotations: &worker_pod.annotations,
+            node_selector: &worker_pod.node_selector,
+            scheduling: worker_pod.affinity.is_some()
+                || worker_pod.tolerations.is_some()
+                || worker_pod.topology_spread_constraints.is_some(),
+        },
+        role,
+        editor_keys,
+    )?;
+
+    for (class, group) in worker_pod.groups.iter().flatten() {
+        check_pod_placement(
+            &format!("worker_pod.groups.{}", class),
+            PodPlacement {
+                requests: &group.requests,
+                limits: &group.limits,
+                annotations: &group.annotations,
+                node_selector: &group.node_selector,
+                scheduling: group.affinity.is_some()
+                    || group.tolerations.is_some()
+                    || group.topology_spread_constraints.is_some(),
+            },
+            role,
+            editor_keys,
+        )?;
+    }
+
+    Ok(())
+}
+
+struct PodPlacement<'a> {
+    requests: &'a Option<BTreeMap<String, String>>,
+    limits: &'a Option<BTreeMap<String, String>>,
+    annotations: &'a Option<BTreeMap<String, String>>,
+    node_selector: &'a Option<BTreeMap<String, String>>,
+    // whether affinities, tolerations, or topology spread constraints are set
+    scheduling: bool,
+}
+
+fn check_pod_placement(
+    field: &str,
+    placement: PodPlacement,
+    role: Role,
+    editor_keys: &[String],
+) -> Result<(), ErrorResp> {
+    for (kind, resources) in [
+        ("requests", placement.requests),
+        ("limits", placement.limits),
+    ] {
+        for (name, value) in resources.iter().flatten() {
+            validate_resource_quantity(name, value)
+                .map_err(|e| bad_request(format!("Invalid {}.{}.{}: {}", field, kind, name, e)))?;
+        }
+    }
+
+    if role >= Role::Admin {
+        return Ok(());
+    }
+
+    if placement.scheduling {
+        return Err(forbidden(format!(
+            "Setting {} affinity, tolerations, or topology spread constraints requires the {} role",
+            field,
+            Role::Admin
+        )));
+    }
+
+    for (kind, values) in [
+        ("requests", placement.requests),
+        ("limits", placement.limits),
+        ("annotations", placement.annotations),
+        ("node_selector", placement.node_selector),
+    ] {
+        if let Some(key) = values
+            .iter()
+            .flatten()
+            .map(|(k, _)| k)
+            .find(|k| !editor_keys.contains(*k))
+        {
+            return Err(forbidden(format!(
+                "Setting {}.{}.{} requires the {} role, as it isn't in kubernetes-scheduler.editor-worker-pod-keys",
+                field,
+                kind,
+                key,
+                Role::Admin
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+// the controller keeps a week of restart history, which failure rates are computed from
+const MAX_FAILURE_RATE_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+fn validate_restart_strategy(strategy: &RestartStrategy) -> Result<(), ErrorResp> {
+    if let Some(multiplier) = strategy.multiplier {
+        if strategy.strategy != RestartStrategyType::ExponentialBackoff {
+            return Err(bad_request(
+                "restart_strategy.multiplier can only be set for exponentialBackoff",
+            ));
+        }
+        if !(multiplier >= 1.0 && multiplier.is_finite()) {
+            return Err(bad_request(
+                "restart_strategy.multiplier must be at least 1",
+            ));
+        }
+    }
+
+    if strategy.max_delay_millis.is_some()
+        && strategy.strategy != RestartStrategyType::ExponentialBackoff
+    {
+        return Err(bad_request(
+            "restart_strategy.max_delay_millis can only be set for exponentialBackoff",
+        ));
+    }
+
+    if let Some(max_delay) = strategy.max_delay_millis {
+        if strategy.delay_millis.unwrap_or(0) > max_delay {
+            return Err(bad_request(
+                "restart_strategy.delay_millis must not be greater than max_delay_millis",
+            ));
+        }
+    }
+
+    if strategy.strategy == RestartStrategyType::FailureRate {
+        if strategy.max_restarts.is_none() {
+            return Err(bad_request(
+                "restart_strategy.max_restarts is required for failureRate",
+            ));
+        }
+        let interval = Duration::from_millis(strategy.interval_millis.unwrap_or(0));
+        if interval.is_zero() || interval > MAX_FAILURE_RATE_INTERVAL {
+            return Err(bad_request(
+                "restart_strategy.interval_millis must be between 1 millisecond and 7 days for \
+                failureRate",
+            ));
+        }
+    } else if strategy.interval_millis.is_some() {
+        return Err(bad_request(
+            "restart_strategy.interval_millis can only be set for failureRate",
+        ));
+    }
+
+    Ok(())
+}
+
+const MAX_BATCH_LINGER: Duration = Duration::from_secs(60);
+
+fn validate_batching(
+    batching: &PipelineBatching,
+    program: &LogicalProgram,
+) -> Result<(), ErrorResp> {
+    let settings = std::iter::once(("batching", batching.max_rows, batching.linger_millis)).chain(
+        batching
+            .operator_overrides
+            .values()
+            .map(|o| ("batching.operator_overrides", o.max_rows, o.linger_millis)),
+    );
+
+    for (field, max_rows, linger_millis) in settings {
+        if max_rows == Some(0) {
+            return Err(bad_request(format!("{}.max_rows must be positive", field)));
+        }
+        if linger_millis.is_some_and(|l| l > MAX_BATCH_LINGER.as_millis() as u64) {
+            return Err(bad_request(format!(
+                "{}.linger_millis may be at most {}",
+                field,
+                MAX_BATCH_LINGER.as_millis()
+            )));
+        }
+    }
+
+    if let Some(operator_id) = batching
+        .operator_overrides
+        .keys()
+        .find(|id| !program.operator_indices.contains_key(*id))
+    {
+        return Err(bad_request(format!(
+            "batching.operator_overrides refers to operator '{}', which is not in the pipeline",
+            operator_id
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_recording(recording: &PipelineRecording) -> Result<(), ErrorResp> {
+    match (recording.mode, recording.sample_rate) {
+        (RecordingMode::Sampled, Some(rate)) if rate > 0.0 && rate <= 1.0 => Ok(()),
+        (RecordingMode::Sampled, Some(_)) => Err(bad_request(
+            "recording.sample_rate must be greater than 0 and at most 1",
+        )),
+        (RecordingMode::Sampled, None) => Err(bad_request(
+            "recording.sample_rate is required in sampled mode",
+        )),
+        (_, Some(_)) => Err(bad_request(
+            "recording.sample_rate may only be set in sampled mode",
+        )),
+        (_, None) => Ok(()),
+    }
+}
+
+async fn validate_replay(
+    replay: &PipelineReplay,
+    auth: &AuthData,
+    db: &DatabaseSource,
+) -> Result<(), ErrorResp> {
+    let checkpoints = api_queries::fetch_get_job_checkpoints(
+        &db.client().await?,
+        &replay.job_id,
+        &auth.organization_id,
+    )
+    .await
+    .map_err(log_and_map)?;
+
+    if !checkpoints
+        .iter()
+        .any(|c| c.epoch == replay.epoch as i32 && c.finish_time.is_some())
+    {
+        return Err(bad_request(format!(
+            "job '{}' has no completed checkpoint {} to replay from",
+            replay.job_id, replay.epoch
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_bootstrap(
+    bootstrap: &[StateBootstrap],
+    program: &LogicalProgram,
+) -> Result<(), ErrorResp> {
+    let mut seen = HashSet::new();
+    for b in bootstrap {
+        if !program.operator_indices.contains_key(&b.operator_id) {
+            return Err(bad_request(format!(
+                "bootstrap refers to operator '{}', which is not in the pipeline",
+                b.operator_id
+            )));
+        }
+        if b.table.is_empty() {
+            return Err(required_field("bootstrap.table"));
+        }
+        if b.url.is_empty() {
+            return Err(required_field("bootstrap.url"));
+        }
+        if !seen.insert((&b.operator_id, &b.table)) {
+            return Err(bad_request(format!(
+                "table '{}' of operator '{}' is bootstrapped more than once",
+                b.table, b.operator_id
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_resource_classes(
+    resource_classes: &BTreeMap<String, String>,
+    program: &LogicalProgram,
+) -> Result<(), ErrorResp> {
+    for (operator_id, class) in resource_classes {
+        if !program.operator_indices.contains_key(operator_id) {
+            return Err(bad_request(format!(
+                "resourceClasses refers to operator '{}', which is not in the pipeline",
+                operator_id
+            )));
+        }
+        if class.trim().is_empty() {
+            return Err(bad_request(format!(
+                "resource class for operator '{}' must not be empty",
+                operator_id
+            )));
+        }
+    }
+
+    Ok(())
+}
+
 /// Create a new pipeline
 ///
 /// The API will create a single job for the pipeline.
@@ -491,37 +1231,112 @@ pub async fn create_pipeline(
     WithRejection(Json(pipeline_post), _): WithRejection<Json<PipelinePost>, ApiError>,
 ) -> Result<Json<Pipeline>, ErrorResp> {
     let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
 
-    let pipeline_pub_id = generate_id(IdTypes::Pipeline);
+    Ok(Json(
+        create_pipeline_from_post(&state, auth_data, pipeline_post, false).await?,
+    ))
+}
 
-    //let transaction = db.transaction().await?;
+/// Creates a pipeline and its job on behalf of an authenticated editor. If `stopped` is set, the
+/// job is created stopped, so that it only runs once it's started.
+pub(crate) async fn create_pipeline_from_post(
+    state: &AppState,
+    auth_data: AuthData,
+    pipeline_post: PipelinePost,
+    stopped: bool,
+) -> Result<Pipeline, ErrorResp> {
+    if let Some(slo) = &pipeline_post.freshness_slo {
+        validate_freshness_slo(slo)?;
+    }
 
-    let (pipeline_id, program) = create_pipeline_int(
-        &pipeline_post,
-        &pipeline_pub_id,
-        auth_data.clone(),
-        &state.database,
-    )
-    .await?;
+    if let Some(strategy) = &pipeline_post.restart_strategy {
+        validate_restart_strategy(strategy)?;
+    }
+
+    validate_priority(pipeline_post.priority)?;
+
+    if let Some(worker_pod) = &pipeline_post.worker_pod {
+        validate_worker_pod(worker_pod, auth_data.role)?;
+    }
+
+    let pipeline_pub_id = generate_id(IdTypes::Pipeline);
+
+    let compiled = compile_pipeline(&pipeline_post, &auth_data, &state.database).await?;
+    let program = &compiled.compiled.program;
+    let settings = &compiled.compiled.settings;
 
     let preview = pipeline_post.preview.unwrap_or(false);
 
-    let checkpoint_interval = pipeline_post
-        .checkpoint_interval_micros
-        .map(Duration::from_micros)
+    // a checkpoint interval set in the query takes precedence over the one in the request
+    let checkpoint_interval = settings
+        .checkpoint_interval
+        .or(pipeline_post
+            .checkpoint_interval_micros
+            .map(Duration::from_micros))
         .unwrap_or(*config().default_checkpoint_interval);
 
-    let job_id = jobs::create_job(
-        &pipeline_post.name,
-        pipeline_id,
-        checkpoint_interval,
-        preview,
-        &auth_data,
-        &state.database,
-    )
-    .await?;
+    let worker_pod = pipeline_post
+        .worker_pod
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(log_and_map)?;
+    let freshness_slo = pipeline_post
+        .freshness_slo
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(log_and_map)?;
+    let restart_strategy = pipeline_post
+        .restart_strategy
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(log_and_map)?;
+
+    let (pipeline, job_id) = audit_log::transaction(&state.database, async |db| {
+        let pipeline_id =
+            store_pipeline(&pipeline_post, &compiled, &pipeline_pub_id, &auth_data, db).await?;
+
+        let job_id = jobs::create_job(
+            &pipeline_post.name,
+            pipeline_id,
+            checkpoint_interval,
+            pipeline_post.priority.unwrap_or(0),
+            &worker_pod,
+            &freshness_slo,
+            &restart_strategy,
+            preview,
+            if stopped {
+                types::public::StopMode::immediate
+            } else {
+                types::public::StopMode::none
+            },
+            &auth_data,
+            db,
+        )
+        .await?;
+
+        let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, db, &auth_data).await?;
+
+        // previews are short-lived and not tracked in the audit log
+        if !preview {
+            audit_log::record(
+                db,
+                &auth_data,
+                AuditAction::Create,
+                AuditResourceType::Pipeline,
+                &pipeline.id,
+                None,
+                Some(pipeline_state(&pipeline)),
+            )
+            .await?;
+        }
 
-    // transaction.commit().await?;
+        Ok((pipeline, job_id))
+    })
+    .await?;
 
     log_event(
         "job_created",
@@ -530,21 +1345,14 @@ pub async fn create_pipeline(
             "is_preview": preview,
             "job_id": job_id,
             "parallelism": pipeline_post.parallelism,
-            "has_udfs": pipeline_post.udfs.map(|e| !e.is_empty() && !e[0].definition.trim().is_empty())
+            "has_udfs": pipeline_post.udfs.as_ref().map(|e| !e.is_empty() && !e[0].definition.trim().is_empty())
               .unwrap_or(false),
             // TODO: program features
             "features": program.features(),
         }),
     );
 
-    let pipeline = query_pipeline_by_pub_id(
-        &pipeline_pub_id,
-        &state.database.client().await?,
-        &auth_data,
-    )
-    .await?;
-
-    Ok(Json(pipeline))
+    Ok(pipeline)
 }
 
 /// Update a pipeline
@@ -567,8 +1375,11 @@ pub async fn patch_pipeline(
     WithRejection(Json(pipeline_patch), _): WithRejection<Json<PipelinePatch>, ApiError>,
 ) -> Result<Json<Pipeline>, ErrorResp> {
     let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
     let db = state.database.client().await?;
 
+    let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &db, &auth_data).await?;
+
     // this assumes there is just one job for the pipeline
     let job_id =
         api_queries::fetch_get_pipeline_jobs(&db, &auth_data.organization_id, &pipeline_pub_id)
@@ -582,6 +1393,12 @@ pub async fn patch_pipeline(
         .checkpoint_interval_micros
         .map(Duration::from_micros);
 
+    let action = match &pipeline_patch.stop {
+        Some(StopType::None) => AuditAction::Start,
+        Some(_) => AuditAction::Stop,
+        None => AuditAction::Update,
+    };
+
     let stop = &pipeline_patch.stop.map(|s| match s {
         StopType::None => types::public::StopMode::none,
         StopType::Graceful => types::public::StopMode::graceful,
@@ -598,7 +1415,32 @@ pub async fn patch_pipeline(
         }
     }
 
+    if let Some(slo) = &pipeline_patch.freshness_slo {
+        validate_freshness_slo(slo)?;
+    }
+
+    if let Some(strategy) = &pipeline_patch.restart_strategy {
+        validate_restart_strategy(strategy)?;
+    }
+
+    validate_priority(pipeline_patch.priority)?;
+
+    if let Some(worker_pod) = &pipeline_patch.worker_pod {
+        validate_worker_pod(worker_pod, auth_data.role)?;
+    }
+
     let parallelism_overrides = if let Some(parallelism) = pipeline_patch.parallelism {
+        let (_, ns_quota) = resolve_namespace(&auth_data, &db, Some(&pipeline.namespace)).await?;
+
+        if let Some(max_parallelism) = ns_quota.and_then(|n| n.max_parallelism) {
+            if parallelism > max_parallelism as u64 {
+                return Err(bad_request(format!(
+                    "Namespace '{}' allows pipelines up to parallelism {}",
+                    pipeline.namespace, max_parallelism
+                )));
+            }
+        }
+
         let res = api_queries::fetch_get_job_details(&db, &auth_data.organization_id, &job_id)
             .await?
             .into_iter()
@@ -617,24 +1459,271 @@ pub async fn patch_pipeline(
         None
     };
 
-    let res = api_queries::execute_update_job(
-        &db,
-        &OffsetDateTime::now_utc(),
-        &auth_data.user_id,
-        stop,
-        &interval.map(|i| i.as_micros() as i64),
-        &parallelism_overrides,
-        &job_id,
-        &auth_data.organization_id,
-    )
+    let updated = audit_log::transaction(&state.database, async |db| {
+        let res = api_queries::execute_update_job(
+            db,
+            &OffsetDateTime::now_utc(),
+            &auth_data.user_id,
+            stop,
+            &pipeline_patch.priority,
+            &pipeline_patch
+                .worker_pod
+                .as_ref()
+                .map(serde_json::to_value)
+                .transpose()
+                .map_err(log_and_map)?,
+            &pipeline_patch
+                .freshness_slo
+                .as_ref()
+                .map(serde_json::to_value)
+                .transpose()
+                .map_err(log_and_map)?,
+            &pipeline_patch
+                .restart_strategy
+                .as_ref()
+                .map(serde_json::to_value)
+                .transpose()
+                .map_err(log_and_map)?,
+            &interval.map(|i| i.as_micros() as i64),
+            &parallelism_overrides,
+            &job_id,
+            &auth_data.organization_id,
+        )
+        .await?;
+
+        if res == 0 {
+            return Err(not_found("Job"));
+        }
+
+        let updated = query_pipeline_by_pub_id(&pipeline_pub_id, db, &auth_data).await?;
+
+        audit_log::record(
+            db,
+            &auth_data,
+            action,
+            AuditResourceType::Pipeline,
+            &pipeline_pub_id,
+            Some(pipeline_state(&pipeline)),
+            Some(pipeline_state(&updated)),
+        )
+        .await?;
+
+        Ok(updated)
+    })
     .await?;
 
-    if res == 0 {
-        return Err(not_found("Job"));
+    Ok(Json(updated))
+}
+
+/// Checks that the UDFs in `new` can be hot-reloaded in place of those in `old`: the same UDFs
+/// must be used, with unchanged signatures, and async and stateful UDFs may not change. Models
+/// may move to other versions with the same inputs and outputs. Returns whether any UDF or model
+/// implementation has changed.
+fn check_udf_reload(old: &ProgramConfig, new: &ProgramConfig) -> Result<bool, String> {
+    let mut changed = false;
+
+    for (name, old_udf) in &old.js_udfs {
+        if new.js_udfs.get(name) != Some(old_udf) {
+            return Err(format!(
+                "JavaScript UDF {} cannot be reloaded; the pipeline must be recreated to change it",
+                name
+            ));
+        }
     }
 
+    for (name, old_udf) in &old.udf_dylibs {
+        let Some(new_udf) = new.udf_dylibs.get(name) else {
+            return Err(format!(
+                "UDF {} is used by the pipeline but was not provided",
+                name
+            ));
+        };
+
+        if new_udf.arg_types != old_udf.arg_types
+            || new_udf.return_type != old_udf.return_type
+            || new_udf.aggregate != old_udf.aggregate
+            || new_udf.is_async != old_udf.is_async
+            || new_udf.is_stateful != old_udf.is_stateful
+        {
+            return Err(format!(
+                "the signature of UDF {} has changed; the pipeline must be recreated to change it",
+                name
+            ));
+        }
+
+        if new_udf.dylib_path != old_udf.dylib_path {
+            if old_udf.is_async {
+                return Err(format!("async UDF {} cannot be reloaded", name));
+            }
+            if old_udf.is_stateful {
+                return Err(format!("stateful UDF {} cannot be reloaded", name));
+            }
+            changed = true;
+        }
+    }
+
+    if let Some(name) = new
+        .udf_dylibs
+        .keys()
+        .find(|name| !old.udf_dylibs.contains_key(*name))
+    {
+        return Err(format!(
+            "UDF {} is not used by the pipeline; UDFs cannot be added without recreating it",
+            name
+        ));
+    }
+
+    for (name, old_model) in &old.models {
+        let Some(new_model) = new.models.get(name) else {
+            return Err(format!(
+                "model {} is used by the pipeline but is no longer available",
+                name
+            ));
+        };
+
+        if new_model.inputs != old_model.inputs || new_model.outputs != old_model.outputs {
+            return Err(format!(
+                "the inputs or outputs of model {} have changed; the pipeline must be recreated to change them",
+                name
+            ));
+        }
+
+        changed |= new_model.url != old_model.url;
+    }
+
+    Ok(changed)
+}
+
+/// Update a pipeline's UDFs
+///
+/// Replaces the implementations of the pipeline's UDFs without restarting it, including moving it
+/// to other versions of global UDFs and models. Each UDF must keep its name and signature, and async and
+/// stateful UDFs cannot be changed. If the pipeline is running, the new implementations are
+/// swapped in once its next checkpoint completes; otherwise they are used the next time it
+/// starts.
+#[utoipa::path(
+    put,
+    path = "/v1/pipelines/{id}/udfs",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    request_body = PipelineUdfsPut,
+    responses(
+        (status = 200, description = "Updated pipeline", body = Pipeline),
+    ),
+)]
+pub async fn put_pipeline_udfs(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+    WithRejection(Json(req), _): WithRejection<Json<PipelineUdfsPut>, ApiError>,
+) -> Result<Json<Pipeline>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+    let db = state.database.client().await?;
+
     let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &db, &auth_data).await?;
-    Ok(Json(pipeline))
+    auth_data.require_namespace(&pipeline.namespace)?;
+
+    if pipeline.preview {
+        return Err(bad_request("Preview pipelines cannot be updated"));
+    }
+
+    // this assumes there is just one job for the pipeline
+    let job_id =
+        api_queries::fetch_get_pipeline_jobs(&db, &auth_data.organization_id, &pipeline_pub_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| bad_request("There are no jobs for the pipeline"))?
+            .id;
+
+    let details = api_queries::fetch_get_job_details(&db, &auth_data.organization_id, &job_id)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found("Job"))?;
+
+    let mut program: LogicalProgram = ArrowProgram::decode(&details.program[..])
+        .map_err(log_and_map)?
+        .try_into()
+        .map_err(log_and_map)?;
+
+    let mut udf_versions = pipeline.udf_versions.clone();
+    udf_versions.extend(req.udf_versions.clone().unwrap_or_default());
+
+    // the query is planned against the catalog tables it was created with
+    let mut catalog_versions: BTreeMap<String, i32> =
+        api_queries::fetch_get_pipeline_catalog_versions(&db, &details.pipeline_id)
+            .await?
+            .into_iter()
+            .map(|t| (t.name, t.version))
+            .collect();
+
+    let compiled = compile_sql(
+        pipeline.query.clone(),
+        &req.udfs,
+        &mut udf_versions,
+        &mut catalog_versions,
+        1,
+        &pipeline.namespace,
+        &auth_data,
+        false,
+        &state.database,
+    )
+    .await?;
+
+    let changed = check_udf_reload(&program.program_config, &compiled.program.program_config)
+        .map_err(bad_request)?;
+
+    // only the UDF dylibs and the models the pipeline already loads are replaced; the rest of
+    // the program is unchanged
+    program.program_config.udf_dylibs = compiled.program.program_config.udf_dylibs;
+    let mut models = compiled.program.program_config.models;
+    models.retain(|name, _| program.program_config.models.contains_key(name));
+    program.program_config.models = models;
+    let program_bytes = ArrowProgram::from(program).encode_to_vec();
+
+    let updated = audit_log::transaction(&state.database, async |db| {
+        api_queries::execute_update_pipeline_udfs(
+            db,
+            &serde_json::to_value(&req.udfs).map_err(log_and_map)?,
+            &serde_json::to_value(&udf_versions).map_err(log_and_map)?,
+            &program_bytes,
+            &details.pipeline_id,
+        )
+        .await?;
+
+        if changed {
+            api_queries::execute_reload_job_udfs(
+                db,
+                &OffsetDateTime::now_utc(),
+                &auth_data.user_id,
+                &job_id,
+                &auth_data.organization_id,
+            )
+            .await?;
+        }
+
+        let updated = query_pipeline_by_pub_id(&pipeline_pub_id, db, &auth_data).await?;
+
+        audit_log::record(
+            db,
+            &auth_data,
+            AuditAction::Update,
+            AuditResourceType::Pipeline,
+            &pipeline_pub_id,
+            Some(pipeline_state(&pipeline)),
+            Some(pipeline_state(&updated)),
+        )
+        .await?;
+
+        Ok(updated)
+    })
+    .await?;
+
+    Ok(Json(updated))
 }
 
 /// Restart a pipeline
@@ -656,8 +1745,11 @@ pub async fn restart_pipeline(
     WithRejection(Json(req), _): WithRejection<Json<PipelineRestart>, ApiError>,
 ) -> Result<Json<Pipeline>, ErrorResp> {
     let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
     let db = state.database.client().await?;
 
+    query_pipeline_by_pub_id(&id, &db, &auth_data).await?;
+
     let job_id = api_queries::fetch_get_pipeline_jobs(&db, &auth_data.organization_id, &id)
         .await?
         .into_iter()
@@ -671,19 +1763,35 @@ pub async fn restart_pipeline(
         RestartMode::safe
     };
 
-    let res = api_queries::execute_restart_job(
-        &db,
-        &OffsetDateTime::now_utc(),
-        &auth_data.user_id,
-        &mode,
-        &job_id,
-        &auth_data.organization_id,
-    )
-    .await?;
+    audit_log::transaction(&state.database, async |db| {
+        let res = api_queries::execute_restart_job(
+            db,
+            &OffsetDateTime::now_utc(),
+            &auth_data.user_id,
+            &mode,
+            &job_id,
+            &auth_data.organization_id,
+        )
+        .await?;
+
+        if res == 0 {
+            return Err(not_found("Pipeline"));
+        }
 
-    if res == 0 {
-        return Err(not_found("Pipeline"));
-    }
+        audit_log::record(
+            db,
+            &auth_data,
+            AuditAction::Restart,
+            AuditResourceType::Pipeline,
+            &id,
+            None,
+            Some(json!({ "force": req.force == Some(true) })),
+        )
+        .await?;
+
+        Ok(())
+    })
+    .await?;
 
     let pipeline = query_pipeline_by_pub_id(&id, &db, &auth_data).await?;
     Ok(Json(pipeline))
@@ -725,6 +1833,7 @@ pub async fn get_pipelines(
         has_more,
         data: pipelines
             .into_iter()
+            .filter(|p| auth_data.scope.allows_pipeline(&p.pub_id, &p.namespace))
             .filter_map(|p| {
                 let id = p.pub_id.clone();
                 match p.try_into() {
@@ -785,16 +1894,18 @@ pub async fn delete_pipeline(
     Path(pipeline_pub_id): Path<String>,
 ) -> Result<(), ErrorResp> {
     let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
 
-    let jobs: Vec<Job> = api_queries::fetch_get_pipeline_jobs(
-        &state.database.client().await?,
-        &auth_data.organization_id,
-        &pipeline_pub_id,
-    )
-    .await?
-    .into_iter()
-    .map(|j| j.into())
-    .collect();
+    let db = state.database.client().await?;
+
+    let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &db, &auth_data).await?;
+
+    let jobs: Vec<Job> =
+        api_queries::fetch_get_pipeline_jobs(&db, &auth_data.organization_id, &pipeline_pub_id)
+            .await?
+            .into_iter()
+            .map(|j| j.into())
+            .collect();
 
     if jobs
         .iter()
@@ -805,16 +1916,31 @@ pub async fn delete_pipeline(
         ));
     }
 
-    let count = api_queries::execute_delete_pipeline(
-        &state.database.client().await?,
-        &pipeline_pub_id,
-        &auth_data.organization_id,
-    )
-    .await?;
+    audit_log::transaction(&state.database, async |db| {
+        let count =
+            api_queries::execute_delete_pipeline(db, &pipeline_pub_id, &auth_data.organization_id)
+                .await?;
 
-    if count != 1 {
-        return Err(not_found("Pipeline"));
-    }
+        if count != 1 {
+            return Err(not_found("Pipeline"));
+        }
+
+        if !pipeline.preview {
+            audit_log::record(
+                db,
+                &auth_data,
+                AuditAction::Delete,
+                AuditResourceType::Pipeline,
+                &pipeline_pub_id,
+                Some(pipeline_state(&pipeline)),
+                None,
+            )
+            .await?;
+        }
+
+        Ok(())
+    })
+    .await?;
 
     Ok(())
 }
@@ -860,6 +1986,8 @@ pub async fn query_pipeline_by_pub_id<'a>(
         .await?
         .into_iter()
         .next()
+        // pipelines outside of an API key's scope are treated as though they don't exist
+        .filter(|p| auth_data.scope.allows_pipeline(&p.pub_id, &p.namespace))
         .ok_or_else(|| not_found("Pipeline"))?
         .try_into()
 }
@@ -878,7 +2006,170 @@ pub async fn query_job_by_pub_id<'a>(
             .await?
             .into_iter()
             .next()
+            .filter(|j| &j.pipeline_pub_id == pipeline_pub_id)
             .ok_or_else(|| not_found("Job"))?
             .into(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_schema::DataType;
+    use arroyo_datastream::logical::{DylibUdfConfig, ModelConfig};
+    use arroyo_rpc::api_types::pipelines::WorkerGroupPlacement;
+
+    fn config(udfs: &[(&str, &str, DataType, bool)]) -> ProgramConfig {
+        ProgramConfig {
+            udf_dylibs: udfs
+                .iter()
+                .map(|(name, path, return_type, is_async)| {
+                    (
+                        name.to_string(),
+                        DylibUdfConfig {
+                            dylib_path: path.to_string(),
+                            arg_types: vec![DataType::Int64],
+                            return_type: return_type.clone(),
+                            aggregate: false,
+                            is_async: *is_async,
+                            is_stateful: false,
+                        },
+                    )
+                })
+                .collect(),
+            js_udfs: Default::default(),
+            models: Default::default(),
+            batching: Default::default(),
+            recording: Default::default(),
+            replay: None,
+            bootstrap: vec![],
+            resource_classes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_check_udf_reload() {
+        let old = config(&[
+            ("a", "a1.so", DataType::Int64, false),
+            ("b", "b1.so", DataType::Utf8, true),
+        ]);
+
+        assert_eq!(check_udf_reload(&old, &old), Ok(false));
+
+        let new = config(&[
+            ("a", "a2.so", DataType::Int64, false),
+            ("b", "b1.so", DataType::Utf8, true),
+        ]);
+        assert_eq!(check_udf_reload(&old, &new), Ok(true));
+
+        // signature changes
+        let new = config(&[
+            ("a", "a2.so", DataType::Utf8, false),
+            ("b", "b1.so", DataType::Utf8, true),
+        ]);
+        assert!(check_udf_reload(&old, &new).is_err());
+
+        // async UDFs can't be reloaded
+        let new = config(&[
+            ("a", "a1.so", DataType::Int64, false),
+            ("b", "b2.so", DataType::Utf8, true),
+        ]);
+        assert!(check_udf_reload(&old, &new).is_err());
+
+        // UDFs can't be removed or added
+        let new = config(&[("a", "a2.so", DataType::Int64, false)]);
+        assert!(check_udf_reload(&old, &new).is_err());
+        assert!(check_udf_reload(&new, &old).is_err());
+    }
+
+    #[test]
+    fn test_check_model_reload() {
+        let with_model = |url: &str, version: i32, outputs: u32| {
+            let mut config = config(&[]);
+            config.models.insert(
+                "fraud_score".to_string(),
+                ModelConfig {
+                    url: url.to_string(),
+                    version,
+                    inputs: 3,
+                    outputs,
+                },
+            );
+            config
+        };
+
+        let old = with_model("v1.onnx", 1, 1);
+        assert_eq!(check_udf_reload(&old, &old), Ok(false));
+        assert_eq!(
+            check_udf_reload(&old, &with_model("v2.onnx", 2, 1)),
+            Ok(true)
+        );
+
+        // the function's signature can't change
+        assert!(check_udf_reload(&old, &with_model("v2.onnx", 2, 2)).is_err());
+
+        // models used by the pipeline can't be removed
+        assert!(check_udf_reload(&old, &config(&[])).is_err());
+    }
+
+    #[test]
+    fn test_validate_priority() {
+        // the default config uses the process scheduler, which can't preempt jobs
+        assert!(validate_priority(None).is_ok());
+        assert!(validate_priority(Some(0)).is_ok());
+        assert!(validate_priority(Some(5)).is_err());
+    }
+
+    #[test]
+    fn test_validate_worker_pod() {
+        // the default config uses the process scheduler, which doesn't run pods
+        assert!(validate_worker_pod(&WorkerPodConfig::default(), Role::Admin).is_err());
+    }
+
+    #[test]
+    fn test_check_worker_pod() {
+        let map = |entries: &[(&str, &str)]| {
+            Some(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<BTreeMap<_, _>>(),
+            )
+        };
+        let editor_keys = vec!["cpu".to_string(), "memory".to_string()];
+
+        let sized = WorkerPodConfig {
+            requests: map(&[("cpu", "2"), ("memory", "4Gi")]),
+            ..Default::default()
+        };
+        assert!(check_worker_pod(&sized, Role::Editor, &editor_keys).is_ok());
+
+        // resource quantities are validated for everyone
+        let invalid = WorkerPodConfig {
+            limits: map(&[("memory", "a lot")]),
+            ..Default::default()
+        };
+        assert!(check_worker_pod(&invalid, Role::Admin, &editor_keys).is_err());
+
+        // keys that aren't allowed for editors, including in groups, require the admin role
+        let placed = WorkerPodConfig {
+            node_selector: map(&[("pool", "dedicated")]),
+            ..Default::default()
+        };
+        assert!(check_worker_pod(&placed, Role::Editor, &editor_keys).is_err());
+        assert!(check_worker_pod(&placed, Role::Admin, &editor_keys).is_ok());
+
+        let grouped = WorkerPodConfig {
+            groups: Some(BTreeMap::from([(
+                "gpu".to_string(),
+                WorkerGroupPlacement {
+                    requests: map(&[("nvidia.com/gpu", "1")]),
+                    ..Default::default()
+                },
+            )])),
+            ..Default::default()
+        };
+        assert!(check_worker_pod(&grouped, Role::Editor, &editor_keys).is_err());
+        assert!(check_worker_pod(&grouped, Role::Admin, &editor_keys).is_ok());
+    }
+}
@@ -0,0 +1,667 @@
+//! A read-only Postgres wire-protocol endpoint that serves the results materialized by pipelines'
+//! flight sinks, so that Postgres clients like psql, Grafana and ORMs can query them directly.
+//!
+//! See [`crate::results`] for how results are exposed. Clients authenticate with an API key,
+//! given as the password (the user name is ignored). Both the simple and extended query protocols
+//! are supported, although queries can't have parameters, and results are always sent in text
+//! format. Session commands like `SET` and `BEGIN` are accepted but have no effect.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use anyhow::{anyhow, bail};
+use arrow::array::{Array, AsArray};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use arroyo_rpc::config::config;
+use axum::http::StatusCode;
+use cornucopia_async::DatabaseSource;
+use futures::TryStreamExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+use crate::rest_utils::ErrorResp;
+use crate::results::ResultCatalog;
+use crate::AuthData;
+
+const PROTOCOL_VERSION: i32 = 196608;
+const SSL_REQUEST: i32 = 80877103;
+const GSSENC_REQUEST: i32 = 80877104;
+const CANCEL_REQUEST: i32 = 80877102;
+
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Commands that clients send to set up their sessions, which are acknowledged but ignored
+const SESSION_COMMANDS: &[&str] = &[
+    "SET",
+    "RESET",
+    "BEGIN",
+    "START",
+    "COMMIT",
+    "END",
+    "ROLLBACK",
+    "DISCARD",
+    "DEALLOCATE",
+];
+
+/// A Postgres error, with its SQLSTATE code
+struct PgError {
+    code: &'static str,
+    message: String,
+}
+
+impl PgError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<ErrorResp> for PgError {
+    fn from(e: ErrorResp) -> Self {
+        let code = match e.status_code {
+            StatusCode::UNAUTHORIZED => "28000",
+            StatusCode::FORBIDDEN => "42501",
+            StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND => "42000",
+            _ => "XX000",
+        };
+        Self::new(code, e.message)
+    }
+}
+
+/// The Postgres type OID that results of `data_type` are sent as
+fn type_oid(data_type: &DataType) -> i32 {
+    match data_type {
+        DataType::Boolean => 16,
+        DataType::Int8 | DataType::Int16 | DataType::UInt8 => 21,
+        DataType::Int32 | DataType::UInt16 => 23,
+        DataType::Int64 | DataType::UInt32 => 20,
+        DataType::Float16 | DataType::Float32 => 700,
+        DataType::Float64 => 701,
+        DataType::UInt64 | DataType::Decimal128(_, _) | DataType::Decimal256(_, _) => 1700,
+        DataType::Binary | DataType::LargeBinary => 17,
+        DataType::Date32 | DataType::Date64 => 1082,
+        DataType::Timestamp(_, None) => 1114,
+        DataType::Timestamp(_, Some(_)) => 1184,
+        DataType::Utf8 | DataType::LargeUtf8 => 25,
+        _ => 25,
+    }
+}
+
+/// Formats a column as Postgres text values
+fn format_column(array: &dyn Array) -> arrow::error::Result<Vec<Option<String>>> {
+    if let DataType::Binary = array.data_type() {
+        return Ok(array
+            .as_binary::<i32>()
+            .iter()
+            .map(|v| v.map(|v| format!("\\x{}", hex(v))))
+            .collect());
+    }
+
+    let options = FormatOptions::default()
+        .with_timestamp_format(Some("%Y-%m-%d %H:%M:%S%.f"))
+        .with_timestamp_tz_format(Some("%Y-%m-%d %H:%M:%S%.f%:z"));
+    let formatter = ArrayFormatter::try_new(array, &options)?;
+    Ok((0..array.len())
+        .map(|i| array.is_valid(i).then(|| formatter.value(i).to_string()))
+        .collect())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Builds backend messages, which are a type byte followed by the length of the body
+struct MessageBuilder {
+    buf: Vec<u8>,
+}
+
+impl MessageBuilder {
+    fn new(tag: u8) -> Self {
+        Self {
+            buf: vec![tag, 0, 0, 0, 0],
+        }
+    }
+
+    fn i16(mut self, v: i16) -> Self {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn i32(mut self, v: i32) -> Self {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn bytes(mut self, v: &[u8]) -> Self {
+        self.buf.extend_from_slice(v);
+        self
+    }
+
+    fn str(mut self, v: &str) -> Self {
+        self.buf.extend_from_slice(v.as_bytes());
+        self.buf.push(0);
+        self
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = (self.buf.len() - 1) as i32;
+        self.buf[1..5].copy_from_slice(&len.to_be_bytes());
+        self.buf
+    }
+}
+
+/// Reads the fields of a frontend message body
+struct MessageReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> MessageReader<'a> {
+    fn i16(&mut self) -> anyhow::Result<i16> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    fn i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            bail!("unexpected end of message");
+        }
+        let (v, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(v)
+    }
+
+    fn str(&mut self) -> anyhow::Result<String> {
+        let end = self
+            .buf
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| anyhow!("unterminated string in message"))?;
+        let s = String::from_utf8(self.buf[..end].to_vec())?;
+        self.buf = &self.buf[end + 1..];
+        Ok(s)
+    }
+}
+
+fn row_description(schema: &Schema) -> Vec<u8> {
+    let mut message = MessageBuilder::new(b'T').i16(schema.fields().len() as i16);
+    for field in schema.fields() {
+        message = message
+            .str(field.name())
+            .i32(0)
+            .i16(0)
+            .i32(type_oid(field.data_type()))
+            .i16(-1)
+            .i32(-1)
+            .i16(0);
+    }
+    message.finish()
+}
+
+fn data_rows(batch: &RecordBatch) -> arrow::error::Result<Vec<u8>> {
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|c| format_column(c.as_ref()))
+        .collect::<arrow::error::Result<Vec<_>>>()?;
+
+    let mut out = vec![];
+    for row in 0..batch.num_rows() {
+        let mut message = MessageBuilder::new(b'D').i16(columns.len() as i16);
+        for column in &columns {
+            message = match &column[row] {
+                Some(v) => message.i32(v.len() as i32).bytes(v.as_bytes()),
+                None => message.i32(-1),
+            };
+        }
+        out.extend(message.finish());
+    }
+    Ok(out)
+}
+
+fn command_complete(tag: &str) -> Vec<u8> {
+    MessageBuilder::new(b'C').str(tag).finish()
+}
+
+fn error_response(error: &PgError) -> Vec<u8> {
+    MessageBuilder::new(b'E')
+        .bytes(b"S")
+        .str("ERROR")
+        .bytes(b"V")
+        .str("ERROR")
+        .bytes(b"C")
+        .str(error.code)
+        .bytes(b"M")
+        .str(&error.message)
+        .bytes(&[0])
+        .finish()
+}
+
+/// Returns the command tag to acknowledge a session command with, if `query` is one
+fn session_command(query: &str) -> Option<String> {
+    let command = query.split_whitespace().next()?.to_uppercase();
+    SESSION_COMMANDS
+        .contains(&command.as_str())
+        .then_some(command)
+}
+
+struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: BufWriter<OwnedWriteHalf>,
+    results: ResultCatalog,
+    auth: Option<AuthData>,
+    statements: HashMap<String, String>,
+    portals: HashMap<String, String>,
+}
+
+impl Connection {
+    fn new(socket: TcpStream, results: ResultCatalog) -> Self {
+        let (reader, writer) = socket.into_split();
+        Self {
+            reader: BufReader::new(reader),
+            writer: BufWriter::new(writer),
+            results,
+            auth: None,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+        }
+    }
+
+    async fn read_body(&mut self) -> anyhow::Result<Vec<u8>> {
+        let len = self.reader.read_i32().await? as usize;
+        if !(4..=MAX_MESSAGE_SIZE).contains(&len) {
+            bail!("invalid message length {}", len);
+        }
+        let mut body = vec![0; len - 4];
+        self.reader.read_exact(&mut body).await?;
+        Ok(body)
+    }
+
+    async fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        self.writer.write_all(message).await?;
+        Ok(())
+    }
+
+    async fn ready_for_query(&mut self) -> anyhow::Result<()> {
+        self.send(&MessageBuilder::new(b'Z').bytes(b"I").finish())
+            .await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Reads the startup message, declining SSL and GSSAPI encryption, and returns whether the
+    /// client wants to continue
+    async fn startup(&mut self) -> anyhow::Result<bool> {
+        loop {
+            let body = self.read_body().await?;
+            let mut reader = MessageReader { buf: &body };
+            match reader.i32()? {
+                SSL_REQUEST | GSSENC_REQUEST => {
+                    self.writer.write_all(b"N").await?;
+                    self.writer.flush().await?;
+                }
+                CANCEL_REQUEST => return Ok(false),
+                PROTOCOL_VERSION => return Ok(true),
+                version => bail!("unsupported protocol version {}", version),
+            }
+        }
+    }
+
+    async fn authenticate(&mut self) -> anyhow::Result<bool> {
+        self.send(&MessageBuilder::new(b'R').i32(3).finish())
+            .await?;
+        self.writer.flush().await?;
+
+        let tag = self.reader.read_u8().await?;
+        if tag != b'p' {
+            bail!("expected a password message");
+        }
+        let body = self.read_body().await?;
+        let password = MessageReader { buf: &body }.str()?;
+
+        let api_key = (!password.is_empty()).then_some(password.as_str());
+        match self.results.authenticate(api_key).await {
+            Ok(auth) => {
+                self.auth = Some(auth);
+            }
+            Err(e) => {
+                let mut error = PgError::from(e);
+                error.code = "28P01";
+                self.send(&error_response(&error)).await?;
+                self.writer.flush().await?;
+                return Ok(false);
+            }
+        }
+
+        self.send(&MessageBuilder::new(b'R').i32(0).finish())
+            .await?;
+        for (name, value) in [
+            ("server_version", "14.0"),
+            ("server_encoding", "UTF8"),
+            ("client_encoding", "UTF8"),
+            ("DateStyle", "ISO, MDY"),
+            ("TimeZone", "UTC"),
+            ("integer_datetimes", "on"),
+            ("standard_conforming_strings", "on"),
+        ] {
+            self.send(&MessageBuilder::new(b'S').str(name).str(value).finish())
+                .await?;
+        }
+        self.ready_for_query().await?;
+        Ok(true)
+    }
+
+    async fn plan(&self, query: &str) -> Result<datafusion::prelude::DataFrame, PgError> {
+        Ok(self
+            .results
+            .plan(self.auth.as_ref().unwrap(), query)
+            .await?)
+    }
+
+    /// Runs a query, sending its results; the row description is only sent for the simple query
+    /// protocol, as the extended protocol sends it in response to Describe
+    async fn execute(
+        &mut self,
+        query: &str,
+        describe: bool,
+    ) -> anyhow::Result<Result<(), PgError>> {
+        if query.trim().trim_end_matches(';').trim().is_empty() {
+            self.send(&MessageBuilder::new(b'I').finish()).await?;
+            return Ok(Ok(()));
+        }
+        if let Some(tag) = session_command(query) {
+            self.send(&command_complete(&tag)).await?;
+            return Ok(Ok(()));
+        }
+
+        let df = match self.plan(query).await {
+            Ok(df) => df,
+            Err(e) => return Ok(Err(e)),
+        };
+        if describe {
+            self.send(&row_description(&Schema::from(df.schema())))
+                .await?;
+        }
+
+        let mut stream = match df.execute_stream().await {
+            Ok(stream) => stream,
+            Err(e) => return Ok(Err(PgError::new("XX000", e.to_string()))),
+        };
+        let mut rows = 0;
+        loop {
+            match stream.try_next().await {
+                Ok(Some(batch)) => {
+                    rows += batch.num_rows();
+                    match data_rows(&batch) {
+                        Ok(data) => self.send(&data).await?,
+                        Err(e) => return Ok(Err(PgError::new("XX000", e.to_string()))),
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => return Ok(Err(PgError::new("XX000", e.to_string()))),
+            }
+        }
+
+        self.send(&command_complete(&format!("SELECT {}", rows)))
+            .await?;
+        Ok(Ok(()))
+    }
+
+    async fn describe(&mut self, query: &str) -> anyhow::Result<Result<(), PgError>> {
+        if session_command(query).is_some() || query.trim().is_empty() {
+            self.send(&MessageBuilder::new(b'n').finish()).await?;
+            return Ok(Ok(()));
+        }
+
+        match self.plan(query).await {
+            Ok(df) => {
+                self.send(&row_description(&Schema::from(df.schema())))
+                    .await?;
+                Ok(Ok(()))
+            }
+            Err(e) => Ok(Err(e)),
+        }
+    }
+
+    /// Handles a message of the extended query protocol
+    async fn extended(&mut self, tag: u8, body: &[u8]) -> anyhow::Result<Result<(), PgError>> {
+        let mut reader = MessageReader { buf: body };
+        match tag {
+            b'P' => {
+                let name = reader.str()?;
+                let query = reader.str()?;
+                if reader.i16()? > 0 {
+                    return Ok(Err(PgError::new(
+                        "0A000",
+                        "query parameters are not supported",
+                    )));
+                }
+                self.statements.insert(name, query);
+                self.send(&MessageBuilder::new(b'1').finish()).await?;
+            }
+            b'B' => {
+                let portal = reader.str()?;
+                let statement = reader.str()?;
+                let formats = reader.i16()?;
+                for _ in 0..formats {
+                    reader.i16()?;
+                }
+                let params = reader.i16()?;
+                for _ in 0..params {
+                    let len = reader.i32()?;
+                    if len > 0 {
+                        reader.bytes(len as usize)?;
+                    }
+                }
+                if params > 0 {
+                    return Ok(Err(PgError::new(
+                        "0A000",
+                        "query parameters are not supported",
+                    )));
+                }
+                for _ in 0..reader.i16()? {
+                    if reader.i16()? != 0 {
+                        return Ok(Err(PgError::new(
+                            "0A000",
+                            "only text result formats are supported",
+                        )));
+                    }
+                }
+
+                let Some(query) = self.statements.get(&statement).cloned() else {
+                    return Ok(Err(PgError::new(
+                        "26000",
+                        format!("prepared statement \"{}\" does not exist", statement),
+                    )));
+                };
+                self.portals.insert(portal, query);
+                self.send(&MessageBuilder::new(b'2').finish()).await?;
+            }
+            b'D' => {
+                let kind = reader.bytes(1)?[0];
+                let name = reader.str()?;
+                let query = if kind == b'S' {
+                    self.statements.get(&name)
+                } else {
+                    self.portals.get(&name)
+                };
+                let Some(query) = query.cloned() else {
+                    return Ok(Err(PgError::new(
+                        "26000",
+                        format!("\"{}\" does not exist", name),
+                    )));
+                };
+                if kind == b'S' {
+                    self.send(&MessageBuilder::new(b't').i16(0).finish())
+                        .await?;
+                }
+                return self.describe(&query).await;
+            }
+            b'E' => {
+                let portal = reader.str()?;
+                let Some(query) = self.portals.get(&portal).cloned() else {
+                    return Ok(Err(PgError::new(
+                        "34000",
+                        format!("portal \"{}\" does not exist", portal),
+                    )));
+                };
+                return self.execute(&query, false).await;
+            }
+            b'C' => {
+                let kind = reader.bytes(1)?[0];
+                let name = reader.str()?;
+                if kind == b'S' {
+                    self.statements.remove(&name);
+                } else {
+                    self.portals.remove(&name);
+                }
+                self.send(&MessageBuilder::new(b'3').finish()).await?;
+            }
+            _ => {
+                return Ok(Err(PgError::new(
+                    "08P01",
+                    format!("unsupported message type '{}'", tag as char),
+                )));
+            }
+        }
+
+        Ok(Ok(()))
+    }
+
+    async fn run(mut self) -> anyhow::Result<()> {
+        if !self.startup().await? || !self.authenticate().await? {
+            return Ok(());
+        }
+
+        // after an error in the extended protocol, messages are discarded until the next Sync
+        let mut failed = false;
+        loop {
+            let tag = match self.reader.read_u8().await {
+                Ok(tag) => tag,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            let body = self.read_body().await?;
+
+            match tag {
+                b'Q' => {
+                    let query = MessageReader { buf: &body }.str()?;
+                    if let Err(e) = self.execute(&query, true).await? {
+                        self.send(&error_response(&e)).await?;
+                    }
+                    self.ready_for_query().await?;
+                }
+                b'S' => {
+                    failed = false;
+                    self.ready_for_query().await?;
+                }
+                b'H' => {
+                    self.writer.flush().await?;
+                }
+                b'X' => return Ok(()),
+                _ if failed => {}
+                _ => {
+                    if let Err(e) = self.extended(tag, &body).await? {
+                        self.send(&error_response(&e)).await?;
+                        failed = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub async fn start_postgres_server(database: DatabaseSource, port: u16) -> anyhow::Result<()> {
+    let addr = SocketAddr::new(config().api.bind_address, port);
+    let listener = TcpListener::bind(addr).await.map_err(|e| {
+        anyhow!(
+            "Failed to start Postgres wire-protocol server on {}: {}",
+            addr,
+            e
+        )
+    })?;
+    let results = ResultCatalog::new(database);
+
+    info!("Starting Postgres wire-protocol server on {:?}", addr);
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        let results = results.clone();
+        tokio::spawn(async move {
+            if let Err(e) = Connection::new(socket, results).run().await {
+                debug!("Postgres connection from {} failed: {:?}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{BinaryArray, Int64Array, StringArray, TimestampNanosecondArray};
+    use arrow::datatypes::{Field, TimeUnit};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_data_rows() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("data", DataType::Binary, false),
+            Field::new(
+                "time",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![7])),
+                Arc::new(StringArray::from(vec![None::<&str>])),
+                Arc::new(BinaryArray::from(vec![&[0xde, 0xad][..]])),
+                Arc::new(TimestampNanosecondArray::from(vec![1_500_000_000])),
+            ],
+        )
+        .unwrap();
+
+        let rows = data_rows(&batch).unwrap();
+        let mut expected = MessageBuilder::new(b'D').i16(4);
+        for value in [
+            Some("7"),
+            None,
+            Some("\\xdead"),
+            Some("1970-01-01 00:00:01.500"),
+        ] {
+            expected = match value {
+                Some(v) => expected.i32(v.len() as i32).bytes(v.as_bytes()),
+                None => expected.i32(-1),
+            };
+        }
+        assert_eq!(rows, expected.finish());
+
+        let description = row_description(&schema);
+        assert_eq!(description[0], b'T');
+        assert_eq!(
+            i32::from_be_bytes(description[1..5].try_into().unwrap()) as usize,
+            description.len() - 1
+        );
+    }
+
+    #[test]
+    fn test_session_command() {
+        assert_eq!(
+            session_command("set extra_float_digits = 3"),
+            Some("SET".to_string())
+        );
+        assert_eq!(session_command("  BEGIN"), Some("BEGIN".to_string()));
+        assert_eq!(session_command("SELECT * FROM counts"), None);
+    }
+}
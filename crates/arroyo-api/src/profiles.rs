@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use base64::Engine;
+use tonic::Code;
+
+use crate::pipelines::query_job_by_pub_id;
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, log_and_map, not_found, ApiError, BearerAuth, ErrorResp,
+};
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::profiles::{JobProfilePost, ProfileFormat, ProfileKind, WorkerProfile};
+use arroyo_rpc::api_types::WorkerProfileCollection;
+use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
+use arroyo_rpc::grpc::{ProfileJobReq, ProfileType};
+use arroyo_rpc::Compressed;
+use arroyo_server_common::profiling::{DEFAULT_CPU_PROFILE_DURATION, MAX_CPU_PROFILE_DURATION};
+use arroyo_server_common::tls;
+
+// profiles (particularly flamegraphs) can exceed tonic's default 4MB message limit
+const MAX_PROFILE_MESSAGE_SIZE: usize = 128 * 1024 * 1024;
+
+/// Profile a job's workers
+///
+/// Collects a CPU or heap profile from every running worker of the job. CPU profiles sample
+/// all workers concurrently for the requested duration, so this request does not return until
+/// the profile is complete. Heap profiles require workers built with the `jemalloc-profiling`
+/// feature. Profiles are returned base64-encoded; a worker that could not be profiled is
+/// returned with an error instead.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/profiles",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+    ),
+    request_body = JobProfilePost,
+    responses(
+        (status = 200, description = "Collected profiles", body = WorkerProfileCollection),
+    ),
+)]
+pub async fn create_job_profile(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+    WithRejection(Json(req), _): WithRejection<Json<JobProfilePost>, ApiError>,
+) -> Result<Json<WorkerProfileCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Admin)?;
+
+    let job = query_job_by_pub_id(
+        &pipeline_pub_id,
+        &job_pub_id,
+        &state.database.client().await?,
+        &auth_data,
+    )
+    .await?;
+
+    let (profile_type, content_type) = match (req.kind, req.format) {
+        (ProfileKind::Cpu, None | Some(ProfileFormat::Flamegraph)) => {
+            (ProfileType::CpuFlamegraph, "image/svg+xml")
+        }
+        (ProfileKind::Cpu, Some(ProfileFormat::Pprof)) => {
+            (ProfileType::CpuPprof, "application/octet-stream")
+        }
+        (ProfileKind::Heap, None | Some(ProfileFormat::Pprof)) => {
+            (ProfileType::HeapPprof, "application/octet-stream")
+        }
+        (ProfileKind::Heap, Some(ProfileFormat::Flamegraph)) => {
+            return Err(bad_request(
+                "Heap profiles are only available in pprof format",
+            ));
+        }
+    };
+
+    let duration = req
+        .duration_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_CPU_PROFILE_DURATION);
+
+    if duration.is_zero() || duration > MAX_CPU_PROFILE_DURATION {
+        return Err(bad_request(format!(
+            "durationSecs must be between 1 and {}",
+            MAX_CPU_PROFILE_DURATION.as_secs()
+        )));
+    }
+
+    let mut controller = tls::connect_grpc(state.controller_addr)
+        .await
+        .map(|channel| ControllerGrpcClient::new(channel).compressed())
+        .map_err(log_and_map)?
+        .max_decoding_message_size(MAX_PROFILE_MESSAGE_SIZE);
+
+    let profiles = match controller
+        .profile_job(ProfileJobReq {
+            job_id: job.id,
+            profile_type: profile_type as i32,
+            duration_micros: duration.as_micros() as u64,
+        })
+        .await
+    {
+        Ok(resp) => resp.into_inner().profiles,
+        Err(e) if e.code() == Code::NotFound => {
+            return Err(not_found("Running workers for job"));
+        }
+        Err(e) => {
+            return Err(log_and_map(e));
+        }
+    };
+
+    let engine = base64::engine::general_purpose::STANDARD;
+
+    Ok(Json(WorkerProfileCollection {
+        data: profiles
+            .into_iter()
+            .map(|p| WorkerProfile {
+                worker_id: p.worker_id,
+                content_type: content_type.to_string(),
+                data: p.error.is_none().then(|| engine.encode(&p.data)),
+                error: p.error,
+            })
+            .collect(),
+    }))
+}
@@ -1,9 +1,11 @@
+use axum::extract::DefaultBodyLimit;
 use axum::response::{Html, IntoResponse, Response};
 use axum::{
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
 
+use arroyo_server_common::compress_responses;
 use http::{header, StatusCode, Uri};
 use rust_embed::RustEmbed;
 use tower_http::cors;
@@ -11,6 +13,13 @@ use tower_http::cors::CorsLayer;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+use crate::api_keys::{create_api_key, delete_api_key, get_api_keys};
+use crate::audit_log::get_audit_log;
+use crate::bundles::{apply_bundle, export_bundle, get_bundle_apply};
+use crate::catalog::{
+    create_catalog_table, create_catalog_table_version, delete_catalog_table,
+    get_catalog_table_pipelines, get_catalog_table_versions, get_catalog_tables,
+};
 use crate::connection_profiles::{
     create_connection_profile, delete_connection_profile, get_connection_profile_autocomplete,
     get_connection_profiles, test_connection_profile,
@@ -20,20 +29,49 @@ use crate::connection_tables::{
     test_schema,
 };
 use crate::connectors::get_connectors;
+use crate::dry_run::dry_run_pipeline;
+use crate::faults::{create_job_fault, delete_job_faults};
 use crate::jobs::{
-    get_checkpoint_details, get_job_checkpoints, get_job_errors, get_job_output, get_jobs,
+    get_checkpoint_details, get_checkpoint_state, get_checkpoint_state_table, get_job_checkpoints,
+    get_job_errors, get_job_output, get_job_restarts, get_jobs, migrate_state, trigger_savepoint,
+};
+use crate::metrics::{
+    get_job_analysis, get_metric_history, get_operator_metric_groups, get_watermark_history,
+};
+use crate::models::{
+    create_model, create_model_version, delete_model, get_model_versions, get_models,
+};
+use crate::namespaces::{create_namespace, delete_namespace, get_namespaces};
+use crate::notifications::{
+    create_notification_target, delete_notification_target, get_notification_target,
+    get_notification_targets,
 };
-use crate::metrics::get_operator_metric_groups;
+use crate::parameters::update_operator_parameters;
 use crate::pipelines::{
     create_pipeline, delete_pipeline, get_pipeline, get_pipeline_jobs, get_pipelines,
-    patch_pipeline, restart_pipeline, validate_query,
+    patch_pipeline, put_pipeline_udfs, restart_pipeline, test_pipeline, validate_query,
 };
+use crate::profiles::create_job_profile;
 use crate::rest_utils::not_found;
-use crate::udfs::{create_udf, delete_udf, get_udfs, validate_udf};
+use crate::schedules::{
+    delete_pipeline_schedule, get_pipeline_schedule, get_scheduled_runs, put_pipeline_schedule,
+};
+use crate::templates::{
+    create_pipeline_template, delete_pipeline_template, get_pipeline_template,
+    get_pipeline_template_instances, get_pipeline_templates, instantiate_pipeline_template,
+};
+use crate::udfs::{
+    create_udf, create_udf_version, delete_udf, get_udf_versions, get_udfs, upload_udf_artifact,
+    validate_udf,
+};
 use crate::ApiDoc;
 use arroyo_rpc::config::config;
 use cornucopia_async::DatabaseSource;
 
+// prebuilt UDF artifacts and models are uploaded base64-encoded, and are far larger than axum's
+// default limit
+const MAX_UDF_ARTIFACT_BODY_SIZE: usize = 512 * 1024 * 1024;
+
 #[derive(RustEmbed)]
 #[folder = "../../webui/dist"]
 struct Assets;
@@ -127,16 +165,34 @@ pub fn create_rest_app(database: DatabaseSource, controller_addr: &str) -> Route
     let jobs_routes = Router::new()
         .route("/", get(get_pipeline_jobs))
         .route("/:job_id/errors", get(get_job_errors))
+        .route("/:job_id/restarts", get(get_job_restarts))
         .route("/:job_id/checkpoints", get(get_job_checkpoints))
         .route(
             "/:job_id/checkpoints/:checkpoint_id/operator_checkpoint_groups",
             get(get_checkpoint_details),
         )
+        .route(
+            "/:job_id/checkpoints/:checkpoint_id/state",
+            get(get_checkpoint_state),
+        )
+        .route(
+            "/:job_id/checkpoints/:checkpoint_id/state/:operator_id/:table",
+            get(get_checkpoint_state_table),
+        )
+        .route("/:job_id/state_migrations", post(migrate_state))
+        .route("/:job_id/savepoints", post(trigger_savepoint))
         .route("/:job_id/output", get(get_job_output))
         .route(
             "/:job_id/operator_metric_groups",
             get(get_operator_metric_groups),
-        );
+        )
+        .route("/:job_id/analyze", get(get_job_analysis))
+        .route("/:job_id/watermark_history", get(get_watermark_history))
+        .route("/:job_id/metric_history", get(get_metric_history))
+        .route("/:job_id/profiles", post(create_job_profile))
+        .route("/:job_id/faults", post(create_job_fault))
+        .route("/:job_id/faults", delete(delete_job_faults))
+        .route("/:job_id/parameters", post(update_operator_parameters));
 
     let api_routes = Router::new()
         .route("/ping", get(ping))
@@ -160,19 +216,78 @@ pub fn create_rest_app(database: DatabaseSource, controller_addr: &str) -> Route
         .route("/udfs", post(create_udf))
         .route("/udfs", get(get_udfs))
         .route("/udfs/validate", post(validate_udf))
+        .route(
+            "/udfs/artifacts",
+            post(upload_udf_artifact).layer(DefaultBodyLimit::max(MAX_UDF_ARTIFACT_BODY_SIZE)),
+        )
         .route("/udfs/:id", delete(delete_udf))
+        .route("/udfs/:id/versions", post(create_udf_version))
+        .route("/udfs/:id/versions", get(get_udf_versions))
+        .route("/catalog", post(create_catalog_table))
+        .route("/catalog", get(get_catalog_tables))
+        .route("/catalog/:id", delete(delete_catalog_table))
+        .route("/catalog/:id/versions", post(create_catalog_table_version))
+        .route("/catalog/:id/versions", get(get_catalog_table_versions))
+        .route("/catalog/:id/pipelines", get(get_catalog_table_pipelines))
+        .route(
+            "/models",
+            post(create_model).layer(DefaultBodyLimit::max(MAX_UDF_ARTIFACT_BODY_SIZE)),
+        )
+        .route("/models", get(get_models))
+        .route("/models/:id", delete(delete_model))
+        .route(
+            "/models/:id/versions",
+            post(create_model_version).layer(DefaultBodyLimit::max(MAX_UDF_ARTIFACT_BODY_SIZE)),
+        )
+        .route("/models/:id/versions", get(get_model_versions))
+        .route("/pipeline_templates", post(create_pipeline_template))
+        .route("/pipeline_templates", get(get_pipeline_templates))
+        .route("/pipeline_templates/:id", get(get_pipeline_template))
+        .route("/pipeline_templates/:id", delete(delete_pipeline_template))
+        .route(
+            "/pipeline_templates/:id/pipelines",
+            post(instantiate_pipeline_template),
+        )
+        .route(
+            "/pipeline_templates/:id/pipelines",
+            get(get_pipeline_template_instances),
+        )
+        .route("/notification_targets", post(create_notification_target))
+        .route("/notification_targets", get(get_notification_targets))
+        .route("/notification_targets/:id", get(get_notification_target))
+        .route(
+            "/notification_targets/:id",
+            delete(delete_notification_target),
+        )
+        .route("/namespaces", post(create_namespace))
+        .route("/namespaces", get(get_namespaces))
+        .route("/namespaces/:id", delete(delete_namespace))
+        .route("/api_keys", post(create_api_key))
+        .route("/api_keys", get(get_api_keys))
+        .route("/api_keys/:id", delete(delete_api_key))
+        .route("/audit_log", get(get_audit_log))
+        .route("/bundles/export", get(export_bundle))
+        .route("/bundles/apply", post(apply_bundle))
+        .route("/bundles/applies/:id", get(get_bundle_apply))
         .route("/pipelines", post(create_pipeline))
         .route("/pipelines", get(get_pipelines))
         .route("/jobs", get(get_jobs))
         .route("/pipelines/validate_query", post(validate_query))
+        .route("/pipelines/test", post(test_pipeline))
+        .route("/pipelines/dry_run", post(dry_run_pipeline))
         .route("/pipelines/:id", patch(patch_pipeline))
         .route("/pipelines/:id", get(get_pipeline))
         .route("/pipelines/:id/restart", post(restart_pipeline))
+        .route("/pipelines/:id/udfs", put(put_pipeline_udfs))
         .route("/pipelines/:id", delete(delete_pipeline))
+        .route("/pipelines/:id/schedule", put(put_pipeline_schedule))
+        .route("/pipelines/:id/schedule", get(get_pipeline_schedule))
+        .route("/pipelines/:id/schedule", delete(delete_pipeline_schedule))
+        .route("/pipelines/:id/scheduled_runs", get(get_scheduled_runs))
         .nest("/pipelines/:id/jobs", jobs_routes)
         .fallback(api_fallback);
 
-    Router::new()
+    let app = Router::new()
         .merge(
             SwaggerUi::new("/api/v1/swagger-ui")
                 .url("/api/v1/api-docs/openapi.json", ApiDoc::openapi()),
@@ -183,5 +298,7 @@ pub fn create_rest_app(database: DatabaseSource, controller_addr: &str) -> Route
             controller_addr: controller_addr.to_string(),
             database,
         })
-        .layer(cors)
+        .layer(cors);
+
+    compress_responses(app)
 }
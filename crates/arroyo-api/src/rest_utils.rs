@@ -1,4 +1,5 @@
 use crate::{cloud, AuthData};
+use arroyo_rpc::api_types::api_keys::Role;
 use arroyo_server_common::log_event;
 use axum::extract::rejection::JsonRejection;
 use axum::http::StatusCode;
@@ -110,6 +111,43 @@ pub(crate) async fn authenticate(
     cloud::authenticate(&db.client().await?, bearer_auth).await
 }
 
+impl AuthData {
+    /// Fails unless the caller has at least the given role
+    pub(crate) fn require_role(&self, role: Role) -> Result<(), ErrorResp> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err(forbidden(format!("This action requires the {} role", role)))
+        }
+    }
+
+    /// Fails unless the caller's API key scope covers the whole namespace
+    pub(crate) fn require_namespace(&self, namespace: &str) -> Result<(), ErrorResp> {
+        if self.scope.allows_namespace(namespace) {
+            Ok(())
+        } else {
+            Err(forbidden(format!(
+                "API key does not have access to namespace '{}'",
+                namespace
+            )))
+        }
+    }
+}
+
+pub(crate) fn unauthorized(message: impl Into<String>) -> ErrorResp {
+    ErrorResp {
+        status_code: StatusCode::UNAUTHORIZED,
+        message: message.into(),
+    }
+}
+
+pub(crate) fn forbidden(message: impl Into<String>) -> ErrorResp {
+    ErrorResp {
+        status_code: StatusCode::FORBIDDEN,
+        message: message.into(),
+    }
+}
+
 pub(crate) fn bad_request(message: impl Into<String>) -> ErrorResp {
     ErrorResp {
         status_code: StatusCode::BAD_REQUEST,
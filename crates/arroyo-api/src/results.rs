@@ -0,0 +1,241 @@
+//! Querying the results materialized by pipelines' flight sinks, which are served over Arrow
+//! Flight SQL and the Postgres wire protocol.
+//!
+//! Results are exposed in the `arroyo` catalog, with a schema for each pipeline (named by its id)
+//! containing a table for each of its flight sinks. Queries are planned by DataFusion against the
+//! snapshots of each pipeline's latest completed checkpoint, so they see a consistent view of the
+//! results as of that checkpoint.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use arroyo_connectors::flight::{served_schema, snapshot_prefix, FlightTable};
+use arroyo_datastream::logical::{LogicalProgram, OperatorName};
+use arroyo_rpc::config::config;
+use arroyo_rpc::grpc::api::{ArrowProgram, ConnectorOp};
+use arroyo_rpc::OperatorConfig;
+use arroyo_storage::StorageProvider;
+use async_trait::async_trait;
+use axum::headers::authorization::Authorization;
+use axum::TypedHeader;
+use cornucopia_async::DatabaseSource;
+use datafusion::catalog::schema::SchemaProvider;
+use datafusion::datasource::{MemTable, TableProvider};
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::{SQLOptions, SessionConfig, SessionContext};
+use datafusion::prelude::DataFrame;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+use prost::Message;
+use tracing::warn;
+
+use crate::queries::api_queries;
+use crate::rest_utils::{
+    authenticate, bad_request, internal_server_error, unauthorized, ErrorResp,
+};
+use crate::AuthData;
+
+pub(crate) const CATALOG: &str = "arroyo";
+
+/// A pipeline with flight sinks, served as a schema of the `arroyo` catalog
+pub(crate) struct ResultPipeline {
+    pub id: String,
+    pub job_id: String,
+    pub tables: HashMap<String, SchemaRef>,
+}
+
+/// Finds the flight sinks of a pipeline's program, along with the schemas of their results
+fn result_tables(program: &[u8]) -> anyhow::Result<HashMap<String, SchemaRef>> {
+    let program: LogicalProgram = ArrowProgram::decode(program)?.try_into()?;
+
+    let mut tables = HashMap::new();
+    for idx in program.graph.node_indices() {
+        let node = &program.graph[idx];
+        if node.operator_name != OperatorName::ConnectorSink {
+            continue;
+        }
+        let op = ConnectorOp::decode(&node.operator_config[..])?;
+        if op.connector != "flight" {
+            continue;
+        }
+
+        let config: OperatorConfig = serde_json::from_str(&op.config)?;
+        let table: FlightTable = serde_json::from_value(config.table)?;
+        let name = table
+            .name
+            .ok_or_else(|| anyhow!("flight sink {} has no name", node.operator_id))?;
+        let edge = program
+            .graph
+            .edges_directed(idx, Direction::Incoming)
+            .next()
+            .ok_or_else(|| anyhow!("flight sink {} has no input", node.operator_id))?;
+
+        tables.insert(name, Arc::new(served_schema(&edge.weight().schema.schema)));
+    }
+
+    Ok(tables)
+}
+
+/// Reads the results of a flight sink, as of checkpoint `epoch`
+async fn load_results(
+    job_id: &str,
+    table: &str,
+    epoch: u32,
+    schema: &SchemaRef,
+) -> anyhow::Result<Vec<RecordBatch>> {
+    let storage = StorageProvider::for_checkpoints(config().checkpoint_storage_url()).await?;
+
+    let mut batches = vec![];
+    for path in storage
+        .list_prefix(&snapshot_prefix(job_id, table, epoch))
+        .await?
+    {
+        if !path.ends_with(".parquet") {
+            continue;
+        }
+        let data = storage.get(path.as_str()).await?;
+        for batch in ParquetRecordBatchReaderBuilder::try_new(data)?.build()? {
+            batches.push(batch?.with_schema(schema.clone())?);
+        }
+    }
+
+    Ok(batches)
+}
+
+/// Serves the tables of a single pipeline, loading their results when a query reads them
+struct ResultSchemaProvider {
+    job_id: String,
+    epoch: Option<u32>,
+    tables: HashMap<String, SchemaRef>,
+}
+
+#[async_trait]
+impl SchemaProvider for ResultSchemaProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        self.tables.keys().cloned().collect()
+    }
+
+    async fn table(&self, name: &str) -> datafusion::error::Result<Option<Arc<dyn TableProvider>>> {
+        let Some(schema) = self.tables.get(name) else {
+            return Ok(None);
+        };
+
+        // until the pipeline has checkpointed, its results are empty
+        let batches = match self.epoch {
+            Some(epoch) => load_results(&self.job_id, name, epoch, schema)
+                .await
+                .map_err(|e| DataFusionError::External(e.into()))?,
+            None => vec![],
+        };
+
+        Ok(Some(Arc::new(MemTable::try_new(
+            schema.clone(),
+            vec![batches],
+        )?)))
+    }
+
+    fn table_exist(&self, name: &str) -> bool {
+        self.tables.contains_key(name)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ResultCatalog {
+    database: DatabaseSource,
+}
+
+impl ResultCatalog {
+    pub fn new(database: DatabaseSource) -> Self {
+        Self { database }
+    }
+
+    /// Authenticates a caller by their API key, if they gave one
+    pub async fn authenticate(&self, api_key: Option<&str>) -> Result<AuthData, ErrorResp> {
+        let bearer = api_key
+            .map(|key| {
+                Authorization::bearer(key)
+                    .map(TypedHeader)
+                    .map_err(|_| unauthorized("Invalid API key"))
+            })
+            .transpose()?;
+
+        authenticate(&self.database, bearer).await
+    }
+
+    /// The pipelines the caller can access that have flight sinks
+    pub async fn pipelines(&self, auth: &AuthData) -> Result<Vec<ResultPipeline>, ErrorResp> {
+        let client = self.database.client().await?;
+        let pipelines =
+            api_queries::fetch_get_result_pipelines(&client, &auth.organization_id).await?;
+
+        let mut results = vec![];
+        for p in pipelines {
+            if !auth.scope.allows_pipeline(&p.pub_id, &p.namespace) {
+                continue;
+            }
+            match result_tables(&p.program) {
+                Ok(tables) if !tables.is_empty() => results.push(ResultPipeline {
+                    id: p.pub_id,
+                    job_id: p.job_id,
+                    tables,
+                }),
+                Ok(_) => {}
+                Err(e) => warn!("failed to read program of pipeline {}: {:?}", p.pub_id, e),
+            }
+        }
+
+        Ok(results)
+    }
+
+    pub async fn plan(&self, auth: &AuthData, query: &str) -> Result<DataFrame, ErrorResp> {
+        let ctx = SessionContext::new_with_config(
+            SessionConfig::new()
+                .with_default_catalog_and_schema(CATALOG, "public")
+                .with_information_schema(true),
+        );
+        let catalog = ctx
+            .catalog(CATALOG)
+            .ok_or_else(|| internal_server_error("missing results catalog"))?;
+
+        let client = self.database.client().await?;
+        for pipeline in self.pipelines(auth).await? {
+            let epoch = api_queries::fetch_get_last_completed_checkpoint_epoch(
+                &client,
+                &pipeline.job_id,
+                &auth.organization_id,
+            )
+            .await?
+            .into_iter()
+            .next()
+            .flatten()
+            .map(|epoch| epoch as u32);
+
+            catalog
+                .register_schema(
+                    &pipeline.id,
+                    Arc::new(ResultSchemaProvider {
+                        job_id: pipeline.job_id,
+                        epoch,
+                        tables: pipeline.tables,
+                    }),
+                )
+                .map_err(|e| internal_server_error(e.to_string()))?;
+        }
+
+        let options = SQLOptions::new()
+            .with_allow_ddl(false)
+            .with_allow_dml(false);
+        ctx.sql_with_options(query, options)
+            .await
+            .map_err(|e| bad_request(e.to_string()))
+    }
+}
@@ -0,0 +1,289 @@
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use serde_json::json;
+use std::str::FromStr;
+
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{AuditAction, AuditResourceType};
+use arroyo_rpc::api_types::pipelines::{
+    PipelineSchedule, PipelineSchedulePost, ScheduledRun, ScheduledRunState,
+};
+use arroyo_rpc::api_types::{PaginationQueryParams, ScheduledRunCollection};
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_rpc::schedule::parse_cron;
+
+use crate::audit_log;
+use crate::pipelines::query_pipeline_by_pub_id;
+use crate::queries::api_queries;
+use crate::queries::api_queries::{DbPipelineSchedule, DbScheduledRun};
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, internal_server_error, log_and_map, not_found, paginate_results,
+    validate_pagination_params, ApiError, BearerAuth, ErrorResp,
+};
+use crate::to_micros;
+
+impl From<DbPipelineSchedule> for PipelineSchedule {
+    fn from(val: DbPipelineSchedule) -> Self {
+        PipelineSchedule {
+            id: val.pub_id,
+            cron: val.cron,
+            enabled: val.enabled,
+            next_run_at: val.next_run_at.map(to_micros),
+            created_at: to_micros(val.created_at),
+        }
+    }
+}
+
+impl TryFrom<DbScheduledRun> for ScheduledRun {
+    type Error = String;
+
+    fn try_from(val: DbScheduledRun) -> Result<Self, Self::Error> {
+        Ok(ScheduledRun {
+            id: val.pub_id,
+            job_id: val.job_id,
+            state: ScheduledRunState::from_str(&val.state)
+                .map_err(|_| format!("invalid scheduled run state '{}'", val.state))?,
+            scheduled_for: to_micros(val.scheduled_for),
+            started_at: to_micros(val.started_at),
+            finished_at: val.finished_at.map(to_micros),
+            failure_message: val.failure_message,
+        })
+    }
+}
+
+fn schedule_state(schedule: Option<&PipelineSchedule>) -> serde_json::Value {
+    json!({
+        "schedule": schedule.map(|s| json!({"cron": s.cron, "enabled": s.enabled})),
+    })
+}
+
+async fn get_schedule(
+    db: &cornucopia_async::Database<'_>,
+    organization_id: &str,
+    pipeline_pub_id: &str,
+) -> Result<Option<PipelineSchedule>, ErrorResp> {
+    Ok(
+        api_queries::fetch_get_pipeline_schedule(db, &pipeline_pub_id, &organization_id)
+            .await?
+            .into_iter()
+            .next()
+            .map(|s| s.into()),
+    )
+}
+
+/// Set a pipeline's schedule
+///
+/// On each scheduled time, the pipeline is re-run from scratch, discarding any state from
+/// previous runs. This is intended for bounded pipelines; if the previous run has not yet
+/// finished when the next one comes due, that run is skipped. Stopping the pipeline does not
+/// stop future runs; disable or delete the schedule instead.
+#[utoipa::path(
+    put,
+    path = "/v1/pipelines/{id}/schedule",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    request_body = PipelineSchedulePost,
+    responses(
+        (status = 200, description = "Updated pipeline schedule", body = PipelineSchedule),
+    ),
+)]
+pub async fn put_pipeline_schedule(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+    WithRejection(Json(req), _): WithRejection<Json<PipelineSchedulePost>, ApiError>,
+) -> Result<Json<PipelineSchedule>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+    let db = state.database.client().await?;
+
+    let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &db, &auth_data).await?;
+    auth_data.require_namespace(&pipeline.namespace)?;
+
+    if pipeline.preview {
+        return Err(bad_request("Preview pipelines cannot be scheduled"));
+    }
+
+    parse_cron(&req.cron).map_err(|e| bad_request(e.to_string()))?;
+
+    let pipeline_id =
+        api_queries::fetch_get_pipeline_id(&db, &pipeline_pub_id, &auth_data.organization_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_found("Pipeline"))?
+            .id;
+
+    let before = get_schedule(&db, &auth_data.organization_id, &pipeline_pub_id).await?;
+
+    let schedule = audit_log::transaction(&state.database, async |db| {
+        api_queries::execute_upsert_pipeline_schedule(
+            db,
+            &generate_id(IdTypes::PipelineSchedule),
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &pipeline_id,
+            &req.cron.trim(),
+            &req.enabled.unwrap_or(true),
+        )
+        .await
+        .map_err(log_and_map)?;
+
+        let schedule = get_schedule(db, &auth_data.organization_id, &pipeline_pub_id)
+            .await?
+            .ok_or_else(|| internal_server_error("Failed to fetch pipeline schedule"))?;
+
+        audit_log::record(
+            db,
+            &auth_data,
+            AuditAction::Update,
+            AuditResourceType::Pipeline,
+            &pipeline_pub_id,
+            Some(schedule_state(before.as_ref())),
+            Some(schedule_state(Some(&schedule))),
+        )
+        .await?;
+
+        Ok(schedule)
+    })
+    .await?;
+
+    Ok(Json(schedule))
+}
+
+/// Get a pipeline's schedule
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{id}/schedule",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    responses(
+        (status = 200, description = "Got pipeline schedule", body = PipelineSchedule),
+    ),
+)]
+pub async fn get_pipeline_schedule(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+) -> Result<Json<PipelineSchedule>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let db = state.database.client().await?;
+
+    query_pipeline_by_pub_id(&pipeline_pub_id, &db, &auth_data).await?;
+
+    let schedule = get_schedule(&db, &auth_data.organization_id, &pipeline_pub_id)
+        .await?
+        .ok_or_else(|| not_found("Pipeline schedule"))?;
+
+    Ok(Json(schedule))
+}
+
+/// Delete a pipeline's schedule
+///
+/// The history of previous scheduled runs is retained.
+#[utoipa::path(
+    delete,
+    path = "/v1/pipelines/{id}/schedule",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    responses(
+        (status = 200, description = "Deleted pipeline schedule"),
+    ),
+)]
+pub async fn delete_pipeline_schedule(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+    let db = state.database.client().await?;
+
+    let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &db, &auth_data).await?;
+    auth_data.require_namespace(&pipeline.namespace)?;
+
+    let before = get_schedule(&db, &auth_data.organization_id, &pipeline_pub_id)
+        .await?
+        .ok_or_else(|| not_found("Pipeline schedule"))?;
+
+    audit_log::transaction(&state.database, async |db| {
+        api_queries::execute_delete_pipeline_schedule(
+            db,
+            &auth_data.organization_id,
+            &pipeline_pub_id,
+        )
+        .await?;
+
+        audit_log::record(
+            db,
+            &auth_data,
+            AuditAction::Update,
+            AuditResourceType::Pipeline,
+            &pipeline_pub_id,
+            Some(schedule_state(Some(&before))),
+            Some(schedule_state(None)),
+        )
+        .await?;
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// List a pipeline's scheduled runs
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{id}/scheduled_runs",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id"),
+        PaginationQueryParams
+    ),
+    responses(
+        (status = 200, description = "Got scheduled runs", body = ScheduledRunCollection),
+    ),
+)]
+pub async fn get_scheduled_runs(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+    query_params: Query<PaginationQueryParams>,
+) -> Result<Json<ScheduledRunCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let db = state.database.client().await?;
+
+    query_pipeline_by_pub_id(&pipeline_pub_id, &db, &auth_data).await?;
+
+    let (starting_after, limit) =
+        validate_pagination_params(query_params.starting_after.clone(), query_params.limit)?;
+
+    let runs = api_queries::fetch_get_scheduled_runs(
+        &db,
+        &pipeline_pub_id,
+        &auth_data.organization_id,
+        &starting_after.unwrap_or_default(),
+        &(limit as i32),
+    )
+    .await?;
+
+    let (runs, has_more) = paginate_results(runs, limit);
+
+    Ok(Json(ScheduledRunCollection {
+        has_more,
+        data: runs
+            .into_iter()
+            .map(|r| r.try_into())
+            .collect::<Result<_, _>>()
+            .map_err(log_and_map)?,
+    }))
+}
@@ -0,0 +1,632 @@
+use std::collections::{BTreeMap, HashSet};
+
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{AuditAction, AuditResourceType};
+use arroyo_rpc::api_types::pipelines::{Pipeline, PipelinePost};
+use arroyo_rpc::api_types::templates::{
+    PipelineTemplate, PipelineTemplateInstance, PipelineTemplateInstancePost, PipelineTemplatePost,
+    TemplateParameter, TemplateParameterType,
+};
+use arroyo_rpc::api_types::{PipelineTemplateCollection, PipelineTemplateInstanceCollection};
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use cornucopia_async::Database;
+
+use crate::audit_log;
+use crate::audit_log::pipeline_template_state;
+use crate::namespaces::resolve_namespace;
+use crate::pipelines::create_pipeline_from_post;
+use crate::queries::api_queries;
+use crate::queries::api_queries::{DbPipelineTemplate, GetPipelineTemplateInstances};
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, internal_server_error, log_and_map, map_insert_err, not_found,
+    ApiError, BearerAuth, ErrorResp,
+};
+use crate::to_micros;
+
+impl From<DbPipelineTemplate> for PipelineTemplate {
+    fn from(val: DbPipelineTemplate) -> Self {
+        PipelineTemplate {
+            id: val.pub_id,
+            name: val.name,
+            query: val.query,
+            description: val.description,
+            parameters: serde_json::from_value(val.parameters)
+                .expect("invalid template parameters"),
+            udfs: serde_json::from_value(val.udfs).expect("invalid template udfs"),
+            namespace: val.namespace,
+            pipeline_count: val.pipeline_count as u32,
+            created_at: to_micros(val.created_at),
+            updated_at: to_micros(val.updated_at),
+        }
+    }
+}
+
+impl From<GetPipelineTemplateInstances> for PipelineTemplateInstance {
+    fn from(val: GetPipelineTemplateInstances) -> Self {
+        PipelineTemplateInstance {
+            pipeline_id: val.pub_id,
+            pipeline_name: val.name,
+            parameters: serde_json::from_value(val.parameters)
+                .expect("invalid template instance parameters"),
+        }
+    }
+}
+
+pub(crate) fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A piece of a template's query: either literal text or a `{{ name }}` placeholder
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn parse(query: &str) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = vec![];
+    let mut rest = query;
+
+    while let Some(start) = rest.find("{{") {
+        segments.push(Segment::Text(&rest[..start]));
+
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "unclosed '{{' in template query".to_string())?;
+        let name = after[..end].trim();
+        if !is_identifier(name) {
+            return Err(format!(
+                "invalid placeholder '{{{{{}}}}}'; placeholders must be parameter names, like '{{{{ topic }}}}'",
+                &after[..end]
+            ));
+        }
+
+        segments.push(Segment::Placeholder(name));
+        rest = &after[end + 2..];
+    }
+
+    segments.push(Segment::Text(rest));
+    Ok(segments)
+}
+
+/// Checks a parameter value against its type, returning the text that is substituted for it
+fn format_value(parameter: &TemplateParameter, value: &str) -> Result<String, String> {
+    match parameter.parameter_type {
+        TemplateParameterType::String => Ok(value.replace('\'', "''")),
+        TemplateParameterType::Number => {
+            let value = value.trim();
+            if value.parse::<f64>().is_ok_and(|n| n.is_finite()) {
+                Ok(value.to_string())
+            } else {
+                Err(format!(
+                    "value '{}' of parameter '{}' is not a number",
+                    value, parameter.name
+                ))
+            }
+        }
+        TemplateParameterType::Boolean => match value.trim().to_lowercase().as_str() {
+            v @ ("true" | "false") => Ok(v.to_string()),
+            _ => Err(format!(
+                "value '{}' of parameter '{}' must be true or false",
+                value, parameter.name
+            )),
+        },
+        TemplateParameterType::Identifier => {
+            if is_identifier(value) {
+                Ok(value.to_string())
+            } else {
+                Err(format!(
+                    "value '{}' of parameter '{}' is not a valid identifier",
+                    value, parameter.name
+                ))
+            }
+        }
+    }
+}
+
+/// Checks that a template's parameters are well-formed and that its query only references
+/// declared parameters
+fn validate_template(query: &str, parameters: &[TemplateParameter]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for parameter in parameters {
+        if !is_identifier(&parameter.name) {
+            return Err(format!(
+                "invalid parameter name '{}'; names must be identifiers",
+                parameter.name
+            ));
+        }
+        if !names.insert(parameter.name.as_str()) {
+            return Err(format!("duplicate parameter '{}'", parameter.name));
+        }
+        if let Some(default) = &parameter.default {
+            format_value(parameter, default)?;
+        }
+    }
+
+    for segment in parse(query)? {
+        if let Segment::Placeholder(name) = segment {
+            if !names.contains(name) {
+                return Err(format!("query references undeclared parameter '{}'", name));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Substitutes parameter values into a template's query, returning the query and the values
+/// that were used for each parameter, including defaults
+fn render(
+    query: &str,
+    parameters: &[TemplateParameter],
+    values: &BTreeMap<String, String>,
+) -> Result<(String, BTreeMap<String, String>), String> {
+    if let Some(unknown) = values
+        .keys()
+        .find(|k| !parameters.iter().any(|p| &p.name == *k))
+    {
+        return Err(format!("template has no parameter '{}'", unknown));
+    }
+
+    let mut resolved = BTreeMap::new();
+    let mut formatted = BTreeMap::new();
+    for parameter in parameters {
+        let value = values
+            .get(&parameter.name)
+            .or(parameter.default.as_ref())
+            .ok_or_else(|| format!("missing value for parameter '{}'", parameter.name))?;
+        formatted.insert(parameter.name.as_str(), format_value(parameter, value)?);
+        resolved.insert(parameter.name.clone(), value.clone());
+    }
+
+    let mut rendered = String::with_capacity(query.len());
+    for segment in parse(query)? {
+        match segment {
+            Segment::Text(text) => rendered.push_str(text),
+            Segment::Placeholder(name) => rendered.push_str(
+                formatted
+                    .get(name)
+                    .ok_or_else(|| format!("query references undeclared parameter '{}'", name))?,
+            ),
+        }
+    }
+
+    Ok((rendered, resolved))
+}
+
+async fn get_template(
+    db: &Database<'_>,
+    organization_id: &str,
+    pub_id: &str,
+) -> Result<PipelineTemplate, ErrorResp> {
+    Ok(
+        api_queries::fetch_get_pipeline_template(db, organization_id, &pub_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_found("Pipeline template"))?
+            .into(),
+    )
+}
+
+/// Create a pipeline template
+///
+/// Templates are pipeline queries with declared parameters, referenced in the query as
+/// `{{ name }}`, from which any number of pipelines can be created with different values.
+#[utoipa::path(
+    post,
+    path = "/v1/pipeline_templates",
+    tag = "pipeline_templates",
+    request_body = PipelineTemplatePost,
+    responses(
+        (status = 200, description = "Created pipeline template", body = PipelineTemplate),
+    ),
+)]
+pub async fn create_pipeline_template(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(req), _): WithRejection<Json<PipelineTemplatePost>, ApiError>,
+) -> Result<Json<PipelineTemplate>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let client = state.database.client().await?;
+
+    let (namespace, _) = resolve_namespace(&auth_data, &client, req.namespace.as_deref()).await?;
+    auth_data.require_namespace(&namespace)?;
+
+    validate_template(&req.query, &req.parameters)
+        .map_err(|e| bad_request(format!("Invalid pipeline template: {}", e)))?;
+
+    let pub_id = generate_id(IdTypes::PipelineTemplate);
+    let created = audit_log::transaction(&state.database, async |client| {
+        api_queries::execute_create_pipeline_template(
+            client,
+            &pub_id,
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &req.name,
+            &req.query,
+            &req.description.unwrap_or_default(),
+            &serde_json::to_value(&req.parameters).unwrap(),
+            &serde_json::to_value(req.udfs.unwrap_or_default()).unwrap(),
+            &namespace,
+        )
+        .await
+        .map_err(|e| map_insert_err("pipeline template", e))?;
+
+        let created = get_template(client, &auth_data.organization_id, &pub_id)
+            .await
+            .map_err(|_| internal_server_error("Failed to fetch created pipeline template"))?;
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Create,
+            AuditResourceType::PipelineTemplate,
+            &pub_id,
+            None,
+            Some(pipeline_template_state(&created)),
+        )
+        .await?;
+
+        Ok(created)
+    })
+    .await?;
+
+    Ok(Json(created))
+}
+
+/// List pipeline templates
+#[utoipa::path(
+    get,
+    path = "/v1/pipeline_templates",
+    tag = "pipeline_templates",
+    responses(
+        (status = 200, description = "List of pipeline templates", body = PipelineTemplateCollection),
+    ),
+)]
+pub async fn get_pipeline_templates(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+) -> Result<Json<PipelineTemplateCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let templates = api_queries::fetch_get_pipeline_templates(
+        &state.database.client().await?,
+        &auth_data.organization_id,
+    )
+    .await?;
+
+    Ok(Json(PipelineTemplateCollection {
+        data: templates.into_iter().map(|t| t.into()).collect(),
+    }))
+}
+
+/// Get a pipeline template
+#[utoipa::path(
+    get,
+    path = "/v1/pipeline_templates/{id}",
+    tag = "pipeline_templates",
+    params(
+        ("id" = String, Path, description = "Pipeline template id")
+    ),
+    responses(
+        (status = 200, description = "Pipeline template", body = PipelineTemplate),
+    ),
+)]
+pub async fn get_pipeline_template(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<Json<PipelineTemplate>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    Ok(Json(
+        get_template(
+            &state.database.client().await?,
+            &auth_data.organization_id,
+            &pub_id,
+        )
+        .await?,
+    ))
+}
+
+/// Delete a pipeline template
+///
+/// Pipelines created from the template are not affected.
+#[utoipa::path(
+    delete,
+    path = "/v1/pipeline_templates/{id}",
+    tag = "pipeline_templates",
+    params(
+        ("id" = String, Path, description = "Pipeline template id")
+    ),
+    responses(
+        (status = 200, description = "Deleted pipeline template"),
+    ),
+)]
+pub async fn delete_pipeline_template(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let client = state.database.client().await?;
+
+    let template = get_template(&client, &auth_data.organization_id, &pub_id).await?;
+    auth_data.require_namespace(&template.namespace)?;
+
+    audit_log::transaction(&state.database, async |client| {
+        let count = api_queries::execute_delete_pipeline_template(
+            client,
+            &auth_data.organization_id,
+            &pub_id,
+        )
+        .await?;
+
+        if count != 1 {
+            return Err(not_found("Pipeline template"));
+        }
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Delete,
+            AuditResourceType::PipelineTemplate,
+            &pub_id,
+            Some(pipeline_template_state(&template)),
+            None,
+        )
+        .await?;
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Create a pipeline from a template
+///
+/// The template's parameters are substituted into its query, and the pipeline is created in the
+/// template's namespace with its UDFs.
+#[utoipa::path(
+    post,
+    path = "/v1/pipeline_templates/{id}/pipelines",
+    tag = "pipeline_templates",
+    params(
+        ("id" = String, Path, description = "Pipeline template id")
+    ),
+    request_body = PipelineTemplateInstancePost,
+    responses(
+        (status = 200, description = "Created pipeline", body = Pipeline),
+        (status = 400, description = "Bad request", body = ErrorResp),
+    ),
+)]
+pub async fn instantiate_pipeline_template(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+    WithRejection(Json(req), _): WithRejection<Json<PipelineTemplateInstancePost>, ApiError>,
+) -> Result<Json<Pipeline>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let template = get_template(
+        &state.database.client().await?,
+        &auth_data.organization_id,
+        &pub_id,
+    )
+    .await?;
+    auth_data.require_namespace(&template.namespace)?;
+
+    let (query, parameters) = render(&template.query, &template.parameters, &req.parameters)
+        .map_err(|e| {
+            bad_request(format!(
+                "Invalid parameters for template {}: {}",
+                template.name, e
+            ))
+        })?;
+
+    let pipeline_post = PipelinePost {
+        name: req.name,
+        query,
+        udfs: Some(template.udfs),
+        udf_versions: None,
+        preview: None,
+        parallelism: req.parallelism,
+        checkpoint_interval_micros: req.checkpoint_interval_micros,
+        namespace: Some(template.namespace),
+        priority: req.priority,
+        worker_pod: None,
+        freshness_slo: None,
+        restart_strategy: None,
+        batching: None,
+        recording: None,
+        replay: None,
+        bootstrap: None,
+        resource_classes: None,
+    };
+
+    let pipeline =
+        create_pipeline_from_post(&state, auth_data.clone(), pipeline_post, false).await?;
+
+    let client = state.database.client().await?;
+    let pipeline_id =
+        api_queries::fetch_get_pipeline_id(&client, &pipeline.id, &auth_data.organization_id)
+            .await
+            .map_err(log_and_map)?
+            .first()
+            .ok_or_else(|| internal_server_error("Failed to fetch created pipeline"))?
+            .id;
+
+    api_queries::execute_add_pipeline_template_instance(
+        &client,
+        &pub_id,
+        &pipeline_id,
+        &serde_json::to_value(&parameters).unwrap(),
+    )
+    .await?;
+
+    Ok(Json(pipeline))
+}
+
+/// Get the pipelines created from a template
+#[utoipa::path(
+    get,
+    path = "/v1/pipeline_templates/{id}/pipelines",
+    tag = "pipeline_templates",
+    params(
+        ("id" = String, Path, description = "Pipeline template id")
+    ),
+    responses(
+        (status = 200, description = "Pipelines created from the template", body = PipelineTemplateInstanceCollection),
+    ),
+)]
+pub async fn get_pipeline_template_instances(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<Json<PipelineTemplateInstanceCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    let client = state.database.client().await?;
+
+    // check that the template exists
+    get_template(&client, &auth_data.organization_id, &pub_id).await?;
+
+    let instances = api_queries::fetch_get_pipeline_template_instances(
+        &client,
+        &auth_data.organization_id,
+        &pub_id,
+    )
+    .await?;
+
+    Ok(Json(PipelineTemplateInstanceCollection {
+        data: instances.into_iter().map(|i| i.into()).collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameter(name: &str, parameter_type: TemplateParameterType) -> TemplateParameter {
+        TemplateParameter {
+            name: name.to_string(),
+            parameter_type,
+            description: None,
+            default: None,
+        }
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render() {
+        let mut region = parameter("region", TemplateParameterType::String);
+        region.default = Some("us-east".to_string());
+        let parameters = vec![
+            parameter("topic", TemplateParameterType::String),
+            parameter("threshold", TemplateParameterType::Number),
+            parameter("sink", TemplateParameterType::Identifier),
+            region,
+        ];
+
+        let query = "INSERT INTO {{sink}} SELECT * FROM events \
+            WHERE topic = '{{ topic }}' AND value > {{ threshold }} AND region = '{{ region }}'";
+
+        let (rendered, resolved) = render(
+            query,
+            &parameters,
+            &values(&[
+                ("topic", "o'brien"),
+                ("threshold", "2.5"),
+                ("sink", "out_1"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            rendered,
+            "INSERT INTO out_1 SELECT * FROM events \
+            WHERE topic = 'o''brien' AND value > 2.5 AND region = 'us-east'"
+        );
+        assert_eq!(
+            resolved,
+            values(&[
+                ("region", "us-east"),
+                ("sink", "out_1"),
+                ("threshold", "2.5"),
+                ("topic", "o'brien")
+            ])
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let parameters = vec![
+            parameter("threshold", TemplateParameterType::Number),
+            parameter("sink", TemplateParameterType::Identifier),
+        ];
+        let query = "INSERT INTO {{ sink }} SELECT * FROM events WHERE value > {{ threshold }}";
+
+        // missing value without a default
+        assert!(render(query, &parameters, &values(&[("sink", "out")])).is_err());
+
+        // unknown parameter
+        assert!(render(
+            query,
+            &parameters,
+            &values(&[("sink", "out"), ("threshold", "1"), ("other", "x")])
+        )
+        .is_err());
+
+        // values that don't match their types
+        assert!(render(
+            query,
+            &parameters,
+            &values(&[("sink", "out"), ("threshold", "1; DROP TABLE x")])
+        )
+        .is_err());
+        assert!(render(
+            query,
+            &parameters,
+            &values(&[("sink", "out x"), ("threshold", "1")])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_validate_template() {
+        let parameters = vec![parameter("topic", TemplateParameterType::String)];
+
+        assert!(validate_template("SELECT '{{ topic }}'", &parameters).is_ok());
+        assert!(validate_template("SELECT '{{ other }}'", &parameters).is_err());
+        assert!(validate_template("SELECT '{{ topic '", &parameters).is_err());
+        assert!(validate_template("SELECT '{{ to-pic }}'", &parameters).is_err());
+        assert!(validate_template(
+            "SELECT 1",
+            &[
+                parameter("topic", TemplateParameterType::String),
+                parameter("topic", TemplateParameterType::Number)
+            ]
+        )
+        .is_err());
+    }
+}
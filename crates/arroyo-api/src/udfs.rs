@@ -1,21 +1,35 @@
+use crate::audit_log;
+use crate::audit_log::udf_state;
+use crate::namespaces::resolve_namespace;
 use crate::queries::api_queries;
-use crate::queries::api_queries::DbUdf;
+use crate::queries::api_queries::{DbUdf, DbUdfVersion};
 use crate::rest::AppState;
 use crate::rest_utils::{
     authenticate, bad_request, internal_server_error, map_insert_err, not_found, ApiError,
     BearerAuth, ErrorResp,
 };
 use crate::{compiler_service, to_micros};
-use arroyo_rpc::api_types::udfs::{GlobalUdf, UdfPost, UdfValidationResult, ValidateUdfPost};
-use arroyo_rpc::api_types::GlobalUdfCollection;
+use arroyo_rpc::api_types::api_keys::Role;
+use arroyo_rpc::api_types::audit_log::{AuditAction, AuditResourceType};
+use arroyo_rpc::api_types::udfs::{
+    GlobalUdf, UdfArtifact, UdfArtifactPost, UdfLanguage, UdfPost, UdfValidationResult, UdfVersion,
+    UdfVersionPost, ValidateUdfPost,
+};
+use arroyo_rpc::api_types::{GlobalUdfCollection, UdfVersionCollection};
 use arroyo_rpc::config::config;
 use arroyo_rpc::grpc::compiler_grpc_client::CompilerGrpcClient;
-use arroyo_rpc::grpc::{BuildUdfReq, UdfCrate};
+use arroyo_rpc::grpc::{BuildUdfReq, PutUdfArtifactReq, UdfCrate};
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_udf_host::ParsedUdfFile;
+use arroyo_udf_js::ParsedJsUdf;
 use axum::extract::{Path, State};
 use axum::Json;
 use axum_extra::extract::WithRejection;
+use base64::Engine;
+use cornucopia_async::Database;
+use http::StatusCode;
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
 use tonic::transport::Channel;
 use tracing::error;
 
@@ -37,10 +51,57 @@ impl From<DbUdf> for GlobalUdf {
             updated_at: to_micros(val.updated_at),
             description: val.description,
             dylib_url: val.dylib_url,
+            namespace: val.namespace,
+            version: val.version,
+        }
+    }
+}
+
+impl From<DbUdfVersion> for UdfVersion {
+    fn from(val: DbUdfVersion) -> Self {
+        UdfVersion {
+            version: val.version,
+            created_at: to_micros(val.created_at),
+            definition: val.definition,
+            description: val.description,
+            dylib_url: val.dylib_url,
         }
     }
 }
 
+/// Resolves a global UDF to the version that a pipeline is pinned to, pinning it to its latest
+/// version if the pipeline doesn't reference it yet
+pub(crate) async fn pinned_version(
+    db: &Database<'_>,
+    organization_id: &str,
+    mut udf: GlobalUdf,
+    udf_versions: &mut BTreeMap<String, i32>,
+) -> Result<GlobalUdf, ErrorResp> {
+    let version = *udf_versions.entry(udf.name.clone()).or_insert(udf.version);
+    if version == udf.version {
+        return Ok(udf);
+    }
+
+    let pinned: UdfVersion =
+        api_queries::fetch_get_udf_version(db, organization_id, &udf.id, &version)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                bad_request(format!(
+                    "Version {} of UDF {} does not exist",
+                    version, udf.name
+                ))
+            })?
+            .into();
+
+    udf.definition = pinned.definition;
+    udf.description = pinned.description;
+    udf.dylib_url = pinned.dylib_url;
+    udf.version = pinned.version;
+    Ok(udf)
+}
+
 /// Create a global UDF
 #[utoipa::path(
     post,
@@ -56,13 +117,8 @@ pub async fn create_udf(
     bearer_auth: BearerAuth,
     WithRejection(Json(req), _): WithRejection<Json<UdfPost>, ApiError>,
 ) -> Result<Json<GlobalUdf>, ErrorResp> {
-    let auth_data = authenticate(&state.database, bearer_auth).await.unwrap();
-
-    // let transaction = client.transaction().await.map_err(log_and_map)?;
-    // transaction
-    //     .execute("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE", &[])
-    //     .await
-    //     .map_err(log_and_map)?;
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
 
     // build udf
     let build_udf_resp = build_udf(&mut compiler_service().await?, &req.definition, true).await?;
@@ -73,33 +129,64 @@ pub async fn create_udf(
 
     let client = state.database.client().await?;
 
+    let (namespace, _) = resolve_namespace(&auth_data, &client, req.namespace.as_deref()).await?;
+    auth_data.require_namespace(&namespace)?;
+
     let udf_name = build_udf_resp.name.expect("udf name not set for valid UDF");
     let udf_url = build_udf_resp.url.expect("udf URL not set for valid UDF");
+    let description = req.description.unwrap_or_default();
 
-    // check for duplicates
     let pub_id = generate_id(IdTypes::Udf);
-    api_queries::execute_create_udf(
-        &client,
-        &pub_id,
-        &auth_data.user_id,
-        &auth_data.organization_id,
-        &req.prefix,
-        &udf_name,
-        &req.definition,
-        &req.description.unwrap_or_default(),
-        &udf_url,
-    )
-    .await
-    .map_err(|e| map_insert_err("udf", e))?;
+    let created_udf = audit_log::transaction(&state.database, async |client| {
+        // check for duplicates
+        api_queries::execute_create_udf(
+            client,
+            &pub_id,
+            &auth_data.user_id,
+            &auth_data.organization_id,
+            &req.prefix,
+            &udf_name,
+            &req.definition,
+            &description,
+            &udf_url,
+            &namespace,
+        )
+        .await
+        .map_err(|e| map_insert_err("udf", e))?;
 
-    let created_udf = api_queries::fetch_get_udf(&client, &auth_data.organization_id, &pub_id)
-        .await?
-        .into_iter()
-        .next()
-        .ok_or_else(|| internal_server_error("Failed to fetch created UDF"))?
-        .into();
+        api_queries::execute_create_udf_version(
+            client,
+            &pub_id,
+            &1,
+            &auth_data.user_id,
+            &req.definition,
+            &description,
+            &udf_url,
+        )
+        .await?;
 
-    // transaction.commit().await.map_err(log_and_map)?;
+        let created_udf: GlobalUdf =
+            api_queries::fetch_get_udf(client, &auth_data.organization_id, &pub_id)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| internal_server_error("Failed to fetch created UDF"))?
+                .into();
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Create,
+            AuditResourceType::Udf,
+            &pub_id,
+            None,
+            Some(udf_state(&created_udf)),
+        )
+        .await?;
+
+        Ok(created_udf)
+    })
+    .await?;
 
     Ok(Json(created_udf))
 }
@@ -117,47 +204,231 @@ pub async fn get_udfs(
     State(state): State<AppState>,
     bearer_auth: BearerAuth,
 ) -> Result<Json<GlobalUdfCollection>, ErrorResp> {
-    let auth_data = authenticate(&state.database, bearer_auth).await.unwrap();
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
 
     let udfs =
         api_queries::fetch_get_udfs(&state.database.client().await?, &auth_data.organization_id)
             .await?;
 
     Ok(Json(GlobalUdfCollection {
-        data: udfs.into_iter().map(|u| u.into()).collect(),
+        data: udfs
+            .into_iter()
+            .map(GlobalUdf::from)
+            // UDFs outside of an API key's scope are treated as though they don't exist
+            .filter(|u| auth_data.scope.allows_namespace(&u.namespace))
+            .collect(),
     }))
 }
 
-/// Delete UDF
+/// Publish a new version of a global UDF
+///
+/// Builds the new definition and makes it the latest version of the UDF, which new pipelines
+/// use. Existing pipelines stay on the version they are pinned to until they are moved to the
+/// new one with `PUT /v1/pipelines/{id}/udfs`.
 #[utoipa::path(
-    delete,
-    path = "/v1/udfs/{id}",
+    post,
+    path = "/v1/udfs/{id}/versions",
     tag = "udfs",
     params(
         ("id" = String, Path, description = "UDF id")
     ),
+    request_body = UdfVersionPost,
     responses(
-        (status = 200, description = "Deleted UDF"),
+        (status = 200, description = "Updated UDF", body = GlobalUdf),
     ),
 )]
-pub async fn delete_udf(
+pub async fn create_udf_version(
     State(state): State<AppState>,
     bearer_auth: BearerAuth,
     Path(udf_pub_id): Path<String>,
-) -> Result<(), ErrorResp> {
-    let auth_data = authenticate(&state.database, bearer_auth).await.unwrap();
+    WithRejection(Json(req), _): WithRejection<Json<UdfVersionPost>, ApiError>,
+) -> Result<Json<GlobalUdf>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
 
-    let count = api_queries::execute_delete_udf(
+    let client = state.database.client().await?;
+
+    let udf: GlobalUdf =
+        api_queries::fetch_get_udf(&client, &auth_data.organization_id, &udf_pub_id)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_found("UDF"))?
+            .into();
+    auth_data.require_namespace(&udf.namespace)?;
+
+    let build_udf_resp = build_udf(&mut compiler_service().await?, &req.definition, true).await?;
+
+    if !build_udf_resp.errors.is_empty() {
+        return Err(bad_request("UDF is invalid"));
+    }
+
+    let udf_name = build_udf_resp.name.expect("udf name not set for valid UDF");
+    let udf_url = build_udf_resp.url.expect("udf URL not set for valid UDF");
+
+    // pipelines are pinned to versions by name, so it can't change between versions
+    if udf_name != udf.name {
+        return Err(bad_request(format!(
+            "New versions of UDF {} must keep its name, but the definition is for {}",
+            udf.name, udf_name
+        )));
+    }
+
+    let description = req
+        .description
+        .or_else(|| udf.description.clone())
+        .unwrap_or_default();
+    let version = udf.version + 1;
+
+    let updated_udf = audit_log::transaction(&state.database, async |client| {
+        let updated = api_queries::execute_update_udf(
+            client,
+            &req.definition,
+            &description,
+            &udf_url,
+            &version,
+            &OffsetDateTime::now_utc(),
+            &auth_data.organization_id,
+            &udf_pub_id,
+            &udf.version,
+        )
+        .await?;
+
+        if updated != 1 {
+            return Err(ErrorResp {
+                status_code: StatusCode::CONFLICT,
+                message: format!(
+                    "UDF {} was modified concurrently; retry the request",
+                    udf.name
+                ),
+            });
+        }
+
+        api_queries::execute_create_udf_version(
+            client,
+            &udf_pub_id,
+            &version,
+            &auth_data.user_id,
+            &req.definition,
+            &description,
+            &udf_url,
+        )
+        .await?;
+
+        let updated_udf: GlobalUdf =
+            api_queries::fetch_get_udf(client, &auth_data.organization_id, &udf_pub_id)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| internal_server_error("Failed to fetch updated UDF"))?
+                .into();
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Update,
+            AuditResourceType::Udf,
+            &udf_pub_id,
+            Some(udf_state(&udf)),
+            Some(udf_state(&updated_udf)),
+        )
+        .await?;
+
+        Ok(updated_udf)
+    })
+    .await?;
+
+    Ok(Json(updated_udf))
+}
+
+/// Get the versions of a global UDF
+#[utoipa::path(
+    get,
+    path = "/v1/udfs/{id}/versions",
+    tag = "udfs",
+    params(
+        ("id" = String, Path, description = "UDF id")
+    ),
+    responses(
+        (status = 200, description = "Versions of the UDF, newest first", body = UdfVersionCollection),
+    ),
+)]
+pub async fn get_udf_versions(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(udf_pub_id): Path<String>,
+) -> Result<Json<UdfVersionCollection>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+
+    let versions = api_queries::fetch_get_udf_versions(
         &state.database.client().await?,
         &auth_data.organization_id,
         &udf_pub_id,
     )
     .await?;
 
-    if count != 1 {
+    if versions.is_empty() {
         return Err(not_found("UDF"));
     }
 
+    Ok(Json(UdfVersionCollection {
+        data: versions.into_iter().map(|v| v.into()).collect(),
+    }))
+}
+
+/// Delete UDF
+#[utoipa::path(
+    delete,
+    path = "/v1/udfs/{id}",
+    tag = "udfs",
+    params(
+        ("id" = String, Path, description = "UDF id")
+    ),
+    responses(
+        (status = 200, description = "Deleted UDF"),
+    ),
+)]
+pub async fn delete_udf(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(udf_pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Editor)?;
+
+    let client = state.database.client().await?;
+
+    let udf = api_queries::fetch_get_udf(&client, &auth_data.organization_id, &udf_pub_id)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found("UDF"))?;
+    auth_data.require_namespace(&udf.namespace)?;
+
+    audit_log::transaction(&state.database, async |client| {
+        let count =
+            api_queries::execute_delete_udf(client, &auth_data.organization_id, &udf_pub_id)
+                .await?;
+
+        if count != 1 {
+            return Err(not_found("UDF"));
+        }
+
+        audit_log::record(
+            client,
+            &auth_data,
+            AuditAction::Delete,
+            AuditResourceType::Udf,
+            &udf_pub_id,
+            Some(udf_state(&udf.into())),
+            None,
+        )
+        .await?;
+
+        Ok(())
+    })
+    .await?;
+
     Ok(())
 }
 
@@ -177,16 +448,10 @@ impl From<anyhow::Error> for UdfResp {
     }
 }
 
-pub async fn build_udf(
-    compiler_service: &mut CompilerGrpcClient<Channel>,
-    udf_definition: &str,
-    save: bool,
-) -> Result<UdfResp, ErrorResp> {
+/// Parses a UDF definition into the crate that the compiler service builds it as
+fn udf_crate(udf_definition: &str) -> anyhow::Result<UdfCrate> {
     // use the ArroyoSchemaProvider to do some validation and to get the function name
-    let file = match ParsedUdfFile::try_parse(udf_definition) {
-        Ok(p) => p,
-        Err(e) => return Ok(e.into()),
-    };
+    let file = ParsedUdfFile::try_parse(udf_definition)?;
 
     let mut dependencies = file.dependencies;
     let plugin_dep = if config().compiler.use_local_udf_crate {
@@ -204,13 +469,28 @@ pub async fn build_udf(
 
     dependencies.insert("arroyo-udf-plugin".to_string(), plugin_dep);
 
+    Ok(UdfCrate {
+        name: file.udf.name,
+        definition: udf_definition.to_string(),
+        dependencies: dependencies.to_string(),
+    })
+}
+
+pub async fn build_udf(
+    compiler_service: &mut CompilerGrpcClient<Channel>,
+    udf_definition: &str,
+    save: bool,
+) -> Result<UdfResp, ErrorResp> {
+    let udf_crate = match udf_crate(udf_definition) {
+        Ok(c) => c,
+        Err(e) => return Ok(e.into()),
+    };
+
+    let name = udf_crate.name.clone();
+
     let check_udfs_resp = match compiler_service
         .build_udf(BuildUdfReq {
-            udf_crate: Some(UdfCrate {
-                name: file.udf.name.clone(),
-                definition: udf_definition.to_string(),
-                dependencies: dependencies.to_string(),
-            }),
+            udf_crate: Some(udf_crate),
             save,
         })
         .await
@@ -227,11 +507,68 @@ pub async fn build_udf(
 
     Ok(UdfResp {
         errors: check_udfs_resp.errors,
-        name: Some(file.udf.name),
+        name: Some(name),
         url: check_udfs_resp.udf_path,
     })
 }
 
+/// Upload a prebuilt UDF artifact
+///
+/// Stores a dylib compiled outside of the cluster for the given UDF definition, which is then
+/// used instead of compiling the UDF. This allows UDFs to be used on clusters without access to
+/// a Rust toolchain or crate registry. The artifact must be built for the same platform as the
+/// workers, against the same dependencies as the definition (including `arroyo-udf-plugin`).
+#[utoipa::path(
+    post,
+    path = "/v1/udfs/artifacts",
+    tag = "udfs",
+    request_body = UdfArtifactPost,
+    responses(
+        (status = 200, description = "Stored artifact", body = UdfArtifact),
+    ),
+)]
+pub async fn upload_udf_artifact(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(req), _): WithRejection<Json<UdfArtifactPost>, ApiError>,
+) -> Result<Json<UdfArtifact>, ErrorResp> {
+    let auth_data = authenticate(&state.database, bearer_auth).await?;
+    auth_data.require_role(Role::Admin)?;
+
+    let udf_crate =
+        udf_crate(&req.definition).map_err(|e| bad_request(format!("Invalid UDF: {}", e)))?;
+    let udf_name = udf_crate.name.clone();
+
+    let dylib = base64::engine::general_purpose::STANDARD
+        .decode(&req.dylib)
+        .map_err(|e| bad_request(format!("dylib is not valid base64: {}", e)))?;
+
+    let resp = compiler_service()
+        .await?
+        .put_udf_artifact(PutUdfArtifactReq {
+            udf_crate: Some(udf_crate),
+            dylib,
+        })
+        .await
+        .map_err(|e| {
+            if e.code() == tonic::Code::InvalidArgument {
+                bad_request(e.message().to_string())
+            } else {
+                error!(
+                    "compiler service failed to store UDF artifact: {}",
+                    e.message()
+                );
+                internal_server_error(format!("Failed to store UDF artifact: {}", e.message()))
+            }
+        })?
+        .into_inner();
+
+    Ok(Json(UdfArtifact {
+        udf_name,
+        dylib_url: resp.udf_path,
+    }))
+}
+
 /// Validate UDFs
 #[utoipa::path(
     post,
@@ -245,6 +582,19 @@ pub async fn build_udf(
 pub async fn validate_udf(
     WithRejection(Json(req), _): WithRejection<Json<ValidateUdfPost>, ApiError>,
 ) -> Result<Json<UdfValidationResult>, ErrorResp> {
+    if req.language == UdfLanguage::JavaScript {
+        return Ok(Json(match ParsedJsUdf::try_parse(&req.definition) {
+            Ok(parsed) => UdfValidationResult {
+                udf_name: Some(parsed.name),
+                errors: vec![],
+            },
+            Err(e) => UdfValidationResult {
+                udf_name: None,
+                errors: vec![e.to_string()],
+            },
+        }));
+    }
+
     let check_udfs_resp = build_udf(&mut compiler_service().await?, &req.definition, false).await?;
 
     Ok(Json(UdfValidationResult {
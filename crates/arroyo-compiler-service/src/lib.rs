@@ -1,8 +1,5 @@
 use anyhow::{anyhow, bail};
-use base64::prelude::BASE64_STANDARD_NO_PAD;
-use base64::Engine;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use sha2::{Digest, Sha256};
 use std::net::SocketAddr;
 use std::process::Stdio;
 use std::str::from_utf8;
@@ -11,9 +8,11 @@ use std::{path::PathBuf, str::FromStr, sync::Arc};
 
 use arroyo_rpc::grpc::{
     compiler_grpc_server::{CompilerGrpc, CompilerGrpcServer},
-    BuildUdfReq, BuildUdfResp, GetUdfPathReq, GetUdfPathResp, UdfCrate,
+    BuildUdfReq, BuildUdfResp, GetUdfPathReq, GetUdfPathResp, PutUdfArtifactReq,
+    PutUdfArtifactResp, UdfCrate,
 };
 use arroyo_rpc::var_str::VarStr;
+use arroyo_rpc::Compressed;
 
 use arroyo_rpc::config::config;
 use arroyo_server_common::wrap_start;
@@ -24,10 +23,13 @@ use tokio::time::timeout;
 use tokio::{process::Command, sync::Mutex};
 use toml::{toml, Table};
 use tonic::{Request, Response, Status};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 const RUSTUP: &str = "curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y";
 
+// prebuilt UDF artifacts are uploaded through the API, and can exceed tonic's default 4MB limit
+const MAX_ARTIFACT_SIZE: usize = 512 * 1024 * 1024;
+
 pub fn to_millis(time: SystemTime) -> u64 {
     time.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
 }
@@ -48,7 +50,11 @@ pub async fn start_service() -> anyhow::Result<()> {
         "compiler service",
         addr.clone(),
         arroyo_server_common::grpc_server()
-            .add_service(CompilerGrpcServer::new(service))
+            .add_service(
+                CompilerGrpcServer::new(service)
+                    .max_decoding_message_size(MAX_ARTIFACT_SIZE)
+                    .compressed(),
+            )
             .serve(addr),
     )
     .await
@@ -59,6 +65,7 @@ pub struct CompileService {
     lock: Arc<Mutex<()>>,
     storage: StorageProvider,
     cargo_path: Arc<Mutex<String>>,
+    toolchain: Arc<Mutex<Option<String>>>,
 }
 
 async fn binary_present(bin: &str) -> bool {
@@ -83,9 +90,45 @@ impl CompileService {
             lock: Arc::new(Mutex::new(())),
             storage,
             cargo_path: Arc::new(Mutex::new("cargo".to_string())),
+            toolchain: Arc::new(Mutex::new(None)),
         })
     }
 
+    /// Returns the version and host of the toolchain used to build UDFs, which is part of the key
+    /// that compiled UDFs are cached under
+    async fn toolchain(&self) -> anyhow::Result<String> {
+        let mut toolchain = self.toolchain.lock().await;
+        if let Some(toolchain) = &*toolchain {
+            return Ok(toolchain.clone());
+        }
+
+        let output = Command::new(&*self.cargo_path.lock().await)
+            .arg("--version")
+            .arg("--verbose")
+            .output()
+            .await
+            .map_err(|e| anyhow!("Failed to run cargo to determine toolchain version: {e}"))?;
+
+        if !output.status.success() {
+            bail!(
+                "Failed to determine toolchain version: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        info!(
+            "Building UDFs with toolchain {}",
+            version.replace('\n', ", ")
+        );
+        *toolchain = Some(version.clone());
+        Ok(version)
+    }
+
+    async fn exists(&self, path: &str) -> bool {
+        self.storage.exists(path).await.is_ok_and(|x| x)
+    }
+
     async fn write_udf_crate(&self, udf_crate: UdfCrate) -> anyhow::Result<()> {
         tokio::fs::create_dir_all(&self.build_dir.join("src")).await?;
         tokio::fs::write(self.build_dir.join("src/lib.rs"), &udf_crate.definition).await?;
@@ -224,12 +267,60 @@ impl CompileService {
     }
 }
 
-fn dylib_path(name: &str, definition: &str) -> String {
-    let mut hasher = DefaultHasher::new();
-    definition.hash(&mut hasher);
-    let hash = BASE64_STANDARD_NO_PAD.encode(hasher.finish().to_le_bytes());
+fn hash_parts(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Hash of everything that determines the output of a UDF build, other than the toolchain
+fn source_hash(name: &str, definition: &str, dependencies: &str) -> String {
+    hash_parts(&[name, definition, dependencies])
+}
+
+/// Path of a dylib that was uploaded for the UDF, which is used regardless of the toolchain
+fn prebuilt_path(name: &str, source_hash: &str) -> String {
+    format!(
+        "udfs/prebuilt/{}_{}.{}",
+        name, source_hash, PLATFORM_FILE_EXTENSION
+    )
+}
+
+/// Path of a dylib that was compiled for the UDF by this toolchain
+fn dylib_path(name: &str, source_hash: &str, toolchain: &str) -> String {
+    format!(
+        "udfs/{}_{}.{}",
+        name,
+        hash_parts(&[source_hash, toolchain]),
+        PLATFORM_FILE_EXTENSION
+    )
+}
+
+/// Path of the marker recording that the UDF passed `cargo check` with this toolchain
+fn checked_path(name: &str, source_hash: &str, toolchain: &str) -> String {
+    format!(
+        "udfs/checked/{}_{}",
+        name,
+        hash_parts(&[source_hash, toolchain])
+    )
+}
 
-    format!("udfs/{}_{}.{}", name, hash, PLATFORM_FILE_EXTENSION)
+fn is_dylib(bytes: &[u8]) -> bool {
+    const MAGIC: &[[u8; 4]] = &[
+        // ELF
+        [0x7f, b'E', b'L', b'F'],
+        // Mach-O (64 and 32 bit, both byte orders) and universal binaries
+        [0xcf, 0xfa, 0xed, 0xfe],
+        [0xce, 0xfa, 0xed, 0xfe],
+        [0xfe, 0xed, 0xfa, 0xcf],
+        [0xfe, 0xed, 0xfa, 0xce],
+        [0xca, 0xfe, 0xba, 0xbe],
+    ];
+
+    bytes.len() >= 4 && MAGIC.iter().any(|m| bytes[..4] == m[..])
 }
 
 #[tonic::async_trait]
@@ -241,6 +332,27 @@ impl CompilerGrpc for CompileService {
         // only allow one request to be active at a given time
         let _guard = self.lock.lock().await;
 
+        let req = request.into_inner();
+        let udf_crate = req
+            .udf_crate
+            .ok_or_else(|| Status::failed_precondition("missing udf_crate field"))?;
+
+        let source_hash = source_hash(
+            &udf_crate.name,
+            &udf_crate.definition,
+            &udf_crate.dependencies,
+        );
+
+        // prebuilt artifacts don't require a toolchain, so they're used even if we can't compile
+        let prebuilt = prebuilt_path(&udf_crate.name, &source_hash);
+        if self.exists(&prebuilt).await {
+            info!("Using prebuilt artifact for UDF {}", udf_crate.name);
+            return Ok(Response::new(BuildUdfResp {
+                errors: vec![],
+                udf_path: Some(self.storage.canonical_url_for(&prebuilt)),
+            }));
+        }
+
         self.check_cc()
             .await
             .map_err(|e| Status::failed_precondition(e.to_string()))?;
@@ -248,16 +360,16 @@ impl CompilerGrpc for CompileService {
             .await
             .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
-        let req = request.into_inner();
-        let udf_crate = req
-            .udf_crate
-            .ok_or_else(|| Status::failed_precondition("missing udf_crate field"))?;
+        let toolchain = self
+            .toolchain()
+            .await
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
 
-        let path = dylib_path(&udf_crate.name, &udf_crate.definition);
+        let path = dylib_path(&udf_crate.name, &source_hash, &toolchain);
         let canonical_url = self.storage.canonical_url_for(&path);
 
         // exit early if udf is already compiled
-        if self.storage.exists(path.as_str()).await.is_ok_and(|x| x) {
+        if self.exists(&path).await {
             info!("UDF {} already compiled, skipping", udf_crate.name);
             return Ok(Response::new(BuildUdfResp {
                 errors: vec![],
@@ -265,6 +377,15 @@ impl CompilerGrpc for CompileService {
             }));
         }
 
+        let checked = checked_path(&udf_crate.name, &source_hash, &toolchain);
+        if !req.save && self.exists(&checked).await {
+            info!("UDF {} already checked, skipping", udf_crate.name);
+            return Ok(Response::new(BuildUdfResp {
+                errors: vec![],
+                udf_path: None,
+            }));
+        }
+
         let start = Instant::now();
 
         let name = udf_crate.name.clone();
@@ -317,6 +438,10 @@ impl CompilerGrpc for CompileService {
                 info!("Wrote UDF dylib to {}", canonical_url);
                 Some(canonical_url)
             } else {
+                // failing to record the check only means it will be re-run next time
+                if let Err(e) = self.storage.put(&checked, vec![]).await {
+                    warn!("Failed to record successful check of UDF {}: {}", name, e);
+                }
                 None
             };
 
@@ -368,16 +493,66 @@ impl CompilerGrpc for CompileService {
     ) -> Result<Response<GetUdfPathResp>, Status> {
         let req = request.into_inner();
 
-        let path = dylib_path(&req.name, &req.definition);
-        let canonical_url = self.storage.canonical_url_for(&path);
+        let source_hash = source_hash(&req.name, &req.definition, &req.dependencies);
 
-        let exists =
-            self.storage.exists(path.as_str()).await.map_err(|e| {
+        let mut paths = vec![prebuilt_path(&req.name, &source_hash)];
+        if let Ok(toolchain) = self.toolchain().await {
+            paths.push(dylib_path(&req.name, &source_hash, &toolchain));
+        }
+
+        for path in paths {
+            let exists = self.storage.exists(path.as_str()).await.map_err(|e| {
                 Status::internal(format!("Failed to read from storage system: {}", e))
             })?;
 
-        Ok(Response::new(GetUdfPathResp {
-            udf_path: exists.then_some(canonical_url),
+            if exists {
+                return Ok(Response::new(GetUdfPathResp {
+                    udf_path: Some(self.storage.canonical_url_for(&path)),
+                }));
+            }
+        }
+
+        Ok(Response::new(GetUdfPathResp { udf_path: None }))
+    }
+
+    async fn put_udf_artifact(
+        &self,
+        request: Request<PutUdfArtifactReq>,
+    ) -> Result<Response<PutUdfArtifactResp>, Status> {
+        let req = request.into_inner();
+        let udf_crate = req
+            .udf_crate
+            .ok_or_else(|| Status::failed_precondition("missing udf_crate field"))?;
+
+        if !is_dylib(&req.dylib) {
+            return Err(Status::invalid_argument(format!(
+                "artifact for UDF {} is not a dynamic library",
+                udf_crate.name
+            )));
+        }
+
+        let source_hash = source_hash(
+            &udf_crate.name,
+            &udf_crate.definition,
+            &udf_crate.dependencies,
+        );
+        let path = prebuilt_path(&udf_crate.name, &source_hash);
+
+        self.storage.put(&path, req.dylib).await.map_err(|e| {
+            Status::internal(format!(
+                "Failed to write UDF library to artifact storage: {}",
+                e
+            ))
+        })?;
+
+        let canonical_url = self.storage.canonical_url_for(&path);
+        info!(
+            "Wrote prebuilt dylib for UDF {} to {}",
+            udf_crate.name, canonical_url
+        );
+
+        Ok(Response::new(PutUdfArtifactResp {
+            udf_path: canonical_url,
         }))
     }
 }
@@ -0,0 +1,345 @@
+//! The C ABI between Arroyo and connector plugins.
+//!
+//! Memory is always freed by the side that allocated it, since Arroyo and a plugin may use
+//! different allocators: byte buffers returned by a plugin are copied by Arroyo and then handed
+//! back to [`free_bytes`], while record batches carry their own release callbacks through the
+//! Arrow C data interface. Panics in a plugin are caught and returned as errors.
+//!
+//! The functions in this module implement the plugin side of the ABI for
+//! [`export_connector!`](crate::export_connector), and aren't meant to be called directly.
+
+use std::any::Any;
+use std::ffi::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr::null_mut;
+use std::time::Duration;
+
+use arrow::array::{Array, RecordBatch, StructArray};
+use arrow::error::ArrowError;
+use arrow::ffi::{from_ffi, to_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+
+use crate::{ConnectorPlugin, OpenContext, PluginConfig, PluginSink, PluginSource, SourcePoll};
+
+/// Bytes borrowed from the caller for the duration of a call
+#[repr(C)]
+pub struct FfiSlice {
+    ptr: *const u8,
+    len: usize,
+}
+
+impl FfiSlice {
+    pub fn new(bytes: &[u8]) -> Self {
+        Self {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+
+    /// # Safety
+    /// The slice must have been created from bytes that are still alive
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(self.ptr, self.len)
+        }
+    }
+}
+
+/// Bytes allocated by a plugin, which must be returned to it to be freed
+#[repr(C)]
+pub struct FfiBytes {
+    ptr: *mut u8,
+    len: usize,
+    capacity: usize,
+}
+
+impl FfiBytes {
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let mut bytes = std::mem::ManuallyDrop::new(bytes);
+        Self {
+            ptr: bytes.as_mut_ptr(),
+            len: bytes.len(),
+            capacity: bytes.capacity(),
+        }
+    }
+
+    fn empty() -> Self {
+        Self::from_vec(vec![])
+    }
+
+    /// # Safety
+    /// The bytes must not have been freed
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(self.ptr, self.len)
+        }
+    }
+}
+
+/// Either the output of a call, or a UTF-8 error message
+#[repr(C)]
+pub struct FfiResult {
+    pub ok: bool,
+    pub bytes: FfiBytes,
+}
+
+impl From<Result<Vec<u8>, String>> for FfiResult {
+    fn from(result: Result<Vec<u8>, String>) -> Self {
+        match result {
+            Ok(bytes) => Self {
+                ok: true,
+                bytes: FfiBytes::from_vec(bytes),
+            },
+            Err(e) => Self {
+                ok: false,
+                bytes: FfiBytes::from_vec(e.into_bytes()),
+            },
+        }
+    }
+}
+
+/// An opened source or sink, or an error message if the handle is null
+#[repr(C)]
+pub struct FfiHandle {
+    pub handle: *mut c_void,
+    pub error: FfiBytes,
+}
+
+/// A record batch, passed as a struct array through the Arrow C data interface. Ownership of the
+/// batch moves to the receiver.
+#[repr(C)]
+pub struct FfiBatch {
+    array: FFI_ArrowArray,
+    schema: FFI_ArrowSchema,
+}
+
+impl FfiBatch {
+    pub fn try_from_batch(batch: RecordBatch) -> Result<Self, ArrowError> {
+        let data = StructArray::from(batch).into_data();
+        let (array, schema) = to_ffi(&data)?;
+        Ok(Self { array, schema })
+    }
+
+    fn empty() -> Self {
+        Self {
+            array: FFI_ArrowArray::empty(),
+            schema: FFI_ArrowSchema::empty(),
+        }
+    }
+
+    /// # Safety
+    /// The batch must have been created with [`FfiBatch::try_from_batch`]
+    pub unsafe fn into_batch(self) -> Result<RecordBatch, ArrowError> {
+        let data = from_ffi(self.array, &self.schema)?;
+        Ok(RecordBatch::from(StructArray::from(data)))
+    }
+}
+
+pub const POLL_BATCH: u8 = 0;
+pub const POLL_IDLE: u8 = 1;
+pub const POLL_FINISHED: u8 = 2;
+pub const POLL_ERROR: u8 = 3;
+
+/// The result of polling a source; `batch` is only set for [`POLL_BATCH`] and `error` for
+/// [`POLL_ERROR`]
+#[repr(C)]
+pub struct FfiPoll {
+    pub status: u8,
+    pub batch: FfiBatch,
+    pub error: FfiBytes,
+}
+
+impl FfiPoll {
+    fn status(status: u8) -> Self {
+        Self {
+            status,
+            batch: FfiBatch::empty(),
+            error: FfiBytes::empty(),
+        }
+    }
+
+    fn error(e: String) -> Self {
+        Self {
+            error: FfiBytes::from_vec(e.into_bytes()),
+            ..Self::status(POLL_ERROR)
+        }
+    }
+}
+
+fn panic_message(e: Box<dyn Any + Send>) -> String {
+    let message = e
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| e.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    format!("connector plugin panicked: {}", message)
+}
+
+fn guard<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|e| Err(panic_message(e)))
+}
+
+fn parse<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    serde_json::from_slice(bytes).map_err(|e| format!("invalid input from Arroyo: {}", e))
+}
+
+fn handle<T>(result: Result<T, String>) -> FfiHandle {
+    match result {
+        Ok(t) => FfiHandle {
+            handle: Box::into_raw(Box::new(t)) as *mut c_void,
+            error: FfiBytes::empty(),
+        },
+        Err(e) => FfiHandle {
+            handle: null_mut(),
+            error: FfiBytes::from_vec(e.into_bytes()),
+        },
+    }
+}
+
+pub fn metadata(plugin: &dyn ConnectorPlugin) -> FfiResult {
+    guard(|| serde_json::to_vec(&plugin.metadata()).map_err(|e| e.to_string())).into()
+}
+
+/// # Safety
+/// `config` must point to valid bytes
+pub unsafe fn validate(plugin: &dyn ConnectorPlugin, config: FfiSlice) -> FfiResult {
+    guard(|| {
+        let config: PluginConfig = parse(config.as_slice())?;
+        serde_json::to_vec(&plugin.validate(config)?).map_err(|e| e.to_string())
+    })
+    .into()
+}
+
+/// # Safety
+/// `ctx` and `state` must point to valid bytes
+pub unsafe fn source_open(
+    plugin: &dyn ConnectorPlugin,
+    ctx: FfiSlice,
+    state: FfiSlice,
+    has_state: bool,
+) -> FfiHandle {
+    handle(guard(|| {
+        let ctx: OpenContext = parse(ctx.as_slice())?;
+        let state = has_state.then(|| state.as_slice().to_vec());
+        plugin.open_source(ctx, state)
+    }))
+}
+
+/// # Safety
+/// `handle` must have been returned by [`source_open`] and not closed
+pub unsafe fn source_poll(handle: *mut c_void, timeout_micros: u64) -> FfiPoll {
+    let source = &mut *(handle as *mut Box<dyn PluginSource>);
+    match guard(|| source.poll(Duration::from_micros(timeout_micros))) {
+        Ok(SourcePoll::Batch(batch)) => match FfiBatch::try_from_batch(batch) {
+            Ok(batch) => FfiPoll {
+                batch,
+                ..FfiPoll::status(POLL_BATCH)
+            },
+            Err(e) => FfiPoll::error(format!("failed to export batch: {}", e)),
+        },
+        Ok(SourcePoll::Idle) => FfiPoll::status(POLL_IDLE),
+        Ok(SourcePoll::Finished) => FfiPoll::status(POLL_FINISHED),
+        Err(e) => FfiPoll::error(e),
+    }
+}
+
+/// # Safety
+/// `handle` must have been returned by [`source_open`] and not closed
+pub unsafe fn source_checkpoint(handle: *mut c_void, epoch: u32) -> FfiResult {
+    let source = &mut *(handle as *mut Box<dyn PluginSource>);
+    guard(|| source.checkpoint(epoch)).into()
+}
+
+/// # Safety
+/// `handle` must have been returned by [`source_open`] and not closed
+pub unsafe fn source_close(handle: *mut c_void) {
+    let mut source = Box::from_raw(handle as *mut Box<dyn PluginSource>);
+    let _ = guard(|| {
+        source.close();
+        Ok(())
+    });
+}
+
+/// # Safety
+/// `ctx` must point to valid bytes
+pub unsafe fn sink_open(plugin: &dyn ConnectorPlugin, ctx: FfiSlice) -> FfiHandle {
+    handle(guard(|| {
+        let ctx: OpenContext = parse(ctx.as_slice())?;
+        plugin.open_sink(ctx)
+    }))
+}
+
+/// # Safety
+/// `handle` must have been returned by [`sink_open`] and not closed
+pub unsafe fn sink_write(handle: *mut c_void, batch: FfiBatch) -> FfiResult {
+    let sink = &mut *(handle as *mut Box<dyn PluginSink>);
+    guard(|| {
+        let batch = batch
+            .into_batch()
+            .map_err(|e| format!("failed to import batch: {}", e))?;
+        sink.write(batch)?;
+        Ok(vec![])
+    })
+    .into()
+}
+
+/// # Safety
+/// `handle` must have been returned by [`sink_open`] and not closed
+pub unsafe fn sink_flush(handle: *mut c_void, epoch: u32) -> FfiResult {
+    let sink = &mut *(handle as *mut Box<dyn PluginSink>);
+    guard(|| sink.flush(epoch).map(|_| vec![])).into()
+}
+
+/// # Safety
+/// `handle` must have been returned by [`sink_open`] and not closed
+pub unsafe fn sink_close(handle: *mut c_void) -> FfiResult {
+    let mut sink = Box::from_raw(handle as *mut Box<dyn PluginSink>);
+    guard(|| sink.close().map(|_| vec![])).into()
+}
+
+/// # Safety
+/// `bytes` must have been returned by this plugin, and not already freed
+pub unsafe fn free_bytes(bytes: FfiBytes) {
+    drop(Vec::from_raw_parts(bytes.ptr, bytes.len, bytes.capacity));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int64Array, StringArray};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_batch_round_trip() {
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "id",
+                Arc::new(Int64Array::from(vec![1, 2, 3])) as Arc<dyn Array>,
+            ),
+            (
+                "name",
+                Arc::new(StringArray::from(vec![Some("a"), None, Some("c")])) as Arc<dyn Array>,
+            ),
+        ])
+        .unwrap();
+
+        let ffi = FfiBatch::try_from_batch(batch.clone()).unwrap();
+        assert_eq!(unsafe { ffi.into_batch() }.unwrap(), batch);
+    }
+
+    #[test]
+    fn test_result_bytes() {
+        let result: FfiResult = Err::<Vec<u8>, _>("bad config".to_string()).into();
+        assert!(!result.ok);
+        assert_eq!(unsafe { result.bytes.as_slice() }, b"bad config");
+        unsafe { free_bytes(result.bytes) };
+
+        assert_eq!(
+            guard::<()>(|| panic!("oh no")).unwrap_err(),
+            "connector plugin panicked: oh no"
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Interface for building Arroyo connectors as plugins, which are loaded at runtime from the
+//! directory configured by `connector-plugin-dir` instead of being compiled into Arroyo.
+//!
+//! A plugin is a `cdylib` crate that implements [`ConnectorPlugin`] and exports it with
+//! [`export_connector!`]:
+//!
+//! ```ignore
+//! use arroyo_connector_sdk::*;
+//!
+//! #[derive(Default)]
+//! struct CounterConnector;
+//!
+//! impl ConnectorPlugin for CounterConnector {
+//!     fn metadata(&self) -> PluginMetadata { ... }
+//!
+//!     fn open_source(
+//!         &self,
+//!         ctx: OpenContext,
+//!         state: Option<Vec<u8>>,
+//!     ) -> Result<Box<dyn PluginSource>, String> { ... }
+//! }
+//!
+//! export_connector!(CounterConnector::default());
+//! ```
+//!
+//! Arroyo and its plugins communicate over a C ABI (see [`ffi`]): configuration and metadata are
+//! exchanged as JSON and data as Arrow record batches through the Arrow C data interface, so a
+//! plugin doesn't need to be built with the same compiler or dependencies as Arroyo. Plugins
+//! built against a different [`ABI_VERSION`] are refused.
+
+pub mod ffi;
+
+use std::time::Duration;
+
+pub use arrow;
+use arrow::array::RecordBatch;
+use arrow_schema::Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The version of the interface between Arroyo and its plugins, which is incremented whenever it
+/// changes incompatibly
+pub const ABI_VERSION: u32 = 1;
+
+/// Describes a connector in Arroyo's connector catalog
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginMetadata {
+    /// Identifies the connector in SQL (`connector = '<id>'`) and the API; must not clash with
+    /// Arroyo's built-in connectors
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    /// An SVG icon for the Web UI
+    #[serde(default)]
+    pub icon: String,
+    pub source: bool,
+    pub sink: bool,
+    /// JSON schema of the table configuration. Connectors that support both sources and sinks
+    /// must have a `type` property in their tables set to either "source" or "sink"
+    pub table_config: Value,
+    /// JSON schema of the connection profile, for connectors that have one
+    #[serde(default)]
+    pub connection_config: Option<Value>,
+}
+
+/// The configuration of a table, along with its connection profile (or an empty object if it
+/// doesn't have one)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PluginConfig {
+    pub profile: Value,
+    pub table: Value,
+}
+
+/// Passed to a plugin when a subtask of a source or sink starts
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OpenContext {
+    pub config: PluginConfig,
+    /// The schema of the table, without Arroyo's internal `_timestamp` column
+    pub schema: Schema,
+    pub job_id: String,
+    pub operator_id: String,
+    pub task_index: usize,
+    pub parallelism: usize,
+}
+
+pub enum SourcePoll {
+    /// A batch of rows, with columns matched to the table's by name. A batch may include a
+    /// `_timestamp` column of nanosecond timestamps to set the event time of its rows; otherwise
+    /// they're given the current time.
+    Batch(RecordBatch),
+    /// No data was available before the timeout
+    Idle,
+    /// The source has no more data, and the subtask should finish
+    Finished,
+}
+
+pub trait ConnectorPlugin: Send + Sync + 'static {
+    fn metadata(&self) -> PluginMetadata;
+
+    /// Validates a table's configuration when it's created. Tables created with SQL have all of
+    /// their options as strings, so this may also return a normalized version of the config,
+    /// which is what the source or sink is opened with.
+    fn validate(&self, config: PluginConfig) -> Result<PluginConfig, String> {
+        Ok(config)
+    }
+
+    /// Opens a subtask of a source, with the state it returned from its last
+    /// [`PluginSource::checkpoint`] if the pipeline is restoring from a checkpoint
+    #[allow(unused_variables)]
+    fn open_source(
+        &self,
+        ctx: OpenContext,
+        state: Option<Vec<u8>>,
+    ) -> Result<Box<dyn PluginSource>, String> {
+        Err("this connector does not support sources".to_string())
+    }
+
+    #[allow(unused_variables)]
+    fn open_sink(&self, ctx: OpenContext) -> Result<Box<dyn PluginSink>, String> {
+        Err("this connector does not support sinks".to_string())
+    }
+}
+
+/// A subtask of a source. Its methods are called from a blocking thread, and never concurrently.
+pub trait PluginSource: Send {
+    /// Returns the next batch of data, waiting at most `timeout` for one so that Arroyo can
+    /// handle checkpoints and stop requests in a timely way
+    fn poll(&mut self, timeout: Duration) -> Result<SourcePoll, String>;
+
+    /// Returns the state needed to resume reading after the last batch returned by `poll`, which
+    /// is stored in the checkpoint for `epoch`
+    fn checkpoint(&mut self, epoch: u32) -> Result<Vec<u8>, String>;
+
+    fn close(&mut self) {}
+}
+
+/// A subtask of a sink. Its methods are called from a blocking thread, and never concurrently.
+/// Batches are passed without Arroyo's internal `_timestamp` column.
+pub trait PluginSink: Send {
+    fn write(&mut self, batch: RecordBatch) -> Result<(), String>;
+
+    /// Called when a checkpoint is taken; once it returns, everything written before it must be
+    /// durable
+    fn flush(&mut self, epoch: u32) -> Result<(), String>;
+
+    fn close(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Exports a [`ConnectorPlugin`] from a plugin library. The expression is evaluated once, the
+/// first time Arroyo calls into the plugin.
+#[macro_export]
+macro_rules! export_connector {
+    ($plugin:expr) => {
+        fn __arroyo_plugin() -> &'static dyn $crate::ConnectorPlugin {
+            static PLUGIN: ::std::sync::OnceLock<Box<dyn $crate::ConnectorPlugin>> =
+                ::std::sync::OnceLock::new();
+            PLUGIN.get_or_init(|| Box::new($plugin)).as_ref()
+        }
+
+        #[no_mangle]
+        pub extern "C-unwind" fn __arroyo_connector_abi_version() -> u32 {
+            $crate::ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C-unwind" fn __arroyo_connector_metadata() -> $crate::ffi::FfiResult {
+            $crate::ffi::metadata(__arroyo_plugin())
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C-unwind" fn __arroyo_connector_validate(
+            config: $crate::ffi::FfiSlice,
+        ) -> $crate::ffi::FfiResult {
+            $crate::ffi::validate(__arroyo_plugin(), config)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C-unwind" fn __arroyo_source_open(
+            ctx: $crate::ffi::FfiSlice,
+            state: $crate::ffi::FfiSlice,
+            has_state: bool,
+        ) -> $crate::ffi::FfiHandle {
+            $crate::ffi::source_open(__arroyo_plugin(), ctx, state, has_state)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C-unwind" fn __arroyo_source_poll(
+            handle: *mut ::std::ffi::c_void,
+            timeout_micros: u64,
+        ) -> $crate::ffi::FfiPoll {
+            $crate::ffi::source_poll(handle, timeout_micros)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C-unwind" fn __arroyo_source_checkpoint(
+            handle: *mut ::std::ffi::c_void,
+            epoch: u32,
+        ) -> $crate::ffi::FfiResult {
+            $crate::ffi::source_checkpoint(handle, epoch)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C-unwind" fn __arroyo_source_close(handle: *mut ::std::ffi::c_void) {
+            $crate::ffi::source_close(handle)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C-unwind" fn __arroyo_sink_open(
+            ctx: $crate::ffi::FfiSlice,
+        ) -> $crate::ffi::FfiHandle {
+            $crate::ffi::sink_open(__arroyo_plugin(), ctx)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C-unwind" fn __arroyo_sink_write(
+            handle: *mut ::std::ffi::c_void,
+            batch: $crate::ffi::FfiBatch,
+        ) -> $crate::ffi::FfiResult {
+            $crate::ffi::sink_write(handle, batch)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C-unwind" fn __arroyo_sink_flush(
+            handle: *mut ::std::ffi::c_void,
+            epoch: u32,
+        ) -> $crate::ffi::FfiResult {
+            $crate::ffi::sink_flush(handle, epoch)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C-unwind" fn __arroyo_sink_close(
+            handle: *mut ::std::ffi::c_void,
+        ) -> $crate::ffi::FfiResult {
+            $crate::ffi::sink_close(handle)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C-unwind" fn __arroyo_free_bytes(bytes: $crate::ffi::FfiBytes) {
+            $crate::ffi::free_bytes(bytes)
+        }
+    };
+}
@@ -0,0 +1,189 @@
+mod operator;
+
+use anyhow::{anyhow, bail};
+use arroyo_operator::connector::{Connection, Connector};
+use arroyo_operator::operator::OperatorNode;
+use arroyo_rpc::api_types::connections::{
+    ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
+};
+use arroyo_rpc::OperatorConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use typify::import_types;
+
+use crate::audit::operator::AuditSinkFunc;
+use crate::EmptyConfig;
+
+const TABLE_SCHEMA: &str = include_str!("./table.json");
+const ICON: &str = include_str!("./audit.svg");
+
+import_types!(schema = "src/audit/table.json");
+
+const DEFAULT_PARTITION_FIELD: &str = "subtask_index";
+const DEFAULT_SEQUENCE_FIELD: &str = "counter";
+
+/// A sink that verifies that a pipeline delivers every record exactly once, by checking the
+/// per-subtask sequence numbers stamped by its source (like the impulse source's `subtask_index`
+/// and `counter`) for gaps and duplicates
+pub struct AuditConnector {}
+
+impl Connector for AuditConnector {
+    type ProfileT = EmptyConfig;
+    type TableT = AuditTable;
+
+    fn name(&self) -> &'static str {
+        "audit"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: self.name().to_string(),
+            name: "Audit".to_string(),
+            icon: ICON.to_string(),
+            description: "Verifies exactly-once delivery of sequence-numbered records".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: false,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn get_schema(
+        &self,
+        _: Self::ProfileT,
+        _: Self::TableT,
+        s: Option<&ConnectionSchema>,
+    ) -> Option<ConnectionSchema> {
+        s.cloned()
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: tokio::sync::mpsc::Sender<TestSourceMessage>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = TestSourceMessage {
+                error: false,
+                done: true,
+                message: "Successfully validated connection".to_string(),
+            };
+            tx.send(message).await.unwrap();
+        });
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        options: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+        _profile: Option<&ConnectionProfile>,
+    ) -> anyhow::Result<Connection> {
+        let fail_on_violation = options
+            .remove("fail_on_violation")
+            .map(|s| {
+                s.parse::<bool>()
+                    .map_err(|_| anyhow!("'fail_on_violation' must be either 'true' or 'false'"))
+            })
+            .transpose()?;
+
+        self.from_config(
+            None,
+            name,
+            EmptyConfig {},
+            AuditTable {
+                partition_field: options.remove("partition_field"),
+                sequence_field: options.remove("sequence_field"),
+                report_path: options.remove("report_path"),
+                fail_on_violation,
+            },
+            schema,
+        )
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        s: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let schema = s
+            .cloned()
+            .ok_or_else(|| anyhow!("no schema for audit sink"))?;
+
+        let partition_field = table
+            .partition_field
+            .as_deref()
+            .unwrap_or(DEFAULT_PARTITION_FIELD);
+        let sequence_field = table
+            .sequence_field
+            .as_deref()
+            .unwrap_or(DEFAULT_SEQUENCE_FIELD);
+
+        // the schema of a sink is usually inferred from its query, in which case the fields are
+        // checked when the operator starts
+        if !schema.fields.is_empty() {
+            for field in [partition_field, sequence_field] {
+                if !schema.fields.iter().any(|f| f.field_name == field) {
+                    bail!("audit sink input does not have a field '{}'", field);
+                }
+            }
+        }
+
+        let description = format!("Audit<{}, {}>", partition_field, sequence_field);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            filter: None,
+            delivery_semantics: None,
+            upsert_key: vec![],
+            partition_watermarks: None,
+            format: None,
+            bad_data: None,
+            framing: None,
+        };
+
+        Ok(Connection {
+            id,
+            connector: self.name(),
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn make_operator(
+        &self,
+        _: Self::ProfileT,
+        table: Self::TableT,
+        _: OperatorConfig,
+    ) -> anyhow::Result<OperatorNode> {
+        Ok(OperatorNode::from_operator(Box::new(AuditSinkFunc::new(
+            table
+                .partition_field
+                .unwrap_or_else(|| DEFAULT_PARTITION_FIELD.to_string()),
+            table
+                .sequence_field
+                .unwrap_or_else(|| DEFAULT_SEQUENCE_FIELD.to_string()),
+            table.report_path,
+            table.fail_on_violation.unwrap_or(false),
+        ))))
+    }
+}
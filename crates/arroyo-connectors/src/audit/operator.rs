@@ -0,0 +1,409 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::SystemTime;
+
+use arrow::array::{AsArray, RecordBatch};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, UInt64Type};
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
+use arroyo_rpc::grpc::TableConfig;
+use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
+use arroyo_storage::StorageProvider;
+use arroyo_types::{to_millis, CheckpointBarrier, SignalMessage};
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// The maximum number of gaps listed for each partition in a report
+const MAX_REPORTED_GAPS: usize = 100;
+
+/// The set of sequence numbers seen for a single partition
+#[derive(Debug, Clone, Default, Encode, Decode, PartialEq)]
+pub struct SequenceTracker {
+    // disjoint, non-adjacent ranges [start, end) of the sequence numbers that have been seen,
+    // keyed by start
+    seen: BTreeMap<u64, u64>,
+    count: u64,
+    duplicates: u64,
+}
+
+impl SequenceTracker {
+    /// Adds the range [start, end) to the seen set, returning how many of its sequence numbers
+    /// had already been seen
+    fn insert_range(&mut self, start: u64, end: u64) -> u64 {
+        let touching: Vec<(u64, u64)> = self
+            .seen
+            .range(..=end)
+            .rev()
+            .take_while(|(_, e)| **e >= start)
+            .map(|(s, e)| (*s, *e))
+            .collect();
+
+        let (mut new_start, mut new_end, mut overlap) = (start, end, 0);
+        for (s, e) in touching {
+            overlap += e.min(end).saturating_sub(s.max(start));
+            new_start = new_start.min(s);
+            new_end = new_end.max(e);
+            self.seen.remove(&s);
+        }
+        self.seen.insert(new_start, new_end);
+
+        overlap
+    }
+
+    pub fn observe(&mut self, sequence: u64) {
+        self.count += 1;
+        self.duplicates += self.insert_range(sequence, sequence + 1);
+    }
+
+    /// Combines the sequence numbers seen by another subtask into this one; anything seen by
+    /// both was delivered twice
+    pub fn merge(&mut self, other: &SequenceTracker) {
+        for (start, end) in &other.seen {
+            self.duplicates += self.insert_range(*start, *end);
+        }
+        self.count += other.count;
+        self.duplicates += other.duplicates;
+    }
+
+    /// The ranges of sequence numbers, inclusive, that are missing between the first and last
+    /// sequence numbers seen
+    pub fn gaps(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.seen
+            .values()
+            .zip(self.seen.keys().skip(1))
+            .map(|(end, next_start)| (*end, *next_start - 1))
+    }
+
+    pub fn missing(&self) -> u64 {
+        self.gaps().map(|(start, end)| end - start + 1).sum()
+    }
+}
+
+#[derive(Debug, Clone, Default, Encode, Decode)]
+pub struct AuditState {
+    partitions: HashMap<u64, SequenceTracker>,
+    restores: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PartitionReport {
+    partition: u64,
+    count: u64,
+    first: Option<u64>,
+    last: Option<u64>,
+    duplicates: u64,
+    missing: u64,
+    gaps: Vec<(u64, u64)>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditReport {
+    job_id: String,
+    operator_id: String,
+    task_index: usize,
+    time: u64,
+    restores: u32,
+    passed: bool,
+    duplicates: u64,
+    missing: u64,
+    partitions: Vec<PartitionReport>,
+}
+
+pub struct AuditSinkFunc {
+    partition_field: String,
+    sequence_field: String,
+    report_path: Option<String>,
+    fail_on_violation: bool,
+    storage: Option<StorageProvider>,
+    state: AuditState,
+    reported_violations: u64,
+}
+
+impl AuditSinkFunc {
+    pub fn new(
+        partition_field: String,
+        sequence_field: String,
+        report_path: Option<String>,
+        fail_on_violation: bool,
+    ) -> Self {
+        Self {
+            partition_field,
+            sequence_field,
+            report_path,
+            fail_on_violation,
+            storage: None,
+            state: AuditState::default(),
+            reported_violations: 0,
+        }
+    }
+
+    fn column(&self, batch: &RecordBatch, name: &str) -> Vec<Option<u64>> {
+        let column = batch.column_by_name(name).unwrap_or_else(|| {
+            panic!("audit sink input does not have a field '{}'", name);
+        });
+        let column = cast(column, &DataType::UInt64).unwrap_or_else(|e| {
+            panic!("audit sink field '{}' is not an integer: {:?}", name, e);
+        });
+        column.as_primitive::<UInt64Type>().iter().collect()
+    }
+
+    fn report(&self, ctx: &ArrowContext) -> AuditReport {
+        let mut partitions: Vec<_> = self
+            .state
+            .partitions
+            .iter()
+            .map(|(partition, tracker)| PartitionReport {
+                partition: *partition,
+                count: tracker.count,
+                first: tracker.seen.keys().next().copied(),
+                last: tracker.seen.values().next_back().map(|end| end - 1),
+                duplicates: tracker.duplicates,
+                missing: tracker.missing(),
+                gaps: tracker.gaps().take(MAX_REPORTED_GAPS).collect(),
+            })
+            .collect();
+        partitions.sort_by_key(|p| p.partition);
+
+        let duplicates = partitions.iter().map(|p| p.duplicates).sum();
+        let missing = partitions.iter().map(|p| p.missing).sum();
+
+        AuditReport {
+            job_id: ctx.task_info.job_id.clone(),
+            operator_id: ctx.task_info.operator_id.clone(),
+            task_index: ctx.task_info.task_index,
+            time: to_millis(SystemTime::now()),
+            restores: self.state.restores,
+            passed: duplicates == 0 && missing == 0,
+            duplicates,
+            missing,
+            partitions,
+        }
+    }
+
+    async fn check(&mut self, ctx: &mut ArrowContext) {
+        let report = self.report(ctx);
+
+        if let Some(storage) = &self.storage {
+            let path = format!(
+                "{}-{}.json",
+                ctx.task_info.operator_id, ctx.task_info.task_index
+            );
+            if let Err(e) = storage
+                .put(path, serde_json::to_vec_pretty(&report).unwrap())
+                .await
+            {
+                warn!("failed to write audit report: {:?}", e);
+            }
+        }
+
+        let violations = report.duplicates + report.missing;
+        if violations > self.reported_violations {
+            self.reported_violations = violations;
+            let message = format!(
+                "exactly-once violation: {} duplicate and {} missing records",
+                report.duplicates, report.missing
+            );
+            warn!(
+                message = message.as_str(),
+                operator_id = %ctx.task_info.operator_id,
+                task_index = ctx.task_info.task_index
+            );
+            ctx.report_error(
+                ErrorCategory::Connector,
+                message.clone(),
+                serde_json::to_string(&report.partitions).unwrap(),
+            )
+            .await;
+
+            if self.fail_on_violation {
+                panic!("{}", message);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ArrowOperator for AuditSinkFunc {
+    fn name(&self) -> String {
+        "AuditSink".to_string()
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        arroyo_state::global_table_config("a", "audit sink state")
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        if let Some(path) = &self.report_path {
+            self.storage = Some(
+                StorageProvider::for_url(path)
+                    .await
+                    .unwrap_or_else(|e| panic!("invalid audit report path {}: {:?}", path, e)),
+            );
+        }
+
+        let table: &mut GlobalKeyedView<usize, AuditState> = ctx
+            .table_manager
+            .get_global_keyed_state("a")
+            .await
+            .expect("should have table a in audit sink");
+
+        // after rescaling, the state of the previous subtasks is divided between the new ones
+        let mut restored = false;
+        for (task_index, state) in table.get_all() {
+            if task_index % ctx.task_info.parallelism != ctx.task_info.task_index {
+                continue;
+            }
+            for (partition, tracker) in &state.partitions {
+                self.state
+                    .partitions
+                    .entry(*partition)
+                    .or_default()
+                    .merge(tracker);
+            }
+            self.state.restores = self.state.restores.max(state.restores);
+            restored = true;
+        }
+
+        if restored {
+            self.state.restores += 1;
+            info!(
+                "audit sink {}-{} restored state for {} partitions",
+                ctx.task_info.operator_id,
+                ctx.task_info.task_index,
+                self.state.partitions.len()
+            );
+        }
+    }
+
+    async fn process_batch(&mut self, batch: RecordBatch, _: &mut ArrowContext) {
+        let partitions = self.column(&batch, &self.partition_field);
+        let sequences = self.column(&batch, &self.sequence_field);
+
+        for (partition, sequence) in partitions.into_iter().zip(sequences) {
+            if let (Some(partition), Some(sequence)) = (partition, sequence) {
+                self.state
+                    .partitions
+                    .entry(partition)
+                    .or_default()
+                    .observe(sequence);
+            }
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, ctx: &mut ArrowContext) {
+        ctx.table_manager
+            .get_global_keyed_state("a")
+            .await
+            .expect("should have table a in audit sink")
+            .insert(ctx.task_info.task_index, self.state.clone())
+            .await;
+
+        self.check(ctx).await;
+    }
+
+    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        self.check(ctx).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{TimestampNanosecondArray, UInt64Array};
+    use arrow::datatypes::{Field, Schema, TimeUnit};
+    use arroyo_operator::operator::OperatorNode;
+    use arroyo_operator_test::OperatorHarness;
+    use arroyo_rpc::df::ArroyoSchema;
+    use std::sync::Arc;
+
+    fn schema() -> Arc<Schema> {
+        Arc::new(Schema::new(vec![
+            Field::new("subtask_index", DataType::UInt64, false),
+            Field::new("counter", DataType::UInt64, false),
+            Field::new(
+                "_timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]))
+    }
+
+    fn batch(counters: &[u64]) -> RecordBatch {
+        RecordBatch::try_new(
+            schema(),
+            vec![
+                Arc::new(UInt64Array::from(vec![0; counters.len()])),
+                Arc::new(UInt64Array::from(counters.to_vec())),
+                Arc::new(TimestampNanosecondArray::from(vec![0; counters.len()])),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn sink() -> OperatorNode {
+        OperatorNode::from_operator(Box::new(AuditSinkFunc::new(
+            "subtask_index".to_string(),
+            "counter".to_string(),
+            None,
+            false,
+        )))
+    }
+
+    #[test]
+    fn test_sequence_tracker() {
+        let mut tracker = SequenceTracker::default();
+        for i in [0, 1, 2, 5, 6, 2, 3, 9] {
+            tracker.observe(i);
+        }
+
+        assert_eq!(tracker.count, 8);
+        assert_eq!(tracker.duplicates, 1);
+        assert_eq!(tracker.gaps().collect::<Vec<_>>(), vec![(4, 4), (7, 8)]);
+        assert_eq!(tracker.missing(), 3);
+
+        let mut other = SequenceTracker::default();
+        for i in [4, 6, 7, 8] {
+            other.observe(i);
+        }
+        tracker.merge(&other);
+
+        assert_eq!(tracker.count, 12);
+        assert_eq!(tracker.duplicates, 2);
+        assert_eq!(tracker.missing(), 0);
+        assert_eq!(tracker.seen, BTreeMap::from([(0, 10)]));
+    }
+
+    #[tokio::test]
+    async fn test_audit_sink_restore() {
+        let mut harness = OperatorHarness::new(arroyo_types::get_test_task_info())
+            .with_in_schema(ArroyoSchema::new_unkeyed(schema(), 2))
+            .start(sink())
+            .await;
+
+        harness.send_batch(0, batch(&[0, 1, 2])).await;
+        harness.checkpoint().await;
+
+        // lost when the sink fails, and so replayed by the source
+        harness.send_batch(0, batch(&[3, 4])).await;
+        harness.restart(sink()).await;
+
+        harness.send_batch(0, batch(&[3, 4, 4, 6])).await;
+        harness.checkpoint().await;
+
+        let state: HashMap<usize, AuditState> = harness.global_state("a").await;
+        let state = state.get(&0).unwrap();
+        assert_eq!(state.restores, 1);
+
+        let tracker = state.partitions.get(&0).unwrap();
+        assert_eq!(tracker.count, 7);
+        assert_eq!(tracker.duplicates, 1);
+        assert_eq!(tracker.gaps().collect::<Vec<_>>(), vec![(5, 5)]);
+
+        assert_eq!(harness.errors().len(), 1);
+    }
+}
@@ -1,13 +1,21 @@
-use arrow::array::RecordBatch;
+use arrow::array::{AsArray, RecordBatch};
+use arrow::compute::max;
+use arrow::datatypes::TimestampNanosecondType;
+use arroyo_metrics::sink_latency_histogram;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
+use arroyo_types::from_nanos;
 use async_trait::async_trait;
+use prometheus::Histogram;
+use std::time::SystemTime;
 
-pub struct BlackholeSinkFunc {}
+pub struct BlackholeSinkFunc {
+    latency: Option<Histogram>,
+}
 
 impl BlackholeSinkFunc {
     pub fn new() -> BlackholeSinkFunc {
-        BlackholeSinkFunc {}
+        BlackholeSinkFunc { latency: None }
     }
 }
 
@@ -17,7 +25,21 @@ impl ArrowOperator for BlackholeSinkFunc {
         "BlackholeSink".to_string()
     }
 
-    async fn process_batch(&mut self, _: RecordBatch, _: &mut ArrowContext) {
-        // no-op
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        self.latency = Some(sink_latency_histogram(&ctx.task_info));
+    }
+
+    async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
+        // the data is dropped, but how long it took to get here is recorded so that the blackhole
+        // can be used to measure the latency of a pipeline
+        let timestamps = batch
+            .column(ctx.in_schemas[0].timestamp_index)
+            .as_primitive::<TimestampNanosecondType>();
+
+        if let (Some(latency), Some(latest)) = (&self.latency, max(timestamps)) {
+            let latest = from_nanos(latest.max(0) as u128);
+            let elapsed = SystemTime::now().duration_since(latest).unwrap_or_default();
+            latency.observe(elapsed.as_secs_f64());
+        }
     }
 }
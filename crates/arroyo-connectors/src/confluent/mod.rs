@@ -9,7 +9,7 @@ use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
 };
 use arroyo_rpc::var_str::VarStr;
-use arroyo_rpc::OperatorConfig;
+use arroyo_rpc::{DeliverySemantics, OperatorConfig};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tokio::sync::mpsc::Sender;
@@ -124,6 +124,22 @@ impl Connector for ConfluentConnector {
         }
     }
 
+    fn supports_upserts(&self) -> bool {
+        true
+    }
+
+    fn supports_delivery_semantics(&self, _: DeliverySemantics) -> bool {
+        true
+    }
+
+    fn supports_partitioning_expressions(&self) -> bool {
+        true
+    }
+
+    fn supports_partition_watermarks(&self) -> bool {
+        true
+    }
+
     fn get_autocomplete(
         &self,
         profile: Self::ProfileT,
@@ -6,7 +6,7 @@ use std::collections::HashMap;
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
 };
-use arroyo_rpc::OperatorConfig;
+use arroyo_rpc::{DeliverySemantics, OperatorConfig};
 
 use crate::filesystem::{
     file_system_sink_from_options, CommitStyle, FileSystemTable, FormatSettings, TableType,
@@ -70,6 +70,10 @@ impl Connector for DeltaLakeConnector {
         ConnectionType::Source
     }
 
+    fn supports_delivery_semantics(&self, semantics: DeliverySemantics) -> bool {
+        semantics == DeliverySemantics::ExactlyOnce
+    }
+
     fn from_config(
         &self,
         id: Option<i64>,
@@ -120,6 +124,10 @@ impl Connector for DeltaLakeConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            filter: None,
+            delivery_semantics: None,
+            upsert_key: vec![],
+            partition_watermarks: None,
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
@@ -174,6 +182,13 @@ impl Connector for DeltaLakeConnector {
             bail!("commit_style must be DeltaLake");
         }
 
+        if !matches!(
+            config.delivery_semantics,
+            None | Some(DeliverySemantics::ExactlyOnce)
+        ) {
+            bail!("Delta Lake sinks only support exactly-once delivery");
+        }
+
         let backend_config = BackendConfig::parse_url(write_path, true)?;
         let is_local = backend_config.is_local();
         match (&format_settings, is_local) {
@@ -1,4 +1,5 @@
 pub mod delta;
+mod notifications;
 mod sink;
 mod source;
 
@@ -13,7 +14,9 @@ use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
 };
 use arroyo_rpc::formats::Format;
-use arroyo_rpc::OperatorConfig;
+use arroyo_rpc::{DeliverySemantics, OperatorConfig};
+use datafusion::logical_expr::Expr;
+use datafusion_proto::bytes::Serializeable;
 use serde::{Deserialize, Serialize};
 
 use crate::{pull_opt, pull_option_to_i64, EmptyConfig};
@@ -84,6 +87,10 @@ impl Connector for FileSystemConnector {
         }
     }
 
+    fn supports_delivery_semantics(&self, _: DeliverySemantics) -> bool {
+        true
+    }
+
     fn from_config(
         &self,
         id: Option<i64>,
@@ -142,6 +149,10 @@ impl Connector for FileSystemConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            filter: None,
+            delivery_semantics: None,
+            upsert_key: vec![],
+            partition_watermarks: None,
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
@@ -174,6 +185,19 @@ impl Connector for FileSystemConnector {
                     .transpose()?
                     .unwrap_or(CompressionFormat::None);
                 let matching_pattern = options.remove("source.regex-pattern");
+                let sqs_queue_url = options.remove("source.sqs_queue_url");
+                let sqs_region = options.remove("source.sqs_region");
+                if sqs_queue_url.is_some()
+                    && !matches!(
+                        BackendConfig::parse_url(&storage_url, true)?,
+                        BackendConfig::S3(_)
+                    )
+                {
+                    bail!("source.sqs_queue_url can only be used with an S3 path");
+                }
+                if sqs_region.is_some() && sqs_queue_url.is_none() {
+                    bail!("source.sqs_region requires source.sqs_queue_url to be set");
+                }
                 self.from_config(
                     None,
                     name,
@@ -184,6 +208,8 @@ impl Connector for FileSystemConnector {
                             storage_options,
                             compression_format: Some(compression_format),
                             regex_pattern: matching_pattern,
+                            sqs_queue_url,
+                            sqs_region,
                         },
                     },
                     schema,
@@ -214,7 +240,12 @@ impl Connector for FileSystemConnector {
                         .ok_or_else(|| anyhow!("format required for FileSystem source"))?,
                     framing: config.framing.clone(),
                     bad_data: config.bad_data.clone(),
+                    filter: config
+                        .filter_bytes()?
+                        .map(|filter| Expr::from_bytes(&filter))
+                        .transpose()?,
                     file_states: HashMap::new(),
+                    notifications: None,
                 })))
             }
             TableType::Sink {
@@ -327,6 +358,9 @@ pub fn file_system_sink_from_options(
         "filesystem sink requires a format, such as json or parquet"
     ))? {
         Format::Parquet(..) => {
+            if opts.contains_key("json_compression") {
+                bail!("json_compression only applies to json files; parquet files are compressed with parquet_compression");
+            }
             let compression = opts
                 .remove("parquet_compression")
                 .map(|value| {
@@ -337,15 +371,65 @@ pub fn file_system_sink_from_options(
                 .transpose()?;
             let row_batch_size = pull_option_to_i64("parquet_row_batch_size", opts)?;
             let row_group_size = pull_option_to_i64("parquet_row_group_size", opts)?;
+            let compression_level = pull_option_to_i64("parquet_compression_level", opts)?;
+            sink::parquet::parquet_compression(compression, compression_level)?;
+            let dictionary_enabled = opts
+                .remove("parquet_dictionary_enabled")
+                .map(|value| {
+                    value.parse::<bool>().map_err(|_| {
+                        anyhow!(
+                            "{} is not a valid parquet_dictionary_enabled argument",
+                            value
+                        )
+                    })
+                })
+                .transpose()?;
+
+            let field_list =
+                |option: &str, opts: &mut HashMap<String, String>| -> Result<Vec<String>> {
+                    let fields: Vec<String> = opts
+                        .remove(option)
+                        .map(|fields| fields.split(',').map(|f| f.trim().to_string()).collect())
+                        .unwrap_or_default();
+                    if let Some(field) = fields
+                        .iter()
+                        .find(|f| !schema.unwrap().fields.iter().any(|sf| &sf.field_name == *f))
+                    {
+                        bail!(
+                            "{} refers to '{}', which is not a field of the table",
+                            option,
+                            field
+                        );
+                    }
+                    Ok(fields)
+                };
+            let sort_by = field_list("parquet_sort_by", opts)?;
+            let bloom_filter_columns = field_list("parquet_bloom_filter_columns", opts)?;
+
             Some(FormatSettings::Parquet {
                 compression,
                 row_batch_size,
                 row_group_size,
+                compression_level,
+                dictionary_enabled,
+                sort_by,
+                bloom_filter_columns,
+            })
+        }
+        Format::Json(..) => {
+            let compression = opts
+                .remove("json_compression")
+                .map(|value| {
+                    JsonCompression::try_from(&value).map_err(|_err| {
+                        anyhow!("{} is not a valid json_compression argument", value)
+                    })
+                })
+                .transpose()?;
+            Some(FormatSettings::Json {
+                json_format: JsonFormat::Json,
+                compression,
             })
         }
-        Format::Json(..) => Some(FormatSettings::Json {
-            json_format: JsonFormat::Json,
-        }),
         other => bail!("Unsupported format: {:?}", other),
     };
     Ok(FileSystemTable {
@@ -0,0 +1,270 @@
+//! Discovery of new files from S3 event notifications, as an alternative to listing the source
+//! path, which becomes impractical for buckets with many objects.
+//!
+//! The bucket is configured to send `s3:ObjectCreated:*` notifications to an SQS queue, either
+//! directly or through an SNS topic. Each subtask of the source polls the queue, reads the objects
+//! that were created, and deletes the messages once a checkpoint containing those objects has
+//! completed. Messages that are received but never deleted (for example because the pipeline failed
+//! before it could checkpoint) become visible again after the queue's visibility timeout, so the
+//! timeout should be longer than two checkpoint intervals.
+
+use aws_config::from_env;
+use aws_sdk_sqs::model::DeleteMessageBatchRequestEntry;
+use aws_sdk_sqs::{Client as SqsClient, Region};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use arroyo_types::UserError;
+
+// SQS limits on the number of messages per receive and delete call
+const MAX_BATCH_SIZE: usize = 10;
+const WAIT_TIME_SECONDS: i32 = 5;
+
+/// An object that was created under the source path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatedObject {
+    pub bucket: String,
+    pub key: String,
+}
+
+/// A message received from the queue, along with the objects it reported
+#[derive(Debug)]
+pub struct Notification {
+    pub receipt_handle: String,
+    pub objects: Vec<CreatedObject>,
+}
+
+#[derive(Deserialize)]
+struct SnsEnvelope {
+    #[serde(rename = "Type")]
+    message_type: String,
+    #[serde(rename = "Message")]
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct S3Event {
+    #[serde(rename = "Records", default)]
+    records: Vec<S3EventRecord>,
+}
+
+#[derive(Deserialize)]
+struct S3EventRecord {
+    #[serde(rename = "eventName")]
+    event_name: String,
+    s3: S3Entity,
+}
+
+#[derive(Deserialize)]
+struct S3Entity {
+    bucket: S3Bucket,
+    object: S3Object,
+}
+
+#[derive(Deserialize)]
+struct S3Bucket {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct S3Object {
+    key: String,
+}
+
+/// Returns the objects created according to an S3 event notification, which may be wrapped in an
+/// SNS notification. Other events (like the test event S3 sends when notifications are
+/// configured) contain no created objects.
+pub fn parse_notification(body: &str) -> anyhow::Result<Vec<CreatedObject>> {
+    let body = match serde_json::from_str::<SnsEnvelope>(body) {
+        Ok(envelope) if envelope.message_type == "Notification" => envelope.message,
+        _ => body.to_string(),
+    };
+
+    let event: S3Event = serde_json::from_str(&body)?;
+
+    event
+        .records
+        .into_iter()
+        .filter(|record| record.event_name.starts_with("ObjectCreated:"))
+        .map(|record| {
+            Ok(CreatedObject {
+                bucket: record.s3.bucket.name,
+                key: decode_key(&record.s3.object.key)?,
+            })
+        })
+        .collect()
+}
+
+/// Object keys in S3 notifications are form-urlencoded, with spaces encoded as '+'
+fn decode_key(key: &str) -> anyhow::Result<String> {
+    Ok(percent_decode_str(&key.replace('+', " "))
+        .decode_utf8()?
+        .into_owned())
+}
+
+pub struct NotificationQueue {
+    client: SqsClient,
+    queue_url: String,
+    // messages whose objects have been fully read since the last checkpoint
+    processed: Vec<String>,
+    // messages whose objects are recorded as finished in the last checkpoint
+    checkpointed: Vec<String>,
+}
+
+impl NotificationQueue {
+    pub async fn new(queue_url: String, region: Option<String>) -> Self {
+        let mut loader = from_env();
+        if let Some(region) = region {
+            loader = loader.region(Region::new(region));
+        }
+
+        Self {
+            client: SqsClient::new(&loader.load().await),
+            queue_url,
+            processed: vec![],
+            checkpointed: vec![],
+        }
+    }
+
+    /// Waits for the next messages on the queue. Messages that can't be parsed are logged and
+    /// returned with no objects, so that they are deleted rather than redelivered forever.
+    pub async fn receive(&self) -> Result<Vec<Notification>, UserError> {
+        let output = self
+            .client
+            .receive_message()
+            .queue_url(&self.queue_url)
+            .max_number_of_messages(MAX_BATCH_SIZE as i32)
+            .wait_time_seconds(WAIT_TIME_SECONDS)
+            .send()
+            .await
+            .map_err(|e| {
+                UserError::new(
+                    "failed to receive from SQS queue",
+                    format!("{}: {:?}", self.queue_url, e),
+                )
+            })?;
+
+        Ok(output
+            .messages()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|message| {
+                let receipt_handle = message.receipt_handle()?.to_string();
+                let objects = match parse_notification(message.body().unwrap_or_default()) {
+                    Ok(objects) => objects,
+                    Err(e) => {
+                        warn!(
+                            "ignoring message {:?} on {} that is not an S3 event notification: {:?}",
+                            message.message_id(),
+                            self.queue_url,
+                            e
+                        );
+                        vec![]
+                    }
+                };
+                Some(Notification {
+                    receipt_handle,
+                    objects,
+                })
+            })
+            .collect())
+    }
+
+    /// Marks a message as processed, so that it's deleted once the next checkpoint completes
+    pub fn processed(&mut self, notification: Notification) {
+        self.processed.push(notification.receipt_handle);
+    }
+
+    /// Called when a checkpoint starts. Sources aren't told when a checkpoint completes, but a new
+    /// one only starts after the previous has, so this deletes the messages recorded in the
+    /// previous checkpoint and holds on to those processed since until the next.
+    pub async fn checkpoint(&mut self) -> Result<(), UserError> {
+        let pending =
+            std::mem::replace(&mut self.checkpointed, std::mem::take(&mut self.processed));
+        for chunk in pending.chunks(MAX_BATCH_SIZE) {
+            let entries = chunk
+                .iter()
+                .enumerate()
+                .map(|(i, handle)| {
+                    DeleteMessageBatchRequestEntry::builder()
+                        .id(i.to_string())
+                        .receipt_handle(handle)
+                        .build()
+                })
+                .collect();
+
+            let output = self
+                .client
+                .delete_message_batch()
+                .queue_url(&self.queue_url)
+                .set_entries(Some(entries))
+                .send()
+                .await
+                .map_err(|e| {
+                    UserError::new(
+                        "failed to delete messages from SQS queue",
+                        format!("{}: {:?}", self.queue_url, e),
+                    )
+                })?;
+
+            // failed deletes are redelivered, and skipped because their objects are finished
+            if let Some(failed) = output.failed().filter(|f| !f.is_empty()) {
+                warn!(
+                    "failed to delete {} messages from {}",
+                    failed.len(),
+                    self.queue_url
+                );
+            }
+        }
+        debug!("deleted {} processed messages", pending.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_s3_event() {
+        let body = r#"{"Records":[
+            {"eventName":"ObjectCreated:Put","s3":{"bucket":{"name":"my-bucket"},"object":{"key":"data/2024/file+one%3D1.json","size":10}}},
+            {"eventName":"ObjectRemoved:Delete","s3":{"bucket":{"name":"my-bucket"},"object":{"key":"data/old.json"}}}
+        ]}"#;
+
+        assert_eq!(
+            parse_notification(body).unwrap(),
+            vec![CreatedObject {
+                bucket: "my-bucket".to_string(),
+                key: "data/2024/file one=1.json".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_sns_wrapped_event() {
+        let event = r#"{"Records":[{"eventName":"ObjectCreated:CompleteMultipartUpload","s3":{"bucket":{"name":"b"},"object":{"key":"a.parquet"}}}]}"#;
+        let body = serde_json::json!({
+            "Type": "Notification",
+            "MessageId": "1",
+            "Message": event,
+        })
+        .to_string();
+
+        assert_eq!(
+            parse_notification(&body).unwrap(),
+            vec![CreatedObject {
+                bucket: "b".to_string(),
+                key: "a.parquet".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_test_event() {
+        let body = r#"{"Service":"Amazon S3","Event":"s3:TestEvent","Bucket":"b"}"#;
+        assert!(parse_notification(body).unwrap().is_empty());
+        assert!(parse_notification("not json").is_err());
+    }
+}
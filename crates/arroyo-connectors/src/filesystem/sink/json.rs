@@ -3,16 +3,69 @@ use std::{fs::File, io::Write, time::Instant};
 use arrow::record_batch::RecordBatch;
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_rpc::{df::ArroyoSchemaRef, formats::Format};
+use flate2::write::GzEncoder;
 
 use super::{
     local::{CurrentFileRecovery, LocalWriter},
     parquet::representitive_timestamp,
-    BatchBufferingWriter, FileSettings, MultiPartWriterStats, TableType,
+    BatchBufferingWriter, FileSettings, FileSystemTable, MultiPartWriterStats, TableType,
 };
+use crate::filesystem::{FormatSettings, JsonCompression};
+
+/// The compression codec configured for JSON files
+pub(crate) fn json_compression(config: &FileSystemTable) -> JsonCompression {
+    match &config.table_type {
+        TableType::Sink {
+            format_settings:
+                Some(FormatSettings::Json {
+                    compression: Some(compression),
+                    ..
+                }),
+            ..
+        } => *compression,
+        _ => JsonCompression::None,
+    }
+}
+
+/// The extension appended to the default file suffix for the configured compression
+pub(crate) fn compression_suffix(config: &FileSystemTable) -> &'static str {
+    match json_compression(config) {
+        JsonCompression::None => "",
+        JsonCompression::Gzip => ".gz",
+        JsonCompression::Zstd => ".zst",
+    }
+}
+
+/// Serializes a batch as newline-delimited JSON, compressing it as a self-contained gzip member
+/// or zstd frame. Concatenated members (and frames) decompress as a single stream, so each batch
+/// can be appended to a file as soon as it's written, and files end on a valid boundary at every
+/// checkpoint.
+fn serialize_batch(
+    serializer: &mut ArrowSerializer,
+    compression: JsonCompression,
+    batch: &RecordBatch,
+) -> anyhow::Result<Vec<u8>> {
+    let mut data = vec![];
+    for row in serializer.serialize(batch) {
+        data.extend(row);
+        data.extend(b"\n");
+    }
+
+    Ok(match compression {
+        JsonCompression::None => data,
+        JsonCompression::Gzip => {
+            let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(&data)?;
+            encoder.finish()?
+        }
+        JsonCompression::Zstd => zstd::encode_all(data.as_slice(), 0)?,
+    })
+}
 
 pub struct JsonWriter {
     current_buffer: Vec<u8>,
     serializer: ArrowSerializer,
+    compression: JsonCompression,
     target_part_size: usize,
 }
 
@@ -38,6 +91,7 @@ impl BatchBufferingWriter for JsonWriter {
         Self {
             current_buffer: Vec::new(),
             serializer: ArrowSerializer::new(format.expect("should have format")),
+            compression: json_compression(config),
             target_part_size,
         }
     }
@@ -46,10 +100,9 @@ impl BatchBufferingWriter for JsonWriter {
     }
 
     fn add_batch_data(&mut self, batch: RecordBatch) -> Option<Vec<u8>> {
-        for k in self.serializer.serialize(&batch) {
-            self.current_buffer.extend(k);
-            self.current_buffer.extend(b"\n");
-        }
+        let data = serialize_batch(&mut self.serializer, self.compression, &batch)
+            .expect("failed to compress JSON");
+        self.current_buffer.extend(data);
         if self.buffer_length() > self.target_part_size {
             Some(self.evict_current_buffer())
         } else {
@@ -92,6 +145,7 @@ pub struct JsonLocalWriter {
     final_path: String,
     file: File,
     serializer: ArrowSerializer,
+    compression: JsonCompression,
     stats: Option<MultiPartWriterStats>,
     schema: ArroyoSchemaRef,
 }
@@ -100,7 +154,7 @@ impl LocalWriter for JsonLocalWriter {
     fn new(
         tmp_path: String,
         final_path: String,
-        _table_properties: &super::FileSystemTable,
+        table_properties: &super::FileSystemTable,
         format: Option<Format>,
         schema: ArroyoSchemaRef,
     ) -> Self {
@@ -109,6 +163,7 @@ impl LocalWriter for JsonLocalWriter {
             tmp_path,
             final_path,
             serializer: ArrowSerializer::new(format.expect("should have format")),
+            compression: json_compression(table_properties),
             file,
             stats: None,
             schema,
@@ -133,10 +188,8 @@ impl LocalWriter for JsonLocalWriter {
         } else {
             self.stats.as_mut().unwrap().last_write_at = Instant::now();
         }
-        for data in self.serializer.serialize(&batch) {
-            self.file.write_all(data.as_slice())?;
-            self.file.write_all(b"\n")?;
-        }
+        let data = serialize_batch(&mut self.serializer, self.compression, &batch)?;
+        self.file.write_all(&data)?;
         Ok(())
     }
 
@@ -173,3 +226,77 @@ impl LocalWriter for JsonLocalWriter {
         self.stats.clone().unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::UInt64Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arroyo_rpc::formats::JsonFormat;
+    use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+    use std::sync::Arc;
+    use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+
+    fn batch(values: impl IntoIterator<Item = u64>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("v", DataType::UInt64, false)]));
+        RecordBatch::try_new(
+            schema,
+            vec![Arc::new(UInt64Array::from_iter_values(values))],
+        )
+        .unwrap()
+    }
+
+    /// Writes two batches the way the sink appends them to a file, then reads the file back the
+    /// way the filesystem source does
+    async fn round_trip(compression: JsonCompression) -> String {
+        let mut serializer = ArrowSerializer::new(Format::Json(JsonFormat::default()));
+        let mut file = vec![];
+        for batch in [batch(1..=2), batch([3])] {
+            file.extend(serialize_batch(&mut serializer, compression, &batch).unwrap());
+        }
+
+        let mut reader: Box<dyn AsyncRead + Unpin + Send> = match compression {
+            JsonCompression::None => Box::new(BufReader::new(file.as_slice())),
+            JsonCompression::Gzip => {
+                let mut decoder = GzipDecoder::new(BufReader::new(file.as_slice()));
+                decoder.multiple_members(true);
+                Box::new(decoder)
+            }
+            JsonCompression::Zstd => {
+                let mut decoder = ZstdDecoder::new(BufReader::new(file.as_slice()));
+                decoder.multiple_members(true);
+                Box::new(decoder)
+            }
+        };
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents).await.unwrap();
+        contents
+    }
+
+    #[tokio::test]
+    async fn test_compressed_batches_read_as_one_file() {
+        let expected = "{\"v\":1}\n{\"v\":2}\n{\"v\":3}\n";
+        for compression in [
+            JsonCompression::None,
+            JsonCompression::Gzip,
+            JsonCompression::Zstd,
+        ] {
+            assert_eq!(round_trip(compression).await, expected, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn test_batches_are_compressed() {
+        let mut serializer = ArrowSerializer::new(Format::Json(JsonFormat::default()));
+        let batch = batch(0..1000);
+        let plain = serialize_batch(&mut serializer, JsonCompression::None, &batch).unwrap();
+
+        let gzip = serialize_batch(&mut serializer, JsonCompression::Gzip, &batch).unwrap();
+        assert_eq!(&gzip[..2], &[0x1f, 0x8b]);
+        assert!(gzip.len() < plain.len());
+
+        let zstd = serialize_batch(&mut serializer, JsonCompression::Zstd, &batch).unwrap();
+        assert_eq!(&zstd[..4], &[0x28, 0xb5, 0x2f, 0xfd]);
+        assert!(zstd.len() < plain.len());
+    }
+}
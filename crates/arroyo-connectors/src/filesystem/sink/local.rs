@@ -16,14 +16,14 @@ use tokio::{fs::OpenOptions, io::AsyncWriteExt};
 use tracing::info;
 use uuid::Uuid;
 
-use crate::filesystem::{sink::two_phase_committer::TwoPhaseCommitter, FileSettings};
+use crate::filesystem::FileSettings;
+use crate::two_phase_committer::{TwoPhaseCommitter, TwoPhaseCommitterOperator};
 use anyhow::{bail, Result};
 
 use super::{
-    add_suffix_prefix, delta, get_partitioner_from_file_settings, parquet::batches_by_partition,
-    two_phase_committer::TwoPhaseCommitterOperator, CommitState, CommitStyle, FileNaming,
-    FileSystemTable, FilenameStrategy, FinishedFile, MultiPartWriterStats, RollingPolicy,
-    TableType,
+    add_suffix_prefix, delta, get_partitioner_from_file_settings, json,
+    parquet::batches_by_partition, CommitState, CommitStyle, FileNaming, FileSystemTable,
+    FilenameStrategy, FinishedFile, MultiPartWriterStats, RollingPolicy, TableType,
 };
 
 pub struct LocalFileSystemWriter<V: LocalWriter> {
@@ -80,7 +80,11 @@ impl<V: LocalWriter> LocalFileSystemWriter<V> {
             });
 
         if filenaming.suffix.is_none() {
-            filenaming.suffix = Some(V::file_suffix().to_string());
+            filenaming.suffix = Some(format!(
+                "{}{}",
+                V::file_suffix(),
+                json::compression_suffix(&table_properties)
+            ));
         }
 
         let writer = Self {
@@ -100,6 +104,7 @@ impl<V: LocalWriter> LocalFileSystemWriter<V> {
             filenaming,
         };
         TwoPhaseCommitterOperator::new(writer)
+            .with_delivery_semantics(config.delivery_semantics.unwrap_or_default())
     }
 
     fn init_schema_and_partitioner(&mut self, record_batch: &RecordBatch) -> Result<()> {
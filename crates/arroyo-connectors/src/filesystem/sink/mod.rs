@@ -48,7 +48,6 @@ mod delta;
 pub mod json;
 pub mod local;
 pub mod parquet;
-mod two_phase_committer;
 
 use self::{
     json::{JsonLocalWriter, JsonWriter},
@@ -62,7 +61,7 @@ use self::{
 use crate::filesystem::{
     CommitStyle, FileNaming, FileSettings, FileSystemTable, FilenameStrategy, TableType,
 };
-use two_phase_committer::{CommitStrategy, TwoPhaseCommitter, TwoPhaseCommitterOperator};
+use crate::two_phase_committer::{CommitStrategy, TwoPhaseCommitter, TwoPhaseCommitterOperator};
 
 pub struct FileSystemSink<R: MultiPartWriter + Send + 'static> {
     sender: Option<Sender<FileSystemMessages>>,
@@ -110,6 +109,7 @@ impl<R: MultiPartWriter + Send + 'static> FileSystemSink<R> {
         config: OperatorConfig,
     ) -> TwoPhaseCommitterOperator<Self> {
         Self::create_and_start(table_properties, config.format)
+            .with_delivery_semantics(config.delivery_semantics.unwrap_or_default())
     }
 
     pub fn start(&mut self, schema: ArroyoSchemaRef) -> Result<()> {
@@ -736,7 +736,11 @@ where
             suffix: None,
         });
         if file_naming.suffix.is_none() {
-            file_naming.suffix = Some(R::suffix());
+            file_naming.suffix = Some(format!(
+                "{}{}",
+                R::suffix(),
+                json::compression_suffix(&writer_properties)
+            ));
         }
 
         Self {
@@ -6,10 +6,11 @@ use std::{
 };
 
 use crate::filesystem::{Compression, FormatSettings};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use arrow::{
     array::{Array, RecordBatch, StringArray, TimestampNanosecondArray},
-    compute::{sort_to_indices, take},
+    compute::{concat_batches, lexsort_to_indices, sort_to_indices, take, SortColumn},
+    datatypes::Schema,
 };
 use arroyo_rpc::{df::ArroyoSchemaRef, formats::Format};
 use arroyo_types::from_nanos;
@@ -17,7 +18,9 @@ use datafusion::physical_plan::PhysicalExpr;
 use parquet::{
     arrow::ArrowWriter,
     basic::{GzipLevel, ZstdLevel},
-    file::properties::WriterProperties,
+    file::properties::{WriterProperties, DEFAULT_MAX_ROW_GROUP_SIZE},
+    format::SortingColumn,
+    schema::types::ColumnPath,
 };
 
 use super::{
@@ -25,36 +28,208 @@ use super::{
     BatchBufferingWriter, FileSettings, FileSystemTable, MultiPartWriterStats, TableType,
 };
 
-fn writer_properties_from_table(table: &FileSystemTable) -> WriterProperties {
+/// The parquet compression for the sink's compression and compression level settings
+pub(crate) fn parquet_compression(
+    compression: Option<Compression>,
+    level: Option<i64>,
+) -> Result<Option<parquet::basic::Compression>> {
+    let Some(compression) = compression else {
+        if level.is_some() {
+            return Err(anyhow!(
+                "parquet_compression_level requires parquet_compression to be set"
+            ));
+        }
+        return Ok(None);
+    };
+
+    Ok(Some(match (compression, level) {
+        (Compression::Gzip, level) => parquet::basic::Compression::GZIP(
+            level
+                .map(|l| GzipLevel::try_new(l as u32))
+                .transpose()?
+                .unwrap_or_default(),
+        ),
+        (Compression::Zstd, level) => parquet::basic::Compression::ZSTD(
+            level
+                .map(|l| ZstdLevel::try_new(l as i32))
+                .transpose()?
+                .unwrap_or_default(),
+        ),
+        (_, Some(_)) => {
+            return Err(anyhow!(
+                "parquet_compression_level is only supported for gzip and zstd compression"
+            ));
+        }
+        (Compression::None, None) => parquet::basic::Compression::UNCOMPRESSED,
+        (Compression::Snappy, None) => parquet::basic::Compression::SNAPPY,
+        (Compression::Lz4, None) => parquet::basic::Compression::LZ4,
+    }))
+}
+
+fn parquet_settings(table: &FileSystemTable) -> Option<&FormatSettings> {
+    match &table.table_type {
+        TableType::Sink {
+            format_settings: Some(settings @ FormatSettings::Parquet { .. }),
+            ..
+        } => Some(settings),
+        _ => None,
+    }
+}
+
+fn writer_properties_from_table(table: &FileSystemTable, schema: &Schema) -> WriterProperties {
     let mut parquet_writer_options = WriterProperties::builder();
-    if let TableType::Sink {
-        format_settings:
-            Some(FormatSettings::Parquet {
-                compression,
-                row_batch_size: _,
-                row_group_size,
-            }),
-        ..
-    } = table.table_type
+    if let Some(FormatSettings::Parquet {
+        compression,
+        row_batch_size: _,
+        row_group_size,
+        compression_level,
+        dictionary_enabled,
+        sort_by,
+        bloom_filter_columns,
+    }) = parquet_settings(table)
     {
-        if let Some(compression) = compression {
-            let compression = match compression {
-                Compression::None => parquet::basic::Compression::UNCOMPRESSED,
-                Compression::Snappy => parquet::basic::Compression::SNAPPY,
-                Compression::Gzip => parquet::basic::Compression::GZIP(GzipLevel::default()),
-                Compression::Zstd => parquet::basic::Compression::ZSTD(ZstdLevel::default()),
-                Compression::Lz4 => parquet::basic::Compression::LZ4,
-            };
+        if let Some(compression) = parquet_compression(*compression, *compression_level)
+            .expect("invalid parquet compression settings")
+        {
             parquet_writer_options = parquet_writer_options.set_compression(compression);
         }
         if let Some(row_group_size) = row_group_size {
             parquet_writer_options =
-                parquet_writer_options.set_max_row_group_size(row_group_size as usize);
+                parquet_writer_options.set_max_row_group_size(*row_group_size as usize);
+        }
+        if let Some(dictionary_enabled) = dictionary_enabled {
+            parquet_writer_options =
+                parquet_writer_options.set_dictionary_enabled(*dictionary_enabled);
+        }
+        if !sort_by.is_empty() {
+            let sorting_columns = sort_by
+                .iter()
+                .map(|column| SortingColumn {
+                    column_idx: schema.index_of(column).expect("sort column not in schema") as i32,
+                    descending: false,
+                    nulls_first: true,
+                })
+                .collect();
+            parquet_writer_options =
+                parquet_writer_options.set_sorting_columns(Some(sorting_columns));
+        }
+        for column in bloom_filter_columns {
+            parquet_writer_options = parquet_writer_options
+                .set_column_bloom_filter_enabled(ColumnPath::from(column.as_str()), true);
         }
     }
     parquet_writer_options.build()
 }
 
+/// Buffers rows until there are enough to fill a row group, then sorts them by the sink's sort-by
+/// columns, so that each row group written is sorted as declared in the file's metadata
+struct RowGroupSorter {
+    sort_columns: Vec<usize>,
+    row_group_size: usize,
+    batches: Vec<RecordBatch>,
+    rows: usize,
+}
+
+impl RowGroupSorter {
+    fn new(table: &FileSystemTable, schema: &Schema) -> Option<Self> {
+        let Some(FormatSettings::Parquet {
+            row_group_size,
+            sort_by,
+            ..
+        }) = parquet_settings(table)
+        else {
+            return None;
+        };
+        if sort_by.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            sort_columns: sort_by
+                .iter()
+                .map(|column| schema.index_of(column).expect("sort column not in schema"))
+                .collect(),
+            row_group_size: row_group_size
+                .map(|size| size as usize)
+                .unwrap_or(DEFAULT_MAX_ROW_GROUP_SIZE),
+            batches: vec![],
+            rows: 0,
+        })
+    }
+
+    /// Adds a batch, returning a sorted row group once enough rows have been buffered
+    fn push(&mut self, batch: RecordBatch) -> Option<RecordBatch> {
+        self.rows += batch.num_rows();
+        self.batches.push(batch);
+        if self.rows >= self.row_group_size {
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    /// Sorts and returns all of the buffered rows
+    fn take(&mut self) -> Option<RecordBatch> {
+        if self.batches.is_empty() {
+            return None;
+        }
+        let batch = concat_batches(&self.batches[0].schema(), &self.batches).unwrap();
+        self.batches.clear();
+        self.rows = 0;
+
+        let sort_columns: Vec<_> = self
+            .sort_columns
+            .iter()
+            .map(|i| SortColumn {
+                values: batch.column(*i).clone(),
+                options: None,
+            })
+            .collect();
+        let indices = lexsort_to_indices(&sort_columns, None).unwrap();
+        Some(
+            RecordBatch::try_new(
+                batch.schema(),
+                batch
+                    .columns()
+                    .iter()
+                    .map(|c| take(c, &indices, None).unwrap())
+                    .collect(),
+            )
+            .unwrap(),
+        )
+    }
+}
+
+/// Writes a batch, going through the sorter if the sink sorts its row groups
+fn write_sorted(
+    writer: &mut ArrowWriter<SharedBuffer>,
+    sorter: &mut Option<RowGroupSorter>,
+    batch: RecordBatch,
+) -> parquet::errors::Result<()> {
+    match sorter {
+        Some(sorter) => {
+            if let Some(row_group) = sorter.push(batch) {
+                writer.write(&row_group)?;
+                // end the row group, so that it only contains the sorted rows
+                writer.flush()?;
+            }
+            Ok(())
+        }
+        None => writer.write(&batch),
+    }
+}
+
+/// Writes any rows buffered by the sorter as a (possibly short) row group
+fn flush_sorted(
+    writer: &mut ArrowWriter<SharedBuffer>,
+    sorter: &mut Option<RowGroupSorter>,
+) -> parquet::errors::Result<()> {
+    if let Some(row_group) = sorter.as_mut().and_then(|s| s.take()) {
+        writer.write(&row_group)?;
+    }
+    Ok(())
+}
+
 /// A buffer with interior mutability shared by the [`ArrowWriter`] and
 /// [`AsyncArrowWriter`]. From Arrow. This lets us write data from the buffer to S3.
 #[derive(Clone)]
@@ -88,6 +263,7 @@ impl Write for SharedBuffer {
 
 pub struct RecordBatchBufferingWriter {
     writer: Option<ArrowWriter<SharedBuffer>>,
+    sorter: Option<RowGroupSorter>,
     shared_buffer: SharedBuffer,
     target_part_size: usize,
     schema: ArroyoSchemaRef,
@@ -109,16 +285,19 @@ impl BatchBufferingWriter for RecordBatchBufferingWriter {
             5 * 1024 * 1024
         };
         let shared_buffer = SharedBuffer::new(target_part_size);
-        let writer_properties = writer_properties_from_table(config);
+        let file_schema = schema.schema_without_timestamp();
+        let writer_properties = writer_properties_from_table(config, &file_schema);
+        let sorter = RowGroupSorter::new(config, &file_schema);
         let writer = ArrowWriter::try_new(
             shared_buffer.clone(),
-            Arc::new(schema.schema_without_timestamp()),
+            Arc::new(file_schema),
             Some(writer_properties),
         )
         .unwrap();
 
         Self {
             writer: Some(writer),
+            sorter,
             shared_buffer,
             target_part_size,
             schema,
@@ -133,7 +312,7 @@ impl BatchBufferingWriter for RecordBatchBufferingWriter {
         let writer = self.writer.as_mut().unwrap();
         // remove timestamp column
         self.schema.remove_timestamp_column(&mut data);
-        writer.write(&data).unwrap();
+        write_sorted(writer, &mut self.sorter, data).unwrap();
         if self.buffer_length() > self.target_part_size {
             Some(self.evict_current_buffer())
         } else {
@@ -154,6 +333,7 @@ impl BatchBufferingWriter for RecordBatchBufferingWriter {
 
     fn get_trailing_bytes_for_checkpoint(&mut self) -> Option<Vec<u8>> {
         let writer: &mut ArrowWriter<SharedBuffer> = self.writer.as_mut().unwrap();
+        flush_sorted(writer, &mut self.sorter).unwrap();
         writer.flush().unwrap();
         let result = self
             .writer
@@ -171,8 +351,9 @@ impl BatchBufferingWriter for RecordBatchBufferingWriter {
     fn close(&mut self, final_batch: Option<RecordBatch>) -> Option<Vec<u8>> {
         let mut writer = self.writer.take().unwrap();
         if let Some(batch) = final_batch {
-            writer.write(&batch).unwrap();
+            write_sorted(&mut writer, &mut self.sorter, batch).unwrap();
         }
+        flush_sorted(&mut writer, &mut self.sorter).unwrap();
         writer.close().unwrap();
         let buffer = self.shared_buffer.buffer.try_lock().unwrap();
         Some(buffer.to_vec())
@@ -181,6 +362,7 @@ impl BatchBufferingWriter for RecordBatchBufferingWriter {
 
 pub struct ParquetLocalWriter {
     writer: Option<ArrowWriter<SharedBuffer>>,
+    sorter: Option<RowGroupSorter>,
     tmp_path: String,
     file: File,
     destination_path: String,
@@ -198,16 +380,19 @@ impl LocalWriter for ParquetLocalWriter {
         schema: ArroyoSchemaRef,
     ) -> Self {
         let shared_buffer = SharedBuffer::new(0);
-        let writer_properties = writer_properties_from_table(table_properties);
+        let file_schema = schema.schema_without_timestamp();
+        let writer_properties = writer_properties_from_table(table_properties, &file_schema);
+        let sorter = RowGroupSorter::new(table_properties, &file_schema);
         let writer = ArrowWriter::try_new(
             shared_buffer.clone(),
-            Arc::new(schema.schema_without_timestamp()),
+            Arc::new(file_schema),
             Some(writer_properties),
         )
         .unwrap();
         let file = File::create(tmp_path.clone()).unwrap();
         Self {
             writer: Some(writer),
+            sorter,
             tmp_path,
             file,
             destination_path: final_path,
@@ -236,7 +421,7 @@ impl LocalWriter for ParquetLocalWriter {
             self.stats.as_mut().unwrap().last_write_at = Instant::now();
         }
         self.schema.remove_timestamp_column(&mut batch);
-        self.writer.as_mut().unwrap().write(&batch)?;
+        write_sorted(self.writer.as_mut().unwrap(), &mut self.sorter, batch)?;
         Ok(())
     }
 
@@ -254,7 +439,8 @@ impl LocalWriter for ParquetLocalWriter {
 
     fn close(&mut self) -> anyhow::Result<FilePreCommit> {
         let writer = self.writer.take();
-        let writer = writer.unwrap();
+        let mut writer = writer.unwrap();
+        flush_sorted(&mut writer, &mut self.sorter)?;
         writer.close()?;
         self.sync()?;
         Ok(FilePreCommit {
@@ -265,6 +451,7 @@ impl LocalWriter for ParquetLocalWriter {
 
     fn checkpoint(&mut self) -> anyhow::Result<Option<CurrentFileRecovery>> {
         let writer = self.writer.as_mut().unwrap();
+        flush_sorted(writer, &mut self.sorter)?;
         writer.flush()?;
         let bytes_written = self.sync()?;
         let trailing_bytes = self
@@ -5,18 +5,24 @@ use std::hash::{Hash, Hasher};
 use std::time::SystemTime;
 
 use anyhow::Result;
-use arrow::array::RecordBatch;
+use arrow::array::{AsArray, RecordBatch};
 
-use arrow::datatypes::SchemaRef;
+use arrow::compute::prep_null_mask_filter;
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::error::ArrowError;
 use arroyo_state::global_table_config;
 use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
 use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
 use async_trait::async_trait;
 use bincode::{Decode, Encode};
-use datafusion::common::ScalarValue;
+use datafusion::common::{DFSchema, ScalarValue};
+use datafusion::execution::context::ExecutionProps;
+use datafusion::logical_expr::Expr;
+use datafusion::physical_expr::create_physical_expr;
 use futures::StreamExt;
+use parquet::arrow::arrow_reader::{ArrowPredicateFn, RowFilter};
 use parquet::arrow::async_reader::ParquetObjectReader;
-use parquet::arrow::ParquetRecordBatchStreamBuilder;
+use parquet::arrow::{ParquetRecordBatchStreamBuilder, ProjectionMask};
 
 use arroyo_operator::context::ArrowContext;
 use regex::Regex;
@@ -24,14 +30,15 @@ use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::select;
 use tokio_stream::wrappers::LinesStream;
 use tokio_stream::Stream;
-use tracing::info;
+use tracing::{info, warn};
 
+use crate::filesystem::notifications::NotificationQueue;
 use crate::filesystem::{CompressionFormat, TableType};
 use arroyo_operator::operator::SourceOperator;
 use arroyo_operator::SourceFinishType;
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::TableConfig;
-use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_rpc::{grpc::StopMode, ControlMessage, TIMESTAMP_FIELD};
 use arroyo_storage::StorageProvider;
 use arroyo_types::{to_nanos, UserError};
 
@@ -41,7 +48,11 @@ pub struct FileSystemSourceFunc {
     pub format: Format,
     pub framing: Option<Framing>,
     pub bad_data: Option<BadData>,
+    /// predicate pushed down by the planner, used to skip parquet rows that can't match
+    pub filter: Option<Expr>,
     pub file_states: HashMap<String, FileReadState>,
+    /// set when new files are discovered from S3 event notifications rather than by listing
+    pub notifications: Option<NotificationQueue>,
 }
 
 #[derive(Encode, Decode, Debug, Clone, PartialEq, PartialOrd)]
@@ -64,7 +75,7 @@ impl SourceOperator for FileSystemSourceFunc {
         match self.run_int(ctx).await {
             Ok(s) => s,
             Err(e) => {
-                ctx.report_error(e.name.clone(), e.details.clone()).await;
+                ctx.report_source_error(e.clone()).await;
 
                 panic!("{}: {}", e.name, e.details);
             }
@@ -84,12 +95,14 @@ impl FileSystemSourceFunc {
     }
 
     async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
-        let (storage_provider, regex_pattern) = match &self.table {
+        let (storage_provider, regex_pattern, sqs_queue_url, sqs_region) = match &self.table {
             TableType::Source {
                 path,
                 storage_options,
                 compression_format: _,
                 regex_pattern,
+                sqs_queue_url,
+                sqs_region,
             } => {
                 let storage_provider =
                     StorageProvider::for_url_with_options(path, storage_options.clone())
@@ -107,7 +120,12 @@ impl FileSystemSourceFunc {
                             err.to_string(),
                         )
                     })?;
-                (storage_provider, matcher)
+                (
+                    storage_provider,
+                    matcher,
+                    sqs_queue_url.clone(),
+                    sqs_region.clone(),
+                )
             }
             TableType::Sink { .. } => {
                 return Err(UserError::new(
@@ -121,6 +139,21 @@ impl FileSystemSourceFunc {
             self.framing.clone(),
             self.bad_data.clone(),
         );
+
+        let state: &mut GlobalKeyedView<String, (String, FileReadState)> = ctx
+            .table_manager
+            .get_global_keyed_state("a")
+            .await
+            .expect("should have table");
+        self.file_states = state.get_all().clone().into_values().collect();
+
+        if let Some(queue_url) = sqs_queue_url {
+            self.notifications = Some(NotificationQueue::new(queue_url, sqs_region).await);
+            return self
+                .run_notifications(ctx, &storage_provider, regex_pattern)
+                .await;
+        }
+
         let parallelism = ctx.task_info.parallelism;
         let task_index = ctx.task_info.task_index;
 
@@ -147,13 +180,6 @@ impl FileSystemSourceFunc {
                 }
             });
 
-        let state: &mut GlobalKeyedView<String, (String, FileReadState)> = ctx
-            .table_manager
-            .get_global_keyed_state("a")
-            .await
-            .expect("should have table");
-        self.file_states = state.get_all().clone().into_values().collect();
-
         while let Some(path) = file_paths.next().await {
             let obj_key = path
                 .map_err(|err| UserError::new("could not get next path", err.to_string()))?
@@ -172,6 +198,71 @@ impl FileSystemSourceFunc {
         Ok(SourceFinishType::Final)
     }
 
+    /// Reads the objects reported by S3 event notifications until the source is stopped. Each
+    /// subtask polls the queue independently, so SQS spreads the files across them.
+    async fn run_notifications(
+        &mut self,
+        ctx: &mut ArrowContext,
+        storage_provider: &StorageProvider,
+        regex_pattern: Option<Regex>,
+    ) -> Result<SourceFinishType, UserError> {
+        let bucket = storage_provider.config().bucket().map(|b| b.to_string());
+        let prefix: Option<object_store::path::Path> = storage_provider
+            .config()
+            .key()
+            .map(|key| key.to_string().into());
+
+        loop {
+            // a control message may cancel the receive after messages have been delivered; they
+            // will be redelivered once their visibility timeout expires
+            let notifications = select! {
+                result = self.notifications.as_ref().unwrap().receive() => result?,
+                msg_res = ctx.control_rx.recv() => {
+                    if let Some(control_message) = msg_res {
+                        if let Some(finish_type) = self.process_control_message(ctx, control_message).await {
+                            return Ok(finish_type);
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            for notification in notifications {
+                for object in &notification.objects {
+                    if bucket.as_deref() != Some(object.bucket.as_str()) {
+                        warn!(
+                            "ignoring notification for s3://{}/{}, which is not in the source bucket",
+                            object.bucket, object.key
+                        );
+                        continue;
+                    }
+
+                    let path = object_store::path::Path::from(object.key.as_str());
+                    if prefix.as_ref().is_some_and(|p| !path.prefix_matches(p))
+                        || regex_pattern
+                            .as_ref()
+                            .is_some_and(|matcher| !matcher.is_match(path.as_ref()))
+                    {
+                        continue;
+                    }
+
+                    let obj_key = path.to_string();
+                    if let Some(FileReadState::Finished) = self.file_states.get(&obj_key) {
+                        // a redelivered notification for a file we've already read
+                        continue;
+                    }
+
+                    if let Some(finish_type) =
+                        self.read_file(ctx, storage_provider, &obj_key).await?
+                    {
+                        return Ok(finish_type);
+                    }
+                }
+                self.notifications.as_mut().unwrap().processed(notification);
+            }
+        }
+    }
+
     async fn get_newline_separated_stream(
         &mut self,
         storage_provider: &StorageProvider,
@@ -183,11 +274,17 @@ impl FileSystemSourceFunc {
 
                 let compression_reader: Box<dyn AsyncRead + Unpin + Send> =
                     match self.get_compression_format() {
+                        // files may contain multiple gzip members or zstd frames, as written by
+                        // the filesystem sink
                         CompressionFormat::Zstd => {
-                            Box::new(ZstdDecoder::new(BufReader::new(stream_reader)))
+                            let mut decoder = ZstdDecoder::new(BufReader::new(stream_reader));
+                            decoder.multiple_members(true);
+                            Box::new(decoder)
                         }
                         CompressionFormat::Gzip => {
-                            Box::new(GzipDecoder::new(BufReader::new(stream_reader)))
+                            let mut decoder = GzipDecoder::new(BufReader::new(stream_reader));
+                            decoder.multiple_members(true);
+                            Box::new(decoder)
                         }
                         CompressionFormat::None => Box::new(BufReader::new(stream_reader)),
                     };
@@ -224,7 +321,7 @@ impl FileSystemSourceFunc {
                     })?;
                 let object_reader =
                     ParquetObjectReader::new(storage_provider.get_backing_store(), object_meta);
-                let reader_builder = ParquetRecordBatchStreamBuilder::new(object_reader)
+                let mut reader_builder = ParquetRecordBatchStreamBuilder::new(object_reader)
                     .await
                     .map_err(|err| {
                         UserError::new(
@@ -233,6 +330,44 @@ impl FileSystemSourceFunc {
                         )
                     })?
                     .with_batch_size(8192);
+
+                // only read the columns of the table that the pipeline uses
+                let file_schema = reader_builder.schema().clone();
+                let column_indices = out_schema
+                    .fields()
+                    .iter()
+                    .filter(|f| f.name() != TIMESTAMP_FIELD)
+                    .map(|f| {
+                        file_schema.index_of(f.name()).map_err(|_| {
+                            UserError::new(
+                                "data does not match schema",
+                                format!(
+                                    "The parquet file {} does not contain the column '{}'",
+                                    path,
+                                    f.name()
+                                ),
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mask =
+                    ProjectionMask::roots(reader_builder.parquet_schema(), column_indices.clone());
+                reader_builder = reader_builder.with_projection(mask);
+
+                if let Some(filter) = &self.filter {
+                    match Self::row_filter(&reader_builder, &file_schema, filter) {
+                        Ok(row_filter) => {
+                            reader_builder = reader_builder.with_row_filter(row_filter);
+                        }
+                        Err(e) => {
+                            warn!(
+                                "not filtering rows of {} with pushed-down predicate: {}",
+                                path, e
+                            );
+                        }
+                    }
+                }
+
                 let stream = reader_builder.build().map_err(|err| {
                     UserError::new(
                         "could not build parquet record batch stream",
@@ -241,8 +376,14 @@ impl FileSystemSourceFunc {
                 })?;
                 let result = Box::new(stream.map(move |res| match res {
                     Ok(record_batch) => {
-                        // add timestamp
-                        let mut columns = record_batch.columns().to_vec();
+                        // the projected columns are in file order, so reorder them to match the table,
+                        // then add the timestamp
+                        let mut columns: Vec<_> = out_schema
+                            .fields()
+                            .iter()
+                            .filter(|f| f.name() != TIMESTAMP_FIELD)
+                            .filter_map(|f| record_batch.column_by_name(f.name()).cloned())
+                            .collect();
                             let current_time = to_nanos(SystemTime::now());
                             let current_time_scalar =
                                 ScalarValue::TimestampNanosecond(Some(current_time as i64), None);
@@ -272,6 +413,43 @@ impl FileSystemSourceFunc {
         }
     }
 
+    fn row_filter<T>(
+        builder: &ParquetRecordBatchStreamBuilder<T>,
+        file_schema: &Schema,
+        filter: &Expr,
+    ) -> anyhow::Result<RowFilter> {
+        let mut indices = filter
+            .to_columns()?
+            .iter()
+            .map(|c| file_schema.index_of(&c.name))
+            .collect::<Result<Vec<_>, _>>()?;
+        indices.sort();
+
+        let filter_schema = file_schema.project(&indices)?;
+        let predicate = create_physical_expr(
+            filter,
+            &DFSchema::try_from(filter_schema)?,
+            &ExecutionProps::new(),
+        )?;
+
+        let mask = ProjectionMask::roots(builder.parquet_schema(), indices);
+        let predicate = ArrowPredicateFn::new(mask, move |batch| {
+            let result = predicate
+                .evaluate(&batch)
+                .and_then(|v| v.into_array(batch.num_rows()))
+                .map_err(|e| ArrowError::ComputeError(e.to_string()))?;
+            let Some(result) = result.as_boolean_opt() else {
+                return Err(ArrowError::ComputeError(
+                    "pushed-down predicate did not evaluate to a boolean".to_string(),
+                ));
+            };
+            // rows where the predicate is null can't match
+            Ok(prep_null_mask_filter(result))
+        });
+
+        Ok(RowFilter::new(vec![Box::new(predicate)]))
+    }
+
     async fn read_file(
         &mut self,
         ctx: &mut ArrowContext,
@@ -409,10 +587,15 @@ impl FileSystemSourceFunc {
                 }
                 // checkpoint our state
                 if self.start_checkpoint(c, ctx).await {
-                    Some(SourceFinishType::Immediate)
-                } else {
-                    None
+                    return Some(SourceFinishType::Immediate);
                 }
+
+                if let Some(notifications) = &mut self.notifications {
+                    if let Err(e) = notifications.checkpoint().await {
+                        warn!("{}: {}", e.name, e.details);
+                    }
+                }
+                None
             }
             ControlMessage::Stop { mode } => {
                 info!("Stopping FileSystem source {:?}", mode);
@@ -424,6 +607,10 @@ impl FileSystemSourceFunc {
             ControlMessage::Commit { .. } => {
                 unreachable!("sources shouldn't receive commit messages");
             }
+            ControlMessage::UpdateParameters { parameters } => {
+                ctx.update_parameters(&parameters);
+                None
+            }
             _ => None,
         }
     }
@@ -0,0 +1,184 @@
+mod operator;
+
+use anyhow::{anyhow, bail};
+use arrow::datatypes::Schema;
+use arroyo_operator::connector::{Connection, Connector};
+use arroyo_operator::operator::OperatorNode;
+use arroyo_rpc::api_types::connections::{
+    ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
+};
+use arroyo_rpc::{OperatorConfig, IS_RETRACT_FIELD, TIMESTAMP_FIELD};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use typify::import_types;
+
+use crate::flight::operator::FlightSinkFunc;
+use crate::EmptyConfig;
+
+const TABLE_SCHEMA: &str = include_str!("./table.json");
+const ICON: &str = include_str!("./flight.svg");
+
+import_types!(schema = "src/flight/table.json");
+
+/// The location, relative to the checkpoint storage, of the snapshots of `table` written by each
+/// subtask of a flight sink on checkpoint `epoch`
+pub fn snapshot_prefix(job_id: &str, table: &str, epoch: u32) -> String {
+    format!("{}/results/{}/{:0>7}", job_id, table, epoch)
+}
+
+/// The schema of the results served for a sink with input `schema`, which leaves out the
+/// timestamp and retraction columns
+pub fn served_schema(schema: &Schema) -> Schema {
+    Schema::new(
+        schema
+            .fields()
+            .iter()
+            .filter(|f| f.name() != TIMESTAMP_FIELD && f.name() != IS_RETRACT_FIELD)
+            .cloned()
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// A sink that materializes its input so that it can be queried over Arrow Flight SQL. The
+/// results are snapshotted to the checkpoint storage on every checkpoint, from where the API
+/// serves the snapshots of the latest completed checkpoint. With an `upsert_key`, the sink keeps
+/// the latest version of each row, and so can consume updating queries.
+pub struct FlightConnector {}
+
+impl Connector for FlightConnector {
+    type ProfileT = EmptyConfig;
+    type TableT = FlightTable;
+
+    fn name(&self) -> &'static str {
+        "flight"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: self.name().to_string(),
+            name: "Arrow Flight".to_string(),
+            icon: ICON.to_string(),
+            description: "Serves query results over Arrow Flight SQL".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: false,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn supports_upserts(&self) -> bool {
+        true
+    }
+
+    fn get_schema(
+        &self,
+        _: Self::ProfileT,
+        _: Self::TableT,
+        s: Option<&ConnectionSchema>,
+    ) -> Option<ConnectionSchema> {
+        s.cloned()
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: tokio::sync::mpsc::Sender<TestSourceMessage>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = TestSourceMessage {
+                error: false,
+                done: true,
+                message: "Successfully validated connection".to_string(),
+            };
+            tx.send(message).await.unwrap();
+        });
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        options: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+        _profile: Option<&ConnectionProfile>,
+    ) -> anyhow::Result<Connection> {
+        self.from_config(
+            None,
+            name,
+            EmptyConfig {},
+            FlightTable {
+                name: Some(options.remove("name").unwrap_or_else(|| name.to_string())),
+            },
+            schema,
+        )
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        s: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let schema = s
+            .cloned()
+            .ok_or_else(|| anyhow!("no schema for flight sink"))?;
+
+        let served_name = table.name.as_deref().unwrap_or(name);
+        if served_name.is_empty() || served_name.contains('/') {
+            bail!("invalid flight table name '{}'", served_name);
+        }
+
+        let description = format!("Flight<{}>", served_name);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            filter: None,
+            delivery_semantics: None,
+            upsert_key: vec![],
+            partition_watermarks: None,
+            format: None,
+            bad_data: None,
+            framing: None,
+        };
+
+        Ok(Connection {
+            id,
+            connector: self.name(),
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn make_operator(
+        &self,
+        _: Self::ProfileT,
+        table: Self::TableT,
+        config: OperatorConfig,
+    ) -> anyhow::Result<OperatorNode> {
+        let name = table
+            .name
+            .ok_or_else(|| anyhow!("flight sink is missing a table name"))?;
+
+        Ok(OperatorNode::from_operator(Box::new(FlightSinkFunc::new(
+            name,
+            config.upsert_key,
+        ))))
+    }
+}
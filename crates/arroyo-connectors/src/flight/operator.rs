@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::{AsArray, BooleanArray, RecordBatch};
+use arrow::compute::concat_batches;
+use arrow::datatypes::SchemaRef;
+use arrow::row::{OwnedRow, RowConverter, SortField};
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::config::config;
+use arroyo_rpc::grpc::TableConfig;
+use arroyo_rpc::IS_RETRACT_FIELD;
+use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
+use arroyo_storage::StorageProvider;
+use arroyo_types::{CheckpointBarrier, SignalMessage};
+use async_trait::async_trait;
+use bytes::Bytes;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use tracing::{info, warn};
+
+use super::{served_schema, snapshot_prefix};
+
+/// The materialized results of the sink
+enum Results {
+    /// Every row that has been written
+    Append(Vec<RecordBatch>),
+    /// The latest version of each row, by the upsert key
+    Upsert {
+        key_indices: Vec<usize>,
+        keys: RowConverter,
+        values: RowConverter,
+        rows: HashMap<OwnedRow, OwnedRow>,
+    },
+}
+
+pub struct FlightSinkFunc {
+    table: String,
+    upsert_key: Vec<String>,
+    storage: Option<StorageProvider>,
+    schema: Option<SchemaRef>,
+    // the indices of the served columns in the input
+    value_indices: Vec<usize>,
+    results: Option<Results>,
+}
+
+impl FlightSinkFunc {
+    pub fn new(table: String, upsert_key: Vec<String>) -> Self {
+        Self {
+            table,
+            upsert_key,
+            storage: None,
+            schema: None,
+            value_indices: vec![],
+            results: None,
+        }
+    }
+
+    fn snapshot_path(&self, job_id: &str, epoch: u32, task_index: usize) -> String {
+        format!(
+            "{}/{:0>3}.parquet",
+            snapshot_prefix(job_id, &self.table, epoch),
+            task_index
+        )
+    }
+
+    /// Applies a batch with the served schema to the results, removing the rows that are
+    /// retracted
+    fn apply(&mut self, batch: &RecordBatch, retracts: Option<&BooleanArray>) {
+        match self
+            .results
+            .as_mut()
+            .expect("flight sink should be started")
+        {
+            Results::Append(batches) => {
+                batches.push(batch.clone());
+            }
+            Results::Upsert {
+                key_indices,
+                keys,
+                values,
+                rows,
+            } => {
+                let key_rows = keys
+                    .convert_columns(batch.project(key_indices).unwrap().columns())
+                    .unwrap();
+                let value_rows = values.convert_columns(batch.columns()).unwrap();
+
+                for (i, key) in key_rows.iter().enumerate() {
+                    if retracts.is_some_and(|r| r.value(i)) {
+                        rows.remove(&key.owned());
+                    } else {
+                        rows.insert(key.owned(), value_rows.row(i).owned());
+                    }
+                }
+            }
+        }
+    }
+
+    fn snapshot(&mut self) -> RecordBatch {
+        let schema = self.schema.clone().expect("flight sink should be started");
+        match self
+            .results
+            .as_mut()
+            .expect("flight sink should be started")
+        {
+            Results::Append(batches) => {
+                let batch = concat_batches(&schema, batches.iter()).unwrap();
+                // keep a single batch, so that the results aren't copied on every checkpoint
+                *batches = vec![batch.clone()];
+                batch
+            }
+            Results::Upsert { values, rows, .. } => {
+                let columns = values
+                    .convert_rows(rows.values().map(|row| row.row()))
+                    .unwrap();
+                RecordBatch::try_new(schema, columns).unwrap()
+            }
+        }
+    }
+
+    async fn restore(&mut self, path: &str) -> anyhow::Result<()> {
+        let schema = self.schema.clone().unwrap();
+        let data: Bytes = self.storage.as_ref().unwrap().get(path).await?;
+        for batch in ParquetRecordBatchReaderBuilder::try_new(data)?.build()? {
+            self.apply(&batch?.with_schema(schema.clone())?, None);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ArrowOperator for FlightSinkFunc {
+    fn name(&self) -> String {
+        format!("FlightSink<{}>", self.table)
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        arroyo_state::global_table_config("s", "flight sink snapshots")
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        let in_schema = ctx.in_schemas[0].schema.clone();
+        let schema = Arc::new(served_schema(&in_schema));
+        self.value_indices = schema
+            .fields()
+            .iter()
+            .map(|f| in_schema.index_of(f.name()).unwrap())
+            .collect();
+
+        self.results = Some(if self.upsert_key.is_empty() {
+            Results::Append(vec![])
+        } else {
+            let key_indices: Vec<_> = self
+                .upsert_key
+                .iter()
+                .map(|k| {
+                    schema
+                        .index_of(k)
+                        .expect("upsert key field should be in the sink's input")
+                })
+                .collect();
+            let sort_fields = |indices: Vec<usize>| {
+                indices
+                    .into_iter()
+                    .map(|i| SortField::new(schema.field(i).data_type().clone()))
+                    .collect::<Vec<_>>()
+            };
+            Results::Upsert {
+                keys: RowConverter::new(sort_fields(key_indices.clone())).unwrap(),
+                values: RowConverter::new(sort_fields((0..schema.fields().len()).collect()))
+                    .unwrap(),
+                key_indices,
+                rows: HashMap::new(),
+            }
+        });
+        self.schema = Some(schema);
+
+        let url = config().checkpoint_storage_url();
+        self.storage = Some(
+            StorageProvider::for_checkpoints(url)
+                .await
+                .unwrap_or_else(|e| panic!("invalid checkpoint storage url {}: {:?}", url, e)),
+        );
+
+        let table: &mut GlobalKeyedView<usize, String> = ctx
+            .table_manager
+            .get_global_keyed_state("s")
+            .await
+            .expect("should have table s in flight sink");
+
+        // after rescaling, the results of the previous subtasks are divided between the new ones
+        let paths: Vec<_> = table
+            .get_all()
+            .iter()
+            .filter(|(task_index, _)| {
+                **task_index % ctx.task_info.parallelism == ctx.task_info.task_index
+            })
+            .map(|(_, path)| path.clone())
+            .collect();
+
+        for path in paths {
+            if let Err(e) = self.restore(&path).await {
+                warn!(
+                    "failed to restore flight sink results from {}: {:?}",
+                    path, e
+                );
+            }
+        }
+    }
+
+    async fn process_batch(&mut self, batch: RecordBatch, _: &mut ArrowContext) {
+        let retracts = batch
+            .schema()
+            .index_of(IS_RETRACT_FIELD)
+            .ok()
+            .map(|i| batch.column(i).as_boolean().clone());
+        let values = batch
+            .project(&self.value_indices)
+            .unwrap()
+            .with_schema(self.schema.clone().unwrap())
+            .unwrap();
+
+        self.apply(&values, retracts.as_ref());
+    }
+
+    async fn handle_checkpoint(&mut self, barrier: CheckpointBarrier, ctx: &mut ArrowContext) {
+        let batch = self.snapshot();
+
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        let mut writer = ArrowWriter::try_new(Vec::new(), batch.schema(), Some(props)).unwrap();
+        writer.write(&batch).unwrap();
+        let data = writer.into_inner().unwrap();
+
+        let path = self.snapshot_path(
+            &ctx.task_info.job_id,
+            barrier.epoch,
+            ctx.task_info.task_index,
+        );
+        self.storage
+            .as_ref()
+            .unwrap()
+            .put(path.clone(), data)
+            .await
+            .unwrap_or_else(|e| panic!("failed to write flight sink results to {}: {:?}", path, e));
+
+        ctx.table_manager
+            .get_global_keyed_state("s")
+            .await
+            .expect("should have table s in flight sink")
+            .insert(ctx.task_info.task_index, path)
+            .await;
+
+        // the previous snapshot is still served until this checkpoint completes, but the one
+        // before it is no longer needed
+        if let Some(epoch) = barrier.epoch.checked_sub(2) {
+            let old = self.snapshot_path(&ctx.task_info.job_id, epoch, ctx.task_info.task_index);
+            if let Err(e) = self.storage.as_ref().unwrap().delete_if_present(old).await {
+                warn!("failed to clean up flight sink results: {:?}", e);
+            }
+        }
+    }
+
+    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        info!(
+            "flight sink {}-{} closing with {} rows",
+            ctx.task_info.operator_id,
+            ctx.task_info.task_index,
+            self.snapshot().num_rows()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int64Array, TimestampNanosecondArray};
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use arroyo_operator::operator::OperatorNode;
+    use arroyo_operator_test::OperatorHarness;
+    use arroyo_rpc::df::ArroyoSchema;
+
+    fn schema() -> Arc<Schema> {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("count", DataType::Int64, false),
+            Field::new(IS_RETRACT_FIELD, DataType::Boolean, false),
+            Field::new(
+                "_timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]))
+    }
+
+    fn batch(rows: &[(i64, i64, bool)]) -> RecordBatch {
+        RecordBatch::try_new(
+            schema(),
+            vec![
+                Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.0))),
+                Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.1))),
+                Arc::new(BooleanArray::from_iter(rows.iter().map(|r| Some(r.2)))),
+                Arc::new(TimestampNanosecondArray::from(vec![0; rows.len()])),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn sink() -> OperatorNode {
+        OperatorNode::from_operator(Box::new(FlightSinkFunc::new(
+            "counts".to_string(),
+            vec!["id".to_string()],
+        )))
+    }
+
+    async fn read_snapshot(path: &str) -> Vec<(i64, i64)> {
+        let storage = StorageProvider::for_checkpoints(config().checkpoint_storage_url())
+            .await
+            .unwrap();
+        let data = storage.get(path).await.unwrap();
+        let mut rows = vec![];
+        for batch in ParquetRecordBatchReaderBuilder::try_new(data)
+            .unwrap()
+            .build()
+            .unwrap()
+        {
+            let batch = batch.unwrap();
+            assert_eq!(batch.num_columns(), 2);
+            let ids = batch
+                .column(0)
+                .as_primitive::<arrow::datatypes::Int64Type>();
+            let counts = batch
+                .column(1)
+                .as_primitive::<arrow::datatypes::Int64Type>();
+            rows.extend(
+                ids.values()
+                    .iter()
+                    .copied()
+                    .zip(counts.values().iter().copied()),
+            );
+        }
+        rows.sort();
+        rows
+    }
+
+    #[tokio::test]
+    async fn test_flight_sink_upserts() {
+        let mut harness = OperatorHarness::new(arroyo_types::get_test_task_info())
+            .with_in_schema(ArroyoSchema::new_unkeyed(schema(), 3))
+            .start(sink())
+            .await;
+
+        harness
+            .send_batch(0, batch(&[(1, 1, false), (2, 1, false)]))
+            .await;
+        harness
+            .send_batch(0, batch(&[(1, 1, true), (1, 2, false)]))
+            .await;
+        let epoch = harness.checkpoint().await;
+
+        let job_id = harness.task_info().job_id.clone();
+        let path = format!("{}/000.parquet", snapshot_prefix(&job_id, "counts", epoch));
+        assert_eq!(read_snapshot(&path).await, vec![(1, 2), (2, 1)]);
+
+        // the results are restored from the snapshot
+        harness.restart(sink()).await;
+        harness.send_batch(0, batch(&[(2, 1, true)])).await;
+        let epoch = harness.checkpoint().await;
+
+        let path = format!("{}/000.parquet", snapshot_prefix(&job_id, "counts", epoch));
+        assert_eq!(read_snapshot(&path).await, vec![(1, 2)]);
+    }
+}
@@ -7,6 +7,7 @@ use tracing::info;
 
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_types::CheckpointBarrier;
 
 pub struct FluvioSinkFunc {
@@ -29,6 +30,7 @@ impl ArrowOperator for FluvioSinkFunc {
             }
             Err(e) => {
                 ctx.report_error(
+                    ErrorCategory::Connector,
                     "Failed to construct Fluvio producer".to_string(),
                     e.to_string(),
                 )
@@ -60,7 +60,7 @@ impl SourceOperator for FluvioSourceFunc {
         match self.run_int(ctx).await {
             Ok(r) => r,
             Err(e) => {
-                ctx.report_error(e.name.clone(), e.details.clone()).await;
+                ctx.report_source_error(e.clone()).await;
 
                 panic!("{}: {}", e.name, e.details);
             }
@@ -224,7 +224,10 @@ impl FluvioSourceFunc {
                         Some(ControlMessage::LoadCompacted {compacted}) => {
                             ctx.load_compacted(compacted).await;
                         }
-                        Some(ControlMessage::NoOp ) => {}
+                        Some(ControlMessage::UpdateParameters { parameters }) => {
+                            ctx.update_parameters(&parameters);
+                        }
+                        Some(ControlMessage::NoOp | ControlMessage::AlignWatermark { .. }) => {}
                         None => {
 
                         }
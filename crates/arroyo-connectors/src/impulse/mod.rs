@@ -163,6 +163,10 @@ impl Connector for ImpulseConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            filter: None,
+            delivery_semantics: None,
+            upsert_key: vec![],
+            partition_watermarks: None,
             format: None,
             bad_data: None,
             framing: None,
@@ -205,7 +205,10 @@ impl ImpulseSourceFunc {
                 Ok(ControlMessage::LoadCompacted { compacted }) => {
                     ctx.load_compacted(compacted).await;
                 }
-                Ok(ControlMessage::NoOp) => {}
+                Ok(ControlMessage::UpdateParameters { parameters }) => {
+                    ctx.update_parameters(&parameters);
+                }
+                Ok(ControlMessage::NoOp | ControlMessage::AlignWatermark { .. }) => {}
                 Err(_) => {
                     // no messages
                 }
@@ -3,16 +3,18 @@ use arroyo_formats::de::ArrowDeserializer;
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::connector::Connection;
 use arroyo_rpc::api_types::connections::{ConnectionProfile, ConnectionSchema, TestSourceMessage};
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::df::ArroyoSchema;
 use arroyo_rpc::formats::{BadData, Format, JsonFormat};
 use arroyo_rpc::schema_resolver::{
     ConfluentSchemaRegistry, ConfluentSchemaRegistryClient, FailingSchemaResolver, SchemaResolver,
 };
-use arroyo_rpc::{schema_resolver, var_str::VarStr, OperatorConfig};
+use arroyo_rpc::{schema_resolver, var_str::VarStr, DeliverySemantics, OperatorConfig};
 use arroyo_types::string_to_map;
 use futures::TryFutureExt;
 use rdkafka::{
     consumer::{BaseConsumer, Consumer},
+    error::{KafkaError, RDKafkaErrorCode},
     ClientConfig, Message, Offset, TopicPartitionList,
 };
 use serde::{Deserialize, Serialize};
@@ -31,8 +33,9 @@ use typify::import_types;
 
 use crate::{pull_opt, send, ConnectionType};
 
-use crate::kafka::sink::KafkaSinkFunc;
+use crate::kafka::sink::{upsert_key_format, KafkaSinkFunc};
 use crate::kafka::source::KafkaSourceFunc;
+use crate::two_phase_committer::TwoPhaseCommitterOperator;
 use arroyo_operator::connector::Connector;
 use arroyo_operator::operator::OperatorNode;
 
@@ -115,6 +118,21 @@ impl KafkaConnector {
                     },
                     group_id: options.remove("source.group_id"),
                     group_id_prefix: options.remove("source.group_id_prefix"),
+                    start_timestamp_millis: options
+                        .remove("source.start_timestamp")
+                        .map(|t| parse_start_timestamp(&t))
+                        .transpose()?,
+                    start_offsets: options
+                        .remove("source.start_offsets")
+                        .map(|s| parse_start_offsets(&s))
+                        .transpose()?
+                        .unwrap_or_default(),
+                    commit_offsets: match options.remove("source.commit_offsets").as_deref() {
+                        Some("true") => Some(true),
+                        Some("false") => Some(false),
+                        None => None,
+                        Some(other) => bail!("invalid value for source.commit_offsets '{}'", other),
+                    },
                 }
             }
             "sink" => {
@@ -125,6 +143,15 @@ impl KafkaConnector {
                         Some("exactly_once") => SinkCommitMode::ExactlyOnce,
                         Some(other) => bail!("invalid value for commit_mode '{}'", other),
                     },
+                    sticky_partitioning: match options.remove("sink.sticky_partitioning").as_deref()
+                    {
+                        Some("true") => Some(true),
+                        Some("false") => Some(false),
+                        None => None,
+                        Some(other) => {
+                            bail!("invalid value for sink.sticky_partitioning '{}'", other)
+                        }
+                    },
                 }
             }
             _ => {
@@ -208,6 +235,10 @@ impl Connector for KafkaConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            filter: None,
+            delivery_semantics: None,
+            upsert_key: vec![],
+            partition_watermarks: None,
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
@@ -303,6 +334,22 @@ impl Connector for KafkaConnector {
         }
     }
 
+    fn supports_upserts(&self) -> bool {
+        true
+    }
+
+    fn supports_delivery_semantics(&self, _: DeliverySemantics) -> bool {
+        true
+    }
+
+    fn supports_partitioning_expressions(&self) -> bool {
+        true
+    }
+
+    fn supports_partition_watermarks(&self) -> bool {
+        true
+    }
+
     fn from_options(
         &self,
         name: &str,
@@ -335,7 +382,22 @@ impl Connector for KafkaConnector {
                 offset,
                 read_mode,
                 group_id_prefix,
+                start_timestamp_millis,
+                start_offsets,
+                commit_offsets,
             } => {
+                if start_timestamp_millis.is_some() && !start_offsets.is_empty() {
+                    bail!("only one of start_timestamp_millis and start_offsets may be set");
+                }
+                let start_offsets = start_offsets
+                    .iter()
+                    .map(|(partition, offset)| {
+                        partition.parse::<i32>().map(|p| (p, *offset)).map_err(|_| {
+                            anyhow!("invalid partition '{}' in start_offsets", partition)
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+
                 let mut client_configs = client_configs(&profile, &table);
                 if let Some(ReadMode::ReadCommitted) = read_mode {
                     client_configs
@@ -368,11 +430,15 @@ impl Connector for KafkaConnector {
                     group_id: group_id.clone(),
                     group_id_prefix: group_id_prefix.clone(),
                     offset_mode: *offset,
+                    start_timestamp_millis: *start_timestamp_millis,
+                    start_offsets,
+                    commit_offsets: commit_offsets.unwrap_or(true),
                     format: config.format.expect("Format must be set for Kafka source"),
                     framing: config.framing,
                     schema_resolver,
                     bad_data: config.bad_data,
                     client_configs,
+                    partition_watermarks: config.partition_watermarks,
                     messages_per_second: NonZeroU32::new(
                         config
                             .rate_limit
@@ -382,18 +448,43 @@ impl Connector for KafkaConnector {
                     .unwrap(),
                 })))
             }
-            TableType::Sink { commit_mode } => {
-                Ok(OperatorNode::from_operator(Box::new(KafkaSinkFunc {
+            TableType::Sink {
+                commit_mode,
+                sticky_partitioning,
+            } => {
+                let sink = KafkaSinkFunc {
                     bootstrap_servers: profile.bootstrap_servers.to_string(),
                     producer: None,
-                    consistency_mode: (*commit_mode).into(),
+                    consistency_mode: config
+                        .delivery_semantics
+                        .map(|s| s.into())
+                        .unwrap_or_else(|| (*commit_mode).into()),
                     write_futures: vec![],
                     client_config: client_configs(&profile, &table),
                     topic: table.topic,
+                    key_serializer: ArrowSerializer::new(upsert_key_format(
+                        config
+                            .format
+                            .as_ref()
+                            .expect("Format must be defined for KafkaSink"),
+                    )),
                     serializer: ArrowSerializer::new(
                         config.format.expect("Format must be defined for KafkaSink"),
                     ),
-                })))
+                    upsert_key: config.upsert_key,
+                    sticky_partitioning: sticky_partitioning.unwrap_or(false),
+                    partition_count: None,
+                    sticky_partition: None,
+                    error_reporter: None,
+                };
+
+                if sink.is_exactly_once() {
+                    Ok(OperatorNode::from_operator(Box::new(
+                        TwoPhaseCommitterOperator::new(sink),
+                    )))
+                } else {
+                    Ok(OperatorNode::from_operator(Box::new(sink)))
+                }
             }
         }
     }
@@ -838,6 +929,37 @@ impl KafkaTester {
     }
 }
 
+/// Parses a start timestamp given either as milliseconds since the epoch or as an RFC 3339
+/// datetime
+fn parse_start_timestamp(s: &str) -> anyhow::Result<i64> {
+    s.parse::<i64>()
+        .or_else(|_| {
+            chrono::DateTime::parse_from_rfc3339(s).map(|t| t.timestamp_millis())
+        })
+        .map_err(|_| {
+            anyhow!(
+                "invalid source.start_timestamp '{}': expected milliseconds since the epoch or an RFC 3339 datetime",
+                s
+            )
+        })
+}
+
+/// Parses start offsets given as comma-separated `partition:offset` pairs
+fn parse_start_offsets(s: &str) -> anyhow::Result<HashMap<String, i64>> {
+    string_to_map(s, ':')
+        .ok_or_else(|| {
+            anyhow!("invalid source.start_offsets: expected comma-separated partition:offset pairs")
+        })?
+        .into_iter()
+        .map(|(partition, offset)| {
+            let offset = offset
+                .parse::<i64>()
+                .map_err(|_| anyhow!("invalid offset '{}' in source.start_offsets", offset))?;
+            Ok((partition, offset))
+        })
+        .collect()
+}
+
 impl SourceOffset {
     fn get_offset(&self) -> Offset {
         match self {
@@ -848,6 +970,21 @@ impl SourceOffset {
     }
 }
 
+/// Distinguishes authentication and authorization failures, which require the user to fix the
+/// connection's credentials or ACLs, from other errors returned by the Kafka client
+pub(crate) fn error_category(e: &KafkaError) -> ErrorCategory {
+    match e.rdkafka_error_code() {
+        Some(
+            RDKafkaErrorCode::Authentication
+            | RDKafkaErrorCode::SaslAuthenticationFailed
+            | RDKafkaErrorCode::TopicAuthorizationFailed
+            | RDKafkaErrorCode::GroupAuthorizationFailed
+            | RDKafkaErrorCode::ClusterAuthorizationFailed,
+        ) => ErrorCategory::ConnectorAuth,
+        _ => ErrorCategory::Connector,
+    }
+}
+
 pub fn client_configs(connection: &KafkaConfig, table: &KafkaTable) -> HashMap<String, String> {
     let mut client_configs: HashMap<String, String> = HashMap::new();
 
@@ -885,3 +1022,78 @@ pub fn client_configs(connection: &KafkaConfig, table: &KafkaTable) -> HashMap<S
 
     client_configs
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_start_timestamp() {
+        assert_eq!(
+            parse_start_timestamp("1704067200000").unwrap(),
+            1704067200000
+        );
+        assert_eq!(
+            parse_start_timestamp("2024-01-01T00:00:00Z").unwrap(),
+            1704067200000
+        );
+        assert_eq!(
+            parse_start_timestamp("2024-01-01T01:00:00.500+01:00").unwrap(),
+            1704067200500
+        );
+
+        for invalid in ["", "yesterday", "2024-01-01", "1704067200000ms"] {
+            let err = parse_start_timestamp(invalid).unwrap_err().to_string();
+            assert!(err.contains("invalid source.start_timestamp"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_parse_start_offsets() {
+        assert_eq!(
+            parse_start_offsets("0:100, 1:0,2:-2").unwrap(),
+            HashMap::from([
+                ("0".to_string(), 100),
+                ("1".to_string(), 0),
+                ("2".to_string(), -2),
+            ])
+        );
+        assert!(parse_start_offsets("").unwrap().is_empty());
+
+        let err = parse_start_offsets("0:100,1").unwrap_err().to_string();
+        assert!(err.contains("partition:offset pairs"), "{}", err);
+        let err = parse_start_offsets("0:latest").unwrap_err().to_string();
+        assert!(err.contains("invalid offset 'latest'"), "{}", err);
+    }
+
+    #[test]
+    fn test_start_position_options() {
+        let mut options = HashMap::from([
+            ("type".to_string(), "source".to_string()),
+            ("topic".to_string(), "events".to_string()),
+            (
+                "source.start_timestamp".to_string(),
+                "2024-01-01T00:00:00Z".to_string(),
+            ),
+            ("source.start_offsets".to_string(), "0:5".to_string()),
+        ]);
+        let table = KafkaConnector::table_from_options(&mut options).unwrap();
+        let TableType::Source {
+            start_timestamp_millis,
+            start_offsets,
+            ..
+        } = table.type_
+        else {
+            panic!("expected a source table");
+        };
+        assert_eq!(start_timestamp_millis, Some(1704067200000));
+        assert_eq!(start_offsets, HashMap::from([("0".to_string(), 5)]));
+
+        let mut options = HashMap::from([
+            ("type".to_string(), "source".to_string()),
+            ("topic".to_string(), "events".to_string()),
+            ("source.start_timestamp".to_string(), "soon".to_string()),
+        ]);
+        assert!(KafkaConnector::table_from_options(&mut options).is_err());
+    }
+}
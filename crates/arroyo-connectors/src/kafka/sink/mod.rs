@@ -1,28 +1,34 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use bincode::{Decode, Encode};
 
-use arroyo_rpc::grpc::{GlobalKeyedTableConfig, TableConfig, TableEnum};
-use arroyo_rpc::{CheckpointEvent, ControlMessage, ControlResp};
+use arroyo_rpc::formats::{AvroFormat, Format, JsonFormat};
+use arroyo_rpc::{DeliverySemantics, IS_RETRACT_FIELD, SINK_KEY_FIELD, SINK_PARTITION_FIELD};
 use arroyo_types::*;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use tracing::{error, warn};
+use tracing::{error, warn, Instrument, Span};
 
+use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord, Producer};
 use rdkafka::util::Timeout;
 
 use rdkafka::ClientConfig;
 
-use arrow::array::RecordBatch;
+use arrow::array::{Array, AsArray, RecordBatch};
+use arrow::datatypes::{DataType, Int32Type, Schema};
 use arroyo_formats::ser::ArrowSerializer;
-use arroyo_operator::context::ArrowContext;
+use arroyo_operator::context::{ArrowContext, ErrorReporter};
 use arroyo_operator::operator::ArrowOperator;
+use arroyo_operator::trace_context;
 use arroyo_types::CheckpointBarrier;
 use async_trait::async_trait;
-use prost::Message;
 use rdkafka::error::{KafkaError, RDKafkaErrorCode};
 use std::time::{Duration, SystemTime};
 
-use super::SinkCommitMode;
+use super::{error_category, SinkCommitMode};
+use crate::two_phase_committer::TwoPhaseCommitter;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 
 #[cfg(test)]
 mod test;
@@ -35,13 +41,31 @@ pub struct KafkaSinkFunc {
     pub write_futures: Vec<DeliveryFuture>,
     pub client_config: HashMap<String, String>,
     pub serializer: ArrowSerializer,
+    /// serializes the upsert key of each row into the message key
+    pub key_serializer: ArrowSerializer,
+    /// if set, messages are keyed by these fields and retractions are written as tombstones, so
+    /// that the sink can be used with compacted topics
+    pub upsert_key: Vec<String>,
+    /// if set, messages without a key or partition all go to one partition, chosen per batch
+    pub sticky_partitioning: bool,
+    pub partition_count: Option<i32>,
+    pub sticky_partition: Option<i32>,
+    /// reports errors of exactly-once sinks, whose writes don't have access to the context
+    pub error_reporter: Option<ErrorReporter>,
 }
 
 pub enum ConsistencyMode {
+    AtMostOnce,
     AtLeastOnce,
+    /// Each checkpoint's messages are written in a Kafka transaction, which is committed once the
+    /// checkpoint completes. Exactly-once sinks run inside a [`TwoPhaseCommitterOperator`].
+    ///
+    /// [`TwoPhaseCommitterOperator`]: crate::two_phase_committer::TwoPhaseCommitterOperator
     ExactlyOnce {
-        next_transaction_index: usize,
-        producer_to_complete: Option<FutureProducer>,
+        /// the index of the open transaction, which is part of its transactional id
+        transaction_index: usize,
+        /// the producers of pre-committed transactions, by transactional id
+        producers_to_commit: HashMap<String, FutureProducer>,
     },
 }
 
@@ -50,94 +74,246 @@ impl From<SinkCommitMode> for ConsistencyMode {
         match commit_mode {
             SinkCommitMode::AtLeastOnce => ConsistencyMode::AtLeastOnce,
             SinkCommitMode::ExactlyOnce => ConsistencyMode::ExactlyOnce {
-                next_transaction_index: 0,
-                producer_to_complete: None,
+                transaction_index: 0,
+                producers_to_commit: HashMap::new(),
             },
         }
     }
 }
 
+impl From<DeliverySemantics> for ConsistencyMode {
+    fn from(semantics: DeliverySemantics) -> Self {
+        match semantics {
+            DeliverySemantics::AtMostOnce => ConsistencyMode::AtMostOnce,
+            DeliverySemantics::AtLeastOnce => SinkCommitMode::AtLeastOnce.into(),
+            DeliverySemantics::ExactlyOnce => SinkCommitMode::ExactlyOnce.into(),
+        }
+    }
+}
+
+fn transactional_id(
+    task_info: &TaskInfo,
+    topic: &str,
+    task_index: usize,
+    transaction_index: usize,
+) -> String {
+    format!(
+        "arroyo-id-{}-{}-{}-{}-{}",
+        task_info.job_id, task_info.operator_id, topic, task_index, transaction_index
+    )
+}
+
 impl KafkaSinkFunc {
-    fn is_committing(&self) -> bool {
+    pub fn is_exactly_once(&self) -> bool {
         matches!(self.consistency_mode, ConsistencyMode::ExactlyOnce { .. })
     }
 
-    fn init_producer(&mut self, task_info: &TaskInfo) -> Result<()> {
+    fn client_config(&self) -> ClientConfig {
         let mut client_config = ClientConfig::new();
         client_config.set("bootstrap.servers", &self.bootstrap_servers);
         for (key, value) in &self.client_config {
             client_config.set(key, value);
         }
+        client_config
+    }
+
+    fn init_producer(&mut self, task_info: &TaskInfo) -> Result<()> {
+        let mut client_config = self.client_config();
 
         match &mut self.consistency_mode {
-            ConsistencyMode::AtLeastOnce => {
+            ConsistencyMode::AtMostOnce | ConsistencyMode::AtLeastOnce => {
                 self.producer = Some(client_config.create()?);
             }
             ConsistencyMode::ExactlyOnce {
-                next_transaction_index,
-                ..
+                transaction_index, ..
             } => {
                 client_config.set("enable.idempotence", "true");
-                let transactional_id = format!(
-                    "arroyo-id-{}-{}-{}-{}-{}",
-                    task_info.job_id,
-                    task_info.operator_id,
-                    self.topic,
-                    task_info.task_index,
-                    next_transaction_index
+                client_config.set(
+                    "transactional.id",
+                    transactional_id(
+                        task_info,
+                        &self.topic,
+                        task_info.task_index,
+                        *transaction_index,
+                    ),
                 );
-                client_config.set("transactional.id", transactional_id);
                 let producer: FutureProducer = client_config.create()?;
+                // this aborts any transaction a previous producer left open under the same id
                 producer.init_transactions(Timeout::After(Duration::from_secs(30)))?;
                 producer.begin_transaction()?;
-                *next_transaction_index += 1;
                 self.producer = Some(producer);
             }
         }
         Ok(())
     }
 
-    async fn flush(&mut self, ctx: &mut ArrowContext) {
-        self.producer
-            .as_ref()
-            .unwrap()
-            // FutureProducer has a thread polling every 100ms,
-            // but better to send a signal immediately
-            // Duration 0 timeouts are non-blocking,
-            .poll(Timeout::After(Duration::ZERO));
-
-        // ensure all messages were delivered before finishing the checkpoint
-        for future in self.write_futures.drain(..) {
-            if let Err((e, _)) = future.await.unwrap() {
-                ctx.error_reporter
-                    .report_error("Kafka producer shut down", e.to_string())
+    async fn start(&mut self, ctx: &mut ArrowContext) {
+        self.init_producer(&ctx.task_info)
+            .expect("Producer creation failed");
+
+        let has_partition_column = ctx
+            .in_schemas
+            .first()
+            .is_some_and(|s| s.schema.index_of(SINK_PARTITION_FIELD).is_ok());
+        if self.sticky_partitioning || has_partition_column {
+            match self.fetch_partition_count() {
+                Ok(count) => {
+                    self.partition_count = Some(count);
+                }
+                Err(e) => {
+                    ctx.report_error(
+                        ErrorCategory::Connector,
+                        format!("Failed to fetch partitions of topic {}", self.topic),
+                        e.to_string(),
+                    )
                     .await;
-                panic!("Kafka producer shut down: {:?}", e);
+                    panic!(
+                        "failed to fetch partitions of topic {}: {:?}",
+                        self.topic, e
+                    );
+                }
             }
         }
     }
 
-    async fn publish(&mut self, k: Option<Vec<u8>>, v: Vec<u8>, ctx: &mut ArrowContext) {
-        let mut rec = {
-            if let Some(k) = k.as_ref() {
-                FutureRecord::to(&self.topic).key(k).payload(&v)
-            } else {
-                FutureRecord::to(&self.topic).payload(&v)
+    async fn flush(&mut self, error_reporter: &mut ErrorReporter) {
+        let span = tracing::trace_span!(
+            "kafka_flush",
+            topic = self.topic,
+            subtask_idx = error_reporter.task_info.task_index
+        );
+
+        async {
+            self.producer
+                .as_ref()
+                .unwrap()
+                // FutureProducer has a thread polling every 100ms,
+                // but better to send a signal immediately
+                // Duration 0 timeouts are non-blocking,
+                .poll(Timeout::After(Duration::ZERO));
+
+            // ensure all messages were delivered before finishing the checkpoint
+            for future in self.write_futures.drain(..) {
+                if let Err((e, _)) = future.await.unwrap() {
+                    error_reporter
+                        .report_error(
+                            error_category(&e),
+                            "Kafka producer shut down",
+                            e.to_string(),
+                        )
+                        .await;
+                    panic!("Kafka producer shut down: {:?}", e);
+                }
             }
+        }
+        .instrument(span)
+        .await
+    }
+
+    fn fetch_partition_count(&mut self) -> Result<i32> {
+        let metadata = self
+            .producer
+            .as_ref()
+            .unwrap()
+            .client()
+            .fetch_metadata(Some(&self.topic), Duration::from_secs(30))?;
+
+        let partitions = metadata
+            .topics()
+            .first()
+            .map(|t| t.partitions().len())
+            .unwrap_or_default();
+        if partitions == 0 {
+            anyhow::bail!("topic {} has no partitions", self.topic);
+        }
+        Ok(partitions as i32)
+    }
+
+    /// The partition for each row of the batch, from the partition column if there is one,
+    /// otherwise from sticky partitioning for rows that have no key
+    fn partitions(&mut self, batch: &RecordBatch, keys: &[Option<Vec<u8>>]) -> Vec<Option<i32>> {
+        let explicit: Vec<Option<i32>> = match batch.schema().index_of(SINK_PARTITION_FIELD) {
+            Ok(i) => batch
+                .column(i)
+                .as_primitive::<Int32Type>()
+                .iter()
+                .map(|p| {
+                    // out-of-range partitions wrap around, so that expressions like
+                    // `hash(x) % 12` keep working as partitions are added
+                    p.map(|p| match self.partition_count {
+                        Some(count) => p.rem_euclid(count),
+                        None => p,
+                    })
+                })
+                .collect(),
+            Err(_) => vec![None; batch.num_rows()],
         };
 
+        if !self.sticky_partitioning {
+            return explicit;
+        }
+
+        // pick a new partition for each batch, so that keyless messages are spread across
+        // partitions over time while each batch goes out in as few requests as possible
+        self.sticky_partition = self
+            .partition_count
+            .map(|count| (rand::random::<u32>() % count as u32) as i32);
+
+        explicit
+            .into_iter()
+            .zip(keys)
+            .map(|(p, k)| match (p, k) {
+                (None, None) => self.sticky_partition,
+                (p, _) => p,
+            })
+            .collect()
+    }
+
+    async fn publish(
+        &mut self,
+        k: Option<Vec<u8>>,
+        v: Option<Vec<u8>>,
+        partition: Option<i32>,
+        traceparent: Option<&str>,
+        error_reporter: &mut ErrorReporter,
+    ) {
+        let mut rec = FutureRecord::<Vec<u8>, Vec<u8>>::to(&self.topic);
+        if let Some(k) = k.as_ref() {
+            rec = rec.key(k);
+        }
+        if let Some(partition) = partition {
+            rec = rec.partition(partition);
+        }
+        // a message without a payload is a tombstone, which deletes the key from compacted topics
+        if let Some(v) = v.as_ref() {
+            rec = rec.payload(v);
+        }
+
+        if let Some(traceparent) = traceparent {
+            rec = rec.headers(OwnedHeaders::new().insert(Header {
+                key: trace_context::TRACEPARENT_HEADER,
+                value: Some(traceparent),
+            }));
+        }
+
         loop {
             match self.producer.as_mut().unwrap().send_result(rec) {
                 Ok(future) => {
-                    self.write_futures.push(future);
+                    if !matches!(self.consistency_mode, ConsistencyMode::AtMostOnce) {
+                        self.write_futures.push(future);
+                    }
                     return;
                 }
                 Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), f)) => {
                     rec = f;
                 }
                 Err((e, _)) => {
-                    ctx.error_reporter
-                        .report_error("Could not write to Kafka", format!("{:?}", e))
+                    error_reporter
+                        .report_error(
+                            error_category(&e),
+                            "Could not write to Kafka",
+                            format!("{:?}", e),
+                        )
                         .await;
 
                     panic!("Failed to write to kafka: {:?}", e);
@@ -150,118 +326,307 @@ impl KafkaSinkFunc {
     }
 }
 
-#[async_trait]
-impl ArrowOperator for KafkaSinkFunc {
-    fn name(&self) -> String {
-        format!("kafka-producer-{}", self.topic)
+/// The format upsert keys are serialized with, which is the table's format without the parts that
+/// only apply to values: keys are never Debezium envelopes, and Avro keys are written as raw
+/// datums because the table's schema registry id belongs to the value schema
+pub(crate) fn upsert_key_format(format: &Format) -> Format {
+    match format {
+        Format::Json(json) => Format::Json(JsonFormat {
+            debezium: false,
+            ..json.clone()
+        }),
+        Format::Avro(avro) => Format::Avro(AvroFormat {
+            confluent_schema_registry: false,
+            raw_datums: true,
+            schema_id: None,
+            ..avro.clone()
+        }),
+        format => format.clone(),
     }
+}
 
-    fn tables(&self) -> HashMap<String, TableConfig> {
-        if self.is_committing() {
-            single_item_hash_map(
-                "i".to_string(),
-                TableConfig {
-                    table_type: TableEnum::GlobalKeyValue.into(),
-                    config: GlobalKeyedTableConfig {
-                        table_name: "i".to_string(),
-                        description: "index for transactional ids".to_string(),
-                        uses_two_phase_commit: true,
-                    }
-                    .encode_to_vec(),
-                },
-            )
+/// The message keys computed by a sink's key expression, which the planner casts to a string
+/// unless it's binary
+fn message_keys(column: &dyn Array) -> Vec<Option<Vec<u8>>> {
+    match column.data_type() {
+        DataType::Binary => column
+            .as_binary::<i32>()
+            .iter()
+            .map(|k| k.map(|k| k.to_vec()))
+            .collect(),
+        _ => column
+            .as_string::<i32>()
+            .iter()
+            .map(|k| k.map(|k| k.as_bytes().to_vec()))
+            .collect(),
+    }
+}
+
+impl KafkaSinkFunc {
+    /// Projects the upsert key of each row. The raw formats serialize a single column called
+    /// `value`, so the (single) key field is renamed for them.
+    fn key_batch(&self, batch: &RecordBatch, key_indices: &[usize]) -> RecordBatch {
+        let keys = batch.project(key_indices).unwrap();
+        if !matches!(
+            self.serializer.format(),
+            Format::RawString(_) | Format::RawBytes(_)
+        ) {
+            return keys;
+        }
+
+        let field = keys.schema().field(0).clone().with_name("value");
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![field])),
+            vec![keys.column(0).clone()],
+        )
+        .unwrap()
+    }
+
+    async fn write_batch(&mut self, batch: RecordBatch, error_reporter: &mut ErrorReporter) {
+        let traceparent = trace_context::propagation_enabled()
+            .then(|| trace_context::traceparent(&Span::current()))
+            .flatten();
+
+        let schema = batch.schema();
+        let retract_index = schema.index_of(IS_RETRACT_FIELD).ok();
+        let key_index = schema.index_of(SINK_KEY_FIELD).ok();
+        let partition_index = schema.index_of(SINK_PARTITION_FIELD).ok();
+        let value_indices: Vec<_> = (0..schema.fields().len())
+            .filter(|i| ![retract_index, key_index, partition_index].contains(&Some(*i)))
+            .collect();
+        let is_retract = retract_index.map(|i| batch.column(i).as_boolean().clone());
+
+        let keys: Vec<Option<Vec<u8>>> = if !self.upsert_key.is_empty() {
+            let key_indices: Vec<_> = self
+                .upsert_key
+                .iter()
+                .map(|k| {
+                    schema
+                        .index_of(k)
+                        .expect("upsert key field should be in the sink's input")
+                })
+                .collect();
+            self.key_serializer
+                .serialize(&self.key_batch(&batch, &key_indices))
+                .map(Some)
+                .collect()
+        } else if let Some(i) = key_index {
+            message_keys(batch.column(i))
         } else {
-            HashMap::new()
+            vec![None; batch.num_rows()]
+        };
+        let partitions = self.partitions(&batch, &keys);
+
+        let values = self
+            .serializer
+            .serialize(&batch.project(&value_indices).unwrap());
+
+        for (i, ((k, v), p)) in keys.into_iter().zip(values).zip(partitions).enumerate() {
+            let retracted = is_retract.as_ref().is_some_and(|r| r.value(i));
+            self.publish(
+                k,
+                (!retracted).then_some(v),
+                p,
+                traceparent.as_deref(),
+                error_reporter,
+            )
+            .await;
         }
     }
+}
+
+#[async_trait]
+impl ArrowOperator for KafkaSinkFunc {
+    fn name(&self) -> String {
+        format!("kafka-producer-{}", self.topic)
+    }
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
-        self.init_producer(&ctx.task_info)
-            .expect("Producer creation failed");
+        self.start(ctx).await;
     }
 
     async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
-        let values = self.serializer.serialize(&batch);
+        self.write_batch(batch, &mut ctx.error_reporter).await;
+    }
+
+    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, ctx: &mut ArrowContext) {
+        self.flush(&mut ctx.error_reporter).await;
+    }
 
-        for v in values {
-            self.publish(None, v, ctx).await;
+    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        self.flush(&mut ctx.error_reporter).await;
+    }
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct KafkaDataRecovery {
+    task_index: usize,
+    /// the index of the transaction that was open at the checkpoint
+    transaction_index: usize,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct KafkaPreCommit {
+    transactional_id: String,
+}
+
+#[async_trait]
+impl TwoPhaseCommitter for KafkaSinkFunc {
+    type DataRecovery = KafkaDataRecovery;
+    type PreCommit = KafkaPreCommit;
+
+    fn name(&self) -> String {
+        format!("kafka-producer-{}", self.topic)
+    }
+
+    async fn abort(
+        &mut self,
+        task_info: &TaskInfo,
+        data_recovery: &[KafkaDataRecovery],
+    ) -> Result<()> {
+        // each subtask aborts its own open transaction when it reuses its transactional id in
+        // `init`, which leaves those of subtasks that were removed by rescaling to subtask 0
+        if task_info.task_index != 0 {
+            return Ok(());
+        }
+        for recovery in data_recovery
+            .iter()
+            .filter(|r| r.task_index >= task_info.parallelism)
+        {
+            let mut client_config = self.client_config();
+            client_config.set(
+                "transactional.id",
+                transactional_id(
+                    task_info,
+                    &self.topic,
+                    recovery.task_index,
+                    recovery.transaction_index,
+                ),
+            );
+            let producer: FutureProducer = client_config.create()?;
+            producer.init_transactions(Timeout::After(Duration::from_secs(30)))?;
         }
+        Ok(())
     }
 
-    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, ctx: &mut ArrowContext) {
-        self.flush(ctx).await;
+    async fn init(
+        &mut self,
+        ctx: &mut ArrowContext,
+        data_recovery: Vec<KafkaDataRecovery>,
+    ) -> Result<()> {
         if let ConsistencyMode::ExactlyOnce {
-            next_transaction_index,
-            producer_to_complete,
+            transaction_index, ..
         } = &mut self.consistency_mode
         {
-            *producer_to_complete = self.producer.take();
-            ctx.table_manager
-                .get_global_keyed_state("i")
-                .await
-                .as_mut()
-                .unwrap()
-                .insert(ctx.task_info.task_index, *next_transaction_index)
-                .await;
-            self.init_producer(&ctx.task_info)
-                .expect("creating new producer during checkpointing");
+            if let Some(recovery) = data_recovery
+                .iter()
+                .find(|r| r.task_index == ctx.task_info.task_index)
+            {
+                *transaction_index = recovery.transaction_index;
+            }
         }
+        self.error_reporter = Some(ctx.error_reporter.clone());
+        self.start(ctx).await;
+        Ok(())
+    }
+
+    async fn insert_batch(&mut self, batch: RecordBatch) -> Result<()> {
+        let mut error_reporter = self
+            .error_reporter
+            .clone()
+            .expect("sink should have been initialized");
+        self.write_batch(batch, &mut error_reporter).await;
+        Ok(())
     }
 
-    async fn handle_commit(
+    async fn commit(
         &mut self,
-        epoch: u32,
-        _commit_data: &HashMap<String, HashMap<u32, Vec<u8>>>,
-        ctx: &mut ArrowContext,
-    ) {
+        _task_info: &TaskInfo,
+        pre_commits: Vec<KafkaPreCommit>,
+    ) -> Result<()> {
         let ConsistencyMode::ExactlyOnce {
-            next_transaction_index: _,
-            producer_to_complete,
+            producers_to_commit,
+            ..
         } = &mut self.consistency_mode
         else {
-            warn!("received commit but consistency mode is not exactly once");
-            return;
+            unreachable!("only exactly-once Kafka sinks use two-phase commit");
         };
 
-        let Some(committing_producer) = producer_to_complete.take() else {
-            unimplemented!("received a commit message without a producer ready to commit. Restoring from commit phase not yet implemented");
-        };
-        let mut commits_attempted = 0;
-        loop {
-            if committing_producer
-                .commit_transaction(Timeout::After(Duration::from_secs(10)))
-                .is_ok()
+        for pre_commit in pre_commits {
+            let Some(producer) = producers_to_commit.remove(&pre_commit.transactional_id) else {
+                // Kafka transactions can only be committed by the producer that started them, so
+                // ones pre-committed before a restart are aborted by the broker once they time out
+                warn!(
+                    "can't commit Kafka transaction {}, which was pre-committed before the sink restarted",
+                    pre_commit.transactional_id
+                );
+                continue;
+            };
+
+            let mut commits_attempted = 0;
+            while let Err(e) = producer.commit_transaction(Timeout::After(Duration::from_secs(10)))
             {
-                break;
-            } else if commits_attempted == 5 {
-                panic!("failed to commit 5 times, giving up");
-            } else {
+                if commits_attempted == 5 {
+                    bail!(
+                        "failed to commit Kafka transaction {} 5 times, giving up: {:?}",
+                        pre_commit.transactional_id,
+                        e
+                    );
+                }
                 error!("failed to commit {} times, retrying", commits_attempted);
                 commits_attempted += 1;
             }
         }
-        let checkpoint_event = ControlResp::CheckpointEvent(CheckpointEvent {
-            checkpoint_epoch: epoch,
-            operator_id: ctx.task_info.operator_id.clone(),
-            subtask_index: ctx.task_info.task_index as u32,
-            time: SystemTime::now(),
-            event_type: arroyo_rpc::grpc::TaskCheckpointEventType::FinishedCommit,
-        });
-        ctx.control_tx
-            .send(checkpoint_event)
-            .await
-            .expect("sent commit event");
+        Ok(())
     }
 
-    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
-        self.flush(ctx).await;
-        if !self.is_committing() {
-            return;
-        }
-        if let Some(ControlMessage::Commit { epoch, commit_data }) = ctx.control_rx.recv().await {
-            self.handle_commit(epoch, &commit_data, ctx).await;
-        } else {
-            warn!("no commit message received, not committing")
+    async fn checkpoint(
+        &mut self,
+        task_info: &TaskInfo,
+        _watermark: Option<SystemTime>,
+        stopping: bool,
+    ) -> Result<(KafkaDataRecovery, HashMap<String, KafkaPreCommit>)> {
+        let mut error_reporter = self
+            .error_reporter
+            .clone()
+            .expect("sink should have been initialized");
+        self.flush(&mut error_reporter).await;
+
+        let ConsistencyMode::ExactlyOnce {
+            transaction_index,
+            producers_to_commit,
+        } = &mut self.consistency_mode
+        else {
+            unreachable!("only exactly-once Kafka sinks use two-phase commit");
+        };
+
+        let transactional_id = transactional_id(
+            task_info,
+            &self.topic,
+            task_info.task_index,
+            *transaction_index,
+        );
+        producers_to_commit.insert(
+            transactional_id.clone(),
+            self.producer.take().expect("sink should have a producer"),
+        );
+        *transaction_index += 1;
+        let recovery = KafkaDataRecovery {
+            task_index: task_info.task_index,
+            transaction_index: *transaction_index,
+        };
+
+        // nothing is written after the final checkpoint, so no transaction is left open
+        if !stopping {
+            self.init_producer(task_info)?;
         }
+
+        Ok((
+            recovery,
+            single_item_hash_map(
+                transactional_id.clone(),
+                KafkaPreCommit { transactional_id },
+            ),
+        ))
     }
 }
@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
-use arrow::array::{RecordBatch, UInt32Array};
+use arrow::array::{BooleanArray, RecordBatch, UInt32Array};
 use arrow::datatypes::Field;
 use arrow::datatypes::{DataType, Schema, SchemaRef};
 use arroyo_formats::ser::ArrowSerializer;
@@ -12,6 +12,7 @@ use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
 use arroyo_rpc::df::ArroyoSchema;
 use arroyo_rpc::formats::{Format, JsonFormat};
+use arroyo_rpc::IS_RETRACT_FIELD;
 use arroyo_types::CheckpointBarrier;
 use arroyo_types::*;
 use itertools::Itertools;
@@ -37,6 +38,13 @@ fn schema() -> SchemaRef {
     )]))
 }
 
+fn updating_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("value", DataType::UInt32, false),
+        Field::new(IS_RETRACT_FIELD, DataType::Boolean, false),
+    ]))
+}
+
 #[derive(Deserialize)]
 struct TestData {
     value: u32,
@@ -69,7 +77,11 @@ impl KafkaTopicTester {
             .expect("new topic should be present");
     }
 
-    async fn get_sink_with_writes(&self) -> KafkaSinkWithWrites {
+    async fn get_sink_with_writes(
+        &self,
+        schema: SchemaRef,
+        upsert_key: Vec<String>,
+    ) -> KafkaSinkWithWrites {
         let mut kafka = KafkaSinkFunc {
             topic: self.topic.to_string(),
             bootstrap_servers: self.server.to_string(),
@@ -78,6 +90,12 @@ impl KafkaTopicTester {
             write_futures: vec![],
             client_config: HashMap::new(),
             serializer: ArrowSerializer::new(Format::Json(JsonFormat::default())),
+            key_serializer: ArrowSerializer::new(Format::Json(JsonFormat::default())),
+            upsert_key,
+            sticky_partitioning: false,
+            partition_count: None,
+            sticky_partition: None,
+            error_reporter: None,
         };
 
         let (_, control_rx) = channel(128);
@@ -91,11 +109,12 @@ impl KafkaTopicTester {
             control_rx,
             command_tx,
             1,
-            vec![ArroyoSchema::new_unkeyed(schema(), 0)],
+            vec![ArroyoSchema::new_unkeyed(schema, 0)],
             None,
             None,
             vec![vec![]],
             HashMap::new(),
+            HashMap::new(),
         )
         .await;
 
@@ -143,7 +162,9 @@ async fn test_kafka_checkpoint_flushes() {
     };
 
     kafka_topic_tester.create_topic("checkpoint", 1).await;
-    let mut sink_with_writes = kafka_topic_tester.get_sink_with_writes().await;
+    let mut sink_with_writes = kafka_topic_tester
+        .get_sink_with_writes(schema(), vec![])
+        .await;
     let mut consumer = kafka_topic_tester.get_consumer("0");
 
     for chunk in &(1u32..200).chunks(7) {
@@ -181,7 +202,9 @@ async fn test_kafka() {
     };
 
     kafka_topic_tester.create_topic("basic", 2).await;
-    let mut sink_with_writes = kafka_topic_tester.get_sink_with_writes().await;
+    let mut sink_with_writes = kafka_topic_tester
+        .get_sink_with_writes(schema(), vec![])
+        .await;
     let mut consumer = kafka_topic_tester.get_consumer("1");
 
     for message in 1u32..20 {
@@ -204,3 +227,52 @@ async fn test_kafka() {
         assert_eq!(message, result.value);
     }
 }
+
+#[tokio::test]
+async fn test_kafka_upsert_tombstones() {
+    let mut kafka_topic_tester = KafkaTopicTester {
+        topic: "arroyo-sink-upsert".to_string(),
+        server: "0.0.0.0:9092".to_string(),
+    };
+
+    kafka_topic_tester.create_topic("upsert", 1).await;
+    let mut sink_with_writes = kafka_topic_tester
+        .get_sink_with_writes(updating_schema(), vec!["value".to_string()])
+        .await;
+    let mut consumer = kafka_topic_tester.get_consumer("2");
+
+    // an upsert of key 1, followed by its retraction
+    let batch = RecordBatch::try_new(
+        updating_schema(),
+        vec![
+            Arc::new(UInt32Array::from(vec![1, 1])),
+            Arc::new(BooleanArray::from(vec![false, true])),
+        ],
+    )
+    .unwrap();
+
+    sink_with_writes
+        .sink
+        .process_batch(batch, &mut sink_with_writes.ctx)
+        .await;
+    sink_with_writes
+        .sink
+        .producer
+        .as_ref()
+        .unwrap()
+        .flush(Duration::from_secs(3))
+        .unwrap();
+
+    let upsert = consumer.recv().await.unwrap().detach();
+    let key: TestData = serde_json::from_slice(upsert.key().unwrap()).unwrap();
+    assert_eq!(key.value, 1);
+    let value: TestData = serde_json::from_slice(upsert.payload().unwrap()).unwrap();
+    assert_eq!(value.value, 1);
+
+    let delete = consumer.recv().await.unwrap().detach();
+    assert_eq!(delete.key(), upsert.key());
+    assert!(
+        delete.payload().is_none(),
+        "retractions should be written as tombstones"
+    );
+}
@@ -1,39 +1,60 @@
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::schema_resolver::SchemaResolver;
-use arroyo_rpc::{grpc::StopMode, ControlMessage, ControlResp};
+use arroyo_rpc::{grpc::StopMode, ControlMessage, PartitionWatermarks};
 
+use arroyo_metrics::SourceLagGauges;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
+use arroyo_operator::trace_context;
+use arroyo_operator::watermarks::PartitionWatermarkTracker;
 use arroyo_operator::SourceFinishType;
 use arroyo_types::*;
 use async_trait::async_trait;
 use bincode::{Decode, Encode};
 use governor::{Quota, RateLimiter as GovernorRateLimiter};
 use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::Headers;
 use rdkafka::{ClientConfig, Message as KMessage, Offset, TopicPartitionList};
 use std::collections::HashMap;
 use std::num::NonZeroU32;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tokio::select;
+use tokio::sync::mpsc::Sender;
 use tokio::time::MissedTickBehavior;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, warn, Instrument};
+
+use super::error_category;
 
 #[cfg(test)]
 mod test;
 
+/// How often the end offsets of the partitions are fetched to compute consumer lag
+const LAG_INTERVAL: Duration = Duration::from_secs(10);
+const LAG_FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct KafkaSourceFunc {
     pub topic: String,
     pub bootstrap_servers: String,
     pub group_id: Option<String>,
     pub group_id_prefix: Option<String>,
     pub offset_mode: super::SourceOffset,
+    /// When starting without state, begin at the first message at or after this timestamp
+    pub start_timestamp_millis: Option<i64>,
+    /// When starting without state, begin these partitions at these offsets
+    pub start_offsets: HashMap<i32, i64>,
+    /// Whether to commit offsets to the consumer group on checkpoints. The committed offsets
+    /// only report progress to external tools; on restore, offsets come from the checkpoint.
+    pub commit_offsets: bool,
     pub format: Format,
     pub framing: Option<Framing>,
     pub bad_data: Option<BadData>,
     pub schema_resolver: Arc<dyn SchemaResolver + Sync>,
     pub client_configs: HashMap<String, String>,
+    /// When set, watermarks are generated in the source for each partition
+    pub partition_watermarks: Option<PartitionWatermarks>,
     pub messages_per_second: NonZeroU32,
 }
 
@@ -43,6 +64,67 @@ pub struct KafkaState {
     offset: i64,
 }
 
+/// The end of a partition, along with the consumer's position in it, as of the last lag fetch
+#[derive(Debug)]
+struct PartitionEnd {
+    partition: i32,
+    position: Option<i64>,
+    high_watermark: i64,
+}
+
+/// Fetches the end offsets of the assigned partitions on a blocking thread, as the client's
+/// metadata calls are synchronous, and sends them back to the source
+fn fetch_partition_ends(
+    consumer: Arc<StreamConsumer>,
+    topic: String,
+    tx: Sender<Vec<PartitionEnd>>,
+) {
+    tokio::task::spawn_blocking(move || {
+        let positions: HashMap<i32, i64> = consumer
+            .position()
+            .map(|tpl| {
+                tpl.elements()
+                    .iter()
+                    .filter_map(|e| match e.offset() {
+                        Offset::Offset(o) => Some((e.partition(), o)),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let ends = consumer
+            .assignment()
+            .map(|tpl| {
+                tpl.elements()
+                    .iter()
+                    .filter_map(|e| {
+                        match consumer.fetch_watermarks(&topic, e.partition(), LAG_FETCH_TIMEOUT) {
+                            Ok((_, high_watermark)) => Some(PartitionEnd {
+                                partition: e.partition(),
+                                position: positions.get(&e.partition()).copied(),
+                                high_watermark,
+                            }),
+                            Err(err) => {
+                                debug!(
+                                    "failed to fetch watermarks for {}-{}: {:?}",
+                                    topic,
+                                    e.partition(),
+                                    err
+                                );
+                                None
+                            }
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // always respond, so that the source knows it can fetch again
+        let _ = tx.blocking_send(ends);
+    });
+}
+
 impl KafkaSourceFunc {
     async fn get_consumer(&mut self, ctx: &mut ArrowContext) -> anyhow::Result<StreamConsumer> {
         info!("Creating kafka consumer for {}", self.bootstrap_servers);
@@ -95,7 +177,7 @@ impl KafkaSourceFunc {
 
         info!("Fetched metadata for topic {}", self.topic);
 
-        let our_partitions: HashMap<_, _> = {
+        let mut our_partitions: HashMap<_, _> = {
             let partitions = metadata.topics()[0].partitions();
             partitions
                 .iter()
@@ -110,6 +192,8 @@ impl KafkaSourceFunc {
                                 // if we've restored partitions and we don't know about this one, that means it's
                                 // new, and we want to start from the beginning so we don't drop data
                                 Offset::Beginning
+                            } else if let Some(offset) = self.start_offsets.get(&p.id()) {
+                                Offset::Offset(*offset)
                             } else {
                                 self.offset_mode.get_offset()
                             }
@@ -120,6 +204,19 @@ impl KafkaSourceFunc {
                 .collect()
         };
 
+        if let (Some(timestamp), false) = (self.start_timestamp_millis, has_state) {
+            let mut lookup = TopicPartitionList::new();
+            for (topic, partition) in our_partitions.keys() {
+                lookup.add_partition_offset(topic, *partition, Offset::Offset(timestamp))?;
+            }
+
+            // partitions without a message at or after the timestamp come back as Offset::End
+            let found = consumer.offsets_for_times(lookup, Duration::from_secs(30))?;
+            for elem in found.elements() {
+                our_partitions.insert((elem.topic().to_string(), elem.partition()), elem.offset());
+            }
+        }
+
         info!(
             "partition map for {}-{}: {:?}",
             self.topic, ctx.task_info.task_index, our_partitions
@@ -133,14 +230,22 @@ impl KafkaSourceFunc {
     }
 
     async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
-        let consumer = self
-            .get_consumer(ctx)
-            .await
-            .map_err(|e| UserError::new("Could not create Kafka consumer", format!("{:?}", e)))?;
+        let consumer =
+            Arc::new(self.get_consumer(ctx).await.map_err(|e| {
+                UserError::new("Could not create Kafka consumer", format!("{:?}", e))
+            })?);
 
         let rate_limiter = GovernorRateLimiter::direct(Quota::per_second(self.messages_per_second));
         let mut offsets = HashMap::new();
 
+        let mut watermarks = self.partition_watermarks.as_ref().map(|config| {
+            let mut tracker = PartitionWatermarkTracker::new(config);
+            for elem in consumer.assignment().unwrap().elements() {
+                tracker.add_partition(elem.partition());
+            }
+            tracker
+        });
+
         if consumer.assignment().unwrap().count() == 0 {
             warn!("Kafka Consumer {}-{} is subscribed to no partitions, as there are more subtasks than partitions... setting idle",
                 ctx.task_info.operator_id, ctx.task_info.task_index);
@@ -160,6 +265,20 @@ impl KafkaSourceFunc {
         let mut flush_ticker = tokio::time::interval(Duration::from_millis(50));
         flush_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
+        let propagate_trace_context = trace_context::propagation_enabled();
+        // the trace context of the first traced message in the current batch
+        let mut traceparent = None;
+        let mut reported_auth_error = false;
+
+        // the timestamp of the last message read from each partition, from which the time lag
+        // is estimated
+        let mut timestamps: HashMap<i32, i64> = HashMap::new();
+        let mut lag_gauges = SourceLagGauges::new(&ctx.task_info);
+        let (lag_tx, mut lag_rx) = tokio::sync::mpsc::channel(1);
+        let mut lag_ticker = tokio::time::interval(LAG_INTERVAL);
+        lag_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut fetching_lag = false;
+
         loop {
             select! {
                 message = consumer.recv() => {
@@ -170,24 +289,70 @@ impl KafkaSourceFunc {
                                     .ok_or_else(|| UserError::new("Failed to read timestamp from Kafka record",
                                         "The message read from Kafka did not contain a message timestamp"))?;
 
+                                if propagate_trace_context && traceparent.is_none() {
+                                    traceparent = read_traceparent(&msg);
+                                }
+
                                 ctx.deserialize_slice(v, from_millis(timestamp as u64)).await?;
 
+                                if let Some(watermarks) = &mut watermarks {
+                                    watermarks.observe(&msg.partition(), from_millis(timestamp as u64));
+                                }
+
                                 if ctx.should_flush() {
-                                    ctx.flush_buffer().await?;
+                                    self.flush(ctx, traceparent.take()).await?;
                                 }
 
                                 offsets.insert(msg.partition(), msg.offset());
+                                timestamps.insert(msg.partition(), timestamp);
                                 rate_limiter.until_ready().await;
                             }
                         },
                         Err(err) => {
-                            error!("encountered error {}", err)
+                            error!("encountered error {}", err);
+
+                            // the client retries on its own, but credential problems won't resolve
+                            // without intervention, so surface them to the user once
+                            let category = error_category(&err);
+                            if category == ErrorCategory::ConnectorAuth && !reported_auth_error {
+                                ctx.report_error(category, "Kafka authentication failed", err.to_string()).await;
+                                reported_auth_error = true;
+                            }
                         }
                     }
                 }
                 _ = flush_ticker.tick() => {
                     if ctx.should_flush() {
-                        ctx.flush_buffer().await?;
+                        self.flush(ctx, traceparent.take()).await?;
+                    }
+
+                    if let Some(watermark) = watermarks.as_mut().and_then(|w| w.poll()) {
+                        // buffered data must go out ahead of the watermark that covers it
+                        self.flush(ctx, traceparent.take()).await?;
+                        ctx.broadcast(ArrowMessage::Signal(SignalMessage::Watermark(watermark))).await;
+                    }
+                }
+                _ = lag_ticker.tick(), if !fetching_lag => {
+                    fetching_lag = true;
+                    fetch_partition_ends(consumer.clone(), self.topic.clone(), lag_tx.clone());
+                }
+                Some(ends) = lag_rx.recv() => {
+                    fetching_lag = false;
+                    let now = to_millis(SystemTime::now()) as i64;
+                    for end in ends {
+                        // prefer the offset we've emitted over the client's position, which may
+                        // include messages that are still buffered
+                        let Some(next) = offsets.get(&end.partition).map(|o| o + 1).or(end.position) else {
+                            continue;
+                        };
+                        let records = (end.high_watermark - next).max(0);
+                        // if there's nothing left to read we're caught up; otherwise we're at
+                        // least as far behind as the last message we read
+                        let lag_ms = match timestamps.get(&end.partition) {
+                            Some(ts) if records > 0 => (now - ts).max(0),
+                            _ => 0,
+                        };
+                        lag_gauges.set(end.partition, records, lag_ms);
                     }
                 }
                 control_message = ctx.control_rx.recv() => {
@@ -202,14 +367,17 @@ impl KafkaSourceFunc {
                                     partition: *partition,
                                     offset: *offset + 1,
                                 }).await;
+                                // like the checkpointed offset, the committed offset is the next one to read
                                 topic_partitions.add_partition_offset(
-                                    &self.topic, *partition, Offset::Offset(*offset)).unwrap();
+                                    &self.topic, *partition, Offset::Offset(*offset + 1)).unwrap();
                             }
 
-                            if let Err(e) = consumer.commit(&topic_partitions, CommitMode::Async) {
-                                // This is just used for progress tracking for metrics, so it's not a fatal error if it
-                                // fails. The actual offset is stored in state.
-                                warn!("Failed to commit offset to Kafka {:?}", e);
+                            if self.commit_offsets && topic_partitions.count() > 0 {
+                                if let Err(e) = consumer.commit(&topic_partitions, CommitMode::Async) {
+                                    // This is just used for progress tracking by external tools, so it's not a
+                                    // fatal error if it fails. The actual offset is stored in state.
+                                    warn!("Failed to commit offset to Kafka {:?}", e);
+                                }
                             }
                             if self.start_checkpoint(c, ctx).await {
                                 return Ok(SourceFinishType::Immediate);
@@ -233,7 +401,10 @@ impl KafkaSourceFunc {
                         Some(ControlMessage::LoadCompacted {compacted}) => {
                             ctx.load_compacted(compacted).await;
                         }
-                        Some(ControlMessage::NoOp) => {}
+                        Some(ControlMessage::UpdateParameters { parameters }) => {
+                            ctx.update_parameters(&parameters);
+                        }
+                        Some(ControlMessage::NoOp | ControlMessage::AlignWatermark { .. }) => {}
                         None => {
 
                         }
@@ -242,6 +413,32 @@ impl KafkaSourceFunc {
             }
         }
     }
+
+    async fn flush(
+        &self,
+        ctx: &mut ArrowContext,
+        traceparent: Option<String>,
+    ) -> Result<(), UserError> {
+        let span = tracing::trace_span!(
+            "kafka_read",
+            topic = self.topic,
+            subtask_idx = ctx.task_info.task_index
+        );
+
+        if let Some(traceparent) = traceparent {
+            trace_context::set_parent(&span, &traceparent);
+        }
+
+        ctx.flush_buffer().instrument(span).await
+    }
+}
+
+fn read_traceparent<M: KMessage>(msg: &M) -> Option<String> {
+    msg.headers()?
+        .iter()
+        .find(|h| h.key == trace_context::TRACEPARENT_HEADER)
+        .and_then(|h| h.value)
+        .and_then(|v| String::from_utf8(v.to_vec()).ok())
 }
 
 #[async_trait]
@@ -250,15 +447,7 @@ impl SourceOperator for KafkaSourceFunc {
         match self.run_int(ctx).await {
             Ok(r) => r,
             Err(e) => {
-                ctx.control_tx
-                    .send(ControlResp::Error {
-                        operator_id: ctx.task_info.operator_id.clone(),
-                        task_index: ctx.task_info.task_index,
-                        message: e.name.clone(),
-                        details: e.details.clone(),
-                    })
-                    .await
-                    .unwrap();
+                ctx.report_source_error(e.clone()).await;
 
                 panic!("{}: {}", e.name, e.details);
             }
@@ -82,11 +82,15 @@ impl KafkaTopicTester {
             group_id: self.group_id.clone(),
             group_id_prefix: None,
             offset_mode: SourceOffset::Earliest,
+            start_timestamp_millis: None,
+            start_offsets: HashMap::new(),
+            commit_offsets: true,
             format: Format::RawString(RawStringFormat {}),
             framing: None,
             bad_data: None,
             schema_resolver: Arc::new(FailingSchemaResolver::new()),
             client_configs: HashMap::new(),
+            partition_watermarks: None,
             messages_per_second: NonZeroU32::new(100).unwrap(),
         });
 
@@ -124,6 +128,7 @@ impl KafkaTopicTester {
             None,
             vec![vec![data_tx]],
             kafka.tables(),
+            HashMap::new(),
         )
         .await;
 
@@ -10,10 +10,11 @@ use serde::{Deserialize, Serialize};
 
 use crate::{pull_opt, pull_option_to_i64, ConnectionSchema, ConnectionType, EmptyConfig};
 
-use crate::kinesis::sink::{FlushConfig, KinesisSinkFunc};
+use crate::kinesis::sink::{FlushConfig, KinesisSinkFunc, RecordAggregator};
 use crate::kinesis::source::KinesisSourceFunc;
 use arroyo_operator::connector::Connector;
 use arroyo_operator::operator::OperatorNode;
+use arroyo_operator::watermarks::PartitionWatermarkTracker;
 
 const TABLE_SCHEMA: &str = include_str!("./table.json");
 const ICON: &str = include_str!("./kinesis.svg");
@@ -76,6 +77,10 @@ impl Connector for KinesisConnector {
         }
     }
 
+    fn supports_partition_watermarks(&self) -> bool {
+        true
+    }
+
     fn from_config(
         &self,
         id: Option<i64>,
@@ -108,6 +113,10 @@ impl Connector for KinesisConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            filter: None,
+            delivery_semantics: None,
+            upsert_key: vec![],
+            partition_watermarks: None,
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
@@ -149,10 +158,20 @@ impl Connector for KinesisConnector {
                 let batch_max_buffer_size =
                     pull_option_to_i64("sink.max_bytes_per_batch", options)?;
                 let records_per_batch = pull_option_to_i64("sink.max_records_per_batch", options)?;
+                let aggregation = match options.remove("sink.aggregation").as_deref() {
+                    Some("true") => Some(true),
+                    Some("false") => Some(false),
+                    None => None,
+                    Some(other) => bail!("invalid value for sink.aggregation '{}'", other),
+                };
+                let aggregation_max_bytes =
+                    pull_option_to_i64("sink.aggregation_max_bytes", options)?;
                 TableType::Sink {
                     batch_flush_interval_millis,
                     batch_max_buffer_size,
                     records_per_batch,
+                    aggregation,
+                    aggregation_max_bytes,
                 }
             }
             _ => {
@@ -188,12 +207,18 @@ impl Connector for KinesisConnector {
                         .ok_or_else(|| anyhow!("format required for kinesis source"))?,
                     framing: config.framing,
                     bad_data: config.bad_data,
+                    watermarks: config
+                        .partition_watermarks
+                        .as_ref()
+                        .map(PartitionWatermarkTracker::new),
                 })))
             }
             TableType::Sink {
                 batch_flush_interval_millis,
                 batch_max_buffer_size,
                 records_per_batch,
+                aggregation,
+                aggregation_max_bytes,
             } => {
                 let flush_config = FlushConfig::new(
                     batch_flush_interval_millis,
@@ -203,6 +228,9 @@ impl Connector for KinesisConnector {
                 Ok(OperatorNode::from_operator(Box::new(KinesisSinkFunc {
                     client: None,
                     in_progress_batch: None,
+                    aggregator: aggregation
+                        .unwrap_or(false)
+                        .then(|| RecordAggregator::new(aggregation_max_bytes)),
                     aws_region: table.aws_region,
                     name: table.stream_name,
                     serializer: ArrowSerializer::new(
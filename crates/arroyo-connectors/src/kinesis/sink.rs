@@ -12,6 +12,8 @@ use aws_sdk_kinesis::{
     client::fluent_builders::PutRecords, model::PutRecordsRequestEntry, types::Blob,
     Client as KinesisClient, Region,
 };
+use md5::{Digest, Md5};
+use prost::Message;
 use tracing::warn;
 use uuid::Uuid;
 
@@ -19,6 +21,8 @@ pub struct KinesisSinkFunc {
     pub client: Option<KinesisClient>,
     pub aws_region: Option<String>,
     pub in_progress_batch: Option<BatchRecordPreparer>,
+    /// if set, rows are packed into KPL aggregated records before being batched
+    pub aggregator: Option<RecordAggregator>,
     pub flush_config: FlushConfig,
     pub serializer: ArrowSerializer,
     pub name: String,
@@ -51,7 +55,14 @@ impl ArrowOperator for KinesisSinkFunc {
         };
 
         for v in self.serializer.serialize(&batch) {
-            batch_preparer.add_record(Uuid::new_v4().to_string(), v);
+            match &mut self.aggregator {
+                Some(aggregator) => {
+                    if let Some((key, record)) = aggregator.add(v) {
+                        batch_preparer.add_record(key, record);
+                    }
+                }
+                None => batch_preparer.add_record(Uuid::new_v4().to_string(), v),
+            }
         }
 
         if self.flush_config.should_flush(&batch_preparer) {
@@ -64,6 +75,7 @@ impl ArrowOperator for KinesisSinkFunc {
     }
 
     async fn handle_checkpoint(&mut self, _: CheckpointBarrier, _: &mut ArrowContext) {
+        self.take_aggregated().await;
         if let Some(batch_preparer) = self.in_progress_batch.take() {
             batch_preparer
                 .flush()
@@ -73,6 +85,15 @@ impl ArrowOperator for KinesisSinkFunc {
     }
 
     async fn handle_tick(&mut self, _: u64, _ctx: &mut ArrowContext) {
+        // rows waiting to be aggregated are subject to the same flush interval as batches
+        if self
+            .aggregator
+            .as_ref()
+            .is_some_and(|a| a.age() >= self.flush_config.max_age)
+        {
+            self.take_aggregated().await;
+        }
+
         let Some(batch_preparer) = &self.in_progress_batch else {
             return;
         };
@@ -111,6 +132,17 @@ impl KinesisSinkFunc {
         }
     }
 
+    /// Moves the partially-filled aggregated record, if any, into the in-progress batch
+    async fn take_aggregated(&mut self) {
+        let Some((key, record)) = self.aggregator.as_mut().and_then(|a| a.finish()) else {
+            return;
+        };
+
+        let mut batch_preparer = self.take_or_create_batch_preparer().await;
+        batch_preparer.add_record(key, record);
+        self.in_progress_batch = Some(batch_preparer);
+    }
+
     async fn take_or_create_batch_preparer(&mut self) -> BatchRecordPreparer {
         match self.in_progress_batch.take() {
             None => BatchRecordPreparer::new(
@@ -134,10 +166,129 @@ pub struct BatchRecordPreparer {
     creation_time: SystemTime,
 }
 
+/// The magic number that starts a KPL aggregated record
+const KPL_MAGIC: [u8; 4] = [0xF3, 0x89, 0x9A, 0xC2];
+const KPL_DIGEST_SIZE: usize = 16;
+const DEFAULT_AGGREGATION_MAX_BYTES: usize = 51_200;
+
+/// An aggregated record in the format written by the Kinesis Producer Library, so that it can be
+/// read by the KCL and the KPL deaggregation libraries
+#[derive(Clone, PartialEq, Message)]
+struct AggregatedRecord {
+    #[prost(string, repeated, tag = "1")]
+    partition_key_table: Vec<String>,
+    #[prost(string, repeated, tag = "2")]
+    explicit_hash_key_table: Vec<String>,
+    #[prost(message, repeated, tag = "3")]
+    records: Vec<AggregatedEntry>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AggregatedEntry {
+    #[prost(uint64, required, tag = "1")]
+    partition_key_index: u64,
+    #[prost(uint64, optional, tag = "2")]
+    explicit_hash_key_index: Option<u64>,
+    #[prost(bytes = "vec", required, tag = "3")]
+    data: Vec<u8>,
+}
+
+/// Packs rows into KPL aggregated records of up to a maximum size. Each aggregated record gets a
+/// random partition key, which its rows share.
+pub struct RecordAggregator {
+    max_bytes: usize,
+    record: AggregatedRecord,
+    size: usize,
+    first_added: SystemTime,
+}
+
+impl RecordAggregator {
+    pub fn new(max_bytes: Option<i64>) -> Self {
+        let mut aggregator = Self {
+            max_bytes: max_bytes
+                .map(|b| b as usize)
+                .unwrap_or(DEFAULT_AGGREGATION_MAX_BYTES),
+            record: AggregatedRecord::default(),
+            size: 0,
+            first_added: SystemTime::now(),
+        };
+        aggregator.reset();
+        aggregator
+    }
+
+    fn reset(&mut self) {
+        let key = Uuid::new_v4().to_string();
+        self.record = AggregatedRecord {
+            partition_key_table: vec![key],
+            explicit_hash_key_table: vec![],
+            records: vec![],
+        };
+        self.size = KPL_MAGIC.len() + KPL_DIGEST_SIZE + self.record.encoded_len();
+    }
+
+    /// How long the oldest row in the current aggregated record has been waiting
+    fn age(&self) -> Duration {
+        if self.record.records.is_empty() {
+            Duration::ZERO
+        } else {
+            self.first_added.elapsed().unwrap_or_default()
+        }
+    }
+
+    /// Adds a row, returning the previous aggregated record and its partition key if the row
+    /// doesn't fit in it
+    pub fn add(&mut self, data: Vec<u8>) -> Option<(String, Vec<u8>)> {
+        let entry = AggregatedEntry {
+            partition_key_index: 0,
+            explicit_hash_key_index: None,
+            data,
+        };
+        let entry_size = prost::encoding::message::encoded_len(3, &entry);
+
+        let finished = if !self.record.records.is_empty() && self.size + entry_size > self.max_bytes
+        {
+            self.finish()
+        } else {
+            None
+        };
+
+        if self.record.records.is_empty() {
+            self.first_added = SystemTime::now();
+        }
+        self.record.records.push(entry);
+        self.size += entry_size;
+
+        finished
+    }
+
+    /// Returns the current aggregated record and its partition key, if it has any rows
+    pub fn finish(&mut self) -> Option<(String, Vec<u8>)> {
+        if self.record.records.is_empty() {
+            return None;
+        }
+
+        let mut record = std::mem::take(&mut self.record);
+        self.reset();
+        let key = record.partition_key_table[0].clone();
+
+        // a single row is written as-is, which deaggregators pass through unchanged
+        if record.records.len() == 1 {
+            return Some((key, record.records.remove(0).data));
+        }
+
+        let body = record.encode_to_vec();
+        let mut data = Vec::with_capacity(KPL_MAGIC.len() + body.len() + KPL_DIGEST_SIZE);
+        data.extend_from_slice(&KPL_MAGIC);
+        data.extend_from_slice(&body);
+        data.extend_from_slice(&Md5::digest(&body));
+        Some((key, data))
+    }
+}
+
 pub struct FlushConfig {
     max_record_count: usize,
     max_data_size: usize,
-    max_age: Duration,
+    pub max_age: Duration,
 }
 
 impl FlushConfig {
@@ -215,3 +366,51 @@ impl BatchRecordPreparer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deaggregate(data: &[u8]) -> Vec<Vec<u8>> {
+        let Some(rest) = data.strip_prefix(&KPL_MAGIC[..]) else {
+            return vec![data.to_vec()];
+        };
+        let (body, digest) = rest.split_at(rest.len() - KPL_DIGEST_SIZE);
+        assert_eq!(&Md5::digest(body)[..], digest);
+
+        let record = AggregatedRecord::decode(body).unwrap();
+        assert_eq!(record.partition_key_table.len(), 1);
+        record.records.into_iter().map(|r| r.data).collect()
+    }
+
+    #[test]
+    fn test_aggregation() {
+        let mut aggregator = RecordAggregator::new(Some(100));
+        let rows: Vec<Vec<u8>> = (0..10).map(|i| format!("row-{:0>10}", i).into()).collect();
+
+        let mut records = vec![];
+        for row in &rows {
+            records.extend(aggregator.add(row.clone()));
+        }
+        records.extend(aggregator.finish());
+        assert!(aggregator.finish().is_none());
+
+        assert!(records.len() > 1 && records.len() < rows.len());
+        for (_, data) in &records {
+            assert!(data.len() <= 100);
+        }
+
+        let deaggregated: Vec<_> = records
+            .iter()
+            .flat_map(|(_, data)| deaggregate(data))
+            .collect();
+        assert_eq!(deaggregated, rows);
+    }
+
+    #[test]
+    fn test_single_row_is_not_aggregated() {
+        let mut aggregator = RecordAggregator::new(None);
+        assert!(aggregator.add(b"only".to_vec()).is_none());
+        assert_eq!(aggregator.finish().unwrap().1, b"only".to_vec());
+    }
+}
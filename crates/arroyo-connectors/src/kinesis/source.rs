@@ -9,13 +9,15 @@ use std::{
 use anyhow::{anyhow, bail, Context as AnyhowContext, Result};
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
+use arroyo_operator::watermarks::PartitionWatermarkTracker;
 use arroyo_operator::SourceFinishType;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::{grpc::StopMode, ControlMessage};
 use arroyo_state::global_table_config;
 use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
-use arroyo_types::{from_nanos, UserError};
+use arroyo_types::{from_nanos, ArrowMessage, SignalMessage, UserError};
 use async_trait::async_trait;
 use aws_config::from_env;
 use aws_sdk_kinesis::{
@@ -52,6 +54,8 @@ pub struct KinesisSourceFunc {
     pub aws_region: Option<String>,
     pub shards: HashMap<String, ShardState>,
     pub offset: SourceOffset,
+    /// When set, watermarks are generated in the source for each shard
+    pub watermarks: Option<PartitionWatermarkTracker<String>>,
 }
 
 #[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
@@ -179,9 +183,9 @@ impl SourceOperator for KinesisSourceFunc {
     async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
         match self.run_int(ctx).await {
             Ok(r) => r,
-            Err(UserError { name, details, .. }) => {
-                ctx.report_error(name.clone(), details.clone()).await;
-                panic!("{}: {}", name, details);
+            Err(e) => {
+                ctx.report_source_error(e.clone()).await;
+                panic!("{}: {}", e.name, e.details);
             }
         }
     }
@@ -215,6 +219,7 @@ impl KinesisSourceFunc {
             futures.push(
                 shard_state.get_update_shard_iterator_future(self.kinesis_client.as_ref().unwrap()),
             );
+            self.add_watermark_shard(&shard_state);
             self.shards.insert(shard_id, shard_state);
         }
         let new_futures = self.sync_shards(ctx).await?;
@@ -251,6 +256,7 @@ impl KinesisSourceFunc {
             Some(shard_iterator) => Ok(Some(self.next_read_future(shard_id, shard_iterator))),
             None => {
                 shard_state.closed = true;
+                self.close_watermark_shard(&shard_id);
                 Ok(None)
             }
         }
@@ -316,7 +322,7 @@ impl KinesisSourceFunc {
                 .map(|record| record.sequence_number().unwrap().to_owned())
         });
 
-        let next_shard_iterator = self.process_records(get_records, ctx).await?;
+        let next_shard_iterator = self.process_records(&shard_id, get_records, ctx).await?;
         let shard_state = self.shards.get_mut(&shard_id).unwrap();
 
         if let Some(last_sequence_number) = last_sequence_number {
@@ -327,6 +333,7 @@ impl KinesisSourceFunc {
             Some(shard_iterator_id) => Ok(Some(self.next_read_future(shard_id, shard_iterator_id))),
             None => {
                 shard_state.closed = true;
+                self.close_watermark_shard(&shard_id);
                 Ok(None)
             }
         }
@@ -341,6 +348,22 @@ impl KinesisSourceFunc {
         )))
     }
 
+    fn add_watermark_shard(&mut self, shard_state: &ShardState) {
+        if let Some(watermarks) = &mut self.watermarks {
+            if !shard_state.closed {
+                watermarks.add_partition(shard_state.shard_id.clone());
+            }
+        }
+    }
+
+    /// A closed shard has no more data, so it no longer holds back the watermark; its data
+    /// continues in the child shards created by the split or merge that closed it
+    fn close_watermark_shard(&mut self, shard_id: &String) {
+        if let Some(watermarks) = &mut self.watermarks {
+            watermarks.remove_partition(shard_id);
+        }
+    }
+
     async fn init_client(&mut self) {
         let mut loader = from_env();
         if let Some(region) = &self.aws_region {
@@ -381,10 +404,16 @@ impl KinesisSourceFunc {
                     if ctx.should_flush() {
                         ctx.flush_buffer().await?;
                     }
+
+                    if let Some(watermark) = self.watermarks.as_mut().and_then(|w| w.poll()) {
+                        // buffered data must go out ahead of the watermark that covers it
+                        ctx.flush_buffer().await?;
+                        ctx.broadcast(ArrowMessage::Signal(SignalMessage::Watermark(watermark))).await;
+                    }
                     match self.sync_shards(ctx).await {
                         Err(err) => {
                             warn!("failed to sync shards: {}", err);
-                            ctx.report_error("failed to sync shards".to_string(), err.to_string()).await;
+                            ctx.report_error(ErrorCategory::Connector, "failed to sync shards".to_string(), err.to_string()).await;
                         },
                         Ok(new_futures) => {
                             futures.extend(new_futures.into_iter());
@@ -421,7 +450,10 @@ impl KinesisSourceFunc {
                         Some(ControlMessage::LoadCompacted { compacted }) => {
                             ctx.load_compacted(compacted).await;
                         },
-                        Some(ControlMessage::NoOp ) => {}
+                        Some(ControlMessage::UpdateParameters { parameters }) => {
+                            ctx.update_parameters(&parameters);
+                        }
+                        Some(ControlMessage::NoOp | ControlMessage::AlignWatermark { .. }) => {}
                         None => {
                         }
                     }
@@ -432,16 +464,21 @@ impl KinesisSourceFunc {
 
     async fn process_records(
         &mut self,
+        shard_id: &String,
         get_records_output: GetRecordsOutput,
         ctx: &mut ArrowContext,
     ) -> Result<Option<String>, UserError> {
         let records = get_records_output.records.unwrap_or_default();
         for record in records {
             let data = record.data.unwrap().into_inner();
-            let timestamp = record.approximate_arrival_timestamp.unwrap();
+            let timestamp =
+                from_nanos(record.approximate_arrival_timestamp.unwrap().as_nanos() as u128);
 
-            ctx.deserialize_slice(&data, from_nanos(timestamp.as_nanos() as u128))
-                .await?;
+            ctx.deserialize_slice(&data, timestamp).await?;
+
+            if let Some(watermarks) = &mut self.watermarks {
+                watermarks.observe(shard_id, timestamp);
+            }
 
             if ctx.should_flush() {
                 ctx.flush_buffer().await?
@@ -472,6 +509,7 @@ impl KinesisSourceFunc {
             futures.push(
                 shard_state.get_update_shard_iterator_future(self.kinesis_client.as_ref().unwrap()),
             );
+            self.add_watermark_shard(&shard_state);
             self.shards.insert(shard_id, shard_state);
         }
         Ok(futures)
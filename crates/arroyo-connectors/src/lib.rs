@@ -16,7 +16,9 @@ use arroyo_rpc::api_types::connections::{
 use arroyo_rpc::primitive_to_sql;
 use arroyo_rpc::var_str::VarStr;
 use arroyo_types::string_to_map;
+use audit::AuditConnector;
 use blackhole::BlackholeConnector;
+use flight::FlightConnector;
 use fluvio::FluvioConnector;
 use impulse::ImpulseConnector;
 use nats::NatsConnector;
@@ -33,9 +35,11 @@ use websocket::WebsocketConnector;
 
 use self::kafka::KafkaConnector;
 
+pub mod audit;
 pub mod blackhole;
 pub mod confluent;
 pub mod filesystem;
+pub mod flight;
 pub mod fluvio;
 pub mod impulse;
 pub mod kafka;
@@ -43,20 +47,24 @@ pub mod kinesis;
 pub mod mqtt;
 pub mod nats;
 pub mod nexmark;
+pub mod plugin;
 pub mod polling_http;
 pub mod preview;
 pub mod redis;
 pub mod single_file;
 pub mod sse;
+pub mod two_phase_committer;
 pub mod webhook;
 pub mod websocket;
 
-pub fn connectors() -> HashMap<&'static str, Box<dyn ErasedConnector>> {
+fn builtin_connectors() -> HashMap<&'static str, Box<dyn ErasedConnector>> {
     let connectors: Vec<Box<dyn ErasedConnector>> = vec![
+        Box::new(AuditConnector {}),
         Box::new(BlackholeConnector {}),
         Box::new(ConfluentConnector {}),
         Box::new(DeltaLakeConnector {}),
         Box::new(FileSystemConnector {}),
+        Box::new(FlightConnector {}),
         Box::new(FluvioConnector {}),
         Box::new(ImpulseConnector {}),
         Box::new(KafkaConnector {}),
@@ -76,6 +84,18 @@ pub fn connectors() -> HashMap<&'static str, Box<dyn ErasedConnector>> {
     connectors.into_iter().map(|c| (c.name(), c)).collect()
 }
 
+pub fn connectors() -> HashMap<&'static str, Box<dyn ErasedConnector>> {
+    let mut connectors = builtin_connectors();
+    // plugins whose ids clash with a built-in connector are rejected when they're loaded, and
+    // are never allowed to replace it
+    for plugin in plugin::plugins() {
+        connectors
+            .entry(plugin.id())
+            .or_insert_with(|| Box::new(plugin::PluginConnector::new(plugin.clone())));
+    }
+    connectors
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct EmptyConfig {}
 
@@ -174,3 +194,44 @@ pub fn header_map(headers: Option<VarStr>) -> HashMap<String, String> {
     )
     .expect("Invalid header map")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_rpc::DeliverySemantics;
+
+    #[test]
+    fn test_delivery_semantics_support() {
+        let connectors = builtin_connectors();
+        let all = [
+            DeliverySemantics::ExactlyOnce,
+            DeliverySemantics::AtLeastOnce,
+            DeliverySemantics::AtMostOnce,
+        ];
+
+        for name in ["kafka", "confluent", "filesystem"] {
+            for semantics in all {
+                assert!(
+                    connectors[name].supports_delivery_semantics(semantics),
+                    "{} should support {}",
+                    name,
+                    semantics
+                );
+            }
+        }
+
+        assert!(connectors["delta"].supports_delivery_semantics(DeliverySemantics::ExactlyOnce));
+        assert!(!connectors["delta"].supports_delivery_semantics(DeliverySemantics::AtLeastOnce));
+
+        for name in ["webhook", "redis", "kinesis", "nats", "mqtt", "fluvio"] {
+            for semantics in all {
+                assert!(
+                    !connectors[name].supports_delivery_semantics(semantics),
+                    "{} should not support {}",
+                    name,
+                    semantics
+                );
+            }
+        }
+    }
+}
@@ -8,6 +8,7 @@ use crate::mqtt::MqttConfig;
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::formats::Format;
 use arroyo_rpc::ControlResp;
 use rumqttc::v5::mqttbytes::QoS;
@@ -80,7 +81,8 @@ impl ArrowOperator for MqttSinkFunc {
                     return;
                 }
                 Err(e) => {
-                    ctx.report_error("Failed to connect", e.to_string()).await;
+                    ctx.report_error(ErrorCategory::Connector, "Failed to connect", e.to_string())
+                        .await;
                 }
             };
 
@@ -108,6 +110,7 @@ impl ArrowOperator for MqttSinkFunc {
                             task_index: ctx.task_info.task_index,
                             message: "Could not write to mqtt".to_string(),
                             details: format!("{:?}", e),
+                            category: ErrorCategory::Connector,
                         })
                         .await
                         .unwrap();
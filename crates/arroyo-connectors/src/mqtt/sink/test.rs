@@ -91,6 +91,7 @@ impl MqttTopicTester {
             None,
             vec![vec![]],
             HashMap::new(),
+            HashMap::new(),
         )
         .await;
 
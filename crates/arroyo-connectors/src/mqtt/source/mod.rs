@@ -6,7 +6,7 @@ use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 use arroyo_rpc::formats::{BadData, Format, Framing};
-use arroyo_rpc::{grpc::StopMode, ControlMessage, ControlResp};
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
 use arroyo_types::{ArrowMessage, SignalMessage, UserError, Watermark};
 use governor::{Quota, RateLimiter as GovernorRateLimiter};
 use rumqttc::v5::mqttbytes::QoS;
@@ -49,15 +49,7 @@ impl SourceOperator for MqttSourceFunc {
         match self.run_int(ctx).await {
             Ok(r) => r,
             Err(e) => {
-                ctx.control_tx
-                    .send(ControlResp::Error {
-                        operator_id: ctx.task_info.operator_id.clone(),
-                        task_index: ctx.task_info.task_index,
-                        message: e.name.clone(),
-                        details: e.details.clone(),
-                    })
-                    .await
-                    .unwrap();
+                ctx.report_source_error(e.clone()).await;
 
                 panic!("{}: {}", e.name, e.details);
             }
@@ -200,7 +192,10 @@ impl MqttSourceFunc {
                         Some(ControlMessage::LoadCompacted {compacted}) => {
                             ctx.load_compacted(compacted).await;
                         }
-                        Some(ControlMessage::NoOp) => {}
+                        Some(ControlMessage::UpdateParameters { parameters }) => {
+                            ctx.update_parameters(&parameters);
+                        }
+                        Some(ControlMessage::NoOp | ControlMessage::AlignWatermark { .. }) => {}
                         None => {
 
                         }
@@ -1,5 +1,5 @@
 use arrow::array::UInt64Array;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
@@ -152,6 +152,7 @@ impl MqttTopicTester {
             None,
             vec![vec![data_tx]],
             mqtt.tables(),
+            HashMap::new(),
         )
         .await;
 
@@ -5,6 +5,7 @@ use arrow::array::RecordBatch;
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::ControlMessage;
 use arroyo_rpc::ControlResp;
@@ -94,6 +95,7 @@ impl ArrowOperator for NatsSinkFunc {
                             task_index: ctx.task_info.task_index,
                             message: e.to_string(),
                             details: e.to_string(),
+                            category: ErrorCategory::Connector,
                         })
                         .await
                         .expect("Something went wrong, data will never be received.");
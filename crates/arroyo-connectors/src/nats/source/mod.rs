@@ -13,7 +13,6 @@ use arroyo_rpc::formats::{Format, Framing};
 use arroyo_rpc::grpc::StopMode;
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::ControlMessage;
-use arroyo_rpc::ControlResp;
 use arroyo_rpc::OperatorConfig;
 use arroyo_types::UserError;
 use async_nats::jetstream::consumer;
@@ -62,15 +61,7 @@ impl SourceOperator for NatsSourceFunc {
         match self.run_int(ctx).await {
             Ok(res) => res,
             Err(err) => {
-                ctx.control_tx
-                    .send(ControlResp::Error {
-                        operator_id: ctx.task_info.operator_id.clone(),
-                        task_index: ctx.task_info.task_index,
-                        message: err.name.clone(),
-                        details: err.details.clone(),
-                    })
-                    .await
-                    .unwrap();
+                ctx.report_source_error(err.clone()).await;
                 panic!("{}: {}", err.name, err.details);
             }
         }
@@ -473,7 +464,10 @@ impl NatsSourceFunc {
                                 Some(ControlMessage::LoadCompacted {compacted}) => {
                                     ctx.load_compacted(compacted).await;
                                 }
-                                Some(ControlMessage::NoOp) => {}
+                                Some(ControlMessage::UpdateParameters { parameters }) => {
+                                    ctx.update_parameters(&parameters);
+                                }
+                                Some(ControlMessage::NoOp | ControlMessage::AlignWatermark { .. }) => {}
                                 None => {}
                             }
                         }
@@ -530,7 +524,10 @@ impl NatsSourceFunc {
                                 Some(ControlMessage::LoadCompacted {compacted}) => {
                                     ctx.load_compacted(compacted).await;
                                 }
-                                Some(ControlMessage::NoOp) => {}
+                                Some(ControlMessage::UpdateParameters { parameters }) => {
+                                    ctx.update_parameters(&parameters);
+                                }
+                                Some(ControlMessage::NoOp | ControlMessage::AlignWatermark { .. }) => {}
                                 None => {}
                             }
                         }
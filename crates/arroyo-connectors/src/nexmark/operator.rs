@@ -2,7 +2,6 @@ use crate::nexmark::{auction_fields, bid_fields, person_fields, NexmarkTable};
 use arrow::array::{
     Int64Builder, RecordBatch, StringBuilder, StructBuilder, TimestampNanosecondBuilder,
 };
-use arroyo_formats::should_flush;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
 use arroyo_operator::SourceFinishType;
@@ -270,7 +269,10 @@ impl SourceOperator for NexmarkSourceFunc {
             next_event.bid.as_ref().write_into(&mut bid_builder);
             timestamp_builder.append_value(to_nanos(next_event.event_timetamp) as i64);
 
-            if should_flush(records, flush_time) {
+            if ctx
+                .current_batch_settings()
+                .should_flush(records, flush_time)
+            {
                 ctx.collect(
                     RecordBatch::try_new(
                         ctx.out_schema.as_ref().unwrap().schema.clone(),
@@ -284,6 +286,7 @@ impl SourceOperator for NexmarkSourceFunc {
                     .unwrap(),
                 )
                 .await;
+                ctx.adapt_batch_settings();
                 records = 0;
                 flush_time = Instant::now();
             }
@@ -321,6 +324,9 @@ impl SourceOperator for NexmarkSourceFunc {
                             }
                         }
                     }
+                    Ok(ControlMessage::UpdateParameters { parameters }) => {
+                        ctx.update_parameters(&parameters);
+                    }
                     Err(TryRecvError::Empty) => {}
                     x => {
                         warn!("{:?}", x);
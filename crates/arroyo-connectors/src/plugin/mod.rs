@@ -0,0 +1,523 @@
+//! Connectors loaded at runtime from plugins built with `arroyo-connector-sdk`.
+//!
+//! Plugins are dynamic libraries (`.so`, `.dylib` or `.dll`) in the directory configured by
+//! `connector-plugin-dir`, which must contain the same plugins on the API server and on every
+//! worker. Each plugin is loaded once per process, and its connector is registered alongside the
+//! built-in ones under the id from its metadata.
+
+mod operator;
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_connector_sdk::arrow::array::RecordBatch;
+use arroyo_connector_sdk::ffi::{
+    FfiBatch, FfiBytes, FfiHandle, FfiPoll, FfiResult, FfiSlice, POLL_BATCH, POLL_FINISHED,
+    POLL_IDLE,
+};
+use arroyo_connector_sdk::{OpenContext, PluginConfig, PluginMetadata, SourcePoll, ABI_VERSION};
+use arroyo_operator::connector::{Connection, Connector};
+use arroyo_operator::operator::OperatorNode;
+use arroyo_rpc::api_types::connections::{
+    ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
+};
+use arroyo_rpc::config::config;
+use arroyo_rpc::OperatorConfig;
+use dlopen2::utils::PLATFORM_FILE_EXTENSION;
+use dlopen2::wrapper::{Container, WrapperApi};
+use serde_json::Value;
+use tokio::sync::mpsc::Sender;
+use tracing::{error, info, warn};
+
+use crate::plugin::operator::{PluginSinkFunc, PluginSourceFunc};
+
+#[derive(WrapperApi)]
+struct ConnectorPluginApi {
+    __arroyo_connector_abi_version: unsafe extern "C-unwind" fn() -> u32,
+    __arroyo_connector_metadata: unsafe extern "C-unwind" fn() -> FfiResult,
+    __arroyo_connector_validate: unsafe extern "C-unwind" fn(config: FfiSlice) -> FfiResult,
+    __arroyo_source_open:
+        unsafe extern "C-unwind" fn(ctx: FfiSlice, state: FfiSlice, has_state: bool) -> FfiHandle,
+    __arroyo_source_poll:
+        unsafe extern "C-unwind" fn(handle: *mut c_void, timeout_micros: u64) -> FfiPoll,
+    __arroyo_source_checkpoint:
+        unsafe extern "C-unwind" fn(handle: *mut c_void, epoch: u32) -> FfiResult,
+    __arroyo_source_close: unsafe extern "C-unwind" fn(handle: *mut c_void),
+    __arroyo_sink_open: unsafe extern "C-unwind" fn(ctx: FfiSlice) -> FfiHandle,
+    __arroyo_sink_write:
+        unsafe extern "C-unwind" fn(handle: *mut c_void, batch: FfiBatch) -> FfiResult,
+    __arroyo_sink_flush: unsafe extern "C-unwind" fn(handle: *mut c_void, epoch: u32) -> FfiResult,
+    __arroyo_sink_close: unsafe extern "C-unwind" fn(handle: *mut c_void) -> FfiResult,
+    __arroyo_free_bytes: unsafe extern "C-unwind" fn(bytes: FfiBytes),
+}
+
+pub struct LoadedPlugin {
+    api: Container<ConnectorPluginApi>,
+    // leaked once per plugin, as connectors are identified by static names
+    id: &'static str,
+    metadata: PluginMetadata,
+}
+
+// copies bytes returned by the plugin, and hands them back to it to be freed
+fn take_bytes(api: &ConnectorPluginApi, bytes: FfiBytes) -> Vec<u8> {
+    let copied = unsafe { bytes.as_slice() }.to_vec();
+    unsafe { api.__arroyo_free_bytes(bytes) };
+    copied
+}
+
+fn take_result(api: &ConnectorPluginApi, result: FfiResult) -> Result<Vec<u8>, String> {
+    let ok = result.ok;
+    let bytes = take_bytes(api, result.bytes);
+    if ok {
+        Ok(bytes)
+    } else {
+        Err(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+impl LoadedPlugin {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let api: Container<ConnectorPluginApi> = unsafe { Container::load(path) }
+            .map_err(|e| anyhow!("unable to load connector plugin: {:?}", e))?;
+
+        let version = unsafe { api.__arroyo_connector_abi_version() };
+        if version != ABI_VERSION {
+            bail!(
+                "connector plugin was built for ABI version {}, but this version of Arroyo \
+                requires version {}; rebuild it with a matching arroyo-connector-sdk",
+                version,
+                ABI_VERSION
+            );
+        }
+
+        let metadata = take_result(&api, unsafe { api.__arroyo_connector_metadata() })
+            .map_err(|e| anyhow!("failed to get connector plugin metadata: {}", e))?;
+        let metadata: PluginMetadata = serde_json::from_slice(&metadata)
+            .map_err(|e| anyhow!("invalid connector plugin metadata: {}", e))?;
+
+        Ok(Self {
+            api,
+            id: metadata.id.clone().leak(),
+            metadata,
+        })
+    }
+
+    pub fn id(&self) -> &'static str {
+        self.id
+    }
+
+    pub fn metadata(&self) -> &PluginMetadata {
+        &self.metadata
+    }
+
+    fn take_bytes(&self, bytes: FfiBytes) -> Vec<u8> {
+        take_bytes(&self.api, bytes)
+    }
+
+    fn take_result(&self, result: FfiResult) -> Result<Vec<u8>, String> {
+        take_result(&self.api, result)
+    }
+
+    fn take_handle(&self, handle: FfiHandle) -> Result<*mut c_void, String> {
+        let error = self.take_bytes(handle.error);
+        if handle.handle.is_null() {
+            Err(String::from_utf8_lossy(&error).into_owned())
+        } else {
+            Ok(handle.handle)
+        }
+    }
+
+    pub fn validate(&self, config: PluginConfig) -> anyhow::Result<PluginConfig> {
+        let config = serde_json::to_vec(&config)?;
+        let result = unsafe { self.api.__arroyo_connector_validate(FfiSlice::new(&config)) };
+        let validated = self
+            .take_result(result)
+            .map_err(|e| anyhow!("invalid configuration for {}: {}", self.id, e))?;
+        Ok(serde_json::from_slice(&validated)?)
+    }
+
+    pub fn open_source(
+        self: &Arc<Self>,
+        ctx: &OpenContext,
+        state: Option<&[u8]>,
+    ) -> Result<PluginSourceHandle, String> {
+        let ctx = serde_json::to_vec(ctx).map_err(|e| e.to_string())?;
+        let handle = unsafe {
+            self.api.__arroyo_source_open(
+                FfiSlice::new(&ctx),
+                FfiSlice::new(state.unwrap_or_default()),
+                state.is_some(),
+            )
+        };
+        Ok(PluginSourceHandle {
+            handle: self.take_handle(handle)?,
+            plugin: self.clone(),
+        })
+    }
+
+    pub fn open_sink(self: &Arc<Self>, ctx: &OpenContext) -> Result<PluginSinkHandle, String> {
+        let ctx = serde_json::to_vec(ctx).map_err(|e| e.to_string())?;
+        let handle = unsafe { self.api.__arroyo_sink_open(FfiSlice::new(&ctx)) };
+        Ok(PluginSinkHandle {
+            handle: Some(self.take_handle(handle)?),
+            plugin: self.clone(),
+        })
+    }
+}
+
+/// An open subtask of a plugin source, which is closed when dropped
+pub struct PluginSourceHandle {
+    plugin: Arc<LoadedPlugin>,
+    handle: *mut c_void,
+}
+
+// the plugin is required to make its sources Send, and they're never used concurrently
+unsafe impl Send for PluginSourceHandle {}
+
+impl PluginSourceHandle {
+    pub fn poll(&mut self, timeout: Duration) -> Result<SourcePoll, String> {
+        let poll = unsafe {
+            self.plugin
+                .api
+                .__arroyo_source_poll(self.handle, timeout.as_micros() as u64)
+        };
+
+        match poll.status {
+            POLL_BATCH => unsafe { poll.batch.into_batch() }
+                .map(SourcePoll::Batch)
+                .map_err(|e| format!("invalid batch from connector plugin: {}", e)),
+            POLL_IDLE => Ok(SourcePoll::Idle),
+            POLL_FINISHED => Ok(SourcePoll::Finished),
+            _ => Err(String::from_utf8_lossy(&self.plugin.take_bytes(poll.error)).into_owned()),
+        }
+    }
+
+    pub fn checkpoint(&mut self, epoch: u32) -> Result<Vec<u8>, String> {
+        let result = unsafe {
+            self.plugin
+                .api
+                .__arroyo_source_checkpoint(self.handle, epoch)
+        };
+        self.plugin.take_result(result)
+    }
+}
+
+impl Drop for PluginSourceHandle {
+    fn drop(&mut self) {
+        unsafe { self.plugin.api.__arroyo_source_close(self.handle) };
+    }
+}
+
+/// An open subtask of a plugin sink, which is closed when dropped if it wasn't explicitly
+pub struct PluginSinkHandle {
+    plugin: Arc<LoadedPlugin>,
+    handle: Option<*mut c_void>,
+}
+
+// the plugin is required to make its sinks Send, and they're never used concurrently
+unsafe impl Send for PluginSinkHandle {}
+
+impl PluginSinkHandle {
+    fn handle(&self) -> Result<*mut c_void, String> {
+        self.handle
+            .ok_or_else(|| "plugin sink is already closed".to_string())
+    }
+
+    pub fn write(&mut self, batch: RecordBatch) -> Result<(), String> {
+        let batch = FfiBatch::try_from_batch(batch)
+            .map_err(|e| format!("failed to export batch to connector plugin: {}", e))?;
+        let result = unsafe { self.plugin.api.__arroyo_sink_write(self.handle()?, batch) };
+        self.plugin.take_result(result).map(|_| ())
+    }
+
+    pub fn flush(&mut self, epoch: u32) -> Result<(), String> {
+        let result = unsafe { self.plugin.api.__arroyo_sink_flush(self.handle()?, epoch) };
+        self.plugin.take_result(result).map(|_| ())
+    }
+
+    pub fn close(&mut self) -> Result<(), String> {
+        let Some(handle) = self.handle.take() else {
+            return Ok(());
+        };
+        let result = unsafe { self.plugin.api.__arroyo_sink_close(handle) };
+        self.plugin.take_result(result).map(|_| ())
+    }
+}
+
+impl Drop for PluginSinkHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            warn!("failed to close {} sink: {}", self.plugin.id, e);
+        }
+    }
+}
+
+/// Checks that a plugin's id isn't already taken by a built-in connector or a plugin loaded before
+/// it, as connectors are looked up by id
+fn check_plugin_id<'a, V>(
+    id: &str,
+    builtins: &HashMap<&'static str, V>,
+    loaded: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<()> {
+    if builtins.contains_key(id) {
+        bail!("id '{}' is already used by a built-in connector", id);
+    }
+    if loaded.into_iter().any(|loaded| loaded == id) {
+        bail!("id '{}' is already used by another plugin", id);
+    }
+    Ok(())
+}
+
+/// The plugins in the configured plugin directory, which are loaded the first time this is called.
+/// Plugins that can't be loaded, or whose ids clash with another connector, are rejected.
+pub fn plugins() -> &'static [Arc<LoadedPlugin>] {
+    static PLUGINS: OnceLock<Vec<Arc<LoadedPlugin>>> = OnceLock::new();
+    PLUGINS.get_or_init(|| {
+        let Some(dir) = config().connector_plugin_dir.clone() else {
+            return vec![];
+        };
+
+        let mut paths: Vec<_> = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|ext| ext == PLATFORM_FILE_EXTENSION)
+                })
+                .collect(),
+            Err(e) => {
+                warn!(
+                    "failed to read connector plugin directory {}: {}",
+                    dir.display(),
+                    e
+                );
+                return vec![];
+            }
+        };
+        paths.sort();
+
+        let builtins = crate::builtin_connectors();
+        let mut plugins: Vec<Arc<LoadedPlugin>> = vec![];
+        for path in paths {
+            match LoadedPlugin::load(&path) {
+                Ok(plugin) => {
+                    if let Err(e) =
+                        check_plugin_id(plugin.id, &builtins, plugins.iter().map(|p| p.id))
+                    {
+                        error!("rejecting connector plugin {}: {}", path.display(), e);
+                        continue;
+                    }
+                    info!(
+                        "loaded connector plugin '{}' from {}",
+                        plugin.id,
+                        path.display()
+                    );
+                    plugins.push(Arc::new(plugin));
+                }
+                Err(e) => {
+                    warn!(
+                        "failed to load connector plugin {}: {:?}",
+                        path.display(),
+                        e
+                    );
+                }
+            }
+        }
+        plugins
+    })
+}
+
+pub struct PluginConnector {
+    plugin: Arc<LoadedPlugin>,
+}
+
+impl PluginConnector {
+    pub fn new(plugin: Arc<LoadedPlugin>) -> Self {
+        Self { plugin }
+    }
+
+    fn connection_type(&self, table: &Value) -> ConnectionType {
+        let metadata = self.plugin.metadata();
+        match (metadata.source, metadata.sink) {
+            (true, false) => ConnectionType::Source,
+            (false, true) => ConnectionType::Sink,
+            _ => match table.get("type").and_then(|t| t.as_str()) {
+                Some("sink") => ConnectionType::Sink,
+                _ => ConnectionType::Source,
+            },
+        }
+    }
+}
+
+impl Connector for PluginConnector {
+    type ProfileT = Value;
+    type TableT = Value;
+
+    fn name(&self) -> &'static str {
+        self.plugin.id()
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        let metadata = self.plugin.metadata();
+        arroyo_rpc::api_types::connections::Connector {
+            id: metadata.id.clone(),
+            name: metadata.name.clone(),
+            icon: metadata.icon.clone(),
+            description: metadata.description.clone(),
+            enabled: true,
+            source: metadata.source,
+            sink: metadata.sink,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: metadata.connection_config.as_ref().map(|c| c.to_string()),
+            table_config: metadata.table_config.to_string(),
+        }
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        self.connection_type(&table)
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        profile: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<TestSourceMessage>,
+    ) {
+        let message = match self.plugin.validate(PluginConfig { profile, table }) {
+            Ok(_) => TestSourceMessage {
+                error: false,
+                done: true,
+                message: "Successfully validated connection".to_string(),
+            },
+            Err(e) => TestSourceMessage {
+                error: true,
+                done: true,
+                message: e.to_string(),
+            },
+        };
+
+        let mut tx = tx;
+        tokio::spawn(async move {
+            crate::send(&mut tx, message).await;
+        });
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        options: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+        profile: Option<&ConnectionProfile>,
+    ) -> anyhow::Result<Connection> {
+        // options are passed to the plugin as strings, for it to parse in `validate`; only those
+        // that are properties of its table are taken, leaving the rest for Arroyo
+        let table: serde_json::Map<String, Value> = self
+            .plugin
+            .metadata()
+            .table_config
+            .get("properties")
+            .and_then(|p| p.as_object())
+            .map(|properties| {
+                properties
+                    .keys()
+                    .filter_map(|k| Some((k.clone(), Value::String(options.remove(k)?))))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let profile = profile
+            .map(|p| p.config.clone())
+            .unwrap_or_else(|| Value::Object(Default::default()));
+
+        self.from_config(None, name, profile, Value::Object(table), schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        profile: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let PluginConfig { profile, table } =
+            self.plugin.validate(PluginConfig { profile, table })?;
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for {} connection", self.name()))?;
+
+        let connection_type = self.connection_type(&table);
+
+        let config = OperatorConfig {
+            connection: profile,
+            table,
+            rate_limit: None,
+            filter: None,
+            delivery_semantics: None,
+            upsert_key: vec![],
+            partition_watermarks: None,
+            format: schema.format.clone(),
+            bad_data: schema.bad_data.clone(),
+            framing: schema.framing.clone(),
+        };
+
+        Ok(Connection {
+            id,
+            connector: self.name(),
+            name: name.to_string(),
+            connection_type,
+            schema,
+            config: serde_json::to_string(&config).unwrap(),
+            description: self.plugin.metadata().name.clone(),
+        })
+    }
+
+    fn make_operator(
+        &self,
+        profile: Self::ProfileT,
+        table: Self::TableT,
+        _: OperatorConfig,
+    ) -> anyhow::Result<OperatorNode> {
+        let connection_type = self.connection_type(&table);
+        let config = PluginConfig { profile, table };
+
+        Ok(match connection_type {
+            ConnectionType::Source => OperatorNode::from_source(Box::new(PluginSourceFunc::new(
+                self.plugin.clone(),
+                config,
+            ))),
+            ConnectionType::Sink => OperatorNode::from_operator(Box::new(PluginSinkFunc::new(
+                self.plugin.clone(),
+                config,
+            ))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_plugin_id() {
+        let builtins = crate::builtin_connectors();
+
+        assert!(check_plugin_id("my_connector", &builtins, []).is_ok());
+
+        let err = check_plugin_id("kafka", &builtins, []).unwrap_err();
+        assert!(err.to_string().contains("built-in connector"), "{}", err);
+
+        let err =
+            check_plugin_id("my_connector", &builtins, ["other", "my_connector"]).unwrap_err();
+        assert!(err.to_string().contains("another plugin"), "{}", err);
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use arrow::array::{new_null_array, ArrayRef, RecordBatch, TimestampNanosecondArray};
+use arrow::compute::cast;
+use arroyo_connector_sdk::{OpenContext, PluginConfig, SourcePoll};
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::{ArrowOperator, SourceOperator};
+use arroyo_operator::SourceFinishType;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
+use arroyo_rpc::df::ArroyoSchema;
+use arroyo_rpc::grpc::{StopMode, TableConfig};
+use arroyo_rpc::ControlMessage;
+use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
+use arroyo_types::{to_nanos, CheckpointBarrier, SignalMessage, UserError};
+use async_trait::async_trait;
+use tracing::{debug, info};
+
+use crate::plugin::{LoadedPlugin, PluginSinkHandle};
+
+// how long a poll may block before the source checks for control messages
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Runs a call into a plugin on a blocking thread, as plugins are free to block
+async fn blocking<H: Send + 'static, T: Send + 'static>(
+    handle: &Arc<Mutex<H>>,
+    f: impl FnOnce(&mut H) -> T + Send + 'static,
+) -> T {
+    let handle = handle.clone();
+    tokio::task::spawn_blocking(move || f(&mut handle.lock().unwrap()))
+        .await
+        .expect("connector plugin call panicked")
+}
+
+fn open_context(config: &PluginConfig, schema: &ArroyoSchema, ctx: &ArrowContext) -> OpenContext {
+    OpenContext {
+        config: config.clone(),
+        schema: schema.schema_without_timestamp(),
+        job_id: ctx.task_info.job_id.clone(),
+        operator_id: ctx.task_info.operator_id.clone(),
+        task_index: ctx.task_info.task_index,
+        parallelism: ctx.task_info.parallelism,
+    }
+}
+
+/// Matches the columns of a batch from a plugin to the output schema by name, casting them where
+/// their types differ. Missing nullable columns are filled with nulls, and a missing timestamp
+/// column with the current time.
+fn conform_batch(batch: RecordBatch, schema: &ArroyoSchema) -> Result<RecordBatch, UserError> {
+    let invalid = |details: String| UserError::new("invalid batch from connector plugin", details);
+
+    let columns = schema
+        .schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let column: ArrayRef = match batch.column_by_name(field.name()) {
+                Some(column) => column.clone(),
+                None if i == schema.timestamp_index => {
+                    let now = to_nanos(SystemTime::now()) as i64;
+                    Arc::new(TimestampNanosecondArray::from(vec![now; batch.num_rows()]))
+                }
+                None if field.is_nullable() => new_null_array(field.data_type(), batch.num_rows()),
+                None => {
+                    return Err(invalid(format!(
+                        "missing non-nullable column '{}'",
+                        field.name()
+                    )))
+                }
+            };
+
+            if column.data_type() == field.data_type() {
+                Ok(column)
+            } else {
+                cast(&column, field.data_type()).map_err(|e| {
+                    invalid(format!(
+                        "column '{}' can't be converted from {} to {}: {}",
+                        field.name(),
+                        column.data_type(),
+                        field.data_type(),
+                        e
+                    ))
+                })
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    RecordBatch::try_new(schema.schema.clone(), columns).map_err(|e| invalid(e.to_string()))
+}
+
+pub struct PluginSourceFunc {
+    plugin: Arc<LoadedPlugin>,
+    config: PluginConfig,
+}
+
+impl PluginSourceFunc {
+    pub fn new(plugin: Arc<LoadedPlugin>, config: PluginConfig) -> Self {
+        Self { plugin, config }
+    }
+
+    async fn run_int(&mut self, ctx: &mut ArrowContext) -> Result<SourceFinishType, UserError> {
+        let schema = ctx
+            .out_schema
+            .clone()
+            .expect("plugin source must have an output schema");
+
+        let state: &mut GlobalKeyedView<usize, Vec<u8>> = ctx
+            .table_manager
+            .get_global_keyed_state("p")
+            .await
+            .expect("should be able to read plugin source state");
+        let restored = state.get(&ctx.task_info.task_index).cloned();
+
+        let open_ctx = open_context(&self.config, &schema, ctx);
+        let plugin = self.plugin.clone();
+        let source =
+            tokio::task::spawn_blocking(move || plugin.open_source(&open_ctx, restored.as_deref()))
+                .await
+                .expect("connector plugin call panicked")
+                .map_err(|e| UserError::new("failed to open plugin source", e))?;
+        let source = Arc::new(Mutex::new(source));
+
+        loop {
+            let poll = blocking(&source, |s| s.poll(POLL_TIMEOUT))
+                .await
+                .map_err(|e| UserError::new("plugin source failed", e))?;
+
+            match poll {
+                SourcePoll::Batch(batch) => {
+                    if batch.num_rows() > 0 {
+                        ctx.collect(conform_batch(batch, &schema)?).await;
+                    }
+                }
+                SourcePoll::Idle => {}
+                SourcePoll::Finished => {
+                    info!("plugin source {} finished", self.plugin.id());
+                    return Ok(SourceFinishType::Final);
+                }
+            }
+
+            match ctx.control_rx.try_recv() {
+                Ok(ControlMessage::Checkpoint(c)) => {
+                    debug!("starting checkpointing {}", ctx.task_info.task_index);
+                    let epoch = c.epoch;
+                    let data = blocking(&source, move |s| s.checkpoint(epoch))
+                        .await
+                        .map_err(|e| UserError::new("failed to checkpoint plugin source", e))?;
+                    ctx.table_manager
+                        .get_global_keyed_state("p")
+                        .await
+                        .expect("should be able to get plugin source state")
+                        .insert(ctx.task_info.task_index, data)
+                        .await;
+
+                    if self.start_checkpoint(c, ctx).await {
+                        return Ok(SourceFinishType::Immediate);
+                    }
+                }
+                Ok(ControlMessage::Stop { mode }) => {
+                    info!("Stopping plugin source {}: {:?}", self.plugin.id(), mode);
+
+                    match mode {
+                        StopMode::Graceful => {
+                            return Ok(SourceFinishType::Graceful);
+                        }
+                        StopMode::Immediate => {
+                            return Ok(SourceFinishType::Immediate);
+                        }
+                    }
+                }
+                Ok(ControlMessage::Commit { .. }) => {
+                    unreachable!("sources shouldn't receive commit messages");
+                }
+                Ok(ControlMessage::LoadCompacted { compacted }) => {
+                    ctx.load_compacted(compacted).await;
+                }
+                Ok(ControlMessage::UpdateParameters { parameters }) => {
+                    ctx.update_parameters(&parameters);
+                }
+                Ok(ControlMessage::NoOp | ControlMessage::AlignWatermark { .. }) => {}
+                Err(_) => {
+                    // no messages
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SourceOperator for PluginSourceFunc {
+    fn name(&self) -> String {
+        format!("PluginSource<{}>", self.plugin.id())
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        arroyo_state::global_table_config("p", "plugin source state")
+    }
+
+    async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_source_error(e.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+}
+
+pub struct PluginSinkFunc {
+    plugin: Arc<LoadedPlugin>,
+    config: PluginConfig,
+    sink: Option<Arc<Mutex<PluginSinkHandle>>>,
+}
+
+impl PluginSinkFunc {
+    pub fn new(plugin: Arc<LoadedPlugin>, config: PluginConfig) -> Self {
+        Self {
+            plugin,
+            config,
+            sink: None,
+        }
+    }
+
+    fn sink(&self) -> &Arc<Mutex<PluginSinkHandle>> {
+        self.sink.as_ref().expect("plugin sink has not been opened")
+    }
+
+    async fn fail(&self, ctx: &mut ArrowContext, message: &str, details: String) -> ! {
+        ctx.report_error(ErrorCategory::Connector, message, details.clone())
+            .await;
+        panic!("{} ({}): {}", message, self.plugin.id(), details);
+    }
+}
+
+#[async_trait]
+impl ArrowOperator for PluginSinkFunc {
+    fn name(&self) -> String {
+        format!("PluginSink<{}>", self.plugin.id())
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        let open_ctx = open_context(&self.config, &ctx.in_schemas[0], ctx);
+        let plugin = self.plugin.clone();
+        let sink = tokio::task::spawn_blocking(move || plugin.open_sink(&open_ctx))
+            .await
+            .expect("connector plugin call panicked");
+
+        match sink {
+            Ok(sink) => self.sink = Some(Arc::new(Mutex::new(sink))),
+            Err(e) => self.fail(ctx, "failed to open plugin sink", e).await,
+        }
+    }
+
+    async fn process_batch(&mut self, mut batch: RecordBatch, ctx: &mut ArrowContext) {
+        ctx.in_schemas[0].remove_timestamp_column(&mut batch);
+
+        if let Err(e) = blocking(self.sink(), move |s| s.write(batch)).await {
+            self.fail(ctx, "failed to write to plugin sink", e).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, b: CheckpointBarrier, ctx: &mut ArrowContext) {
+        let epoch = b.epoch;
+        if let Err(e) = blocking(self.sink(), move |s| s.flush(epoch)).await {
+            self.fail(ctx, "failed to flush plugin sink", e).await;
+        }
+    }
+
+    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        if let Some(sink) = self.sink.take() {
+            if let Err(e) = blocking(&sink, |s| s.close()).await {
+                self.fail(ctx, "failed to close plugin sink", e).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array, Int64Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+
+    #[test]
+    fn test_conform_batch() {
+        let schema = ArroyoSchema::new_unkeyed(
+            Arc::new(Schema::new(vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("name", DataType::Utf8, true),
+                Field::new(
+                    "_timestamp",
+                    DataType::Timestamp(TimeUnit::Nanosecond, None),
+                    false,
+                ),
+            ])),
+            2,
+        );
+
+        let batch = RecordBatch::try_from_iter(vec![(
+            "id",
+            Arc::new(Int32Array::from(vec![1, 2])) as ArrayRef,
+        )])
+        .unwrap();
+
+        let conformed = conform_batch(batch, &schema).unwrap();
+        assert_eq!(conformed.schema(), schema.schema);
+        assert_eq!(
+            conformed
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap(),
+            &Int64Array::from(vec![1, 2])
+        );
+        assert_eq!(conformed.column(1).null_count(), 2);
+
+        let batch = RecordBatch::try_from_iter(vec![(
+            "name",
+            Arc::new(StringArray::from(vec!["a"])) as ArrayRef,
+        )])
+        .unwrap();
+        assert!(conform_batch(batch, &schema).is_err());
+    }
+}
@@ -7,6 +7,7 @@ use std::collections::HashMap;
 use std::time::Duration;
 use std::time::SystemTime;
 
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::ControlMessage;
 use arroyo_types::{ArrowMessage, SignalMessage, UserError, Watermark};
 
@@ -68,7 +69,7 @@ impl SourceOperator for PollingHttpSourceFunc {
         match self.run_int(ctx).await {
             Ok(r) => r,
             Err(e) => {
-                ctx.report_error(e.name.clone(), e.details.clone()).await;
+                ctx.report_source_error(e.clone()).await;
 
                 panic!("{}: {}", e.name, e.details);
             }
@@ -115,7 +116,10 @@ impl PollingHttpSourceFunc {
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
-            ControlMessage::NoOp => {}
+            ControlMessage::UpdateParameters { parameters } => {
+                ctx.update_parameters(&parameters);
+            }
+            ControlMessage::NoOp | ControlMessage::AlignWatermark { .. } => {}
         }
         None
     }
@@ -224,7 +228,7 @@ impl PollingHttpSourceFunc {
                                 self.state.last_message = Some(buf);
                             }
                             Err(e) => {
-                                ctx.report_user_error(e).await;
+                                ctx.report_user_error(ErrorCategory::Connector, e).await;
                             }
                         }
                     }
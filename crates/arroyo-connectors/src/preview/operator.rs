@@ -7,6 +7,8 @@ use arroyo_operator::operator::ArrowOperator;
 use arroyo_rpc::config::config;
 use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::grpc::SinkDataReq;
+use arroyo_rpc::Compressed;
+use arroyo_server_common::tls;
 use arroyo_types::{from_nanos, to_micros, SignalMessage};
 use tonic::transport::Channel;
 
@@ -23,9 +25,10 @@ impl ArrowOperator for PreviewSink {
 
     async fn on_start(&mut self, _: &mut ArrowContext) {
         self.client = Some(
-            ControllerGrpcClient::connect(config().controller_endpoint())
+            tls::connect_grpc(config().controller_endpoint())
                 .await
-                .unwrap(),
+                .map(|channel| ControllerGrpcClient::new(channel).compressed())
+                .expect("Unable to connect to controller"),
         );
     }
 
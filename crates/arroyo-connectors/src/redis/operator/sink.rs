@@ -3,11 +3,12 @@ use arrow::array::{AsArray, RecordBatch};
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::context::{ArrowContext, ErrorReporter};
 use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_types::CheckpointBarrier;
 use async_trait::async_trait;
 use redis::aio::{ConnectionLike, ConnectionManager};
 use redis::cluster_async::ClusterConnection;
-use redis::{Cmd, Pipeline, RedisFuture};
+use redis::{Cmd, ErrorKind, Pipeline, RedisError, RedisFuture};
 use std::collections::HashSet;
 use std::time::{Duration, Instant};
 use tokio::select;
@@ -97,6 +98,14 @@ impl ConnectionLike for GeneralConnection {
     }
 }
 
+fn error_category(e: &RedisError) -> ErrorCategory {
+    if e.kind() == ErrorKind::AuthenticationFailed {
+        ErrorCategory::ConnectorAuth
+    } else {
+        ErrorCategory::Connector
+    }
+}
+
 struct RedisWriter {
     rx: Receiver<RedisCmd>,
     tx: Sender<u32>,
@@ -211,6 +220,7 @@ impl RedisWriter {
                 Err(e) => {
                     self.error_reporter
                         .report_error(
+                            error_category(&e),
                             "Redis error",
                             format!("Failed to write data to redis: {:?}", e),
                         )
@@ -314,7 +324,8 @@ impl ArrowOperator for RedisSinkFunc {
                     return;
                 }
                 Err(e) => {
-                    ctx.report_error("Failed to connect", e.to_string()).await;
+                    ctx.report_error(error_category(&e), "Failed to connect", e.to_string())
+                        .await;
                 }
             }
 
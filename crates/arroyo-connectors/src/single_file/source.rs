@@ -109,6 +109,9 @@ impl SingleFileSourceFunc {
                     }
                 }
             }
+            Some(ControlMessage::UpdateParameters { parameters }) => {
+                ctx.update_parameters(&parameters);
+            }
             Some(ControlMessage::NoOp) => {
                 // No-op messages allow the source to advance and process a record
             }
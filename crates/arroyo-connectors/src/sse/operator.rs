@@ -2,6 +2,7 @@ use crate::sse::SseTable;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::{OperatorNode, SourceOperator};
 use arroyo_operator::SourceFinishType;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::{StopMode, TableConfig};
 use arroyo_rpc::{ControlMessage, ControlResp, OperatorConfig};
@@ -81,7 +82,7 @@ impl SourceOperator for SSESourceFunc {
         match self.run_int(ctx).await {
             Ok(r) => r,
             Err(e) => {
-                ctx.report_error(e.name.clone(), e.details.clone()).await;
+                ctx.report_source_error(e.clone()).await;
 
                 panic!("{}: {}", e.name, e.details);
             }
@@ -127,7 +128,10 @@ impl SSESourceFunc {
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
-            ControlMessage::NoOp => {}
+            ControlMessage::UpdateParameters { parameters } => {
+                ctx.update_parameters(&parameters);
+            }
+            ControlMessage::NoOp | ControlMessage::AlignWatermark { .. } => {}
         }
         None
     }
@@ -188,7 +192,9 @@ impl SSESourceFunc {
                                         operator_id: ctx.task_info.operator_id.clone(),
                                         task_index: ctx.task_info.task_index,
                                         message: "Error while reading from EventSource".to_string(),
-                                        details: format!("{:?}", e)}
+                                        details: format!("{:?}", e),
+                                        category: ErrorCategory::Connector,
+                                    }
                                 ).await.unwrap();
                                 panic!("Error while reading from EventSource: {:?}", e);
                             }
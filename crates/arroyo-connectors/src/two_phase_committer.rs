@@ -0,0 +1,525 @@
+//! A reusable operator for exactly-once sinks built on two-phase commit.
+//!
+//! Sinks implement [`TwoPhaseCommitter`] and are wrapped in a [`TwoPhaseCommitterOperator`], which
+//! handles the interaction with the checkpointing system:
+//!
+//! * on each checkpoint barrier the committer pre-commits the data written since the last
+//!   checkpoint (for example, by finishing a multipart upload without making it visible), and the
+//!   resulting pre-commit data is stored in the checkpoint
+//! * once the checkpoint has completed across the whole pipeline, the controller sends a
+//!   [`ControlMessage::Commit`], and the pre-committed data is made visible
+//! * on restore, the committer is first asked to abort anything written after the checkpoint
+//!   being restored from, and then the pre-commits of that checkpoint are committed if they
+//!   hadn't been already
+//!
+//! Commits must be idempotent, as they may be retried after a failure.
+//!
+//! Sinks configured for at-least-once or at-most-once delivery skip the second phase: the data
+//! pre-committed at each checkpoint is committed immediately, rather than waiting for the
+//! checkpoint to complete. For at-most-once sinks, failed commits are dropped instead of failing
+//! the pipeline.
+
+use std::{collections::HashMap, time::SystemTime};
+
+use anyhow::Result;
+use arrow::record_batch::RecordBatch;
+use arroyo_operator::{context::ArrowContext, operator::ArrowOperator};
+use arroyo_rpc::{
+    grpc::{GlobalKeyedTableConfig, TableConfig, TableEnum},
+    CheckpointEvent, ControlMessage, DeliverySemantics,
+};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedView;
+use arroyo_types::{Data, SignalMessage, TaskInfo, Watermark};
+use async_trait::async_trait;
+use bincode::config;
+use prost::Message;
+use tracing::{info, warn};
+
+pub struct TwoPhaseCommitterOperator<TPC: TwoPhaseCommitter> {
+    committer: TPC,
+    pre_commits: Vec<TPC::PreCommit>,
+    delivery_semantics: DeliverySemantics,
+}
+
+/// A sink that writes its data to an external system using two-phase commit.
+///
+/// There are two associated types: `DataRecovery`, which is stored in each checkpoint by every
+/// subtask and describes in-progress writes that should be resumed on restore, and `PreCommit`,
+/// which describes data that has been pre-committed and will be made visible once the checkpoint
+/// completes.
+#[async_trait]
+pub trait TwoPhaseCommitter: Send + 'static {
+    type DataRecovery: Data;
+    type PreCommit: Data;
+
+    fn name(&self) -> String;
+
+    /// Called when restoring from a checkpoint, before `init`, with the recovery data of every
+    /// subtask in that checkpoint. Committers should abort any writes they made after the
+    /// checkpoint (such as open transactions), as they will be replayed.
+    async fn abort(
+        &mut self,
+        _task_info: &TaskInfo,
+        _data_recovery: &[Self::DataRecovery],
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn init(
+        &mut self,
+        task_info: &mut ArrowContext,
+        data_recovery: Vec<Self::DataRecovery>,
+    ) -> Result<()>;
+    async fn insert_batch(&mut self, batch: RecordBatch) -> Result<()>;
+    /// Makes the pre-committed data visible. This must be idempotent, as the same pre-commits may
+    /// be committed again after a failure.
+    // TODO: figure out how to have the relevant vectors be of pointers across async boundaries.
+    async fn commit(
+        &mut self,
+        task_info: &TaskInfo,
+        pre_commit: Vec<Self::PreCommit>,
+    ) -> Result<()>;
+    /// Pre-commits the data written since the last checkpoint, returning the recovery data for
+    /// this subtask and the pre-commits to be committed once the checkpoint completes
+    async fn checkpoint(
+        &mut self,
+        task_info: &TaskInfo,
+        watermark: Option<SystemTime>,
+        stopping: bool,
+    ) -> Result<(Self::DataRecovery, HashMap<String, Self::PreCommit>)>;
+    fn commit_strategy(&self) -> CommitStrategy {
+        CommitStrategy::PerSubtask
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CommitStrategy {
+    // Per subtask uses the subtask itself as the committer, writing the pre-commit messages to state for restoration.
+    PerSubtask,
+    // Per operator uses subtask 0 as the committer, passing all PreCommit data through the control system/checkpoint metadata.
+    PerOperator,
+}
+
+impl<TPC: TwoPhaseCommitter> TwoPhaseCommitterOperator<TPC> {
+    pub fn new(committer: TPC) -> Self {
+        Self {
+            committer,
+            pre_commits: Vec::new(),
+            delivery_semantics: DeliverySemantics::ExactlyOnce,
+        }
+    }
+
+    pub fn with_delivery_semantics(mut self, delivery_semantics: DeliverySemantics) -> Self {
+        self.delivery_semantics = delivery_semantics;
+        self
+    }
+
+    fn waits_for_commit(&self) -> bool {
+        self.delivery_semantics == DeliverySemantics::ExactlyOnce
+    }
+
+    async fn handle_commit(
+        &mut self,
+        epoch: u32,
+        mut commit_data: HashMap<String, HashMap<u32, Vec<u8>>>,
+        ctx: &mut ArrowContext,
+    ) {
+        info!("received commit message");
+        let pre_commits = match self.committer.commit_strategy() {
+            CommitStrategy::PerSubtask => std::mem::take(&mut self.pre_commits),
+            CommitStrategy::PerOperator => {
+                // only subtask 0 should be committing
+                if ctx.task_info.task_index == 0 {
+                    commit_data
+                        .remove("p")
+                        .unwrap_or_default()
+                        .into_values()
+                        .flat_map(|serialized_data| Self::map_from_serialized_data(serialized_data))
+                        .collect()
+                } else {
+                    vec![]
+                }
+            }
+        };
+
+        self.committer
+            .commit(&ctx.task_info, pre_commits)
+            .await
+            .expect("pre-commits of a completed checkpoint must be committed");
+        let checkpoint_event = arroyo_rpc::ControlResp::CheckpointEvent(CheckpointEvent {
+            checkpoint_epoch: epoch,
+            operator_id: ctx.task_info.operator_id.clone(),
+            subtask_index: ctx.task_info.task_index as u32,
+            time: SystemTime::now(),
+            event_type: arroyo_rpc::grpc::TaskCheckpointEventType::FinishedCommit,
+        });
+        ctx.control_tx
+            .send(checkpoint_event)
+            .await
+            .expect("sent commit event");
+    }
+
+    fn map_from_serialized_data(serialized_data: Vec<u8>) -> Vec<TPC::PreCommit> {
+        let map: HashMap<String, TPC::PreCommit> =
+            bincode::decode_from_slice(&serialized_data, config::standard())
+                .unwrap()
+                .0;
+        map.into_values().collect()
+    }
+}
+
+#[async_trait]
+impl<TPC: TwoPhaseCommitter> ArrowOperator for TwoPhaseCommitterOperator<TPC> {
+    fn name(&self) -> String {
+        self.committer.name()
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        let mut tables = arroyo_state::global_table_config("r", "recovery data");
+        tables.insert(
+            "p".into(),
+            TableConfig {
+                table_type: TableEnum::GlobalKeyValue.into(),
+                config: GlobalKeyedTableConfig {
+                    table_name: "p".into(),
+                    description: "pre-commit data".into(),
+                    uses_two_phase_commit: self.waits_for_commit(),
+                }
+                .encode_to_vec(),
+            },
+        );
+        tables
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        let tracking_key_state: &mut GlobalKeyedView<usize, TPC::DataRecovery> = ctx
+            .table_manager
+            .get_global_keyed_state("r")
+            .await
+            .expect("should be able to get table");
+
+        let state_vec: Vec<_> = tracking_key_state.get_all().values().cloned().collect();
+        if !state_vec.is_empty() {
+            self.committer
+                .abort(&ctx.task_info, &state_vec)
+                .await
+                .expect("writes after the restored checkpoint must be aborted before replay");
+        }
+
+        self.committer
+            .init(ctx, state_vec)
+            .await
+            .expect("the committer must initialize from the restored recovery data");
+
+        // subtask 0 is responsible for finishing commits if we were interrupted mid commit.
+        if ctx.task_info.task_index == 0 {
+            let pre_commit_state: &mut GlobalKeyedView<String, TPC::PreCommit> = ctx
+                .table_manager
+                .get_global_keyed_state("p")
+                .await
+                .expect("should be able to get table");
+            self.pre_commits = pre_commit_state.get_all().values().cloned().collect();
+        }
+    }
+
+    async fn process_batch(&mut self, batch: RecordBatch, _ctx: &mut ArrowContext) {
+        self.committer
+            .insert_batch(batch)
+            .await
+            .expect("record inserted");
+    }
+
+    async fn on_close(&mut self, _final_mesage: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        if !self.waits_for_commit() {
+            // the final checkpoint has already committed everything
+            return;
+        }
+        if let Some(ControlMessage::Commit { epoch, commit_data }) = ctx.control_rx.recv().await {
+            self.handle_commit(epoch, commit_data, ctx).await;
+        } else {
+            warn!("no commit message received, not committing")
+        }
+    }
+
+    async fn handle_commit(
+        &mut self,
+        epoch: u32,
+        commit_data: &HashMap<String, HashMap<u32, Vec<u8>>>,
+        ctx: &mut ArrowContext,
+    ) {
+        self.handle_commit(epoch, commit_data.clone(), ctx).await;
+    }
+
+    async fn handle_checkpoint(
+        &mut self,
+        checkpoint_barrier: arroyo_types::CheckpointBarrier,
+        ctx: &mut ArrowContext,
+    ) {
+        let (recovery_data, pre_commits) = self
+            .committer
+            .checkpoint(
+                &ctx.task_info,
+                ctx.watermark().and_then(|watermark| match watermark {
+                    Watermark::EventTime(watermark) => Some(watermark),
+                    arroyo_types::Watermark::Idle => None,
+                }),
+                checkpoint_barrier.then_stop,
+            )
+            .await
+            .unwrap();
+
+        let recovery_data_state: &mut GlobalKeyedView<usize, _> = ctx
+            .table_manager
+            .get_global_keyed_state("r")
+            .await
+            .expect("should be able to get table");
+        recovery_data_state
+            .insert(ctx.task_info.task_index, recovery_data)
+            .await;
+        self.pre_commits.clear();
+        if pre_commits.is_empty() {
+            return;
+        }
+
+        if !self.waits_for_commit() {
+            if let Err(e) = self
+                .committer
+                .commit(&ctx.task_info, pre_commits.into_values().collect())
+                .await
+            {
+                if self.delivery_semantics == DeliverySemantics::AtMostOnce {
+                    warn!(
+                        "dropping data for epoch {} that failed to commit: {:?}",
+                        checkpoint_barrier.epoch, e
+                    );
+                } else {
+                    panic!("failed to commit: {:?}", e);
+                }
+            }
+            return;
+        }
+        let commit_strategy = self.committer.commit_strategy();
+        match commit_strategy {
+            CommitStrategy::PerSubtask => {
+                let pre_commit_state = ctx
+                    .table_manager
+                    .get_global_keyed_state("p")
+                    .await
+                    .expect("should be able to get table");
+                for (key, value) in pre_commits {
+                    self.pre_commits.push(value.clone());
+                    pre_commit_state.insert(key, value).await;
+                }
+                ctx.table_manager
+                    .insert_committing_data("p", vec![])
+                    .await
+                    .expect("should be able to send committing data");
+            }
+            CommitStrategy::PerOperator => {
+                let serialized_pre_commits =
+                    bincode::encode_to_vec(&pre_commits, config::standard()).unwrap();
+                ctx.table_manager
+                    .insert_committing_data("p", serialized_pre_commits)
+                    .await
+                    .expect("should be able to send committing data");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::UInt64Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arroyo_rpc::df::ArroyoSchema;
+    use arroyo_rpc::grpc::TaskCheckpointEventType;
+    use arroyo_rpc::ControlResp;
+    use arroyo_types::{get_test_task_info, CheckpointBarrier};
+    use std::sync::Arc;
+    use tokio::sync::mpsc::{channel, Receiver};
+
+    /// Records the calls made to it. Each checkpoint pre-commits the rows written since the
+    /// previous one, and the recovery data is the number of checkpoints taken.
+    #[derive(Default)]
+    struct RecordingCommitter {
+        checkpoints: u32,
+        rows: usize,
+        aborted: Vec<u32>,
+        restored: Vec<u32>,
+        committed: Vec<String>,
+    }
+
+    #[async_trait]
+    impl TwoPhaseCommitter for RecordingCommitter {
+        type DataRecovery = u32;
+        type PreCommit = String;
+
+        fn name(&self) -> String {
+            "recording".to_string()
+        }
+
+        async fn abort(&mut self, _task_info: &TaskInfo, data_recovery: &[u32]) -> Result<()> {
+            self.aborted.extend_from_slice(data_recovery);
+            Ok(())
+        }
+
+        async fn init(&mut self, _ctx: &mut ArrowContext, data_recovery: Vec<u32>) -> Result<()> {
+            self.checkpoints = data_recovery.iter().copied().max().unwrap_or_default();
+            self.restored = data_recovery;
+            Ok(())
+        }
+
+        async fn insert_batch(&mut self, batch: RecordBatch) -> Result<()> {
+            self.rows += batch.num_rows();
+            Ok(())
+        }
+
+        async fn commit(&mut self, _task_info: &TaskInfo, pre_commit: Vec<String>) -> Result<()> {
+            self.committed.extend(pre_commit);
+            Ok(())
+        }
+
+        async fn checkpoint(
+            &mut self,
+            _task_info: &TaskInfo,
+            _watermark: Option<SystemTime>,
+            _stopping: bool,
+        ) -> Result<(u32, HashMap<String, String>)> {
+            self.checkpoints += 1;
+            let pre_commit = format!("{}-{}", self.checkpoints, std::mem::take(&mut self.rows));
+            Ok((
+                self.checkpoints,
+                HashMap::from([(pre_commit.clone(), pre_commit)]),
+            ))
+        }
+    }
+
+    fn schema() -> Arc<Schema> {
+        Arc::new(Schema::new(vec![Field::new(
+            "value",
+            DataType::UInt64,
+            false,
+        )]))
+    }
+
+    fn batch(rows: u64) -> RecordBatch {
+        RecordBatch::try_new(
+            schema(),
+            vec![Arc::new(UInt64Array::from_iter_values(0..rows))],
+        )
+        .unwrap()
+    }
+
+    fn barrier(epoch: u32) -> CheckpointBarrier {
+        CheckpointBarrier {
+            epoch,
+            min_epoch: 0,
+            timestamp: SystemTime::now(),
+            then_stop: false,
+        }
+    }
+
+    async fn context(
+        operator: &TwoPhaseCommitterOperator<RecordingCommitter>,
+    ) -> (ArrowContext, Receiver<ControlResp>) {
+        let (_, control_rx) = channel(128);
+        let (command_tx, command_rx) = channel(128);
+        let ctx = ArrowContext::new(
+            get_test_task_info(),
+            None,
+            control_rx,
+            command_tx,
+            1,
+            vec![ArroyoSchema::new_unkeyed(schema(), 0)],
+            None,
+            None,
+            vec![],
+            operator.tables(),
+            HashMap::new(),
+        )
+        .await;
+        (ctx, command_rx)
+    }
+
+    #[tokio::test]
+    async fn test_pre_commit_is_committed_once_checkpoint_completes() {
+        let mut operator = TwoPhaseCommitterOperator::new(RecordingCommitter::default());
+        let (mut ctx, mut command_rx) = context(&operator).await;
+
+        operator.on_start(&mut ctx).await;
+        assert!(operator.committer.aborted.is_empty());
+
+        operator.process_batch(batch(3), &mut ctx).await;
+        operator.process_batch(batch(2), &mut ctx).await;
+        operator.handle_checkpoint(barrier(1), &mut ctx).await;
+
+        // pre-committed, but not visible until the checkpoint completes
+        assert!(operator.committer.committed.is_empty());
+        assert_eq!(operator.pre_commits, vec!["1-5".to_string()]);
+
+        operator.handle_commit(1, HashMap::new(), &mut ctx).await;
+        assert_eq!(operator.committer.committed, vec!["1-5".to_string()]);
+        assert!(operator.pre_commits.is_empty());
+
+        match command_rx.try_recv().unwrap() {
+            ControlResp::CheckpointEvent(event) => {
+                assert_eq!(event.checkpoint_epoch, 1);
+                assert_eq!(event.event_type, TaskCheckpointEventType::FinishedCommit);
+            }
+            other => panic!("expected a commit event, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_at_least_once_commits_at_checkpoint() {
+        let mut operator = TwoPhaseCommitterOperator::new(RecordingCommitter::default())
+            .with_delivery_semantics(DeliverySemantics::AtLeastOnce);
+        let (mut ctx, _command_rx) = context(&operator).await;
+
+        operator.on_start(&mut ctx).await;
+        operator.process_batch(batch(4), &mut ctx).await;
+        operator.handle_checkpoint(barrier(1), &mut ctx).await;
+
+        assert_eq!(operator.committer.committed, vec!["1-4".to_string()]);
+        assert!(operator.pre_commits.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_restore_aborts_then_commits_restored_pre_commits() {
+        let mut operator = TwoPhaseCommitterOperator::new(RecordingCommitter::default());
+        let (mut ctx, _command_rx) = context(&operator).await;
+
+        // the state of a checkpoint whose pre-commit hadn't been committed before the failure
+        ctx.table_manager
+            .get_global_keyed_state("r")
+            .await
+            .unwrap()
+            .insert(0usize, 2u32)
+            .await;
+        ctx.table_manager
+            .get_global_keyed_state("p")
+            .await
+            .unwrap()
+            .insert("2-7".to_string(), "2-7".to_string())
+            .await;
+
+        operator.on_start(&mut ctx).await;
+        assert_eq!(operator.committer.aborted, vec![2]);
+        assert_eq!(operator.committer.restored, vec![2]);
+        assert!(operator.committer.committed.is_empty());
+        assert_eq!(operator.pre_commits, vec!["2-7".to_string()]);
+
+        // the controller resends the commit of a checkpoint restored in its committing phase
+        operator.handle_commit(2, HashMap::new(), &mut ctx).await;
+        assert_eq!(operator.committer.committed, vec!["2-7".to_string()]);
+
+        operator.process_batch(batch(1), &mut ctx).await;
+        operator.handle_checkpoint(barrier(3), &mut ctx).await;
+        operator.handle_commit(3, HashMap::new(), &mut ctx).await;
+        assert_eq!(
+            operator.committer.committed,
+            vec!["2-7".to_string(), "3-1".to_string()]
+        );
+    }
+}
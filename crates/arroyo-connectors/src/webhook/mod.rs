@@ -2,10 +2,12 @@ mod operator;
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use anyhow::anyhow;
+use arroyo_rpc::formats::Format;
 use arroyo_rpc::OperatorConfig;
+use arroyo_storage::BackendConfig;
 
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::connector::Connection;
@@ -20,9 +22,9 @@ use tokio::sync::mpsc::Sender;
 use tokio::sync::{Mutex, Semaphore};
 use typify::import_types;
 
-use crate::{construct_http_client, pull_opt, EmptyConfig};
+use crate::{construct_http_client, pull_opt, pull_option_to_u64, EmptyConfig};
 
-use crate::webhook::operator::WebhookSinkFunc;
+use crate::webhook::operator::{BatchConfig, CircuitBreaker, WebhookSinkFunc};
 use arroyo_operator::connector::Connector;
 use arroyo_operator::operator::OperatorNode;
 
@@ -32,6 +34,8 @@ import_types!(schema = "src/webhook/table.json", convert = { {type = "string", f
 const ICON: &str = include_str!("./webhook.svg");
 
 const MAX_INFLIGHT: u32 = 50;
+const DEFAULT_BATCH_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_CIRCUIT_BREAKER_RESET: Duration = Duration::from_secs(30);
 
 pub struct WebhookConnector {}
 
@@ -143,7 +147,9 @@ impl Connector for WebhookConnector {
         table: Self::TableT,
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<arroyo_operator::connector::Connection> {
-        let description = format!("WebhookSink<{}>", table.endpoint.sub_env_vars()?);
+        // the endpoint is only substituted by the operator, once secrets have been resolved on the
+        // worker, so that they aren't needed to plan the pipeline or shown in its description
+        let description = format!("WebhookSink<{}>", table.endpoint.raw_val());
 
         let schema = schema
             .map(|s| s.to_owned())
@@ -159,6 +165,10 @@ impl Connector for WebhookConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            filter: None,
+            delivery_semantics: None,
+            upsert_key: vec![],
+            partition_watermarks: None,
             format: Some(format),
             bad_data: schema.bad_data.clone(),
             framing: schema.framing.clone(),
@@ -186,11 +196,29 @@ impl Connector for WebhookConnector {
 
         let headers = options.remove("headers").map(VarStr::new);
 
+        let non_zero = |name: &str, options: &mut HashMap<String, String>| {
+            pull_option_to_u64(name, options)?
+                .map(|t| t.try_into())
+                .transpose()
+                .map_err(|_| anyhow!("{} must be greater than 0", name))
+        };
+
         let table = WebhookTable {
             endpoint: VarStr::new(endpoint),
             headers,
+            batch_size: non_zero("batch_size", options)?,
+            batch_timeout_millis: non_zero("batch_timeout_millis", options)?,
+            max_retries: pull_option_to_u64("max_retries", options)?,
+            circuit_breaker_threshold: non_zero("circuit_breaker_threshold", options)?,
+            circuit_breaker_reset_millis: non_zero("circuit_breaker_reset_millis", options)?,
+            dead_letter_path: options.remove("dead_letter_path"),
         };
 
+        if let Some(path) = &table.dead_letter_path {
+            BackendConfig::parse_url(path, true)
+                .map_err(|e| anyhow!("invalid dead_letter_path '{}': {}", path, e))?;
+        }
+
         let client = construct_http_client(
             &table.endpoint.sub_env_vars()?,
             table
@@ -211,6 +239,10 @@ impl Connector for WebhookConnector {
         config: OperatorConfig,
     ) -> anyhow::Result<OperatorNode> {
         let url = table.endpoint.sub_env_vars()?;
+        let format = config
+            .format
+            .expect("No format configured for webhook sink");
+
         Ok(OperatorNode::from_operator(Box::new(WebhookSinkFunc {
             url: Arc::new(url.clone()),
             client: construct_http_client(
@@ -222,12 +254,29 @@ impl Connector for WebhookConnector {
                     .transpose()?,
             )?,
             semaphore: Arc::new(Semaphore::new(MAX_INFLIGHT as usize)),
-            serializer: ArrowSerializer::new(
-                config
-                    .format
-                    .expect("No format configured for webhook sink"),
-            ),
+            json: matches!(format, Format::Json(_)),
+            serializer: ArrowSerializer::new(format),
             last_reported_error_at: Arc::new(Mutex::new(SystemTime::UNIX_EPOCH)),
+            batching: table.batch_size.map(|max_rows| BatchConfig {
+                max_rows: max_rows.get() as usize,
+                timeout: table
+                    .batch_timeout_millis
+                    .map(|t| Duration::from_millis(t.get()))
+                    .unwrap_or(DEFAULT_BATCH_TIMEOUT),
+            }),
+            max_retries: table.max_retries.map(|r| r.min(u32::MAX as u64) as u32),
+            circuit_breaker: table.circuit_breaker_threshold.map(|threshold| {
+                Arc::new(CircuitBreaker::new(
+                    threshold.get().min(u32::MAX as u64) as u32,
+                    table
+                        .circuit_breaker_reset_millis
+                        .map(|t| Duration::from_millis(t.get()))
+                        .unwrap_or(DEFAULT_CIRCUIT_BREAKER_RESET),
+                ))
+            }),
+            dead_letter_path: table.dead_letter_path,
+            dead_letter: None,
+            buffer: vec![],
         })))
     }
 }
@@ -3,21 +3,28 @@ use async_trait::async_trait;
 use std::collections::HashMap;
 use std::{
     sync::Arc,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
-use arroyo_types::CheckpointBarrier;
+use arroyo_types::{CheckpointBarrier, SignalMessage};
 
+use tokio::sync::mpsc::Sender;
 use tokio::sync::{Mutex, Semaphore};
 use tracing::warn;
+use uuid::Uuid;
 
 use crate::webhook::MAX_INFLIGHT;
 use arroyo_formats::ser::ArrowSerializer;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::ArrowOperator;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::ControlResp;
 use arroyo_state::global_table_config;
+use arroyo_storage::StorageProvider;
+use reqwest::StatusCode;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
 
 pub struct WebhookSinkFunc {
     pub url: Arc<String>,
@@ -25,6 +32,336 @@ pub struct WebhookSinkFunc {
     pub client: reqwest::Client,
     pub serializer: ArrowSerializer,
     pub last_reported_error_at: Arc<Mutex<SystemTime>>,
+    /// whether the sink writes JSON, in which case batched rows are sent as a JSON array rather
+    /// than separated by newlines
+    pub json: bool,
+    pub batching: Option<BatchConfig>,
+    /// how many times a request is retried before it's considered failed; unlimited if not set
+    pub max_retries: Option<u32>,
+    pub circuit_breaker: Option<Arc<CircuitBreaker>>,
+    /// where to write the payloads of requests that permanently fail
+    pub dead_letter_path: Option<String>,
+    pub dead_letter: Option<Arc<StorageProvider>>,
+    pub buffer: Vec<Vec<u8>>,
+}
+
+pub struct BatchConfig {
+    pub max_rows: usize,
+    pub timeout: Duration,
+}
+
+fn batch_body(rows: Vec<Vec<u8>>, json: bool) -> Vec<u8> {
+    let (start, separator, end): (&[u8], &[u8], &[u8]) = if json {
+        (b"[", b",", b"]")
+    } else {
+        (b"", b"\n", b"")
+    };
+
+    let mut body = start.to_vec();
+    for (i, row) in rows.into_iter().enumerate() {
+        if i > 0 {
+            body.extend_from_slice(separator);
+        }
+        body.extend(row);
+    }
+    body.extend_from_slice(end);
+    body
+}
+
+/// Stops sending requests to the endpoint for a while once `threshold` requests in a row have
+/// failed. After the reset timeout a single trial request is let through, which either closes the
+/// breaker or opens it again.
+pub struct CircuitBreaker {
+    threshold: u32,
+    reset_timeout: Duration,
+    state: std::sync::Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    trial_in_progress: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, reset_timeout: Duration) -> Self {
+        Self {
+            threshold,
+            reset_timeout,
+            state: Default::default(),
+        }
+    }
+
+    /// Waits until the breaker allows a request to be sent
+    async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                match state.open_until {
+                    None => return,
+                    Some(until) if until > now => until - now,
+                    Some(_) if !state.trial_in_progress => {
+                        state.trial_in_progress = true;
+                        return;
+                    }
+                    Some(_) => Duration::from_millis(100),
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::default();
+    }
+
+    /// Records a failed request, returning true if this opened the breaker
+    fn record_failure(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        state.trial_in_progress = false;
+        if state.consecutive_failures < self.threshold {
+            return false;
+        }
+
+        let was_closed = state.open_until.is_none();
+        state.open_until = Some(Instant::now() + self.reset_timeout);
+        was_closed
+    }
+
+    fn is_open(&self) -> bool {
+        self.state.lock().unwrap().open_until.is_some()
+    }
+}
+
+enum RequestError {
+    /// the request may succeed if retried, like a network error or a 5xx response
+    Retryable(String, ErrorCategory),
+    /// the endpoint rejected the payload
+    Permanent(String, ErrorCategory),
+}
+
+fn classify(result: reqwest::Result<reqwest::Response>) -> Result<(), RequestError> {
+    let status = match result {
+        Ok(response) => response.status(),
+        Err(e) => {
+            return Err(e
+                .status()
+                .and_then(|status| classify_status(status).err())
+                .unwrap_or_else(|| {
+                    RequestError::Retryable(e.to_string(), ErrorCategory::Connector)
+                }))
+        }
+    };
+    classify_status(status)
+}
+
+fn classify_status(status: StatusCode) -> Result<(), RequestError> {
+    if status.is_success() || status.is_redirection() || status.is_informational() {
+        return Ok(());
+    }
+
+    let details = format!("server responded with error code: {}", status.as_u16());
+    let category = if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        ErrorCategory::ConnectorAuth
+    } else {
+        ErrorCategory::Connector
+    };
+
+    if status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || category == ErrorCategory::ConnectorAuth
+    {
+        Err(RequestError::Retryable(details, category))
+    } else {
+        Err(RequestError::Permanent(details, category))
+    }
+}
+
+/// Exponential backoff with jitter, so that concurrent requests don't retry in lockstep
+fn backoff(retries: u32) -> Duration {
+    let max = Duration::from_millis(50 * (1 << retries.min(16))).min(MAX_BACKOFF);
+    max.mul_f64(0.5 + rand::random::<f64>() / 2.0)
+}
+
+/// The state shared by the tasks sending requests for a subtask
+struct RequestContext {
+    url: Arc<String>,
+    client: reqwest::Client,
+    max_retries: Option<u32>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    dead_letter: Option<Arc<StorageProvider>>,
+    dead_letter_suffix: &'static str,
+    last_reported_error_at: Arc<Mutex<SystemTime>>,
+    control_tx: Sender<ControlResp>,
+    operator_id: String,
+    task_index: usize,
+}
+
+impl RequestContext {
+    async fn send(&self, body: bytes::Bytes) {
+        let mut retries = 0;
+        loop {
+            if let Some(breaker) = &self.circuit_breaker {
+                breaker.acquire().await;
+            }
+
+            let req = self
+                .client
+                .post(&*self.url)
+                .body(body.clone())
+                .build()
+                .expect("failed to build request");
+
+            let (details, category) = match classify(self.client.execute(req).await) {
+                Ok(()) => {
+                    if let Some(breaker) = &self.circuit_breaker {
+                        breaker.record_success();
+                    }
+                    return;
+                }
+                Err(RequestError::Permanent(details, category)) => {
+                    // the endpoint is up, so this doesn't count against the circuit breaker
+                    if let Some(breaker) = &self.circuit_breaker {
+                        breaker.record_success();
+                    }
+                    self.report_error("webhook rejected payload".to_string(), &details, category)
+                        .await;
+                    self.dead_letter(body).await;
+                    return;
+                }
+                Err(RequestError::Retryable(details, category)) => (details, category),
+            };
+
+            if let Some(breaker) = &self.circuit_breaker {
+                if breaker.record_failure() {
+                    self.report_error(
+                        "webhook circuit breaker opened".to_string(),
+                        &format!(
+                            "{} requests in a row failed: {}",
+                            breaker.threshold, details
+                        ),
+                        category,
+                    )
+                    .await;
+                }
+            }
+
+            if self.max_retries.is_some_and(|max| retries >= max) {
+                self.report_error(
+                    format!("webhook failed after {} retries", retries),
+                    &details,
+                    category,
+                )
+                .await;
+                self.dead_letter(body).await;
+                return;
+            }
+
+            self.report_error(
+                format!("webhook failed (retry {})", retries),
+                &details,
+                category,
+            )
+            .await;
+
+            retries += 1;
+            tokio::time::sleep(backoff(retries)).await;
+        }
+    }
+
+    async fn dead_letter(&self, body: bytes::Bytes) {
+        let Some(storage) = &self.dead_letter else {
+            warn!("dropping webhook payload of {} bytes", body.len());
+            return;
+        };
+
+        let path = format!(
+            "{}-{}-{}.{}",
+            self.operator_id,
+            self.task_index,
+            Uuid::new_v4(),
+            self.dead_letter_suffix
+        );
+        if let Err(e) = storage.put(path, body.to_vec()).await {
+            warn!(
+                "failed to write webhook payload to dead letter path: {:?}",
+                e
+            );
+        }
+    }
+
+    /// Reports an error to the controller, at most once per second
+    async fn report_error(&self, message: String, details: &str, category: ErrorCategory) {
+        let Ok(mut last_reported) = self.last_reported_error_at.try_lock() else {
+            return;
+        };
+
+        if last_reported.elapsed().unwrap_or_default() > Duration::from_secs(1) {
+            warn!("{}: {}", message, details);
+
+            self.control_tx
+                .send(ControlResp::Error {
+                    operator_id: self.operator_id.clone(),
+                    task_index: self.task_index,
+                    message,
+                    details: details.to_string(),
+                    category,
+                })
+                .await
+                .unwrap();
+
+            *last_reported = SystemTime::now();
+        }
+    }
+}
+
+impl WebhookSinkFunc {
+    async fn send(&mut self, body: Vec<u8>, ctx: &mut ArrowContext) {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("websink semaphore closed");
+
+        let request_ctx = RequestContext {
+            url: self.url.clone(),
+            client: self.client.clone(),
+            max_retries: self.max_retries,
+            circuit_breaker: self.circuit_breaker.clone(),
+            dead_letter: self.dead_letter.clone(),
+            dead_letter_suffix: if self.json { "json" } else { "bin" },
+            last_reported_error_at: self.last_reported_error_at.clone(),
+            control_tx: ctx.control_tx.clone(),
+            operator_id: ctx.task_info.operator_id.clone(),
+            task_index: ctx.task_info.task_index,
+        };
+
+        tokio::task::spawn(async move {
+            // move the permit into the task
+            let _permit = permit;
+            request_ctx.send(body.into()).await;
+        });
+    }
+
+    async fn flush(&mut self, ctx: &mut ArrowContext) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let rows = std::mem::take(&mut self.buffer);
+        let body = batch_body(rows, self.json);
+        self.send(body, ctx).await;
+    }
+
+    /// Waits for all in-flight requests to finish, including their retries
+    async fn wait_for_inflight(&self) {
+        let _permits = self.semaphore.acquire_many(MAX_INFLIGHT).await.unwrap();
+    }
 }
 
 #[async_trait]
@@ -37,86 +374,99 @@ impl ArrowOperator for WebhookSinkFunc {
         global_table_config("s", "webhook sink state")
     }
 
+    fn tick_interval(&self) -> Option<Duration> {
+        self.batching.as_ref().map(|b| b.timeout)
+    }
+
+    async fn on_start(&mut self, _ctx: &mut ArrowContext) {
+        if let Some(path) = &self.dead_letter_path {
+            self.dead_letter = Some(Arc::new(
+                StorageProvider::for_url(path)
+                    .await
+                    .unwrap_or_else(|e| panic!("invalid dead letter path {}: {:?}", path, e)),
+            ));
+        }
+    }
+
     async fn process_batch(&mut self, record: RecordBatch, ctx: &mut ArrowContext) {
         for body in self.serializer.serialize(&record) {
-            let permit = self
-                .semaphore
-                .clone()
-                .acquire_owned()
-                .await
-                .expect("websink semaphore closed");
-
-            let body: bytes::Bytes = body.into();
-
-            let client = self.client.clone();
-            let control_tx = ctx.control_tx.clone();
-            let error_lock = self.last_reported_error_at.clone();
-            let url = self.url.clone();
-
-            // these are just used for (potential) error reporting and we don't need to clone them
-            let operator_id = ctx.task_info.operator_id.clone();
-            let task_index = ctx.task_info.task_index;
-
-            tokio::task::spawn(async move {
-                // move the permit into the task
-                let _permit = permit;
-                let mut retries = 0;
-                loop {
-                    let req = client
-                        .post(&*url)
-                        .body(body.clone())
-                        .build()
-                        .expect("failed to build request");
-
-                    match client.execute(req).await {
-                        Ok(_) => break,
-                        Err(e) => {
-                            if let Ok(mut last_reported) = error_lock.try_lock() {
-                                if last_reported.elapsed().unwrap_or_default()
-                                    > Duration::from_secs(1)
-                                {
-                                    warn!("websink request failed: {:?}", e);
-
-                                    let details = if let Some(status) = e.status() {
-                                        format!(
-                                            "server responded with error code: {}",
-                                            status.as_u16()
-                                        )
-                                    } else {
-                                        e.to_string()
-                                    };
-
-                                    control_tx
-                                        .send(ControlResp::Error {
-                                            operator_id: operator_id.clone(),
-                                            task_index,
-                                            message: format!("webhook failed (retry {})", retries),
-                                            details,
-                                        })
-                                        .await
-                                        .unwrap();
-
-                                    *last_reported = SystemTime::now();
-                                }
-                            }
-
-                            retries += 1;
-
-                            tokio::time::sleep(Duration::from_millis(
-                                (50 * (1 << retries)).min(5_000),
-                            ))
-                            .await
-                        }
-                    }
-                }
-            });
+            let Some(batching) = &self.batching else {
+                self.send(body, ctx).await;
+                continue;
+            };
+
+            self.buffer.push(body);
+            if self.buffer.len() >= batching.max_rows {
+                self.flush(ctx).await;
+            }
         }
     }
 
-    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, _ctx: &mut ArrowContext) {
+    async fn handle_tick(&mut self, _: u64, ctx: &mut ArrowContext) {
+        self.flush(ctx).await;
+    }
+
+    async fn handle_checkpoint(&mut self, _: CheckpointBarrier, ctx: &mut ArrowContext) {
+        self.flush(ctx).await;
+
         // wait to acquire all of the permits (effectively blocking until all inflight requests are done)
-        let _permits = self.semaphore.acquire_many(MAX_INFLIGHT).await.unwrap();
+        self.wait_for_inflight().await;
+
+        if self.circuit_breaker.as_ref().is_some_and(|b| b.is_open()) {
+            warn!("checkpointing webhook sink while its circuit breaker is open");
+        }
 
         // TODO: instead of blocking checkpoints on in-progress (or failing) requests, we should store them to state
     }
+
+    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        self.flush(ctx).await;
+        self.wait_for_inflight().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_body() {
+        let rows = || vec![b"{\"a\":1}".to_vec(), b"{\"a\":2}".to_vec()];
+        assert_eq!(batch_body(rows(), true), b"[{\"a\":1},{\"a\":2}]");
+        assert_eq!(batch_body(rows(), false), b"{\"a\":1}\n{\"a\":2}");
+    }
+
+    #[test]
+    fn test_classify_status() {
+        assert!(classify_status(StatusCode::OK).is_ok());
+        assert!(matches!(
+            classify_status(StatusCode::SERVICE_UNAVAILABLE),
+            Err(RequestError::Retryable(..))
+        ));
+        assert!(matches!(
+            classify_status(StatusCode::TOO_MANY_REQUESTS),
+            Err(RequestError::Retryable(..))
+        ));
+        assert!(matches!(
+            classify_status(StatusCode::BAD_REQUEST),
+            Err(RequestError::Permanent(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+        assert!(!breaker.record_failure());
+        assert!(breaker.record_failure());
+        assert!(breaker.is_open());
+        // further failures while open don't reopen it
+        assert!(!breaker.record_failure());
+
+        let start = Instant::now();
+        breaker.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        breaker.record_success();
+        assert!(!breaker.is_open());
+    }
 }
@@ -6,6 +6,7 @@ use std::time::SystemTime;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::SourceOperator;
 use arroyo_operator::SourceFinishType;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::{grpc::StopMode, ControlMessage};
@@ -16,7 +17,7 @@ use bincode::{Decode, Encode};
 use futures::{SinkExt, StreamExt};
 use tokio::select;
 use tokio_tungstenite::tungstenite::handshake::client::generate_key;
-use tokio_tungstenite::tungstenite::http::Uri;
+use tokio_tungstenite::tungstenite::http::{StatusCode, Uri};
 use tokio_tungstenite::{connect_async, tungstenite};
 use tracing::{debug, info};
 use tungstenite::http::Request;
@@ -66,7 +67,7 @@ impl SourceOperator for WebsocketSourceFunc {
         match self.run_int(ctx).await {
             Ok(r) => r,
             Err(e) => {
-                ctx.report_error(e.name.clone(), e.details.clone()).await;
+                ctx.report_source_error(e.clone()).await;
 
                 panic!("{}: {}", e.name, e.details);
             }
@@ -112,7 +113,10 @@ impl WebsocketSourceFunc {
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
-            ControlMessage::NoOp => {}
+            ControlMessage::UpdateParameters { parameters } => {
+                ctx.update_parameters(&parameters);
+            }
+            ControlMessage::NoOp | ControlMessage::AlignWatermark { .. } => {}
         }
         None
     }
@@ -135,8 +139,12 @@ impl WebsocketSourceFunc {
         let uri = match Uri::from_str(&self.url.to_string()) {
             Ok(uri) => uri,
             Err(e) => {
-                ctx.report_error("Failed to parse endpoint".to_string(), format!("{:?}", e))
-                    .await;
+                ctx.report_error(
+                    ErrorCategory::Connector,
+                    "Failed to parse endpoint".to_string(),
+                    format!("{:?}", e),
+                )
+                .await;
                 panic!("Failed to parse endpoint: {:?}", e);
             }
         };
@@ -144,8 +152,12 @@ impl WebsocketSourceFunc {
         let host = match uri.host() {
             Some(host) => host,
             None => {
-                ctx.report_error("Endpoint must have a host".to_string(), "".to_string())
-                    .await;
+                ctx.report_error(
+                    ErrorCategory::Connector,
+                    "Endpoint must have a host".to_string(),
+                    "".to_string(),
+                )
+                .await;
                 panic!("Endpoint must have a host");
             }
         };
@@ -166,8 +178,12 @@ impl WebsocketSourceFunc {
         {
             Ok(request) => request,
             Err(e) => {
-                ctx.report_error("Failed to build request".to_string(), format!("{:?}", e))
-                    .await;
+                ctx.report_error(
+                    ErrorCategory::Connector,
+                    "Failed to build request".to_string(),
+                    format!("{:?}", e),
+                )
+                .await;
                 panic!("Failed to build request: {:?}", e);
             }
         };
@@ -175,7 +191,18 @@ impl WebsocketSourceFunc {
         let ws_stream = match connect_async(request).await {
             Ok((ws_stream, _)) => ws_stream,
             Err(e) => {
+                let category = match &e {
+                    tungstenite::Error::Http(response)
+                        if response.status() == StatusCode::UNAUTHORIZED
+                            || response.status() == StatusCode::FORBIDDEN =>
+                    {
+                        ErrorCategory::ConnectorAuth
+                    }
+                    _ => ErrorCategory::Connector,
+                };
+
                 ctx.report_error(
+                    category,
                     "Failed to connect to websocket server".to_string(),
                     e.to_string(),
                 )
@@ -189,6 +216,7 @@ impl WebsocketSourceFunc {
         for msg in &self.subscription_messages {
             if let Err(e) = tx.send(tungstenite::Message::Text(msg.clone())).await {
                 ctx.report_error(
+                    ErrorCategory::Connector,
                     "Failed to send subscription message to websocket server".to_string(),
                     e.to_string(),
                 )
@@ -226,7 +254,7 @@ impl WebsocketSourceFunc {
                                         // ignore
                                     },
                                     tungstenite::Message::Close(_) => {
-                                        ctx.report_error("Received close frame from server".to_string(), "".to_string()).await;
+                                        ctx.report_error(ErrorCategory::Connector, "Received close frame from server".to_string(), "".to_string()).await;
                                         panic!("Received close frame from server");
                                     },
                                     tungstenite::Message::Frame(_) => {
@@ -235,7 +263,7 @@ impl WebsocketSourceFunc {
                                 };
                             }
                         Some(Err(e)) => {
-                            ctx.report_error("Error while reading from websocket".to_string(), format!("{:?}", e)).await;
+                            ctx.report_error(ErrorCategory::Connector, "Error while reading from websocket".to_string(), format!("{:?}", e)).await;
                             panic!("Error while reading from websocket: {:?}", e);
                         }
                         None => {
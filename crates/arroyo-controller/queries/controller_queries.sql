@@ -1,9 +1,12 @@
---! all_jobs : Job(ttl_micros?, state?, start_time?, finish_time?, tasks?, failure_message?, run_id?, pipeline_path?, wasm_path?)
+--! all_jobs : Job(ttl_micros?, state?, start_time?, finish_time?, tasks?, failure_message?, failure_category?, run_id?, pipeline_path?, wasm_path?, max_task_slots?, max_state_bytes?, preempted_slots?, worker_pod?, freshness_slo?, restart_strategy?)
 SELECT
     c.id as id,
     c.organization_id as org_id,
     pipeline_name,
     pipeline_id,
+    p.namespace as namespace,
+    n.max_task_slots as max_task_slots,
+    n.max_state_bytes as max_state_bytes,
     checkpoint_interval_micros,
     ttl_micros,
     parallelism_overrides,
@@ -13,23 +16,33 @@ SELECT
     finish_time,
     tasks,
     failure_message,
+    failure_category,
     restarts,
     run_id,
     pipeline_path,
     wasm_path,
     c.restart_nonce as config_restart_nonce,
     s.restart_nonce as status_restart_nonce,
-    restart_mode
+    restart_mode,
+    c.priority as priority,
+    c.preempted_slots as preempted_slots,
+    c.worker_pod as worker_pod,
+    c.udf_reload_nonce as udf_reload_nonce,
+    c.freshness_slo as freshness_slo,
+    c.restart_strategy as restart_strategy
 FROM job_configs c
-LEFT JOIN job_statuses s ON c.id = s.id;
+LEFT JOIN job_statuses s ON c.id = s.id
+INNER JOIN pipelines p ON c.pipeline_id = p.id
+LEFT JOIN namespaces n ON n.organization_id = p.organization_id AND n.name = p.namespace;
 
---! update_job_status (start_time?, finish_time?, tasks?, failure_message?, pipeline_path?, wasm_path?)
+--! update_job_status (start_time?, finish_time?, tasks?, failure_message?, failure_category?, pipeline_path?, wasm_path?)
 UPDATE job_statuses
 SET state = :state,
     start_time = :start_time,
     finish_time = :finish_time,
     tasks = :tasks,
     failure_message = :failure_message,
+    failure_category = :failure_category,
     restarts = :restarts,
     pipeline_path = :pipeline_path,
     wasm_path = :wasm_path,
@@ -37,22 +50,45 @@ SET state = :state,
     restart_nonce = :restart_nonce
 WHERE id = :job_id;
 
+--! preempt_job
+UPDATE job_configs
+SET preempted_slots = :preempted_slots
+WHERE id = :job_id AND preempted_slots IS NULL;
+
+--! get_preempted_jobs : (state?)
+SELECT c.id as id, c.preempted_slots as preempted_slots, s.state as state
+FROM job_configs c
+LEFT JOIN job_statuses s ON c.id = s.id
+WHERE c.preempted_slots IS NOT NULL
+ORDER BY c.priority DESC, c.created_at ASC;
+
+--! resume_preempted_job
+UPDATE job_configs
+SET preempted_slots = NULL
+WHERE id = :job_id;
+
 --! get_program
 SELECT program, proto_version FROM pipelines WHERE id = :id;
 
---! mark_checkpoints_compacted
+--! mark_checkpoint_compacted
 UPDATE checkpoints
     set state = 'compacted'
-WHERE job_id = :job_id AND epoch < :epoch;
+WHERE job_id = :job_id AND epoch = :epoch;
 
 --! drop_old_checkpoint_rows
 DELETE FROM checkpoints
-WHERE job_id = :job_id AND epoch < :epoch;
+WHERE job_id = :job_id AND epoch < :epoch AND state IN ('compacted', 'failed');
+
+--! completed_checkpoints : (finish_time?)
+SELECT epoch, finish_time, savepoint
+FROM checkpoints
+WHERE job_id = :job_id AND state = 'ready' AND epoch <= :epoch
+ORDER BY epoch DESC;
 
 --! create_checkpoint
 INSERT INTO checkpoints
-(pub_id, organization_id, job_id, state_backend, epoch, min_epoch, start_time)
-VALUES (:pub_id, :organization_id, :job_id, :state_backend, :epoch, :min_epoch, :start_time);
+(pub_id, organization_id, job_id, state_backend, epoch, min_epoch, start_time, savepoint)
+VALUES (:pub_id, :organization_id, :job_id, :state_backend, :epoch, :min_epoch, :start_time, :savepoint);
 
 --! update_checkpoint (finish_time?)
 UPDATE checkpoints
@@ -69,12 +105,6 @@ SET
     state = 'ready'
 WHERE pub_id = :pub_id;
 
---! mark_compacting
-UPDATE checkpoints
-SET
-    state = 'compacting'
-WHERE job_id = :job_id AND epoch >= :min_epoch AND epoch < :epoch;
-
 --! mark_failed
 UPDATE checkpoints
 SET
@@ -88,9 +118,21 @@ WHERE job_id = :job_id AND (state = 'ready' or state = 'committing')
 ORDER BY epoch DESC
 LIMIT 1;
 
---! create_job_log_message
-INSERT INTO job_log_messages (pub_id, job_id, operator_id, task_index, log_level, message, details)
-VALUES (:pub_id, :job_id, :operator_id, :task_index, :log_level, :message, :details);
+--! last_replicated_checkpoint
+SELECT pub_id, epoch, min_epoch
+FROM checkpoints
+WHERE job_id = :job_id AND state = 'ready' AND replicated
+ORDER BY epoch DESC
+LIMIT 1;
+
+--! mark_checkpoint_replicated
+UPDATE checkpoints
+SET replicated = true
+WHERE job_id = :job_id AND epoch = :epoch;
+
+--! create_job_log_message (error_category?)
+INSERT INTO job_log_messages (pub_id, job_id, operator_id, task_index, log_level, message, details, error_category)
+VALUES (:pub_id, :job_id, :operator_id, :task_index, :log_level, :message, :details, :error_category);
 
 --! clean_preview_pipelines
 DELETE FROM pipelines WHERE id in (
@@ -99,4 +141,93 @@ DELETE FROM pipelines WHERE id in (
   INNER JOIN job_statuses js ON jc.id = js.id
   WHERE (js.state = 'Finished' OR js.state = 'Stopped' OR js.state = 'Failed')
     AND jc.ttl_micros > 0
-    AND jc.created_at < :created_at);
\ No newline at end of file
+    AND jc.created_at < :created_at);
+--! get_pipeline_schedules : (next_run_at?, state?, run_id?)
+SELECT
+    s.id as id,
+    s.pipeline_id as pipeline_id,
+    s.cron as cron,
+    s.next_run_at as next_run_at,
+    c.id as job_id,
+    st.state as state,
+    st.run_id as run_id
+FROM pipeline_schedules s
+INNER JOIN job_configs c ON c.pipeline_id = s.pipeline_id
+LEFT JOIN job_statuses st ON st.id = c.id
+WHERE s.enabled;
+
+--! set_schedule_next_run
+UPDATE pipeline_schedules
+SET next_run_at = :next_run_at
+WHERE id = :id;
+
+--! trigger_scheduled_job
+UPDATE job_configs
+SET
+    updated_at = :updated_at,
+    stop = 'none',
+    restart_nonce = restart_nonce + 1,
+    restart_mode = 'safe'
+WHERE id = :job_id;
+
+--! reset_checkpoints
+UPDATE checkpoints
+SET state = 'failed'
+WHERE job_id = :job_id AND (state = 'ready' OR state = 'committing');
+
+--! create_scheduled_run (finished_at?, failure_message?)
+INSERT INTO scheduled_runs (pub_id, pipeline_id, job_id, run_id, state, scheduled_for, finished_at, failure_message)
+VALUES (:pub_id, :pipeline_id, :job_id, :run_id, :state, :scheduled_for, :finished_at, :failure_message);
+
+--! get_active_scheduled_runs : (state?, run_id?, failure_message?)
+SELECT
+    r.id as id,
+    r.run_id as triggered_run_id,
+    s.state as state,
+    s.run_id as run_id,
+    s.failure_message as failure_message
+FROM scheduled_runs r
+LEFT JOIN job_statuses s ON s.id = r.job_id
+WHERE r.state = 'running';
+
+--! finish_scheduled_run (failure_message?)
+UPDATE scheduled_runs
+SET state = :state, finished_at = :finished_at, failure_message = :failure_message
+WHERE id = :id;
+
+--! record_watermark
+INSERT INTO watermark_history (job_id, operator_id, time, watermark)
+VALUES (:job_id, :operator_id, :time, :watermark);
+
+--! clean_watermark_history
+DELETE FROM watermark_history WHERE time < :cutoff;
+
+--! record_metric_sample
+INSERT INTO metric_history (job_id, operator_id, metric, resolution_secs, time, value)
+VALUES (:job_id, :operator_id, :metric, :resolution_secs, :time, :value);
+
+--! clean_metric_history
+DELETE FROM metric_history WHERE resolution_secs = :resolution_secs AND time < :cutoff;
+
+--! record_job_restart
+INSERT INTO job_restarts (job_id, run_id, time, attempt, delay_micros, reason)
+VALUES (:job_id, :run_id, :time, :attempt, :delay_micros, :reason);
+
+--! get_job_restarts_since
+SELECT run_id, time FROM job_restarts WHERE job_id = :job_id AND time >= :since;
+
+--! clean_job_restarts
+DELETE FROM job_restarts WHERE time < :cutoff;
+
+--! get_job_notification_targets : (payload_template?)
+SELECT
+    notification_targets.pub_id as pub_id,
+    notification_targets.config as config,
+    notification_targets.events as events,
+    notification_targets.payload_template as payload_template,
+    pipelines.pub_id as pipeline_pub_id
+FROM notification_targets
+INNER JOIN pipelines ON pipelines.id = :pipeline_id
+WHERE notification_targets.organization_id = :organization_id
+    AND (notification_targets.pipeline_id IS NULL
+        OR notification_targets.pipeline_id = pipelines.id);
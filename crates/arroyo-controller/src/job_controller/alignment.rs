@@ -0,0 +1,128 @@
+//! Computes watermark-alignment bounds for a pipeline's sources.
+//!
+//! Sources that share a watermark-alignment group should make progress through event time at
+//! roughly the same rate, so that operators joining or windowing over them don't need to buffer
+//! the data of a fast source while waiting for a slow one (as happens when backfilling from
+//! sources with different amounts of history). Each time metrics are collected from the workers,
+//! the controller finds the slowest watermark in each group and sends every member the maximum
+//! watermark it may advance to; members that get further ahead than that stop reading until the
+//! rest of the group catches up.
+
+use arroyo_datastream::logical::{LogicalProgram, OperatorName};
+use arroyo_rpc::grpc::api::ExpressionWatermarkConfig;
+use prost::Message;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+#[derive(Debug)]
+struct AlignmentGroup {
+    max_drift: Duration,
+    operators: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct WatermarkAligner {
+    groups: HashMap<String, AlignmentGroup>,
+}
+
+impl WatermarkAligner {
+    pub fn new(program: &LogicalProgram) -> Self {
+        let mut groups: HashMap<String, AlignmentGroup> = HashMap::new();
+
+        for node in program.graph.node_weights() {
+            if node.operator_name != OperatorName::ExpressionWatermark {
+                continue;
+            }
+
+            let config = match ExpressionWatermarkConfig::decode(&node.operator_config[..]) {
+                Ok(config) => config,
+                Err(e) => {
+                    warn!(
+                        "invalid config for watermark operator {}: {:?}",
+                        node.operator_id, e
+                    );
+                    continue;
+                }
+            };
+
+            let Some(group) = config.alignment_group else {
+                continue;
+            };
+
+            let max_drift =
+                Duration::from_micros(config.alignment_max_drift_micros.unwrap_or_default());
+            let group = groups.entry(group).or_insert_with(|| AlignmentGroup {
+                max_drift,
+                operators: vec![],
+            });
+            // if members of the group disagree, use the tightest bound
+            group.max_drift = group.max_drift.min(max_drift);
+            group.operators.push(node.operator_id.clone());
+        }
+
+        // a group with a single member has nothing to align with
+        groups.retain(|_, g| g.operators.len() > 1);
+
+        Self { groups }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Returns the maximum watermark that each aligned operator may advance to, given the current
+    /// watermark of each operator. Groups where some member has not reported a watermark (because
+    /// it hasn't emitted one yet, or is idle) are not bounded.
+    pub fn bounds(&self, watermarks: &HashMap<String, SystemTime>) -> HashMap<String, SystemTime> {
+        let mut bounds = HashMap::new();
+
+        for group in self.groups.values() {
+            let Some(slowest) = group
+                .operators
+                .iter()
+                .map(|op| watermarks.get(op).copied())
+                .collect::<Option<Vec<_>>>()
+                .and_then(|w| w.into_iter().min())
+            else {
+                continue;
+            };
+
+            for op in &group.operators {
+                bounds.insert(op.clone(), slowest + group.max_drift);
+            }
+        }
+
+        bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounds() {
+        let mut groups = HashMap::new();
+        groups.insert(
+            "g".to_string(),
+            AlignmentGroup {
+                max_drift: Duration::from_secs(10),
+                operators: vec!["a".to_string(), "b".to_string()],
+            },
+        );
+        let aligner = WatermarkAligner { groups };
+
+        let t = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let mut watermarks = HashMap::new();
+        watermarks.insert("a".to_string(), t);
+
+        // b hasn't reported a watermark yet
+        assert!(aligner.bounds(&watermarks).is_empty());
+
+        watermarks.insert("b".to_string(), t + Duration::from_secs(60));
+        let bounds = aligner.bounds(&watermarks);
+        assert_eq!(bounds.get("a"), Some(&(t + Duration::from_secs(10))));
+        assert_eq!(bounds.get("b"), Some(&(t + Duration::from_secs(10))));
+    }
+}
@@ -0,0 +1,193 @@
+//! Tracks compliance with a pipeline's freshness SLO.
+//!
+//! Each time metrics are collected from the workers, the lag between wall-clock time and the
+//! watermark of the pipeline's sinks is compared against the SLO's target. Compliance is the
+//! fraction of those samples over the trailing window that met the target, and the burn rate is
+//! the rate at which the error budget (`1 - objective`) is being consumed; a burn rate above 1
+//! means the objective will be missed if the current rate of violations continues. Alerts are
+//! sent to the SLO's webhook when the pipeline starts and stops violating the target.
+
+use arroyo_rpc::api_types::pipelines::FreshnessSlo;
+use lazy_static::lazy_static;
+use prometheus::{register_gauge_vec, GaugeVec};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+const COMPLIANCE_WINDOW: Duration = Duration::from_secs(60 * 60);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref FRESHNESS_LAG: GaugeVec = register_gauge_vec!(
+        "arroyo_controller_freshness_lag_ms",
+        "Milliseconds between the current time and the watermark of the job's sinks",
+        &["job_id"]
+    )
+    .unwrap();
+    static ref FRESHNESS_COMPLIANCE: GaugeVec = register_gauge_vec!(
+        "arroyo_controller_freshness_slo_compliance",
+        "Fraction of the last hour that the job met its freshness target",
+        &["job_id"]
+    )
+    .unwrap();
+    static ref FRESHNESS_BURN_RATE: GaugeVec = register_gauge_vec!(
+        "arroyo_controller_freshness_slo_burn_rate",
+        "Rate at which the job is consuming the error budget of its freshness SLO",
+        &["job_id"]
+    )
+    .unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Status {
+    compliance: f64,
+    burn_rate: f64,
+}
+
+pub struct FreshnessTracker {
+    job_id: Arc<String>,
+    samples: VecDeque<(Instant, bool)>,
+    violating: bool,
+}
+
+impl FreshnessTracker {
+    pub fn new(job_id: Arc<String>) -> Self {
+        Self {
+            job_id,
+            samples: VecDeque::new(),
+            violating: false,
+        }
+    }
+
+    fn record(&mut self, now: Instant, met: bool, objective: f64) -> Status {
+        self.samples.push_back((now, met));
+        while let Some((t, _)) = self.samples.front() {
+            if now.duration_since(*t) > COMPLIANCE_WINDOW {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let met = self.samples.iter().filter(|(_, met)| *met).count();
+        let compliance = met as f64 / self.samples.len() as f64;
+
+        Status {
+            compliance,
+            burn_rate: (1.0 - compliance) / (1.0 - objective),
+        }
+    }
+
+    /// Records the current sink watermark lag against the SLO
+    pub fn observe(&mut self, slo: &FreshnessSlo, lag: Duration) {
+        let target = Duration::from_millis(slo.target_millis);
+        let met = lag <= target;
+        let status = self.record(Instant::now(), met, slo.objective());
+
+        let labels = [self.job_id.as_str()];
+        FRESHNESS_LAG
+            .with_label_values(&labels)
+            .set(lag.as_millis() as f64);
+        FRESHNESS_COMPLIANCE
+            .with_label_values(&labels)
+            .set(status.compliance);
+        FRESHNESS_BURN_RATE
+            .with_label_values(&labels)
+            .set(status.burn_rate);
+
+        if met == self.violating {
+            self.violating = !met;
+
+            if self.violating {
+                warn!(
+                    message = "job is violating its freshness SLO",
+                    job_id = *self.job_id,
+                    lag_ms = lag.as_millis() as u64,
+                    target_ms = slo.target_millis
+                );
+            } else {
+                info!(
+                    message = "job is meeting its freshness SLO again",
+                    job_id = *self.job_id,
+                    lag_ms = lag.as_millis() as u64
+                );
+            }
+
+            if let Some(url) = &slo.alert_webhook_url {
+                self.send_alert(url.clone(), slo, lag, status);
+            }
+        }
+    }
+
+    fn send_alert(&self, url: String, slo: &FreshnessSlo, lag: Duration, status: Status) {
+        let body = json!({
+            "jobId": *self.job_id,
+            "status": if self.violating { "violated" } else { "resolved" },
+            "time": arroyo_types::to_millis(SystemTime::now()),
+            "lagMillis": lag.as_millis() as u64,
+            "targetMillis": slo.target_millis,
+            "objective": slo.objective(),
+            "compliance": status.compliance,
+            "burnRate": status.burn_rate,
+        });
+
+        let job_id = self.job_id.clone();
+        tokio::spawn(async move {
+            let result = reqwest::Client::new()
+                .post(&url)
+                .timeout(WEBHOOK_TIMEOUT)
+                .json(&body)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            if let Err(e) = result {
+                warn!(
+                    message = "failed to send freshness SLO alert",
+                    job_id = *job_id,
+                    error = format!("{:?}", e)
+                );
+            }
+        });
+    }
+}
+
+impl Drop for FreshnessTracker {
+    fn drop(&mut self) {
+        let labels = [self.job_id.as_str()];
+        let _ = FRESHNESS_LAG.remove_label_values(&labels);
+        let _ = FRESHNESS_COMPLIANCE.remove_label_values(&labels);
+        let _ = FRESHNESS_BURN_RATE.remove_label_values(&labels);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compliance() {
+        let mut tracker = FreshnessTracker::new(Arc::new("job".to_string()));
+        let start = Instant::now();
+
+        for i in 0..9 {
+            let status = tracker.record(start + Duration::from_secs(i), true, 0.9);
+            assert_eq!(status.compliance, 1.0);
+            assert_eq!(status.burn_rate, 0.0);
+        }
+
+        let status = tracker.record(start + Duration::from_secs(9), false, 0.9);
+        assert!((status.compliance - 0.9).abs() < 1e-9);
+        assert!((status.burn_rate - 1.0).abs() < 1e-9);
+
+        // samples older than the window no longer count
+        let status = tracker.record(
+            start + COMPLIANCE_WINDOW + Duration::from_secs(10),
+            true,
+            0.9,
+        );
+        assert_eq!(status.compliance, 1.0);
+    }
+}
@@ -1,9 +1,10 @@
 use arroyo_datastream::logical::LogicalProgram;
 use arroyo_rpc::api_types::metrics::{
-    Metric, MetricGroup, MetricName, OperatorMetricGroup, SubtaskMetrics,
+    Metric, MetricGroup, MetricName, OperatorMetricGroup, OperatorRuntimeStats, SubtaskMetrics,
 };
 use arroyo_types::to_micros;
 use petgraph::prelude::NodeIndex;
+use petgraph::Direction;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -33,10 +34,34 @@ pub struct TaskKey {
     pub subtask_idx: u32,
 }
 
+/// A partition of an operator's keyed output, which is sent to the downstream subtask with the
+/// same index
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PartitionKey {
+    pub operator_id: u32,
+    pub partition: u32,
+}
+
 #[derive(Clone)]
 pub struct JobMetrics {
     program: Arc<LogicalProgram>,
     tasks: Arc<RwLock<HashMap<TaskKey, TaskMetrics>>>,
+    partitions: Arc<RwLock<HashMap<PartitionKey, PartitionMetrics>>>,
+    // total rows sent by each operator with range-partitioned outputs, by key hash bucket
+    routing_keys: Arc<RwLock<HashMap<u32, Vec<u64>>>>,
+    // how far each partition read by a source is behind, keyed by the source's partition number
+    source_lag: Arc<RwLock<HashMap<PartitionKey, SourceLagMetrics>>>,
+}
+
+/// The ratio of the largest rate to the mean rate, or None if there's nothing to compare
+fn skew(rates: &[f64]) -> Option<f64> {
+    let total: f64 = rates.iter().sum();
+    if rates.len() < 2 || total <= 0.0 {
+        return None;
+    }
+
+    let max = rates.iter().copied().fold(0.0, f64::max);
+    Some(max / (total / rates.len() as f64))
 }
 
 impl JobMetrics {
@@ -57,6 +82,45 @@ impl JobMetrics {
         Self {
             program,
             tasks: Arc::new(RwLock::new(tasks)),
+            partitions: Default::default(),
+            routing_keys: Default::default(),
+            source_lag: Default::default(),
+        }
+    }
+
+    pub async fn update_routing_keys(&self, values: HashMap<u32, Vec<u64>>) {
+        if !values.is_empty() {
+            *self.routing_keys.write().await = values;
+        }
+    }
+
+    /// The distribution of keys sent by each operator with range-partitioned outputs, indexed by
+    /// node index
+    pub async fn routing_key_distributions(&self) -> HashMap<u32, Vec<u64>> {
+        self.routing_keys.read().await.clone()
+    }
+
+    /// Records the total (messages, bytes) sent to each partition of the keyed exchanges
+    pub async fn update_partitions(&self, values: &HashMap<PartitionKey, (u64, u64)>) {
+        let now = SystemTime::now();
+
+        let mut partitions = self.partitions.write().await;
+        for (key, (messages, bytes)) in values {
+            let partition = partitions.entry(*key).or_insert_with(PartitionMetrics::new);
+            partition.messages.add(now, *messages);
+            partition.bytes.add(now, *bytes);
+        }
+    }
+
+    /// Records the (records, milliseconds) that each partition read by a source is behind
+    pub async fn update_source_lag(&self, values: &HashMap<PartitionKey, (u64, u64)>) {
+        let now = SystemTime::now();
+
+        let mut source_lag = self.source_lag.write().await;
+        for (key, (records, lag_ms)) in values {
+            let lag = source_lag.entry(*key).or_insert_with(SourceLagMetrics::new);
+            lag.records.push((now, *records as f64));
+            lag.time_ms.push((now, *lag_ms as f64));
         }
     }
 
@@ -84,6 +148,7 @@ impl JobMetrics {
         for (metric, value) in values {
             if let Some(rate) = task.rates.get_mut(&metric) {
                 rate.add(now, *value);
+                task.totals.insert(*metric, *value);
             }
         }
 
@@ -99,6 +164,150 @@ impl JobMetrics {
         // never reads any data
         let backpressure = 1.0 - (queue_remaining + 1.0) / (queue_size + 1.0);
         task.update_backpressure(now, backpressure);
+
+        if let Some(lag) = values.get(&MetricName::WatermarkLagMs) {
+            task.watermark_lag.push((now, *lag as f64));
+        }
+
+        if let Some(watermark) = values.get(&MetricName::WatermarkMs) {
+            task.watermark =
+                (*watermark > 0).then(|| UNIX_EPOCH + Duration::from_millis(*watermark));
+        }
+    }
+
+    /// Records the total seconds that each (operator, subtask) has spent processing batches
+    pub async fn update_busy_time(&self, values: &HashMap<(u32, u32), f64>) {
+        let mut tasks = self.tasks.write().await;
+        for ((operator_id, subtask_idx), busy_secs) in values {
+            if let Some(task) = tasks.get_mut(&TaskKey {
+                operator_id: *operator_id,
+                subtask_idx: *subtask_idx,
+            }) {
+                task.busy_secs = *busy_secs;
+            }
+        }
+    }
+
+    /// Returns the totals most recently reported for each operator, summed across its subtasks
+    pub async fn operator_stats(&self) -> Vec<OperatorRuntimeStats> {
+        let mut stats: HashMap<u32, OperatorRuntimeStats> = HashMap::new();
+
+        for (k, v) in self.tasks.read().await.iter() {
+            let op = stats.entry(k.operator_id).or_default();
+            let total = |metric| v.totals.get(&metric).copied().unwrap_or_default();
+
+            op.rows_received += total(MetricName::MessagesRecv);
+            op.rows_sent += total(MetricName::MessagesSent);
+            op.bytes_received += total(MetricName::BytesRecv);
+            op.bytes_sent += total(MetricName::BytesSent);
+            op.busy_secs += v.busy_secs;
+        }
+
+        stats
+            .into_iter()
+            .filter_map(|(op_id, mut stats)| {
+                stats.operator_id = self
+                    .program
+                    .graph
+                    .node_weight(NodeIndex::new(op_id as usize))?
+                    .operator_id
+                    .clone();
+                Some(stats)
+            })
+            .collect()
+    }
+
+    /// Returns the watermark of each operator, which is the minimum of the watermarks most
+    /// recently reported by its subtasks. Subtasks that are idle or have not yet emitted a
+    /// watermark are ignored.
+    pub async fn operator_watermarks(&self) -> HashMap<String, SystemTime> {
+        let mut watermarks: HashMap<u32, SystemTime> = HashMap::new();
+        for (k, v) in self.tasks.read().await.iter() {
+            if let Some(watermark) = v.watermark {
+                watermarks
+                    .entry(k.operator_id)
+                    .and_modify(|w| *w = (*w).min(watermark))
+                    .or_insert(watermark);
+            }
+        }
+
+        watermarks
+            .into_iter()
+            .filter_map(|(op_id, watermark)| {
+                Some((
+                    self.program
+                        .graph
+                        .node_weight(NodeIndex::new(op_id as usize))?
+                        .operator_id
+                        .clone(),
+                    watermark,
+                ))
+            })
+            .collect()
+    }
+
+    /// Returns the largest watermark lag across the subtasks of the job's sinks, as most
+    /// recently reported by the workers. Subtasks that are idle or have not yet emitted a
+    /// watermark are ignored.
+    pub async fn sink_watermark_lag(&self) -> Option<Duration> {
+        let sinks: Vec<_> = self
+            .program
+            .graph
+            .externals(Direction::Outgoing)
+            .map(|n| n.index() as u32)
+            .collect();
+
+        self.tasks
+            .read()
+            .await
+            .iter()
+            .filter(|(k, _)| sinks.contains(&k.operator_id))
+            .filter_map(|(_, v)| v.watermark_lag.last())
+            .map(|(_, lag)| lag as u64)
+            .filter(|lag| *lag > 0)
+            .max()
+            .map(Duration::from_millis)
+    }
+
+    /// Returns the current value of each operator's metrics, aggregated across its subtasks:
+    /// rates are summed, while backpressure and watermark lag take the worst subtask
+    pub async fn operator_samples(&self) -> HashMap<String, HashMap<MetricName, f64>> {
+        let mut samples: HashMap<u32, HashMap<MetricName, f64>> = HashMap::new();
+
+        for (k, v) in self.tasks.read().await.iter() {
+            let op = samples.entry(k.operator_id).or_default();
+
+            for (metric, rate) in &v.rates {
+                if let Some((_, value)) = rate.last() {
+                    *op.entry(*metric).or_default() += value;
+                }
+            }
+
+            for (metric, value) in [
+                (MetricName::Backpressure, v.backpressure.last()),
+                (MetricName::WatermarkLagMs, v.watermark_lag.last()),
+            ] {
+                if let Some((_, value)) = value {
+                    let current = op.entry(metric).or_insert(value);
+                    *current = current.max(value);
+                }
+            }
+        }
+
+        samples
+            .into_iter()
+            .filter(|(_, metrics)| !metrics.is_empty())
+            .filter_map(|(op_id, metrics)| {
+                Some((
+                    self.program
+                        .graph
+                        .node_weight(NodeIndex::new(op_id as usize))?
+                        .operator_id
+                        .clone(),
+                    metrics,
+                ))
+            })
+            .collect()
     }
 
     pub async fn get_groups(&self) -> Vec<OperatorMetricGroup> {
@@ -134,6 +343,65 @@ impl JobMetrics {
                         })
                         .collect(),
                 });
+
+            op.entry(MetricName::WatermarkLagMs)
+                .or_default()
+                .push(SubtaskMetrics {
+                    index: k.subtask_idx,
+                    metrics: v
+                        .watermark_lag
+                        .iter()
+                        .map(|(t, v)| Metric {
+                            time: to_micros(t),
+                            value: v,
+                        })
+                        .collect(),
+                });
+        }
+
+        let mut partition_rates: HashMap<u32, Vec<f64>> = HashMap::new();
+        for (k, v) in self.partitions.read().await.iter() {
+            let op = metric_groups.entry(k.operator_id).or_default();
+
+            for (metric, rate) in [
+                (MetricName::PartitionMessagesSent, &v.messages),
+                (MetricName::PartitionBytesSent, &v.bytes),
+            ] {
+                op.entry(metric).or_default().push(SubtaskMetrics {
+                    index: k.partition,
+                    metrics: rate
+                        .iter()
+                        .map(|(t, v)| Metric {
+                            time: to_micros(t),
+                            value: v,
+                        })
+                        .collect(),
+                });
+            }
+
+            if let Some((_, rate)) = v.messages.last() {
+                partition_rates.entry(k.operator_id).or_default().push(rate);
+            }
+        }
+
+        for (k, v) in self.source_lag.read().await.iter() {
+            let op = metric_groups.entry(k.operator_id).or_default();
+
+            for (metric, values) in [
+                (MetricName::SourcePartitionLag, &v.records),
+                (MetricName::SourcePartitionLagMs, &v.time_ms),
+            ] {
+                op.entry(metric).or_default().push(SubtaskMetrics {
+                    index: k.partition,
+                    metrics: values
+                        .iter()
+                        .map(|(t, v)| Metric {
+                            time: to_micros(t),
+                            value: v,
+                        })
+                        .collect(),
+                });
+            }
         }
 
         metric_groups
@@ -148,6 +416,7 @@ impl JobMetrics {
                     .clone();
                 OperatorMetricGroup {
                     operator_id,
+                    skew: partition_rates.get(&op_id).and_then(|rates| skew(rates)),
                     metric_groups: metrics
                         .into_iter()
                         .map(|(name, subtasks)| MetricGroup {
@@ -166,7 +435,12 @@ impl JobMetrics {
 
 pub struct TaskMetrics {
     rates: HashMap<MetricName, RateMetric>,
+    // the latest value of each of the rate metrics' counters
+    totals: HashMap<MetricName, u64>,
+    busy_secs: f64,
     backpressure: CircularBuffer<(SystemTime, f64), NUM_BUCKETS>,
+    watermark_lag: CircularBuffer<(SystemTime, f64), NUM_BUCKETS>,
+    watermark: Option<SystemTime>,
 }
 
 impl TaskMetrics {
@@ -176,7 +450,11 @@ impl TaskMetrics {
                 .iter()
                 .map(|&m| (m, RateMetric::new()))
                 .collect(),
+            totals: HashMap::new(),
+            busy_secs: 0.0,
             backpressure: CircularBuffer::new((UNIX_EPOCH, 0.0)),
+            watermark_lag: CircularBuffer::new((UNIX_EPOCH, 0.0)),
+            watermark: None,
         }
     }
 
@@ -185,6 +463,34 @@ impl TaskMetrics {
     }
 }
 
+pub struct PartitionMetrics {
+    messages: RateMetric,
+    bytes: RateMetric,
+}
+
+impl PartitionMetrics {
+    pub fn new() -> Self {
+        Self {
+            messages: RateMetric::new(),
+            bytes: RateMetric::new(),
+        }
+    }
+}
+
+pub struct SourceLagMetrics {
+    records: CircularBuffer<(SystemTime, f64), NUM_BUCKETS>,
+    time_ms: CircularBuffer<(SystemTime, f64), NUM_BUCKETS>,
+}
+
+impl SourceLagMetrics {
+    pub fn new() -> Self {
+        Self {
+            records: CircularBuffer::new((UNIX_EPOCH, 0.0)),
+            time_ms: CircularBuffer::new((UNIX_EPOCH, 0.0)),
+        }
+    }
+}
+
 /// Calculates an exponentially-weighted moving average over metrics collected from the job
 pub struct RateMetric {
     values: CircularBuffer<(SystemTime, f64), NUM_BUCKETS>,
@@ -231,6 +537,10 @@ impl RateMetric {
         self.values.is_empty()
     }
 
+    pub fn last(&self) -> Option<(SystemTime, f64)> {
+        self.values.last()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (SystemTime, f64)> + '_ {
         self.values.iter()
     }
@@ -285,7 +595,7 @@ impl<T: Copy, const N: usize> CircularBuffer<T, N> {
 
 #[cfg(test)]
 mod tests {
-    use crate::job_controller::job_metrics::{RateMetric, COLLECTION_RATE, NUM_BUCKETS};
+    use crate::job_controller::job_metrics::{skew, RateMetric, COLLECTION_RATE, NUM_BUCKETS};
     use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
     #[test]
@@ -326,4 +636,13 @@ mod tests {
             last_time = time;
         }
     }
+
+    #[test]
+    fn test_skew() {
+        assert_eq!(skew(&[]), None);
+        assert_eq!(skew(&[10.0]), None);
+        assert_eq!(skew(&[0.0, 0.0]), None);
+        assert_eq!(skew(&[5.0, 5.0, 5.0]), Some(1.0));
+        assert_eq!(skew(&[0.0, 0.0, 0.0, 8.0]), Some(4.0));
+    }
 }
@@ -0,0 +1,162 @@
+//! Rolls up the operator metrics that the controller samples into the job's metric history.
+//!
+//! Raw samples are written as they are taken. Each sample is also added to the open per-minute
+//! and per-hour buckets for its operator and metric; once a sample arrives past the end of a
+//! bucket, the bucket's average is written with the bucket's start time and a new bucket is
+//! opened. Buckets that are still open when the job stops are dropped.
+
+use arroyo_rpc::api_types::metrics::MetricName;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const MINUTE_RESOLUTION_SECS: u64 = 60;
+pub const HOUR_RESOLUTION_SECS: u64 = 60 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricRow {
+    pub operator_id: String,
+    pub metric: MetricName,
+    pub resolution_secs: u64,
+    pub time: SystemTime,
+    pub value: f64,
+}
+
+struct Bucket {
+    start: SystemTime,
+    sum: f64,
+    count: u32,
+}
+
+fn bucket_start(time: SystemTime, resolution_secs: u64) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    UNIX_EPOCH + Duration::from_secs(secs - secs % resolution_secs)
+}
+
+#[derive(Default)]
+pub struct MetricRollup {
+    buckets: HashMap<(String, MetricName, u64), Bucket>,
+}
+
+impl MetricRollup {
+    /// Records a set of samples taken at `now`, returning the rows to write to the metric
+    /// history: the raw samples at `raw_resolution_secs`, along with the averages of any buckets
+    /// that have closed
+    pub fn record(
+        &mut self,
+        now: SystemTime,
+        raw_resolution_secs: u64,
+        samples: HashMap<String, HashMap<MetricName, f64>>,
+    ) -> Vec<MetricRow> {
+        let mut rows = vec![];
+
+        for (operator_id, metrics) in samples {
+            for (metric, value) in metrics {
+                for resolution_secs in [MINUTE_RESOLUTION_SECS, HOUR_RESOLUTION_SECS] {
+                    let start = bucket_start(now, resolution_secs);
+                    let bucket = self
+                        .buckets
+                        .entry((operator_id.clone(), metric, resolution_secs))
+                        .or_insert(Bucket {
+                            start,
+                            sum: 0.0,
+                            count: 0,
+                        });
+
+                    if bucket.start != start {
+                        if bucket.count > 0 {
+                            rows.push(MetricRow {
+                                operator_id: operator_id.clone(),
+                                metric,
+                                resolution_secs,
+                                time: bucket.start,
+                                value: bucket.sum / bucket.count as f64,
+                            });
+                        }
+
+                        *bucket = Bucket {
+                            start,
+                            sum: 0.0,
+                            count: 0,
+                        };
+                    }
+
+                    bucket.sum += value;
+                    bucket.count += 1;
+                }
+
+                rows.push(MetricRow {
+                    operator_id: operator_id.clone(),
+                    metric,
+                    resolution_secs: raw_resolution_secs,
+                    time: now,
+                    value,
+                });
+            }
+        }
+
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(value: f64) -> HashMap<String, HashMap<MetricName, f64>> {
+        [(
+            "op".to_string(),
+            [(MetricName::MessagesRecv, value)].into_iter().collect(),
+        )]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_rollup() {
+        let mut rollup = MetricRollup::default();
+        let start = UNIX_EPOCH + Duration::from_secs(HOUR_RESOLUTION_SECS * 1000);
+
+        for i in 0..12 {
+            let rows = rollup.record(start + Duration::from_secs(i * 5), 5, samples(i as f64));
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0].resolution_secs, 5);
+        }
+
+        let mut rows = rollup.record(start + Duration::from_secs(60), 5, samples(100.0));
+        rows.sort_by_key(|r| r.resolution_secs);
+        assert_eq!(
+            rows,
+            vec![
+                MetricRow {
+                    operator_id: "op".to_string(),
+                    metric: MetricName::MessagesRecv,
+                    resolution_secs: 5,
+                    time: start + Duration::from_secs(60),
+                    value: 100.0,
+                },
+                MetricRow {
+                    operator_id: "op".to_string(),
+                    metric: MetricName::MessagesRecv,
+                    resolution_secs: MINUTE_RESOLUTION_SECS,
+                    time: start,
+                    value: 5.5,
+                },
+            ]
+        );
+
+        let rows = rollup.record(
+            start + Duration::from_secs(HOUR_RESOLUTION_SECS),
+            5,
+            samples(0.0),
+        );
+        let hour = rows
+            .iter()
+            .find(|r| r.resolution_secs == HOUR_RESOLUTION_SECS)
+            .unwrap();
+        assert_eq!(hour.time, start);
+        assert_eq!(hour.value, (66.0 + 100.0) / 13.0);
+    }
+}
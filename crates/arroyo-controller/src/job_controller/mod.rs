@@ -1,45 +1,59 @@
 use std::str::FromStr;
 use std::sync::Arc;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     time::{Duration, Instant, SystemTime},
 };
 
 use crate::types::public::StopMode as SqlStopMode;
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 use arroyo_rpc::grpc::{
-    worker_grpc_client::WorkerGrpcClient, CheckpointReq, CommitReq, JobFinishedReq, LabelPair,
-    LoadCompactedDataReq, MetricsReq, StopExecutionReq, StopMode, TaskCheckpointEventType,
+    worker_grpc_client::WorkerGrpcClient, AlignWatermarksReq, CheckpointReq, CommitReq,
+    JobFinishedReq, LabelPair, LoadCompactedDataReq, MetricsReq, ReloadUdfsReq, RestartRegionReq,
+    StopExecutionReq, StopMode, TaskAssignment, TaskCheckpointEventType,
 };
 use arroyo_state::{BackingStore, StateBackend};
-use arroyo_types::{to_micros, WorkerId};
+use arroyo_types::{
+    to_micros, WorkerId, PARTITION_BYTES_SENT, PARTITION_MESSAGES_SENT, PROCESS_BATCH_TIME,
+    ROUTING_KEY_BUCKETS, ROUTING_KEY_DISTRIBUTION, SOURCE_PARTITION_LAG, SOURCE_PARTITION_LAG_MS,
+};
 use cornucopia_async::DatabaseSource;
 
+use prost::Message;
 use time::OffsetDateTime;
 
-use arroyo_datastream::logical::LogicalProgram;
+use arroyo_datastream::logical::{LogicalProgram, ProgramConfig};
 use arroyo_rpc::api_types::metrics::MetricName;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::config::config;
+use arroyo_rpc::grpc::api::ArrowProgramConfig;
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_state::checkpoint_state::CheckpointState;
 use arroyo_state::parquet::ParquetBackend;
 use tokio::{sync::mpsc::Receiver, task::JoinHandle};
 use tonic::{transport::Channel, Request};
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
-use crate::job_controller::job_metrics::{get_metric_name, JobMetrics};
+use crate::job_controller::alignment::WatermarkAligner;
+use crate::job_controller::freshness::FreshnessTracker;
+use crate::job_controller::job_metrics::{get_metric_name, JobMetrics, PartitionKey};
+use crate::job_controller::metric_history::MetricRollup;
+use crate::job_controller::retention::{retained_epochs, CompletedCheckpoint};
 use crate::types::public::CheckpointState as DbCheckpointState;
 use crate::{queries::controller_queries, JobConfig, JobMessage, RunningMessage};
 use arroyo_state::committing_state::CommittingState;
 
 use self::checkpointer::CheckpointingOrCommittingState;
 
+mod alignment;
 mod checkpointer;
+mod freshness;
 pub mod job_metrics;
+pub mod metric_history;
+mod retention;
 
-const CHECKPOINTS_TO_KEEP: u32 = 4;
 const CHECKPOINT_ROWS_TO_KEEP: u32 = 100;
-const COMPACT_EVERY: u32 = 2;
+const WATERMARK_HISTORY_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum WorkerState {
@@ -65,12 +79,13 @@ impl WorkerStatus {
 pub enum TaskState {
     Running,
     Finished,
-    Failed(String),
+    Failed(String, ErrorCategory),
 }
 
 #[derive(Debug)]
 pub struct TaskStatus {
     state: TaskState,
+    worker_id: WorkerId,
 }
 
 // Stores a model of the current state of a running job to use in the state machine
@@ -87,13 +102,25 @@ pub struct RunningJobModel {
     checkpoint_state: Option<CheckpointingOrCommittingState>,
     epoch: u32,
     min_epoch: u32,
+    // the latest epoch that replication to the checkpoint replica was started for; if it fails,
+    // the next checkpoint is replicated instead
+    replicated_epoch: u32,
     last_checkpoint: Instant,
+    last_cleanup: Instant,
+    // total size of the state written by the most recent successful checkpoint
+    last_checkpoint_bytes: u64,
+    // the number of checkpoints that have completed since the job was scheduled
+    completed_checkpoints: u32,
     workers: HashMap<WorkerId, WorkerStatus>,
     tasks: HashMap<(String, u32), TaskStatus>,
     operator_parallelism: HashMap<String, usize>,
+    // the failover regions of the program, along with when each was last restarted on its own
+    regions: Vec<(HashSet<String>, Option<Instant>)>,
     metrics: JobMetrics,
     metric_update_task: Option<JoinHandle<()>>,
     last_updated_metrics: Instant,
+    last_recorded_watermarks: Instant,
+    last_recorded_metrics: Instant,
 }
 
 impl std::fmt::Debug for RunningJobModel {
@@ -104,11 +131,46 @@ impl std::fmt::Debug for RunningJobModel {
             .field("checkpointing", &self.checkpoint_state.is_some())
             .field("epoch", &self.epoch)
             .field("min_epoch", &self.min_epoch)
+            .field("replicated_epoch", &self.replicated_epoch)
             .field("last_checkpoint", &self.last_checkpoint)
             .finish()
     }
 }
 
+/// Finds the region that contains all of the failed tasks, if it runs entirely on one worker and
+/// hasn't been restarted within `healthy_duration`
+fn find_failed_region(
+    regions: &[(HashSet<String>, Option<Instant>)],
+    tasks: &HashMap<(String, u32), TaskStatus>,
+    healthy_duration: Duration,
+) -> Option<(usize, WorkerId)> {
+    let failed: HashSet<&str> = tasks
+        .iter()
+        .filter(|(_, t)| matches!(t.state, TaskState::Failed(..)))
+        .map(|((operator_id, _), _)| operator_id.as_str())
+        .collect();
+
+    let (idx, (region, last_restart)) = regions
+        .iter()
+        .enumerate()
+        .find(|(_, (region, _))| failed.iter().any(|op| region.contains(*op)))?;
+
+    if !failed.iter().all(|op| region.contains(*op)) {
+        return None;
+    }
+
+    if last_restart.is_some_and(|t| t.elapsed() < healthy_duration) {
+        return None;
+    }
+
+    let mut workers = tasks
+        .iter()
+        .filter(|((operator_id, _), _)| region.contains(operator_id))
+        .map(|(_, t)| t.worker_id);
+    let worker_id = workers.next()?;
+    workers.all(|w| w == worker_id).then_some((idx, worker_id))
+}
+
 impl RunningJobModel {
     pub async fn update_db(
         checkpoint_state: &CheckpointState,
@@ -251,11 +313,12 @@ impl RunningJobModel {
                 operator_id,
                 subtask_index,
                 reason,
+                category,
                 ..
             } => {
                 let key = (operator_id, subtask_index);
                 if let Some(status) = self.tasks.get_mut(&key) {
-                    status.state = TaskState::Failed(reason);
+                    status.state = TaskState::Failed(reason, category);
                 } else {
                     warn!(
                         message = "Received task failed message for unknown task",
@@ -315,6 +378,7 @@ impl RunningJobModel {
         organization_id: &str,
         db: &DatabaseSource,
         then_stop: bool,
+        savepoint: bool,
     ) -> anyhow::Result<()> {
         self.epoch += 1;
 
@@ -322,7 +386,8 @@ impl RunningJobModel {
             message = "Starting checkpointing",
             job_id = *self.job_id,
             epoch = self.epoch,
-            then_stop
+            then_stop,
+            savepoint
         );
 
         // TODO: maybe parallelize
@@ -351,6 +416,8 @@ impl RunningJobModel {
             &(self.epoch as i32),
             &(self.min_epoch as i32),
             &OffsetDateTime::now_utc(),
+            // the checkpoint a job stops with is a savepoint, which may be protected from cleanup
+            &(then_stop || savepoint),
         )
         .await?;
 
@@ -410,6 +477,13 @@ impl RunningJobModel {
                 CheckpointingOrCommittingState::Checkpointing(checkpointing) => {
                     checkpointing.save_state().await?;
 
+                    self.last_checkpoint_bytes = checkpointing
+                        .operator_details
+                        .values()
+                        .flat_map(|op| op.tasks.values())
+                        .filter_map(|t| t.bytes)
+                        .sum();
+
                     let committing_state = checkpointing.committing_state();
                     let duration = checkpointing
                         .start_time()
@@ -422,6 +496,7 @@ impl RunningJobModel {
                         Self::update_checkpoint_in_db(&checkpointing, db, DbCheckpointState::ready)
                             .await?;
                         self.last_checkpoint = Instant::now();
+                        self.completed_checkpoints += 1;
                         self.checkpoint_state = None;
                         self.compact_state().await?;
 
@@ -460,6 +535,7 @@ impl RunningJobModel {
                 CheckpointingOrCommittingState::Committing(committing) => {
                     Self::finish_committing(committing.checkpoint_id(), db).await?;
                     self.last_checkpoint = Instant::now();
+                    self.completed_checkpoints += 1;
                     self.checkpoint_state = None;
                     info!(
                         message = "Finished committing checkpointing",
@@ -472,12 +548,9 @@ impl RunningJobModel {
         Ok(())
     }
 
-    pub fn cleanup_needed(&self) -> Option<u32> {
-        if self.epoch - self.min_epoch > CHECKPOINTS_TO_KEEP && self.epoch % COMPACT_EVERY == 0 {
-            Some(self.epoch - CHECKPOINTS_TO_KEEP)
-        } else {
-            None
-        }
+    pub fn cleanup_needed(&self) -> bool {
+        self.epoch > 0
+            && self.last_cleanup.elapsed() > *config().pipeline.checkpoint_retention.gc_interval
     }
 
     pub fn failed(&self) -> bool {
@@ -493,13 +566,14 @@ impl RunningJobModel {
         }
 
         for ((operator_id, subtask), status) in &self.tasks {
-            if let TaskState::Failed(reason) = &status.state {
+            if let TaskState::Failed(reason, category) = &status.state {
                 error!(
                     message = "task failed",
                     job_id = *self.job_id,
                     operator_id,
                     subtask,
                     reason,
+                    category = category.to_string(),
                 );
                 return true;
             }
@@ -508,6 +582,58 @@ impl RunningJobModel {
         false
     }
 
+    /// If regional failover is possible for the current failures, returns the index of the
+    /// region to restart and the worker it's running on. This requires that all of the failed
+    /// tasks are in one region that runs entirely on one worker, that no checkpoint is in
+    /// progress, and that the region hasn't already been restarted within the healthy duration.
+    fn failed_region(&self) -> Option<(usize, WorkerId)> {
+        if self.checkpoint_state.is_some() || self.workers.values().any(|w| w.heartbeat_timeout()) {
+            return None;
+        }
+
+        find_failed_region(
+            &self.regions,
+            &self.tasks,
+            *config().pipeline.healthy_duration,
+        )
+    }
+
+    /// Restarts the tasks of a single region from the last completed checkpoint, leaving the rest
+    /// of the pipeline running
+    async fn restart_region(&mut self, idx: usize, worker_id: WorkerId) -> anyhow::Result<()> {
+        let (region, last_restart) = &mut self.regions[idx];
+
+        // no checkpoint is in progress, so the current epoch is the last completed one
+        let restore_epoch = (self.epoch > 0).then_some(self.epoch);
+
+        info!(
+            message = "restarting failed region",
+            job_id = *self.job_id,
+            operators = format!("{:?}", region),
+            worker_id = worker_id.0,
+            restore_epoch,
+        );
+
+        self.workers
+            .get_mut(&worker_id)
+            .ok_or_else(|| anyhow::anyhow!("no worker {:?} for region", worker_id))?
+            .connect
+            .restart_region(Request::new(RestartRegionReq {
+                operator_ids: region.iter().cloned().collect(),
+                restore_epoch,
+            }))
+            .await?;
+
+        for ((operator_id, _), task) in self.tasks.iter_mut() {
+            if region.contains(operator_id) {
+                task.state = TaskState::Running;
+            }
+        }
+        *last_restart = Some(Instant::now());
+
+        Ok(())
+    }
+
     pub fn any_finished_sources(&self) -> bool {
         let source_tasks = self.program.sources();
 
@@ -523,11 +649,26 @@ impl RunningJobModel {
     }
 }
 
+/// Progress of a hot-reload of the job's UDFs, which is applied at a checkpoint boundary
+enum UdfReload {
+    // waiting to start a checkpoint
+    Requested(ProgramConfig),
+    // waiting for the in-progress checkpoint to complete
+    Checkpointing(ProgramConfig),
+}
+
 pub struct JobController {
     db: DatabaseSource,
     config: JobConfig,
     model: RunningJobModel,
     cleanup_task: Option<JoinHandle<anyhow::Result<u32>>>,
+    replication_task: Option<JoinHandle<anyhow::Result<u32>>>,
+    udf_reload: Option<UdfReload>,
+    freshness: FreshnessTracker,
+    aligner: WatermarkAligner,
+    metric_rollup: MetricRollup,
+    // whether a savepoint has been requested and not yet started
+    savepoint_requested: bool,
 }
 
 impl std::fmt::Debug for JobController {
@@ -536,6 +677,7 @@ impl std::fmt::Debug for JobController {
             .field("config", &self.config)
             .field("model", &self.model)
             .field("cleaning", &self.cleanup_task.is_some())
+            .field("replicating", &self.replication_task.is_some())
             .finish()
     }
 }
@@ -543,6 +685,7 @@ impl std::fmt::Debug for JobController {
 pub enum ControllerProgress {
     Continue,
     Finishing,
+    StateQuotaExceeded { bytes: u64, limit: u64 },
 }
 
 impl JobController {
@@ -553,9 +696,11 @@ impl JobController {
         epoch: u32,
         min_epoch: u32,
         worker_connects: HashMap<WorkerId, WorkerGrpcClient<Channel>>,
+        assignments: &[TaskAssignment],
         commit_state: Option<CommittingState>,
         metrics: JobMetrics,
     ) -> Self {
+        let aligner = WatermarkAligner::new(&program);
         Self {
             db,
             model: RunningJobModel {
@@ -564,7 +709,11 @@ impl JobController {
                 checkpoint_state: commit_state.map(CheckpointingOrCommittingState::Committing),
                 epoch,
                 min_epoch,
+                replicated_epoch: 0,
                 last_checkpoint: Instant::now(),
+                last_cleanup: Instant::now(),
+                last_checkpoint_bytes: 0,
+                completed_checkpoints: 0,
                 workers: worker_connects
                     .into_iter()
                     .map(|(id, connect)| {
@@ -579,28 +728,35 @@ impl JobController {
                         )
                     })
                     .collect(),
-                tasks: program
-                    .graph
-                    .node_weights()
-                    .flat_map(|node| {
-                        (0..node.parallelism).map(|idx| {
-                            (
-                                (node.operator_id.clone(), idx as u32),
-                                TaskStatus {
-                                    state: TaskState::Running,
-                                },
-                            )
-                        })
+                tasks: assignments
+                    .iter()
+                    .map(|a| {
+                        (
+                            (a.operator_id.clone(), a.operator_subtask as u32),
+                            TaskStatus {
+                                state: TaskState::Running,
+                                worker_id: WorkerId(a.worker_id),
+                            },
+                        )
                     })
                     .collect(),
                 operator_parallelism: program.tasks_per_operator(),
+                regions: program.regions().into_iter().map(|r| (r, None)).collect(),
                 metrics,
                 metric_update_task: None,
                 last_updated_metrics: Instant::now(),
+                last_recorded_watermarks: Instant::now(),
+                last_recorded_metrics: Instant::now(),
                 program,
             },
+            freshness: FreshnessTracker::new(config.id.clone()),
+            aligner,
+            metric_rollup: MetricRollup::default(),
             config,
             cleanup_task: None,
+            replication_task: None,
+            udf_reload: None,
+            savepoint_requested: false,
         }
     }
 
@@ -608,6 +764,85 @@ impl JobController {
         self.config = config;
     }
 
+    /// Takes a checkpoint that's marked as a savepoint as soon as no other checkpoint is in
+    /// progress, without stopping the job
+    pub fn request_savepoint(&mut self) {
+        info!(message = "savepoint requested", job_id = *self.config.id);
+        self.savepoint_requested = true;
+    }
+
+    /// Swaps the given UDFs into the running workers once the next checkpoint completes. This
+    /// replaces any reload that has not yet been applied. If the reload fails, the job is
+    /// restarted, which starts it with the new UDFs.
+    pub fn reload_udfs(&mut self, program_config: ProgramConfig) {
+        info!(
+            message = "scheduling UDF reload for next checkpoint",
+            job_id = *self.config.id
+        );
+        self.udf_reload = Some(UdfReload::Requested(program_config));
+    }
+
+    async fn progress_udf_reload(&mut self) -> anyhow::Result<()> {
+        self.udf_reload = match self.udf_reload.take() {
+            Some(UdfReload::Requested(program_config)) => {
+                if self.model.checkpoint_state.is_none() && self.cleanup_task.is_none() {
+                    self.checkpoint(false).await?;
+                }
+
+                // if a checkpoint is already in progress, we can reload once it completes
+                if self.model.checkpoint_state.is_some() {
+                    Some(UdfReload::Checkpointing(program_config))
+                } else {
+                    Some(UdfReload::Requested(program_config))
+                }
+            }
+            Some(UdfReload::Checkpointing(program_config))
+                if self.model.checkpoint_state.is_none() =>
+            {
+                // every worker loads and checks the new UDFs before any swaps them in, so that
+                // a UDF that fails to load leaves all of them running the previous ones
+                let prepare = ReloadUdfsReq {
+                    program_config: ArrowProgramConfig::from(program_config).encode_to_vec(),
+                    commit: false,
+                };
+                for worker in self.model.workers.values_mut() {
+                    worker
+                        .connect
+                        .reload_udfs(prepare.clone())
+                        .await
+                        .map_err(|e| anyhow!("failed to load UDFs on {:?}: {}", worker.id, e))?;
+                }
+
+                let commit = ReloadUdfsReq {
+                    program_config: vec![],
+                    commit: true,
+                };
+                for (i, worker) in self.model.workers.values_mut().enumerate() {
+                    if let Err(e) = worker.connect.reload_udfs(commit.clone()).await {
+                        // the job is restarted on this error, which brings every worker onto the
+                        // new UDFs
+                        bail!(
+                            "failed to swap in UDFs on {:?} after {} other workers had: {}",
+                            worker.id,
+                            i,
+                            e
+                        );
+                    }
+                }
+
+                info!(
+                    message = "reloaded UDFs",
+                    job_id = *self.config.id,
+                    epoch = self.model.epoch
+                );
+                None
+            }
+            other => other,
+        };
+
+        Ok(())
+    }
+
     pub async fn handle_message(&mut self, msg: RunningMessage) -> anyhow::Result<()> {
         self.model.handle_message(msg, &self.db).await
     }
@@ -636,6 +871,10 @@ impl JobController {
 
         self.model.metric_update_task = Some(tokio::spawn(async move {
             let mut metrics: HashMap<(u32, u32), HashMap<MetricName, u64>> = HashMap::new();
+            let mut partitions: HashMap<PartitionKey, (u64, u64)> = HashMap::new();
+            let mut routing_keys: HashMap<u32, Vec<u64>> = HashMap::new();
+            let mut source_lag: HashMap<PartitionKey, (u64, u64)> = HashMap::new();
+            let mut busy_time: HashMap<(u32, u32), f64> = HashMap::new();
 
             for (id, mut connect) in workers {
                 let Ok(e) = connect.get_metrics(MetricsReq {}).await else {
@@ -654,8 +893,134 @@ impl JobController {
                     )
                 }
 
-                e.into_inner()
+                let (routing_key_families, families): (Vec<_>, Vec<_>) = e
+                    .into_inner()
                     .metrics
+                    .into_iter()
+                    .partition(|f| f.name.as_deref() == Some(ROUTING_KEY_DISTRIBUTION));
+
+                let (partition_families, families): (Vec<_>, Vec<_>) =
+                    families.into_iter().partition(|f| {
+                        f.name.as_deref() == Some(PARTITION_MESSAGES_SENT)
+                            || f.name.as_deref() == Some(PARTITION_BYTES_SENT)
+                    });
+
+                let (source_lag_families, families): (Vec<_>, Vec<_>) =
+                    families.into_iter().partition(|f| {
+                        f.name.as_deref() == Some(SOURCE_PARTITION_LAG)
+                            || f.name.as_deref() == Some(SOURCE_PARTITION_LAG_MS)
+                    });
+
+                let (busy_families, families): (Vec<_>, Vec<_>) = families
+                    .into_iter()
+                    .partition(|f| f.name.as_deref() == Some(PROCESS_BATCH_TIME));
+
+                for m in busy_families.into_iter().flat_map(|f| f.metric) {
+                    let Some(operator_idx) = find_label(&m.label, "operator_id")
+                        .and_then(|id| program.operator_index(id))
+                    else {
+                        continue;
+                    };
+                    let Some(subtask_idx) =
+                        find_label(&m.label, "subtask_idx").and_then(|s| u32::from_str(s).ok())
+                    else {
+                        continue;
+                    };
+                    let Some(busy_secs) = m.histogram.and_then(|h| h.sample_sum) else {
+                        continue;
+                    };
+
+                    busy_time.insert((operator_idx, subtask_idx), busy_secs);
+                }
+
+                // source lag is reported for each partition of the external system, each of
+                // which is read by exactly one subtask
+                for family in source_lag_families {
+                    let is_time = family.name.as_deref() == Some(SOURCE_PARTITION_LAG_MS);
+                    for m in family.metric {
+                        let Some(operator_idx) = find_label(&m.label, "operator_id")
+                            .and_then(|id| program.operator_index(id))
+                        else {
+                            continue;
+                        };
+                        let Some(partition) =
+                            find_label(&m.label, "partition").and_then(|p| u32::from_str(p).ok())
+                        else {
+                            continue;
+                        };
+                        let Some(value) = m.gauge.and_then(|g| g.value) else {
+                            continue;
+                        };
+
+                        let (records, lag_ms) = source_lag
+                            .entry(PartitionKey {
+                                operator_id: operator_idx,
+                                partition,
+                            })
+                            .or_default();
+                        if is_time {
+                            *lag_ms = value as u64;
+                        } else {
+                            *records = value as u64;
+                        }
+                    }
+                }
+
+                // as are the key distributions of range-partitioned exchanges
+                for m in routing_key_families.into_iter().flat_map(|f| f.metric) {
+                    let Some(operator_idx) = find_label(&m.label, "operator_id")
+                        .and_then(|id| program.operator_index(id))
+                    else {
+                        continue;
+                    };
+                    let Some(bucket) = find_label(&m.label, "bucket")
+                        .and_then(|b| usize::from_str(b).ok())
+                        .filter(|b| *b < ROUTING_KEY_BUCKETS)
+                    else {
+                        continue;
+                    };
+                    let Some(value) = m.counter.and_then(|c| c.value) else {
+                        continue;
+                    };
+
+                    routing_keys
+                        .entry(operator_idx)
+                        .or_insert_with(|| vec![0; ROUTING_KEY_BUCKETS])[bucket] += value as u64;
+                }
+
+                // partition metrics are summed across the subtasks of the sending operator
+                for family in partition_families {
+                    let is_bytes = family.name.as_deref() == Some(PARTITION_BYTES_SENT);
+                    for m in family.metric {
+                        let Some(operator_idx) = find_label(&m.label, "operator_id")
+                            .and_then(|id| program.operator_index(id))
+                        else {
+                            continue;
+                        };
+                        let Some(partition) = find_label(&m.label, "next_node_idx")
+                            .and_then(|idx| u32::from_str(idx).ok())
+                        else {
+                            continue;
+                        };
+                        let Some(value) = m.counter.and_then(|c| c.value) else {
+                            continue;
+                        };
+
+                        let (messages, bytes) = partitions
+                            .entry(PartitionKey {
+                                operator_id: operator_idx,
+                                partition,
+                            })
+                            .or_default();
+                        if is_bytes {
+                            *bytes += value as u64;
+                        } else {
+                            *messages += value as u64;
+                        }
+                    }
+                }
+
+                families
                     .into_iter()
                     .filter_map(|f| Some((get_metric_name(&f.name?)?, f.metric)))
                     .flat_map(|(metric, values)| {
@@ -684,10 +1049,23 @@ impl JobController {
             for ((operator_idx, subtask_idx), values) in metrics {
                 job_metrics.update(operator_idx, subtask_idx, &values).await;
             }
+
+            job_metrics.update_busy_time(&busy_time).await;
+            job_metrics.update_partitions(&partitions).await;
+            job_metrics.update_source_lag(&source_lag).await;
+            job_metrics.update_routing_keys(routing_keys).await;
         }));
     }
 
     pub async fn progress(&mut self) -> anyhow::Result<ControllerProgress> {
+        // can we recover from failed tasks by restarting just their region?
+        if config().pipeline.regional_failover {
+            if let Some((region, worker_id)) = self.model.failed_region() {
+                self.model.restart_region(region, worker_id).await?;
+                return Ok(ControllerProgress::Continue);
+            }
+        }
+
         // have any of our workers failed?
         if self.model.failed() {
             bail!("worker failed");
@@ -734,15 +1112,63 @@ impl JobController {
             }
         }
 
-        if let Some(new_epoch) = self.model.cleanup_needed() {
-            if self.cleanup_task.is_none() && self.model.checkpoint_state.is_none() {
-                self.cleanup_task = Some(self.start_cleanup(new_epoch));
+        // check on replication
+        if self
+            .replication_task
+            .as_ref()
+            .is_some_and(|task| task.is_finished())
+        {
+            match self.replication_task.take().unwrap().await {
+                Ok(Ok(epoch)) => {
+                    debug!(
+                        message = "finished checkpoint replication",
+                        job_id = *self.config.id,
+                        epoch
+                    );
+                }
+                Ok(Err(e)) => {
+                    error!(
+                        message = "checkpoint replication failed",
+                        job_id = *self.config.id,
+                        error = format!("{:?}", e)
+                    );
+                }
+                Err(e) => {
+                    error!(
+                        message = "checkpoint replication panicked",
+                        job_id = *self.config.id,
+                        error = format!("{:?}", e)
+                    );
+                }
             }
         }
 
+        // cleanup may delete files of the checkpoint being replicated, so the two don't overlap
+        if self.model.cleanup_needed()
+            && self.cleanup_task.is_none()
+            && self.replication_task.is_none()
+            && self.model.checkpoint_state.is_none()
+        {
+            self.cleanup_task = Some(self.start_cleanup());
+        }
+
+        if config().checkpoint_replication.url.is_some()
+            && self.model.epoch > self.model.replicated_epoch
+            && self.replication_task.is_none()
+            && self.cleanup_task.is_none()
+            && self.model.checkpoint_state.is_none()
+        {
+            self.replication_task = Some(self.start_replication());
+        }
+
         // check on checkpointing
         if self.model.checkpoint_state.is_some() {
             self.model.finish_checkpoint_if_done(&self.db).await?;
+        } else if self.savepoint_requested && self.cleanup_task.is_none() {
+            self.model
+                .start_checkpoint(&self.config.organization_id, &self.db, false, true)
+                .await?;
+            self.savepoint_requested = false;
         } else if self.model.last_checkpoint.elapsed() > self.config.checkpoint_interval
             && self.cleanup_task.is_none()
         {
@@ -750,15 +1176,148 @@ impl JobController {
             self.checkpoint(false).await?;
         }
 
+        // hot-reload updated UDFs at a checkpoint boundary
+        if self.udf_reload.is_some() {
+            self.progress_udf_reload().await?;
+        }
+
+        // has the job's state outgrown its namespace's quota?
+        if let Some(limit) = self.config.quota.max_state_bytes {
+            if self.model.last_checkpoint_bytes > limit {
+                return Ok(ControllerProgress::StateQuotaExceeded {
+                    bytes: self.model.last_checkpoint_bytes,
+                    limit,
+                });
+            }
+        }
+
         // update metrics
         if self.model.last_updated_metrics.elapsed() > job_metrics::COLLECTION_RATE {
             self.update_metrics().await;
             self.model.last_updated_metrics = Instant::now();
+
+            if let Some(slo) = &self.config.freshness_slo {
+                if let Some(lag) = self.model.metrics.sink_watermark_lag().await {
+                    self.freshness.observe(slo, lag);
+                }
+            }
+
+            if !self.aligner.is_empty() {
+                self.align_watermarks().await;
+            }
+        }
+
+        if self.model.last_recorded_watermarks.elapsed() > WATERMARK_HISTORY_INTERVAL {
+            if let Err(e) = self.record_watermarks().await {
+                warn!(
+                    message = "failed to record watermark history",
+                    job_id = *self.config.id,
+                    error = format!("{:?}", e)
+                );
+            }
+            self.model.last_recorded_watermarks = Instant::now();
+        }
+
+        let history = &config().controller.metrics_history;
+        if history.enabled && self.model.last_recorded_metrics.elapsed() > *history.sample_interval
+        {
+            if let Err(e) = self.record_metrics().await {
+                warn!(
+                    message = "failed to record metric history",
+                    job_id = *self.config.id,
+                    error = format!("{:?}", e)
+                );
+            }
+            self.model.last_recorded_metrics = Instant::now();
         }
 
         Ok(ControllerProgress::Continue)
     }
 
+    /// Sends the watermark bounds for each aligned watermark operator to the workers
+    async fn align_watermarks(&mut self) {
+        let watermarks = self.model.metrics.operator_watermarks().await;
+        let bounds = self.aligner.bounds(&watermarks);
+        if bounds.is_empty() {
+            return;
+        }
+
+        let req = AlignWatermarksReq {
+            max_watermark_micros: bounds
+                .into_iter()
+                .map(|(op, watermark)| (op, to_micros(watermark)))
+                .collect(),
+        };
+
+        for w in self.model.workers.values_mut() {
+            if w.state != WorkerState::Running {
+                continue;
+            }
+            if let Err(e) = w.connect.align_watermarks(req.clone()).await {
+                warn!(
+                    message = "failed to send watermark alignment to worker",
+                    job_id = *self.config.id,
+                    worker_id = w.id.0,
+                    error = format!("{:?}", e)
+                );
+            }
+        }
+    }
+
+    /// Samples the current watermark of each operator into the job's watermark history
+    async fn record_watermarks(&self) -> anyhow::Result<()> {
+        let watermarks = self.model.metrics.operator_watermarks().await;
+        if watermarks.is_empty() {
+            return Ok(());
+        }
+
+        let c = self.db.client().await?;
+        let now = OffsetDateTime::now_utc();
+        for (operator_id, watermark) in watermarks {
+            controller_queries::execute_record_watermark(
+                &c,
+                &*self.config.id,
+                &operator_id,
+                &now,
+                &watermark.into(),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Samples the current metrics of each operator into the job's metric history, along with
+    /// the rolled-up averages of any minute or hour that has ended
+    async fn record_metrics(&mut self) -> anyhow::Result<()> {
+        let samples = self.model.metrics.operator_samples().await;
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let rows = self.metric_rollup.record(
+            SystemTime::now(),
+            config().controller.metrics_history.raw_resolution_secs(),
+            samples,
+        );
+
+        let c = self.db.client().await?;
+        for row in rows {
+            controller_queries::execute_record_metric_sample(
+                &c,
+                &*self.config.id,
+                &row.operator_id,
+                &row.metric.to_string(),
+                &(row.resolution_secs as i32),
+                &row.time.into(),
+                &row.value,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn stop_job(&mut self, stop_mode: StopMode) -> anyhow::Result<()> {
         for c in self.model.workers.values_mut() {
             c.connect
@@ -774,7 +1333,7 @@ impl JobController {
     pub async fn checkpoint(&mut self, then_stop: bool) -> anyhow::Result<bool> {
         if self.model.checkpoint_state.is_none() {
             self.model
-                .start_checkpoint(&self.config.organization_id, &self.db, then_stop)
+                .start_checkpoint(&self.config.organization_id, &self.db, then_stop, false)
                 .await?;
             Ok(true)
         } else {
@@ -845,58 +1404,245 @@ impl JobController {
         self.model.operator_parallelism.get(op).cloned()
     }
 
-    fn start_cleanup(&mut self, new_min: u32) -> JoinHandle<anyhow::Result<u32>> {
-        let min_epoch = self.model.min_epoch.max(1);
+    /// The number of checkpoints that have completed since the job was scheduled
+    pub fn completed_checkpoints(&self) -> u32 {
+        self.model.completed_checkpoints
+    }
+
+    /// The category of the error that caused a task to fail, if any task has failed
+    pub fn failure_category(&self) -> Option<ErrorCategory> {
+        self.model.tasks.values().find_map(|t| match &t.state {
+            TaskState::Failed(_, category) => Some(*category),
+            _ => None,
+        })
+    }
+
+    /// Deletes the checkpoints that fall outside of the retention policy, along with any files
+    /// that retained checkpoints don't reference, returning the new min epoch
+    fn start_cleanup(&mut self) -> JoinHandle<anyhow::Result<u32>> {
         let job_id = self.config.id.clone();
         let db = self.db.clone();
+        let cur_epoch = self.model.epoch;
+        self.model.last_cleanup = Instant::now();
 
         info!(
             message = "Starting cleaning",
             job_id = *job_id,
-            min_epoch,
-            new_min
+            epoch = cur_epoch
         );
         let start = Instant::now();
-        let cur_epoch = self.model.epoch;
 
         tokio::spawn(async move {
-            let checkpoint = StateBackend::load_checkpoint_metadata(&job_id, cur_epoch).await?;
-
-            controller_queries::execute_mark_compacting(
+            let checkpoints: Vec<_> = controller_queries::fetch_completed_checkpoints(
                 &db.client().await?,
                 &*job_id,
-                &(min_epoch as i32),
-                &(new_min as i32),
+                &(cur_epoch as i32),
             )
-            .await?;
+            .await?
+            .into_iter()
+            .map(|r| CompletedCheckpoint {
+                epoch: r.epoch as u32,
+                finish_time: r.finish_time,
+                savepoint: r.savepoint,
+            })
+            .collect();
 
-            StateBackend::cleanup_checkpoint(checkpoint, min_epoch, new_min).await?;
+            let retained = retained_epochs(
+                &checkpoints,
+                &config().pipeline.checkpoint_retention,
+                OffsetDateTime::now_utc(),
+            );
 
-            controller_queries::execute_mark_checkpoints_compacted(
-                &db.client().await?,
-                &*job_id,
-                &(new_min as i32),
-            )
-            .await?;
+            let metadata = StateBackend::load_checkpoint_metadata(&job_id, cur_epoch).await?;
+            let deleted = StateBackend::cleanup_checkpoints(metadata, &retained).await?;
+
+            let c = db.client().await?;
+            for checkpoint in &checkpoints {
+                if checkpoint.epoch != cur_epoch && !retained.contains(&checkpoint.epoch) {
+                    controller_queries::execute_mark_checkpoint_compacted(
+                        &c,
+                        &*job_id,
+                        &(checkpoint.epoch as i32),
+                    )
+                    .await?;
+                }
+            }
 
-            if let Some(epoch_to_filter_before) = min_epoch.checked_sub(CHECKPOINT_ROWS_TO_KEEP) {
+            if let Some(epoch_to_filter_before) = cur_epoch.checked_sub(CHECKPOINT_ROWS_TO_KEEP) {
                 controller_queries::execute_drop_old_checkpoint_rows(
-                    &db.client().await?,
+                    &c,
                     &*job_id,
                     &(epoch_to_filter_before as i32),
                 )
                 .await?;
             }
 
+            let min_epoch = retained
+                .first()
+                .copied()
+                .unwrap_or(cur_epoch)
+                .min(cur_epoch);
+
             info!(
                 message = "Finished cleaning",
                 job_id = *job_id,
                 min_epoch,
-                new_min,
+                retained = retained.len(),
+                deleted_files = deleted,
                 duration = start.elapsed().as_secs_f32()
             );
 
-            Ok(new_min)
+            Ok(min_epoch)
         })
     }
+
+    /// Copies the latest completed checkpoint to the checkpoint replica, returning its epoch
+    fn start_replication(&mut self) -> JoinHandle<anyhow::Result<u32>> {
+        let job_id = self.config.id.clone();
+        let db = self.db.clone();
+        let epoch = self.model.epoch;
+        self.model.replicated_epoch = epoch;
+
+        tokio::spawn(async move {
+            let replication = &config().checkpoint_replication;
+            // when failed over, checkpoints are written directly to the replica
+            if !replication.failover {
+                let replica_url = replication
+                    .url
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("no checkpoint replica configured"))?;
+
+                let start = Instant::now();
+                let metadata = StateBackend::load_checkpoint_metadata(&job_id, epoch).await?;
+                let copied = StateBackend::replicate_checkpoint(&metadata, replica_url).await?;
+
+                info!(
+                    message = "Replicated checkpoint",
+                    job_id = *job_id,
+                    epoch,
+                    copied,
+                    duration = start.elapsed().as_secs_f32()
+                );
+            }
+
+            controller_queries::execute_mark_checkpoint_replicated(
+                &db.client().await?,
+                &*job_id,
+                &(epoch as i32),
+            )
+            .await?;
+
+            Ok(epoch)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEALTHY_DURATION: Duration = Duration::from_secs(120);
+
+    fn regions(regions: &[&[&str]]) -> Vec<(HashSet<String>, Option<Instant>)> {
+        regions
+            .iter()
+            .map(|r| (r.iter().map(|op| op.to_string()).collect(), None))
+            .collect()
+    }
+
+    fn tasks(tasks: &[(&str, u32, u64, bool)]) -> HashMap<(String, u32), TaskStatus> {
+        tasks
+            .iter()
+            .map(|(op, subtask, worker, failed)| {
+                let state = if *failed {
+                    TaskState::Failed("failed".to_string(), ErrorCategory::Internal)
+                } else {
+                    TaskState::Running
+                };
+                (
+                    (op.to_string(), *subtask),
+                    TaskStatus {
+                        state,
+                        worker_id: WorkerId(*worker),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_failed_region_disconnected() {
+        let regions = regions(&[&["source_a", "sink_a"], &["source_b", "sink_b"]]);
+        let tasks = tasks(&[
+            ("source_a", 0, 1, false),
+            ("sink_a", 0, 1, false),
+            ("source_b", 0, 2, false),
+            ("sink_b", 0, 2, true),
+        ]);
+
+        assert_eq!(
+            find_failed_region(&regions, &tasks, HEALTHY_DURATION),
+            Some((1, WorkerId(2)))
+        );
+    }
+
+    #[test]
+    fn test_failed_region_connected() {
+        // failures in more than one region can't be recovered by restarting just one of them
+        let regions = regions(&[&["source_a", "sink_a"], &["source_b", "sink_b"]]);
+        let tasks = tasks(&[
+            ("source_a", 0, 1, true),
+            ("sink_a", 0, 1, false),
+            ("source_b", 0, 1, false),
+            ("sink_b", 0, 1, true),
+        ]);
+
+        assert_eq!(find_failed_region(&regions, &tasks, HEALTHY_DURATION), None);
+    }
+
+    #[test]
+    fn test_failed_region_single_region() {
+        // when the whole pipeline is connected, its only region is restarted
+        let regions = regions(&[&["source_a", "source_b", "join", "sink"]]);
+        let tasks = tasks(&[
+            ("source_a", 0, 1, false),
+            ("source_b", 0, 1, false),
+            ("join", 0, 1, true),
+            ("sink", 0, 1, false),
+        ]);
+
+        assert_eq!(
+            find_failed_region(&regions, &tasks, HEALTHY_DURATION),
+            Some((0, WorkerId(1)))
+        );
+    }
+
+    #[test]
+    fn test_failed_region_spanning_workers() {
+        // the region's tasks run on two workers, so the whole job has to be restarted
+        let regions = regions(&[&["source_a", "sink_a"], &["source_b", "sink_b"]]);
+        let tasks = tasks(&[
+            ("source_a", 0, 1, false),
+            ("source_a", 1, 2, true),
+            ("sink_a", 0, 1, false),
+            ("source_b", 0, 1, false),
+            ("sink_b", 0, 1, false),
+        ]);
+
+        assert_eq!(find_failed_region(&regions, &tasks, HEALTHY_DURATION), None);
+    }
+
+    #[test]
+    fn test_failed_region_recently_restarted() {
+        let mut regions = regions(&[&["source_a", "sink_a"], &["source_b", "sink_b"]]);
+        regions[0].1 = Some(Instant::now());
+        let tasks = tasks(&[
+            ("source_a", 0, 1, true),
+            ("sink_a", 0, 1, false),
+            ("source_b", 0, 1, false),
+            ("sink_b", 0, 1, false),
+        ]);
+
+        assert_eq!(find_failed_region(&regions, &tasks, HEALTHY_DURATION), None);
+    }
 }
@@ -0,0 +1,129 @@
+use arroyo_rpc::config::CheckpointRetentionConfig;
+use std::collections::{BTreeSet, HashSet};
+use time::OffsetDateTime;
+
+const HOUR_SECS: i64 = 60 * 60;
+const DAY_SECS: i64 = 24 * HOUR_SECS;
+
+#[derive(Debug, Clone)]
+pub struct CompletedCheckpoint {
+    pub epoch: u32,
+    pub finish_time: Option<OffsetDateTime>,
+    pub savepoint: bool,
+}
+
+/// Keeps the latest checkpoint in each of the `count` most recent buckets of `bucket_secs`
+fn keep_per_bucket(
+    checkpoints: &[CompletedCheckpoint],
+    bucket_secs: i64,
+    count: u32,
+    now: OffsetDateTime,
+    retained: &mut BTreeSet<u32>,
+) {
+    if count == 0 {
+        return;
+    }
+
+    let oldest_bucket = now.unix_timestamp().div_euclid(bucket_secs) - count as i64 + 1;
+    let mut seen = HashSet::new();
+
+    // checkpoints are sorted latest first, so the first we see in each bucket is its latest
+    for c in checkpoints {
+        let Some(finish_time) = c.finish_time else {
+            continue;
+        };
+        let bucket = finish_time.unix_timestamp().div_euclid(bucket_secs);
+        if bucket >= oldest_bucket && seen.insert(bucket) {
+            retained.insert(c.epoch);
+        }
+    }
+}
+
+/// Decides which of a job's completed checkpoints to keep under the retention policy. The latest
+/// checkpoint is always kept, as the job would restore from it.
+pub fn retained_epochs(
+    checkpoints: &[CompletedCheckpoint],
+    policy: &CheckpointRetentionConfig,
+    now: OffsetDateTime,
+) -> BTreeSet<u32> {
+    let mut checkpoints = checkpoints.to_vec();
+    checkpoints.sort_by(|a, b| b.epoch.cmp(&a.epoch));
+
+    let mut retained: BTreeSet<u32> = checkpoints
+        .iter()
+        .take(policy.keep_last.max(1) as usize)
+        .map(|c| c.epoch)
+        .collect();
+
+    keep_per_bucket(
+        &checkpoints,
+        HOUR_SECS,
+        policy.keep_hourly,
+        now,
+        &mut retained,
+    );
+    keep_per_bucket(
+        &checkpoints,
+        DAY_SECS,
+        policy.keep_daily,
+        now,
+        &mut retained,
+    );
+
+    if policy.protect_savepoints {
+        retained.extend(checkpoints.iter().filter(|c| c.savepoint).map(|c| c.epoch));
+    }
+
+    retained
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn policy(keep_last: u32, keep_hourly: u32, keep_daily: u32) -> CheckpointRetentionConfig {
+        CheckpointRetentionConfig {
+            keep_last,
+            keep_hourly,
+            keep_daily,
+            protect_savepoints: true,
+            gc_interval: Duration::from_secs(60).into(),
+        }
+    }
+
+    #[test]
+    fn test_retention() {
+        let now = OffsetDateTime::from_unix_timestamp(10 * DAY_SECS + 12 * HOUR_SECS).unwrap();
+
+        // one checkpoint every 20 minutes for the last two days, latest first
+        let mut checkpoints: Vec<_> = (0..144u32)
+            .map(|i| CompletedCheckpoint {
+                epoch: 144 - i,
+                finish_time: Some(now - time::Duration::minutes(20 * i as i64)),
+                savepoint: false,
+            })
+            .collect();
+        checkpoints[100].savepoint = true;
+
+        let retained = retained_epochs(&checkpoints, &policy(3, 0, 0), now);
+        assert_eq!(
+            retained.into_iter().collect::<Vec<_>>(),
+            vec![44, 142, 143, 144]
+        );
+
+        let retained = retained_epochs(&checkpoints, &policy(1, 3, 0), now);
+        // the latest of the current hour, and the latest of each of the two before
+        assert_eq!(
+            retained.into_iter().collect::<Vec<_>>(),
+            vec![44, 140, 143, 144]
+        );
+
+        let retained = retained_epochs(&checkpoints, &policy(1, 0, 7), now);
+        // the latest of each of the three days that have checkpoints
+        assert_eq!(
+            retained.into_iter().collect::<Vec<_>>(),
+            vec![35, 44, 107, 144]
+        );
+    }
+}
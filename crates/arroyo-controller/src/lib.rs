@@ -3,22 +3,33 @@
 #![allow(clippy::type_complexity)]
 
 use anyhow::Result;
+use arroyo_rpc::api_types::pipelines::{
+    ErrorCategory, FreshnessSlo, RestartStrategy, WorkerPodConfig,
+};
 use arroyo_rpc::config;
 use arroyo_rpc::config::config;
+use arroyo_rpc::fault_injection;
 use arroyo_rpc::grpc::controller_grpc_server::{ControllerGrpc, ControllerGrpcServer};
+use arroyo_rpc::grpc::worker_grpc_client::WorkerGrpcClient;
 use arroyo_rpc::grpc::{
     GrpcOutputSubscription, HeartbeatNodeReq, HeartbeatNodeResp, HeartbeatReq, HeartbeatResp,
-    JobMetricsReq, JobMetricsResp, OutputData, RegisterNodeReq, RegisterNodeResp,
+    InjectFaultReq, InjectJobFaultReq, InjectJobFaultResp, JobMetricsReq, JobMetricsResp,
+    OutputData, ProfileJobReq, ProfileJobResp, ProfileReq, RegisterNodeReq, RegisterNodeResp,
     RegisterWorkerReq, RegisterWorkerResp, TaskCheckpointCompletedReq, TaskCheckpointCompletedResp,
     TaskFailedReq, TaskFailedResp, TaskFinishedReq, TaskFinishedResp, TaskStartedReq,
-    TaskStartedResp, WorkerFinishedReq, WorkerFinishedResp,
+    TaskStartedResp, TriggerSavepointReq, TriggerSavepointResp, UpdateJobParametersReq,
+    UpdateJobParametersResp, UpdateParametersReq, WorkerDrainingReq, WorkerDrainingResp,
+    WorkerFinishedReq, WorkerFinishedResp,
 };
 use arroyo_rpc::grpc::{
     SinkDataReq, SinkDataResp, TaskCheckpointEventReq, TaskCheckpointEventResp, WorkerErrorReq,
-    WorkerErrorRes,
+    WorkerErrorRes, WorkerProfile,
 };
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_rpc::secrets::start_secret_refresher;
+use arroyo_rpc::Compressed;
 use arroyo_server_common::shutdown::ShutdownGuard;
+use arroyo_server_common::tls;
 use arroyo_server_common::wrap_start;
 use arroyo_types::{from_micros, NodeId, WorkerId};
 use cornucopia_async::DatabaseSource;
@@ -40,20 +51,32 @@ use tracing::{debug, info, warn};
 
 //pub mod compiler;
 pub mod job_controller;
+mod notifications;
+mod preemption;
+mod quotas;
+mod restarts;
+mod scheduled_runs;
 pub mod schedulers;
 mod states;
 
 const TTL_PIPELINE_CLEANUP_TIME: Duration = Duration::from_secs(60 * 60);
+const WATERMARK_HISTORY_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const RESTART_HISTORY_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
 
 include!(concat!(env!("OUT_DIR"), "/controller-sql.rs"));
 
 use crate::job_controller::job_metrics::JobMetrics;
+use crate::preemption::SlotAllocations;
+use crate::quotas::{NamespaceQuota, NamespaceSlots};
 use crate::schedulers::{NodeScheduler, ProcessScheduler, Scheduler};
 use types::public::LogLevel;
 use types::public::{RestartMode, StopMode};
 
 pub const CHECKPOINTS_TO_KEEP: u32 = 5;
 
+// profiles (particularly flamegraphs) can exceed tonic's default 4MB message limit
+const MAX_PROFILE_MESSAGE_SIZE: usize = 128 * 1024 * 1024;
+
 lazy_static! {
     static ref ACTIVE_PIPELINES: Gauge = register_gauge!(
         "arroyo_controller_active_pipelines",
@@ -72,18 +95,25 @@ lazy_static! {
     .unwrap();
 }
 
-#[derive(Eq, PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub struct JobConfig {
     id: Arc<String>,
     organization_id: String,
     pipeline_name: String,
     pipeline_id: i64,
+    namespace: String,
+    quota: NamespaceQuota,
     stop_mode: StopMode,
     checkpoint_interval: Duration,
     ttl: Option<Duration>,
     parallelism_overrides: HashMap<String, usize>,
     restart_nonce: i32,
     restart_mode: RestartMode,
+    priority: i32,
+    worker_pod: Option<WorkerPodConfig>,
+    udf_reload_nonce: i32,
+    freshness_slo: Option<FreshnessSlo>,
+    restart_strategy: Option<RestartStrategy>,
 }
 
 #[derive(Clone, Debug)]
@@ -95,6 +125,7 @@ pub struct JobStatus {
     finish_time: Option<OffsetDateTime>,
     tasks: Option<i32>,
     failure_message: Option<String>,
+    failure_category: Option<String>,
     restarts: i32,
     pipeline_path: Option<String>,
     wasm_path: Option<String>,
@@ -111,6 +142,7 @@ impl JobStatus {
             &self.finish_time,
             &self.tasks,
             &self.failure_message,
+            &self.failure_category,
             &self.restarts,
             &self.pipeline_path,
             &self.wasm_path,
@@ -152,6 +184,7 @@ pub enum RunningMessage {
         operator_id: String,
         subtask_index: u32,
         reason: String,
+        category: ErrorCategory,
     },
     WorkerHeartbeat {
         worker_id: WorkerId,
@@ -171,12 +204,17 @@ pub enum JobMessage {
         rpc_address: String,
         data_address: String,
         slots: usize,
+        resource_class: Option<String>,
     },
     TaskStarted {
         worker_id: WorkerId,
         operator_id: String,
         operator_subtask: u64,
     },
+    WorkerDraining {
+        worker_id: WorkerId,
+    },
+    TriggerSavepoint,
     RunningMessage(RunningMessage),
 }
 
@@ -186,6 +224,10 @@ pub struct ControllerServer {
     data_txs: Arc<tokio::sync::Mutex<HashMap<String, Vec<Sender<Result<OutputData, Status>>>>>>,
     scheduler: Arc<dyn Scheduler>,
     metrics: Arc<RwLock<HashMap<Arc<String>, JobMetrics>>>,
+    namespace_slots: Arc<std::sync::Mutex<NamespaceSlots>>,
+    slot_allocations: Arc<std::sync::Mutex<SlotAllocations>>,
+    // rpc addresses of the workers that have registered for each job
+    worker_addresses: Arc<RwLock<HashMap<String, HashMap<WorkerId, String>>>>,
     db: DatabaseSource,
 }
 
@@ -203,6 +245,13 @@ impl ControllerGrpc for ControllerServer {
 
         let req = request.into_inner();
 
+        self.worker_addresses
+            .write()
+            .await
+            .entry(req.job_id.clone())
+            .or_default()
+            .insert(WorkerId(req.worker_id), req.rpc_address.clone());
+
         self.send_to_job_queue(
             &req.job_id,
             JobMessage::WorkerConnect {
@@ -211,6 +260,7 @@ impl ControllerGrpc for ControllerServer {
                 rpc_address: req.rpc_address,
                 data_address: req.data_address,
                 slots: req.slots as usize,
+                resource_class: req.resource_class,
             },
         )
         .await?;
@@ -323,6 +373,7 @@ impl ControllerGrpc for ControllerServer {
                 worker_id: WorkerId(req.worker_id),
                 operator_id: req.operator_id,
                 subtask_index: req.operator_subtask as u32,
+                category: req.category().into(),
                 reason: req.error,
             }),
         )
@@ -358,10 +409,49 @@ impl ControllerGrpc for ControllerServer {
         &self,
         request: Request<WorkerFinishedReq>,
     ) -> Result<Response<WorkerFinishedResp>, Status> {
-        self.scheduler.worker_finished(request.into_inner()).await;
+        let req = request.into_inner();
+
+        {
+            let mut addresses = self.worker_addresses.write().await;
+            if let Some(job_workers) = addresses.get_mut(&req.job_id) {
+                job_workers.remove(&WorkerId(req.worker_id));
+                if job_workers.is_empty() {
+                    addresses.remove(&req.job_id);
+                }
+            }
+        }
+
+        self.scheduler.worker_finished(req).await;
         Ok(Response::new(WorkerFinishedResp {}))
     }
 
+    async fn worker_draining(
+        &self,
+        request: Request<WorkerDrainingReq>,
+    ) -> Result<Response<WorkerDrainingResp>, Status> {
+        let req = request.into_inner();
+        info!(
+            message = "worker draining",
+            job_id = req.job_id,
+            worker_id = req.worker_id,
+            node_id = req.node_id
+        );
+
+        if let Some(node_id) = req.node_id {
+            self.scheduler.drain_node(NodeId(node_id)).await;
+        }
+
+        self.send_to_job_queue(
+            &req.job_id,
+            JobMessage::WorkerDraining {
+                worker_id: WorkerId(req.worker_id),
+            },
+        )
+        .await?;
+
+        Ok(Response::new(WorkerDrainingResp {}))
+    }
+
     async fn send_sink_data(
         &self,
         request: Request<SinkDataReq>,
@@ -431,6 +521,7 @@ impl ControllerGrpc for ControllerServer {
             &LogLevel::error,
             &req.message,
             &req.details,
+            &Some(ErrorCategory::from(req.category()).to_string()),
         )
         .await
         {
@@ -454,11 +545,194 @@ impl ControllerGrpc for ControllerServer {
 
         Ok(Response::new(JobMetricsResp {
             metrics: serde_json::to_string(&metrics.get_groups().await).unwrap(),
+            operator_stats: serde_json::to_string(&metrics.operator_stats().await).unwrap(),
         }))
     }
+
+    async fn profile_job(
+        &self,
+        request: Request<ProfileJobReq>,
+    ) -> Result<Response<ProfileJobResp>, Status> {
+        let req = request.into_inner();
+
+        let workers = self.running_workers(&req.job_id).await?;
+
+        info!(
+            message = "collecting profiles from workers",
+            job_id = req.job_id,
+            workers = workers.len(),
+            profile_type = ?req.profile_type()
+        );
+
+        // profile all workers concurrently so that they cover the same period
+        let profiles = futures::future::join_all(workers.into_iter().map(|(worker_id, addr)| {
+            let profile_req = ProfileReq {
+                profile_type: req.profile_type,
+                duration_micros: req.duration_micros,
+            };
+            async move {
+                let result: anyhow::Result<Vec<u8>> = async {
+                    let mut client = tls::connect_grpc(addr)
+                        .await
+                        .map(|channel| WorkerGrpcClient::new(channel).compressed())?
+                        .max_decoding_message_size(MAX_PROFILE_MESSAGE_SIZE);
+                    Ok(client.profile(profile_req).await?.into_inner().data)
+                }
+                .await;
+
+                match result {
+                    Ok(data) => WorkerProfile {
+                        worker_id: worker_id.0,
+                        data,
+                        error: None,
+                    },
+                    Err(e) => {
+                        warn!(
+                            message = "failed to collect profile from worker",
+                            worker_id = worker_id.0,
+                            error = format!("{:?}", e)
+                        );
+                        WorkerProfile {
+                            worker_id: worker_id.0,
+                            data: vec![],
+                            error: Some(e.to_string()),
+                        }
+                    }
+                }
+            }
+        }))
+        .await;
+
+        Ok(Response::new(ProfileJobResp { profiles }))
+    }
+
+    async fn inject_job_fault(
+        &self,
+        request: Request<InjectJobFaultReq>,
+    ) -> Result<Response<InjectJobFaultResp>, Status> {
+        let req = request.into_inner();
+
+        if !fault_injection::enabled() {
+            return Err(Status::failed_precondition(
+                "Fault injection is not enabled",
+            ));
+        }
+
+        let workers = self.running_workers(&req.job_id).await?;
+
+        info!(
+            message = "injecting fault into workers",
+            job_id = req.job_id,
+            workers = workers.len(),
+            fault = ?req.fault
+        );
+
+        for (worker_id, addr) in &workers {
+            let mut client = tls::connect_grpc(addr.clone())
+                .await
+                .map(|channel| WorkerGrpcClient::new(channel).compressed())
+                .map_err(|e| {
+                    Status::unavailable(format!(
+                        "Failed to connect to worker {}: {}",
+                        worker_id.0, e
+                    ))
+                })?;
+
+            client
+                .inject_fault(InjectFaultReq {
+                    fault: req.fault.clone(),
+                })
+                .await?;
+        }
+
+        Ok(Response::new(InjectJobFaultResp {
+            workers: workers.len() as u32,
+        }))
+    }
+
+    async fn trigger_savepoint(
+        &self,
+        request: Request<TriggerSavepointReq>,
+    ) -> Result<Response<TriggerSavepointResp>, Status> {
+        let req = request.into_inner();
+
+        self.send_to_job_queue(&req.job_id, JobMessage::TriggerSavepoint)
+            .await?;
+
+        Ok(Response::new(TriggerSavepointResp {}))
+    }
+
+    async fn update_job_parameters(
+        &self,
+        request: Request<UpdateJobParametersReq>,
+    ) -> Result<Response<UpdateJobParametersResp>, Status> {
+        let req = request.into_inner();
+
+        let workers = self.running_workers(&req.job_id).await?;
+
+        info!(
+            message = "updating operator parameters",
+            job_id = req.job_id,
+            operator_id = req.operator_id,
+            parameters = ?req.parameters
+        );
+
+        let mut subtasks = 0;
+        for (worker_id, addr) in &workers {
+            let mut client = tls::connect_grpc(addr.clone())
+                .await
+                .map(|channel| WorkerGrpcClient::new(channel).compressed())
+                .map_err(|e| {
+                    Status::unavailable(format!(
+                        "Failed to connect to worker {}: {}",
+                        worker_id.0, e
+                    ))
+                })?;
+
+            subtasks += client
+                .update_parameters(UpdateParametersReq {
+                    operator_id: req.operator_id.clone(),
+                    parameters: req.parameters.clone(),
+                })
+                .await?
+                .into_inner()
+                .subtasks;
+        }
+
+        Ok(Response::new(UpdateJobParametersResp { subtasks }))
+    }
 }
 
 impl ControllerServer {
+    /// The addresses of the workers running the job
+    async fn running_workers(&self, job_id: &str) -> Result<Vec<(WorkerId, String)>, Status> {
+        let running = self
+            .scheduler
+            .workers_for_job(job_id, None)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to look up workers for job: {}", e)))?;
+
+        let workers: Vec<_> = {
+            let mut addresses = self.worker_addresses.write().await;
+            let Some(job_workers) = addresses.get_mut(job_id) else {
+                return Err(Status::not_found("No running workers for job"));
+            };
+
+            // forget workers from previous runs
+            job_workers.retain(|id, _| running.contains(id));
+            job_workers
+                .iter()
+                .map(|(id, addr)| (*id, addr.clone()))
+                .collect()
+        };
+
+        if workers.is_empty() {
+            return Err(Status::not_found("No running workers for job"));
+        }
+
+        Ok(workers)
+    }
+
     pub async fn new(database: DatabaseSource) -> Self {
         let scheduler: Arc<dyn Scheduler> = match &config().controller.scheduler {
             config::Scheduler::Node => {
@@ -485,6 +759,9 @@ impl ControllerServer {
             job_state: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
             db: database,
             metrics: Default::default(),
+            namespace_slots: Default::default(),
+            slot_allocations: Default::default(),
+            worker_addresses: Default::default(),
         }
     }
 
@@ -514,6 +791,8 @@ impl ControllerServer {
         let jobs = Arc::clone(&self.job_state);
         let scheduler = Arc::clone(&self.scheduler);
         let metrics = Arc::clone(&self.metrics);
+        let namespace_slots = Arc::clone(&self.namespace_slots);
+        let slot_allocations = Arc::clone(&self.slot_allocations);
 
         let token = guard.token();
 
@@ -531,7 +810,17 @@ impl ControllerServer {
                         organization_id: p.org_id,
                         pipeline_name: p.pipeline_name,
                         pipeline_id: p.pipeline_id,
-                        stop_mode: p.stop,
+                        namespace: p.namespace,
+                        quota: NamespaceQuota {
+                            max_task_slots: p.max_task_slots.map(|s| s as usize),
+                            max_state_bytes: p.max_state_bytes.map(|b| b as u64),
+                        },
+                        // a preempted job is kept stopped until there's room to resume it
+                        stop_mode: if p.preempted_slots.is_some() && p.stop == StopMode::none {
+                            StopMode::checkpoint
+                        } else {
+                            p.stop
+                        },
                         checkpoint_interval: Duration::from_micros(
                             p.checkpoint_interval_micros as u64,
                         ),
@@ -545,6 +834,41 @@ impl ControllerServer {
                             .collect(),
                         restart_nonce: p.config_restart_nonce,
                         restart_mode: p.restart_mode,
+                        priority: p.priority,
+                        worker_pod: p.worker_pod.and_then(|v| {
+                            serde_json::from_value(v)
+                                .map_err(|e| {
+                                    warn!(
+                                        message = "invalid worker pod config for job",
+                                        job_id = *id,
+                                        error = format!("{:?}", e)
+                                    )
+                                })
+                                .ok()
+                        }),
+                        udf_reload_nonce: p.udf_reload_nonce,
+                        freshness_slo: p.freshness_slo.and_then(|v| {
+                            serde_json::from_value(v)
+                                .map_err(|e| {
+                                    warn!(
+                                        message = "invalid freshness SLO for job",
+                                        job_id = *id,
+                                        error = format!("{:?}", e)
+                                    )
+                                })
+                                .ok()
+                        }),
+                        restart_strategy: p.restart_strategy.and_then(|v| {
+                            serde_json::from_value(v)
+                                .map_err(|e| {
+                                    warn!(
+                                        message = "invalid restart strategy for job",
+                                        job_id = *id,
+                                        error = format!("{:?}", e)
+                                    )
+                                })
+                                .ok()
+                        }),
                     };
 
                     let mut jobs = jobs.lock().await;
@@ -557,6 +881,7 @@ impl ControllerServer {
                         finish_time: p.finish_time,
                         tasks: p.tasks,
                         failure_message: p.failure_message,
+                        failure_category: p.failure_category,
                         restarts: p.restarts,
                         pipeline_path: p.pipeline_path,
                         wasm_path: p.wasm_path,
@@ -575,6 +900,8 @@ impl ControllerServer {
                                 scheduler.clone(),
                                 guard.clone_temporary(),
                                 metrics.clone(),
+                                namespace_slots.clone(),
+                                slot_allocations.clone(),
                             )
                             .await,
                         );
@@ -590,6 +917,29 @@ impl ControllerServer {
                     if res > 0 {
                         info!("Cleaned {res} preview pipelines from database");
                     }
+
+                    queries::controller_queries::execute_clean_watermark_history(
+                        &client,
+                        &(OffsetDateTime::now_utc() - WATERMARK_HISTORY_RETENTION),
+                    )
+                    .await?;
+
+                    for (resolution_secs, retention) in
+                        config().controller.metrics_history.resolutions()
+                    {
+                        queries::controller_queries::execute_clean_metric_history(
+                            &client,
+                            &(resolution_secs as i32),
+                            &(OffsetDateTime::now_utc() - retention),
+                        )
+                        .await?;
+                    }
+
+                    queries::controller_queries::execute_clean_job_restarts(
+                        &client,
+                        &(OffsetDateTime::now_utc() - RESTART_HISTORY_RETENTION),
+                    )
+                    .await?;
                     cleaned_at = Instant::now();
                 }
 
@@ -613,14 +963,23 @@ impl ControllerServer {
         info!("Starting arroyo-controller on {}", addr);
 
         self.start_updater(guard.child("updater"));
+        // workers run by the embedded scheduler share this process's secret cache
+        start_secret_refresher();
+        scheduled_runs::start_schedule_runner(self.db.clone(), guard.child("schedule-runner"));
+        preemption::start_resumer(
+            self.db.clone(),
+            self.scheduler.clone(),
+            self.slot_allocations.clone(),
+            guard.child("preemption-resumer"),
+        );
         guard.into_spawn_task(wrap_start(
             "controller",
             addr,
             arroyo_server_common::grpc_server()
                 .accept_http1(true)
-                .add_service(ControllerGrpcServer::new(self.clone()))
+                .add_service(ControllerGrpcServer::new(self.clone()).compressed())
                 .add_service(reflection)
-                .serve(addr.clone()),
+                .serve_with_incoming(tls::bind(addr)),
         ));
     }
 }
@@ -0,0 +1,354 @@
+//! Sends notifications about changes in a job's state to the notification targets configured for
+//! its organization or pipeline.
+//!
+//! Delivery happens in the background so that it never holds up the state machine; failed
+//! deliveries are retried a few times with backoff and then logged and dropped.
+
+use crate::queries::controller_queries;
+use crate::JobConfig;
+use arroyo_rpc::api_types::notifications::{
+    NotificationError, NotificationEvent, NotificationPayload, NotificationTargetConfig,
+};
+use cornucopia_async::DatabaseSource;
+use serde_json::json;
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const DELIVERY_ATTEMPTS: u32 = 3;
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+// PagerDuty rejects summaries longer than this
+const PAGERDUTY_MAX_SUMMARY: usize = 1024;
+
+/// A change in a job's state to notify targets of
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub event: NotificationEvent,
+    pub state: String,
+    pub message: String,
+    pub error: Option<NotificationError>,
+    pub checkpoint_failures: Option<u32>,
+}
+
+impl Notification {
+    pub fn new(event: NotificationEvent, state: &str, message: impl Into<String>) -> Self {
+        Self {
+            event,
+            state: state.to_string(),
+            message: message.into(),
+            error: None,
+            checkpoint_failures: None,
+        }
+    }
+
+    pub fn with_error(
+        mut self,
+        message: impl Into<String>,
+        category: Option<String>,
+        err: &anyhow::Error,
+    ) -> Self {
+        self.error = Some(NotificationError {
+            message: message.into(),
+            category,
+            details: Some(format!("{:#}", err)),
+        });
+        self
+    }
+}
+
+/// Sends the notification to every target of the job's organization or pipeline that is
+/// subscribed to its event
+pub fn notify(db: &DatabaseSource, config: &JobConfig, notification: Notification) {
+    let db = db.clone();
+    let config = config.clone();
+
+    tokio::spawn(async move {
+        if let Err(e) = send(&db, &config, notification).await {
+            warn!(
+                message = "failed to send job notifications",
+                job_id = *config.id,
+                error = format!("{:?}", e)
+            );
+        }
+    });
+}
+
+async fn send(
+    db: &DatabaseSource,
+    config: &JobConfig,
+    notification: Notification,
+) -> anyhow::Result<()> {
+    let targets = controller_queries::fetch_get_job_notification_targets(
+        &db.client().await?,
+        &config.pipeline_id,
+        &config.organization_id,
+    )
+    .await?;
+
+    let Some(pipeline_pub_id) = targets.first().map(|t| t.pipeline_pub_id.clone()) else {
+        return Ok(());
+    };
+
+    let payload = NotificationPayload {
+        event: notification.event,
+        job_id: config.id.to_string(),
+        pipeline_id: pipeline_pub_id,
+        pipeline_name: config.pipeline_name.clone(),
+        state: notification.state,
+        message: notification.message,
+        error: notification.error,
+        checkpoint_failures: notification.checkpoint_failures,
+        time: arroyo_types::to_micros(SystemTime::now()),
+    };
+
+    let client = reqwest::Client::new();
+
+    for target in targets {
+        let events: Vec<NotificationEvent> = match serde_json::from_value(target.events) {
+            Ok(events) => events,
+            Err(e) => {
+                warn!(
+                    message = "invalid notification target events",
+                    target = target.pub_id,
+                    error = format!("{:?}", e)
+                );
+                continue;
+            }
+        };
+
+        if !events.contains(&payload.event) {
+            continue;
+        }
+
+        let target_config: NotificationTargetConfig = match serde_json::from_value(target.config) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!(
+                    message = "invalid notification target config",
+                    target = target.pub_id,
+                    error = format!("{:?}", e)
+                );
+                continue;
+            }
+        };
+
+        let client = client.clone();
+        let payload = payload.clone();
+        let job_id = config.id.clone();
+        tokio::spawn(async move {
+            if let Err(e) = deliver(
+                &client,
+                &target_config,
+                target.payload_template.as_deref(),
+                &payload,
+            )
+            .await
+            {
+                warn!(
+                    message = "failed to deliver notification",
+                    job_id = *job_id,
+                    target = target.pub_id,
+                    event = ?payload.event,
+                    error = e
+                );
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn default_text(payload: &NotificationPayload) -> String {
+    let mut text = format!(
+        "Pipeline {} (job {}): {}",
+        payload.pipeline_name, payload.job_id, payload.message
+    );
+    if let Some(error) = &payload.error {
+        text.push_str(": ");
+        text.push_str(&error.message);
+    }
+    text
+}
+
+/// The PagerDuty dedup key for the incident an event triggers; failures of the same kind for the
+/// same job are grouped into one incident
+fn dedup_key(job_id: &str, event: NotificationEvent) -> String {
+    match event {
+        NotificationEvent::JobFailed | NotificationEvent::JobRecovered => {
+            format!("arroyo-{}", job_id)
+        }
+        NotificationEvent::CheckpointFailures => format!("arroyo-{}-checkpoints", job_id),
+        NotificationEvent::SchedulingTimeout => format!("arroyo-{}-scheduling", job_id),
+    }
+}
+
+/// Builds the requests to send for the payload, as pairs of URL and JSON body
+fn requests(
+    config: &NotificationTargetConfig,
+    template: Option<&str>,
+    payload: &NotificationPayload,
+) -> Result<Vec<(String, String)>, String> {
+    Ok(match config {
+        NotificationTargetConfig::Webhook { url, .. } => {
+            let body = match template {
+                Some(template) => payload.render(template, true)?,
+                None => serde_json::to_string(payload).unwrap(),
+            };
+            vec![(url.clone(), body)]
+        }
+        NotificationTargetConfig::Slack { webhook_url } => {
+            let text = match template {
+                Some(template) => payload.render(template, false)?,
+                None => default_text(payload),
+            };
+            vec![(webhook_url.clone(), json!({ "text": text }).to_string())]
+        }
+        NotificationTargetConfig::PagerDuty { routing_key } => {
+            if payload.event == NotificationEvent::JobRecovered {
+                // a recovered job resolves any incident that was opened for it
+                [
+                    NotificationEvent::JobFailed,
+                    NotificationEvent::CheckpointFailures,
+                    NotificationEvent::SchedulingTimeout,
+                ]
+                .into_iter()
+                .map(|event| {
+                    let body = json!({
+                        "routing_key": routing_key,
+                        "event_action": "resolve",
+                        "dedup_key": dedup_key(&payload.job_id, event),
+                    });
+                    (PAGERDUTY_EVENTS_URL.to_string(), body.to_string())
+                })
+                .collect()
+            } else {
+                let mut summary = match template {
+                    Some(template) => payload.render(template, false)?,
+                    None => default_text(payload),
+                };
+                if summary.len() > PAGERDUTY_MAX_SUMMARY {
+                    let mut end = PAGERDUTY_MAX_SUMMARY;
+                    while !summary.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    summary.truncate(end);
+                }
+
+                let body = json!({
+                    "routing_key": routing_key,
+                    "event_action": "trigger",
+                    "dedup_key": dedup_key(&payload.job_id, payload.event),
+                    "payload": {
+                        "summary": summary,
+                        "source": "arroyo",
+                        "severity": "error",
+                        "custom_details": payload,
+                    },
+                });
+                vec![(PAGERDUTY_EVENTS_URL.to_string(), body.to_string())]
+            }
+        }
+    })
+}
+
+async fn deliver(
+    client: &reqwest::Client,
+    config: &NotificationTargetConfig,
+    template: Option<&str>,
+    payload: &NotificationPayload,
+) -> Result<(), String> {
+    let headers = match config {
+        NotificationTargetConfig::Webhook { headers, .. } => headers.clone(),
+        _ => Default::default(),
+    };
+
+    for (url, body) in requests(config, template, payload)? {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+
+            let mut request = client
+                .post(&url)
+                .timeout(DELIVERY_TIMEOUT)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            for (name, value) in &headers {
+                request = request.header(name, value);
+            }
+
+            match request.send().await.and_then(|r| r.error_for_status()) {
+                Ok(_) => break,
+                Err(e) if attempt >= DELIVERY_ATTEMPTS => return Err(format!("{:?}", e)),
+                Err(_) => {
+                    tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(event: NotificationEvent) -> NotificationPayload {
+        NotificationPayload {
+            event,
+            job_id: "job_1".to_string(),
+            pipeline_id: "pl_1".to_string(),
+            pipeline_name: "orders".to_string(),
+            state: "Failed".to_string(),
+            message: "Job failed".to_string(),
+            error: None,
+            checkpoint_failures: None,
+            time: 0,
+        }
+    }
+
+    #[test]
+    fn test_pagerduty_requests() {
+        let config = NotificationTargetConfig::PagerDuty {
+            routing_key: "key".to_string(),
+        };
+
+        let triggered = requests(&config, None, &payload(NotificationEvent::JobFailed)).unwrap();
+        assert_eq!(triggered.len(), 1);
+        let body: serde_json::Value = serde_json::from_str(&triggered[0].1).unwrap();
+        assert_eq!(body["event_action"], "trigger");
+        assert_eq!(body["dedup_key"], "arroyo-job_1");
+        assert_eq!(
+            body["payload"]["summary"],
+            "Pipeline orders (job job_1): Job failed"
+        );
+
+        let resolved = requests(&config, None, &payload(NotificationEvent::JobRecovered)).unwrap();
+        assert_eq!(resolved.len(), 3);
+        assert!(resolved
+            .iter()
+            .all(|(_, body)| body.contains("\"resolve\"")));
+    }
+
+    #[test]
+    fn test_webhook_template() {
+        let config = NotificationTargetConfig::Webhook {
+            url: "http://localhost/hook".to_string(),
+            headers: Default::default(),
+        };
+
+        let reqs = requests(
+            &config,
+            Some(r#"{"job": "{{ jobId }}", "event": "{{ event }}"}"#),
+            &payload(NotificationEvent::SchedulingTimeout),
+        )
+        .unwrap();
+        assert_eq!(
+            reqs,
+            vec![(
+                "http://localhost/hook".to_string(),
+                r#"{"job": "job_1", "event": "schedulingTimeout"}"#.to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Priority-based preemption of running jobs.
+//!
+//! When a job can't be scheduled because the cluster is out of task slots, lower-priority jobs
+//! may be preempted to make room for it: they are stopped with a checkpoint and marked as
+//! preempted in the database, which holds the number of slots they need to resume. Once enough
+//! slots are free again, preempted jobs are resumed from their checkpoints in priority order.
+//!
+//! This only applies to the node scheduler, which is the only one that manages a fixed pool of
+//! slots; see [`Scheduler::free_slots`].
+
+use crate::queries::controller_queries;
+use crate::schedulers::Scheduler;
+use arroyo_server_common::shutdown::ShutdownGuard;
+use cornucopia_async::DatabaseSource;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+const RESUME_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Allocation {
+    priority: i32,
+    slots: usize,
+}
+
+/// Tracks the task slots held by each scheduled job, along with its priority, and the slots
+/// needed by jobs that are waiting to be scheduled
+#[derive(Debug, Default)]
+pub struct SlotAllocations {
+    allocations: HashMap<Arc<String>, Allocation>,
+    waiting: HashMap<Arc<String>, usize>,
+}
+
+impl SlotAllocations {
+    pub fn allocate(&mut self, job_id: &Arc<String>, priority: i32, slots: usize) {
+        self.waiting.remove(job_id);
+        self.allocations
+            .insert(job_id.clone(), Allocation { priority, slots });
+    }
+
+    /// Records that the job is waiting for `slots` task slots to become free
+    pub fn wait(&mut self, job_id: &Arc<String>, slots: usize) {
+        self.waiting.insert(job_id.clone(), slots);
+    }
+
+    pub fn release(&mut self, job_id: &Arc<String>) {
+        self.allocations.remove(job_id);
+        self.waiting.remove(job_id);
+    }
+
+    /// The total number of slots needed by jobs waiting to be scheduled
+    pub fn waiting_slots(&self) -> usize {
+        self.waiting.values().sum()
+    }
+
+    /// Chooses jobs with a lower priority than `priority` to preempt so that at least
+    /// `slots_needed` slots are freed, starting with the lowest-priority (and, within a priority,
+    /// largest) jobs. Returns `None` if preempting every eligible job would still not free
+    /// enough slots.
+    pub fn select_victims(
+        &self,
+        job_id: &Arc<String>,
+        priority: i32,
+        slots_needed: usize,
+    ) -> Option<Vec<(Arc<String>, usize)>> {
+        let mut candidates: Vec<_> = self
+            .allocations
+            .iter()
+            .filter(|(id, a)| *id != job_id && a.priority < priority)
+            .collect();
+
+        candidates.sort_by_key(|(id, a)| (a.priority, std::cmp::Reverse(a.slots), (*id).clone()));
+
+        let mut victims = vec![];
+        let mut freed = 0;
+        for (id, a) in candidates {
+            if freed >= slots_needed {
+                break;
+            }
+            victims.push((id.clone(), a.slots));
+            freed += a.slots;
+        }
+
+        (freed >= slots_needed).then_some(victims)
+    }
+}
+
+/// Marks the job as preempted, which causes the controller to stop it with a checkpoint
+pub(crate) async fn preempt(
+    db: &DatabaseSource,
+    job_id: &str,
+    slots: usize,
+    preempted_by: &str,
+) -> anyhow::Result<()> {
+    info!(
+        message = "preempting job to make room for a higher-priority job",
+        job_id, preempted_by, slots
+    );
+
+    controller_queries::execute_preempt_job(&db.client().await?, &(slots as i32), &job_id).await?;
+    Ok(())
+}
+
+async fn resume_preempted(
+    db: &DatabaseSource,
+    scheduler: &Arc<dyn Scheduler>,
+    allocations: &Mutex<SlotAllocations>,
+) -> anyhow::Result<()> {
+    let client = db.client().await?;
+    let preempted = controller_queries::fetch_get_preempted_jobs(&client).await?;
+    if preempted.is_empty() {
+        return Ok(());
+    }
+
+    // slots that jobs are already waiting on are not available to preempted jobs
+    let mut free = scheduler
+        .free_slots()
+        .await
+        .map(|free| free.saturating_sub(allocations.lock().unwrap().waiting_slots()));
+
+    for job in preempted {
+        match job.state.as_deref() {
+            Some("Stopped") => {}
+            Some("Failed" | "Finished") => {
+                // there's nothing to resume, but the job should no longer be considered preempted
+                controller_queries::execute_resume_preempted_job(&client, &job.id).await?;
+                continue;
+            }
+            // still stopping
+            _ => continue,
+        }
+
+        let slots = job.preempted_slots as usize;
+        if let Some(free) = &mut free {
+            if slots > *free {
+                // resume strictly in priority order, so that a large high-priority job isn't
+                // starved by smaller lower-priority ones
+                break;
+            }
+            *free -= slots;
+        }
+
+        info!(message = "resuming preempted job", job_id = job.id, slots);
+        controller_queries::execute_resume_preempted_job(&client, &job.id).await?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn start_resumer(
+    db: DatabaseSource,
+    scheduler: Arc<dyn Scheduler>,
+    allocations: Arc<Mutex<SlotAllocations>>,
+    guard: ShutdownGuard,
+) {
+    let token = guard.token();
+
+    guard.into_spawn_task(async move {
+        let mut interval = tokio::time::interval(RESUME_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        while !token.is_cancelled() {
+            interval.tick().await;
+
+            if let Err(e) = resume_preempted(&db, &scheduler, &allocations).await {
+                warn!(
+                    message = "failed to resume preempted jobs",
+                    error = format!("{:?}", e)
+                );
+            }
+        }
+
+        Ok(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_victims() {
+        let mut allocations = SlotAllocations::default();
+
+        let low = Arc::new("low".to_string());
+        let low_large = Arc::new("low_large".to_string());
+        let mid = Arc::new("mid".to_string());
+        let high = Arc::new("high".to_string());
+
+        allocations.allocate(&low, 0, 2);
+        allocations.allocate(&low_large, 0, 4);
+        allocations.allocate(&mid, 5, 4);
+
+        // the largest of the lowest-priority jobs is chosen first
+        assert_eq!(
+            allocations.select_victims(&high, 10, 3),
+            Some(vec![(low_large.clone(), 4)])
+        );
+
+        assert_eq!(
+            allocations.select_victims(&high, 10, 8),
+            Some(vec![
+                (low_large.clone(), 4),
+                (low.clone(), 2),
+                (mid.clone(), 4)
+            ])
+        );
+
+        // jobs of equal or higher priority are never preempted
+        assert_eq!(allocations.select_victims(&high, 5, 8), None);
+        assert_eq!(allocations.select_victims(&low, 0, 1), None);
+
+        // a job is never chosen to make room for itself, even if its priority has since been
+        // raised
+        allocations.allocate(&high, 0, 4);
+        assert_eq!(allocations.select_victims(&high, 10, 11), None);
+
+        allocations.wait(&high, 4);
+        assert_eq!(allocations.waiting_slots(), 4);
+        allocations.release(&high);
+        assert_eq!(allocations.waiting_slots(), 0);
+    }
+}
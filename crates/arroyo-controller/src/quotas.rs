@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Resource limits configured for the namespace a job belongs to; `None` means unlimited
+#[derive(Eq, PartialEq, Clone, Debug, Default)]
+pub struct NamespaceQuota {
+    pub max_task_slots: Option<usize>,
+    pub max_state_bytes: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReserveResult {
+    Reserved,
+    /// The namespace does not currently have enough free slots, but may once other jobs stop
+    Unavailable {
+        in_use: usize,
+        limit: usize,
+    },
+    /// The job needs more slots than the namespace will ever allow
+    ExceedsQuota {
+        limit: usize,
+    },
+}
+
+/// Tracks the task slots reserved by jobs in each namespace, so that the total across all
+/// running jobs in a namespace can be held under its `max_task_slots` quota
+#[derive(Debug, Default)]
+pub struct NamespaceSlots {
+    reservations: HashMap<String, HashMap<Arc<String>, usize>>,
+}
+
+impl NamespaceSlots {
+    /// Attempts to reserve `slots` for the job, replacing any existing reservation it holds
+    pub fn try_reserve(
+        &mut self,
+        namespace: &str,
+        job_id: &Arc<String>,
+        slots: usize,
+        quota: &NamespaceQuota,
+    ) -> ReserveResult {
+        let jobs = self.reservations.entry(namespace.to_string()).or_default();
+
+        if let Some(limit) = quota.max_task_slots {
+            if slots > limit {
+                return ReserveResult::ExceedsQuota { limit };
+            }
+
+            let in_use: usize = jobs
+                .iter()
+                .filter(|(id, _)| *id != job_id)
+                .map(|(_, s)| *s)
+                .sum();
+
+            if in_use + slots > limit {
+                return ReserveResult::Unavailable { in_use, limit };
+            }
+        }
+
+        jobs.insert(job_id.clone(), slots);
+        ReserveResult::Reserved
+    }
+
+    pub fn release(&mut self, namespace: &str, job_id: &Arc<String>) {
+        if let Some(jobs) = self.reservations.get_mut(namespace) {
+            jobs.remove(job_id);
+            if jobs.is_empty() {
+                self.reservations.remove(namespace);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_and_release() {
+        let mut slots = NamespaceSlots::default();
+        let quota = NamespaceQuota {
+            max_task_slots: Some(8),
+            max_state_bytes: None,
+        };
+
+        let job_a = Arc::new("job_a".to_string());
+        let job_b = Arc::new("job_b".to_string());
+
+        assert_eq!(
+            slots.try_reserve("ns", &job_a, 16, &quota),
+            ReserveResult::ExceedsQuota { limit: 8 }
+        );
+        assert_eq!(
+            slots.try_reserve("ns", &job_a, 6, &quota),
+            ReserveResult::Reserved
+        );
+        assert_eq!(
+            slots.try_reserve("ns", &job_b, 4, &quota),
+            ReserveResult::Unavailable {
+                in_use: 6,
+                limit: 8
+            }
+        );
+
+        // re-reserving for the same job replaces its previous reservation
+        assert_eq!(
+            slots.try_reserve("ns", &job_a, 4, &quota),
+            ReserveResult::Reserved
+        );
+        assert_eq!(
+            slots.try_reserve("ns", &job_b, 4, &quota),
+            ReserveResult::Reserved
+        );
+
+        // other namespaces are tracked independently
+        assert_eq!(
+            slots.try_reserve("other", &job_a, 8, &quota),
+            ReserveResult::Reserved
+        );
+
+        slots.release("ns", &job_a);
+        assert_eq!(
+            slots.try_reserve("ns", &job_b, 8, &quota),
+            ReserveResult::Reserved
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Decides whether and when jobs are restarted after failures, according to their restart
+//! strategies, and records the restarts that are made
+
+use crate::queries::controller_queries;
+use arroyo_rpc::api_types::pipelines::{RestartStrategy, RestartStrategyType};
+use arroyo_rpc::config::config;
+use cornucopia_async::DatabaseSource;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RestartDecision {
+    /// Restart the job once `delay` has passed
+    Restart { delay: Duration },
+    /// Fail the job permanently, with the given message
+    Fail(String),
+}
+
+fn millis(millis: Option<u64>) -> Duration {
+    Duration::from_millis(millis.unwrap_or(0))
+}
+
+/// Decides what to do about a job failure. `restarts` is the number of times the job has
+/// restarted since it was last healthy, and `recent_restarts` the number of times it has
+/// restarted within the strategy's failure-rate interval.
+pub fn decide(
+    strategy: Option<&RestartStrategy>,
+    restarts: u32,
+    recent_restarts: u32,
+) -> RestartDecision {
+    let too_many = || RestartDecision::Fail("Job has restarted too many times".to_string());
+
+    let Some(strategy) = strategy else {
+        let allowed = config().pipeline.allowed_restarts;
+        if allowed != -1 && restarts as i64 >= allowed as i64 {
+            return too_many();
+        }
+        return RestartDecision::Restart {
+            delay: Duration::ZERO,
+        };
+    };
+
+    match strategy.strategy {
+        RestartStrategyType::FixedDelay | RestartStrategyType::ExponentialBackoff
+            if strategy.max_restarts.is_some_and(|max| restarts >= max) =>
+        {
+            too_many()
+        }
+        RestartStrategyType::FixedDelay => RestartDecision::Restart {
+            delay: millis(strategy.delay_millis),
+        },
+        RestartStrategyType::ExponentialBackoff => {
+            let initial = millis(strategy.delay_millis);
+            let max_delay = Duration::from_millis(
+                strategy
+                    .max_delay_millis
+                    .unwrap_or(RestartStrategy::DEFAULT_MAX_DELAY_MILLIS),
+            );
+            let multiplier = strategy
+                .multiplier
+                .unwrap_or(RestartStrategy::DEFAULT_MULTIPLIER);
+
+            // the growth factor may overflow to infinity, which is capped at the max delay
+            let delay = if initial.is_zero() {
+                Duration::ZERO
+            } else {
+                Duration::try_from_secs_f64(
+                    initial.as_secs_f64() * multiplier.powi(restarts.min(i32::MAX as u32) as i32),
+                )
+                .unwrap_or(max_delay)
+            };
+
+            RestartDecision::Restart {
+                delay: delay.min(max_delay),
+            }
+        }
+        RestartStrategyType::FailureRate => {
+            let max = strategy.max_restarts.unwrap_or(0);
+            if recent_restarts >= max {
+                return RestartDecision::Fail(format!(
+                    "Job has restarted more than {} times in {:?}",
+                    max,
+                    millis(strategy.interval_millis)
+                ));
+            }
+            RestartDecision::Restart {
+                delay: millis(strategy.delay_millis),
+            }
+        }
+    }
+}
+
+/// Counts the job's restarts within the strategy's failure-rate interval, for strategies that
+/// limit the failure rate
+pub async fn recent_restarts(
+    db: &DatabaseSource,
+    job_id: &str,
+    strategy: Option<&RestartStrategy>,
+) -> anyhow::Result<u32> {
+    let Some(strategy) = strategy.filter(|s| s.strategy == RestartStrategyType::FailureRate) else {
+        return Ok(0);
+    };
+
+    let since = OffsetDateTime::now_utc() - millis(strategy.interval_millis);
+    let restarts =
+        controller_queries::fetch_get_job_restarts_since(&db.client().await?, &job_id, &since)
+            .await?;
+
+    Ok(restarts.len() as u32)
+}
+
+pub async fn record_restart(
+    db: &DatabaseSource,
+    job_id: &str,
+    run_id: i64,
+    attempt: i32,
+    delay: Duration,
+    reason: &str,
+) -> anyhow::Result<()> {
+    controller_queries::execute_record_job_restart(
+        &db.client().await?,
+        &job_id,
+        &run_id,
+        &OffsetDateTime::now_utc(),
+        &attempt,
+        &(delay.as_micros() as i64),
+        &reason,
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strategy(strategy: RestartStrategyType) -> RestartStrategy {
+        RestartStrategy {
+            strategy,
+            delay_millis: Some(1000),
+            max_restarts: Some(3),
+            multiplier: None,
+            max_delay_millis: None,
+            interval_millis: None,
+        }
+    }
+
+    fn delay(decision: RestartDecision) -> Duration {
+        match decision {
+            RestartDecision::Restart { delay } => delay,
+            RestartDecision::Fail(message) => panic!("unexpected failure: {}", message),
+        }
+    }
+
+    #[test]
+    fn test_fixed_delay() {
+        let fixed = strategy(RestartStrategyType::FixedDelay);
+        assert_eq!(delay(decide(Some(&fixed), 0, 0)), Duration::from_secs(1));
+        assert_eq!(delay(decide(Some(&fixed), 2, 10)), Duration::from_secs(1));
+        assert!(matches!(
+            decide(Some(&fixed), 3, 0),
+            RestartDecision::Fail(_)
+        ));
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let mut backoff = strategy(RestartStrategyType::ExponentialBackoff);
+        backoff.max_restarts = None;
+        backoff.max_delay_millis = Some(10_000);
+
+        let delays: Vec<_> = (0..6)
+            .map(|restarts| delay(decide(Some(&backoff), restarts, 0)).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+
+        // the delay saturates rather than overflowing
+        assert_eq!(
+            delay(decide(Some(&backoff), 10_000, 0)),
+            Duration::from_secs(10)
+        );
+
+        backoff.multiplier = Some(3.0);
+        assert_eq!(delay(decide(Some(&backoff), 2, 0)), Duration::from_secs(9));
+    }
+
+    #[test]
+    fn test_failure_rate() {
+        let mut rate = strategy(RestartStrategyType::FailureRate);
+        rate.interval_millis = Some(60 * 60 * 1000);
+
+        // consecutive restarts don't matter, only those within the interval
+        assert_eq!(delay(decide(Some(&rate), 100, 2)), Duration::from_secs(1));
+        assert!(matches!(
+            decide(Some(&rate), 0, 3),
+            RestartDecision::Fail(_)
+        ));
+    }
+}
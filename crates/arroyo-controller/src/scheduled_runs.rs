@@ -0,0 +1,198 @@
+//! Runs bounded pipelines on a cron schedule.
+//!
+//! When a schedule comes due, the pipeline's job is restarted from scratch (its checkpoints are
+//! discarded) and a scheduled run is recorded. The run is then tracked until the job reaches a
+//! terminal state, at which point its outcome is written back to the run history.
+
+use crate::queries::controller_queries;
+use crate::queries::controller_queries::{GetActiveScheduledRuns, GetPipelineSchedules};
+use arroyo_rpc::api_types::pipelines::ScheduledRunState;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_rpc::schedule::{next_run_after, parse_cron};
+use arroyo_server_common::shutdown::ShutdownGuard;
+use cornucopia_async::{Database, DatabaseSource};
+use std::time::{Duration, SystemTime};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+fn is_terminal(state: Option<&str>) -> bool {
+    matches!(state, Some("Finished" | "Failed" | "Stopped"))
+}
+
+/// Determines the outcome of a run whose job has reached a terminal state
+fn run_outcome(state: &str) -> ScheduledRunState {
+    match state {
+        "Finished" => ScheduledRunState::Succeeded,
+        "Failed" => ScheduledRunState::Failed,
+        _ => ScheduledRunState::Cancelled,
+    }
+}
+
+async fn set_next_run(
+    db: &Database<'_>,
+    schedule: &GetPipelineSchedules,
+    now: SystemTime,
+) -> anyhow::Result<()> {
+    let next = match parse_cron(&schedule.cron) {
+        Ok(cron) => next_run_after(&cron, now).map(OffsetDateTime::from),
+        Err(e) => {
+            // the API validates expressions, so this should only happen if the cron library
+            // has changed what it accepts
+            warn!(
+                message = "invalid cron expression for pipeline schedule",
+                schedule_id = schedule.id,
+                error = format!("{:?}", e)
+            );
+            None
+        }
+    };
+
+    controller_queries::execute_set_schedule_next_run(db, &next, &schedule.id).await?;
+    Ok(())
+}
+
+async fn trigger(
+    db: &Database<'_>,
+    schedule: &GetPipelineSchedules,
+    scheduled_for: OffsetDateTime,
+    now: OffsetDateTime,
+) -> anyhow::Result<()> {
+    let run_id = schedule.run_id.unwrap_or(0);
+
+    if schedule.state.is_some() && !is_terminal(schedule.state.as_deref()) {
+        info!(
+            message = "skipping scheduled run; previous run still in progress",
+            job_id = schedule.job_id,
+        );
+
+        controller_queries::execute_create_scheduled_run(
+            db,
+            &generate_id(IdTypes::ScheduledRun),
+            &schedule.pipeline_id,
+            &schedule.job_id,
+            &run_id,
+            &ScheduledRunState::Skipped.to_string(),
+            &scheduled_for,
+            &Some(now),
+            &Some("the previous run was still in progress".to_string()),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    info!(message = "starting scheduled run", job_id = schedule.job_id);
+
+    // each run processes its input from scratch rather than resuming from the previous run
+    controller_queries::execute_reset_checkpoints(db, &schedule.job_id).await?;
+    controller_queries::execute_trigger_scheduled_job(db, &now, &schedule.job_id).await?;
+
+    controller_queries::execute_create_scheduled_run(
+        db,
+        &generate_id(IdTypes::ScheduledRun),
+        &schedule.pipeline_id,
+        &schedule.job_id,
+        &run_id,
+        &ScheduledRunState::Running.to_string(),
+        &scheduled_for,
+        &None,
+        &None,
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn update_run(db: &Database<'_>, run: GetActiveScheduledRuns) -> anyhow::Result<()> {
+    let Some(state) = run.state.as_deref() else {
+        // the job has not yet been picked up by the controller
+        return Ok(());
+    };
+
+    // the job's run id is incremented when it is scheduled, so until it moves past the run id
+    // recorded at trigger time, the job's state reflects the previous run
+    if run.run_id.unwrap_or(0) <= run.triggered_run_id || !is_terminal(Some(state)) {
+        return Ok(());
+    }
+
+    let outcome = run_outcome(state);
+    let failure_message = match outcome {
+        ScheduledRunState::Failed => run.failure_message,
+        _ => None,
+    };
+
+    controller_queries::execute_finish_scheduled_run(
+        db,
+        &outcome.to_string(),
+        &OffsetDateTime::now_utc(),
+        &failure_message,
+        &run.id,
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn check_schedules(db: &DatabaseSource) -> anyhow::Result<()> {
+    let client = db.client().await?;
+    let now = SystemTime::now();
+
+    for run in controller_queries::fetch_get_active_scheduled_runs(&client).await? {
+        update_run(&client, run).await?;
+    }
+
+    for schedule in controller_queries::fetch_get_pipeline_schedules(&client).await? {
+        match schedule.next_run_at {
+            None => {
+                set_next_run(&client, &schedule, now).await?;
+            }
+            Some(next_run_at) if SystemTime::from(next_run_at) <= now => {
+                trigger(&client, &schedule, next_run_at, OffsetDateTime::from(now)).await?;
+                set_next_run(&client, &schedule, now).await?;
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn start_schedule_runner(db: DatabaseSource, guard: ShutdownGuard) {
+    let token = guard.token();
+
+    guard.into_spawn_task(async move {
+        let mut interval = tokio::time::interval(SCHEDULE_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        while !token.is_cancelled() {
+            interval.tick().await;
+
+            if let Err(e) = check_schedules(&db).await {
+                warn!(
+                    message = "failed to process pipeline schedules",
+                    error = format!("{:?}", e)
+                );
+            }
+        }
+
+        Ok(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_outcome() {
+        assert!(is_terminal(Some("Finished")));
+        assert!(!is_terminal(Some("Running")));
+        assert!(!is_terminal(None));
+
+        assert_eq!(run_outcome("Finished"), ScheduledRunState::Succeeded);
+        assert_eq!(run_outcome("Failed"), ScheduledRunState::Failed);
+        assert_eq!(run_outcome("Stopped"), ScheduledRunState::Cancelled);
+    }
+}
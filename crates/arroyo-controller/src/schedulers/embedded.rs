@@ -126,4 +126,8 @@ impl Scheduler for EmbeddedScheduler {
             .map(|(k, _)| *k)
             .collect())
     }
+
+    async fn free_slots(&self) -> Option<usize> {
+        None
+    }
 }
@@ -1,19 +1,19 @@
-mod quantities;
-
-use crate::schedulers::kubernetes::quantities::QuantityParser;
 use crate::schedulers::{Scheduler, SchedulerError, StartPipelineReq};
 use anyhow::bail;
 use arroyo_rpc::config::{config, KubernetesSchedulerConfig, ResourceMode};
 use arroyo_rpc::grpc::{api, HeartbeatNodeReq, RegisterNodeReq, WorkerFinishedReq};
+use arroyo_rpc::quantities::QuantityParser;
 use arroyo_types::{WorkerId, ARROYO_PROGRAM_ENV, JOB_ID_ENV, RUN_ID_ENV};
 use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
-use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::api::core::v1::{Pod, ResourceRequirements};
 use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
 use kube::api::{DeleteParams, ListParams};
 use kube::{Api, Client};
 use prost::Message;
 use serde_json::json;
+use std::collections::BTreeMap;
 use std::time::Duration;
 use tonic::Status;
 use tracing::{info, warn};
@@ -40,20 +40,58 @@ impl KubernetesScheduler {
         Self { client, config }
     }
 
-    fn make_pod(&self, req: &StartPipelineReq, program: &str, number: usize, slots: usize) -> Pod {
+    fn make_pod(
+        &self,
+        req: &StartPipelineReq,
+        program: &str,
+        number: usize,
+        slots: usize,
+        resource_class: Option<&str>,
+    ) -> Pod {
         let c = &self.config;
+        let overrides = req.worker_pod.clone().unwrap_or_default();
+        let config_group = resource_class
+            .and_then(|class| c.worker.groups.get(class))
+            .cloned()
+            .unwrap_or_default();
+        let override_group = resource_class
+            .and_then(|class| overrides.groups.as_ref()?.get(class))
+            .cloned()
+            .unwrap_or_default();
+
+        // resources are overridden one by one, from the configured defaults to the pipeline's
+        // settings for this resource class
+        let mut resources = c.worker.resources.clone();
+        apply_resource_overrides(
+            &mut resources,
+            config_group.resources.requests.as_ref(),
+            config_group.resources.limits.as_ref(),
+        );
+        apply_resource_overrides(
+            &mut resources,
+            overrides.requests.as_ref().map(to_quantities).as_ref(),
+            overrides.limits.as_ref().map(to_quantities).as_ref(),
+        );
+        apply_resource_overrides(
+            &mut resources,
+            override_group.requests.as_ref().map(to_quantities).as_ref(),
+            override_group.limits.as_ref().map(to_quantities).as_ref(),
+        );
 
         let resources = match c.resource_mode {
             ResourceMode::PerSlot => {
-                let mut r = c.worker.resources.clone();
+                let mut r = resources;
 
                 for (name, rs) in [("limits", &mut r.limits), ("requests", &mut r.requests)] {
                     if let Some(l) = rs {
                         if let Some(cpu) = l.get_mut("cpu") {
                             let v = cpu.parse_cpu().unwrap_or_else(|e| {
                                 if number == 0 {
-                                    warn!("Invalid value '{}' for \
-                                kubernetes_scheduler.worker.resources.{}.cpu: {}; defaulting to 900m", cpu.0, name, e);
+                                    warn!(
+                                        "Invalid value '{}' for worker \
+                                resources.{}.cpu: {}; defaulting to 900m",
+                                        cpu.0, name, e
+                                    );
                                 }
                                 Quantity("900m".to_string()).parse_cpu().unwrap()
                             });
@@ -64,8 +102,11 @@ impl KubernetesScheduler {
                         if let Some(mem) = l.get_mut("memory") {
                             let v = mem.parse_memory().unwrap_or_else(|e| {
                                 if number == 0 {
-                                    warn!("Invalid value '{}' for \
-                                kubernetes_scheduler.worker.resources.{}.memory: {}; defaulting to 500Mi", mem.0, name, e);
+                                    warn!(
+                                        "Invalid value '{}' for worker \
+                                resources.{}.memory: {}; defaulting to 500Mi",
+                                        mem.0, name, e
+                                    );
                                 }
                                 Quantity("500Mi".to_string()).parse_memory().unwrap()
                             });
@@ -77,9 +118,52 @@ impl KubernetesScheduler {
 
                 r
             }
-            ResourceMode::PerPod => c.worker.resources.clone(),
+            ResourceMode::PerPod => resources,
         };
 
+        let mut annotations = c.worker.annotations.clone();
+        annotations.extend(config_group.annotations);
+        annotations.extend(overrides.annotations.unwrap_or_default());
+        annotations.extend(override_group.annotations.unwrap_or_default());
+
+        let mut node_selector = c.worker.node_selector.clone();
+        node_selector.extend(config_group.node_selector);
+        node_selector.extend(overrides.node_selector.unwrap_or_default());
+        node_selector.extend(override_group.node_selector.unwrap_or_default());
+
+        // the most specific affinity and spread constraints win, while tolerations accumulate
+        let affinity = override_group
+            .affinity
+            .or(overrides.affinity)
+            .or(config_group.affinity)
+            .or_else(|| c.worker.affinity.clone());
+
+        let mut tolerations = c.worker.tolerations.clone();
+        tolerations.extend(config_group.tolerations);
+        tolerations.extend(overrides.tolerations.unwrap_or_default());
+        tolerations.extend(override_group.tolerations.unwrap_or_default());
+
+        let mut topology_spread_constraints = override_group
+            .topology_spread_constraints
+            .or(overrides.topology_spread_constraints)
+            .or(config_group.topology_spread_constraints)
+            .unwrap_or_else(|| c.worker.topology_spread_constraints.clone());
+        for constraint in &mut topology_spread_constraints {
+            if constraint.label_selector.is_none() {
+                constraint.label_selector = Some(LabelSelector {
+                    match_labels: Some(
+                        [
+                            (JOB_ID_LABEL.to_string(), (*req.job_id).clone()),
+                            (RUN_ID_LABEL.to_string(), format!("{}", req.run_id)),
+                        ]
+                        .into_iter()
+                        .collect(),
+                    ),
+                    ..Default::default()
+                });
+            }
+        }
+
         let mut labels = c.worker.labels.clone();
         labels.insert(CLUSTER_LABEL.to_string(), c.worker.name());
         labels.insert(JOB_ID_LABEL.to_string(), (*req.job_id).clone());
@@ -122,6 +206,13 @@ impl KubernetesScheduler {
             }));
         }
 
+        if let Some(resource_class) = resource_class {
+            env.as_array_mut().unwrap().push(json!({
+                "name": "ARROYO__WORKER__RESOURCE_CLASS",
+                "value": resource_class,
+            }));
+        }
+
         let owner: Vec<_> = config()
             .kubernetes_scheduler
             .controller
@@ -142,12 +233,16 @@ impl KubernetesScheduler {
                 "name": format!("{}-{}-{}-{}", c.worker.name(), req.job_id.to_ascii_lowercase().replace('_', "-"), req.run_id, number),
                 "namespace": c.namespace,
                 "labels": labels,
-                "annotations": c.worker.annotations,
+                "annotations": annotations,
                 "ownerReferences": owner,
             },
             "spec": {
                 "volumes": c.worker.volumes,
                 "restartPolicy": "Never",
+                "nodeSelector": node_selector,
+                "affinity": affinity,
+                "tolerations": tolerations,
+                "topologySpreadConstraints": topology_spread_constraints,
                 "containers": [
                     {
                         "name": "worker",
@@ -175,31 +270,57 @@ impl KubernetesScheduler {
     }
 }
 
+fn to_quantities(resources: &BTreeMap<String, String>) -> BTreeMap<String, Quantity> {
+    resources
+        .iter()
+        .map(|(k, v)| (k.clone(), Quantity(v.clone())))
+        .collect()
+}
+
+/// Applies resource requests and limits on top of the worker resources, replacing those that
+/// are set in both
+fn apply_resource_overrides(
+    resources: &mut ResourceRequirements,
+    requests: Option<&BTreeMap<String, Quantity>>,
+    limits: Option<&BTreeMap<String, Quantity>>,
+) {
+    for (rs, o) in [
+        (&mut resources.requests, requests),
+        (&mut resources.limits, limits),
+    ] {
+        if let Some(o) = o {
+            rs.get_or_insert_with(Default::default)
+                .extend(o.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+    }
+}
+
 #[async_trait]
 impl Scheduler for KubernetesScheduler {
     async fn start_workers(&self, req: StartPipelineReq) -> Result<(), SchedulerError> {
         let api: Api<Pod> = Api::default_namespaced(self.client.as_ref().unwrap().clone());
 
-        let replicas = (req.slots as f32 / config().kubernetes_scheduler.worker.task_slots as f32)
-            .ceil() as usize;
-
-        info!(
-            job_id = *req.job_id,
-            message = "starting workers on k8s",
-            replicas,
-            task_slots = req.slots
-        );
-
         let program = general_purpose::STANDARD_NO_PAD
             .encode(api::ArrowProgram::from(req.program.clone()).encode_to_vec());
 
+        // the slots of each resource class run in their own pods, which can be placed separately
+        let mut groups: Vec<_> = req
+            .class_slots
+            .iter()
+            .map(|(class, slots)| (Some(class.as_str()), *slots))
+            .collect();
+        let class_slots: usize = groups.iter().map(|(_, slots)| slots).sum();
+        groups.push((None, req.slots.saturating_sub(class_slots)));
+
         let max_slots_per_pod = config().kubernetes_scheduler.worker.task_slots as usize;
-        let mut slots_scheduled = 0;
         let mut pods = vec![];
-        for i in 0..replicas {
-            let slots_here = (req.slots - slots_scheduled).min(max_slots_per_pod);
-            pods.push(self.make_pod(&req, &program, i, slots_here));
-            slots_scheduled += slots_here;
+        for (resource_class, slots) in groups {
+            let mut slots_scheduled = 0;
+            while slots_scheduled < slots {
+                let slots_here = (slots - slots_scheduled).min(max_slots_per_pod);
+                pods.push(self.make_pod(&req, &program, pods.len(), slots_here, resource_class));
+                slots_scheduled += slots_here;
+            }
         }
 
         info!(
@@ -303,12 +424,19 @@ impl Scheduler for KubernetesScheduler {
             })
             .collect()
     }
+
+    async fn free_slots(&self) -> Option<usize> {
+        // capacity is managed by kubernetes
+        None
+    }
 }
 
 #[cfg(test)]
 mod test {
     use arroyo_datastream::logical::LogicalProgram;
-    use arroyo_rpc::config::config;
+    use arroyo_rpc::api_types::pipelines::{WorkerGroupPlacement, WorkerPodConfig};
+    use arroyo_rpc::config::{config, KubernetesWorkerGroupConfig, ResourceMode};
+    use k8s_openapi::api::core::v1::Toleration;
     use serde_json::json;
     use std::sync::Arc;
 
@@ -325,7 +453,9 @@ mod test {
             hash: "12123123h".to_string(),
             run_id: 1,
             slots: 8,
+            class_slots: Default::default(),
             env_vars: Default::default(),
+            worker_pod: None,
         };
 
         let mut config = config().kubernetes_scheduler.clone();
@@ -360,6 +490,200 @@ mod test {
 
         KubernetesScheduler::with_config(None, config)
             // test that we don't panic when creating the replicaset
-            .make_pod(&req, "program", 3, 4);
+            .make_pod(&req, "program", 3, 4, None);
+    }
+
+    #[test]
+    fn test_worker_pod_overrides() {
+        let req = StartPipelineReq {
+            name: "test_pipeline".to_string(),
+            program: LogicalProgram::default(),
+            wasm_path: "file:///wasm".to_string(),
+            job_id: Arc::new("job123".to_string()),
+            hash: "12123123h".to_string(),
+            run_id: 1,
+            slots: 8,
+            class_slots: Default::default(),
+            env_vars: Default::default(),
+            worker_pod: Some(WorkerPodConfig {
+                requests: Some([("cpu".to_string(), "2".to_string())].into_iter().collect()),
+                limits: None,
+                node_selector: Some(
+                    [("pool".to_string(), "highmem".to_string())]
+                        .into_iter()
+                        .collect(),
+                ),
+                annotations: Some(
+                    [("team".to_string(), "data".to_string())]
+                        .into_iter()
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+        };
+
+        let mut config = config().kubernetes_scheduler.clone();
+        config.resource_mode = ResourceMode::PerSlot;
+        config
+            .worker
+            .annotations
+            .insert("owner".to_string(), "arroyo".to_string());
+
+        let pod =
+            KubernetesScheduler::with_config(None, config).make_pod(&req, "program", 0, 4, None);
+
+        let spec = pod.spec.unwrap();
+        let requests = spec.containers[0]
+            .resources
+            .as_ref()
+            .unwrap()
+            .requests
+            .clone()
+            .unwrap();
+
+        // the pipeline's cpu request is scaled by the number of slots, while the configured
+        // memory request is kept
+        assert_eq!(requests.get("cpu").unwrap().0, "8");
+        assert!(requests.contains_key("memory"));
+
+        assert_eq!(
+            spec.node_selector.unwrap().get("pool").map(|s| s.as_str()),
+            Some("highmem")
+        );
+
+        let annotations = pod.metadata.annotations.unwrap();
+        assert_eq!(annotations.get("team").map(|s| s.as_str()), Some("data"));
+        assert_eq!(annotations.get("owner").map(|s| s.as_str()), Some("arroyo"));
+    }
+
+    #[test]
+    fn test_worker_group_placement() {
+        let toleration = |key: &str| -> Toleration {
+            serde_json::from_value(json!({
+                "key": key,
+                "operator": "Exists",
+                "effect": "NoSchedule"
+            }))
+            .unwrap()
+        };
+
+        let req = StartPipelineReq {
+            name: "test_pipeline".to_string(),
+            program: LogicalProgram::default(),
+            wasm_path: "file:///wasm".to_string(),
+            job_id: Arc::new("job123".to_string()),
+            hash: "12123123h".to_string(),
+            run_id: 1,
+            slots: 8,
+            class_slots: [("memory".to_string(), 2)].into_iter().collect(),
+            env_vars: Default::default(),
+            worker_pod: Some(WorkerPodConfig {
+                tolerations: Some(vec![toleration("dedicated")]),
+                topology_spread_constraints: Some(vec![serde_json::from_value(json!({
+                    "maxSkew": 1,
+                    "topologyKey": "topology.kubernetes.io/zone",
+                    "whenUnsatisfiable": "DoNotSchedule"
+                }))
+                .unwrap()]),
+                groups: Some(
+                    [(
+                        "memory".to_string(),
+                        WorkerGroupPlacement {
+                            node_selector: Some(
+                                [("pool".to_string(), "highmem".to_string())]
+                                    .into_iter()
+                                    .collect(),
+                            ),
+                            tolerations: Some(vec![toleration("highmem")]),
+                            requests: Some(
+                                [("cpu".to_string(), "2".to_string())].into_iter().collect(),
+                            ),
+                            annotations: Some(
+                                [("team".to_string(), "data".to_string())]
+                                    .into_iter()
+                                    .collect(),
+                            ),
+                            ..Default::default()
+                        },
+                    )]
+                    .into_iter()
+                    .collect(),
+                ),
+                ..Default::default()
+            }),
+        };
+
+        let mut config = config().kubernetes_scheduler.clone();
+        config.worker.groups.insert(
+            "memory".to_string(),
+            KubernetesWorkerGroupConfig {
+                annotations: [("tier".to_string(), "highmem".to_string())]
+                    .into_iter()
+                    .collect(),
+                resources: serde_json::from_value(json!({
+                    "requests": { "cpu": "1", "memory": "8Gi" }
+                }))
+                .unwrap(),
+                ..Default::default()
+            },
+        );
+        let scheduler = KubernetesScheduler::with_config(None, config);
+
+        let pod = scheduler.make_pod(&req, "program", 0, 2, Some("memory"));
+        let annotations = pod.metadata.annotations.unwrap();
+        assert_eq!(annotations.get("team").map(|s| s.as_str()), Some("data"));
+        assert_eq!(annotations.get("tier").map(|s| s.as_str()), Some("highmem"));
+
+        let spec = pod.spec.unwrap();
+
+        // the group's memory request comes from the config and its cpu request from the
+        // pipeline, both per slot
+        let requests = spec.containers[0]
+            .resources
+            .as_ref()
+            .unwrap()
+            .requests
+            .clone()
+            .unwrap();
+        assert_eq!(requests.get("cpu").unwrap().0, "4");
+        assert_eq!(requests.get("memory").unwrap().0, "16Gi");
+        assert_eq!(
+            spec.node_selector.unwrap().get("pool").map(|s| s.as_str()),
+            Some("highmem")
+        );
+        assert_eq!(spec.tolerations.unwrap().len(), 2);
+        assert!(spec.containers[0]
+            .env
+            .as_ref()
+            .unwrap()
+            .iter()
+            .any(|e| e.name == "ARROYO__WORKER__RESOURCE_CLASS"
+                && e.value.as_deref() == Some("memory")));
+
+        // the spread constraint is limited to the pods of this run
+        let constraints = spec.topology_spread_constraints.unwrap();
+        let labels = constraints[0]
+            .label_selector
+            .as_ref()
+            .unwrap()
+            .match_labels
+            .as_ref()
+            .unwrap();
+        assert_eq!(labels.get("job_id").map(|s| s.as_str()), Some("job123"));
+
+        let pod = scheduler.make_pod(&req, "program", 1, 6, None);
+        assert!(pod.metadata.annotations.unwrap_or_default().is_empty());
+
+        let spec = pod.spec.unwrap();
+        assert!(spec.node_selector.unwrap_or_default().get("pool").is_none());
+        let requests = spec.containers[0]
+            .resources
+            .as_ref()
+            .unwrap()
+            .requests
+            .clone()
+            .unwrap();
+        assert_eq!(requests.get("cpu").unwrap().0, "5400m");
+        assert_eq!(spec.tolerations.unwrap().len(), 1);
     }
 }
@@ -1,11 +1,14 @@
 use anyhow::bail;
 use arroyo_datastream::logical::LogicalProgram;
+use arroyo_rpc::api_types::pipelines::WorkerPodConfig;
 use arroyo_rpc::config::config;
 use arroyo_rpc::grpc::node_grpc_client::NodeGrpcClient;
 use arroyo_rpc::grpc::{
     api, HeartbeatNodeReq, RegisterNodeReq, StartWorkerReq, StopWorkerReq, StopWorkerStatus,
     WorkerFinishedReq,
 };
+use arroyo_rpc::Compressed;
+use arroyo_server_common::tls;
 use arroyo_types::{to_nanos, NodeId, WorkerId, ARROYO_PROGRAM_FILE_ENV, JOB_ID_ENV, RUN_ID_ENV};
 use base64::{engine::general_purpose, Engine as _};
 use lazy_static::lazy_static;
@@ -62,6 +65,14 @@ pub trait Scheduler: Send + Sync {
         job_id: &str,
         run_id: Option<i64>,
     ) -> anyhow::Result<Vec<WorkerId>>;
+    /// The number of task slots free for new workers, or None if the scheduler does not manage a
+    /// fixed pool of slots. Only the node scheduler does; the others start workers on demand and
+    /// never run out of slots, so jobs scheduled by them are never preempted (and the API rejects
+    /// pipeline priorities for them).
+    async fn free_slots(&self) -> Option<usize>;
+    /// Stops scheduling new workers onto a node that is being decommissioned. Schedulers that
+    /// don't manage nodes leave placement to their backend.
+    async fn drain_node(&self, _node_id: NodeId) {}
 }
 
 pub struct ProcessWorker {
@@ -93,7 +104,10 @@ pub struct StartPipelineReq {
     pub hash: String,
     pub run_id: i64,
     pub slots: usize,
+    /// How many of `slots` must be on workers of each resource class; the rest may be on any
+    pub class_slots: HashMap<String, usize>,
     pub env_vars: HashMap<String, String>,
+    pub worker_pod: Option<WorkerPodConfig>,
 }
 
 #[async_trait::async_trait]
@@ -226,6 +240,10 @@ impl Scheduler for ProcessScheduler {
             .collect())
     }
 
+    async fn free_slots(&self) -> Option<usize> {
+        None
+    }
+
     async fn stop_workers(
         &self,
         job_id: &str,
@@ -255,10 +273,12 @@ struct NodeStatus {
     scheduled_slots: HashMap<WorkerId, usize>,
     addr: String,
     last_heartbeat: Instant,
+    draining: bool,
+    resource_class: Option<String>,
 }
 
 impl NodeStatus {
-    fn new(id: NodeId, slots: usize, addr: String) -> NodeStatus {
+    fn new(id: NodeId, slots: usize, addr: String, resource_class: Option<String>) -> NodeStatus {
         FREE_SLOTS.add(slots as f64);
         REGISTERED_SLOTS.add(slots as f64);
 
@@ -268,9 +288,15 @@ impl NodeStatus {
             scheduled_slots: HashMap::new(),
             addr,
             last_heartbeat: Instant::now(),
+            draining: false,
+            resource_class,
         }
     }
 
+    fn schedulable(&self) -> bool {
+        !self.draining && self.last_heartbeat.elapsed() < Duration::from_secs(30)
+    }
+
     fn take_slots(&mut self, worker: WorkerId, slots: usize) {
         if let Some(v) = self.free_slots.checked_sub(slots) {
             FREE_SLOTS.sub(slots as f64);
@@ -385,7 +411,10 @@ impl NodeScheduler {
             worker_id = worker_id.0
         );
 
-        let Ok(mut client) = NodeGrpcClient::connect(format!("http://{}", node.addr)).await else {
+        let Ok(mut client) = tls::connect_grpc(format!("http://{}", node.addr))
+            .await
+            .map(|channel| NodeGrpcClient::new(channel).compressed())
+        else {
             warn!("Failed to connect to worker to stop; this likely means it is dead");
             return Ok(Some(worker_id));
         };
@@ -422,6 +451,7 @@ impl Scheduler for NodeScheduler {
                 NodeId(req.node_id),
                 req.task_slots as usize,
                 req.addr,
+                req.resource_class,
             ));
         }
     }
@@ -482,6 +512,34 @@ impl Scheduler for NodeScheduler {
             .collect())
     }
 
+    async fn free_slots(&self) -> Option<usize> {
+        let state = self.state.lock().await;
+        Some(
+            state
+                .nodes
+                .values()
+                .filter(|n| n.schedulable())
+                .map(|n| n.free_slots)
+                .sum(),
+        )
+    }
+
+    async fn drain_node(&self, node_id: NodeId) {
+        let mut state = self.state.lock().await;
+        if let Some(node) = state.nodes.get_mut(&node_id) {
+            if !node.draining {
+                info!(
+                    message = "draining node",
+                    node_id = node_id.0,
+                    addr = node.addr
+                );
+                node.draining = true;
+            }
+        } else {
+            warn!("Got drain request for unknown node {}", node_id.0);
+        }
+    }
+
     #[allow(unreachable_code, unused)]
     async fn start_workers(
         &self,
@@ -492,7 +550,12 @@ impl Scheduler for NodeScheduler {
 
         state.expire_nodes(Instant::now() - Duration::from_secs(30));
 
-        let free_slots = state.nodes.values().map(|n| n.free_slots).sum::<usize>();
+        let free_slots = state
+            .nodes
+            .values()
+            .filter(|n| !n.draining)
+            .map(|n| n.free_slots)
+            .sum::<usize>();
         let slots = start_pipeline_req.slots;
         if slots > free_slots {
             return Err(SchedulerError::NotEnoughSlots {
@@ -500,103 +563,133 @@ impl Scheduler for NodeScheduler {
             });
         }
 
-        let mut to_schedule = slots;
+        let mut demands: Vec<_> = start_pipeline_req
+            .class_slots
+            .iter()
+            .map(|(class, slots)| (Some(class.clone()), *slots))
+            .collect();
+        let class_slots: usize = demands.iter().map(|(_, slots)| slots).sum();
+
+        for (class, slots) in &demands {
+            let free_slots = state
+                .nodes
+                .values()
+                .filter(|n| !n.draining && n.resource_class == *class)
+                .map(|n| n.free_slots)
+                .sum::<usize>();
+            if *slots > free_slots {
+                return Err(SchedulerError::NotEnoughSlots {
+                    slots_needed: slots - free_slots,
+                });
+            }
+        }
+
+        demands.push((None, slots.saturating_sub(class_slots)));
+
         let mut slots_assigned = vec![];
-        while to_schedule > 0 {
-            // find the node with the most free slots and fill it
-            let node = {
-                if let Some(status) = state
-                    .nodes
-                    .values()
-                    .filter(|n| {
-                        n.free_slots > 0 && n.last_heartbeat.elapsed() < Duration::from_secs(30)
-                    })
-                    .max_by_key(|n| n.free_slots)
-                    .cloned()
-                {
-                    status
-                } else {
-                    unreachable!();
-                }
-            };
+        for (resource_class, slots) in demands {
+            let mut to_schedule = slots;
+            while to_schedule > 0 {
+                // find the node with the most free slots and fill it; slots that may be of any
+                // class go to nodes without one first, leaving the specialized nodes for the
+                // operators that need them
+                let node = {
+                    if let Some(status) = state
+                        .nodes
+                        .values()
+                        .filter(|n| {
+                            n.free_slots > 0
+                                && n.schedulable()
+                                && (resource_class.is_none() || n.resource_class == resource_class)
+                        })
+                        .max_by_key(|n| (n.resource_class.is_none(), n.free_slots))
+                        .cloned()
+                    {
+                        status
+                    } else {
+                        unreachable!();
+                    }
+                };
 
-            let slots_for_this_one = node.free_slots.min(to_schedule);
-            info!(
-                "Scheduling {} slots on node {}",
-                slots_for_this_one, node.addr
-            );
+                let slots_for_this_one = node.free_slots.min(to_schedule);
+                info!(
+                    "Scheduling {} slots on node {}",
+                    slots_for_this_one, node.addr
+                );
 
-            let mut client = NodeGrpcClient::connect(format!("http://{}", node.addr))
-                .await
-                // TODO: handle this issue more gracefully by moving trying other nodes
-                .map_err(|e| {
-                    // release back slots already scheduled.
-                    slots_assigned
-                        .iter()
-                        .for_each(|(node_id, worker_id, slots)| {
-                            state
-                                .nodes
-                                .get_mut(node_id)
-                                .unwrap()
-                                .release_slots(*worker_id, *slots);
-                        });
-                    SchedulerError::Other(format!(
-                        "Failed to connect to node {}: {:?}",
-                        node.addr, e
-                    ))
-                })?;
-
-            let req = StartWorkerReq {
-                name: start_pipeline_req.name.clone(),
-                job_id: (*start_pipeline_req.job_id).clone(),
-                slots: slots_for_this_one as u64,
-                node_id: node.id.0,
-                run_id: start_pipeline_req.run_id as u64,
-                env_vars: start_pipeline_req.env_vars.clone(),
-                program: api::ArrowProgram::from(start_pipeline_req.program.clone())
-                    .encode_to_vec(),
-            };
+                let mut client = tls::connect_grpc(format!("http://{}", node.addr))
+                    .await
+                    .map(|channel| NodeGrpcClient::new(channel).compressed())
+                    // TODO: handle this issue more gracefully by moving trying other nodes
+                    .map_err(|e| {
+                        // release back slots already scheduled.
+                        slots_assigned
+                            .iter()
+                            .for_each(|(node_id, worker_id, slots)| {
+                                state
+                                    .nodes
+                                    .get_mut(node_id)
+                                    .unwrap()
+                                    .release_slots(*worker_id, *slots);
+                            });
+                        SchedulerError::Other(format!(
+                            "Failed to connect to node {}: {:?}",
+                            node.addr, e
+                        ))
+                    })?;
+
+                let req = StartWorkerReq {
+                    name: start_pipeline_req.name.clone(),
+                    job_id: (*start_pipeline_req.job_id).clone(),
+                    slots: slots_for_this_one as u64,
+                    node_id: node.id.0,
+                    run_id: start_pipeline_req.run_id as u64,
+                    env_vars: start_pipeline_req.env_vars.clone(),
+                    program: api::ArrowProgram::from(start_pipeline_req.program.clone())
+                        .encode_to_vec(),
+                };
 
-            let res = client
-                .start_worker(Request::new(req))
-                .await
-                .map_err(|e| {
-                    // release back slots already scheduled.
-                    slots_assigned
-                        .iter()
-                        .for_each(|(node_id, worker_id, slots)| {
-                            state
-                                .nodes
-                                .get_mut(node_id)
-                                .unwrap()
-                                .release_slots(*worker_id, *slots);
-                        });
-                    SchedulerError::Other(format!(
-                        "Failed to start worker on node {}: {:?}",
-                        node.addr, e
-                    ))
-                })?
-                .into_inner();
+                let res = client
+                    .start_worker(Request::new(req))
+                    .await
+                    .map_err(|e| {
+                        // release back slots already scheduled.
+                        slots_assigned
+                            .iter()
+                            .for_each(|(node_id, worker_id, slots)| {
+                                state
+                                    .nodes
+                                    .get_mut(node_id)
+                                    .unwrap()
+                                    .release_slots(*worker_id, *slots);
+                            });
+                        SchedulerError::Other(format!(
+                            "Failed to start worker on node {}: {:?}",
+                            node.addr, e
+                        ))
+                    })?
+                    .into_inner();
+
+                state
+                    .nodes
+                    .get_mut(&node.id)
+                    .unwrap()
+                    .take_slots(WorkerId(res.worker_id), slots_for_this_one);
 
-            state
-                .nodes
-                .get_mut(&node.id)
-                .unwrap()
-                .take_slots(WorkerId(res.worker_id), slots_for_this_one);
-
-            state.workers.insert(
-                WorkerId(res.worker_id),
-                NodeWorker {
-                    job_id: start_pipeline_req.job_id.clone(),
-                    run_id: start_pipeline_req.run_id,
-                    node_id: node.id,
-                    running: true,
-                },
-            );
+                state.workers.insert(
+                    WorkerId(res.worker_id),
+                    NodeWorker {
+                        job_id: start_pipeline_req.job_id.clone(),
+                        run_id: start_pipeline_req.run_id,
+                        node_id: node.id,
+                        running: true,
+                    },
+                );
 
-            slots_assigned.push((node.id, WorkerId(res.worker_id), slots_for_this_one));
+                slots_assigned.push((node.id, WorkerId(res.worker_id), slots_for_this_one));
 
-            to_schedule -= slots_for_this_one;
+                to_schedule -= slots_for_this_one;
+            }
         }
         Ok(())
     }
@@ -631,3 +724,46 @@ impl Scheduler for NodeScheduler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn register(scheduler: &NodeScheduler, node_id: u64, task_slots: u64) {
+        scheduler
+            .register_node(RegisterNodeReq {
+                node_id,
+                task_slots,
+                addr: format!("localhost:{}", 9000 + node_id),
+                resource_class: None,
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_drain_node() {
+        let scheduler = NodeScheduler::new();
+        register(&scheduler, 1, 4).await;
+        register(&scheduler, 2, 8).await;
+        assert_eq!(scheduler.free_slots().await, Some(12));
+
+        // a draining node's slots can't be used by new workers
+        scheduler.drain_node(NodeId(2)).await;
+        assert_eq!(scheduler.free_slots().await, Some(4));
+
+        // draining is idempotent, and unknown nodes are ignored
+        scheduler.drain_node(NodeId(2)).await;
+        scheduler.drain_node(NodeId(3)).await;
+        assert_eq!(scheduler.free_slots().await, Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_draining_node_not_schedulable() {
+        let scheduler = NodeScheduler::new();
+        register(&scheduler, 1, 4).await;
+        scheduler.drain_node(NodeId(1)).await;
+
+        let state = scheduler.state.lock().await;
+        assert!(!state.nodes[&NodeId(1)].schedulable());
+    }
+}
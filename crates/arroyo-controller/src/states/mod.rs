@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
 use std::time::{Duration, Instant};
 use std::{fmt::Debug, sync::Arc};
 
@@ -17,10 +17,14 @@ use anyhow::{anyhow, Result};
 use cornucopia_async::DatabaseSource;
 
 use crate::job_controller::JobController;
+use crate::notifications::{self, Notification};
+use crate::preemption::SlotAllocations;
 use crate::queries::controller_queries;
+use crate::quotas::NamespaceSlots;
 use crate::types::public::StopMode;
 use crate::{schedulers::Scheduler, JobConfig, JobMessage, JobStatus};
-use arroyo_datastream::logical::LogicalProgram;
+use arroyo_datastream::logical::{LogicalProgram, ProgramConfig};
+use arroyo_rpc::api_types::notifications::NotificationEvent;
 use arroyo_rpc::config::config;
 use arroyo_server_common::shutdown::ShutdownGuard;
 use prost::Message;
@@ -81,12 +85,23 @@ impl State for Created {
         "Created"
     }
 
-    async fn next(self: Box<Self>, _: &mut JobContext) -> Result<Transition, StateError> {
+    async fn next(self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
+        // jobs may be created stopped, in which case they don't run until they're started
+        if ctx.config.stop_mode != StopMode::none {
+            return Ok(Transition::next(*self, Stopped {}));
+        }
+
         Ok(Transition::next(*self, Compiling))
     }
 }
 
 async fn handle_terminal<'a>(ctx: &mut JobContext<'a>) {
+    ctx.namespace_slots
+        .lock()
+        .unwrap()
+        .release(&ctx.config.namespace, &ctx.config.id);
+    ctx.slot_allocations.lock().unwrap().release(&ctx.config.id);
+
     if let Err(e) = ctx
         .scheduler
         .stop_workers(&ctx.config.id, Some(ctx.status.run_id), true)
@@ -134,7 +149,14 @@ impl State for Finished {
 
     async fn next(self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
         handle_terminal(ctx).await;
-        Ok(Transition::Stop)
+        if ctx.config.restart_nonce != ctx.status.restart_nonce
+            && ctx.config.stop_mode == StopMode::none
+        {
+            // a new run has been requested (e.g., by a schedule)
+            Ok(Transition::next(*self, Compiling {}))
+        } else {
+            Ok(Transition::Stop)
+        }
     }
 
     fn is_terminal(&self) -> bool {
@@ -168,8 +190,27 @@ impl State for Stopped {
 
 // State transitions
 impl TransitionTo<Compiling> for Created {}
+impl TransitionTo<Stopped> for Created {}
 
-impl TransitionTo<Compiling> for Stopped {}
+impl TransitionTo<Compiling> for Stopped {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(|ctx| {
+            // starting the job satisfies any restart that was requested while it was stopped
+            ctx.status.restart_nonce = ctx.config.restart_nonce;
+        })
+    }
+}
+
+impl TransitionTo<Compiling> for Finished {
+    fn update_status(&self) -> TransitionFn {
+        Box::new(|ctx| {
+            ctx.status.restart_nonce = ctx.config.restart_nonce;
+            ctx.status.restarts = 0;
+            ctx.status.failure_message = None;
+            ctx.status.failure_category = None;
+        })
+    }
+}
 
 impl TransitionTo<Compiling> for Scheduling {}
 
@@ -224,6 +265,7 @@ impl TransitionTo<Compiling> for Failed {
             ctx.status.restart_nonce = ctx.config.restart_nonce;
             ctx.status.restarts = 0;
             ctx.status.failure_message = None;
+            ctx.status.failure_category = None;
         })
     }
 }
@@ -363,6 +405,15 @@ pub struct JobContext<'a> {
     job_controller: Option<JobController>,
     last_transitioned_at: Instant,
     metrics: Arc<tokio::sync::RwLock<HashMap<Arc<String>, JobMetrics>>>,
+    namespace_slots: Arc<Mutex<NamespaceSlots>>,
+    slot_allocations: Arc<Mutex<SlotAllocations>>,
+    // the UDF reload nonce as of when the program's UDFs were last loaded
+    udf_reload_nonce: i32,
+    // whether the job has failed since it was last running, so that targets are notified when it
+    // recovers
+    recovering: bool,
+    // the number of times in a row the job has failed because of checkpoint errors
+    checkpoint_failures: u32,
 }
 
 impl<'a> JobContext<'a> {
@@ -371,6 +422,37 @@ impl<'a> JobContext<'a> {
         Ok(())
     }
 
+    /// If the pipeline's UDFs have been updated since the program was loaded, reloads them from
+    /// the database and returns the new UDF config
+    pub async fn refresh_udfs(
+        &mut self,
+        udf_reload_nonce: i32,
+    ) -> anyhow::Result<Option<ProgramConfig>> {
+        if udf_reload_nonce == self.udf_reload_nonce {
+            return Ok(None);
+        }
+
+        let program = StateMachine::get_program(&self.db, &self.config.id, self.config.pipeline_id)
+            .await?
+            .ok_or_else(|| anyhow!("could not load updated program"))?;
+
+        info!(
+            message = "loaded updated UDFs",
+            job_id = *self.config.id,
+            udfs = program.program_config.udf_dylibs.len()
+        );
+
+        self.program.program_config = program.program_config.clone();
+        self.udf_reload_nonce = udf_reload_nonce;
+
+        Ok(Some(program.program_config))
+    }
+
+    /// Sends a notification about the job to its notification targets
+    pub fn notify(&self, notification: Notification) {
+        notifications::notify(&self.db, &self.config, notification);
+    }
+
     pub fn retryable(
         &self,
         state: Box<dyn State>,
@@ -431,102 +513,117 @@ async fn execute_state<'a>(
 ) -> (Option<Box<dyn State>>, JobContext<'a>) {
     let state_name = state.name();
 
-    let next: Option<Box<dyn State>> = match state.next(&mut ctx).await {
-        Ok(Transition::Advance(s)) => {
-            info!(
-                message = "state transition",
-                job_id = *ctx.config.id,
-                from = state_name,
-                to = s.state.name(),
-                duration_ms = ctx.last_transitioned_at.elapsed().as_millis()
-            );
-
-            log_event(
-                "state_transition",
-                json!({
-                    "service": "controller",
-                    "job_id": ctx.config.id,
-                    "from": state_name,
-                    "to": s.state.name(),
-                    "scheduler": &config().controller.scheduler,
-                    "duration_ms": ctx.last_transitioned_at.elapsed().as_millis() as u64,
-                }),
-            );
-
-            (s.update_fn)(&mut ctx);
-            ctx.retries_attempted = 0;
-            ctx.last_transitioned_at = Instant::now();
-
-            Some(s.state)
-        }
-        Ok(Transition::Stop) => None,
-        Err(StateError::FatalError { message, source })
-        | Err(StateError::RetryableError {
-            message,
-            source,
-            retries: 1,
-            ..
-        })
-        | Err(StateError::RetryableError {
-            message,
-            source,
-            retries: 0,
-            ..
-        }) => {
-            error!(
-                message = "fatal state error",
-                job_id = *ctx.config.id,
-                state = state_name,
-                error_message = message,
-                error = format!("{:?}", source)
-            );
-            log_event(
-                "fatal_state_error",
-                json!({
-                    "service": "controller",
-                    "job_id": ctx.config.id,
-                    "state": state_name,
-                    "error_message": message,
-                    "error": format!("{:?}", source),
-                    "retries": 0,
-                }),
-            );
-            ctx.status.failure_message = Some(message);
-            ctx.status.finish_time = Some(OffsetDateTime::now_utc());
-            let s: Box<dyn State> = Box::new(Failed {});
-            Some(s)
-        }
-        Err(StateError::RetryableError {
-            state,
-            message,
-            source,
-            retries,
-        }) => {
-            error!(
-                message = "retryable state error",
-                job_id = *ctx.config.id,
-                state = state_name,
-                error_message = message,
-                error = format!("{:?}", source),
+    let next: Option<Box<dyn State>> =
+        match state.next(&mut ctx).await {
+            Ok(Transition::Advance(s)) => {
+                info!(
+                    message = "state transition",
+                    job_id = *ctx.config.id,
+                    from = state_name,
+                    to = s.state.name(),
+                    duration_ms = ctx.last_transitioned_at.elapsed().as_millis()
+                );
+
+                log_event(
+                    "state_transition",
+                    json!({
+                        "service": "controller",
+                        "job_id": ctx.config.id,
+                        "from": state_name,
+                        "to": s.state.name(),
+                        "scheduler": &config().controller.scheduler,
+                        "duration_ms": ctx.last_transitioned_at.elapsed().as_millis() as u64,
+                    }),
+                );
+
+                if ctx.recovering && s.state.name() == "Running" {
+                    ctx.recovering = false;
+                    ctx.notify(Notification::new(
+                        NotificationEvent::JobRecovered,
+                        "Running",
+                        "Job is running again",
+                    ));
+                }
+
+                (s.update_fn)(&mut ctx);
+                ctx.retries_attempted = 0;
+                ctx.last_transitioned_at = Instant::now();
+
+                Some(s.state)
+            }
+            Ok(Transition::Stop) => None,
+            Err(StateError::FatalError { message, source })
+            | Err(StateError::RetryableError {
+                message,
+                source,
+                retries: 1,
+                ..
+            })
+            | Err(StateError::RetryableError {
+                message,
+                source,
+                retries: 0,
+                ..
+            }) => {
+                error!(
+                    message = "fatal state error",
+                    job_id = *ctx.config.id,
+                    state = state_name,
+                    error_message = message,
+                    error = format!("{:?}", source)
+                );
+                log_event(
+                    "fatal_state_error",
+                    json!({
+                        "service": "controller",
+                        "job_id": ctx.config.id,
+                        "state": state_name,
+                        "error_message": message,
+                        "error": format!("{:?}", source),
+                        "retries": 0,
+                    }),
+                );
+                ctx.recovering = true;
+                ctx.notify(
+                    Notification::new(NotificationEvent::JobFailed, "Failed", "Job failed")
+                        .with_error(&message, ctx.status.failure_category.clone(), &source),
+                );
+                ctx.status.failure_message = Some(message);
+                ctx.status.finish_time = Some(OffsetDateTime::now_utc());
+                let s: Box<dyn State> = Box::new(Failed {});
+                Some(s)
+            }
+            Err(StateError::RetryableError {
+                state,
+                message,
+                source,
                 retries,
-            );
-            log_event(
-                "state_error",
-                json!({
-                    "service": "controller",
-                    "job_id": ctx.config.id,
-                    "state": state_name,
-                    "error_message": message,
-                    "error": format!("{:?}", source),
-                    "retries": retries,
-                }),
-            );
-
-            tokio::time::sleep(Duration::from_millis(500)).await;
-            ctx.retries_attempted += 1;
-            Some(state)
-        }
-    };
+            }) => {
+                error!(
+                    message = "retryable state error",
+                    job_id = *ctx.config.id,
+                    state = state_name,
+                    error_message = message,
+                    error = format!("{:?}", source),
+                    retries,
+                );
+                log_event(
+                    "state_error",
+                    json!({
+                        "service": "controller",
+                        "job_id": ctx.config.id,
+                        "state": state_name,
+                        "error_message": message,
+                        "error": format!("{:?}", source),
+                        "retries": retries,
+                    }),
+                );
+
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                ctx.retries_attempted += 1;
+                Some(state)
+            }
+        };
 
     if let Some(s) = &next {
         ctx.status.state = s.name().to_string();
@@ -549,7 +646,11 @@ pub async fn run_to_completion(
     mut rx: Receiver<JobMessage>,
     scheduler: Arc<dyn Scheduler>,
     metrics: Arc<tokio::sync::RwLock<HashMap<Arc<String>, JobMetrics>>>,
+    namespace_slots: Arc<Mutex<NamespaceSlots>>,
+    slot_allocations: Arc<Mutex<SlotAllocations>>,
 ) {
+    let udf_reload_nonce = config.read().unwrap().udf_reload_nonce;
+    let recovering = status.state == "Failed" || status.state == "Recovering";
     let mut ctx = JobContext {
         config: config.read().unwrap().clone(),
         status: &mut status,
@@ -561,6 +662,11 @@ pub async fn run_to_completion(
         job_controller: None,
         last_transitioned_at: Instant::now(),
         metrics,
+        namespace_slots,
+        slot_allocations,
+        udf_reload_nonce,
+        recovering,
+        checkpoint_failures: 0,
     };
 
     loop {
@@ -582,6 +688,8 @@ pub struct StateMachine {
     metrics: Arc<tokio::sync::RwLock<HashMap<Arc<String>, JobMetrics>>>,
     db: DatabaseSource,
     scheduler: Arc<dyn Scheduler>,
+    namespace_slots: Arc<Mutex<NamespaceSlots>>,
+    slot_allocations: Arc<Mutex<SlotAllocations>>,
 }
 
 impl StateMachine {
@@ -592,6 +700,8 @@ impl StateMachine {
         scheduler: Arc<dyn Scheduler>,
         shutdown_guard: ShutdownGuard,
         metrics: Arc<tokio::sync::RwLock<HashMap<Arc<String>, JobMetrics>>>,
+        namespace_slots: Arc<Mutex<NamespaceSlots>>,
+        slot_allocations: Arc<Mutex<SlotAllocations>>,
     ) -> Self {
         let mut this = Self {
             tx: None,
@@ -599,6 +709,8 @@ impl StateMachine {
             metrics,
             db,
             scheduler,
+            namespace_slots,
+            slot_allocations,
         };
 
         this.start(status, shutdown_guard).await;
@@ -680,6 +792,8 @@ impl StateMachine {
                 let db = self.db.clone();
                 let scheduler = self.scheduler.clone();
                 let metrics = self.metrics.clone();
+                let namespace_slots = self.namespace_slots.clone();
+                let slot_allocations = self.slot_allocations.clone();
                 let pipeline_id = config.read().unwrap().pipeline_id;
                 match Self::get_program(&db, &status.id, pipeline_id).await {
                     Ok(Some(program)) => {
@@ -695,6 +809,8 @@ impl StateMachine {
                                 rx,
                                 scheduler,
                                 metrics,
+                                namespace_slots,
+                                slot_allocations,
                             )
                             .await;
                             info!(message = "finished state machine", job_id = *id);
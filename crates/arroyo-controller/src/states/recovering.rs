@@ -6,9 +6,14 @@ use tokio::time::timeout;
 use tracing::{info, warn};
 
 use super::{compiling::Compiling, JobContext, State, StateError, Transition};
+use crate::types::public::StopMode as SqlStopMode;
+use crate::JobMessage;
 
 #[derive(Debug)]
-pub struct Recovering {}
+pub struct Recovering {
+    /// How long the restart strategy waits before restarting the job
+    pub delay: Duration,
+}
 
 impl Recovering {
     // tries, with increasing levels of force, to tear down the existing cluster
@@ -96,6 +101,36 @@ impl State for Recovering {
             return Err(ctx.retryable(self, "failed to tear down existing cluster", e, 10));
         }
 
+        if !self.delay.is_zero() {
+            info!(
+                message = "waiting before restarting job",
+                job_id = *ctx.config.id,
+                delay_ms = self.delay.as_millis() as u64
+            );
+
+            let wait = tokio::time::sleep(self.delay);
+            tokio::pin!(wait);
+
+            loop {
+                tokio::select! {
+                    _ = &mut wait => break,
+                    msg = ctx.rx.recv() => match msg {
+                        // a stop or restart request ends the wait; it's then handled as the job
+                        // is rescheduled
+                        Some(JobMessage::ConfigUpdate(c)) => {
+                            if c.stop_mode != SqlStopMode::none
+                                || c.restart_nonce != ctx.status.restart_nonce
+                            {
+                                break;
+                            }
+                        }
+                        Some(msg) => ctx.handle(msg)?,
+                        None => panic!("job queue shut down"),
+                    }
+                }
+            }
+        }
+
         Ok(Transition::next(*self, Compiling))
     }
 }
@@ -1,10 +1,14 @@
 use std::time::{Duration, Instant};
 
+use anyhow::anyhow;
+
 use time::OffsetDateTime;
 use tokio::time::MissedTickBehavior;
 
-use tracing::error;
+use tracing::{error, info, warn};
 
+use crate::notifications::Notification;
+use crate::restarts::{self, RestartDecision};
 use crate::states::finishing::Finishing;
 use crate::states::recovering::Recovering;
 use crate::states::rescaling::Rescaling;
@@ -12,6 +16,8 @@ use crate::states::restarting::Restarting;
 use crate::states::{fatal, stop_if_desired_running};
 use crate::JobMessage;
 use crate::{job_controller::ControllerProgress, states::StateError};
+use arroyo_rpc::api_types::notifications::NotificationEvent;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::config::config;
 use arroyo_server_common::log_event;
 use serde_json::json;
@@ -59,6 +65,13 @@ impl State for Running {
                                 }));
                             }
 
+                            let updated_udfs = match ctx.refresh_udfs(c.udf_reload_nonce).await {
+                                Ok(udfs) => udfs,
+                                Err(e) => {
+                                    return Err(ctx.retryable(self, "failed to load updated UDFs", e, 10));
+                                }
+                            };
+
                             let job_controller = ctx.job_controller.as_mut().unwrap();
 
                             for (op, p) in &c.parallelism_overrides {
@@ -72,8 +85,32 @@ impl State for Running {
                                 }
                             }
 
+                            if let Some(udfs) = updated_udfs {
+                                job_controller.reload_udfs(udfs);
+                            }
+
                             job_controller.update_config(c);
                         }
+                        Some(JobMessage::TaskStarted { .. }) => {
+                            // tasks of a region that was restarted on its own
+                        }
+                        Some(JobMessage::WorkerDraining { worker_id }) => {
+                            // tasks can't be moved between workers individually, so the whole
+                            // job is stopped with a final checkpoint and rescheduled onto new
+                            // workers; the draining worker's node no longer takes new workers
+                            info!(
+                                message = "worker is draining; rescheduling job onto new workers",
+                                job_id = *ctx.config.id,
+                                worker_id = worker_id.0
+                            );
+                            return Ok(Transition::next(
+                                *self,
+                                Rescaling {}
+                            ));
+                        }
+                        Some(JobMessage::TriggerSavepoint) => {
+                            ctx.job_controller.as_mut().unwrap().request_savepoint();
+                        }
                         Some(JobMessage::RunningMessage(msg)) => {
                             if let Err(e) = ctx.job_controller.as_mut().unwrap().handle_message(msg).await {
                                 return Err(ctx.retryable(self, "job encountered an error", e, 10));
@@ -109,6 +146,13 @@ impl State for Running {
                                 Finishing {}
                             ))
                         },
+                        Ok(ControllerProgress::StateQuotaExceeded { bytes, limit }) => {
+                            ctx.status.failure_category = Some(ErrorCategory::ResourceExhaustion.to_string());
+                            return Err(fatal(
+                                format!("Job state ({} bytes) exceeds the namespace's state size quota of {} bytes", bytes, limit),
+                                anyhow!("namespace state size quota exceeded for namespace '{}'", ctx.config.namespace)
+                            ));
+                        },
                         Err(err) => {
                             error!(message = "error while running", error = format!("{:?}", err), job_id = *ctx.config.id);
                             log_event("running_error", json!({
@@ -116,15 +160,63 @@ impl State for Running {
                                 "job_id": ctx.config.id,
                                 "error": format!("{:?}", err),
                             }));
-                            if pipeline_config.allowed_restarts != -1 && ctx.status.restarts >= pipeline_config.allowed_restarts {
-                                return Err(fatal(
-                                    "Job has restarted too many times",
-                                    err
-                                ));
+
+                            let job_controller = ctx.job_controller.as_ref().unwrap();
+                            let category = job_controller.failure_category();
+                            if job_controller.completed_checkpoints() > 0 {
+                                ctx.checkpoint_failures = 0;
+                            }
+                            if category == Some(ErrorCategory::Checkpoint) {
+                                ctx.checkpoint_failures += 1;
+                                if ctx.checkpoint_failures == config().controller.checkpoint_failure_alert_threshold {
+                                    let mut notification = Notification::new(
+                                        NotificationEvent::CheckpointFailures,
+                                        "Running",
+                                        format!("Checkpointing failed {} times in a row", ctx.checkpoint_failures),
+                                    ).with_error(err.to_string(), category.map(|c| c.to_string()), &err);
+                                    notification.checkpoint_failures = Some(ctx.checkpoint_failures);
+                                    ctx.notify(notification);
+                                }
+                            }
+
+                            let strategy = ctx.config.restart_strategy.as_ref();
+                            let recent_restarts = match restarts::recent_restarts(&ctx.db, &ctx.config.id, strategy).await {
+                                Ok(recent) => recent,
+                                Err(e) => {
+                                    return Err(ctx.retryable(self, "failed to load restart history", e, 10));
+                                }
+                            };
+
+                            let delay = match restarts::decide(strategy, ctx.status.restarts.max(0) as u32, recent_restarts) {
+                                RestartDecision::Restart { delay } => delay,
+                                RestartDecision::Fail(message) => {
+                                    ctx.status.failure_category = category.map(|c| c.to_string());
+                                    return Err(fatal(message, err));
+                                }
+                            };
+
+                            if let Err(e) = restarts::record_restart(
+                                &ctx.db,
+                                &ctx.config.id,
+                                ctx.status.run_id,
+                                ctx.status.restarts + 1,
+                                delay,
+                                &format!("{:#}", err),
+                            ).await {
+                                // the history is informational, so this shouldn't hold up recovery
+                                warn!(message = "failed to record restart", error = format!("{:?}", e),
+                                    job_id = *ctx.config.id);
                             }
+
+                            ctx.recovering = true;
+                            ctx.notify(
+                                Notification::new(NotificationEvent::JobFailed, "Recovering", "Job failed and is being restarted")
+                                    .with_error(err.to_string(), category.map(|c| c.to_string()), &err),
+                            );
+
                             return Ok(Transition::next(
                                 *self,
-                                Recovering {}
+                                Recovering { delay }
                             ))
                         }
                     }
@@ -10,9 +10,12 @@ use tokio::{select, sync::Mutex, task::JoinHandle};
 use tonic::{transport::Channel, Request};
 use tracing::{error, info, warn};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use arroyo_datastream::logical::LogicalProgram;
+use arroyo_rpc::api_types::notifications::NotificationEvent;
 use arroyo_rpc::config::config;
+use arroyo_rpc::Compressed;
+use arroyo_server_common::tls;
 use arroyo_state::{
     committing_state::CommittingState,
     tables::{global_keyed_map::GlobalKeyedTable, ErasedTable},
@@ -20,6 +23,9 @@ use arroyo_state::{
 };
 
 use crate::job_controller::job_metrics::JobMetrics;
+use crate::notifications::Notification;
+use crate::preemption;
+use crate::quotas::ReserveResult;
 use crate::{
     job_controller::JobController, queries::controller_queries, states::stop_if_desired_non_running,
 };
@@ -36,25 +42,72 @@ struct WorkerStatus {
     id: WorkerId,
     data_address: String,
     slots: usize,
+    resource_class: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Scheduling {}
 
+/// The task slots the job needs on workers of each resource class. Each slot runs one subtask of
+/// every operator placed on it, so a class needs as many slots as its most parallel operator.
+fn class_slots_for_job(job: &LogicalProgram) -> HashMap<String, usize> {
+    let mut class_slots: HashMap<String, usize> = HashMap::new();
+    for node in job.graph.node_weights() {
+        if let Some(class) = job.program_config.resource_classes.get(&node.operator_id) {
+            let slots = class_slots.entry(class.clone()).or_default();
+            *slots = (*slots).max(node.parallelism);
+        }
+    }
+    class_slots
+}
+
 fn slots_for_job(job: &LogicalProgram) -> usize {
+    let class_slots: usize = class_slots_for_job(job).values().sum();
+
+    // operators without a resource class can also run in the slots of those with one
     job.graph
         .node_weights()
+        .filter(|n| {
+            !job.program_config
+                .resource_classes
+                .contains_key(&n.operator_id)
+        })
         .map(|n| n.parallelism)
         .max()
         .unwrap_or(0)
+        .max(class_slots)
 }
 
 fn compute_assignments(
     workers: Vec<&WorkerStatus>,
     program: &LogicalProgram,
-) -> Vec<TaskAssignment> {
+) -> anyhow::Result<Vec<TaskAssignment>> {
     let mut assignments = vec![];
     for node in program.graph.node_weights() {
+        let class = program
+            .program_config
+            .resource_classes
+            .get(&node.operator_id);
+
+        // operators with a resource class may only run on workers of that class, while the rest
+        // prefer workers without one
+        let mut candidates: Vec<_> = workers
+            .iter()
+            .filter(|w| class.is_none() || w.resource_class.as_ref() == class)
+            .collect();
+        candidates.sort_by_key(|w| w.resource_class.is_some());
+
+        let available: usize = candidates.iter().map(|w| w.slots).sum();
+        if available < node.parallelism {
+            bail!(
+                "operator {} needs {} slots on workers of resource class '{}', but only {} are available",
+                node.operator_id,
+                node.parallelism,
+                class.map(|c| c.as_str()).unwrap_or("any"),
+                available
+            );
+        }
+
         let mut worker_idx = 0;
         let mut current_count = 0;
 
@@ -62,19 +115,19 @@ fn compute_assignments(
             assignments.push(TaskAssignment {
                 operator_id: node.operator_id.clone(),
                 operator_subtask: i as u64,
-                worker_id: workers[worker_idx].id.0,
-                worker_addr: workers[worker_idx].data_address.clone(),
+                worker_id: candidates[worker_idx].id.0,
+                worker_addr: candidates[worker_idx].data_address.clone(),
             });
             current_count += 1;
 
-            if current_count == workers[worker_idx].slots {
+            if current_count == candidates[worker_idx].slots {
                 worker_idx += 1;
                 current_count = 0;
             }
         }
     }
 
-    assignments
+    Ok(assignments)
 }
 
 async fn handle_worker_connect<'a>(
@@ -90,6 +143,7 @@ async fn handle_worker_connect<'a>(
             rpc_address,
             data_address,
             slots,
+            resource_class,
             ..
         } => {
             workers.insert(
@@ -98,6 +152,7 @@ async fn handle_worker_connect<'a>(
                     id: worker_id,
                     data_address,
                     slots,
+                    resource_class,
                 },
             );
 
@@ -114,16 +169,18 @@ async fn handle_worker_connect<'a>(
                 );
 
                 for i in 0..3 {
-                    match Channel::from_shared(rpc_address.clone())
-                        .unwrap()
-                        .timeout(Duration::from_secs(90))
-                        .connect()
-                        .await
+                    match tls::connect_channel(
+                        Channel::from_shared(rpc_address.clone())
+                            .unwrap()
+                            .timeout(Duration::from_secs(90)),
+                    )
+                    .await
                     {
                         Ok(channel) => {
                             {
                                 let mut connects = connects.lock().await;
-                                connects.insert(worker_id, WorkerGrpcClient::new(channel));
+                                connects
+                                    .insert(worker_id, WorkerGrpcClient::new(channel).compressed());
                             }
                             return;
                         }
@@ -152,12 +209,109 @@ async fn handle_worker_connect<'a>(
 }
 
 impl Scheduling {
+    /// Reserves task slots for this job against its namespace's quota, waiting for other jobs
+    /// in the namespace to release theirs for up to the worker startup time
+    async fn reserve_namespace_slots<'a>(
+        &self,
+        ctx: &mut JobContext<'a>,
+        slots_needed: usize,
+    ) -> Result<(), StateError> {
+        let start = Instant::now();
+        loop {
+            let result = ctx.namespace_slots.lock().unwrap().try_reserve(
+                &ctx.config.namespace,
+                &ctx.config.id,
+                slots_needed,
+                &ctx.config.quota,
+            );
+
+            match result {
+                ReserveResult::Reserved => return Ok(()),
+                ReserveResult::ExceedsQuota { limit } => {
+                    return Err(fatal(
+                        format!(
+                            "Job requires {} task slots, but namespace '{}' is limited to {}",
+                            slots_needed, ctx.config.namespace, limit
+                        ),
+                        anyhow!("namespace task slot quota exceeded"),
+                    ));
+                }
+                ReserveResult::Unavailable { in_use, limit } => {
+                    warn!(
+                        message = "not enough task slots available in namespace",
+                        job_id = *ctx.config.id,
+                        namespace = ctx.config.namespace,
+                        slots_needed,
+                        in_use,
+                        limit
+                    );
+                    if start.elapsed() > *config().pipeline.worker_startup_time {
+                        return Err(fatal(
+                            format!(
+                                "Not enough task slots available in namespace '{}'",
+                                ctx.config.namespace
+                            ),
+                            anyhow!(
+                                "needed {} slots, but {} of {} are in use",
+                                slots_needed,
+                                in_use,
+                                limit
+                            ),
+                        ));
+                    }
+                }
+            }
+
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Preempts lower-priority jobs to free up `slots_short` task slots for this job; returns
+    /// whether any new jobs were preempted
+    async fn preempt_for<'a>(
+        &self,
+        ctx: &mut JobContext<'a>,
+        slots_needed: usize,
+        slots_short: usize,
+        preempted: &mut HashSet<Arc<String>>,
+    ) -> bool {
+        let victims = {
+            let mut allocations = ctx.slot_allocations.lock().unwrap();
+            allocations.wait(&ctx.config.id, slots_needed);
+            allocations.select_victims(&ctx.config.id, ctx.config.priority, slots_short)
+        };
+
+        let mut preempted_any = false;
+        for (job_id, slots) in victims.unwrap_or_default() {
+            if preempted.contains(&job_id) {
+                continue;
+            }
+
+            match preemption::preempt(&ctx.db, &job_id, slots, &ctx.config.id).await {
+                Ok(_) => {
+                    preempted.insert(job_id);
+                    preempted_any = true;
+                }
+                Err(e) => {
+                    warn!(
+                        message = "failed to preempt job",
+                        job_id = *job_id,
+                        error = format!("{:?}", e)
+                    );
+                }
+            }
+        }
+
+        preempted_any
+    }
+
     async fn start_workers<'a>(
         self: Box<Self>,
         ctx: &mut JobContext<'a>,
         slots_needed: usize,
     ) -> Result<Box<Self>, StateError> {
-        let start = Instant::now();
+        let mut start = Instant::now();
+        let mut preempted = HashSet::new();
         loop {
             match ctx
                 .scheduler
@@ -169,16 +323,25 @@ impl Scheduling {
                     name: ctx.config.pipeline_name.clone(),
                     hash: ctx.program.get_hash(),
                     slots: slots_needed,
+                    class_slots: class_slots_for_job(&ctx.program),
                     env_vars: [(
                         "ARROYO__CHECKPOINT_URL".to_string(),
-                        config().checkpoint_url.clone(),
+                        config().checkpoint_storage_url().to_string(),
                     )]
                     .into_iter()
                     .collect(),
+                    worker_pod: ctx.config.worker_pod.clone(),
                 })
                 .await
             {
-                Ok(_) => break,
+                Ok(_) => {
+                    ctx.slot_allocations.lock().unwrap().allocate(
+                        &ctx.config.id,
+                        ctx.config.priority,
+                        slots_needed,
+                    );
+                    break;
+                }
                 Err(SchedulerError::NotEnoughSlots { slots_needed: s }) => {
                     warn!(
                         message = "not enough slots for job",
@@ -186,6 +349,12 @@ impl Scheduling {
                         slots_for_job = slots_needed,
                         slots_needed = s
                     );
+
+                    if self.preempt_for(ctx, slots_needed, s, &mut preempted).await {
+                        // give the preempted jobs time to checkpoint and stop
+                        start = Instant::now();
+                    }
+
                     if start.elapsed() > *config().pipeline.worker_startup_time {
                         return Err(fatal(
                             "Not enough slots to schedule job",
@@ -233,7 +402,20 @@ impl State for Scheduling {
         ctx.program
             .update_parallelism(&ctx.config.parallelism_overrides);
 
+        // rebalance range-partitioned exchanges using the keys observed in the previous run
+        let routing_keys = match ctx.metrics.read().await.get(&ctx.config.id) {
+            Some(metrics) => metrics.routing_key_distributions().await,
+            None => HashMap::new(),
+        };
+        ctx.program.update_range_boundaries(&routing_keys);
+
+        // pick up any UDFs that were updated while the job wasn't running
+        if let Err(e) = ctx.refresh_udfs(ctx.config.udf_reload_nonce).await {
+            return Err(ctx.retryable(self, "failed to load updated UDFs", e, 10));
+        }
+
         let slots_needed: usize = slots_for_job(&*ctx.program);
+        self.reserve_namespace_slots(ctx, slots_needed).await?;
         self = self.start_workers(ctx, slots_needed).await?;
 
         // wait for them to connect and make outbound RPC connections
@@ -266,9 +448,14 @@ impl State for Scheduling {
                     }
                 }
                 _ = tokio::time::sleep(timeout) => {
+                    let err = anyhow!("timed out after {:?} while waiting for worker startup", *config.worker_startup_time);
+                    ctx.notify(
+                        Notification::new(NotificationEvent::SchedulingTimeout, "Scheduling", "Job timed out while being scheduled")
+                            .with_error("timed out while waiting for workers to start", None, &err),
+                    );
                     return Err(ctx.retryable(self,
                         "timed out while waiting for workers to start",
-                        anyhow!("timed out after {:?} while waiting for worker startup", *config.worker_startup_time), 3));
+                        err, 3));
                 }
             }
 
@@ -295,29 +482,48 @@ impl State for Scheduling {
             needs_commits: bool,
         }
 
-        let checkpoint_info = controller_queries::fetch_last_successful_checkpoint(
-            &ctx.db.client().await.unwrap(),
-            &*ctx.config.id,
-        )
-        .await
-        .unwrap()
-        .into_iter()
-        .next()
-        .map(|r| {
-            info!(
-                message = "restoring checkpoint",
-                job_id = *ctx.config.id,
-                epoch = r.epoch,
-                min_epoch = r.min_epoch
-            );
-
-            CheckpointInfo {
+        let checkpoint_info = if config().checkpoint_replication.failover {
+            // when failing over, only checkpoints that made it to the replica can be restored
+            controller_queries::fetch_last_replicated_checkpoint(
+                &ctx.db.client().await.unwrap(),
+                &*ctx.config.id,
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .next()
+            .map(|r| CheckpointInfo {
+                epoch: r.epoch as u32,
+                min_epoch: r.min_epoch as u32,
+                id: r.pub_id,
+                needs_commits: false,
+            })
+        } else {
+            controller_queries::fetch_last_successful_checkpoint(
+                &ctx.db.client().await.unwrap(),
+                &*ctx.config.id,
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .next()
+            .map(|r| CheckpointInfo {
                 epoch: r.epoch as u32,
                 min_epoch: r.min_epoch as u32,
                 id: r.pub_id,
                 needs_commits: r.needs_commits,
-            }
-        });
+            })
+        };
+
+        if let Some(info) = &checkpoint_info {
+            info!(
+                message = "restoring checkpoint",
+                job_id = *ctx.config.id,
+                epoch = info.epoch,
+                min_epoch = info.min_epoch,
+                from_replica = config().checkpoint_replication.failover
+            );
+        }
 
         {
             // mark in-progress checkpoints as failed
@@ -439,7 +645,8 @@ impl State for Scheduling {
                 })?;
         }
 
-        let assignments = compute_assignments(workers.values().collect(), &*ctx.program);
+        let assignments = compute_assignments(workers.values().collect(), &*ctx.program)
+            .map_err(|e| fatal("Failed to assign tasks to workers", e))?;
         let worker_connects = Arc::try_unwrap(worker_connects).unwrap().into_inner();
         let tasks: Vec<_> = worker_connects
             .into_iter()
@@ -527,9 +734,14 @@ impl State for Scheduling {
                     }
                 }
                 _ = tokio::time::sleep(timeout) => {
+                    let err = anyhow!("timed out after {:?} while waiting for worker startup", *config.task_startup_time);
+                    ctx.notify(
+                        Notification::new(NotificationEvent::SchedulingTimeout, "Scheduling", "Job timed out while being scheduled")
+                            .with_error("timed out while waiting for tasks to start", None, &err),
+                    );
                     return Err(ctx.retryable(self,
                         "timed out while waiting for tasks to start",
-                        anyhow!("timed out after {:?} while waiting for worker startup", *config.task_startup_time), 3));
+                        err, 3));
                 }
             }
         }
@@ -555,6 +767,7 @@ impl State for Scheduling {
                 .map(|info| info.min_epoch)
                 .unwrap_or(0),
             worker_connects,
+            &assignments,
             committing_state,
             metrics,
         );
@@ -570,3 +783,72 @@ impl State for Scheduling {
         Ok(Transition::next(*self, Running {}))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_datastream::logical::{LogicalGraph, LogicalNode, OperatorName, ProgramConfig};
+
+    fn node(operator_id: &str, parallelism: usize) -> LogicalNode {
+        LogicalNode {
+            operator_id: operator_id.to_string(),
+            description: operator_id.to_string(),
+            operator_name: OperatorName::ArrowValue,
+            operator_config: vec![],
+            parallelism,
+        }
+    }
+
+    fn worker(id: u64, slots: usize, resource_class: Option<&str>) -> WorkerStatus {
+        WorkerStatus {
+            id: WorkerId(id),
+            data_address: format!("worker-{}", id),
+            slots,
+            resource_class: resource_class.map(|c| c.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_resource_class_assignments() {
+        let mut graph = LogicalGraph::new();
+        graph.add_node(node("parse", 4));
+        graph.add_node(node("aggregate", 2));
+
+        let program = LogicalProgram::new(
+            graph,
+            ProgramConfig {
+                resource_classes: [("aggregate".to_string(), "memory".to_string())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(slots_for_job(&program), 4);
+        assert_eq!(
+            class_slots_for_job(&program),
+            [("memory".to_string(), 2)].into_iter().collect()
+        );
+
+        let workers = [
+            worker(1, 2, Some("memory")),
+            worker(2, 1, None),
+            worker(3, 1, None),
+        ];
+        let assignments = compute_assignments(workers.iter().collect(), &program).unwrap();
+
+        let workers_for = |operator_id: &str| {
+            assignments
+                .iter()
+                .filter(|a| a.operator_id == operator_id)
+                .map(|a| a.worker_id)
+                .collect::<Vec<_>>()
+        };
+        // parsing fills the workers without a class before spilling onto the memory worker
+        assert_eq!(workers_for("parse"), vec![2, 3, 1, 1]);
+        assert_eq!(workers_for("aggregate"), vec![1, 1]);
+
+        // without a memory worker, the aggregate can't be placed
+        assert!(compute_assignments(workers[1..].iter().collect(), &program).is_err());
+    }
+}
@@ -48,12 +48,45 @@ pub struct WasmUDF {
     pub def: WasmDef,
 }
 
+/// Controls when a window emits results besides when the watermark passes its end
+#[derive(Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Debug)]
+pub struct WindowTrigger {
+    /// emit partial results for open windows on this interval
+    pub early_fire: Option<Duration>,
+    /// how long after the watermark passes a window's end records may still update it, emitting
+    /// a corrected result
+    pub allowed_lateness: Duration,
+}
+
 #[derive(Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd)]
 pub enum WindowType {
-    Tumbling { width: Duration },
-    Sliding { width: Duration, slide: Duration },
+    /// `processing_time` windows are assigned and fired by the wall clock rather than by the
+    /// event time of their records
+    Tumbling {
+        width: Duration,
+        processing_time: bool,
+        trigger: Option<WindowTrigger>,
+    },
+    Sliding {
+        width: Duration,
+        slide: Duration,
+        processing_time: bool,
+    },
+    /// Windows that grow by `step` from the start of each `size`-aligned period until they reach
+    /// `size`, when a new period begins
+    Cumulating {
+        step: Duration,
+        size: Duration,
+    },
     Instant,
-    Session { gap: Duration },
+    Session {
+        gap: Duration,
+    },
+    /// Windows over every `size` records of a key, starting a new window every `slide` records
+    Count {
+        size: u64,
+        slide: u64,
+    },
 }
 
 fn format_duration(duration: Duration) -> String {
@@ -77,23 +110,67 @@ fn format_duration(duration: Duration) -> String {
 impl Debug for WindowType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Tumbling { width } => {
-                write!(f, "TumblingWindow({})", format_duration(*width))
+            Self::Tumbling {
+                width,
+                processing_time,
+                trigger,
+            } => {
+                write!(
+                    f,
+                    "{}TumblingWindow({}",
+                    if *processing_time {
+                        "ProcessingTime"
+                    } else {
+                        ""
+                    },
+                    format_duration(*width)
+                )?;
+                if let Some(trigger) = trigger {
+                    if let Some(early_fire) = trigger.early_fire {
+                        write!(f, ", early fire: {}", format_duration(early_fire))?;
+                    }
+                    write!(
+                        f,
+                        ", allowed lateness: {}",
+                        format_duration(trigger.allowed_lateness)
+                    )?;
+                }
+                write!(f, ")")
             }
-            Self::Sliding { width, slide } => {
+            Self::Sliding {
+                width,
+                slide,
+                processing_time,
+            } => {
                 write!(
                     f,
-                    "SlidingWindow(size: {}, slide: {})",
+                    "{}SlidingWindow(size: {}, slide: {})",
+                    if *processing_time {
+                        "ProcessingTime"
+                    } else {
+                        ""
+                    },
                     format_duration(*width),
                     format_duration(*slide)
                 )
             }
+            Self::Cumulating { step, size } => {
+                write!(
+                    f,
+                    "CumulatingWindow(step: {}, size: {})",
+                    format_duration(*step),
+                    format_duration(*size)
+                )
+            }
             Self::Instant => {
                 write!(f, "InstantWindow")
             }
             Self::Session { gap } => {
                 write!(f, "SessionWindow({})", format_duration(*gap))
             }
+            Self::Count { size, slide } => {
+                write!(f, "CountWindow(size: {}, slide: {})", size, slide)
+            }
         }
     }
 }
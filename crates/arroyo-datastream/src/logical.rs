@@ -1,15 +1,21 @@
 use datafusion_proto::protobuf::ArrowType;
 
-use anyhow::anyhow;
-use arrow_schema::DataType;
-use arroyo_rpc::api_types::pipelines::{PipelineEdge, PipelineGraph, PipelineNode};
+use anyhow::{anyhow, bail};
+use arrow_schema::{DataType, Field};
+use arroyo_rpc::api_types::pipelines::{
+    BatchingOverride, PipelineBatching, PipelineEdge, PipelineGraph, PipelineNode,
+    PipelineRecording, PipelineReplay, RecordingMode, StateBootstrap,
+};
 use arroyo_rpc::df::ArroyoSchema;
 use arroyo_rpc::grpc::api;
 use arroyo_rpc::grpc::api::{
-    ArrowDylibUdfConfig, ArrowProgram, ArrowProgramConfig, ConnectorOp, EdgeType,
+    ArrowDylibUdfConfig, ArrowJsUdfConfig, ArrowModelConfig, ArrowProgram, ArrowProgramConfig,
+    BatchingConfig, ConnectorOp, EdgeType, RecordingConfig, ReplayConfig, StateBootstrapConfig,
 };
+use arroyo_types::{range_boundaries_for_distribution, rescale_targets, valid_range_boundaries};
 use petgraph::graph::DiGraph;
 use petgraph::prelude::EdgeRef;
+use petgraph::unionfind::UnionFind;
 use petgraph::Direction;
 use prost::Message;
 use rand::distributions::Alphanumeric;
@@ -19,6 +25,8 @@ use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::Hasher;
+use std::ops::Range;
+use std::sync::Arc;
 use strum::{Display, EnumString};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, EnumString, Display)]
@@ -27,15 +35,99 @@ pub enum OperatorName {
     ArrowValue,
     ArrowKey,
     AsyncUdf,
+    StatefulUdf,
     Join,
     InstantJoin,
     WindowFunction,
     TumblingWindowAggregate,
     SlidingWindowAggregate,
     SessionWindowAggregate,
+    CountWindowAggregate,
     UpdatingAggregate,
     ConnectorSource,
     ConnectorSink,
+    CustomOperator,
+}
+
+/// A parameter of an operator that can be changed while its pipeline is running; updates are
+/// applied by each subtask between batches, and last until the job is restarted
+#[derive(Copy, Clone, Debug)]
+pub struct RuntimeParameter {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Whether the value must be a whole number
+    pub integer: bool,
+    /// The smallest allowed value
+    pub min: f64,
+}
+
+pub const MAX_ROWS_PER_SECOND: RuntimeParameter = RuntimeParameter {
+    name: "max_rows_per_second",
+    description:
+        "Maximum rows per second emitted by each subtask of the source; 0 removes the limit",
+    integer: false,
+    min: 0.0,
+};
+
+pub const FLUSH_INTERVAL_MS: RuntimeParameter = RuntimeParameter {
+    name: "flush_interval_ms",
+    description: "How often the aggregate emits its updated results, in milliseconds",
+    integer: true,
+    min: 1.0,
+};
+
+pub const IDLE_TIME_MS: RuntimeParameter = RuntimeParameter {
+    name: "idle_time_ms",
+    description: "How long a subtask may go without receiving data before it is marked idle, in milliseconds; 0 disables idleness",
+    integer: true,
+    min: 0.0,
+};
+
+impl OperatorName {
+    /// The parameters of this operator that can be changed while the pipeline is running
+    pub fn runtime_parameters(&self) -> &'static [RuntimeParameter] {
+        match self {
+            OperatorName::ConnectorSource => &[MAX_ROWS_PER_SECOND],
+            OperatorName::UpdatingAggregate => &[FLUSH_INTERVAL_MS],
+            OperatorName::ExpressionWatermark => &[IDLE_TIME_MS],
+            _ => &[],
+        }
+    }
+
+    pub fn validate_runtime_parameters(
+        &self,
+        parameters: &HashMap<String, f64>,
+    ) -> anyhow::Result<()> {
+        let supported = self.runtime_parameters();
+        if supported.is_empty() {
+            bail!("{} operators have no runtime parameters", self);
+        }
+
+        for (name, value) in parameters {
+            let Some(parameter) = supported.iter().find(|p| p.name == name) else {
+                bail!(
+                    "unknown parameter '{}' for {} operator; supported parameters are {}",
+                    name,
+                    self,
+                    supported
+                        .iter()
+                        .map(|p| p.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            };
+
+            if !value.is_finite() || *value < parameter.min {
+                bail!("{} must be at least {}", name, parameter.min);
+            }
+
+            if parameter.integer && value.fract() != 0.0 {
+                bail!("{} must be a whole number", name);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
@@ -94,6 +186,11 @@ impl TryFrom<LogicalProgram> for PipelineGraph {
                             .map_err(|_| anyhow!("invalid graph: could not decode connector configuration for {}", node.operator_id))?
                             .connector
                     }
+                    OperatorName::CustomOperator => {
+                        api::CustomOperator::decode(&node.operator_config[..])
+                            .map_err(|_| anyhow!("invalid graph: could not decode custom operator configuration for {}", node.operator_id))?
+                            .name
+                    }
                     op => op.to_string(),
                 },
                 description: node.description.clone(),
@@ -112,7 +209,12 @@ impl TryFrom<LogicalProgram> for PipelineGraph {
                     dest_id: target.operator_id.to_string(),
                     key_type: "()".to_string(),
                     value_type: "()".to_string(),
-                    edge_type: format!("{:?}", edge.weight().edge_type),
+                    edge_type: match (edge.weight().edge_type, &edge.weight().partitioning) {
+                        (LogicalEdgeType::Shuffle, p) if *p != EdgePartitioning::Hash => {
+                            p.to_string()
+                        }
+                        (edge_type, _) => format!("{:?}", edge_type),
+                    },
                 }
             })
             .collect();
@@ -124,11 +226,64 @@ impl TryFrom<LogicalProgram> for PipelineGraph {
     }
 }
 
+/// How the rows of a shuffle edge are divided among the subtasks of its target
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum EdgePartitioning {
+    /// the key hash space is divided evenly between the subtasks; rows of unkeyed edges are spread
+    /// evenly between them
+    #[default]
+    Hash,
+    /// the key hash space is divided at the given boundaries, which are sampled from the observed
+    /// distribution of keys so that skewed keys are spread evenly; until a distribution has been
+    /// observed (or if the parallelism has changed since) the space is divided evenly, as for
+    /// `Hash`
+    Range { boundaries: Vec<u64> },
+    /// rows are spread evenly between all of the subtasks, regardless of their keys
+    Rebalance,
+    /// rows are spread evenly between a subset of the subtasks local to each source subtask (see
+    /// [`rescale_targets`]), which needs fewer connections than `Rebalance`
+    Rescale,
+    /// every row is sent to every subtask
+    Broadcast,
+}
+
+impl EdgePartitioning {
+    /// Whether rows are routed by their keys
+    pub fn is_keyed(&self) -> bool {
+        matches!(
+            self,
+            EdgePartitioning::Hash | EdgePartitioning::Range { .. }
+        )
+    }
+
+    /// The subtasks of the target that subtask `i` of the edge's source (with `sources` subtasks)
+    /// sends to, out of the target's `targets` subtasks
+    pub fn targets(&self, i: usize, sources: usize, targets: usize) -> Range<usize> {
+        match self {
+            EdgePartitioning::Rescale => rescale_targets(i, sources, targets),
+            _ => 0..targets,
+        }
+    }
+}
+
+impl Display for EdgePartitioning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EdgePartitioning::Hash => write!(f, "Hash"),
+            EdgePartitioning::Range { .. } => write!(f, "Range"),
+            EdgePartitioning::Rebalance => write!(f, "Rebalance"),
+            EdgePartitioning::Rescale => write!(f, "Rescale"),
+            EdgePartitioning::Broadcast => write!(f, "Broadcast"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct LogicalEdge {
     pub edge_type: LogicalEdgeType,
     pub schema: ArroyoSchema,
     pub projection: Option<Vec<usize>>,
+    pub partitioning: EdgePartitioning,
 }
 
 impl LogicalEdge {
@@ -141,6 +296,7 @@ impl LogicalEdge {
             edge_type,
             schema,
             projection,
+            partitioning: EdgePartitioning::Hash,
         }
     }
 
@@ -149,6 +305,20 @@ impl LogicalEdge {
             edge_type,
             schema,
             projection: None,
+            partitioning: EdgePartitioning::Hash,
+        }
+    }
+
+    /// The boundaries to range-partition this edge with, if it's range partitioned and they're
+    /// valid for a target with the given parallelism
+    pub fn range_boundaries(&self, parallelism: usize) -> Option<&[u64]> {
+        match &self.partitioning {
+            EdgePartitioning::Range { boundaries }
+                if valid_range_boundaries(boundaries, parallelism) =>
+            {
+                Some(boundaries)
+            }
+            _ => None,
         }
     }
 }
@@ -174,6 +344,30 @@ impl Debug for LogicalNode {
     }
 }
 
+impl LogicalNode {
+    /// A node that runs the custom operator registered under `name`, which is constructed from
+    /// `config` on the workers
+    pub fn custom(
+        operator_id: impl Into<String>,
+        name: impl Into<String>,
+        config: impl Into<String>,
+        parallelism: usize,
+    ) -> Self {
+        let name = name.into();
+        LogicalNode {
+            operator_id: operator_id.into(),
+            description: name.clone(),
+            operator_name: OperatorName::CustomOperator,
+            operator_config: api::CustomOperator {
+                name,
+                config: config.into(),
+            }
+            .encode_to_vec(),
+            parallelism,
+        }
+    }
+}
+
 pub type LogicalGraph = DiGraph<LogicalNode, LogicalEdge>;
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -183,11 +377,45 @@ pub struct DylibUdfConfig {
     pub return_type: DataType,
     pub aggregate: bool,
     pub is_async: bool,
+    pub is_stateful: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct JsUdfConfig {
+    pub definition: String,
+}
+
+/// An ONNX model that is called as a SQL function, taking `inputs` numeric arguments and
+/// returning `outputs` scores
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ModelConfig {
+    pub url: String,
+    pub version: i32,
+    pub inputs: u32,
+    pub outputs: u32,
+}
+
+impl ModelConfig {
+    /// Models with a single output return a score, and otherwise a list of scores
+    pub fn return_type(&self) -> DataType {
+        if self.outputs == 1 {
+            DataType::Float64
+        } else {
+            DataType::List(Arc::new(Field::new("item", DataType::Float64, true)))
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct ProgramConfig {
     pub udf_dylibs: HashMap<String, DylibUdfConfig>,
+    pub js_udfs: HashMap<String, JsUdfConfig>,
+    pub models: HashMap<String, ModelConfig>,
+    pub batching: PipelineBatching,
+    pub recording: PipelineRecording,
+    pub replay: Option<PipelineReplay>,
+    pub bootstrap: Vec<StateBootstrap>,
+    pub resource_classes: HashMap<String, String>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -219,6 +447,24 @@ impl LogicalProgram {
         }
     }
 
+    /// Recomputes the boundaries of range-partitioned edges from the distribution of keys sent by
+    /// their source operators, keyed by node index
+    pub fn update_range_boundaries(&mut self, distributions: &HashMap<u32, Vec<u64>>) {
+        for idx in self.graph.edge_indices() {
+            let (source, target) = self.graph.edge_endpoints(idx).unwrap();
+            let Some(distribution) = distributions.get(&(source.index() as u32)) else {
+                continue;
+            };
+            let parallelism = self.graph[target].parallelism;
+
+            if let EdgePartitioning::Range { boundaries } = &mut self.graph[idx].partitioning {
+                if let Some(b) = range_boundaries_for_distribution(distribution, parallelism) {
+                    *boundaries = b;
+                }
+            }
+        }
+    }
+
     pub fn task_count(&self) -> usize {
         // TODO: this can be cached
         self.graph.node_weights().map(|nw| nw.parallelism).sum()
@@ -248,6 +494,27 @@ impl LogicalProgram {
             .collect()
     }
 
+    /// Splits the program into its failover regions: the sets of operators that are connected
+    /// to each other by some path of edges. As no data flows between regions, a failure in one
+    /// can be recovered by restarting that region alone.
+    pub fn regions(&self) -> Vec<HashSet<String>> {
+        let mut components = UnionFind::new(self.graph.node_count());
+        for edge in self.graph.edge_indices() {
+            let (from, to) = self.graph.edge_endpoints(edge).unwrap();
+            components.union(from.index(), to.index());
+        }
+
+        let mut regions: HashMap<usize, HashSet<String>> = HashMap::new();
+        for idx in self.graph.node_indices() {
+            regions
+                .entry(components.find(idx.index()))
+                .or_default()
+                .insert(self.graph[idx].operator_id.clone());
+        }
+
+        regions.into_values().collect()
+    }
+
     pub fn operator_index(&self, name: &str) -> Option<u32> {
         self.operator_indices.get(name).cloned()
     }
@@ -266,6 +533,7 @@ impl LogicalProgram {
         for t in self.graph.node_weights() {
             let feature = match &t.operator_name {
                 OperatorName::AsyncUdf => "async-udf".to_string(),
+                OperatorName::StatefulUdf => "stateful-udf".to_string(),
                 OperatorName::ExpressionWatermark
                 | OperatorName::ArrowValue
                 | OperatorName::ArrowKey => continue,
@@ -277,6 +545,7 @@ impl LogicalProgram {
                 }
                 OperatorName::SlidingWindowAggregate => "sql-sliding-window-aggregate".to_string(),
                 OperatorName::SessionWindowAggregate => "sql-session-window-aggregate".to_string(),
+                OperatorName::CountWindowAggregate => "sql-count-window-aggregate".to_string(),
                 OperatorName::UpdatingAggregate => "sql-updating-aggregate".to_string(),
                 OperatorName::ConnectorSource => {
                     let Ok(connector_op) = ConnectorOp::decode(&t.operator_config[..]) else {
@@ -290,6 +559,7 @@ impl LogicalProgram {
                     };
                     format!("{}-sink", connector_op.connector)
                 }
+                OperatorName::CustomOperator => "custom-operator".to_string(),
             };
             s.insert(feature);
         }
@@ -335,6 +605,18 @@ impl TryFrom<ArrowProgram> for LogicalProgram {
                     } else {
                         Some(edge.projection.iter().map(|p| *p as usize).collect())
                     },
+                    partitioning: if edge.range_partitioned {
+                        EdgePartitioning::Range {
+                            boundaries: edge.range_boundaries.clone(),
+                        }
+                    } else {
+                        match edge.exchange() {
+                            api::EdgeExchange::Hash => EdgePartitioning::Hash,
+                            api::EdgeExchange::Rebalance => EdgePartitioning::Rebalance,
+                            api::EdgeExchange::Rescale => EdgePartitioning::Rescale,
+                            api::EdgeExchange::Broadcast => EdgePartitioning::Broadcast,
+                        }
+                    },
                 },
             );
         }
@@ -343,6 +625,14 @@ impl TryFrom<ArrowProgram> for LogicalProgram {
             .program_config
             .unwrap_or_else(|| ArrowProgramConfig {
                 udf_dylibs: HashMap::new(),
+                js_udfs: HashMap::new(),
+                models: HashMap::new(),
+                batching: None,
+                operator_batching: HashMap::new(),
+                recording: None,
+                replay: None,
+                bootstrap: vec![],
+                resource_classes: HashMap::new(),
             })
             .into();
 
@@ -368,6 +658,7 @@ impl From<DylibUdfConfig> for ArrowDylibUdfConfig {
                 .encode_to_vec(),
             aggregate: from.aggregate,
             is_async: from.is_async,
+            is_stateful: from.is_stateful,
         }
     }
 }
@@ -392,6 +683,7 @@ impl From<ArrowDylibUdfConfig> for DylibUdfConfig {
             .expect("invalid arrow type"),
             aggregate: from.aggregate,
             is_async: from.is_async,
+            is_stateful: from.is_stateful,
         }
     }
 }
@@ -404,18 +696,162 @@ impl From<ProgramConfig> for ArrowProgramConfig {
                 .into_iter()
                 .map(|(k, v)| (k, v.into()))
                 .collect(),
+            js_udfs: from
+                .js_udfs
+                .into_iter()
+                .map(|(k, v)| {
+                    (
+                        k,
+                        ArrowJsUdfConfig {
+                            definition: v.definition,
+                        },
+                    )
+                })
+                .collect(),
+            models: from
+                .models
+                .into_iter()
+                .map(|(k, v)| {
+                    (
+                        k,
+                        ArrowModelConfig {
+                            url: v.url,
+                            version: v.version,
+                            inputs: v.inputs,
+                            outputs: v.outputs,
+                        },
+                    )
+                })
+                .collect(),
+            batching: Some(BatchingConfig {
+                max_rows: from.batching.max_rows,
+                linger_millis: from.batching.linger_millis,
+                adaptive: from.batching.adaptive,
+            }),
+            operator_batching: from
+                .batching
+                .operator_overrides
+                .into_iter()
+                .map(|(k, v)| {
+                    (
+                        k,
+                        BatchingConfig {
+                            max_rows: v.max_rows,
+                            linger_millis: v.linger_millis,
+                            adaptive: v.adaptive,
+                        },
+                    )
+                })
+                .collect(),
+            recording: match from.recording.mode {
+                RecordingMode::Off => None,
+                RecordingMode::Full => Some(RecordingConfig {
+                    sampled: false,
+                    sample_rate: None,
+                }),
+                RecordingMode::Sampled => Some(RecordingConfig {
+                    sampled: true,
+                    sample_rate: from.recording.sample_rate,
+                }),
+            },
+            replay: from.replay.map(|r| ReplayConfig {
+                job_id: r.job_id,
+                epoch: r.epoch,
+            }),
+            bootstrap: from
+                .bootstrap
+                .into_iter()
+                .map(|b| StateBootstrapConfig {
+                    operator_id: b.operator_id,
+                    table: b.table,
+                    url: b.url,
+                })
+                .collect(),
+            resource_classes: from.resource_classes,
         }
     }
 }
 
 impl From<ArrowProgramConfig> for ProgramConfig {
     fn from(from: ArrowProgramConfig) -> Self {
+        let batching = from.batching.unwrap_or_default();
         ProgramConfig {
             udf_dylibs: from
                 .udf_dylibs
                 .into_iter()
                 .map(|(k, v)| (k, v.into()))
                 .collect(),
+            js_udfs: from
+                .js_udfs
+                .into_iter()
+                .map(|(k, v)| {
+                    (
+                        k,
+                        JsUdfConfig {
+                            definition: v.definition,
+                        },
+                    )
+                })
+                .collect(),
+            models: from
+                .models
+                .into_iter()
+                .map(|(k, v)| {
+                    (
+                        k,
+                        ModelConfig {
+                            url: v.url,
+                            version: v.version,
+                            inputs: v.inputs,
+                            outputs: v.outputs,
+                        },
+                    )
+                })
+                .collect(),
+            batching: PipelineBatching {
+                max_rows: batching.max_rows,
+                linger_millis: batching.linger_millis,
+                adaptive: batching.adaptive,
+                operator_overrides: from
+                    .operator_batching
+                    .into_iter()
+                    .map(|(k, v)| {
+                        (
+                            k,
+                            BatchingOverride {
+                                max_rows: v.max_rows,
+                                linger_millis: v.linger_millis,
+                                adaptive: v.adaptive,
+                            },
+                        )
+                    })
+                    .collect(),
+            },
+            recording: from
+                .recording
+                .map(|r| PipelineRecording {
+                    mode: if r.sampled {
+                        RecordingMode::Sampled
+                    } else {
+                        RecordingMode::Full
+                    },
+                    sample_rate: r.sample_rate,
+                })
+                .unwrap_or_default(),
+            replay: from.replay.map(|r| PipelineReplay {
+                job_id: r.job_id,
+                epoch: r.epoch,
+            }),
+            bootstrap: from
+                .bootstrap
+                .into_iter()
+                .map(|b| StateBootstrap {
+                    operator_id: b.operator_id,
+                    table: b.table,
+                    url: b.url,
+                })
+                .collect(),
+            resource_classes: from.resource_classes,
         }
     }
 }
@@ -455,6 +891,19 @@ impl From<LogicalProgram> for ArrowProgram {
                         .as_ref()
                         .map(|p| p.iter().map(|v| *v as u32).collect())
                         .unwrap_or_default(),
+                    range_partitioned: matches!(edge.partitioning, EdgePartitioning::Range { .. }),
+                    range_boundaries: match &edge.partitioning {
+                        EdgePartitioning::Range { boundaries } => boundaries.clone(),
+                        _ => vec![],
+                    },
+                    exchange: match edge.partitioning {
+                        EdgePartitioning::Hash | EdgePartitioning::Range { .. } => {
+                            api::EdgeExchange::Hash
+                        }
+                        EdgePartitioning::Rebalance => api::EdgeExchange::Rebalance,
+                        EdgePartitioning::Rescale => api::EdgeExchange::Rescale,
+                        EdgePartitioning::Broadcast => api::EdgeExchange::Broadcast,
+                    } as i32,
                 }
             })
             .collect();
@@ -466,3 +915,76 @@ impl From<LogicalProgram> for ArrowProgram {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(operators: &[&str], edges: &[(&str, &str)]) -> LogicalProgram {
+        let mut graph = LogicalGraph::new();
+        let indices: HashMap<_, _> = operators
+            .iter()
+            .map(|op| (*op, graph.add_node(LogicalNode::custom(*op, "test", "", 1))))
+            .collect();
+
+        for (from, to) in edges {
+            graph.add_edge(
+                indices[from],
+                indices[to],
+                LogicalEdge::project_all(
+                    LogicalEdgeType::Forward,
+                    ArroyoSchema::from_fields(vec![]),
+                ),
+            );
+        }
+
+        LogicalProgram::new(graph, ProgramConfig::default())
+    }
+
+    fn sorted(regions: Vec<HashSet<String>>) -> Vec<Vec<String>> {
+        let mut regions: Vec<Vec<String>> = regions
+            .into_iter()
+            .map(|r| {
+                let mut r: Vec<_> = r.into_iter().collect();
+                r.sort();
+                r
+            })
+            .collect();
+        regions.sort();
+        regions
+    }
+
+    #[test]
+    fn test_regions_disconnected() {
+        let program = program(
+            &["source_a", "sink_a", "source_b", "map_b", "sink_b"],
+            &[
+                ("source_a", "sink_a"),
+                ("source_b", "map_b"),
+                ("map_b", "sink_b"),
+            ],
+        );
+
+        assert_eq!(
+            sorted(program.regions()),
+            vec![
+                vec!["map_b", "sink_b", "source_b"],
+                vec!["sink_a", "source_a"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_regions_connected() {
+        // the two sources are joined, so a failure anywhere affects the whole pipeline
+        let program = program(
+            &["source_a", "source_b", "join", "sink"],
+            &[("source_a", "join"), ("source_b", "join"), ("join", "sink")],
+        );
+
+        assert_eq!(
+            sorted(program.regions()),
+            vec![vec!["join", "sink", "source_a", "source_b"]]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Measures JSON deserialization throughput, both for sources that receive one record per message
+//! (like Kafka) and for sources that receive newline-delimited payloads (like files or HTTP).
+//!
+//! Run with `cargo bench -p arroyo-formats --bench json`
+
+use arrow_array::builder::make_builder;
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use arroyo_formats::de::ArrowDeserializer;
+use arroyo_rpc::df::ArroyoSchema;
+use arroyo_rpc::formats::{
+    BadData, Format, Framing, FramingMethod, JsonFormat, NewlineDelimitedFraming,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+const RECORDS: usize = 1000;
+
+fn schema() -> ArroyoSchema {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, true),
+        Field::new("price", DataType::Float64, true),
+        Field::new("active", DataType::Boolean, true),
+        Field::new(
+            "_timestamp",
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            false,
+        ),
+    ]));
+
+    ArroyoSchema::from_schema_unkeyed(schema).unwrap()
+}
+
+fn records() -> Vec<Vec<u8>> {
+    (0..RECORDS)
+        .map(|i| {
+            json!({
+                "id": i,
+                "name": format!("item-{}", i),
+                "price": i as f64 * 1.25,
+                "active": i % 2 == 0,
+                "ignored": {"nested": [1, 2, 3]},
+            })
+            .to_string()
+            .into_bytes()
+        })
+        .collect()
+}
+
+fn deserializer(framing: Option<Framing>) -> ArrowDeserializer {
+    ArrowDeserializer::new(
+        Format::Json(JsonFormat::default()),
+        schema(),
+        framing,
+        BadData::Fail {},
+    )
+}
+
+fn bench_json(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let records = records();
+    let payload = records.join(&b'\n');
+
+    let mut group = c.benchmark_group("json");
+    group.throughput(Throughput::Bytes(payload.len() as u64));
+
+    group.bench_function("per_message", |b| {
+        b.to_async(&runtime).iter_batched(
+            || deserializer(None),
+            |mut deserializer| {
+                let records = &records;
+                async move {
+                    let now = SystemTime::now();
+                    for record in records {
+                        deserializer.deserialize_slice(&mut [], record, now).await;
+                    }
+                    deserializer.flush_buffer().unwrap().unwrap()
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("newline_delimited", |b| {
+        b.to_async(&runtime).iter_batched(
+            || {
+                deserializer(Some(Framing {
+                    method: FramingMethod::Newline(NewlineDelimitedFraming {
+                        max_line_length: None,
+                    }),
+                }))
+            },
+            |mut deserializer| {
+                let payload = &payload;
+                async move {
+                    deserializer
+                        .deserialize_slice(&mut [], payload, SystemTime::now())
+                        .await;
+                    deserializer.flush_buffer().unwrap().unwrap()
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("newline_delimited_line_by_line", |b| {
+        b.to_async(&runtime).iter_batched(
+            || {
+                deserializer(Some(Framing {
+                    method: FramingMethod::Newline(NewlineDelimitedFraming {
+                        // setting a max line length forces the line-by-line path
+                        max_line_length: Some(u64::MAX),
+                    }),
+                }))
+            },
+            |mut deserializer| {
+                let payload = &payload;
+                async move {
+                    deserializer
+                        .deserialize_slice(&mut [], payload, SystemTime::now())
+                        .await;
+                    deserializer.flush_buffer().unwrap().unwrap()
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_json);
+criterion_main!(benches);
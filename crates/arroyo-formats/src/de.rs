@@ -1,5 +1,4 @@
 use crate::avro::de;
-use crate::should_flush;
 use arrow::compute::kernels;
 use arrow_array::builder::{
     ArrayBuilder, GenericByteBuilder, StringBuilder, TimestampNanosecondBuilder,
@@ -7,8 +6,11 @@ use arrow_array::builder::{
 use arrow_array::types::GenericBinaryType;
 use arrow_array::RecordBatch;
 use arroyo_rpc::df::ArroyoSchema;
-use arroyo_rpc::formats::{AvroFormat, BadData, Format, Framing, FramingMethod, JsonFormat};
+use arroyo_rpc::formats::{
+    AvroFormat, BadData, Format, Framing, FramingMethod, JsonFormat, NewlineDelimitedFraming,
+};
 use arroyo_rpc::schema_resolver::{FailingSchemaResolver, FixedSchemaResolver, SchemaResolver};
+use arroyo_rpc::BatchSettings;
 use arroyo_types::{to_nanos, SourceError};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -146,8 +148,18 @@ impl ArrowDeserializer {
         msg: &[u8],
         timestamp: SystemTime,
     ) -> Vec<SourceError> {
+        if self.buffered_count == 0 {
+            // linger is measured from the first buffered row
+            self.buffered_since = Instant::now();
+        }
+
         match &*self.format {
             Format::Avro(_) => self.deserialize_slice_avro(buffer, msg, timestamp).await,
+            Format::Json(json) if self.can_decode_json_in_bulk(json) => self
+                .deserialize_json_bulk(msg, timestamp)
+                .err()
+                .into_iter()
+                .collect(),
             _ => FramingIterator::new(self.framing.clone(), msg)
                 .map(|t| self.deserialize_single(buffer, t, timestamp))
                 .filter_map(|t| t.err())
@@ -155,8 +167,8 @@ impl ArrowDeserializer {
         }
     }
 
-    pub fn should_flush(&self) -> bool {
-        should_flush(self.buffered_count, self.buffered_since)
+    pub fn should_flush(&self, settings: &BatchSettings) -> bool {
+        settings.should_flush(self.buffered_count, self.buffered_since)
     }
 
     pub fn flush_buffer(&mut self) -> Option<Result<RecordBatch, SourceError>> {
@@ -199,6 +211,58 @@ impl ArrowDeserializer {
         }
     }
 
+    /// Newline-delimited JSON can be handed to the decoder all at once rather than line-by-line,
+    /// which avoids the per-record overhead of framing and decoding. This isn't possible when
+    /// lines may be truncated, or when bad records need to be identified so they can be dropped.
+    fn can_decode_json_in_bulk(&self, json: &JsonFormat) -> bool {
+        let newline_framed = matches!(
+            self.framing.as_deref(),
+            Some(Framing {
+                method: FramingMethod::Newline(NewlineDelimitedFraming {
+                    max_line_length: None
+                })
+            })
+        );
+
+        newline_framed
+            && !json.unstructured
+            && !json.confluent_schema_registry
+            && matches!(self.bad_data, BadData::Fail {})
+    }
+
+    fn deserialize_json_bulk(
+        &mut self,
+        msg: &[u8],
+        timestamp: SystemTime,
+    ) -> Result<(), SourceError> {
+        let Some((decoder, timestamp_builder)) = &mut self.json_decoder else {
+            panic!("json decoder not initialized");
+        };
+
+        let buffered = decoder.len();
+        let mut remaining = msg;
+        while !remaining.is_empty() {
+            let read = decoder
+                .decode(remaining)
+                .map_err(|e| SourceError::bad_data(format!("invalid JSON: {:?}", e)))?;
+            if read == 0 {
+                return Err(SourceError::bad_data(
+                    "invalid JSON: could not decode record",
+                ));
+            }
+            remaining = &remaining[read..];
+        }
+
+        let records = decoder.len() - buffered;
+        let timestamp = to_nanos(timestamp) as i64;
+        for _ in 0..records {
+            timestamp_builder.append_value(timestamp);
+        }
+        self.buffered_count += records;
+
+        Ok(())
+    }
+
     fn deserialize_single(
         &mut self,
         buffer: &mut [Box<dyn ArrayBuilder>],
@@ -545,6 +609,56 @@ mod tests {
         assert!(matches!(err, SourceError::BadData { .. }));
     }
 
+    #[tokio::test]
+    async fn test_newline_delimited_json() {
+        let (mut arrays, _) = setup_deserializer(BadData::Fail {});
+        let schema = Arc::new(Schema::new(vec![
+            arrow_schema::Field::new("x", arrow_schema::DataType::Int64, true),
+            arrow_schema::Field::new(
+                "_timestamp",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+
+        let mut deserializer = ArrowDeserializer::new(
+            Format::Json(JsonFormat::default()),
+            ArroyoSchema::from_schema_unkeyed(schema).unwrap(),
+            Some(Framing {
+                method: FramingMethod::Newline(NewlineDelimitedFraming {
+                    max_line_length: None,
+                }),
+            }),
+            BadData::Fail {},
+        );
+
+        let now = SystemTime::now();
+        let errors = deserializer
+            .deserialize_slice(
+                &mut arrays[..],
+                b"{\"x\": 1}\n{\"x\": 2}\n\n{\"x\": 3}\n",
+                now,
+            )
+            .await;
+        assert_eq!(errors, vec![]);
+
+        let batch = deserializer.flush_buffer().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(
+            batch.columns()[0]
+                .as_primitive::<Int64Type>()
+                .values()
+                .to_vec(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            batch.columns()[1]
+                .as_primitive::<TimestampNanosecondType>()
+                .value(2),
+            to_nanos(now) as i64
+        );
+    }
+
     #[tokio::test]
     async fn test_raw_bytes() {
         let schema = Arc::new(Schema::new(vec![
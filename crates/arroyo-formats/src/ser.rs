@@ -1,16 +1,21 @@
 use crate::avro::schema;
 use crate::{avro, json};
 use arrow_array::cast::AsArray;
-use arrow_array::types::GenericBinaryType;
+use arrow_array::types::{GenericBinaryType, TimestampNanosecondType};
 use arrow_array::RecordBatch;
 use arrow_json::writer::record_batch_to_vec;
-use arrow_schema::{DataType, Field};
+use arrow_schema::{DataType, Field, Fields};
 use arroyo_rpc::formats::{
     AvroFormat, Format, JsonFormat, RawBytesFormat, RawStringFormat, TimestampFormat,
 };
 use arroyo_rpc::TIMESTAMP_FIELD;
+use arroyo_types::to_millis;
 use serde_json::Value;
 use std::sync::Arc;
+use std::time::SystemTime;
+
+/// The `connector` reported in the source metadata of Debezium envelopes written by Arroyo
+const DEBEZIUM_CONNECTOR: &str = "arroyo";
 
 pub struct ArrowSerializer {
     kafka_schema: Option<Value>,
@@ -29,6 +34,10 @@ impl ArrowSerializer {
         }
     }
 
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+
     fn projection(schema: &arrow_schema::Schema) -> Vec<usize> {
         schema
             .fields
@@ -59,15 +68,60 @@ impl ArrowSerializer {
         json::arrow_to_kafka_json("ArroyoJson", &Self::projected_schema(schema).into())
     }
 
+    /// The metadata fields that are added to Debezium envelopes when they're serialized, after
+    /// the `before`, `after` and `op` fields produced by the planner
+    fn debezium_metadata_fields() -> Vec<Field> {
+        let source = Fields::from(vec![
+            Field::new("version", DataType::Utf8, false),
+            Field::new("connector", DataType::Utf8, false),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("ts_ms", DataType::Int64, true),
+            Field::new("snapshot", DataType::Utf8, false),
+        ]);
+        vec![
+            Field::new("source", DataType::Struct(source), false),
+            Field::new("ts_ms", DataType::Int64, false),
+        ]
+    }
+
+    fn is_debezium(&self) -> bool {
+        matches!(
+            &self.format,
+            Format::Json(JsonFormat { debezium: true, .. })
+        )
+    }
+
     pub fn serialize(&mut self, batch: &RecordBatch) -> Box<dyn Iterator<Item = Vec<u8>> + Send> {
         if self.projection.is_empty() {
             self.projection = Self::projection(&batch.schema());
         }
 
         if self.kafka_schema.is_none() {
-            self.kafka_schema = Some(Self::kafka_schema(&batch.schema()));
+            self.kafka_schema = Some(if self.is_debezium() {
+                let mut fields = Self::projected_schema(&batch.schema());
+                fields.extend(Self::debezium_metadata_fields());
+                json::arrow_to_kafka_json("ArroyoJson", &fields.into())
+            } else {
+                Self::kafka_schema(&batch.schema())
+            });
         }
 
+        // the event time is projected out of the serialized rows, but is reported in the source
+        // metadata of debezium envelopes
+        let event_times: Option<Vec<Option<i64>>> = self.is_debezium().then(|| {
+            batch
+                .schema()
+                .index_of(TIMESTAMP_FIELD)
+                .ok()
+                .and_then(|i| {
+                    batch
+                        .column(i)
+                        .as_primitive_opt::<TimestampNanosecondType>()
+                })
+                .map(|ts| ts.iter().map(|t| t.map(|t| t / 1_000_000)).collect())
+                .unwrap_or_else(|| vec![None; batch.num_rows()])
+        });
+
         if self.avro_schema.is_none() {
             self.avro_schema = Some(Arc::new(Self::avro_schema(&batch.schema())));
         }
@@ -77,7 +131,7 @@ impl ArrowSerializer {
             .expect("batch has wrong number of columns");
 
         match &self.format {
-            Format::Json(json) => self.serialize_json(json, &batch),
+            Format::Json(json) => self.serialize_json(json, &batch, event_times),
             Format::Avro(avro) => self.serialize_avro(avro, &batch),
             Format::Parquet(_) => todo!("parquet"),
             Format::RawString(RawStringFormat {}) => self.serialize_raw_string(&batch),
@@ -89,6 +143,7 @@ impl ArrowSerializer {
         &self,
         json: &JsonFormat,
         batch: &RecordBatch,
+        event_times: Option<Vec<Option<i64>>>,
     ) -> Box<dyn Iterator<Item = Vec<u8>> + Send> {
         let header = json.confluent_schema_registry.then(|| {
             if json.include_schema {
@@ -111,6 +166,11 @@ impl ArrowSerializer {
         )
         .unwrap();
 
+        let rows = match event_times {
+            Some(event_times) => add_debezium_metadata(rows, event_times),
+            None => rows,
+        };
+
         let include_schema = json.include_schema.then(|| self.kafka_schema.clone());
 
         Box::new(rows.into_iter().map(move |row| {
@@ -235,6 +295,34 @@ impl ArrowSerializer {
     }
 }
 
+/// Completes the Debezium envelopes produced by the planner (which contain `before`, `after`, and
+/// `op`) with the `source` metadata block and the `ts_ms` processing time
+fn add_debezium_metadata(rows: Vec<Vec<u8>>, event_times: Vec<Option<i64>>) -> Vec<Vec<u8>> {
+    let processed_at = to_millis(SystemTime::now());
+
+    rows.into_iter()
+        .zip(event_times)
+        .map(|(mut row, event_time)| {
+            // each row is serialized as a JSON object, which we extend in place
+            assert_eq!(row.pop(), Some(b'}'), "debezium row is not a JSON object");
+            let metadata = json! {{
+                "source": {
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "connector": DEBEZIUM_CONNECTOR,
+                    "name": DEBEZIUM_CONNECTOR,
+                    "ts_ms": event_time,
+                    "snapshot": "false",
+                },
+                "ts_ms": processed_at,
+            }};
+            let metadata = serde_json::to_vec(&metadata).unwrap();
+            row.push(b',');
+            row.extend_from_slice(&metadata[1..]);
+            row
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::ser::ArrowSerializer;
@@ -425,4 +513,83 @@ mod tests {
         assert_eq!(iter.next().unwrap(), br#"{"value":null}"#);
         assert_eq!(iter.next().unwrap(), br#"{"value":1712274910045}"#);
     }
+
+    #[test]
+    fn test_json_debezium() {
+        let mut serializer = ArrowSerializer::new(Format::Json(arroyo_rpc::formats::JsonFormat {
+            confluent_schema_registry: false,
+            schema_id: None,
+            include_schema: false,
+            debezium: true,
+            unstructured: false,
+            timestamp_format: TimestampFormat::UnixMillis,
+        }));
+
+        let value_fields = arrow_schema::Fields::from(vec![arrow_schema::Field::new(
+            "x",
+            arrow_schema::DataType::Int32,
+            false,
+        )]);
+        let values: arrow_array::ArrayRef = Arc::new(arrow_array::Int32Array::from(vec![1, 2]));
+        let is_retract = [true, false];
+
+        let schema = Arc::new(Schema::new(vec![
+            arrow_schema::Field::new(
+                "before",
+                arrow_schema::DataType::Struct(value_fields.clone()),
+                true,
+            ),
+            arrow_schema::Field::new(
+                "after",
+                arrow_schema::DataType::Struct(value_fields.clone()),
+                true,
+            ),
+            arrow_schema::Field::new("op", arrow_schema::DataType::Utf8, false),
+            arrow_schema::Field::new(
+                "_timestamp",
+                arrow_schema::DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]));
+
+        let batch = arrow_array::RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(arrow_array::StructArray::new(
+                    value_fields.clone(),
+                    vec![values.clone()],
+                    Some(is_retract.to_vec().into()),
+                )),
+                Arc::new(arrow_array::StructArray::new(
+                    value_fields,
+                    vec![values],
+                    Some(is_retract.map(|r| !r).to_vec().into()),
+                )),
+                Arc::new(arrow_array::StringArray::from(vec!["d", "c"])),
+                Arc::new(arrow_array::TimestampNanosecondArray::from(vec![
+                    1_000_000_000,
+                    2_000_000_000,
+                ])),
+            ],
+        )
+        .unwrap();
+
+        let rows: Vec<serde_json::Value> = serializer
+            .serialize(&batch)
+            .map(|row| serde_json::from_slice(&row).unwrap())
+            .collect();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["before"]["x"], 1);
+        assert!(rows[0]["after"].is_null());
+        assert_eq!(rows[0]["op"], "d");
+        assert_eq!(rows[0]["source"]["connector"], "arroyo");
+        assert_eq!(rows[0]["source"]["ts_ms"], 1000);
+        assert!(rows[0]["ts_ms"].is_u64());
+
+        assert!(rows[1]["before"].is_null());
+        assert_eq!(rows[1]["after"]["x"], 2);
+        assert_eq!(rows[1]["op"], "c");
+        assert_eq!(rows[1]["source"]["ts_ms"], 2000);
+    }
 }
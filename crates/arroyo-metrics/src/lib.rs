@@ -1,14 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, OnceLock, RwLock};
 
 use arroyo_types::{
     TaskInfo, BATCHES_RECV, BATCHES_SENT, BYTES_RECV, BYTES_SENT, DESERIALIZATION_ERRORS,
-    MESSAGES_RECV, MESSAGES_SENT,
+    MESSAGES_RECV, MESSAGES_SENT, PARTITION_BYTES_SENT, PARTITION_MESSAGES_SENT,
+    PROCESS_BATCH_TIME, ROUTING_KEY_BUCKETS, ROUTING_KEY_DISTRIBUTION, SINK_LATENCY,
+    SOURCE_PARTITION_LAG, SOURCE_PARTITION_LAG_MS,
 };
 use lazy_static::lazy_static;
 use prometheus::{
-    labels, register_histogram, register_int_counter_vec, register_int_gauge, Histogram,
-    HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts,
+    exponential_buckets, labels, register_histogram, register_histogram_vec,
+    register_int_counter_vec, register_int_gauge, register_int_gauge_vec, Histogram, HistogramOpts,
+    HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
 };
 
 pub fn gauge_for_task(
@@ -43,6 +46,13 @@ pub fn histogram_for_task(
 lazy_static! {
     pub static ref TASK_METRIC_LABELS: Vec<&'static str> =
         vec!["operator_id", "subtask_idx", "operator_name"];
+    pub static ref PARTITION_METRIC_LABELS: Vec<&'static str> = vec![
+        "operator_id",
+        "subtask_idx",
+        "operator_name",
+        "next_node",
+        "next_node_idx"
+    ];
     pub static ref MESSAGE_RECV_COUNTER: IntCounterVec = register_int_counter_vec!(
         MESSAGES_RECV,
         "Count of messages received by this subtask",
@@ -85,6 +95,56 @@ lazy_static! {
         &TASK_METRIC_LABELS
     )
     .unwrap();
+    pub static ref PARTITION_MESSAGES_SENT_COUNTER: IntCounterVec = register_int_counter_vec!(
+        PARTITION_MESSAGES_SENT,
+        "Count of messages sent by this subtask to each partition of a keyed exchange",
+        &PARTITION_METRIC_LABELS
+    )
+    .unwrap();
+    pub static ref PARTITION_BYTES_SENT_COUNTER: IntCounterVec = register_int_counter_vec!(
+        PARTITION_BYTES_SENT,
+        "Estimated count of bytes sent by this subtask to each partition of a keyed exchange",
+        &PARTITION_METRIC_LABELS
+    )
+    .unwrap();
+    pub static ref ROUTING_KEY_METRIC_LABELS: Vec<&'static str> =
+        vec!["operator_id", "subtask_idx", "operator_name", "bucket"];
+    pub static ref ROUTING_KEY_DISTRIBUTION_COUNTER: IntCounterVec = register_int_counter_vec!(
+        ROUTING_KEY_DISTRIBUTION,
+        "Count of rows sent by this subtask over range-partitioned edges, by key hash bucket",
+        &ROUTING_KEY_METRIC_LABELS
+    )
+    .unwrap();
+    pub static ref SOURCE_PARTITION_METRIC_LABELS: Vec<&'static str> =
+        vec!["operator_id", "subtask_idx", "operator_name", "partition"];
+    pub static ref SOURCE_PARTITION_LAG_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        SOURCE_PARTITION_LAG,
+        "Number of records in each partition of the source that this subtask has not yet read",
+        &SOURCE_PARTITION_METRIC_LABELS
+    )
+    .unwrap();
+    pub static ref SOURCE_PARTITION_LAG_MS_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        SOURCE_PARTITION_LAG_MS,
+        "Estimated milliseconds that this subtask is behind the end of each partition of the source",
+        &SOURCE_PARTITION_METRIC_LABELS
+    )
+    .unwrap();
+    pub static ref PROCESS_BATCH_TIME_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        PROCESS_BATCH_TIME,
+        "Wall time spent processing each batch received by this subtask",
+        &TASK_METRIC_LABELS,
+        // 10µs to ~2.6s
+        exponential_buckets(0.00001, 4.0, 10).unwrap()
+    )
+    .unwrap();
+    pub static ref SINK_LATENCY_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        SINK_LATENCY,
+        "Time between the event time of the latest row in each batch and its arrival at this sink",
+        &TASK_METRIC_LABELS,
+        // 1ms to ~33s
+        exponential_buckets(0.001, 2.0, 16).unwrap()
+    )
+    .unwrap();
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
@@ -158,6 +218,53 @@ impl TaskCounters {
     }
 }
 
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TaskHistograms {
+    ProcessBatchTime,
+}
+
+impl TaskHistograms {
+    pub fn variants() -> [TaskHistograms; 1] {
+        [TaskHistograms::ProcessBatchTime]
+    }
+
+    fn metric(&self) -> &'static HistogramVec {
+        match self {
+            TaskHistograms::ProcessBatchTime => &PROCESS_BATCH_TIME_HISTOGRAM,
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn for_task<F>(&self, task_info: &Arc<TaskInfo>, f: F)
+    where
+        F: Fn(&Histogram),
+    {
+        static CACHE: OnceLock<Arc<RwLock<HashMap<(TaskHistograms, Arc<TaskInfo>), Histogram>>>> =
+            OnceLock::new();
+        let cache = CACHE.get_or_init(|| Arc::new(RwLock::new(HashMap::new())));
+
+        {
+            if let Some(histogram) = cache.read().unwrap().get(&(*self, task_info.clone())) {
+                f(histogram);
+                return;
+            }
+        }
+
+        let histogram = self.metric().with_label_values(&[
+            &task_info.operator_id,
+            &task_info.task_index.to_string(),
+            &task_info.operator_name,
+        ]);
+
+        f(&histogram);
+
+        cache
+            .write()
+            .unwrap()
+            .insert((*self, task_info.clone()), histogram);
+    }
+}
+
 pub type QueueGauges = Vec<Vec<Option<IntGauge>>>;
 
 pub fn register_queue_gauge<T>(
@@ -194,3 +301,106 @@ pub fn register_queue_gauge<T>(
         })
         .collect()
 }
+
+/// Counters of the messages and bytes sent to each downstream partition, indexed like the
+/// collector's output queues
+pub type PartitionCounters = Vec<Vec<(IntCounter, IntCounter)>>;
+
+pub fn partition_counters<T>(task_info: &TaskInfo, out_qs: &[Vec<T>]) -> PartitionCounters {
+    out_qs
+        .iter()
+        .enumerate()
+        .map(|(i, qs)| {
+            (0..qs.len())
+                .map(|j| {
+                    let labels = [
+                        task_info.operator_id.as_str(),
+                        &task_info.task_index.to_string(),
+                        &task_info.operator_name,
+                        &i.to_string(),
+                        &j.to_string(),
+                    ];
+                    (
+                        PARTITION_MESSAGES_SENT_COUNTER.with_label_values(&labels),
+                        PARTITION_BYTES_SENT_COUNTER.with_label_values(&labels),
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Counters of the rows sent with keys in each bucket of the hash space, from which the
+/// boundaries of range-partitioned edges are computed
+pub fn routing_key_counters(task_info: &TaskInfo) -> Vec<IntCounter> {
+    (0..ROUTING_KEY_BUCKETS)
+        .map(|bucket| {
+            ROUTING_KEY_DISTRIBUTION_COUNTER.with_label_values(&[
+                task_info.operator_id.as_str(),
+                &task_info.task_index.to_string(),
+                &task_info.operator_name,
+                &bucket.to_string(),
+            ])
+        })
+        .collect()
+}
+
+/// The histogram of end-to-end latencies observed by a sink subtask
+pub fn sink_latency_histogram(task_info: &TaskInfo) -> Histogram {
+    SINK_LATENCY_HISTOGRAM.with_label_values(&[
+        task_info.operator_id.as_str(),
+        &task_info.task_index.to_string(),
+        &task_info.operator_name,
+    ])
+}
+
+/// Reports how far a source subtask is behind the end of each partition it reads, in records and
+/// in estimated time. The gauges are removed when this is dropped.
+pub struct SourceLagGauges {
+    operator_id: String,
+    subtask_idx: String,
+    operator_name: String,
+    partitions: HashSet<String>,
+}
+
+impl SourceLagGauges {
+    pub fn new(task_info: &TaskInfo) -> Self {
+        Self {
+            operator_id: task_info.operator_id.clone(),
+            subtask_idx: task_info.task_index.to_string(),
+            operator_name: task_info.operator_name.clone(),
+            partitions: HashSet::new(),
+        }
+    }
+
+    fn labels<'a>(&'a self, partition: &'a str) -> [&'a str; 4] {
+        [
+            &self.operator_id,
+            &self.subtask_idx,
+            &self.operator_name,
+            partition,
+        ]
+    }
+
+    pub fn set(&mut self, partition: impl ToString, records: i64, lag_ms: i64) {
+        let partition = partition.to_string();
+        let labels = self.labels(&partition);
+        SOURCE_PARTITION_LAG_GAUGE
+            .with_label_values(&labels)
+            .set(records);
+        SOURCE_PARTITION_LAG_MS_GAUGE
+            .with_label_values(&labels)
+            .set(lag_ms);
+        self.partitions.insert(partition);
+    }
+}
+
+impl Drop for SourceLagGauges {
+    fn drop(&mut self) {
+        for partition in &self.partitions {
+            let labels = self.labels(partition);
+            let _ = SOURCE_PARTITION_LAG_GAUGE.remove_label_values(&labels);
+            let _ = SOURCE_PARTITION_LAG_MS_GAUGE.remove_label_values(&labels);
+        }
+    }
+}
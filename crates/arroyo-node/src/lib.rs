@@ -16,7 +16,9 @@ use arroyo_rpc::grpc::{
     RegisterNodeReq, StartWorkerReq, StartWorkerResp, StopWorkerReq, StopWorkerResp,
     StopWorkerStatus, WorkerFinishedReq,
 };
+use arroyo_rpc::Compressed;
 use arroyo_server_common::shutdown::ShutdownGuard;
+use arroyo_server_common::tls;
 use arroyo_server_common::wrap_start;
 use arroyo_types::{
     to_millis, to_nanos, NodeId, WorkerId, ARROYO_PROGRAM_FILE_ENV, JOB_ID_ENV, RUN_ID_ENV,
@@ -113,10 +115,15 @@ impl NodeServer {
             command.env(env, value);
         }
 
+        if let Some(resource_class) = &config().node.resource_class {
+            command.env("ARROYO__WORKER__RESOURCE_CLASS", resource_class);
+        }
+
         let mut child = command
             .arg("worker")
             .env("RUST_LOG", "info")
             .env("ARROYO__WORKER__ID", format!("{}", worker_id.0))
+            .env("ARROYO__NODE__ID", format!("{}", node_id.0))
             .env(JOB_ID_ENV, req.job_id.clone())
             .env("ARROYO__WORKER__TASK_SLOTS", format!("{}", slots))
             .env(RUN_ID_ENV, format!("{}", req.run_id))
@@ -256,7 +263,7 @@ impl NodeGrpc for NodeServer {
 pub async fn start_server(guard: ShutdownGuard) -> NodeId {
     let config = config();
 
-    let node_id = NodeId(random());
+    let node_id = NodeId(config.node.id.unwrap_or_else(random));
 
     let (worker_finished_tx, mut worker_finished_rx) = channel(128);
 
@@ -279,8 +286,8 @@ pub async fn start_server(guard: ShutdownGuard) -> NodeId {
             bind_addr.clone(),
             arroyo_server_common::grpc_server()
                 .max_frame_size(Some((1 << 24) - 1)) // 16MB
-                .add_service(NodeGrpcServer::new(server))
-                .serve(bind_addr),
+                .add_service(NodeGrpcServer::new(server).compressed())
+                .serve_with_incoming(tls::bind(bind_addr)),
         ),
     );
 
@@ -302,13 +309,14 @@ pub async fn start_server(guard: ShutdownGuard) -> NodeId {
     guard.into_spawn_task(async move {
         let mut attempts = 0;
         loop {
-            match ControllerGrpcClient::connect(config.controller_endpoint()).await {
+            match tls::connect_grpc(config.controller_endpoint()).await.map(|channel| ControllerGrpcClient::new(channel).compressed()) {
                 Ok(mut controller) => {
                     controller
                         .register_node(Request::new(RegisterNodeReq {
                             node_id: node_id.0,
                             task_slots: config.node.task_slots as u64,
                             addr: req_addr.clone(),
+                            resource_class: config.node.resource_class.clone(),
                         }))
                         .await
                         .unwrap();
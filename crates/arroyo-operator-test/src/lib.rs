@@ -0,0 +1,527 @@
+//! A harness for unit testing a single operator without running a pipeline.
+//!
+//! The operator runs on its own task, exactly as it would on a worker, but its inputs, outputs
+//! and control channels are driven by the test. Tests can feed it batches, watermarks and
+//! checkpoint barriers, inspect everything it emits and reports, read back the state it
+//! checkpointed, and simulate a failure by restarting it from the most recent checkpoint.
+//!
+//! ```ignore
+//! let mut harness = OperatorHarness::new(task_info)
+//!     .with_in_schema(schema.clone())
+//!     .start(OperatorNode::from_operator(Box::new(MyOperator::new())))
+//!     .await;
+//!
+//! harness.send_batch(0, batch).await;
+//! harness.send_watermark(0, Watermark::EventTime(t)).await;
+//! let epoch = harness.checkpoint().await;
+//! let outputs = harness.drain_batches().await;
+//!
+//! harness.restart(OperatorNode::from_operator(Box::new(MyOperator::new()))).await;
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use arrow::array::RecordBatch;
+use arroyo_operator::context::{batch_bounded, ArrowContext, BatchReceiver, BatchSender};
+use arroyo_operator::operator::OperatorNode;
+use arroyo_rpc::df::ArroyoSchema;
+use arroyo_rpc::grpc::{
+    CheckpointMetadata, SubtaskCheckpointMetadata, TableConfig, TaskCheckpointCompletedReq,
+    TaskCheckpointEventReq, TaskCheckpointEventType,
+};
+use arroyo_rpc::{ControlMessage, ControlResp};
+use arroyo_state::checkpoint_state::CheckpointState;
+use arroyo_state::tables::table_manager::TableManager;
+use arroyo_state::{BackingStore, StateBackend};
+use arroyo_types::{
+    to_micros, ArrowMessage, CheckpointBarrier, Data, Key, SignalMessage, TaskInfo, Watermark,
+};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::Barrier;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+/// How long to wait for the operator to respond before failing the test
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the output must be quiet before `drain` considers it drained
+const DRAIN_QUIET_PERIOD: Duration = Duration::from_millis(100);
+
+const QUEUE_SIZE: u32 = 1024;
+
+/// Configures and starts an [`OperatorHarness`]
+pub struct OperatorHarness {
+    task_info: TaskInfo,
+    in_schemas: Vec<ArroyoSchema>,
+    out_schema: Option<ArroyoSchema>,
+    input_partitions: usize,
+    timeout: Duration,
+}
+
+impl OperatorHarness {
+    /// Creates a harness for the given subtask. The job id is made unique so that the state of
+    /// separate tests doesn't collide in the checkpoint storage.
+    pub fn new(mut task_info: TaskInfo) -> Self {
+        task_info.job_id = format!("{}-{}", task_info.job_id, rand::random::<u32>());
+        Self {
+            task_info,
+            in_schemas: vec![],
+            out_schema: None,
+            input_partitions: 1,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Adds an input to the operator; operators with more than one input (like joins) take them
+    /// in order
+    pub fn with_in_schema(mut self, schema: ArroyoSchema) -> Self {
+        self.in_schemas.push(schema);
+        self
+    }
+
+    pub fn with_out_schema(mut self, schema: ArroyoSchema) -> Self {
+        self.out_schema = Some(schema);
+        self
+    }
+
+    /// The number of upstream subtasks feeding the operator, each of which is a separate input
+    /// partition with its own watermark and barriers
+    pub fn with_input_partitions(mut self, input_partitions: usize) -> Self {
+        assert!(input_partitions > 0, "operators need at least one input");
+        self.input_partitions = input_partitions;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn start(self, node: OperatorNode) -> RunningOperator {
+        let mut running = RunningOperator {
+            harness: self,
+            epoch: 0,
+            last_checkpoint: None,
+            inputs: vec![],
+            control_tx: channel(1).0,
+            control_rx: channel(1).1,
+            output: batch_bounded(1).1,
+            tables: HashMap::new(),
+            handle: None,
+            control_log: vec![],
+            finished: false,
+        };
+        running.spawn(node, None).await;
+        running
+    }
+}
+
+/// An operator started by an [`OperatorHarness`]
+pub struct RunningOperator {
+    harness: OperatorHarness,
+    epoch: u32,
+    last_checkpoint: Option<SubtaskCheckpointMetadata>,
+    inputs: Vec<BatchSender>,
+    control_tx: Sender<ControlMessage>,
+    control_rx: Receiver<ControlResp>,
+    output: BatchReceiver,
+    tables: HashMap<String, TableConfig>,
+    handle: Option<JoinHandle<()>>,
+    control_log: Vec<ControlResp>,
+    finished: bool,
+}
+
+impl RunningOperator {
+    async fn spawn(&mut self, node: OperatorNode, restore_epoch: Option<u32>) {
+        let task_info = self.harness.task_info.clone();
+        let is_source = matches!(node, OperatorNode::Source(_));
+
+        let (control_tx, control_rx) = channel(QUEUE_SIZE as usize);
+        let (resp_tx, resp_rx) = channel(QUEUE_SIZE as usize);
+        let (out_tx, out_rx) = batch_bounded(QUEUE_SIZE);
+
+        let (inputs, in_qs): (Vec<_>, Vec<_>) = if is_source {
+            (vec![], vec![])
+        } else {
+            (0..self.harness.input_partitions)
+                .map(|_| batch_bounded(QUEUE_SIZE))
+                .unzip()
+        };
+
+        let restore_from = restore_epoch.map(|epoch| CheckpointMetadata {
+            job_id: task_info.job_id.clone(),
+            epoch,
+            min_epoch: 1,
+            start_time: 0,
+            finish_time: 0,
+            operator_ids: vec![task_info.operator_id.clone()],
+        });
+
+        self.tables = node.tables();
+
+        let ctx = ArrowContext::new(
+            task_info,
+            restore_from,
+            control_rx,
+            resp_tx,
+            self.harness.input_partitions,
+            self.harness.in_schemas.clone(),
+            self.harness.out_schema.clone(),
+            None,
+            vec![vec![out_tx]],
+            self.tables.clone(),
+            HashMap::new(),
+        )
+        .await;
+
+        self.inputs = inputs;
+        self.control_tx = control_tx;
+        self.control_rx = resp_rx;
+        self.output = out_rx;
+        self.finished = false;
+        self.handle = Some(tokio::spawn(Box::new(node).start(
+            ctx,
+            in_qs,
+            Arc::new(Barrier::new(1)),
+        )));
+    }
+
+    pub fn task_info(&self) -> &TaskInfo {
+        &self.harness.task_info
+    }
+
+    /// The epoch of the most recent completed checkpoint, or 0 if there hasn't been one
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// The metadata reported by the operator for its most recent checkpoint, including the
+    /// tables it wrote and their sizes
+    pub fn last_checkpoint(&self) -> Option<&SubtaskCheckpointMetadata> {
+        self.last_checkpoint.as_ref()
+    }
+
+    /// Control responses (errors, task lifecycle events) reported by the operator, other than
+    /// those for checkpoints
+    pub fn control_log(&self) -> &[ControlResp] {
+        &self.control_log
+    }
+
+    /// The errors reported by the operator, as (message, details)
+    pub fn errors(&self) -> Vec<(&str, &str)> {
+        self.control_log
+            .iter()
+            .filter_map(|resp| match resp {
+                ControlResp::Error {
+                    message, details, ..
+                } => Some((message.as_str(), details.as_str())),
+                ControlResp::TaskFailed { error, .. } => Some((error.as_str(), "")),
+                _ => None,
+            })
+            .collect()
+    }
+
+    async fn send_input(&self, partition: usize, message: ArrowMessage) {
+        self.inputs
+            .get(partition)
+            .unwrap_or_else(|| panic!("operator has no input partition {}", partition))
+            .send(message)
+            .await
+            .expect("operator has stopped reading its input");
+    }
+
+    pub async fn send_batch(&self, partition: usize, batch: RecordBatch) {
+        self.send_input(partition, ArrowMessage::Data(batch)).await;
+    }
+
+    pub async fn send_watermark(&self, partition: usize, watermark: Watermark) {
+        self.send_input(
+            partition,
+            ArrowMessage::Signal(SignalMessage::Watermark(watermark)),
+        )
+        .await;
+    }
+
+    /// Sends the watermark on every input partition, so that the operator's watermark advances
+    pub async fn advance_watermark(&self, watermark: SystemTime) {
+        for partition in 0..self.inputs.len() {
+            self.send_watermark(partition, Watermark::EventTime(watermark))
+                .await;
+        }
+    }
+
+    /// Sends a control message directly to the operator
+    pub async fn send_control(&self, message: ControlMessage) {
+        self.control_tx
+            .send(message)
+            .await
+            .expect("operator has stopped reading control messages");
+    }
+
+    async fn next_control(&mut self) -> ControlResp {
+        tokio::time::timeout(self.harness.timeout, self.control_rx.recv())
+            .await
+            .unwrap_or_else(|_| {
+                panic!(
+                    "timed out waiting for a control response from {}",
+                    self.harness.task_info.operator_id
+                )
+            })
+            .expect("operator control channel closed")
+    }
+
+    /// Takes a checkpoint, as the controller would: a barrier is injected into every input (or
+    /// sent to a source as a control message), and once the operator reports that it has
+    /// finished, the checkpoint is recorded so that the operator can be restored from it.
+    /// Returns the checkpoint's epoch.
+    pub async fn checkpoint(&mut self) -> u32 {
+        self.checkpoint_with(false).await
+    }
+
+    /// Takes a final checkpoint, after which the operator stops
+    pub async fn checkpoint_and_stop(&mut self) -> u32 {
+        self.checkpoint_with(true).await
+    }
+
+    async fn checkpoint_with(&mut self, then_stop: bool) -> u32 {
+        let epoch = self.epoch + 1;
+        let barrier = CheckpointBarrier {
+            epoch,
+            min_epoch: 1,
+            timestamp: SystemTime::now(),
+            then_stop,
+        };
+
+        if self.inputs.is_empty() {
+            self.send_control(ControlMessage::Checkpoint(barrier)).await;
+        } else {
+            for partition in 0..self.inputs.len() {
+                self.send_input(
+                    partition,
+                    ArrowMessage::Signal(SignalMessage::Barrier(barrier)),
+                )
+                .await;
+            }
+        }
+
+        let task_info = self.harness.task_info.clone();
+        let mut state = CheckpointState::new(
+            Arc::new(task_info.job_id.clone()),
+            format!("harness-checkpoint-{}", epoch),
+            epoch,
+            1,
+            HashMap::from([(task_info.operator_id.clone(), 1)]),
+        );
+
+        loop {
+            match self.next_control().await {
+                ControlResp::CheckpointEvent(e) if e.checkpoint_epoch == epoch => {
+                    state
+                        .checkpoint_event(TaskCheckpointEventReq {
+                            worker_id: 0,
+                            time: to_micros(e.time),
+                            job_id: task_info.job_id.clone(),
+                            operator_id: e.operator_id,
+                            subtask_index: e.subtask_index,
+                            epoch,
+                            event_type: e.event_type as i32,
+                        })
+                        .expect("invalid checkpoint event");
+                }
+                ControlResp::CheckpointCompleted(c) if c.checkpoint_epoch == epoch => {
+                    self.last_checkpoint = Some(c.subtask_metadata.clone());
+                    state
+                        .checkpoint_finished(TaskCheckpointCompletedReq {
+                            worker_id: 0,
+                            time: c.subtask_metadata.finish_time,
+                            job_id: task_info.job_id.clone(),
+                            operator_id: c.operator_id,
+                            epoch,
+                            needs_commit: false,
+                            metadata: Some(c.subtask_metadata),
+                        })
+                        .await
+                        .expect("failed to record checkpoint");
+                    break;
+                }
+                resp => self.record(resp),
+            }
+        }
+
+        assert!(state.done(), "checkpoint {} did not complete", epoch);
+        state
+            .save_state()
+            .await
+            .expect("failed to write checkpoint metadata");
+
+        let committing = state.committing_state();
+        if !committing.done() {
+            let commit_data = committing
+                .committing_data()
+                .remove(&task_info.operator_id)
+                .map(|data| {
+                    data.committing_data
+                        .into_iter()
+                        .map(|(table, data)| (table, data.commit_data_by_subtask))
+                        .collect()
+                })
+                .unwrap_or_default();
+            self.commit(epoch, commit_data).await;
+        }
+
+        debug!("harness checkpoint {} completed", epoch);
+        self.epoch = epoch;
+        epoch
+    }
+
+    /// Sends the operator the data its two-phase-commit tables pre-committed, and waits for it
+    /// to finish committing
+    async fn commit(&mut self, epoch: u32, commit_data: HashMap<String, HashMap<u32, Vec<u8>>>) {
+        self.send_control(ControlMessage::Commit { epoch, commit_data })
+            .await;
+
+        loop {
+            match self.next_control().await {
+                ControlResp::CheckpointEvent(e)
+                    if e.checkpoint_epoch == epoch
+                        && e.event_type == TaskCheckpointEventType::FinishedCommit =>
+                {
+                    return;
+                }
+                resp => self.record(resp),
+            }
+        }
+    }
+
+    fn record(&mut self, resp: ControlResp) {
+        if let ControlResp::TaskFinished { .. } = resp {
+            self.finished = true;
+        }
+        if let ControlResp::TaskFailed { error, .. } = &resp {
+            panic!(
+                "operator {} failed: {}",
+                self.harness.task_info.operator_id, error
+            );
+        }
+        self.control_log.push(resp);
+    }
+
+    /// Reads the checkpointed contents of a global keyed table, as of the most recent checkpoint
+    pub async fn global_state<K: Key, V: Data>(&self, table: &str) -> HashMap<K, V> {
+        let task_info = &self.harness.task_info;
+        let metadata = StateBackend::load_operator_metadata(
+            &task_info.job_id,
+            &task_info.operator_id,
+            self.epoch,
+        )
+        .await
+        .expect("failed to load operator metadata")
+        .unwrap_or_else(|| panic!("no checkpoint for epoch {}", self.epoch));
+
+        let mut table_manager = TableManager::new(
+            Arc::new(task_info.clone()),
+            self.tables.clone(),
+            channel(QUEUE_SIZE as usize).0,
+            Some(metadata),
+            HashMap::new(),
+        )
+        .await
+        .expect("failed to restore tables");
+
+        table_manager
+            .get_global_keyed_state::<K, V>(table)
+            .await
+            .unwrap_or_else(|e| panic!("failed to read table {}: {:?}", table, e))
+            .get_all()
+            .clone()
+    }
+
+    /// Returns the next message emitted by the operator, or None if it doesn't emit one within
+    /// `timeout`
+    pub async fn next_output(&mut self, timeout: Duration) -> Option<ArrowMessage> {
+        tokio::time::timeout(timeout, self.output.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Returns all of the messages the operator emits until it goes quiet
+    pub async fn drain(&mut self) -> Vec<ArrowMessage> {
+        let mut messages = vec![];
+        while let Some(message) = self.next_output(DRAIN_QUIET_PERIOD).await {
+            messages.push(message);
+        }
+        messages
+    }
+
+    /// Returns the batches the operator emits until it goes quiet, ignoring signals
+    pub async fn drain_batches(&mut self) -> Vec<RecordBatch> {
+        self.drain()
+            .await
+            .into_iter()
+            .filter_map(|message| match message {
+                ArrowMessage::Data(batch) => Some(batch),
+                ArrowMessage::Signal(_) => None,
+            })
+            .collect()
+    }
+
+    /// Signals the end of the input and waits for the operator to finish
+    pub async fn finish(&mut self) {
+        if self.inputs.is_empty() {
+            self.send_control(ControlMessage::Stop {
+                mode: arroyo_rpc::grpc::StopMode::Graceful,
+            })
+            .await;
+        } else {
+            for partition in 0..self.inputs.len() {
+                self.send_input(partition, ArrowMessage::Signal(SignalMessage::EndOfData))
+                    .await;
+            }
+        }
+
+        while !self.finished {
+            let resp = self.next_control().await;
+            self.record(resp);
+        }
+
+        if let Some(handle) = self.handle.take() {
+            tokio::time::timeout(self.harness.timeout, handle)
+                .await
+                .expect("timed out waiting for operator to finish")
+                .expect("operator panicked");
+        }
+    }
+
+    /// Simulates a failure: the running operator is killed without being allowed to finish, and
+    /// the new one is restored from the most recent checkpoint (or started fresh if there hasn't
+    /// been one). Output that hadn't been read is lost.
+    pub async fn restart(&mut self, node: OperatorNode) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            let _ = handle.await;
+        }
+
+        let restore_epoch = (self.epoch > 0).then_some(self.epoch);
+        self.spawn(node, restore_epoch).await;
+    }
+
+    /// Restarts the operator as a different subtask of a job with a different parallelism,
+    /// restored from the most recent checkpoint
+    pub async fn rescale(&mut self, node: OperatorNode, task_index: usize, parallelism: usize) {
+        assert!(task_index < parallelism);
+        self.harness.task_info.task_index = task_index;
+        self.harness.task_info.parallelism = parallelism;
+        self.restart(node).await;
+    }
+}
+
+impl Drop for RunningOperator {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! The source of processing time for a subtask.
+//!
+//! Subtasks normally read processing time from the system clock. For tests, they can instead use
+//! a simulated clock that follows the data: it fast-forwards to the latest event time and
+//! watermark that the subtask has received, and otherwise stands still. This makes processing
+//! time deterministic, and lets pipelines with windows of hours or days be run over fixture data
+//! in seconds.
+
+use std::time::SystemTime;
+
+use arrow::array::{AsArray, RecordBatch};
+use arrow::compute::max;
+use arrow::datatypes::TimestampNanosecondType;
+use arroyo_rpc::TIMESTAMP_FIELD;
+use arroyo_types::from_nanos;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Clock {
+    #[default]
+    System,
+    /// The current simulated time, which starts at the epoch
+    Simulated(SystemTime),
+}
+
+impl Clock {
+    pub fn simulated() -> Self {
+        Clock::Simulated(SystemTime::UNIX_EPOCH)
+    }
+
+    pub fn is_simulated(&self) -> bool {
+        matches!(self, Clock::Simulated(_))
+    }
+
+    pub fn now(&self) -> SystemTime {
+        match self {
+            Clock::System => SystemTime::now(),
+            Clock::Simulated(t) => *t,
+        }
+    }
+
+    /// Moves a simulated clock forward to `time`, if it's later than the current time
+    pub fn advance_to(&mut self, time: SystemTime) {
+        if let Clock::Simulated(t) = self {
+            *t = (*t).max(time);
+        }
+    }
+
+    /// Moves a simulated clock forward to the latest event time in the batch
+    pub fn advance_to_batch(&mut self, batch: &RecordBatch) {
+        if !self.is_simulated() {
+            return;
+        }
+
+        let Some(timestamps) = batch
+            .column_by_name(TIMESTAMP_FIELD)
+            .and_then(|c| c.as_primitive_opt::<TimestampNanosecondType>())
+        else {
+            return;
+        };
+
+        if let Some(latest) = max(timestamps) {
+            self.advance_to(from_nanos(latest.max(0) as u128));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::TimestampNanosecondArray;
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn batch(timestamps: Vec<i64>) -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new(
+                TIMESTAMP_FIELD,
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            )])),
+            vec![Arc::new(TimestampNanosecondArray::from(timestamps))],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_simulated_clock() {
+        let mut clock = Clock::simulated();
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH);
+
+        let day = Duration::from_secs(24 * 60 * 60);
+        clock.advance_to_batch(&batch(vec![
+            day.as_nanos() as i64,
+            2 * day.as_nanos() as i64,
+        ]));
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH + 2 * day);
+
+        // time never goes backwards
+        clock.advance_to(SystemTime::UNIX_EPOCH + day);
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH + 2 * day);
+
+        clock.advance_to(SystemTime::UNIX_EPOCH + 3 * day);
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH + 3 * day);
+    }
+
+    #[test]
+    fn test_system_clock_does_not_advance() {
+        let mut clock = Clock::System;
+        clock.advance_to(SystemTime::UNIX_EPOCH);
+        assert!(clock.now() > SystemTime::UNIX_EPOCH);
+    }
+}
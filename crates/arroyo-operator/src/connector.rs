@@ -3,7 +3,7 @@ use anyhow::anyhow;
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, TestSourceMessage,
 };
-use arroyo_rpc::OperatorConfig;
+use arroyo_rpc::{DeliverySemantics, OperatorConfig};
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use serde_json::value::Value;
@@ -46,6 +46,33 @@ pub trait Connector: Send {
 
     fn table_type(&self, config: Self::ProfileT, table: Self::TableT) -> ConnectionType;
 
+    /// Whether the connector's sink can consume updating inputs by upserting and deleting rows by
+    /// key (configured with the `upsert_key` option). Updating inputs come from aggregates without
+    /// windows (joins don't accept them), and arrive with `_is_retract` set on retractions; of
+    /// the built-in connectors only Kafka supports this, writing retractions as tombstones.
+    fn supports_upserts(&self) -> bool {
+        false
+    }
+
+    /// Whether the connector's sink can provide the given delivery guarantee (configured with the
+    /// `delivery_semantics` option)
+    #[allow(unused)]
+    fn supports_delivery_semantics(&self, semantics: DeliverySemantics) -> bool {
+        false
+    }
+
+    /// Whether the connector's sink can key and partition the messages it writes by SQL
+    /// expressions (configured with the `key_expression` and `partition_expression` options)
+    fn supports_partitioning_expressions(&self) -> bool {
+        false
+    }
+
+    /// Whether the connector's source can generate watermarks for each of its partitions
+    /// (configured with the `partition_watermarks` option)
+    fn supports_partition_watermarks(&self) -> bool {
+        false
+    }
+
     #[allow(unused)]
     fn get_schema(
         &self,
@@ -127,6 +154,14 @@ pub trait ErasedConnector: Send {
 
     fn config_description(&self, s: &serde_json::Value) -> Result<String, serde_json::Error>;
 
+    fn supports_upserts(&self) -> bool;
+
+    fn supports_delivery_semantics(&self, semantics: DeliverySemantics) -> bool;
+
+    fn supports_partitioning_expressions(&self) -> bool;
+
+    fn supports_partition_watermarks(&self) -> bool;
+
     fn get_schema(
         &self,
         config: &serde_json::Value,
@@ -189,6 +224,22 @@ impl<C: Connector> ErasedConnector for C {
         Ok(self.config_description(self.parse_config(s)?))
     }
 
+    fn supports_upserts(&self) -> bool {
+        self.supports_upserts()
+    }
+
+    fn supports_delivery_semantics(&self, semantics: DeliverySemantics) -> bool {
+        self.supports_delivery_semantics(semantics)
+    }
+
+    fn supports_partitioning_expressions(&self) -> bool {
+        self.supports_partitioning_expressions()
+    }
+
+    fn supports_partition_watermarks(&self) -> bool {
+        self.supports_partition_watermarks()
+    }
+
     fn validate_config(&self, config: &serde_json::Value) -> Result<(), serde_json::Error> {
         self.parse_config(config)?;
         Ok(())
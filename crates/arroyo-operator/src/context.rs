@@ -1,32 +1,49 @@
-use crate::{server_for_hash_array, RateLimiter};
+use crate::clock::Clock;
+use crate::partitioner::Partitioner;
+use crate::recording::InputRecorder;
+use crate::timers::{TimerService, TimerState, TIMER_TABLE};
+use crate::trace_context;
+use crate::{server_for_hash_array, server_for_hash_array_in_ranges, RateLimiter};
 use arrow::array::{make_builder, Array, ArrayBuilder, PrimitiveArray, RecordBatch};
-use arrow::compute::{partition, sort_to_indices, take};
+use arrow::compute::{concat_batches, partition, sort_to_indices, take};
 use arrow::datatypes::{SchemaRef, UInt64Type};
+use arroyo_datastream::logical::MAX_ROWS_PER_SECOND;
 use arroyo_formats::de::ArrowDeserializer;
-use arroyo_formats::should_flush;
-use arroyo_metrics::{register_queue_gauge, QueueGauges, TaskCounters};
+use arroyo_metrics::{
+    gauge_for_task, partition_counters, register_queue_gauge, routing_key_counters,
+    PartitionCounters, QueueGauges, TaskCounters, TaskHistograms,
+};
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::config::config;
 use arroyo_rpc::df::ArroyoSchema;
+use arroyo_rpc::fault_injection::{self, FaultKind};
 use arroyo_rpc::formats::{BadData, Format, Framing};
 use arroyo_rpc::grpc::{CheckpointMetadata, TableConfig, TaskCheckpointEventType};
 use arroyo_rpc::schema_resolver::SchemaResolver;
-use arroyo_rpc::{get_hasher, CompactionResult, ControlMessage, ControlResp};
+use arroyo_rpc::{get_hasher, BatchSettings, CompactionResult, ControlMessage, ControlResp};
 use arroyo_state::tables::table_manager::TableManager;
 use arroyo_state::{BackingStore, StateBackend};
 use arroyo_types::{
-    from_micros, ArrowMessage, CheckpointBarrier, SourceError, TaskInfo, UserError, Watermark,
+    from_micros, routing_key_bucket, to_micros, valid_range_boundaries, ArrowMessage,
+    CheckpointBarrier, SignalMessage, SourceError, TaskInfo, UserError, Watermark,
+    ROUTING_KEY_BUCKETS, WATERMARK, WATERMARK_LAG,
 };
 use datafusion::common::hash_utils;
+use datafusion::execution::memory_pool::FairSpillPool;
+use datafusion::execution::runtime_env::{RuntimeConfig, RuntimeEnv};
+use datafusion::execution::TaskContext;
+use datafusion::prelude::{SessionConfig, SessionContext};
+use prometheus::IntCounter;
 use rand::Rng;
 use std::collections::HashMap;
 use std::mem::size_of_val;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::{Instant, SystemTime};
-use tokio::sync::mpsc::error::SendError;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc::error::{SendError, TryRecvError};
 use tokio::sync::mpsc::{unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender};
 use tokio::sync::Notify;
-use tracing::warn;
+use tracing::{debug, info, warn, Span};
 
 pub type QueueItem = ArrowMessage;
 
@@ -165,20 +182,69 @@ pub struct BatchReceiver {
     queued_messages: Arc<AtomicU32>,
     queued_bytes: Arc<AtomicU64>,
     notify: Arc<Notify>,
+    // an item received while coalescing that couldn't be merged into the returned batch
+    pending: Option<QueueItem>,
 }
 
 impl BatchReceiver {
     pub async fn recv(&mut self) -> Option<QueueItem> {
+        if let Some(item) = self.pending.take() {
+            return Some(item);
+        }
+
         let item = self.rx.recv().await;
         if let Some(item) = &item {
-            let count = message_count(item, self.size);
-            self.queued_messages.fetch_sub(count, Ordering::SeqCst);
-            self.queued_bytes
-                .fetch_sub(message_bytes(item), Ordering::AcqRel);
-            self.notify.notify_waiters();
+            self.dequeued(item);
         }
         item
     }
+
+    /// Like `recv`, but merges data batches that are already queued behind the next item into it,
+    /// up to `max_rows` rows. This never waits for more data to arrive, and never merges batches
+    /// across signals.
+    pub async fn recv_coalesced(&mut self, max_rows: usize) -> Option<QueueItem> {
+        let first = match self.recv().await? {
+            ArrowMessage::Data(batch) if batch.num_rows() < max_rows => batch,
+            item => return Some(item),
+        };
+
+        let mut rows = first.num_rows();
+        let mut batches = vec![first];
+        while rows < max_rows {
+            match self.rx.try_recv() {
+                Ok(item) => {
+                    self.dequeued(&item);
+                    match item {
+                        ArrowMessage::Data(batch) if rows + batch.num_rows() <= max_rows => {
+                            rows += batch.num_rows();
+                            batches.push(batch);
+                        }
+                        item => {
+                            self.pending = Some(item);
+                            break;
+                        }
+                    }
+                }
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => break,
+            }
+        }
+
+        if batches.len() == 1 {
+            return batches.pop().map(ArrowMessage::Data);
+        }
+
+        let batch = concat_batches(&batches[0].schema(), &batches)
+            .expect("batches from the same input should have the same schema");
+        Some(ArrowMessage::Data(batch))
+    }
+
+    fn dequeued(&self, item: &QueueItem) {
+        let count = message_count(item, self.size);
+        self.queued_messages.fetch_sub(count, Ordering::SeqCst);
+        self.queued_bytes
+            .fetch_sub(message_bytes(item), Ordering::AcqRel);
+        self.notify.notify_waiters();
+    }
 }
 
 pub fn batch_bounded(size: u32) -> (BatchSender, BatchReceiver) {
@@ -200,6 +266,7 @@ pub fn batch_bounded(size: u32) -> (BatchSender, BatchReceiver) {
             notify,
             queued_bytes,
             queued_messages,
+            pending: None,
         },
     )
 }
@@ -229,8 +296,8 @@ impl ContextBuffer {
         self.buffer[0].len()
     }
 
-    pub fn should_flush(&self) -> bool {
-        should_flush(self.size(), self.created)
+    pub fn should_flush(&self, settings: &BatchSettings) -> bool {
+        settings.should_flush(self.size(), self.created)
     }
 
     pub fn finish(self) -> RecordBatch {
@@ -251,11 +318,109 @@ pub struct ArrowContext {
     pub in_schemas: Vec<ArroyoSchema>,
     pub out_schema: Option<ArroyoSchema>,
     pub collector: ArrowCollector,
+    pub batch_settings: BatchSettings,
+    // with adaptive batching, the settings for the next batch, which may have been grown past
+    // `batch_settings` while the outputs are backpressured
+    adapted_batch_settings: Option<BatchSettings>,
     buffer: Option<ContextBuffer>,
     buffered_error: Option<UserError>,
     error_rate_limiter: RateLimiter,
     deserializer: Option<ArrowDeserializer>,
     pub table_manager: TableManager,
+    task_context: Arc<TaskContext>,
+    // micros since the epoch of the last event-time watermark emitted by this subtask, or 0 if
+    // it has not emitted one or is idle
+    emitted_watermark: Arc<AtomicU64>,
+    /// For sources in pipelines with recording enabled, records the batches the source emits
+    pub recorder: Option<InputRecorder>,
+    /// Per-key event-time timers, for operators that include the timer table in their `tables()`
+    pub timers: TimerService,
+    /// Where processing time comes from; operators should read it with [`ArrowContext::now`]
+    pub clock: Clock,
+}
+
+/// Creates the DataFusion context for a task, with a memory pool limited to the
+/// `worker.task-memory-limit` if one is configured
+fn task_context(task_info: &TaskInfo) -> Arc<TaskContext> {
+    let mut runtime_config = RuntimeConfig::new();
+    if let Some(limit) = config().worker.task_memory_limit {
+        runtime_config = runtime_config.with_memory_pool(Arc::new(FairSpillPool::new(limit)));
+    }
+
+    let runtime = RuntimeEnv::new(runtime_config).unwrap_or_else(|e| {
+        panic!(
+            "failed to create runtime for {}-{}: {:?}",
+            task_info.operator_id, task_info.task_index, e
+        )
+    });
+
+    SessionContext::new_with_config_rt(SessionConfig::new(), Arc::new(runtime)).task_ctx()
+}
+
+/// Prefix of the panic message when a subtask fails to restore its state from a checkpoint, which
+/// allows the failure to be categorized
+pub const STATE_RESTORE_FAILURE: &str = "failed to restore state";
+
+/// Name of the error returned by the deserializer when a source is configured to fail on bad data
+pub const DESERIALIZATION_ERROR: &str = "Deserialization error";
+
+const WATERMARK_LAG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Periodically reports the subtask's emitted watermark and the difference between wall-clock
+/// time and that watermark, so that the lag keeps growing if event time stalls. Stops once the
+/// subtask's context has been dropped.
+fn report_watermark_lag(task_info: &TaskInfo, emitted_watermark: &Arc<AtomicU64>) {
+    let Some(lag_gauge) = gauge_for_task(
+        task_info,
+        WATERMARK_LAG,
+        "Milliseconds between the current time and the watermark emitted by this subtask",
+        HashMap::new(),
+    ) else {
+        return;
+    };
+
+    let Some(watermark_gauge) = gauge_for_task(
+        task_info,
+        WATERMARK,
+        "The watermark emitted by this subtask, in milliseconds since the epoch",
+        HashMap::new(),
+    ) else {
+        let _ = prometheus::unregister(Box::new(lag_gauge));
+        return;
+    };
+
+    let emitted_watermark = Arc::downgrade(emitted_watermark);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(WATERMARK_LAG_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            let Some(watermark) = emitted_watermark.upgrade() else {
+                break;
+            };
+
+            let (watermark, lag) = match watermark.load(Ordering::Relaxed) {
+                0 => (0, 0),
+                micros => (
+                    (micros / 1000) as i64,
+                    SystemTime::now()
+                        .duration_since(from_micros(micros))
+                        .unwrap_or_default()
+                        .as_millis() as i64,
+                ),
+            };
+
+            watermark_gauge.set(watermark);
+            lag_gauge.set(lag);
+        }
+
+        // allow the gauges to be registered again if the subtask is restarted in this process
+        let _ = prometheus::unregister(Box::new(watermark_gauge));
+        let _ = prometheus::unregister(Box::new(lag_gauge));
+    });
 }
 
 #[derive(Clone)]
@@ -265,19 +430,38 @@ pub struct ErrorReporter {
 }
 
 impl ErrorReporter {
-    pub async fn report_error(&mut self, message: impl Into<String>, details: impl Into<String>) {
+    pub async fn report_error(
+        &mut self,
+        category: ErrorCategory,
+        message: impl Into<String>,
+        details: impl Into<String>,
+    ) {
         self.tx
             .send(ControlResp::Error {
                 operator_id: self.task_info.operator_id.clone(),
                 task_index: self.task_info.task_index,
                 message: message.into(),
                 details: details.into(),
+                category,
             })
             .await
             .unwrap();
     }
 }
 
+/// How the collector routes rows to the subtasks of one of its outputs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Exchange {
+    /// keyed rows are routed by the hash of their key (or its range, for range-partitioned
+    /// outputs), and unkeyed rows are spread evenly
+    #[default]
+    Hash,
+    /// rows are spread evenly, regardless of their keys
+    RoundRobin,
+    /// every row is sent to every subtask
+    Broadcast,
+}
+
 #[derive(Clone)]
 pub struct ArrowCollector {
     task_info: Arc<TaskInfo>,
@@ -287,45 +471,85 @@ pub struct ArrowCollector {
     tx_queue_rem_gauges: QueueGauges,
     tx_queue_size_gauges: QueueGauges,
     tx_queue_bytes_gauges: QueueGauges,
+    // only tracked for keyed exchanges, where skewed keys can overload a single partition
+    partition_counters: PartitionCounters,
+    // for each output, the boundaries to use if it's range partitioned
+    range_boundaries: Vec<Option<Vec<u64>>>,
+    // for each output, how rows are routed; outputs without one are hash partitioned
+    exchanges: Vec<Exchange>,
+    // only tracked if there are range-partitioned outputs
+    routing_key_counters: Vec<IntCounter>,
+    propagate_trace_context: bool,
+    // limits the rate of rows sent, set through the `max_rows_per_second` runtime parameter
+    throttle: Option<Throttle>,
+    // routes rows in place of the planner's partitioning, if set by the operator
+    partitioner: Option<Arc<dyn Partitioner>>,
+}
+
+/// Paces batches so that on average no more than `rows_per_second` rows are sent
+#[derive(Clone, Debug)]
+struct Throttle {
+    rows_per_second: f64,
+    next_send: Instant,
+}
+
+impl Throttle {
+    /// How long to wait before sending a batch of `rows`
+    fn delay(&mut self, now: Instant, rows: usize) -> Duration {
+        let start = self.next_send.max(now);
+        self.next_send = start + Duration::from_secs_f64(rows as f64 / self.rows_per_second);
+        start - now
+    }
+}
+
+fn routing_hashes(record: &RecordBatch, keys: &[usize]) -> PrimitiveArray<UInt64Type> {
+    let mut buf = vec![0; record.num_rows()];
+    let keys: Vec<_> = keys.iter().map(|i| record.column(*i).clone()).collect();
+    hash_utils::create_hashes(&keys[..], &get_hasher(), &mut buf).unwrap();
+    PrimitiveArray::from(buf)
+}
+
+/// Splits a batch into a batch for each of the partitions its rows are assigned to by `servers`
+fn split_by_partition(
+    record: &RecordBatch,
+    servers: &PrimitiveArray<UInt64Type>,
+) -> Vec<(usize, RecordBatch)> {
+    let indices = sort_to_indices(servers, None, None).unwrap();
+    let columns = record
+        .columns()
+        .iter()
+        .map(|c| take(c, &indices, None).unwrap())
+        .collect();
+    let sorted = RecordBatch::try_new(record.schema(), columns).unwrap();
+    let sorted_keys = take(servers, &indices, None).unwrap();
+
+    let partition: arrow::compute::Partitions =
+        partition(vec![sorted_keys.clone()].as_slice()).unwrap();
+    let typed_keys: &PrimitiveArray<UInt64Type> = sorted_keys.as_any().downcast_ref().unwrap();
+    partition
+        .ranges()
+        .into_iter()
+        .map(|range| {
+            let server_batch = sorted.slice(range.start, range.end - range.start);
+            let server_id = typed_keys.value(range.start) as usize;
+            (server_id, server_batch)
+        })
+        .collect()
 }
 
 fn repartition<'a>(
     record: &'a RecordBatch,
-    keys: &'a Option<Vec<usize>>,
+    hashes: Option<&PrimitiveArray<UInt64Type>>,
+    range_boundaries: Option<&[u64]>,
     qs: usize,
 ) -> impl Iterator<Item = (usize, RecordBatch)> + 'a {
-    let mut buf = vec![0; record.num_rows()];
-
-    if let Some(keys) = keys {
-        let keys: Vec<_> = keys.iter().map(|i| record.column(*i).clone()).collect();
-
-        hash_utils::create_hashes(&keys[..], &get_hasher(), &mut buf).unwrap();
-        let buf_array = PrimitiveArray::from(buf);
-
-        let servers = server_for_hash_array(&buf_array, qs).unwrap();
+    if let Some(hashes) = hashes {
+        let servers = match range_boundaries {
+            Some(boundaries) => server_for_hash_array_in_ranges(hashes, boundaries),
+            None => server_for_hash_array(hashes, qs).unwrap(),
+        };
 
-        let indices = sort_to_indices(&servers, None, None).unwrap();
-        let columns = record
-            .columns()
-            .iter()
-            .map(|c| take(c, &indices, None).unwrap())
-            .collect();
-        let sorted = RecordBatch::try_new(record.schema(), columns).unwrap();
-        let sorted_keys = take(&servers, &indices, None).unwrap();
-
-        let partition: arrow::compute::Partitions =
-            partition(vec![sorted_keys.clone()].as_slice()).unwrap();
-        let typed_keys: &PrimitiveArray<UInt64Type> = sorted_keys.as_any().downcast_ref().unwrap();
-        let result: Vec<_> = partition
-            .ranges()
-            .into_iter()
-            .map(|range| {
-                let server_batch = sorted.slice(range.start, range.end - range.start);
-                let server_id = typed_keys.value(range.start) as usize;
-                (server_id, server_batch)
-            })
-            .collect();
-        result.into_iter()
+        split_by_partition(record, &servers).into_iter()
     } else {
         let range_size = record.num_rows() / qs + 1;
         let rotation = rand::thread_rng().gen_range(0..qs);
@@ -346,7 +570,97 @@ fn repartition<'a>(
 }
 
 impl ArrowCollector {
+    /// The fraction of the fullest output queue that is in use
+    pub fn queue_utilization(&self) -> f64 {
+        self.out_qs
+            .iter()
+            .flatten()
+            .map(|q| 1.0 - q.capacity() as f64 / q.size().max(1) as f64)
+            .fold(0.0, f64::max)
+    }
+
+    /// Sets how each of the collector's outputs is partitioned; `Some` for outputs that are range
+    /// partitioned, with the boundaries to use if they've been computed
+    pub fn set_range_partitioning(&mut self, range_boundaries: Vec<Option<Vec<u64>>>) {
+        self.routing_key_counters = if range_boundaries.iter().any(|b| b.is_some()) {
+            routing_key_counters(&self.task_info)
+        } else {
+            vec![]
+        };
+        self.range_boundaries = range_boundaries;
+    }
+
+    /// Sets how rows are routed to the subtasks of each of the collector's outputs
+    pub fn set_exchanges(&mut self, exchanges: Vec<Exchange>) {
+        self.exchanges = exchanges;
+    }
+
+    fn record_routing_keys(&self, hashes: &PrimitiveArray<UInt64Type>) {
+        let mut counts = [0u64; ROUTING_KEY_BUCKETS];
+        for hash in hashes.values() {
+            counts[routing_key_bucket(*hash)] += 1;
+        }
+
+        for (counter, count) in self.routing_key_counters.iter().zip(counts) {
+            if count > 0 {
+                counter.inc_by(count);
+            }
+        }
+    }
+
+    /// The number of outputs (out-edges) of the operator
+    pub fn outputs(&self) -> usize {
+        self.out_qs.len()
+    }
+
+    /// The number of partitions (downstream subtasks) of one of the operator's outputs
+    pub fn partitions(&self, output: usize) -> usize {
+        self.out_qs[output].len()
+    }
+
+    /// Routes the rows of each batch with `partitioner` instead of the planner's partitioning
+    pub fn set_partitioner(&mut self, partitioner: Arc<dyn Partitioner>) {
+        self.partitioner = Some(partitioner);
+    }
+
     pub async fn collect(&mut self, record: RecordBatch) {
+        self.send(record, None, None).await;
+    }
+
+    /// Sends a batch to a single one of the operator's outputs, rather than to all of them
+    pub async fn collect_to(&mut self, output: usize, record: RecordBatch) {
+        assert!(
+            output < self.out_qs.len(),
+            "operator {} has no output {}",
+            self.task_info.operator_id,
+            output
+        );
+        self.send(record, Some(output), None).await;
+    }
+
+    /// Sends a whole batch to one partition (downstream subtask) of each of the operator's
+    /// outputs, bypassing the planner's partitioning and any partitioner
+    pub async fn collect_to_partition(&mut self, partition: usize, record: RecordBatch) {
+        for (i, q) in self.out_qs.iter().enumerate() {
+            assert!(
+                partition < q.len(),
+                "output {} of operator {} has no partition {}",
+                i,
+                self.task_info.operator_id,
+                partition
+            );
+        }
+        self.send(record, None, Some(partition)).await;
+    }
+
+    async fn send(&mut self, record: RecordBatch, output: Option<usize>, partition: Option<usize>) {
+        if let Some(throttle) = &mut self.throttle {
+            let delay = throttle.delay(Instant::now(), record.num_rows());
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+        }
+
         TaskCounters::MessagesSent
             .for_task(&self.task_info, |c| c.inc_by(record.num_rows() as u64));
         TaskCounters::BatchesSent.for_task(&self.task_info, |c| c.inc());
@@ -375,10 +689,81 @@ impl ArrowCollector {
                 );
             });
 
+        let traceparent = self
+            .propagate_trace_context
+            .then(|| trace_context::traceparent(&Span::current()))
+            .flatten();
+
+        let total_rows = record.num_rows().max(1);
+        let total_bytes = if self.partition_counters.is_empty() {
+            0
+        } else {
+            record.get_array_memory_size()
+        };
+
+        let hashes = out_schema
+            .key_indices
+            .as_ref()
+            .map(|keys| routing_hashes(&record, keys));
+
+        if let Some(hashes) = &hashes {
+            if !self.routing_key_counters.is_empty() {
+                self.record_routing_keys(hashes);
+            }
+        }
+
         for (i, out_q) in self.out_qs.iter_mut().enumerate() {
-            let partitions = repartition(&record, &out_schema.key_indices, out_q.len());
+            if output.is_some_and(|o| o != i) {
+                continue;
+            }
+
+            let partitions: Vec<_> = if let Some(partition) = partition {
+                vec![(partition, record.clone())]
+            } else if let Some(partitioner) = &self.partitioner {
+                let servers = partitioner.partition(&record, out_q.len());
+                assert!(
+                    servers.len() == record.num_rows()
+                        && servers.values().iter().all(|p| (*p as usize) < out_q.len()),
+                    "partitioner for operator {} must assign each row a partition less than {}",
+                    self.task_info.operator_id,
+                    out_q.len()
+                );
+                split_by_partition(&record, &servers)
+            } else {
+                match self.exchanges.get(i).copied().unwrap_or_default() {
+                    Exchange::Hash => {
+                        let boundaries = self
+                            .range_boundaries
+                            .get(i)
+                            .and_then(|b| b.as_deref())
+                            .filter(|b| valid_range_boundaries(b, out_q.len()));
+                        repartition(&record, hashes.as_ref(), boundaries, out_q.len()).collect()
+                    }
+                    Exchange::RoundRobin => repartition(&record, None, None, out_q.len()).collect(),
+                    Exchange::Broadcast => (0..out_q.len()).map(|p| (p, record.clone())).collect(),
+                }
+            };
 
             for (partition, batch) in partitions {
+                if let Some((messages, bytes)) = self
+                    .partition_counters
+                    .get(i)
+                    .and_then(|c| c.get(partition))
+                {
+                    messages.inc_by(batch.num_rows() as u64);
+                    // partitions are slices of the same buffers, so attribute bytes by row count
+                    bytes.inc_by((total_bytes * batch.num_rows() / total_rows) as u64);
+                }
+
+                if let Some(traceparent) = &traceparent {
+                    out_q[partition]
+                        .send(ArrowMessage::Signal(SignalMessage::TraceContext(
+                            traceparent.clone(),
+                        )))
+                        .await
+                        .unwrap();
+                }
+
                 out_q[partition]
                     .send(ArrowMessage::Data(batch))
                     .await
@@ -426,17 +811,31 @@ impl ArrowContext {
         projection: Option<Vec<usize>>,
         out_qs: Vec<Vec<BatchSender>>,
         tables: HashMap<String, TableConfig>,
+        bootstrap: HashMap<String, String>,
     ) -> Self {
         let (watermark, metadata) = if let Some(metadata) = restore_from {
             let (watermark, operator_metadata) = {
+                // when replaying, the checkpoint belongs to the job being replayed
+                let job_id = if metadata.job_id.is_empty() {
+                    &task_info.job_id
+                } else {
+                    &metadata.job_id
+                };
                 let metadata = StateBackend::load_operator_metadata(
-                    &task_info.job_id,
+                    job_id,
                     &task_info.operator_id,
                     metadata.epoch,
                 )
                 .await
-                .expect("lookup should succeed")
-                .expect("require metadata");
+                .unwrap_or_else(|e| {
+                    panic!("{STATE_RESTORE_FAILURE}: could not load operator metadata: {e:?}")
+                })
+                .unwrap_or_else(|| {
+                    panic!(
+                        "{STATE_RESTORE_FAILURE}: no operator metadata for epoch {}",
+                        metadata.epoch
+                    )
+                });
                 (
                     metadata
                         .operator_metadata
@@ -477,6 +876,19 @@ impl ArrowContext {
             0,
         );
 
+        let partition_counters = if out_schema
+            .as_ref()
+            .map(|s| s.key_indices.is_some())
+            .unwrap_or(false)
+        {
+            partition_counters(&task_info, &out_qs)
+        } else {
+            vec![]
+        };
+
+        let emitted_watermark = Arc::new(AtomicU64::new(0));
+        report_watermark_lag(&task_info, &emitted_watermark);
+
         let task_info = Arc::new(task_info);
 
         // initialize counters so that tasks that never produce data still report 0
@@ -484,11 +896,23 @@ impl ArrowContext {
             // just initialize it
             m.for_task(&task_info, |_| {});
         }
+        for m in TaskHistograms::variants() {
+            m.for_task(&task_info, |_| {});
+        }
 
-        let table_manager =
-            TableManager::new(task_info.clone(), tables, control_tx.clone(), metadata)
-                .await
-                .expect("should be able to create TableManager");
+        let table_manager = TableManager::new(
+            task_info.clone(),
+            tables,
+            control_tx.clone(),
+            metadata,
+            bootstrap,
+        )
+        .await
+        .unwrap_or_else(|e| {
+            panic!("{STATE_RESTORE_FAILURE}: could not create table manager: {e:?}")
+        });
+
+        let task_context = task_context(&task_info);
 
         Self {
             task_info: task_info.clone(),
@@ -506,19 +930,79 @@ impl ArrowContext {
                 tx_queue_rem_gauges,
                 tx_queue_size_gauges,
                 tx_queue_bytes_gauges,
+                partition_counters,
+                range_boundaries: vec![],
+                exchanges: vec![],
+                routing_key_counters: vec![],
                 out_schema: out_schema.clone(),
                 projection,
+                propagate_trace_context: trace_context::propagation_enabled(),
+                throttle: None,
+                partitioner: None,
             },
             error_reporter: ErrorReporter {
                 tx: control_tx,
                 task_info,
             },
+            batch_settings: BatchSettings::default(),
+            adapted_batch_settings: None,
             buffer: out_schema.map(|t| ContextBuffer::new(t.schema)),
             error_rate_limiter: RateLimiter::new(),
             deserializer: None,
             buffered_error: None,
             table_manager,
+            task_context,
+            emitted_watermark,
+            recorder: None,
+            timers: TimerService::from_config(),
+            clock: Clock::System,
+        }
+    }
+
+    /// The current processing time, which is simulated in tests that run with simulated time
+    pub fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    /// Restores the timers for the keys routed to this subtask from the timer table
+    pub async fn restore_timers(&mut self) {
+        let table = self
+            .table_manager
+            .get_global_keyed_state::<usize, TimerState>(TIMER_TABLE)
+            .await
+            .expect("operator with timers must register the timer table");
+
+        // every subtask sees the timers of all of them, so only restore keys routed to this one
+        let key_range = &self.task_info.key_range;
+        for state in table.get_all().values() {
+            self.timers.restore(state, |hash| key_range.contains(&hash));
         }
+
+        if !self.timers.is_empty() {
+            debug!(
+                "restored {} timers for {}-{}",
+                self.timers.len(),
+                self.task_info.operator_name,
+                self.task_info.task_index
+            );
+        }
+    }
+
+    pub async fn checkpoint_timers(&mut self) {
+        let state = self.timers.state();
+        self.table_manager
+            .get_global_keyed_state::<usize, TimerState>(TIMER_TABLE)
+            .await
+            .expect("operator with timers must register the timer table")
+            .insert(self.task_info.task_index, state)
+            .await;
+    }
+
+    /// The DataFusion context that operators should execute plans with. Its memory pool enforces
+    /// the task's memory budget, so that joins, aggregations and sorts spill to disk or fail with
+    /// a resources-exhausted error when they would exceed it.
+    pub fn task_ctx(&self) -> Arc<TaskContext> {
+        self.task_context.clone()
     }
 
     pub fn watermark(&self) -> Option<Watermark> {
@@ -535,19 +1019,29 @@ impl ArrowContext {
         }
 
         if self.buffer.as_ref().unwrap().size() > 0 {
+            self.inject_source_faults().await;
             let buffer = self.buffer.take().unwrap();
             let batch = buffer.finish();
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record(&batch);
+            }
             self.collector.collect(batch).await;
             self.buffer = Some(ContextBuffer::new(
                 self.out_schema.as_ref().map(|t| t.schema.clone()).unwrap(),
             ));
+            self.adapt_batch_settings();
         }
 
         if let Some(deserializer) = self.deserializer.as_mut() {
             if let Some(buffer) = deserializer.flush_buffer() {
                 match buffer {
                     Ok(batch) => {
+                        self.inject_source_faults().await;
+                        if let Some(recorder) = &mut self.recorder {
+                            recorder.record(&batch);
+                        }
                         self.collector.collect(batch).await;
+                        self.adapt_batch_settings();
                     }
                     Err(e) => {
                         self.collect_source_errors(vec![e]).await?;
@@ -564,44 +1058,135 @@ impl ArrowContext {
     }
 
     pub async fn collect(&mut self, record: RecordBatch) {
+        self.inject_source_faults().await;
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&record);
+        }
         self.collector.collect(record).await;
     }
 
+    /// Sends a whole batch to one partition (downstream subtask) of each of the operator's
+    /// outputs; see [`crate::partitioner`]
+    pub async fn collect_to_partition(&mut self, partition: usize, record: RecordBatch) {
+        self.inject_source_faults().await;
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&record);
+        }
+        self.collector.collect_to_partition(partition, record).await;
+    }
+
+    /// Routes the operator's output with `partitioner` instead of the planner's partitioning;
+    /// see [`crate::partitioner`]
+    pub fn set_partitioner(&mut self, partitioner: impl Partitioner) {
+        self.collector.set_partitioner(Arc::new(partitioner));
+    }
+
+    /// Sources check for injected faults before emitting each batch; other operators check when
+    /// they receive one
+    async fn inject_source_faults(&self) {
+        if !self.in_schemas.is_empty() || !fault_injection::enabled() {
+            return;
+        }
+
+        fault_injection::maybe_kill(&self.task_info.operator_id, self.task_info.task_index);
+        fault_injection::maybe_pause(
+            FaultKind::StallSource,
+            &self.task_info.operator_id,
+            self.task_info.task_index,
+        )
+        .await;
+    }
+
     pub fn should_flush(&self) -> bool {
+        let settings = self.current_batch_settings();
         self.buffer
             .as_ref()
-            .map(|b| b.should_flush())
+            .map(|b| b.should_flush(&settings))
             .unwrap_or(false)
             || self
                 .deserializer
                 .as_ref()
-                .map(|d| d.should_flush())
+                .map(|d| d.should_flush(&settings))
                 .unwrap_or(false)
     }
 
+    /// The settings that buffered source rows are currently batched with
+    pub fn current_batch_settings(&self) -> BatchSettings {
+        self.adapted_batch_settings.unwrap_or(self.batch_settings)
+    }
+
+    /// Updates the adaptive batch settings after a source emits a batch; sources that build their
+    /// own batches rather than using the context's buffer should call this after collecting each
+    pub fn adapt_batch_settings(&mut self) {
+        if !self.batch_settings.adaptive {
+            return;
+        }
+
+        let current = self.current_batch_settings();
+        let adapted = current.adapt(&self.batch_settings, self.collector.queue_utilization());
+        if adapted != current {
+            debug!(
+                "{}-{} adjusted batching to {} rows with {:?} linger",
+                self.task_info.operator_id,
+                self.task_info.task_index,
+                adapted.max_rows,
+                adapted.linger
+            );
+        }
+        self.adapted_batch_settings = Some(adapted);
+    }
+
     pub async fn broadcast(&mut self, message: ArrowMessage) {
         if let Err(e) = self.flush_buffer().await {
             self.buffered_error.replace(e);
         }
+
+        if let ArrowMessage::Signal(SignalMessage::Watermark(watermark)) = &message {
+            let micros = match watermark {
+                Watermark::EventTime(t) => to_micros(*t),
+                Watermark::Idle => 0,
+            };
+            self.emitted_watermark.store(micros, Ordering::Relaxed);
+        }
+
         self.collector.broadcast(message).await;
     }
 
-    pub async fn report_error(&mut self, message: impl Into<String>, details: impl Into<String>) {
-        self.error_reporter.report_error(message, details).await;
+    pub async fn report_error(
+        &mut self,
+        category: ErrorCategory,
+        message: impl Into<String>,
+        details: impl Into<String>,
+    ) {
+        self.error_reporter
+            .report_error(category, message, details)
+            .await;
     }
 
-    pub async fn report_user_error(&mut self, error: UserError) {
+    pub async fn report_user_error(&mut self, category: ErrorCategory, error: UserError) {
         self.control_tx
             .send(ControlResp::Error {
                 operator_id: self.task_info.operator_id.clone(),
                 task_index: self.task_info.task_index,
                 message: error.name,
                 details: error.details,
+                category,
             })
             .await
             .unwrap();
     }
 
+    /// Reports an error returned by a source; errors raised by the context's deserializer are
+    /// categorized as deserialization errors, and anything else as an error in the connector
+    pub async fn report_source_error(&mut self, error: UserError) {
+        let category = if error.name == DESERIALIZATION_ERROR {
+            ErrorCategory::Deserialization
+        } else {
+            ErrorCategory::Connector
+        };
+        self.report_user_error(category, error).await;
+    }
+
     pub async fn send_checkpoint_event(
         &mut self,
         barrier: CheckpointBarrier,
@@ -621,6 +1206,22 @@ impl ArrowContext {
             .unwrap();
     }
 
+    /// Applies the runtime parameters that are handled by the context rather than the operator;
+    /// sources call this when they receive `ControlMessage::UpdateParameters`
+    pub fn update_parameters(&mut self, parameters: &HashMap<String, f64>) {
+        if let Some(limit) = parameters.get(MAX_ROWS_PER_SECOND.name) {
+            self.collector.throttle = (*limit > 0.0).then(|| Throttle {
+                rows_per_second: *limit,
+                next_send: Instant::now(),
+            });
+        }
+
+        info!(
+            "updated parameters of {}-{} to {:?}",
+            self.task_info.operator_id, self.task_info.task_index, parameters
+        );
+    }
+
     pub async fn load_compacted(&mut self, compaction: CompactionResult) {
         //TODO: support compaction in the table manager
         self.table_manager
@@ -672,12 +1273,13 @@ impl ArrowContext {
             .deserializer
             .as_mut()
             .expect("deserializer not initialized!");
+        let buffer = self.buffer.as_mut().expect("no out schema");
+        if buffer.size() == 0 {
+            // linger is measured from the first buffered row
+            buffer.created = Instant::now();
+        }
         let errors = deserializer
-            .deserialize_slice(
-                &mut self.buffer.as_mut().expect("no out schema").buffer,
-                msg,
-                time,
-            )
+            .deserialize_slice(&mut buffer.buffer, msg, time)
             .await;
         self.collect_source_errors(errors).await?;
 
@@ -705,6 +1307,7 @@ impl ArrowContext {
                                         task_index: self.task_info.task_index,
                                         message: "Dropping invalid data".to_string(),
                                         details,
+                                        category: ErrorCategory::Deserialization,
                                     })
                                     .await
                                     .unwrap();
@@ -713,7 +1316,7 @@ impl ArrowContext {
                         TaskCounters::DeserializationErrors.for_task(&self.task_info, |c| c.inc())
                     }
                     BadData::Fail {} => {
-                        return Err(UserError::new("Deserialization error", details));
+                        return Err(UserError::new(DESERIALIZATION_ERROR, details));
                     }
                 },
                 SourceError::Other { name, details } => {
@@ -728,13 +1331,57 @@ impl ArrowContext {
 
 #[cfg(test)]
 mod tests {
-    use arrow::array::{ArrayRef, Int64Array, TimestampNanosecondArray, UInt64Array};
+    use arrow::array::{ArrayRef, AsArray, Int64Array, TimestampNanosecondArray, UInt64Array};
     use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
     use arroyo_types::to_nanos;
     use std::time::Duration;
 
     use super::*;
 
+    #[test]
+    fn test_adaptive_batch_settings() {
+        let configured = BatchSettings {
+            max_rows: 100,
+            linger: Duration::from_millis(10),
+            adaptive: true,
+        };
+
+        let mut settings = configured;
+        for _ in 0..10 {
+            settings = settings.adapt(&configured, 1.0);
+        }
+        assert_eq!(settings.max_rows, 1600);
+        assert_eq!(settings.linger, Duration::from_millis(160));
+
+        // settings are held while the queues are partially full
+        assert_eq!(settings.adapt(&configured, 0.5), settings);
+
+        let settings = settings.adapt(&configured, 0.0);
+        assert_eq!(settings.max_rows, 800);
+        assert_eq!(settings.linger, Duration::from_millis(80));
+
+        // but never shrink below the configured settings
+        assert_eq!(configured.adapt(&configured, 0.0), configured);
+    }
+
+    #[test]
+    fn test_throttle() {
+        let now = Instant::now();
+        let mut throttle = Throttle {
+            rows_per_second: 100.0,
+            next_send: now,
+        };
+
+        assert_eq!(throttle.delay(now, 50), Duration::ZERO);
+        assert_eq!(throttle.delay(now, 50), Duration::from_millis(500));
+        assert_eq!(throttle.delay(now, 10), Duration::from_secs(1));
+
+        // time that passes without sending isn't banked
+        let later = now + Duration::from_secs(10);
+        assert_eq!(throttle.delay(later, 100), Duration::ZERO);
+        assert_eq!(throttle.delay(later, 100), Duration::from_secs(1));
+    }
+
     #[test]
     fn test_watermark_holder() {
         let t1 = SystemTime::UNIX_EPOCH;
@@ -762,6 +1409,127 @@ mod tests {
         assert_eq!(w.watermark(), Some(Watermark::Idle));
     }
 
+    fn rows(values: impl IntoIterator<Item = u64>) -> QueueItem {
+        let schema = Arc::new(Schema::new(vec![Field::new("v", DataType::UInt64, false)]));
+        ArrowMessage::Data(
+            RecordBatch::try_new(
+                schema,
+                vec![Arc::new(UInt64Array::from_iter_values(values))],
+            )
+            .unwrap(),
+        )
+    }
+
+    fn values(item: Option<QueueItem>) -> Vec<u64> {
+        match item {
+            Some(ArrowMessage::Data(batch)) => batch
+                .column(0)
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            other => panic!("expected data, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recv_coalesced_batching_limits() {
+        let (tx, mut rx) = batch_bounded(100);
+
+        for batch in [rows(1..=2), rows(3..=5), rows([6]), rows(7..=10)] {
+            tx.send(batch).await.unwrap();
+        }
+        // merges queued batches until the limit is reached...
+        assert_eq!(
+            values(rx.recv_coalesced(6).await),
+            (1..=6).collect::<Vec<_>>()
+        );
+        // ...and returns what's queued once it runs out, without waiting for more
+        assert_eq!(
+            values(rx.recv_coalesced(6).await),
+            (7..=10).collect::<Vec<_>>()
+        );
+
+        // a batch that would go over the limit is returned on its own by the next call
+        for batch in [rows(1..=3), rows(4..=6)] {
+            tx.send(batch).await.unwrap();
+        }
+        assert_eq!(values(rx.recv_coalesced(4).await), vec![1, 2, 3]);
+        assert_eq!(values(rx.recv_coalesced(4).await), vec![4, 5, 6]);
+
+        // as is a batch that's already at the limit
+        for batch in [rows(1..=8), rows([9])] {
+            tx.send(batch).await.unwrap();
+        }
+        assert_eq!(values(rx.recv_coalesced(4).await).len(), 8);
+        assert_eq!(values(rx.recv_coalesced(4).await), vec![9]);
+
+        // every coalesced batch is released from the queue
+        assert_eq!(tx.capacity(), 100);
+        assert_eq!(tx.queued_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_recv_coalesced_stops_at_signals() {
+        let (tx, mut rx) = batch_bounded(100);
+        let barrier = CheckpointBarrier {
+            epoch: 1,
+            min_epoch: 0,
+            timestamp: SystemTime::now(),
+            then_stop: false,
+        };
+
+        tx.send(rows([1])).await.unwrap();
+        tx.send(rows([2])).await.unwrap();
+        tx.send(ArrowMessage::Signal(SignalMessage::Barrier(barrier)))
+            .await
+            .unwrap();
+        tx.send(rows([3])).await.unwrap();
+        tx.send(ArrowMessage::Signal(SignalMessage::Watermark(
+            Watermark::EventTime(SystemTime::UNIX_EPOCH),
+        )))
+        .await
+        .unwrap();
+        tx.send(rows([4])).await.unwrap();
+
+        // data after a barrier belongs to the next epoch, so it's never merged into the data
+        // before it, and signals are delivered in order
+        assert_eq!(values(rx.recv_coalesced(100).await), vec![1, 2]);
+        assert!(matches!(
+            rx.recv_coalesced(100).await,
+            Some(ArrowMessage::Signal(SignalMessage::Barrier(b))) if b.epoch == 1
+        ));
+        assert_eq!(values(rx.recv_coalesced(100).await), vec![3]);
+        assert!(matches!(
+            rx.recv_coalesced(100).await,
+            Some(ArrowMessage::Signal(SignalMessage::Watermark(_)))
+        ));
+        assert_eq!(values(rx.recv_coalesced(100).await), vec![4]);
+    }
+
+    #[tokio::test]
+    async fn test_recv_coalesced_empty_and_closed() {
+        let (tx, mut rx) = batch_bounded(100);
+
+        // an empty queue waits for data rather than returning an empty batch
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), rx.recv_coalesced(10))
+                .await
+                .is_err()
+        );
+
+        tx.send(rows(1..=3)).await.unwrap();
+        tx.send(rows(4..=6)).await.unwrap();
+        tx.send(rows([7])).await.unwrap();
+        drop(tx);
+
+        // everything queued before the channel closed is still delivered, including the batch
+        // held back while coalescing
+        assert_eq!(values(rx.recv_coalesced(4).await), vec![1, 2, 3]);
+        assert_eq!(values(rx.recv_coalesced(4).await), vec![4, 5, 6, 7]);
+        assert!(rx.recv_coalesced(4).await.is_none());
+        assert!(rx.recv_coalesced(4).await.is_none());
+    }
+
     #[tokio::test]
     async fn test_shuffles() {
         let timestamp = SystemTime::now();
@@ -834,6 +1602,13 @@ mod tests {
             tx_queue_rem_gauges,
             tx_queue_size_gauges,
             tx_queue_bytes_gauges,
+            partition_counters: vec![],
+            range_boundaries: vec![],
+            exchanges: vec![],
+            routing_key_counters: vec![],
+            propagate_trace_context: false,
+            throttle: None,
+            partitioner: None,
         };
 
         collector.collect(record).await;
@@ -862,6 +1637,83 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_custom_partitioning() {
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::UInt64, false)]));
+        let record = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt64Array::from(vec![1, 2, 3, 4, 5]))],
+        )
+        .unwrap();
+
+        let (tx1, mut rx1) = batch_bounded(8);
+        let (tx2, mut rx2) = batch_bounded(8);
+
+        let task_info = Arc::new(TaskInfo {
+            job_id: "test-job".to_string(),
+            operator_name: "test-operator".to_string(),
+            operator_id: "test-operator-1".to_string(),
+            task_index: 0,
+            parallelism: 1,
+            key_range: 0..=1,
+        });
+
+        let out_qs = vec![vec![tx1, tx2]];
+        let gauge = |name: &str| register_queue_gauge(name, "test gauge", &task_info, &out_qs, 0);
+
+        let mut collector = ArrowCollector {
+            task_info: task_info.clone(),
+            out_schema: Some(ArroyoSchema::new_unkeyed(schema, 0)),
+            projection: None,
+            tx_queue_rem_gauges: gauge("arroyo_test_partitioning_rem"),
+            tx_queue_size_gauges: gauge("arroyo_test_partitioning_size"),
+            tx_queue_bytes_gauges: gauge("arroyo_test_partitioning_bytes"),
+            out_qs,
+            partition_counters: vec![],
+            range_boundaries: vec![],
+            exchanges: vec![],
+            routing_key_counters: vec![],
+            propagate_trace_context: false,
+            throttle: None,
+            partitioner: None,
+        };
+
+        assert_eq!(collector.partitions(0), 2);
+
+        // odd values go to the first partition, even values to the second
+        collector.set_partitioner(Arc::new(|batch: &RecordBatch, partitions: usize| {
+            batch
+                .column(0)
+                .as_primitive::<UInt64Type>()
+                .unary::<_, UInt64Type>(|v| (v + 1) % partitions as u64)
+        }));
+        collector.collect(record.clone()).await;
+
+        // the whole batch goes to the second partition
+        collector.collect_to_partition(1, record).await;
+
+        drop(collector);
+
+        let mut values = vec![vec![], vec![]];
+        for (i, rx) in [&mut rx1, &mut rx2].into_iter().enumerate() {
+            while let Some(m) = rx.recv().await {
+                if let ArrowMessage::Data(batch) = m {
+                    let mut v = batch
+                        .column(0)
+                        .as_primitive::<UInt64Type>()
+                        .values()
+                        .to_vec();
+                    // rows are sorted by partition, which doesn't preserve their order
+                    v.sort();
+                    values[i].push(v);
+                }
+            }
+        }
+
+        assert_eq!(values[0], vec![vec![1, 3, 5]]);
+        assert_eq!(values[1], vec![vec![2, 4], vec![1, 2, 3, 4, 5]]);
+    }
+
     #[tokio::test]
     async fn test_batch_queues() {
         let (tx, mut rx) = batch_bounded(8);
@@ -0,0 +1,44 @@
+//! A registry of custom operators, which lets programs that embed Arroyo add their own operators
+//! to pipelines without modifying the operator crates.
+//!
+//! Operators are registered by name, typically at the start of `main` before the worker is
+//! started, and are added to a pipeline's graph as nodes with the `CustomOperator` operator name,
+//! whose config holds the registered name along with a config string that's passed to the
+//! factory. Every worker that may run the pipeline must register the operator.
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::operator::OperatorNode;
+
+/// Constructs a custom operator from the config string of its node
+pub type CustomOperatorFactory = Arc<dyn Fn(&str) -> anyhow::Result<OperatorNode> + Send + Sync>;
+
+fn registry() -> &'static RwLock<HashMap<String, CustomOperatorFactory>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, CustomOperatorFactory>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Registers a custom operator under `name`, replacing any operator previously registered with
+/// that name
+pub fn register_custom_operator(
+    name: impl Into<String>,
+    factory: impl Fn(&str) -> anyhow::Result<OperatorNode> + Send + Sync + 'static,
+) {
+    registry()
+        .write()
+        .unwrap()
+        .insert(name.into(), Arc::new(factory));
+}
+
+/// The factory for the custom operator registered under `name`
+pub fn custom_operator(name: &str) -> Option<CustomOperatorFactory> {
+    registry().read().unwrap().get(name).cloned()
+}
+
+/// The names of all registered custom operators
+pub fn custom_operator_names() -> Vec<String> {
+    let mut names: Vec<_> = registry().read().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
@@ -0,0 +1,270 @@
+//! Per-key state for operators whose input is keyed, checkpointed incrementally to a keyed state
+//! table.
+//!
+//! Operators keep the live state of their keys in a [`KeyedState`], restore it in `on_start` and
+//! checkpoint it in `handle_checkpoint`. Only the keys written or removed since the previous
+//! checkpoint are written to the table, and each key is restored to the subtask its routing hash
+//! belongs to, including after rescaling. Keys that aren't written for the state's TTL (in event
+//! time) are dropped.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use arroyo_rpc::grpc::TableConfig;
+use arroyo_state::keyed_state_table_config;
+use arroyo_state::tables::expiring_time_key_map::KeyedStateEntry;
+use arroyo_types::single_item_hash_map;
+
+use crate::context::ArrowContext;
+
+/// How long keys are kept after they were last written, unless an operator configures otherwise
+pub const DEFAULT_STATE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The key of a row of a keyed stream
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub(crate) hash: u64,
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl Key {
+    /// Creates a key from its row-encoded key columns and the hash it's routed to subtasks by,
+    /// which must be the hash of the key columns that the operator's input was partitioned with
+    pub fn new(bytes: Vec<u8>, hash: u64) -> Self {
+        Self { hash, bytes }
+    }
+
+    /// The encoded key, which is stable across restarts
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The hash the key is routed to subtasks by
+    pub fn routing_hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[derive(Debug)]
+struct Entry<S> {
+    hash: u64,
+    state: S,
+    // the watermark at the checkpoint that last wrote the key
+    timestamp: SystemTime,
+    // whether the key has been written since it was last checkpointed
+    dirty: bool,
+}
+
+/// The state of each key handled by the subtask
+#[derive(Debug)]
+pub struct KeyedState<S> {
+    table: String,
+    ttl: Duration,
+    entries: HashMap<Vec<u8>, Entry<S>>,
+    // keys removed since the last checkpoint, with their routing hashes
+    removed: HashMap<Vec<u8>, u64>,
+}
+
+impl<S> KeyedState<S> {
+    /// Creates state that's checkpointed to `table`, which must be registered with the config
+    /// from [`KeyedState::table_config`]
+    pub fn new(table: impl Into<String>, ttl: Duration) -> Self {
+        Self {
+            table: table.into(),
+            ttl,
+            entries: HashMap::new(),
+            removed: HashMap::new(),
+        }
+    }
+
+    pub fn table_config(&self, description: impl Into<String>) -> HashMap<String, TableConfig> {
+        single_item_hash_map(
+            self.table.clone(),
+            keyed_state_table_config(&self.table, description, self.ttl),
+        )
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&S> {
+        self.entries.get(&key.bytes).map(|e| &e.state)
+    }
+
+    /// The state of the key, if it has any
+    pub fn get_existing_mut(&mut self, key: &Key) -> Option<&mut S> {
+        self.entries.get_mut(&key.bytes).map(|e| {
+            e.dirty = true;
+            &mut e.state
+        })
+    }
+
+    pub fn insert(&mut self, key: &Key, state: S) -> Option<S> {
+        self.removed.remove(&key.bytes);
+        self.entries
+            .insert(
+                key.bytes.clone(),
+                Entry {
+                    hash: key.hash,
+                    state,
+                    timestamp: SystemTime::UNIX_EPOCH,
+                    dirty: true,
+                },
+            )
+            .map(|e| e.state)
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<S> {
+        let entry = self.entries.remove(&key.bytes)?;
+        self.removed.insert(key.bytes.clone(), entry.hash);
+        Some(entry.state)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Restores the keys of the subtask from the table, decoding their state with `decode`
+    pub async fn restore(
+        &mut self,
+        ctx: &mut ArrowContext,
+        decode: impl Fn(&[u8]) -> Result<S>,
+    ) -> Result<()> {
+        let watermark = ctx.last_present_watermark();
+        let restored = ctx
+            .table_manager
+            .get_keyed_state_table(&self.table, watermark)
+            .await?
+            .take_restored();
+
+        for entry in restored {
+            let Some(value) = entry.value else {
+                continue;
+            };
+            self.entries.insert(
+                entry.key,
+                Entry {
+                    hash: entry.routing_hash,
+                    state: decode(&value)?,
+                    timestamp: entry.timestamp,
+                    dirty: false,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Writes the keys that were written or removed since the last checkpoint to the table,
+    /// encoding their state with `encode`, then drops the keys that have outlived the TTL
+    pub async fn checkpoint(
+        &mut self,
+        epoch: u32,
+        ctx: &mut ArrowContext,
+        encode: impl Fn(&S) -> Result<Vec<u8>>,
+    ) -> Result<()> {
+        let watermark = ctx.last_present_watermark();
+        let timestamp = watermark.unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let mut updates: Vec<_> = self
+            .removed
+            .drain()
+            .map(|(key, hash)| KeyedStateEntry {
+                routing_hash: hash,
+                key,
+                value: None,
+                timestamp,
+            })
+            .collect();
+
+        for (key, entry) in self.entries.iter_mut().filter(|(_, e)| e.dirty) {
+            updates.push(KeyedStateEntry {
+                routing_hash: entry.hash,
+                key: key.clone(),
+                value: Some(encode(&entry.state)?),
+                timestamp,
+            });
+            entry.timestamp = timestamp;
+            // until there's a watermark, keys are rewritten at each checkpoint so that they're
+            // stamped with the first one instead of expiring as soon as it arrives
+            entry.dirty = watermark.is_none();
+        }
+
+        ctx.table_manager
+            .get_keyed_state_table(&self.table, watermark)
+            .await?
+            .write(updates, epoch as u64)
+            .await?;
+
+        if let Some(watermark) = watermark {
+            let cutoff = watermark - self.ttl;
+            self.entries.retain(|_, e| e.timestamp >= cutoff);
+        }
+        Ok(())
+    }
+}
+
+impl<S: Default> KeyedState<S> {
+    /// The state of the key, which is initialized to the default if it has none
+    pub fn get_mut(&mut self, key: &Key) -> &mut S {
+        if !self.entries.contains_key(&key.bytes) {
+            self.insert(key, S::default());
+        }
+        self.get_existing_mut(key).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(hash: u64, bytes: &[u8]) -> Key {
+        Key::new(bytes.to_vec(), hash)
+    }
+
+    #[test]
+    fn test_keyed_state() {
+        let mut state: KeyedState<u64> = KeyedState::new("k", DEFAULT_STATE_TTL);
+        let a = key(1, b"a");
+        let b = key(2, b"b");
+
+        assert!(state.is_empty());
+        assert_eq!(state.get(&a), None);
+
+        *state.get_mut(&a) += 3;
+        *state.get_mut(&a) += 4;
+        assert_eq!(state.get(&a), Some(&7));
+
+        assert_eq!(state.insert(&b, 10), None);
+        assert_eq!(state.insert(&b, 11), Some(10));
+        assert_eq!(state.len(), 2);
+
+        assert_eq!(state.remove(&a), Some(7));
+        assert_eq!(state.get(&a), None);
+        assert_eq!(state.get_existing_mut(&a), None);
+        assert_eq!(state.len(), 1);
+    }
+
+    #[test]
+    fn test_keyed_state_tracks_changes() {
+        let mut state: KeyedState<u64> = KeyedState::new("k", DEFAULT_STATE_TTL);
+        let a = key(1, b"a");
+
+        state.insert(&a, 1);
+        assert!(state.entries[a.as_bytes()].dirty);
+
+        state.entries.get_mut(a.as_bytes()).unwrap().dirty = false;
+        *state.get_existing_mut(&a).unwrap() += 1;
+        assert!(state.entries[a.as_bytes()].dirty);
+
+        // removing a key records a tombstone for the next checkpoint...
+        state.remove(&a);
+        assert_eq!(state.removed.get(a.as_bytes()), Some(&1));
+        assert_eq!(state.remove(&a), None);
+
+        // ...unless it's written again first
+        state.insert(&a, 3);
+        assert!(state.removed.is_empty());
+    }
+}
@@ -9,7 +9,9 @@ use crate::inq_reader::InQReader;
 use arrow::array::types::{TimestampNanosecondType, UInt64Type};
 use arrow::array::{Array, PrimitiveArray, RecordBatch, UInt64Array};
 use arrow::compute::kernels::numeric::{div, rem};
-use arroyo_types::{ArrowMessage, CheckpointBarrier, Data, SignalMessage, TaskInfoRef};
+use arroyo_types::{
+    server_for_hash_in_ranges, ArrowMessage, CheckpointBarrier, Data, SignalMessage, TaskInfoRef,
+};
 use bincode::{Decode, Encode};
 
 use crate::context::ArrowContext;
@@ -17,11 +19,22 @@ use crate::operator::Registry;
 use operator::{OperatorConstructor, OperatorNode};
 use tokio_stream::Stream;
 
+pub mod clock;
 pub mod connector;
 pub mod context;
+pub mod custom;
 pub mod inq_reader;
+pub mod keyed_state;
+pub mod models;
 pub mod operator;
+pub mod partitioner;
+pub mod process;
+pub mod recording;
+pub mod timers;
+pub mod trace_context;
 pub mod udfs;
+pub mod watermarks;
+pub mod window;
 
 pub trait TimerT: Data + PartialEq + Eq + 'static {}
 
@@ -40,6 +53,13 @@ pub fn server_for_hash_array(
     Ok(result.clone())
 }
 
+pub fn server_for_hash_array_in_ranges(
+    hash: &PrimitiveArray<UInt64Type>,
+    boundaries: &[u64],
+) -> PrimitiveArray<UInt64Type> {
+    hash.unary(|h| server_for_hash_in_ranges(h, boundaries) as u64)
+}
+
 pub enum SourceFinishType {
     // stop messages should be propagated through the dataflow
     Graceful,
@@ -0,0 +1,261 @@
+use anyhow::{anyhow, bail};
+use arrow::array::builder::{Float64Builder, ListBuilder};
+use arrow::array::{Array, ArrayRef, AsArray};
+use arrow::datatypes::{DataType, Float64Type};
+use arroyo_datastream::logical::ModelConfig;
+use arroyo_storage::StorageProvider;
+use datafusion::common::{exec_err, DataFusionError, Result, ScalarValue};
+use datafusion::logical_expr::{ColumnarValue, ScalarUDFImpl, Signature, Volatility};
+use ndarray::Array2;
+use ort::Session;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::info;
+
+/// Fetches an ONNX model from the artifact store and builds an inference session for it
+async fn load_session(name: &str, config: &ModelConfig) -> anyhow::Result<Session> {
+    let bytes = StorageProvider::get_url(&config.url).await.map_err(|e| {
+        anyhow!(
+            "unable to fetch model {} from '{}': {:?}",
+            name,
+            config.url,
+            e
+        )
+    })?;
+
+    let session = Session::builder()?
+        .with_intra_threads(1)?
+        .commit_from_memory(&bytes)
+        .map_err(|e| anyhow!("unable to load model {}: {}", name, e))?;
+
+    if session.inputs.len() != 1 || session.outputs.is_empty() {
+        bail!(
+            "model {} must have a single input tensor and at least one output, but has {} inputs and {} outputs",
+            name,
+            session.inputs.len(),
+            session.outputs.len()
+        );
+    }
+
+    Ok(session)
+}
+
+fn model_err(e: ort::Error) -> DataFusionError {
+    DataFusionError::External(Box::new(e))
+}
+
+/// Scores batches with an ONNX model. Each argument is one of the model's input features; the
+/// rows of a batch are passed to the model as a single `[rows, inputs]` f32 tensor, and the
+/// first output is read back as `[rows, outputs]`. Rows with null features score null.
+#[derive(Clone)]
+pub struct OnnxModel {
+    name: String,
+    signature: Signature,
+    config: ModelConfig,
+    // swapped out when the model is reloaded
+    session: Arc<RwLock<Arc<Session>>>,
+}
+
+impl Debug for OnnxModel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OnnxModel")
+            .field("name", &self.name)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl OnnxModel {
+    pub async fn load(name: &str, config: &ModelConfig) -> anyhow::Result<Self> {
+        let session = load_session(name, config).await?;
+
+        Ok(Self {
+            name: name.to_string(),
+            signature: Signature::exact(
+                vec![DataType::Float64; config.inputs as usize],
+                Volatility::Volatile,
+            ),
+            config: config.clone(),
+            session: Arc::new(RwLock::new(Arc::new(session))),
+        })
+    }
+
+    fn score(&self, args: &[ArrayRef]) -> Result<ArrayRef> {
+        let len = args.first().map(|a| a.len()).unwrap_or_default();
+        let features: Vec<_> = args
+            .iter()
+            .map(|a| a.as_primitive::<Float64Type>())
+            .collect();
+
+        let valid: Vec<bool> = (0..len)
+            .map(|i| features.iter().all(|f| f.is_valid(i)))
+            .collect();
+        let rows = valid.iter().filter(|v| **v).count();
+        let outputs = self.config.outputs as usize;
+
+        let mut scores = vec![];
+        if rows > 0 {
+            let mut data = Vec::with_capacity(rows * features.len());
+            for i in (0..len).filter(|i| valid[*i]) {
+                data.extend(features.iter().map(|f| f.value(i) as f32));
+            }
+
+            let input = Array2::from_shape_vec((rows, features.len()), data)
+                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+
+            let session = self.session.read().unwrap().clone();
+            let result = session
+                .run(ort::inputs![input].map_err(model_err)?)
+                .map_err(model_err)?;
+            let output = result[0].try_extract_tensor::<f32>().map_err(model_err)?;
+
+            if output.len() != rows * outputs {
+                return exec_err!(
+                    "model {} returned {} values for {} rows, but has {} outputs",
+                    self.name,
+                    output.len(),
+                    rows,
+                    outputs
+                );
+            }
+
+            scores.extend(output.iter().map(|v| *v as f64));
+        }
+
+        let mut row_scores = scores.chunks(outputs);
+        if outputs == 1 {
+            let mut builder = Float64Builder::with_capacity(len);
+            for valid in valid {
+                builder.append_option(valid.then(|| row_scores.next().unwrap()[0]));
+            }
+            Ok(Arc::new(builder.finish()))
+        } else {
+            let mut builder = ListBuilder::with_capacity(Float64Builder::new(), len);
+            for valid in valid {
+                if valid {
+                    builder.values().append_slice(row_scores.next().unwrap());
+                    builder.append(true);
+                } else {
+                    builder.append_null();
+                }
+            }
+            Ok(Arc::new(builder.finish()))
+        }
+    }
+}
+
+impl ScalarUDFImpl for OnnxModel {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(self.config.return_type())
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let scalar = args.iter().all(|a| matches!(a, ColumnarValue::Scalar(_)));
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+        let result = self.score(&arrays)?;
+
+        if scalar {
+            Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(
+                &result, 0,
+            )?))
+        } else {
+            Ok(ColumnarValue::Array(result))
+        }
+    }
+}
+
+/// Swaps new versions of the models used by a running pipeline in place of the current ones.
+/// A model's inputs and outputs can't change, as they are part of the function's signature.
+#[derive(Clone, Default)]
+pub struct ModelReloader {
+    models: Arc<Mutex<HashMap<String, OnnxModel>>>,
+}
+
+impl ModelReloader {
+    pub(crate) fn register(&self, model: &OnnxModel) {
+        self.models
+            .lock()
+            .unwrap()
+            .insert(model.name.clone(), model.clone());
+    }
+
+    /// Loads the models whose url has changed and checks that they can replace the current ones,
+    /// without yet swapping them in
+    pub async fn prepare(
+        &self,
+        models: &HashMap<String, ModelConfig>,
+    ) -> anyhow::Result<PreparedModels> {
+        let changed: Vec<_> = {
+            let current = self.models.lock().unwrap();
+            models
+                .iter()
+                .map(|(name, config)| (name, config, current.get(name).cloned()))
+                .filter(|(_, config, current)| {
+                    current
+                        .as_ref()
+                        .map(|m| m.config.url != config.url)
+                        .unwrap_or(true)
+                })
+                .collect()
+        };
+
+        let mut loaded = vec![];
+        for (name, config, current) in changed {
+            let Some(current) = current else {
+                bail!("model {} is not used by the running pipeline", name);
+            };
+
+            if current.config.inputs != config.inputs || current.config.outputs != config.outputs {
+                bail!(
+                    "the inputs or outputs of model {} have changed; the pipeline must be recreated to change them",
+                    name
+                );
+            }
+
+            loaded.push((current, config.clone(), load_session(name, config).await?));
+        }
+
+        Ok(PreparedModels {
+            reloader: self.clone(),
+            loaded,
+        })
+    }
+}
+
+/// Models that have been loaded and checked by [`ModelReloader::prepare`], ready to be swapped in
+pub struct PreparedModels {
+    reloader: ModelReloader,
+    loaded: Vec<(OnnxModel, ModelConfig, Session)>,
+}
+
+impl PreparedModels {
+    /// Swaps the prepared models in for the current ones, returning the number of models that
+    /// were reloaded
+    pub fn commit(self) -> usize {
+        let count = self.loaded.len();
+        for (mut model, config, session) in self.loaded {
+            *model.session.write().unwrap() = Arc::new(session);
+            info!(
+                "Reloaded model {} at version {} from {}",
+                model.name, config.version, config.url
+            );
+            model.config = config;
+            self.reloader.register(&model);
+        }
+        count
+    }
+}
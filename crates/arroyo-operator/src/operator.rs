@@ -1,18 +1,26 @@
 use crate::context::{ArrowContext, BatchReceiver};
 use crate::inq_reader::InQReader;
+use crate::models::{ModelReloader, OnnxModel};
+use crate::timers::TIMER_TABLE;
+use crate::trace_context;
 use crate::udfs::{ArroyoUdaf, UdafArg};
-use crate::{CheckpointCounter, ControlOutcome, SourceFinishType};
-use anyhow::anyhow;
+use crate::{ArrowTimerValue, CheckpointCounter, ControlOutcome, SourceFinishType};
+use anyhow::{anyhow, bail};
 use arrow::array::RecordBatch;
 use arrow::datatypes::DataType;
-use arroyo_datastream::logical::DylibUdfConfig;
-use arroyo_metrics::TaskCounters;
+use arroyo_datastream::logical::{DylibUdfConfig, JsUdfConfig, ModelConfig};
+use arroyo_metrics::{TaskCounters, TaskHistograms};
+use arroyo_rpc::config::{config, UdfBadData};
+use arroyo_rpc::fault_injection::{self, FaultKind};
 use arroyo_rpc::grpc::{TableConfig, TaskCheckpointEventType};
 use arroyo_rpc::{ControlMessage, ControlResp};
 use arroyo_storage::StorageProvider;
 use arroyo_types::{ArrowMessage, CheckpointBarrier, SignalMessage, Watermark};
 use arroyo_udf_host::parse::inner_type;
-use arroyo_udf_host::{ContainerOrLocal, LocalUdf, SyncUdfDylib, UdfDylib, UdfInterface};
+use arroyo_udf_host::{
+    ContainerOrLocal, LocalUdf, SyncUdfDylib, UdfDylib, UdfInterface, UdfLimits,
+};
+use arroyo_udf_js::{JsUdf, ParsedJsUdf};
 use async_trait::async_trait;
 use datafusion::common::{DataFusionError, Result as DFResult};
 use datafusion::execution::FunctionRegistry;
@@ -28,7 +36,7 @@ use std::future::Future;
 use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::Barrier;
 use tokio_stream::StreamExt;
 use tracing::{debug, error, info, warn, Instrument};
@@ -92,6 +100,10 @@ impl OperatorNode {
 
                 s.on_close(ctx).await;
 
+                if let Some(recorder) = &mut ctx.recorder {
+                    recorder.finish(&ctx.task_info).await;
+                }
+
                 result.into()
             }
             OperatorNode::Operator(o) => operator_run_behavior(o, ctx, in_qs, ready).await,
@@ -133,8 +145,23 @@ impl OperatorNode {
 async fn run_checkpoint(checkpoint_barrier: CheckpointBarrier, ctx: &mut ArrowContext) -> bool {
     let watermark = ctx.watermarks.last_present_watermark();
 
+    fault_injection::maybe_pause(
+        FaultKind::DelayCheckpoint,
+        &ctx.task_info.operator_id,
+        ctx.task_info.task_index,
+    )
+    .await;
+
+    if let Some(recorder) = &mut ctx.recorder {
+        recorder
+            .flush(&ctx.task_info, checkpoint_barrier.epoch)
+            .instrument(tracing::info_span!("write_recording"))
+            .await;
+    }
+
     ctx.table_manager
         .checkpoint(checkpoint_barrier, watermark)
+        .instrument(tracing::info_span!("write_state"))
         .await;
 
     ctx.send_checkpoint_event(checkpoint_barrier, TaskCheckpointEventType::FinishedSync)
@@ -169,13 +196,24 @@ pub trait SourceOperator: Send + 'static {
         checkpoint_barrier: CheckpointBarrier,
         ctx: &mut ArrowContext,
     ) -> bool {
-        ctx.send_checkpoint_event(
-            checkpoint_barrier,
-            TaskCheckpointEventType::StartedCheckpointing,
-        )
-        .await;
+        let span = tracing::info_span!(
+            "checkpoint",
+            epoch = checkpoint_barrier.epoch,
+            operator_id = ctx.task_info.operator_id,
+            subtask_idx = ctx.task_info.task_index
+        );
 
-        run_checkpoint(checkpoint_barrier, ctx).await
+        async {
+            ctx.send_checkpoint_event(
+                checkpoint_barrier,
+                TaskCheckpointEventType::StartedCheckpointing,
+            )
+            .await;
+
+            run_checkpoint(checkpoint_barrier, ctx).await
+        }
+        .instrument(span)
+        .await
     }
 }
 
@@ -185,6 +223,10 @@ async fn operator_run_behavior(
     in_qs: &mut [BatchReceiver],
     ready: Arc<Barrier>,
 ) -> Option<SignalMessage> {
+    if this.tables().contains_key(TIMER_TABLE) {
+        ctx.restore_timers().await;
+    }
+
     this.on_start(ctx).await;
 
     ready.wait().await;
@@ -200,9 +242,10 @@ async fn operator_run_behavior(
     let mut sel = InQReader::new();
     let in_partitions = in_qs.len();
 
+    let max_rows = ctx.batch_settings.max_rows;
     for (i, q) in in_qs.iter_mut().enumerate() {
         let stream = async_stream::stream! {
-          while let Some(item) = q.recv().await {
+          while let Some(item) = q.recv_coalesced(max_rows).await {
             yield(i,item);
           }
         };
@@ -210,6 +253,8 @@ async fn operator_run_behavior(
     }
     let mut blocked = vec![];
     let mut final_message = None;
+    // the trace context for the next batch on each input, if it was sent by the upstream operator
+    let mut trace_contexts: HashMap<usize, String> = HashMap::new();
 
     let mut ticks = 0u64;
     let mut interval =
@@ -220,10 +265,17 @@ async fn operator_run_behavior(
         let operator_future: OptionFuture<_> = this.future_to_poll().into();
         tokio::select! {
             Some(control_message) = ctx.control_rx.recv() => {
+                let tick_interval = this.tick_interval();
                 this.handle_controller_message(control_message, ctx).await;
+
+                // the tick interval may be a runtime parameter
+                if this.tick_interval() != tick_interval {
+                    interval = tokio::time::interval(this.tick_interval().unwrap_or(Duration::from_secs(60)));
+                    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+                }
             }
 
-            p = sel.next() => {
+            p = sel.next(), if !this.inputs_paused() => {
                 match p {
                     Some(((idx, message), s)) => {
                         let local_idx = idx;
@@ -233,15 +285,27 @@ async fn operator_run_behavior(
 
                         match message {
                             ArrowMessage::Data(record) => {
+                                fault_injection::maybe_kill(&task_info.operator_id, task_info.task_index);
                                 TaskCounters::BatchesReceived.for_task(&ctx.task_info, |c| c.inc());
                                 TaskCounters::MessagesReceived.for_task(&ctx.task_info, |c| c.inc_by(record.num_rows() as u64));
                                 TaskCounters::BytesReceived.for_task(&ctx.task_info, |c| c.inc_by(record.get_array_memory_size() as u64));
+                                let span = tracing::trace_span!("process_batch",
+                                    name,
+                                    operator_id = task_info.operator_id,
+                                    subtask_idx = task_info.task_index,
+                                    rows = record.num_rows());
+                                if let Some(traceparent) = trace_contexts.remove(&idx) {
+                                    trace_context::set_parent(&span, &traceparent);
+                                }
+                                ctx.clock.advance_to_batch(&record);
+                                let start = Instant::now();
                                 this.process_batch_index(idx, in_partitions, record, ctx)
-                                    .instrument(tracing::trace_span!("handle_fn",
-                                        name,
-                                        operator_id = task_info.operator_id,
-                                        subtask_idx = task_info.task_index)
-                                ).await;
+                                    .instrument(span)
+                                    .await;
+                                TaskHistograms::ProcessBatchTime.for_task(&ctx.task_info, |h| h.observe(start.elapsed().as_secs_f64()));
+                            }
+                            ArrowMessage::Signal(SignalMessage::TraceContext(traceparent)) => {
+                                trace_contexts.insert(idx, traceparent);
                             }
                             ArrowMessage::Signal(signal) => {
                                 match this.handle_control_message(idx, &signal, &mut counter, &mut closed, in_partitions, ctx).await {
@@ -304,12 +368,23 @@ pub trait ArrowOperator: Send + 'static {
             ctx.task_info.task_index
         );
 
-        if let Watermark::EventTime(_t) = watermark {
-            // let finished = ProcessFnUtils::finished_timers(t, ctx).await;
-            //
-            // for (k, tv) in finished {
-            //     self.handle_timer(k, tv.data, ctx).await;
-            // }
+        if let Watermark::EventTime(t) = watermark {
+            // fire expired timers in bounded batches, yielding in between so that a large backlog
+            // of timers doesn't starve the rest of the pipeline
+            loop {
+                let timers = ctx.timers.pop_expired(t);
+                if timers.is_empty() {
+                    break;
+                }
+                let last_batch = timers.len() < ctx.timers.fire_batch_size();
+
+                self.handle_timers(timers, ctx).await;
+
+                if last_batch {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
         }
 
         if let Some(watermark) = self.handle_watermark(watermark, ctx).await {
@@ -336,6 +411,21 @@ pub trait ArrowOperator: Send + 'static {
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
+            ControlMessage::AlignWatermark { max_watermark } => {
+                self.handle_watermark_alignment(max_watermark);
+            }
+            ControlMessage::UpdateParameters { parameters } => {
+                match self.update_parameters(&parameters) {
+                    Ok(()) => info!(
+                        "updated parameters of {}-{} to {:?}",
+                        ctx.task_info.operator_id, ctx.task_info.task_index, parameters
+                    ),
+                    Err(e) => warn!(
+                        "failed to update parameters of {}-{}: {:?}",
+                        ctx.task_info.operator_id, ctx.task_info.task_index, e
+                    ),
+                }
+            }
             ControlMessage::NoOp => {}
         }
     }
@@ -380,15 +470,40 @@ pub trait ArrowOperator: Send + 'static {
                         ctx.task_info.task_index
                     );
 
-                    ctx.send_checkpoint_event(*t, TaskCheckpointEventType::StartedCheckpointing)
+                    let span = tracing::info_span!(
+                        "checkpoint",
+                        epoch = t.epoch,
+                        operator_id = ctx.task_info.operator_id,
+                        subtask_idx = ctx.task_info.task_index
+                    );
+
+                    let stop = async {
+                        ctx.send_checkpoint_event(
+                            *t,
+                            TaskCheckpointEventType::StartedCheckpointing,
+                        )
                         .await;
 
-                    self.handle_checkpoint(*t, ctx).await;
+                        self.handle_checkpoint(*t, ctx)
+                            .instrument(tracing::info_span!("handle_checkpoint"))
+                            .await;
 
-                    ctx.send_checkpoint_event(*t, TaskCheckpointEventType::FinishedOperatorSetup)
+                        if self.tables().contains_key(TIMER_TABLE) {
+                            ctx.checkpoint_timers().await;
+                        }
+
+                        ctx.send_checkpoint_event(
+                            *t,
+                            TaskCheckpointEventType::FinishedOperatorSetup,
+                        )
                         .await;
 
-                    if run_checkpoint(*t, ctx).await {
+                        run_checkpoint(*t, ctx).await
+                    }
+                    .instrument(span)
+                    .await;
+
+                    if stop {
                         return ControlOutcome::Stop;
                     }
                 }
@@ -407,8 +522,9 @@ pub trait ArrowOperator: Send + 'static {
                     .expect("watermark index is too big");
 
                 if let Some(watermark) = watermark {
-                    if let Watermark::EventTime(_t) = watermark {
+                    if let Watermark::EventTime(t) = watermark {
                         // TOOD: pass to table_manager
+                        ctx.clock.advance_to(t);
                     }
 
                     self.handle_watermark_int(watermark, ctx).await;
@@ -426,6 +542,9 @@ pub trait ArrowOperator: Send + 'static {
                     return ControlOutcome::Finish;
                 }
             }
+            SignalMessage::TraceContext(_) => {
+                // handled by the run loop, which applies it to the next batch
+            }
         }
         ControlOutcome::Continue
     }
@@ -468,6 +587,14 @@ pub trait ArrowOperator: Send + 'static {
     #[allow(unused_variables)]
     async fn handle_timer(&mut self, key: Vec<u8>, value: Vec<u8>, ctx: &mut ArrowContext) {}
 
+    /// Called with each batch of timers that have expired as the watermark advances, earliest
+    /// first. Operators can override this to process a batch of timers at once.
+    async fn handle_timers(&mut self, timers: Vec<ArrowTimerValue>, ctx: &mut ArrowContext) {
+        for timer in timers {
+            self.handle_timer(timer.key, timer.data, ctx).await;
+        }
+    }
+
     async fn handle_watermark(
         &mut self,
         watermark: Watermark,
@@ -492,18 +619,189 @@ pub trait ArrowOperator: Send + 'static {
     #[allow(unused_variables)]
     async fn handle_tick(&mut self, tick: u64, ctx: &mut ArrowContext) {}
 
+    /// Called when the controller bounds how far the operator's watermark may advance, for
+    /// operators that are part of a watermark-alignment group
+    #[allow(unused_variables)]
+    fn handle_watermark_alignment(&mut self, max_watermark: SystemTime) {}
+
+    /// Called between batches with updates to the operator's runtime parameters, which have
+    /// been checked against those declared by `OperatorName::runtime_parameters`
+    #[allow(unused_variables)]
+    fn update_parameters(&mut self, parameters: &HashMap<String, f64>) -> anyhow::Result<()> {
+        bail!("{} does not support runtime parameters", self.name())
+    }
+
+    /// Operators may temporarily stop reading their inputs (for example, while their watermark is
+    /// too far ahead of other sources); control messages and ticks are still handled while paused
+    fn inputs_paused(&self) -> bool {
+        false
+    }
+
     #[allow(unused_variables)]
     async fn on_close(&mut self, final_message: &Option<SignalMessage>, ctx: &mut ArrowContext) {}
 }
 
+/// Downloads a UDF dylib from the object store and loads it
+async fn fetch_dylib(name: &str, config: &DylibUdfConfig) -> anyhow::Result<UdfDylib> {
+    let signature = Signature::exact(config.arg_types.clone(), Volatility::Volatile);
+
+    let udf = StorageProvider::get_url(&config.dylib_path)
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "unable to fetch UDF dylib from '{}': {:?}",
+                config.dylib_path,
+                e
+            )
+        })?;
+
+    // write the dylib to a local file
+    let local_udfs_dir = "/tmp/arroyo/local_udfs";
+    tokio::fs::create_dir_all(local_udfs_dir)
+        .await
+        .map_err(|e| anyhow!("unable to create local udfs dir: {:?}", e))?;
+
+    let dylib_file_name = Path::new(&config.dylib_path)
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid dylib path: {}", config.dylib_path))?;
+    let local_dylib_path = Path::new(local_udfs_dir).join(dylib_file_name);
+
+    tokio::fs::write(&local_dylib_path, udf)
+        .await
+        .map_err(|e| anyhow!("unable to write dylib to file: {:?}", e))?;
+
+    let interface = if config.is_async {
+        UdfInterface::Async(Arc::new(ContainerOrLocal::Container(unsafe {
+            Container::load(&local_dylib_path)
+                .map_err(|e| anyhow!("unable to load UDF dylib: {:?}", e))?
+        })))
+    } else if config.is_stateful {
+        UdfInterface::Stateful(Arc::new(ContainerOrLocal::Container(unsafe {
+            Container::load(&local_dylib_path)
+                .map_err(|e| anyhow!("unable to load UDF dylib: {:?}", e))?
+        })))
+    } else {
+        UdfInterface::Sync(Arc::new(ContainerOrLocal::Container(unsafe {
+            Container::load(&local_dylib_path)
+                .map_err(|e| anyhow!("unable to load UDF dylib: {:?}", e))?
+        })))
+    };
+
+    let dylib = UdfDylib::new(
+        name.to_string(),
+        signature,
+        config.return_type.clone(),
+        interface,
+    );
+
+    if let Some(limit) = arroyo_rpc::config::config().pipeline.udf.memory_limit {
+        dylib.set_memory_limit(limit);
+    }
+
+    Ok(dylib)
+}
+
+/// The execution limits for sync and stateful UDFs, as configured in `pipeline.udf`
+pub fn udf_limits() -> UdfLimits {
+    let config = &config().pipeline.udf;
+    UdfLimits {
+        timeout: Some(*config.timeout),
+        null_on_failure: config.bad_data == UdfBadData::Drop,
+    }
+}
+
 #[derive(Default)]
 pub struct Registry {
     dylibs: Arc<std::sync::Mutex<HashMap<String, Arc<UdfDylib>>>>,
+    reloadable: UdfReloader,
+    models: ModelReloader,
     udfs: HashMap<String, Arc<ScalarUDF>>,
     udafs: HashMap<String, Arc<AggregateUDF>>,
     udwfs: HashMap<String, Arc<WindowUDF>>,
 }
 
+/// Swaps new implementations into the sync dylib UDFs of a running pipeline. Async UDFs can't
+/// be reloaded, as their runtimes hold in-flight state, and neither can stateful UDFs, whose
+/// stored state may not be understood by a new implementation.
+#[derive(Clone, Default)]
+pub struct UdfReloader {
+    // udf name -> (current dylib path, udf if it can be reloaded)
+    udfs: Arc<std::sync::Mutex<HashMap<String, (String, Option<SyncUdfDylib>)>>>,
+}
+
+impl UdfReloader {
+    fn register(&self, name: &str, config: &DylibUdfConfig, dylib: Option<SyncUdfDylib>) {
+        self.udfs
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), (config.dylib_path.clone(), dylib));
+    }
+
+    /// Loads the given UDFs and checks that they can replace the currently running
+    /// implementations, without yet swapping them in. UDFs whose dylib is unchanged are skipped.
+    pub async fn prepare(
+        &self,
+        udfs: &HashMap<String, DylibUdfConfig>,
+    ) -> anyhow::Result<PreparedUdfs> {
+        let changed: Vec<_> = {
+            let current = self.udfs.lock().unwrap();
+            udfs.iter()
+                .map(|(name, config)| (name, config, current.get(name).cloned()))
+                .filter(|(_, config, current)| {
+                    current
+                        .as_ref()
+                        .map(|(path, _)| *path != config.dylib_path)
+                        .unwrap_or(true)
+                })
+                .collect()
+        };
+
+        let mut loaded = vec![];
+        for (name, config, current) in changed {
+            let Some((_, current)) = current else {
+                bail!("UDF {} is not used by the running pipeline", name);
+            };
+
+            let Some(udf) = current else {
+                bail!(
+                    "async and stateful UDFs cannot be reloaded, but {} was changed",
+                    name
+                );
+            };
+
+            let dylib = fetch_dylib(name, config).await?;
+            udf.check_compatible(&dylib)?;
+            loaded.push((name.clone(), config.clone(), udf, dylib));
+        }
+
+        Ok(PreparedUdfs {
+            reloader: self.clone(),
+            loaded,
+        })
+    }
+}
+
+/// UDFs that have been loaded and checked by [`UdfReloader::prepare`], ready to be swapped in
+pub struct PreparedUdfs {
+    reloader: UdfReloader,
+    loaded: Vec<(String, DylibUdfConfig, SyncUdfDylib, UdfDylib)>,
+}
+
+impl PreparedUdfs {
+    /// Swaps the prepared UDFs in for the currently running implementations, returning the
+    /// number of UDFs that were reloaded
+    pub fn commit(self) -> usize {
+        let count = self.loaded.len();
+        for (name, config, udf, dylib) in self.loaded {
+            udf.swap(&dylib)
+                .expect("UDF compatibility was checked when it was prepared");
+            info!("Reloaded UDF {} from {}", name, config.dylib_path);
+            self.reloader.register(&name, &config, Some(udf));
+        }
+        count
+    }
+}
+
 impl Registry {
     pub async fn load_dylib(
         &mut self,
@@ -517,62 +815,58 @@ impl Registry {
             }
         }
 
-        // Download a UDF dylib from the object store
-        let signature = Signature::exact(config.arg_types.clone(), Volatility::Volatile);
-
-        let udf = StorageProvider::get_url(&config.dylib_path)
-            .await
-            .unwrap_or_else(|e| {
-                panic!(
-                    "Unable to fetch UDF dylib from '{}': {:?}",
-                    config.dylib_path, e
-                )
-            });
-
-        // write the dylib to a local file
-        let local_udfs_dir = "/tmp/arroyo/local_udfs";
-        tokio::fs::create_dir_all(local_udfs_dir)
-            .await
-            .map_err(|e| anyhow!("unable to create local udfs dir: {:?}", e))?;
-
-        let dylib_file_name = Path::new(&config.dylib_path)
-            .file_name()
-            .ok_or_else(|| anyhow!("Invalid dylib path: {}", config.dylib_path))?;
-        let local_dylib_path = Path::new(local_udfs_dir).join(dylib_file_name);
-
-        tokio::fs::write(&local_dylib_path, udf)
-            .await
-            .map_err(|e| anyhow!("unable to write dylib to file: {:?}", e))?;
-
-        let interface = if config.is_async {
-            UdfInterface::Async(Arc::new(ContainerOrLocal::Container(unsafe {
-                Container::load(local_dylib_path).unwrap()
-            })))
-        } else {
-            UdfInterface::Sync(Arc::new(ContainerOrLocal::Container(unsafe {
-                Container::load(local_dylib_path).unwrap()
-            })))
-        };
-
-        let udf = Arc::new(UdfDylib::new(
-            name.to_string(),
-            signature,
-            config.return_type.clone(),
-            interface,
-        ));
+        let udf = Arc::new(fetch_dylib(name, config).await?);
 
         self.dylibs
             .lock()
             .unwrap()
             .insert(config.dylib_path.clone(), udf.clone());
 
-        if !config.is_async {
+        if config.is_async || config.is_stateful {
+            self.reloadable.register(name, config, None);
+        } else {
             self.add_udfs(&udf, config);
         }
 
         Ok(udf)
     }
 
+    /// Returns a handle that can be used to hot-reload the sync dylib UDFs in this registry
+    pub fn udf_reloader(&self) -> UdfReloader {
+        self.reloadable.clone()
+    }
+
+    /// Loads an ONNX model from the artifact store and registers it as a function
+    pub async fn add_model(&mut self, name: &str, config: &ModelConfig) -> anyhow::Result<()> {
+        let model = OnnxModel::load(name, config).await?;
+        self.models.register(&model);
+        self.udfs
+            .insert(name.to_string(), Arc::new(ScalarUDF::new_from_impl(model)));
+        Ok(())
+    }
+
+    /// Returns a handle that can be used to hot-reload the models in this registry
+    pub fn model_reloader(&self) -> ModelReloader {
+        self.models.clone()
+    }
+
+    /// Registers a JavaScript UDF; unlike dylib UDFs, these are not reloadable
+    pub fn add_js_udf(&mut self, name: &str, udf_config: &JsUdfConfig) -> anyhow::Result<()> {
+        let parsed = ParsedJsUdf::try_parse(&udf_config.definition)?;
+        if parsed.name != name {
+            bail!(
+                "JavaScript UDF {} defines function {} instead",
+                name,
+                parsed.name
+            );
+        }
+
+        let udf = JsUdf::new(parsed).with_limits(udf_limits(), config().pipeline.udf.memory_limit);
+        self.udfs
+            .insert(name.to_string(), Arc::new(ScalarUDF::new_from_impl(udf)));
+        Ok(())
+    }
+
     pub fn add_local_udf(&mut self, local_udf: &LocalUdf) {
         let udf = Arc::new(UdfDylib::new(
             (*local_udf.config.name).to_string(),
@@ -586,7 +880,7 @@ impl Registry {
             .unwrap()
             .insert(local_udf.config.name.to_string(), udf.clone());
 
-        if !local_udf.is_async {
+        if !local_udf.is_async && !local_udf.is_stateful {
             self.add_udfs(
                 &udf,
                 &DylibUdfConfig {
@@ -600,13 +894,18 @@ impl Registry {
                     return_type: (*local_udf.config.return_type).clone(),
                     aggregate: local_udf.is_aggregate,
                     is_async: local_udf.is_async,
+                    is_stateful: local_udf.is_stateful,
                 },
             );
         }
     }
 
     fn add_udfs(&mut self, dylib: &UdfDylib, config: &DylibUdfConfig) {
-        let dylib: SyncUdfDylib = dylib.try_into().unwrap();
+        let dylib = SyncUdfDylib::try_from(dylib)
+            .unwrap()
+            .with_limits(udf_limits());
+        self.reloadable
+            .register(dylib.name(), config, Some(dylib.clone()));
         if config.aggregate {
             let output_type = Arc::new(config.return_type.clone());
 
@@ -0,0 +1,32 @@
+//! Custom routing of an operator's output to downstream subtasks.
+//!
+//! By default the planner decides how rows are routed: keyed outputs are hash partitioned by
+//! their key columns, while other outputs are spread evenly across the downstream subtasks.
+//! Operators that need control over routing (for example, to keep related rows on the same
+//! subtask without keying them, or to prefer subtasks on the same worker) can install a
+//! [`Partitioner`] with [`ArrowContext::set_partitioner`](crate::context::ArrowContext::set_partitioner),
+//! or send a batch to a single subtask with
+//! [`ArrowContext::collect_to_partition`](crate::context::ArrowContext::collect_to_partition).
+//!
+//! Keyed downstream operators rely on each key arriving at the subtask that owns its key range,
+//! so custom routing should only be used when the downstream operators don't keep keyed state.
+
+use arrow::array::types::UInt64Type;
+use arrow::array::{PrimitiveArray, RecordBatch};
+
+/// Decides which downstream subtask each row of an operator's output is sent to
+pub trait Partitioner: Send + Sync + 'static {
+    /// Returns the partition, in `0..partitions`, of each row of the batch. `partitions` is the
+    /// number of subtasks of the downstream operator, which may differ between the operator's
+    /// outputs.
+    fn partition(&self, batch: &RecordBatch, partitions: usize) -> PrimitiveArray<UInt64Type>;
+}
+
+impl<F> Partitioner for F
+where
+    F: Fn(&RecordBatch, usize) -> PrimitiveArray<UInt64Type> + Send + Sync + 'static,
+{
+    fn partition(&self, batch: &RecordBatch, partitions: usize) -> PrimitiveArray<UInt64Type> {
+        self(batch, partitions)
+    }
+}
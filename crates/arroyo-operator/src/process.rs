@@ -0,0 +1,360 @@
+//! A supported API for writing custom keyed operators in Rust.
+//!
+//! Implement [`KeyedProcessFunction`] and register it with
+//! [`register_custom_operator`](crate::custom::register_custom_operator), wrapping it in a
+//! [`KeyedProcessOperator`]:
+//!
+//! ```ignore
+//! #[derive(Default)]
+//! struct CountOnTimer;
+//!
+//! #[async_trait]
+//! impl KeyedProcessFunction for CountOnTimer {
+//!     type State = u64;
+//!
+//!     fn name(&self) -> String {
+//!         "count_on_timer".to_string()
+//!     }
+//!
+//!     async fn process_batch(
+//!         &mut self,
+//!         _batch: RecordBatch,
+//!         keys: &[Key],
+//!         ctx: &mut ProcessContext<'_, u64>,
+//!     ) {
+//!         for key in keys {
+//!             *ctx.state().get_mut(key) += 1;
+//!             let fire_at = ctx.watermark().unwrap_or(SystemTime::UNIX_EPOCH) + Duration::from_secs(60);
+//!             ctx.register_timer(key, fire_at, vec![]);
+//!         }
+//!     }
+//!
+//!     async fn on_timer(&mut self, key: Key, _: SystemTime, _: Vec<u8>, ctx: &mut ProcessContext<'_, u64>) {
+//!         let count = ctx.state().remove(&key).unwrap_or_default();
+//!         // build a batch from ctx.key_columns(&[key]) and the count, and ctx.collect() it
+//!     }
+//! }
+//!
+//! register_custom_operator("count_on_timer", |_config| {
+//!     Ok(KeyedProcessOperator::new(CountOnTimer).into_node())
+//! });
+//! ```
+//!
+//! The operator's input must be keyed; rows are routed to subtasks by key, and each key has its
+//! own state and timers, which are checkpointed and restored (including after rescaling) by the
+//! runtime. Timers fire when the watermark passes them. A key's state is dropped once it hasn't
+//! been written for the operator's state TTL (see [`KeyedProcessOperator::with_state_ttl`]).
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use arrow::array::{ArrayRef, RecordBatch};
+use arrow::row::{RowConverter, SortField};
+use arroyo_rpc::df::ArroyoSchema;
+use arroyo_rpc::get_hasher;
+use arroyo_rpc::grpc::TableConfig;
+use arroyo_types::{CheckpointBarrier, Data, SignalMessage, TaskInfo, Watermark};
+use async_trait::async_trait;
+use datafusion::common::hash_utils::create_hashes;
+use tracing::info;
+
+use crate::context::ArrowContext;
+use crate::keyed_state::DEFAULT_STATE_TTL;
+pub use crate::keyed_state::{Key, KeyedState};
+use crate::operator::{ArrowOperator, OperatorNode};
+use crate::timers::timer_table_config;
+use crate::ArrowTimerValue;
+
+/// What a [`KeyedProcessFunction`] can do while handling an event: access the state of keys,
+/// register timers, read the watermark, and emit output
+pub struct ProcessContext<'a, S> {
+    ctx: &'a mut ArrowContext,
+    state: &'a mut KeyedState<S>,
+    converter: &'a RowConverter,
+}
+
+impl<'a, S: Default> ProcessContext<'a, S> {
+    pub fn state(&mut self) -> &mut KeyedState<S> {
+        self.state
+    }
+
+    /// Registers a timer for the key, which fires once the watermark passes `time`. Timers are
+    /// rounded up to the configured granularity, and a key has at most one timer at each time,
+    /// holding the `data` of the latest registration. Returns the time the timer will fire at, or
+    /// None if the key is at its limit of outstanding timers.
+    pub fn register_timer(
+        &mut self,
+        key: &Key,
+        time: SystemTime,
+        data: Vec<u8>,
+    ) -> Option<SystemTime> {
+        self.ctx
+            .timers
+            .register(key.hash, key.bytes.clone(), time, data)
+    }
+
+    /// Cancels the key's timer at `time`, returning its data
+    pub fn cancel_timer(&mut self, key: &Key, time: SystemTime) -> Option<Vec<u8>> {
+        self.ctx.timers.cancel(&key.bytes, time)
+    }
+
+    /// The current event-time watermark of the operator, if it has received one
+    pub fn watermark(&self) -> Option<SystemTime> {
+        self.ctx.last_present_watermark()
+    }
+
+    /// Decodes keys back into the values of the input's key columns
+    pub fn key_columns(&self, keys: &[Key]) -> Vec<ArrayRef> {
+        let parser = self.converter.parser();
+        self.converter
+            .convert_rows(keys.iter().map(|k| parser.parse(&k.bytes)))
+            .expect("keys should decode with the input's key columns")
+    }
+
+    pub fn input_schema(&self) -> &ArroyoSchema {
+        &self.ctx.in_schemas[0]
+    }
+
+    pub fn output_schema(&self) -> &ArroyoSchema {
+        self.ctx
+            .out_schema
+            .as_ref()
+            .expect("keyed process operator should have an output")
+    }
+
+    pub fn task_info(&self) -> &TaskInfo {
+        &self.ctx.task_info
+    }
+
+    /// Emits a batch, which must have the output schema, to all of the operator's outputs
+    pub async fn collect(&mut self, batch: RecordBatch) {
+        self.ctx.collect(batch).await;
+    }
+
+    /// The number of outputs of the operator, in the order of its out-edges in the graph
+    pub fn outputs(&self) -> usize {
+        self.ctx.collector.outputs()
+    }
+
+    /// Emits a batch to only one of the operator's outputs, so that different results (like
+    /// late or invalid rows) can be sent to different downstream operators. All outputs share
+    /// the output schema.
+    pub async fn collect_to(&mut self, output: usize, batch: RecordBatch) {
+        self.ctx.collector.collect_to(output, batch).await;
+    }
+
+    /// The number of partitions (downstream subtasks) of one of the operator's outputs
+    pub fn partitions(&self, output: usize) -> usize {
+        self.ctx.collector.partitions(output)
+    }
+
+    /// Emits a batch to a single partition of each of the operator's outputs instead of routing
+    /// its rows by key. Downstream keyed operators expect rows to be routed by key, so this should
+    /// only be used for outputs that don't feed keyed state.
+    pub async fn collect_to_partition(&mut self, partition: usize, batch: RecordBatch) {
+        self.ctx.collect_to_partition(partition, batch).await;
+    }
+}
+
+/// Custom logic that runs on each key of a keyed stream, with per-key state and timers
+#[async_trait]
+pub trait KeyedProcessFunction: Send + 'static {
+    /// The state kept for each key, which is checkpointed
+    type State: Data + Default;
+
+    fn name(&self) -> String;
+
+    #[allow(unused_variables)]
+    async fn on_start(&mut self, ctx: &mut ProcessContext<'_, Self::State>) {}
+
+    /// Called with each input batch, along with the key of each of its rows
+    async fn process_batch(
+        &mut self,
+        batch: RecordBatch,
+        keys: &[Key],
+        ctx: &mut ProcessContext<'_, Self::State>,
+    );
+
+    /// Called when a timer registered for `key` fires, with the data it was registered with
+    #[allow(unused_variables)]
+    async fn on_timer(
+        &mut self,
+        key: Key,
+        time: SystemTime,
+        data: Vec<u8>,
+        ctx: &mut ProcessContext<'_, Self::State>,
+    ) {
+    }
+
+    /// Called when the watermark advances, after any timers it passes have fired
+    #[allow(unused_variables)]
+    async fn on_watermark(
+        &mut self,
+        watermark: SystemTime,
+        ctx: &mut ProcessContext<'_, Self::State>,
+    ) {
+    }
+
+    #[allow(unused_variables)]
+    async fn on_close(&mut self, ctx: &mut ProcessContext<'_, Self::State>) {}
+}
+
+/// Runs a [`KeyedProcessFunction`] as an operator
+pub struct KeyedProcessOperator<F: KeyedProcessFunction> {
+    function: F,
+    key_indices: Vec<usize>,
+    converter: RowConverter,
+    state: KeyedState<F::State>,
+}
+
+impl<F: KeyedProcessFunction> KeyedProcessOperator<F> {
+    pub fn new(function: F) -> Self {
+        Self {
+            function,
+            key_indices: vec![],
+            converter: RowConverter::new(vec![]).unwrap(),
+            state: KeyedState::new("k", DEFAULT_STATE_TTL),
+        }
+    }
+
+    /// Sets how long (in event time) the state of a key is kept after it was last written,
+    /// which defaults to a day
+    pub fn with_state_ttl(mut self, ttl: Duration) -> Self {
+        self.state = KeyedState::new("k", ttl);
+        self
+    }
+
+    pub fn into_node(self) -> OperatorNode {
+        OperatorNode::from_operator(Box::new(self))
+    }
+
+    fn key_hashes(&self, key_columns: &[ArrayRef]) -> Vec<u64> {
+        let mut hashes = vec![0; key_columns.first().map(|c| c.len()).unwrap_or_default()];
+        create_hashes(key_columns, &get_hasher(), &mut hashes).unwrap();
+        hashes
+    }
+}
+
+// splits the operator into the function and the context it's called with
+macro_rules! process_context {
+    ($self:ident, $ctx:ident) => {
+        (
+            &mut $self.function,
+            ProcessContext {
+                ctx: &mut *$ctx,
+                state: &mut $self.state,
+                converter: &$self.converter,
+            },
+        )
+    };
+}
+
+#[async_trait]
+impl<F: KeyedProcessFunction> ArrowOperator for KeyedProcessOperator<F> {
+    fn name(&self) -> String {
+        self.function.name()
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        let mut tables = self.state.table_config("keyed process function state");
+        tables.extend(timer_table_config());
+        tables
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        let schema = ctx.in_schemas[0].schema.clone();
+        self.key_indices = ctx.in_schemas[0]
+            .key_indices
+            .clone()
+            .expect("keyed process function input must be keyed");
+        self.converter = RowConverter::new(
+            self.key_indices
+                .iter()
+                .map(|i| SortField::new(schema.field(*i).data_type().clone()))
+                .collect(),
+        )
+        .unwrap();
+
+        self.state
+            .restore(ctx, |bytes| {
+                Ok(bincode::decode_from_slice(bytes, bincode::config::standard())?.0)
+            })
+            .await
+            .expect("should be able to restore keyed process function state");
+
+        info!(
+            "restored state for {} keys of {}",
+            self.state.len(),
+            self.function.name()
+        );
+
+        let (function, mut pctx) = process_context!(self, ctx);
+        function.on_start(&mut pctx).await;
+    }
+
+    async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
+        let key_columns: Vec<_> = self
+            .key_indices
+            .iter()
+            .map(|i| batch.column(*i).clone())
+            .collect();
+        let rows = self.converter.convert_columns(&key_columns).unwrap();
+        let keys: Vec<_> = self
+            .key_hashes(&key_columns)
+            .into_iter()
+            .zip(rows.iter())
+            .map(|(hash, row)| Key {
+                hash,
+                bytes: row.as_ref().to_vec(),
+            })
+            .collect();
+
+        let (function, mut pctx) = process_context!(self, ctx);
+        function.process_batch(batch, &keys, &mut pctx).await;
+    }
+
+    async fn handle_timers(&mut self, timers: Vec<ArrowTimerValue>, ctx: &mut ArrowContext) {
+        for timer in timers {
+            let parser = self.converter.parser();
+            let key_columns = self
+                .converter
+                .convert_rows([parser.parse(&timer.key)])
+                .unwrap();
+            let key = Key {
+                hash: self.key_hashes(&key_columns)[0],
+                bytes: timer.key,
+            };
+
+            let (function, mut pctx) = process_context!(self, ctx);
+            function
+                .on_timer(key, timer.time, timer.data, &mut pctx)
+                .await;
+        }
+    }
+
+    async fn handle_watermark(
+        &mut self,
+        watermark: Watermark,
+        ctx: &mut ArrowContext,
+    ) -> Option<Watermark> {
+        if let Watermark::EventTime(t) = watermark {
+            let (function, mut pctx) = process_context!(self, ctx);
+            function.on_watermark(t, &mut pctx).await;
+        }
+        Some(watermark)
+    }
+
+    async fn handle_checkpoint(&mut self, barrier: CheckpointBarrier, ctx: &mut ArrowContext) {
+        self.state
+            .checkpoint(barrier.epoch, ctx, |state| {
+                Ok(bincode::encode_to_vec(state, bincode::config::standard())?)
+            })
+            .await
+            .expect("should be able to checkpoint keyed process function state");
+    }
+
+    async fn on_close(&mut self, _: &Option<SignalMessage>, ctx: &mut ArrowContext) {
+        let (function, mut pctx) = process_context!(self, ctx);
+        function.on_close(&mut pctx).await;
+    }
+}
@@ -0,0 +1,184 @@
+//! Recording of the batches emitted by sources, so that a pipeline can be replayed from a
+//! checkpoint with exactly the inputs it originally read.
+//!
+//! Each source subtask buffers the batches it emits (or a random sample of them) and writes them
+//! to the checkpoint store as an Arrow IPC file when it checkpoints. The file for epoch `n`
+//! contains the batches emitted between the barriers for epochs `n - 1` and `n`, so replaying from
+//! checkpoint `n` means reading the files for the epochs after `n`, in order.
+
+use std::io::Cursor;
+
+use anyhow::{anyhow, Context};
+use arrow::array::RecordBatch;
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arroyo_rpc::config::config;
+use arroyo_storage::StorageProvider;
+use arroyo_types::TaskInfo;
+use futures::TryStreamExt;
+use tracing::{info, warn};
+
+const RECORDING_PREFIX: &str = "epoch-";
+const RECORDING_SUFFIX: &str = ".arrow";
+
+fn subtask_path(job_id: &str, operator_id: &str, task_index: usize) -> String {
+    format!(
+        "{}/{}/recordings/operator-{}/subtask-{:0>3}",
+        config().checkpoint_url,
+        job_id,
+        operator_id,
+        task_index
+    )
+}
+
+fn recording_file(epoch: u32) -> String {
+    format!("{}{:0>7}{}", RECORDING_PREFIX, epoch, RECORDING_SUFFIX)
+}
+
+/// Buffers the batches emitted by a source subtask, and writes them out on each checkpoint
+pub struct InputRecorder {
+    sample_rate: f64,
+    batches: Vec<RecordBatch>,
+    next_epoch: u32,
+    storage: Option<StorageProvider>,
+}
+
+impl InputRecorder {
+    /// Creates a recorder that records each batch with probability `sample_rate`, for a subtask
+    /// that is starting after checkpoint `restore_epoch`
+    pub fn new(sample_rate: f64, restore_epoch: Option<u32>) -> Self {
+        Self {
+            sample_rate,
+            batches: vec![],
+            next_epoch: restore_epoch.unwrap_or(0) + 1,
+            storage: None,
+        }
+    }
+
+    pub fn record(&mut self, batch: &RecordBatch) {
+        if batch.num_rows() > 0
+            && (self.sample_rate >= 1.0 || rand::random::<f64>() < self.sample_rate)
+        {
+            self.batches.push(batch.clone());
+        }
+    }
+
+    /// Writes the batches recorded since the last checkpoint as the recording for `epoch`
+    pub async fn flush(&mut self, task_info: &TaskInfo, epoch: u32) {
+        self.next_epoch = epoch + 1;
+        if self.batches.is_empty() {
+            return;
+        }
+
+        let batches = std::mem::take(&mut self.batches);
+        if let Err(e) = self.write(task_info, epoch, &batches).await {
+            // a recording is a debugging aid, and shouldn't take down the pipeline
+            warn!(
+                "failed to write recording for {}-{} epoch {}: {:?}",
+                task_info.operator_id, task_info.task_index, epoch, e
+            );
+        }
+    }
+
+    /// Writes out whatever was recorded after the last checkpoint, when the source finishes
+    pub async fn finish(&mut self, task_info: &TaskInfo) {
+        self.flush(task_info, self.next_epoch).await;
+    }
+
+    async fn write(
+        &mut self,
+        task_info: &TaskInfo,
+        epoch: u32,
+        batches: &[RecordBatch],
+    ) -> anyhow::Result<()> {
+        if self.storage.is_none() {
+            let path = subtask_path(
+                &task_info.job_id,
+                &task_info.operator_id,
+                task_info.task_index,
+            );
+            self.storage = Some(
+                StorageProvider::for_url(&path)
+                    .await
+                    .with_context(|| format!("invalid recording path {}", path))?,
+            );
+        }
+
+        let mut writer = StreamWriter::try_new(vec![], &batches[0].schema())?;
+        for batch in batches {
+            writer.write(batch)?;
+        }
+        writer.finish()?;
+
+        self.storage
+            .as_ref()
+            .unwrap()
+            .put(recording_file(epoch), writer.into_inner()?)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// The recordings of a source subtask for the epochs after `after_epoch`, in epoch order
+pub struct Recordings {
+    storage: StorageProvider,
+    files: Vec<(u32, String)>,
+}
+
+impl Recordings {
+    pub async fn open(
+        job_id: &str,
+        operator_id: &str,
+        task_index: usize,
+        after_epoch: u32,
+    ) -> anyhow::Result<Self> {
+        let path = subtask_path(job_id, operator_id, task_index);
+        let storage = StorageProvider::for_url(&path)
+            .await
+            .with_context(|| format!("invalid recording path {}", path))?;
+
+        let mut files: Vec<(u32, String)> = storage
+            .list(false)
+            .await?
+            .try_filter_map(|p| async move {
+                let Some(name) = p.filename() else {
+                    return Ok(None);
+                };
+                Ok(name
+                    .strip_prefix(RECORDING_PREFIX)
+                    .and_then(|n| n.strip_suffix(RECORDING_SUFFIX))
+                    .and_then(|n| n.parse::<u32>().ok())
+                    .filter(|epoch| *epoch > after_epoch)
+                    .map(|epoch| (epoch, name.to_string())))
+            })
+            .try_collect()
+            .await
+            .unwrap_or_else(|e| {
+                // a subtask that never recorded anything has no directory
+                info!("no recordings found at {}: {:?}", path, e);
+                vec![]
+            });
+        files.sort();
+
+        Ok(Self { storage, files })
+    }
+
+    pub fn epochs(&self) -> impl Iterator<Item = u32> + '_ {
+        self.files.iter().map(|(epoch, _)| *epoch)
+    }
+
+    /// Reads the batches recorded for the given epoch
+    pub async fn read(&self, epoch: u32) -> anyhow::Result<Vec<RecordBatch>> {
+        let (_, file) = self
+            .files
+            .iter()
+            .find(|(e, _)| *e == epoch)
+            .ok_or_else(|| anyhow!("no recording for epoch {}", epoch))?;
+
+        let data = self.storage.get(file.as_str()).await?;
+        StreamReader::try_new(Cursor::new(data), None)?
+            .map(|batch| batch.map_err(anyhow::Error::from))
+            .collect()
+    }
+}
@@ -0,0 +1,312 @@
+use crate::ArrowTimerValue;
+use arroyo_rpc::config::config;
+use arroyo_rpc::grpc::TableConfig;
+use arroyo_state::global_table_config;
+use arroyo_types::{from_nanos, to_nanos};
+use bincode::{Decode, Encode};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// Name of the global table that operators using per-key timers must include in their
+/// `tables()`; timers are restored from and checkpointed to it by the operator runtime
+pub const TIMER_TABLE: &str = "[";
+
+pub fn timer_table_config() -> HashMap<String, TableConfig> {
+    global_table_config(TIMER_TABLE, "outstanding per-key timers")
+}
+
+/// The timers of a single subtask, as stored in the timer table. Each entry carries the hash of
+/// its key so that timers can be reassigned to the right subtask on restore after rescaling.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, Default)]
+pub struct TimerState {
+    pub entries: Vec<(u64, ArrowTimerValue)>,
+}
+
+/// Per-key event-time timers for a subtask. Timers are rounded up to `granularity`, so that a key
+/// registering many timers close together keeps only one of them, with the data of the most
+/// recent registration. Each key may have at most `max_per_key` outstanding timers, and expired
+/// timers are handed out in batches of at most `fire_batch_size`.
+#[derive(Debug)]
+pub struct TimerService {
+    granularity: Duration,
+    max_per_key: usize,
+    fire_batch_size: usize,
+    by_time: BTreeMap<SystemTime, HashMap<Vec<u8>, (u64, Vec<u8>)>>,
+    per_key: HashMap<Vec<u8>, BTreeSet<SystemTime>>,
+    dropped: u64,
+}
+
+impl TimerService {
+    pub fn new(granularity: Duration, max_per_key: usize, fire_batch_size: usize) -> Self {
+        Self {
+            granularity,
+            max_per_key,
+            fire_batch_size: fire_batch_size.max(1),
+            by_time: BTreeMap::new(),
+            per_key: HashMap::new(),
+            dropped: 0,
+        }
+    }
+
+    pub fn from_config() -> Self {
+        let config = &config().pipeline.timers;
+        Self::new(
+            *config.granularity,
+            config.max_per_key,
+            config.fire_batch_size,
+        )
+    }
+
+    pub fn fire_batch_size(&self) -> usize {
+        self.fire_batch_size
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_time.values().map(|t| t.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_time.is_empty()
+    }
+
+    fn round(&self, time: SystemTime) -> SystemTime {
+        let granularity = self.granularity.as_nanos();
+        if granularity == 0 {
+            return time;
+        }
+
+        let nanos = to_nanos(time);
+        let rem = nanos % granularity;
+        if rem == 0 {
+            time
+        } else {
+            from_nanos(nanos - rem + granularity)
+        }
+    }
+
+    /// Registers a timer for `key` at `time` (rounded up to the granularity), returning the time
+    /// it will fire at. If the key already has a timer at that time, its data is replaced. Returns
+    /// None if the key is already at its limit of outstanding timers, in which case the timer is
+    /// dropped.
+    pub fn register(
+        &mut self,
+        hash: u64,
+        key: Vec<u8>,
+        time: SystemTime,
+        data: Vec<u8>,
+    ) -> Option<SystemTime> {
+        let time = self.round(time);
+
+        let times = self.per_key.entry(key.clone()).or_default();
+        if !times.contains(&time) && times.len() >= self.max_per_key {
+            self.dropped += 1;
+            if self.dropped.is_power_of_two() {
+                warn!(
+                    "dropped timer at {:?} for key with {} outstanding timers ({} dropped so far)",
+                    time,
+                    times.len(),
+                    self.dropped
+                );
+            }
+            if times.is_empty() {
+                self.per_key.remove(&key);
+            }
+            return None;
+        }
+
+        times.insert(time);
+        self.by_time
+            .entry(time)
+            .or_default()
+            .insert(key, (hash, data));
+        Some(time)
+    }
+
+    /// Removes the timer for `key` at `time` (rounded up to the granularity), returning its data
+    pub fn cancel(&mut self, key: &[u8], time: SystemTime) -> Option<Vec<u8>> {
+        let time = self.round(time);
+        let timers = self.by_time.get_mut(&time)?;
+        let (_, data) = timers.remove(key)?;
+        if timers.is_empty() {
+            self.by_time.remove(&time);
+        }
+        self.remove_key_time(key, time);
+        Some(data)
+    }
+
+    fn remove_key_time(&mut self, key: &[u8], time: SystemTime) {
+        if let Some(times) = self.per_key.get_mut(key) {
+            times.remove(&time);
+            if times.is_empty() {
+                self.per_key.remove(key);
+            }
+        }
+    }
+
+    /// Removes and returns up to `fire_batch_size` timers at or before `watermark`, earliest
+    /// first. Callers should keep calling this until it returns an empty batch.
+    pub fn pop_expired(&mut self, watermark: SystemTime) -> Vec<ArrowTimerValue> {
+        let mut fired = vec![];
+
+        while fired.len() < self.fire_batch_size {
+            let Some(mut entry) = self.by_time.first_entry() else {
+                break;
+            };
+            let time = *entry.key();
+            if time > watermark {
+                break;
+            }
+
+            let timers = entry.get_mut();
+            let keys: Vec<_> = timers
+                .keys()
+                .take(self.fire_batch_size - fired.len())
+                .cloned()
+                .collect();
+            for key in keys {
+                let (_, data) = timers.remove(&key).unwrap();
+                fired.push(ArrowTimerValue { time, key, data });
+            }
+            if timers.is_empty() {
+                entry.remove();
+            }
+        }
+
+        for timer in &fired {
+            self.remove_key_time(&timer.key, timer.time);
+        }
+
+        fired
+    }
+
+    pub fn state(&self) -> TimerState {
+        TimerState {
+            entries: self
+                .by_time
+                .iter()
+                .flat_map(|(time, timers)| {
+                    timers.iter().map(|(key, (hash, data))| {
+                        (
+                            *hash,
+                            ArrowTimerValue {
+                                time: *time,
+                                key: key.clone(),
+                                data: data.clone(),
+                            },
+                        )
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores the timers from `state` whose key hashes satisfy `include`. Restored timers bypass
+    /// the per-key limit, as they were accepted when they were registered.
+    pub fn restore(&mut self, state: &TimerState, include: impl Fn(u64) -> bool) {
+        for (hash, timer) in &state.entries {
+            if !include(*hash) {
+                continue;
+            }
+
+            self.per_key
+                .entry(timer.key.clone())
+                .or_default()
+                .insert(timer.time);
+            self.by_time
+                .entry(timer.time)
+                .or_default()
+                .insert(timer.key.clone(), (*hash, timer.data.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let mut timers = TimerService::new(Duration::from_millis(100), 10, 100);
+
+        assert_eq!(
+            timers.register(1, vec![1], at(1010), vec![1]),
+            Some(at(1100))
+        );
+        assert_eq!(
+            timers.register(1, vec![1], at(1090), vec![2]),
+            Some(at(1100))
+        );
+        assert_eq!(
+            timers.register(1, vec![1], at(1100), vec![3]),
+            Some(at(1100))
+        );
+        assert_eq!(
+            timers.register(2, vec![2], at(1050), vec![4]),
+            Some(at(1100))
+        );
+        assert_eq!(timers.len(), 2);
+
+        assert!(timers.pop_expired(at(1099)).is_empty());
+        let mut fired = timers.pop_expired(at(1100));
+        fired.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(
+            fired,
+            vec![
+                ArrowTimerValue {
+                    time: at(1100),
+                    key: vec![1],
+                    data: vec![3]
+                },
+                ArrowTimerValue {
+                    time: at(1100),
+                    key: vec![2],
+                    data: vec![4]
+                },
+            ]
+        );
+        assert!(timers.is_empty());
+    }
+
+    #[test]
+    fn test_per_key_limit() {
+        let mut timers = TimerService::new(Duration::ZERO, 2, 100);
+
+        assert!(timers.register(1, vec![1], at(1), vec![]).is_some());
+        assert!(timers.register(1, vec![1], at(2), vec![]).is_some());
+        assert!(timers.register(1, vec![1], at(3), vec![]).is_none());
+        // replacing an existing timer is still allowed
+        assert!(timers.register(1, vec![1], at(2), vec![1]).is_some());
+        assert!(timers.register(2, vec![2], at(3), vec![]).is_some());
+
+        assert_eq!(timers.cancel(&[1], at(1)), Some(vec![]));
+        assert!(timers.register(1, vec![1], at(3), vec![]).is_some());
+        assert_eq!(timers.len(), 3);
+    }
+
+    #[test]
+    fn test_batches_and_restore() {
+        let mut timers = TimerService::new(Duration::ZERO, 10, 3);
+        for i in 0..5u8 {
+            timers.register(i as u64, vec![i], at(10 - i as u64), vec![]);
+        }
+
+        let state = timers.state();
+        let first = timers.pop_expired(at(10));
+        assert_eq!(
+            first.iter().map(|t| t.key[0]).collect::<Vec<_>>(),
+            vec![4, 3, 2]
+        );
+        assert_eq!(timers.pop_expired(at(10)).len(), 2);
+        assert!(timers.pop_expired(at(10)).is_empty());
+
+        let mut restored = TimerService::new(Duration::ZERO, 10, 3);
+        restored.restore(&state, |hash| hash % 2 == 0);
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored.state().entries.len(), 3);
+    }
+}
@@ -0,0 +1,45 @@
+//! Propagation of OpenTelemetry trace context through pipelines.
+//!
+//! When enabled, the trace context of the span that emits a batch is sent downstream as a
+//! [`SignalMessage::TraceContext`](arroyo_types::SignalMessage::TraceContext) immediately before
+//! the batch, and the receiving operator processes the batch in a span that continues that
+//! trace. Connectors use the same W3C `traceparent` format to continue traces from, and pass
+//! them on to, external systems.
+
+use arroyo_rpc::config::config;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use std::collections::HashMap;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Whether trace context should be propagated through pipelines
+pub fn propagation_enabled() -> bool {
+    config().logging.tracing.propagation_enabled()
+}
+
+/// Returns the W3C `traceparent` of the span, if it belongs to a sampled trace
+pub fn traceparent(span: &Span) -> Option<String> {
+    let context = span.context();
+    if !context.span().span_context().is_sampled() {
+        return None;
+    }
+
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&context, &mut carrier);
+    carrier.remove(TRACEPARENT_HEADER)
+}
+
+/// Makes the span a child of the remote span identified by a W3C `traceparent`; invalid
+/// values are ignored
+pub fn set_parent(span: &Span, traceparent: &str) {
+    let carrier = HashMap::from([(TRACEPARENT_HEADER.to_string(), traceparent.to_string())]);
+    let context = TraceContextPropagator::new().extract(&carrier);
+
+    if context.span().span_context().is_valid() {
+        span.set_parent(context);
+    }
+}
@@ -0,0 +1,217 @@
+use arroyo_rpc::PartitionWatermarks;
+use arroyo_types::Watermark;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often an advancing watermark is emitted, matching the default period of the watermark
+/// operator
+const WATERMARK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct PartitionState {
+    max_timestamp: Option<SystemTime>,
+    last_event: Instant,
+}
+
+/// Tracks the watermark of each partition (or shard) read by a source subtask, for sources
+/// configured with [`PartitionWatermarks`]. The watermark of the subtask is the minimum over the
+/// partitions that aren't idle; a partition that hasn't produced any data yet holds the watermark
+/// back until it does or goes idle. Emitted watermarks never move backwards, even if an idle
+/// partition becomes active again behind the others.
+///
+/// Sources must flush the data they've buffered before emitting the watermarks returned by
+/// [`poll`](Self::poll), so that the data isn't considered late by downstream operators.
+#[derive(Debug)]
+pub struct PartitionWatermarkTracker<P> {
+    lateness: Duration,
+    idle_time: Option<Duration>,
+    partitions: HashMap<P, PartitionState>,
+    last_watermark: Option<Watermark>,
+    max_emitted: Option<SystemTime>,
+    last_emitted_at: Option<Instant>,
+}
+
+impl<P: Hash + Eq + Clone + Debug> PartitionWatermarkTracker<P> {
+    pub fn new(config: &PartitionWatermarks) -> Self {
+        Self {
+            lateness: Duration::from_micros(config.lateness_micros),
+            idle_time: config.idle_time_micros.map(Duration::from_micros),
+            partitions: HashMap::new(),
+            last_watermark: None,
+            max_emitted: None,
+            last_emitted_at: None,
+        }
+    }
+
+    /// Starts tracking a partition assigned to the subtask, which holds back the watermark until
+    /// it produces data or goes idle
+    pub fn add_partition(&mut self, partition: P) {
+        self.add_partition_at(partition, Instant::now());
+    }
+
+    fn add_partition_at(&mut self, partition: P, now: Instant) {
+        self.partitions
+            .entry(partition)
+            .or_insert_with(|| PartitionState {
+                max_timestamp: None,
+                last_event: now,
+            });
+    }
+
+    /// Stops tracking a partition, for example because it has been closed
+    pub fn remove_partition(&mut self, partition: &P) {
+        self.partitions.remove(partition);
+    }
+
+    /// Records the timestamp of a message read from the partition
+    pub fn observe(&mut self, partition: &P, timestamp: SystemTime) {
+        self.observe_at(partition, timestamp, Instant::now());
+    }
+
+    fn observe_at(&mut self, partition: &P, timestamp: SystemTime, now: Instant) {
+        let state = match self.partitions.get_mut(partition) {
+            Some(state) => state,
+            None => {
+                self.add_partition_at(partition.clone(), now);
+                self.partitions.get_mut(partition).unwrap()
+            }
+        };
+
+        state.max_timestamp = Some(state.max_timestamp.map_or(timestamp, |t| t.max(timestamp)));
+        state.last_event = now;
+    }
+
+    fn is_idle(&self, state: &PartitionState, now: Instant) -> bool {
+        self.idle_time
+            .is_some_and(|idle_time| now.saturating_duration_since(state.last_event) > idle_time)
+    }
+
+    fn watermark_at(&self, now: Instant) -> Option<Watermark> {
+        let mut watermark: Option<SystemTime> = None;
+        let mut any_active = false;
+
+        for state in self.partitions.values() {
+            if self.is_idle(state, now) {
+                continue;
+            }
+            any_active = true;
+
+            let partition_watermark = state
+                .max_timestamp?
+                .checked_sub(self.lateness)
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            watermark = Some(watermark.map_or(partition_watermark, |w| w.min(partition_watermark)));
+        }
+
+        if !any_active {
+            return Some(Watermark::Idle);
+        }
+
+        watermark.map(|w| Watermark::EventTime(self.max_emitted.map_or(w, |max| max.max(w))))
+    }
+
+    /// The current watermark of the subtask, if it has one
+    pub fn watermark(&self) -> Option<Watermark> {
+        self.watermark_at(Instant::now())
+    }
+
+    /// Returns the watermark to emit, if it has changed since the last one was emitted
+    pub fn poll(&mut self) -> Option<Watermark> {
+        self.poll_at(Instant::now())
+    }
+
+    fn poll_at(&mut self, now: Instant) -> Option<Watermark> {
+        let watermark = self.watermark_at(now)?;
+
+        let emit = match (watermark, self.last_watermark) {
+            (Watermark::Idle, Some(Watermark::Idle)) => false,
+            (Watermark::Idle, _) => true,
+            (Watermark::EventTime(t), Some(Watermark::EventTime(last))) => {
+                t > last
+                    && !self
+                        .last_emitted_at
+                        .is_some_and(|at| now.saturating_duration_since(at) < WATERMARK_INTERVAL)
+            }
+            (Watermark::EventTime(_), _) => true,
+        };
+
+        if !emit {
+            return None;
+        }
+
+        if let Watermark::EventTime(t) = watermark {
+            self.max_emitted = Some(t);
+        }
+        self.last_watermark = Some(watermark);
+        self.last_emitted_at = Some(now);
+        Some(watermark)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn tracker() -> PartitionWatermarkTracker<i32> {
+        PartitionWatermarkTracker::new(&PartitionWatermarks {
+            lateness_micros: 1_000_000,
+            idle_time_micros: Some(10_000_000),
+        })
+    }
+
+    #[test]
+    fn test_minimum_across_partitions() {
+        let start = Instant::now();
+        let mut tracker = tracker();
+        tracker.add_partition_at(0, start);
+        tracker.add_partition_at(1, start);
+
+        // partition 1 hasn't produced data yet, so it holds back the watermark
+        tracker.observe_at(&0, time(100), start);
+        assert_eq!(tracker.poll_at(start), None);
+
+        tracker.observe_at(&1, time(50), start);
+        assert_eq!(tracker.poll_at(start), Some(Watermark::EventTime(time(49))));
+
+        // the lagging partition advances the watermark, at most once per interval
+        tracker.observe_at(&1, time(60), start);
+        assert_eq!(tracker.poll_at(start), None);
+        let later = start + WATERMARK_INTERVAL;
+        assert_eq!(tracker.poll_at(later), Some(Watermark::EventTime(time(59))));
+    }
+
+    #[test]
+    fn test_idle_partitions() {
+        let start = Instant::now();
+        let mut tracker = tracker();
+        tracker.observe_at(&0, time(100), start);
+        tracker.observe_at(&1, time(50), start);
+        assert_eq!(tracker.poll_at(start), Some(Watermark::EventTime(time(49))));
+
+        // partition 1 goes idle, so partition 0 determines the watermark
+        let later = start + Duration::from_secs(11);
+        tracker.observe_at(&0, time(110), start + Duration::from_secs(5));
+        assert_eq!(
+            tracker.poll_at(later),
+            Some(Watermark::EventTime(time(109)))
+        );
+
+        // when partition 1 becomes active again behind partition 0, the watermark doesn't regress
+        tracker.observe_at(&1, time(60), later);
+        assert_eq!(
+            tracker.watermark_at(later),
+            Some(Watermark::EventTime(time(109)))
+        );
+
+        // once every partition is idle, so is the subtask
+        let much_later = later + Duration::from_secs(20);
+        assert_eq!(tracker.poll_at(much_later), Some(Watermark::Idle));
+        assert_eq!(tracker.poll_at(much_later), None);
+    }
+}
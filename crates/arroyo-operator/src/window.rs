@@ -0,0 +1,346 @@
+use arrow::array::{TimestampNanosecondArray, UInt32Array};
+use arroyo_types::{from_nanos, to_nanos, Window};
+use chrono::{DateTime, Datelike, FixedOffset, Months, NaiveDate, NaiveTime, Utc, Weekday};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime};
+
+/// Decides which windows each record belongs to, for operators that aggregate or join over
+/// event-time windows. Besides the built-in tumbling, sliding, and session assigners, custom
+/// operators can implement this to window by other boundaries, like calendar months or business
+/// hours.
+pub trait WindowAssigner: Debug + Send + Sync {
+    /// Returns the windows that a record with the given event time belongs to, which may be
+    /// empty if the record doesn't fall in any window
+    fn assign_windows(&self, timestamp: SystemTime) -> Vec<Window>;
+
+    /// Whether the windows assigned by this assigner may overlap windows assigned to other records
+    /// of the same key, and need to be combined with [`WindowAssigner::merge_windows`], as for
+    /// session windows
+    fn is_merging(&self) -> bool {
+        false
+    }
+
+    /// Merges the windows of a single key, returning each resulting window along with the windows
+    /// that were combined into it. Windows that aren't merged are returned as their own group.
+    fn merge_windows(&self, windows: Vec<Window>) -> Vec<(Window, Vec<Window>)> {
+        windows.into_iter().map(|w| (w, vec![w])).collect()
+    }
+
+    /// Assigns windows to each row of a batch given its timestamp column, returning the indices of
+    /// the rows that belong to each window (for use with `arrow::compute::take`). Rows with null
+    /// timestamps are not assigned to any window.
+    fn assign_batch(&self, timestamps: &TimestampNanosecondArray) -> BTreeMap<Window, UInt32Array> {
+        let mut rows: BTreeMap<Window, Vec<u32>> = BTreeMap::new();
+        for (i, timestamp) in timestamps.iter().enumerate() {
+            let Some(timestamp) = timestamp else {
+                continue;
+            };
+
+            for window in self.assign_windows(from_nanos(timestamp as u128)) {
+                rows.entry(window).or_default().push(i as u32);
+            }
+        }
+
+        rows.into_iter()
+            .map(|(window, rows)| (window, UInt32Array::from(rows)))
+            .collect()
+    }
+}
+
+/// Fixed-size, non-overlapping windows aligned to the epoch
+#[derive(Debug, Clone, Copy)]
+pub struct TumblingWindowAssigner {
+    pub width: Duration,
+}
+
+impl WindowAssigner for TumblingWindowAssigner {
+    fn assign_windows(&self, timestamp: SystemTime) -> Vec<Window> {
+        if self.width == Duration::ZERO {
+            return vec![Window::new(timestamp, timestamp)];
+        }
+
+        let mut nanos = to_nanos(timestamp);
+        nanos -= nanos % self.width.as_nanos();
+        let start = from_nanos(nanos);
+
+        vec![Window::new(start, start + self.width)]
+    }
+}
+
+/// Fixed-size windows that start every `slide`, so that each record belongs to `width / slide`
+/// windows
+#[derive(Debug, Clone, Copy)]
+pub struct SlidingWindowAssigner {
+    pub width: Duration,
+    pub slide: Duration,
+}
+
+impl WindowAssigner for SlidingWindowAssigner {
+    fn assign_windows(&self, timestamp: SystemTime) -> Vec<Window> {
+        assert!(
+            self.slide > Duration::ZERO,
+            "sliding windows must have a non-zero slide"
+        );
+
+        let nanos = to_nanos(timestamp);
+        let mut start = nanos - nanos % self.slide.as_nanos();
+        let mut windows = vec![];
+
+        while start + self.width.as_nanos() > nanos {
+            let window_start = from_nanos(start);
+            windows.push(Window::new(window_start, window_start + self.width));
+
+            let Some(prev) = start.checked_sub(self.slide.as_nanos()) else {
+                break;
+            };
+            start = prev;
+        }
+
+        windows.reverse();
+        windows
+    }
+}
+
+/// Windows that extend for as long as records keep arriving within `gap` of each other
+#[derive(Debug, Clone, Copy)]
+pub struct SessionWindowAssigner {
+    pub gap: Duration,
+}
+
+impl WindowAssigner for SessionWindowAssigner {
+    fn assign_windows(&self, timestamp: SystemTime) -> Vec<Window> {
+        vec![Window::session(timestamp, self.gap)]
+    }
+
+    fn is_merging(&self) -> bool {
+        true
+    }
+
+    fn merge_windows(&self, mut windows: Vec<Window>) -> Vec<(Window, Vec<Window>)> {
+        windows.sort();
+
+        let mut merged: Vec<(Window, Vec<Window>)> = vec![];
+        for window in windows {
+            match merged.last_mut() {
+                Some((current, members)) if window.start <= current.end => {
+                    current.end = current.end.max(window.end);
+                    members.push(window);
+                }
+                _ => merged.push((window, vec![window])),
+            }
+        }
+
+        merged
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarUnit {
+    Day,
+    /// Weeks starting on Monday
+    Week,
+    Month,
+    Year,
+}
+
+/// Non-overlapping windows aligned to calendar boundaries in a fixed timezone, like one window
+/// per month. Unlike tumbling windows, these may vary in length.
+#[derive(Debug, Clone, Copy)]
+pub struct CalendarWindowAssigner {
+    pub unit: CalendarUnit,
+    pub timezone: FixedOffset,
+}
+
+impl CalendarWindowAssigner {
+    pub fn utc(unit: CalendarUnit) -> Self {
+        Self {
+            unit,
+            timezone: FixedOffset::east_opt(0).unwrap(),
+        }
+    }
+
+    fn at_midnight(&self, date: NaiveDate) -> SystemTime {
+        date.and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_local_timezone(self.timezone)
+            .single()
+            .expect("fixed offsets have no ambiguous times")
+            .into()
+    }
+}
+
+impl WindowAssigner for CalendarWindowAssigner {
+    fn assign_windows(&self, timestamp: SystemTime) -> Vec<Window> {
+        let date = DateTime::<Utc>::from(timestamp)
+            .with_timezone(&self.timezone)
+            .date_naive();
+
+        let (start, end) = match self.unit {
+            CalendarUnit::Day => (date, date + chrono::Duration::days(1)),
+            CalendarUnit::Week => {
+                let start =
+                    date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64);
+                (start, start + chrono::Duration::days(7))
+            }
+            CalendarUnit::Month => {
+                let start = date.with_day(1).unwrap();
+                (start, start + Months::new(1))
+            }
+            CalendarUnit::Year => {
+                let start = date.with_ordinal(1).unwrap();
+                (start, start + Months::new(12))
+            }
+        };
+
+        vec![Window::new(self.at_midnight(start), self.at_midnight(end))]
+    }
+}
+
+/// One window per day spanning the business hours of that day, from `open` until `close` in a
+/// fixed timezone. Records outside of business hours (or on weekends, if `weekdays_only` is set)
+/// are not assigned to any window.
+#[derive(Debug, Clone, Copy)]
+pub struct BusinessHoursWindowAssigner {
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+    pub weekdays_only: bool,
+    pub timezone: FixedOffset,
+}
+
+impl WindowAssigner for BusinessHoursWindowAssigner {
+    fn assign_windows(&self, timestamp: SystemTime) -> Vec<Window> {
+        let local = DateTime::<Utc>::from(timestamp).with_timezone(&self.timezone);
+        let date = local.date_naive();
+
+        if self.weekdays_only && matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+            return vec![];
+        }
+
+        if local.time() < self.open || local.time() >= self.close {
+            return vec![];
+        }
+
+        let at = |time: NaiveTime| -> SystemTime {
+            date.and_time(time)
+                .and_local_timezone(self.timezone)
+                .single()
+                .expect("fixed offsets have no ambiguous times")
+                .into()
+        };
+
+        vec![Window::new(at(self.open), at(self.close))]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn time(s: &str) -> SystemTime {
+        Utc.from_utc_datetime(&s.parse().unwrap()).into()
+    }
+
+    #[test]
+    fn test_sliding() {
+        let assigner = SlidingWindowAssigner {
+            width: Duration::from_secs(60),
+            slide: Duration::from_secs(20),
+        };
+
+        let windows = assigner.assign_windows(time("2024-01-01T00:00:45"));
+        assert_eq!(
+            windows,
+            vec![
+                Window::new(time("2024-01-01T00:00:00"), time("2024-01-01T00:01:00")),
+                Window::new(time("2024-01-01T00:00:20"), time("2024-01-01T00:01:20")),
+                Window::new(time("2024-01-01T00:00:40"), time("2024-01-01T00:01:40")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_session_merge() {
+        let assigner = SessionWindowAssigner {
+            gap: Duration::from_secs(10),
+        };
+
+        let windows = ["00:00:00", "00:00:05", "00:00:30", "00:00:12"]
+            .iter()
+            .flat_map(|t| assigner.assign_windows(time(&format!("2024-01-01T{}", t))))
+            .collect();
+
+        let merged = assigner.merge_windows(windows);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(
+            merged[0].0,
+            Window::new(time("2024-01-01T00:00:00"), time("2024-01-01T00:00:22"))
+        );
+        assert_eq!(merged[0].1.len(), 3);
+        assert_eq!(
+            merged[1].0,
+            Window::new(time("2024-01-01T00:00:30"), time("2024-01-01T00:00:40"))
+        );
+    }
+
+    #[test]
+    fn test_calendar() {
+        let monthly = CalendarWindowAssigner::utc(CalendarUnit::Month);
+        assert_eq!(
+            monthly.assign_windows(time("2024-02-17T13:45:00")),
+            vec![Window::new(
+                time("2024-02-01T00:00:00"),
+                time("2024-03-01T00:00:00")
+            )]
+        );
+
+        let weekly = CalendarWindowAssigner {
+            unit: CalendarUnit::Week,
+            timezone: FixedOffset::east_opt(-5 * 3600).unwrap(),
+        };
+        // Monday 02:00 UTC is still Sunday in UTC-5
+        assert_eq!(
+            weekly.assign_windows(time("2024-01-08T02:00:00")),
+            vec![Window::new(
+                time("2024-01-01T05:00:00"),
+                time("2024-01-08T05:00:00")
+            )]
+        );
+    }
+
+    #[test]
+    fn test_business_hours() {
+        let assigner = BusinessHoursWindowAssigner {
+            open: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            weekdays_only: true,
+            timezone: FixedOffset::east_opt(0).unwrap(),
+        };
+
+        assert_eq!(
+            assigner.assign_windows(time("2024-01-03T10:30:00")),
+            vec![Window::new(
+                time("2024-01-03T09:00:00"),
+                time("2024-01-03T17:00:00")
+            )]
+        );
+        assert!(assigner
+            .assign_windows(time("2024-01-03T17:00:00"))
+            .is_empty());
+        // a Saturday
+        assert!(assigner
+            .assign_windows(time("2024-01-06T10:30:00"))
+            .is_empty());
+
+        let timestamps = TimestampNanosecondArray::from(vec![
+            Some(to_nanos(time("2024-01-03T10:30:00")) as i64),
+            None,
+            Some(to_nanos(time("2024-01-03T20:00:00")) as i64),
+            Some(to_nanos(time("2024-01-04T09:00:00")) as i64),
+        ]);
+        let batch = assigner.assign_batch(&timestamps);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.values().next().unwrap(), &UInt32Array::from(vec![0]));
+    }
+}
@@ -28,6 +28,7 @@ use tokio::sync::oneshot;
 
 use crate::extension::debezium::{DEBEZIUM_UNROLLING_EXTENSION_NAME, TO_DEBEZIUM_EXTENSION_NAME};
 use crate::extension::key_calculation::KeyCalculationExtension;
+use crate::extension::sink::SinkExtension;
 use crate::extension::{ArroyoExtension, NodeWithIncomingEdges};
 use crate::physical::{
     ArroyoMemExec, ArroyoPhysicalExtensionCodec, DebeziumUnrollingExec, DecodingContext,
@@ -46,6 +47,11 @@ pub(crate) struct PlanToGraphVisitor<'a> {
     graph: DiGraph<LogicalNode, LogicalEdge>,
     output_schemas: HashMap<NodeIndex, ArroyoSchemaRef>,
     named_nodes: HashMap<NamedNode, NodeIndex>,
+    // subplans planned by previously added statements, so that statements in the same
+    // pipeline that compute the same thing can share operators
+    planned_subplans: HashMap<LogicalPlan, NodeIndex>,
+    // subplans planned by the current statement, which are only shared once it's complete
+    pending_subplans: Vec<(LogicalPlan, NodeIndex)>,
     // each node that needs to know its inputs should push an empty vec in pre_visit.
     // In post_visit each node should clean up its vec and push its index to the last vec, if present.
     traversal: Vec<Vec<NodeIndex>>,
@@ -58,6 +64,8 @@ impl<'a> PlanToGraphVisitor<'a> {
             graph: Default::default(),
             output_schemas: Default::default(),
             named_nodes: Default::default(),
+            planned_subplans: Default::default(),
+            pending_subplans: vec![],
             traversal: vec![],
             planner: Planner::new(schema_provider),
         }
@@ -275,9 +283,22 @@ impl<'a> PlanToGraphVisitor<'a> {
     pub(crate) fn add_plan(&mut self, plan: LogicalPlan) -> Result<()> {
         self.traversal.clear();
         plan.visit(self)?;
+        self.planned_subplans
+            .extend(std::mem::take(&mut self.pending_subplans));
         Ok(())
     }
 
+    /// Returns the node already planned for an identical subplan by an earlier statement.
+    /// Sinks are never shared, as each INSERT should produce its own write.
+    fn shared_subplan(&self, node: &Arc<dyn UserDefinedLogicalNode>) -> Option<NodeIndex> {
+        if node.as_any().downcast_ref::<SinkExtension>().is_some() {
+            return None;
+        }
+        self.planned_subplans
+            .get(&LogicalPlan::Extension(Extension { node: node.clone() }))
+            .copied()
+    }
+
     pub fn into_graph(self) -> LogicalGraph {
         self.graph
     }
@@ -286,7 +307,7 @@ impl<'a> PlanToGraphVisitor<'a> {
         &mut self,
         input_nodes: Vec<NodeIndex>,
         extension: &dyn ArroyoExtension,
-    ) -> Result<()> {
+    ) -> Result<NodeIndex> {
         if let Some(node_name) = extension.node_name() {
             if self.named_nodes.contains_key(&node_name) {
                 // we should've short circuited
@@ -319,7 +340,7 @@ impl<'a> PlanToGraphVisitor<'a> {
         if let Some(node_name) = extension.node_name() {
             self.named_nodes.insert(node_name, node_index);
         }
-        Ok(())
+        Ok(node_index)
     }
 }
 
@@ -342,6 +363,10 @@ impl<'a> TreeNodeVisitor for PlanToGraphVisitor<'a> {
                 return Ok(TreeNodeRecursion::Jump);
             }
         }
+        if let Some(node_index) = self.shared_subplan(node) {
+            self.add_index_to_traversal(node_index);
+            return Ok(TreeNodeRecursion::Jump);
+        }
 
         if !node.inputs().is_empty() {
             self.traversal.push(vec![]);
@@ -369,6 +394,9 @@ impl<'a> TreeNodeVisitor for PlanToGraphVisitor<'a> {
                 return Ok(TreeNodeRecursion::Jump);
             }
         }
+        if self.shared_subplan(node).is_some() {
+            return Ok(TreeNodeRecursion::Jump);
+        }
 
         let input_nodes = if !node.inputs().is_empty() {
             self.traversal.pop().unwrap_or_default()
@@ -378,8 +406,13 @@ impl<'a> TreeNodeVisitor for PlanToGraphVisitor<'a> {
         let arroyo_extension: &dyn ArroyoExtension = node
             .try_into()
             .map_err(|e: DataFusionError| e.context("converting extension"))?;
-        self.build_extension(input_nodes, arroyo_extension)
+        let node_index = self
+            .build_extension(input_nodes, arroyo_extension)
             .map_err(|e| e.context("building extension"))?;
+        self.pending_subplans.push((
+            LogicalPlan::Extension(Extension { node: node.clone() }),
+            node_index,
+        ));
 
         Ok(TreeNodeRecursion::Continue)
     }
@@ -0,0 +1,252 @@
+//! Shared tables and views, which are defined once in the catalog and can be referenced by name
+//! from any pipeline. Only the catalog tables that a query reads (directly, or through other
+//! catalog views) are planned, ahead of the query's own statements; tables the query defines
+//! itself take precedence over catalog tables with the same name.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+
+use datafusion::common::{plan_err, DataFusionError, Result};
+use datafusion::sql::sqlparser::ast::{visit_relations, Statement};
+use unicase::UniCase;
+
+use crate::tables::Table;
+use crate::{parse_sql, ArroyoSchemaProvider};
+
+/// Parses a catalog definition, which must be a single `CREATE TABLE` or `CREATE VIEW`
+/// statement, returning the name of the table it defines along with the statement
+pub fn parse_definition(definition: &str) -> Result<(String, Statement)> {
+    let mut statements = parse_sql(definition)?;
+    if statements.len() != 1 {
+        return plan_err!(
+            "a catalog definition must be a single CREATE TABLE or CREATE VIEW statement"
+        );
+    }
+
+    let statement = statements.remove(0);
+    let Some(name) = defined_name(&statement) else {
+        return plan_err!("a catalog definition must be a CREATE TABLE or CREATE VIEW statement");
+    };
+
+    Ok((name, statement))
+}
+
+fn defined_name(statement: &Statement) -> Option<String> {
+    match statement {
+        Statement::CreateTable { name, .. } | Statement::CreateView { name, .. } => {
+            Some(name.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn referenced_tables(statement: &Statement) -> Vec<String> {
+    let mut names = vec![];
+    let _ = visit_relations(statement, |name| {
+        names.push(name.to_string());
+        ControlFlow::<()>::Continue(())
+    });
+    names
+}
+
+/// Collects catalog tables along with the catalog tables they depend on, ordered so that each
+/// table comes after its dependencies
+struct Resolver<'a> {
+    catalog: &'a HashMap<UniCase<String>, Statement>,
+    shadowed: HashSet<UniCase<String>>,
+    visiting: HashSet<UniCase<String>>,
+    resolved: HashSet<UniCase<String>>,
+    ordered: Vec<(String, Statement)>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(catalog: &'a HashMap<UniCase<String>, Statement>) -> Self {
+        Self {
+            catalog,
+            shadowed: HashSet::new(),
+            visiting: HashSet::new(),
+            resolved: HashSet::new(),
+            ordered: vec![],
+        }
+    }
+
+    fn visit(&mut self, name: UniCase<String>) -> Result<()> {
+        if self.resolved.contains(&name) {
+            return Ok(());
+        }
+        // use the name as it's defined in the catalog, rather than as it's referenced
+        let Some((name, statement)) = self.catalog.get_key_value(&name) else {
+            return Ok(());
+        };
+        let name = name.clone();
+
+        if !self.visiting.insert(name.clone()) {
+            return plan_err!("catalog table '{}' depends on itself", name);
+        }
+        for dependency in referenced_tables(statement) {
+            let dependency = UniCase::new(dependency);
+            if dependency == name {
+                continue;
+            }
+            if self.shadowed.contains(&dependency) && self.catalog.contains_key(&dependency) {
+                return plan_err!(
+                    "the query redefines table '{}', which catalog table '{}' depends on",
+                    dependency,
+                    name
+                );
+            }
+            self.visit(dependency)?;
+        }
+        self.visiting.remove(&name);
+
+        self.resolved.insert(name.clone());
+        self.ordered.push((name.into_inner(), statement.clone()));
+        Ok(())
+    }
+}
+
+/// Returns the catalog statements that `statements` depend on, in the order they must be planned
+/// in, along with the names of the catalog tables they define
+pub(crate) fn resolve(
+    statements: &[Statement],
+    catalog: &HashMap<UniCase<String>, Statement>,
+) -> Result<(Vec<Statement>, Vec<String>)> {
+    let mut resolver = Resolver::new(catalog);
+    resolver.shadowed = statements
+        .iter()
+        .filter_map(defined_name)
+        .map(UniCase::new)
+        .collect();
+
+    for name in statements.iter().flat_map(referenced_tables) {
+        let name = UniCase::new(name);
+        if !resolver.shadowed.contains(&name) {
+            resolver.visit(name)?;
+        }
+    }
+
+    Ok(resolver
+        .ordered
+        .into_iter()
+        .map(|(name, statement)| (statement, name))
+        .unzip())
+}
+
+/// Returns every catalog statement that isn't shadowed by a table defined in `statements`, in the
+/// order they must be planned in
+pub(crate) fn resolve_all(
+    statements: &[Statement],
+    catalog: &HashMap<UniCase<String>, Statement>,
+) -> Result<Vec<Statement>> {
+    let mut resolver = Resolver::new(catalog);
+    resolver.shadowed = statements
+        .iter()
+        .filter_map(defined_name)
+        .map(UniCase::new)
+        .collect();
+
+    for name in catalog.keys() {
+        if !resolver.shadowed.contains(name) {
+            resolver.visit(name.clone())?;
+        }
+    }
+
+    Ok(resolver
+        .ordered
+        .into_iter()
+        .map(|(_, statement)| statement)
+        .collect())
+}
+
+/// Plans the catalog table `name` against the other tables in the catalog, returning the names
+/// of the catalog tables it depends on
+pub fn plan_catalog_table(
+    name: &str,
+    mut schema_provider: ArroyoSchemaProvider,
+) -> Result<Vec<String>> {
+    let mut resolver = Resolver::new(&schema_provider.catalog);
+    resolver.visit(UniCase::new(name.to_string()))?;
+    let ordered = resolver.ordered;
+
+    let mut dependencies = vec![];
+    for (table_name, statement) in ordered {
+        let table = Table::try_from_statement(&statement, &schema_provider)?.ok_or_else(|| {
+            DataFusionError::Plan(format!("catalog table '{}' is not a table", table_name))
+        })?;
+        schema_provider.insert_table(table);
+
+        if UniCase::new(table_name.as_str()) != UniCase::new(name) {
+            dependencies.push(table_name);
+        }
+    }
+
+    Ok(dependencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+    use datafusion::sql::sqlparser::parser::Parser;
+
+    fn catalog(definitions: &[&str]) -> HashMap<UniCase<String>, Statement> {
+        definitions
+            .iter()
+            .map(|d| {
+                let (name, statement) = parse_definition(d).unwrap();
+                (UniCase::new(name), statement)
+            })
+            .collect()
+    }
+
+    fn resolved_names(query: &str, catalog: &HashMap<UniCase<String>, Statement>) -> Vec<String> {
+        let statements = Parser::parse_sql(&PostgreSqlDialect {}, query).unwrap();
+        resolve(&statements, catalog).unwrap().1
+    }
+
+    #[test]
+    fn test_resolve() {
+        let catalog = catalog(&[
+            "CREATE VIEW big_orders AS SELECT * FROM orders WHERE amount > 100",
+            "CREATE TABLE orders (id BIGINT, amount BIGINT)",
+            "CREATE TABLE customers (id BIGINT, name TEXT)",
+        ]);
+
+        // dependencies are planned first, and unused tables not at all
+        assert_eq!(
+            resolved_names("SELECT count(*) FROM BIG_ORDERS", &catalog),
+            vec!["orders".to_string(), "big_orders".to_string()]
+        );
+
+        // tables defined in the query shadow those in the catalog
+        assert_eq!(
+            resolved_names(
+                "CREATE TABLE customers (id BIGINT, name TEXT, email TEXT); \
+                SELECT * FROM orders JOIN customers ON orders.id = customers.id",
+                &catalog
+            ),
+            vec!["orders".to_string()]
+        );
+
+        // but not those that catalog views depend on
+        let statements = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "CREATE TABLE orders (id BIGINT); SELECT * FROM big_orders",
+        )
+        .unwrap();
+        assert!(resolve(&statements, &catalog).is_err());
+
+        assert!(parse_definition("SELECT 1").is_err());
+        assert!(parse_definition("CREATE TABLE a (x INT); CREATE TABLE b (x INT)").is_err());
+    }
+
+    #[test]
+    fn test_resolve_cycle() {
+        let catalog = catalog(&[
+            "CREATE VIEW a AS SELECT * FROM b",
+            "CREATE VIEW b AS SELECT * FROM a",
+        ]);
+        let statements = Parser::parse_sql(&PostgreSqlDialect {}, "SELECT * FROM a").unwrap();
+        assert!(resolve(&statements, &catalog).is_err());
+    }
+}
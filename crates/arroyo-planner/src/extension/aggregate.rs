@@ -4,18 +4,19 @@ use arrow::datatypes::IntervalMonthDayNanoType;
 
 use arroyo_datastream::{
     logical::{LogicalEdge, LogicalEdgeType, LogicalNode, OperatorName},
-    WindowType,
+    WindowTrigger, WindowType,
 };
 use arroyo_rpc::{
     df::{ArroyoSchema, ArroyoSchemaRef},
     grpc::api::{
-        SessionWindowAggregateOperator, SlidingWindowAggregateOperator,
-        TumblingWindowAggregateOperator,
+        self, CountWindowAggregateOperator, SessionWindowAggregateOperator,
+        SlidingWindowAggregateOperator, TumblingWindowAggregateOperator,
     },
     TIMESTAMP_FIELD,
 };
 use datafusion::common::{plan_err, Column, DFField, DFSchema, DFSchemaRef, Result, ScalarValue};
 use datafusion::error::DataFusionError;
+use datafusion::functions::datetime::date_bin;
 use datafusion::logical_expr;
 use datafusion::logical_expr::{
     expr::ScalarFunction, Aggregate, BinaryExpr, Expr, Extension, LogicalPlan,
@@ -26,7 +27,7 @@ use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
 use datafusion_proto::{physical_plan::AsExecutionPlan, protobuf::PhysicalPlanNode};
 use prost::Message;
 
-use crate::physical::window_scalar_function;
+use crate::physical::{triggered_window_scalar_function, window_scalar_function};
 use crate::{
     builder::{NamedNode, Planner, SplitPlanOutput},
     physical::ArroyoPhysicalExtensionCodec,
@@ -70,8 +71,23 @@ impl AggregateExtension {
         index: usize,
         input_schema: DFSchemaRef,
         width: Duration,
+        processing_time: bool,
+        trigger: Option<&WindowTrigger>,
     ) -> Result<LogicalNode> {
         let binning_function_proto = planner.binning_function_proto(width, input_schema.clone())?;
+        let trigger = trigger
+            .map(|trigger| {
+                let WindowBehavior::FromOperator { window_index, .. } = &self.window_behavior
+                else {
+                    return plan_err!("expected window from operator");
+                };
+                Ok(api::WindowTrigger {
+                    early_fire_micros: trigger.early_fire.map(|d| d.as_micros() as u64),
+                    allowed_lateness_micros: trigger.allowed_lateness.as_micros() as u64,
+                    window_index: *window_index as u64,
+                })
+            })
+            .transpose()?;
         let SplitPlanOutput {
             partial_aggregation_plan,
             partial_schema,
@@ -99,6 +115,8 @@ impl AggregateExtension {
             partial_aggregation_plan: partial_aggregation_plan.encode_to_vec(),
             final_aggregation_plan: finish_plan.encode_to_vec(),
             final_projection: Some(final_physical_plan_node.encode_to_vec()),
+            processing_time,
+            trigger,
         };
 
         Ok(LogicalNode {
@@ -117,6 +135,8 @@ impl AggregateExtension {
         input_schema: DFSchemaRef,
         width: Duration,
         slide: Duration,
+        processing_time: bool,
+        cumulative: bool,
     ) -> Result<LogicalNode> {
         let binning_function_proto = planner.binning_function_proto(slide, input_schema.clone())?;
 
@@ -148,11 +168,22 @@ impl AggregateExtension {
             partial_aggregation_plan: partial_aggregation_plan.encode_to_vec(),
             final_aggregation_plan: finish_plan.encode_to_vec(),
             final_projection: final_physical_plan_node.encode_to_vec(),
+            processing_time,
+            cumulative,
             // TODO add final aggregation.
         };
         Ok(LogicalNode {
-            operator_id: format!("sliding_window_{}", index),
-            description: "sliding window".to_string(),
+            operator_id: if cumulative {
+                format!("cumulating_window_{}", index)
+            } else {
+                format!("sliding_window_{}", index)
+            },
+            description: if cumulative {
+                "cumulating window"
+            } else {
+                "sliding window"
+            }
+            .to_string(),
             operator_name: OperatorName::SlidingWindowAggregate,
             operator_config: config.encode_to_vec(),
             parallelism: 1,
@@ -172,30 +203,9 @@ impl AggregateExtension {
             is_nested: false,
         } = &self.window_behavior
         else {
-            return plan_err!("expected sliding window");
-        };
-        let output_schema = self.aggregate.schema().clone();
-        let LogicalPlan::Aggregate(agg) = self.aggregate.clone() else {
-            return plan_err!("expected aggregate");
+            return plan_err!("expected session window");
         };
-        let key_count = self.key_fields.len();
-        let unkeyed_aggregate_schema = Arc::new(DFSchema::new_with_metadata(
-            output_schema.fields()[key_count..].to_vec(),
-            output_schema.metadata().clone(),
-        )?);
-
-        let unkeyed_aggregate = Aggregate::try_new_with_schema(
-            agg.input.clone(),
-            vec![],
-            agg.aggr_expr.clone(),
-            unkeyed_aggregate_schema.clone(),
-        )?;
-        let aggregate_plan = planner.sync_plan(&LogicalPlan::Aggregate(unkeyed_aggregate))?;
-
-        let physical_plan_node = PhysicalPlanNode::try_from_physical_plan(
-            aggregate_plan,
-            &ArroyoPhysicalExtensionCodec::default(),
-        )?;
+        let physical_plan_node = self.unkeyed_aggregate_plan(planner)?;
         let input_schema = ArroyoSchema::from_schema_keys(
             Arc::new(input_schema.as_ref().into()),
             self.key_fields.clone(),
@@ -221,6 +231,73 @@ impl AggregateExtension {
         })
     }
 
+    pub fn count_window_config(
+        &self,
+        planner: &Planner,
+        index: usize,
+        input_schema: DFSchemaRef,
+    ) -> Result<LogicalNode> {
+        let WindowBehavior::FromOperator {
+            window: WindowType::Count { size, slide },
+            window_index,
+            window_field,
+            is_nested: false,
+        } = &self.window_behavior
+        else {
+            return plan_err!("expected count window");
+        };
+        let physical_plan_node = self.unkeyed_aggregate_plan(planner)?;
+        let input_schema = ArroyoSchema::from_schema_keys(
+            Arc::new(input_schema.as_ref().into()),
+            self.key_fields.clone(),
+        )?;
+
+        let config = CountWindowAggregateOperator {
+            name: format!("count_window_{}", index),
+            size: *size,
+            slide: *slide,
+            window_field_name: window_field.name().to_string(),
+            window_index: *window_index as u64,
+            input_schema: Some(input_schema.into()),
+            final_aggregation_plan: physical_plan_node.encode_to_vec(),
+        };
+
+        Ok(LogicalNode {
+            operator_id: config.name.clone(),
+            description: format!("CountWindow<{}, {}>", size, slide),
+            operator_name: OperatorName::CountWindowAggregate,
+            operator_config: config.encode_to_vec(),
+            parallelism: 1,
+        })
+    }
+
+    /// The aggregate without its group by keys, which operators that split their input by key
+    /// themselves (like session and count windows) run over the rows of a single window
+    fn unkeyed_aggregate_plan(&self, planner: &Planner) -> Result<PhysicalPlanNode> {
+        let output_schema = self.aggregate.schema().clone();
+        let LogicalPlan::Aggregate(agg) = self.aggregate.clone() else {
+            return plan_err!("expected aggregate");
+        };
+        let key_count = self.key_fields.len();
+        let unkeyed_aggregate_schema = Arc::new(DFSchema::new_with_metadata(
+            output_schema.fields()[key_count..].to_vec(),
+            output_schema.metadata().clone(),
+        )?);
+
+        let unkeyed_aggregate = Aggregate::try_new_with_schema(
+            agg.input.clone(),
+            vec![],
+            agg.aggr_expr.clone(),
+            unkeyed_aggregate_schema.clone(),
+        )?;
+        let aggregate_plan = planner.sync_plan(&LogicalPlan::Aggregate(unkeyed_aggregate))?;
+
+        PhysicalPlanNode::try_from_physical_plan(
+            aggregate_plan,
+            &ArroyoPhysicalExtensionCodec::default(),
+        )
+    }
+
     pub fn instant_window_config(
         &self,
         planner: &Planner,
@@ -267,6 +344,8 @@ impl AggregateExtension {
             partial_aggregation_plan: partial_aggregation_plan.encode_to_vec(),
             final_aggregation_plan: finish_plan.encode_to_vec(),
             final_projection,
+            processing_time: false,
+            trigger: None,
         };
 
         Ok(LogicalNode {
@@ -278,7 +357,8 @@ impl AggregateExtension {
         })
     }
 
-    // projection assuming that _timestamp has been populated with the start of the bin.
+    // projection assuming that _timestamp has been populated with the start of the bin, or for
+    // cumulating windows (whose width varies) with the end of the bin.
     pub fn final_projection(
         aggregate_plan: &LogicalPlan,
         window_behavior: WindowBehavior,
@@ -298,29 +378,41 @@ impl AggregateExtension {
             .iter()
             .map(|field| Expr::Column(field.qualified_column()))
             .collect();
-        let (window_field, window_index, width, is_nested) = match window_behavior {
-            WindowBehavior::InData => return Ok(timestamp_append),
-            WindowBehavior::FromOperator {
-                window,
-                window_field,
-                window_index,
-                is_nested,
-            } => match window {
-                WindowType::Tumbling { width, .. } | WindowType::Sliding { width, .. } => {
-                    (window_field, window_index, width, is_nested)
-                }
-                WindowType::Session { .. } => {
-                    return Ok(LogicalPlan::Extension(Extension {
-                        node: Arc::new(WindowAppendExtension::new(
-                            timestamp_append,
-                            window_field,
-                            window_index,
-                        )),
-                    }))
-                }
-                WindowType::Instant => return Ok(timestamp_append),
-            },
-        };
+        let (window_field, window_index, width, is_nested, triggered, cumulative) =
+            match window_behavior {
+                WindowBehavior::InData => return Ok(timestamp_append),
+                WindowBehavior::FromOperator {
+                    window,
+                    window_field,
+                    window_index,
+                    is_nested,
+                } => match window {
+                    WindowType::Tumbling { width, trigger, .. } => (
+                        window_field,
+                        window_index,
+                        width,
+                        is_nested,
+                        trigger.is_some(),
+                        false,
+                    ),
+                    WindowType::Sliding { width, .. } => {
+                        (window_field, window_index, width, is_nested, false, false)
+                    }
+                    WindowType::Cumulating { size, .. } => {
+                        (window_field, window_index, size, is_nested, false, true)
+                    }
+                    WindowType::Session { .. } | WindowType::Count { .. } => {
+                        return Ok(LogicalPlan::Extension(Extension {
+                            node: Arc::new(WindowAppendExtension::new(
+                                timestamp_append,
+                                window_field,
+                                window_index,
+                            )),
+                        }))
+                    }
+                    WindowType::Instant => return Ok(timestamp_append),
+                },
+            };
         if is_nested {
             return Self::nested_final_projection(
                 timestamp_append,
@@ -332,20 +424,48 @@ impl AggregateExtension {
         let timestamp_column =
             Column::new(timestamp_field.qualifier().cloned(), timestamp_field.name());
         aggregate_fields.insert(window_index, window_field.clone());
-        let window_expression = Expr::ScalarFunction(ScalarFunction {
-            func_def: ScalarFunctionDefinition::UDF(Arc::new(window_scalar_function())),
-            args: vec![
+        let interval = |nanos: i64| {
+            Box::new(Expr::Literal(ScalarValue::IntervalMonthDayNano(Some(
+                IntervalMonthDayNanoType::make_value(0, 0, nanos),
+            ))))
+        };
+        let last_nanosecond = Expr::BinaryExpr(BinaryExpr {
+            left: Box::new(Expr::Column(timestamp_column.clone())),
+            op: logical_expr::Operator::Minus,
+            right: interval(1),
+        });
+        let mut window_args = if cumulative {
+            vec![
+                // the start of the period containing the bin
+                date_bin().call(vec![
+                    *interval(width.as_nanos() as i64),
+                    last_nanosecond.clone(),
+                ]),
+                // copy bin_end as second argument
+                Expr::Column(timestamp_column.clone()),
+            ]
+        } else {
+            vec![
                 // copy bin_start as first argument
                 Expr::Column(timestamp_column.clone()),
                 // add width interval to _timestamp for bin end
                 Expr::BinaryExpr(BinaryExpr {
                     left: Box::new(Expr::Column(timestamp_column.clone())),
                     op: logical_expr::Operator::Plus,
-                    right: Box::new(Expr::Literal(ScalarValue::IntervalMonthDayNano(Some(
-                        IntervalMonthDayNanoType::make_value(0, 0, width.as_nanos() as i64),
-                    )))),
+                    right: interval(width.as_nanos() as i64),
                 }),
-            ],
+            ]
+        };
+        let window_function = if triggered {
+            // the operator overwrites the emit mode of partial and late results
+            window_args.push(Expr::Literal(ScalarValue::Utf8(Some("final".to_string()))));
+            triggered_window_scalar_function()
+        } else {
+            window_scalar_function()
+        };
+        let window_expression = Expr::ScalarFunction(ScalarFunction {
+            func_def: ScalarFunctionDefinition::UDF(Arc::new(window_function)),
+            args: window_args,
         });
         aggregate_expressions.insert(
             window_index,
@@ -353,13 +473,15 @@ impl AggregateExtension {
                 .alias_qualified(window_field.qualifier().cloned(), window_field.name()),
         );
         aggregate_fields.push(timestamp_field.clone());
-        let bin_end_calculation = Expr::BinaryExpr(BinaryExpr {
-            left: Box::new(Expr::Column(timestamp_column.clone())),
-            op: logical_expr::Operator::Plus,
-            right: Box::new(Expr::Literal(ScalarValue::IntervalMonthDayNano(Some(
-                IntervalMonthDayNanoType::make_value(0, 0, (width.as_nanos() - 1) as i64),
-            )))),
-        });
+        let bin_end_calculation = if cumulative {
+            last_nanosecond
+        } else {
+            Expr::BinaryExpr(BinaryExpr {
+                left: Box::new(Expr::Column(timestamp_column.clone())),
+                op: logical_expr::Operator::Plus,
+                right: interval((width.as_nanos() - 1) as i64),
+            })
+        };
         aggregate_expressions.push(bin_end_calculation);
         Ok(LogicalPlan::Projection(
             logical_expr::Projection::try_new_with_schema(
@@ -504,15 +626,39 @@ impl ArroyoExtension for AggregateExtension {
                     self.instant_window_config(planner, index, input_df_schema, true)?
                 } else {
                     match window {
-                        WindowType::Tumbling { width } => {
-                            self.tumbling_window_config(planner, index, input_df_schema, *width)?
-                        }
-                        WindowType::Sliding { width, slide } => self.sliding_window_config(
+                        WindowType::Tumbling {
+                            width,
+                            processing_time,
+                            trigger,
+                        } => self.tumbling_window_config(
+                            planner,
+                            index,
+                            input_df_schema,
+                            *width,
+                            *processing_time,
+                            trigger.as_ref(),
+                        )?,
+                        WindowType::Sliding {
+                            width,
+                            slide,
+                            processing_time,
+                        } => self.sliding_window_config(
                             planner,
                             index,
                             input_df_schema,
                             *width,
                             *slide,
+                            *processing_time,
+                            false,
+                        )?,
+                        WindowType::Cumulating { step, size } => self.sliding_window_config(
+                            planner,
+                            index,
+                            input_df_schema,
+                            *size,
+                            *step,
+                            false,
+                            true,
                         )?,
                         WindowType::Instant => {
                             return plan_err!(
@@ -522,6 +668,9 @@ impl ArroyoExtension for AggregateExtension {
                         WindowType::Session { gap: _ } => {
                             self.session_window_config(planner, index, input_df_schema)?
                         }
+                        WindowType::Count { .. } => {
+                            self.count_window_config(planner, index, input_df_schema)?
+                        }
                     }
                 }
             }
@@ -28,6 +28,7 @@ use crate::ASYNC_RESULT_FIELD;
 use join::JoinExtension;
 
 use self::debezium::{DebeziumUnrollingExtension, ToDebeziumExtension};
+use self::stateful_udf::StatefulUDFExtension;
 use self::updating_aggregate::UpdatingAggregateExtension;
 use self::{
     aggregate::AggregateExtension, key_calculation::KeyCalculationExtension,
@@ -41,6 +42,7 @@ pub(crate) mod join;
 pub(crate) mod key_calculation;
 pub(crate) mod remote_table;
 pub(crate) mod sink;
+pub(crate) mod stateful_udf;
 pub(crate) mod table_source;
 pub(crate) mod updating_aggregate;
 pub(crate) mod watermark_node;
@@ -87,6 +89,7 @@ impl<'a> TryFrom<&'a dyn UserDefinedLogicalNode> for &'a dyn ArroyoExtension {
             .or_else(|_| try_from_t::<JoinExtension>(node))
             .or_else(|_| try_from_t::<WindowFunctionExtension>(node))
             .or_else(|_| try_from_t::<AsyncUDFExtension>(node))
+            .or_else(|_| try_from_t::<StatefulUDFExtension>(node))
             .or_else(|_| try_from_t::<ToDebeziumExtension>(node))
             .or_else(|_| try_from_t::<DebeziumUnrollingExtension>(node))
             .or_else(|_| try_from_t::<UpdatingAggregateExtension>(node))
@@ -53,10 +53,24 @@ impl SinkExtension {
                         schema = input.schema().clone();
                     }
                     (true, false) => {
-                        return plan_err!("input is updating, but sink is not updating");
+                        // upsert sinks consume the retraction flag directly
+                        if connector_table.upsert_key.is_empty() {
+                            return plan_err!(
+                                "input is updating, but sink is not updating; use a debezium \
+                                format or set upsert_key to write updates to this sink"
+                            );
+                        }
                     }
                     (false, false) => {}
                 }
+
+                if connector_table.key_expression.is_some()
+                    || connector_table.partition_expression.is_some()
+                {
+                    input =
+                        Arc::new(connector_table.add_partitioning_columns(input.as_ref().clone())?);
+                    schema = input.schema().clone();
+                }
             }
             Table::MemoryTable { .. } => return plan_err!("memory tables not supported"),
             Table::TableFromQuery { .. } => {}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::fmt::Formatter;
+use std::sync::Arc;
+
+use arroyo_datastream::logical::{
+    DylibUdfConfig, LogicalEdge, LogicalEdgeType, LogicalNode, OperatorName,
+};
+use arroyo_rpc::df::{ArroyoSchema, ArroyoSchemaRef};
+use arroyo_rpc::grpc::api::StatefulUdfOperator;
+use datafusion::common::{DFField, DFSchema, DFSchemaRef, Result};
+use datafusion::logical_expr::{
+    Expr, LogicalPlan, UserDefinedLogicalNode, UserDefinedLogicalNodeCore,
+};
+use datafusion_proto::physical_plan::to_proto::serialize_physical_expr;
+use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
+use prost::Message;
+
+use crate::builder::{NamedNode, Planner};
+use crate::STATEFUL_RESULT_FIELD;
+
+use super::{ArroyoExtension, NodeWithIncomingEdges};
+
+pub(crate) const STATEFUL_UDF_EXTENSION_NAME: &str = "StatefulUDFExtension";
+
+/// Calls a stateful UDF, whose state is kept per value of its first argument. The input is keyed
+/// by that argument, so that all calls for a key are made by the same subtask.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct StatefulUDFExtension {
+    pub(crate) input: Arc<LogicalPlan>,
+    pub(crate) name: String,
+    pub(crate) udf: DylibUdfConfig,
+    pub(crate) arg_exprs: Vec<Expr>,
+    pub(crate) final_exprs: Vec<Expr>,
+    pub(crate) final_schema: DFSchemaRef,
+}
+
+impl ArroyoExtension for StatefulUDFExtension {
+    fn node_name(&self) -> Option<NamedNode> {
+        None
+    }
+
+    fn plan_node(
+        &self,
+        planner: &Planner,
+        index: usize,
+        input_schemas: Vec<ArroyoSchemaRef>,
+    ) -> Result<NodeWithIncomingEdges> {
+        let arg_exprs = self
+            .arg_exprs
+            .iter()
+            .map(|e| {
+                let p = planner.create_physical_expr(e, self.input.schema())?;
+                Ok(serialize_physical_expr(p, &DefaultPhysicalExtensionCodec {})?.encode_to_vec())
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut final_fields = self.input.schema().fields().clone();
+        final_fields.push(DFField::new_unqualified(
+            STATEFUL_RESULT_FIELD,
+            self.udf.return_type.clone(),
+            true,
+        ));
+        let post_udf_schema = DFSchema::new_with_metadata(final_fields, HashMap::new())?;
+
+        let final_exprs = self
+            .final_exprs
+            .iter()
+            .map(|e| {
+                let p = planner.create_physical_expr(e, &post_udf_schema)?;
+                Ok(serialize_physical_expr(p, &DefaultPhysicalExtensionCodec {})?.encode_to_vec())
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let config = StatefulUdfOperator {
+            name: self.name.clone(),
+            udf: Some(self.udf.clone().into()),
+            arg_exprs,
+            final_exprs,
+        };
+
+        let node = LogicalNode {
+            operator_id: format!("stateful_udf_{}", index),
+            description: format!("stateful_udf<{}>", self.name),
+            operator_name: OperatorName::StatefulUdf,
+            operator_config: config.encode_to_vec(),
+            parallelism: 1,
+        };
+
+        let incoming_edge =
+            LogicalEdge::project_all(LogicalEdgeType::Shuffle, input_schemas[0].as_ref().clone());
+        Ok(NodeWithIncomingEdges {
+            node,
+            edges: vec![incoming_edge],
+        })
+    }
+
+    fn output_schema(&self) -> ArroyoSchema {
+        ArroyoSchema::from_fields(
+            self.final_schema
+                .fields()
+                .iter()
+                .map(|f| (**f.field()).clone())
+                .collect(),
+        )
+    }
+}
+
+impl UserDefinedLogicalNodeCore for StatefulUDFExtension {
+    fn name(&self) -> &str {
+        STATEFUL_UDF_EXTENSION_NAME
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        vec![&self.input]
+    }
+
+    fn schema(&self) -> &DFSchemaRef {
+        &self.final_schema
+    }
+
+    fn expressions(&self) -> Vec<Expr> {
+        self.arg_exprs
+            .iter()
+            .chain(self.final_exprs.iter())
+            .map(|e| e.to_owned())
+            .collect()
+    }
+
+    fn fmt_for_explain(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "StatefulUDFExtension<{}>: {}",
+            self.name,
+            self.final_schema
+                .fields()
+                .iter()
+                .map(|f| f.qualified_name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    fn from_template(&self, exprs: &[Expr], inputs: &[LogicalPlan]) -> Self {
+        assert_eq!(inputs.len(), 1, "input size inconsistent");
+        assert_eq!(
+            &UserDefinedLogicalNode::expressions(self),
+            exprs,
+            "Tried to recreate stateful UDF node with different expressions"
+        );
+
+        Self {
+            input: Arc::new(inputs[0].clone()),
+            name: self.name.clone(),
+            udf: self.udf.clone(),
+            arg_exprs: self.arg_exprs.clone(),
+            final_exprs: self.final_exprs.clone(),
+            final_schema: self.final_schema.clone(),
+        }
+    }
+}
@@ -12,6 +12,7 @@ use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
 use prost::Message;
 use std::fmt::Formatter;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub(crate) const WATERMARK_NODE_NAME: &str = "WatermarkNode";
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -21,6 +22,14 @@ pub struct WatermarkNode {
     pub watermark_expression: Expr,
     pub schema: DFSchemaRef,
     timestamp_index: usize,
+    /// the watermark-alignment group of the source and its max drift
+    pub alignment: Option<(String, Duration)>,
+    /// whether the source generates its own watermarks, which are passed through rather than
+    /// computed from the expression
+    pub source_watermarks: bool,
+    /// whether the expression yields punctuation, where each non-null value marks that all data
+    /// up to that time has been sent
+    pub punctuated: bool,
 }
 
 impl UserDefinedLogicalNodeCore for WatermarkNode {
@@ -69,6 +78,9 @@ impl UserDefinedLogicalNodeCore for WatermarkNode {
             watermark_expression: exprs[0].clone(),
             schema: self.schema.clone(),
             timestamp_index,
+            alignment: self.alignment.clone(),
+            source_watermarks: self.source_watermarks,
+            punctuated: self.punctuated,
         }
     }
 }
@@ -96,6 +108,13 @@ impl ArroyoExtension for WatermarkNode {
                 idle_time_micros: None,
                 expression: expression.encode_to_vec(),
                 input_schema: Some(self.arroyo_schema().try_into().unwrap()),
+                alignment_group: self.alignment.as_ref().map(|(group, _)| group.clone()),
+                alignment_max_drift_micros: self
+                    .alignment
+                    .as_ref()
+                    .map(|(_, drift)| drift.as_micros() as u64),
+                source_watermarks: self.source_watermarks,
+                punctuated: self.punctuated,
             }
             .encode_to_vec(),
         };
@@ -116,6 +135,9 @@ impl WatermarkNode {
         input: LogicalPlan,
         qualifier: OwnedTableReference,
         watermark_expression: Expr,
+        alignment: Option<(String, Duration)>,
+        source_watermarks: bool,
+        punctuated: bool,
     ) -> Result<Self> {
         let schema = add_timestamp_field(input.schema().clone(), Some(qualifier.clone()))?;
         let timestamp_index = schema
@@ -127,6 +149,9 @@ impl WatermarkNode {
             watermark_expression,
             schema,
             timestamp_index,
+            alignment,
+            source_watermarks,
+            punctuated,
         })
     }
     pub(crate) fn arroyo_schema(&self) -> ArroyoSchema {
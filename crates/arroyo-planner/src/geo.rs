@@ -0,0 +1,597 @@
+//! Geospatial functions. Geometries are represented as text, either GeoJSON (as produced by
+//! `st_point`) or WKT, so that they can be read from string columns and written as literals.
+//! Coordinates are longitude/latitude in degrees.
+
+use arrow_array::builder::{BooleanBuilder, Float64Builder, StringBuilder};
+use arrow_array::cast::{as_primitive_array, as_string_array};
+use arrow_array::types::{Float64Type, Int64Type};
+use arrow_array::{Array, ArrayRef, StringArray};
+use arrow_schema::DataType;
+use datafusion::common::{DataFusionError, Result, ScalarValue};
+use datafusion::logical_expr::{create_udf, ColumnarValue, ScalarUDF, Volatility};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+const MAX_GEOHASH_PRECISION: i64 = 12;
+
+type Coord = (f64, f64);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Geometry {
+    Point(Coord),
+    /// An exterior ring followed by any holes
+    Polygon(Vec<Vec<Coord>>),
+    Collection(Vec<Geometry>),
+}
+
+fn geo_err<T>(message: impl Into<String>) -> Result<T> {
+    Err(DataFusionError::Execution(message.into()))
+}
+
+impl Geometry {
+    fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.starts_with('{') {
+            let value: Value = serde_json::from_str(s)
+                .map_err(|e| DataFusionError::Execution(format!("invalid GeoJSON: {}", e)))?;
+            Self::from_geojson(&value)
+        } else {
+            Self::from_wkt(s)
+        }
+    }
+
+    fn from_geojson(value: &Value) -> Result<Self> {
+        let coord = |v: &Value| -> Result<Coord> {
+            match v.as_array().map(|c| c.as_slice()) {
+                Some([x, y, ..]) => match (x.as_f64(), y.as_f64()) {
+                    (Some(x), Some(y)) => Ok((x, y)),
+                    _ => geo_err("GeoJSON coordinates must be numbers"),
+                },
+                _ => geo_err("GeoJSON positions must have two coordinates"),
+            }
+        };
+        let list = |v: &Value| -> Result<Vec<Value>> {
+            v.as_array()
+                .cloned()
+                .ok_or_else(|| DataFusionError::Execution("expected a GeoJSON array".to_string()))
+        };
+        let polygon = |v: &Value| -> Result<Geometry> {
+            Ok(Geometry::Polygon(
+                list(v)?
+                    .iter()
+                    .map(|ring| list(ring)?.iter().map(coord).collect::<Result<Vec<_>>>())
+                    .collect::<Result<_>>()?,
+            ))
+        };
+
+        let coordinates = &value["coordinates"];
+        match value["type"].as_str() {
+            Some("Point") => Ok(Geometry::Point(coord(coordinates)?)),
+            Some("Polygon") => polygon(coordinates),
+            Some("MultiPolygon") => Ok(Geometry::Collection(
+                list(coordinates)?
+                    .iter()
+                    .map(polygon)
+                    .collect::<Result<_>>()?,
+            )),
+            Some("GeometryCollection") => Ok(Geometry::Collection(
+                list(&value["geometries"])?
+                    .iter()
+                    .map(Self::from_geojson)
+                    .collect::<Result<_>>()?,
+            )),
+            Some("Feature") => Self::from_geojson(&value["geometry"]),
+            Some("FeatureCollection") => Ok(Geometry::Collection(
+                list(&value["features"])?
+                    .iter()
+                    .map(Self::from_geojson)
+                    .collect::<Result<_>>()?,
+            )),
+            Some(t) => geo_err(format!("unsupported GeoJSON type '{}'", t)),
+            None => geo_err("GeoJSON is missing a type"),
+        }
+    }
+
+    fn from_wkt(s: &str) -> Result<Self> {
+        let (kind, body) = s
+            .find('(')
+            .map(|i| (s[..i].trim().to_ascii_uppercase(), &s[i..]))
+            .ok_or_else(|| DataFusionError::Execution(format!("invalid WKT '{}'", s)))?;
+
+        let nested = WktNested::parse(body)
+            .ok_or_else(|| DataFusionError::Execution(format!("invalid WKT '{}'", s)))?;
+
+        let polygon = |n: &WktNested| -> Option<Geometry> {
+            Some(Geometry::Polygon(
+                n.list()?
+                    .iter()
+                    .map(|ring| ring.coords().map(|c| c.to_vec()))
+                    .collect::<Option<_>>()?,
+            ))
+        };
+
+        let geometry = match kind.as_str() {
+            "POINT" => match nested.coords() {
+                Some([c]) => Some(Geometry::Point(*c)),
+                _ => None,
+            },
+            "POLYGON" => polygon(&nested),
+            "MULTIPOLYGON" => nested
+                .list()
+                .and_then(|ps| ps.iter().map(polygon).collect::<Option<_>>())
+                .map(Geometry::Collection),
+            _ => return geo_err(format!("unsupported WKT geometry '{}'", kind)),
+        };
+
+        geometry.ok_or_else(|| DataFusionError::Execution(format!("invalid WKT '{}'", s)))
+    }
+
+    fn to_geojson(&self) -> Value {
+        match self {
+            Geometry::Point((x, y)) => json!({"type": "Point", "coordinates": [x, y]}),
+            Geometry::Polygon(rings) => json!({
+                "type": "Polygon",
+                "coordinates": rings
+                    .iter()
+                    .map(|r| r.iter().map(|(x, y)| vec![*x, *y]).collect::<Vec<_>>())
+                    .collect::<Vec<_>>()
+            }),
+            Geometry::Collection(geometries) => json!({
+                "type": "GeometryCollection",
+                "geometries": geometries.iter().map(|g| g.to_geojson()).collect::<Vec<_>>()
+            }),
+        }
+    }
+
+    fn as_point(&self) -> Result<Coord> {
+        match self {
+            Geometry::Point(c) => Ok(*c),
+            _ => geo_err("expected a point geometry"),
+        }
+    }
+
+    /// Whether the point is inside the geometry; points are only contained by equal points
+    fn contains(&self, point: Coord) -> bool {
+        match self {
+            Geometry::Point(c) => *c == point,
+            Geometry::Polygon(rings) => {
+                let mut rings = rings.iter();
+                rings
+                    .next()
+                    .is_some_and(|exterior| ring_contains(exterior, point))
+                    && !rings.any(|hole| ring_contains(hole, point))
+            }
+            Geometry::Collection(geometries) => geometries.iter().any(|g| g.contains(point)),
+        }
+    }
+}
+
+/// Nested parenthesized lists of coordinates, as used by WKT
+#[derive(Debug)]
+enum WktNested {
+    Coords(Vec<Coord>),
+    List(Vec<WktNested>),
+}
+
+impl WktNested {
+    fn parse(s: &str) -> Option<Self> {
+        let (nested, rest) = Self::parse_inner(s.trim())?;
+        rest.trim().is_empty().then_some(nested)
+    }
+
+    fn parse_inner(s: &str) -> Option<(Self, &str)> {
+        let s = s.strip_prefix('(')?.trim_start();
+        if s.starts_with('(') {
+            let mut items = vec![];
+            let mut rest = s;
+            loop {
+                let (item, r) = Self::parse_inner(rest)?;
+                items.push(item);
+                let r = r.trim_start();
+                if let Some(r) = r.strip_prefix(',') {
+                    rest = r.trim_start();
+                } else {
+                    return Some((WktNested::List(items), r.strip_prefix(')')?));
+                }
+            }
+        } else {
+            let end = s.find(')')?;
+            let coords = s[..end]
+                .split(',')
+                .map(|c| {
+                    let mut parts = c.split_whitespace().map(|p| p.parse::<f64>().ok());
+                    Some((parts.next()??, parts.next()??))
+                })
+                .collect::<Option<_>>()?;
+            Some((WktNested::Coords(coords), &s[end + 1..]))
+        }
+    }
+
+    fn coords(&self) -> Option<&[Coord]> {
+        match self {
+            WktNested::Coords(c) => Some(c),
+            WktNested::List(_) => None,
+        }
+    }
+
+    fn list(&self) -> Option<&[WktNested]> {
+        match self {
+            WktNested::List(l) => Some(l),
+            WktNested::Coords(_) => None,
+        }
+    }
+}
+
+// ray casting; polygons are treated as planar in longitude/latitude, which is accurate enough
+// for geofences that don't span the antimeridian
+fn ring_contains(ring: &[Coord], (x, y): Coord) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for (i, &(xi, yi)) in ring.iter().enumerate() {
+        let (xj, yj) = ring[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Great-circle distance in meters between two points, using the haversine formula
+fn haversine_distance((lon1, lat1): Coord, (lon2, lat2): Coord) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+}
+
+fn geohash_encode((lon, lat): Coord, precision: i64) -> Result<String> {
+    if !(1..=MAX_GEOHASH_PRECISION).contains(&precision) {
+        return geo_err(format!(
+            "geohash precision must be between 1 and {}, but was {}",
+            MAX_GEOHASH_PRECISION, precision
+        ));
+    }
+    if !(-180.0..=180.0).contains(&lon) || !(-90.0..=90.0).contains(&lat) {
+        return geo_err(format!("invalid coordinates ({}, {})", lon, lat));
+    }
+
+    let mut lon_range = (-180.0, 180.0);
+    let mut lat_range = (-90.0, 90.0);
+    let mut hash = String::with_capacity(precision as usize);
+    let mut even = true;
+    for _ in 0..precision {
+        let mut index = 0;
+        for _ in 0..5 {
+            let (range, value) = if even {
+                (&mut lon_range, lon)
+            } else {
+                (&mut lat_range, lat)
+            };
+            let mid = (range.0 + range.1) / 2.0;
+            index <<= 1;
+            if value >= mid {
+                index |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+        hash.push(GEOHASH_ALPHABET[index] as char);
+    }
+    Ok(hash)
+}
+
+/// Returns the center of the geohash cell
+fn geohash_decode(hash: &str) -> Result<Coord> {
+    if hash.is_empty() {
+        return geo_err("geohash must not be empty");
+    }
+
+    let mut lon_range = (-180.0, 180.0);
+    let mut lat_range = (-90.0, 90.0);
+    let mut even = true;
+    for c in hash.to_ascii_lowercase().bytes() {
+        let Some(index) = GEOHASH_ALPHABET.iter().position(|b| *b == c) else {
+            return geo_err(format!("invalid geohash '{}'", hash));
+        };
+        for bit in (0..5).rev() {
+            let range = if even { &mut lon_range } else { &mut lat_range };
+            let mid = (range.0 + range.1) / 2.0;
+            if (index >> bit) & 1 == 1 {
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+    }
+    Ok((
+        (lon_range.0 + lon_range.1) / 2.0,
+        (lat_range.0 + lat_range.1) / 2.0,
+    ))
+}
+
+/// A geometry argument, which is parsed once if it's a literal
+enum GeometryArg<'a> {
+    Scalar(Option<Geometry>),
+    Array(&'a StringArray),
+}
+
+impl<'a> GeometryArg<'a> {
+    fn new(arg: &'a ColumnarValue, array: &'a ArrayRef) -> Result<Self> {
+        Ok(match arg {
+            ColumnarValue::Scalar(ScalarValue::Utf8(s)) => {
+                GeometryArg::Scalar(s.as_deref().map(Geometry::parse).transpose()?)
+            }
+            _ => GeometryArg::Array(as_string_array(array)),
+        })
+    }
+
+    fn get(&self, i: usize) -> Result<Option<Geometry>> {
+        match self {
+            GeometryArg::Scalar(g) => Ok(g.clone()),
+            GeometryArg::Array(a) if a.is_null(i) => Ok(None),
+            GeometryArg::Array(a) => Geometry::parse(a.value(i)).map(Some),
+        }
+    }
+}
+
+/// Evaluates a geo function over its arguments, returning a scalar if they're all scalars
+fn geo_function(
+    args: &[ColumnarValue],
+    f: impl Fn(&[ColumnarValue], &[ArrayRef]) -> Result<ArrayRef>,
+) -> Result<ColumnarValue> {
+    let scalar = args.iter().all(|a| matches!(a, ColumnarValue::Scalar(_)));
+    let arrays = ColumnarValue::values_to_arrays(args)?;
+    let result = f(args, &arrays)?;
+    if scalar {
+        Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(
+            &result, 0,
+        )?))
+    } else {
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+fn coords_at(
+    lons: &arrow_array::Float64Array,
+    lats: &arrow_array::Float64Array,
+    i: usize,
+) -> Option<Coord> {
+    (lons.is_valid(i) && lats.is_valid(i)).then(|| (lons.value(i), lats.value(i)))
+}
+
+pub fn st_point(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    geo_function(args, |_, arrays| {
+        let lons = as_primitive_array::<Float64Type>(&arrays[0]);
+        let lats = as_primitive_array::<Float64Type>(&arrays[1]);
+        let mut builder = StringBuilder::with_capacity(lons.len(), lons.len() * 48);
+        for i in 0..lons.len() {
+            builder.append_option(
+                coords_at(lons, lats, i).map(|c| Geometry::Point(c).to_geojson().to_string()),
+            );
+        }
+        Ok(Arc::new(builder.finish()))
+    })
+}
+
+pub fn st_distance(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    geo_function(args, |args, arrays| {
+        let a = GeometryArg::new(&args[0], &arrays[0])?;
+        let b = GeometryArg::new(&args[1], &arrays[1])?;
+        let mut builder = Float64Builder::with_capacity(arrays[0].len());
+        for i in 0..arrays[0].len() {
+            builder.append_option(match (a.get(i)?, b.get(i)?) {
+                (Some(a), Some(b)) => Some(haversine_distance(a.as_point()?, b.as_point()?)),
+                _ => None,
+            });
+        }
+        Ok(Arc::new(builder.finish()))
+    })
+}
+
+pub fn st_contains(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    geo_function(args, |args, arrays| {
+        let container = GeometryArg::new(&args[0], &arrays[0])?;
+        let point = GeometryArg::new(&args[1], &arrays[1])?;
+        let mut builder = BooleanBuilder::with_capacity(arrays[0].len());
+        for i in 0..arrays[0].len() {
+            builder.append_option(match (container.get(i)?, point.get(i)?) {
+                (Some(container), Some(point)) => Some(container.contains(point.as_point()?)),
+                _ => None,
+            });
+        }
+        Ok(Arc::new(builder.finish()))
+    })
+}
+
+pub fn geohash_encode_fn(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    geo_function(args, |_, arrays| {
+        let lons = as_primitive_array::<Float64Type>(&arrays[0]);
+        let lats = as_primitive_array::<Float64Type>(&arrays[1]);
+        let precisions = as_primitive_array::<Int64Type>(&arrays[2]);
+        let mut builder = StringBuilder::with_capacity(lons.len(), lons.len() * 12);
+        for i in 0..lons.len() {
+            match coords_at(lons, lats, i) {
+                Some(c) if precisions.is_valid(i) => {
+                    builder.append_value(geohash_encode(c, precisions.value(i))?);
+                }
+                _ => builder.append_null(),
+            }
+        }
+        Ok(Arc::new(builder.finish()))
+    })
+}
+
+pub fn geohash_decode_fn(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    geo_function(args, |_, arrays| {
+        let hashes = as_string_array(&arrays[0]);
+        let mut builder = StringBuilder::with_capacity(hashes.len(), hashes.len() * 48);
+        for hash in hashes.iter() {
+            match hash {
+                Some(hash) => builder.append_value(
+                    Geometry::Point(geohash_decode(hash)?)
+                        .to_geojson()
+                        .to_string(),
+                ),
+                None => builder.append_null(),
+            }
+        }
+        Ok(Arc::new(builder.finish()))
+    })
+}
+
+pub fn get_geo_functions() -> HashMap<String, Arc<ScalarUDF>> {
+    type GeoFn = fn(&[ColumnarValue]) -> Result<ColumnarValue>;
+    let functions: [(&str, Vec<DataType>, DataType, GeoFn); 5] = [
+        (
+            "st_point",
+            vec![DataType::Float64, DataType::Float64],
+            DataType::Utf8,
+            st_point,
+        ),
+        (
+            "st_distance",
+            vec![DataType::Utf8, DataType::Utf8],
+            DataType::Float64,
+            st_distance,
+        ),
+        (
+            "st_contains",
+            vec![DataType::Utf8, DataType::Utf8],
+            DataType::Boolean,
+            st_contains,
+        ),
+        (
+            "geohash_encode",
+            vec![DataType::Float64, DataType::Float64, DataType::Int64],
+            DataType::Utf8,
+            geohash_encode_fn,
+        ),
+        (
+            "geohash_decode",
+            vec![DataType::Utf8],
+            DataType::Utf8,
+            geohash_decode_fn,
+        ),
+    ];
+
+    functions
+        .into_iter()
+        .map(|(name, args, ret, f)| {
+            (
+                name.to_string(),
+                Arc::new(create_udf(
+                    name,
+                    args,
+                    Arc::new(ret),
+                    Volatility::Immutable,
+                    Arc::new(f),
+                )),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow_array::{BooleanArray, Float64Array};
+
+    const ZONE: &str = "POLYGON((0 0, 10 0, 10 10, 0 10, 0 0), (4 4, 6 4, 6 6, 4 6, 4 4))";
+
+    #[test]
+    fn test_parse() {
+        let geojson = r#"{"type": "Feature", "properties": {}, "geometry":
+            {"type": "Polygon", "coordinates": [[[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]],
+            [[4, 4], [6, 4], [6, 6], [4, 6], [4, 4]]]}}"#;
+        assert_eq!(
+            Geometry::parse(geojson).unwrap(),
+            Geometry::parse(ZONE).unwrap()
+        );
+
+        assert_eq!(
+            Geometry::parse("point (1.5 -2)").unwrap(),
+            Geometry::Point((1.5, -2.0))
+        );
+
+        let multi = Geometry::parse("MULTIPOLYGON(((0 0, 1 0, 1 1, 0 0)), ((5 5, 6 5, 6 6, 5 5)))")
+            .unwrap();
+        assert!(matches!(multi, Geometry::Collection(ref g) if g.len() == 2));
+
+        assert!(Geometry::parse("POLYGON((0 0, 1 0)").is_err());
+        assert!(Geometry::parse("LINESTRING(0 0, 1 1)").is_err());
+        assert!(Geometry::parse(r#"{"type": "Point"}"#).is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        let points = Arc::new(StringArray::from(vec![
+            Some(r#"{"type": "Point", "coordinates": [1, 1]}"#),
+            Some("POINT(5 5)"),
+            Some("POINT(11 5)"),
+            None,
+        ]));
+
+        let ColumnarValue::Array(result) = st_contains(&[
+            ColumnarValue::Scalar(ZONE.into()),
+            ColumnarValue::Array(points),
+        ])
+        .unwrap() else {
+            panic!("Expected array, got scalar");
+        };
+
+        assert_eq!(
+            *result,
+            BooleanArray::from(vec![Some(true), Some(false), Some(false), None])
+        );
+    }
+
+    #[test]
+    fn test_point_and_distance() {
+        let ColumnarValue::Scalar(ScalarValue::Utf8(Some(london))) = st_point(&[
+            ColumnarValue::Scalar(ScalarValue::Float64(Some(-0.1278))),
+            ColumnarValue::Scalar(ScalarValue::Float64(Some(51.5074))),
+        ])
+        .unwrap() else {
+            panic!("Expected scalar");
+        };
+
+        let ColumnarValue::Scalar(ScalarValue::Float64(Some(distance))) = st_distance(&[
+            ColumnarValue::Scalar(london.into()),
+            ColumnarValue::Scalar("POINT(2.3522 48.8566)".into()),
+        ])
+        .unwrap() else {
+            panic!("Expected scalar");
+        };
+
+        // London to Paris is about 344km
+        assert!((distance - 343_556.0).abs() < 100.0, "{}", distance);
+    }
+
+    #[test]
+    fn test_geohash() {
+        assert_eq!(geohash_encode((-0.1278, 51.5074), 7).unwrap(), "gcpvj0d");
+        assert!(geohash_encode((0.0, 0.0), 13).is_err());
+
+        let (lon, lat) = geohash_decode("gcpvj0d").unwrap();
+        assert!((lon - -0.1278).abs() < 0.001 && (lat - 51.5074).abs() < 0.001);
+        assert!(geohash_decode("gcpvja").is_err());
+
+        let ColumnarValue::Array(result) = geohash_encode_fn(&[
+            ColumnarValue::Array(Arc::new(Float64Array::from(vec![Some(-0.1278), None]))),
+            ColumnarValue::Array(Arc::new(Float64Array::from(vec![Some(51.5074), Some(0.0)]))),
+            ColumnarValue::Scalar(ScalarValue::Int64(Some(5))),
+        ])
+        .unwrap() else {
+            panic!("Expected array, got scalar");
+        };
+        assert_eq!(*result, StringArray::from(vec![Some("gcpvj"), None]));
+    }
+}
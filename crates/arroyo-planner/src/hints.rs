@@ -0,0 +1,492 @@
+//! Planner hints, which are given in `/*+ ... */` comments anywhere in the query and apply to the
+//! whole pipeline.
+//!
+//! Supported hints:
+//! * `RANGE_PARTITION`: keyed shuffles (those in front of aggregates and window functions) divide
+//!   the key space between subtasks at boundaries sampled from the keys observed while the
+//!   pipeline runs, rather than evenly. This spreads skewed keys more evenly, at the cost of
+//!   the boundaries only taking effect once the pipeline has been restarted or rescaled.
+//! * `FORWARD`: keyed shuffles whose input has already been partitioned by a key of the same
+//!   types, with the same parallelism and nothing but forward edges in between, are replaced with
+//!   forward edges. The planner can't check that the keys have the same values, so this is only
+//!   correct when they do (for example, when aggregating an aggregate by the same columns).
+//! * `REBALANCE(table, ...)`: the rows read from the given source tables (or all sources, if
+//!   none are given) are spread evenly across all subtasks of the next operator, to even out
+//!   skewed source partitions.
+//! * `RESCALE(table, ...)`: like `REBALANCE`, but each source subtask only sends to a subset of the
+//!   next operator's subtasks, which needs fewer connections when parallelisms differ.
+//! * `BROADCAST(table, ...)`: every row read from the given source tables (or all sources) is sent
+//!   to every subtask of the next operator.
+//! * `PARALLELISM(name=n, ...)`: runs operators with a parallelism other than the pipeline's, so
+//!   that they can be scaled independently. Names are either a source or sink table, or a kind of
+//!   operator: `source`, `sink`, `join`, `aggregate` (including windowed aggregates),
+//!   `window_function`, `watermark`, `projection`, or `udf`. A table takes precedence over its
+//!   kind. Operators chained by forward edges whose parallelisms may differ are connected by
+//!   rebalancing shuffles instead.
+//!
+//! Keyed shuffles use hash partitioning unless one of these hints changes them.
+
+use arrow_schema::DataType;
+use arroyo_datastream::logical::{EdgePartitioning, LogicalEdgeType, LogicalGraph, OperatorName};
+use arroyo_rpc::df::ArroyoSchema;
+use datafusion::common::{plan_err, Result};
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+use std::collections::HashMap;
+
+const RANGE_PARTITION: &str = "RANGE_PARTITION";
+const FORWARD: &str = "FORWARD";
+const REBALANCE: &str = "REBALANCE";
+const RESCALE: &str = "RESCALE";
+const BROADCAST: &str = "BROADCAST";
+const PARALLELISM: &str = "PARALLELISM";
+
+const HINTS: [&str; 6] = [
+    RANGE_PARTITION,
+    FORWARD,
+    REBALANCE,
+    RESCALE,
+    BROADCAST,
+    PARALLELISM,
+];
+
+const OPERATOR_KINDS: &str =
+    "source, sink, join, aggregate, window_function, watermark, projection, udf";
+
+/// The kind of operator that a name in a `PARALLELISM` hint refers to
+fn operator_kind(name: &OperatorName) -> &'static str {
+    match name {
+        OperatorName::ConnectorSource => "source",
+        OperatorName::ConnectorSink => "sink",
+        OperatorName::Join | OperatorName::InstantJoin => "join",
+        OperatorName::TumblingWindowAggregate
+        | OperatorName::SlidingWindowAggregate
+        | OperatorName::SessionWindowAggregate
+        | OperatorName::CountWindowAggregate
+        | OperatorName::UpdatingAggregate => "aggregate",
+        OperatorName::WindowFunction => "window_function",
+        OperatorName::ExpressionWatermark => "watermark",
+        OperatorName::ArrowValue | OperatorName::ArrowKey => "projection",
+        OperatorName::AsyncUdf | OperatorName::StatefulUdf => "udf",
+        OperatorName::CustomOperator => "custom",
+    }
+}
+
+/// An exchange applied to the output of source tables
+#[derive(Debug, PartialEq, Eq)]
+struct SourceExchange {
+    // empty for all sources
+    tables: Vec<String>,
+    partitioning: EdgePartitioning,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct PlannerHints {
+    range_partition: bool,
+    forward: bool,
+    source_exchanges: Vec<SourceExchange>,
+    /// Parallelism by table or operator kind, lowercased
+    parallelism: Vec<(String, usize)>,
+}
+
+/// Splits the body of a hint comment into hints and their (possibly empty) arguments
+fn split_hints(body: &str) -> Result<Vec<(&str, Vec<&str>)>> {
+    let mut hints = vec![];
+    let mut rest = body;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            return Ok(hints);
+        }
+
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == ',' || c == '(')
+            .unwrap_or(rest.len());
+        let (name, after) = rest.split_at(end);
+        if name.is_empty() {
+            return plan_err!("invalid planner hint '{}'", rest);
+        }
+
+        let after = after.trim_start();
+        if let Some(args) = after.strip_prefix('(') {
+            let Some(close) = args.find(')') else {
+                return plan_err!("unterminated arguments to planner hint '{}'", name);
+            };
+            hints.push((
+                name,
+                args[..close]
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .filter(|a| !a.is_empty())
+                    .map(|a| a.trim_matches('"'))
+                    .collect(),
+            ));
+            rest = &args[close + 1..];
+        } else {
+            hints.push((name, vec![]));
+            rest = after;
+        }
+    }
+}
+
+/// Parses the `name=n` arguments of a `PARALLELISM` hint, which may have whitespace around the `=`
+fn parse_parallelism(args: &[&str]) -> Result<Vec<(String, usize)>> {
+    if args.is_empty() {
+        return plan_err!("planner hint PARALLELISM takes arguments like PARALLELISM(join=16)");
+    }
+
+    let assignments = args
+        .join(" ")
+        .split('=')
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("=");
+
+    let mut parallelism: Vec<(String, usize)> = vec![];
+    for assignment in assignments.split_whitespace() {
+        let Some((name, value)) = assignment.split_once('=') else {
+            return plan_err!(
+                "invalid argument '{}' to planner hint PARALLELISM; expected name=parallelism",
+                assignment
+            );
+        };
+
+        let Some(p) = value.parse().ok().filter(|p: &usize| *p > 0) else {
+            return plan_err!(
+                "invalid parallelism '{}' for '{}'; expected a positive integer",
+                value,
+                name
+            );
+        };
+
+        parallelism.push((name.trim_matches('"').to_lowercase(), p));
+    }
+
+    Ok(parallelism)
+}
+
+/// The table a source operator reads from, from its id
+fn source_table(operator_id: &str) -> Option<&str> {
+    let (table, index) = operator_id.strip_prefix("source_")?.rsplit_once('_')?;
+    index.chars().all(|c| c.is_ascii_digit()).then_some(table)
+}
+
+/// The table a source reads from or a sink writes to, from its id
+fn connector_table(operator_id: &str) -> Option<&str> {
+    source_table(operator_id).or_else(|| {
+        let (table, index) = operator_id.strip_prefix("sink_")?.rsplit_once('_')?;
+        index.chars().all(|c| c.is_ascii_digit()).then_some(table)
+    })
+}
+
+impl PlannerHints {
+    pub(crate) fn parse(query: &str) -> Result<Self> {
+        let mut hints = Self::default();
+
+        let mut rest = query;
+        while let Some(start) = rest.find("/*+") {
+            let body = &rest[start + 3..];
+            let Some(end) = body.find("*/") else {
+                return plan_err!("unterminated planner hint");
+            };
+
+            for (hint, args) in split_hints(&body[..end])? {
+                if hint.eq_ignore_ascii_case(PARALLELISM) {
+                    for (name, p) in parse_parallelism(&args)? {
+                        if hints.parallelism.iter().any(|(n, _)| *n == name) {
+                            return plan_err!("parallelism of '{}' is hinted more than once", name);
+                        }
+                        hints.parallelism.push((name, p));
+                    }
+                    continue;
+                }
+
+                let partitioning = if hint.eq_ignore_ascii_case(REBALANCE) {
+                    EdgePartitioning::Rebalance
+                } else if hint.eq_ignore_ascii_case(RESCALE) {
+                    EdgePartitioning::Rescale
+                } else if hint.eq_ignore_ascii_case(BROADCAST) {
+                    EdgePartitioning::Broadcast
+                } else if !args.is_empty() {
+                    return plan_err!("planner hint '{}' doesn't take arguments", hint);
+                } else if hint.eq_ignore_ascii_case(RANGE_PARTITION) {
+                    hints.range_partition = true;
+                    continue;
+                } else if hint.eq_ignore_ascii_case(FORWARD) {
+                    hints.forward = true;
+                    continue;
+                } else {
+                    return plan_err!(
+                        "unknown planner hint '{}'; supported hints are: {}",
+                        hint,
+                        HINTS.join(", ")
+                    );
+                };
+
+                hints.source_exchanges.push(SourceExchange {
+                    tables: args.into_iter().map(|a| a.to_string()).collect(),
+                    partitioning,
+                });
+            }
+
+            rest = &body[end + 2..];
+        }
+
+        Ok(hints)
+    }
+
+    /// Applies the hints to `graph`, returning the parallelism of each operator whose parallelism
+    /// was hinted, by operator id
+    pub(crate) fn apply(&self, graph: &mut LogicalGraph) -> Result<HashMap<String, usize>> {
+        let parallelism = self.apply_parallelism(graph)?;
+        self.apply_source_exchanges(graph)?;
+
+        if self.forward {
+            let forwardable: Vec<_> = graph
+                .edge_indices()
+                .filter(|idx| is_forwardable(graph, *idx))
+                .collect();
+            for idx in forwardable {
+                let edge = &mut graph[idx];
+                edge.edge_type = LogicalEdgeType::Forward;
+                edge.partitioning = EdgePartitioning::Hash;
+            }
+        }
+
+        if self.range_partition {
+            // joins are excluded, as both of their inputs would need to be partitioned identically
+            for edge in graph.edge_weights_mut() {
+                if edge.edge_type == LogicalEdgeType::Shuffle
+                    && edge.schema.key_indices.is_some()
+                    && edge.partitioning == EdgePartitioning::Hash
+                {
+                    edge.partitioning = EdgePartitioning::Range { boundaries: vec![] };
+                }
+            }
+        }
+
+        Ok(parallelism)
+    }
+
+    fn apply_parallelism(&self, graph: &mut LogicalGraph) -> Result<HashMap<String, usize>> {
+        let mut hinted = HashMap::new();
+        if self.parallelism.is_empty() {
+            return Ok(hinted);
+        }
+
+        for (name, _) in &self.parallelism {
+            if !OPERATOR_KINDS.split(", ").any(|k| k == name)
+                && !graph.node_weights().any(|n| {
+                    connector_table(&n.operator_id).is_some_and(|t| t.eq_ignore_ascii_case(name))
+                })
+            {
+                return plan_err!(
+                    "planner hint PARALLELISM refers to '{}', which is neither a source or sink \
+                    table of the query nor a kind of operator ({})",
+                    name,
+                    OPERATOR_KINDS
+                );
+            }
+        }
+
+        for node in graph.node_weights_mut() {
+            let table = connector_table(&node.operator_id).map(|t| t.to_lowercase());
+            let kind = operator_kind(&node.operator_name);
+            let p = self
+                .parallelism
+                .iter()
+                .find(|(name, _)| Some(name) == table.as_ref())
+                .or_else(|| self.parallelism.iter().find(|(name, _)| name == kind));
+
+            if let Some((_, p)) = p {
+                node.parallelism = *p;
+                hinted.insert(node.operator_id.clone(), *p);
+            }
+        }
+
+        // the parallelism of operators without hints is only decided when the pipeline is
+        // created, so forward edges are replaced wherever the two ends could differ
+        for idx in graph.edge_indices().collect::<Vec<_>>() {
+            let (source, target) = graph.edge_endpoints(idx).unwrap();
+            if graph[idx].edge_type == LogicalEdgeType::Forward
+                && hinted.get(&graph[source].operator_id) != hinted.get(&graph[target].operator_id)
+            {
+                graph[idx].edge_type = LogicalEdgeType::Shuffle;
+                graph[idx].partitioning = EdgePartitioning::Rebalance;
+            }
+        }
+
+        Ok(hinted)
+    }
+
+    fn apply_source_exchanges(&self, graph: &mut LogicalGraph) -> Result<()> {
+        let sources: Vec<_> = graph
+            .node_indices()
+            .filter(|idx| graph[*idx].operator_name == OperatorName::ConnectorSource)
+            .collect();
+
+        for exchange in &self.source_exchanges {
+            for table in &exchange.tables {
+                if !sources.iter().any(|idx| {
+                    source_table(&graph[*idx].operator_id)
+                        .is_some_and(|t| t.eq_ignore_ascii_case(table))
+                }) {
+                    return plan_err!(
+                        "planner hint {} refers to '{}', which isn't a source table of the query",
+                        exchange.partitioning.to_string().to_uppercase(),
+                        table
+                    );
+                }
+            }
+        }
+
+        for idx in sources {
+            let table = source_table(&graph[idx].operator_id).unwrap_or_default();
+            let mut exchanges = self.source_exchanges.iter().filter(|e| {
+                e.tables.is_empty() || e.tables.iter().any(|t| t.eq_ignore_ascii_case(table))
+            });
+
+            let Some(exchange) = exchanges.next() else {
+                continue;
+            };
+            if exchanges.next().is_some() {
+                return plan_err!("source table '{}' has more than one exchange hint", table);
+            }
+
+            let edges: Vec<_> = graph
+                .edges_directed(idx, Direction::Outgoing)
+                .map(|e| e.id())
+                .collect();
+            for edge in edges {
+                graph[edge].edge_type = LogicalEdgeType::Shuffle;
+                graph[edge].partitioning = exchange.partitioning.clone();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a keyed shuffle can be replaced with a forward edge, because the rows are already
+/// partitioned by a key of the same types on the same number of subtasks
+fn is_forwardable(graph: &LogicalGraph, idx: EdgeIndex) -> bool {
+    let edge = &graph[idx];
+    if edge.edge_type != LogicalEdgeType::Shuffle || edge.partitioning != EdgePartitioning::Hash {
+        return false;
+    }
+
+    let Some(key_types) = key_types(&edge.schema) else {
+        return false;
+    };
+    let (source, target) = graph.edge_endpoints(idx).unwrap();
+    let parallelism = graph[target].parallelism;
+
+    let mut node: NodeIndex = source;
+    loop {
+        if graph[node].parallelism != parallelism {
+            return false;
+        }
+
+        let inputs: Vec<_> = graph.edges_directed(node, Direction::Incoming).collect();
+        match inputs.as_slice() {
+            [input] if input.weight().edge_type == LogicalEdgeType::Forward => {
+                node = input.source();
+            }
+            [] => return false,
+            inputs => {
+                return inputs.iter().all(|input| {
+                    input.weight().partitioning.is_keyed()
+                        && key_types(&input.weight().schema).as_ref() == Some(&key_types)
+                });
+            }
+        }
+    }
+}
+
+fn key_types(schema: &ArroyoSchema) -> Option<Vec<DataType>> {
+    let keys = schema.key_indices.as_ref().filter(|k| !k.is_empty())?;
+    Some(
+        keys.iter()
+            .map(|k| schema.schema.field(*k).data_type().clone())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hints() {
+        assert_eq!(
+            PlannerHints::parse("SELECT count(*) FROM t GROUP BY k").unwrap(),
+            PlannerHints::default()
+        );
+
+        assert!(
+            PlannerHints::parse("/*+ range_partition */ SELECT count(*) FROM t GROUP BY k")
+                .unwrap()
+                .range_partition
+        );
+
+        // regular comments aren't hints
+        assert!(
+            !PlannerHints::parse("/* range_partition */ SELECT 1")
+                .unwrap()
+                .range_partition
+        );
+
+        assert!(PlannerHints::parse("/*+ SHUFFLE */ SELECT 1").is_err());
+        assert!(PlannerHints::parse("/*+ RANGE_PARTITION SELECT 1").is_err());
+        assert!(PlannerHints::parse("/*+ FORWARD(t) */ SELECT 1").is_err());
+        assert!(PlannerHints::parse("/*+ REBALANCE(t */ SELECT 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_exchange_hints() {
+        let hints =
+            PlannerHints::parse("/*+ FORWARD, REBALANCE(bids, auctions) broadcast */ SELECT 1")
+                .unwrap();
+
+        assert!(hints.forward);
+        assert!(!hints.range_partition);
+        assert_eq!(
+            hints.source_exchanges,
+            vec![
+                SourceExchange {
+                    tables: vec!["bids".to_string(), "auctions".to_string()],
+                    partitioning: EdgePartitioning::Rebalance,
+                },
+                SourceExchange {
+                    tables: vec![],
+                    partitioning: EdgePartitioning::Broadcast,
+                },
+            ]
+        );
+
+        assert_eq!(source_table("source_my_table_3"), Some("my_table"));
+        assert_eq!(source_table("value_3"), None);
+    }
+
+    #[test]
+    fn test_parse_parallelism_hints() {
+        let hints = PlannerHints::parse("/*+ PARALLELISM(join=16, Sink = 2 orders =3) */ SELECT 1")
+            .unwrap();
+        assert_eq!(
+            hints.parallelism,
+            vec![
+                ("join".to_string(), 16),
+                ("sink".to_string(), 2),
+                ("orders".to_string(), 3)
+            ]
+        );
+
+        assert!(PlannerHints::parse("/*+ PARALLELISM */ SELECT 1").is_err());
+        assert!(PlannerHints::parse("/*+ PARALLELISM(join) */ SELECT 1").is_err());
+        assert!(PlannerHints::parse("/*+ PARALLELISM(join=0) */ SELECT 1").is_err());
+        assert!(PlannerHints::parse("/*+ PARALLELISM(join=2, join=4) */ SELECT 1").is_err());
+
+        assert_eq!(connector_table("sink_results_4"), Some("results"));
+        assert_eq!(connector_table("source_orders_0"), Some("orders"));
+    }
+}
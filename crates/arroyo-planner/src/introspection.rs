@@ -0,0 +1,357 @@
+//! Introspection of the tables and connections available to a query, through `SHOW TABLES`,
+//! `SHOW CONNECTIONS`, `DESCRIBE <table>`, and queries over the `information_schema` views,
+//! and of the pipeline a query compiles to, through `EXPLAIN`.
+//!
+//! These statements don't produce pipelines; instead they're answered directly by the planner
+//! from its schema provider, including the catalog tables visible to the query and any tables
+//! the query defines ahead of the introspection statement.
+
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, RecordBatch, StringArray, StringBuilder, UInt64Builder};
+use arrow::compute::concat_batches;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use datafusion::catalog::schema::{MemorySchemaProvider, SchemaProvider};
+use datafusion::common::{plan_err, DataFusionError, Result};
+use datafusion::datasource::MemTable;
+use datafusion::prelude::SessionContext;
+use datafusion::sql::parser::Statement as DFStatement;
+use datafusion::sql::sqlparser::ast::{visit_relations, ShowStatementFilter, Statement};
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::parser::Parser;
+use petgraph::Direction;
+use tracing::warn;
+
+use crate::catalog;
+use crate::settings::PipelineSettings;
+use crate::tables::{ConnectorTable, Table};
+use crate::{parse_and_get_arrow_program, parse_sql, ArroyoSchemaProvider, SqlConfig};
+
+pub const INFORMATION_SCHEMA: &str = "information_schema";
+
+fn reads_information_schema(statement: &Statement) -> bool {
+    let mut reads = false;
+    let mut others = false;
+    let _ = visit_relations(statement, |name| {
+        if name.0.len() == 2 && name.0[0].value.eq_ignore_ascii_case(INFORMATION_SCHEMA) {
+            reads = true;
+        } else {
+            others = true;
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    reads && !others
+}
+
+/// Whether the statement introspects the available tables, rather than being part of a pipeline
+pub fn is_introspection(statement: &Statement) -> bool {
+    match statement {
+        Statement::ShowTables { .. }
+        | Statement::ShowColumns { .. }
+        | Statement::ExplainTable { .. }
+        | Statement::Explain { .. } => true,
+        Statement::ShowVariable { variable } => {
+            variable.len() == 1 && variable[0].value.eq_ignore_ascii_case("connections")
+        }
+        Statement::Query(_) => reads_information_schema(statement),
+        _ => false,
+    }
+}
+
+/// Whether the last statement of the query is an introspection statement
+pub fn is_introspection_query(query: &str) -> bool {
+    parse_sql(query).is_ok_and(|statements| statements.last().is_some_and(is_introspection))
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Rewrites the shorthand introspection statements into queries over the information schema
+fn to_query(statement: Statement, schema_provider: &ArroyoSchemaProvider) -> Result<Statement> {
+    let sql = match &statement {
+        Statement::ShowTables { filter, .. } => {
+            let filter = match filter {
+                None => String::new(),
+                Some(ShowStatementFilter::Like(pattern)) => {
+                    format!("WHERE table_name LIKE {}", quote(pattern))
+                }
+                Some(ShowStatementFilter::ILike(pattern)) => {
+                    format!("WHERE table_name ILIKE {}", quote(pattern))
+                }
+                Some(ShowStatementFilter::Where(expr)) => format!("WHERE {}", expr),
+            };
+            format!(
+                "SELECT table_name, table_type, connector, description \
+                FROM information_schema.tables {} ORDER BY table_name",
+                filter
+            )
+        }
+        Statement::ShowColumns { table_name, .. } | Statement::ExplainTable { table_name, .. } => {
+            let name = table_name.to_string();
+            let table = schema_provider
+                .get_table(&name)
+                .ok_or_else(|| DataFusionError::Plan(format!("table '{}' not found", name)))?;
+            format!(
+                "SELECT column_name, data_type, is_nullable FROM information_schema.columns \
+                WHERE table_name = {} ORDER BY ordinal_position",
+                quote(table.name())
+            )
+        }
+        Statement::ShowVariable { .. } => "SELECT connection_name, connector, description \
+            FROM information_schema.connections ORDER BY connection_name"
+            .to_string(),
+        _ => return Ok(statement),
+    };
+
+    Ok(Parser::parse_sql(&PostgreSqlDialect {}, &sql)?.remove(0))
+}
+
+fn string_array(values: impl IntoIterator<Item = Option<impl AsRef<str>>>) -> ArrayRef {
+    Arc::new(values.into_iter().collect::<StringArray>())
+}
+
+fn tables_view(tables: &[&Table]) -> Result<RecordBatch> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("table_name", DataType::Utf8, false),
+        Field::new("table_type", DataType::Utf8, false),
+        Field::new("connector", DataType::Utf8, true),
+        Field::new("description", DataType::Utf8, true),
+    ]));
+
+    let connector = |table: &Table| match table {
+        Table::ConnectorTable(ConnectorTable {
+            connector,
+            description,
+            ..
+        }) => (Some(connector.clone()), Some(description.clone())),
+        _ => (None, None),
+    };
+
+    let table_type = |table: &Table| match table {
+        Table::ConnectorTable(c) => c.connection_type.to_string(),
+        Table::MemoryTable { .. } => "TABLE".to_string(),
+        Table::TableFromQuery { .. } | Table::PreviewSink { .. } => "VIEW".to_string(),
+    };
+
+    Ok(RecordBatch::try_new(
+        schema,
+        vec![
+            string_array(tables.iter().map(|t| Some(t.name()))),
+            string_array(tables.iter().map(|t| Some(table_type(t)))),
+            string_array(tables.iter().map(|t| connector(t).0)),
+            string_array(tables.iter().map(|t| connector(t).1)),
+        ],
+    )?)
+}
+
+fn columns_view(tables: &[&Table]) -> Result<RecordBatch> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("table_name", DataType::Utf8, false),
+        Field::new("column_name", DataType::Utf8, false),
+        Field::new("ordinal_position", DataType::UInt64, false),
+        Field::new("data_type", DataType::Utf8, false),
+        Field::new("is_nullable", DataType::Utf8, false),
+    ]));
+
+    let mut table_name = StringBuilder::new();
+    let mut column_name = StringBuilder::new();
+    let mut ordinal_position = UInt64Builder::new();
+    let mut data_type = StringBuilder::new();
+    let mut is_nullable = StringBuilder::new();
+
+    for table in tables {
+        for (i, field) in table.get_fields().iter().enumerate() {
+            table_name.append_value(table.name());
+            column_name.append_value(field.name());
+            ordinal_position.append_value(i as u64 + 1);
+            data_type.append_value(field.data_type().to_string());
+            is_nullable.append_value(if field.is_nullable() { "YES" } else { "NO" });
+        }
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(table_name.finish()),
+        Arc::new(column_name.finish()),
+        Arc::new(ordinal_position.finish()),
+        Arc::new(data_type.finish()),
+        Arc::new(is_nullable.finish()),
+    ];
+
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+fn connections_view(schema_provider: &ArroyoSchemaProvider) -> Result<RecordBatch> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("connection_name", DataType::Utf8, false),
+        Field::new("connector", DataType::Utf8, false),
+        Field::new("description", DataType::Utf8, false),
+    ]));
+
+    let profiles: Vec<_> = schema_provider.profiles.values().collect();
+
+    Ok(RecordBatch::try_new(
+        schema,
+        vec![
+            string_array(profiles.iter().map(|p| Some(&p.name))),
+            string_array(profiles.iter().map(|p| Some(&p.connector))),
+            string_array(profiles.iter().map(|p| Some(&p.description))),
+        ],
+    )?)
+}
+
+fn information_schema(schema_provider: &ArroyoSchemaProvider) -> Result<Arc<dyn SchemaProvider>> {
+    let mut tables: Vec<_> = schema_provider
+        .tables
+        .values()
+        .filter(|t| !matches!(t, Table::PreviewSink { .. }))
+        .collect();
+    tables.sort_by(|a, b| a.name().cmp(b.name()));
+
+    let schema = MemorySchemaProvider::new();
+    for (name, batch) in [
+        ("tables", tables_view(&tables)?),
+        ("columns", columns_view(&tables)?),
+        ("connections", connections_view(schema_provider)?),
+    ] {
+        let table = MemTable::try_new(batch.schema(), vec![vec![batch]])?;
+        schema.register_table(name.to_string(), Arc::new(table))?;
+    }
+
+    Ok(Arc::new(schema))
+}
+
+/// Compiles the statements ahead of an `EXPLAIN` along with the explained statement, and
+/// describes the operators of the resulting pipeline. Operators shared by several statements
+/// have more than one downstream operator.
+async fn explain(
+    mut statements: Vec<Statement>,
+    statement: Statement,
+    schema_provider: ArroyoSchemaProvider,
+) -> Result<RecordBatch> {
+    statements.push(statement);
+    let query = statements
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>()
+        .join(";\n");
+
+    let compiled =
+        parse_and_get_arrow_program(query, schema_provider, SqlConfig::default()).await?;
+    let graph = &compiled.program.graph;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("operator_id", DataType::Utf8, false),
+        Field::new("operator", DataType::Utf8, false),
+        Field::new("description", DataType::Utf8, false),
+        Field::new("parallelism", DataType::UInt64, false),
+        Field::new("inputs", DataType::Utf8, false),
+        Field::new("outputs", DataType::Utf8, false),
+    ]));
+
+    let neighbors = |index, direction| {
+        graph
+            .neighbors_directed(index, direction)
+            .map(|n| graph[n].operator_id.clone())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut operator_id = StringBuilder::new();
+    let mut operator = StringBuilder::new();
+    let mut description = StringBuilder::new();
+    let mut parallelism = UInt64Builder::new();
+    let mut inputs = StringBuilder::new();
+    let mut outputs = StringBuilder::new();
+
+    for index in graph.node_indices() {
+        let node = &graph[index];
+        operator_id.append_value(&node.operator_id);
+        operator.append_value(node.operator_name.to_string());
+        description.append_value(&node.description);
+        parallelism.append_value(node.parallelism as u64);
+        inputs.append_value(neighbors(index, Direction::Incoming));
+        outputs.append_value(neighbors(index, Direction::Outgoing));
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(operator_id.finish()),
+        Arc::new(operator.finish()),
+        Arc::new(description.finish()),
+        Arc::new(parallelism.finish()),
+        Arc::new(inputs.finish()),
+        Arc::new(outputs.finish()),
+    ];
+
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+/// If the last statement of the query is an introspection statement, evaluates it against the
+/// tables available to the query, returning the results. Statements ahead of it may only define
+/// tables or make settings, except for `EXPLAIN`, which explains the whole pipeline.
+pub async fn introspect(
+    query: &str,
+    mut schema_provider: ArroyoSchemaProvider,
+) -> Result<Option<RecordBatch>> {
+    let mut statements = parse_sql(query)?;
+    if !statements.last().is_some_and(is_introspection) {
+        return Ok(None);
+    }
+    let statement = statements.pop().unwrap();
+
+    if let Statement::Explain {
+        statement, analyze, ..
+    } = statement
+    {
+        if analyze {
+            return plan_err!(
+                "EXPLAIN ANALYZE is not supported in queries; the plan of a running job can be \
+                analyzed with the jobs API, at /v1/pipelines/{{pipeline_id}}/jobs/{{job_id}}/analyze"
+            );
+        }
+        return explain(statements, *statement, schema_provider)
+            .await
+            .map(Some);
+    }
+
+    for catalog_statement in catalog::resolve_all(&statements, &schema_provider.catalog)? {
+        match Table::try_from_statement(&catalog_statement, &schema_provider) {
+            Ok(Some(table)) => schema_provider.insert_table(table),
+            Ok(None) => {}
+            Err(e) => warn!("Invalid catalog table: {}", e),
+        }
+    }
+
+    let mut settings = PipelineSettings::default();
+    for statement in &statements {
+        if settings.try_from_statement(statement)? {
+            continue;
+        }
+
+        let Some(table) = Table::try_from_statement(statement, &schema_provider)? else {
+            return plan_err!(
+                "only CREATE TABLE and SET statements may come before an introspection statement"
+            );
+        };
+        schema_provider.insert_table(table);
+    }
+
+    let statement = to_query(statement, &schema_provider)?;
+
+    let ctx = SessionContext::new();
+    ctx.catalog(&ctx.state().config_options().catalog.default_catalog)
+        .ok_or_else(|| DataFusionError::Plan("no default catalog".to_string()))?
+        .register_schema(INFORMATION_SCHEMA, information_schema(&schema_provider)?)?;
+
+    let plan = ctx
+        .state()
+        .statement_to_plan(DFStatement::Statement(Box::new(statement)))
+        .await?;
+    let df = ctx.execute_logical_plan(plan).await?;
+    let schema: SchemaRef = Arc::new(df.schema().into());
+    let batches = df.collect().await?;
+
+    let batch = concat_batches(&schema, &batches)?;
+    Ok(Some(batch))
+}
@@ -1,6 +1,6 @@
 use arrow_array::builder::{ListBuilder, StringBuilder};
 use arrow_array::cast::as_string_array;
-use arrow_array::{Array, ArrayRef, StringArray};
+use arrow_array::{Array, ArrayRef, BooleanArray, StringArray};
 use arrow_schema::{DataType, Field};
 use datafusion::common::Result;
 use datafusion::common::{DataFusionError, ScalarValue};
@@ -49,6 +49,37 @@ pub fn get_json_functions() -> HashMap<String, Arc<ScalarUDF>> {
         )),
     );
 
+    for (name, f) in [
+        (
+            "json_extract",
+            json_extract as fn(&[ColumnarValue]) -> Result<ColumnarValue>,
+        ),
+        ("json_extract_string", json_extract_string),
+        ("json_query", json_query),
+    ] {
+        udfs.insert(
+            name.to_string(),
+            Arc::new(create_udf(
+                name,
+                vec![DataType::Utf8, DataType::Utf8],
+                Arc::new(DataType::Utf8),
+                Volatility::Immutable,
+                Arc::new(f),
+            )),
+        );
+    }
+
+    udfs.insert(
+        "json_exists".to_string(),
+        Arc::new(create_udf(
+            "json_exists",
+            vec![DataType::Utf8, DataType::Utf8],
+            Arc::new(DataType::Boolean),
+            Volatility::Immutable,
+            Arc::new(json_exists),
+        )),
+    );
+
     udfs
 }
 
@@ -184,6 +215,61 @@ pub fn extract_json_string(args: &[ColumnarValue]) -> Result<ColumnarValue> {
     )
 }
 
+/// Returns the first value matched by the path, as JSON
+pub fn json_extract(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    json_function::<String, StringArray, _, _>(
+        "json_extract",
+        |s, path| path.query(&s).first().map(|v| v.to_string()),
+        |s| s.as_deref().into(),
+        args,
+    )
+}
+
+/// Returns the first value matched by the path as text, if it's a string, number, or boolean.
+/// Unlike `extract_json_string`, non-string scalars are converted rather than returning null.
+pub fn json_extract_string(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    json_function::<String, StringArray, _, _>(
+        "json_extract_string",
+        |s, path| match path.query(&s).first()? {
+            serde_json::Value::String(s) => Some(s.clone()),
+            v @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_)) => Some(v.to_string()),
+            _ => None,
+        },
+        |s| s.as_deref().into(),
+        args,
+    )
+}
+
+/// Returns the values matched by the path as JSON; a single match is returned as is, while
+/// multiple matches are wrapped in an array
+pub fn json_query(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    json_function::<String, StringArray, _, _>(
+        "json_query",
+        |s, path| {
+            let matches = path.query(&s).all();
+            match matches.as_slice() {
+                [] => None,
+                [v] => Some(v.to_string()),
+                vs => Some(
+                    serde_json::Value::Array(vs.iter().map(|v| (*v).clone()).collect()).to_string(),
+                ),
+            }
+        },
+        |s| s.as_deref().into(),
+        args,
+    )
+}
+
+/// Returns whether the path matches any value; null if the input isn't valid JSON
+pub fn json_exists(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    json_function::<bool, BooleanArray, _, _>(
+        "json_exists",
+        |s, path| Some(!path.query(&s).is_empty()),
+        ScalarValue::Boolean,
+        args,
+    )
+}
+
 #[cfg(test)]
 mod test {
     use arrow_array::builder::{ListBuilder, StringBuilder};
@@ -272,6 +358,55 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_json_path_functions() {
+        use arrow_array::BooleanArray;
+
+        let input = || {
+            super::ColumnarValue::Array(Arc::new(StringArray::from(vec![
+                Some(r#"{"a": 1, "b": [2, 3], "c": { "d": "hello" }}"#),
+                Some(r#"{"a": true}"#),
+                Some("not json"),
+                None,
+            ])))
+        };
+
+        let call = |f: fn(&[super::ColumnarValue]) -> super::Result<super::ColumnarValue>,
+                    path: &str| {
+            let super::ColumnarValue::Array(result) =
+                f(&[input(), super::ColumnarValue::Scalar(path.into())]).unwrap()
+            else {
+                panic!("Expected array, got scalar");
+            };
+            result
+        };
+
+        assert_eq!(
+            *call(super::json_extract, "$.c"),
+            StringArray::from(vec![Some(r#"{"d":"hello"}"#), None, None, None])
+        );
+        assert_eq!(
+            *call(super::json_extract_string, "$.a"),
+            StringArray::from(vec![Some("1"), Some("true"), None, None])
+        );
+        assert_eq!(
+            *call(super::json_extract_string, "$.c.d"),
+            StringArray::from(vec![Some("hello"), None, None, None])
+        );
+        assert_eq!(
+            *call(super::json_query, "$.b[*]"),
+            StringArray::from(vec![Some("[2,3]"), None, None, None])
+        );
+        assert_eq!(
+            *call(super::json_query, "$.b[0]"),
+            StringArray::from(vec![Some("2"), None, None, None])
+        );
+        assert_eq!(
+            *call(super::json_exists, "$.c.d"),
+            BooleanArray::from(vec![Some(true), Some(false), None, None])
+        );
+    }
+
     #[test]
     fn test_extract_json_string() {
         let input = Arc::new(StringArray::from(vec![
@@ -1,14 +1,23 @@
 #![allow(clippy::new_without_default)]
 
 pub mod builder;
+pub mod catalog;
 pub(crate) mod extension;
 pub mod external;
+mod geo;
+mod hints;
+pub mod introspection;
 mod json;
 pub mod logical;
 pub mod physical;
 mod plan;
+mod pushdown;
+mod regexp;
 mod rewriters;
+mod sampling;
 pub mod schemas;
+pub mod settings;
+mod similarity;
 mod tables;
 pub mod types;
 pub mod udafs;
@@ -20,7 +29,7 @@ use anyhow::bail;
 use arrow::array::ArrayRef;
 use arrow::datatypes::{self, DataType};
 use arrow_schema::Schema;
-use arroyo_datastream::WindowType;
+use arroyo_datastream::{WindowTrigger, WindowType};
 
 use datafusion::common::{plan_err, DFField, OwnedTableReference, Result, ScalarValue};
 use datafusion::datasource::DefaultTableSource;
@@ -29,6 +38,7 @@ use datafusion::physical_plan::functions::make_scalar_function;
 
 use datafusion::prelude::create_udf;
 
+use datafusion::sql::sqlparser::ast::Statement;
 use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
 use datafusion::sql::sqlparser::parser::Parser;
 use datafusion::sql::{planner::ContextProvider, TableReference};
@@ -37,26 +47,33 @@ use datafusion::common::tree_node::TreeNode;
 use datafusion::logical_expr::expr::ScalarFunction;
 use datafusion::logical_expr::{
     create_udaf, Expr, Extension, LogicalPlan, ReturnTypeFunction, ScalarFunctionDefinition,
-    ScalarUDF, Signature, Volatility, WindowUDF,
+    ScalarUDF, Signature, TypeSignature, Volatility, WindowUDF,
 };
 
 use datafusion::logical_expr::{AggregateUDF, TableSource};
 use logical::LogicalBatchInput;
 
-use schemas::window_arrow_struct;
+use pushdown::SourcePushdown;
+use schemas::{triggered_window_arrow_struct, window_arrow_struct};
 use tables::{Insert, Table};
 
 use crate::builder::PlanToGraphVisitor;
 use crate::extension::sink::SinkExtension;
+use crate::hints::PlannerHints;
 use crate::plan::ArroyoRewriter;
-use arroyo_datastream::logical::{DylibUdfConfig, ProgramConfig};
+use crate::settings::PipelineSettings;
+use arroyo_datastream::logical::{DylibUdfConfig, JsUdfConfig, ModelConfig, ProgramConfig};
 use arroyo_rpc::api_types::connections::ConnectionProfile;
 use datafusion::common::DataFusionError;
 use std::collections::HashSet;
 use std::fmt::Debug;
 
+use crate::geo::get_geo_functions;
 use crate::json::get_json_functions;
+use crate::regexp::get_regexp_functions;
 use crate::rewriters::{SourceMetadataVisitor, TimeWindowUdfChecker, UnnestRewriter};
+use crate::sampling::hash_sample_function;
+use crate::similarity::get_similarity_functions;
 use crate::types::interval_month_day_nanos_to_duration;
 
 use crate::udafs::EmptyUdaf;
@@ -66,6 +83,7 @@ use arroyo_rpc::df::ArroyoSchema;
 use arroyo_rpc::TIMESTAMP_FIELD;
 use arroyo_udf_host::parse::{inner_type, UdfDef};
 use arroyo_udf_host::ParsedUdfFile;
+use arroyo_udf_js::{JsUdf, ParsedJsUdf};
 use datafusion::execution::FunctionRegistry;
 use datafusion::logical_expr;
 use datafusion::logical_expr::expr_rewriter::FunctionRewrite;
@@ -76,12 +94,23 @@ use tracing::{info, warn};
 use unicase::UniCase;
 
 const DEFAULT_IDLE_TIME: Option<Duration> = Some(Duration::from_secs(5 * 60));
+const DEFAULT_MAX_WATERMARK_DRIFT: Duration = Duration::from_secs(60);
+// matches the lateness of the default watermark expression
+const DEFAULT_PARTITION_WATERMARK_LATENESS: Duration = Duration::from_secs(1);
 pub const ASYNC_RESULT_FIELD: &str = "__async_result";
+pub const STATEFUL_RESULT_FIELD: &str = "__stateful_result";
 
 #[derive(Clone, Debug)]
 pub struct CompiledSql {
     pub program: LogicalProgram,
     pub connection_ids: Vec<i64>,
+    /// Settings made with `SET` statements in the query
+    pub settings: PipelineSettings,
+    /// The catalog tables the query reads, directly or through other catalog views
+    pub catalog_tables: Vec<String>,
+    /// The parallelism of operators given in `PARALLELISM` hints, by operator id, which takes
+    /// precedence over the parallelism of the pipeline
+    pub parallelism_overrides: HashMap<String, usize>,
 }
 
 #[derive(Clone, Default)]
@@ -95,7 +124,11 @@ pub struct ArroyoSchemaProvider {
     pub udf_defs: HashMap<String, UdfDef>,
     config_options: datafusion::config::ConfigOptions,
     pub dylib_udfs: HashMap<String, DylibUdfConfig>,
+    pub js_udfs: HashMap<String, JsUdfConfig>,
+    pub models: HashMap<String, ModelConfig>,
     pub function_rewriters: Vec<Arc<dyn FunctionRewrite + Send + Sync>>,
+    source_pushdown: HashMap<UniCase<String>, SourcePushdown>,
+    catalog: HashMap<UniCase<String>, Statement>,
 }
 
 impl ArroyoSchemaProvider {
@@ -119,10 +152,70 @@ impl ArroyoSchemaProvider {
                 make_scalar_function(fn_impl),
             )),
         );
+        functions.insert(
+            "cumulate".to_string(),
+            Arc::new(create_udf(
+                "cumulate",
+                vec![
+                    DataType::Interval(datatypes::IntervalUnit::MonthDayNano),
+                    DataType::Interval(datatypes::IntervalUnit::MonthDayNano),
+                ],
+                window_return_type.clone(),
+                Volatility::Volatile,
+                #[allow(deprecated)]
+                make_scalar_function(fn_impl),
+            )),
+        );
         functions.insert(
             "tumble".to_string(),
+            Arc::new({
+                let interval = DataType::Interval(datatypes::IntervalUnit::MonthDayNano);
+                // tumble(width) or, with a trigger, tumble(width, early_fire, allowed_lateness)
+                let return_type: ReturnTypeFunction = Arc::new(|args| {
+                    Ok(Arc::new(if args.len() == 3 {
+                        triggered_window_arrow_struct()
+                    } else {
+                        window_arrow_struct()
+                    }))
+                });
+                #[allow(deprecated)]
+                ScalarUDF::new(
+                    "tumble",
+                    &Signature::one_of(
+                        vec![
+                            TypeSignature::Exact(vec![interval.clone()]),
+                            TypeSignature::Exact(vec![
+                                interval.clone(),
+                                interval.clone(),
+                                interval,
+                            ]),
+                        ],
+                        Volatility::Volatile,
+                    ),
+                    &return_type,
+                    #[allow(deprecated)]
+                    &make_scalar_function(fn_impl),
+                )
+            }),
+        );
+        functions.insert(
+            "proctime_hop".to_string(),
             Arc::new(create_udf(
-                "tumble",
+                "proctime_hop",
+                vec![
+                    DataType::Interval(datatypes::IntervalUnit::MonthDayNano),
+                    DataType::Interval(datatypes::IntervalUnit::MonthDayNano),
+                ],
+                window_return_type.clone(),
+                Volatility::Volatile,
+                #[allow(deprecated)]
+                make_scalar_function(fn_impl),
+            )),
+        );
+        functions.insert(
+            "proctime_tumble".to_string(),
+            Arc::new(create_udf(
+                "proctime_tumble",
                 vec![DataType::Interval(datatypes::IntervalUnit::MonthDayNano)],
                 window_return_type.clone(),
                 Volatility::Volatile,
@@ -130,6 +223,28 @@ impl ArroyoSchemaProvider {
                 make_scalar_function(fn_impl),
             )),
         );
+        functions.insert(
+            "count_hop".to_string(),
+            Arc::new(create_udf(
+                "count_hop",
+                vec![DataType::Int64, DataType::Int64],
+                window_return_type.clone(),
+                Volatility::Volatile,
+                #[allow(deprecated)]
+                make_scalar_function(fn_impl),
+            )),
+        );
+        functions.insert(
+            "count_tumble".to_string(),
+            Arc::new(create_udf(
+                "count_tumble",
+                vec![DataType::Int64],
+                window_return_type.clone(),
+                Volatility::Volatile,
+                #[allow(deprecated)]
+                make_scalar_function(fn_impl),
+            )),
+        );
         functions.insert(
             "session".to_string(),
             Arc::new(create_udf(
@@ -167,6 +282,8 @@ impl ArroyoSchemaProvider {
         );
 
         functions.extend(get_json_functions());
+        functions.extend(get_geo_functions());
+        functions.insert("hash_sample".to_string(), hash_sample_function());
 
         let mut registry = Self {
             functions,
@@ -176,6 +293,10 @@ impl ArroyoSchemaProvider {
         datafusion_functions::register_all(&mut registry).unwrap();
         datafusion::functions_array::register_all(&mut registry).unwrap();
 
+        // these replace the DataFusion functions of the same names
+        registry.functions.extend(get_regexp_functions());
+        registry.functions.extend(get_similarity_functions());
+
         registry
     }
 
@@ -190,6 +311,14 @@ impl ArroyoSchemaProvider {
         self.profiles.insert(profile.name.clone(), profile);
     }
 
+    /// Adds a table or view from the catalog, which is planned only if the query reads it,
+    /// returning its name
+    pub fn add_catalog_table(&mut self, definition: &str) -> Result<String> {
+        let (name, statement) = catalog::parse_definition(definition)?;
+        self.catalog.insert(UniCase::new(name.clone()), statement);
+        Ok(name)
+    }
+
     fn insert_table(&mut self, table: Table) {
         self.tables
             .insert(UniCase::new(table.name().to_string()), table);
@@ -203,6 +332,11 @@ impl ArroyoSchemaProvider {
         self.tables.get_mut(&UniCase::new(table_name.into()))
     }
 
+    pub(crate) fn get_source_pushdown(&self, table_name: &str) -> Option<&SourcePushdown> {
+        self.source_pushdown
+            .get(&UniCase::new(table_name.to_string()))
+    }
+
     pub fn add_rust_udf(&mut self, body: &str, url: &str) -> anyhow::Result<String> {
         let parsed = ParsedUdfFile::try_parse(body)?;
 
@@ -227,6 +361,7 @@ impl ArroyoSchemaProvider {
                 return_type: parsed.udf.ret_type.data_type.clone(),
                 aggregate: parsed.udf.vec_arguments > 0,
                 is_async: parsed.udf.udf_type.is_async(),
+                is_stateful: parsed.udf.udf_type.is_stateful(),
             },
         );
 
@@ -293,6 +428,64 @@ impl ArroyoSchemaProvider {
 
         Ok(parsed.udf.name)
     }
+
+    pub fn add_js_udf(&mut self, body: &str) -> anyhow::Result<String> {
+        let parsed = ParsedJsUdf::try_parse(body)?;
+        let name = parsed.name.clone();
+
+        self.js_udfs.insert(
+            name.clone(),
+            JsUdfConfig {
+                definition: body.to_string(),
+            },
+        );
+
+        self.udf_defs.insert(name.clone(), parsed.udf_def());
+
+        if self
+            .functions
+            .insert(
+                name.clone(),
+                Arc::new(ScalarUDF::new_from_impl(JsUdf::new(parsed))),
+            )
+            .is_some()
+        {
+            warn!("Global UDF '{}' is being overwritten", name);
+        }
+
+        Ok(name)
+    }
+
+    /// Registers an ONNX model as a function that takes the model's inputs as numeric arguments
+    /// and returns its scores; the model itself is only loaded by the workers
+    pub fn add_model(&mut self, name: &str, model: ModelConfig) -> anyhow::Result<()> {
+        if model.inputs == 0 || model.outputs == 0 {
+            bail!("model {} must have at least one input and one output", name);
+        }
+
+        let fn_impl = |args: &[ArrayRef]| Ok(Arc::new(args[0].clone()) as ArrayRef);
+
+        if self
+            .functions
+            .insert(
+                name.to_string(),
+                Arc::new(create_udf(
+                    name,
+                    vec![DataType::Float64; model.inputs as usize],
+                    Arc::new(model.return_type()),
+                    Volatility::Volatile,
+                    #[allow(deprecated)]
+                    make_scalar_function(fn_impl),
+                )),
+            )
+            .is_some()
+        {
+            warn!("Global UDF '{}' is being overwritten by model", name);
+        }
+
+        self.models.insert(name.to_string(), model);
+        Ok(())
+    }
 }
 
 fn create_table(table_name: String, schema: Arc<Schema>) -> Arc<dyn TableSource> {
@@ -450,13 +643,23 @@ fn get_duration(expression: &Expr) -> Result<Duration> {
     }
 }
 
+fn get_count(expression: &Expr) -> Result<u64> {
+    match expression {
+        Expr::Literal(ScalarValue::Int64(Some(val))) if *val > 0 => Ok(*val as u64),
+        _ => plan_err!(
+            "unsupported count expression, expect a positive integer literal, not {}",
+            expression
+        ),
+    }
+}
+
 fn find_window(expression: &Expr) -> Result<Option<WindowType>> {
     match expression {
         Expr::ScalarFunction(ScalarFunction {
             func_def: ScalarFunctionDefinition::UDF(fun),
             args,
         }) => match fun.name() {
-            "hop" => {
+            name @ ("hop" | "proctime_hop") => {
                 if args.len() != 2 {
                     unreachable!();
                 }
@@ -464,19 +667,61 @@ fn find_window(expression: &Expr) -> Result<Option<WindowType>> {
                 let width = get_duration(&args[1])?;
                 if width.as_nanos() % slide.as_nanos() != 0 {
                     return plan_err!(
-                        "hop() width {:?} currently must be a multiple of slide {:?}",
+                        "{}() width {:?} currently must be a multiple of slide {:?}",
+                        name,
                         width,
                         slide
                     );
                 }
-                Ok(Some(WindowType::Sliding { width, slide }))
+                Ok(Some(WindowType::Sliding {
+                    width,
+                    slide,
+                    processing_time: name == "proctime_hop",
+                }))
             }
-            "tumble" => {
-                if args.len() != 1 {
-                    unreachable!("wrong number of arguments for tumble(), expect one");
+            "cumulate" => {
+                if args.len() != 2 {
+                    unreachable!("wrong number of arguments for cumulate(), expected two");
+                }
+                let step = get_duration(&args[0])?;
+                let size = get_duration(&args[1])?;
+                if step.is_zero() {
+                    return plan_err!("cumulate() step must be greater than zero");
+                }
+                if size.as_nanos() % step.as_nanos() != 0 {
+                    return plan_err!(
+                        "cumulate() size {:?} must be a multiple of step {:?}",
+                        size,
+                        step
+                    );
+                }
+                Ok(Some(WindowType::Cumulating { step, size }))
+            }
+            name @ ("tumble" | "proctime_tumble") => {
+                if args.len() != 1 && !(name == "tumble" && args.len() == 3) {
+                    unreachable!("wrong number of arguments for {}()", name);
                 }
                 let width = get_duration(&args[0])?;
-                Ok(Some(WindowType::Tumbling { width }))
+                if name == "proctime_tumble" && width.is_zero() {
+                    return plan_err!("proctime_tumble() width must be greater than zero");
+                }
+                let trigger = if args.len() == 3 {
+                    if width.is_zero() {
+                        return plan_err!("tumble() with a trigger must have a non-zero width");
+                    }
+                    let early_fire = get_duration(&args[1])?;
+                    Some(WindowTrigger {
+                        early_fire: (!early_fire.is_zero()).then_some(early_fire),
+                        allowed_lateness: get_duration(&args[2])?,
+                    })
+                } else {
+                    None
+                };
+                Ok(Some(WindowType::Tumbling {
+                    width,
+                    processing_time: name == "proctime_tumble",
+                    trigger,
+                }))
             }
             "session" => {
                 if args.len() != 1 {
@@ -485,6 +730,28 @@ fn find_window(expression: &Expr) -> Result<Option<WindowType>> {
                 let gap = get_duration(&args[0])?;
                 Ok(Some(WindowType::Session { gap }))
             }
+            "count_hop" => {
+                if args.len() != 2 {
+                    unreachable!("wrong number of arguments for count_hop(), expected two");
+                }
+                let slide = get_count(&args[0])?;
+                let size = get_count(&args[1])?;
+                if slide > size {
+                    return plan_err!(
+                        "count_hop() slide {} must not be larger than size {}",
+                        slide,
+                        size
+                    );
+                }
+                Ok(Some(WindowType::Count { size, slide }))
+            }
+            "count_tumble" => {
+                if args.len() != 1 {
+                    unreachable!("wrong number of arguments for count_tumble(), expected one");
+                }
+                let size = get_count(&args[0])?;
+                Ok(Some(WindowType::Count { size, slide: size }))
+            }
             _ => Ok(None),
         },
         Expr::Alias(logical_expr::expr::Alias {
@@ -515,15 +782,42 @@ pub fn rewrite_plan(
     Ok(rewritten_plan.data)
 }
 
+/// Parses a query, first rewriting any `TABLESAMPLE` clauses, which the parser doesn't support
+pub(crate) fn parse_sql(query: &str) -> Result<Vec<Statement>> {
+    Ok(Parser::parse_sql(
+        &PostgreSqlDialect {},
+        &sampling::rewrite_tablesample(query)?,
+    )?)
+}
+
 pub async fn parse_and_get_arrow_program(
     query: String,
     mut schema_provider: ArroyoSchemaProvider,
     // TODO: use config
     _config: SqlConfig,
 ) -> Result<CompiledSql> {
-    let dialect = PostgreSqlDialect {};
+    let hints = PlannerHints::parse(&query)?;
+    let statements = parse_sql(&query)?;
+    let (catalog_statements, catalog_tables) =
+        catalog::resolve(&statements, &schema_provider.catalog)?;
+    let statements: Vec<_> = catalog_statements.into_iter().chain(statements).collect();
+    schema_provider.source_pushdown = pushdown::analyze(&statements, &schema_provider)?;
+
+    let mut settings = PipelineSettings::default();
     let mut inserts = vec![];
-    for statement in Parser::parse_sql(&dialect, &query)? {
+    for statement in statements {
+        if settings.try_from_statement(&statement)? {
+            continue;
+        }
+
+        if introspection::is_introspection(&statement) {
+            return plan_err!(
+                "'{}' can't be part of a pipeline; introspection statements can only be run on \
+                their own, after any table definitions",
+                statement
+            );
+        }
+
         if let Some(table) = Table::try_from_statement(&statement, &schema_provider)? {
             schema_provider.insert_table(table);
         } else {
@@ -601,17 +895,29 @@ pub async fn parse_and_get_arrow_program(
     for extension in extensions {
         plan_to_graph_visitor.add_plan(extension)?;
     }
-    let graph = plan_to_graph_visitor.into_graph();
+    let mut graph = plan_to_graph_visitor.into_graph();
+    let parallelism_overrides = hints.apply(&mut graph)?;
+    settings.apply(&mut graph)?;
     let program = LogicalProgram::new(
         graph,
         ProgramConfig {
             udf_dylibs: schema_provider.dylib_udfs.clone(),
+            js_udfs: schema_provider.js_udfs.clone(),
+            models: schema_provider.models.clone(),
+            batching: Default::default(),
+            recording: Default::default(),
+            replay: None,
+            bootstrap: vec![],
+            resource_classes: HashMap::new(),
         },
     );
 
     Ok(CompiledSql {
         program,
         connection_ids: used_connections.into_iter().collect(),
+        settings,
+        catalog_tables,
+        parallelism_overrides,
     })
 }
 
@@ -2,13 +2,14 @@ use std::{any::Any, sync::Arc};
 
 use arrow_schema::SchemaRef;
 use datafusion::common::Result as DFResult;
-use datafusion::logical_expr::{Expr, TableType};
+use datafusion::logical_expr::{Expr, TableProviderFilterPushDown, TableType};
 use datafusion::{
     datasource::TableProvider, execution::context::SessionState, physical_plan::ExecutionPlan,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::physical::ArroyoMemExec;
+use crate::pushdown::is_pushable;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogicalBatchInput {
@@ -32,6 +33,24 @@ impl TableProvider for LogicalBatchInput {
         TableType::Temporary
     }
 
+    /// Simple predicates are recorded on the table scan so that they can be pushed down into
+    /// the source, but are still applied by the query.
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DFResult<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|f| {
+                if is_pushable(f) {
+                    TableProviderFilterPushDown::Inexact
+                } else {
+                    TableProviderFilterPushDown::Unsupported
+                }
+            })
+            .collect())
+    }
+
     /// Create an ExecutionPlan that will scan the table.
     /// The table provider will be usually responsible for grouping
     /// the source data into partitions that can be efficiently
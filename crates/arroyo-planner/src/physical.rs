@@ -26,8 +26,13 @@ use datafusion::{
     },
 };
 
+use crate::geo::get_geo_functions;
 use crate::json::get_json_functions;
+use crate::regexp::get_regexp_functions;
 use crate::rewriters::UNNESTED_COL;
+use crate::sampling::hash_sample_function;
+use crate::schemas::triggered_window_arrow_struct;
+use crate::similarity::get_similarity_functions;
 use arroyo_operator::operator::Registry;
 use arroyo_rpc::grpc::api::{
     arroyo_exec_node, ArroyoExecNode, DebeziumEncodeNode, MemExecNode, UnnestExecNode,
@@ -161,6 +166,54 @@ pub fn window_scalar_function() -> ScalarUDF {
     )
 }
 
+/// Builds the window struct of a triggered window from its start, end, and emit mode
+pub fn triggered_window_function(columns: &[ColumnarValue]) -> Result<ColumnarValue> {
+    if columns.len() != 3 {
+        return plan_err!(
+            "triggered_window function expected 3 arguments, got {}",
+            columns.len()
+        );
+    }
+    let len = columns
+        .iter()
+        .find_map(|column| match column {
+            ColumnarValue::Array(array) => Some(array.len()),
+            ColumnarValue::Scalar(_) => None,
+        })
+        .unwrap_or(1);
+    let DataType::Struct(fields) = triggered_window_arrow_struct() else {
+        unreachable!()
+    };
+    let arrays = columns
+        .iter()
+        .map(|column| column.clone().into_array(len))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(ColumnarValue::Array(Arc::new(StructArray::try_new(
+        fields, arrays, None,
+    )?)))
+}
+
+pub fn triggered_window_scalar_function() -> ScalarUDF {
+    let return_type: ReturnTypeFunction =
+        Arc::new(|_| Ok(Arc::new(triggered_window_arrow_struct())));
+    let implementation: ScalarFunctionImplementation = Arc::new(triggered_window_function);
+    #[allow(deprecated)]
+    ScalarUDF::new(
+        "triggered_window",
+        &Signature::exact(
+            vec![
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                DataType::Utf8,
+            ],
+            Volatility::Immutable,
+        ),
+        &return_type,
+        &implementation,
+    )
+}
+
 #[derive(Debug)]
 pub struct ArroyoPhysicalExtensionCodec {
     pub context: DecodingContext,
@@ -193,12 +246,23 @@ pub enum DecodingContext {
 pub fn new_registry() -> Registry {
     let mut registry = Registry::default();
     registry.add_udf(Arc::new(window_scalar_function()));
+    registry.add_udf(Arc::new(triggered_window_scalar_function()));
     for json_function in get_json_functions().values() {
         registry.add_udf(json_function.clone());
     }
+    for geo_function in get_geo_functions().into_values() {
+        registry.add_udf(geo_function);
+    }
+    registry.add_udf(hash_sample_function());
 
     datafusion::functions::register_all(&mut registry).unwrap();
     datafusion::functions_array::register_all(&mut registry).unwrap();
+    for function in get_regexp_functions()
+        .into_values()
+        .chain(get_similarity_functions().into_values())
+    {
+        registry.add_udf(function);
+    }
     registry
 }
 
@@ -191,6 +191,30 @@ impl TreeNodeRewriter for AggregateRewriter {
                                 "can't reinvoke session window in nested aggregates. Need to pass the window struct up from the source query."
                             );
                         }
+                        if matches!(
+                            input_window,
+                            arroyo_datastream::WindowType::Tumbling {
+                                trigger: Some(_),
+                                ..
+                            }
+                        ) {
+                            return plan_err!(
+                                "can't reinvoke a tumbling window with a trigger in nested aggregates"
+                            );
+                        }
+                        if matches!(input_window, arroyo_datastream::WindowType::Count { .. }) {
+                            return plan_err!(
+                                "can't reinvoke count window in nested aggregates. Need to pass the window struct up from the source query."
+                            );
+                        }
+                        if matches!(
+                            input_window,
+                            arroyo_datastream::WindowType::Cumulating { .. }
+                        ) {
+                            return plan_err!(
+                                "can't reinvoke cumulate window in nested aggregates. Need to pass the window struct up from the source query."
+                            );
+                        }
                         group_expr.remove(window_index);
                         key_fields.remove(window_index);
                         let window_field = schema.field(window_index).clone();
@@ -51,6 +51,24 @@ impl JoinRewriter {
                         "can't handle session windows in joins".into(),
                     ));
                 }
+                if let WindowType::Tumbling {
+                    trigger: Some(_), ..
+                } = left_window
+                {
+                    return Err(DataFusionError::NotImplemented(
+                        "can't handle windows with triggers in joins".into(),
+                    ));
+                }
+                if let WindowType::Count { .. } = left_window {
+                    return Err(DataFusionError::NotImplemented(
+                        "can't handle count windows in joins".into(),
+                    ));
+                }
+                if let WindowType::Cumulating { .. } = left_window {
+                    return Err(DataFusionError::NotImplemented(
+                        "can't handle cumulate windows in joins".into(),
+                    ));
+                }
 
                 Ok(true)
             }
@@ -25,7 +25,7 @@ use crate::{
 };
 use crate::{
     extension::{remote_table::RemoteTableExtension, ArroyoExtension},
-    rewriters::AsyncUdfRewriter,
+    rewriters::{AsyncUdfRewriter, StatefulUdfRewriter},
 };
 
 use self::window_fn::WindowFunctionRewriter;
@@ -231,7 +231,7 @@ impl TreeNodeVisitor for WindowDetectingVisitor {
                             }
                             if self.fields.is_empty() {
                                 return Err(DataFusionError::Plan(
-                                    "must have window in aggregate. Make sure you are calling one of the windowing functions (hop, tumble, session) or using the window field of the input".to_string(),
+                                    "must have window in aggregate. Make sure you are calling one of the windowing functions (hop, tumble, cumulate, session, count_tumble, count_hop) or using the window field of the input".to_string(),
                                 ));
                             }
                         }
@@ -294,7 +294,11 @@ impl<'a> TreeNodeRewriter for ArroyoRewriter<'a> {
                     projection.expr.push(Expr::Column(field.qualified_column()));
                 }
 
-                return AsyncUdfRewriter::new(self.schema_provider).f_up(node);
+                let node = StatefulUdfRewriter::new(self.schema_provider).f_up(node)?;
+                if node.transformed {
+                    return Ok(node);
+                }
+                return AsyncUdfRewriter::new(self.schema_provider).f_up(node.data);
             }
             LogicalPlan::Aggregate(aggregate) => {
                 return AggregateRewriter {}.f_up(LogicalPlan::Aggregate(aggregate));
@@ -308,7 +312,10 @@ impl<'a> TreeNodeRewriter for ArroyoRewriter<'a> {
                 }
                 .f_up(LogicalPlan::TableScan(table_scan));
             }
-            LogicalPlan::Filter(_) => {}
+            LogicalPlan::Filter(_) => {
+                // stateful UDFs can only be called from projections
+                return StatefulUdfRewriter::new(self.schema_provider).f_up(node);
+            }
             LogicalPlan::Window(_) => {
                 return WindowFunctionRewriter {}.f_up(node);
             }
@@ -55,6 +55,21 @@ impl TreeNodeRewriter for WindowFunctionRewriter {
         if matches!(input_window, WindowType::Session { .. }) {
             return plan_err!("Window functions do not support session windows");
         }
+        if matches!(
+            input_window,
+            WindowType::Tumbling {
+                trigger: Some(_),
+                ..
+            }
+        ) {
+            return plan_err!("Window functions do not support windows with triggers");
+        }
+        if matches!(input_window, WindowType::Count { .. }) {
+            return plan_err!("Window functions do not support count windows");
+        }
+        if matches!(input_window, WindowType::Cumulating { .. }) {
+            return plan_err!("Window functions do not support cumulate windows");
+        }
 
         let input_window_fields = window_detecting_visitor.fields;
 
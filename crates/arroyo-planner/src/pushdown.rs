@@ -0,0 +1,338 @@
+//! Pushes column projections and simple predicates from queries down into the sources they read.
+//!
+//! A source table is read by a single source operator, no matter how many statements in the
+//! pipeline query it, so what can be pushed down is determined by looking at every statement
+//! before any of them are planned. Nullable columns that no query references are not deserialized
+//! (they're filled with nulls above the source instead), and sources that can make use of it are
+//! given a predicate that every row needed by the pipeline satisfies. Queries still apply their own
+//! predicates, so a source is free to ignore it.
+
+use std::collections::{HashMap, HashSet};
+
+use arroyo_rpc::OperatorConfig;
+use datafusion::common::tree_node::{Transformed, TreeNode, TreeNodeRecursion, TreeNodeVisitor};
+use datafusion::common::{Column, DataFusionError, Result, ScalarValue};
+use datafusion::logical_expr::expr::{Exists, InSubquery};
+use datafusion::logical_expr::utils::{conjunction, disjunction};
+use datafusion::logical_expr::{
+    Between, BinaryExpr, Cast, DdlStatement, Expr, LogicalPlan, Operator, TryCast,
+};
+use datafusion::sql::sqlparser::ast::Statement;
+use datafusion_proto::bytes::Serializeable;
+use unicase::UniCase;
+
+use crate::tables::{produce_optimized_plan, ConnectorTable, FieldSpec, Insert, Table};
+use crate::ArroyoSchemaProvider;
+
+/// What can be pushed down into the source of a connector table
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SourcePushdown {
+    /// struct fields that no query uses, and so don't need to be read
+    pruned: HashSet<String>,
+    /// a predicate that every row used by the pipeline satisfies
+    filter: Option<Expr>,
+}
+
+impl SourcePushdown {
+    pub(crate) fn is_pruned(&self, field: &str) -> bool {
+        self.pruned.contains(field)
+    }
+
+    /// Returns the table that the source operator should read, without the pruned fields and
+    /// with the pushed-down predicate added to its config
+    pub(crate) fn apply(&self, table: &ConnectorTable) -> Result<ConnectorTable> {
+        let mut table = table.clone();
+        table.fields.retain(|f| match f {
+            FieldSpec::StructField(f) => !self.pruned.contains(f.name()),
+            FieldSpec::VirtualField { .. } => true,
+        });
+
+        if let Some(filter) = &self.filter {
+            let mut config: OperatorConfig = serde_json::from_str(&table.config).map_err(|e| {
+                DataFusionError::Plan(format!("invalid config for table {}: {}", table.name, e))
+            })?;
+            config.set_filter(&filter.to_bytes()?);
+            table.config = serde_json::to_string(&config).unwrap();
+        }
+
+        Ok(table)
+    }
+}
+
+/// Whether a predicate is simple enough to be evaluated by a source
+pub(crate) fn is_pushable(expr: &Expr) -> bool {
+    match expr {
+        Expr::Column(_) | Expr::Literal(_) => true,
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+            matches!(
+                op,
+                Operator::Eq
+                    | Operator::NotEq
+                    | Operator::Lt
+                    | Operator::LtEq
+                    | Operator::Gt
+                    | Operator::GtEq
+                    | Operator::And
+                    | Operator::Or
+            ) && is_pushable(left)
+                && is_pushable(right)
+        }
+        Expr::Not(e)
+        | Expr::IsNull(e)
+        | Expr::IsNotNull(e)
+        | Expr::IsTrue(e)
+        | Expr::IsFalse(e)
+        | Expr::Cast(Cast { expr: e, .. })
+        | Expr::TryCast(TryCast { expr: e, .. }) => is_pushable(e),
+        Expr::Between(Between {
+            expr, low, high, ..
+        }) => is_pushable(expr) && is_pushable(low) && is_pushable(high),
+        Expr::InList(in_list) => is_pushable(&in_list.expr) && in_list.list.iter().all(is_pushable),
+        _ => false,
+    }
+}
+
+/// Collects the columns referenced by a plan (by name only, as tables may be aliased), along with
+/// the tables it scans and the predicates recorded on those scans
+#[derive(Default)]
+struct UsageVisitor {
+    columns: HashSet<String>,
+    all_columns: bool,
+    scans: Vec<(String, Vec<Expr>)>,
+}
+
+impl UsageVisitor {
+    fn visit_expr(&mut self, expr: &Expr) -> Result<()> {
+        expr.apply(&mut |e: &Expr| {
+            match e {
+                Expr::Column(c) | Expr::OuterReferenceColumn(_, c) => {
+                    self.columns.insert(c.name.clone());
+                }
+                Expr::Wildcard { .. } => {
+                    self.all_columns = true;
+                }
+                Expr::ScalarSubquery(subquery)
+                | Expr::Exists(Exists { subquery, .. })
+                | Expr::InSubquery(InSubquery { subquery, .. }) => {
+                    subquery.subquery.visit(self)?;
+                }
+                _ => {}
+            }
+            Ok(TreeNodeRecursion::Continue)
+        })?;
+        Ok(())
+    }
+}
+
+impl TreeNodeVisitor for UsageVisitor {
+    type Node = LogicalPlan;
+
+    fn f_down(&mut self, node: &Self::Node) -> Result<TreeNodeRecursion> {
+        if let LogicalPlan::TableScan(scan) = node {
+            self.scans
+                .push((scan.table_name.table().to_string(), scan.filters.clone()));
+        }
+        for expr in node.expressions() {
+            self.visit_expr(&expr)?;
+        }
+        Ok(TreeNodeRecursion::Continue)
+    }
+}
+
+#[derive(Default)]
+struct TableUsage {
+    columns: HashSet<String>,
+    all_columns: bool,
+    scan_filters: Vec<Vec<Expr>>,
+}
+
+impl TableUsage {
+    fn pruned_fields(&self, table: &ConnectorTable) -> Result<HashSet<String>> {
+        if self.all_columns || !table.supports_projection_pushdown() {
+            return Ok(HashSet::new());
+        }
+
+        let mut needed: HashSet<String> = self.columns.clone();
+        needed.extend(table.event_time_field.iter().cloned());
+        needed.extend(table.watermark_field.iter().cloned());
+        needed.extend(table.watermark_punctuation_field.iter().cloned());
+        // virtual fields are always computed above the source
+        for field in &table.fields {
+            if let FieldSpec::VirtualField { expression, .. } = field {
+                needed.extend(expression.to_columns()?.into_iter().map(|c| c.name));
+            }
+        }
+
+        let struct_fields: Vec<_> = table
+            .fields
+            .iter()
+            .filter_map(|f| match f {
+                FieldSpec::StructField(f) => Some(f),
+                FieldSpec::VirtualField { .. } => None,
+            })
+            .collect();
+
+        // only nullable fields can be replaced by nulls, and we still read non-nullable fields so
+        // that rows missing them are rejected as bad data
+        let mut pruned: HashSet<String> = struct_fields
+            .iter()
+            .filter(|f| {
+                !needed.contains(f.name())
+                    && f.is_nullable()
+                    && f.metadata().is_empty()
+                    && ScalarValue::try_from(f.data_type()).is_ok()
+            })
+            .map(|f| f.name().clone())
+            .collect();
+
+        if pruned.len() == struct_fields.len() {
+            if let Some(f) = struct_fields.first() {
+                pruned.remove(f.name());
+            }
+        }
+
+        Ok(pruned)
+    }
+
+    fn filter(&self, table: &ConnectorTable) -> Result<Option<Expr>> {
+        if !table.supports_filter_pushdown() {
+            return Ok(None);
+        }
+
+        let physical: HashSet<&str> = table
+            .fields
+            .iter()
+            .filter_map(|f| match f {
+                FieldSpec::StructField(f) => Some(f.name().as_str()),
+                FieldSpec::VirtualField { .. } => None,
+            })
+            .collect();
+
+        let mut scan_predicates = vec![];
+        for filters in &self.scan_filters {
+            // dropping conjuncts the source can't evaluate leaves a weaker, but still correct,
+            // predicate
+            let mut pushable = vec![];
+            for filter in filters {
+                let columns = filter.to_columns()?;
+                if columns.iter().all(|c| physical.contains(c.name.as_str())) {
+                    pushable.push(unqualify(filter.clone())?);
+                }
+            }
+
+            // if any scan needs every row, so does the source
+            let Some(predicate) = conjunction(pushable) else {
+                return Ok(None);
+            };
+            scan_predicates.push(predicate);
+        }
+
+        Ok(disjunction(scan_predicates))
+    }
+}
+
+fn unqualify(expr: Expr) -> Result<Expr> {
+    Ok(expr
+        .transform_up_mut(&mut |e| match e {
+            Expr::Column(c) => Ok(Transformed::yes(Expr::Column(Column::new_unqualified(
+                c.name,
+            )))),
+            e => Ok(Transformed::no(e)),
+        })?
+        .data)
+}
+
+/// Determines what can be pushed down into the source of each connector table read by the
+/// statements
+pub(crate) fn analyze(
+    statements: &[Statement],
+    schema_provider: &ArroyoSchemaProvider,
+) -> Result<HashMap<UniCase<String>, SourcePushdown>> {
+    let mut schema_provider = schema_provider.clone();
+    let mut usage: HashMap<UniCase<String>, TableUsage> = HashMap::new();
+
+    for statement in statements {
+        let plan = if let Some(table) = Table::try_from_statement(statement, &schema_provider)? {
+            let plan = match &table {
+                Table::TableFromQuery { .. } => {
+                    match produce_optimized_plan(statement, &schema_provider)? {
+                        LogicalPlan::Ddl(DdlStatement::CreateView(view)) => {
+                            Some(view.input.as_ref().clone())
+                        }
+                        LogicalPlan::Ddl(DdlStatement::CreateMemoryTable(table)) => {
+                            Some(table.input.as_ref().clone())
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            schema_provider.insert_table(table);
+            plan
+        } else {
+            match Insert::try_from_statement(statement, &mut schema_provider)? {
+                Insert::InsertQuery { logical_plan, .. } | Insert::Anonymous { logical_plan } => {
+                    Some(logical_plan)
+                }
+            }
+        };
+
+        let Some(plan) = plan else {
+            continue;
+        };
+
+        let mut visitor = UsageVisitor::default();
+        plan.visit(&mut visitor)?;
+        visitor
+            .columns
+            .extend(plan.schema().fields().iter().map(|f| f.name().clone()));
+
+        for (table, filters) in visitor.scans {
+            let usage = usage.entry(UniCase::new(table)).or_default();
+            usage.columns.extend(visitor.columns.iter().cloned());
+            usage.all_columns |= visitor.all_columns;
+            usage.scan_filters.push(filters);
+        }
+    }
+
+    let mut pushdown = HashMap::new();
+    for (name, usage) in usage {
+        let Some(Table::ConnectorTable(table)) = schema_provider.get_table(name.as_str()) else {
+            continue;
+        };
+        if table.is_updating() || table.fields.is_empty() {
+            continue;
+        }
+
+        pushdown.insert(
+            name,
+            SourcePushdown {
+                pruned: usage.pruned_fields(table)?,
+                filter: usage.filter(table)?,
+            },
+        );
+    }
+
+    Ok(pushdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::logical_expr::{col, lit};
+
+    #[test]
+    fn test_is_pushable() {
+        assert!(is_pushable(&col("a").gt(lit(5)).and(col("b").is_null())));
+        assert!(is_pushable(&col("a").in_list(vec![lit(1), lit(2)], false)));
+        assert!(is_pushable(&!col("a").between(lit(1), lit(10))));
+        assert!(!is_pushable(&(col("a") + lit(1)).gt(lit(5))));
+        assert!(!is_pushable(&col("a").like(lit("x%"))));
+    }
+
+    #[test]
+    fn test_unqualify() {
+        let expr = Expr::Column(Column::new(Some("t"), "a")).eq(lit(1));
+        assert_eq!(unqualify(expr).unwrap(), col("a").eq(lit(1)));
+    }
+}
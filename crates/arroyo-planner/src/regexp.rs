@@ -0,0 +1,444 @@
+use arrow_array::builder::{BooleanBuilder, ListBuilder, StringBuilder};
+use arrow_array::cast::{as_primitive_array, as_string_array};
+use arrow_array::types::Int64Type;
+use arrow_array::{Array, ArrayRef};
+use arrow_schema::{DataType, Field};
+use datafusion::common::{plan_err, DataFusionError, Result, ScalarValue};
+use datafusion::logical_expr::{
+    ColumnarValue, ScalarUDF, ScalarUDFImpl, Signature, TypeSignature, Volatility,
+};
+use regex::{Regex, RegexBuilder};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// the number of compiled patterns each function keeps around
+const PATTERN_CACHE_SIZE: usize = 128;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum RegexpKind {
+    Match,
+    Extract,
+    Replace,
+    Like,
+}
+
+impl RegexpKind {
+    fn name(&self) -> &'static str {
+        match self {
+            RegexpKind::Match => "regexp_match",
+            RegexpKind::Extract => "regexp_extract",
+            RegexpKind::Replace => "regexp_replace",
+            RegexpKind::Like => "regexp_like",
+        }
+    }
+
+    fn signature(&self) -> Signature {
+        use DataType::*;
+        let variants = match self {
+            RegexpKind::Match | RegexpKind::Like => {
+                vec![vec![Utf8, Utf8], vec![Utf8, Utf8, Utf8]]
+            }
+            RegexpKind::Extract => vec![vec![Utf8, Utf8], vec![Utf8, Utf8, Int64]],
+            RegexpKind::Replace => vec![vec![Utf8, Utf8, Utf8], vec![Utf8, Utf8, Utf8, Utf8]],
+        };
+
+        Signature::one_of(
+            variants.into_iter().map(TypeSignature::Exact).collect(),
+            Volatility::Immutable,
+        )
+    }
+
+    fn return_type(&self) -> DataType {
+        match self {
+            RegexpKind::Match => DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+            RegexpKind::Extract | RegexpKind::Replace => DataType::Utf8,
+            RegexpKind::Like => DataType::Boolean,
+        }
+    }
+
+    // the position of the flags argument, for the functions that take one
+    fn flags_arg(&self) -> Option<usize> {
+        match self {
+            RegexpKind::Match | RegexpKind::Like => Some(2),
+            RegexpKind::Replace => Some(3),
+            RegexpKind::Extract => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct Flags {
+    case_insensitive: bool,
+    global: bool,
+}
+
+impl Flags {
+    fn parse(kind: RegexpKind, flags: Option<&str>) -> Result<Self> {
+        let mut parsed = Flags::default();
+        for c in flags.unwrap_or_default().chars() {
+            match c {
+                'i' => parsed.case_insensitive = true,
+                'g' if kind == RegexpKind::Replace => parsed.global = true,
+                c => {
+                    return plan_err!("unsupported flag '{}' for {}", c, kind.name());
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Compiled patterns, shared by every invocation of a function so that patterns are compiled
+/// once rather than for every batch
+#[derive(Debug, Default)]
+struct PatternCache {
+    patterns: Mutex<HashMap<(String, bool), Arc<Regex>>>,
+}
+
+impl PatternCache {
+    fn get(&self, pattern: &str, case_insensitive: bool) -> Result<Arc<Regex>> {
+        let key = (pattern.to_string(), case_insensitive);
+        let mut patterns = self.patterns.lock().unwrap();
+        if let Some(regex) = patterns.get(&key) {
+            return Ok(regex.clone());
+        }
+
+        let regex = Arc::new(
+            RegexBuilder::new(pattern)
+                .case_insensitive(case_insensitive)
+                .build()
+                .map_err(|e| {
+                    DataFusionError::Execution(format!("invalid regex '{}': {}", pattern, e))
+                })?,
+        );
+
+        if patterns.len() >= PATTERN_CACHE_SIZE {
+            patterns.clear();
+        }
+        patterns.insert(key, regex.clone());
+        Ok(regex)
+    }
+}
+
+#[derive(Debug)]
+struct RegexpFunction {
+    kind: RegexpKind,
+    signature: Signature,
+    cache: PatternCache,
+}
+
+impl RegexpFunction {
+    fn new(kind: RegexpKind) -> Self {
+        Self {
+            kind,
+            signature: kind.signature(),
+            cache: PatternCache::default(),
+        }
+    }
+
+    fn evaluate(&self, args: &[ArrayRef]) -> Result<ArrayRef> {
+        let len = args[0].len();
+        let values = as_string_array(&args[0]);
+        let patterns = as_string_array(&args[1]);
+        let flags = self
+            .kind
+            .flags_arg()
+            .and_then(|i| args.get(i))
+            .map(|a| as_string_array(a));
+
+        // patterns are almost always literals, so we avoid going to the cache for every row
+        let mut last: Option<(&str, Flags, Arc<Regex>)> = None;
+        let mut regex_for = |i: usize| -> Result<Option<(Arc<Regex>, Flags)>> {
+            if patterns.is_null(i) {
+                return Ok(None);
+            }
+            let pattern = patterns.value(i);
+            let flags = Flags::parse(
+                self.kind,
+                flags.and_then(|f| (!f.is_null(i)).then(|| f.value(i))),
+            )?;
+            if let Some((p, f, regex)) = &last {
+                if *p == pattern && *f == flags {
+                    return Ok(Some((regex.clone(), flags)));
+                }
+            }
+            let regex = self.cache.get(pattern, flags.case_insensitive)?;
+            last = Some((pattern, flags, regex.clone()));
+            Ok(Some((regex, flags)))
+        };
+
+        Ok(match self.kind {
+            RegexpKind::Match => {
+                let mut builder = ListBuilder::with_capacity(StringBuilder::new(), len);
+                for i in 0..len {
+                    let regex = regex_for(i)?;
+                    let captures = values
+                        .is_valid(i)
+                        .then(|| regex.and_then(|(r, _)| r.captures(values.value(i))))
+                        .flatten();
+                    match captures {
+                        Some(captures) if captures.len() == 1 => {
+                            builder.append_value([captures.get(0).map(|m| m.as_str())]);
+                        }
+                        Some(captures) => {
+                            builder.append_value(
+                                captures.iter().skip(1).map(|m| m.map(|m| m.as_str())),
+                            );
+                        }
+                        None => builder.append_null(),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            RegexpKind::Extract => {
+                let groups = args.get(2).map(|g| as_primitive_array::<Int64Type>(g));
+                let mut builder = StringBuilder::with_capacity(len, len * 8);
+                for i in 0..len {
+                    let regex = regex_for(i)?;
+                    let group = match groups {
+                        Some(groups) if groups.is_null(i) => None,
+                        Some(groups) => Some(groups.value(i)),
+                        None => Some(1),
+                    };
+                    let group = match group {
+                        Some(group) if group < 0 => {
+                            return Err(DataFusionError::Execution(format!(
+                                "regexp_extract group must not be negative, but was {}",
+                                group
+                            )));
+                        }
+                        group => group,
+                    };
+                    let extracted = match (regex, group) {
+                        (Some((regex, _)), Some(group)) if values.is_valid(i) => regex
+                            .captures(values.value(i))
+                            .and_then(|c| c.get(group as usize))
+                            .map(|m| m.as_str()),
+                        _ => None,
+                    };
+                    builder.append_option(extracted);
+                }
+                Arc::new(builder.finish())
+            }
+            RegexpKind::Replace => {
+                let replacements = as_string_array(&args[2]);
+                let mut builder = StringBuilder::with_capacity(len, values.value_data().len());
+                for i in 0..len {
+                    let regex = regex_for(i)?;
+                    match regex {
+                        Some((regex, flags)) if values.is_valid(i) && replacements.is_valid(i) => {
+                            let value = values.value(i);
+                            let replacement = replacements.value(i);
+                            if flags.global {
+                                builder.append_value(regex.replace_all(value, replacement));
+                            } else {
+                                builder.append_value(regex.replace(value, replacement));
+                            }
+                        }
+                        _ => builder.append_null(),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            RegexpKind::Like => {
+                let mut builder = BooleanBuilder::with_capacity(len);
+                for i in 0..len {
+                    let regex = regex_for(i)?;
+                    builder.append_option(
+                        regex
+                            .filter(|_| values.is_valid(i))
+                            .map(|(r, _)| r.is_match(values.value(i))),
+                    );
+                }
+                Arc::new(builder.finish())
+            }
+        })
+    }
+}
+
+impl ScalarUDFImpl for RegexpFunction {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.kind.name()
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(self.kind.return_type())
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let scalar = args.iter().all(|a| matches!(a, ColumnarValue::Scalar(_)));
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+        let result = self.evaluate(&arrays)?;
+
+        if scalar {
+            Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(
+                &result, 0,
+            )?))
+        } else {
+            Ok(ColumnarValue::Array(result))
+        }
+    }
+}
+
+/// The regular expression functions. Each call returns new instances, with their own caches of
+/// compiled patterns.
+///
+/// * `regexp_match(str, pattern[, flags])` returns the capture groups of the first match, or the
+///   whole match if the pattern has no groups
+/// * `regexp_extract(str, pattern[, group])` returns the given capture group (by default the
+///   first) of the first match; group 0 is the whole match
+/// * `regexp_replace(str, pattern, replacement[, flags])` replaces the first match, or every
+///   match with the `g` flag
+/// * `regexp_like(str, pattern[, flags])` returns whether the pattern matches
+///
+/// The `i` flag makes matching case-insensitive.
+pub fn get_regexp_functions() -> HashMap<String, Arc<ScalarUDF>> {
+    [
+        RegexpKind::Match,
+        RegexpKind::Extract,
+        RegexpKind::Replace,
+        RegexpKind::Like,
+    ]
+    .into_iter()
+    .map(|kind| {
+        (
+            kind.name().to_string(),
+            Arc::new(ScalarUDF::new_from_impl(RegexpFunction::new(kind))),
+        )
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{get_regexp_functions, ColumnarValue};
+    use arrow_array::builder::{ListBuilder, StringBuilder};
+    use arrow_array::{ArrayRef, BooleanArray, Int64Array, StringArray};
+    use datafusion::common::ScalarValue;
+    use std::sync::Arc;
+
+    fn call(name: &str, args: &[ColumnarValue]) -> ArrayRef {
+        let functions = get_regexp_functions();
+        match functions.get(name).unwrap().invoke(args).unwrap() {
+            ColumnarValue::Array(a) => a,
+            ColumnarValue::Scalar(s) => panic!("Expected array, got scalar {:?}", s),
+        }
+    }
+
+    fn lines() -> ColumnarValue {
+        ColumnarValue::Array(Arc::new(StringArray::from(vec![
+            Some("GET /index.html 200"),
+            Some("post /api/users 404"),
+            Some("no request here"),
+            None,
+        ])))
+    }
+
+    fn scalar(s: &str) -> ColumnarValue {
+        ColumnarValue::Scalar(s.into())
+    }
+
+    #[test]
+    fn test_regexp_extract() {
+        let pattern = scalar(r"^(\w+) (\S+) (\d+)$");
+
+        assert_eq!(
+            *call("regexp_extract", &[lines(), pattern.clone()]),
+            StringArray::from(vec![Some("GET"), Some("post"), None, None])
+        );
+        assert_eq!(
+            *call(
+                "regexp_extract",
+                &[
+                    lines(),
+                    pattern,
+                    ColumnarValue::Scalar(ScalarValue::Int64(Some(3)))
+                ]
+            ),
+            StringArray::from(vec![Some("200"), Some("404"), None, None])
+        );
+
+        let groups = Arc::new(Int64Array::from(vec![Some(0), Some(2), Some(1), None]));
+        assert_eq!(
+            *call(
+                "regexp_extract",
+                &[
+                    lines(),
+                    scalar(r"(\w+) (\S+)"),
+                    ColumnarValue::Array(groups)
+                ]
+            ),
+            StringArray::from(vec![
+                Some("GET /index.html"),
+                Some("/api/users"),
+                Some("no"),
+                None
+            ])
+        );
+    }
+
+    #[test]
+    fn test_regexp_match() {
+        let mut expected = ListBuilder::new(StringBuilder::new());
+        expected.append_value([Some("GET"), Some("200")]);
+        expected.append_value([Some("post"), Some("404")]);
+        expected.append_null();
+        expected.append_null();
+
+        assert_eq!(
+            *call("regexp_match", &[lines(), scalar(r"^(\w+) \S+ (\d+)$")]),
+            expected.finish()
+        );
+    }
+
+    #[test]
+    fn test_regexp_replace_and_like() {
+        assert_eq!(
+            *call("regexp_replace", &[lines(), scalar(r"\d"), scalar("#")]),
+            StringArray::from(vec![
+                Some("GET /index.html #00"),
+                Some("post /api/users #04"),
+                Some("no request here"),
+                None
+            ])
+        );
+        assert_eq!(
+            *call(
+                "regexp_replace",
+                &[lines(), scalar(r"\d"), scalar("#"), scalar("g")]
+            ),
+            StringArray::from(vec![
+                Some("GET /index.html ###"),
+                Some("post /api/users ###"),
+                Some("no request here"),
+                None
+            ])
+        );
+
+        assert_eq!(
+            *call("regexp_like", &[lines(), scalar("^get")]),
+            BooleanArray::from(vec![Some(false), Some(false), Some(false), None])
+        );
+        assert_eq!(
+            *call("regexp_like", &[lines(), scalar("^get"), scalar("i")]),
+            BooleanArray::from(vec![Some(true), Some(false), Some(false), None])
+        );
+
+        let functions = get_regexp_functions();
+        assert!(functions["regexp_like"]
+            .invoke(&[lines(), scalar("^get"), scalar("g")])
+            .is_err());
+        assert!(functions["regexp_like"]
+            .invoke(&[lines(), scalar("(")])
+            .is_err());
+    }
+}
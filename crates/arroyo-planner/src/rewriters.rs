@@ -3,16 +3,19 @@ use crate::extension::remote_table::RemoteTableExtension;
 use crate::extension::sink::SinkExtension;
 use crate::extension::table_source::TableSourceExtension;
 use crate::extension::watermark_node::WatermarkNode;
+use crate::pushdown::SourcePushdown;
 use crate::schemas::add_timestamp_field;
 use crate::tables::ConnectorTable;
 use crate::tables::FieldSpec;
 use crate::tables::Table;
-use crate::{ArroyoSchemaProvider, ASYNC_RESULT_FIELD};
+use crate::{ArroyoSchemaProvider, ASYNC_RESULT_FIELD, STATEFUL_RESULT_FIELD};
 
 use arrow_schema::DataType;
 use arroyo_rpc::IS_RETRACT_FIELD;
 use arroyo_rpc::TIMESTAMP_FIELD;
 
+use crate::extension::key_calculation::KeyCalculationExtension;
+use crate::extension::stateful_udf::StatefulUDFExtension;
 use crate::extension::AsyncUDFExtension;
 use arroyo_udf_host::parse::{AsyncOptions, UdfType};
 use datafusion::common::tree_node::{
@@ -40,26 +43,32 @@ pub struct SourceRewriter<'a> {
 }
 
 impl<'a> SourceRewriter<'a> {
+    fn field_expression(table: &ConnectorTable, name: &str) -> DFResult<Expr> {
+        table
+            .fields
+            .iter()
+            .find_map(|f| {
+                if f.field().name() == name {
+                    return match f {
+                        FieldSpec::StructField(f) => Some(Expr::Column(Column {
+                            relation: None,
+                            name: f.name().to_string(),
+                        })),
+                        FieldSpec::VirtualField { expression, .. } => Some(expression.clone()),
+                    };
+                }
+                None
+            })
+            .ok_or_else(|| DataFusionError::Plan(format!("Watermark field {} not found", name)))
+    }
+
     fn watermark_expression(table: &ConnectorTable) -> DFResult<Expr> {
-        let expr = match table.watermark_field.clone() {
-            Some(watermark_field) => table
-                .fields
-                .iter()
-                .find_map(|f| {
-                    if f.field().name() == &watermark_field {
-                        return match f {
-                            FieldSpec::StructField(f) => Some(Expr::Column(Column {
-                                relation: None,
-                                name: f.name().to_string(),
-                            })),
-                            FieldSpec::VirtualField { expression, .. } => Some(expression.clone()),
-                        };
-                    }
-                    None
-                })
-                .ok_or_else(|| {
-                    DataFusionError::Plan(format!("Watermark field {} not found", watermark_field))
-                })?,
+        let expr = match table
+            .watermark_punctuation_field
+            .as_ref()
+            .or(table.watermark_field.as_ref())
+        {
+            Some(watermark_field) => Self::field_expression(table, watermark_field)?,
             None => Expr::BinaryExpr(BinaryExpr {
                 left: Box::new(Expr::Column(Column {
                     relation: None,
@@ -78,20 +87,27 @@ impl<'a> SourceRewriter<'a> {
         table: &ConnectorTable,
         qualifier: &OwnedTableReference,
         projection: &Option<Vec<usize>>,
+        pushdown: Option<&SourcePushdown>,
     ) -> DFResult<Vec<Expr>> {
         let mut expressions = table
             .fields
             .iter()
             .map(|field| match field {
-                FieldSpec::StructField(f) => Expr::Column(Column {
+                // fields that aren't read by the source are filled with nulls, so that the schema
+                // seen by the rest of the plan is unchanged
+                FieldSpec::StructField(f) if pushdown.is_some_and(|p| p.is_pruned(f.name())) => {
+                    Ok(Expr::Literal(ScalarValue::try_from(f.data_type())?)
+                        .alias_qualified(Some(qualifier.clone()), f.name().to_string()))
+                }
+                FieldSpec::StructField(f) => Ok(Expr::Column(Column {
                     relation: Some(qualifier.clone()),
                     name: f.name().to_string(),
-                }),
-                FieldSpec::VirtualField { field, expression } => expression
+                })),
+                FieldSpec::VirtualField { field, expression } => Ok(expression
                     .clone()
-                    .alias_qualified(Some(qualifier.clone()), field.name().to_string()),
+                    .alias_qualified(Some(qualifier.clone()), field.name().to_string())),
             })
-            .collect::<Vec<_>>();
+            .collect::<DFResult<Vec<_>>>()?;
 
         if let Some(projection) = projection {
             expressions = projection.iter().map(|i| expressions[*i].clone()).collect();
@@ -141,11 +157,19 @@ impl<'a> SourceRewriter<'a> {
 
     fn projection(&self, table_scan: &TableScan, table: &ConnectorTable) -> DFResult<LogicalPlan> {
         let qualifier = table_scan.table_name.clone();
+        let pushdown = self
+            .schema_provider
+            .get_source_pushdown(table_scan.table_name.table());
+
+        let source_table = match pushdown {
+            Some(pushdown) => pushdown.apply(table)?,
+            None => table.clone(),
+        };
 
         let table_source_extension = LogicalPlan::Extension(Extension {
             node: Arc::new(TableSourceExtension::new(
                 qualifier.to_owned(),
-                table.clone(),
+                source_table,
             )),
         });
 
@@ -165,7 +189,7 @@ impl<'a> SourceRewriter<'a> {
         };
 
         Ok(LogicalPlan::Projection(Projection::try_new(
-            Self::projection_expressions(table, &qualifier, &projection)?,
+            Self::projection_expressions(table, &qualifier, &projection, pushdown)?,
             Arc::new(projection_input),
         )?))
     }
@@ -191,6 +215,9 @@ impl<'a> SourceRewriter<'a> {
             remote,
             table_scan.table_name.clone(),
             Self::watermark_expression(table)?,
+            table.watermark_alignment.clone(),
+            table.has_partition_watermarks(),
+            table.watermark_punctuation_field.is_some(),
         )
         .map_err(|err| {
             DataFusionError::Internal(format!("failed to create watermark expression: {}", err))
@@ -549,6 +576,150 @@ impl<'a> TreeNodeRewriter for AsyncUdfRewriter<'a> {
     }
 }
 
+/// Moves calls to stateful UDFs out of projections into a [`StatefulUDFExtension`], keyed by the
+/// first argument of the UDF
+pub struct StatefulUdfRewriter<'a> {
+    provider: &'a ArroyoSchemaProvider,
+}
+
+impl<'a> StatefulUdfRewriter<'a> {
+    pub fn new(provider: &'a ArroyoSchemaProvider) -> Self {
+        Self { provider }
+    }
+
+    fn split_stateful(
+        expr: Expr,
+        provider: &ArroyoSchemaProvider,
+    ) -> DFResult<(Expr, Option<(String, Vec<Expr>)>)> {
+        let mut c: Option<(String, Vec<Expr>)> = None;
+        let expr = expr.transform_up_mut(&mut |e| {
+            if let Expr::ScalarFunction(ScalarFunction {
+                func_def: ScalarFunctionDefinition::UDF(udf),
+                args,
+            }) = &e
+            {
+                if provider
+                    .udf_defs
+                    .get(udf.name())
+                    .is_some_and(|udf| udf.udf_type.is_stateful())
+                {
+                    if c.replace((udf.name().to_string(), args.clone())).is_some() {
+                        return plan_err!(
+                            "multiple stateful UDF calls in the same expression, which is not allowed"
+                        );
+                    }
+                    return Ok(Transformed::yes(Expr::Column(Column::new_unqualified(
+                        STATEFUL_RESULT_FIELD,
+                    ))));
+                }
+            }
+            Ok(Transformed::no(e))
+        })?;
+
+        Ok((expr.data, c))
+    }
+}
+
+impl<'a> TreeNodeRewriter for StatefulUdfRewriter<'a> {
+    type Node = LogicalPlan;
+
+    fn f_up(&mut self, node: Self::Node) -> DFResult<Transformed<Self::Node>> {
+        let LogicalPlan::Projection(mut projection) = node else {
+            for e in node.expressions() {
+                if let (_, Some((udf, _))) = Self::split_stateful(e.clone(), self.provider)? {
+                    return plan_err!(
+                        "stateful UDFs are only supported in projections, but {udf} was called in another context"
+                    );
+                }
+            }
+            return Ok(Transformed::no(node));
+        };
+
+        let mut call = None;
+
+        for e in projection.expr.iter_mut() {
+            let (new_e, Some(udf)) = Self::split_stateful(e.clone(), self.provider)? else {
+                continue;
+            };
+
+            if let Some((prev, _)) = call.replace(udf) {
+                return plan_err!(
+                    "Projection contains multiple stateful UDFs, which is not supported \
+                    \n(hint: two stateful UDFs calls, {} and {}, appear in the same SELECT statement)",
+                    prev,
+                    call.unwrap().0
+                );
+            }
+
+            *e = new_e;
+        }
+
+        let Some((name, args)) = call else {
+            return Ok(Transformed::no(LogicalPlan::Projection(projection)));
+        };
+
+        if projection
+            .input
+            .schema()
+            .has_column_with_unqualified_name(IS_RETRACT_FIELD)
+        {
+            return plan_err!(
+                "stateful UDF {} cannot be called on an updating input, as its state can't be retracted",
+                name
+            );
+        }
+
+        for e in args.iter().chain(projection.expr.iter()) {
+            if let (_, Some((udf, _, _))) = AsyncUdfRewriter::split_async(e.clone(), self.provider)?
+            {
+                return plan_err!(
+                    "async UDF {} cannot be called in the same SELECT statement as stateful UDF {}",
+                    udf,
+                    name
+                );
+            }
+        }
+
+        // key the input by the first argument, so that each key's state is held by one subtask
+        let input = projection.input;
+        let mut key_projection_expressions = vec![args[0].clone().alias("_key_0")];
+        key_projection_expressions.extend(
+            input
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| Expr::Column(field.qualified_column())),
+        );
+
+        let auto_schema =
+            Projection::try_new(key_projection_expressions.clone(), input.clone())?.schema;
+        let mut key_fields = vec![auto_schema.fields()[0].clone()];
+        key_fields.extend(input.schema().fields().iter().cloned());
+        let key_schema = Arc::new(DFSchema::new_with_metadata(key_fields, HashMap::new())?);
+        let key_projection = LogicalPlan::Projection(Projection::try_new_with_schema(
+            key_projection_expressions,
+            input,
+            key_schema,
+        )?);
+        let key_plan = LogicalPlan::Extension(Extension {
+            node: Arc::new(KeyCalculationExtension::new(key_projection, vec![0])),
+        });
+
+        let udf = self.provider.dylib_udfs.get(&name).unwrap().clone();
+
+        Ok(Transformed::yes(LogicalPlan::Extension(Extension {
+            node: Arc::new(StatefulUDFExtension {
+                input: Arc::new(key_plan),
+                name,
+                udf,
+                arg_exprs: args,
+                final_exprs: projection.expr,
+                final_schema: projection.schema,
+            }),
+        })))
+    }
+}
+
 pub struct SourceMetadataVisitor<'a> {
     schema_provider: &'a ArroyoSchemaProvider,
     pub connection_ids: HashSet<i64>,
@@ -610,7 +781,8 @@ impl TreeNodeVisitor for TimeWindowExprChecker {
     fn f_down(&mut self, node: &Self::Node) -> DFResult<TreeNodeRecursion> {
         if let Expr::ScalarFunction(ScalarFunction { func_def, args: _ }) = node {
             match func_def.name() {
-                "tumble" | "hop" | "session" => {
+                "tumble" | "hop" | "cumulate" | "session" | "proctime_tumble" | "proctime_hop"
+                | "count_tumble" | "count_hop" => {
                     return plan_err!(
                         "time window function {} is not allowed in this context. Are you missing a GROUP BY clause?",
                         func_def.name()
@@ -0,0 +1,374 @@
+//! Sampling of streams with `TABLESAMPLE`, which can follow any table in a `FROM` clause:
+//!
+//! * `TABLESAMPLE BERNOULLI(p)` keeps each row independently with probability `p` percent
+//! * `TABLESAMPLE HASH(p, key[, key...])` keeps the rows whose keys hash into the lowest `p`
+//!   percent of the hash space, so that a key is either always or never sampled, in every
+//!   pipeline and across restarts
+//!
+//! The SQL parser doesn't support `TABLESAMPLE`, so sampled tables are rewritten into filtered
+//! subqueries before the query is parsed. The hash-based sampler is also available as the
+//! `hash_sample(p, key[, key...])` function.
+
+use arrow_array::builder::BooleanBuilder;
+use arrow_array::ArrayRef;
+use arrow_schema::DataType;
+use arroyo_rpc::get_hasher;
+use datafusion::common::hash_utils::create_hashes;
+use datafusion::common::{exec_err, plan_datafusion_err, plan_err, Result, ScalarValue};
+use datafusion::logical_expr::{ColumnarValue, ScalarUDF, ScalarUDFImpl, Signature, Volatility};
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::keywords::Keyword;
+use datafusion::sql::sqlparser::tokenizer::{Token, Tokenizer};
+use std::any::Any;
+use std::sync::Arc;
+
+const TABLESAMPLE: &str = "TABLESAMPLE";
+// the resolution that sampling percentages are applied at
+const HASH_BUCKETS: u64 = 1_000_000;
+
+fn tokenize(sql: &str) -> Result<Vec<Token>> {
+    Tokenizer::new(&PostgreSqlDialect {}, sql)
+        .tokenize()
+        .map_err(|e| plan_datafusion_err!("{}", e))
+}
+
+fn is_word(token: &Token, word: &str) -> bool {
+    matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(word))
+}
+
+fn to_sql(tokens: &[Token]) -> String {
+    tokens.iter().map(|t| t.to_string()).collect()
+}
+
+/// Returns the index of the next token at or after `i` that isn't whitespace
+fn skip_whitespace(tokens: &[Token], mut i: usize) -> usize {
+    while matches!(tokens.get(i), Some(Token::Whitespace(_))) {
+        i += 1;
+    }
+    i
+}
+
+/// Returns the index of the last token before `end` that isn't whitespace
+fn previous_token(tokens: &[Token], end: usize) -> Option<usize> {
+    (0..end)
+        .rev()
+        .find(|i| !matches!(tokens[*i], Token::Whitespace(_)))
+}
+
+#[derive(Debug, PartialEq)]
+enum SampleMethod {
+    Bernoulli,
+    Hash,
+}
+
+/// A parsed `TABLESAMPLE` clause, whose arguments are kept as tokens
+struct Sample {
+    method: SampleMethod,
+    args: Vec<Vec<Token>>,
+}
+
+impl Sample {
+    /// Parses the clause that follows `TABLESAMPLE`, returning it along with the index of the
+    /// token after it
+    fn parse(tokens: &[Token], i: usize) -> Result<(Self, usize)> {
+        let i = skip_whitespace(tokens, i);
+        let method = match tokens.get(i) {
+            Some(t) if is_word(t, "BERNOULLI") => SampleMethod::Bernoulli,
+            Some(t) if is_word(t, "HASH") => SampleMethod::Hash,
+            Some(t) if is_word(t, "SYSTEM") => {
+                return plan_err!(
+                    "TABLESAMPLE SYSTEM is not supported for streams; use BERNOULLI or HASH"
+                );
+            }
+            _ => return plan_err!("TABLESAMPLE must be followed by BERNOULLI(p) or HASH(p, key)"),
+        };
+
+        let mut i = skip_whitespace(tokens, i + 1);
+        if tokens.get(i) != Some(&Token::LParen) {
+            return plan_err!("expected '(' after TABLESAMPLE {:?}", method);
+        }
+
+        let mut args = vec![vec![]];
+        let mut depth = 0;
+        loop {
+            i += 1;
+            match tokens.get(i) {
+                None => return plan_err!("unterminated TABLESAMPLE clause"),
+                Some(Token::RParen) if depth == 0 => break,
+                Some(Token::Comma) if depth == 0 => args.push(vec![]),
+                Some(token) => {
+                    match token {
+                        Token::LParen => depth += 1,
+                        Token::RParen => depth -= 1,
+                        _ => {}
+                    }
+                    args.last_mut().unwrap().push(token.clone());
+                }
+            }
+        }
+
+        let next = skip_whitespace(tokens, i + 1);
+        if tokens.get(next).is_some_and(|t| is_word(t, "REPEATABLE")) {
+            return plan_err!(
+                "TABLESAMPLE ... REPEATABLE is not supported; use TABLESAMPLE HASH(p, key) to \
+                sample deterministically"
+            );
+        }
+
+        if args
+            .iter()
+            .any(|a| a.iter().all(|t| matches!(t, Token::Whitespace(_))))
+        {
+            return plan_err!("empty argument in TABLESAMPLE clause");
+        }
+
+        match method {
+            SampleMethod::Bernoulli if args.len() != 1 => {
+                plan_err!("TABLESAMPLE BERNOULLI takes a single percentage")
+            }
+            SampleMethod::Hash if args.len() < 2 => {
+                plan_err!("TABLESAMPLE HASH takes a percentage followed by the keys to sample by")
+            }
+            _ => Ok((Self { method, args }, i + 1)),
+        }
+    }
+
+    fn filter(&self) -> String {
+        let percent = to_sql(&self.args[0]);
+        match self.method {
+            SampleMethod::Bernoulli => format!("random() * 100 < ({})", percent.trim()),
+            SampleMethod::Hash => format!(
+                "hash_sample({}, {})",
+                percent.trim(),
+                self.args[1..]
+                    .iter()
+                    .map(|a| to_sql(a).trim().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Finds the table that a `TABLESAMPLE` clause applies to at the end of `tokens`, returning the
+/// index of its first token along with its name and the name it's referenced by
+fn sampled_relation(tokens: &[Token]) -> Result<(usize, String, String)> {
+    let not_a_table = || plan_err!("TABLESAMPLE must follow a table name");
+
+    let Some(last) = previous_token(tokens, tokens.len()) else {
+        return not_a_table();
+    };
+    let Token::Word(last_word) = &tokens[last] else {
+        return not_a_table();
+    };
+
+    // the last word is an alias unless it follows a '.' or begins the relation
+    let before = previous_token(tokens, last);
+    let (alias, mut end) = match before.map(|i| (i, &tokens[i])) {
+        Some((i, Token::Word(w))) if w.quote_style.is_none() && w.keyword == Keyword::AS => {
+            (Some(last_word.to_string()), i)
+        }
+        Some((_, Token::Word(w)))
+            if w.quote_style.is_some() || !matches!(w.keyword, Keyword::FROM | Keyword::JOIN) =>
+        {
+            (Some(last_word.to_string()), last)
+        }
+        _ => (None, last + 1),
+    };
+
+    // the table name is made up of words separated by '.'
+    let Some(mut start) = previous_token(tokens, end) else {
+        return not_a_table();
+    };
+    if !matches!(tokens[start], Token::Word(_)) {
+        return not_a_table();
+    }
+    while let Some(period) = previous_token(tokens, start).filter(|i| tokens[*i] == Token::Period) {
+        match previous_token(tokens, period) {
+            Some(i) if matches!(tokens[i], Token::Word(_)) => start = i,
+            _ => return not_a_table(),
+        }
+    }
+    end = previous_token(tokens, end).unwrap() + 1;
+
+    let name = to_sql(&tokens[start..end]);
+    let alias = alias.unwrap_or_else(|| tokens[end - 1].to_string());
+
+    Ok((start, name, alias))
+}
+
+/// Rewrites each sampled table in the query into a subquery that filters it to the sample,
+/// referenced by the same name
+pub(crate) fn rewrite_tablesample(sql: &str) -> Result<String> {
+    if !sql.to_ascii_uppercase().contains(TABLESAMPLE) {
+        return Ok(sql.to_string());
+    }
+
+    let tokens = tokenize(sql)?;
+    let mut rewritten: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if !is_word(&tokens[i], TABLESAMPLE) {
+            rewritten.push(tokens[i].clone());
+            i += 1;
+            continue;
+        }
+
+        let (sample, next) = Sample::parse(&tokens, i + 1)?;
+        let (start, name, alias) = sampled_relation(&rewritten)?;
+        rewritten.truncate(start);
+        rewritten.extend(tokenize(&format!(
+            "(SELECT * FROM {} AS {} WHERE {}) AS {}",
+            name,
+            alias,
+            sample.filter(),
+            alias
+        ))?);
+        i = next;
+    }
+
+    Ok(to_sql(&rewritten))
+}
+
+/// Whether each row of `keys` falls into the lowest `percent` percent of the hash space
+fn hash_sample(percent: f64, keys: &[ArrayRef]) -> Result<ArrayRef> {
+    let len = keys[0].len();
+    let mut hashes = vec![0; len];
+    create_hashes(keys, &get_hasher(), &mut hashes)?;
+
+    let threshold = (percent.clamp(0.0, 100.0) / 100.0 * HASH_BUCKETS as f64) as u64;
+    let mut builder = BooleanBuilder::with_capacity(len);
+    for hash in hashes {
+        builder.append_value(hash % HASH_BUCKETS < threshold);
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+#[derive(Debug)]
+struct HashSample {
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for HashSample {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "hash_sample"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        if arg_types.len() < 2 {
+            return plan_err!("hash_sample takes a percentage followed by the keys to sample by");
+        }
+        if !arg_types[0].is_numeric() {
+            return plan_err!(
+                "the percentage passed to hash_sample must be numeric, not {}",
+                arg_types[0]
+            );
+        }
+        Ok(DataType::Boolean)
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let ColumnarValue::Scalar(percent) = &args[0] else {
+            return exec_err!("the percentage passed to hash_sample must be a constant");
+        };
+        let ScalarValue::Float64(Some(percent)) = percent.cast_to(&DataType::Float64)? else {
+            return exec_err!("the percentage passed to hash_sample must not be null");
+        };
+
+        let scalar = args[1..]
+            .iter()
+            .all(|a| matches!(a, ColumnarValue::Scalar(_)));
+        let keys = ColumnarValue::values_to_arrays(&args[1..])?;
+        let result = hash_sample(percent, &keys)?;
+
+        if scalar {
+            Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(
+                &result, 0,
+            )?))
+        } else {
+            Ok(ColumnarValue::Array(result))
+        }
+    }
+}
+
+pub fn hash_sample_function() -> Arc<ScalarUDF> {
+    Arc::new(ScalarUDF::new_from_impl(HashSample {
+        signature: Signature::variadic_any(Volatility::Immutable),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::cast::as_boolean_array;
+    use arrow_array::Int64Array;
+
+    #[test]
+    fn test_rewrite_tablesample() {
+        assert_eq!(
+            rewrite_tablesample("SELECT * FROM events").unwrap(),
+            "SELECT * FROM events"
+        );
+
+        assert_eq!(
+            rewrite_tablesample("SELECT * FROM events TABLESAMPLE BERNOULLI(10)").unwrap(),
+            "SELECT * FROM (SELECT * FROM events AS events WHERE random() * 100 < (10)) AS events"
+        );
+
+        assert_eq!(
+            rewrite_tablesample(
+                "SELECT count(*) FROM public.events AS e tablesample hash (2.5, e.user_id) \
+                JOIN users u TABLESAMPLE BERNOULLI(50) ON e.user_id = u.id"
+            )
+            .unwrap(),
+            "SELECT count(*) FROM (SELECT * FROM public.events AS e WHERE hash_sample(2.5, e.user_id)) \
+            AS e JOIN (SELECT * FROM users AS u WHERE random() * 100 < (50)) AS u ON e.user_id = u.id"
+        );
+
+        // a column or string called tablesample is left alone
+        assert_eq!(
+            rewrite_tablesample("SELECT \"tablesample\", 'TABLESAMPLE' FROM events").unwrap(),
+            "SELECT \"tablesample\", 'TABLESAMPLE' FROM events"
+        );
+
+        assert!(rewrite_tablesample("SELECT * FROM events TABLESAMPLE SYSTEM(10)").is_err());
+        assert!(rewrite_tablesample("SELECT * FROM events TABLESAMPLE HASH(10)").is_err());
+        assert!(rewrite_tablesample("SELECT * FROM events TABLESAMPLE BERNOULLI(10, id)").is_err());
+        assert!(rewrite_tablesample("SELECT 1 TABLESAMPLE BERNOULLI(10)").is_err());
+    }
+
+    #[test]
+    fn test_hash_sample() {
+        let keys: ArrayRef = Arc::new(Int64Array::from_iter_values(0..10_000));
+
+        let sampled = hash_sample(10.0, &[keys.clone()]).unwrap();
+        let count = as_boolean_array(&sampled).true_count();
+        assert!((800..1200).contains(&count), "sampled {} rows", count);
+
+        // the same keys are always sampled, and a larger sample includes a smaller one
+        assert_eq!(&sampled, &hash_sample(10.0, &[keys.clone()]).unwrap());
+        let larger = hash_sample(50.0, &[keys.clone()]).unwrap();
+        let larger = as_boolean_array(&larger);
+        assert!(as_boolean_array(&sampled)
+            .iter()
+            .zip(larger.iter())
+            .all(|(small, large)| !small.unwrap() || large.unwrap()));
+
+        assert_eq!(
+            as_boolean_array(&hash_sample(0.0, &[keys.clone()]).unwrap()).true_count(),
+            0
+        );
+        assert_eq!(
+            as_boolean_array(&hash_sample(100.0, &[keys]).unwrap()).true_count(),
+            10_000
+        );
+    }
+}
@@ -21,6 +21,19 @@ pub fn window_arrow_struct() -> DataType {
     )
 }
 
+/// The window struct of windows with a trigger, whose results are marked as `partial` (fired
+/// early), `final`, or `late` (corrected by records that arrived after the window closed)
+pub fn triggered_window_arrow_struct() -> DataType {
+    let DataType::Struct(fields) = window_arrow_struct() else {
+        unreachable!()
+    };
+    let mut fields = fields.to_vec();
+    fields.push(Arc::new(Field::new(EMIT_MODE_FIELD, DataType::Utf8, false)));
+    DataType::Struct(fields.into())
+}
+
+pub const EMIT_MODE_FIELD: &str = "emit_mode";
+
 pub(crate) fn add_timestamp_field(
     schema: DFSchemaRef,
     qualifier: Option<OwnedTableReference>,
@@ -0,0 +1,180 @@
+//! Pipeline settings, which are given in `SET` statements anywhere in the query and override the
+//! settings the pipeline was created with.
+//!
+//! Supported settings:
+//! * `parallelism`: the parallelism every operator of the pipeline starts with, e.g.
+//!   `SET parallelism = 8`
+//! * `checkpoint_interval`: how often the pipeline checkpoints, as a duration like `'30s'` or
+//!   `'5 minutes'`
+//! * `sink.semantics` (or `sink.delivery_semantics`): the delivery semantics of sinks that don't
+//!   set `delivery_semantics` in their `WITH` options; one of `'exactly_once'`,
+//!   `'at_least_once'`, or `'at_most_once'`
+
+use arroyo_datastream::logical::{LogicalGraph, OperatorName};
+use arroyo_rpc::config::HumanReadableDuration;
+use arroyo_rpc::grpc::api::ConnectorOp;
+use arroyo_rpc::{DeliverySemantics, OperatorConfig};
+use datafusion::common::{plan_err, DataFusionError, Result};
+use datafusion::sql::sqlparser::ast::{Expr, Statement, UnaryOperator, Value};
+use prost::Message;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+const SETTINGS: &str = "parallelism, checkpoint_interval, sink.semantics";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PipelineSettings {
+    pub parallelism: Option<usize>,
+    pub checkpoint_interval: Option<Duration>,
+    pub sink_delivery_semantics: Option<DeliverySemantics>,
+}
+
+fn value_string(name: &str, value: &[Expr]) -> Result<String> {
+    let [value] = value else {
+        return plan_err!("SET {} takes a single value", name);
+    };
+
+    match value {
+        Expr::Value(Value::Number(n, _)) => Ok(n.clone()),
+        Expr::Value(Value::SingleQuotedString(s) | Value::DoubleQuotedString(s)) => Ok(s.clone()),
+        Expr::Identifier(ident) => Ok(ident.value.clone()),
+        Expr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr,
+        } => Ok(format!("-{}", value_string(name, &[*expr.clone()])?)),
+        _ => plan_err!("invalid value for SET {}: {}", name, value),
+    }
+}
+
+impl PipelineSettings {
+    /// Records the setting made by `statement`, returning false if it isn't a `SET` statement
+    pub fn try_from_statement(&mut self, statement: &Statement) -> Result<bool> {
+        let Statement::SetVariable {
+            variable, value, ..
+        } = statement
+        else {
+            return Ok(false);
+        };
+
+        let name = variable.to_string().to_lowercase();
+        let value = value_string(&name, value)?;
+
+        match name.as_str() {
+            "parallelism" => {
+                let parallelism: usize =
+                    value.parse().ok().filter(|p| *p > 0).ok_or_else(|| {
+                        DataFusionError::Plan(format!(
+                            "invalid parallelism '{}'; expected a positive integer",
+                            value
+                        ))
+                    })?;
+                self.parallelism = Some(parallelism);
+            }
+            "checkpoint_interval" => {
+                let interval =
+                    HumanReadableDuration::deserialize(value.as_str().into_deserializer())
+                        .map_err(|e: serde::de::value::Error| {
+                            DataFusionError::Plan(format!(
+                                "invalid checkpoint_interval '{}': {}",
+                                value, e
+                            ))
+                        })?;
+                self.checkpoint_interval = Some(*interval);
+            }
+            "sink.semantics" | "sink.delivery_semantics" => {
+                let mut opts = HashMap::from([("delivery_semantics".to_string(), value)]);
+                self.sink_delivery_semantics = DeliverySemantics::from_opts(&mut opts)
+                    .map_err(|e| DataFusionError::Plan(format!("invalid {}: {}", name, e)))?;
+            }
+            _ => {
+                return plan_err!(
+                    "unknown setting '{}'; supported settings are: {}",
+                    name,
+                    SETTINGS
+                );
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Applies the settings that are part of the plan to `graph`
+    pub(crate) fn apply(&self, graph: &mut LogicalGraph) -> Result<()> {
+        let Some(semantics) = self.sink_delivery_semantics else {
+            return Ok(());
+        };
+
+        for node in graph.node_weights_mut() {
+            if node.operator_name != OperatorName::ConnectorSink {
+                continue;
+            }
+
+            let mut op = ConnectorOp::decode(&node.operator_config[..])
+                .map_err(|e| DataFusionError::Plan(format!("invalid sink configuration: {}", e)))?;
+            let mut config: OperatorConfig = serde_json::from_str(&op.config)
+                .map_err(|e| DataFusionError::Plan(format!("invalid sink configuration: {}", e)))?;
+
+            // semantics set on the sink table itself take precedence
+            if config.delivery_semantics.is_none() {
+                config.delivery_semantics = Some(semantics);
+                op.config = serde_json::to_string(&config).unwrap();
+                node.operator_config = op.encode_to_vec();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+    use datafusion::sql::sqlparser::parser::Parser;
+
+    fn parse(sql: &str) -> Result<PipelineSettings> {
+        let mut settings = PipelineSettings::default();
+        for statement in Parser::parse_sql(&PostgreSqlDialect {}, sql).unwrap() {
+            assert!(settings.try_from_statement(&statement)?);
+        }
+        Ok(settings)
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        assert_eq!(
+            parse(
+                "SET parallelism = 8; SET checkpoint_interval = '30s'; \
+                SET sink.semantics = 'at_least_once';"
+            )
+            .unwrap(),
+            PipelineSettings {
+                parallelism: Some(8),
+                checkpoint_interval: Some(Duration::from_secs(30)),
+                sink_delivery_semantics: Some(DeliverySemantics::AtLeastOnce),
+            }
+        );
+
+        assert_eq!(
+            parse("SET checkpoint_interval TO '5 minutes'")
+                .unwrap()
+                .checkpoint_interval,
+            Some(Duration::from_secs(300))
+        );
+
+        assert!(parse("SET parallelism = 0").is_err());
+        assert!(parse("SET parallelism = -2").is_err());
+        assert!(parse("SET checkpoint_interval = 'soon'").is_err());
+        assert!(parse("SET sink.semantics = 'twice'").is_err());
+        assert!(parse("SET batch_size = 100").is_err());
+
+        let statement = Parser::parse_sql(&PostgreSqlDialect {}, "SELECT 1")
+            .unwrap()
+            .remove(0);
+        assert!(!PipelineSettings::default()
+            .try_from_statement(&statement)
+            .unwrap());
+    }
+}
@@ -0,0 +1,379 @@
+//! String similarity and phonetic functions, for fuzzy matching and deduplication of entities.
+//! Similarities are computed over unicode characters, and are case-sensitive; use `lower` to
+//! compare case-insensitively.
+
+use arrow_array::builder::{Float64Builder, Int64Builder, StringBuilder};
+use arrow_array::cast::{as_primitive_array, as_string_array};
+use arrow_array::types::Int64Type;
+use arrow_array::{Array, ArrayRef};
+use arrow_schema::DataType;
+use datafusion::common::{DataFusionError, Result, ScalarValue};
+use datafusion::logical_expr::{
+    ColumnarValue, ScalarUDF, ScalarUDFImpl, Signature, TypeSignature, Volatility,
+};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+const JARO_WINKLER_PREFIX_SCALE: f64 = 0.1;
+const JARO_WINKLER_MAX_PREFIX: usize = 4;
+const DEFAULT_NGRAM_SIZE: i64 = 3;
+
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+fn jaro(a: &[char], b: &[char]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0;
+
+    for (i, ca) in a.iter().enumerate() {
+        let end = (i + window + 1).min(b.len());
+        for j in i.saturating_sub(window)..end {
+            if !b_matched[j] && b[j] == *ca {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+
+    if matches == 0 {
+        return 0.0;
+    }
+
+    let b_matches = b
+        .iter()
+        .zip(&b_matched)
+        .filter(|(_, m)| **m)
+        .map(|(c, _)| c);
+    let transpositions = a
+        .iter()
+        .zip(&a_matched)
+        .filter(|(_, m)| **m)
+        .zip(b_matches)
+        .filter(|((ca, _), cb)| *ca != *cb)
+        .count();
+
+    let m = matches as f64;
+    (m / a.len() as f64 + m / b.len() as f64 + (m - transpositions as f64 / 2.0) / m) / 3.0
+}
+
+pub fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let jaro = jaro(&a, &b);
+    let prefix = a
+        .iter()
+        .zip(&b)
+        .take(JARO_WINKLER_MAX_PREFIX)
+        .take_while(|(a, b)| a == b)
+        .count();
+    jaro + prefix as f64 * JARO_WINKLER_PREFIX_SCALE * (1.0 - jaro)
+}
+
+/// American Soundex; characters other than ASCII letters are ignored
+pub fn soundex(s: &str) -> String {
+    let code = |c: char| match c.to_ascii_lowercase() {
+        'b' | 'f' | 'p' | 'v' => Some('1'),
+        'c' | 'g' | 'j' | 'k' | 'q' | 's' | 'x' | 'z' => Some('2'),
+        'd' | 't' => Some('3'),
+        'l' => Some('4'),
+        'm' | 'n' => Some('5'),
+        'r' => Some('6'),
+        _ => None,
+    };
+
+    let mut letters = s.chars().filter(|c| c.is_ascii_alphabetic());
+    let Some(first) = letters.next() else {
+        return String::new();
+    };
+
+    let mut result = String::with_capacity(4);
+    result.push(first.to_ascii_uppercase());
+    let mut last = code(first);
+    for c in letters {
+        if result.len() == 4 {
+            break;
+        }
+        match code(c) {
+            Some(d) if Some(d) != last => {
+                result.push(d);
+                last = Some(d);
+            }
+            Some(_) => {}
+            // h and w don't separate letters with the same code, while vowels do
+            None if matches!(c.to_ascii_lowercase(), 'h' | 'w') => {}
+            None => last = None,
+        }
+    }
+
+    while result.len() < 4 {
+        result.push('0');
+    }
+    result
+}
+
+fn ngrams(s: &str, n: usize) -> HashSet<&str> {
+    let boundaries: Vec<usize> = s
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(s.len()))
+        .collect();
+    if boundaries.len() <= n {
+        // strings shorter than n are their own only n-gram
+        return [s].into_iter().filter(|s| !s.is_empty()).collect();
+    }
+    boundaries.windows(n + 1).map(|w| &s[w[0]..w[n]]).collect()
+}
+
+/// The Jaccard similarity of the sets of character n-grams of the strings
+pub fn ngram_similarity(a: &str, b: &str, n: usize) -> f64 {
+    let a = ngrams(a, n);
+    let b = ngrams(b, n);
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
+#[derive(Debug)]
+struct StringFunction {
+    name: &'static str,
+    signature: Signature,
+    return_type: DataType,
+    f: fn(&[ArrayRef]) -> Result<ArrayRef>,
+}
+
+impl ScalarUDFImpl for StringFunction {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(self.return_type.clone())
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let scalar = args.iter().all(|a| matches!(a, ColumnarValue::Scalar(_)));
+        let result = (self.f)(&ColumnarValue::values_to_arrays(args)?)?;
+
+        if scalar {
+            Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(
+                &result, 0,
+            )?))
+        } else {
+            Ok(ColumnarValue::Array(result))
+        }
+    }
+}
+
+fn pairs(args: &[ArrayRef]) -> impl Iterator<Item = Option<(&str, &str)>> {
+    let a = as_string_array(&args[0]);
+    let b = as_string_array(&args[1]);
+    a.iter().zip(b.iter()).map(|(a, b)| a.zip(b))
+}
+
+fn levenshtein_fn(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let mut builder = Int64Builder::with_capacity(args[0].len());
+    for pair in pairs(args) {
+        builder.append_option(pair.map(|(a, b)| levenshtein(a, b) as i64));
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn jaro_winkler_fn(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let mut builder = Float64Builder::with_capacity(args[0].len());
+    for pair in pairs(args) {
+        builder.append_option(pair.map(|(a, b)| jaro_winkler(a, b)));
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn soundex_fn(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let values = as_string_array(&args[0]);
+    let mut builder = StringBuilder::with_capacity(values.len(), values.len() * 4);
+    for value in values.iter() {
+        builder.append_option(value.map(soundex));
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn ngram_similarity_fn(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let sizes = args.get(2).map(|s| as_primitive_array::<Int64Type>(s));
+    let mut builder = Float64Builder::with_capacity(args[0].len());
+    for (i, pair) in pairs(args).enumerate() {
+        let n = match sizes {
+            Some(sizes) if sizes.is_null(i) => None,
+            Some(sizes) => Some(sizes.value(i)),
+            None => Some(DEFAULT_NGRAM_SIZE),
+        };
+        let n = match n {
+            Some(n) if n < 1 => {
+                return Err(DataFusionError::Execution(format!(
+                    "ngram_similarity n-gram size must be positive, but was {}",
+                    n
+                )));
+            }
+            n => n,
+        };
+        builder.append_option(
+            pair.zip(n)
+                .map(|((a, b), n)| ngram_similarity(a, b, n as usize)),
+        );
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+/// * `levenshtein(a, b)` returns the edit distance between the strings
+/// * `jaro_winkler(a, b)` returns the Jaro-Winkler similarity, from 0 to 1
+/// * `soundex(s)` returns the Soundex code of the string
+/// * `ngram_similarity(a, b[, n])` returns the Jaccard similarity, from 0 to 1, of the strings'
+///   character n-grams, which are trigrams by default
+pub fn get_similarity_functions() -> HashMap<String, Arc<ScalarUDF>> {
+    use DataType::*;
+    let exact = |args: Vec<Vec<DataType>>| {
+        Signature::one_of(
+            args.into_iter().map(TypeSignature::Exact).collect(),
+            Volatility::Immutable,
+        )
+    };
+
+    [
+        StringFunction {
+            name: "levenshtein",
+            signature: exact(vec![vec![Utf8, Utf8]]),
+            return_type: Int64,
+            f: levenshtein_fn,
+        },
+        StringFunction {
+            name: "jaro_winkler",
+            signature: exact(vec![vec![Utf8, Utf8]]),
+            return_type: Float64,
+            f: jaro_winkler_fn,
+        },
+        StringFunction {
+            name: "soundex",
+            signature: exact(vec![vec![Utf8]]),
+            return_type: Utf8,
+            f: soundex_fn,
+        },
+        StringFunction {
+            name: "ngram_similarity",
+            signature: exact(vec![vec![Utf8, Utf8], vec![Utf8, Utf8, Int64]]),
+            return_type: Float64,
+            f: ngram_similarity_fn,
+        },
+    ]
+    .into_iter()
+    .map(|f| (f.name.to_string(), Arc::new(ScalarUDF::new_from_impl(f))))
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow_array::{Float64Array, StringArray};
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("flaw", "flaw"), 0);
+        assert_eq!(levenshtein("café", "cafe"), 1);
+    }
+
+    #[test]
+    fn test_jaro_winkler() {
+        assert!((jaro_winkler("MARTHA", "MARHTA") - 0.9611).abs() < 0.0001);
+        assert!((jaro_winkler("DIXON", "DICKSONX") - 0.8133).abs() < 0.0001);
+        assert_eq!(jaro_winkler("", ""), 1.0);
+        assert_eq!(jaro_winkler("abc", "xyz"), 0.0);
+    }
+
+    #[test]
+    fn test_soundex() {
+        for (s, expected) in [
+            ("Robert", "R163"),
+            ("Rupert", "R163"),
+            ("Ashcraft", "A261"),
+            ("Tymczak", "T522"),
+            ("Pfister", "P236"),
+            ("Lee", "L000"),
+            ("123", ""),
+        ] {
+            assert_eq!(soundex(s), expected, "soundex({})", s);
+        }
+    }
+
+    #[test]
+    fn test_ngram_similarity() {
+        assert_eq!(ngram_similarity("night", "night", 3), 1.0);
+        // {nig, igh, ght} and {nac, ach, cht}
+        assert_eq!(ngram_similarity("night", "nacht", 3), 0.0);
+        // {ni, ig, gh, ht} and {na, ac, ch, ht}
+        assert_eq!(ngram_similarity("night", "nacht", 2), 1.0 / 7.0);
+        assert_eq!(ngram_similarity("ab", "ab", 3), 1.0);
+        assert_eq!(ngram_similarity("", "", 3), 1.0);
+    }
+
+    #[test]
+    fn test_udfs() {
+        let functions = get_similarity_functions();
+        let names = ColumnarValue::Array(Arc::new(StringArray::from(vec![
+            Some("Jonathan"),
+            Some("Jon"),
+            None,
+        ])));
+
+        let ColumnarValue::Array(result) = functions["ngram_similarity"]
+            .invoke(&[names, ColumnarValue::Scalar("Jonathan".into())])
+            .unwrap()
+        else {
+            panic!("Expected array, got scalar");
+        };
+        assert_eq!(
+            *result,
+            Float64Array::from(vec![Some(1.0), Some(1.0 / 6.0), None])
+        );
+
+        let ColumnarValue::Scalar(result) = functions["soundex"]
+            .invoke(&[ColumnarValue::Scalar("Honeyman".into())])
+            .unwrap()
+        else {
+            panic!("Expected scalar");
+        };
+        assert_eq!(result, ScalarValue::Utf8(Some("H555".to_string())));
+    }
+}
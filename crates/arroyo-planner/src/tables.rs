@@ -10,11 +10,16 @@ use arroyo_operator::connector::Connection;
 use arroyo_rpc::api_types::connections::{
     ConnectionProfile, ConnectionSchema, ConnectionType, SourceField,
 };
-use arroyo_rpc::formats::{BadData, Format, Framing, JsonFormat};
+use arroyo_rpc::formats::{AvroFormat, BadData, Format, Framing, JsonFormat};
 use arroyo_rpc::grpc::api::ConnectorOp;
+use arroyo_rpc::{
+    DeliverySemantics, OperatorConfig, PartitionWatermarks, SINK_KEY_FIELD, SINK_PARTITION_FIELD,
+};
 use arroyo_types::ArroyoExtensionType;
 use datafusion::common::{config::ConfigOptions, DFField, DFSchema, Result};
 use datafusion::common::{plan_err, Column, DataFusionError};
+use datafusion::logical_expr::expr_rewriter::normalize_col;
+use datafusion::logical_expr::{cast, ExprSchemable, LogicalPlanBuilder};
 use datafusion::logical_expr::{
     CreateMemoryTable, CreateView, DdlStatement, DmlStatement, Expr, Extension, LogicalPlan,
     WriteOp,
@@ -42,6 +47,8 @@ use datafusion::optimizer::OptimizerRule;
 use datafusion::sql::planner::PlannerContext;
 use datafusion::sql::sqlparser;
 use datafusion::sql::sqlparser::ast::Query;
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::parser::Parser;
 use datafusion::{
     optimizer::{analyzer::Analyzer, optimizer::Optimizer, OptimizerContext},
     sql::{
@@ -56,7 +63,10 @@ use crate::{
     external::{ProcessingMode, SqlSource},
     ArroyoSchemaProvider,
 };
-use crate::{rewrite_plan, DEFAULT_IDLE_TIME};
+use crate::{
+    rewrite_plan, DEFAULT_IDLE_TIME, DEFAULT_MAX_WATERMARK_DRIFT,
+    DEFAULT_PARTITION_WATERMARK_LATENESS,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ConnectorTable {
@@ -70,7 +80,19 @@ pub struct ConnectorTable {
     pub format: Option<Format>,
     pub event_time_field: Option<String>,
     pub watermark_field: Option<String>,
+    /// for sources, a field whose non-null values mark that all data up to that time has been
+    /// sent, which are used as the watermark
+    pub watermark_punctuation_field: Option<String>,
     pub idle_time: Option<Duration>,
+    /// for sinks that upsert rows, the fields that identify a row
+    pub upsert_key: Vec<String>,
+    /// for sources, the watermark-alignment group the source belongs to and how far its
+    /// watermark may get ahead of the slowest source in the group
+    pub watermark_alignment: Option<(String, Duration)>,
+    /// for sinks, expressions over the table's fields that compute the key and partition of
+    /// each message
+    pub key_expression: Option<Expr>,
+    pub partition_expression: Option<Expr>,
 
     pub inferred_fields: Option<Vec<DFField>>,
 }
@@ -102,7 +124,7 @@ impl From<Field> for FieldSpec {
     }
 }
 
-fn produce_optimized_plan(
+pub(crate) fn produce_optimized_plan(
     statement: &Statement,
     schema_provider: &ArroyoSchemaProvider,
 ) -> Result<LogicalPlan> {
@@ -180,7 +202,14 @@ impl From<Connection> for ConnectorTable {
             format: value.schema.format.clone(),
             event_time_field: None,
             watermark_field: None,
+            watermark_punctuation_field: None,
             idle_time: DEFAULT_IDLE_TIME,
+            upsert_key: serde_json::from_str::<OperatorConfig>(&value.config)
+                .map(|config| config.upsert_key)
+                .unwrap_or_default(),
+            watermark_alignment: None,
+            key_expression: None,
+            partition_expression: None,
             inferred_fields: None,
         }
     }
@@ -193,6 +222,7 @@ impl ConnectorTable {
         mut fields: Vec<FieldSpec>,
         options: &mut HashMap<String, String>,
         connection_profile: Option<&ConnectionProfile>,
+        schema_provider: &ArroyoSchemaProvider,
     ) -> Result<Self> {
         // TODO: a more principled way of letting connectors dictate types to use
         if "delta" == connector {
@@ -255,6 +285,39 @@ impl ConnectorTable {
             .collect::<Result<_>>()?;
         let bad_data = BadData::from_opts(options)
             .map_err(|e| DataFusionError::Plan(format!("Invalid bad_data: '{e}'")))?;
+        let delivery_semantics = DeliverySemantics::from_opts(options)
+            .map_err(|e| DataFusionError::Plan(format!("invalid delivery_semantics: {e}")))?;
+        let upsert_key: Vec<String> = options
+            .remove("upsert_key")
+            .map(|key| key.split(',').map(|f| f.trim().to_string()).collect())
+            .unwrap_or_default();
+        if let Some(field) = upsert_key
+            .iter()
+            .find(|k| !schema_fields.iter().any(|f| &f.field_name == *k))
+        {
+            return plan_err!("upsert_key field '{}' is not a field of the table", field);
+        }
+        // upsert keys are serialized with the table's format, and the raw formats can only
+        // serialize a single field of their type
+        let raw_key_type = match &format {
+            Some(Format::RawString(_)) => Some(DataType::Utf8),
+            Some(Format::RawBytes(_)) => Some(DataType::Binary),
+            _ => None,
+        };
+        if let Some(raw_key_type) = raw_key_type.filter(|_| !upsert_key.is_empty()) {
+            let key_type = fields
+                .iter()
+                .find(|f| f.field().name() == &upsert_key[0])
+                .map(|f| f.field().data_type());
+            if upsert_key.len() != 1 || key_type != Some(&raw_key_type) {
+                return plan_err!(
+                    "with a raw format, upsert_key must be a single field of type {}",
+                    raw_key_type
+                );
+            }
+        }
+        let key_expression = options.remove("key_expression");
+        let partition_expression = options.remove("partition_expression");
 
         let schema = ConnectionSchema::try_new(
             format,
@@ -267,17 +330,90 @@ impl ConnectorTable {
         )
         .map_err(|e| DataFusionError::Plan(format!("could not create connection schema: {}", e)))?;
 
-        let connection = connector
+        let mut connection = connector
             .from_options(name, options, Some(&schema), connection_profile)
             .map_err(|e| DataFusionError::Plan(e.to_string()))?;
 
+        if delivery_semantics.is_some() || !upsert_key.is_empty() {
+            if connection.connection_type != ConnectionType::Sink {
+                return plan_err!(
+                    "delivery_semantics and upsert_key can only be set for sink tables"
+                );
+            }
+            if let Some(semantics) = delivery_semantics {
+                if !connector.supports_delivery_semantics(semantics) {
+                    return plan_err!(
+                        "the {} connector does not support {} delivery",
+                        connector.name(),
+                        semantics
+                    );
+                }
+            }
+            if !upsert_key.is_empty() && !connector.supports_upserts() {
+                return plan_err!(
+                    "the {} connector does not support upserts",
+                    connector.name()
+                );
+            }
+            let mut config: OperatorConfig =
+                serde_json::from_str(&connection.config).map_err(|e| {
+                    DataFusionError::Plan(format!("invalid config for table {}: {}", name, e))
+                })?;
+            config.delivery_semantics = delivery_semantics;
+            config.upsert_key = upsert_key.clone();
+            connection.config = serde_json::to_string(&config).unwrap();
+        }
+
+        if key_expression.is_some() || partition_expression.is_some() {
+            if connection.connection_type != ConnectionType::Sink {
+                return plan_err!(
+                    "key_expression and partition_expression can only be set for sink tables"
+                );
+            }
+            if !connector.supports_partitioning_expressions() {
+                return plan_err!(
+                    "the {} connector does not support key_expression or partition_expression",
+                    connector.name()
+                );
+            }
+            if key_expression.is_some() && !upsert_key.is_empty() {
+                return plan_err!(
+                    "key_expression can't be set along with upsert_key, which determines the key of each message"
+                );
+            }
+        }
+
         let mut table: ConnectorTable = connection.into();
         if !fields.is_empty() {
             table.fields = fields;
         }
 
+        if (key_expression.is_some() || partition_expression.is_some()) && table.is_update() {
+            return plan_err!(
+                "key_expression and partition_expression can't be used with debezium formats"
+            );
+        }
+        table.key_expression = key_expression
+            .map(|sql| table.parse_expression("key_expression", &sql, schema_provider))
+            .transpose()?;
+        table.partition_expression = partition_expression
+            .map(|sql| table.parse_expression("partition_expression", &sql, schema_provider))
+            .transpose()?;
+
         table.event_time_field = options.remove("event_time_field");
         table.watermark_field = options.remove("watermark_field");
+        table.watermark_punctuation_field = options.remove("watermark_punctuation_field");
+        if let Some(field) = &table.watermark_punctuation_field {
+            if table.connection_type != ConnectionType::Source {
+                return plan_err!("watermark_punctuation_field can only be set for source tables");
+            }
+            if table.watermark_field.is_some() {
+                return plan_err!(
+                    "watermark_punctuation_field can't be set along with watermark_field"
+                );
+            }
+            table.get_time_field(field)?;
+        }
 
         table.idle_time = options
             .remove("idle_micros")
@@ -288,6 +424,95 @@ impl ConnectorTable {
             .filter(|t| *t <= 0)
             .map(|t| Duration::from_micros(t as u64));
 
+        let max_drift = options
+            .remove("watermark_alignment_max_drift_micros")
+            .map(|t| u64::from_str(&t))
+            .transpose()
+            .map_err(|_| {
+                DataFusionError::Plan(
+                    "watermark_alignment_max_drift_micros must be set to a number".to_string(),
+                )
+            })?
+            .map(Duration::from_micros);
+        table.watermark_alignment = match (options.remove("watermark_alignment_group"), max_drift) {
+            (Some(group), max_drift) => {
+                if table.connection_type != ConnectionType::Source {
+                    return plan_err!(
+                        "watermark_alignment_group can only be set for source tables"
+                    );
+                }
+                Some((group, max_drift.unwrap_or(DEFAULT_MAX_WATERMARK_DRIFT)))
+            }
+            (None, Some(_)) => {
+                return plan_err!(
+                    "watermark_alignment_max_drift_micros requires watermark_alignment_group to be set"
+                );
+            }
+            (None, None) => None,
+        };
+
+        let partition_watermarks = options
+            .remove("partition_watermarks")
+            .map(|s| bool::from_str(&s))
+            .transpose()
+            .map_err(|_| {
+                DataFusionError::Plan("partition_watermarks must be 'true' or 'false'".to_string())
+            })?
+            .unwrap_or(false);
+        let lateness = options
+            .remove("partition_watermark_lateness_micros")
+            .map(|t| u64::from_str(&t))
+            .transpose()
+            .map_err(|_| {
+                DataFusionError::Plan(
+                    "partition_watermark_lateness_micros must be set to a number".to_string(),
+                )
+            })?;
+        if partition_watermarks {
+            if table.connection_type != ConnectionType::Source {
+                return plan_err!("partition_watermarks can only be set for source tables");
+            }
+            if !connector.supports_partition_watermarks() {
+                return plan_err!(
+                    "the {} connector does not support partition_watermarks",
+                    connector.name()
+                );
+            }
+            // the source tracks each partition by the timestamps of the messages it reads, so the
+            // watermark can't be computed from the fields of the table
+            if table.event_time_field.is_some()
+                || table.watermark_field.is_some()
+                || table.watermark_punctuation_field.is_some()
+            {
+                return plan_err!(
+                    "partition_watermarks can't be used with event_time_field, watermark_field, \
+                    or watermark_punctuation_field"
+                );
+            }
+            if table.watermark_alignment.is_some() {
+                return plan_err!(
+                    "partition_watermarks can't be used with watermark_alignment_group"
+                );
+            }
+
+            let mut config: OperatorConfig = serde_json::from_str(&table.config).map_err(|e| {
+                DataFusionError::Plan(format!("invalid config for table {}: {}", name, e))
+            })?;
+            config.partition_watermarks = Some(PartitionWatermarks {
+                lateness_micros: lateness
+                    .unwrap_or(DEFAULT_PARTITION_WATERMARK_LATENESS.as_micros() as u64),
+                idle_time_micros: table
+                    .idle_time
+                    .or(DEFAULT_IDLE_TIME)
+                    .map(|t| t.as_micros() as u64),
+            });
+            table.config = serde_json::to_string(&config).unwrap();
+        } else if lateness.is_some() {
+            return plan_err!(
+                "partition_watermark_lateness_micros requires partition_watermarks to be enabled"
+            );
+        }
+
         if !options.is_empty() {
             let keys: Vec<String> = options.keys().map(|s| format!("'{}'", s)).collect();
             return plan_err!(
@@ -299,6 +524,67 @@ impl ConnectorTable {
         Ok(table)
     }
 
+    /// Parses a SQL expression over the fields of the table, for options like `key_expression`
+    fn parse_expression(
+        &self,
+        option: &str,
+        sql: &str,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<Expr> {
+        if self.fields.is_empty() {
+            return plan_err!("{} requires the fields of the table to be defined", option);
+        }
+
+        let schema = DFSchema::try_from(self.physical_schema())?;
+        let expr = Parser::new(&PostgreSqlDialect {})
+            .try_with_sql(sql)?
+            .parse_expr()?;
+
+        SqlToRel::new(schema_provider)
+            .sql_to_expr(expr, &schema, &mut PlannerContext::default())
+            .map_err(|e| e.context(format!("invalid {}", option)))
+    }
+
+    /// Adds the message key and partition computed by the table's `key_expression` and
+    /// `partition_expression` to the input of its sink, as extra columns
+    pub(crate) fn add_partitioning_columns(&self, input: LogicalPlan) -> Result<LogicalPlan> {
+        let mut exprs: Vec<Expr> = input
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| Expr::Column(f.qualified_column()))
+            .collect();
+
+        if let Some(key) = &self.key_expression {
+            let key = normalize_col(key.clone(), &input)?;
+            let key = match key.get_type(input.schema())? {
+                DataType::Utf8 | DataType::Binary => key,
+                _ => cast(key, DataType::Utf8),
+            };
+            exprs.push(key.alias(SINK_KEY_FIELD));
+        }
+
+        if let Some(partition) = &self.partition_expression {
+            let partition = normalize_col(partition.clone(), &input)?;
+            let data_type = partition.get_type(input.schema())?;
+            if !data_type.is_integer() {
+                return plan_err!(
+                    "partition_expression must be an integer, but has type {}",
+                    data_type
+                );
+            }
+            exprs.push(cast(partition, DataType::Int32).alias(SINK_PARTITION_FIELD));
+        }
+
+        LogicalPlanBuilder::from(input).project(exprs)?.build()
+    }
+
+    /// Whether the table's source generates its own watermarks for each partition
+    pub(crate) fn has_partition_watermarks(&self) -> bool {
+        serde_json::from_str::<OperatorConfig>(&self.config)
+            .is_ok_and(|config| config.partition_watermarks.is_some())
+    }
+
     fn has_virtual_fields(&self) -> bool {
         self.fields.iter().any(|f| f.is_virtual())
     }
@@ -420,6 +706,26 @@ impl ConnectorTable {
             Some(Format::Json(JsonFormat { debezium: true, .. }))
         )
     }
+
+    /// Whether the source can skip deserializing fields that aren't used
+    pub(crate) fn supports_projection_pushdown(&self) -> bool {
+        matches!(
+            &self.format,
+            Some(Format::Json(JsonFormat {
+                debezium: false,
+                unstructured: false,
+                ..
+            })) | Some(Format::Avro(AvroFormat {
+                into_unstructured_json: false,
+                ..
+            })) | Some(Format::Parquet(_))
+        )
+    }
+
+    /// Whether the source can use a pushed-down predicate to skip reading data
+    pub(crate) fn supports_filter_pushdown(&self) -> bool {
+        matches!(&self.format, Some(Format::Parquet(_)))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -609,6 +915,7 @@ impl Table {
                             fields,
                             &mut with_map,
                             connection_profile,
+                            schema_provider,
                         )
                         .map_err(|e| e.context(format!("Failed to create table {}", name)))?,
                     )))
@@ -616,11 +923,15 @@ impl Table {
             }
         } else {
             match &produce_optimized_plan(statement, schema_provider) {
-                // views and memory tables are the same now.
+                // views aren't materialized; their plan is inlined wherever they're read, and
+                // identical subplans are shared between the statements that read them
                 Ok(LogicalPlan::Ddl(DdlStatement::CreateView(CreateView {
                     name, input, ..
-                })))
-                | Ok(LogicalPlan::Ddl(DdlStatement::CreateMemoryTable(CreateMemoryTable {
+                }))) => Ok(Some(Table::TableFromQuery {
+                    name: name.to_string(),
+                    logical_plan: rewrite_plan(input.as_ref().clone(), schema_provider)?,
+                })),
+                Ok(LogicalPlan::Ddl(DdlStatement::CreateMemoryTable(CreateMemoryTable {
                     name,
                     input,
                     ..
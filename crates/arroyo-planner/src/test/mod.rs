@@ -5,10 +5,12 @@ use arroyo_connectors::{
     nexmark::{NexmarkConnector, NexmarkTable},
     EmptyConfig,
 };
+use arroyo_datastream::logical::ModelConfig;
 use arroyo_operator::connector::Connector;
-use arroyo_udf_host::parse::NullableType;
+use arroyo_udf_host::parse::{NullableType, UdfType};
 use test_log::test;
 
+use crate::introspection::introspect;
 use crate::{parse_and_get_program, ArroyoSchemaProvider, SqlConfig};
 
 fn get_test_schema_provider() -> ArroyoSchemaProvider {
@@ -58,3 +60,273 @@ async fn test_udf() {
         .await
         .unwrap();
 }
+
+#[test(tokio::test)]
+async fn test_vectorized_udf() {
+    let mut schema_provider = get_test_schema_provider();
+
+    schema_provider
+        .add_rust_udf(
+            "#[udf] fn my_double(x: &Int64Array) -> Int64Array { x.unary(|x| x * 2) }",
+            "",
+        )
+        .unwrap();
+
+    let def = schema_provider.udf_defs.get("my_double").unwrap();
+    assert_eq!(def.udf_type, UdfType::Vectorized);
+    assert_eq!(def.args[0], NullableType::null(DataType::Int64));
+
+    let sql = "SELECT my_double(bid.auction) FROM nexmark";
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[test(tokio::test)]
+async fn test_js_udf() {
+    let mut schema_provider = get_test_schema_provider();
+
+    schema_provider
+        .add_js_udf(
+            "export function describe_bid(auction: bigint, url: string | null): string { \
+                return `${auction}@${url ?? ''}`; \
+            }",
+        )
+        .unwrap();
+
+    let def = schema_provider.udf_defs.get("describe_bid").unwrap();
+    assert_eq!(def.ret, NullableType::not_null(DataType::Utf8));
+    assert_eq!(def.args[1], NullableType::null(DataType::Utf8));
+
+    let sql = "SELECT describe_bid(bid.auction, bid.url) FROM nexmark";
+    let compiled = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    assert!(compiled
+        .program
+        .program_config
+        .js_udfs
+        .contains_key("describe_bid"));
+}
+
+#[test(tokio::test)]
+async fn test_model() {
+    let mut schema_provider = get_test_schema_provider();
+
+    let model = ModelConfig {
+        url: "file:///tmp/models/bid_fraud.onnx".to_string(),
+        version: 2,
+        inputs: 2,
+        outputs: 1,
+    };
+    schema_provider
+        .add_model("bid_fraud", model.clone())
+        .unwrap();
+
+    // integer features are cast to doubles
+    let sql = "SELECT bid.auction, bid_fraud(bid.price, bid.bidder) as score FROM nexmark \
+        WHERE bid_fraud(bid.price, bid.bidder) > 0.9";
+    let compiled = parse_and_get_program(sql, schema_provider.clone(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        compiled.program.program_config.models.get("bid_fraud"),
+        Some(&model)
+    );
+
+    let sql = "SELECT bid_fraud(bid.price) FROM nexmark";
+    assert!(
+        parse_and_get_program(sql, schema_provider, SqlConfig::default())
+            .await
+            .is_err()
+    );
+}
+
+#[test(tokio::test)]
+async fn test_introspection() {
+    use arrow::array::AsArray;
+
+    let column = |batch: &arrow::array::RecordBatch, i: usize| -> Vec<String> {
+        batch
+            .column(i)
+            .as_string::<i32>()
+            .iter()
+            .map(|v| v.unwrap_or_default().to_string())
+            .collect()
+    };
+
+    let mut schema_provider = get_test_schema_provider();
+    schema_provider
+        .add_catalog_table("CREATE TABLE orders (id BIGINT NOT NULL, amount DOUBLE)")
+        .unwrap();
+
+    let tables = introspect(
+        "CREATE TABLE counts (id BIGINT, count BIGINT); SHOW TABLES",
+        schema_provider.clone(),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(column(&tables, 0), vec!["counts", "nexmark", "orders"]);
+    assert_eq!(column(&tables, 1), vec!["TABLE", "SOURCE", "TABLE"]);
+
+    let columns = introspect("DESCRIBE ORDERS", schema_provider.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(column(&columns, 0), vec!["id", "amount"]);
+    assert_eq!(column(&columns, 2), vec!["NO", "YES"]);
+
+    let counts = introspect(
+        "SELECT count(*) AS n FROM information_schema.columns WHERE table_name = 'orders'",
+        schema_provider.clone(),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(counts.num_rows(), 1);
+
+    // queries over other tables aren't introspection
+    assert!(introspect("SELECT * FROM nexmark", schema_provider.clone())
+        .await
+        .unwrap()
+        .is_none());
+
+    assert!(introspect("DESCRIBE missing", schema_provider.clone())
+        .await
+        .is_err());
+
+    // and introspection can't be part of a pipeline
+    assert!(parse_and_get_program(
+        "SHOW TABLES; SELECT * FROM nexmark",
+        schema_provider,
+        SqlConfig::default()
+    )
+    .await
+    .is_err());
+}
+
+#[test(tokio::test)]
+async fn test_statement_set_shares_subplans() {
+    use arroyo_datastream::logical::OperatorName;
+
+    let sql = tokio::fs::read_to_string("src/test/queries/statement_set_shared_aggregate.sql")
+        .await
+        .unwrap();
+    let compiled = parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let count = |name: OperatorName| {
+        compiled
+            .program
+            .graph
+            .node_weights()
+            .filter(|n| n.operator_name == name)
+            .count()
+    };
+
+    // both statements read from the same aggregate, but each writes to its own sink
+    assert_eq!(count(OperatorName::TumblingWindowAggregate), 1);
+    assert_eq!(count(OperatorName::ConnectorSink), 2);
+}
+
+#[test(tokio::test)]
+async fn test_exchange_hints() {
+    use arroyo_datastream::logical::{EdgePartitioning, LogicalEdgeType, OperatorName};
+    use petgraph::visit::EdgeRef;
+
+    let sql = tokio::fs::read_to_string("src/test/queries/exchange_hints.sql")
+        .await
+        .unwrap();
+    let compiled = parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+    let graph = &compiled.program.graph;
+
+    // the source's output is rebalanced rather than forwarded
+    let source_edges: Vec<_> = graph
+        .edge_references()
+        .filter(|e| graph[e.source()].operator_name == OperatorName::ConnectorSource)
+        .collect();
+    assert!(!source_edges.is_empty());
+    for edge in source_edges {
+        assert_eq!(edge.weight().edge_type, LogicalEdgeType::Shuffle);
+        assert_eq!(edge.weight().partitioning, EdgePartitioning::Rebalance);
+    }
+
+    // the aggregate's input isn't already partitioned by its key, so it's still shuffled
+    assert!(graph.edge_references().any(|e| {
+        e.weight().edge_type == LogicalEdgeType::Shuffle
+            && e.weight().partitioning == EdgePartitioning::Hash
+    }));
+}
+
+#[test(tokio::test)]
+async fn test_parallelism_hints() {
+    use arroyo_datastream::logical::{LogicalEdgeType, OperatorName};
+    use petgraph::visit::EdgeRef;
+
+    let sql = tokio::fs::read_to_string("src/test/queries/parallelism_hints.sql")
+        .await
+        .unwrap();
+    let compiled = parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+    let graph = &compiled.program.graph;
+
+    for node in graph.node_weights() {
+        let expected = match node.operator_name {
+            OperatorName::TumblingWindowAggregate => 8,
+            OperatorName::ConnectorSink => 2,
+            _ => SqlConfig::default().default_parallelism,
+        };
+        assert_eq!(node.parallelism, expected, "{}", node.operator_id);
+        assert_eq!(
+            compiled.parallelism_overrides.get(&node.operator_id),
+            (expected != SqlConfig::default().default_parallelism).then_some(&expected)
+        );
+    }
+
+    // no forward edge connects operators whose parallelism was hinted differently
+    for edge in graph.edge_references() {
+        if edge.weight().edge_type == LogicalEdgeType::Forward {
+            assert_eq!(
+                compiled
+                    .parallelism_overrides
+                    .get(&graph[edge.source()].operator_id),
+                compiled
+                    .parallelism_overrides
+                    .get(&graph[edge.target()].operator_id),
+            );
+        }
+    }
+}
+
+#[test(tokio::test)]
+async fn test_explain_shared_view() {
+    use arrow::array::AsArray;
+
+    let sql = tokio::fs::read_to_string("src/test/queries/view_shared_by_statements.sql")
+        .await
+        .unwrap();
+    let (statements, last) = sql.trim().trim_end_matches(';').rsplit_once(';').unwrap();
+    let explained = format!("{};\nEXPLAIN {}", statements, last.trim());
+
+    let batch = introspect(&explained, get_test_schema_provider())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let operators = batch.column(1).as_string::<i32>();
+    let outputs = batch.column(5).as_string::<i32>();
+    let aggregates: Vec<_> = (0..batch.num_rows())
+        .filter(|i| operators.value(*i) == "TumblingWindowAggregate")
+        .collect();
+
+    // the view's aggregate is computed once, and feeds both statements
+    assert_eq!(aggregates.len(), 1);
+    assert_eq!(outputs.value(aggregates[0]).split(", ").count(), 2);
+}
@@ -0,0 +1,15 @@
+CREATE TABLE impulse with (
+    connector = 'impulse',
+    event_rate = '100'
+);
+
+CREATE TABLE audit (
+    subtask_index BIGINT UNSIGNED NOT NULL,
+    counter BIGINT UNSIGNED NOT NULL
+) with (
+    connector = 'audit',
+    report_path = '/tmp/arroyo/audit'
+);
+
+INSERT INTO audit
+SELECT subtask_index, counter FROM impulse;
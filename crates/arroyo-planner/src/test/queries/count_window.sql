@@ -0,0 +1,16 @@
+CREATE TABLE Nexmark WITH (
+    connector = 'nexmark',
+    event_rate = '10'
+);
+
+SELECT
+    bid.auction as auction,
+    count_hop(5, 10) as window,
+    avg(bid.price) as avg_price
+FROM
+    nexmark
+where
+    bid is not null
+GROUP BY
+    1,
+    2
@@ -0,0 +1,15 @@
+--fail=must be a multiple of step
+CREATE TABLE Nexmark WITH (
+    connector = 'nexmark',
+    event_rate = '10'
+);
+
+SELECT
+    cumulate(interval '7 minutes', interval '1 hour') as window,
+    count(*) as bids
+FROM
+    nexmark
+where
+    bid is not null
+GROUP BY
+    1
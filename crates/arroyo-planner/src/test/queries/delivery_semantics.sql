@@ -0,0 +1,30 @@
+CREATE TABLE nexmark (
+    auction bigint,
+    bidder bigint,
+    price bigint,
+    channel text,
+    url  text,
+    datetime timestamp,
+    extra text,
+) WITH (
+    connector = 'filesystem',
+    format = 'parquet',
+    type = 'source',
+    path = '/home/data',
+    'source.regex-pattern' = '00001-000.parquet',
+    event_time_field = 'datetime'
+);
+
+CREATE TABLE bids (
+    auction bigint,
+    price bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    format = 'json',
+    type = 'sink',
+    topic = 'bids',
+    delivery_semantics = 'at_most_once'
+);
+
+INSERT INTO bids SELECT auction, price FROM nexmark;
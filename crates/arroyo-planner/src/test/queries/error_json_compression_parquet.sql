@@ -0,0 +1,30 @@
+--fail=json_compression only applies to json files
+CREATE TABLE nexmark (
+    auction bigint,
+    bidder bigint,
+    price bigint,
+    channel text,
+    url  text,
+    datetime timestamp,
+    extra text,
+) WITH (
+    connector = 'filesystem',
+    format = 'parquet',
+    type = 'source',
+    path = '/home/data',
+    'source.regex-pattern' = '00001-000.parquet',
+    event_time_field = 'datetime'
+);
+
+CREATE TABLE bids_out (
+    auction bigint,
+    price bigint
+) WITH (
+    connector = 'filesystem',
+    format = 'parquet',
+    type = 'sink',
+    path = '/home/data/out',
+    json_compression = 'gzip'
+);
+
+INSERT INTO bids_out SELECT auction, price FROM nexmark;
@@ -0,0 +1,18 @@
+--fail=partition_watermarks can't be used with event_time_field or watermark_field
+CREATE TABLE orders (
+    customer_id bigint,
+    amount bigint,
+    created_at timestamp
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    format = 'json',
+    type = 'source',
+    topic = 'orders',
+    event_time_field = 'created_at',
+    partition_watermarks = 'true'
+);
+
+SELECT customer_id, sum(amount)
+FROM orders
+GROUP BY customer_id, tumble(interval '1 minute');
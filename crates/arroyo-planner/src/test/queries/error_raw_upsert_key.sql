@@ -0,0 +1,30 @@
+--fail=with a raw format, upsert_key must be a single field of type Utf8
+CREATE TABLE nexmark (
+    auction bigint,
+    bidder bigint,
+    price bigint,
+    channel text,
+    url  text,
+    datetime timestamp,
+    extra text,
+) WITH (
+    connector = 'filesystem',
+    format = 'parquet',
+    type = 'source',
+    path = '/home/data',
+    'source.regex-pattern' = '00001-000.parquet',
+    event_time_field = 'datetime'
+);
+
+CREATE TABLE channel_counts (
+    value text
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    format = 'raw_string',
+    type = 'sink',
+    topic = 'channel_counts',
+    upsert_key = 'value,value'
+);
+
+INSERT INTO channel_counts SELECT cast(count(*) as text) as value FROM nexmark GROUP BY channel;
@@ -0,0 +1,8 @@
+--fail=cannot be called on an updating input
+SELECT running_average(auction, bids)
+FROM (
+  SELECT bid.auction as auction, count(*) as bids
+  FROM nexmark
+  WHERE bid is not null
+  GROUP BY 1
+);
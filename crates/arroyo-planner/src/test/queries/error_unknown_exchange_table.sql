@@ -0,0 +1,8 @@
+--fail=planner hint REBALANCE refers to 'bids', which isn't a source table of the query
+/*+ REBALANCE(bids) */
+CREATE TABLE impulse with (
+    connector = 'impulse',
+    event_rate = '100'
+);
+
+SELECT count(*) FROM impulse GROUP BY tumble(interval '10 seconds');
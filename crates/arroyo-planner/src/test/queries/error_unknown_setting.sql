@@ -0,0 +1,9 @@
+--fail=unknown setting 'batch_size'
+SET batch_size = 100;
+
+CREATE TABLE nexmark with (
+    connector = 'nexmark',
+    event_rate = '10'
+);
+
+SELECT bid.auction FROM nexmark WHERE bid is not null;
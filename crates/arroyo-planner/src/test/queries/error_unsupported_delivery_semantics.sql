@@ -0,0 +1,29 @@
+--fail=the webhook connector does not support exactly_once delivery
+CREATE TABLE nexmark (
+    auction bigint,
+    bidder bigint,
+    price bigint,
+    channel text,
+    url  text,
+    datetime timestamp,
+    extra text,
+) WITH (
+    connector = 'filesystem',
+    format = 'parquet',
+    type = 'source',
+    path = '/home/data',
+    'source.regex-pattern' = '00001-000.parquet',
+    event_time_field = 'datetime'
+);
+
+CREATE TABLE bids (
+    auction bigint,
+    price bigint
+) WITH (
+    connector = 'webhook',
+    endpoint = 'http://localhost:8080/bids',
+    format = 'json',
+    delivery_semantics = 'exactly_once'
+);
+
+INSERT INTO bids SELECT auction, price FROM nexmark;
@@ -0,0 +1,18 @@
+CREATE TABLE impulse with (
+    connector = 'impulse',
+    event_rate = '100'
+);
+
+CREATE VIEW locations AS
+SELECT
+    counter,
+    ST_Point(CAST(counter % 360 AS DOUBLE) - 180.0, CAST(counter % 180 AS DOUBLE) - 90.0) as location
+FROM impulse;
+
+SELECT
+    counter,
+    ST_Distance(location, 'POINT(-0.1278 51.5074)') as distance_from_london,
+    geohash_encode(CAST(counter % 360 AS DOUBLE) - 180.0, 0.0, 6) as cell,
+    geohash_decode('gcpvj0d') as cell_center
+FROM locations
+WHERE ST_Contains('POLYGON((-10 35, 30 35, 30 70, -10 70, -10 35))', location);
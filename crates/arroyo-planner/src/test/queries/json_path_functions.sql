@@ -0,0 +1,16 @@
+CREATE TABLE impulse with (
+    connector = 'impulse',
+    event_rate = '100'
+);
+
+CREATE VIEW events AS
+SELECT '{"id": ' || CAST(counter AS TEXT) || ', "tags": ["a", "b"], "user": {"name": "x"}}' as body
+FROM impulse;
+
+SELECT
+    json_extract(body, '$.user') as user,
+    json_extract_string(body, '$.id') as id,
+    json_query(body, '$.tags[*]') as tags,
+    json_exists(body, '$.user.name') as has_name
+FROM events
+WHERE json_exists(body, '$.tags');
@@ -0,0 +1,16 @@
+CREATE TABLE orders (
+    customer_id bigint,
+    amount bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    format = 'json',
+    type = 'source',
+    topic = 'orders',
+    partition_watermarks = 'true',
+    partition_watermark_lateness_micros = '5000000'
+);
+
+SELECT customer_id, sum(amount)
+FROM orders
+GROUP BY customer_id, tumble(interval '1 minute');
@@ -0,0 +1,34 @@
+CREATE TABLE nexmark (
+    auction bigint,
+    bidder bigint,
+    price bigint,
+    channel text,
+    url  text,
+    datetime timestamp,
+    extra text,
+) WITH (
+    connector = 'filesystem',
+    format = 'parquet',
+    type = 'source',
+    path = '/home/data',
+    'source.regex-pattern' = '00001-000.parquet',
+    event_time_field = 'datetime'
+);
+
+CREATE TABLE bids (
+    auction bigint,
+    bidder bigint,
+    price bigint,
+    channel text
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    format = 'json',
+    type = 'sink',
+    topic = 'bids',
+    key_expression = 'concat(channel, ''-'', bidder)',
+    partition_expression = 'auction % 12',
+    'sink.sticky_partitioning' = 'true'
+);
+
+INSERT INTO bids SELECT auction, bidder, price, channel FROM nexmark;
@@ -0,0 +1,17 @@
+/*+ PARALLELISM(aggregate = 8, counts = 2) */
+CREATE TABLE impulse with (
+    connector = 'impulse',
+    event_rate = '100'
+);
+
+CREATE TABLE counts (
+    subtask_index BIGINT UNSIGNED NOT NULL,
+    count BIGINT NOT NULL
+) with (
+    connector = 'blackhole'
+);
+
+INSERT INTO counts
+SELECT subtask_index, count(*) as count
+FROM impulse
+GROUP BY subtask_index, tumble(interval '10 seconds');
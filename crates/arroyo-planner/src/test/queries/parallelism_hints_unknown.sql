@@ -0,0 +1,8 @@
+--fail=planner hint PARALLELISM refers to 'nonexistent'
+/*+ PARALLELISM(nonexistent = 4) */
+CREATE TABLE impulse with (
+    connector = 'impulse',
+    event_rate = '100'
+);
+
+SELECT count(*) FROM impulse GROUP BY tumble(interval '10 seconds');
@@ -0,0 +1,16 @@
+CREATE TABLE Nexmark WITH (
+    connector = 'nexmark',
+    event_rate = '10'
+);
+
+SELECT
+    bid.auction as auction,
+    proctime_hop(INTERVAL '2' second, INTERVAL '10' second) as window,
+    count(*) as count
+FROM
+    nexmark
+where
+    bid is not null
+GROUP BY
+    1,
+    2
\ No newline at end of file
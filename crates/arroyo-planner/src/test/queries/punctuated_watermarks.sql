@@ -0,0 +1,19 @@
+CREATE TABLE events (
+    event_type text,
+    value bigint,
+    created_at timestamp,
+    sent_through timestamp
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    format = 'json',
+    type = 'source',
+    topic = 'events',
+    event_time_field = 'created_at',
+    watermark_punctuation_field = 'sent_through'
+);
+
+SELECT event_type, sum(value)
+FROM events
+WHERE sent_through IS NULL
+GROUP BY event_type, tumble(interval '1 minute');
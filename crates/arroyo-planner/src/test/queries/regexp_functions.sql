@@ -0,0 +1,16 @@
+CREATE TABLE impulse with (
+    connector = 'impulse',
+    event_rate = '100'
+);
+
+CREATE VIEW logs AS
+SELECT 'GET /users/' || CAST(counter AS TEXT) || ' 200' as line
+FROM impulse;
+
+SELECT
+    regexp_match(line, '^(\w+) (\S+)') as parts,
+    regexp_extract(line, '^(\w+) (\S+) (\d+)$', 3) as status,
+    regexp_extract(line, '/users/(\d+)') as user_id,
+    regexp_replace(line, '\d+', 'N', 'g') as normalized
+FROM logs
+WHERE regexp_like(line, '^get ', 'i');
@@ -0,0 +1,21 @@
+SET parallelism = 8;
+SET checkpoint_interval = '30s';
+SET sink.semantics = 'at_least_once';
+
+CREATE TABLE nexmark with (
+    connector = 'nexmark',
+    event_rate = '10'
+);
+
+CREATE TABLE bids (
+    auction bigint,
+    price bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    format = 'json',
+    type = 'sink',
+    topic = 'bids'
+);
+
+INSERT INTO bids SELECT bid.auction, bid.price FROM nexmark WHERE bid is not null;
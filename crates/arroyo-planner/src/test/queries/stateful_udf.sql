@@ -0,0 +1,3 @@
+SELECT bid.auction, running_average(bid.auction, bid.price) + 1 as average_price
+FROM nexmark
+WHERE bid is not null;
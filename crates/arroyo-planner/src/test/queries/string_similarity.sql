@@ -0,0 +1,18 @@
+CREATE TABLE impulse with (
+    connector = 'impulse',
+    event_rate = '100'
+);
+
+CREATE VIEW names AS
+SELECT 'Jon' || CAST(counter % 10 AS TEXT) as name
+FROM impulse;
+
+SELECT
+    name,
+    levenshtein(name, 'Jonathan') as distance,
+    jaro_winkler(name, 'Jonathan') as jw,
+    soundex(name) as code,
+    ngram_similarity(name, 'Jonathan') as trigram,
+    ngram_similarity(name, 'Jonathan', 2) as bigram
+FROM names
+WHERE jaro_winkler(lower(name), 'jon') > 0.8;
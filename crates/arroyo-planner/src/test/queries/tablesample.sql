@@ -0,0 +1,34 @@
+CREATE TABLE impulse with (
+    connector = 'impulse',
+    event_rate = '100'
+);
+
+CREATE TABLE full_counts (
+    subtask_index BIGINT,
+    count BIGINT
+) WITH (
+    connector = 'blackhole'
+);
+
+CREATE TABLE sampled_counts (
+    subtask_index BIGINT,
+    count BIGINT
+) WITH (
+    connector = 'blackhole'
+);
+
+INSERT INTO full_counts
+SELECT subtask_index, count(*)
+FROM impulse
+GROUP BY subtask_index, tumble(interval '1 minute');
+
+INSERT INTO sampled_counts
+SELECT i.subtask_index, count(*)
+FROM impulse AS i TABLESAMPLE BERNOULLI(5)
+GROUP BY i.subtask_index, tumble(interval '1 minute');
+
+INSERT INTO sampled_counts
+SELECT subtask_index, count(*)
+FROM impulse TABLESAMPLE HASH(1.5, counter % 1000)
+WHERE hash_sample(50, subtask_index)
+GROUP BY subtask_index, tumble(interval '1 minute');
@@ -0,0 +1,30 @@
+CREATE TABLE nexmark (
+    auction bigint,
+    bidder bigint,
+    price bigint,
+    channel text,
+    url  text,
+    datetime timestamp,
+    extra text,
+) WITH (
+    connector = 'filesystem',
+    format = 'parquet',
+    type = 'source',
+    path = '/home/data',
+    'source.regex-pattern' = '00001-000.parquet',
+    event_time_field = 'datetime'
+);
+
+CREATE TABLE bid_counts (
+    bidder bigint,
+    bids bigint
+) WITH (
+    connector = 'kafka',
+    bootstrap_servers = 'localhost:9092',
+    format = 'json',
+    type = 'sink',
+    topic = 'bid_counts',
+    upsert_key = 'bidder'
+);
+
+INSERT INTO bid_counts SELECT bidder, count(*) as bids FROM nexmark GROUP BY bidder;
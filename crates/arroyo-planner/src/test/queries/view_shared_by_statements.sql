@@ -0,0 +1,29 @@
+CREATE TABLE impulse with (
+    connector = 'impulse',
+    event_rate = '100'
+);
+
+CREATE TABLE counts (
+    subtask_index BIGINT UNSIGNED NOT NULL,
+    count BIGINT NOT NULL
+) with (
+    connector = 'blackhole'
+);
+
+CREATE TABLE large_counts (
+    subtask_index BIGINT UNSIGNED NOT NULL,
+    count BIGINT NOT NULL
+) with (
+    connector = 'blackhole'
+);
+
+CREATE VIEW impulse_counts AS
+SELECT subtask_index, count(*) as count
+FROM impulse
+GROUP BY subtask_index, tumble(interval '1 second');
+
+INSERT INTO counts
+SELECT subtask_index, count FROM impulse_counts;
+
+INSERT INTO large_counts
+SELECT subtask_index, count FROM impulse_counts WHERE count > 10;
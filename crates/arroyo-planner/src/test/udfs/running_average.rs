@@ -0,0 +1,19 @@
+/*
+[dependencies]
+serde = { version = "1", features = ["derive"] }
+*/
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Average {
+    count: u64,
+    sum: i64,
+}
+
+#[udf]
+fn running_average(average: &mut Average, _auction: i64, value: i64) -> f64 {
+    average.count += 1;
+    average.sum += value;
+    average.sum as f64 / average.count as f64
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// Roles are ordered by privilege; each role can do everything the roles below it can
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    /// Read-only access to pipelines, jobs, connections, and UDFs
+    Viewer,
+    /// Can additionally create, update, and delete pipelines, connections, and UDFs
+    Editor,
+    /// Can additionally manage namespaces and API keys
+    Admin,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("invalid role '{}'", s)),
+        }
+    }
+}
+
+/// Restricts an API key to a subset of the organization's resources. An unset field
+/// places no restriction on that kind of resource.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyScope {
+    /// Pipeline ids the key may access
+    pub pipelines: Option<Vec<String>>,
+    /// Namespaces whose pipelines the key may access
+    pub namespaces: Option<Vec<String>>,
+}
+
+impl ApiKeyScope {
+    pub fn is_unrestricted(&self) -> bool {
+        self.pipelines.is_none() && self.namespaces.is_none()
+    }
+
+    pub fn allows_pipeline(&self, pipeline_id: &str, namespace: &str) -> bool {
+        self.pipelines
+            .as_ref()
+            .map(|p| p.iter().any(|p| p == pipeline_id))
+            .unwrap_or(true)
+            && self
+                .namespaces
+                .as_ref()
+                .map(|n| n.iter().any(|n| n == namespace))
+                .unwrap_or(true)
+    }
+
+    pub fn allows_namespace(&self, namespace: &str) -> bool {
+        self.pipelines.is_none()
+            && self
+                .namespaces
+                .as_ref()
+                .map(|n| n.iter().any(|n| n == namespace))
+                .unwrap_or(true)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyPost {
+    pub name: String,
+    pub role: Role,
+    pub scope: Option<ApiKeyScope>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub role: Role,
+    pub scope: ApiKeyScope,
+    pub created_at: u64,
+    /// The secret token for the key; only returned when the key is created
+    pub key: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_ordering() {
+        assert!(Role::Admin > Role::Editor);
+        assert!(Role::Editor > Role::Viewer);
+        assert_eq!(Role::from_str(&Role::Editor.to_string()), Ok(Role::Editor));
+    }
+
+    #[test]
+    fn test_scope() {
+        let unrestricted = ApiKeyScope::default();
+        assert!(unrestricted.allows_pipeline("pl_1", "default"));
+        assert!(unrestricted.allows_namespace("prod"));
+
+        let by_namespace = ApiKeyScope {
+            pipelines: None,
+            namespaces: Some(vec!["prod".to_string()]),
+        };
+        assert!(by_namespace.allows_pipeline("pl_1", "prod"));
+        assert!(!by_namespace.allows_pipeline("pl_1", "default"));
+        assert!(by_namespace.allows_namespace("prod"));
+        assert!(!by_namespace.allows_namespace("default"));
+
+        let by_pipeline = ApiKeyScope {
+            pipelines: Some(vec!["pl_1".to_string()]),
+            namespaces: None,
+        };
+        assert!(by_pipeline.allows_pipeline("pl_1", "default"));
+        assert!(!by_pipeline.allows_pipeline("pl_2", "default"));
+        assert!(!by_pipeline.allows_namespace("default"));
+    }
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(
+    Serialize, Deserialize, Copy, Clone, Debug, ToSchema, PartialEq, Eq, Display, EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Start,
+    Stop,
+    Restart,
+    Delete,
+}
+
+#[derive(
+    Serialize, Deserialize, Copy, Clone, Debug, ToSchema, PartialEq, Eq, Display, EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditResourceType {
+    Pipeline,
+    Udf,
+    ConnectionProfile,
+    ConnectionTable,
+    CatalogTable,
+    PipelineTemplate,
+    NotificationTarget,
+    Model,
+}
+
+/// A record of a change made to a resource through the API
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogEntry {
+    pub id: String,
+    pub created_at: u64,
+    /// The user (or API key owner) that made the change
+    pub user_id: String,
+    pub action: AuditAction,
+    pub resource_type: AuditResourceType,
+    pub resource_id: String,
+    /// The fields of the resource that changed, as `{"field": {"before": .., "after": ..}}`;
+    /// sensitive connection config values are redacted
+    pub diff: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "snake_case")]
+pub struct AuditLogQueryParams {
+    pub starting_after: Option<String>,
+    pub limit: Option<u32>,
+    pub resource_type: Option<AuditResourceType>,
+    pub resource_id: Option<String>,
+}
@@ -0,0 +1,163 @@
+use crate::api_types::connections::ConnectionSchema;
+use crate::api_types::pipelines::{
+    FreshnessSlo, PipelineBatching, RestartStrategy, WorkerPodConfig,
+};
+use crate::api_types::udfs::Udf;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+
+/// A declarative description of pipelines and the resources they depend on, which can be exported
+/// from a cluster, checked into version control, and applied to make a cluster match it.
+/// Resources are identified by name.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineBundle {
+    /// The version of the bundle format
+    pub version: u32,
+    #[serde(default)]
+    pub connection_profiles: Vec<ConnectionProfileSpec>,
+    #[serde(default)]
+    pub connection_tables: Vec<ConnectionTableSpec>,
+    /// Global UDFs
+    #[serde(default)]
+    pub udfs: Vec<GlobalUdfSpec>,
+    #[serde(default)]
+    pub pipelines: Vec<PipelineSpec>,
+}
+
+impl PipelineBundle {
+    pub const VERSION: u32 = 1;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionProfileSpec {
+    pub name: String,
+    pub connector: String,
+    /// Secrets should be referenced rather than included, as bundles are stored in plain text
+    pub config: serde_json::Value,
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionTableSpec {
+    pub name: String,
+    pub connector: String,
+    /// The name of the connection profile the table uses
+    pub connection_profile: Option<String>,
+    pub config: serde_json::Value,
+    pub schema: Option<ConnectionSchema>,
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalUdfSpec {
+    /// The name of the UDF, which must match the function in its definition
+    pub name: String,
+    pub prefix: String,
+    pub definition: String,
+    pub description: Option<String>,
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineSpec {
+    pub name: String,
+    pub query: String,
+    #[serde(default)]
+    pub udfs: Vec<Udf>,
+    /// Versions of global UDFs to use, by name
+    #[serde(default)]
+    pub udf_versions: BTreeMap<String, i32>,
+    pub parallelism: u64,
+    pub checkpoint_interval_micros: Option<u64>,
+    pub namespace: Option<String>,
+    pub priority: Option<i32>,
+    pub worker_pod: Option<WorkerPodConfig>,
+    pub freshness_slo: Option<FreshnessSlo>,
+    pub restart_strategy: Option<RestartStrategy>,
+    pub batching: Option<PipelineBatching>,
+    #[serde(default)]
+    pub resource_classes: BTreeMap<String, String>,
+    /// Whether the pipeline should be stopped rather than running
+    #[serde(default)]
+    pub stopped: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "snake_case")]
+pub struct BundleExportQueryParams {
+    /// Only export this pipeline, along with the resources it uses
+    pub pipeline_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "snake_case")]
+pub struct BundleApplyQueryParams {
+    /// Only report the changes that would be made
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Delete pipelines that aren't in the bundle, within the namespaces of its pipelines
+    #[serde(default)]
+    pub prune: bool,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum BundleResourceType {
+    ConnectionProfile,
+    ConnectionTable,
+    Udf,
+    Pipeline,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum BundleAction {
+    Create,
+    /// Updated in place; pipelines keep running with their state
+    Update,
+    /// A pipeline whose query changed, which is replaced by a new pipeline that starts from the
+    /// state of the old one's last checkpoint
+    Replace,
+    Delete,
+    Unchanged,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleChange {
+    pub resource_type: BundleResourceType,
+    pub name: String,
+    pub action: BundleAction,
+    /// The id of the resource, once it exists
+    pub id: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum BundleApplyStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleApplyResult {
+    /// The id to poll the apply's status with; dry runs complete with the request, so have none
+    pub id: Option<String>,
+    pub dry_run: bool,
+    pub status: BundleApplyStatus,
+    /// Why the apply failed; changes made before the failure are kept
+    pub error: Option<String>,
+    /// The changes made so far, or that would be made for a dry run
+    pub changes: Vec<BundleChange>,
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogTablePost {
+    /// A single `CREATE TABLE` or `CREATE VIEW` statement; the table is referenced by the name
+    /// it defines
+    pub definition: String,
+    pub description: Option<String>,
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogTable {
+    pub id: String,
+    pub name: String,
+    pub definition: String,
+    pub description: Option<String>,
+    pub namespace: String,
+    /// The latest published version of the table
+    pub version: i32,
+    /// The number of pipelines that read the table
+    pub pipeline_count: u32,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogTableVersionPost {
+    /// The new definition of the table, which must keep its name
+    pub definition: String,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogTableVersion {
+    pub version: i32,
+    pub created_at: u64,
+    pub definition: String,
+    pub description: Option<String>,
+}
+
+/// A pipeline that reads a catalog table
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogTablePipeline {
+    pub pipeline_id: String,
+    pub pipeline_name: String,
+    /// The version of the table the pipeline was planned with
+    pub version: i32,
+}
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -43,3 +44,75 @@ pub struct OperatorCheckpointGroup {
     pub bytes: u64,
     pub subtasks: Vec<SubtaskCheckpointGroup>,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StateTable {
+    pub name: String,
+    pub table_type: String,
+    pub description: String,
+    pub files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorState {
+    pub operator_id: String,
+    pub tables: Vec<StateTable>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StateDumpFormat {
+    /// One JSON object per row, newline-delimited
+    #[default]
+    Json,
+    Parquet,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "snake_case")]
+pub struct StateTableQueryParams {
+    pub format: Option<StateDumpFormat>,
+    /// Only return the rows for this key, given as the values of the table's key columns joined
+    /// with commas
+    pub key: Option<String>,
+}
+
+/// Moves the state of operator `from` of the old pipeline to operator `to` of the new one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorStateMapping {
+    pub from: String,
+    pub to: String,
+    /// Renames of the operator's state tables, from old name to new name; other tables keep
+    /// their names
+    #[serde(default)]
+    pub tables: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StateMigrationPost {
+    pub source_job_id: String,
+    pub source_epoch: u32,
+    /// Explicit mappings between operators; operators that aren't listed are matched with
+    /// operators of the same type and description in the new pipeline, where that's unambiguous
+    #[serde(default)]
+    pub mappings: Vec<OperatorStateMapping>,
+    /// Only report how operators would be matched, without writing a checkpoint
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StateMigration {
+    /// The epoch of the checkpoint that was written, which the job will restore from when it's
+    /// next started; unset for dry runs
+    pub epoch: Option<u32>,
+    pub mappings: Vec<OperatorStateMapping>,
+    /// Stateful operators of the old pipeline whose state isn't carried over
+    pub orphaned: Vec<String>,
+}
@@ -36,6 +36,7 @@ pub struct ConnectionProfile {
     pub connector: String,
     pub config: serde_json::Value,
     pub description: String,
+    pub namespace: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -44,6 +45,7 @@ pub struct ConnectionProfilePost {
     pub name: String,
     pub connector: String,
     pub config: serde_json::Value,
+    pub namespace: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema, PartialEq, Eq, Hash)]
@@ -314,6 +316,7 @@ pub struct ConnectionTable {
     pub config: serde_json::Value,
     pub schema: ConnectionSchema,
     pub consumers: u32,
+    pub namespace: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -324,6 +327,7 @@ pub struct ConnectionTablePost {
     pub connection_profile_id: Option<String>,
     pub config: serde_json::Value,
     pub schema: Option<ConnectionSchema>,
+    pub namespace: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -0,0 +1,51 @@
+use crate::grpc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    /// Panics a subtask, which restarts the job from its last checkpoint
+    KillSubtask,
+    /// Delays a subtask writing its state for a checkpoint
+    DelayCheckpoint,
+    /// Drops data batches received over the network from other workers
+    DropNetworkBatch,
+    /// Pauses a source before it emits a batch
+    StallSource,
+}
+
+impl From<FaultKind> for grpc::FaultKind {
+    fn from(kind: FaultKind) -> Self {
+        match kind {
+            FaultKind::KillSubtask => grpc::FaultKind::KillSubtask,
+            FaultKind::DelayCheckpoint => grpc::FaultKind::DelayCheckpoint,
+            FaultKind::DropNetworkBatch => grpc::FaultKind::DropNetworkBatch,
+            FaultKind::StallSource => grpc::FaultKind::StallSource,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobFaultPost {
+    pub kind: FaultKind,
+    /// The operator to inject the fault into (defaults to all operators); not supported for
+    /// drop_network_batch
+    pub operator_id: Option<String>,
+    /// The subtask of the operator to inject the fault into (defaults to all subtasks)
+    pub subtask_index: Option<u32>,
+    /// The probability that the fault fires each time a subtask checks for it (defaults to 1)
+    pub probability: Option<f64>,
+    /// How many times the fault may fire on each worker (defaults to 1)
+    pub count: Option<u32>,
+    /// For delay_checkpoint and stall_source, how long to pause for (defaults to 10 seconds)
+    pub duration_millis: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobFaultInjection {
+    /// The number of workers the fault was injected into
+    pub workers: u32,
+}
@@ -1,9 +1,20 @@
 use serde::{Deserialize, Serialize};
-use strum_macros::{EnumCount, EnumString};
-use utoipa::ToSchema;
+use strum_macros::{Display, EnumCount, EnumString};
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(
-    Serialize, Deserialize, Copy, Clone, Debug, ToSchema, Hash, PartialEq, Eq, EnumCount, EnumString,
+    Serialize,
+    Deserialize,
+    Copy,
+    Clone,
+    Debug,
+    ToSchema,
+    Hash,
+    PartialEq,
+    Eq,
+    EnumCount,
+    EnumString,
+    Display,
 )]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
@@ -15,6 +26,16 @@ pub enum MetricName {
     Backpressure,
     TxQueueSize,
     TxQueueRem,
+    WatermarkLagMs,
+    WatermarkMs,
+    /// Rate of messages sent by a keyed exchange to each downstream subtask
+    PartitionMessagesSent,
+    /// Rate of bytes sent by a keyed exchange to each downstream subtask
+    PartitionBytesSent,
+    /// Number of records in each partition of a source that have not yet been read
+    SourcePartitionLag,
+    /// Estimated milliseconds that a source is behind the end of each of its partitions
+    SourcePartitionLagMs,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -43,4 +64,102 @@ pub struct MetricGroup {
 pub struct OperatorMetricGroup {
     pub operator_id: String,
     pub metric_groups: Vec<MetricGroup>,
+    /// For operators whose output is partitioned by key, the ratio of the message rate of the
+    /// busiest downstream subtask to the mean rate across downstream subtasks; 1 means the
+    /// data is evenly distributed
+    pub skew: Option<f64>,
+}
+
+/// What an operator has processed since the job's workers started, summed over its subtasks
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorRuntimeStats {
+    pub operator_id: String,
+    pub rows_received: u64,
+    pub rows_sent: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    /// Seconds spent processing the batches the operator received
+    pub busy_secs: f64,
+}
+
+/// An operator of a job's plan, annotated with what it has processed at runtime
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorAnalysis {
+    pub operator_id: String,
+    pub operator: String,
+    pub description: String,
+    pub parallelism: u32,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// Runtime statistics, if the job is running
+    pub stats: Option<OperatorRuntimeStats>,
+    /// The operator's share of the time spent processing batches across the whole job, from 0
+    /// to 1
+    pub busy_fraction: Option<f64>,
+    /// Size of the operator's state in the latest completed checkpoint
+    pub state_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobAnalysis {
+    /// The checkpoint that state sizes were read from
+    pub checkpoint_epoch: Option<u32>,
+    pub operators: Vec<OperatorAnalysis>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WatermarkSample {
+    pub time: u64,
+    pub watermark: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorWatermarkHistory {
+    pub operator_id: String,
+    pub watermarks: Vec<WatermarkSample>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorMetricHistory {
+    pub operator_id: String,
+    pub metric: MetricName,
+    /// Width in seconds of the interval that each value covers; values at resolutions coarser
+    /// than the controller's sample interval are averages over the interval
+    pub resolution_secs: u64,
+    pub metrics: Vec<Metric>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "snake_case")]
+pub struct MetricHistoryQueryParams {
+    /// Start of the time range, in micros since the epoch (defaults to 24 hours ago)
+    pub start_time: Option<u64>,
+    /// End of the time range, in micros since the epoch (defaults to now)
+    pub end_time: Option<u64>,
+    /// Only return this metric (defaults to all metrics)
+    pub metric: Option<MetricName>,
+    /// Resolution in seconds to read; must be one of the stored resolutions. By default, the
+    /// finest resolution that has been retained for the whole time range and returns at most a
+    /// few hundred values per metric is chosen
+    pub resolution_secs: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "snake_case")]
+pub struct WatermarkHistoryQueryParams {
+    /// Start of the time range, in micros since the epoch (defaults to 24 hours ago)
+    pub start_time: Option<u64>,
+    /// End of the time range, in micros since the epoch (defaults to now)
+    pub end_time: Option<u64>,
+    /// Width in seconds of the buckets that samples are downsampled into; by default this is
+    /// chosen to return a few hundred samples per operator
+    pub resolution_secs: Option<u64>,
 }
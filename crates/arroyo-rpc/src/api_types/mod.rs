@@ -1,16 +1,36 @@
+use api_keys::*;
+use audit_log::*;
+use bundles::*;
+use catalog::*;
 use checkpoints::*;
 use connections::*;
 use metrics::*;
+use models::*;
+use namespaces::*;
+use notifications::*;
 use pipelines::*;
+use profiles::*;
+use templates::*;
 use udfs::*;
 
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 
+pub mod api_keys;
+pub mod audit_log;
+pub mod bundles;
+pub mod catalog;
 pub mod checkpoints;
 pub mod connections;
+pub mod faults;
 pub mod metrics;
+pub mod models;
+pub mod namespaces;
+pub mod notifications;
+pub mod parameters;
 pub mod pipelines;
+pub mod profiles;
+pub mod templates;
 pub mod udfs;
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -19,6 +39,8 @@ pub mod udfs;
     PipelineCollection = PaginatedCollection<Pipeline>,
     JobLogMessageCollection = PaginatedCollection<JobLogMessage>,
     ConnectionTableCollection = PaginatedCollection<ConnectionTable>,
+    AuditLogCollection = PaginatedCollection<AuditLogEntry>,
+    ScheduledRunCollection = PaginatedCollection<ScheduledRun>,
 )]
 pub struct PaginatedCollection<T> {
     pub data: Vec<T>,
@@ -30,11 +52,27 @@ pub struct PaginatedCollection<T> {
 #[aliases(
     JobCollection = NonPaginatedCollection<Job>,
     OperatorCheckpointGroupCollection = NonPaginatedCollection<OperatorCheckpointGroup>,
+    OperatorStateCollection = NonPaginatedCollection<OperatorState>,
     CheckpointCollection = NonPaginatedCollection<Checkpoint>,
     OperatorMetricGroupCollection = NonPaginatedCollection<OperatorMetricGroup>,
+    OperatorWatermarkHistoryCollection = NonPaginatedCollection<OperatorWatermarkHistory>,
+    OperatorMetricHistoryCollection = NonPaginatedCollection<OperatorMetricHistory>,
+    JobRestartCollection = NonPaginatedCollection<JobRestart>,
     ConnectorCollection = NonPaginatedCollection<Connector>,
     ConnectionProfileCollection = NonPaginatedCollection<ConnectionProfile>,
     GlobalUdfCollection = NonPaginatedCollection<GlobalUdf>,
+    UdfVersionCollection = NonPaginatedCollection<UdfVersion>,
+    CatalogTableCollection = NonPaginatedCollection<CatalogTable>,
+    CatalogTableVersionCollection = NonPaginatedCollection<CatalogTableVersion>,
+    CatalogTablePipelineCollection = NonPaginatedCollection<CatalogTablePipeline>,
+    ModelCollection = NonPaginatedCollection<Model>,
+    ModelVersionCollection = NonPaginatedCollection<ModelVersion>,
+    PipelineTemplateCollection = NonPaginatedCollection<PipelineTemplate>,
+    PipelineTemplateInstanceCollection = NonPaginatedCollection<PipelineTemplateInstance>,
+    NotificationTargetCollection = NonPaginatedCollection<NotificationTarget>,
+    NamespaceCollection = NonPaginatedCollection<Namespace>,
+    ApiKeyCollection = NonPaginatedCollection<ApiKey>,
+    WorkerProfileCollection = NonPaginatedCollection<WorkerProfile>,
 )]
 pub struct NonPaginatedCollection<T> {
     pub data: Vec<T>,
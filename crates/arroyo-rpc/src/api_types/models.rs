@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPost {
+    /// The name of the SQL function that scores rows with the model
+    pub name: String,
+    pub description: Option<String>,
+    pub namespace: Option<String>,
+    /// The number of input features, which are passed to the function as numeric arguments
+    pub inputs: u32,
+    /// The number of scores the model outputs; models with a single output return a `DOUBLE`,
+    /// and otherwise a `DOUBLE[]`
+    pub outputs: u32,
+    /// The ONNX model, base64-encoded. It must take a single `[batch, inputs]` float tensor and
+    /// return a `[batch, outputs]` float tensor as its first output.
+    pub model: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub namespace: String,
+    /// The latest published version of the model
+    pub version: i32,
+    pub url: String,
+    pub inputs: u32,
+    pub outputs: u32,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelVersionPost {
+    pub description: Option<String>,
+    /// The new ONNX model, base64-encoded, which must have the same inputs and outputs as the
+    /// previous version
+    pub model: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelVersion {
+    pub version: i32,
+    pub created_at: u64,
+    pub description: Option<String>,
+    pub url: String,
+    pub inputs: u32,
+    pub outputs: u32,
+}
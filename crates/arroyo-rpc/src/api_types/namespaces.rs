@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespacePost {
+    pub name: String,
+    pub max_parallelism: Option<u32>,
+    pub max_task_slots: Option<u32>,
+    pub max_state_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Namespace {
+    pub id: String,
+    pub name: String,
+    pub created_at: u64,
+    pub max_parallelism: Option<u32>,
+    pub max_task_slots: Option<u32>,
+    pub max_state_bytes: Option<u64>,
+}
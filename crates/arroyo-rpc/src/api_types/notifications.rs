@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// A change in a job's state that notification targets can be alerted of
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationEvent {
+    /// The job failed, either permanently or before being restarted
+    JobFailed,
+    /// The job is running again after failing
+    JobRecovered,
+    /// Several checkpoints in a row failed
+    CheckpointFailures,
+    /// Workers or tasks did not start in time while scheduling the job
+    SchedulingTimeout,
+}
+
+impl NotificationEvent {
+    pub fn all() -> Vec<NotificationEvent> {
+        vec![
+            NotificationEvent::JobFailed,
+            NotificationEvent::JobRecovered,
+            NotificationEvent::CheckpointFailures,
+            NotificationEvent::SchedulingTimeout,
+        ]
+    }
+}
+
+/// Where notifications are sent
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum NotificationTargetConfig {
+    /// POSTs the payload to a URL
+    #[serde(rename_all = "camelCase")]
+    Webhook {
+        url: String,
+        /// Headers added to each request, for example for authentication
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    /// Posts a message to a Slack incoming webhook
+    #[serde(rename_all = "camelCase")]
+    Slack { webhook_url: String },
+    /// Triggers and resolves PagerDuty incidents through the Events API v2
+    #[serde(rename_all = "camelCase")]
+    PagerDuty { routing_key: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationTargetPost {
+    pub name: String,
+    pub config: NotificationTargetConfig,
+    /// The events to notify the target of; defaults to all of them
+    pub events: Option<Vec<NotificationEvent>>,
+    /// Only notify the target of events for this pipeline, rather than for every pipeline in the
+    /// organization
+    pub pipeline_id: Option<String>,
+    /// A template for the body of webhooks, the text of Slack messages, or the summary of
+    /// PagerDuty incidents, which may reference the fields of the notification as `{{ field }}`
+    pub payload_template: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationTarget {
+    pub id: String,
+    pub name: String,
+    pub config: NotificationTargetConfig,
+    pub events: Vec<NotificationEvent>,
+    pub pipeline_id: Option<String>,
+    pub payload_template: Option<String>,
+    pub created_at: u64,
+}
+
+/// The contents of a notification, which is the body of webhooks that don't have a payload
+/// template
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationPayload {
+    pub event: NotificationEvent,
+    pub job_id: String,
+    pub pipeline_id: String,
+    pub pipeline_name: String,
+    /// The state the job transitioned to
+    pub state: String,
+    /// A human-readable description of what happened
+    pub message: String,
+    /// The error that caused the failure or timeout, if any
+    pub error: Option<NotificationError>,
+    /// The number of consecutive failed checkpoints, for `checkpointFailures` events
+    pub checkpoint_failures: Option<u32>,
+    pub time: u64,
+}
+
+/// The structured error included in notifications
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationError {
+    pub message: String,
+    /// The category of the error, as reported on the job
+    pub category: Option<String>,
+    /// The full error, including its causes
+    pub details: Option<String>,
+}
+
+impl NotificationPayload {
+    /// The fields that payload templates can reference; nested fields of the error are flattened
+    /// with dots, like `{{ error.message }}`
+    pub const TEMPLATE_FIELDS: &'static [&'static str] = &[
+        "event",
+        "jobId",
+        "pipelineId",
+        "pipelineName",
+        "state",
+        "message",
+        "error.message",
+        "error.category",
+        "error.details",
+        "checkpointFailures",
+        "time",
+    ];
+
+    fn field(&self, name: &str) -> String {
+        let value = serde_json::to_value(self).unwrap();
+        let value = name
+            .split('.')
+            .try_fold(&value, |v, part| v.get(part))
+            .unwrap_or(&Value::Null);
+
+        match value {
+            Value::Null => String::new(),
+            Value::String(s) => s.clone(),
+            v => v.to_string(),
+        }
+    }
+
+    /// Checks that a payload template only references known fields
+    pub fn validate_template(template: &str) -> Result<(), String> {
+        for name in placeholders(template)? {
+            if !Self::TEMPLATE_FIELDS.contains(&name) {
+                return Err(format!(
+                    "unknown field '{}' in payload template; valid fields are {}",
+                    name,
+                    Self::TEMPLATE_FIELDS.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Substitutes the payload's fields into a template. When `json_escape` is set, values are
+    /// escaped so that they can be placed inside JSON strings.
+    pub fn render(&self, template: &str, json_escape: bool) -> Result<String, String> {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find("{{") {
+            rendered.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| "unclosed '{{' in payload template".to_string())?;
+            let name = after[..end].trim();
+
+            if !Self::TEMPLATE_FIELDS.contains(&name) {
+                return Err(format!("unknown field '{}' in payload template", name));
+            }
+            let value = self.field(name);
+
+            if json_escape {
+                let escaped = serde_json::to_string(&value).unwrap();
+                rendered.push_str(&escaped[1..escaped.len() - 1]);
+            } else {
+                rendered.push_str(&value);
+            }
+
+            rest = &after[end + 2..];
+        }
+
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+}
+
+fn placeholders(template: &str) -> Result<Vec<&str>, String> {
+    let mut names = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "unclosed '{{' in payload template".to_string())?;
+        names.push(after[..end].trim());
+        rest = &after[end + 2..];
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let payload = NotificationPayload {
+            event: NotificationEvent::JobFailed,
+            job_id: "job_1".to_string(),
+            pipeline_id: "pl_1".to_string(),
+            pipeline_name: "orders".to_string(),
+            state: "Failed".to_string(),
+            message: "job failed".to_string(),
+            error: Some(NotificationError {
+                message: "connection \"refused\"".to_string(),
+                category: Some("connector".to_string()),
+                details: None,
+            }),
+            checkpoint_failures: None,
+            time: 0,
+        };
+
+        assert_eq!(
+            payload
+                .render(
+                    "{{event}}: {{ pipelineName }} ({{ error.category }})",
+                    false
+                )
+                .unwrap(),
+            "jobFailed: orders (connector)"
+        );
+        assert_eq!(
+            payload
+                .render(
+                    r#"{"text": "{{ error.message }}{{ error.details }}"}"#,
+                    true
+                )
+                .unwrap(),
+            r#"{"text": "connection \"refused\""}"#
+        );
+
+        assert!(NotificationPayload::validate_template("{{ jobId }} {{ error.message }}").is_ok());
+        assert!(NotificationPayload::validate_template("{{ nope }}").is_err());
+        assert!(NotificationPayload::validate_template("{{ jobId").is_err());
+    }
+}
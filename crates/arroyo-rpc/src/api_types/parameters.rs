@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorParametersPost {
+    pub operator_id: String,
+    /// The parameters to change, by name; parameters that aren't included keep their current
+    /// values. Supported parameters depend on the operator: `max_rows_per_second` for sources,
+    /// `flush_interval_ms` for updating aggregates and `idle_time_ms` for watermark generators.
+    pub parameters: HashMap<String, f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorParametersUpdate {
+    /// The number of subtasks that the update was sent to
+    pub subtasks: u32,
+}
@@ -1,6 +1,11 @@
 use crate::api_types::udfs::Udf;
 use crate::grpc as grpc_proto;
+use crate::BatchSettings;
+use k8s_openapi::api::core::v1::{Affinity, Toleration, TopologySpreadConstraint};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use strum_macros::{Display, EnumString};
 use utoipa::ToSchema;
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -8,6 +13,7 @@ use utoipa::ToSchema;
 pub struct ValidateQueryPost {
     pub query: String,
     pub udfs: Option<Vec<Udf>>, // needed for query validation but are not themselves validated
+    pub namespace: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -15,6 +21,105 @@ pub struct ValidateQueryPost {
 pub struct QueryValidationResult {
     pub graph: Option<PipelineGraph>,
     pub errors: Vec<String>,
+    /// The results of introspection statements like `SHOW TABLES`, `DESCRIBE <table>`, or
+    /// queries over `information_schema`, which are evaluated rather than planned as pipelines
+    pub introspection: Option<IntrospectionResult>,
+}
+
+/// A table of results, with each value formatted as text
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IntrospectionResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Option<String>>>,
+}
+
+/// Checks that a pipeline could be created and run, without starting it
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineDryRunPost {
+    pub query: String,
+    pub udfs: Option<Vec<Udf>>,
+    pub namespace: Option<String>,
+    /// Whether to connect to the external systems of each source and sink; defaults to true
+    pub check_connectivity: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum DryRunStage {
+    Udfs,
+    Planning,
+    Connectivity,
+    Schema,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRunDiagnostic {
+    pub stage: DryRunStage,
+    pub severity: DiagnosticSeverity,
+    /// The UDF or operator the diagnostic is about, if any
+    pub subject: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineDryRunResult {
+    /// Whether there were no errors; warnings don't make a pipeline invalid
+    pub valid: bool,
+    pub graph: Option<PipelineGraph>,
+    pub diagnostics: Vec<DryRunDiagnostic>,
+}
+
+/// Runs a query with its sources replaced by fixture rows, and compares what it writes to each
+/// sink with the expected rows
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineTestPost {
+    pub query: String,
+    pub udfs: Option<Vec<Udf>>,
+    pub namespace: Option<String>,
+    /// The rows read by each source table, by table name
+    pub inputs: BTreeMap<String, Vec<serde_json::Value>>,
+    /// The rows expected to be written to each sink table, by table name
+    pub expected: BTreeMap<String, Vec<serde_json::Value>>,
+    /// Whether the rows written to each sink must be in the same order as the expected rows;
+    /// defaults to false
+    pub ordered: Option<bool>,
+    /// Whether processing time is simulated, fast-forwarding to the event time of each row
+    /// rather than following the system clock; defaults to false
+    pub simulated_time: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineTestResult {
+    pub passed: bool,
+    pub sinks: Vec<SinkTestResult>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SinkTestResult {
+    pub name: String,
+    pub passed: bool,
+    /// The rows written to the sink
+    pub actual: Vec<serde_json::Value>,
+    /// Expected rows that were not written
+    pub missing: Vec<serde_json::Value>,
+    /// Rows that were written but not expected
+    pub unexpected: Vec<serde_json::Value>,
+    /// Why the sink failed, if not because of missing or unexpected rows
+    pub error: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -23,9 +128,27 @@ pub struct PipelinePost {
     pub name: String,
     pub query: String,
     pub udfs: Option<Vec<Udf>>,
+    /// Versions of global UDFs and models to use, by name; those that aren't listed are pinned to
+    /// their latest version, so publishing a new version doesn't change the pipeline
+    pub udf_versions: Option<BTreeMap<String, i32>>,
     pub preview: Option<bool>,
     pub parallelism: u64,
     pub checkpoint_interval_micros: Option<u64>,
+    pub namespace: Option<String>,
+    /// When the cluster is out of task slots, pipelines may preempt running pipelines with a
+    /// lower priority; defaults to 0. Only the node scheduler manages a fixed pool of task
+    /// slots, so a non-zero priority is rejected with any other scheduler.
+    pub priority: Option<i32>,
+    pub worker_pod: Option<WorkerPodConfig>,
+    pub freshness_slo: Option<FreshnessSlo>,
+    pub restart_strategy: Option<RestartStrategy>,
+    pub batching: Option<PipelineBatching>,
+    pub recording: Option<PipelineRecording>,
+    pub replay: Option<PipelineReplay>,
+    pub bootstrap: Option<Vec<StateBootstrap>>,
+    /// The resource class (like "memory" or "cpu") of the workers each operator must run on, by
+    /// operator id; operators that aren't listed may run on any worker
+    pub resource_classes: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -34,6 +157,23 @@ pub struct PipelinePatch {
     pub parallelism: Option<u64>,
     pub checkpoint_interval_micros: Option<u64>,
     pub stop: Option<StopType>,
+    /// See [`PipelinePost::priority`]
+    pub priority: Option<i32>,
+    /// Takes effect the next time the pipeline's workers are scheduled
+    pub worker_pod: Option<WorkerPodConfig>,
+    pub freshness_slo: Option<FreshnessSlo>,
+    /// Takes effect the next time the pipeline fails
+    pub restart_strategy: Option<RestartStrategy>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineUdfsPut {
+    /// The new definitions of the pipeline's UDFs; each must keep its name and signature
+    pub udfs: Vec<Udf>,
+    /// Global UDF and model versions to move the pipeline to, by name; others keep their current
+    /// versions
+    pub udf_versions: Option<BTreeMap<String, i32>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -49,6 +189,8 @@ pub struct Pipeline {
     pub name: String,
     pub query: String,
     pub udfs: Vec<Udf>,
+    /// The versions of the global UDFs the pipeline is pinned to, by name
+    pub udf_versions: BTreeMap<String, i32>,
     pub checkpoint_interval_micros: u64,
     pub stop: StopType,
     pub created_at: u64,
@@ -57,6 +199,257 @@ pub struct Pipeline {
     pub action_in_progress: bool,
     pub graph: PipelineGraph,
     pub preview: bool,
+    pub namespace: String,
+    pub priority: i32,
+    pub worker_pod: Option<WorkerPodConfig>,
+    pub freshness_slo: Option<FreshnessSlo>,
+    pub restart_strategy: Option<RestartStrategy>,
+    pub batching: PipelineBatching,
+    pub recording: PipelineRecording,
+    pub replay: Option<PipelineReplay>,
+    pub bootstrap: Vec<StateBootstrap>,
+    pub resource_classes: BTreeMap<String, String>,
+}
+
+/// Controls the size of the record batches that flow through a pipeline. Sources emit a batch
+/// once it reaches `maxRows` rows or its first row has waited `lingerMillis`, and other operators
+/// merge small batches that are queued on their inputs into batches of up to `maxRows` rows. If
+/// `adaptive` is set, sources whose outputs are backpressured grow their batches beyond these
+/// settings, and shrink them back once the backpressure clears. Unset values use the
+/// `pipeline.source-batch-size`, `pipeline.source-batch-linger` and `pipeline.source-batch-adaptive`
+/// config.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineBatching {
+    pub max_rows: Option<u64>,
+    pub linger_millis: Option<u64>,
+    pub adaptive: Option<bool>,
+    /// Overrides of these settings for individual operators, by operator id
+    #[serde(default)]
+    pub operator_overrides: BTreeMap<String, BatchingOverride>,
+}
+
+/// Records the batches emitted by a pipeline's sources to the checkpoint store, alongside the
+/// checkpoints, so that they can later be replayed with `PipelineReplay`. In `sampled` mode each
+/// batch is recorded with probability `sampleRate`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineRecording {
+    pub mode: RecordingMode,
+    pub sample_rate: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum RecordingMode {
+    #[default]
+    Off,
+    Full,
+    Sampled,
+}
+
+impl PipelineRecording {
+    /// The probability with which each source batch is recorded
+    pub fn sample_rate(&self) -> f64 {
+        match self.mode {
+            RecordingMode::Off => 0.0,
+            RecordingMode::Full => 1.0,
+            RecordingMode::Sampled => self.sample_rate.unwrap_or(1.0),
+        }
+    }
+}
+
+/// Runs a pipeline from a checkpoint of an earlier job of the same query, reading the source
+/// batches that job recorded after the checkpoint, in the order they were recorded, instead of
+/// reading from the sources. The pipeline finishes once the recording has been replayed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineReplay {
+    pub job_id: String,
+    pub epoch: u32,
+}
+
+/// Hydrates the keyed state of an operator from a bounded Parquet snapshot before the pipeline
+/// starts streaming, so that it doesn't need to re-read its sources' full history. Columns of the
+/// snapshot are matched to those of the state table by name. Bootstrapping only applies when the
+/// pipeline's first job starts; once it has checkpointed, the bootstrapped state is restored from
+/// its checkpoints like any other.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StateBootstrap {
+    pub operator_id: String,
+    pub table: String,
+    /// URL of the Parquet file to load the table from
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchingOverride {
+    pub max_rows: Option<u64>,
+    pub linger_millis: Option<u64>,
+    pub adaptive: Option<bool>,
+}
+
+impl PipelineBatching {
+    /// The batch settings for an operator, falling back to the pipeline's settings and then to the
+    /// cluster defaults
+    pub fn for_operator(&self, operator_id: &str) -> BatchSettings {
+        let defaults = BatchSettings::default();
+        let operator = self.operator_overrides.get(operator_id);
+
+        let max_rows = operator
+            .and_then(|o| o.max_rows)
+            .or(self.max_rows)
+            .map(|r| r as usize)
+            .unwrap_or(defaults.max_rows);
+        let linger = operator
+            .and_then(|o| o.linger_millis)
+            .or(self.linger_millis)
+            .map(Duration::from_millis)
+            .unwrap_or(defaults.linger);
+        let adaptive = operator
+            .and_then(|o| o.adaptive)
+            .or(self.adaptive)
+            .unwrap_or(defaults.adaptive);
+
+        BatchSettings {
+            max_rows,
+            linger,
+            adaptive,
+        }
+    }
+}
+
+/// A target for how far the output of a pipeline may fall behind the current time, measured as
+/// the lag between wall-clock time and the watermark of the pipeline's sinks
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FreshnessSlo {
+    /// The maximum allowed lag, in milliseconds
+    pub target_millis: u64,
+    /// The fraction of time over the trailing hour that the target should be met; defaults to
+    /// 0.99
+    pub objective: Option<f64>,
+    /// A URL that alerts are POSTed to when the pipeline starts or stops violating the target
+    pub alert_webhook_url: Option<String>,
+}
+
+impl FreshnessSlo {
+    pub const DEFAULT_OBJECTIVE: f64 = 0.99;
+
+    pub fn objective(&self) -> f64 {
+        self.objective.unwrap_or(Self::DEFAULT_OBJECTIVE)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum RestartStrategyType {
+    /// Waits `delayMillis` before each restart, and fails after `maxRestarts` restarts without
+    /// the pipeline becoming healthy in between
+    FixedDelay,
+    /// Like `fixedDelay`, but the delay is multiplied by `multiplier` after each restart, up to
+    /// `maxDelayMillis`
+    ExponentialBackoff,
+    /// Waits `delayMillis` before each restart, and fails if there are more than `maxRestarts`
+    /// restarts within `intervalMillis`, however healthy the pipeline was in between
+    FailureRate,
+}
+
+/// How a pipeline recovers when it fails. Pipelines without a restart strategy restart
+/// immediately, up to `pipeline.allowed-restarts` times before becoming healthy.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RestartStrategy {
+    pub strategy: RestartStrategyType,
+    /// How long to wait before restarting; for `exponentialBackoff`, before the first restart.
+    /// Defaults to 0.
+    pub delay_millis: Option<u64>,
+    /// The number of restarts allowed before the pipeline fails; unlimited if not set, except for
+    /// `failureRate`, where it's required
+    pub max_restarts: Option<u32>,
+    /// For `exponentialBackoff`, the factor the delay grows by with each restart; defaults to 2
+    pub multiplier: Option<f64>,
+    /// For `exponentialBackoff`, the longest delay between restarts; defaults to 5 minutes
+    pub max_delay_millis: Option<u64>,
+    /// For `failureRate`, the window that restarts are counted over
+    pub interval_millis: Option<u64>,
+}
+
+impl RestartStrategy {
+    pub const DEFAULT_MULTIPLIER: f64 = 2.0;
+    pub const DEFAULT_MAX_DELAY_MILLIS: u64 = 5 * 60 * 1000;
+}
+
+/// A restart of a job after it failed
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobRestart {
+    /// The run that failed
+    pub run_id: u64,
+    pub time: u64,
+    /// How many times the job had restarted without becoming healthy, including this restart
+    pub attempt: u32,
+    /// How long the job waited before restarting
+    pub delay_micros: u64,
+    pub reason: String,
+}
+
+/// Customizes the Kubernetes pods that run a pipeline's workers, overriding the
+/// `kubernetes-scheduler.worker` config for that pipeline. Only accepted with the Kubernetes
+/// scheduler; setting anything other than the resources, annotations, and node selectors listed in
+/// `kubernetes-scheduler.editor-worker-pod-keys` requires the admin role.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerPodConfig {
+    /// Resource requests, like `{"cpu": "2", "memory": "4Gi"}`; in the per-slot resource mode,
+    /// these are per task slot. Resources not specified here use the configured defaults.
+    pub requests: Option<BTreeMap<String, String>>,
+    /// Resource limits, in the same form as `requests`
+    pub limits: Option<BTreeMap<String, String>>,
+    /// Added to the configured node selector
+    pub node_selector: Option<BTreeMap<String, String>>,
+    /// Added to the configured pod annotations
+    pub annotations: Option<BTreeMap<String, String>>,
+    /// Kubernetes node affinity and pod (anti-)affinity, replacing the configured affinity
+    #[schema(value_type = Option<Object>)]
+    pub affinity: Option<Affinity>,
+    /// Added to the configured tolerations, to allow the pods onto tainted (e.g., dedicated)
+    /// node pools
+    #[schema(value_type = Option<Vec<Object>>)]
+    pub tolerations: Option<Vec<Toleration>>,
+    /// Replaces the configured topology spread constraints, for example to spread the pods
+    /// across zones; constraints without a label selector apply to the pods of the same run
+    #[schema(value_type = Option<Vec<Object>>)]
+    pub topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+    /// Placement and resources for the workers of each resource class, applied on top of the
+    /// settings above
+    pub groups: Option<BTreeMap<String, WorkerGroupPlacement>>,
+}
+
+/// Placement and resources of the Kubernetes pods that run the workers of one resource class, so
+/// that the workers of heavy operators can be sized separately from the rest of the pipeline
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerGroupPlacement {
+    /// Resource requests, overriding those of the pipeline and the configured defaults
+    pub requests: Option<BTreeMap<String, String>>,
+    /// Resource limits, in the same form as `requests`
+    pub limits: Option<BTreeMap<String, String>>,
+    /// Added to the pod annotations
+    pub annotations: Option<BTreeMap<String, String>>,
+    /// Added to the node selector
+    pub node_selector: Option<BTreeMap<String, String>>,
+    /// Replaces the affinity
+    #[schema(value_type = Option<Object>)]
+    pub affinity: Option<Affinity>,
+    /// Added to the tolerations
+    #[schema(value_type = Option<Vec<Object>>)]
+    pub tolerations: Option<Vec<Toleration>>,
+    /// Replaces the topology spread constraints
+    #[schema(value_type = Option<Vec<Object>>)]
+    pub topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -106,7 +499,58 @@ pub struct Job {
     pub finish_time: Option<u64>,
     pub tasks: Option<u64>,
     pub failure_message: Option<String>,
+    /// The class of the task failure that caused the job to fail, if known
+    pub failure_category: Option<ErrorCategory>,
     pub created_at: u64,
+    /// Whether the job has been stopped to make room for a higher-priority job; it will be
+    /// resumed from its last checkpoint once enough task slots are free
+    pub preempted: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineSchedulePost {
+    /// A cron expression, with either 5 fields or 6 fields (including seconds); times are in UTC
+    pub cron: String,
+    pub enabled: Option<bool>,
+}
+
+/// A schedule on which a bounded pipeline is re-run from scratch
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineSchedule {
+    pub id: String,
+    pub cron: String,
+    pub enabled: bool,
+    pub next_run_at: Option<u64>,
+    pub created_at: u64,
+}
+
+#[derive(
+    Serialize, Deserialize, Copy, Clone, Debug, ToSchema, PartialEq, Eq, Display, EnumString,
+)]
+#[serde(rename_all = "camelCase")]
+#[strum(serialize_all = "snake_case")]
+pub enum ScheduledRunState {
+    Running,
+    Succeeded,
+    Failed,
+    /// The pipeline was stopped before the run completed
+    Cancelled,
+    /// The run was not started because the previous run was still in progress
+    Skipped,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledRun {
+    pub id: String,
+    pub job_id: String,
+    pub state: ScheduledRunState,
+    pub scheduled_for: u64,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub failure_message: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -117,6 +561,62 @@ pub enum JobLogLevel {
     Error,
 }
 
+/// The class of an error reported by a job, so that clients can react differently to, for
+/// example, bad credentials and bad data
+#[derive(
+    Serialize, Deserialize, Copy, Clone, Debug, ToSchema, PartialEq, Eq, Hash, Display, EnumString,
+)]
+#[serde(rename_all = "camelCase")]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorCategory {
+    /// A connector could not authenticate with, or was not authorized by, an external system
+    ConnectorAuth,
+    /// Any other failure communicating with an external system
+    Connector,
+    /// Input data could not be deserialized
+    Deserialization,
+    /// State could not be restored from a checkpoint
+    StateRestore,
+    /// State could not be written for a checkpoint
+    Checkpoint,
+    /// A UDF or other user-provided code failed
+    UserCode,
+    /// The job ran out of memory, disk, or another resource
+    ResourceExhaustion,
+    /// An error in Arroyo itself, or one that could not be classified
+    Internal,
+}
+
+impl From<grpc_proto::ErrorCategory> for ErrorCategory {
+    fn from(value: grpc_proto::ErrorCategory) -> Self {
+        match value {
+            grpc_proto::ErrorCategory::Internal => ErrorCategory::Internal,
+            grpc_proto::ErrorCategory::ConnectorAuth => ErrorCategory::ConnectorAuth,
+            grpc_proto::ErrorCategory::Connector => ErrorCategory::Connector,
+            grpc_proto::ErrorCategory::Deserialization => ErrorCategory::Deserialization,
+            grpc_proto::ErrorCategory::StateRestore => ErrorCategory::StateRestore,
+            grpc_proto::ErrorCategory::Checkpoint => ErrorCategory::Checkpoint,
+            grpc_proto::ErrorCategory::UserCode => ErrorCategory::UserCode,
+            grpc_proto::ErrorCategory::ResourceExhaustion => ErrorCategory::ResourceExhaustion,
+        }
+    }
+}
+
+impl From<ErrorCategory> for grpc_proto::ErrorCategory {
+    fn from(value: ErrorCategory) -> Self {
+        match value {
+            ErrorCategory::Internal => grpc_proto::ErrorCategory::Internal,
+            ErrorCategory::ConnectorAuth => grpc_proto::ErrorCategory::ConnectorAuth,
+            ErrorCategory::Connector => grpc_proto::ErrorCategory::Connector,
+            ErrorCategory::Deserialization => grpc_proto::ErrorCategory::Deserialization,
+            ErrorCategory::StateRestore => grpc_proto::ErrorCategory::StateRestore,
+            ErrorCategory::Checkpoint => grpc_proto::ErrorCategory::Checkpoint,
+            ErrorCategory::UserCode => grpc_proto::ErrorCategory::UserCode,
+            ErrorCategory::ResourceExhaustion => grpc_proto::ErrorCategory::ResourceExhaustion,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct JobLogMessage {
@@ -127,6 +627,7 @@ pub struct JobLogMessage {
     pub level: JobLogLevel,
     pub message: String,
     pub details: String,
+    pub category: Option<ErrorCategory>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileKind {
+    Cpu,
+    Heap,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileFormat {
+    Flamegraph,
+    Pprof,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProfilePost {
+    pub kind: ProfileKind,
+    /// Output format of CPU profiles (defaults to flamegraph); heap profiles are always pprof
+    pub format: Option<ProfileFormat>,
+    /// How long to sample CPU usage for (defaults to 30 seconds)
+    pub duration_secs: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerProfile {
+    pub worker_id: u64,
+    pub content_type: String,
+    /// The base64-encoded profile, if it was collected successfully
+    pub data: Option<String>,
+    pub error: Option<String>,
+}
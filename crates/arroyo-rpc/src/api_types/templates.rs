@@ -0,0 +1,84 @@
+use crate::api_types::udfs::Udf;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// How a template parameter's values are checked and substituted into the query
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum TemplateParameterType {
+    /// Any text; single quotes are doubled, so that the parameter can be placed inside a SQL
+    /// string literal like `'{{ topic }}'`
+    #[default]
+    String,
+    /// A number, substituted as-is
+    Number,
+    /// `true` or `false`
+    Boolean,
+    /// A SQL identifier, like a table or column name
+    Identifier,
+}
+
+/// A parameter of a pipeline template, which is referenced in its query as `{{ name }}`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateParameter {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub parameter_type: TemplateParameterType,
+    pub description: Option<String>,
+    /// The value used when a pipeline is created without one; parameters without defaults are
+    /// required
+    pub default: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineTemplatePost {
+    pub name: String,
+    /// The query of the pipelines created from the template, which may reference its parameters
+    /// as `{{ name }}`
+    pub query: String,
+    pub description: Option<String>,
+    pub parameters: Vec<TemplateParameter>,
+    pub udfs: Option<Vec<Udf>>,
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineTemplate {
+    pub id: String,
+    pub name: String,
+    pub query: String,
+    pub description: Option<String>,
+    pub parameters: Vec<TemplateParameter>,
+    pub udfs: Vec<Udf>,
+    pub namespace: String,
+    /// The number of pipelines that have been created from the template
+    pub pipeline_count: u32,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// Creates a pipeline from a template, in the template's namespace
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineTemplateInstancePost {
+    pub name: String,
+    /// Values for the template's parameters, by name
+    pub parameters: BTreeMap<String, String>,
+    pub parallelism: u64,
+    pub checkpoint_interval_micros: Option<u64>,
+    pub priority: Option<i32>,
+}
+
+/// A pipeline created from a template
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineTemplateInstance {
+    pub pipeline_id: String,
+    pub pipeline_name: String,
+    /// The values of all of the template's parameters, including defaults
+    pub parameters: BTreeMap<String, String>,
+}
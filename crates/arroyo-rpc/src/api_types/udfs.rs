@@ -1,16 +1,29 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum UdfLanguage {
+    #[default]
+    Rust,
+    /// A single exported function, run in a sandboxed JavaScript engine
+    JavaScript,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Udf {
     pub definition: String,
+    #[serde(default)]
+    pub language: UdfLanguage,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ValidateUdfPost {
     pub definition: String,
+    #[serde(default)]
+    pub language: UdfLanguage,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -26,6 +39,23 @@ pub struct UdfPost {
     pub prefix: String,
     pub definition: String,
     pub description: Option<String>,
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UdfArtifactPost {
+    /// Source of the UDF the artifact was built from
+    pub definition: String,
+    /// The compiled dynamic library, base64-encoded
+    pub dylib: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UdfArtifact {
+    pub udf_name: String,
+    pub dylib_url: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -39,4 +69,25 @@ pub struct GlobalUdf {
     pub definition: String,
     pub description: Option<String>,
     pub dylib_url: String,
+    pub namespace: String,
+    /// The latest published version of the UDF
+    pub version: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UdfVersionPost {
+    /// The new definition of the UDF, which must keep its name
+    pub definition: String,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UdfVersion {
+    pub version: i32,
+    pub created_at: u64,
+    pub definition: String,
+    pub description: Option<String>,
+    pub dylib_url: String,
 }
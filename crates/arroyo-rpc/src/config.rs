@@ -1,7 +1,11 @@
+use crate::fault_injection::FaultSpec;
 use arc_swap::ArcSwapOption;
 use figment::providers::{Env, Format, Json, Toml, Yaml};
 use figment::Figment;
-use k8s_openapi::api::core::v1::{EnvVar, ResourceRequirements, Volume, VolumeMount};
+use k8s_openapi::api::core::v1::{
+    Affinity, EnvVar, ResourceRequirements, Toleration, TopologySpreadConstraint, Volume,
+    VolumeMount,
+};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
 use log::warn;
 use regex::Regex;
@@ -211,9 +215,26 @@ pub struct Config {
     // Logging config
     pub logging: LogConfig,
 
+    /// Configuration for resolving secrets referenced by connection configs
+    pub secrets: SecretsConfig,
+
     /// URL of an object store or filesystem for storing checkpoints
     pub checkpoint_url: String,
 
+    /// Replication of completed checkpoints to a secondary store, for disaster recovery
+    #[serde(default)]
+    pub checkpoint_replication: CheckpointReplicationConfig,
+
+    /// Encryption at rest of checkpoint and state files
+    #[serde(default)]
+    pub checkpoint_encryption: CheckpointEncryptionConfig,
+
+    /// Mutual TLS for traffic between the controller, nodes and workers
+    pub internal_tls: InternalTlsConfig,
+
+    /// Compression of gRPC messages between services and of HTTP responses
+    pub compression: CompressionConfig,
+
     /// Default interval for checkpointing
     pub default_checkpoint_interval: HumanReadableDuration,
 
@@ -237,6 +258,15 @@ pub struct Config {
     /// Telemetry config
     #[serde(default)]
     pub disable_telemetry: bool,
+
+    /// Fault injection, for testing how pipelines recover from failures
+    #[serde(default)]
+    pub fault_injection: FaultInjectionConfig,
+
+    /// Directory to load connector plugins (dynamic libraries built with arroyo-connector-sdk)
+    /// from; this must be set for the API server and workers
+    #[serde(default)]
+    pub connector_plugin_dir: Option<PathBuf>,
 }
 
 impl Config {
@@ -253,6 +283,15 @@ impl Config {
             .map(|t| t.to_string())
             .unwrap_or_else(|| format!("http://localhost:{}", self.compiler.rpc_port))
     }
+
+    /// The URL that checkpoints are written to and restored from; this is the replica when
+    /// failing over to it
+    pub fn checkpoint_storage_url(&self) -> &str {
+        match &self.checkpoint_replication.url {
+            Some(url) if self.checkpoint_replication.failover => url,
+            _ => &self.checkpoint_url,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -263,6 +302,27 @@ pub struct ApiConfig {
 
     /// The HTTP port for the API service
     pub http_port: u16,
+
+    /// Whether requests must be authenticated with an API key. If disabled (the default),
+    /// requests without a key are treated as coming from an organization admin, which the bundled
+    /// web UI relies on; only enable this once every client sends a key.
+    #[serde(default)]
+    pub require_api_key: bool,
+
+    /// A key that authenticates as an unrestricted admin, presented as a bearer token like any
+    /// other key; this is how the first API keys are created once `require-api-key` is enabled
+    #[serde(default)]
+    pub admin_key: Option<Sensitive<String>>,
+
+    /// The port for the Arrow Flight SQL endpoint, which serves the results of pipelines' flight
+    /// sinks; the endpoint is disabled if unset
+    #[serde(default)]
+    pub flight_port: Option<u16>,
+
+    /// The port for the read-only Postgres wire-protocol endpoint, which serves the same results
+    /// as the Arrow Flight SQL endpoint; the endpoint is disabled if unset
+    #[serde(default)]
+    pub postgres_port: Option<u16>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -276,6 +336,51 @@ pub struct ControllerConfig {
 
     /// The scheduler to use
     pub scheduler: Scheduler,
+
+    /// The number of times in a row a job must fail because of checkpoint errors before
+    /// notification targets are alerted
+    pub checkpoint_failure_alert_threshold: u32,
+
+    pub metrics_history: MetricsHistoryConfig,
+}
+
+/// Operator metrics are sampled into the database by the controller while jobs run, and rolled
+/// up from raw samples into per-minute and per-hour averages, each of which is kept for its own
+/// retention period
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MetricsHistoryConfig {
+    /// Whether to record metrics history
+    pub enabled: bool,
+
+    /// How often metrics are sampled
+    pub sample_interval: HumanReadableDuration,
+
+    /// How long raw samples are kept
+    pub raw_retention: HumanReadableDuration,
+
+    /// How long per-minute averages are kept
+    pub minute_retention: HumanReadableDuration,
+
+    /// How long per-hour averages are kept
+    pub hour_retention: HumanReadableDuration,
+}
+
+impl MetricsHistoryConfig {
+    /// The resolution that raw samples are recorded at, in whole seconds
+    pub fn raw_resolution_secs(&self) -> u64 {
+        self.sample_interval.as_secs().max(1)
+    }
+
+    /// The resolutions of the stored metrics history, finest first, along with how long each
+    /// is kept
+    pub fn resolutions(&self) -> [(u64, Duration); 3] {
+        [
+            (self.raw_resolution_secs(), *self.raw_retention),
+            (60, *self.minute_retention),
+            (60 * 60, *self.hour_retention),
+        ]
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -339,6 +444,54 @@ pub struct WorkerConfig {
 
     /// Size of the queues between nodes in the dataflow graph
     pub queue_size: u32,
+
+    /// Compression to request for batches sent to other workers over the network; the codec for
+    /// each connection is agreed with the receiving worker when it's opened
+    #[serde(default)]
+    pub network_compression: NetworkCompression,
+
+    /// Memory budget in bytes for each task on this worker, shared by the joins, aggregations and
+    /// sorts it executes; these spill to disk where possible, and otherwise fail the task, when
+    /// they would exceed it. Unlimited if not set.
+    #[serde(default)]
+    pub task_memory_limit: Option<usize>,
+
+    /// Resource class of this worker (like "memory" or "cpu"); operators that require a class are
+    /// only scheduled onto workers of that class
+    #[serde(default)]
+    pub resource_class: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum NetworkCompression {
+    #[default]
+    None,
+    Lz4,
+    Zstd,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum RpcCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CompressionConfig {
+    /// Encoding used to compress gRPC messages sent between the api, controller, nodes, workers
+    /// and compiler. All services accept both gzip and zstd regardless of this setting, but
+    /// services from before compression was supported can't read compressed messages, so this
+    /// defaults to `none`. To opt in, upgrade every service first, then set
+    /// `ARROYO__COMPRESSION__GRPC` (or `compression.grpc`) to `gzip` or `zstd` and restart them.
+    pub grpc: RpcCompression,
+
+    /// Whether to compress responses of the REST and admin APIs for clients that accept it
+    pub http: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -355,6 +508,11 @@ pub struct NodeConfig {
 
     /// Number of task slots for this node
     pub task_slots: u32,
+
+    /// Resource class of this node (like "memory" or "cpu"), which is given to the workers it
+    /// runs
+    #[serde(default)]
+    pub resource_class: Option<String>,
 }
 
 impl NodeConfig {
@@ -371,6 +529,10 @@ pub struct AdminConfig {
 
     /// HTTP port the admin service will listen on
     pub http_port: u16,
+
+    /// Bearer token required by the profiling endpoints under `/debug/pprof`; those endpoints
+    /// are disabled if this is not set
+    pub auth_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -382,10 +544,14 @@ pub struct PipelineConfig {
     /// Batch linger time (how long to wait before flushing)
     pub source_batch_linger: HumanReadableDuration,
 
+    /// Whether sources grow their batch size and linger while their outputs are backpressured
+    pub source_batch_adaptive: bool,
+
     /// How often to flush aggregates
     pub update_aggregate_flush_interval: HumanReadableDuration,
 
-    /// How many restarts to allow before moving to failed (-1 for infinite)
+    /// How many restarts to allow before moving to failed (-1 for infinite), for pipelines
+    /// without a restart strategy
     pub allowed_restarts: i32,
 
     /// After this amount of time, we consider the job to be healthy and reset the restarts counter
@@ -400,7 +566,77 @@ pub struct PipelineConfig {
     /// Amount of time to wait for tasks to startup before considering it failed
     pub task_startup_time: HumanReadableDuration,
 
+    /// When a task fails, restart only the connected region of the dataflow that contains it
+    /// from the last checkpoint, leaving unrelated branches running. Falls back to restarting the
+    /// whole pipeline when the region spans multiple workers or a checkpoint is in progress.
+    pub regional_failover: bool,
+
     pub compaction: CompactionConfig,
+
+    pub checkpoint_retention: CheckpointRetentionConfig,
+
+    pub udf: UdfConfig,
+
+    pub timers: TimerConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CheckpointRetentionConfig {
+    /// Number of most recent checkpoints to keep for each job; the latest is always kept
+    pub keep_last: u32,
+
+    /// Also keep the latest checkpoint from each of this many most recent hours
+    pub keep_hourly: u32,
+
+    /// Also keep the latest checkpoint from each of this many most recent days
+    pub keep_daily: u32,
+
+    /// Never delete savepoints, the checkpoints that jobs were stopped with
+    pub protect_savepoints: bool,
+
+    /// How often the controller deletes checkpoints that aren't retained, along with any
+    /// checkpoint files they no longer reference
+    pub gc_interval: HumanReadableDuration,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TimerConfig {
+    /// Per-key timers are rounded up to a multiple of this, so that timers for a key that land
+    /// close together are coalesced into one
+    pub granularity: HumanReadableDuration,
+
+    /// Maximum number of outstanding timers for a single key; further timers for the key are
+    /// dropped until some of them fire
+    pub max_per_key: usize,
+
+    /// Maximum number of expired timers handed to an operator at once when the watermark
+    /// advances; the operator yields to other tasks between batches
+    pub fire_batch_size: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UdfConfig {
+    /// How long a single invocation of a UDF may run before it is abandoned
+    pub timeout: HumanReadableDuration,
+
+    /// Maximum number of bytes a UDF dylib may hold while processing a batch; unlimited if not set
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+
+    /// What to do with rows for which a UDF panics, times out, or exceeds its memory limit
+    pub bad_data: UdfBadData,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum UdfBadData {
+    /// Fail the operator, causing the pipeline to restart
+    Fail,
+    /// Return null for the rows that failed and keep processing
+    Drop,
 }
 
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
@@ -478,6 +714,11 @@ pub struct KubernetesSchedulerConfig {
     pub controller: Option<OwnerReference>,
     pub resource_mode: ResourceMode,
     pub worker: KubernetesWorkerConfig,
+    /// Resource names, annotation keys and node selector keys that editors may set in a
+    /// pipeline's worker pod config; the rest of it, including affinities, tolerations and
+    /// topology spread constraints, requires the admin role
+    #[serde(default)]
+    pub editor_worker_pod_keys: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -500,6 +741,23 @@ pub struct KubernetesWorkerConfig {
     #[serde(default)]
     pub annotations: BTreeMap<String, String>,
 
+    #[serde(default)]
+    pub node_selector: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub affinity: Option<Affinity>,
+
+    #[serde(default)]
+    pub tolerations: Vec<Toleration>,
+
+    /// Constraints without a label selector are applied to the pods of the same pipeline run
+    #[serde(default)]
+    pub topology_spread_constraints: Vec<TopologySpreadConstraint>,
+
+    /// Placement for the workers of each resource class, applied on top of the settings above
+    #[serde(default)]
+    pub groups: BTreeMap<String, KubernetesWorkerGroupConfig>,
+
     #[serde(default)]
     pub env: Vec<EnvVar>,
 
@@ -516,6 +774,31 @@ pub struct KubernetesWorkerConfig {
     pub command: String,
 }
 
+/// Placement and sizing of the Kubernetes workers of a resource class. The node selector,
+/// annotations and tolerations are added to those of all workers, resources override theirs one by
+/// one, while the affinity and topology spread constraints replace them.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct KubernetesWorkerGroupConfig {
+    #[serde(default)]
+    pub node_selector: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub affinity: Option<Affinity>,
+
+    #[serde(default)]
+    pub tolerations: Vec<Toleration>,
+
+    #[serde(default)]
+    pub topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub resources: ResourceRequirements,
+}
+
 impl KubernetesWorkerConfig {
     pub fn name(&self) -> String {
         self.name
@@ -525,11 +808,21 @@ impl KubernetesWorkerConfig {
     }
 }
 
+#[derive(Clone)]
 pub struct HumanReadableDuration {
     duration: Duration,
     original: String,
 }
 
+impl From<Duration> for HumanReadableDuration {
+    fn from(duration: Duration) -> Self {
+        Self {
+            duration,
+            original: format!("{}micros", duration.as_micros()),
+        }
+    }
+}
+
 impl Deref for HumanReadableDuration {
     type Target = Duration;
 
@@ -578,6 +871,7 @@ impl<'de> Deserialize<'de> for HumanReadableDuration {
             "s" | "secs" | "seconds" => Duration::from_secs(n),
             "m" | "mins" | "minutes" => Duration::from_secs(n * 60),
             "h" | "hrs" | "hours" => Duration::from_secs(n * 60 * 60),
+            "d" | "days" => Duration::from_secs(n * 24 * 60 * 60),
             x => return Err(de::Error::custom(format!("unknown time unit '{}'", x))),
         };
 
@@ -588,12 +882,129 @@ impl<'de> Deserialize<'de> for HumanReadableDuration {
     }
 }
 
+#[derive(Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FaultInjectionConfig {
+    /// Whether faults may be injected, through the API or by `faults`; this must be set for the
+    /// API server, controller and workers. Never enable this in production.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Faults that workers inject for as long as they run
+    #[serde(default)]
+    pub faults: Vec<FaultSpec>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CheckpointReplicationConfig {
+    /// URL of an object store, typically in another region, that completed checkpoints are
+    /// asynchronously copied to
+    pub url: Option<String>,
+
+    /// Restore pipelines from the replica and write new checkpoints to it, for failing over to
+    /// the replica's region when the primary checkpoint store is unavailable
+    #[serde(default)]
+    pub failover: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CheckpointEncryptionConfig {
+    /// AWS KMS key (id, ARN, or alias) used to encrypt the data keys that checkpoint and state
+    /// files are encrypted with on the client
+    pub kms_key_id: Option<String>,
+
+    /// Base64-encoded 256-bit key used to encrypt the data keys, for stores outside of AWS; only
+    /// one of this and `kms-key-id` may be set
+    pub local_key: Option<Sensitive<String>>,
+
+    /// AWS KMS key to have S3 encrypt checkpoint objects with (SSE-KMS); requires an S3
+    /// checkpoint URL
+    pub sse_kms_key_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct InternalTlsConfig {
+    /// Whether gRPC traffic to and from the controller, nodes and workers, and data sent between
+    /// workers, is encrypted with TLS, with both sides presenting certificates signed by `ca-file`
+    pub enabled: bool,
+
+    /// PEM certificate chain this service presents to its peers
+    pub cert_file: Option<PathBuf>,
+
+    /// PEM private key for `cert-file`
+    pub key_file: Option<PathBuf>,
+
+    /// PEM CA certificates that peers' certificates must be signed by
+    pub ca_file: Option<PathBuf>,
+
+    /// Name that servers' certificates are verified against, rather than the host that's
+    /// connected to; set this when services address each other by IP
+    pub server_name: Option<String>,
+
+    /// How often the certificate files are checked for changes; rotated certificates are used
+    /// for new connections
+    pub reload_interval: HumanReadableDuration,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SecretsConfig {
+    /// How often cached secrets are re-fetched to pick up rotations; set to 0s to disable
+    pub refresh_interval: HumanReadableDuration,
+
+    /// HashiCorp Vault configuration, used for `vault://` secrets
+    #[serde(default)]
+    pub vault: VaultConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct VaultConfig {
+    /// Address of the Vault server; defaults to the VAULT_ADDR environment variable
+    pub address: Option<String>,
+
+    /// Token used to authenticate to Vault; defaults to the VAULT_TOKEN environment variable
+    pub token: Option<Sensitive<String>>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct LogConfig {
     /// Set the log format
     #[serde(default)]
     pub format: LogFormat,
+
+    /// Export traces to an OpenTelemetry collector
+    pub tracing: TracingConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TracingConfig {
+    /// Whether to export traces over OTLP
+    pub enabled: bool,
+
+    /// The OTLP gRPC endpoint of the collector (for example, Jaeger or Tempo)
+    pub endpoint: String,
+
+    /// The fraction of traces to sample, between 0 and 1. Traces that are continued from an
+    /// upstream context follow the upstream sampling decision.
+    pub sample_ratio: f64,
+
+    /// Whether to continue traces from the W3C trace context (`traceparent`) headers of
+    /// incoming Kafka messages, carry it through the pipeline, and attach it to the messages
+    /// written by Kafka sinks
+    pub propagate_context: bool,
+}
+
+impl TracingConfig {
+    /// Whether trace context should be propagated through pipelines
+    pub fn propagation_enabled(&self) -> bool {
+        self.enabled && self.propagate_context
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -690,6 +1101,25 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_checkpoint_replica_failover() {
+        figment::Jail::expect_with(|jail| {
+            jail.set_env("ARROYO__CHECKPOINT_URL", "s3://primary/checkpoints");
+            jail.set_env(
+                "ARROYO__CHECKPOINT_REPLICATION__URL",
+                "s3://replica/checkpoints",
+            );
+
+            let config: Config = load_config(&vec![]).extract().unwrap();
+            assert_eq!(config.checkpoint_storage_url(), "s3://primary/checkpoints");
+
+            jail.set_env("ARROYO__CHECKPOINT_REPLICATION__FAILOVER", "true");
+            let config: Config = load_config(&vec![]).extract().unwrap();
+            assert_eq!(config.checkpoint_storage_url(), "s3://replica/checkpoints");
+            Ok(())
+        });
+    }
+
     #[test]
     fn test_sensitive_config() {
         figment::Jail::expect_with(|jail| {
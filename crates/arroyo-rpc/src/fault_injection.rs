@@ -0,0 +1,228 @@
+//! Fault injection for testing how pipelines recover from failures.
+//!
+//! Faults are registered with a worker either by its configuration (`fault-injection.faults`),
+//! in which case they apply for as long as the worker runs, or on demand through the API, which
+//! the controller forwards to each of a job's workers. The engine checks for matching faults at
+//! a few points (when a subtask processes or emits a batch, before it writes its state for a
+//! checkpoint, and when batches arrive over the network), and each matching fault fires with its
+//! configured probability until it has fired `count` times.
+//!
+//! Nothing is injected unless `fault-injection.enabled` is set.
+
+use crate::config::{config, HumanReadableDuration};
+use crate::grpc;
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Prefix of the panic message when a subtask is killed by an injected fault
+pub const INJECTED_FAULT: &str = "injected fault";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FaultKind {
+    /// Panics a subtask the next time it processes or emits a batch
+    KillSubtask,
+    /// Delays writing a subtask's state for a checkpoint
+    DelayCheckpoint,
+    /// Drops a data batch received from another worker
+    DropNetworkBatch,
+    /// Pauses a source before it emits a batch
+    StallSource,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FaultSpec {
+    pub kind: FaultKind,
+
+    /// The operator to inject the fault into; all operators if not set. Not supported for
+    /// network faults, which apply to every exchange into the worker.
+    #[serde(default)]
+    pub operator_id: Option<String>,
+
+    /// The subtask of the operator to inject the fault into; all subtasks if not set
+    #[serde(default)]
+    pub subtask_index: Option<u32>,
+
+    /// The probability that the fault fires each time it's checked
+    #[serde(default = "default_probability")]
+    pub probability: f64,
+
+    /// How many times the fault may fire; unlimited if not set
+    #[serde(default)]
+    pub count: Option<u32>,
+
+    /// For delays and stalls, how long to pause for
+    #[serde(default)]
+    pub duration: Option<HumanReadableDuration>,
+}
+
+fn default_probability() -> f64 {
+    1.0
+}
+
+impl FaultSpec {
+    fn matches(&self, kind: FaultKind, operator_id: Option<&str>, subtask_index: usize) -> bool {
+        self.kind == kind
+            && self
+                .operator_id
+                .as_ref()
+                .map(|id| Some(id.as_str()) == operator_id)
+                .unwrap_or(true)
+            && self
+                .subtask_index
+                .map(|idx| idx as usize == subtask_index)
+                .unwrap_or(true)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration.as_deref().copied().unwrap_or_default()
+    }
+}
+
+impl From<FaultKind> for grpc::FaultKind {
+    fn from(kind: FaultKind) -> Self {
+        match kind {
+            FaultKind::KillSubtask => grpc::FaultKind::KillSubtask,
+            FaultKind::DelayCheckpoint => grpc::FaultKind::DelayCheckpoint,
+            FaultKind::DropNetworkBatch => grpc::FaultKind::DropNetworkBatch,
+            FaultKind::StallSource => grpc::FaultKind::StallSource,
+        }
+    }
+}
+
+impl From<grpc::FaultKind> for FaultKind {
+    fn from(kind: grpc::FaultKind) -> Self {
+        match kind {
+            grpc::FaultKind::KillSubtask => FaultKind::KillSubtask,
+            grpc::FaultKind::DelayCheckpoint => FaultKind::DelayCheckpoint,
+            grpc::FaultKind::DropNetworkBatch => FaultKind::DropNetworkBatch,
+            grpc::FaultKind::StallSource => FaultKind::StallSource,
+        }
+    }
+}
+
+impl From<FaultSpec> for grpc::FaultSpec {
+    fn from(spec: FaultSpec) -> Self {
+        let duration_micros = spec.duration().as_micros() as u64;
+        Self {
+            kind: grpc::FaultKind::from(spec.kind) as i32,
+            operator_id: spec.operator_id,
+            subtask_index: spec.subtask_index,
+            probability: spec.probability,
+            count: spec.count,
+            duration_micros,
+        }
+    }
+}
+
+impl From<grpc::FaultSpec> for FaultSpec {
+    fn from(spec: grpc::FaultSpec) -> Self {
+        Self {
+            kind: spec.kind().into(),
+            operator_id: spec.operator_id,
+            subtask_index: spec.subtask_index,
+            probability: spec.probability,
+            count: spec.count,
+            duration: Some(HumanReadableDuration::from(Duration::from_micros(
+                spec.duration_micros,
+            ))),
+        }
+    }
+}
+
+struct ActiveFault {
+    spec: FaultSpec,
+    remaining: Option<u32>,
+}
+
+fn faults() -> &'static Mutex<Vec<ActiveFault>> {
+    static FAULTS: OnceLock<Mutex<Vec<ActiveFault>>> = OnceLock::new();
+    FAULTS.get_or_init(|| {
+        Mutex::new(
+            config()
+                .fault_injection
+                .faults
+                .iter()
+                .map(|spec| ActiveFault {
+                    spec: spec.clone(),
+                    remaining: spec.count,
+                })
+                .collect(),
+        )
+    })
+}
+
+pub fn enabled() -> bool {
+    config().fault_injection.enabled
+}
+
+/// Registers a fault with this process
+pub fn inject(spec: FaultSpec) -> anyhow::Result<()> {
+    if !enabled() {
+        anyhow::bail!("fault injection is not enabled");
+    }
+
+    info!(message = "registering fault", fault = ?spec);
+    faults().lock().unwrap().push(ActiveFault {
+        remaining: spec.count,
+        spec,
+    });
+    Ok(())
+}
+
+/// Removes all faults registered with this process, including those from its configuration
+pub fn clear() {
+    faults().lock().unwrap().clear();
+}
+
+/// Returns the first registered fault of `kind` that matches the subtask and fires
+pub fn check(
+    kind: FaultKind,
+    operator_id: Option<&str>,
+    subtask_index: usize,
+) -> Option<FaultSpec> {
+    if !enabled() {
+        return None;
+    }
+
+    let mut faults = faults().lock().unwrap();
+    let idx = faults.iter().position(|f| {
+        f.remaining != Some(0)
+            && f.spec.matches(kind, operator_id, subtask_index)
+            && rand::random::<f64>() < f.spec.probability
+    })?;
+
+    let fault = &mut faults[idx];
+    let spec = fault.spec.clone();
+    if let Some(remaining) = &mut fault.remaining {
+        *remaining -= 1;
+    }
+    if fault.remaining == Some(0) {
+        faults.remove(idx);
+    }
+
+    warn!(
+        message = "injecting fault",
+        kind = ?kind,
+        operator_id,
+        subtask_index
+    );
+    Some(spec)
+}
+
+/// Panics if a `KillSubtask` fault fires for the subtask
+pub fn maybe_kill(operator_id: &str, subtask_index: usize) {
+    if check(FaultKind::KillSubtask, Some(operator_id), subtask_index).is_some() {
+        panic!("{INJECTED_FAULT}: killing subtask {operator_id}-{subtask_index}");
+    }
+}
+
+/// Sleeps if a fault of `kind` fires for the subtask
+pub async fn maybe_pause(kind: FaultKind, operator_id: &str, subtask_index: usize) {
+    if let Some(fault) = check(kind, Some(operator_id), subtask_index) {
+        tokio::time::sleep(fault.duration()).await;
+    }
+}
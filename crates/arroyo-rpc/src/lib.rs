@@ -1,27 +1,46 @@
 pub mod api_types;
+pub mod fault_injection;
 pub mod formats;
 pub mod public_ids;
+pub mod quantities;
+pub mod schedule;
 pub mod schema_resolver;
+pub mod secrets;
 pub mod var_str;
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{fs, time::SystemTime};
 
 use crate::api_types::connections::PrimitiveType;
+use crate::api_types::pipelines::ErrorCategory;
+use crate::config::RpcCompression;
 use crate::formats::{BadData, Format, Framing};
+use crate::grpc::compiler_grpc_client::CompilerGrpcClient;
+use crate::grpc::compiler_grpc_server::CompilerGrpcServer;
+use crate::grpc::controller_grpc_client::ControllerGrpcClient;
+use crate::grpc::controller_grpc_server::ControllerGrpcServer;
+use crate::grpc::node_grpc_client::NodeGrpcClient;
+use crate::grpc::node_grpc_server::NodeGrpcServer;
+use crate::grpc::worker_grpc_client::WorkerGrpcClient;
+use crate::grpc::worker_grpc_server::WorkerGrpcServer;
 use crate::grpc::{LoadCompactedDataReq, SubtaskCheckpointMetadata};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use arrow::row::{OwnedRow, RowConverter, Rows, SortField};
 use arrow_array::{Array, ArrayRef, BooleanArray};
 use arrow_schema::DataType;
 use arroyo_types::{CheckpointBarrier, HASH_SEEDS};
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
 use grpc::{StopMode, TableCheckpointMetadata, TaskCheckpointEventType};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tonic::{
+    codec::CompressionEncoding,
     metadata::{Ascii, MetadataValue},
     service::Interceptor,
+    transport::Channel,
 };
 
 pub mod config;
@@ -53,6 +72,16 @@ pub enum ControlMessage {
     LoadCompacted {
         compacted: CompactionResult,
     },
+    /// Sent to the watermark operators of a watermark-alignment group, bounding how far their
+    /// watermark may advance before they stop reading their inputs
+    AlignWatermark {
+        max_watermark: SystemTime,
+    },
+    /// Changes the operator's runtime parameters, which have already been validated against
+    /// those it supports
+    UpdateParameters {
+        parameters: HashMap<String, f64>,
+    },
     NoOp,
 }
 
@@ -104,12 +133,14 @@ pub enum ControlResp {
         operator_id: String,
         task_index: usize,
         error: String,
+        category: ErrorCategory,
     },
     Error {
         operator_id: String,
         task_index: usize,
         message: String,
         details: String,
+        category: ErrorCategory,
     },
 }
 
@@ -142,6 +173,72 @@ impl Interceptor for FileAuthInterceptor {
     }
 }
 
+/// gRPC clients and servers whose messages can be compressed
+pub trait Compressed: Sized {
+    fn send_compressed(self, encoding: CompressionEncoding) -> Self;
+
+    fn accept_compressed(self, encoding: CompressionEncoding) -> Self;
+
+    /// Accepts gzip and zstd-compressed messages, and compresses the messages it sends with the
+    /// configured encoding
+    fn compressed(self) -> Self {
+        let this = self
+            .accept_compressed(CompressionEncoding::Gzip)
+            .accept_compressed(CompressionEncoding::Zstd);
+
+        match config::config().compression.grpc {
+            RpcCompression::None => this,
+            RpcCompression::Gzip => this.send_compressed(CompressionEncoding::Gzip),
+            RpcCompression::Zstd => this.send_compressed(CompressionEncoding::Zstd),
+        }
+    }
+}
+
+macro_rules! impl_compressed {
+    ($client:ty, $server:ident, $service:path) => {
+        impl Compressed for $client {
+            fn send_compressed(self, encoding: CompressionEncoding) -> Self {
+                self.send_compressed(encoding)
+            }
+
+            fn accept_compressed(self, encoding: CompressionEncoding) -> Self {
+                self.accept_compressed(encoding)
+            }
+        }
+
+        impl<T: $service> Compressed for $server<T> {
+            fn send_compressed(self, encoding: CompressionEncoding) -> Self {
+                self.send_compressed(encoding)
+            }
+
+            fn accept_compressed(self, encoding: CompressionEncoding) -> Self {
+                self.accept_compressed(encoding)
+            }
+        }
+    };
+}
+
+impl_compressed!(
+    ControllerGrpcClient<Channel>,
+    ControllerGrpcServer,
+    grpc::controller_grpc_server::ControllerGrpc
+);
+impl_compressed!(
+    WorkerGrpcClient<Channel>,
+    WorkerGrpcServer,
+    grpc::worker_grpc_server::WorkerGrpc
+);
+impl_compressed!(
+    NodeGrpcClient<Channel>,
+    NodeGrpcServer,
+    grpc::node_grpc_server::NodeGrpc
+);
+impl_compressed!(
+    CompilerGrpcClient<Channel>,
+    CompilerGrpcServer,
+    grpc::compiler_grpc_server::CompilerGrpc
+);
+
 pub fn primitive_to_sql(primitive_type: PrimitiveType) -> &'static str {
     match primitive_type {
         PrimitiveType::Int32 => "INTEGER",
@@ -166,6 +263,63 @@ pub struct RateLimit {
     pub messages_per_second: u32,
 }
 
+/// The guarantee a sink provides about how many times each row is written to its destination
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliverySemantics {
+    /// Writes only become visible once the checkpoint covering them has completed
+    #[default]
+    ExactlyOnce,
+    /// Writes are made durable as part of each checkpoint, without waiting for it to complete
+    /// across the pipeline, so rows may be written again when restoring
+    AtLeastOnce,
+    /// Writes are not waited on by checkpoints and failed writes are dropped, so rows may be lost
+    AtMostOnce,
+}
+
+/// Configures a source to track the event time of each of its partitions (or shards) separately,
+/// emitting as its watermark the minimum across the partitions that aren't idle. This keeps a
+/// lagging partition from having its data dropped as late because other partitions are ahead.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PartitionWatermarks {
+    /// How far each partition's watermark trails the latest timestamp read from it
+    pub lateness_micros: u64,
+    /// How long a partition may go without data before it's considered idle and stops holding
+    /// back the watermark
+    pub idle_time_micros: Option<u64>,
+}
+
+impl DeliverySemantics {
+    pub fn from_opts(opts: &mut HashMap<String, String>) -> Result<Option<Self>, String> {
+        let Some(semantics) = opts.remove("delivery_semantics") else {
+            return Ok(None);
+        };
+
+        Ok(Some(match semantics.as_str() {
+            "exactly_once" => DeliverySemantics::ExactlyOnce,
+            "at_least_once" => DeliverySemantics::AtLeastOnce,
+            "at_most_once" => DeliverySemantics::AtMostOnce,
+            s => {
+                return Err(format!(
+                    "unknown delivery semantics '{}'; expected one of 'exactly_once', \
+                    'at_least_once', or 'at_most_once'",
+                    s
+                ))
+            }
+        }))
+    }
+}
+
+impl std::fmt::Display for DeliverySemantics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DeliverySemantics::ExactlyOnce => "exactly_once",
+            DeliverySemantics::AtLeastOnce => "at_least_once",
+            DeliverySemantics::AtMostOnce => "at_most_once",
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct OperatorConfig {
     pub connection: Value,
@@ -174,6 +328,22 @@ pub struct OperatorConfig {
     pub bad_data: Option<BadData>,
     pub framing: Option<Framing>,
     pub rate_limit: Option<RateLimit>,
+    /// A predicate pushed down into the source by the planner, as a base64-encoded datafusion-proto
+    /// `LogicalExprNode`. Sources may use it to skip data that can't match, but aren't required to;
+    /// the query still applies the predicate to every row that the source emits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// The delivery guarantee requested for a sink, for sinks that support configuring it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delivery_semantics: Option<DeliverySemantics>,
+    /// For sinks that support upserts, the fields that identify a row. Rows written to the sink
+    /// replace earlier rows with the same key, and retractions delete them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub upsert_key: Vec<String>,
+    /// For sources that support it, generate watermarks in the source for each partition
+    /// separately, rather than in a watermark operator over the source's combined output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_watermarks: Option<PartitionWatermarks>,
 }
 
 impl Default for OperatorConfig {
@@ -185,6 +355,91 @@ impl Default for OperatorConfig {
             bad_data: None,
             framing: None,
             rate_limit: None,
+            filter: None,
+            delivery_semantics: None,
+            upsert_key: vec![],
+            partition_watermarks: None,
+        }
+    }
+}
+
+impl OperatorConfig {
+    pub fn set_filter(&mut self, filter: &[u8]) {
+        self.filter = Some(BASE64_STANDARD.encode(filter));
+    }
+
+    pub fn filter_bytes(&self) -> Result<Option<Vec<u8>>> {
+        self.filter
+            .as_ref()
+            .map(|f| {
+                BASE64_STANDARD
+                    .decode(f)
+                    .context("pushed-down filter is not valid base64")
+            })
+            .transpose()
+    }
+}
+
+/// Controls how rows are grouped into record batches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchSettings {
+    /// The maximum number of rows in a batch
+    pub max_rows: usize,
+    /// How long a source may hold buffered rows while waiting for a batch to fill
+    pub linger: Duration,
+    /// Whether sources may grow their batches beyond these settings while their outputs are
+    /// backpressured
+    pub adaptive: bool,
+}
+
+/// How far adaptive batching may grow a source's batch size and linger beyond its settings
+pub const MAX_ADAPTIVE_BATCH_FACTOR: u32 = 16;
+// outputs fuller than this are considered backpressured, and emptier than this have headroom
+const ADAPTIVE_GROW_UTILIZATION: f64 = 0.75;
+const ADAPTIVE_SHRINK_UTILIZATION: f64 = 0.25;
+const MIN_ADAPTIVE_LINGER: Duration = Duration::from_millis(1);
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        Self {
+            max_rows: config::config().pipeline.source_batch_size,
+            linger: *config::config().pipeline.source_batch_linger,
+            adaptive: config::config().pipeline.source_batch_adaptive,
+        }
+    }
+}
+
+impl BatchSettings {
+    /// Whether a buffer holding `size` rows, the first of which was buffered at `since`, should
+    /// be flushed
+    pub fn should_flush(&self, size: usize, since: Instant) -> bool {
+        size > 0 && (size >= self.max_rows || since.elapsed() >= self.linger)
+    }
+
+    /// For adaptive batching, the settings to use for the next batch given these (current) settings,
+    /// the configured settings, and the fraction of the source's output queues that is full. Under
+    /// backpressure batches are doubled, trading latency for per-batch overhead, up to
+    /// [`MAX_ADAPTIVE_BATCH_FACTOR`] times the configured settings; once the queues drain they're
+    /// halved back down to the configured settings.
+    pub fn adapt(&self, configured: &BatchSettings, queue_utilization: f64) -> BatchSettings {
+        if queue_utilization >= ADAPTIVE_GROW_UTILIZATION {
+            BatchSettings {
+                max_rows: (self.max_rows * 2)
+                    .min(configured.max_rows * MAX_ADAPTIVE_BATCH_FACTOR as usize),
+                linger: (self.linger * 2)
+                    .max(MIN_ADAPTIVE_LINGER)
+                    .min(configured.linger * MAX_ADAPTIVE_BATCH_FACTOR)
+                    .max(configured.linger),
+                ..*configured
+            }
+        } else if queue_utilization <= ADAPTIVE_SHRINK_UTILIZATION {
+            BatchSettings {
+                max_rows: (self.max_rows / 2).max(configured.max_rows),
+                linger: (self.linger / 2).max(configured.linger),
+                ..*configured
+            }
+        } else {
+            *self
         }
     }
 }
@@ -198,6 +453,10 @@ pub fn error_chain(e: anyhow::Error) -> String {
 
 pub const TIMESTAMP_FIELD: &str = "_timestamp";
 pub const IS_RETRACT_FIELD: &str = "_is_retract";
+/// Columns added to the input of a sink to hold the message key and partition computed by its
+/// `key_expression` and `partition_expression`
+pub const SINK_KEY_FIELD: &str = "_sink_key";
+pub const SINK_PARTITION_FIELD: &str = "_sink_partition";
 // need to handle the empty case as a row converter without sort fields emits empty Rows.
 #[derive(Debug)]
 pub enum Converter {
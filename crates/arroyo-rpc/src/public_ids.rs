@@ -22,6 +22,15 @@ pub enum IdTypes {
     ConnectionTable,
     ConnectionTablePipeline,
     Udf,
+    Namespace,
+    AuditLogEntry,
+    PipelineSchedule,
+    ScheduledRun,
+    CatalogTable,
+    PipelineTemplate,
+    NotificationTarget,
+    Model,
+    BundleApply,
 }
 
 pub fn generate_id(id_type: IdTypes) -> String {
@@ -38,6 +47,15 @@ pub fn generate_id(id_type: IdTypes) -> String {
         IdTypes::ConnectionTable => "ct",
         IdTypes::ConnectionTablePipeline => "ctp",
         IdTypes::Udf => "udf",
+        IdTypes::Namespace => "ns",
+        IdTypes::AuditLogEntry => "al",
+        IdTypes::PipelineSchedule => "ps",
+        IdTypes::ScheduledRun => "sr",
+        IdTypes::CatalogTable => "cat",
+        IdTypes::PipelineTemplate => "tpl",
+        IdTypes::NotificationTarget => "nt",
+        IdTypes::Model => "mdl",
+        IdTypes::BundleApply => "ba",
     };
     let id = nanoid!(ID_LENGTH, &ALPHABET);
     format!("{}_{}", prefix, id)
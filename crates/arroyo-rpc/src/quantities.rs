@@ -1,3 +1,6 @@
+//! Parsing of Kubernetes resource quantities, used to size worker pods and to validate the
+//! resources requested for a pipeline's workers
+
 use anyhow::{anyhow, bail, Result};
 use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use regex::Regex;
@@ -94,7 +97,6 @@ pub enum ParsedQuantity {
 }
 
 impl ParsedQuantity {
-    #[allow(unused)]
     pub fn value(&self) -> i128 {
         match self {
             ParsedQuantity::Cpu(i) => *i as i128,
@@ -174,6 +176,23 @@ impl QuantityParser for Quantity {
     }
 }
 
+/// Checks that `value` is a valid, non-negative quantity of the resource `name`; cpu is measured
+/// in cores, and every other resource (memory, storage, devices) in plain or SI-suffixed units
+pub fn validate_resource_quantity(name: &str, value: &str) -> Result<()> {
+    let quantity = Quantity(value.to_string());
+    let parsed = if name == "cpu" {
+        quantity.parse_cpu()?
+    } else {
+        quantity.parse_memory()?
+    };
+
+    if parsed.value() < 0 {
+        bail!("quantity {} is negative", value);
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 #[allow(non_snake_case)]
 mod tests {
@@ -303,4 +322,15 @@ mod tests {
         test_mem("50");
         test_mem("100Ki");
     }
+
+    #[test]
+    fn test_validate_resource_quantity() {
+        assert!(validate_resource_quantity("cpu", "500m").is_ok());
+        assert!(validate_resource_quantity("memory", "4Gi").is_ok());
+        assert!(validate_resource_quantity("nvidia.com/gpu", "1").is_ok());
+
+        assert!(validate_resource_quantity("cpu", "2Gi").is_err());
+        assert!(validate_resource_quantity("memory", "lots").is_err());
+        assert!(validate_resource_quantity("memory", "-1Gi").is_err());
+    }
 }
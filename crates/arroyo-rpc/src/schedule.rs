@@ -0,0 +1,59 @@
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Parses a cron expression for a pipeline schedule. Both standard five-field expressions
+/// (`min hour day month weekday`) and six-field expressions with a leading seconds field are
+/// accepted.
+pub fn parse_cron(expr: &str) -> anyhow::Result<Schedule> {
+    let fields = expr.split_whitespace().count();
+
+    let expr = match fields {
+        5 => format!("0 {}", expr.trim()),
+        6 => expr.trim().to_string(),
+        _ => bail!(
+            "invalid cron expression '{}': expected 5 or 6 fields, found {}",
+            expr,
+            fields
+        ),
+    };
+
+    Schedule::from_str(&expr).map_err(|e| anyhow!("invalid cron expression '{}': {}", expr, e))
+}
+
+/// Returns the first time strictly after `after` that matches the schedule
+pub fn next_run_after(schedule: &Schedule, after: SystemTime) -> Option<SystemTime> {
+    let after: DateTime<Utc> = after.into();
+    schedule.after(&after).next().map(|t| t.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_cron() {
+        assert!(parse_cron("0 * * * *").is_ok());
+        assert!(parse_cron("30 0 * * * *").is_ok());
+        assert!(parse_cron("0 0 * *").is_err());
+        assert!(parse_cron("not a cron expression").is_err());
+    }
+
+    #[test]
+    fn test_next_run_after() {
+        // every day at 02:00 UTC
+        let schedule = parse_cron("0 2 * * *").unwrap();
+
+        // 2024-01-01T00:00:00Z
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1704067200);
+
+        let next = next_run_after(&schedule, start).unwrap();
+        assert_eq!(next, start + Duration::from_secs(2 * 60 * 60));
+
+        let next = next_run_after(&schedule, next).unwrap();
+        assert_eq!(next, start + Duration::from_secs(26 * 60 * 60));
+    }
+}
@@ -344,6 +344,74 @@ impl ConfluentSchemaRegistry {
             .context(format!("subject '{}'", self.subject))
     }
 
+    /// Whether the schema could be registered as a new version of the subject under its
+    /// compatibility settings. Any schema is compatible with a subject that doesn't exist yet.
+    pub async fn is_compatible(
+        &self,
+        schema: impl Into<String>,
+        schema_type: ConfluentSchemaType,
+    ) -> anyhow::Result<bool> {
+        #[derive(Deserialize)]
+        struct CompatibilityResponse {
+            is_compatible: bool,
+        }
+
+        let url = self
+            .client
+            .endpoint
+            .join(&format!(
+                "compatibility/subjects/{}/versions/latest",
+                self.subject
+            ))
+            .unwrap();
+
+        let resp = self
+            .client
+            .client
+            .post(url)
+            .json(&PostSchemaRequest {
+                schema: schema.into(),
+                schema_type,
+            })
+            .send()
+            .await
+            .map_err(|e| {
+                warn!("Got error response from schema registry: {:?}", e);
+                anyhow!(
+                    "could not connect to Schema Registry at {}: unknown error",
+                    self.client.endpoint
+                )
+            })?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let bytes = resp.bytes().await.map(|b| b.to_vec()).unwrap_or_default();
+            match serde_json::from_slice::<RegistryErrorResponse>(&bytes) {
+                // subject or version not found
+                Ok(e) if e.error_code == 40401 || e.error_code == 40402 => return Ok(true),
+                Ok(e) => bail!(
+                    "schema registry returned error {} checking compatibility with subject '{}': {}",
+                    status.as_u16(),
+                    self.subject,
+                    e.message
+                ),
+                Err(_) => bail!(
+                    "schema registry returned error {} checking compatibility with subject '{}': {}",
+                    status.as_u16(),
+                    self.subject,
+                    String::from_utf8_lossy(&bytes)
+                ),
+            }
+        }
+
+        let resp: CompatibilityResponse = resp
+            .json()
+            .await
+            .map_err(|e| anyhow!("could not parse response from schema registry: {}", e))?;
+
+        Ok(resp.is_compatible)
+    }
+
     pub async fn get_schema_for_id(
         &self,
         id: u32,
@@ -0,0 +1,453 @@
+//! Resolution of secrets referenced from connection configs.
+//!
+//! Rather than storing credentials directly in connection profiles and tables, configs may
+//! reference a secret held in an external secret manager using `{{ secret:<uri> }}` in any
+//! field that supports environment variable substitution. Supported URIs are:
+//!
+//! * `env://NAME` -- the environment variable `NAME`
+//! * `vault://<path>#<key>` -- the field `key` of the secret at `path` in HashiCorp Vault
+//!   (KV v1 or v2)
+//! * `aws-sm://<secret-id>[#<key>]` -- an AWS Secrets Manager secret, optionally extracting
+//!   `key` from a JSON secret
+//! * `gcp-sm://projects/<project>/secrets/<secret>[/versions/<version>][#<key>]` -- a GCP
+//!   Secret Manager secret, optionally extracting `key` from a JSON secret
+//!
+//! Secrets are fetched when a pipeline is started on a worker (and when connections are tested)
+//! and cached in-process. The cache is periodically refreshed so that rotated secrets are picked
+//! up by connectors the next time they (re)connect.
+
+use crate::config::config;
+use anyhow::{anyhow, bail, Context};
+use base64::Engine;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Once, OnceLock, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+const GCP_METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SecretUri {
+    Env {
+        name: String,
+    },
+    Vault {
+        path: String,
+        key: String,
+    },
+    AwsSecretsManager {
+        secret_id: String,
+        key: Option<String>,
+    },
+    GcpSecretManager {
+        name: String,
+        key: Option<String>,
+    },
+}
+
+impl FromStr for SecretUri {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s
+            .split_once("://")
+            .ok_or_else(|| anyhow!("invalid secret URI '{}': missing scheme", s))?;
+
+        let (path, key) = match rest.split_once('#') {
+            Some((path, key)) if !key.is_empty() => (path, Some(key.to_string())),
+            Some((path, _)) => (path, None),
+            None => (rest, None),
+        };
+
+        if path.is_empty() {
+            bail!("invalid secret URI '{}': missing path", s);
+        }
+
+        Ok(match scheme {
+            "env" => SecretUri::Env {
+                name: path.to_string(),
+            },
+            "vault" => SecretUri::Vault {
+                path: path.trim_matches('/').to_string(),
+                key: key.ok_or_else(|| {
+                    anyhow!("invalid secret URI '{}': vault secrets require a #key", s)
+                })?,
+            },
+            "aws-sm" => SecretUri::AwsSecretsManager {
+                secret_id: path.to_string(),
+                key,
+            },
+            "gcp-sm" => {
+                let name = if path.contains("/versions/") {
+                    path.to_string()
+                } else {
+                    format!("{}/versions/latest", path.trim_end_matches('/'))
+                };
+
+                if !name.starts_with("projects/") {
+                    bail!(
+                        "invalid secret URI '{}': expected gcp-sm://projects/<project>/secrets/<secret>",
+                        s
+                    );
+                }
+
+                SecretUri::GcpSecretManager { name, key }
+            }
+            scheme => bail!("unsupported secret provider '{}' in '{}'", scheme, s),
+        })
+    }
+}
+
+impl Display for SecretUri {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretUri::Env { name } => write!(f, "env://{}", name),
+            SecretUri::Vault { path, key } => write!(f, "vault://{}#{}", path, key),
+            SecretUri::AwsSecretsManager { secret_id, key } => {
+                write!(f, "aws-sm://{}", secret_id)?;
+                if let Some(key) = key {
+                    write!(f, "#{}", key)?;
+                }
+                Ok(())
+            }
+            SecretUri::GcpSecretManager { name, key } => {
+                write!(f, "gcp-sm://{}", name)?;
+                if let Some(key) = key {
+                    write!(f, "#{}", key)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl SecretUri {
+    pub async fn fetch(&self) -> anyhow::Result<String> {
+        match self {
+            SecretUri::Env { name } => {
+                std::env::var(name).map_err(|_| anyhow!("environment variable {} not found", name))
+            }
+            SecretUri::Vault { path, key } => fetch_vault(path, key).await,
+            SecretUri::AwsSecretsManager { secret_id, key } => {
+                let secret = fetch_aws(secret_id).await?;
+                extract_key(secret, key.as_deref())
+            }
+            SecretUri::GcpSecretManager { name, key } => {
+                let secret = fetch_gcp(name).await?;
+                extract_key(secret, key.as_deref())
+            }
+        }
+    }
+}
+
+fn extract_key(secret: String, key: Option<&str>) -> anyhow::Result<String> {
+    let Some(key) = key else {
+        return Ok(secret);
+    };
+
+    let value: Value =
+        serde_json::from_str(&secret).context("secret is not a JSON object, cannot extract key")?;
+
+    json_field(&value, key)
+}
+
+fn json_field(value: &Value, key: &str) -> anyhow::Result<String> {
+    match value.get(key) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(v) if !v.is_null() => Ok(v.to_string()),
+        _ => bail!("secret does not contain key '{}'", key),
+    }
+}
+
+async fn fetch_vault(path: &str, key: &str) -> anyhow::Result<String> {
+    let vault = &config().secrets.vault;
+
+    let address = vault
+        .address
+        .clone()
+        .or_else(|| std::env::var("VAULT_ADDR").ok())
+        .ok_or_else(|| anyhow!("no vault address configured (set secrets.vault.address)"))?;
+
+    let token = vault
+        .token
+        .as_ref()
+        .map(|t| (**t).clone())
+        .or_else(|| std::env::var("VAULT_TOKEN").ok())
+        .ok_or_else(|| anyhow!("no vault token configured (set secrets.vault.token)"))?;
+
+    let resp = reqwest::Client::new()
+        .get(format!("{}/v1/{}", address.trim_end_matches('/'), path))
+        .header("X-Vault-Token", token)
+        .send()
+        .await?
+        .error_for_status()
+        .with_context(|| format!("failed to read vault secret '{}'", path))?;
+
+    let body: Value = resp.json().await?;
+    let data = &body["data"];
+
+    // KV v2 nests the secret's fields under data.data, while KV v1 returns them directly in data
+    match data.get("data").filter(|d| d.is_object()) {
+        Some(inner) => json_field(inner, key),
+        None => json_field(data, key),
+    }
+}
+
+async fn fetch_aws(secret_id: &str) -> anyhow::Result<String> {
+    let config = aws_config::load_from_env().await;
+    let client = aws_sdk_secretsmanager::Client::new(&config);
+
+    let resp = client
+        .get_secret_value()
+        .secret_id(secret_id)
+        .send()
+        .await
+        .with_context(|| format!("failed to read AWS secret '{}'", secret_id))?;
+
+    if let Some(s) = resp.secret_string() {
+        return Ok(s.to_string());
+    }
+
+    let binary = resp
+        .secret_binary()
+        .ok_or_else(|| anyhow!("AWS secret '{}' has no value", secret_id))?;
+
+    String::from_utf8(binary.as_ref().to_vec())
+        .map_err(|_| anyhow!("AWS secret '{}' is not valid UTF-8", secret_id))
+}
+
+async fn gcp_access_token(client: &reqwest::Client) -> anyhow::Result<String> {
+    if let Ok(token) = std::env::var("GOOGLE_OAUTH_ACCESS_TOKEN") {
+        return Ok(token);
+    }
+
+    let resp: Value = client
+        .get(GCP_METADATA_TOKEN_URL)
+        .header("Metadata-Flavor", "Google")
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .context("failed to fetch GCP access token from metadata server")?
+        .json()
+        .await?;
+
+    resp["access_token"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow!("GCP metadata server did not return an access token"))
+}
+
+async fn fetch_gcp(name: &str) -> anyhow::Result<String> {
+    let client = reqwest::Client::new();
+    let token = gcp_access_token(&client).await?;
+
+    let resp: Value = client
+        .get(format!(
+            "https://secretmanager.googleapis.com/v1/{}:access",
+            name
+        ))
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()
+        .with_context(|| format!("failed to read GCP secret '{}'", name))?
+        .json()
+        .await?;
+
+    let data = resp["payload"]["data"]
+        .as_str()
+        .ok_or_else(|| anyhow!("GCP secret '{}' has no payload", name))?;
+
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .context("GCP secret payload is not valid base64")?;
+
+    String::from_utf8(bytes).map_err(|_| anyhow!("GCP secret '{}' is not valid UTF-8", name))
+}
+
+fn cache() -> &'static RwLock<HashMap<SecretUri, String>> {
+    static CACHE: OnceLock<RwLock<HashMap<SecretUri, String>>> = OnceLock::new();
+    CACHE.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn secret_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\{\{\s*secret:([^\s}]+)\s*}}").unwrap())
+}
+
+/// Finds all of the secret references (`{{ secret:<uri> }}`) in `text`
+pub fn secret_references(text: &str) -> anyhow::Result<Vec<SecretUri>> {
+    secret_regex()
+        .captures_iter(text)
+        .map(|c| c.get(1).unwrap().as_str().parse())
+        .collect()
+}
+
+/// Replaces all secret references in `text` with their cached values; fails if any of the
+/// referenced secrets have not been resolved with [`resolve_secrets`]
+pub fn substitute_secrets(text: &str) -> anyhow::Result<String> {
+    let cache = cache().read().unwrap();
+
+    let mut err = None;
+    let result = secret_regex().replace_all(text, |caps: &regex::Captures| {
+        let uri = caps.get(1).unwrap().as_str();
+        match uri.parse::<SecretUri>() {
+            Ok(parsed) => match cache.get(&parsed) {
+                Some(value) => value.clone(),
+                None => {
+                    err.get_or_insert_with(|| anyhow!("secret {} has not been resolved", uri));
+                    String::new()
+                }
+            },
+            Err(e) => {
+                err.get_or_insert(e);
+                String::new()
+            }
+        }
+    });
+
+    match err {
+        Some(e) => Err(e),
+        None => Ok(result.into_owned()),
+    }
+}
+
+/// Fetches every secret referenced in `text` that is not already cached, so that it can later
+/// be substituted synchronously
+pub async fn resolve_secrets(text: &str) -> anyhow::Result<()> {
+    for uri in secret_references(text)? {
+        if cache().read().unwrap().contains_key(&uri) {
+            continue;
+        }
+
+        let value = uri
+            .fetch()
+            .await
+            .with_context(|| format!("failed to resolve secret {}", uri))?;
+
+        cache().write().unwrap().insert(uri, value);
+    }
+
+    Ok(())
+}
+
+async fn refresh_secrets() {
+    let uris: Vec<_> = cache().read().unwrap().keys().cloned().collect();
+
+    for uri in uris {
+        match uri.fetch().await {
+            Ok(value) => {
+                let mut cache = cache().write().unwrap();
+                if cache.get(&uri) != Some(&value) {
+                    info!("secret {} has been rotated", uri);
+                    cache.insert(uri, value);
+                }
+            }
+            Err(e) => {
+                // keep using the previous value; it may still be valid
+                warn!("failed to refresh secret {}: {:?}", uri, e);
+            }
+        }
+    }
+}
+
+/// Starts a background task that periodically re-fetches all cached secrets so that rotations
+/// in the secret manager are picked up; only the first call in a process has any effect
+pub fn start_secret_refresher() {
+    static STARTED: Once = Once::new();
+
+    let interval = *config().secrets.refresh_interval;
+    if interval.is_zero() {
+        return;
+    }
+
+    STARTED.call_once(|| {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // the first tick completes immediately
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                refresh_secrets().await;
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_secret_uri() {
+        assert_eq!(
+            SecretUri::from_str("env://KAFKA_PASSWORD").unwrap(),
+            SecretUri::Env {
+                name: "KAFKA_PASSWORD".to_string()
+            }
+        );
+
+        assert_eq!(
+            SecretUri::from_str("vault://secret/data/kafka#password").unwrap(),
+            SecretUri::Vault {
+                path: "secret/data/kafka".to_string(),
+                key: "password".to_string()
+            }
+        );
+
+        assert_eq!(
+            SecretUri::from_str("aws-sm://prod/kafka#password").unwrap(),
+            SecretUri::AwsSecretsManager {
+                secret_id: "prod/kafka".to_string(),
+                key: Some("password".to_string())
+            }
+        );
+
+        assert_eq!(
+            SecretUri::from_str("gcp-sm://projects/p/secrets/kafka").unwrap(),
+            SecretUri::GcpSecretManager {
+                name: "projects/p/secrets/kafka/versions/latest".to_string(),
+                key: None
+            }
+        );
+
+        assert!(SecretUri::from_str("vault://secret/data/kafka").is_err());
+        assert!(SecretUri::from_str("gcp-sm://kafka").is_err());
+        assert!(SecretUri::from_str("s3://bucket/key").is_err());
+        assert!(SecretUri::from_str("KAFKA_PASSWORD").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_and_substitute() {
+        std::env::set_var("SECRETS_TEST_PASSWORD", "hunter2");
+        let text = "user:{{ secret:env://SECRETS_TEST_PASSWORD }}";
+
+        assert!(substitute_secrets(text).is_err());
+
+        resolve_secrets(text).await.unwrap();
+        assert_eq!(substitute_secrets(text).unwrap(), "user:hunter2");
+
+        assert!(resolve_secrets("{{ secret:env://SECRETS_TEST_MISSING }}")
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_extract_key() {
+        let secret = r#"{"username": "arroyo", "port": 9092}"#.to_string();
+        assert_eq!(
+            extract_key(secret.clone(), Some("username")).unwrap(),
+            "arroyo"
+        );
+        assert_eq!(extract_key(secret.clone(), Some("port")).unwrap(), "9092");
+        assert!(extract_key(secret.clone(), Some("password")).is_err());
+        assert_eq!(extract_key(secret.clone(), None).unwrap(), secret);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::secrets::substitute_secrets;
 use anyhow::bail;
 use regex::Regex;
 use serde::de::Visitor;
@@ -52,6 +53,15 @@ impl VarStr {
         VarStr { raw_val }
     }
 
+    /// The value before substitution, which references environment variables and secrets
+    /// rather than containing them
+    pub fn raw_val(&self) -> &str {
+        &self.raw_val
+    }
+
+    /// Substitutes environment variables (`{{ VAR_NAME }}`) and secret references
+    /// (`{{ secret:<uri> }}`); secrets must already have been fetched with
+    /// [`crate::secrets::resolve_secrets`]
     pub fn sub_env_vars(&self) -> anyhow::Result<String> {
         // Regex to match patterns like {{ VAR_NAME }}
         static RE: OnceLock<Regex> = OnceLock::new();
@@ -71,7 +81,7 @@ impl VarStr {
             }
         }
 
-        Ok(result)
+        substitute_secrets(&result)
     }
 }
 
@@ -110,4 +120,10 @@ mod tests {
             expected
         );
     }
+
+    #[test]
+    fn test_unresolved_secret() {
+        let input = "password: {{ secret:env://VAR_STR_UNRESOLVED_SECRET }}";
+        assert!(VarStr::new(input.to_string()).sub_env_vars().is_err());
+    }
 }
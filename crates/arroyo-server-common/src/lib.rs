@@ -1,34 +1,49 @@
 #![allow(clippy::type_complexity)]
 
+pub mod profiling;
 pub mod shutdown;
+pub mod tls;
 
 use anyhow::anyhow;
 use arroyo_types::POSTHOG_KEY;
 use axum::body::Bytes;
-use axum::extract::State;
-use axum::http::StatusCode;
+use axum::extract::{Query, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::Router;
 use hyper::Body;
 use lazy_static::lazy_static;
 use once_cell::sync::OnceCell;
+use profiling::CpuProfileFormat;
 use prometheus::{register_int_counter, Encoder, IntCounter, ProtobufEncoder, TextEncoder};
 use reqwest::Client;
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::error::Error;
 use std::future::Future;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
+use subtle::ConstantTimeEq;
 use tonic::body::BoxBody;
 use tonic::transport::Server;
 use tower::layer::util::Stack;
 use tower::{Layer, Service};
 use tower_http::classify::{GrpcCode, GrpcErrorsAsFailures, SharedClassifier};
+use tower_http::compression::CompressionLayer;
 use tower_http::trace::{DefaultOnFailure, TraceLayer};
 
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::Sampler;
+use opentelemetry_sdk::Resource;
 use tracing::metadata::LevelFilter;
 use tracing::{debug, info, span, Level};
+use tracing_subscriber::filter::filter_fn;
 use tracing_subscriber::fmt::format::{FmtSpan, Format};
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
@@ -44,7 +59,67 @@ pub const VERSION: &str = "0.11.0-dev";
 
 static CLUSTER_ID: OnceCell<String> = OnceCell::new();
 
-pub fn init_logging(_name: &str) -> WorkerGuard {
+/// Flushes buffered logs and exports any pending traces when dropped
+pub struct LogGuard {
+    _logs: WorkerGuard,
+    tracing: bool,
+}
+
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        if self.tracing {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+/// Builds a layer that exports spans from Arroyo crates to the configured OTLP collector, if
+/// tracing is enabled
+fn otlp_layer(name: &str) -> Option<impl tracing_subscriber::Layer<Registry> + Send + Sync> {
+    let tracing_config = &config().logging.tracing;
+    if !tracing_config.enabled {
+        return None;
+    }
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&tracing_config.endpoint),
+        )
+        .with_trace_config(
+            opentelemetry_sdk::trace::config()
+                .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                    tracing_config.sample_ratio,
+                ))))
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    format!("arroyo-{}", name),
+                )])),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio);
+
+    match tracer {
+        Ok(tracer) => Some(
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(filter_fn(|metadata| {
+                    // export all of our spans, but only the more important events within them
+                    metadata.target().starts_with("arroyo")
+                        && (metadata.is_span() || *metadata.level() <= Level::INFO)
+                })),
+        ),
+        Err(e) => {
+            eprintln!("Failed to initialize OTLP trace exporter {:?}", e);
+            None
+        }
+    }
+}
+
+pub fn init_logging(name: &str) -> LogGuard {
     if let Err(e) = LogTracer::init() {
         eprintln!("Failed to initialize log tracer {:?}", e);
     }
@@ -56,10 +131,13 @@ pub fn init_logging(_name: &str) -> WorkerGuard {
 
     let (nonblocking, guard) = tracing_appender::non_blocking(std::io::stdout());
 
+    let otlp = otlp_layer(name);
+    let tracing = otlp.is_some();
+
     match config().logging.format {
         LogFormat::Plaintext => {
             tracing::subscriber::set_global_default(
-                Registry::default().with(
+                Registry::default().with(otlp).with(
                     tracing_subscriber::fmt::layer()
                         .with_line_number(false)
                         .with_file(false)
@@ -72,7 +150,7 @@ pub fn init_logging(_name: &str) -> WorkerGuard {
         }
         LogFormat::Logfmt => {
             tracing::subscriber::set_global_default(
-                Registry::default().with(
+                Registry::default().with(otlp).with(
                     tracing_subscriber::fmt::layer()
                         .event_format(tracing_logfmt::EventsFormatter)
                         .fmt_fields(tracing_logfmt::FieldsFormatter)
@@ -84,7 +162,7 @@ pub fn init_logging(_name: &str) -> WorkerGuard {
         }
         LogFormat::Json => {
             tracing::subscriber::set_global_default(
-                Registry::default().with(
+                Registry::default().with(otlp).with(
                     tracing_subscriber::fmt::layer()
                         .event_format(Format::default().json())
                         .with_writer(nonblocking)
@@ -108,7 +186,10 @@ pub fn init_logging(_name: &str) -> WorkerGuard {
         }
     }));
 
-    guard
+    LogGuard {
+        _logs: guard,
+        tracing,
+    }
 }
 
 pub fn set_cluster_id(cluster_id: &str) {
@@ -191,6 +272,79 @@ async fn config_route() -> Result<String, StatusCode> {
     Ok(toml::to_string(&*config()).unwrap())
 }
 
+fn check_admin_auth(headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let Some(token) = &config().admin.auth_token else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "profiling endpoints are disabled; set admin.auth-token to enable them".to_string(),
+        ));
+    };
+
+    if !bearer_token_matches(headers, token) {
+        return Err((StatusCode::UNAUTHORIZED, "invalid admin token".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Whether the request's bearer token is `token`, compared in constant time so that the token
+/// can't be recovered from response timings
+fn bearer_token_matches(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|provided| provided.as_bytes().ct_eq(token.as_bytes()).into())
+}
+
+#[derive(Deserialize)]
+struct CpuProfileParams {
+    seconds: Option<u64>,
+    format: Option<String>,
+}
+
+async fn cpu_profile(
+    headers: HeaderMap,
+    Query(params): Query<CpuProfileParams>,
+) -> Result<Response, (StatusCode, String)> {
+    check_admin_auth(&headers)?;
+
+    let (format, content_type) = match params.format.as_deref() {
+        None | Some("flamegraph") => (CpuProfileFormat::Flamegraph, "image/svg+xml"),
+        Some("pprof") => (CpuProfileFormat::Pprof, "application/octet-stream"),
+        Some(f) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "unknown profile format '{}'; expected flamegraph or pprof",
+                    f
+                ),
+            ))
+        }
+    };
+
+    let duration = params
+        .seconds
+        .map(Duration::from_secs)
+        .unwrap_or(profiling::DEFAULT_CPU_PROFILE_DURATION);
+
+    let data = profiling::cpu_profile(duration, format)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    Ok(([(CONTENT_TYPE, content_type)], data).into_response())
+}
+
+async fn heap_profile(headers: HeaderMap) -> Result<Response, (StatusCode, String)> {
+    check_admin_auth(&headers)?;
+
+    let data = profiling::heap_profile()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    Ok(([(CONTENT_TYPE, "application/octet-stream")], data).into_response())
+}
+
 async fn details<'a>(State(state): State<Arc<AdminState>>) -> String {
     serde_json::to_string_pretty(&json!({
         "service": state.name,
@@ -201,6 +355,15 @@ async fn details<'a>(State(state): State<Arc<AdminState>>) -> String {
     .unwrap()
 }
 
+/// Compresses responses with gzip or zstd for clients that accept it, if enabled in the config
+pub fn compress_responses(router: Router) -> Router {
+    if config().compression.http {
+        router.layer(CompressionLayer::new())
+    } else {
+        router
+    }
+}
+
 pub async fn start_admin_server(service: &str) -> anyhow::Result<()> {
     let addr = config().admin.bind_address;
     let port = config().admin.http_port;
@@ -217,7 +380,10 @@ pub async fn start_admin_server(service: &str) -> anyhow::Result<()> {
         .route("/metrics.pb", get(metrics_proto))
         .route("/details", get(details))
         .route("/config", get(config_route))
+        .route("/debug/pprof/profile", get(cpu_profile))
+        .route("/debug/pprof/heap", get(heap_profile))
         .with_state(state);
+    let app = compress_responses(app);
 
     let addr = SocketAddr::new(addr, port);
 
@@ -344,3 +510,24 @@ pub async fn wrap_start(
         )
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_bearer_token_matches() {
+        let headers = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, HeaderValue::from_static(value));
+            headers
+        };
+
+        assert!(bearer_token_matches(&headers("Bearer secret"), "secret"));
+        assert!(!bearer_token_matches(&headers("Bearer secre"), "secret"));
+        assert!(!bearer_token_matches(&headers("Bearer secrets"), "secret"));
+        assert!(!bearer_token_matches(&headers("secret"), "secret"));
+        assert!(!bearer_token_matches(&HeaderMap::new(), "secret"));
+    }
+}
@@ -0,0 +1,88 @@
+//! On-demand CPU and heap profiling of the current process.
+//!
+//! CPU profiles are collected by sampling stack traces for a fixed duration, and can be rendered
+//! as a flamegraph SVG or as a pprof protobuf. Heap profiles require the process to be built with
+//! the `jemalloc-profiling` feature, which replaces the system allocator with jemalloc and enables
+//! its sampling heap profiler; they are returned as gzipped pprof protobufs.
+
+use anyhow::{anyhow, bail};
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+pub const DEFAULT_CPU_PROFILE_DURATION: Duration = Duration::from_secs(30);
+pub const MAX_CPU_PROFILE_DURATION: Duration = Duration::from_secs(5 * 60);
+const CPU_SAMPLE_FREQUENCY: i32 = 99;
+
+#[cfg(feature = "jemalloc-profiling")]
+#[global_allocator]
+static ALLOC: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+// sample an allocation every 512KiB on average
+#[cfg(feature = "jemalloc-profiling")]
+#[allow(non_upper_case_globals)]
+#[export_name = "malloc_conf"]
+pub static malloc_conf: &[u8] = b"prof:true,prof_active:true,lg_prof_sample:19\0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuProfileFormat {
+    Flamegraph,
+    Pprof,
+}
+
+/// Samples the CPU usage of the process for `duration`. Only one CPU profile may be collected at
+/// a time.
+pub async fn cpu_profile(duration: Duration, format: CpuProfileFormat) -> anyhow::Result<Vec<u8>> {
+    static PROFILING: Mutex<()> = Mutex::const_new(());
+
+    if duration.is_zero() || duration > MAX_CPU_PROFILE_DURATION {
+        bail!(
+            "profile duration must be between 1 second and {} seconds",
+            MAX_CPU_PROFILE_DURATION.as_secs()
+        );
+    }
+
+    let _lock = PROFILING
+        .try_lock()
+        .map_err(|_| anyhow!("a CPU profile is already being collected"))?;
+
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(CPU_SAMPLE_FREQUENCY)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?;
+
+    tokio::time::sleep(duration).await;
+
+    let report = guard.report().build()?;
+    let mut buf = vec![];
+    match format {
+        CpuProfileFormat::Flamegraph => {
+            report.flamegraph(&mut buf)?;
+        }
+        CpuProfileFormat::Pprof => {
+            use pprof::protos::Message;
+            report.pprof()?.encode(&mut buf)?;
+        }
+    }
+
+    Ok(buf)
+}
+
+/// Dumps the allocations sampled by the heap profiler that are currently live
+#[cfg(feature = "jemalloc-profiling")]
+pub async fn heap_profile() -> anyhow::Result<Vec<u8>> {
+    let prof_ctl = jemalloc_pprof::PROF_CTL
+        .as_ref()
+        .ok_or_else(|| anyhow!("the jemalloc heap profiler is not available"))?;
+
+    let mut prof_ctl = prof_ctl.lock().await;
+    if !prof_ctl.activated() {
+        bail!("the jemalloc heap profiler is not active");
+    }
+
+    prof_ctl.dump_pprof()
+}
+
+#[cfg(not(feature = "jemalloc-profiling"))]
+pub async fn heap_profile() -> anyhow::Result<Vec<u8>> {
+    bail!("heap profiling requires a build with the jemalloc-profiling feature")
+}
@@ -0,0 +1,541 @@
+//! Mutual TLS for internal traffic: gRPC between the API, controller, nodes and workers, and the
+//! data sent between workers.
+//!
+//! When `internal-tls.enabled` is set, every internal server requires clients to present a
+//! certificate signed by the configured CA, and every client verifies the server's certificate
+//! against it (by the configured `server-name`, or otherwise the host it connects to). The
+//! certificate, key and CA files are checked for changes every `reload-interval`; rotated
+//! certificates are used for new connections, while existing connections keep the certificates
+//! they were established with.
+
+use anyhow::{anyhow, bail, Context};
+use arroyo_rpc::config::{config, InternalTlsConfig};
+use futures::Stream;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::{client, server, TlsAcceptor, TlsConnector};
+use tonic::transport::server::{Connected, TcpConnectInfo};
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
+use tracing::{info, warn};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn read_certs(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("failed to read certificates from {}", path.display()))?;
+
+    if certs.is_empty() {
+        bail!("no certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+fn load_configs(
+    config: &InternalTlsConfig,
+) -> anyhow::Result<(Arc<ServerConfig>, Arc<ClientConfig>)> {
+    let (Some(cert_file), Some(key_file), Some(ca_file)) =
+        (&config.cert_file, &config.key_file, &config.ca_file)
+    else {
+        bail!("internal-tls requires cert-file, key-file and ca-file to be set");
+    };
+
+    let certs = read_certs(cert_file)?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(
+        File::open(key_file).with_context(|| format!("failed to open {}", key_file.display()))?,
+    ))?
+    .ok_or_else(|| anyhow!("no private key found in {}", key_file.display()))?;
+
+    let mut roots = RootCertStore::empty();
+    for cert in read_certs(ca_file)? {
+        roots.add(cert)?;
+    }
+    let roots = Arc::new(roots);
+
+    let verifier =
+        WebPkiClientVerifier::builder_with_provider(roots.clone(), crypto_provider()).build()?;
+    let server = ServerConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certs.clone(), key.clone_key())?;
+    let client = ClientConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_client_auth_cert(certs, key)?;
+
+    Ok((Arc::new(server), Arc::new(client)))
+}
+
+/// The modification times of the certificate files, which are compared to detect rotations
+fn modified(config: &InternalTlsConfig) -> Vec<Option<SystemTime>> {
+    [&config.cert_file, &config.key_file, &config.ca_file]
+        .into_iter()
+        .map(|f| f.as_ref()?.metadata().ok()?.modified().ok())
+        .collect()
+}
+
+struct TlsState {
+    server: Arc<ServerConfig>,
+    client: Arc<ClientConfig>,
+    modified: Vec<Option<SystemTime>>,
+    checked: Instant,
+}
+
+struct Tls {
+    state: RwLock<TlsState>,
+}
+
+impl Tls {
+    fn load(config: &InternalTlsConfig) -> anyhow::Result<Self> {
+        let modified = modified(config);
+        let (server, client) = load_configs(config)?;
+        Ok(Self {
+            state: RwLock::new(TlsState {
+                server,
+                client,
+                modified,
+                checked: Instant::now(),
+            }),
+        })
+    }
+
+    /// The current server and client configs, reloading them first if the files have changed
+    fn current(&self, config: &InternalTlsConfig) -> (Arc<ServerConfig>, Arc<ClientConfig>) {
+        {
+            let state = self.state.read().unwrap();
+            if state.checked.elapsed() < *config.reload_interval {
+                return (state.server.clone(), state.client.clone());
+            }
+        }
+
+        let mut state = self.state.write().unwrap();
+        if state.checked.elapsed() >= *config.reload_interval {
+            state.checked = Instant::now();
+            let modified = modified(config);
+            if modified != state.modified {
+                match load_configs(config) {
+                    Ok((server, client)) => {
+                        info!("Reloaded internal TLS certificates");
+                        state.server = server;
+                        state.client = client;
+                        state.modified = modified;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to reload internal TLS certificates, continuing with the \
+                            previous ones: {:?}",
+                            e
+                        );
+                    }
+                }
+            }
+        }
+
+        (state.server.clone(), state.client.clone())
+    }
+}
+
+static TLS: OnceLock<Option<Tls>> = OnceLock::new();
+
+fn tls() -> Option<&'static Tls> {
+    TLS.get_or_init(|| {
+        let config = &config().internal_tls;
+        config.enabled.then(|| {
+            Tls::load(config)
+                .unwrap_or_else(|e| panic!("invalid internal-tls configuration: {:?}", e))
+        })
+    })
+    .as_ref()
+}
+
+/// Whether internal traffic uses mutual TLS
+pub fn enabled() -> bool {
+    tls().is_some()
+}
+
+macro_rules! delegate_io {
+    ($t:ident) => {
+        impl AsyncRead for $t {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut TaskContext<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                match self.get_mut() {
+                    $t::Plain(s) => Pin::new(s).poll_read(cx, buf),
+                    $t::Tls(s) => Pin::new(s).poll_read(cx, buf),
+                }
+            }
+        }
+
+        impl AsyncWrite for $t {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut TaskContext<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                match self.get_mut() {
+                    $t::Plain(s) => Pin::new(s).poll_write(cx, buf),
+                    $t::Tls(s) => Pin::new(s).poll_write(cx, buf),
+                }
+            }
+
+            fn poll_write_vectored(
+                self: Pin<&mut Self>,
+                cx: &mut TaskContext<'_>,
+                bufs: &[io::IoSlice<'_>],
+            ) -> Poll<io::Result<usize>> {
+                match self.get_mut() {
+                    $t::Plain(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+                    $t::Tls(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+                }
+            }
+
+            fn is_write_vectored(&self) -> bool {
+                match self {
+                    $t::Plain(s) => s.is_write_vectored(),
+                    $t::Tls(s) => s.is_write_vectored(),
+                }
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+                match self.get_mut() {
+                    $t::Plain(s) => Pin::new(s).poll_flush(cx),
+                    $t::Tls(s) => Pin::new(s).poll_flush(cx),
+                }
+            }
+
+            fn poll_shutdown(
+                self: Pin<&mut Self>,
+                cx: &mut TaskContext<'_>,
+            ) -> Poll<io::Result<()>> {
+                match self.get_mut() {
+                    $t::Plain(s) => Pin::new(s).poll_shutdown(cx),
+                    $t::Tls(s) => Pin::new(s).poll_shutdown(cx),
+                }
+            }
+        }
+
+        impl $t {
+            /// The underlying TCP connection
+            pub fn get_ref(&self) -> &TcpStream {
+                match self {
+                    $t::Plain(s) => s,
+                    $t::Tls(s) => s.get_ref().0,
+                }
+            }
+        }
+    };
+}
+
+/// A connection accepted by an internal server
+pub enum ServerStream {
+    Plain(TcpStream),
+    Tls(Box<server::TlsStream<TcpStream>>),
+}
+
+delegate_io!(ServerStream);
+
+impl Connected for ServerStream {
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.get_ref().connect_info()
+    }
+}
+
+/// A connection to an internal server
+pub enum ClientStream {
+    Plain(TcpStream),
+    Tls(Box<client::TlsStream<TcpStream>>),
+}
+
+delegate_io!(ClientStream);
+
+/// Performs the server side of the TLS handshake on an accepted connection, if TLS is enabled
+pub async fn accept(stream: TcpStream) -> io::Result<ServerStream> {
+    let Some(tls) = tls() else {
+        return Ok(ServerStream::Plain(stream));
+    };
+
+    let (server, _) = tls.current(&config().internal_tls);
+    let stream = TlsAcceptor::from(server).accept(stream).await?;
+    Ok(ServerStream::Tls(Box::new(stream)))
+}
+
+/// Performs the client side of the TLS handshake on a connection to `host`, if TLS is enabled
+pub async fn connect(stream: TcpStream, host: &str) -> io::Result<ClientStream> {
+    let Some(tls) = tls() else {
+        return Ok(ClientStream::Plain(stream));
+    };
+
+    let name = config()
+        .internal_tls
+        .server_name
+        .clone()
+        .unwrap_or_else(|| {
+            host.trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string()
+        });
+    let name =
+        ServerName::try_from(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let (_, client) = tls.current(&config().internal_tls);
+    let stream = TlsConnector::from(client).connect(name, stream).await?;
+    Ok(ClientStream::Tls(Box::new(stream)))
+}
+
+fn accept_loop(
+    listener: impl Future<Output = io::Result<TcpListener>> + Send + 'static,
+) -> impl Stream<Item = io::Result<ServerStream>> {
+    let (tx, rx) = mpsc::channel(16);
+
+    tokio::spawn(async move {
+        let listener = match listener.await {
+            Ok(listener) => listener,
+            Err(e) => {
+                let _ = tx.send(Err(e)).await;
+                return;
+            }
+        };
+
+        while !tx.is_closed() {
+            let (stream, addr) = match listener.accept().await {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            // handshakes happen off of the accept loop, so that slow clients can't block others
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Ok(Err(e)) => warn!("TLS handshake with {} failed: {}", addr, e),
+                    Err(_) => warn!("Timed out waiting for TLS handshake from {}", addr),
+                }
+            });
+        }
+    });
+
+    futures::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|s| (s, rx)) })
+}
+
+/// Incoming connections for a gRPC server (with `serve_with_incoming`) on `listener`
+pub fn incoming(listener: TcpListener) -> impl Stream<Item = io::Result<ServerStream>> {
+    accept_loop(async move { Ok(listener) })
+}
+
+/// Incoming connections for a gRPC server (with `serve_with_incoming`) bound to `addr`; if it
+/// can't be bound, the stream returns the error
+pub fn bind(addr: SocketAddr) -> impl Stream<Item = io::Result<ServerStream>> {
+    accept_loop(TcpListener::bind(addr))
+}
+
+/// Connects to an internal gRPC server at `endpoint`
+pub async fn connect_channel(endpoint: Endpoint) -> Result<Channel, tonic::transport::Error> {
+    if !enabled() {
+        return endpoint.connect().await;
+    }
+
+    endpoint
+        .connect_with_connector(service_fn(|uri: Uri| async move {
+            let host = uri
+                .host()
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("no host in {}", uri))
+                })?
+                .to_string();
+            let port = uri.port_u16().unwrap_or(80);
+
+            let stream = TcpStream::connect((host.trim_matches(&['[', ']'][..]), port)).await?;
+            connect(stream, &host).await
+        }))
+        .await
+}
+
+/// Connects to an internal gRPC server at `addr`, which is an `http://` URL
+pub async fn connect_grpc(addr: impl Into<String>) -> Result<Channel, tonic::transport::Error> {
+    connect_channel(Endpoint::from_shared(addr.into())?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use std::path::PathBuf;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    struct Ca {
+        cert: rcgen::Certificate,
+        key: KeyPair,
+    }
+
+    fn ca() -> Ca {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        Ca { cert, key }
+    }
+
+    /// Writes `ca` and a certificate for localhost signed by it to `dir`, as the config expects
+    fn write_certs(dir: &Path, ca: &Ca) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&key, &ca.cert, &ca.key)
+            .unwrap();
+
+        let files = [
+            ("ca.pem", ca.cert.pem()),
+            ("cert.pem", cert.pem()),
+            ("key.pem", key.serialize_pem()),
+        ];
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::write(&path, contents).unwrap();
+            // make sure the rotation is visible even if the filesystem's mtimes are coarse
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() + Duration::from_secs(60))
+                .unwrap();
+        }
+    }
+
+    fn tls_config(dir: &Path) -> InternalTlsConfig {
+        InternalTlsConfig {
+            enabled: true,
+            cert_file: Some(dir.join("cert.pem")),
+            key_file: Some(dir.join("key.pem")),
+            ca_file: Some(dir.join("ca.pem")),
+            server_name: None,
+            reload_interval: Duration::ZERO.into(),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("arroyo-tls-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Connects a client using `client` to a server using `server` and exchanges a message each
+    /// way, returning whether the server accepted the connection
+    async fn handshake(server: Arc<ServerConfig>, client: Arc<ClientConfig>) -> bool {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = TlsAcceptor::from(server).accept(stream).await?;
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await?;
+            stream.write_all(b"pong").await?;
+            stream.flush().await?;
+            io::Result::Ok(buf)
+        });
+
+        let client = async move {
+            let stream = TcpStream::connect(addr).await?;
+            let mut stream = TlsConnector::from(client)
+                .connect(ServerName::try_from("localhost").unwrap(), stream)
+                .await?;
+            stream.write_all(b"ping").await?;
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await?;
+            io::Result::Ok(buf)
+        };
+
+        let (server, client) = tokio::join!(server, client);
+        match server.unwrap() {
+            Ok(received) => {
+                assert_eq!(&received, b"ping");
+                assert_eq!(&client.unwrap(), b"pong");
+                true
+            }
+            Err(_) => {
+                assert!(client.is_err());
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        let dir = temp_dir("mutual");
+        let ca = ca();
+        write_certs(&dir, &ca);
+        let config = tls_config(&dir);
+
+        let tls = Tls::load(&config).unwrap();
+        let (server, client) = tls.current(&config);
+
+        assert!(handshake(server.clone(), client).await);
+
+        // a client that trusts the server but doesn't present a certificate is refused
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.cert.der().clone()).unwrap();
+        let anonymous = ClientConfig::builder_with_provider(crypto_provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        assert!(!handshake(server, Arc::new(anonymous)).await);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reload_rotated_certificates() {
+        let dir = temp_dir("rotate");
+        write_certs(&dir, &ca());
+        let config = tls_config(&dir);
+
+        let tls = Tls::load(&config).unwrap();
+        let (old_server, old_client) = tls.current(&config);
+
+        // unchanged files aren't reloaded
+        let (server, _) = tls.current(&config);
+        assert!(Arc::ptr_eq(&old_server, &server));
+
+        write_certs(&dir, &ca());
+        let (server, client) = tls.current(&config);
+        assert!(!Arc::ptr_eq(&old_server, &server));
+
+        // new connections use the rotated certificates, which no longer trust the old CA
+        assert!(handshake(server.clone(), client).await);
+        assert!(!handshake(server, old_client).await);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -252,6 +252,7 @@ async fn run_and_checkpoint(job_id: Arc<String>, program: Program, checkpoint_in
     let (running_engine, mut control_rx) = engine
         .start(StreamConfig {
             restore_epoch: None,
+            replay: None,
         })
         .await;
     info!("Smoke test checkpointing enabled");
@@ -296,6 +297,7 @@ async fn finish_from_checkpoint(job_id: &str, program: Program) {
     let (running_engine, mut control_rx) = engine
         .start(StreamConfig {
             restore_epoch: Some(3),
+            replay: None,
         })
         .await;
 
@@ -358,6 +360,7 @@ async fn run_completely(
     let (running_engine, mut control_rx) = engine
         .start(StreamConfig {
             restore_epoch: None,
+            replay: None,
         })
         .await;
 
@@ -0,0 +1,103 @@
+//! Hydrates keyed state tables from bounded Parquet snapshots when a pipeline first starts, so
+//! that operators don't need to re-read the full history of their sources
+
+use crate::tables::expiring_time_key_map::ExpiringTimeKeyTable;
+use crate::tables::{ErasedTable, Table};
+use anyhow::{bail, Context, Result};
+use arrow::compute::cast;
+use arrow_array::{ArrayRef, RecordBatch, TimestampNanosecondArray, UInt64Array};
+use arroyo_rpc::df::ArroyoSchema;
+use arroyo_rpc::grpc::{
+    ExpiringKeyedTimeTableConfig, TableCheckpointMetadata, TableConfig, TableEnum,
+};
+use arroyo_storage::{StorageProvider, StorageProviderRef};
+use arroyo_types::{to_nanos, TaskInfoRef};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use prost::Message;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::info;
+
+/// Casts the columns of a snapshot batch to the table's schema, matching them by name. Rows
+/// without a timestamp are stamped with `now`, and rows without a generation get generation 0.
+fn conform_batch(
+    batch: &RecordBatch,
+    schema: &ArroyoSchema,
+    now: SystemTime,
+) -> Result<RecordBatch> {
+    let columns = schema
+        .schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            if let Some((idx, _)) = batch.schema().column_with_name(field.name()) {
+                return cast(batch.column(idx), field.data_type()).with_context(|| {
+                    format!("could not convert snapshot column '{}'", field.name())
+                });
+            }
+
+            Ok(if i == schema.timestamp_index {
+                Arc::new(TimestampNanosecondArray::from(vec![
+                    to_nanos(now) as i64;
+                    batch.num_rows()
+                ])) as ArrayRef
+            } else if field.name() == "_generation" {
+                Arc::new(UInt64Array::from(vec![0; batch.num_rows()]))
+            } else {
+                bail!("snapshot is missing column '{}'", field.name());
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(RecordBatch::try_new(schema.schema.clone(), columns)?)
+}
+
+/// Loads the snapshot at `url` into `table`, returning the metadata to restore the table from.
+/// Only the rows whose keys belong to this subtask are written.
+pub(crate) async fn bootstrap_table(
+    table: &str,
+    table_config: &TableConfig,
+    url: &str,
+    task_info: TaskInfoRef,
+    storage: StorageProviderRef,
+) -> Result<Option<TableCheckpointMetadata>> {
+    let table_type = table_config.table_type();
+    if table_type != TableEnum::ExpiringKeyedTimeTable {
+        bail!(
+            "table '{}' can't be bootstrapped; only keyed time tables support bootstrapping",
+            table
+        );
+    }
+
+    let config: ExpiringKeyedTimeTableConfig =
+        ExpiringTimeKeyTable::checked_proto_decode(table_type, table_config.config.clone())?;
+    let expiring_table =
+        <ExpiringTimeKeyTable as Table>::from_config(config, task_info, storage, None)?;
+    let schema = expiring_table.memory_schema();
+
+    let data = StorageProvider::get_url(url)
+        .await
+        .with_context(|| format!("failed to read bootstrap snapshot {}", url))?;
+
+    let now = SystemTime::now();
+    let batches = ParquetRecordBatchReaderBuilder::try_new(data)
+        .with_context(|| format!("bootstrap snapshot {} is not a valid Parquet file", url))?
+        .build()?
+        .map(|batch| conform_batch(&batch?, &schema, now))
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| format!("failed to load bootstrap snapshot {}", url))?;
+
+    let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+    let metadata = expiring_table.write_bootstrap(&batches).await?;
+
+    info!(
+        "bootstrapped table '{}' from {} ({} rows in snapshot)",
+        table, url, rows
+    );
+
+    Ok(metadata.map(|metadata| TableCheckpointMetadata {
+        table_type: table_type.into(),
+        data: metadata.encode_to_vec(),
+    }))
+}
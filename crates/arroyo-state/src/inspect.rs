@@ -0,0 +1,241 @@
+//! Tools for inspecting the contents of checkpoints, for debugging pipelines without having to
+//! know how their state is laid out in storage
+
+use crate::parquet::ParquetBackend;
+use crate::tables::expiring_time_key_map::ExpiringTimeKeyTable;
+use crate::tables::global_keyed_map::GlobalKeyedTable;
+use crate::tables::ErasedTable;
+use crate::BackingStore;
+use anyhow::{anyhow, bail, Context, Result};
+use arrow::compute::{cast, filter_record_batch};
+use arrow::json::LineDelimitedWriter;
+use arrow_array::{Array, BooleanArray, RecordBatch, StringArray};
+use arrow_schema::DataType;
+use arroyo_rpc::config::config;
+use arroyo_rpc::df::ArroyoSchema;
+use arroyo_rpc::grpc::{
+    ExpiringKeyedTimeTableConfig, GlobalKeyedTableConfig, OperatorCheckpointMetadata, TableEnum,
+};
+use arroyo_storage::StorageProvider;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+
+#[derive(Debug, Clone)]
+pub struct TableSummary {
+    pub name: String,
+    pub table_type: String,
+    pub description: String,
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OperatorSummary {
+    pub operator_id: String,
+    pub tables: Vec<TableSummary>,
+}
+
+fn table_type_name(table_type: TableEnum) -> &'static str {
+    match table_type {
+        TableEnum::MissingTableType => "missing",
+        TableEnum::GlobalKeyValue => "global_key_value",
+        TableEnum::ExpiringKeyedTimeTable => "expiring_keyed_time",
+    }
+}
+
+fn table_summary(metadata: &OperatorCheckpointMetadata, table: &str) -> Result<TableSummary> {
+    let config = metadata
+        .table_configs
+        .get(table)
+        .ok_or_else(|| anyhow!("missing table config for table {}", table))?
+        .clone();
+    let checkpoint = metadata
+        .table_checkpoint_metadata
+        .get(table)
+        .ok_or_else(|| anyhow!("missing checkpoint metadata for table {}", table))?
+        .clone();
+
+    let table_type = config.table_type();
+    let (description, mut files): (String, Vec<String>) = match table_type {
+        TableEnum::MissingTableType => bail!("missing table type for table {}", table),
+        TableEnum::GlobalKeyValue => (
+            GlobalKeyedTable::checked_proto_decode::<GlobalKeyedTableConfig>(
+                table_type,
+                config.config.clone(),
+            )?
+            .description,
+            GlobalKeyedTable::files_to_keep(config, checkpoint)?
+                .into_iter()
+                .collect(),
+        ),
+        TableEnum::ExpiringKeyedTimeTable => (
+            ExpiringTimeKeyTable::checked_proto_decode::<ExpiringKeyedTimeTableConfig>(
+                table_type,
+                config.config.clone(),
+            )?
+            .description,
+            ExpiringTimeKeyTable::files_to_keep(config, checkpoint)?
+                .into_iter()
+                .collect(),
+        ),
+    };
+    files.sort();
+
+    Ok(TableSummary {
+        name: table.to_string(),
+        table_type: table_type_name(table_type).to_string(),
+        description,
+        files,
+    })
+}
+
+/// Lists the operators of a checkpoint along with the state tables each of them checkpointed
+pub async fn describe_checkpoint(job_id: &str, epoch: u32) -> Result<Vec<OperatorSummary>> {
+    let metadata = ParquetBackend::load_checkpoint_metadata(job_id, epoch)
+        .await
+        .with_context(|| format!("failed to load checkpoint {} for job {}", epoch, job_id))?;
+
+    let mut operators = vec![];
+    for operator_id in &metadata.operator_ids {
+        let Some(operator_metadata) =
+            ParquetBackend::load_operator_metadata(job_id, operator_id, epoch).await?
+        else {
+            continue;
+        };
+
+        let mut tables: Vec<_> = operator_metadata
+            .table_checkpoint_metadata
+            .keys()
+            .map(|table| table_summary(&operator_metadata, table))
+            .collect::<Result<_>>()?;
+        tables.sort_by(|a, b| a.name.cmp(&b.name));
+
+        operators.push(OperatorSummary {
+            operator_id: operator_id.clone(),
+            tables,
+        });
+    }
+
+    Ok(operators)
+}
+
+/// Keeps the rows of `batch` whose key columns, cast to strings and joined with commas, equal
+/// `key`
+fn filter_by_key(batch: RecordBatch, key_indices: &[usize], key: &str) -> Result<RecordBatch> {
+    let columns = key_indices
+        .iter()
+        .map(|i| {
+            let column = cast(batch.column(*i), &DataType::Utf8)?;
+            Ok(column
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .ok_or_else(|| anyhow!("failed to cast key column to strings"))?
+                .clone())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mask: BooleanArray = (0..batch.num_rows())
+        .map(|row| {
+            let row_key = columns
+                .iter()
+                .map(|c| if c.is_null(row) { "" } else { c.value(row) })
+                .collect::<Vec<_>>()
+                .join(",");
+            Some(row_key == key)
+        })
+        .collect();
+
+    Ok(filter_record_batch(&batch, &mask)?)
+}
+
+/// Reads the contents of a state table from a checkpoint. Rows of keyed time tables can be
+/// filtered to a single key, given as the values of its key columns joined with commas.
+pub async fn read_table(
+    job_id: &str,
+    epoch: u32,
+    operator_id: &str,
+    table: &str,
+    key: Option<&str>,
+) -> Result<Vec<RecordBatch>> {
+    let metadata = ParquetBackend::load_operator_metadata(job_id, operator_id, epoch)
+        .await?
+        .ok_or_else(|| {
+            anyhow!(
+                "no checkpoint for operator {} at epoch {}",
+                operator_id,
+                epoch
+            )
+        })?;
+
+    let summary = table_summary(&metadata, table)?;
+    let table_config = metadata.table_configs.get(table).unwrap().clone();
+    let is_time_table = table_config.table_type() == TableEnum::ExpiringKeyedTimeTable;
+
+    let key_indices = if is_time_table {
+        let table_config: ExpiringKeyedTimeTableConfig =
+            ExpiringTimeKeyTable::checked_proto_decode(
+                table_config.table_type(),
+                table_config.config,
+            )?;
+        let schema: ArroyoSchema = table_config
+            .schema
+            .ok_or_else(|| anyhow!("missing schema for table {}", table))?
+            .try_into()?;
+        schema.key_indices.unwrap_or_default()
+    } else {
+        vec![]
+    };
+
+    if key.is_some() && key_indices.is_empty() {
+        bail!("table {} is not keyed, so can't be filtered by key", table);
+    }
+
+    let storage = StorageProvider::for_checkpoints(config().checkpoint_storage_url()).await?;
+
+    let mut batches = vec![];
+    for file in &summary.files {
+        let data = storage.get(file.as_str()).await?;
+
+        for batch in ParquetRecordBatchReaderBuilder::try_new(data)?.build()? {
+            let mut batch = batch?;
+            if is_time_table {
+                // trim the hash and operation columns that are added when the table is written
+                let projection: Vec<_> = (0..batch.num_columns() - 2).collect();
+                batch = batch.project(&projection)?;
+            }
+
+            if let Some(key) = key {
+                batch = filter_by_key(batch, &key_indices, key)?;
+            }
+
+            if batch.num_rows() > 0 {
+                batches.push(batch);
+            }
+        }
+    }
+
+    Ok(batches)
+}
+
+/// Encodes a batch read by [`read_table`] as newline-delimited JSON, one object per row
+pub fn to_json_lines(batch: &RecordBatch) -> Result<Vec<u8>> {
+    let mut writer = LineDelimitedWriter::new(vec![]);
+    writer.write(batch)?;
+    writer.finish()?;
+    Ok(writer.into_inner())
+}
+
+/// Encodes batches read by [`read_table`] as a single Parquet file
+pub fn to_parquet(batches: &[RecordBatch]) -> Result<Vec<u8>> {
+    let Some(first) = batches.first() else {
+        bail!("table has no data");
+    };
+
+    let mut buf = vec![];
+    let mut writer = ArrowWriter::try_new(&mut buf, first.schema(), None)?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.close()?;
+
+    Ok(buf)
+}
@@ -1,9 +1,11 @@
 use anyhow::Result;
 use arrow_array::RecordBatch;
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
 use arroyo_rpc::grpc::{
     CheckpointMetadata, ExpiringKeyedTimeTableConfig, GlobalKeyedTableConfig,
     OperatorCheckpointMetadata, TableCheckpointMetadata, TableConfig, TableEnum,
 };
+use arroyo_rpc::TIMESTAMP_FIELD;
 use arroyo_types::single_item_hash_map;
 use async_trait::async_trait;
 use bincode::config::Configuration;
@@ -12,14 +14,18 @@ use bincode::{Decode, Encode};
 use arroyo_rpc::df::ArroyoSchema;
 use prost::Message;
 use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::hash::{Hash, Hasher};
 use std::ops::RangeInclusive;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
+mod bootstrap;
 pub mod checkpoint_state;
 pub mod committing_state;
+pub mod inspect;
 mod metrics;
+pub mod migrate;
 pub mod parquet;
 pub(crate) mod schemas;
 pub mod tables;
@@ -89,6 +95,42 @@ pub fn timestamp_table_config(
     }
 }
 
+/// The column of a [`keyed_state_table_config`] table holding the hash each key is routed by,
+/// which is used in place of a hash of the key columns to assign rows to subtasks
+pub const ROUTING_HASH_FIELD: &str = "_routing_hash";
+pub const KEYED_STATE_KEY_FIELD: &str = "_key";
+pub const KEYED_STATE_VALUE_FIELD: &str = "_value";
+
+/// Config for a table holding one serialized value per key, which is read with
+/// [`TableManager::get_keyed_state_table`](tables::table_manager::TableManager::get_keyed_state_table).
+/// Keys are restored to the subtask their routing hash belongs to, and keys that haven't been
+/// written for `ttl` (in event time) are dropped.
+pub fn keyed_state_table_config(
+    name: impl Into<String>,
+    description: impl Into<String>,
+    ttl: Duration,
+) -> TableConfig {
+    let schema = Schema::new(vec![
+        Field::new(ROUTING_HASH_FIELD, DataType::UInt64, false),
+        Field::new(KEYED_STATE_KEY_FIELD, DataType::Binary, false),
+        // null for keys that have been deleted
+        Field::new(KEYED_STATE_VALUE_FIELD, DataType::Binary, true),
+        Field::new(
+            TIMESTAMP_FIELD,
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            false,
+        ),
+    ]);
+
+    timestamp_table_config(
+        name,
+        description,
+        ttl,
+        true,
+        ArroyoSchema::new_keyed(Arc::new(schema), 3, vec![0, 1]),
+    )
+}
+
 #[derive(Debug, Encode, Decode, PartialEq, Eq, Clone)]
 pub struct DeleteTimeKeyOperation {
     pub timestamp: SystemTime,
@@ -147,12 +189,21 @@ pub trait BackingStore {
     /// writes the checkpoint metadata to the backing store
     async fn write_checkpoint_metadata(metadata: CheckpointMetadata) -> Result<()>;
 
-    /// cleans up a checkpoint by deleting data that is no longer needed
-    async fn cleanup_checkpoint(
+    /// deletes the data of all checkpoints of the job before the latest one (`metadata`) that are
+    /// not in `retained`, along with any other files under the job's checkpoint directory that
+    /// aren't referenced by a retained checkpoint. Returns the number of files deleted.
+    async fn cleanup_checkpoints(
         metadata: CheckpointMetadata,
-        old_min_epoch: u32,
-        new_min_epoch: u32,
-    ) -> Result<()>;
+        retained: &BTreeSet<u32>,
+    ) -> Result<usize>;
+
+    /// copies a completed checkpoint, along with all of the files it references, to the store at
+    /// `replica_url`. The checkpoint's metadata is written last, so that a checkpoint only appears
+    /// in the replica once it can be restored from. Returns the number of files copied.
+    async fn replicate_checkpoint(
+        metadata: &CheckpointMetadata,
+        replica_url: &str,
+    ) -> Result<usize>;
 }
 
 pub fn hash_key<K: Hash>(key: &K) -> u64 {
@@ -0,0 +1,321 @@
+//! Carries state across changes to a pipeline's query. Operator ids are assigned by position in
+//! the plan, so an edited query may give a stateful operator a new id (or give its old id to a
+//! different operator), which would leave its state orphaned. Migration rewrites a checkpoint of
+//! the old pipeline as a checkpoint of the new one, with its operators and tables renamed.
+
+use crate::parquet::ParquetBackend;
+use crate::tables::expiring_time_key_map::ExpiringTimeKeyTable;
+use crate::tables::global_keyed_map::GlobalKeyedTable;
+use crate::tables::ErasedTable;
+use crate::BackingStore;
+use anyhow::{anyhow, bail, Context, Result};
+use arroyo_rpc::grpc::{
+    CheckpointMetadata, ExpiringKeyedTimeTableConfig, GlobalKeyedTableConfig, TableConfig,
+    TableEnum,
+};
+use arroyo_types::to_micros;
+use prost::Message;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::SystemTime;
+use tracing::info;
+
+/// An operator of a pipeline's plan, as used to match operators between versions of a query
+#[derive(Debug, Clone)]
+pub struct PlanOperator {
+    pub operator_id: String,
+    pub operator_name: String,
+    pub description: String,
+}
+
+/// Moves the state of operator `from` in the old plan to operator `to` in the new one, renaming
+/// the tables in `tables`; tables that aren't listed keep their names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperatorMapping {
+    pub from: String,
+    pub to: String,
+    pub tables: BTreeMap<String, String>,
+}
+
+/// Decides which operator of the new plan each stateful operator of the old plan maps to.
+/// Explicit mappings take precedence; the remaining operators are matched if they have the same
+/// id, type and description, and then if exactly one unmatched operator of the new plan has the
+/// same type and description. Returns the mappings along with the old operators that couldn't be
+/// matched.
+pub fn match_operators(
+    old: &[PlanOperator],
+    new: &[PlanOperator],
+    stateful: &HashSet<String>,
+    explicit: &[OperatorMapping],
+) -> Result<(Vec<OperatorMapping>, Vec<String>)> {
+    let mut mappings = vec![];
+    let mut used: HashSet<&str> = HashSet::new();
+
+    for mapping in explicit {
+        if !old.iter().any(|op| op.operator_id == mapping.from) {
+            bail!("operator '{}' is not in the old pipeline", mapping.from);
+        }
+        if !new.iter().any(|op| op.operator_id == mapping.to) {
+            bail!("operator '{}' is not in the new pipeline", mapping.to);
+        }
+        if !used.insert(&mapping.to) {
+            bail!("operator '{}' is mapped to more than once", mapping.to);
+        }
+        mappings.push(mapping.clone());
+    }
+
+    let mut unmatched: Vec<_> = old
+        .iter()
+        .filter(|op| stateful.contains(&op.operator_id))
+        .filter(|op| !explicit.iter().any(|m| m.from == op.operator_id))
+        .collect();
+
+    let same_shape = |a: &PlanOperator, b: &PlanOperator| {
+        a.operator_name == b.operator_name && a.description == b.description
+    };
+
+    unmatched.retain(|op| {
+        match new
+            .iter()
+            .find(|n| n.operator_id == op.operator_id && same_shape(op, n))
+        {
+            Some(n) if !used.contains(n.operator_id.as_str()) => {
+                used.insert(&n.operator_id);
+                mappings.push(OperatorMapping {
+                    from: op.operator_id.clone(),
+                    to: n.operator_id.clone(),
+                    tables: BTreeMap::new(),
+                });
+                false
+            }
+            _ => true,
+        }
+    });
+
+    unmatched.retain(|op| {
+        let mut candidates = new
+            .iter()
+            .filter(|n| !used.contains(n.operator_id.as_str()) && same_shape(op, n));
+        match (candidates.next(), candidates.next()) {
+            (Some(n), None) => {
+                used.insert(&n.operator_id);
+                mappings.push(OperatorMapping {
+                    from: op.operator_id.clone(),
+                    to: n.operator_id.clone(),
+                    tables: BTreeMap::new(),
+                });
+                false
+            }
+            _ => true,
+        }
+    });
+
+    Ok((
+        mappings,
+        unmatched
+            .into_iter()
+            .map(|op| op.operator_id.clone())
+            .collect(),
+    ))
+}
+
+/// Renames a table, including the name stored in its config
+fn rename_table_config(config: TableConfig, name: &str) -> Result<TableConfig> {
+    let table_type = config.table_type();
+    let data = match table_type {
+        TableEnum::MissingTableType => bail!("missing table type for table {}", name),
+        TableEnum::GlobalKeyValue => {
+            let mut table_config: GlobalKeyedTableConfig =
+                GlobalKeyedTable::checked_proto_decode(table_type, config.config)?;
+            table_config.table_name = name.to_string();
+            table_config.encode_to_vec()
+        }
+        TableEnum::ExpiringKeyedTimeTable => {
+            let mut table_config: ExpiringKeyedTimeTableConfig =
+                ExpiringTimeKeyTable::checked_proto_decode(table_type, config.config)?;
+            table_config.table_name = name.to_string();
+            table_config.encode_to_vec()
+        }
+    };
+
+    Ok(TableConfig {
+        table_type: config.table_type,
+        config: data,
+    })
+}
+
+/// Returns the operators of a checkpoint that have state
+pub async fn stateful_operators(job_id: &str, epoch: u32) -> Result<HashSet<String>> {
+    let metadata = ParquetBackend::load_checkpoint_metadata(job_id, epoch)
+        .await
+        .with_context(|| format!("failed to load checkpoint {} for job {}", epoch, job_id))?;
+
+    let mut operators = HashSet::new();
+    for operator_id in metadata.operator_ids {
+        if ParquetBackend::load_operator_metadata(job_id, &operator_id, epoch)
+            .await?
+            .is_some_and(|m| !m.table_checkpoint_metadata.is_empty())
+        {
+            operators.insert(operator_id);
+        }
+    }
+
+    Ok(operators)
+}
+
+/// Writes checkpoint `target_epoch` of `target_job_id` with the state of checkpoint
+/// `source_epoch` of `source_job_id`, moved between operators according to `mappings`. The new
+/// checkpoint refers to the source checkpoint's data files rather than copying them, so the
+/// source checkpoint must be kept (for example as a savepoint) while the new job depends on it.
+pub async fn migrate_checkpoint(
+    source_job_id: &str,
+    source_epoch: u32,
+    target_job_id: &str,
+    target_epoch: u32,
+    mappings: &[OperatorMapping],
+) -> Result<CheckpointMetadata> {
+    let source = ParquetBackend::load_checkpoint_metadata(source_job_id, source_epoch)
+        .await
+        .with_context(|| {
+            format!(
+                "failed to load checkpoint {} for job {}",
+                source_epoch, source_job_id
+            )
+        })?;
+
+    let mut operator_ids = vec![];
+    for mapping in mappings {
+        if !source.operator_ids.contains(&mapping.from) {
+            bail!(
+                "checkpoint {} has no state for operator {}",
+                source_epoch,
+                mapping.from
+            );
+        }
+
+        let mut metadata =
+            ParquetBackend::load_operator_metadata(source_job_id, &mapping.from, source_epoch)
+                .await?
+                .ok_or_else(|| {
+                    anyhow!(
+                        "checkpoint {} has no state for operator {}",
+                        source_epoch,
+                        mapping.from
+                    )
+                })?;
+
+        for table in mapping.tables.keys() {
+            if !metadata.table_checkpoint_metadata.contains_key(table) {
+                bail!("operator {} has no table '{}'", mapping.from, table);
+            }
+        }
+
+        let rename = |table: String| mapping.tables.get(&table).cloned().unwrap_or(table);
+
+        metadata.table_checkpoint_metadata = metadata
+            .table_checkpoint_metadata
+            .into_iter()
+            .map(|(table, m)| (rename(table), m))
+            .collect();
+        metadata.table_configs = metadata
+            .table_configs
+            .into_iter()
+            .map(|(table, config)| {
+                let table = rename(table);
+                let config = rename_table_config(config, &table)?;
+                Ok((table, config))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let operator_metadata = metadata
+            .operator_metadata
+            .as_mut()
+            .ok_or_else(|| anyhow!("missing operator metadata for {}", mapping.from))?;
+        operator_metadata.job_id = target_job_id.to_string();
+        operator_metadata.operator_id = mapping.to.clone();
+        operator_metadata.epoch = target_epoch;
+
+        ParquetBackend::write_operator_checkpoint_metadata(metadata).await?;
+        operator_ids.push(mapping.to.clone());
+    }
+
+    let now = to_micros(SystemTime::now());
+    let metadata = CheckpointMetadata {
+        job_id: target_job_id.to_string(),
+        epoch: target_epoch,
+        min_epoch: target_epoch,
+        start_time: now,
+        finish_time: now,
+        operator_ids,
+    };
+    ParquetBackend::write_checkpoint_metadata(metadata.clone()).await?;
+
+    info!(
+        "migrated checkpoint {} of job {} to checkpoint {} of job {}",
+        source_epoch, source_job_id, target_epoch, target_job_id
+    );
+
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(id: &str, name: &str, description: &str) -> PlanOperator {
+        PlanOperator {
+            operator_id: id.to_string(),
+            operator_name: name.to_string(),
+            description: description.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_match_operators() {
+        let old = vec![
+            op("tumbling_1", "TumblingWindowAggregate", "count(*)"),
+            op("tumbling_2", "TumblingWindowAggregate", "sum(x)"),
+            op("join_3", "Join", "a.id = b.id"),
+        ];
+        // a new aggregate was added ahead of the others, shifting their ids
+        let new = vec![
+            op("tumbling_1", "TumblingWindowAggregate", "max(y)"),
+            op("tumbling_2", "TumblingWindowAggregate", "count(*)"),
+            op("tumbling_3", "TumblingWindowAggregate", "sum(x)"),
+            op("join_4", "Join", "a.id = b.id and a.x = b.x"),
+        ];
+        let stateful = old.iter().map(|op| op.operator_id.clone()).collect();
+
+        let (mut mappings, orphaned) = match_operators(&old, &new, &stateful, &[]).unwrap();
+        mappings.sort_by(|a, b| a.from.cmp(&b.from));
+        assert_eq!(
+            mappings
+                .iter()
+                .map(|m| (m.from.as_str(), m.to.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("tumbling_1", "tumbling_2"), ("tumbling_2", "tumbling_3")]
+        );
+        assert_eq!(orphaned, vec!["join_3".to_string()]);
+
+        let explicit = vec![OperatorMapping {
+            from: "join_3".to_string(),
+            to: "join_4".to_string(),
+            tables: BTreeMap::new(),
+        }];
+        let (mappings, orphaned) = match_operators(&old, &new, &stateful, &explicit).unwrap();
+        assert_eq!(mappings.len(), 3);
+        assert!(orphaned.is_empty());
+
+        let conflicting = vec![OperatorMapping {
+            from: "tumbling_1".to_string(),
+            to: "tumbling_3".to_string(),
+            tables: BTreeMap::new(),
+        }];
+        let (mappings, orphaned) = match_operators(&old, &new, &stateful, &conflicting).unwrap();
+        // tumbling_2 can no longer move to tumbling_3, which is taken
+        assert_eq!(mappings, conflicting);
+        assert_eq!(
+            orphaned,
+            vec!["tumbling_2".to_string(), "join_3".to_string()]
+        );
+    }
+}
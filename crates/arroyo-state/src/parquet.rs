@@ -2,34 +2,48 @@ use crate::tables::expiring_time_key_map::ExpiringTimeKeyTable;
 use crate::tables::global_keyed_map::GlobalKeyedTable;
 use crate::tables::{CompactionConfig, ErasedTable};
 use crate::BackingStore;
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use arroyo_rpc::grpc;
 use arroyo_rpc::grpc::{CheckpointMetadata, OperatorCheckpointMetadata, TableCheckpointMetadata};
 use arroyo_storage::StorageProvider;
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
 
 use arroyo_rpc::config::config;
 use prost::Message;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::ops::RangeInclusive;
 use std::sync::Arc;
 use std::time::SystemTime;
-use tokio::sync::Mutex;
 use tracing::{debug, info};
 
 pub const FULL_KEY_RANGE: RangeInclusive<u64> = 0..=u64::MAX;
 pub const GENERATIONS_TO_COMPACT: u32 = 1; // only compact generation 0 files
 
+/// The store that checkpoints are replicated to, unless we're failing over to it
+async fn get_replica_storage_provider() -> anyhow::Result<Option<StorageProvider>> {
+    let replication = &config().checkpoint_replication;
+    let Some(url) = replication.url.as_ref().filter(|_| !replication.failover) else {
+        return Ok(None);
+    };
+
+    Ok(Some(StorageProvider::for_checkpoints(url).await.context(
+        format!(
+            "failed to construct checkpoint replica backend for URL {}",
+            url
+        ),
+    )?))
+}
+
 async fn get_storage_provider() -> anyhow::Result<StorageProvider> {
     // TODO: this should be encoded in the config so that the controller doesn't need
     // to be synchronized with the workers
-    let storage_url = &config().checkpoint_url;
-
-    StorageProvider::for_url(storage_url).await.context(format!(
-        "failed to construct checkpoint backend for URL {}",
-        storage_url
-    ))
+    let storage_url = config().checkpoint_storage_url();
+
+    StorageProvider::for_checkpoints(storage_url)
+        .await
+        .context(format!(
+            "failed to construct checkpoint backend for URL {}",
+            storage_url
+        ))
 }
 
 pub struct ParquetBackend;
@@ -46,6 +60,143 @@ fn operator_path(job_id: &str, epoch: u32, operator: &str) -> String {
     format!("{}/operator-{}", base_path(job_id, epoch), operator)
 }
 
+/// The epoch of the checkpoint directory that a path is in, if any
+fn checkpoint_epoch(path: &str) -> Option<u32> {
+    path.split('/')
+        .find_map(|part| part.strip_prefix("checkpoint-")?.parse().ok())
+}
+
+async fn load_checkpoint_metadata_from(
+    storage_client: &StorageProvider,
+    job_id: &str,
+    epoch: u32,
+) -> Result<CheckpointMetadata> {
+    let data = storage_client
+        .get(&metadata_path(&base_path(job_id, epoch)))
+        .await?;
+    Ok(CheckpointMetadata::decode(&data[..])?)
+}
+
+async fn load_operator_metadata_from(
+    storage_client: &StorageProvider,
+    job_id: &str,
+    operator_id: &str,
+    epoch: u32,
+) -> Result<Option<OperatorCheckpointMetadata>> {
+    storage_client
+        .get_if_present(&metadata_path(&operator_path(job_id, epoch, operator_id)))
+        .await?
+        .map(|data| Ok(OperatorCheckpointMetadata::decode(&data[..])?))
+        .transpose()
+}
+
+async fn write_checkpoint_metadata_to(
+    storage_client: &StorageProvider,
+    metadata: &CheckpointMetadata,
+) -> Result<()> {
+    let path = metadata_path(&base_path(&metadata.job_id, metadata.epoch));
+    storage_client.put(&path, metadata.encode_to_vec()).await?;
+    Ok(())
+}
+
+/// Deletes the files in `storage_client` (and the replica, if there is one) that aren't
+/// referenced by the current checkpoint or the retained ones, returning how many were deleted
+async fn cleanup_checkpoints_in(
+    storage_client: &StorageProvider,
+    replica_client: Option<&StorageProvider>,
+    mut metadata: CheckpointMetadata,
+    retained: &BTreeSet<u32>,
+) -> Result<usize> {
+    let job_id = metadata.job_id.clone();
+    info!(
+        message = "Cleaning checkpoints",
+        job_id,
+        epoch = metadata.epoch,
+        retained = format!("{:?}", retained)
+    );
+
+    // collect everything referenced by the retained checkpoints; if any of them can't be
+    // read, we bail rather than risk deleting its files
+    let mut keep = HashSet::new();
+    for epoch in retained.iter().copied().chain([metadata.epoch]) {
+        let checkpoint = if epoch == metadata.epoch {
+            metadata.clone()
+        } else {
+            load_checkpoint_metadata_from(storage_client, &job_id, epoch)
+                .await
+                .with_context(|| format!("failed to load retained checkpoint {}", epoch))?
+        };
+
+        keep.insert(metadata_path(&base_path(&job_id, epoch)));
+        for operator_id in &checkpoint.operator_ids {
+            keep.insert(metadata_path(&operator_path(&job_id, epoch, operator_id)));
+            if let Some(operator_metadata) =
+                load_operator_metadata_from(storage_client, &job_id, operator_id, epoch).await?
+            {
+                keep.extend(referenced_files(&operator_metadata)?);
+            }
+        }
+    }
+
+    // the replica is cleaned up along with the primary store, so that it doesn't accumulate
+    // checkpoints that have been deleted here
+    let mut deleted = 0;
+    for client in [Some(storage_client), replica_client].into_iter().flatten() {
+        for path in client
+            .list_prefix(&format!("{}/checkpoints", job_id))
+            .await?
+        {
+            // checkpoints after the latest may still be in progress, and the latest epoch's
+            // directory holds newly compacted files that checkpoints don't reference yet
+            if !checkpoint_epoch(&path).is_some_and(|epoch| epoch < metadata.epoch) {
+                continue;
+            }
+
+            if !keep.contains(&path) {
+                debug!(message = "Deleting checkpoint file", job_id, path);
+                client.delete_if_present(path).await?;
+                deleted += 1;
+            }
+        }
+    }
+
+    metadata.min_epoch = retained
+        .first()
+        .copied()
+        .unwrap_or(metadata.epoch)
+        .min(metadata.epoch);
+    write_checkpoint_metadata_to(storage_client, &metadata).await?;
+
+    info!(message = "Finished cleaning checkpoints", job_id, deleted);
+
+    Ok(deleted)
+}
+
+/// The data files referenced by an operator's checkpoint
+fn referenced_files(metadata: &OperatorCheckpointMetadata) -> Result<HashSet<String>> {
+    let mut files = HashSet::new();
+    for (table_name, table_metadata) in &metadata.table_checkpoint_metadata {
+        let table_config = metadata
+            .table_configs
+            .get(table_name)
+            .ok_or_else(|| anyhow!("missing table config for table {}", table_name))?
+            .clone();
+
+        files.extend(match table_config.table_type() {
+            grpc::TableEnum::MissingTableType => {
+                bail!("missing table type for table {}", table_name)
+            }
+            grpc::TableEnum::GlobalKeyValue => {
+                GlobalKeyedTable::files_to_keep(table_config, table_metadata.clone())?
+            }
+            grpc::TableEnum::ExpiringKeyedTimeTable => {
+                ExpiringTimeKeyTable::files_to_keep(table_config, table_metadata.clone())?
+            }
+        });
+    }
+    Ok(files)
+}
+
 #[async_trait::async_trait]
 impl BackingStore for ParquetBackend {
     fn name() -> &'static str {
@@ -53,12 +204,7 @@ impl BackingStore for ParquetBackend {
     }
 
     async fn load_checkpoint_metadata(job_id: &str, epoch: u32) -> Result<CheckpointMetadata> {
-        let storage_client = get_storage_provider().await?;
-        let data = storage_client
-            .get(&metadata_path(&base_path(job_id, epoch)))
-            .await?;
-        let metadata = CheckpointMetadata::decode(&data[..])?;
-        Ok(metadata)
+        load_checkpoint_metadata_from(&get_storage_provider().await?, job_id, epoch).await
     }
 
     async fn load_operator_metadata(
@@ -66,12 +212,8 @@ impl BackingStore for ParquetBackend {
         operator_id: &str,
         epoch: u32,
     ) -> Result<Option<OperatorCheckpointMetadata>> {
-        let storage_client = get_storage_provider().await?;
-        storage_client
-            .get_if_present(&metadata_path(&operator_path(job_id, epoch, operator_id)))
-            .await?
-            .map(|data| Ok(OperatorCheckpointMetadata::decode(&data[..])?))
-            .transpose()
+        load_operator_metadata_from(&get_storage_provider().await?, job_id, operator_id, epoch)
+            .await
     }
 
     async fn write_operator_checkpoint_metadata(
@@ -94,72 +236,72 @@ impl BackingStore for ParquetBackend {
 
     async fn write_checkpoint_metadata(metadata: CheckpointMetadata) -> Result<()> {
         debug!("writing checkpoint {:?}", metadata);
-        let storage_client = get_storage_provider().await?;
-        let path = metadata_path(&base_path(&metadata.job_id, metadata.epoch));
-        storage_client.put(&path, metadata.encode_to_vec()).await?;
-        Ok(())
+        write_checkpoint_metadata_to(&get_storage_provider().await?, &metadata).await
     }
 
     async fn prepare_checkpoint_load(_metadata: &CheckpointMetadata) -> anyhow::Result<()> {
         Ok(())
     }
 
-    async fn cleanup_checkpoint(
-        mut metadata: CheckpointMetadata,
-        old_min_epoch: u32,
-        min_epoch: u32,
-    ) -> Result<()> {
-        info!(
-            message = "Cleaning checkpoint",
-            min_epoch,
-            job_id = metadata.job_id
-        );
+    async fn cleanup_checkpoints(
+        metadata: CheckpointMetadata,
+        retained: &BTreeSet<u32>,
+    ) -> Result<usize> {
+        let storage_client = get_storage_provider().await?;
+        let replica_client = get_replica_storage_provider().await?;
+        cleanup_checkpoints_in(&storage_client, replica_client.as_ref(), metadata, retained).await
+    }
 
-        let mut futures: FuturesUnordered<_> = metadata
-            .operator_ids
-            .iter()
-            .map(|operator_id| {
-                Self::cleanup_operator(
-                    metadata.job_id.clone(),
-                    operator_id.clone(),
-                    old_min_epoch,
-                    min_epoch,
-                )
-            })
-            .collect();
-
-        let storage_client = Mutex::new(get_storage_provider().await?);
-
-        // wait for all of the futures to complete
-        while let Some(result) = futures.next().await {
-            let operator_id = result?;
-
-            for epoch_to_remove in old_min_epoch..min_epoch {
-                let path = metadata_path(&operator_path(
-                    &metadata.job_id,
-                    epoch_to_remove,
-                    &operator_id,
-                ));
-                storage_client.lock().await.delete_if_present(path).await?;
-            }
-            debug!(
-                message = "Finished cleaning operator",
-                job_id = metadata.job_id,
+    async fn replicate_checkpoint(
+        metadata: &CheckpointMetadata,
+        replica_url: &str,
+    ) -> Result<usize> {
+        let job_id = &metadata.job_id;
+        let storage_client = get_storage_provider().await?;
+        let replica_client = StorageProvider::for_checkpoints(replica_url)
+            .await
+            .context(format!(
+                "failed to construct checkpoint replica backend for URL {}",
+                replica_url
+            ))?;
+
+        let mut paths = vec![];
+        for operator_id in &metadata.operator_ids {
+            let Some(operator_metadata) =
+                Self::load_operator_metadata(job_id, operator_id, metadata.epoch).await?
+            else {
+                continue;
+            };
+
+            paths.extend(referenced_files(&operator_metadata)?);
+            paths.push(metadata_path(&operator_path(
+                job_id,
+                metadata.epoch,
                 operator_id,
-                min_epoch
-            );
+            )));
         }
 
-        for epoch_to_remove in old_min_epoch..min_epoch {
-            storage_client
-                .lock()
-                .await
-                .delete_if_present(metadata_path(&base_path(&metadata.job_id, epoch_to_remove)))
-                .await?;
+        let mut copied = 0;
+        for path in paths {
+            // data files are immutable and shared between checkpoints, so any that are already in
+            // the replica were copied with an earlier checkpoint
+            if !path.ends_with("/metadata") && replica_client.exists(path.clone()).await? {
+                continue;
+            }
+
+            let data = storage_client.get(path.clone()).await?;
+            replica_client.put(path, data.to_vec()).await?;
+            copied += 1;
         }
-        metadata.min_epoch = min_epoch;
-        Self::write_checkpoint_metadata(metadata).await?;
-        Ok(())
+
+        replica_client
+            .put(
+                metadata_path(&base_path(job_id, metadata.epoch)),
+                metadata.encode_to_vec(),
+            )
+            .await?;
+
+        Ok(copied + 1)
     }
 }
 
@@ -218,83 +360,6 @@ impl ParquetBackend {
         }
         Ok(result)
     }
-
-    /// Delete files no longer referenced by the new min epoch
-    pub async fn cleanup_operator(
-        job_id: String,
-        operator_id: String,
-        old_min_epoch: u32,
-        new_min_epoch: u32,
-    ) -> Result<String> {
-        let operator_metadata = Self::load_operator_metadata(&job_id, &operator_id, new_min_epoch)
-            .await?
-            .expect("expect new_min_epoch metadata to still be present");
-        let paths_to_keep: HashSet<String> = operator_metadata
-            .table_checkpoint_metadata
-            .iter()
-            .flat_map(|(table_name, metadata)| {
-                let table_config = operator_metadata
-                    .table_configs
-                    .get(table_name)
-                    .unwrap()
-                    .clone();
-
-                match table_config.table_type() {
-                    grpc::TableEnum::MissingTableType => todo!("should handle error"),
-                    grpc::TableEnum::GlobalKeyValue => {
-                        GlobalKeyedTable::files_to_keep(table_config, metadata.clone()).unwrap()
-                    }
-                    grpc::TableEnum::ExpiringKeyedTimeTable => {
-                        ExpiringTimeKeyTable::files_to_keep(table_config, metadata.clone()).unwrap()
-                    }
-                }
-            })
-            .collect();
-
-        let mut deleted_paths = HashSet::new();
-        let storage_client = get_storage_provider().await?;
-
-        for epoch_to_remove in old_min_epoch..new_min_epoch {
-            let Some(metadata) =
-                Self::load_operator_metadata(&job_id, &operator_id, epoch_to_remove).await?
-            else {
-                continue;
-            };
-
-            // delete any files that are not in the new min epoch
-            for file in metadata
-                .table_checkpoint_metadata
-                .iter()
-                // TODO: factor this out
-                .flat_map(|(table_name, metadata)| {
-                    let table_config = operator_metadata
-                        .table_configs
-                        .get(table_name)
-                        .ok_or_else(|| anyhow::anyhow!("missing table config for operator {}, table {}, metadata is {:?}, operator_metadata is {:?}",
-                         operator_id, table_name, metadata, operator_metadata)).unwrap()
-                        .clone();
-
-                    match table_config.table_type() {
-                        grpc::TableEnum::MissingTableType => todo!("should handle error"),
-                        grpc::TableEnum::GlobalKeyValue => {
-                            GlobalKeyedTable::files_to_keep(table_config, metadata.clone()).unwrap()
-                        }
-                        grpc::TableEnum::ExpiringKeyedTimeTable => {
-                            ExpiringTimeKeyTable::files_to_keep(table_config, metadata.clone())
-                                .unwrap()
-                        }
-                    }
-                })
-            {
-                if !paths_to_keep.contains(&file) && !deleted_paths.contains(&file) {
-                    deleted_paths.insert(file.clone());
-                    storage_client.delete_if_present(file).await?;
-                }
-            }
-        }
-
-        Ok(operator_id)
-    }
 }
 
 #[derive(Debug)]
@@ -321,3 +386,59 @@ impl ParquetStats {
         self.max_routing_key = self.max_routing_key.max(other.max_routing_key);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_cleanup_checkpoints_with_key() {
+        // the last component of a local checkpoint URL is its key, which all paths are relative to
+        let dir = format!(
+            "/tmp/arroyo-testing/cleanup-{}",
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        );
+        let storage = StorageProvider::for_checkpoints(&format!("file://{}/ckpt", dir))
+            .await
+            .unwrap();
+
+        let job_id = "job_cleanup".to_string();
+
+        let data_file = format!("{}/operator-op_1/table-a-000", base_path(&job_id, 1));
+        let mut writer = storage.writer(&data_file.as_str().into()).await.unwrap();
+        writer.write_all(b"data").await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        for epoch in [1, 2] {
+            write_checkpoint_metadata_to(
+                &storage,
+                &CheckpointMetadata {
+                    job_id: job_id.clone(),
+                    epoch,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let on_disk = |path: &str| std::path::Path::new(&format!("{}/ckpt/{}", dir, path)).exists();
+        assert!(on_disk(&data_file));
+
+        let current = load_checkpoint_metadata_from(&storage, &job_id, 2)
+            .await
+            .unwrap();
+        let deleted = cleanup_checkpoints_in(&storage, None, current, &BTreeSet::new())
+            .await
+            .unwrap();
+
+        assert_eq!(deleted, 2);
+        assert!(!on_disk(&data_file));
+        assert!(!on_disk(&metadata_path(&base_path(&job_id, 1))));
+        assert!(on_disk(&metadata_path(&base_path(&job_id, 2))));
+    }
+}
@@ -16,7 +16,7 @@ use bincode::config;
 use datafusion::common::{hash_utils::create_hashes, ScalarValue};
 use tracing::warn;
 
-use crate::{parquet::ParquetStats, DataOperation};
+use crate::{parquet::ParquetStats, DataOperation, ROUTING_HASH_FIELD};
 
 #[allow(unused)]
 #[derive(Debug, Clone)]
@@ -24,6 +24,9 @@ pub struct SchemaWithHashAndOperation {
     state_schema: ArroyoSchemaRef,
     memory_schema: ArroyoSchemaRef,
     generation_index: Option<usize>,
+    // a column of the memory schema holding precomputed routing hashes, used instead of hashing
+    // the key columns
+    routing_hash_index: Option<usize>,
     hash_index: usize,
     operation_index: usize,
 }
@@ -47,6 +50,12 @@ impl SchemaWithHashAndOperation {
             None
         };
 
+        let routing_hash_index = memory_schema
+            .schema
+            .index_of(ROUTING_HASH_FIELD)
+            .ok()
+            .filter(|i| *memory_schema.schema.field(*i).data_type() == DataType::UInt64);
+
         fields.push(Arc::new(Field::new("_key_hash", DataType::UInt64, false)));
         let hash_index = fields.len() - 1;
         fields.push(Arc::new(Field::new(
@@ -68,6 +77,7 @@ impl SchemaWithHashAndOperation {
             state_schema,
             memory_schema,
             generation_index,
+            routing_hash_index,
             hash_index,
             operation_index,
         }
@@ -160,17 +170,26 @@ impl SchemaWithHashAndOperation {
         &mut self,
         record_batch: &RecordBatch,
     ) -> Result<(RecordBatch, ParquetStats)> {
-        let key_batch = self
-            .memory_schema
-            .key_indices
-            .as_ref()
-            .map(|key_indices| record_batch.project(key_indices))
-            .transpose()?
-            .unwrap_or_else(|| record_batch.project(&[]).unwrap());
-
-        let mut hash_buffer = vec![0u64; key_batch.num_rows()];
-        let _hashes = create_hashes(key_batch.columns(), &get_hasher(), &mut hash_buffer)?;
-        let hash_array = PrimitiveArray::<UInt64Type>::from(hash_buffer);
+        let hash_array = match self.routing_hash_index {
+            Some(index) => record_batch
+                .column(index)
+                .as_primitive_opt::<UInt64Type>()
+                .ok_or_else(|| anyhow!("routing hash column should be a UInt64"))?
+                .clone(),
+            None => {
+                let key_batch = self
+                    .memory_schema
+                    .key_indices
+                    .as_ref()
+                    .map(|key_indices| record_batch.project(key_indices))
+                    .transpose()?
+                    .unwrap_or_else(|| record_batch.project(&[]).unwrap());
+
+                let mut hash_buffer = vec![0u64; key_batch.num_rows()];
+                let _hashes = create_hashes(key_batch.columns(), &get_hasher(), &mut hash_buffer)?;
+                PrimitiveArray::<UInt64Type>::from(hash_buffer)
+            }
+        };
 
         let hash_min = min(&hash_array).unwrap();
         let hash_max = max(&hash_array).unwrap();
@@ -11,7 +11,8 @@ use arrow::row::OwnedRow;
 use arrow_array::{
     cast::AsArray,
     types::{TimestampNanosecondType, UInt64Type},
-    BooleanArray, PrimitiveArray, RecordBatch, TimestampNanosecondArray, UInt64Array,
+    Array, BinaryArray, BooleanArray, PrimitiveArray, RecordBatch, TimestampNanosecondArray,
+    UInt64Array,
 };
 use arrow_ord::{partition::partition, sort::sort_to_indices};
 use arroyo_rpc::{
@@ -22,14 +23,17 @@ use arroyo_rpc::{
     },
     Converter,
 };
-use arroyo_storage::StorageProviderRef;
+use arroyo_storage::{StorageProvider, StorageProviderRef};
 use arroyo_types::{
     from_micros, from_nanos, print_time, server_for_hash, to_micros, to_nanos, TaskInfoRef,
 };
 
 use futures::{StreamExt, TryStreamExt};
 use parquet::{
-    arrow::{async_reader::ParquetObjectReader, AsyncArrowWriter, ParquetRecordBatchStreamBuilder},
+    arrow::{
+        async_reader::{AsyncFileReader, ParquetObjectReader},
+        AsyncArrowWriter, ParquetRecordBatchStreamBuilder,
+    },
     basic::{Compression, ZstdLevel},
     file::properties::WriterProperties,
 };
@@ -44,6 +48,32 @@ use tracing::debug;
 
 use super::{table_checkpoint_path, CompactionConfig, Table, TableEpochCheckpointer};
 
+/// Opens a parquet file written by a table checkpoint. Encrypted files can't be read in ranges, so
+/// they're fetched and decrypted in full.
+async fn parquet_reader(
+    storage_provider: &StorageProvider,
+    file: String,
+) -> Result<Box<dyn AsyncFileReader>> {
+    if storage_provider.is_encrypted() {
+        let data = storage_provider.get(file).await?;
+        return Ok(Box::new(std::io::Cursor::new(data)));
+    }
+
+    let object_meta = match storage_provider.head(file.as_str()).await {
+        Ok(meta) => meta,
+        // files from older checkpoints were written without the checkpoint URL's key
+        Err(e) => storage_provider
+            .get_backing_store()
+            .head(&file.into())
+            .await
+            .map_err(|_| e)?,
+    };
+    Ok(Box::new(ParquetObjectReader::new(
+        storage_provider.get_backing_store(),
+        object_meta,
+    )))
+}
+
 #[derive(Debug, Clone)]
 pub struct ExpiringTimeKeyTable {
     table_name: String,
@@ -123,13 +153,7 @@ impl ExpiringTimeKeyTable {
     {
         let mut result = vec![];
         for (file, needs_filtering) in files {
-            let object_meta = self
-                .storage_provider
-                .get_backing_store()
-                .head(&(file.into()))
-                .await?;
-            let object_reader =
-                ParquetObjectReader::new(self.storage_provider.get_backing_store(), object_meta);
+            let object_reader = parquet_reader(&self.storage_provider, file).await?;
             let reader_builder = ParquetRecordBatchStreamBuilder::new(object_reader).await?;
             let mut stream = reader_builder.build()?;
             // projection to trim the metadata fields. Should probably be factored out.
@@ -155,6 +179,57 @@ impl ExpiringTimeKeyTable {
         Ok(result)
     }
 
+    pub(crate) fn memory_schema(&self) -> ArroyoSchemaRef {
+        self.schema.memory_schema()
+    }
+
+    /// Writes the rows of a bootstrap snapshot whose keys belong to this subtask to a file for
+    /// epoch 0, returning checkpoint metadata that the table can be restored from
+    pub(crate) async fn write_bootstrap(
+        &self,
+        batches: &[RecordBatch],
+    ) -> Result<Option<ExpiringKeyedTimeTableCheckpointMetadata>> {
+        let mut schema = self.schema.clone();
+        let mut checkpointer = ExpiringTimeKeyTableCheckpointer::new(self.clone(), 0)?;
+
+        for batch in batches.iter().filter(|batch| batch.num_rows() > 0) {
+            let (annotated_batch, _) = schema.annotate_record_batch(batch)?;
+            let Some(owned_batch) =
+                schema.filter_by_hash_index(annotated_batch, &self.task_info.key_range)?
+            else {
+                continue;
+            };
+            if owned_batch.num_rows() == 0 {
+                continue;
+            }
+
+            if checkpointer.writer.is_none() {
+                checkpointer.init_writer().await?;
+            }
+            checkpointer.update_parquet_stats(schema.batch_stats_from_state_batch(&owned_batch)?);
+            checkpointer
+                .writer
+                .as_mut()
+                .expect("writer should be set")
+                .write(&owned_batch)
+                .await?;
+        }
+
+        let checkpoint = CheckpointMessage {
+            epoch: 0,
+            time: SystemTime::now(),
+            watermark: None,
+            then_stop: false,
+        };
+
+        Ok(checkpointer
+            .finish(&checkpoint, None)
+            .await?
+            .map(|(metadata, _)| ExpiringKeyedTimeTableCheckpointMetadata {
+                files: metadata.files,
+            }))
+    }
+
     fn get_cutoff(&self, watermark: Option<SystemTime>) -> SystemTime {
         watermark
             .map(|watermark| watermark - self.retention)
@@ -245,6 +320,27 @@ impl ExpiringTimeKeyTable {
         }
         Ok(view)
     }
+
+    pub(crate) async fn get_keyed_state_view(
+        &self,
+        state_tx: Sender<StateMessage>,
+        watermark: Option<SystemTime>,
+    ) -> Result<KeyedStateView> {
+        let cutoff = self.get_cutoff(watermark);
+        let files = self.get_files_with_filtering(cutoff);
+        let memory_schema = self.schema.memory_schema();
+        let batches = self
+            .call_on_filtered_batches(files, |batch| {
+                Ok(vec![memory_schema.filter_by_time(batch, Some(cutoff))?])
+            })
+            .await?;
+
+        let mut view = KeyedStateView::new(self.clone(), state_tx);
+        for batch in batches {
+            view.restore_batch(&batch)?;
+        }
+        Ok(view)
+    }
 }
 
 #[async_trait::async_trait]
@@ -285,13 +381,8 @@ impl Table for ExpiringTimeKeyTable {
         })
     }
 
-    fn epoch_checkpointer(
-        &self,
-        epoch: u32,
-        previous_metadata: Option<Self::TableSubtaskCheckpointMetadata>,
-    ) -> Result<Self::Checkpointer> {
-        let prior_files = previous_metadata.map(|meta| meta.files).unwrap_or_default();
-        ExpiringTimeKeyTableCheckpointer::new(self.clone(), epoch, prior_files)
+    fn epoch_checkpointer(&self, epoch: u32) -> Result<Self::Checkpointer> {
+        ExpiringTimeKeyTableCheckpointer::new(self.clone(), epoch)
     }
 
     fn merge_checkpoint_metadata(
@@ -491,14 +582,7 @@ impl TimeTableCompactor {
             {
                 continue;
             }
-            let reader = ParquetObjectReader::new(
-                compactor.storage_provider.get_backing_store(),
-                compactor
-                    .storage_provider
-                    .get_backing_store()
-                    .head(&(file_name.clone().into()))
-                    .await?,
-            );
+            let reader = parquet_reader(&compactor.storage_provider, file_name.clone()).await?;
             let first_partition =
                 server_for_hash(file.min_routing_key, operator_metadata.parallelism as usize);
             let last_partition =
@@ -565,10 +649,9 @@ impl TimeTableCompactor {
                 self.operator_metadata.epoch,
                 true,
             );
-            let (_multipart_id, async_writer) = self
+            let async_writer = self
                 .storage_provider
-                .get_backing_store()
-                .put_multipart(&(file_name.clone().into()))
+                .writer(&(file_name.clone().into()))
                 .await?;
             let writer = Some(AsyncArrowWriter::try_new(
                 async_writer,
@@ -636,15 +719,10 @@ pub struct ExpiringTimeKeyTableCheckpointer {
     epoch: u32,
     writer: Option<AsyncArrowWriter<Box<dyn AsyncWrite + Send + Unpin>>>,
     parquet_stats: Option<ParquetStats>,
-    prior_files: Vec<ParquetTimeFile>,
 }
 
 impl ExpiringTimeKeyTableCheckpointer {
-    fn new(
-        parent: ExpiringTimeKeyTable,
-        epoch: u32,
-        prior_files: Vec<ParquetTimeFile>,
-    ) -> Result<Self> {
+    fn new(parent: ExpiringTimeKeyTable, epoch: u32) -> Result<Self> {
         let file_name = table_checkpoint_path(
             &parent.task_info.job_id,
             &parent.task_info.operator_id,
@@ -659,15 +737,13 @@ impl ExpiringTimeKeyTableCheckpointer {
             epoch,
             writer: None,
             parquet_stats: None,
-            prior_files,
         })
     }
     async fn init_writer(&mut self) -> Result<()> {
-        let (_multipart_id, async_writer) = self
+        let async_writer = self
             .parent
             .storage_provider
-            .get_backing_store()
-            .put_multipart(&self.file_name.clone().into())
+            .writer(&self.file_name.clone().into())
             .await?;
         let writer_properties = WriterProperties::builder()
             .set_compression(Compression::ZSTD(ZstdLevel::default()))
@@ -705,13 +781,15 @@ impl TableEpochCheckpointer for ExpiringTimeKeyTableCheckpointer {
     async fn finish(
         mut self,
         checkpoint: &CheckpointMessage,
+        previous_metadata: Option<Self::SubTableCheckpointMessage>,
     ) -> Result<Option<(Self::SubTableCheckpointMessage, usize)>> {
         let cutoff = checkpoint
             .watermark
             .map(|watermark| to_micros(watermark - self.parent.retention))
             .unwrap_or_default();
-        let mut files: Vec<_> = self
-            .prior_files
+        let mut files: Vec<_> = previous_metadata
+            .map(|meta| meta.files)
+            .unwrap_or_default()
             .into_iter()
             .filter(|file| {
                 // file must have some data greater than the cutoff and routing keys within the range.
@@ -728,8 +806,7 @@ impl TableEpochCheckpointer for ExpiringTimeKeyTableCheckpointer {
             let meta = self
                 .parent
                 .storage_provider
-                .get_backing_store()
-                .head(&(self.file_name.clone().into()))
+                .head(self.file_name.as_str())
                 .await?;
             bytes += meta.size;
             let file = ParquetTimeFile {
@@ -1251,3 +1328,125 @@ impl LastKeyValueView {
         Ok(())
     }
 }
+
+/// A key of a [`KeyedStateView`] with its latest value, or None if it was deleted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyedStateEntry {
+    pub routing_hash: u64,
+    pub key: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+    pub timestamp: SystemTime,
+}
+
+/// A view of a table created with [`keyed_state_table_config`](crate::keyed_state_table_config),
+/// which holds one serialized value per key. Operators keep the live values themselves; the view
+/// restores the latest value of each of the subtask's keys and writes the keys that changed.
+#[derive(Debug)]
+pub struct KeyedStateView {
+    parent: ExpiringTimeKeyTable,
+    // the latest restored entry of each key, until it's taken by the operator
+    restored: HashMap<Vec<u8>, (KeyedStateEntry, u64)>,
+    state_tx: Sender<StateMessage>,
+}
+
+impl KeyedStateView {
+    fn new(parent: ExpiringTimeKeyTable, state_tx: Sender<StateMessage>) -> Self {
+        Self {
+            parent,
+            restored: HashMap::new(),
+            state_tx,
+        }
+    }
+
+    fn restore_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        let hashes = batch
+            .column(0)
+            .as_primitive_opt::<UInt64Type>()
+            .ok_or_else(|| anyhow!("routing hash column should be a UInt64"))?;
+        let keys = batch
+            .column(1)
+            .as_binary_opt::<i32>()
+            .ok_or_else(|| anyhow!("key column should be binary"))?;
+        let values = batch
+            .column(2)
+            .as_binary_opt::<i32>()
+            .ok_or_else(|| anyhow!("value column should be binary"))?;
+        let timestamps = batch
+            .column(3)
+            .as_primitive_opt::<TimestampNanosecondType>()
+            .ok_or_else(|| anyhow!("should be able to extract timestamp array"))?;
+        let generations = batch
+            .column(
+                self.parent
+                    .schema
+                    .generation_index()
+                    .ok_or_else(|| anyhow!("should have generation index"))?,
+            )
+            .as_primitive_opt::<UInt64Type>()
+            .ok_or_else(|| anyhow!("should have generation array"))?;
+
+        for i in 0..batch.num_rows() {
+            let generation = generations.value(i);
+            // files aren't ordered by when their rows were written, so keep the latest generation
+            if let Some((_, current)) = self.restored.get(keys.value(i)) {
+                if *current > generation {
+                    continue;
+                }
+            }
+
+            let entry = KeyedStateEntry {
+                routing_hash: hashes.value(i),
+                key: keys.value(i).to_vec(),
+                value: (!values.is_null(i)).then(|| values.value(i).to_vec()),
+                timestamp: from_nanos(timestamps.value(i) as u128),
+            };
+            self.restored.insert(entry.key.clone(), (entry, generation));
+        }
+        Ok(())
+    }
+
+    /// Takes the restored keys of the subtask that haven't been deleted, leaving the view empty
+    pub fn take_restored(&mut self) -> Vec<KeyedStateEntry> {
+        mem::take(&mut self.restored)
+            .into_values()
+            .map(|(entry, _)| entry)
+            .filter(|entry| entry.value.is_some())
+            .collect()
+    }
+
+    /// Writes the new values of keys, or deletes keys whose value is None. Each key may only be
+    /// written once per generation, and generations must increase across writes, so using the
+    /// epoch of the checkpoint being taken is a natural choice.
+    pub async fn write(&mut self, entries: Vec<KeyedStateEntry>, generation: u64) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let batch = RecordBatch::try_new(
+            self.parent.schema.memory_schema().schema.clone(),
+            vec![
+                Arc::new(UInt64Array::from_iter_values(
+                    entries.iter().map(|e| e.routing_hash),
+                )),
+                Arc::new(BinaryArray::from_iter_values(
+                    entries.iter().map(|e| e.key.as_slice()),
+                )),
+                Arc::new(BinaryArray::from_iter(
+                    entries.iter().map(|e| e.value.as_deref()),
+                )),
+                Arc::new(TimestampNanosecondArray::from_iter_values(
+                    entries.iter().map(|e| to_nanos(e.timestamp) as i64),
+                )),
+                Arc::new(UInt64Array::from_value(generation, entries.len())),
+            ],
+        )?;
+
+        self.state_tx
+            .send(StateMessage::TableData {
+                table: self.parent.table_name.to_string(),
+                data: TableData::RecordBatch(batch),
+            })
+            .await?;
+        Ok(())
+    }
+}
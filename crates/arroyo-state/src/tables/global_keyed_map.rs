@@ -106,11 +106,7 @@ impl Table for GlobalKeyedTable {
 
     type TableCheckpointMessage = GlobalKeyedTableTaskCheckpointMetadata;
 
-    fn epoch_checkpointer(
-        &self,
-        epoch: u32,
-        _previous_metadata: Option<Self::TableSubtaskCheckpointMetadata>,
-    ) -> Result<Self::Checkpointer> {
+    fn epoch_checkpointer(&self, epoch: u32) -> Result<Self::Checkpointer> {
         Ok(Self::Checkpointer {
             table_name: self.table_name.clone(),
             epoch,
@@ -258,6 +254,7 @@ impl TableEpochCheckpointer for GlobalKeyedCheckpointer {
     async fn finish(
         self,
         _checkpoint: &CheckpointMessage,
+        _previous_metadata: Option<Self::SubTableCheckpointMessage>,
     ) -> Result<Option<(Self::SubTableCheckpointMessage, usize)>> {
         let _start_time = to_micros(SystemTime::now());
         let (keys, values): (Vec<_>, Vec<_>) = self
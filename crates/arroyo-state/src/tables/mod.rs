@@ -89,12 +89,9 @@ pub(crate) trait Table: Send + Sync + 'static + Clone {
     // finishes said data, and returns a metadata protobuf.
     // the metadata protobuf should be sufficient to know all checkpoint data for the table that
     // the subtask cares about at that epoch, including previously written data,
-    // which will be determined from the previous metadata.
-    fn epoch_checkpointer(
-        &self,
-        epoch: u32,
-        previous_metadata: Option<Self::TableSubtaskCheckpointMetadata>,
-    ) -> Result<Self::Checkpointer>;
+    // which will be determined from the previous metadata passed to finish. The previous epoch
+    // may still be uploading when the checkpointer is created.
+    fn epoch_checkpointer(&self, epoch: u32) -> Result<Self::Checkpointer>;
     // A controller method to merge the metadata from each subtask into a single Table metadata.
     // Will do things like dedup files and compute overall min and max watermarks.
     fn merge_checkpoint_metadata(
@@ -165,12 +162,8 @@ pub trait ErasedTable: Send + Sync + 'static {
     // finishes said data, and returns a metadata protobuf.
     // the metadata protobuf should be sufficient to know all checkpoint data for the table that
     // the subtask cares about at that epoch, including previously written data,
-    // which will be determined from the previous metadata.
-    fn epoch_checkpointer(
-        &self,
-        epoch: u32,
-        previous_metadata: Option<TableSubtaskCheckpointMetadata>,
-    ) -> Result<Box<dyn ErasedCheckpointer>>;
+    // which will be determined from the previous metadata passed to finish.
+    fn epoch_checkpointer(&self, epoch: u32) -> Result<Box<dyn ErasedCheckpointer>>;
     // A controller method to merge the metadata from each subtask into a single Table metadata.
     // Will do things like dedup files and compute overall min and max watermarks.
     fn merge_checkpoint_metadata(
@@ -259,15 +252,8 @@ impl<T: Table + Sized + 'static> ErasedTable for T {
         T::from_config(config, task_info, storage_provider, checkpoint_message)
     }
 
-    fn epoch_checkpointer(
-        &self,
-        epoch: u32,
-        previous_metadata: Option<TableSubtaskCheckpointMetadata>,
-    ) -> Result<Box<dyn ErasedCheckpointer>> {
-        let previous_metadata = previous_metadata
-            .map(|metadata| Self::checked_proto_decode(metadata.table_type(), metadata.data))
-            .transpose()?;
-        let checkpointer = self.epoch_checkpointer(epoch, previous_metadata)?;
+    fn epoch_checkpointer(&self, epoch: u32) -> Result<Box<dyn ErasedCheckpointer>> {
+        let checkpointer = self.epoch_checkpointer(epoch)?;
         Ok(Box::new(checkpointer) as Box<dyn ErasedCheckpointer>)
     }
 
@@ -390,12 +376,14 @@ impl<T: Table + Sized + 'static> ErasedTable for T {
 
 #[async_trait::async_trait]
 pub trait TableEpochCheckpointer: Send {
-    type SubTableCheckpointMessage: prost::Message;
+    type SubTableCheckpointMessage: prost::Message + Default;
     async fn insert_data(&mut self, data: TableData) -> Result<()>;
-    // returning Ok(None) means there is no state to restore.
+    // uploads the epoch's data, returning the metadata for the epoch given the metadata of the
+    // previous one. returning Ok(None) means there is no state to restore.
     async fn finish(
         self,
         checkpoint: &CheckpointMessage,
+        previous_metadata: Option<Self::SubTableCheckpointMessage>,
     ) -> Result<Option<(Self::SubTableCheckpointMessage, usize)>>;
 
     fn table_type() -> TableEnum;
@@ -409,6 +397,7 @@ pub trait ErasedCheckpointer: Send {
     async fn finish(
         mut self: Box<Self>,
         checkpoint: &CheckpointMessage,
+        previous_metadata: Option<TableSubtaskCheckpointMetadata>,
     ) -> Result<Option<(TableSubtaskCheckpointMetadata, usize)>>;
 }
 
@@ -421,9 +410,24 @@ impl<T: TableEpochCheckpointer + Sized> ErasedCheckpointer for T {
     async fn finish(
         mut self: Box<Self>,
         checkpoint: &CheckpointMessage,
+        previous_metadata: Option<TableSubtaskCheckpointMetadata>,
     ) -> Result<Option<(TableSubtaskCheckpointMetadata, usize)>> {
         let subtask_index = self.subtask_index();
-        let subtask = (*self).finish(checkpoint).await?;
+        let previous_metadata = previous_metadata
+            .map(|metadata| {
+                if metadata.table_type() != T::table_type() {
+                    bail!(
+                        "mismatched table type, expected type {:?}, got {:?}",
+                        T::table_type(),
+                        metadata.table_type()
+                    );
+                }
+                Ok(T::SubTableCheckpointMessage::decode(
+                    metadata.data.as_slice(),
+                )?)
+            })
+            .transpose()?;
+        let subtask = (*self).finish(checkpoint, previous_metadata).await?;
         Ok(subtask.map(|(metadata, size)| {
             (
                 TableSubtaskCheckpointMetadata {
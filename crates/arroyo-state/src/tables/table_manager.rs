@@ -3,11 +3,12 @@ use std::any::Any;
 use std::{collections::HashMap, sync::Arc, time::SystemTime};
 
 use anyhow::{anyhow, bail, Context, Result};
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
 use arroyo_rpc::CompactionResult;
 use arroyo_rpc::{
     grpc::{
-        OperatorCheckpointMetadata, SubtaskCheckpointMetadata, TableConfig, TableEnum,
-        TableSubtaskCheckpointMetadata,
+        OperatorCheckpointMetadata, SubtaskCheckpointMetadata, TableCheckpointMetadata,
+        TableConfig, TableEnum, TableSubtaskCheckpointMetadata,
     },
     CheckpointCompleted, ControlResp,
 };
@@ -21,11 +22,11 @@ use tokio::sync::{
 use arroyo_rpc::config::config;
 use tracing::{debug, error, info, warn};
 
-use crate::{tables::global_keyed_map::GlobalKeyedTable, StateMessage};
+use crate::{bootstrap, tables::global_keyed_map::GlobalKeyedTable, StateMessage};
 use crate::{CheckpointMessage, TableData};
 
 use super::expiring_time_key_map::{
-    ExpiringTimeKeyTable, ExpiringTimeKeyView, KeyTimeView, LastKeyValueView,
+    ExpiringTimeKeyTable, ExpiringTimeKeyView, KeyTimeView, KeyedStateView, LastKeyValueView,
 };
 use super::global_keyed_map::GlobalKeyedView;
 use super::{ErasedCheckpointer, ErasedTable};
@@ -48,20 +49,61 @@ pub struct BackendWriter {
     // TODO: compaction
 }
 
-#[allow(unused)]
+// how many finished epochs may be waiting to be uploaded before the flusher stops accepting data
+// for new ones
+const MAX_PENDING_UPLOADS: usize = 4;
+
+/// Receives the state written by a subtask and writes it into the checkpointers for the current
+/// epoch. When the subtask takes a checkpoint, the epoch's checkpointers are handed off to the
+/// [`BackendUploader`] and checkpointers for the next epoch are created immediately, so that the
+/// subtask can resume writing state while the previous epoch is still being uploaded.
 pub struct BackendFlusher {
     queue: Receiver<StateMessage>,
-    storage: StorageProviderRef,
+    uploads: Sender<EpochUpload>,
+    control_tx: Sender<ControlResp>,
+    task_info: TaskInfoRef,
+    tables: HashMap<String, Arc<Box<dyn ErasedTable>>>,
+    table_checkpointers: HashMap<String, Box<dyn ErasedCheckpointer>>,
+    current_epoch: u32,
+}
+
+/// The state for an epoch that has been checkpointed by the subtask, but not yet uploaded
+struct EpochUpload {
+    checkpoint: CheckpointMessage,
+    checkpointers: HashMap<String, Box<dyn ErasedCheckpointer>>,
+    compacted_tables: Option<HashMap<String, TableCheckpointMetadata>>,
+}
+
+/// Uploads the state for each epoch, in order, and only then reports the subtask's checkpoint as
+/// completed to the controller
+struct BackendUploader {
+    queue: Receiver<EpochUpload>,
     control_tx: Sender<ControlResp>,
     finish_tx: Option<oneshot::Sender<()>>,
     task_info: TaskInfoRef,
     tables: HashMap<String, Arc<Box<dyn ErasedTable>>>,
     table_configs: HashMap<String, TableConfig>,
-    table_checkpointers: HashMap<String, Box<dyn ErasedCheckpointer>>,
     current_epoch: u32,
     last_epoch_checkpoints: HashMap<String, TableSubtaskCheckpointMetadata>,
 }
 
+async fn report_failure(
+    control_tx: &Sender<ControlResp>,
+    task_info: &TaskInfoRef,
+    err: anyhow::Error,
+) {
+    error!("Failed to flush state file: {:?}", err);
+    control_tx
+        .send(ControlResp::TaskFailed {
+            operator_id: task_info.operator_id.clone(),
+            task_index: task_info.task_index,
+            error: err.to_string(),
+            category: ErrorCategory::Checkpoint,
+        })
+        .await
+        .unwrap();
+}
+
 impl BackendFlusher {
     fn start(mut self) {
         tokio::spawn(async move {
@@ -73,15 +115,7 @@ impl BackendFlusher {
                         }
                     }
                     Err(err) => {
-                        error!("Failed to flush state file: {:?}", err);
-                        self.control_tx
-                            .send(ControlResp::TaskFailed {
-                                operator_id: self.task_info.operator_id.clone(),
-                                task_index: self.task_info.task_index,
-                                error: err.to_string(),
-                            })
-                            .await
-                            .unwrap();
+                        report_failure(&self.control_tx, &self.task_info, err).await;
                         return;
                     }
                 }
@@ -93,14 +127,10 @@ impl BackendFlusher {
         let mut checkpoint_epoch = None;
 
         for (table_name, checkpointer) in &self.tables {
-            let epoch_checkpointer = checkpointer.epoch_checkpointer(
-                self.current_epoch,
-                self.last_epoch_checkpoints.remove(table_name),
-            )?;
+            let epoch_checkpointer = checkpointer.epoch_checkpointer(self.current_epoch)?;
             self.table_checkpointers
                 .insert(table_name.clone(), epoch_checkpointer);
         }
-        self.last_epoch_checkpoints.clear();
         let mut compacted_tables = None;
 
         // accumulate writes in the RecordBatchBuilders until we get a checkpoint
@@ -130,16 +160,61 @@ impl BackendFlusher {
         let Some(cp) = checkpoint_epoch else {
             bail!("somehow exited loop without checkpoint_epoch being set");
         };
+        let then_stop = cp.then_stop;
+
+        self.current_epoch += 1;
+        if self
+            .uploads
+            .send(EpochUpload {
+                checkpoint: cp,
+                checkpointers: std::mem::take(&mut self.table_checkpointers),
+                compacted_tables,
+            })
+            .await
+            .is_err()
+        {
+            // the uploader has failed, and has already reported the error
+            return Ok(false);
+        }
+
+        Ok(!then_stop)
+    }
+}
+
+impl BackendUploader {
+    fn start(mut self) {
+        tokio::spawn(async move {
+            while let Some(upload) = self.queue.recv().await {
+                let then_stop = upload.checkpoint.then_stop;
+                if let Err(err) = self.upload(upload).await {
+                    report_failure(&self.control_tx, &self.task_info, err).await;
+                    return;
+                }
+                if then_stop {
+                    if self.finish_tx.take().unwrap().send(()).is_err() {
+                        warn!("can't send finish");
+                    }
+                    return;
+                }
+            }
+        });
+    }
+
+    async fn upload(&mut self, upload: EpochUpload) -> Result<()> {
+        let cp = upload.checkpoint;
         let mut metadatas = HashMap::new();
         let mut bytes = 0;
-        for (table_name, checkpointer) in self.table_checkpointers.drain() {
-            if let Some((subtask_checkpoint_data, size)) = checkpointer.finish(&cp).await? {
+        for (table_name, checkpointer) in upload.checkpointers {
+            let previous_metadata = self.last_epoch_checkpoints.remove(&table_name);
+            if let Some((subtask_checkpoint_data, size)) =
+                checkpointer.finish(&cp, previous_metadata).await?
+            {
                 metadatas.insert(table_name.clone(), subtask_checkpoint_data);
                 bytes += size;
             }
         }
 
-        if let Some(compaction_metas) = compacted_tables {
+        if let Some(compaction_metas) = upload.compacted_tables {
             for (table_name, compacted_metadata) in compaction_metas {
                 let table = self.tables.get(&table_name).unwrap();
                 let Some(compacted_metadata) =
@@ -179,15 +254,7 @@ impl BackendFlusher {
                 subtask_metadata,
             }))
             .await?;
-        if cp.then_stop {
-            self.finish_tx
-                .take()
-                .unwrap()
-                .send(())
-                .map_err(|_| anyhow::anyhow!("can't send finish"))?;
-            return Ok(false);
-        }
-        Ok(true)
+        Ok(())
     }
 }
 
@@ -197,24 +264,33 @@ impl BackendWriter {
         control_tx: Sender<ControlResp>,
         table_configs: HashMap<String, TableConfig>,
         tables: HashMap<String, Arc<Box<dyn ErasedTable>>>,
-        storage: StorageProviderRef,
         current_epoch: u32,
         last_epoch_checkpoints: HashMap<String, TableSubtaskCheckpointMetadata>,
     ) -> Self {
         let (tx, rx) = mpsc::channel(1024 * 1024);
+        let (upload_tx, upload_rx) = mpsc::channel(MAX_PENDING_UPLOADS);
         let (finish_tx, finish_rx) = oneshot::channel();
 
+        (BackendUploader {
+            queue: upload_rx,
+            control_tx: control_tx.clone(),
+            finish_tx: Some(finish_tx),
+            task_info: task_info.clone(),
+            tables: tables.clone(),
+            table_configs,
+            current_epoch,
+            last_epoch_checkpoints,
+        })
+        .start();
+
         (BackendFlusher {
             queue: rx,
-            storage,
+            uploads: upload_tx,
             control_tx,
-            finish_tx: Some(finish_tx),
             task_info,
             tables,
-            table_configs,
             current_epoch,
             table_checkpointers: HashMap::new(),
-            last_epoch_checkpoints,
         })
         .start();
 
@@ -230,11 +306,11 @@ async fn get_storage_provider() -> anyhow::Result<StorageProviderRef> {
     // to be synchronized with the workers
 
     Ok(Arc::new(
-        StorageProvider::for_url(&config().checkpoint_url)
+        StorageProvider::for_checkpoints(config().checkpoint_storage_url())
             .await
             .context(format!(
                 "failed to construct checkpoint backend for URL {}",
-                config().checkpoint_url
+                config().checkpoint_storage_url()
             ))?,
     ))
 }
@@ -245,15 +321,45 @@ impl TableManager {
         table_configs: HashMap<String, TableConfig>,
         tx: Sender<ControlResp>,
         checkpoint_metadata: Option<OperatorCheckpointMetadata>,
+        bootstrap: HashMap<String, String>,
     ) -> Result<Self> {
         let storage = get_storage_provider().await?;
 
+        // tables are only bootstrapped when starting fresh; otherwise the bootstrapped state is
+        // part of the checkpoint being restored
+        let mut bootstrapped = HashMap::new();
+        if checkpoint_metadata.is_none() {
+            for (table, url) in bootstrap {
+                let table_config = table_configs.get(&table).ok_or_else(|| {
+                    anyhow!(
+                        "can't bootstrap table '{}', which operator {} doesn't have",
+                        table,
+                        task_info.operator_id
+                    )
+                })?;
+                if let Some(metadata) = bootstrap::bootstrap_table(
+                    &table,
+                    table_config,
+                    &url,
+                    task_info.clone(),
+                    storage.clone(),
+                )
+                .await?
+                {
+                    bootstrapped.insert(table, metadata);
+                }
+            }
+        }
+
         let tables = table_configs
             .iter()
             .map(|(table_name, table_config)| {
-                let table_restore_from = checkpoint_metadata.as_ref().and_then(|metadata| {
-                    metadata.table_checkpoint_metadata.get(table_name).cloned()
-                });
+                let table_restore_from = checkpoint_metadata
+                    .as_ref()
+                    .and_then(|metadata| {
+                        metadata.table_checkpoint_metadata.get(table_name).cloned()
+                    })
+                    .or_else(|| bootstrapped.get(table_name).cloned());
                 let erased_table = match table_config.table_type() {
                     TableEnum::MissingTableType => bail!("should have table type"),
                     TableEnum::GlobalKeyValue => {
@@ -302,6 +408,14 @@ impl TableManager {
             None => {
                 epoch = 1;
                 min_epoch = 1;
+                // carry the bootstrapped files into the first checkpoint
+                for (table, table_metadata) in bootstrapped {
+                    if let Some(metadata) =
+                        tables[&table].subtask_metadata_from_table(table_metadata)?
+                    {
+                        last_epoch_checkpoints.insert(table, metadata);
+                    }
+                }
             }
         }
 
@@ -310,7 +424,6 @@ impl TableManager {
             tx,
             table_configs,
             tables.clone(),
-            storage.clone(),
             epoch,
             last_epoch_checkpoints,
         );
@@ -488,4 +601,195 @@ impl TableManager {
             .ok_or_else(|| anyhow!("Failed to downcast table {}", table_name))?;
         Ok(cache)
     }
+
+    pub async fn get_keyed_state_table(
+        &mut self,
+        table_name: &str,
+        watermark: Option<SystemTime>,
+    ) -> Result<&mut KeyedStateView> {
+        if let std::collections::hash_map::Entry::Vacant(e) =
+            self.caches.entry(table_name.to_string())
+        {
+            let table_implementation = self
+                .tables
+                .get(table_name)
+                .ok_or_else(|| anyhow!("no registered table {}", table_name))?;
+            let expiring_time_key_table = table_implementation
+                .as_any()
+                .downcast_ref::<ExpiringTimeKeyTable>()
+                .ok_or_else(|| anyhow!("wrong table type for table {}", table_name))?;
+            let saved_data = expiring_time_key_table
+                .get_keyed_state_view(self.writer.sender.clone(), watermark)
+                .await?;
+            let cache: Box<dyn Any + Send> = Box::new(saved_data);
+            e.insert(cache);
+        }
+        let cache = self.caches.get_mut(table_name).unwrap();
+        let cache: &mut KeyedStateView = cache
+            .downcast_mut()
+            .ok_or_else(|| anyhow!("Failed to downcast table {}", table_name))?;
+        Ok(cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_types::get_test_task_info;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    type Finished = Arc<Mutex<Vec<(u32, Option<Vec<u8>>)>>>;
+
+    /// Finishes its epoch after a delay, recording the previous epoch's metadata it was given
+    struct TestCheckpointer {
+        delay: Duration,
+        fail: bool,
+        finished: Finished,
+    }
+
+    #[async_trait::async_trait]
+    impl ErasedCheckpointer for TestCheckpointer {
+        async fn insert_data(&mut self, _data: TableData) -> Result<()> {
+            Ok(())
+        }
+
+        async fn finish(
+            self: Box<Self>,
+            checkpoint: &CheckpointMessage,
+            previous_metadata: Option<TableSubtaskCheckpointMetadata>,
+        ) -> Result<Option<(TableSubtaskCheckpointMetadata, usize)>> {
+            tokio::time::sleep(self.delay).await;
+            if self.fail {
+                bail!("failed to write epoch {}", checkpoint.epoch);
+            }
+            self.finished
+                .lock()
+                .unwrap()
+                .push((checkpoint.epoch, previous_metadata.map(|m| m.data)));
+            Ok(Some((
+                TableSubtaskCheckpointMetadata {
+                    subtask_index: 0,
+                    table_type: TableEnum::GlobalKeyValue.into(),
+                    data: vec![checkpoint.epoch as u8],
+                },
+                10,
+            )))
+        }
+    }
+
+    fn upload(epoch: u32, checkpointer: TestCheckpointer, then_stop: bool) -> EpochUpload {
+        let checkpointer: Box<dyn ErasedCheckpointer> = Box::new(checkpointer);
+        EpochUpload {
+            checkpoint: CheckpointMessage {
+                epoch,
+                time: SystemTime::now(),
+                watermark: None,
+                then_stop,
+            },
+            checkpointers: HashMap::from([("t".to_string(), checkpointer)]),
+            compacted_tables: None,
+        }
+    }
+
+    fn start_uploader() -> (
+        Sender<EpochUpload>,
+        Receiver<ControlResp>,
+        oneshot::Receiver<()>,
+    ) {
+        let (upload_tx, upload_rx) = mpsc::channel(MAX_PENDING_UPLOADS);
+        let (control_tx, control_rx) = mpsc::channel(16);
+        let (finish_tx, finish_rx) = oneshot::channel();
+        BackendUploader {
+            queue: upload_rx,
+            control_tx,
+            finish_tx: Some(finish_tx),
+            task_info: Arc::new(get_test_task_info()),
+            tables: HashMap::new(),
+            table_configs: HashMap::new(),
+            current_epoch: 1,
+            last_epoch_checkpoints: HashMap::new(),
+        }
+        .start();
+        (upload_tx, control_rx, finish_rx)
+    }
+
+    #[tokio::test]
+    async fn test_uploads_complete_in_epoch_order() {
+        let finished = Finished::default();
+        let (upload_tx, mut control_rx, finish_rx) = start_uploader();
+
+        // the first epoch is the slowest to upload, but must still be reported first
+        for (epoch, delay) in [(1, 50), (2, 0), (3, 10)] {
+            let checkpointer = TestCheckpointer {
+                delay: Duration::from_millis(delay),
+                fail: false,
+                finished: finished.clone(),
+            };
+            upload_tx
+                .send(upload(epoch, checkpointer, epoch == 3))
+                .await
+                .unwrap();
+        }
+
+        for epoch in 1..=3 {
+            match control_rx.recv().await.unwrap() {
+                ControlResp::CheckpointCompleted(completed) => {
+                    assert_eq!(completed.checkpoint_epoch, epoch);
+                    assert_eq!(completed.subtask_metadata.bytes, 10);
+                }
+                other => panic!("expected a completed checkpoint, got {:?}", other),
+            }
+        }
+
+        // each epoch is finished on top of the metadata of the one before it
+        assert_eq!(
+            *finished.lock().unwrap(),
+            vec![(1, None), (2, Some(vec![1])), (3, Some(vec![2]))]
+        );
+        finish_rx
+            .await
+            .expect("uploader should finish after the final epoch");
+    }
+
+    #[tokio::test]
+    async fn test_upload_failure_fails_the_checkpoint() {
+        let finished = Finished::default();
+        let (upload_tx, mut control_rx, _finish_rx) = start_uploader();
+
+        let checkpointer = TestCheckpointer {
+            delay: Duration::ZERO,
+            fail: true,
+            finished: finished.clone(),
+        };
+        upload_tx
+            .send(upload(1, checkpointer, false))
+            .await
+            .unwrap();
+
+        match control_rx.recv().await.unwrap() {
+            ControlResp::TaskFailed {
+                error, category, ..
+            } => {
+                assert!(matches!(category, ErrorCategory::Checkpoint));
+                assert!(error.contains("failed to write epoch 1"), "{}", error);
+            }
+            other => panic!("expected the task to fail, got {:?}", other),
+        }
+
+        // the uploader stops, so the epoch is never reported as completed and the flusher can't
+        // hand off any more epochs
+        assert!(control_rx.recv().await.is_none());
+        upload_tx.closed().await;
+        let checkpointer = TestCheckpointer {
+            delay: Duration::ZERO,
+            fail: false,
+            finished: finished.clone(),
+        };
+        assert!(upload_tx
+            .send(upload(2, checkpointer, false))
+            .await
+            .is_err());
+        assert!(finished.lock().unwrap().is_empty());
+    }
 }
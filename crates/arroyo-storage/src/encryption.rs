@@ -0,0 +1,433 @@
+//! Client-side envelope encryption of the objects written through a [`StorageProvider`](crate::StorageProvider).
+//!
+//! Each object is encrypted with AES-256-GCM under a data key, and the data key is itself
+//! encrypted ("wrapped") by a key-encryption key that never leaves its key manager, either AWS KMS
+//! or a locally-configured key. The wrapped data key is stored in the object's header, so that any
+//! process with access to the key-encryption key can decrypt it. Data keys are reused for a while
+//! rather than generated for every object, to avoid a KMS call for each state file.
+//!
+//! Encrypted objects have the layout
+//!
+//! ```text
+//! MAGIC | wrapped key length (u32 LE) | wrapped key | nonce | ciphertext and tag
+//! ```
+//!
+//! where the header ahead of the nonce is authenticated along with the ciphertext. Objects that
+//! don't start with the magic bytes are returned unchanged when decrypting, so that stores
+//! written before encryption was enabled can still be read.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use arroyo_rpc::config::CheckpointEncryptionConfig;
+use aws_sdk_kms::model::DataKeySpec;
+use aws_sdk_kms::types::Blob;
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use futures::future::BoxFuture;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use tokio::io::AsyncWrite;
+
+use crate::StorageError;
+
+const MAGIC: &[u8; 8] = b"ARROYOE1";
+const KEY_LEN: usize = 32;
+
+/// How long a data key is used to encrypt new objects before a new one is generated
+const DATA_KEY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The most unwrapped data keys kept for decryption before the cache is cleared
+const MAX_CACHED_KEYS: usize = 1024;
+
+enum KeySource {
+    Kms {
+        client: aws_sdk_kms::Client,
+        key_id: String,
+    },
+    Local {
+        key: LessSafeKey,
+    },
+}
+
+struct DataKey {
+    key: Arc<LessSafeKey>,
+    wrapped: Vec<u8>,
+    created_at: Instant,
+}
+
+pub struct EnvelopeEncryption {
+    source: KeySource,
+    rng: SystemRandom,
+    current: Mutex<Option<DataKey>>,
+    unwrapped: Mutex<HashMap<Vec<u8>, Arc<LessSafeKey>>>,
+}
+
+impl Debug for EnvelopeEncryption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let source = match &self.source {
+            KeySource::Kms { key_id, .. } => format!("kms:{}", key_id),
+            KeySource::Local { .. } => "local".to_string(),
+        };
+        f.debug_struct("EnvelopeEncryption")
+            .field("source", &source)
+            .finish()
+    }
+}
+
+fn encryption_error(message: impl Into<String>) -> StorageError {
+    StorageError::Encryption(message.into())
+}
+
+fn aes_key(bytes: &[u8]) -> Result<LessSafeKey, StorageError> {
+    if bytes.len() != KEY_LEN {
+        return Err(encryption_error(format!(
+            "encryption keys must be {} bytes, not {}",
+            KEY_LEN,
+            bytes.len()
+        )));
+    }
+    let key = UnboundKey::new(&AES_256_GCM, bytes)
+        .map_err(|_| encryption_error("invalid encryption key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+impl EnvelopeEncryption {
+    /// Encrypts data keys with the AWS KMS key `key_id` (a key id, ARN, or alias)
+    pub async fn kms(key_id: String) -> Self {
+        let config = aws_config::load_from_env().await;
+        Self::new(KeySource::Kms {
+            client: aws_sdk_kms::Client::new(&config),
+            key_id,
+        })
+    }
+
+    /// Encrypts data keys with a local 256-bit key
+    pub fn local(key: &[u8]) -> Result<Self, StorageError> {
+        Ok(Self::new(KeySource::Local { key: aes_key(key)? }))
+    }
+
+    fn new(source: KeySource) -> Self {
+        Self {
+            source,
+            rng: SystemRandom::new(),
+            current: Mutex::new(None),
+            unwrapped: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The encryption configured for checkpoints, if any
+    pub async fn from_config(
+        config: &CheckpointEncryptionConfig,
+    ) -> Result<Option<Self>, StorageError> {
+        match (&config.kms_key_id, &config.local_key) {
+            (Some(_), Some(_)) => Err(encryption_error(
+                "only one of kms-key-id and local-key may be set for checkpoint encryption",
+            )),
+            (Some(key_id), None) => Ok(Some(Self::kms(key_id.clone()).await)),
+            (None, Some(key)) => {
+                let key = BASE64_STANDARD.decode(key.trim()).map_err(|_| {
+                    encryption_error("checkpoint encryption local-key is not valid base64")
+                })?;
+                Ok(Some(Self::local(&key)?))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    fn nonce(&self) -> Result<[u8; NONCE_LEN], StorageError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| encryption_error("failed to generate nonce"))?;
+        Ok(nonce)
+    }
+
+    async fn generate_data_key(&self) -> Result<DataKey, StorageError> {
+        let (plaintext, wrapped) = match &self.source {
+            KeySource::Kms { client, key_id } => {
+                let resp = client
+                    .generate_data_key()
+                    .key_id(key_id)
+                    .key_spec(DataKeySpec::Aes256)
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        encryption_error(format!("failed to generate data key with KMS: {}", e))
+                    })?;
+                let plaintext = resp
+                    .plaintext()
+                    .ok_or_else(|| encryption_error("KMS returned no data key"))?
+                    .as_ref()
+                    .to_vec();
+                let wrapped = resp
+                    .ciphertext_blob()
+                    .ok_or_else(|| encryption_error("KMS returned no encrypted data key"))?
+                    .as_ref()
+                    .to_vec();
+                (plaintext, wrapped)
+            }
+            KeySource::Local { key } => {
+                let mut plaintext = vec![0u8; KEY_LEN];
+                self.rng
+                    .fill(&mut plaintext)
+                    .map_err(|_| encryption_error("failed to generate data key"))?;
+
+                let nonce = self.nonce()?;
+                let mut sealed = plaintext.clone();
+                key.seal_in_place_append_tag(
+                    Nonce::assume_unique_for_key(nonce),
+                    Aad::empty(),
+                    &mut sealed,
+                )
+                .map_err(|_| encryption_error("failed to encrypt data key"))?;
+
+                let mut wrapped = nonce.to_vec();
+                wrapped.extend_from_slice(&sealed);
+                (plaintext, wrapped)
+            }
+        };
+
+        Ok(DataKey {
+            key: Arc::new(aes_key(&plaintext)?),
+            wrapped,
+            created_at: Instant::now(),
+        })
+    }
+
+    async fn unwrap_data_key(&self, wrapped: &[u8]) -> Result<Arc<LessSafeKey>, StorageError> {
+        if let Some(key) = self.unwrapped.lock().unwrap().get(wrapped) {
+            return Ok(key.clone());
+        }
+
+        let plaintext = match &self.source {
+            KeySource::Kms { client, key_id } => client
+                .decrypt()
+                .key_id(key_id)
+                .ciphertext_blob(Blob::new(wrapped.to_vec()))
+                .send()
+                .await
+                .map_err(|e| {
+                    encryption_error(format!("failed to decrypt data key with KMS: {}", e))
+                })?
+                .plaintext()
+                .ok_or_else(|| encryption_error("KMS returned no data key"))?
+                .as_ref()
+                .to_vec(),
+            KeySource::Local { key } => {
+                if wrapped.len() < NONCE_LEN {
+                    return Err(encryption_error("encrypted data key is truncated"));
+                }
+                let (nonce, sealed) = wrapped.split_at(NONCE_LEN);
+                let mut sealed = sealed.to_vec();
+                key.open_in_place(
+                    Nonce::try_assume_unique_for_key(nonce).unwrap(),
+                    Aad::empty(),
+                    &mut sealed,
+                )
+                .map_err(|_| {
+                    encryption_error("failed to decrypt data key; was it written with another key?")
+                })?
+                .to_vec()
+            }
+        };
+
+        let key = Arc::new(aes_key(&plaintext)?);
+        let mut unwrapped = self.unwrapped.lock().unwrap();
+        if unwrapped.len() >= MAX_CACHED_KEYS {
+            unwrapped.clear();
+        }
+        unwrapped.insert(wrapped.to_vec(), key.clone());
+        Ok(key)
+    }
+
+    /// The data key to encrypt new objects with, and its wrapped form
+    async fn data_key(&self) -> Result<(Arc<LessSafeKey>, Vec<u8>), StorageError> {
+        if let Some(current) = self.current.lock().unwrap().as_ref() {
+            if current.created_at.elapsed() < DATA_KEY_TTL {
+                return Ok((current.key.clone(), current.wrapped.clone()));
+            }
+        }
+
+        let data_key = self.generate_data_key().await?;
+        let result = (data_key.key.clone(), data_key.wrapped.clone());
+        *self.current.lock().unwrap() = Some(data_key);
+        Ok(result)
+    }
+
+    pub async fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let (key, wrapped) = self.data_key().await?;
+
+        let mut out = Vec::with_capacity(
+            MAGIC.len() + 4 + wrapped.len() + NONCE_LEN + plaintext.len() + AES_256_GCM.tag_len(),
+        );
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&(wrapped.len() as u32).to_le_bytes());
+        out.extend_from_slice(&wrapped);
+        let header_len = out.len();
+
+        let nonce = self.nonce()?;
+        out.extend_from_slice(&nonce);
+
+        let mut sealed = plaintext.to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(&out[..header_len]),
+            &mut sealed,
+        )
+        .map_err(|_| encryption_error("failed to encrypt object"))?;
+        out.extend_from_slice(&sealed);
+
+        Ok(out)
+    }
+
+    /// Decrypts an object written by [`encrypt`](Self::encrypt); objects that weren't encrypted
+    /// are returned unchanged
+    pub async fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, StorageError> {
+        if !data.starts_with(MAGIC) {
+            return Ok(data.to_vec());
+        }
+
+        let truncated = || encryption_error("encrypted object is truncated");
+
+        let len_start = MAGIC.len();
+        let wrapped_start = len_start + 4;
+        let wrapped_len = u32::from_le_bytes(
+            data.get(len_start..wrapped_start)
+                .ok_or_else(truncated)?
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        let header_len = wrapped_start + wrapped_len;
+        let wrapped = data.get(wrapped_start..header_len).ok_or_else(truncated)?;
+        let nonce = data
+            .get(header_len..header_len + NONCE_LEN)
+            .ok_or_else(truncated)?;
+
+        let key = self.unwrap_data_key(wrapped).await?;
+
+        let mut sealed = data[header_len + NONCE_LEN..].to_vec();
+        let plaintext_len = key
+            .open_in_place(
+                Nonce::try_assume_unique_for_key(nonce).unwrap(),
+                Aad::from(&data[..header_len]),
+                &mut sealed,
+            )
+            .map_err(|_| encryption_error("failed to decrypt object; it may be corrupted"))?
+            .len();
+        sealed.truncate(plaintext_len);
+
+        Ok(sealed)
+    }
+}
+
+/// Buffers an object written through [`AsyncWrite`], then encrypts and uploads it on shutdown
+pub(crate) struct EncryptingWriter {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+    encryption: Arc<EnvelopeEncryption>,
+    buffer: Vec<u8>,
+    upload: Option<BoxFuture<'static, io::Result<()>>>,
+}
+
+impl EncryptingWriter {
+    pub(crate) fn new(
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+        encryption: Arc<EnvelopeEncryption>,
+    ) -> Self {
+        Self {
+            store,
+            path,
+            encryption,
+            buffer: vec![],
+            upload: None,
+        }
+    }
+}
+
+impl AsyncWrite for EncryptingWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.upload.is_some() {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "write after shutdown",
+            )));
+        }
+        this.buffer.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.upload.is_none() {
+            let store = this.store.clone();
+            let path = this.path.clone();
+            let encryption = this.encryption.clone();
+            let data = std::mem::take(&mut this.buffer);
+
+            this.upload = Some(Box::pin(async move {
+                let encrypted = encryption
+                    .encrypt(&data)
+                    .await
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                store
+                    .put(&path, encrypted.into())
+                    .await
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                Ok(())
+            }));
+        }
+
+        this.upload.as_mut().unwrap().as_mut().poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let encryption = EnvelopeEncryption::local(&[7u8; KEY_LEN]).unwrap();
+
+        let data = b"checkpoint data".to_vec();
+        let encrypted = encryption.encrypt(&data).await.unwrap();
+        assert!(encrypted.starts_with(MAGIC));
+        assert!(!encrypted.windows(data.len()).any(|w| w == data.as_slice()));
+        assert_eq!(encryption.decrypt(&encrypted).await.unwrap(), data);
+
+        // a different process with the same key can decrypt it
+        let other = EnvelopeEncryption::local(&[7u8; KEY_LEN]).unwrap();
+        assert_eq!(other.decrypt(&encrypted).await.unwrap(), data);
+
+        // but not one with a different key
+        let wrong = EnvelopeEncryption::local(&[8u8; KEY_LEN]).unwrap();
+        assert!(wrong.decrypt(&encrypted).await.is_err());
+
+        // tampering is detected
+        let mut tampered = encrypted.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(encryption.decrypt(&tampered).await.is_err());
+
+        // unencrypted objects are passed through
+        assert_eq!(encryption.decrypt(b"PAR1").await.unwrap(), b"PAR1");
+    }
+}
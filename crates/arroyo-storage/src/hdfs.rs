@@ -0,0 +1,743 @@
+//! An [`ObjectStore`] for HDFS, via the WebHDFS REST API exposed by the namenode.
+//!
+//! Object paths are HDFS paths relative to the root of the filesystem. WebHDFS has no multipart
+//! uploads, so parts are written as separate files next to the target and concatenated into it
+//! when the upload completes (see [`WebHdfs::put_part`] and [`WebHdfs::complete_parts`]).
+
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream};
+use futures::{StreamExt, TryStreamExt};
+use object_store::multipart::PartId;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetResult, GetResultPayload, ListResult, MultipartId, ObjectMeta, ObjectStore,
+    PutMode, PutOptions, PutResult,
+};
+use reqwest::{Method, Response, StatusCode};
+use serde::Deserialize;
+use tokio::io::AsyncWrite;
+
+const STORE: &str = "WebHDFS";
+
+/// Writes are buffered until they reach this size before being appended to the file
+const WRITE_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+enum WebHdfsError {
+    #[error("request to {op} failed: {source}")]
+    Request {
+        op: &'static str,
+        source: reqwest::Error,
+    },
+
+    #[error("{op} failed with status {status}: {message}")]
+    Remote {
+        op: &'static str,
+        status: StatusCode,
+        exception: String,
+        message: String,
+    },
+
+    #[error("{op} redirected without a location")]
+    MissingRedirect { op: &'static str },
+
+    #[error("{op} returned false")]
+    Failed { op: &'static str },
+}
+
+impl WebHdfsError {
+    fn into_object_store_error(self, path: &Path) -> object_store::Error {
+        match &self {
+            WebHdfsError::Remote {
+                status, exception, ..
+            } if *status == StatusCode::NOT_FOUND || exception == "FileNotFoundException" => {
+                object_store::Error::NotFound {
+                    path: path.to_string(),
+                    source: Box::new(self),
+                }
+            }
+            WebHdfsError::Remote { exception, .. } if exception == "FileAlreadyExistsException" => {
+                object_store::Error::AlreadyExists {
+                    path: path.to_string(),
+                    source: Box::new(self),
+                }
+            }
+            _ => object_store::Error::Generic {
+                store: STORE,
+                source: Box::new(self),
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileStatus {
+    #[serde(default)]
+    path_suffix: String,
+    length: usize,
+    modification_time: i64,
+    #[serde(rename = "type")]
+    file_type: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct FileStatusResponse {
+    file_status: FileStatus,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct FileStatuses {
+    file_status: Vec<FileStatus>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListStatusResponse {
+    file_statuses: FileStatuses,
+}
+
+#[derive(Deserialize)]
+struct BooleanResponse {
+    boolean: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RemoteExceptionResponse {
+    remote_exception: RemoteException,
+}
+
+#[derive(Deserialize)]
+struct RemoteException {
+    exception: String,
+    message: String,
+}
+
+#[derive(Debug)]
+struct WebHdfsInner {
+    client: reqwest::Client,
+    endpoint: String,
+    user: Option<String>,
+    delegation_token: Option<String>,
+}
+
+/// A WebHDFS client for the namenode at `endpoint` (e.g. `http://namenode:9870`)
+#[derive(Debug, Clone)]
+pub struct WebHdfs {
+    inner: Arc<WebHdfsInner>,
+}
+
+impl Display for WebHdfs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WebHdfs({})", self.inner.endpoint)
+    }
+}
+
+impl WebHdfs {
+    pub fn new(
+        endpoint: String,
+        user: Option<String>,
+        delegation_token: Option<String>,
+    ) -> object_store::Result<Self> {
+        // data is read from and written to datanodes by following the namenode's redirects, which
+        // we do ourselves so that request bodies are only sent to the datanodes
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| object_store::Error::Generic {
+                store: STORE,
+                source: Box::new(e),
+            })?;
+
+        Ok(Self {
+            inner: Arc::new(WebHdfsInner {
+                client,
+                endpoint: endpoint.trim_end_matches('/').to_string(),
+                user,
+                delegation_token,
+            }),
+        })
+    }
+
+    fn url(&self, path: &Path, op: &str, params: &[(&str, String)]) -> String {
+        let mut url = reqwest::Url::parse(&format!("{}/webhdfs/v1/", self.inner.endpoint))
+            .expect("endpoint was validated when the store was created");
+        url.path_segments_mut()
+            .unwrap()
+            .pop_if_empty()
+            .extend(path.parts().map(|p| p.as_ref().to_string()));
+
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("op", op);
+            if let Some(user) = &self.inner.user {
+                query.append_pair("user.name", user);
+            }
+            if let Some(token) = &self.inner.delegation_token {
+                query.append_pair("delegation", token);
+            }
+            for (k, v) in params {
+                query.append_pair(k, v);
+            }
+        }
+
+        url.to_string()
+    }
+
+    async fn check(op: &'static str, resp: Response) -> Result<Response, WebHdfsError> {
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+
+        let body = resp.text().await.unwrap_or_default();
+        let (exception, message) = match serde_json::from_str::<RemoteExceptionResponse>(&body) {
+            Ok(e) => (e.remote_exception.exception, e.remote_exception.message),
+            Err(_) => (String::new(), body),
+        };
+
+        Err(WebHdfsError::Remote {
+            op,
+            status,
+            exception,
+            message,
+        })
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &Path,
+        op: &'static str,
+        params: &[(&str, String)],
+    ) -> Result<Response, WebHdfsError> {
+        let resp = self
+            .inner
+            .client
+            .request(method, self.url(path, op, params))
+            .send()
+            .await
+            .map_err(|source| WebHdfsError::Request { op, source })?;
+
+        Self::check(op, resp).await
+    }
+
+    /// Sends a request to the namenode, then follows its redirect to a datanode, sending `body`
+    /// there if there is one
+    async fn redirected(
+        &self,
+        method: Method,
+        path: &Path,
+        op: &'static str,
+        params: &[(&str, String)],
+        body: Option<Bytes>,
+    ) -> Result<Response, WebHdfsError> {
+        let resp = self
+            .inner
+            .client
+            .request(method.clone(), self.url(path, op, params))
+            .send()
+            .await
+            .map_err(|source| WebHdfsError::Request { op, source })?;
+
+        if !resp.status().is_redirection() {
+            return Self::check(op, resp).await;
+        }
+
+        let location = resp
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .ok_or(WebHdfsError::MissingRedirect { op })?
+            .to_string();
+
+        let mut request = self.inner.client.request(method, location);
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                .body(body);
+        }
+
+        let resp = request
+            .send()
+            .await
+            .map_err(|source| WebHdfsError::Request { op, source })?;
+
+        Self::check(op, resp).await
+    }
+
+    async fn boolean(
+        &self,
+        method: Method,
+        path: &Path,
+        op: &'static str,
+        params: &[(&str, String)],
+    ) -> Result<(), WebHdfsError> {
+        let resp: BooleanResponse = self
+            .request(method, path, op, params)
+            .await?
+            .json()
+            .await
+            .map_err(|source| WebHdfsError::Request { op, source })?;
+
+        if resp.boolean {
+            Ok(())
+        } else {
+            Err(WebHdfsError::Failed { op })
+        }
+    }
+
+    async fn create(&self, path: &Path, data: Bytes, overwrite: bool) -> object_store::Result<()> {
+        self.redirected(
+            Method::PUT,
+            path,
+            "CREATE",
+            &[("overwrite", overwrite.to_string())],
+            Some(data),
+        )
+        .await
+        .map_err(|e| e.into_object_store_error(path))?;
+        Ok(())
+    }
+
+    async fn append(&self, path: &Path, data: Bytes) -> object_store::Result<()> {
+        self.redirected(Method::POST, path, "APPEND", &[], Some(data))
+            .await
+            .map_err(|e| e.into_object_store_error(path))?;
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.boolean(
+            Method::PUT,
+            from,
+            "RENAME",
+            &[("destination", format!("/{}", to))],
+        )
+        .await
+        .map_err(|e| e.into_object_store_error(from))
+    }
+
+    async fn status(&self, path: &Path) -> object_store::Result<FileStatus> {
+        let resp: FileStatusResponse = self
+            .request(Method::GET, path, "GETFILESTATUS", &[])
+            .await
+            .map_err(|e| e.into_object_store_error(path))?
+            .json()
+            .await
+            .map_err(|source| {
+                WebHdfsError::Request {
+                    op: "GETFILESTATUS",
+                    source,
+                }
+                .into_object_store_error(path)
+            })?;
+
+        Ok(resp.file_status)
+    }
+
+    async fn list_status(&self, path: &Path) -> object_store::Result<Vec<FileStatus>> {
+        let result = self
+            .request(Method::GET, path, "LISTSTATUS", &[])
+            .await
+            .map_err(|e| e.into_object_store_error(path));
+
+        let resp = match result {
+            Ok(resp) => resp,
+            // listing a prefix that doesn't exist returns nothing, like other object stores
+            Err(object_store::Error::NotFound { .. }) => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        let resp: ListStatusResponse = resp.json().await.map_err(|source| {
+            WebHdfsError::Request {
+                op: "LISTSTATUS",
+                source,
+            }
+            .into_object_store_error(path)
+        })?;
+
+        Ok(resp.file_statuses.file_status)
+    }
+
+    fn meta(location: Path, status: &FileStatus) -> ObjectMeta {
+        ObjectMeta {
+            location,
+            last_modified: modification_time(status.modification_time),
+            size: status.length,
+            e_tag: None,
+            version: None,
+        }
+    }
+
+    async fn list_recursive(&self, prefix: Option<Path>) -> object_store::Result<Vec<ObjectMeta>> {
+        let mut objects = vec![];
+        let mut dirs = vec![prefix.unwrap_or_default()];
+
+        while let Some(dir) = dirs.pop() {
+            for status in self.list_status(&dir).await? {
+                if status.path_suffix.is_empty() {
+                    // the prefix is itself a file
+                    objects.push(Self::meta(dir.clone(), &status));
+                    continue;
+                }
+                let location = dir.child(status.path_suffix.as_str());
+                if status.file_type == "DIRECTORY" {
+                    dirs.push(location);
+                } else {
+                    objects.push(Self::meta(location, &status));
+                }
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn read(
+        &self,
+        location: &Path,
+        range: Option<Range<usize>>,
+    ) -> object_store::Result<GetResult> {
+        let status = self.status(location).await?;
+        let meta = Self::meta(location.clone(), &status);
+        let range = range.unwrap_or(0..meta.size);
+
+        let stream = if range.is_empty() {
+            stream::empty().boxed()
+        } else {
+            self.redirected(
+                Method::GET,
+                location,
+                "OPEN",
+                &[
+                    ("offset", range.start.to_string()),
+                    ("length", range.len().to_string()),
+                ],
+                None,
+            )
+            .await
+            .map_err(|e| e.into_object_store_error(location))?
+            .bytes_stream()
+            .map_err(|source| object_store::Error::Generic {
+                store: STORE,
+                source: Box::new(source),
+            })
+            .boxed()
+        };
+
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(stream),
+            meta,
+            range,
+        })
+    }
+
+    fn part_path(location: &Path, multipart_id: &MultipartId, part_number: usize) -> Path {
+        // parts are written alongside the target, since concat requires them to be in the same
+        // directory
+        let filename = location.filename().unwrap_or_default();
+        let part = format!(".{}.{}.part-{:05}", filename, multipart_id, part_number);
+        let mut parts: Vec<_> = location.parts().collect();
+        parts.pop();
+        Path::from_iter(parts).child(part.as_str())
+    }
+
+    /// Writes one part of a multipart upload started with an id from [`new_multipart_id`]
+    pub async fn put_part(
+        &self,
+        location: &Path,
+        multipart_id: &MultipartId,
+        part_number: usize,
+        data: Bytes,
+    ) -> object_store::Result<PartId> {
+        let path = Self::part_path(location, multipart_id, part_number);
+        self.create(&path, data, true).await?;
+        Ok(PartId {
+            content_id: path.to_string(),
+        })
+    }
+
+    /// Completes a multipart upload by concatenating its parts into `location`
+    pub async fn complete_parts(
+        &self,
+        location: &Path,
+        parts: Vec<PartId>,
+    ) -> object_store::Result<()> {
+        let mut parts = parts.into_iter().map(|p| Path::from(p.content_id));
+        let Some(first) = parts.next() else {
+            return self.create(location, Bytes::new(), true).await;
+        };
+
+        // concat needs a non-empty target, so the first part becomes it
+        let _ = self.delete(location).await;
+        self.rename(&first, location).await?;
+
+        let rest: Vec<_> = parts.map(|p| format!("/{}", p)).collect();
+        if !rest.is_empty() {
+            self.request(
+                Method::POST,
+                location,
+                "CONCAT",
+                &[("sources", rest.join(","))],
+            )
+            .await
+            .map_err(|e| e.into_object_store_error(location))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn new_multipart_id() -> MultipartId {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+fn modification_time(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl ObjectStore for WebHdfs {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        let overwrite = match opts.mode {
+            PutMode::Overwrite => true,
+            PutMode::Create => false,
+            PutMode::Update(_) => return Err(object_store::Error::NotImplemented),
+        };
+
+        self.create(location, bytes, overwrite).await?;
+        Ok(PutResult {
+            e_tag: None,
+            version: None,
+        })
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> object_store::Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        Ok((
+            new_multipart_id(),
+            Box::new(WebHdfsWriter {
+                store: self.clone(),
+                location: location.clone(),
+                buffer: vec![],
+                created: false,
+                inflight: None,
+                shutdown: false,
+            }),
+        ))
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &Path,
+        _multipart_id: &MultipartId,
+    ) -> object_store::Result<()> {
+        self.delete(location).await
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        if options.range.is_some() {
+            return Err(object_store::Error::NotImplemented);
+        }
+
+        if options.head {
+            let status = self.status(location).await?;
+            let meta = Self::meta(location.clone(), &status);
+            return Ok(GetResult {
+                range: 0..meta.size,
+                payload: GetResultPayload::Stream(stream::empty().boxed()),
+                meta,
+            });
+        }
+
+        self.read(location, None).await
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> object_store::Result<Bytes> {
+        self.read(location, Some(range)).await?.bytes().await
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        // deleting a missing file returns false, which object stores treat as success
+        match self
+            .boolean(
+                Method::DELETE,
+                location,
+                "DELETE",
+                &[("recursive", "false".to_string())],
+            )
+            .await
+        {
+            Ok(()) | Err(WebHdfsError::Failed { .. }) => Ok(()),
+            Err(e) => Err(e.into_object_store_error(location)),
+        }
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        let prefix = prefix.cloned();
+        stream::once(self.list_recursive(prefix))
+            .map_ok(|objects| stream::iter(objects.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        let prefix = prefix.cloned().unwrap_or_default();
+        let mut result = ListResult {
+            common_prefixes: vec![],
+            objects: vec![],
+        };
+
+        for status in self.list_status(&prefix).await? {
+            if status.path_suffix.is_empty() {
+                continue;
+            }
+            let location = prefix.child(status.path_suffix.as_str());
+            if status.file_type == "DIRECTORY" {
+                result.common_prefixes.push(location);
+            } else {
+                result.objects.push(Self::meta(location, &status));
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        let data = self.get(from).await?.bytes().await?;
+        self.create(to, data, true).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        let data = self.get(from).await?.bytes().await?;
+        self.create(to, data, false).await
+    }
+}
+
+/// Writes a file by creating it and appending to it as data is buffered
+struct WebHdfsWriter {
+    store: WebHdfs,
+    location: Path,
+    buffer: Vec<u8>,
+    created: bool,
+    inflight: Option<BoxFuture<'static, object_store::Result<()>>>,
+    shutdown: bool,
+}
+
+impl WebHdfsWriter {
+    fn start_write(&mut self) {
+        let store = self.store.clone();
+        let location = self.location.clone();
+        let data = Bytes::from(std::mem::take(&mut self.buffer));
+        let created = self.created;
+        self.created = true;
+
+        self.inflight = Some(Box::pin(async move {
+            if created {
+                store.append(&location, data).await
+            } else {
+                store.create(&location, data, true).await
+            }
+        }));
+    }
+
+    fn poll_inflight(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        if let Some(inflight) = &mut self.inflight {
+            let result = futures::ready!(inflight.as_mut().poll(cx));
+            self.inflight = None;
+            result.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for WebHdfsWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_inflight(cx))?;
+
+        this.buffer.extend_from_slice(buf);
+        if this.buffer.len() >= WRITE_BUFFER_SIZE {
+            this.start_write();
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.get_mut().poll_inflight(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_inflight(cx))?;
+
+        if !this.shutdown {
+            this.shutdown = true;
+            if !this.buffer.is_empty() || !this.created {
+                this.start_write();
+                return this.poll_inflight(cx);
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls() {
+        let store = WebHdfs::new(
+            "http://namenode:9870/".to_string(),
+            Some("arroyo".to_string()),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            store.url(
+                &Path::from("checkpoints/job-1/metadata"),
+                "OPEN",
+                &[("offset", "10".to_string())]
+            ),
+            "http://namenode:9870/webhdfs/v1/checkpoints/job-1/metadata?op=OPEN&user.name=arroyo&offset=10"
+        );
+
+        assert_eq!(
+            WebHdfs::part_path(&Path::from("out/data.parquet"), &"abc".to_string(), 2),
+            Path::from("out/.data.parquet.abc.part-00002")
+        );
+    }
+}
@@ -6,24 +6,31 @@ use std::{
     sync::{Arc, OnceLock},
 };
 
+use arroyo_rpc::config::config;
 use arroyo_rpc::retry;
 use aws::ArroyoCredentialProvider;
 use bytes::Bytes;
-use futures::{Stream, StreamExt};
+use futures::{Stream, StreamExt, TryStreamExt};
 use object_store::aws::{AmazonS3ConfigKey, AwsCredential};
 use object_store::gcp::GoogleCloudStorageBuilder;
 use object_store::multipart::PartId;
 use object_store::path::Path;
-use object_store::{aws::AmazonS3Builder, local::LocalFileSystem, ObjectStore};
+use object_store::{aws::AmazonS3Builder, local::LocalFileSystem, ObjectMeta, ObjectStore};
 use object_store::{CredentialProvider, MultipartId};
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
 use std::time::{Duration, Instant};
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::io::AsyncWrite;
+use tokio::sync::{OnceCell, RwLock};
 use tracing::{debug, error, trace};
 
+use crate::encryption::{EncryptingWriter, EnvelopeEncryption};
+use crate::hdfs::WebHdfs;
+
 mod aws;
+pub mod encryption;
+pub mod hdfs;
 
 /// A reference-counted reference to a [StorageProvider].
 pub type StorageProviderRef = Arc<StorageProvider>;
@@ -37,6 +44,9 @@ pub struct StorageProvider {
     // May require storage_options to properly instantiate
     object_store_base_url: String,
     storage_options: HashMap<String, String>,
+    encryption: Option<Arc<EnvelopeEncryption>>,
+    // HDFS has no multipart uploads, so they're emulated by the store
+    hdfs: Option<WebHdfs>,
 }
 
 #[derive(Error, Debug)]
@@ -55,6 +65,9 @@ pub enum StorageError {
 
     #[error("failed to load credentials: {0}")]
     CredentialsError(String),
+
+    #[error("encryption error: {0}")]
+    Encryption(String),
 }
 
 // https://s3.us-west-2.amazonaws.com/DOC-EXAMPLE-BUCKET1/puppy.jpg
@@ -83,12 +96,16 @@ const GCS_PATH: &str =
     r"^https://storage\.googleapis\.com/(?P<bucket>[a-z\d\-_\.]+)(/(?P<key>.+))?$";
 const GCS_URL: &str = r"^[gG][sS]://(?P<bucket>[a-z0-9\-\.]+)(/(?P<key>.+))?$";
 
+// webhdfs://namenode:9870/my/path, or swebhdfs:// for https
+const WEBHDFS_URL: &str = r"^(?P<scheme>s?webhdfs)://(?P<authority>[^/]+)(/(?P<key>.+))?$";
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Copy)]
 enum Backend {
     S3,
     #[allow(clippy::upper_case_acronyms)]
     GCS,
     Local,
+    Hdfs,
 }
 
 fn matchers() -> &'static HashMap<Backend, Vec<Regex>> {
@@ -115,6 +132,8 @@ fn matchers() -> &'static HashMap<Backend, Vec<Regex>> {
             ],
         );
 
+        m.insert(Backend::Hdfs, vec![Regex::new(WEBHDFS_URL).unwrap()]);
+
         m.insert(
             Backend::Local,
             vec![
@@ -171,11 +190,20 @@ pub struct LocalConfig {
     pub key: Option<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HdfsConfig {
+    /// The WebHDFS endpoint of the namenode, like `http://namenode:9870`
+    pub endpoint: String,
+    /// The path within HDFS, without a leading slash
+    pub key: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BackendConfig {
     S3(S3Config),
     GCS(GCSConfig),
     Local(LocalConfig),
+    Hdfs(HdfsConfig),
 }
 
 impl BackendConfig {
@@ -186,6 +214,7 @@ impl BackendConfig {
                     Backend::S3 => Self::parse_s3(matches),
                     Backend::GCS => Self::parse_gcs(matches),
                     Backend::Local => Self::parse_local(matches, with_key),
+                    Backend::Hdfs => Self::parse_hdfs(matches),
                 };
             }
         }
@@ -252,6 +281,24 @@ impl BackendConfig {
         Ok(BackendConfig::GCS(GCSConfig { bucket, key }))
     }
 
+    fn parse_hdfs(matches: Captures) -> Result<Self, StorageError> {
+        let protocol = match matches.name("scheme").unwrap().as_str() {
+            "swebhdfs" => "https",
+            _ => "http",
+        };
+        let authority = matches
+            .name("authority")
+            .expect("authority should always be available")
+            .as_str();
+
+        let key = matches.name("key").map(|m| m.as_str().to_string());
+
+        Ok(BackendConfig::Hdfs(HdfsConfig {
+            endpoint: format!("{}://{}", protocol, authority),
+            key,
+        }))
+    }
+
     fn parse_local(matches: Captures, with_key: bool) -> Result<Self, StorageError> {
         let path = matches
             .name("path")
@@ -278,17 +325,27 @@ impl BackendConfig {
         }))
     }
 
-    fn key(&self) -> Option<&String> {
+    pub fn key(&self) -> Option<&String> {
         match self {
             BackendConfig::S3(s3) => s3.key.as_ref(),
             BackendConfig::GCS(gcs) => gcs.key.as_ref(),
             BackendConfig::Local(local) => local.key.as_ref(),
+            BackendConfig::Hdfs(hdfs) => hdfs.key.as_ref(),
         }
     }
 
     pub fn is_local(&self) -> bool {
         matches!(self, BackendConfig::Local { .. })
     }
+
+    /// The bucket for object stores that have one
+    pub fn bucket(&self) -> Option<&str> {
+        match self {
+            BackendConfig::S3(s3) => Some(&s3.bucket),
+            BackendConfig::GCS(gcs) => Some(&gcs.bucket),
+            BackendConfig::Local(_) | BackendConfig::Hdfs(_) => None,
+        }
+    }
 }
 
 fn last<I: Sized, const COUNT: usize>(opts: [Option<I>; COUNT]) -> Option<I> {
@@ -327,6 +384,65 @@ impl StorageProvider {
             BackendConfig::S3(config) => Self::construct_s3(config, options).await,
             BackendConfig::GCS(config) => Self::construct_gcs(config).await,
             BackendConfig::Local(config) => Self::construct_local(config).await,
+            BackendConfig::Hdfs(config) => Self::construct_hdfs(config, options),
+        }
+    }
+
+    /// Constructs a provider for checkpoint and state files under `url`, applying the
+    /// server-side and client-side encryption configured in `checkpoint-encryption`
+    pub async fn for_checkpoints(url: &str) -> Result<Self, StorageError> {
+        let config = config();
+        let encryption_config = &config.checkpoint_encryption;
+
+        let mut options = HashMap::new();
+        if let Some(key_id) = &encryption_config.sse_kms_key_id {
+            if !matches!(BackendConfig::parse_url(url, false)?, BackendConfig::S3(_)) {
+                return Err(StorageError::Encryption(format!(
+                    "SSE-KMS is only supported for S3 checkpoint URLs, not {}",
+                    url
+                )));
+            }
+            options.insert(
+                "aws_server_side_encryption".to_string(),
+                "aws:kms".to_string(),
+            );
+            options.insert("aws_sse_kms_key_id".to_string(), key_id.clone());
+        }
+
+        let provider = Self::for_url_with_options(url, options).await?;
+
+        // data keys are cached by the encryption, so share it across providers
+        static ENCRYPTION: OnceCell<Option<Arc<EnvelopeEncryption>>> = OnceCell::const_new();
+        let encryption = ENCRYPTION
+            .get_or_try_init(|| async {
+                Ok::<_, StorageError>(
+                    EnvelopeEncryption::from_config(encryption_config)
+                        .await?
+                        .map(Arc::new),
+                )
+            })
+            .await?;
+
+        Ok(match encryption {
+            Some(encryption) => provider.with_encryption(encryption.clone()),
+            None => provider,
+        })
+    }
+
+    /// Encrypts objects written through this provider, and decrypts those read through it
+    pub fn with_encryption(mut self, encryption: Arc<EnvelopeEncryption>) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    async fn decrypt(&self, bytes: Bytes) -> Result<Bytes, StorageError> {
+        match &self.encryption {
+            Some(encryption) => Ok(encryption.decrypt(&bytes).await?.into()),
+            None => Ok(bytes),
         }
     }
 
@@ -344,6 +460,7 @@ impl StorageProvider {
             BackendConfig::S3(config) => Self::construct_s3(config, options).await,
             BackendConfig::GCS(config) => Self::construct_gcs(config).await,
             BackendConfig::Local(config) => Self::construct_local(config).await,
+            BackendConfig::Hdfs(config) => Self::construct_hdfs(config, options),
         }?;
 
         provider.get("").await
@@ -355,6 +472,7 @@ impl StorageProvider {
             BackendConfig::S3(s3) => s3.key.as_ref(),
             BackendConfig::GCS(gcs) => gcs.key.as_ref(),
             BackendConfig::Local(local) => local.key.as_ref(),
+            BackendConfig::Hdfs(hdfs) => hdfs.key.as_ref(),
         }
         .ok_or_else(|| StorageError::NoKeyInUrl)?;
         Ok(key.clone())
@@ -440,6 +558,8 @@ impl StorageProvider {
                 .into_iter()
                 .map(|(k, v)| (k.as_ref().to_string(), v))
                 .collect(),
+            encryption: None,
+            hdfs: None,
         })
     }
 
@@ -505,6 +625,8 @@ impl StorageProvider {
             object_store_base_url,
             canonical_url,
             storage_options: HashMap::new(),
+            encryption: None,
+            hdfs: None,
         })
     }
 
@@ -528,6 +650,52 @@ impl StorageProvider {
             canonical_url,
             object_store_base_url,
             storage_options: HashMap::new(),
+            encryption: None,
+            hdfs: None,
+        })
+    }
+
+    fn construct_hdfs(
+        config: HdfsConfig,
+        options: HashMap<String, String>,
+    ) -> Result<Self, StorageError> {
+        let mut user = std::env::var("HADOOP_USER_NAME").ok();
+        let mut delegation_token = std::env::var("WEBHDFS_DELEGATION_TOKEN").ok();
+        for (key, value) in &options {
+            match key.as_str() {
+                "user" => user = Some(value.clone()),
+                "delegation_token" => delegation_token = Some(value.clone()),
+                _ => {
+                    return Err(StorageError::CredentialsError(format!(
+                        "invalid HDFS config key: {}",
+                        key
+                    )))
+                }
+            }
+        }
+
+        let store = WebHdfs::new(config.endpoint.clone(), user, delegation_token)?;
+
+        let scheme = if config.endpoint.starts_with("https://") {
+            "swebhdfs"
+        } else {
+            "webhdfs"
+        };
+        let authority = config.endpoint.split_once("://").unwrap().1;
+        let object_store_base_url = format!("{}://{}", scheme, authority);
+        let canonical_url = match &config.key {
+            Some(key) => format!("{}/{}", object_store_base_url, key),
+            None => object_store_base_url.clone(),
+        };
+
+        Ok(Self {
+            config: BackendConfig::Hdfs(config),
+            object_store: Arc::new(store.clone()),
+            canonical_url,
+            object_store_base_url,
+            storage_options: options,
+            encryption: None,
+            hdfs: Some(store),
         })
     }
 
@@ -574,7 +742,7 @@ impl StorageProvider {
             .bytes()
             .await?;
 
-        Ok(bytes)
+        self.decrypt(bytes).await
     }
 
     pub async fn get_if_present<P: Into<String>>(
@@ -589,7 +757,7 @@ impl StorageProvider {
         {
             Ok(obj) => {
                 let bytes = obj.bytes().await?;
-                Ok(Some(bytes))
+                Ok(Some(self.decrypt(bytes).await?))
             }
             Err(err) => {
                 if let object_store::Error::NotFound { .. } = &err {
@@ -611,6 +779,16 @@ impl StorageProvider {
         }
     }
 
+    /// Returns the metadata of the object at `path`; its location is qualified with the
+    /// provider's key, so it can be read directly through [`Self::get_backing_store`]
+    pub async fn head<P: Into<String>>(&self, path: P) -> Result<ObjectMeta, StorageError> {
+        let path: String = path.into();
+        Ok(self
+            .object_store
+            .head(&self.qualify_path(&path.into()))
+            .await?)
+    }
+
     pub async fn get_as_stream<P: Into<String>>(
         &self,
         path: P,
@@ -630,7 +808,10 @@ impl StorageProvider {
         bytes: Vec<u8>,
     ) -> Result<String, StorageError> {
         let path = path.into().into();
-        let bytes: Bytes = bytes.into();
+        let bytes: Bytes = match &self.encryption {
+            Some(encryption) => encryption.encrypt(&bytes).await?.into(),
+            None => bytes.into(),
+        };
         storage_retry!(
             self.object_store
                 .put(&self.qualify_path(&path), bytes.clone())
@@ -650,16 +831,67 @@ impl StorageProvider {
         }
     }
 
+    /// Lists the paths of all objects under `prefix`, relative to the provider's key so that they
+    /// can be passed back to `get` and `delete_if_present`
+    pub async fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        let key_part_count = self
+            .config
+            .key()
+            .map(|key| Path::from(key.to_string()).parts().count())
+            .unwrap_or_default();
+
+        let prefix = self.qualify_path(&prefix.into());
+        Ok(self
+            .object_store
+            .list(Some(&prefix))
+            .map_ok(|meta| {
+                meta.location
+                    .parts()
+                    .skip(key_part_count)
+                    .collect::<Path>()
+                    .to_string()
+            })
+            .try_collect()
+            .await?)
+    }
+
     pub async fn delete_if_present<P: Into<String>>(&self, path: P) -> Result<(), StorageError> {
-        let path = path.into();
-        match self.object_store.delete(&path.into()).await {
+        let path: String = path.into();
+        match self
+            .object_store
+            .delete(&self.qualify_path(&path.into()))
+            .await
+        {
             Ok(_) => Ok(()),
             Err(object_store::Error::NotFound { .. }) => Ok(()),
             Err(e) => Err(e.into()),
         }
     }
 
+    /// Returns a writer for the object at `path`, which is qualified with the provider's key like
+    /// the paths used with `put`, `list_prefix` and `delete_if_present`. The object is uploaded in
+    /// parts as it's written unless the provider is encrypted, in which case it's buffered and
+    /// uploaded when the writer is shut down.
+    pub async fn writer(
+        &self,
+        path: &Path,
+    ) -> Result<Box<dyn AsyncWrite + Send + Unpin>, StorageError> {
+        let path = self.qualify_path(path);
+        match &self.encryption {
+            Some(encryption) => Ok(Box::new(EncryptingWriter::new(
+                self.object_store.clone(),
+                path,
+                encryption.clone(),
+            ))),
+            None => Ok(self.object_store.put_multipart(&path).await?.1),
+        }
+    }
+
     pub async fn start_multipart(&self, path: &Path) -> Result<MultipartId, StorageError> {
+        if self.hdfs.is_some() {
+            return Ok(hdfs::new_multipart_id());
+        }
+
         Ok(
             storage_retry!(self.object_store.initiate_multipart_upload(path).await)
                 .map_err(Into::<StorageError>::into)?
@@ -674,6 +906,14 @@ impl StorageProvider {
         part_number: usize,
         bytes: Bytes,
     ) -> Result<PartId, StorageError> {
+        if let Some(hdfs) = &self.hdfs {
+            return storage_retry!(
+                hdfs.put_part(path, multipart_id, part_number, bytes.clone())
+                    .await
+            )
+            .map_err(Into::<StorageError>::into);
+        }
+
         storage_retry!(
             self.object_store
                 .get_put_part(path, multipart_id)
@@ -690,6 +930,10 @@ impl StorageProvider {
         multipart_id: &MultipartId,
         parts: Vec<PartId>,
     ) -> Result<(), StorageError> {
+        if let Some(hdfs) = &self.hdfs {
+            return Ok(hdfs.complete_parts(path, parts).await?);
+        }
+
         storage_retry!(
             self.object_store
                 .get_put_part(path, multipart_id)
@@ -844,6 +1088,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_hdfs_configs() {
+        assert_eq!(
+            BackendConfig::parse_url("webhdfs://namenode:9870/user/arroyo/checkpoints", false)
+                .unwrap(),
+            BackendConfig::Hdfs(crate::HdfsConfig {
+                endpoint: "http://namenode:9870".to_string(),
+                key: Some("user/arroyo/checkpoints".to_string()),
+            })
+        );
+
+        assert_eq!(
+            BackendConfig::parse_url("swebhdfs://namenode.example.com:9871", false).unwrap(),
+            BackendConfig::Hdfs(crate::HdfsConfig {
+                endpoint: "https://namenode.example.com:9871".to_string(),
+                key: None,
+            })
+        );
+    }
+
     #[tokio::test]
     async fn test_local_fs() {
         let storage = StorageProvider::for_url("file:///tmp/arroyo-testing/storage-tests")
@@ -180,6 +180,9 @@ pub enum SignalMessage {
     Watermark(Watermark),
     Stop,
     EndOfData,
+    /// The W3C `traceparent` of the trace that the next data batch on this channel belongs to;
+    /// only sent when trace propagation is enabled
+    TraceContext(String),
 }
 
 impl ArrowMessage {
@@ -449,6 +452,15 @@ pub static BATCHES_SENT: &str = "arroyo_worker_batches_sent";
 pub static TX_QUEUE_SIZE: &str = "arroyo_worker_tx_queue_size";
 pub static TX_QUEUE_REM: &str = "arroyo_worker_tx_queue_rem";
 pub static DESERIALIZATION_ERRORS: &str = "arroyo_worker_deserialization_errors";
+pub static PROCESS_BATCH_TIME: &str = "arroyo_worker_process_batch_seconds";
+pub static SINK_LATENCY: &str = "arroyo_worker_sink_latency_seconds";
+pub static WATERMARK_LAG: &str = "arroyo_worker_watermark_lag_ms";
+pub static WATERMARK: &str = "arroyo_worker_watermark_ms";
+pub static PARTITION_MESSAGES_SENT: &str = "arroyo_worker_partition_messages_sent";
+pub static PARTITION_BYTES_SENT: &str = "arroyo_worker_partition_bytes_sent";
+pub static ROUTING_KEY_DISTRIBUTION: &str = "arroyo_worker_routing_key_distribution";
+pub static SOURCE_PARTITION_LAG: &str = "arroyo_worker_source_partition_lag";
+pub static SOURCE_PARTITION_LAG_MS: &str = "arroyo_worker_source_partition_lag_ms";
 
 #[derive(Debug, Copy, Clone, Encode, Decode, PartialEq, Eq)]
 pub struct CheckpointBarrier {
@@ -583,10 +595,114 @@ pub fn range_for_server(i: usize, n: usize) -> RangeInclusive<u64> {
     start..=end
 }
 
+/// Under rescale (local round-robin) exchanges, each of the `n` source subtasks sends to a
+/// contiguous subset of the `m` target subtasks: the targets are divided between the sources when
+/// there are more of them, and otherwise groups of neighboring sources share a single target
+pub fn rescale_targets(i: usize, n: usize, m: usize) -> Range<usize> {
+    if n <= m {
+        (i * m).div_ceil(n)..((i + 1) * m).div_ceil(n)
+    } else {
+        let target = i * m / n;
+        target..target + 1
+    }
+}
+
+/// Number of equal-sized buckets the key hash space is divided into when sampling the
+/// distribution of keys sent over range-partitioned edges
+pub const ROUTING_KEY_BUCKETS: usize = 64;
+
+pub fn routing_key_bucket(x: u64) -> usize {
+    (x / ((u64::MAX / ROUTING_KEY_BUCKETS as u64) + 1)) as usize
+}
+
+/// Whether `boundaries` can range-partition the hash space across `n` servers: there must be one
+/// fewer boundary than servers, and they must be non-zero and strictly increasing
+pub fn valid_range_boundaries(boundaries: &[u64], n: usize) -> bool {
+    boundaries.len() + 1 == n
+        && boundaries.first().map(|b| *b > 0).unwrap_or(true)
+        && boundaries.windows(2).all(|w| w[0] < w[1])
+}
+
+/// Under range partitioning, server `i` is assigned the hashes from `boundaries[i - 1]` up to but
+/// not including `boundaries[i]`
+pub fn server_for_hash_in_ranges(x: u64, boundaries: &[u64]) -> usize {
+    boundaries.partition_point(|b| *b <= x)
+}
+
+pub fn range_for_server_in_ranges(i: usize, boundaries: &[u64]) -> RangeInclusive<u64> {
+    let start = if i == 0 { 0 } else { boundaries[i - 1] };
+    let end = boundaries.get(i).map(|b| b - 1).unwrap_or(u64::MAX);
+    start..=end
+}
+
+/// Computes boundaries that divide a sampled distribution of hashes (the counts for each of the
+/// equal-sized buckets of the hash space) evenly across `n` servers, assuming hashes are uniformly
+/// distributed within each bucket
+pub fn range_boundaries_for_distribution(distribution: &[u64], n: usize) -> Option<Vec<u64>> {
+    let total: u64 = distribution.iter().sum();
+    if n <= 1 || total == 0 {
+        return None;
+    }
+
+    let bucket_size = (u64::MAX / distribution.len() as u64) + 1;
+    let mut boundaries: Vec<u64> = Vec::with_capacity(n - 1);
+    let mut bucket = 0;
+    let mut cumulative = 0;
+
+    for k in 1..n {
+        let target = (total as u128 * k as u128 / n as u128) as u64;
+        while bucket < distribution.len() && cumulative + distribution[bucket] <= target {
+            cumulative += distribution[bucket];
+            bucket += 1;
+        }
+
+        let boundary = if bucket == distribution.len() {
+            u64::MAX
+        } else {
+            let fraction = (target - cumulative) as f64 / distribution[bucket] as f64;
+            (bucket as u64 * bucket_size).saturating_add((fraction * bucket_size as f64) as u64)
+        };
+
+        let min = boundaries.last().map(|b| b.saturating_add(1)).unwrap_or(1);
+        boundaries.push(boundary.max(min));
+    }
+
+    valid_range_boundaries(&boundaries, n).then_some(boundaries)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_range_boundaries() {
+        // a uniform distribution is divided like the hash space
+        let uniform = vec![10; ROUTING_KEY_BUCKETS];
+        let boundaries = range_boundaries_for_distribution(&uniform, 4).unwrap();
+        for (i, b) in boundaries.iter().enumerate() {
+            assert_eq!(*b, *range_for_server(i + 1, 4).start());
+        }
+
+        // with all keys in the first bucket, it's divided between all of the servers
+        let mut skewed = vec![0; ROUTING_KEY_BUCKETS];
+        skewed[0] = 100;
+        let boundaries = range_boundaries_for_distribution(&skewed, 4).unwrap();
+        assert!(valid_range_boundaries(&boundaries, 4));
+        assert!(boundaries.iter().all(|b| routing_key_bucket(*b) == 0));
+
+        for i in 0..4 {
+            let range = range_for_server_in_ranges(i, &boundaries);
+            assert_eq!(server_for_hash_in_ranges(*range.start(), &boundaries), i);
+            assert_eq!(server_for_hash_in_ranges(*range.end(), &boundaries), i);
+        }
+        assert_eq!(*range_for_server_in_ranges(3, &boundaries).end(), u64::MAX);
+
+        assert_eq!(
+            range_boundaries_for_distribution(&vec![0; ROUTING_KEY_BUCKETS], 4),
+            None
+        );
+    }
+
     #[test]
     fn test_range_for_server() {
         let n = 6;
@@ -621,6 +737,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rescale_targets() {
+        let targets = |n, m| (0..n).map(|i| rescale_targets(i, n, m)).collect::<Vec<_>>();
+
+        assert_eq!(targets(2, 2), vec![0..1, 1..2]);
+        assert_eq!(targets(2, 4), vec![0..2, 2..4]);
+        assert_eq!(targets(2, 5), vec![0..3, 3..5]);
+        assert_eq!(targets(4, 2), vec![0..1, 0..1, 1..2, 1..2]);
+        assert_eq!(targets(3, 1), vec![0..1, 0..1, 0..1]);
+
+        // every target receives from at least one source
+        for (n, m) in [(3, 7), (7, 3), (5, 5), (1, 4)] {
+            for j in 0..m {
+                assert!(targets(n, m).iter().any(|r| r.contains(&j)));
+            }
+        }
+    }
+
     #[test]
     fn test_server_for_hash() {
         let n = 2;
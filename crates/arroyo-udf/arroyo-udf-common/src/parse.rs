@@ -3,8 +3,8 @@ use arrow::datatypes::{DataType, Field, TimeUnit};
 use regex::Regex;
 use std::sync::Arc;
 use std::time::Duration;
-use syn::PathArguments::AngleBracketed;
 use syn::__private::ToTokens;
+use syn::PathArguments::AngleBracketed;
 use syn::{FnArg, GenericArgument, ItemFn, LitInt, LitStr, ReturnType, Type};
 
 /// An Arrow DataType that also carries around its own nullability info
@@ -97,6 +97,31 @@ fn rust_primitive_to_arrow(typ: &Type) -> Option<DataType> {
     }
 }
 
+/// Maps the Arrow array types that vectorized UDFs take and return to their data types
+fn arrow_array_to_arrow(typ: &Type) -> Option<DataType> {
+    let Type::Path(pat) = typ else {
+        return None;
+    };
+
+    match pat.path.segments.last()?.ident.to_string().as_str() {
+        "BooleanArray" => Some(DataType::Boolean),
+        "Int8Array" => Some(DataType::Int8),
+        "Int16Array" => Some(DataType::Int16),
+        "Int32Array" => Some(DataType::Int32),
+        "Int64Array" => Some(DataType::Int64),
+        "UInt8Array" => Some(DataType::UInt8),
+        "UInt16Array" => Some(DataType::UInt16),
+        "UInt32Array" => Some(DataType::UInt32),
+        "UInt64Array" => Some(DataType::UInt64),
+        "Float32Array" => Some(DataType::Float32),
+        "Float64Array" => Some(DataType::Float64),
+        "StringArray" => Some(DataType::Utf8),
+        "BinaryArray" => Some(DataType::Binary),
+        "TimestampMicrosecondArray" => Some(DataType::Timestamp(TimeUnit::Microsecond, None)),
+        _ => None,
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct UdfDef {
     pub args: Vec<NullableType>,
@@ -126,11 +151,26 @@ impl Default for AsyncOptions {
 pub enum UdfType {
     Sync,
     Async(AsyncOptions),
+    /// A sync UDF whose first parameter is a `&mut` reference to state that is kept per value of
+    /// its first SQL argument, and checkpointed along with the pipeline
+    Stateful,
+    /// A sync UDF that is called once per batch with a reference to an Arrow array for each of
+    /// its arguments, and returns an array with a result for every row. Nulls are passed
+    /// through to the UDF rather than being handled for it.
+    Vectorized,
 }
 
 impl UdfType {
     pub fn is_async(&self) -> bool {
-        !matches!(self, UdfType::Sync)
+        matches!(self, UdfType::Async(_))
+    }
+
+    pub fn is_stateful(&self) -> bool {
+        matches!(self, UdfType::Stateful)
+    }
+
+    pub fn is_vectorized(&self) -> bool {
+        matches!(self, UdfType::Vectorized)
     }
 }
 
@@ -188,6 +228,8 @@ impl ParsedUdf {
         let name = function.sig.ident.to_string();
         let mut args = vec![];
         let mut vec_arguments = 0;
+        let mut stateful = false;
+        let mut array_arguments = 0;
         for (i, arg) in function.sig.inputs.iter().enumerate() {
             match arg {
                 FnArg::Receiver(_) => {
@@ -197,6 +239,27 @@ impl ParsedUdf {
                     )
                 }
                 FnArg::Typed(t) => {
+                    if let Type::Reference(r) = &*t.ty {
+                        if i == 0 && r.mutability.is_some() {
+                            stateful = true;
+                            continue;
+                        }
+                        if r.mutability.is_none() {
+                            if let Some(data_type) = arrow_array_to_arrow(&r.elem) {
+                                array_arguments += 1;
+                                args.push(NullableType::null(data_type));
+                                continue;
+                            }
+                        }
+                        bail!(
+                            "Function {} arg {} is a reference; only the first argument may be \
+                            a mutable reference, to the state of a stateful UDF (&mut State), \
+                            and other references must be to Arrow arrays (like &Int64Array)",
+                            name,
+                            i
+                        );
+                    }
+
                     if let Some(vec_type) = Self::vec_inner_type(&t.ty) {
                         vec_arguments += 1;
                         let vec_type = rust_to_arrow(&vec_type).ok_or_else(|| {
@@ -223,17 +286,72 @@ impl ParsedUdf {
             }
         }
 
-        let ret = match &function.sig.output {
+        let (ret, array_return) = match &function.sig.output {
             ReturnType::Default => bail!("Function {} return type must be specified", name),
-            ReturnType::Type(_, t) => rust_to_arrow(t).ok_or_else(|| {
-                anyhow!(
-                    "Could not convert function {} return type into a SQL data type",
-                    name
-                )
-            })?,
+            ReturnType::Type(_, t) => match arrow_array_to_arrow(t) {
+                Some(data_type) => (NullableType::null(data_type), true),
+                None => (
+                    rust_to_arrow(t).ok_or_else(|| {
+                        anyhow!(
+                            "Could not convert function {} return type into a SQL data type",
+                            name
+                        )
+                    })?,
+                    false,
+                ),
+            },
         };
 
-        let udf_type = if function.sig.asyncness.is_some() {
+        let vectorized = array_return || array_arguments > 0;
+        if vectorized {
+            if stateful || array_arguments != args.len() || !array_return {
+                bail!(
+                    "Function {} is vectorized, so all of its arguments must be references to \
+                    Arrow arrays (like &Int64Array) and it must return an Arrow array",
+                    name
+                );
+            }
+            if function.sig.asyncness.is_some() {
+                bail!(
+                    "Function {} is vectorized, and vectorized UDFs cannot be async",
+                    name
+                );
+            }
+            if args.is_empty() {
+                bail!(
+                    "Function {} is vectorized, so must take at least one array argument",
+                    name
+                );
+            }
+        }
+
+        if stateful {
+            if function.sig.asyncness.is_some() {
+                bail!(
+                    "Function {} is stateful, and stateful UDFs cannot be async",
+                    name
+                );
+            }
+            if vec_arguments > 0 {
+                bail!(
+                    "Function {} is stateful, and UDAFs cannot be stateful",
+                    name
+                );
+            }
+            if args.is_empty() {
+                bail!(
+                    "Function {} is stateful, so must take at least one argument after its state, \
+                    which the state is kept per value of",
+                    name
+                );
+            }
+        }
+
+        let udf_type = if stateful {
+            UdfType::Stateful
+        } else if vectorized {
+            UdfType::Vectorized
+        } else if function.sig.asyncness.is_some() {
             let mut t = AsyncOptions::default();
 
             if let Some(attr) = function
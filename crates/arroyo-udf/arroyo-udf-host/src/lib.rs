@@ -1,22 +1,28 @@
+mod sandbox;
 #[cfg(test)]
 mod test;
 
 use anyhow::{anyhow, bail};
-use arrow::array::{make_array, Array, ArrayData, ArrayRef, UInt64Array};
+use arrow::array::{
+    make_array, new_empty_array, new_null_array, Array, ArrayData, ArrayRef, BinaryArray,
+    StructArray, UInt64Array,
+};
+use arrow::compute::concat;
 use arrow::datatypes::DataType;
 use arrow::ffi::from_ffi;
 use arroyo_udf_common::async_udf::{DrainResult, SendableFfiAsyncUdfHandle};
 use arroyo_udf_common::{FfiArraySchema, FfiArrays, RunResult};
 use async_ffi::FfiFuture;
 use datafusion::common::ScalarValue;
-use datafusion::error::Result as DFResult;
+use datafusion::error::{DataFusionError, Result as DFResult};
 use datafusion::logical_expr::{ColumnarValue, ScalarUDFImpl, Signature};
 use dlopen2::wrapper::{Container, WrapperApi};
 use quote::{format_ident, ToTokens};
 use std::any::Any;
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use syn::{parse_file, Item};
 
@@ -24,6 +30,7 @@ pub use arroyo_udf_common::parse;
 use arroyo_udf_common::parse::ParsedUdf;
 use regex::Regex;
 use toml::Table;
+use tracing::warn;
 
 pub fn parse_dependencies(definition: &str) -> anyhow::Result<Table> {
     // get content of dependencies comment using regex
@@ -96,11 +103,31 @@ impl ParsedUdfFile {
 #[derive(WrapperApi)]
 pub struct UdfDylibInterface {
     __run: unsafe extern "C-unwind" fn(args: FfiArrays) -> RunResult,
+    // not exported by UDFs built before memory limits were supported
+    __set_memory_limit: Option<unsafe extern "C-unwind" fn(bytes: u64)>,
 }
 
 impl UdfDylibInterface {
     pub fn new(run: unsafe extern "C-unwind" fn(FfiArrays) -> RunResult) -> Self {
-        Self { __run: run }
+        Self {
+            __run: run,
+            __set_memory_limit: None,
+        }
+    }
+}
+
+#[derive(WrapperApi)]
+pub struct StatefulUdfDylibInterface {
+    __run_stateful: unsafe extern "C-unwind" fn(args: FfiArrays) -> RunResult,
+    __set_memory_limit: Option<unsafe extern "C-unwind" fn(bytes: u64)>,
+}
+
+impl StatefulUdfDylibInterface {
+    pub fn new(run: unsafe extern "C-unwind" fn(FfiArrays) -> RunResult) -> Self {
+        Self {
+            __run_stateful: run,
+            __set_memory_limit: None,
+        }
     }
 }
 
@@ -162,6 +189,17 @@ impl<T: WrapperApi> ContainerOrLocal<T> {
 pub enum UdfInterface {
     Sync(Arc<ContainerOrLocal<UdfDylibInterface>>),
     Async(Arc<ContainerOrLocal<AsyncUdfDylibInterface>>),
+    Stateful(Arc<ContainerOrLocal<StatefulUdfDylibInterface>>),
+}
+
+impl UdfInterface {
+    fn kind(&self) -> &'static str {
+        match self {
+            UdfInterface::Sync(_) => "sync",
+            UdfInterface::Async(_) => "async",
+            UdfInterface::Stateful(_) => "stateful",
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -186,14 +224,106 @@ impl UdfDylib {
             udf,
         }
     }
+
+    /// Limits the memory that the UDF's library may hold, for sync and stateful UDFs whose
+    /// library supports it. A call that takes the library over its limit fails, which is handled
+    /// according to the [`UdfLimits`] of the caller.
+    pub fn set_memory_limit(&self, bytes: u64) {
+        let set_limit = match &self.udf {
+            UdfInterface::Sync(udf) => udf.inner().__set_memory_limit,
+            UdfInterface::Stateful(udf) => udf.inner().__set_memory_limit,
+            UdfInterface::Async(_) => None,
+        };
+
+        if let Some(set_limit) = set_limit {
+            unsafe { set_limit(bytes) };
+        }
+    }
+}
+
+/// Limits on the execution of sync and stateful UDFs
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UdfLimits {
+    /// How long a single invocation may run before it's abandoned. Once too many abandoned
+    /// invocations are still running, further invocations fail without being run.
+    pub timeout: Option<Duration>,
+    /// Whether rows whose invocation fails (by panicking, timing out, or exceeding the memory
+    /// limit of the UDF) get null results, rather than failing the query
+    pub null_on_failure: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum UdfFailure {
+    Panicked,
+    TimedOut,
+    // too many invocations have timed out and are still running
+    Exhausted,
+}
+
+impl UdfFailure {
+    fn describe(&self, name: &str, limits: &UdfLimits) -> String {
+        match self {
+            UdfFailure::Panicked => format!("panic in UDF {}", name),
+            UdfFailure::TimedOut => format!(
+                "UDF {} timed out after {:?}",
+                name,
+                limits.timeout.unwrap_or_default()
+            ),
+            UdfFailure::Exhausted => format!(
+                "UDF {} was not run, as {} timed out invocations of UDFs are still running",
+                name,
+                sandbox::MAX_ABANDONED
+            ),
+        }
+    }
+
+    /// Whether the failure can't be covered up with null results, even if the UDF allows it
+    fn is_fatal(&self) -> bool {
+        *self == UdfFailure::Exhausted
+    }
+}
+
+/// Calls into a UDF library with the given args, within the time limit of `limits`
+fn call_udf(
+    limits: &UdfLimits,
+    args: Vec<ArrayData>,
+    run: impl FnOnce(FfiArrays) -> RunResult + Send + 'static,
+) -> Result<ArrayData, UdfFailure> {
+    let f = move || match run(FfiArrays::from_vec(args)) {
+        RunResult::Ok(FfiArraySchema(array, schema)) => {
+            Some(unsafe { from_ffi(array, &schema) }.expect("UDF returned an invalid array"))
+        }
+        RunResult::Err => None,
+    };
+
+    let result = match limits.timeout {
+        Some(timeout) => sandbox::run_with_timeout(timeout, f).map_err(|e| match e {
+            sandbox::Interrupted::TimedOut => UdfFailure::TimedOut,
+            sandbox::Interrupted::Crashed => UdfFailure::Panicked,
+            sandbox::Interrupted::TooManyAbandoned => UdfFailure::Exhausted,
+        })?,
+        None => f(),
+    };
+
+    result.ok_or(UdfFailure::Panicked)
+}
+
+fn concat_rows(rows: &[ArrayRef], data_type: &DataType) -> ArrayRef {
+    if rows.is_empty() {
+        return new_empty_array(data_type);
+    }
+    concat(&rows.iter().map(|a| a.as_ref()).collect::<Vec<_>>()).unwrap()
 }
 
+/// A sync UDF backed by a dylib. Clones share the underlying implementation, which may be
+/// replaced at runtime with [`SyncUdfDylib::swap`] to hot-reload the UDF.
 #[derive(Clone)]
 pub struct SyncUdfDylib {
     name: Arc<String>,
     signature: Arc<Signature>,
     return_type: Arc<DataType>,
-    udf: Arc<ContainerOrLocal<UdfDylibInterface>>,
+    udf: Arc<RwLock<Arc<ContainerOrLocal<UdfDylibInterface>>>>,
+    limits: UdfLimits,
 }
 
 impl SyncUdfDylib {
@@ -207,13 +337,115 @@ impl SyncUdfDylib {
             name: Arc::new(name),
             signature: Arc::new(signature),
             return_type: Arc::new(return_type),
-            udf: Arc::new(ContainerOrLocal::Local(udf)),
+            udf: Arc::new(RwLock::new(Arc::new(ContainerOrLocal::Local(udf)))),
+            limits: UdfLimits::default(),
         }
     }
 
+    pub fn with_limits(mut self, limits: UdfLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    fn implementation(&self) -> Arc<ContainerOrLocal<UdfDylibInterface>> {
+        self.udf.read().unwrap().clone()
+    }
+
+    fn run(&self, args: &[ArrayRef]) -> Result<ArrayRef, UdfFailure> {
+        let udf = self.implementation();
+        let data = args.iter().map(|a| a.to_data()).collect();
+        call_udf(&self.limits, data, move |args| unsafe {
+            (udf.inner().__run)(args)
+        })
+        .map(make_array)
+    }
+
+    /// Invokes the UDF on each row separately after it failed on a batch, so that only the rows
+    /// it fails on get null results. If it times out, the remaining rows are given nulls without
+    /// retrying them, to bound the time spent on the batch.
+    fn run_by_row(&self, args: &[ArrayRef], num_rows: usize, failure: UdfFailure) -> ArrayRef {
+        let mut timed_out = failure == UdfFailure::TimedOut;
+        let mut failed = 0;
+
+        let rows: Vec<_> = (0..num_rows)
+            .map(|i| {
+                if !timed_out {
+                    let row: Vec<_> = args.iter().map(|a| a.slice(i, 1)).collect();
+                    match self.run(&row) {
+                        Ok(result) => return result,
+                        Err(e) => timed_out = e != UdfFailure::Panicked,
+                    }
+                }
+                failed += 1;
+                new_null_array(&self.return_type, 1)
+            })
+            .collect();
+
+        warn!(
+            "{}; returning null for the {} of {} rows it failed on",
+            failure.describe(&self.name, &self.limits),
+            failed,
+            num_rows
+        );
+
+        concat_rows(&rows, &self.return_type)
+    }
+
+    /// Checks that `other` can replace this UDF's implementation: it must be a sync UDF with the
+    /// same name, signature, and return type
+    pub fn check_compatible(&self, other: &UdfDylib) -> anyhow::Result<()> {
+        if !matches!(other.udf, UdfInterface::Sync(_)) {
+            bail!(
+                "UDF {} cannot be replaced by a {} implementation",
+                self.name,
+                other.udf.kind()
+            );
+        }
+
+        if other.name != self.name {
+            bail!(
+                "UDF {} cannot be replaced by a UDF with a different name ({})",
+                self.name,
+                other.name
+            );
+        }
+
+        if other.signature.type_signature != self.signature.type_signature {
+            bail!(
+                "UDF {} cannot be replaced by a UDF with different argument types",
+                self.name
+            );
+        }
+
+        if other.return_type != self.return_type {
+            bail!(
+                "UDF {} cannot be replaced by a UDF with a different return type ({} instead of {})",
+                self.name,
+                other.return_type,
+                self.return_type
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the implementation of this UDF (and all of its clones) with that of `other`,
+    /// which must be compatible. Invocations that are already in progress complete with the
+    /// previous implementation.
+    pub fn swap(&self, other: &UdfDylib) -> anyhow::Result<()> {
+        self.check_compatible(other)?;
+
+        let UdfInterface::Sync(udf) = &other.udf else {
+            unreachable!("checked that the UDF is sync");
+        };
+
+        *self.udf.write().unwrap() = udf.clone();
+        Ok(())
+    }
 }
 
 impl Debug for SyncUdfDylib {
@@ -227,14 +459,15 @@ impl TryFrom<&UdfDylib> for SyncUdfDylib {
 
     fn try_from(value: &UdfDylib) -> std::result::Result<Self, Self::Error> {
         let UdfInterface::Sync(udf) = &value.udf else {
-            bail!("UDF is async but expected sync")
+            bail!("UDF is {} but expected sync", value.udf.kind())
         };
 
         Ok(Self {
             name: value.name.clone(),
             signature: value.signature.clone(),
             return_type: value.return_type.clone(),
-            udf: udf.clone(),
+            udf: Arc::new(RwLock::new(udf.clone())),
+            limits: UdfLimits::default(),
         })
     }
 }
@@ -251,7 +484,7 @@ impl TryFrom<&UdfDylib> for AsyncUdfDylib {
 
     fn try_from(value: &UdfDylib) -> std::result::Result<Self, Self::Error> {
         let UdfInterface::Async(udf) = &value.udf else {
-            bail!("UDF is sync but expected async")
+            bail!("UDF is {} but expected async", value.udf.kind())
         };
 
         Ok(Self {
@@ -343,19 +576,18 @@ impl Drop for AsyncUdfDylib {
 
 impl SyncUdfDylib {
     pub fn invoke_udaf(&self, args: &[ArrayRef]) -> DFResult<ScalarValue> {
-        let data: Vec<_> = args.iter().map(|a| a.to_data()).collect();
-
-        let args = FfiArrays::from_vec(data);
-
-        match unsafe { self.udf.inner().__run(args) } {
-            RunResult::Ok(FfiArraySchema(array, schema)) => {
-                let result_array = make_array(unsafe { from_ffi(array, &schema).unwrap() });
+        match self.run(args) {
+            Ok(result_array) => {
                 assert_eq!(result_array.len(), 1);
                 Ok(ScalarValue::try_from_array(result_array.as_ref(), 0).unwrap())
             }
-            RunResult::Err => {
-                panic!("panic in UDF {}", self.name);
+            Err(e) if self.limits.null_on_failure && !e.is_fatal() => {
+                warn!("{}; returning null", e.describe(&self.name, &self.limits));
+                ScalarValue::try_from(&*self.return_type)
             }
+            Err(e) => Err(DataFusionError::Execution(
+                e.describe(&self.name, &self.limits),
+            )),
         }
     }
 }
@@ -392,22 +624,166 @@ impl ScalarUDFImpl for SyncUdfDylib {
 
         let args = args
             .iter()
-            .map(|arg| arg.clone().into_array(num_rows).unwrap().to_data())
-            .collect::<Vec<_>>();
+            .map(|arg| arg.clone().into_array(num_rows))
+            .collect::<DFResult<Vec<_>>>()?;
+
+        match self.run(&args) {
+            Ok(result) => Ok(ColumnarValue::Array(result)),
+            Err(e) if self.limits.null_on_failure && !e.is_fatal() => {
+                Ok(ColumnarValue::Array(self.run_by_row(&args, num_rows, e)))
+            }
+            Err(e) => Err(DataFusionError::Execution(
+                e.describe(&self.name, &self.limits),
+            )),
+        }
+    }
+}
 
-        let args = FfiArrays::from_vec(args);
+/// A UDF that keeps state per value of its first argument. The state itself is owned by the
+/// caller, which passes it in with each invocation and stores the updated state that's returned.
+pub struct StatefulUdfDylib {
+    name: Arc<String>,
+    return_type: Arc<DataType>,
+    udf: Arc<ContainerOrLocal<StatefulUdfDylibInterface>>,
+    limits: UdfLimits,
+}
 
-        let result = unsafe { (self.udf.inner().__run)(args) };
+impl TryFrom<&UdfDylib> for StatefulUdfDylib {
+    type Error = anyhow::Error;
 
-        match result {
-            RunResult::Ok(FfiArraySchema(array, schema)) => {
-                let result_array = unsafe { from_ffi(array, &schema).unwrap() };
-                Ok(ColumnarValue::Array(make_array(result_array)))
+    fn try_from(value: &UdfDylib) -> std::result::Result<Self, Self::Error> {
+        let UdfInterface::Stateful(udf) = &value.udf else {
+            bail!("UDF is {} but expected stateful", value.udf.kind())
+        };
+
+        Ok(Self {
+            name: value.name.clone(),
+            return_type: value.return_type.clone(),
+            udf: udf.clone(),
+            limits: UdfLimits::default(),
+        })
+    }
+}
+
+impl StatefulUdfDylib {
+    pub fn with_limits(mut self, limits: UdfLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn return_type(&self) -> &DataType {
+        &self.return_type
+    }
+
+    /// Invokes the UDF on a batch. `keys` identifies the state used by each row, and `states`
+    /// must hold the stored state for each key (if it has one) on one of its rows, and be null
+    /// elsewhere. Returns the results along with the updated states, which are set on the last
+    /// row of each key that was updated and null elsewhere.
+    pub fn invoke(
+        &self,
+        keys: &BinaryArray,
+        states: &BinaryArray,
+        args: &[ArrayRef],
+    ) -> anyhow::Result<(ArrayRef, BinaryArray)> {
+        match self.run(keys, states, args) {
+            Ok(result) => Ok(result),
+            Err(e) if self.limits.null_on_failure && !e.is_fatal() => {
+                Ok(self.run_by_row(keys, states, args, e))
             }
-            RunResult::Err => {
-                panic!("panic in UDF {}", self.name);
+            Err(e) => bail!(e.describe(&self.name, &self.limits)),
+        }
+    }
+
+    fn run(
+        &self,
+        keys: &BinaryArray,
+        states: &BinaryArray,
+        args: &[ArrayRef],
+    ) -> Result<(ArrayRef, BinaryArray), UdfFailure> {
+        let udf = self.udf.clone();
+        let data: Vec<_> = [keys.to_data(), states.to_data()]
+            .into_iter()
+            .chain(args.iter().map(|a| a.to_data()))
+            .collect();
+
+        let result = StructArray::from(call_udf(&self.limits, data, move |args| unsafe {
+            (udf.inner().__run_stateful)(args)
+        })?);
+
+        let states = result
+            .column_by_name("state")
+            .and_then(|s| s.as_any().downcast_ref::<BinaryArray>())
+            .expect("stateful UDF returned no state")
+            .clone();
+        let results = result
+            .column_by_name("result")
+            .expect("stateful UDF returned no results")
+            .clone();
+        Ok((results, states))
+    }
+
+    /// Like [`SyncUdfDylib::run_by_row`], but threads the state of each key through the rows
+    /// that succeed
+    fn run_by_row(
+        &self,
+        keys: &BinaryArray,
+        states: &BinaryArray,
+        args: &[ArrayRef],
+        failure: UdfFailure,
+    ) -> (ArrayRef, BinaryArray) {
+        let mut current: HashMap<&[u8], Vec<u8>> = (0..keys.len())
+            .filter(|i| !states.is_null(*i))
+            .map(|i| (keys.value(i), states.value(i).to_vec()))
+            .collect();
+        let mut last_updates: HashMap<&[u8], usize> = HashMap::new();
+
+        let mut timed_out = failure == UdfFailure::TimedOut;
+        let mut failed = 0;
+
+        let mut rows = vec![];
+        for i in 0..keys.len() {
+            let key = keys.value(i);
+            if !timed_out {
+                let row_states: BinaryArray =
+                    std::iter::once(current.get(key).map(|s| s.as_slice())).collect();
+                let row_args: Vec<_> = args.iter().map(|a| a.slice(i, 1)).collect();
+
+                match self.run(&keys.slice(i, 1), &row_states, &row_args) {
+                    Ok((result, updated)) => {
+                        if !updated.is_null(0) {
+                            current.insert(key, updated.value(0).to_vec());
+                            last_updates.insert(key, i);
+                        }
+                        rows.push(result);
+                        continue;
+                    }
+                    Err(e) => timed_out = e != UdfFailure::Panicked,
+                }
             }
+            failed += 1;
+            rows.push(new_null_array(&self.return_type, 1));
+        }
+
+        warn!(
+            "{}; returning null for the {} of {} rows it failed on",
+            failure.describe(&self.name, &self.limits),
+            failed,
+            keys.len()
+        );
+
+        let mut updated: Vec<Option<&[u8]>> = vec![None; keys.len()];
+        for (key, i) in last_updates {
+            updated[i] = current.get(key).map(|s| s.as_slice());
         }
+
+        (
+            concat_rows(&rows, &self.return_type),
+            updated.into_iter().collect(),
+        )
     }
 }
 
@@ -416,6 +792,7 @@ pub struct LocalUdf {
     pub config: UdfDylib,
     pub is_aggregate: bool,
     pub is_async: bool,
+    pub is_stateful: bool,
 }
 
 #[cfg(test)]
@@ -529,4 +906,32 @@ pub fn my_udf() -> i64 {
 
         assert_eq!(parsed.udf.name.as_str(), "hello");
     }
+
+    #[test]
+    fn test_vectorized() {
+        let s = r#"
+            use arroyo_udf_plugin::udf;
+            use arroyo_udf_plugin::arrow::array::{Int64Array, StringArray};
+
+            #[udf]
+            fn lengths(s: &StringArray, offset: &Int64Array) -> Int64Array {
+                s.iter().zip(offset).map(|(s, o)| Some(s?.len() as i64 + o?)).collect()
+            }
+        "#;
+
+        let parsed = ParsedUdfFile::try_parse(s).unwrap();
+        assert_eq!(parsed.udf.udf_type, UdfType::Vectorized);
+        assert!(parsed.udf.args.iter().all(|a| a.nullable));
+        assert_eq!(parsed.udf.args[0].data_type, DataType::Utf8);
+        assert_eq!(parsed.udf.ret_type.data_type, DataType::Int64);
+
+        // array and scalar arguments can't be mixed
+        let s = r#"
+            #[udf]
+            fn lengths(s: &StringArray, offset: i64) -> Int64Array {
+                unimplemented!()
+            }
+        "#;
+        assert!(ParsedUdfFile::try_parse(s).is_err());
+    }
 }
@@ -0,0 +1,148 @@
+//! Runs UDF invocations on a blocking thread, so that callers can stop waiting for one that hangs.
+//!
+//! A running UDF can't be interrupted, so when an invocation times out its thread is abandoned
+//! until the invocation finally returns. Each abandoned thread is lost to the process for as long
+//! as the UDF hangs, so only a limited number may exist at once; past that, invocations fail
+//! without being run.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// The most invocations that may have timed out and still be running
+pub(crate) const MAX_ABANDONED: usize = 8;
+
+static ABANDONED: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Interrupted {
+    TimedOut,
+    Crashed,
+    TooManyAbandoned,
+}
+
+/// Set when either the invocation finishes or its caller gives up on it, whichever is first.
+/// Dropped by the invocation even if the UDF panics, so that an abandoned invocation always
+/// releases its slot once it's done.
+struct Finished(Arc<AtomicBool>);
+
+impl Drop for Finished {
+    fn drop(&mut self) {
+        if self.0.swap(true, Ordering::SeqCst) {
+            ABANDONED.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Runs `f` on a blocking thread, waiting at most `timeout` for it to finish
+pub(crate) fn run_with_timeout<T: Send + 'static>(
+    timeout: Duration,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, Interrupted> {
+    if ABANDONED.load(Ordering::SeqCst) >= MAX_ABANDONED {
+        return Err(Interrupted::TooManyAbandoned);
+    }
+
+    let (tx, rx) = sync_channel(1);
+    let finished = Arc::new(AtomicBool::new(false));
+    let guard = Finished(finished.clone());
+    let job = move || {
+        let _guard = guard;
+        let _ = tx.send(f());
+    };
+
+    let runtime = Handle::try_current().ok();
+    match &runtime {
+        Some(handle) => {
+            handle.spawn_blocking(job);
+        }
+        None => {
+            thread::Builder::new()
+                .name("udf-invocation".to_string())
+                .spawn(job)
+                .expect("failed to spawn UDF invocation thread");
+        }
+    }
+
+    // let the runtime move this worker's other tasks elsewhere while we wait
+    let result = match runtime.map(|h| h.runtime_flavor()) {
+        Some(RuntimeFlavor::MultiThread) => {
+            tokio::task::block_in_place(|| rx.recv_timeout(timeout))
+        }
+        _ => rx.recv_timeout(timeout),
+    };
+
+    match result {
+        Ok(result) => Ok(result),
+        Err(RecvTimeoutError::Disconnected) => Err(Interrupted::Crashed),
+        Err(RecvTimeoutError::Timeout) => {
+            ABANDONED.fetch_add(1, Ordering::SeqCst);
+            if finished.swap(true, Ordering::SeqCst) {
+                // it finished just as we timed out
+                ABANDONED.fetch_sub(1, Ordering::SeqCst);
+                return rx.recv().map_err(|_| Interrupted::Crashed);
+            }
+            Err(Interrupted::TimedOut)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn wait_for_abandoned() {
+        while ABANDONED.load(Ordering::SeqCst) > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    // a single test, as the number of abandoned invocations is shared by the whole process
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_run_with_timeout() {
+        assert_eq!(run_with_timeout(Duration::from_secs(1), || 5), Ok(5));
+
+        assert_eq!(
+            run_with_timeout(Duration::from_millis(10), || thread::sleep(
+                Duration::from_millis(200)
+            )),
+            Err(Interrupted::TimedOut)
+        );
+
+        // a new thread is used after a timeout
+        assert_eq!(run_with_timeout(Duration::from_secs(1), || 6), Ok(6));
+
+        assert_eq!(
+            run_with_timeout(Duration::from_secs(1), || panic!("udf panicked")),
+            Err(Interrupted::Crashed)
+        );
+
+        wait_for_abandoned().await;
+
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let release_rx = Arc::new(std::sync::Mutex::new(release_rx));
+
+        for _ in 0..MAX_ABANDONED {
+            let release_rx = release_rx.clone();
+            assert_eq!(
+                run_with_timeout(Duration::from_millis(10), move || {
+                    let _ = release_rx.lock().unwrap().recv();
+                }),
+                Err(Interrupted::TimedOut)
+            );
+        }
+
+        assert_eq!(
+            run_with_timeout(Duration::from_secs(1), || 5),
+            Err(Interrupted::TooManyAbandoned)
+        );
+
+        // once the hung invocations return, their threads are released
+        drop(release_tx);
+        wait_for_abandoned().await;
+        assert_eq!(run_with_timeout(Duration::from_secs(1), || 5), Ok(5));
+    }
+}
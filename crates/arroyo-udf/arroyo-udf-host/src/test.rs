@@ -1,5 +1,7 @@
-use crate::{AsyncUdfDylib, AsyncUdfDylibInterface, SyncUdfDylib};
-use arrow::array::{Array, ArrayRef, Int32Array, StringArray, UInt64Array};
+use crate::{AsyncUdfDylib, AsyncUdfDylibInterface, StatefulUdfDylib, SyncUdfDylib, UdfLimits};
+use arrow::array::{
+    Array, ArrayRef, BinaryArray, Int32Array, Int64Array, StringArray, UInt64Array,
+};
 use arrow::datatypes::DataType;
 use datafusion::logical_expr::{ColumnarValue, ScalarUDFImpl};
 use std::sync::Arc;
@@ -40,6 +42,42 @@ fn test_udf() {
     assert_eq!(result.value(2), "20-c");
 }
 
+mod test_udf_2 {
+    use crate as arroyo_udf_host;
+    use arroyo_udf_macros::local_udf;
+
+    #[local_udf]
+    fn my_udf(x: i32, y: String) -> Option<String> {
+        Some(format!("{y}:{x}"))
+    }
+}
+
+#[test]
+fn test_swap_udf() {
+    let sync_udf: SyncUdfDylib = (&test_udf_1::__local().config).try_into().unwrap();
+    let clone = sync_udf.clone();
+
+    // a UDF with a different signature can't be swapped in
+    assert!(sync_udf.swap(&test_udaf::__local().config).is_err());
+
+    sync_udf.swap(&test_udf_2::__local().config).unwrap();
+
+    // the new implementation is visible through clones
+    let result = clone
+        .invoke(&[
+            ColumnarValue::Array(Arc::new(Int32Array::from(vec![1]))),
+            ColumnarValue::Array(Arc::new(StringArray::from(vec!["a"]))),
+        ])
+        .unwrap();
+
+    let ColumnarValue::Array(a) = result else {
+        panic!("not an array");
+    };
+
+    let result = a.as_any().downcast_ref::<StringArray>().unwrap();
+    assert_eq!(result.value(0), "a:1");
+}
+
 mod test_udaf {
     use crate as arroyo_udf_host;
     use arroyo_udf_macros::local_udf;
@@ -61,6 +99,120 @@ fn test_udaf() {
     assert_eq!(result, ScalarValue::UInt64(Some(3)));
 }
 
+mod test_panicking_udf {
+    use crate as arroyo_udf_host;
+    use arroyo_udf_macros::local_udf;
+
+    #[local_udf]
+    fn checked_div(x: u64, y: u64) -> u64 {
+        x / y
+    }
+}
+
+#[test]
+fn test_udf_failure_policy() {
+    let args = [
+        ColumnarValue::Array(Arc::new(UInt64Array::from(vec![10, 5, 8]))),
+        ColumnarValue::Array(Arc::new(UInt64Array::from(vec![2, 0, 4]))),
+    ];
+
+    let udf = test_panicking_udf::__local().config;
+    let sync_udf: SyncUdfDylib = (&udf).try_into().unwrap();
+    assert!(sync_udf.invoke(&args).is_err());
+
+    let sync_udf = sync_udf.with_limits(UdfLimits {
+        timeout: None,
+        null_on_failure: true,
+    });
+
+    let ColumnarValue::Array(a) = sync_udf.invoke(&args).unwrap() else {
+        panic!("not an array");
+    };
+
+    let result = a.as_any().downcast_ref::<UInt64Array>().unwrap();
+    assert_eq!(result.len(), 3);
+    assert_eq!(result.value(0), 5);
+    assert!(result.is_null(1));
+    assert_eq!(result.value(2), 2);
+}
+
+mod test_vectorized_udf {
+    use crate as arroyo_udf_host;
+    use arroyo_udf_macros::local_udf;
+    use arroyo_udf_plugin::arrow::array::Int64Array;
+    use arroyo_udf_plugin::arrow::compute::binary;
+
+    #[local_udf]
+    fn scale(x: &Int64Array, factor: &Int64Array) -> Int64Array {
+        binary(x, factor, |x, factor| x * factor).unwrap()
+    }
+}
+
+#[test]
+fn test_vectorized_udf() {
+    let local = test_vectorized_udf::__local();
+    assert!(!local.is_aggregate);
+
+    let sync_udf: SyncUdfDylib = (&local.config).try_into().unwrap();
+    let result = sync_udf
+        .invoke(&[
+            ColumnarValue::Array(Arc::new(Int64Array::from(vec![Some(1), None, Some(3)]))),
+            ColumnarValue::Scalar(ScalarValue::Int64(Some(10))),
+        ])
+        .unwrap();
+
+    let ColumnarValue::Array(a) = result else {
+        panic!("not an array");
+    };
+
+    // nulls are handled by the UDF itself
+    let result = a.as_any().downcast_ref::<Int64Array>().unwrap();
+    assert_eq!(result, &Int64Array::from(vec![Some(10), None, Some(30)]));
+}
+
+mod test_stateful_udf {
+    use crate as arroyo_udf_host;
+    use arroyo_udf_macros::local_udf;
+
+    #[local_udf]
+    fn running_sum(count: &mut u64, user: String, x: u64) -> u64 {
+        *count += x;
+        *count
+    }
+}
+
+#[test]
+fn test_stateful_udf() {
+    let local = test_stateful_udf::__local();
+    assert!(local.is_stateful);
+    assert!(SyncUdfDylib::try_from(&local.config).is_err());
+
+    let udf: StatefulUdfDylib = (&local.config).try_into().unwrap();
+
+    let keys = BinaryArray::from_iter_values(["a", "b", "a", "a"]);
+    // "b" has stored state, "a" starts from its default
+    let states = BinaryArray::from_iter([None, Some("10"), None, None]);
+    let (results, states) = udf
+        .invoke(
+            &keys,
+            &states,
+            &[
+                Arc::new(StringArray::from(vec!["a", "b", "a", "a"])) as ArrayRef,
+                Arc::new(UInt64Array::from(vec![1, 2, 3, 4])) as ArrayRef,
+            ],
+        )
+        .unwrap();
+
+    let results = results.as_any().downcast_ref::<UInt64Array>().unwrap();
+    assert_eq!(results, &UInt64Array::from(vec![1, 12, 4, 8]));
+
+    // updated states are returned on the last row of each key
+    assert_eq!(
+        states,
+        BinaryArray::from_iter([None, Some("12"), None, Some("8")])
+    );
+}
+
 mod test_async_udf {
     use arroyo_udf_macros::udf;
     use std::time::Duration;
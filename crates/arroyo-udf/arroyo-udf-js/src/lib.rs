@@ -0,0 +1,523 @@
+//! UDFs written in JavaScript, which are run in QuickJS isolates.
+//!
+//! A JavaScript UDF is a source file that exports a single function, whose signature declares its
+//! SQL types with TypeScript annotations:
+//!
+//! ```js
+//! export function greet(name: string, times: number | null): string {
+//!     return `hello ${name}`.repeat(times ?? 1);
+//! }
+//! ```
+//!
+//! Only the exported function's signature may use TypeScript syntax; the rest of the source must
+//! be plain JavaScript.
+
+use anyhow::{anyhow, bail};
+use arrow::array::{
+    Array, ArrayRef, AsArray, BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder,
+    StringBuilder,
+};
+use arrow::datatypes::{DataType, Float64Type, Int64Type};
+use arroyo_udf_common::parse::{NullableType, UdfDef, UdfType};
+use arroyo_udf_host::UdfLimits;
+use datafusion::common::{DataFusionError, Result as DFResult};
+use datafusion::logical_expr::{ColumnarValue, ScalarUDFImpl, Signature, Volatility};
+use regex::Regex;
+use rquickjs::function::Args;
+use rquickjs::{BigInt, Context, Ctx, FromJs, Function, Runtime, TypedArray, Value};
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use tracing::warn;
+
+#[cfg(test)]
+mod test;
+
+const SUPPORTED_TYPES: &str = "number, bigint, string, boolean and Uint8Array";
+
+fn parse_type(annotation: &str) -> anyhow::Result<NullableType> {
+    let mut nullable = false;
+    let mut data_type = None;
+
+    for part in annotation.split('|').map(str::trim) {
+        let t = match part {
+            "null" | "undefined" => {
+                nullable = true;
+                continue;
+            }
+            "number" => DataType::Float64,
+            "bigint" => DataType::Int64,
+            "string" => DataType::Utf8,
+            "boolean" => DataType::Boolean,
+            "Uint8Array" => DataType::Binary,
+            t => bail!(
+                "unsupported type '{}'; JavaScript UDFs support {}",
+                t,
+                SUPPORTED_TYPES
+            ),
+        };
+
+        if data_type.replace(t).is_some() {
+            bail!("union type '{}' is not supported", annotation.trim());
+        }
+    }
+
+    let data_type =
+        data_type.ok_or_else(|| anyhow!("type '{}' has no non-null type", annotation.trim()))?;
+
+    Ok(if nullable {
+        NullableType::null(data_type)
+    } else {
+        NullableType::not_null(data_type)
+    })
+}
+
+/// A JavaScript UDF, parsed from its source
+#[derive(Clone, Debug)]
+pub struct ParsedJsUdf {
+    pub name: String,
+    pub args: Vec<NullableType>,
+    pub ret: NullableType,
+    /// The source with the TypeScript annotations removed from the UDF's signature
+    source: String,
+}
+
+impl ParsedJsUdf {
+    pub fn try_parse(definition: &str) -> anyhow::Result<Self> {
+        static SIGNATURE: OnceLock<Regex> = OnceLock::new();
+        let signature = SIGNATURE.get_or_init(|| {
+            Regex::new(
+                r"export\s+function\s+([A-Za-z_$][\w$]*)\s*\(([^)]*)\)\s*(?::\s*([^{]+?))?\s*\{",
+            )
+            .unwrap()
+        });
+
+        let mut functions = signature.captures_iter(definition);
+        let captures = functions.next().ok_or_else(|| {
+            anyhow!(
+                "no UDF found; a JavaScript UDF must export its function, like \
+                `export function name(arg: string): string {{ ... }}`"
+            )
+        })?;
+
+        if functions.next().is_some() {
+            bail!("a JavaScript UDF may only export one function");
+        }
+
+        let name = captures[1].to_string();
+
+        let mut params = vec![];
+        let mut args = vec![];
+        for param in captures[2]
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+        {
+            let (param_name, annotation) = param.split_once(':').ok_or_else(|| {
+                anyhow!(
+                    "In function {}: argument '{}' needs a type annotation",
+                    name,
+                    param
+                )
+            })?;
+
+            params.push(param_name.trim());
+            args.push(parse_type(annotation).map_err(|e| anyhow!("In function {}: {}", name, e))?);
+        }
+
+        let ret = captures
+            .get(3)
+            .ok_or_else(|| anyhow!("In function {}: the return type must be annotated", name))?;
+        let ret = parse_type(ret.as_str()).map_err(|e| anyhow!("In function {}: {}", name, e))?;
+
+        let signature = captures.get(0).unwrap();
+        let source = format!(
+            "{}function {}({}) {{{}",
+            &definition[..signature.start()],
+            name,
+            params.join(", "),
+            &definition[signature.end()..]
+        );
+
+        Ok(Self {
+            name,
+            args,
+            ret,
+            source,
+        })
+    }
+
+    pub fn udf_def(&self) -> UdfDef {
+        UdfDef {
+            args: self.args.clone(),
+            ret: self.ret.clone(),
+            aggregate: false,
+            udf_type: UdfType::Sync,
+        }
+    }
+}
+
+struct Isolate {
+    // the context must be dropped before its runtime
+    context: Context,
+    _runtime: Runtime,
+    /// When the currently-running call must finish by, checked by the interrupt handler
+    deadline: Arc<Mutex<Option<Instant>>>,
+}
+
+/// A UDF that runs JavaScript in QuickJS isolates. Each concurrent invocation takes an isolate
+/// from the UDF's pool, creating one if none are free. Isolates are discarded whenever a call
+/// fails, as a call that's interrupted or runs out of memory can leave them in an inconsistent
+/// state.
+pub struct JsUdf {
+    parsed: ParsedJsUdf,
+    signature: Signature,
+    limits: UdfLimits,
+    memory_limit: Option<u64>,
+    isolates: Mutex<Vec<Isolate>>,
+}
+
+impl Debug for JsUdf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsUdf")
+            .field("name", &self.parsed.name)
+            .field("args", &self.parsed.args)
+            .field("ret", &self.parsed.ret)
+            .field("limits", &self.limits)
+            .field("memory_limit", &self.memory_limit)
+            .finish()
+    }
+}
+
+impl JsUdf {
+    pub fn new(parsed: ParsedJsUdf) -> Self {
+        let signature = Signature::exact(
+            parsed.args.iter().map(|t| t.data_type.clone()).collect(),
+            Volatility::Volatile,
+        );
+
+        Self {
+            parsed,
+            signature,
+            limits: UdfLimits::default(),
+            memory_limit: None,
+            isolates: Mutex::new(vec![]),
+        }
+    }
+
+    /// Sets the timeout of each call and the failure policy, along with the most memory (in
+    /// bytes) each of the UDF's isolates may use
+    pub fn with_limits(mut self, limits: UdfLimits, memory_limit: Option<u64>) -> Self {
+        self.limits = limits;
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    fn create_isolate(&self) -> anyhow::Result<Isolate> {
+        let runtime = Runtime::new()?;
+        if let Some(limit) = self.memory_limit {
+            runtime.set_memory_limit(limit as usize);
+        }
+
+        let deadline: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+        let handler_deadline = deadline.clone();
+        runtime.set_interrupt_handler(Some(Box::new(move || {
+            handler_deadline
+                .lock()
+                .unwrap()
+                .is_some_and(|deadline| Instant::now() > deadline)
+        })));
+
+        let context = Context::full(&runtime)?;
+        context.with(|ctx| {
+            ctx.eval::<(), _>(self.parsed.source.as_str())
+                .map_err(|e| anyhow!("failed to evaluate UDF source: {}", describe(&ctx, e)))?;
+
+            ctx.globals()
+                .get::<_, Function>(self.parsed.name.as_str())
+                .map_err(|e| anyhow!("UDF function is not defined: {}", describe(&ctx, e)))?;
+
+            Ok::<_, anyhow::Error>(())
+        })?;
+
+        Ok(Isolate {
+            context,
+            _runtime: runtime,
+            deadline,
+        })
+    }
+
+    fn call_row(
+        &self,
+        isolate: &Isolate,
+        args: &[ArrayRef],
+        row: usize,
+        results: &mut ResultBuilder,
+    ) -> Result<(), String> {
+        let deadline = self.limits.timeout.map(|t| Instant::now() + t);
+        *isolate.deadline.lock().unwrap() = deadline;
+
+        let result = isolate.context.with(|ctx| {
+            let f: Function = ctx
+                .globals()
+                .get(self.parsed.name.as_str())
+                .map_err(|e| describe(&ctx, e))?;
+
+            let mut js_args = Args::new(ctx.clone(), args.len());
+            for array in args {
+                let value = to_js(&ctx, array, row).map_err(|e| describe(&ctx, e))?;
+                js_args.push_arg(value).map_err(|e| describe(&ctx, e))?;
+            }
+
+            let value: Value = f.call_arg(js_args).map_err(|e| describe(&ctx, e))?;
+            results.append(&ctx, &self.parsed.ret, value)
+        });
+
+        *isolate.deadline.lock().unwrap() = None;
+
+        match (result, deadline) {
+            (Err(_), Some(deadline)) if Instant::now() > deadline => Err(format!(
+                "timed out after {:?}",
+                self.limits.timeout.unwrap()
+            )),
+            (result, _) => result,
+        }
+    }
+}
+
+impl ScalarUDFImpl for JsUdf {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        &self.parsed.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> DFResult<DataType> {
+        Ok(self.parsed.ret.data_type.clone())
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> DFResult<ColumnarValue> {
+        let num_rows = args
+            .iter()
+            .map(|arg| {
+                if let ColumnarValue::Array(array) = arg {
+                    array.len()
+                } else {
+                    1
+                }
+            })
+            .max()
+            .unwrap_or(1);
+
+        let args = args
+            .iter()
+            .map(|arg| arg.clone().into_array(num_rows))
+            .collect::<DFResult<Vec<_>>>()?;
+
+        let mut results = ResultBuilder::new(&self.parsed.ret.data_type, num_rows);
+        let mut isolate = self.isolates.lock().unwrap().pop();
+        let mut failures = 0;
+
+        for row in 0..num_rows {
+            // like Rust UDFs, functions are not called for nulls in non-nullable arguments
+            if self
+                .parsed
+                .args
+                .iter()
+                .zip(&args)
+                .any(|(t, array)| !t.nullable && array.is_null(row))
+            {
+                results.append_null();
+                continue;
+            }
+
+            if isolate.is_none() {
+                isolate = Some(self.create_isolate().map_err(|e| {
+                    DataFusionError::Execution(format!(
+                        "failed to start JavaScript UDF {}: {}",
+                        self.parsed.name, e
+                    ))
+                })?);
+            }
+
+            if let Err(e) = self.call_row(isolate.as_ref().unwrap(), &args, row, &mut results) {
+                isolate = None;
+
+                if !self.limits.null_on_failure {
+                    return Err(DataFusionError::Execution(format!(
+                        "JavaScript UDF {} failed: {}",
+                        self.parsed.name, e
+                    )));
+                }
+
+                if failures == 0 {
+                    warn!(
+                        "JavaScript UDF {} failed, returning null: {}",
+                        self.parsed.name, e
+                    );
+                }
+                failures += 1;
+                results.append_null();
+            }
+        }
+
+        if let Some(isolate) = isolate {
+            self.isolates.lock().unwrap().push(isolate);
+        }
+
+        if failures > 1 {
+            warn!(
+                "JavaScript UDF {} failed for {} of {} rows",
+                self.parsed.name, failures, num_rows
+            );
+        }
+
+        Ok(ColumnarValue::Array(results.finish()))
+    }
+}
+
+fn describe(ctx: &Ctx, e: rquickjs::Error) -> String {
+    if !e.is_exception() {
+        return e.to_string();
+    }
+
+    let exception = ctx.catch();
+    if let Some(exception) = exception.as_exception() {
+        exception
+            .message()
+            .unwrap_or_else(|| "unknown exception".to_string())
+    } else if let Some(s) = exception.as_string().and_then(|s| s.to_string().ok()) {
+        s
+    } else {
+        format!("{:?}", exception)
+    }
+}
+
+fn to_js<'js>(ctx: &Ctx<'js>, array: &ArrayRef, row: usize) -> rquickjs::Result<Value<'js>> {
+    if array.is_null(row) {
+        return Ok(Value::new_null(ctx.clone()));
+    }
+
+    Ok(match array.data_type() {
+        DataType::Float64 => {
+            Value::new_float(ctx.clone(), array.as_primitive::<Float64Type>().value(row))
+        }
+        DataType::Int64 => {
+            BigInt::from_i64(ctx.clone(), array.as_primitive::<Int64Type>().value(row))?
+                .into_value()
+        }
+        DataType::Utf8 => {
+            rquickjs::String::from_str(ctx.clone(), array.as_string::<i32>().value(row))?
+                .into_value()
+        }
+        DataType::Boolean => Value::new_bool(ctx.clone(), array.as_boolean().value(row)),
+        DataType::Binary => {
+            TypedArray::<u8>::new(ctx.clone(), array.as_binary::<i32>().value(row).to_vec())?
+                .into_value()
+        }
+        t => unreachable!("JavaScript UDF called with unsupported type {}", t),
+    })
+}
+
+enum ResultBuilder {
+    Float64(Float64Builder),
+    Int64(Int64Builder),
+    Utf8(StringBuilder),
+    Boolean(BooleanBuilder),
+    Binary(BinaryBuilder),
+}
+
+impl ResultBuilder {
+    fn new(data_type: &DataType, capacity: usize) -> Self {
+        match data_type {
+            DataType::Float64 => Self::Float64(Float64Builder::with_capacity(capacity)),
+            DataType::Int64 => Self::Int64(Int64Builder::with_capacity(capacity)),
+            DataType::Utf8 => Self::Utf8(StringBuilder::with_capacity(capacity, capacity * 8)),
+            DataType::Boolean => Self::Boolean(BooleanBuilder::with_capacity(capacity)),
+            DataType::Binary => Self::Binary(BinaryBuilder::with_capacity(capacity, capacity * 8)),
+            t => unreachable!("JavaScript UDF with unsupported return type {}", t),
+        }
+    }
+
+    fn append_null(&mut self) {
+        match self {
+            Self::Float64(b) => b.append_null(),
+            Self::Int64(b) => b.append_null(),
+            Self::Utf8(b) => b.append_null(),
+            Self::Boolean(b) => b.append_null(),
+            Self::Binary(b) => b.append_null(),
+        }
+    }
+
+    fn append<'js>(
+        &mut self,
+        ctx: &Ctx<'js>,
+        ret: &NullableType,
+        value: Value<'js>,
+    ) -> Result<(), String> {
+        if value.is_null() || value.is_undefined() {
+            if !ret.nullable {
+                return Err(format!(
+                    "returned {}, but its return type is not nullable",
+                    value.type_name()
+                ));
+            }
+            self.append_null();
+            return Ok(());
+        }
+
+        let mismatch = |expected: &str| {
+            format!(
+                "returned a {} where a {} was expected",
+                value.type_name(),
+                expected
+            )
+        };
+
+        match self {
+            Self::Float64(b) => {
+                b.append_value(value.as_number().ok_or_else(|| mismatch("number"))?)
+            }
+            Self::Int64(b) => {
+                let v = match value.as_big_int() {
+                    Some(v) => v.clone().to_i64().map_err(|e| e.to_string())?,
+                    None => value.as_int().ok_or_else(|| mismatch("bigint"))? as i64,
+                };
+                b.append_value(v);
+            }
+            Self::Utf8(b) => {
+                let s = value.as_string().ok_or_else(|| mismatch("string"))?;
+                b.append_value(s.to_string().map_err(|e| e.to_string())?);
+            }
+            Self::Boolean(b) => b.append_value(value.as_bool().ok_or_else(|| mismatch("boolean"))?),
+            Self::Binary(b) => {
+                let array = TypedArray::<u8>::from_js(ctx, value.clone())
+                    .map_err(|_| mismatch("Uint8Array"))?;
+                b.append_value(
+                    array
+                        .as_bytes()
+                        .ok_or_else(|| "returned a detached Uint8Array".to_string())?,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> ArrayRef {
+        match self {
+            Self::Float64(mut b) => Arc::new(b.finish()),
+            Self::Int64(mut b) => Arc::new(b.finish()),
+            Self::Utf8(mut b) => Arc::new(b.finish()),
+            Self::Boolean(mut b) => Arc::new(b.finish()),
+            Self::Binary(mut b) => Arc::new(b.finish()),
+        }
+    }
+}
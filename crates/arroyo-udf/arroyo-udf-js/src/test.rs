@@ -0,0 +1,132 @@
+use crate::{JsUdf, ParsedJsUdf};
+use arrow::array::{Array, AsArray, Float64Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Int64Type};
+use arroyo_udf_host::UdfLimits;
+use datafusion::logical_expr::{ColumnarValue, ScalarUDFImpl};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn invoke(udf: &JsUdf, args: Vec<Arc<dyn Array>>) -> datafusion::common::Result<Arc<dyn Array>> {
+    let args: Vec<_> = args.into_iter().map(ColumnarValue::Array).collect();
+    let ColumnarValue::Array(result) = udf.invoke(&args)? else {
+        panic!("not an array");
+    };
+    Ok(result)
+}
+
+#[test]
+fn test_parse() {
+    let parsed = ParsedJsUdf::try_parse(
+        r#"
+function helper(s) {
+    return s.toUpperCase();
+}
+
+export function shout(s: string, times: bigint | null): string | null {
+    return helper(s).repeat(Number(times ?? 1n));
+}
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(parsed.name, "shout");
+    assert_eq!(parsed.args.len(), 2);
+    assert_eq!(parsed.args[0].data_type, DataType::Utf8);
+    assert!(!parsed.args[0].nullable);
+    assert_eq!(parsed.args[1].data_type, DataType::Int64);
+    assert!(parsed.args[1].nullable);
+    assert_eq!(parsed.ret.data_type, DataType::Utf8);
+    assert!(parsed.ret.nullable);
+    assert!(parsed.source.contains("function shout(s, times) {"));
+
+    assert!(ParsedJsUdf::try_parse("function f(x) { return x; }").is_err());
+    assert!(ParsedJsUdf::try_parse("export function f(x) { return x; }").is_err());
+    assert!(ParsedJsUdf::try_parse("export function f(x: number) { return x; }").is_err());
+    assert!(ParsedJsUdf::try_parse("export function f(x: Date): number { return 1; }").is_err());
+    assert!(
+        ParsedJsUdf::try_parse("export function f(x: number | string): number { return 1; }")
+            .is_err()
+    );
+}
+
+#[test]
+fn test_invoke() {
+    let udf = JsUdf::new(
+        ParsedJsUdf::try_parse(
+            "export function label(x: bigint, y: number | null): string { \
+                return `${x}:${y ?? 'none'}`; \
+            }",
+        )
+        .unwrap(),
+    );
+
+    let result = invoke(
+        &udf,
+        vec![
+            Arc::new(Int64Array::from(vec![Some(1), None, Some(3)])),
+            Arc::new(Float64Array::from(vec![Some(0.5), Some(1.0), None])),
+        ],
+    )
+    .unwrap();
+
+    let result = result.as_any().downcast_ref::<StringArray>().unwrap();
+    assert_eq!(result.len(), 3);
+    assert_eq!(result.value(0), "1:0.5");
+    // not called for a null in a non-nullable argument
+    assert!(result.is_null(1));
+    assert_eq!(result.value(2), "3:none");
+}
+
+#[test]
+fn test_failures() {
+    let parsed = ParsedJsUdf::try_parse(
+        "export function check(x: bigint): bigint { \
+            if (x < 0n) { throw new Error('negative'); } \
+            return x * 2n; \
+        }",
+    )
+    .unwrap();
+
+    let args = || -> Vec<Arc<dyn Array>> { vec![Arc::new(Int64Array::from(vec![1, -1, 2]))] };
+
+    let udf = JsUdf::new(parsed.clone());
+    let err = invoke(&udf, args()).unwrap_err();
+    assert!(err.to_string().contains("negative"), "{}", err);
+
+    let udf = JsUdf::new(parsed).with_limits(
+        UdfLimits {
+            timeout: None,
+            null_on_failure: true,
+        },
+        None,
+    );
+    let result = invoke(&udf, args()).unwrap();
+    let result = result.as_primitive::<Int64Type>();
+    assert_eq!(result.value(0), 2);
+    assert!(result.is_null(1));
+    assert_eq!(result.value(2), 4);
+}
+
+#[test]
+fn test_timeout() {
+    let udf = JsUdf::new(
+        ParsedJsUdf::try_parse(
+            "export function spin(x: bigint): bigint { if (x > 0n) { while (true) {} } return x; }",
+        )
+        .unwrap(),
+    )
+    .with_limits(
+        UdfLimits {
+            timeout: Some(Duration::from_millis(50)),
+            null_on_failure: true,
+        },
+        None,
+    );
+
+    let result = invoke(&udf, vec![Arc::new(Int64Array::from(vec![0, 1, 0]))]).unwrap();
+    let result = result.as_primitive::<Int64Type>();
+    assert_eq!(result.value(0), 0);
+    assert!(result.is_null(1));
+    // the isolate is recreated after the timeout
+    assert_eq!(result.value(2), 0);
+}
@@ -4,7 +4,7 @@ use proc_macro2::{Span, TokenStream};
 use quote::{format_ident, quote};
 use syn::parse::{Parse, ParseStream};
 use syn::spanned::Spanned;
-use syn::{parse_quote, FnArg, ItemFn};
+use syn::{parse_quote, FnArg, ItemFn, Type};
 
 fn data_type_to_arrow_type_token(data_type: &DataType) -> TokenStream {
     match data_type {
@@ -72,12 +72,25 @@ pub fn udf(
     let mangle = Some(quote! { #[no_mangle] });
     let tokens = if parsed.0.udf_type.is_async() {
         async_udf(parsed, mangle)
+    } else if parsed.0.udf_type.is_stateful() {
+        stateful_udf(parsed, mangle)
+    } else if parsed.0.udf_type.is_vectorized() {
+        vectorized_udf(parsed, mangle)
     } else {
         sync_udf(parsed, mangle)
     };
 
     (quote! {
         #tokens
+
+        #[global_allocator]
+        static __ALLOCATOR: arroyo_udf_plugin::memory::TrackingAllocator =
+            arroyo_udf_plugin::memory::TrackingAllocator;
+
+        #[no_mangle]
+        pub extern "C-unwind" fn __set_memory_limit(bytes: u64) {
+            arroyo_udf_plugin::memory::set_limit(bytes);
+        }
     })
     .into()
 }
@@ -105,8 +118,19 @@ pub fn local_udf(
                 ))))
         };
         (tokens, interface)
+    } else if parsed.0.udf_type.is_stateful() {
+        let tokens = stateful_udf(parsed, None);
+        let interface = quote! {
+            arroyo_udf_host::UdfInterface::Stateful(std::sync::Arc::new(arroyo_udf_host::ContainerOrLocal::Local(
+                arroyo_udf_host::StatefulUdfDylibInterface::new(__run_stateful))))
+        };
+        (tokens, interface)
     } else {
-        let tokens = sync_udf(parsed, None);
+        let tokens = if parsed.0.udf_type.is_vectorized() {
+            vectorized_udf(parsed, None)
+        } else {
+            sync_udf(parsed, None)
+        };
         let interface = quote! {
             arroyo_udf_host::UdfInterface::Sync(std::sync::Arc::new(arroyo_udf_host::ContainerOrLocal::Local(
                 arroyo_udf_host::UdfDylibInterface::new(__run))))
@@ -132,6 +156,7 @@ pub fn local_udf(
                 ),
                 is_aggregate: config.vec_arguments > 0,
                 is_async: config.udf_type.is_async(),
+                is_stateful: config.udf_type.is_stateful(),
             }
         }
     )).into()
@@ -171,24 +196,18 @@ fn arg_vars(parsed: &ParsedUdf) -> (Vec<TokenStream>, Vec<TokenStream>) {
         .unzip()
 }
 
-fn sync_udf(parsed: ParsedFunction, mangle: Option<TokenStream>) -> TokenStream {
-    let (parsed, item) = (parsed.0, parsed.1);
-    let udf_name = format_ident!("{}", parsed.name);
-
-    let results_builder = if matches!(parsed.ret_type.data_type, DataType::Utf8) {
+fn sync_results_builder(parsed: &ParsedUdf) -> TokenStream {
+    if matches!(parsed.ret_type.data_type, DataType::Utf8) {
         quote!(let mut results_builder = arroyo_udf_plugin::arrow::array::StringBuilder::with_capacity(batch_size, batch_size * 8);)
     } else {
         let return_type = data_type_to_arrow_type_token(&parsed.ret_type.data_type);
         quote!(let mut results_builder = arroyo_udf_plugin::arrow::array::PrimitiveBuilder::<arroyo_udf_plugin::arrow::datatypes::#return_type>::with_capacity(batch_size);)
-    };
-
-    let (defs, args) = arg_vars(&parsed);
-
-    let udaf = parsed
-        .args
-        .iter()
-        .any(|arg| matches!(arg.data_type, DataType::List(_)));
+    }
+}
 
+/// For each row, appends a null result and skips the call if a non-nullable arg is null, and
+/// converts string args to owned Strings
+fn row_preparation(parsed: &ParsedUdf) -> (Vec<TokenStream>, Vec<Option<TokenStream>>) {
     let unwrapping: Vec<_> = parsed
         .args
         .iter()
@@ -229,11 +248,34 @@ fn sync_udf(parsed: ParsedFunction, mangle: Option<TokenStream>) -> TokenStream
         })
         .collect();
 
+    (unwrapping, to_string)
+}
+
+fn arg_zip(args: &[TokenStream]) -> TokenStream {
     let mut arg_zip = quote!(arg_0.iter());
     for i in 1..args.len() {
         let next_arg = format_ident!("arg_{}", i);
         arg_zip = quote!(#arg_zip.zip(#next_arg.iter()));
     }
+    arg_zip
+}
+
+fn sync_udf(parsed: ParsedFunction, mangle: Option<TokenStream>) -> TokenStream {
+    let (parsed, item) = (parsed.0, parsed.1);
+    let udf_name = format_ident!("{}", parsed.name);
+
+    let results_builder = sync_results_builder(&parsed);
+
+    let (defs, args) = arg_vars(&parsed);
+
+    let udaf = parsed
+        .args
+        .iter()
+        .any(|arg| matches!(arg.data_type, DataType::List(_)));
+
+    let (unwrapping, to_string) = row_preparation(&parsed);
+
+    let arg_zip = arg_zip(&args);
 
     let call = if parsed.ret_type.nullable {
         quote!(results_builder.append_option(#udf_name(#(#args),*));)
@@ -244,6 +286,7 @@ fn sync_udf(parsed: ParsedFunction, mangle: Option<TokenStream>) -> TokenStream
     let call_loop = if udaf {
         quote! {
             #call
+            arroyo_udf_plugin::memory::check_limit();
         }
     } else {
         quote! {
@@ -251,6 +294,7 @@ fn sync_udf(parsed: ParsedFunction, mangle: Option<TokenStream>) -> TokenStream
                 #(#unwrapping;)*
                 #(#to_string;)*
                 #call
+                arroyo_udf_plugin::memory::check_limit();
             }
         }
     };
@@ -287,6 +331,131 @@ fn sync_udf(parsed: ParsedFunction, mangle: Option<TokenStream>) -> TokenStream
     }
 }
 
+fn stateful_udf(parsed: ParsedFunction, mangle: Option<TokenStream>) -> TokenStream {
+    let (parsed, item) = (parsed.0, parsed.1);
+    let udf_name = format_ident!("{}", parsed.name);
+
+    let state_type = match item.sig.inputs.first() {
+        Some(FnArg::Typed(t)) => match &*t.ty {
+            Type::Reference(r) => r.elem.clone(),
+            _ => unreachable!("stateful UDFs take their state as the first argument"),
+        },
+        _ => unreachable!("stateful UDFs take their state as the first argument"),
+    };
+
+    let results_builder = sync_results_builder(&parsed);
+    let (defs, args) = arg_vars(&parsed);
+    let (unwrapping, to_string) = row_preparation(&parsed);
+    let arg_zip = arg_zip(&args);
+
+    let call = if parsed.ret_type.nullable {
+        quote!(results_builder.append_option(#udf_name(store.get(i), #(#args),*));)
+    } else {
+        quote!(results_builder.append_option(Some(#udf_name(store.get(i), #(#args),*)));)
+    };
+
+    quote! {
+        #item
+
+        #mangle
+        pub extern "C-unwind" fn __run_stateful(args: arroyo_udf_plugin::FfiArrays) -> arroyo_udf_plugin::RunResult {
+            let args = args.into_vec();
+            let batch_size = args[0].len();
+
+            let result = std::panic::catch_unwind(|| {
+                let mut args = args.into_iter();
+                let keys = arroyo_udf_plugin::arrow::array::BinaryArray::from(args.next().unwrap());
+                let states = arroyo_udf_plugin::arrow::array::BinaryArray::from(args.next().unwrap());
+                let mut store = arroyo_udf_plugin::stateful::StateStore::<#state_type>::new(&keys, &states);
+
+                #results_builder
+
+                #(#defs;)*
+
+                for (i, (#(#args),*)) in #arg_zip.enumerate() {
+                    #(#unwrapping;)*
+                    #(#to_string;)*
+                    #call
+                    arroyo_udf_plugin::memory::check_limit();
+                }
+
+                store.finish(std::sync::Arc::new(results_builder.finish()))
+            });
+
+            match result {
+                Ok(data) => {
+                    arroyo_udf_plugin::RunResult::Ok(arroyo_udf_plugin::FfiArraySchema::from_data(data))
+                }
+                Err(_) => {
+                    arroyo_udf_plugin::RunResult::Err
+                }
+            }
+        }
+    }
+}
+
+fn vectorized_udf(parsed: ParsedFunction, mangle: Option<TokenStream>) -> TokenStream {
+    let (parsed, item) = (parsed.0, parsed.1);
+    let udf_name = format_ident!("{}", parsed.name);
+
+    let (defs, args): (Vec<_>, Vec<_>) = item
+        .sig
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            let FnArg::Typed(t) = arg else {
+                unreachable!("UDFs cannot take self");
+            };
+            let Type::Reference(r) = &*t.ty else {
+                unreachable!("vectorized UDFs take references to arrays");
+            };
+            let array_type = &r.elem;
+            let id = format_ident!("arg_{}", i);
+
+            (
+                quote!(let #id = <#array_type>::from(args.next().unwrap());),
+                quote!(&#id),
+            )
+        })
+        .unzip();
+
+    quote! {
+        #item
+
+        #mangle
+        pub extern "C-unwind" fn __run(args: arroyo_udf_plugin::FfiArrays) -> arroyo_udf_plugin::RunResult {
+            let args = args.into_vec();
+            let batch_size = args[0].len();
+
+            let result = std::panic::catch_unwind(|| {
+                let mut args = args.into_iter();
+
+                #(#defs)*
+
+                let result = #udf_name(#(#args),*);
+                assert_eq!(
+                    arroyo_udf_plugin::arrow::array::Array::len(&result),
+                    batch_size,
+                    "vectorized UDF must return a result for every row"
+                );
+                arroyo_udf_plugin::memory::check_limit();
+
+                arroyo_udf_plugin::arrow::array::Array::to_data(&result)
+            });
+
+            match result {
+                Ok(data) => {
+                    arroyo_udf_plugin::RunResult::Ok(arroyo_udf_plugin::FfiArraySchema::from_data(data))
+                }
+                Err(_) => {
+                    arroyo_udf_plugin::RunResult::Err
+                }
+            }
+        }
+    }
+}
+
 fn async_udf(parsed: ParsedFunction, mangle: Option<TokenStream>) -> TokenStream {
     let (parsed, item) = (parsed.0, parsed.1);
 
@@ -1,4 +1,6 @@
 pub mod async_udf;
+pub mod memory;
+pub mod stateful;
 
 pub use arrow;
 pub use arroyo_udf_common::{ArrowDatum, FfiArraySchema, FfiArrays, RunResult};
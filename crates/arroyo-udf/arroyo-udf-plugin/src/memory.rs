@@ -0,0 +1,67 @@
+//! Tracks the memory held by a UDF library, so that Arroyo can limit it.
+//!
+//! The `#[udf]` macro installs [`TrackingAllocator`] as the library's global allocator, and checks
+//! the limit set by Arroyo after each call into the UDF. An allocator can't fail an allocation
+//! without aborting the process, so the limit is enforced between calls: the call that takes the
+//! library over its limit panics, which Arroyo handles like any other failure of the UDF.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+pub struct TrackingAllocator;
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+/// The number of bytes currently allocated by the library
+pub fn allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+pub fn set_limit(bytes: u64) {
+    LIMIT.store(bytes.try_into().unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+/// Panics if the library holds more memory than its limit
+pub fn check_limit() {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    let allocated = allocated();
+    if allocated > limit {
+        panic!(
+            "UDF exceeded its memory limit of {} bytes (holding {} bytes)",
+            limit, allocated
+        );
+    }
+}
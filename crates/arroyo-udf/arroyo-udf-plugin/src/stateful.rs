@@ -0,0 +1,83 @@
+//! Support for stateful UDFs, whose state is stored by Arroyo between calls.
+//!
+//! Arroyo passes each batch along with a binary key for every row (the encoded value of the
+//! UDF's first argument) and, for the first row of each key in the batch, the serialized state
+//! for that key if it has any. The UDF returns its results along with the updated state of each
+//! key, set on the last row of that key. State is serialized as JSON, so the state type must
+//! implement serde's `Serialize` and `Deserialize`, as well as `Default` for keys seen for the
+//! first time.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayData, ArrayRef, BinaryArray, StructArray};
+use arrow::datatypes::{DataType, Field};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub struct StateStore<'a, S> {
+    keys: &'a BinaryArray,
+    states: &'a BinaryArray,
+    // the row that carries the stored state of each key that has one
+    stored: HashMap<&'a [u8], usize>,
+    // the state of each key seen in this batch, along with the last row that used it
+    current: HashMap<&'a [u8], (usize, S)>,
+}
+
+impl<'a, S: Default + Serialize + DeserializeOwned> StateStore<'a, S> {
+    pub fn new(keys: &'a BinaryArray, states: &'a BinaryArray) -> Self {
+        let stored = (0..keys.len())
+            .filter(|i| !states.is_null(*i))
+            .map(|i| (keys.value(i), i))
+            .collect();
+
+        Self {
+            keys,
+            states,
+            stored,
+            current: HashMap::new(),
+        }
+    }
+
+    /// Returns the state for the key of row `i`
+    pub fn get(&mut self, i: usize) -> &mut S {
+        let states = self.states;
+        let stored = &self.stored;
+        let keys = self.keys;
+        let key = keys.value(i);
+        let (last, state) = self.current.entry(key).or_insert_with(|| {
+            let state = match stored.get(key) {
+                Some(row) => serde_json::from_slice(states.value(*row))
+                    .unwrap_or_else(|e| panic!("failed to deserialize UDF state: {}", e)),
+                None => S::default(),
+            };
+            (i, state)
+        });
+        *last = i;
+        state
+    }
+
+    /// Combines the results of the UDF with the updated states into the array returned to Arroyo
+    pub fn finish(self, results: ArrayRef) -> ArrayData {
+        let mut states: Vec<Option<Vec<u8>>> = vec![None; self.keys.len()];
+        for (last, state) in self.current.into_values() {
+            states[last] = Some(
+                serde_json::to_vec(&state)
+                    .unwrap_or_else(|e| panic!("failed to serialize UDF state: {}", e)),
+            );
+        }
+        let states: BinaryArray = states.iter().map(|s| s.as_deref()).collect();
+
+        StructArray::from(vec![
+            (
+                Arc::new(Field::new("result", results.data_type().clone(), true)),
+                results,
+            ),
+            (
+                Arc::new(Field::new("state", DataType::Binary, true)),
+                Arc::new(states) as ArrayRef,
+            ),
+        ])
+        .to_data()
+    }
+}
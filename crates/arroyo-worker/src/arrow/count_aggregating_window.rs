@@ -0,0 +1,384 @@
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use arrow::{
+    compute::{concat_batches, max, min, take},
+    ipc::{reader::StreamReader, writer::StreamWriter},
+    row::SortField,
+};
+use arrow_array::{
+    types::TimestampNanosecondType, PrimitiveArray, RecordBatch, StructArray,
+    TimestampNanosecondArray, UInt32Array,
+};
+use arrow_schema::{DataType, Field, FieldRef};
+use arroyo_df::schemas::window_arrow_struct;
+use arroyo_operator::{
+    context::ArrowContext,
+    keyed_state::{Key, KeyedState, DEFAULT_STATE_TTL},
+    operator::{ArrowOperator, OperatorConstructor, OperatorNode, Registry},
+};
+use arroyo_rpc::{
+    df::{ArroyoSchema, ArroyoSchemaRef},
+    get_hasher,
+    grpc::{api, TableConfig},
+    Converter,
+};
+use arroyo_types::CheckpointBarrier;
+use datafusion::common::hash_utils::create_hashes;
+use datafusion::execution::{
+    context::SessionContext,
+    runtime_env::{RuntimeConfig, RuntimeEnv},
+    TaskContext,
+};
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion_proto::{physical_plan::AsExecutionPlan, protobuf::PhysicalPlanNode};
+use prost::Message;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio_stream::StreamExt;
+use tracing::info;
+
+use arroyo_df::physical::{ArroyoPhysicalExtensionCodec, DecodingContext};
+
+/// Aggregates every `size` records of each key, starting a new window every `slide` records.
+///
+/// The records of each key's open windows are buffered until the window fills up; the partial
+/// windows of keys that don't receive any records for a day are dropped.
+pub struct CountAggregatingWindowFunc {
+    size: usize,
+    slide: usize,
+    input_schema: ArroyoSchemaRef,
+    window_field: FieldRef,
+    window_index: usize,
+    final_physical_exec: Arc<dyn ExecutionPlan>,
+    receiver: Arc<RwLock<Option<UnboundedReceiver<RecordBatch>>>>,
+    row_converter: Converter,
+    // the records of each key's open windows, starting with the first record of the earliest one
+    keys: KeyedState<RecordBatch>,
+    // replaced with the task's context, which enforces its memory budget, on start
+    task_ctx: Arc<TaskContext>,
+}
+
+/// Takes the records of the earliest window if it's full, dropping the records that don't belong
+/// to any later window
+fn next_window(records: &mut RecordBatch, size: usize, slide: usize) -> Option<RecordBatch> {
+    if records.num_rows() < size {
+        return None;
+    }
+
+    let window = records.slice(0, size);
+    *records = records.slice(slide, records.num_rows() - slide);
+    Some(window)
+}
+
+#[derive(Debug)]
+struct CountWindowResult {
+    key: Vec<u8>,
+    window_start: i64,
+    window_end: i64,
+    batch: RecordBatch,
+}
+
+fn encode_records(batch: &RecordBatch) -> Result<Vec<u8>> {
+    let mut writer = StreamWriter::try_new(vec![], &batch.schema())?;
+    writer.write(batch)?;
+    writer.finish()?;
+    Ok(writer.into_inner()?)
+}
+
+fn decode_records(bytes: &[u8]) -> Result<RecordBatch> {
+    let reader = StreamReader::try_new(Cursor::new(bytes), None)?;
+    let schema = reader.schema();
+    let batches: Vec<_> = reader.collect::<Result<_, _>>()?;
+    Ok(concat_batches(&schema, &batches)?)
+}
+
+impl CountAggregatingWindowFunc {
+    fn key_count(&self) -> usize {
+        self.input_schema
+            .key_indices
+            .as_ref()
+            .map(|keys| keys.len())
+            .unwrap_or(0)
+    }
+
+    fn add_records(&mut self, key: &Key, batch: RecordBatch) -> Result<()> {
+        match self.keys.get_existing_mut(key) {
+            Some(records) => {
+                *records = concat_batches(&batch.schema(), [&*records, &batch])?;
+            }
+            None => {
+                self.keys.insert(key, batch);
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits the batch by key, preserving the order of each key's records
+    fn add_batch(&mut self, batch: &RecordBatch) -> Result<Vec<Key>> {
+        let key_columns = batch.columns()[0..self.key_count()].to_vec();
+        let rows = self
+            .row_converter
+            .convert_all_columns(&key_columns, batch.num_rows())?;
+        let mut hashes = vec![0; batch.num_rows()];
+        if !key_columns.is_empty() {
+            create_hashes(&key_columns, &get_hasher(), &mut hashes)?;
+        }
+
+        let mut indices_by_key: HashMap<&[u8], (u64, Vec<u32>)> = HashMap::new();
+        for (i, row) in rows.iter().enumerate() {
+            indices_by_key
+                .entry(row.as_ref())
+                .or_insert_with(|| (hashes[i], vec![]))
+                .1
+                .push(i as u32);
+        }
+
+        let mut updated = vec![];
+        for (key, (hash, indices)) in indices_by_key {
+            let indices = UInt32Array::from(indices);
+            let columns = batch
+                .columns()
+                .iter()
+                .map(|c| take(c, &indices, None))
+                .collect::<Result<_, _>>()?;
+            let key_batch = RecordBatch::try_new(batch.schema(), columns)?;
+            let key = Key::new(key.to_vec(), hash);
+            self.add_records(&key, key_batch)?;
+            updated.push(key);
+        }
+        Ok(updated)
+    }
+
+    async fn aggregate_window(
+        &self,
+        key: Vec<u8>,
+        window: RecordBatch,
+    ) -> Result<CountWindowResult> {
+        let timestamps = window
+            .column(self.input_schema.timestamp_index)
+            .as_any()
+            .downcast_ref::<TimestampNanosecondArray>()
+            .ok_or_else(|| anyhow!("timestamp column should be a nanosecond timestamp"))?;
+        let window_start = min(timestamps).ok_or_else(|| anyhow!("window should not be empty"))?;
+        let window_end = max(timestamps).ok_or_else(|| anyhow!("window should not be empty"))? + 1;
+
+        let (sender, receiver) = unbounded_channel();
+        {
+            let mut internal_receiver = self.receiver.write().unwrap();
+            *internal_receiver = Some(receiver);
+        }
+        self.final_physical_exec.reset()?;
+        let result_stream = self.final_physical_exec.execute(0, self.task_ctx.clone())?;
+        sender.send(window)?;
+        drop(sender);
+
+        let result_batches: Vec<_> = result_stream
+            .map(|batch| Ok(batch?))
+            .collect::<Result<_>>()
+            .await?;
+        if result_batches.len() != 1 || result_batches[0].num_rows() != 1 {
+            bail!(
+                "expect count window result to be exactly one row, not {:?}",
+                result_batches
+            );
+        }
+
+        Ok(CountWindowResult {
+            key,
+            window_start,
+            window_end,
+            batch: result_batches.into_iter().next().unwrap(),
+        })
+    }
+
+    async fn full_windows(&mut self, keys: Vec<Key>) -> Result<Vec<CountWindowResult>> {
+        let (size, slide) = (self.size, self.slide);
+        let mut results = vec![];
+        for key in keys {
+            while let Some(window) = self
+                .keys
+                .get_existing_mut(&key)
+                .and_then(|records| next_window(records, size, slide))
+            {
+                results.push(
+                    self.aggregate_window(key.as_bytes().to_vec(), window)
+                        .await?,
+                );
+            }
+
+            if self
+                .keys
+                .get(&key)
+                .is_some_and(|records| records.num_rows() == 0)
+            {
+                self.keys.remove(&key);
+            }
+        }
+        Ok(results)
+    }
+
+    fn to_record_batch(
+        &self,
+        results: Vec<CountWindowResult>,
+        ctx: &mut ArrowContext,
+    ) -> Result<RecordBatch> {
+        let key_columns = self
+            .row_converter
+            .convert_raw_rows(results.iter().map(|r| r.key.as_slice()).collect())?;
+
+        let window_start_array = PrimitiveArray::<TimestampNanosecondType>::from(
+            results.iter().map(|r| r.window_start).collect::<Vec<_>>(),
+        );
+        let window_end_array = PrimitiveArray::<TimestampNanosecondType>::from(
+            results.iter().map(|r| r.window_end).collect::<Vec<_>>(),
+        );
+        // results are timestamped by the last record in their window
+        let timestamp_array = PrimitiveArray::<TimestampNanosecondType>::from(
+            results.iter().map(|r| r.window_end - 1).collect::<Vec<_>>(),
+        );
+        let merged_batch = concat_batches(
+            &results[0].batch.schema(),
+            results.iter().map(|result| &result.batch),
+        )?;
+        let DataType::Struct(window_fields) = self.window_field.data_type() else {
+            bail!("expected window field to be a struct");
+        };
+        let window_struct_array = StructArray::try_new(
+            window_fields.clone(),
+            vec![Arc::new(window_start_array), Arc::new(window_end_array)],
+            None,
+        )?;
+        let mut columns = key_columns;
+        columns.insert(self.window_index, Arc::new(window_struct_array));
+        columns.extend_from_slice(merged_batch.columns());
+        columns.push(Arc::new(timestamp_array));
+        RecordBatch::try_new(
+            ctx.out_schema.as_ref().unwrap().schema.clone(),
+            columns.clone(),
+        )
+        .context(format!(
+            "failed to create batch.\nout schema:\n{:?}\ncolumns:\n{:?}",
+            ctx.out_schema, columns
+        ))
+    }
+}
+
+pub struct CountAggregatingWindowConstructor;
+
+impl OperatorConstructor for CountAggregatingWindowConstructor {
+    type ConfigT = api::CountWindowAggregateOperator;
+    fn with_config(
+        &self,
+        config: Self::ConfigT,
+        registry: Arc<Registry>,
+    ) -> anyhow::Result<OperatorNode> {
+        if config.size == 0 || config.slide == 0 || config.slide > config.size {
+            bail!(
+                "invalid count window with size {} and slide {}",
+                config.size,
+                config.slide
+            );
+        }
+
+        let window_field = Arc::new(Field::new(
+            config.window_field_name,
+            window_arrow_struct(),
+            true,
+        ));
+
+        let receiver = Arc::new(RwLock::new(None));
+
+        let codec = ArroyoPhysicalExtensionCodec {
+            context: DecodingContext::UnboundedBatchStream(receiver.clone()),
+        };
+        let final_plan = PhysicalPlanNode::decode(&mut config.final_aggregation_plan.as_slice())?;
+        let final_execution_plan = final_plan.try_into_physical_plan(
+            registry.as_ref(),
+            &RuntimeEnv::new(RuntimeConfig::new()).unwrap(),
+            &codec,
+        )?;
+
+        let input_schema: ArroyoSchema = config
+            .input_schema
+            .ok_or_else(|| anyhow!("missing input schema"))?
+            .try_into()?;
+        let key_count = input_schema
+            .key_indices
+            .as_ref()
+            .map(|keys| keys.len())
+            .unwrap_or(0);
+        let row_converter = Converter::new(
+            input_schema
+                .schema
+                .fields()
+                .into_iter()
+                .take(key_count)
+                .map(|field| SortField::new(field.data_type().clone()))
+                .collect(),
+        )?;
+
+        Ok(OperatorNode::from_operator(Box::new(
+            CountAggregatingWindowFunc {
+                size: config.size as usize,
+                slide: config.slide as usize,
+                input_schema: Arc::new(input_schema),
+                window_field,
+                window_index: config.window_index as usize,
+                final_physical_exec: final_execution_plan,
+                receiver,
+                row_converter,
+                keys: KeyedState::new("k", DEFAULT_STATE_TTL),
+                task_ctx: SessionContext::new().task_ctx(),
+            },
+        )))
+    }
+}
+
+#[async_trait::async_trait]
+impl ArrowOperator for CountAggregatingWindowFunc {
+    fn name(&self) -> String {
+        "count_window".to_string()
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        self.task_ctx = ctx.task_ctx();
+
+        self.keys
+            .restore(ctx, decode_records)
+            .await
+            .expect("should be able to restore count window state");
+
+        info!("restored count windows for {} keys", self.keys.len());
+    }
+
+    async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
+        let keys = self.add_batch(&batch).expect("should be able to add batch");
+        let results = self
+            .full_windows(keys)
+            .await
+            .expect("should be able to compute count windows");
+
+        if !results.is_empty() {
+            let result_batch = self
+                .to_record_batch(results, ctx)
+                .expect("should convert to record batch");
+            ctx.collect(result_batch).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, barrier: CheckpointBarrier, ctx: &mut ArrowContext) {
+        self.keys
+            .checkpoint(barrier.epoch, ctx, encode_records)
+            .await
+            .expect("should be able to checkpoint count window state");
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        self.keys
+            .table_config("buffered records of each key's open count windows")
+    }
+}
@@ -0,0 +1,28 @@
+use anyhow::anyhow;
+use arroyo_operator::custom::{custom_operator, custom_operator_names};
+use arroyo_operator::operator::{OperatorConstructor, OperatorNode, Registry};
+use arroyo_rpc::grpc::api;
+use std::sync::Arc;
+
+/// Constructs operators registered through [`arroyo_operator::custom::register_custom_operator`]
+pub struct CustomOperatorConstructor;
+
+impl OperatorConstructor for CustomOperatorConstructor {
+    type ConfigT = api::CustomOperator;
+
+    fn with_config(
+        &self,
+        config: Self::ConfigT,
+        _registry: Arc<Registry>,
+    ) -> anyhow::Result<OperatorNode> {
+        let factory = custom_operator(&config.name).ok_or_else(|| {
+            anyhow!(
+                "no custom operator registered with name '{}' (registered operators: {})",
+                config.name,
+                custom_operator_names().join(", ")
+            )
+        })?;
+
+        factory(&config.config)
+    }
+}
@@ -18,10 +18,9 @@ use arroyo_rpc::{
 };
 use arroyo_state::timestamp_table_config;
 use arroyo_types::{from_nanos, print_time, CheckpointBarrier, Watermark};
-use datafusion::execution::context::SessionContext;
 use datafusion::execution::{
     runtime_env::{RuntimeConfig, RuntimeEnv},
-    SendableRecordBatchStream,
+    SendableRecordBatchStream, TaskContext,
 };
 use datafusion::physical_plan::ExecutionPlan;
 use datafusion_proto::{physical_plan::AsExecutionPlan, protobuf::PhysicalPlanNode};
@@ -82,7 +81,11 @@ impl InstantJoin {
             Side::Right => self.right_input_schema.clone(),
         }
     }
-    async fn get_or_insert_exec(&mut self, time: SystemTime) -> Result<&mut InstantComputeHolder> {
+    async fn get_or_insert_exec(
+        &mut self,
+        time: SystemTime,
+        task_ctx: &Arc<TaskContext>,
+    ) -> Result<&mut InstantComputeHolder> {
         if let std::collections::btree_map::Entry::Vacant(e) = self.execs.entry(time) {
             let (left_sender, left_receiver) = unbounded_channel();
             let (right_sender, right_receiver) = unbounded_channel();
@@ -90,9 +93,7 @@ impl InstantJoin {
             self.right_receiver.write().unwrap().replace(right_receiver);
             self.join_exec.reset()?;
 
-            let new_exec = self
-                .join_exec
-                .execute(0, SessionContext::new().task_ctx())?;
+            let new_exec = self.join_exec.execute(0, task_ctx.clone())?;
             let next_batch_future = NextBatchFuture::new(time, new_exec);
             self.futures.lock().await.push(next_batch_future.clone());
             let exec = InstantComputeHolder {
@@ -111,6 +112,7 @@ impl InstantJoin {
         batch: RecordBatch,
         ctx: &mut ArrowContext,
     ) -> Result<()> {
+        let task_ctx = ctx.task_ctx();
         let table = ctx
             .table_manager
             .get_expiring_time_key_table(side.name(), ctx.last_present_watermark())
@@ -140,7 +142,7 @@ impl InstantJoin {
         // We expect that a record batch will usually only be a single timestamp, so we special case that.
         if max_timestamp == min_timestamp {
             let exec = self
-                .get_or_insert_exec(from_nanos(max_timestamp as u128))
+                .get_or_insert_exec(from_nanos(max_timestamp as u128), &task_ctx)
                 .await?;
             exec.insert(batch, side)?;
             return Ok(());
@@ -162,7 +164,7 @@ impl InstantJoin {
         for range in ranges {
             let batch = sorted.slice(range.start, range.end - range.start);
             let time = from_nanos(typed_timestamps.value(range.start) as u128);
-            let exec = self.get_or_insert_exec(time).await?;
+            let exec = self.get_or_insert_exec(time, &task_ctx).await?;
             exec.insert(batch, side)?;
         }
         Ok(())
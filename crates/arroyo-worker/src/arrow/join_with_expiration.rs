@@ -15,7 +15,6 @@ use arroyo_rpc::{
     grpc::{api, TableConfig},
 };
 use arroyo_state::timestamp_table_config;
-use datafusion::execution::context::SessionContext;
 use datafusion::execution::runtime_env::{RuntimeConfig, RuntimeEnv};
 use datafusion::physical_plan::ExecutionPlan;
 use datafusion_proto::{physical_plan::AsExecutionPlan, protobuf::PhysicalPlanNode};
@@ -124,7 +123,7 @@ impl JoinWithExpiration {
         self.join_execution_plan.reset().unwrap();
         let mut records = self
             .join_execution_plan
-            .execute(0, SessionContext::new().task_ctx())
+            .execute(0, ctx.task_ctx())
             .expect("successfully computed?");
         while let Some(batch) = records.next().await {
             let batch = batch.expect("should be able to compute batch");
@@ -22,10 +22,14 @@ use std::sync::Arc;
 use std::sync::RwLock;
 
 pub mod async_udf;
+pub mod count_aggregating_window;
+pub mod custom;
 pub mod instant_join;
 pub mod join_with_expiration;
+pub mod replay_source;
 pub mod session_aggregating_window;
 pub mod sliding_aggregating_window;
+pub mod stateful_udf;
 pub(crate) mod sync;
 pub mod tumbling_aggregating_window;
 pub mod updating_aggregator;
@@ -0,0 +1,123 @@
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::operator::SourceOperator;
+use arroyo_operator::recording::Recordings;
+use arroyo_operator::SourceFinishType;
+use arroyo_rpc::api_types::pipelines::ErrorCategory;
+use arroyo_rpc::grpc::StopMode;
+use arroyo_rpc::ControlMessage;
+use async_trait::async_trait;
+use tokio::sync::mpsc::error::TryRecvError;
+use tracing::{info, warn};
+
+/// Stands in for a source when replaying a pipeline, emitting the batches that the source
+/// recorded after the checkpoint being replayed from
+pub struct ReplaySourceFunc {
+    job_id: String,
+    epoch: u32,
+}
+
+impl ReplaySourceFunc {
+    pub fn new(job_id: String, epoch: u32) -> Self {
+        Self { job_id, epoch }
+    }
+
+    /// Handles the control messages received so far, returning how the source should finish if
+    /// it's been told to stop
+    async fn handle_control(&mut self, ctx: &mut ArrowContext) -> Option<SourceFinishType> {
+        loop {
+            match ctx.control_rx.try_recv() {
+                Ok(ControlMessage::Checkpoint(c)) => {
+                    if self.start_checkpoint(c, ctx).await {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+                Ok(ControlMessage::Stop { mode }) => {
+                    info!("Stopping replay source {:?}", mode);
+                    return Some(match mode {
+                        StopMode::Graceful => SourceFinishType::Graceful,
+                        StopMode::Immediate => SourceFinishType::Immediate,
+                    });
+                }
+                Ok(ControlMessage::Commit { .. }) => {
+                    unreachable!("sources shouldn't receive commit messages");
+                }
+                Ok(ControlMessage::LoadCompacted { compacted }) => {
+                    ctx.load_compacted(compacted).await;
+                }
+                Ok(ControlMessage::UpdateParameters { parameters }) => {
+                    ctx.update_parameters(&parameters);
+                }
+                Ok(ControlMessage::NoOp | ControlMessage::AlignWatermark { .. }) => {}
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    warn!("control channel closed for replay source");
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SourceOperator for ReplaySourceFunc {
+    fn name(&self) -> String {
+        "replay".to_string()
+    }
+
+    async fn run(&mut self, ctx: &mut ArrowContext) -> SourceFinishType {
+        let recordings = match Recordings::open(
+            &self.job_id,
+            &ctx.task_info.operator_id,
+            ctx.task_info.task_index,
+            self.epoch,
+        )
+        .await
+        {
+            Ok(recordings) => recordings,
+            Err(e) => {
+                ctx.report_error(
+                    ErrorCategory::Internal,
+                    "Failed to open recordings",
+                    format!("{:?}", e),
+                )
+                .await;
+                panic!("failed to open recordings for job {}: {:?}", self.job_id, e);
+            }
+        };
+
+        let epochs: Vec<_> = recordings.epochs().collect();
+        info!(
+            "Replaying {} recorded epochs of {}-{} from job {}",
+            epochs.len(),
+            ctx.task_info.operator_id,
+            ctx.task_info.task_index,
+            self.job_id
+        );
+
+        for epoch in epochs {
+            let batches = match recordings.read(epoch).await {
+                Ok(batches) => batches,
+                Err(e) => {
+                    ctx.report_error(
+                        ErrorCategory::Internal,
+                        format!("Failed to read recording for epoch {}", epoch),
+                        format!("{:?}", e),
+                    )
+                    .await;
+                    panic!("failed to read recording for epoch {}: {:?}", epoch, e);
+                }
+            };
+
+            for batch in batches {
+                ctx.collect(batch).await;
+
+                if let Some(finish) = self.handle_control(ctx).await {
+                    return finish;
+                }
+            }
+        }
+
+        // a replay is bounded by the recorded inputs
+        SourceFinishType::Final
+    }
+}
@@ -37,7 +37,7 @@ use arroyo_operator::operator::Registry;
 use arroyo_rpc::df::{ArroyoSchema, ArroyoSchemaRef};
 use datafusion::execution::{
     runtime_env::{RuntimeConfig, RuntimeEnv},
-    SendableRecordBatchStream,
+    SendableRecordBatchStream, TaskContext,
 };
 use datafusion_proto::{physical_plan::AsExecutionPlan, protobuf::PhysicalPlanNode};
 use prost::Message;
@@ -54,6 +54,8 @@ pub struct SessionAggregatingWindowFunc {
     key_computations: HashMap<OwnedRow, KeyComputingHolder>,
     keys_by_start_time: BTreeMap<SystemTime, HashSet<OwnedRow>>,
     row_converter: Converter,
+    // replaced with the task's context, which enforces its memory budget, on start
+    task_ctx: Arc<TaskContext>,
 }
 
 impl SessionAggregatingWindowFunc {
@@ -200,6 +202,7 @@ impl SessionAggregatingWindowFunc {
                     .entry(row.clone())
                     .or_insert_with(|| KeyComputingHolder {
                         session_window_config: self.config.clone(),
+                        task_ctx: self.task_ctx.clone(),
                         active_session: None,
                         batches_by_start_time: BTreeMap::new(),
                     });
@@ -382,9 +385,10 @@ impl ActiveSession {
         aggregation_plan: Arc<dyn ExecutionPlan>,
         initial_timestamp: SystemTime,
         sender: UnboundedSender<RecordBatch>,
+        task_ctx: Arc<TaskContext>,
     ) -> Result<Self> {
         aggregation_plan.reset()?;
-        let result_exec = aggregation_plan.execute(0, SessionContext::new().task_ctx())?;
+        let result_exec = aggregation_plan.execute(0, task_ctx)?;
         Ok(Self {
             data_start: initial_timestamp,
             data_end: initial_timestamp,
@@ -503,6 +507,7 @@ struct SessionWindowResult {
 
 struct KeyComputingHolder {
     session_window_config: Arc<SessionWindowConfig>,
+    task_ctx: Arc<TaskContext>,
     // this is computing the currently active batch.
     active_session: Option<ActiveSession>,
     // buffered batches that may not be in the current session.
@@ -571,6 +576,7 @@ impl KeyComputingHolder {
                         self.session_window_config.final_physical_exec.clone(),
                         *initial_timestamp,
                         sender,
+                        self.task_ctx.clone(),
                     )
                     .await?,
                 );
@@ -742,6 +748,7 @@ impl OperatorConstructor for SessionAggregatingWindowConstructor {
                 keys_by_start_time: BTreeMap::new(),
                 key_computations: HashMap::new(),
                 row_converter,
+                task_ctx: SessionContext::new().task_ctx(),
             },
         )))
     }
@@ -755,6 +762,7 @@ impl ArrowOperator for SessionAggregatingWindowFunc {
     }
 
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        self.task_ctx = ctx.task_ctx();
         let start_times_map: &mut GlobalKeyedView<usize, Option<SystemTime>> =
             ctx.table_manager.get_global_keyed_state("e").await.unwrap();
         let start_time = start_times_map
@@ -15,9 +15,11 @@ use arroyo_operator::{
 };
 use arroyo_rpc::grpc::{api, TableConfig};
 use arroyo_state::timestamp_table_config;
-use arroyo_types::{from_nanos, print_time, to_nanos, CheckpointBarrier, Watermark};
+use arroyo_types::{
+    from_nanos, print_time, to_nanos, ArrowMessage, CheckpointBarrier, SignalMessage, Watermark,
+};
 use datafusion::common::ScalarValue;
-use datafusion::{execution::context::SessionContext, physical_plan::ExecutionPlan};
+use datafusion::physical_plan::ExecutionPlan;
 
 use futures::stream::FuturesUnordered;
 
@@ -45,6 +47,12 @@ use super::sync::streams::KeyedCloneableStreamFuture;
 pub struct SlidingAggregatingWindowFunc<K: Copy> {
     slide: Duration,
     width: Duration,
+    // bins are assigned and windows closed by the wall clock rather than by event time and
+    // watermarks
+    processing_time: bool,
+    // windows start at the beginning of the width-aligned period containing them and grow by a
+    // slide at a time, as for CUMULATE
+    cumulative: bool,
     binning_function: Arc<dyn PhysicalExpr>,
     partial_aggregation_plan: Arc<dyn ExecutionPlan>,
     partial_schema: ArroyoSchema,
@@ -97,6 +105,17 @@ impl<K: Copy> SlidingAggregatingWindowFunc<K> {
 
         from_nanos(nanos)
     }
+
+    /// The start of the window ending at `window_end`
+    fn window_start(&self, window_end: SystemTime) -> SystemTime {
+        if self.cumulative {
+            let mut nanos = to_nanos(window_end - self.slide);
+            nanos -= nanos % self.width.as_nanos();
+            from_nanos(nanos)
+        } else {
+            window_end - self.width
+        }
+    }
 }
 
 impl SlidingAggregatingWindowFunc<SystemTime> {
@@ -114,6 +133,7 @@ impl SlidingAggregatingWindowFunc<SystemTime> {
     }
 
     async fn advance(&mut self, ctx: &mut ArrowContext) -> Result<()> {
+        let task_ctx = ctx.task_ctx();
         let bin_start = match self.state {
             SlidingWindowState::NoData => unreachable!(),
             SlidingWindowState::OnlyBufferedData { earliest_bin_time } => earliest_bin_time,
@@ -155,8 +175,8 @@ impl SlidingAggregatingWindowFunc<SystemTime> {
             }
         }
         partial_table.flush_timestamp(bin_end).await?;
-        partial_table.expire_timestamp(bin_end - self.width + self.slide);
-        let interval_start = bin_end - self.width;
+        partial_table.expire_timestamp(self.window_start(bin_end + self.slide));
+        let interval_start = self.window_start(bin_end);
         let interval_end = bin_end;
         {
             let mut batches = self.final_batches_passer.write().unwrap();
@@ -167,10 +187,10 @@ impl SlidingAggregatingWindowFunc<SystemTime> {
         self.finish_execution_plan.reset()?;
         let mut final_exec = self
             .finish_execution_plan
-            .execute(0, SessionContext::new().task_ctx())
+            .execute(0, task_ctx.clone())
             .unwrap();
         self.tiered_record_batches
-            .delete_before(bin_end + self.slide - self.width)?;
+            .delete_before(self.window_start(bin_end + self.slide))?;
 
         self.state = if self.tiered_record_batches.is_empty() {
             match partial_table.get_min_time() {
@@ -187,9 +207,14 @@ impl SlidingAggregatingWindowFunc<SystemTime> {
         let mut aggregate_results = Vec::new();
         while let Some(batch) = final_exec.next().await {
             let batch = batch.expect("should be able to compute batch");
+            // the width of cumulating windows varies, so the projection is given their end instead
             let with_timestamp = Self::add_bin_start_as_timestamp(
                 &batch,
-                interval_start,
+                if self.cumulative {
+                    interval_end
+                } else {
+                    interval_start
+                },
                 self.projection_input_schema.clone(),
             )?;
             aggregate_results.push(with_timestamp);
@@ -199,9 +224,7 @@ impl SlidingAggregatingWindowFunc<SystemTime> {
             *batches = aggregate_results;
         }
         self.final_projection.reset()?;
-        let mut final_projection_exec = self
-            .final_projection
-            .execute(0, SessionContext::new().task_ctx())?;
+        let mut final_projection_exec = self.final_projection.execute(0, task_ctx.clone())?;
         while let Some(batch) = final_projection_exec.next().await {
             let batch = batch.expect("should be able to compute batch");
             ctx.collector.collect(batch).await;
@@ -460,6 +483,14 @@ impl OperatorConstructor for SlidingAggregatingWindowConstructor {
             .ok_or_else(|| anyhow!("missing input schema"))?
             .try_into()?;
         let slide = Duration::from_micros(config.slide_micros);
+        if config.processing_time && slide == Duration::ZERO {
+            bail!("processing-time windows must have a non-zero slide");
+        }
+        if config.cumulative
+            && (slide == Duration::ZERO || width.as_nanos() % slide.as_nanos() != 0)
+        {
+            bail!("cumulating windows must have a non-zero step that evenly divides their size");
+        }
         let binning_function = PhysicalExprNode::decode(&mut config.binning_function.as_slice())?;
         let binning_function = parse_physical_expr(
             &binning_function,
@@ -509,6 +540,8 @@ impl OperatorConstructor for SlidingAggregatingWindowConstructor {
             SlidingAggregatingWindowFunc {
                 slide,
                 width,
+                processing_time: config.processing_time,
+                cumulative: config.cumulative,
                 binning_function,
                 partial_aggregation_plan,
                 partial_schema,
@@ -535,13 +568,33 @@ impl ArrowOperator for SlidingAggregatingWindowFunc<SystemTime> {
         "sliding_window".to_string()
     }
 
+    fn tick_interval(&self) -> Option<Duration> {
+        // close processing-time windows promptly after they end
+        self.processing_time
+            .then(|| self.slide.min(Duration::from_secs(1)))
+    }
+
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
-        let watermark = ctx.last_present_watermark();
+        let watermark = if self.processing_time {
+            None
+        } else {
+            ctx.last_present_watermark()
+        };
         let table = ctx
             .table_manager
             .get_expiring_time_key_table("t", watermark)
             .await
             .expect("should be able to load table");
+        let watermark = if self.processing_time {
+            // the windows ending by the current bin are emitted before checkpointing, so only
+            // the latest restored bin can still be open
+            table
+                .all_batches_for_watermark(None)
+                .map(|(t, _)| self.bin_start(*t))
+                .max()
+        } else {
+            watermark
+        };
         // bins before the watermark should be put into the TieredRecordBatchHolder, those after in the exec.
         let watermark_bin = self.bin_start(watermark.unwrap_or(SystemTime::UNIX_EPOCH));
         for (timestamp, batches) in table.all_batches_for_watermark(watermark) {
@@ -578,12 +631,19 @@ impl ArrowOperator for SlidingAggregatingWindowFunc<SystemTime> {
 
     // TODO: filter out late data
     async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
-        let bin = self
-            .binning_function
-            .evaluate(&batch)
-            .unwrap()
-            .into_array(batch.num_rows())
-            .unwrap();
+        let task_ctx = ctx.task_ctx();
+        let bin = if self.processing_time {
+            let now = to_nanos(self.bin_start(ctx.now())) as i64;
+            ScalarValue::TimestampNanosecond(Some(now), None)
+                .to_array_of_size(batch.num_rows())
+                .unwrap()
+        } else {
+            self.binning_function
+                .evaluate(&batch)
+                .unwrap()
+                .into_array(batch.num_rows())
+                .unwrap()
+        };
         let indices = sort_to_indices(bin.as_ref(), None, None).unwrap();
         let columns = batch
             .columns()
@@ -603,7 +663,11 @@ impl ArrowOperator for SlidingAggregatingWindowFunc<SystemTime> {
             // the binning function already rounded down to the bin start.
             let bin_start = from_nanos(typed_bin.value(range.start) as u128);
 
-            let watermark = ctx.last_present_watermark();
+            let watermark = if self.processing_time {
+                Some(ctx.now())
+            } else {
+                ctx.last_present_watermark()
+            };
 
             if watermark.is_some() && bin_start < self.bin_start(watermark.unwrap()) {
                 return;
@@ -634,7 +698,7 @@ impl ArrowOperator for SlidingAggregatingWindowFunc<SystemTime> {
                 self.partial_aggregation_plan.reset().expect("reset plan");
                 let new_exec = self
                     .partial_aggregation_plan
-                    .execute(0, SessionContext::new().task_ctx())
+                    .execute(0, task_ctx.clone())
                     .unwrap();
                 let next_batch_future = NextBatchFuture::new(bin_start, new_exec);
                 self.futures.push(next_batch_future.clone());
@@ -654,6 +718,11 @@ impl ArrowOperator for SlidingAggregatingWindowFunc<SystemTime> {
         watermark: Watermark,
         ctx: &mut ArrowContext,
     ) -> Option<Watermark> {
+        if self.processing_time {
+            // our output is timestamped by processing time, so we emit our own watermarks on tick
+            return None;
+        }
+
         let last_watermark = ctx.last_present_watermark()?;
 
         while self.should_advance(last_watermark) {
@@ -663,13 +732,35 @@ impl ArrowOperator for SlidingAggregatingWindowFunc<SystemTime> {
         Some(watermark)
     }
 
+    async fn handle_tick(&mut self, _: u64, ctx: &mut ArrowContext) {
+        if !self.processing_time {
+            return;
+        }
+
+        let now = ctx.now();
+        while self.should_advance(now) {
+            self.advance(ctx).await.unwrap();
+        }
+        ctx.broadcast(ArrowMessage::Signal(SignalMessage::Watermark(
+            Watermark::EventTime(self.bin_start(now)),
+        )))
+        .await;
+    }
+
     async fn handle_checkpoint(&mut self, _b: CheckpointBarrier, ctx: &mut ArrowContext) {
-        let watermark = ctx
-            .watermark()
-            .and_then(|watermark: Watermark| match watermark {
-                Watermark::EventTime(watermark) => Some(watermark),
-                Watermark::Idle => None,
-            });
+        let watermark = if self.processing_time {
+            let now = ctx.now();
+            while self.should_advance(now) {
+                self.advance(ctx).await.unwrap();
+            }
+            Some(self.bin_start(now))
+        } else {
+            ctx.watermark()
+                .and_then(|watermark: Watermark| match watermark {
+                    Watermark::EventTime(watermark) => Some(watermark),
+                    Watermark::Idle => None,
+                })
+        };
         let table = ctx
             .table_manager
             .get_expiring_time_key_table("t", watermark)
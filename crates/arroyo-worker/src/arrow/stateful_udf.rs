@@ -0,0 +1,230 @@
+use anyhow::anyhow;
+use arrow::row::{RowConverter, SortField};
+use arrow_array::{Array, ArrayRef, BinaryArray, RecordBatch};
+use arrow_schema::{Field, Schema};
+use arroyo_datastream::logical::DylibUdfConfig;
+use arroyo_df::STATEFUL_RESULT_FIELD;
+use arroyo_operator::context::ArrowContext;
+use arroyo_operator::keyed_state::{Key, KeyedState, DEFAULT_STATE_TTL};
+use arroyo_operator::operator::{
+    udf_limits, ArrowOperator, OperatorConstructor, OperatorNode, Registry,
+};
+use arroyo_rpc::get_hasher;
+use arroyo_rpc::grpc::api;
+use arroyo_rpc::grpc::TableConfig;
+use arroyo_types::CheckpointBarrier;
+use arroyo_udf_host::StatefulUdfDylib;
+use async_trait::async_trait;
+use datafusion::common::hash_utils::create_hashes;
+use datafusion::physical_expr::PhysicalExpr;
+use datafusion_proto::physical_plan::from_proto::parse_physical_expr;
+use datafusion_proto::physical_plan::DefaultPhysicalExtensionCodec;
+use datafusion_proto::protobuf::PhysicalExprNode;
+use prost::Message;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::info;
+
+/// Calls a stateful UDF, storing the state of each of its keys (the value of its first argument)
+/// between calls and in checkpoints. Keys whose state hasn't changed for a day are dropped.
+pub struct StatefulUdfOperator {
+    name: String,
+    udf: StatefulUdfDylib,
+    config: api::StatefulUdfOperator,
+    registry: Arc<Registry>,
+    arg_exprs: Vec<Arc<dyn PhysicalExpr>>,
+    final_exprs: Vec<Arc<dyn PhysicalExpr>>,
+    key_indices: Vec<usize>,
+    key_converter: RowConverter,
+    post_udf_schema: Option<Arc<Schema>>,
+    // the serialized state of each key
+    states: KeyedState<Vec<u8>>,
+}
+
+pub struct StatefulUdfConstructor;
+
+impl OperatorConstructor for StatefulUdfConstructor {
+    type ConfigT = api::StatefulUdfOperator;
+
+    fn with_config(
+        &self,
+        config: Self::ConfigT,
+        registry: Arc<Registry>,
+    ) -> anyhow::Result<OperatorNode> {
+        let udf_config: DylibUdfConfig = config
+            .udf
+            .clone()
+            .ok_or_else(|| anyhow!("no UDF config"))?
+            .into();
+
+        let udf = registry.get_dylib(&udf_config.dylib_path).ok_or_else(|| {
+            anyhow!(
+                "Stateful UDF operator configured to use UDF {} at {} which was not loaded at startup",
+                config.name,
+                udf_config.dylib_path,
+            )
+        })?;
+
+        Ok(OperatorNode::from_operator(Box::new(StatefulUdfOperator {
+            name: config.name.clone(),
+            udf: StatefulUdfDylib::try_from(&*udf)?.with_limits(udf_limits()),
+            config,
+            registry,
+            arg_exprs: vec![],
+            final_exprs: vec![],
+            key_indices: vec![],
+            key_converter: RowConverter::new(vec![]).unwrap(),
+            post_udf_schema: None,
+            states: KeyedState::new("k", DEFAULT_STATE_TTL),
+        })))
+    }
+}
+
+impl StatefulUdfOperator {
+    fn parse_exprs(&self, exprs: &[Vec<u8>], schema: &Schema) -> Vec<Arc<dyn PhysicalExpr>> {
+        exprs
+            .iter()
+            .map(|expr| {
+                parse_physical_expr(
+                    &PhysicalExprNode::decode(&mut expr.as_slice()).unwrap(),
+                    &*self.registry,
+                    schema,
+                    &DefaultPhysicalExtensionCodec {},
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ArrowOperator for StatefulUdfOperator {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn tables(&self) -> HashMap<String, TableConfig> {
+        self.states.table_config("StatefulUdfOperator state")
+    }
+
+    async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        let input_schema = ctx.in_schemas[0].schema.clone();
+        self.key_indices = ctx.in_schemas[0]
+            .key_indices
+            .clone()
+            .expect("stateful UDF input must be keyed");
+
+        self.key_converter = RowConverter::new(
+            self.key_indices
+                .iter()
+                .map(|i| SortField::new(input_schema.field(*i).data_type().clone()))
+                .collect(),
+        )
+        .unwrap();
+
+        let mut post_udf_fields = input_schema.fields.to_vec();
+        post_udf_fields.push(Arc::new(Field::new(
+            STATEFUL_RESULT_FIELD,
+            self.udf.return_type().clone(),
+            true,
+        )));
+        let post_udf_schema = Arc::new(Schema::new(post_udf_fields));
+
+        self.arg_exprs = self.parse_exprs(&self.config.arg_exprs, &input_schema);
+        self.final_exprs = self.parse_exprs(&self.config.final_exprs, &post_udf_schema);
+        self.post_udf_schema = Some(post_udf_schema);
+
+        self.states
+            .restore(ctx, |state| Ok(state.to_vec()))
+            .await
+            .expect("should be able to restore stateful UDF state");
+
+        info!(
+            "Restored state for {} keys of stateful UDF {}",
+            self.states.len(),
+            self.udf.name()
+        );
+    }
+
+    async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
+        let key_columns: Vec<_> = self
+            .key_indices
+            .iter()
+            .map(|i| batch.column(*i).clone())
+            .collect();
+        let rows = self.key_converter.convert_columns(&key_columns).unwrap();
+        let keys = BinaryArray::from_iter_values(rows.iter());
+
+        let mut hashes = vec![0; batch.num_rows()];
+        create_hashes(&key_columns, &get_hasher(), &mut hashes).unwrap();
+
+        // the UDF expects the stored state of each key on its first row
+        let mut seen = HashSet::new();
+        let states: BinaryArray = (0..keys.len())
+            .map(|i| {
+                let key = keys.value(i);
+                if seen.insert(key) {
+                    self.states
+                        .get(&Key::new(key.to_vec(), hashes[i]))
+                        .map(|state| state.as_slice())
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let args: Vec<ArrayRef> = self
+            .arg_exprs
+            .iter()
+            .map(|expr| {
+                expr.evaluate(&batch)
+                    .unwrap()
+                    .into_array(batch.num_rows())
+                    .unwrap()
+            })
+            .collect();
+
+        let (results, updated) = self
+            .udf
+            .invoke(&keys, &states, &args)
+            .expect("failed to invoke stateful UDF");
+
+        for i in 0..updated.len() {
+            if !updated.is_null(i) {
+                self.states.insert(
+                    &Key::new(keys.value(i).to_vec(), hashes[i]),
+                    updated.value(i).to_vec(),
+                );
+            }
+        }
+
+        let mut columns = batch.columns().to_vec();
+        columns.push(results);
+        let batch = RecordBatch::try_new(self.post_udf_schema.clone().unwrap(), columns)
+            .expect("could not construct record batch from stateful UDF result");
+
+        let result: Vec<_> = self
+            .final_exprs
+            .iter()
+            .map(|expr| {
+                expr.evaluate(&batch)
+                    .unwrap()
+                    .into_array(batch.num_rows())
+                    .unwrap()
+            })
+            .collect();
+
+        ctx.collect(
+            RecordBatch::try_new(ctx.out_schema.as_ref().unwrap().schema.clone(), result)
+                .expect("failed to construct record batch"),
+        )
+        .await;
+    }
+
+    async fn handle_checkpoint(&mut self, barrier: CheckpointBarrier, ctx: &mut ArrowContext) {
+        self.states
+            .checkpoint(barrier.epoch, ctx, |state| Ok(state.clone()))
+            .await
+            .expect("should be able to checkpoint stateful UDF state");
+    }
+}
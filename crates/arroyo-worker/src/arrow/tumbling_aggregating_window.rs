@@ -5,21 +5,25 @@ use std::{
     collections::{BTreeMap, HashMap},
     mem,
     sync::{Arc, RwLock},
-    time::SystemTime,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use arrow::compute::{partition, sort_to_indices, take};
-use arrow_array::{types::TimestampNanosecondType, Array, PrimitiveArray, RecordBatch};
+use arrow_array::{
+    types::TimestampNanosecondType, Array, PrimitiveArray, RecordBatch, StringArray, StructArray,
+};
 use arrow_schema::SchemaRef;
 use arroyo_df::schemas::add_timestamp_field_arrow;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::operator::{ArrowOperator, OperatorConstructor, OperatorNode, Registry};
 use arroyo_rpc::grpc::{api, TableConfig};
 use arroyo_state::timestamp_table_config;
-use arroyo_types::{from_nanos, print_time, to_nanos, CheckpointBarrier, Watermark};
+use arroyo_types::{
+    from_nanos, print_time, to_nanos, ArrowMessage, CheckpointBarrier, SignalMessage, Watermark,
+};
 use datafusion::common::ScalarValue;
-use datafusion::{execution::context::SessionContext, physical_plan::ExecutionPlan};
+use datafusion::physical_plan::ExecutionPlan;
 use futures::{stream::FuturesUnordered, StreamExt};
 
 use arroyo_df::physical::{ArroyoPhysicalExtensionCodec, DecodingContext};
@@ -45,6 +49,14 @@ type NextBatchFuture<K> = KeyedCloneableStreamFuture<K, SendableRecordBatchStrea
 
 pub struct TumblingAggregatingWindowFunc<K: Copy> {
     width: Duration,
+    // bins are assigned and closed by the wall clock rather than by event time and watermarks
+    processing_time: bool,
+    // emit partial results for open bins on this interval
+    early_fire: Option<Duration>,
+    // closed bins are kept for this long, emitting late results when records arrive for them
+    allowed_lateness: Duration,
+    // for windows with a trigger, the index of the window struct whose emit mode we set
+    emit_mode_index: Option<usize>,
     binning_function: Arc<dyn PhysicalExpr>,
     partial_aggregation_plan: Arc<dyn ExecutionPlan>,
     partial_schema: ArroyoSchema,
@@ -69,12 +81,26 @@ impl<K: Copy> TumblingAggregatingWindowFunc<K> {
 
         from_nanos(nanos)
     }
+
+    /// The time that bins are closed by: the watermark, or for processing-time windows the
+    /// current time
+    fn current_time(&self, ctx: &ArrowContext) -> Option<SystemTime> {
+        if self.processing_time {
+            Some(ctx.now())
+        } else {
+            ctx.last_present_watermark()
+        }
+    }
 }
 
 struct BinComputingHolder<K: Copy> {
     active_exec: Option<NextBatchFuture<K>>,
     finished_batches: Vec<RecordBatch>,
     sender: Option<UnboundedSender<RecordBatch>>,
+    // whether the final result for the bin has been emitted
+    fired: bool,
+    // whether records have arrived since the last result was emitted
+    updated: bool,
 }
 
 impl<K: Copy> Default for BinComputingHolder<K> {
@@ -83,6 +109,46 @@ impl<K: Copy> Default for BinComputingHolder<K> {
             active_exec: None,
             finished_batches: Vec::new(),
             sender: None,
+            fired: false,
+            updated: false,
+        }
+    }
+}
+
+impl<K: Copy> BinComputingHolder<K> {
+    /// Finishes the in-progress partial aggregation, returning the batches it produced (which
+    /// are also added to `finished_batches`)
+    async fn drain(&mut self) -> Vec<RecordBatch> {
+        self.sender.take();
+        let mut drained = vec![];
+        let Some(mut active_exec) = self.active_exec.take() else {
+            return drained;
+        };
+        while let (_bin, Some((batch, next_exec))) = active_exec.await {
+            active_exec = next_exec;
+            let batch = batch.expect("should be able to compute batch");
+            self.finished_batches.push(batch.clone());
+            drained.push(batch);
+        }
+        drained
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmitMode {
+    /// fired early, before the bin closed
+    Partial,
+    Final,
+    /// corrected for records that arrived after the bin closed
+    Late,
+}
+
+impl EmitMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EmitMode::Partial => "partial",
+            EmitMode::Final => "final",
+            EmitMode::Late => "late",
         }
     }
 }
@@ -102,6 +168,144 @@ impl TumblingAggregatingWindowFunc<SystemTime> {
         RecordBatch::try_new(schema.clone(), columns)
             .map_err(|err| anyhow::anyhow!("schema: {:?}\nbatch:{:?}\nerr:{}", schema, batch, err))
     }
+
+    /// Sets the emit mode of results from windows with a trigger
+    fn with_emit_mode(&self, batch: RecordBatch, mode: EmitMode) -> Result<RecordBatch> {
+        let Some(index) = self.emit_mode_index else {
+            return Ok(batch);
+        };
+        let window = batch
+            .column(index)
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .ok_or_else(|| anyhow!("window column should be a struct"))?;
+        let (fields, mut columns, nulls) = window.clone().into_parts();
+        *columns
+            .last_mut()
+            .ok_or_else(|| anyhow!("window struct should have an emit mode"))? =
+            Arc::new(StringArray::from(vec![mode.as_str(); batch.num_rows()]));
+        let mut batch_columns = batch.columns().to_vec();
+        batch_columns[index] = Arc::new(StructArray::try_new(fields, columns, nulls)?);
+        Ok(RecordBatch::try_new(batch.schema(), batch_columns)?)
+    }
+
+    /// Computes and emits the current result of a bin
+    async fn emit_bin(&mut self, bin: SystemTime, mode: EmitMode, ctx: &mut ArrowContext) {
+        let exec = self.execs.get_mut(&bin).expect("bin should exist");
+        let drained = exec.drain().await;
+        exec.updated = false;
+        exec.fired |= mode != EmitMode::Partial;
+        let finished_batches = if self.emit_mode_index.is_some() {
+            // bins with a trigger may be emitted again, so keep their batches
+            exec.finished_batches.clone()
+        } else {
+            mem::take(&mut exec.finished_batches)
+        };
+
+        if self.emit_mode_index.is_some() && !drained.is_empty() {
+            // these batches won't be drained again at the next checkpoint
+            let watermark = ctx.last_present_watermark();
+            let table = ctx
+                .table_manager
+                .get_expiring_time_key_table("t", watermark)
+                .await
+                .expect("should get table");
+            for batch in drained {
+                let state_batch = Self::add_bin_start_as_timestamp(
+                    &batch,
+                    bin,
+                    self.partial_schema.schema.clone(),
+                )
+                .expect("should be able to add timestamp");
+                table.insert(bin, state_batch);
+            }
+        }
+
+        {
+            let mut batches = self.final_batches_passer.write().unwrap();
+            *batches = finished_batches;
+        }
+        self.finish_execution_plan
+            .reset()
+            .expect("reset execution plan");
+        let mut final_exec = self
+            .finish_execution_plan
+            .execute(0, ctx.task_ctx())
+            .unwrap();
+        let mut aggregate_results = vec![];
+        while let Some(batch) = final_exec.next().await {
+            let batch = batch.expect("should be able to compute batch");
+            let with_timestamp = Self::add_bin_start_as_timestamp(
+                &batch,
+                bin,
+                self.aggregate_with_timestamp_schema.clone(),
+            )
+            .expect("should be able to add timestamp");
+            if self.final_projection.is_some() {
+                aggregate_results.push(with_timestamp);
+            } else {
+                ctx.collect(with_timestamp).await;
+            }
+        }
+        if let Some(final_projection) = self.final_projection.as_ref() {
+            {
+                let mut batches = self.final_batches_passer.write().unwrap();
+                *batches = aggregate_results;
+            }
+            final_projection.reset().expect("reset execution plan");
+            let mut final_projection_exec = final_projection.execute(0, ctx.task_ctx()).unwrap();
+            while let Some(batch) = final_projection_exec.next().await {
+                let batch = batch.expect("should be able to compute batch");
+                let batch = self
+                    .with_emit_mode(batch, mode)
+                    .expect("should be able to set emit mode");
+                ctx.collect(batch).await;
+            }
+        }
+    }
+
+    /// Emits the results of all bins that start before `bin` that haven't been emitted, or that
+    /// have been updated by late records since, and drops the bins that are past their allowed
+    /// lateness
+    async fn close_bins_before(&mut self, bin: SystemTime, ctx: &mut ArrowContext) {
+        let to_emit: Vec<_> = self
+            .execs
+            .range(..bin)
+            .filter(|(_, exec)| !exec.fired || exec.updated)
+            .map(|(bin, exec)| {
+                (
+                    *bin,
+                    if exec.fired {
+                        EmitMode::Late
+                    } else {
+                        EmitMode::Final
+                    },
+                )
+            })
+            .collect();
+
+        for (bin, mode) in to_emit {
+            self.emit_bin(bin, mode, ctx).await;
+        }
+
+        let cutoff = bin.checked_sub(self.allowed_lateness).unwrap_or(UNIX_EPOCH);
+        self.execs = self.execs.split_off(&cutoff);
+    }
+
+    /// Emits partial results for the open bins that have been updated since they were last
+    /// emitted
+    async fn fire_early(&mut self, ctx: &mut ArrowContext) {
+        let to_emit: Vec<_> = self
+            .execs
+            .iter()
+            .filter(|(_, exec)| !exec.fired && exec.updated)
+            .map(|(bin, _)| *bin)
+            .collect();
+
+        for bin in to_emit {
+            self.emit_bin(bin, EmitMode::Partial, ctx).await;
+        }
+    }
 }
 
 pub struct TumblingAggregateWindowConstructor;
@@ -114,6 +318,27 @@ impl OperatorConstructor for TumblingAggregateWindowConstructor {
         registry: Arc<Registry>,
     ) -> anyhow::Result<OperatorNode> {
         let width = Duration::from_micros(config.width_micros);
+        if config.processing_time && width == Duration::ZERO {
+            bail!("processing-time windows must have a non-zero width");
+        }
+        if config.processing_time && config.trigger.is_some() {
+            bail!("processing-time windows don't support triggers");
+        }
+        let early_fire = config
+            .trigger
+            .as_ref()
+            .and_then(|trigger| trigger.early_fire_micros)
+            .map(Duration::from_micros)
+            .filter(|interval| !interval.is_zero());
+        let allowed_lateness = config
+            .trigger
+            .as_ref()
+            .map(|trigger| Duration::from_micros(trigger.allowed_lateness_micros))
+            .unwrap_or_default();
+        let emit_mode_index = config
+            .trigger
+            .as_ref()
+            .map(|trigger| trigger.window_index as usize);
         let input_schema: ArroyoSchema = config
             .input_schema
             .ok_or_else(|| anyhow!("requires input schema"))?
@@ -183,6 +408,10 @@ impl OperatorConstructor for TumblingAggregateWindowConstructor {
         Ok(OperatorNode::from_operator(Box::new(
             TumblingAggregatingWindowFunc {
                 width,
+                processing_time: config.processing_time,
+                early_fire,
+                allowed_lateness,
+                emit_mode_index,
                 binning_function,
                 partial_aggregation_plan,
                 partial_schema,
@@ -204,16 +433,40 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
         "tumbling_window".to_string()
     }
 
+    fn tick_interval(&self) -> Option<Duration> {
+        if self.processing_time {
+            // close processing-time bins promptly after they end
+            Some(self.width.min(Duration::from_secs(1)))
+        } else {
+            self.early_fire
+        }
+    }
+
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
-        let watermark = ctx.last_present_watermark();
+        let watermark = if self.processing_time {
+            None
+        } else {
+            ctx.last_present_watermark()
+        };
         let table = ctx
             .table_manager
             .get_expiring_time_key_table("t", watermark)
             .await
             .expect("should be able to load table");
-        for (timestamp, batch) in table.all_batches_for_watermark(watermark) {
+        let mut restored: Vec<_> = table.all_batches_for_watermark(watermark).collect();
+        if self.processing_time {
+            // every bin but the current one is closed before checkpointing, so only the latest
+            // restored bin can still be open; it's closed on the first tick if it has ended
+            let latest = restored.iter().map(|(t, _)| self.bin_start(**t)).max();
+            restored.retain(|(t, _)| Some(self.bin_start(**t)) == latest);
+        }
+        // bins that had closed before the checkpoint have already emitted their final results
+        let closed_before = watermark.map(|watermark| self.bin_start(watermark));
+        for (timestamp, batch) in restored {
             let bin = self.bin_start(*timestamp);
             let holder = self.execs.entry(bin).or_default();
+            holder.fired = closed_before.is_some_and(|closed_before| bin < closed_before);
+            holder.updated = !holder.fired;
             batch
                 .iter()
                 .for_each(|batch| holder.finished_batches.push(batch.clone()));
@@ -221,12 +474,18 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
     }
 
     async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
-        let bin = self
-            .binning_function
-            .evaluate(&batch)
-            .unwrap()
-            .into_array(batch.num_rows())
-            .unwrap();
+        let bin = if self.processing_time {
+            let now = to_nanos(self.bin_start(ctx.now())) as i64;
+            ScalarValue::TimestampNanosecond(Some(now), None)
+                .to_array_of_size(batch.num_rows())
+                .unwrap()
+        } else {
+            self.binning_function
+                .evaluate(&batch)
+                .unwrap()
+                .into_array(batch.num_rows())
+                .unwrap()
+        };
         let indices = sort_to_indices(bin.as_ref(), None, None).unwrap();
         let columns = batch
             .columns()
@@ -245,9 +504,11 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
         for range in partition.ranges() {
             // the binning function already rounded down to the bin start.
             let bin_start = from_nanos(typed_bin.value(range.start) as u128);
-            let watermark = ctx.last_present_watermark();
+            let watermark = self.current_time(ctx);
 
-            if watermark.is_some() && bin_start < self.bin_start(watermark.unwrap()) {
+            if watermark.is_some()
+                && bin_start + self.allowed_lateness < self.bin_start(watermark.unwrap())
+            {
                 warn!(
                     "bin start {} is before watermark {}, skipping",
                     print_time(bin_start),
@@ -258,6 +519,7 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
 
             let bin_batch = sorted.slice(range.start, range.end - range.start);
             let bin_exec = self.execs.entry(bin_start).or_default();
+            bin_exec.updated = true;
             if bin_exec.active_exec.is_none() {
                 let (unbounded_sender, unbounded_receiver) = unbounded_channel();
                 bin_exec.sender = Some(unbounded_sender);
@@ -268,7 +530,7 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
                 self.partial_aggregation_plan.reset().unwrap();
                 let new_exec = self
                     .partial_aggregation_plan
-                    .execute(0, SessionContext::new().task_ctx())
+                    .execute(0, ctx.task_ctx())
                     .unwrap();
                 let next_batch_future = NextBatchFuture::new(bin_start, new_exec);
                 self.futures.lock().await.push(next_batch_future.clone());
@@ -288,76 +550,33 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
         watermark: Watermark,
         ctx: &mut ArrowContext,
     ) -> Option<Watermark> {
+        if self.processing_time {
+            // our output is timestamped by processing time, so we emit our own watermarks on tick
+            return None;
+        }
+
         if let Some(watermark) = ctx.last_present_watermark() {
-            let bin = self.bin_start(watermark);
-            while !self.execs.is_empty() {
-                let should_pop = {
-                    let Some((first_bin, _exec)) = self.execs.first_key_value() else {
-                        unreachable!("isn't empty")
-                    };
-                    *first_bin < bin
-                };
-                if should_pop {
-                    let Some((popped_bin, mut exec)) = self.execs.pop_first() else {
-                        unreachable!("should have an entry")
-                    };
-                    if let Some(mut active_exec) = exec.active_exec.take() {
-                        exec.sender.take();
-                        while let (_bin, Some((batch, new_exec))) = active_exec.await {
-                            active_exec = new_exec;
-                            let batch = batch.expect("should be able to compute batch");
-                            exec.finished_batches.push(batch);
-                        }
-                    }
-                    {
-                        let mut batches = self.final_batches_passer.write().unwrap();
-                        let finished_batches = mem::take(&mut exec.finished_batches);
-                        *batches = finished_batches;
-                    }
-                    self.finish_execution_plan
-                        .reset()
-                        .expect("reset execution plan");
-                    let mut final_exec = self
-                        .finish_execution_plan
-                        .execute(0, SessionContext::new().task_ctx())
-                        .unwrap();
-                    let mut aggregate_results = vec![];
-                    while let Some(batch) = final_exec.next().await {
-                        let batch = batch.expect("should be able to compute batch");
-                        let with_timestamp = Self::add_bin_start_as_timestamp(
-                            &batch,
-                            popped_bin,
-                            self.aggregate_with_timestamp_schema.clone(),
-                        )
-                        .expect("should be able to add timestamp");
-                        if self.final_projection.is_some() {
-                            aggregate_results.push(with_timestamp);
-                        } else {
-                            ctx.collect(with_timestamp).await;
-                        }
-                    }
-                    if let Some(final_projection) = self.final_projection.as_ref() {
-                        {
-                            let mut batches = self.final_batches_passer.write().unwrap();
-                            *batches = aggregate_results;
-                        }
-                        final_projection.reset().expect("reset execution plan");
-                        let mut final_projection_exec = final_projection
-                            .execute(0, SessionContext::new().task_ctx())
-                            .unwrap();
-                        while let Some(batch) = final_projection_exec.next().await {
-                            let batch = batch.expect("should be able to compute batch");
-                            ctx.collect(batch).await;
-                        }
-                    }
-                } else {
-                    break;
-                }
-            }
+            self.close_bins_before(self.bin_start(watermark), ctx).await;
         }
         Some(watermark)
     }
 
+    async fn handle_tick(&mut self, _: u64, ctx: &mut ArrowContext) {
+        if !self.processing_time {
+            if self.early_fire.is_some() {
+                self.fire_early(ctx).await;
+            }
+            return;
+        }
+
+        let bin = self.bin_start(ctx.now());
+        self.close_bins_before(bin, ctx).await;
+        ctx.broadcast(ArrowMessage::Signal(SignalMessage::Watermark(
+            Watermark::EventTime(bin),
+        )))
+        .await;
+    }
+
     fn future_to_poll(
         &mut self,
     ) -> Option<Pin<Box<dyn Future<Output = Box<dyn Any + Send>> + Send>>> {
@@ -395,12 +614,18 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
     }
 
     async fn handle_checkpoint(&mut self, _b: CheckpointBarrier, ctx: &mut ArrowContext) {
-        let watermark = ctx
-            .watermark()
-            .and_then(|watermark: Watermark| match watermark {
-                Watermark::EventTime(watermark) => Some(watermark),
-                Watermark::Idle => None,
-            });
+        let watermark = if self.processing_time {
+            let bin = self.bin_start(ctx.now());
+            self.close_bins_before(bin, ctx).await;
+            // expire everything before the current bin
+            Some(bin + self.width)
+        } else {
+            ctx.watermark()
+                .and_then(|watermark: Watermark| match watermark {
+                    Watermark::EventTime(watermark) => Some(watermark),
+                    Watermark::Idle => None,
+                })
+        };
         let table = ctx
             .table_manager
             .get_expiring_time_key_table("t", watermark)
@@ -409,13 +634,7 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
 
         // This was a separate map just to the active execs, which could, in corner cases, be much smaller.
         for (bin, exec) in self.execs.iter_mut() {
-            exec.sender.take();
-            let Some(mut active_exec) = exec.active_exec.take() else {
-                continue;
-            };
-            while let (_bin_, Some((batch, next_exec))) = active_exec.await {
-                active_exec = next_exec;
-                let batch = batch.expect("should be able to compute batch");
+            for batch in exec.drain().await {
                 let state_batch = Self::add_bin_start_as_timestamp(
                     &batch,
                     *bin,
@@ -423,7 +642,6 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
                 )
                 .expect("should be able to add timestamp");
                 table.insert(*bin, state_batch);
-                exec.finished_batches.push(batch);
             }
         }
         table.flush(watermark).await.unwrap();
@@ -435,7 +653,7 @@ impl ArrowOperator for TumblingAggregatingWindowFunc<SystemTime> {
             timestamp_table_config(
                 "t",
                 "tumbling_intermediate",
-                self.width,
+                self.width + self.allowed_lateness,
                 false,
                 self.partial_schema.clone(),
             ),
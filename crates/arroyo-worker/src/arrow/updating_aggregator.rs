@@ -16,15 +16,16 @@ use arroyo_operator::{
 use arroyo_rpc::grpc::{api::UpdatingAggregateOperator, TableConfig};
 use arroyo_state::timestamp_table_config;
 use arroyo_types::{CheckpointBarrier, SignalMessage, Watermark};
-use datafusion::{execution::context::SessionContext, physical_plan::ExecutionPlan};
+use datafusion::physical_plan::ExecutionPlan;
 
+use arroyo_datastream::logical::FLUSH_INTERVAL_MS;
 use arroyo_df::physical::{ArroyoPhysicalExtensionCodec, DecodingContext};
 use arroyo_operator::operator::Registry;
 use arroyo_rpc::df::ArroyoSchemaRef;
 use datafusion::common::ScalarValue;
 use datafusion::execution::{
     runtime_env::{RuntimeConfig, RuntimeEnv},
-    SendableRecordBatchStream,
+    SendableRecordBatchStream, TaskContext,
 };
 use datafusion::logical_expr::ColumnarValue;
 use datafusion_proto::{physical_plan::AsExecutionPlan, protobuf::PhysicalPlanNode};
@@ -55,6 +56,7 @@ impl UpdatingAggregatingFunc {
         if self.sender.is_none() {
             return Ok(());
         }
+        let task_ctx = ctx.task_ctx();
         {
             self.sender.take();
         }
@@ -79,8 +81,7 @@ impl UpdatingAggregatingFunc {
                 let (sender, receiver) = unbounded_channel();
                 sender.send(combine_batch)?;
                 self.receiver.write().unwrap().replace(receiver);
-                self.combine_plan
-                    .execute(0, SessionContext::new().task_ctx())?
+                self.combine_plan.execute(0, task_ctx.clone())?
             };
             while let Some(batch) = combine_exec.next().await {
                 let batch = batch?;
@@ -104,8 +105,7 @@ impl UpdatingAggregatingFunc {
             sender.send(final_input_batch)?;
             self.receiver.write().unwrap().replace(receiver);
 
-            self.finish_execution_plan
-                .execute(0, SessionContext::new().task_ctx())?
+            self.finish_execution_plan.execute(0, task_ctx.clone())?
         };
         let final_output_table = ctx
             .table_manager
@@ -147,16 +147,13 @@ impl UpdatingAggregatingFunc {
         Ok(())
     }
 
-    fn init_exec(&mut self) {
+    fn init_exec(&mut self, task_ctx: Arc<TaskContext>) {
         let (sender, receiver) = unbounded_channel();
         {
             let mut internal_receiver = self.receiver.write().unwrap();
             *internal_receiver = Some(receiver);
         }
-        let new_exec = self
-            .partial_aggregation_plan
-            .execute(0, SessionContext::new().task_ctx())
-            .unwrap();
+        let new_exec = self.partial_aggregation_plan.execute(0, task_ctx).unwrap();
         self.exec = Arc::new(Mutex::new(Some(new_exec)));
         self.sender = Some(sender);
     }
@@ -168,9 +165,9 @@ impl ArrowOperator for UpdatingAggregatingFunc {
         "UpdatingAggregatingFunc".to_string()
     }
 
-    async fn process_batch(&mut self, batch: RecordBatch, _ctx: &mut ArrowContext) {
+    async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
         if self.sender.is_none() {
-            self.init_exec();
+            self.init_exec(ctx.task_ctx());
         }
         self.sender.as_ref().unwrap().send(batch).unwrap();
     }
@@ -209,6 +206,13 @@ impl ArrowOperator for UpdatingAggregatingFunc {
         Some(self.flush_interval)
     }
 
+    fn update_parameters(&mut self, parameters: &HashMap<String, f64>) -> anyhow::Result<()> {
+        if let Some(flush_interval) = parameters.get(FLUSH_INTERVAL_MS.name) {
+            self.flush_interval = Duration::from_millis(*flush_interval as u64);
+        }
+        Ok(())
+    }
+
     async fn handle_tick(&mut self, _tick: u64, ctx: &mut ArrowContext) {
         self.flush(ctx).await.unwrap();
     }
@@ -1,5 +1,6 @@
 use arrow::compute::kernels;
 use arrow_array::RecordBatch;
+use arroyo_datastream::logical::IDLE_TIME_MS;
 use arroyo_operator::context::ArrowContext;
 use arroyo_operator::get_timestamp_col;
 use arroyo_operator::operator::{ArrowOperator, OperatorConstructor, OperatorNode, Registry};
@@ -19,9 +20,14 @@ use datafusion_proto::protobuf::PhysicalExprNode;
 use prost::Message;
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{debug, info};
 
+/// How long a watermark-alignment bound from the controller applies for. If the controller stops
+/// sending bounds (for example, because another member of the group has gone idle), the generator
+/// resumes reading rather than waiting indefinitely.
+const ALIGNMENT_BOUND_TTL: Duration = Duration::from_secs(30);
+
 #[derive(Encode, Decode, Copy, Clone, Debug, PartialEq)]
 pub struct WatermarkGeneratorState {
     last_watermark_emitted_at: SystemTime,
@@ -35,6 +41,12 @@ pub struct WatermarkGenerator {
     last_event: SystemTime,
     idle: bool,
     expression: Arc<dyn PhysicalExpr>,
+    /// the maximum watermark allowed by the operator's alignment group, and when it was received
+    alignment_bound: Option<(SystemTime, Instant)>,
+    /// whether the source generates its own watermarks, which are passed through
+    source_watermarks: bool,
+    /// whether the expression yields punctuation rather than a watermark for every row
+    punctuated: bool,
 }
 
 impl WatermarkGenerator {
@@ -53,7 +65,43 @@ impl WatermarkGenerator {
             last_event: SystemTime::now(),
             idle: false,
             expression,
+            alignment_bound: None,
+            source_watermarks: false,
+            punctuated: false,
+        }
+    }
+}
+
+impl WatermarkGenerator {
+    /// Emits the latest punctuation in the batch as the watermark, if it advances it. Rows without
+    /// punctuation (where the expression is null) don't affect the watermark.
+    async fn handle_punctuation(
+        &mut self,
+        punctuation: &arrow::array::TimestampNanosecondArray,
+        ctx: &mut ArrowContext,
+    ) {
+        let Some(watermark) = kernels::aggregate::max(punctuation) else {
+            return;
+        };
+        let watermark = from_nanos(watermark as u128);
+
+        if watermark <= self.state_cache.max_watermark && !self.idle {
+            return;
         }
+
+        self.state_cache.max_watermark = self.state_cache.max_watermark.max(watermark);
+        debug!(
+            "[{}] Emitting punctuated watermark {}",
+            ctx.task_info.task_index,
+            to_millis(self.state_cache.max_watermark)
+        );
+        ctx.collector
+            .broadcast(ArrowMessage::Signal(SignalMessage::Watermark(
+                Watermark::EventTime(self.state_cache.max_watermark),
+            )))
+            .await;
+        self.state_cache.last_watermark_emitted_at = watermark;
+        self.idle = false;
     }
 }
 
@@ -75,13 +123,15 @@ impl OperatorConstructor for WatermarkGeneratorConstructor {
             &DefaultPhysicalExtensionCodec {},
         )?;
 
-        Ok(OperatorNode::from_operator(Box::new(
-            WatermarkGenerator::expression(
-                Duration::from_micros(config.period_micros),
-                config.idle_time_micros.map(Duration::from_micros),
-                expression,
-            ),
-        )))
+        let mut generator = WatermarkGenerator::expression(
+            Duration::from_micros(config.period_micros),
+            config.idle_time_micros.map(Duration::from_micros),
+            expression,
+        );
+        generator.source_watermarks = config.source_watermarks;
+        generator.punctuated = config.punctuated;
+
+        Ok(OperatorNode::from_operator(Box::new(generator)))
     }
 }
 
@@ -99,13 +149,34 @@ impl ArrowOperator for WatermarkGenerator {
         Some(Duration::from_secs(1))
     }
 
+    fn update_parameters(&mut self, parameters: &HashMap<String, f64>) -> anyhow::Result<()> {
+        if let Some(idle_time) = parameters.get(IDLE_TIME_MS.name) {
+            self.idle_time = (*idle_time > 0.0).then(|| Duration::from_millis(*idle_time as u64));
+        }
+        Ok(())
+    }
+
+    fn handle_watermark_alignment(&mut self, max_watermark: SystemTime) {
+        self.alignment_bound = Some((max_watermark, Instant::now()));
+    }
+
+    fn inputs_paused(&self) -> bool {
+        match self.alignment_bound {
+            Some((max_watermark, received_at)) => {
+                received_at.elapsed() < ALIGNMENT_BOUND_TTL
+                    && self.state_cache.max_watermark > max_watermark
+            }
+            None => false,
+        }
+    }
+
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
         let gs = ctx
             .table_manager
             .get_global_keyed_state("s")
             .await
             .expect("should have watermark table.");
-        self.last_event = SystemTime::now();
+        self.last_event = ctx.now();
 
         let state = *(gs
             .get(&ctx.task_info.task_index)
@@ -132,7 +203,13 @@ impl ArrowOperator for WatermarkGenerator {
 
     async fn process_batch(&mut self, record: RecordBatch, ctx: &mut ArrowContext) {
         ctx.collector.collect(record.clone()).await;
-        self.last_event = SystemTime::now();
+        self.last_event = ctx.now();
+
+        if self.source_watermarks {
+            // watermarks (including idleness) come from the source, and are forwarded as they
+            // arrive
+            return;
+        }
 
         let timestamp_column = get_timestamp_col(&record, ctx);
         let Some(max_timestamp) = kernels::aggregate::max(timestamp_column) else {
@@ -153,6 +230,11 @@ impl ArrowOperator for WatermarkGenerator {
             .downcast_ref::<arrow::array::TimestampNanosecondArray>()
             .unwrap();
 
+        if self.punctuated {
+            self.handle_punctuation(watermark, ctx).await;
+            return;
+        }
+
         let watermark = from_nanos(kernels::aggregate::min(watermark).unwrap() as u128);
 
         self.state_cache.max_watermark = self.state_cache.max_watermark.max(watermark);
@@ -188,8 +270,23 @@ impl ArrowOperator for WatermarkGenerator {
     }
 
     async fn handle_tick(&mut self, _: u64, ctx: &mut ArrowContext) {
+        if self.source_watermarks {
+            return;
+        }
+
+        if self.inputs_paused() {
+            // we're not receiving events because we've stopped reading, not because the source is
+            // idle
+            self.last_event = ctx.now();
+            return;
+        }
+
         if let Some(idle_time) = self.idle_time {
-            if self.last_event.elapsed().unwrap_or(Duration::ZERO) > idle_time && !self.idle {
+            let since_last_event = ctx
+                .now()
+                .duration_since(self.last_event)
+                .unwrap_or(Duration::ZERO);
+            if since_last_event > idle_time && !self.idle {
                 info!(
                     "Setting partition {} to idle after {:?}",
                     ctx.task_info.task_index, idle_time
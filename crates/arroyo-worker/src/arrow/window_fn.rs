@@ -14,9 +14,9 @@ use arroyo_rpc::grpc::TableConfig;
 use arroyo_rpc::{df::ArroyoSchemaRef, grpc::api};
 use arroyo_state::timestamp_table_config;
 use arroyo_types::{from_nanos, CheckpointBarrier, Watermark};
-use datafusion::execution::context::SessionContext;
 use datafusion::execution::runtime_env::{RuntimeConfig, RuntimeEnv};
 use datafusion::execution::SendableRecordBatchStream;
+use datafusion::execution::TaskContext;
 use datafusion::physical_plan::ExecutionPlan;
 use datafusion_proto::physical_plan::AsExecutionPlan;
 use datafusion_proto::protobuf::PhysicalPlanNode;
@@ -87,16 +87,13 @@ impl WindowFunctionOperator {
         Ok(batches)
     }
 
-    async fn insert_exec(&mut self, timestamp: SystemTime) {
+    async fn insert_exec(&mut self, timestamp: SystemTime, task_ctx: Arc<TaskContext>) {
         let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
         {
             let mut internal_receiver = self.receiver.write().unwrap();
             *internal_receiver = Some(receiver);
         }
-        let new_exec = self
-            .window_exec
-            .execute(0, SessionContext::new().task_ctx())
-            .unwrap();
+        let new_exec = self.window_exec.execute(0, task_ctx).unwrap();
         let next_batch_future = NextBatchFuture::new(timestamp, new_exec);
         self.futures.lock().await.push(next_batch_future.clone());
         self.execs.insert(
@@ -108,9 +105,13 @@ impl WindowFunctionOperator {
         );
     }
 
-    async fn get_or_insert_exec(&mut self, timestamp: SystemTime) -> &InstantComputeHolder {
+    async fn get_or_insert_exec(
+        &mut self,
+        timestamp: SystemTime,
+        task_ctx: &Arc<TaskContext>,
+    ) -> &InstantComputeHolder {
         if !self.execs.contains_key(&timestamp) {
-            self.insert_exec(timestamp).await;
+            self.insert_exec(timestamp, task_ctx.clone()).await;
         }
         self.execs
             .get(&timestamp)
@@ -124,6 +125,7 @@ impl ArrowOperator for WindowFunctionOperator {
         "WindowFunction".to_string()
     }
     async fn on_start(&mut self, ctx: &mut ArrowContext) {
+        let task_ctx = ctx.task_ctx();
         let watermark = ctx.last_present_watermark();
         let table = ctx
             .table_manager
@@ -131,13 +133,14 @@ impl ArrowOperator for WindowFunctionOperator {
             .await
             .unwrap();
         for (timestamp, batches) in table.all_batches_for_watermark(watermark) {
-            let exec = self.get_or_insert_exec(*timestamp).await;
+            let exec = self.get_or_insert_exec(*timestamp, &task_ctx).await;
             for batch in batches {
                 exec.sender.send(batch.clone()).unwrap();
             }
         }
     }
     async fn process_batch(&mut self, batch: RecordBatch, ctx: &mut ArrowContext) {
+        let task_ctx = ctx.task_ctx();
         let current_watermark = ctx.last_present_watermark();
         let table = ctx
             .table_manager
@@ -149,7 +152,7 @@ impl ArrowOperator for WindowFunctionOperator {
             .unwrap()
         {
             table.insert(timestamp, batch.clone());
-            let bin_exec = self.get_or_insert_exec(timestamp).await;
+            let bin_exec = self.get_or_insert_exec(timestamp, &task_ctx).await;
             bin_exec.sender.send(batch).unwrap();
         }
     }
@@ -0,0 +1,209 @@
+//! Runs a pipeline in-process to measure its throughput and latency, as used by the Nexmark
+//! benchmark.
+//!
+//! The sinks of the pipeline are replaced by blackhole sinks, which record how long after its
+//! event time each batch arrives. Throughput is measured as the rows read by the sources over the
+//! time the pipeline takes to run to completion, so all of its sources must be bounded.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use arroyo_datastream::logical::{LogicalProgram, OperatorName};
+use arroyo_metrics::{MESSAGES_SENT_COUNTER, MESSAGE_RECV_COUNTER, SINK_LATENCY_HISTOGRAM};
+use arroyo_rpc::grpc::api::ConnectorOp;
+use arroyo_rpc::OperatorConfig;
+use prometheus::core::Collector;
+use prometheus::proto::Metric;
+use prost::Message;
+use serde_json::json;
+use tracing::info;
+
+use crate::fixture_test::run_to_completion;
+
+#[derive(Debug, Clone)]
+pub struct BenchmarkResult {
+    /// Rows read by the sources
+    pub events: u64,
+    /// Rows written to the sinks
+    pub outputs: u64,
+    /// Time from starting the pipeline until it finished
+    pub elapsed: Duration,
+    /// Rows read by the sources per second
+    pub throughput: f64,
+    /// Upper bound of the median latency at the sinks, in seconds
+    pub latency_p50: Option<f64>,
+    /// Upper bound of the 99th percentile latency at the sinks, in seconds
+    pub latency_p99: Option<f64>,
+}
+
+/// The metrics of the benchmarked operators. The counters are shared by every pipeline run in
+/// this process, so a run is measured by the difference between snapshots taken before and after.
+#[derive(Debug, Default)]
+struct Snapshot {
+    source_rows: u64,
+    sink_rows: u64,
+    latencies: u64,
+    /// Upper bound of each latency bucket, and the number of latencies at or below it
+    latency_buckets: Vec<(f64, u64)>,
+}
+
+fn operator_id(metric: &Metric) -> Option<&str> {
+    metric
+        .get_label()
+        .iter()
+        .find(|l| l.get_name() == "operator_id")
+        .map(|l| l.get_value())
+}
+
+fn metrics_for(collector: &dyn Collector, operators: &HashSet<String>) -> Vec<Metric> {
+    collector
+        .collect()
+        .into_iter()
+        .flat_map(|family| family.get_metric().to_vec())
+        .filter(|m| operator_id(m).is_some_and(|id| operators.contains(id)))
+        .collect()
+}
+
+impl Snapshot {
+    fn take(sources: &HashSet<String>, sinks: &HashSet<String>) -> Self {
+        let mut snapshot = Self {
+            source_rows: metrics_for(&*MESSAGES_SENT_COUNTER, sources)
+                .iter()
+                .map(|m| m.get_counter().get_value() as u64)
+                .sum(),
+            sink_rows: metrics_for(&*MESSAGE_RECV_COUNTER, sinks)
+                .iter()
+                .map(|m| m.get_counter().get_value() as u64)
+                .sum(),
+            ..Default::default()
+        };
+
+        for metric in metrics_for(&*SINK_LATENCY_HISTOGRAM, sinks) {
+            let histogram = metric.get_histogram();
+            snapshot.latencies += histogram.get_sample_count();
+
+            let buckets = histogram.get_bucket();
+            snapshot.latency_buckets.resize(buckets.len(), (0.0, 0));
+            for (total, bucket) in snapshot.latency_buckets.iter_mut().zip(buckets) {
+                total.0 = bucket.get_upper_bound();
+                total.1 += bucket.get_cumulative_count();
+            }
+        }
+
+        snapshot
+    }
+}
+
+/// The upper bound of the bucket containing the `q`th quantile of the latencies recorded between
+/// the two snapshots, or infinity if it's beyond the largest bucket
+fn latency_quantile(before: &Snapshot, after: &Snapshot, q: f64) -> Option<f64> {
+    let count = after.latencies.saturating_sub(before.latencies);
+    if count == 0 {
+        return None;
+    }
+
+    let rank = ((count as f64 * q).ceil() as u64).max(1);
+    Some(
+        after
+            .latency_buckets
+            .iter()
+            .enumerate()
+            .find(|(i, (_, cumulative))| {
+                let previous = before.latency_buckets.get(*i).map(|b| b.1).unwrap_or(0);
+                cumulative.saturating_sub(previous) >= rank
+            })
+            .map(|(_, (bound, _))| *bound)
+            .unwrap_or(f64::INFINITY),
+    )
+}
+
+fn blackhole_op(description: String) -> Vec<u8> {
+    let config = OperatorConfig {
+        connection: json!({}),
+        table: json!({}),
+        ..Default::default()
+    };
+
+    ConnectorOp {
+        connector: "blackhole".to_string(),
+        config: serde_json::to_string(&config).unwrap(),
+        description,
+    }
+    .encode_to_vec()
+}
+
+/// Runs the program to completion with every operator at the given parallelism, measuring its
+/// throughput and latency
+pub async fn run(
+    mut program: LogicalProgram,
+    parallelism: usize,
+    timeout: Duration,
+) -> anyhow::Result<BenchmarkResult> {
+    let mut sources = HashSet::new();
+    let mut sinks = HashSet::new();
+
+    for node in program.graph.node_weights_mut() {
+        node.parallelism = parallelism;
+
+        match node.operator_name {
+            OperatorName::ConnectorSource => {
+                sources.insert(node.operator_id.clone());
+            }
+            OperatorName::ConnectorSink => {
+                sinks.insert(node.operator_id.clone());
+                node.description = "sink<blackhole>".to_string();
+                node.operator_config = blackhole_op(node.description.clone());
+            }
+            _ => {}
+        }
+    }
+
+    let job_id = format!("benchmark_{:016x}", rand::random::<u64>());
+    info!("Running benchmark {}", job_id);
+
+    let before = Snapshot::take(&sources, &sinks);
+    let start = Instant::now();
+    run_to_completion(program, &job_id, timeout, false).await?;
+    let elapsed = start.elapsed();
+    let after = Snapshot::take(&sources, &sinks);
+
+    let events = after.source_rows.saturating_sub(before.source_rows);
+
+    Ok(BenchmarkResult {
+        events,
+        outputs: after.sink_rows.saturating_sub(before.sink_rows),
+        elapsed,
+        throughput: events as f64 / elapsed.as_secs_f64(),
+        latency_p50: latency_quantile(&before, &after, 0.5),
+        latency_p99: latency_quantile(&before, &after, 0.99),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_quantile() {
+        let before = Snapshot {
+            latencies: 10,
+            latency_buckets: vec![(0.001, 10), (0.002, 10), (0.004, 10)],
+            ..Default::default()
+        };
+
+        // 100 latencies since the first snapshot: 50 under 1ms, 40 under 2ms, 8 under 4ms, and
+        // 2 over
+        let after = Snapshot {
+            latencies: 110,
+            latency_buckets: vec![(0.001, 60), (0.002, 100), (0.004, 108)],
+            ..Default::default()
+        };
+
+        assert_eq!(latency_quantile(&before, &after, 0.5), Some(0.001));
+        assert_eq!(latency_quantile(&before, &after, 0.9), Some(0.002));
+        assert_eq!(latency_quantile(&before, &after, 0.98), Some(0.004));
+        assert_eq!(latency_quantile(&before, &after, 0.99), Some(f64::INFINITY));
+
+        assert_eq!(latency_quantile(&before, &before, 0.5), None);
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Debug, Formatter};
 use std::mem;
+use std::ops::RangeInclusive;
 use std::sync::{Arc, RwLock};
 
 use std::time::SystemTime;
@@ -13,10 +14,14 @@ use futures::StreamExt;
 use tracing::{info, warn};
 
 use crate::arrow::async_udf::AsyncUdfConstructor;
+use crate::arrow::count_aggregating_window::CountAggregatingWindowConstructor;
+use crate::arrow::custom::CustomOperatorConstructor;
 use crate::arrow::instant_join::InstantJoinConstructor;
 use crate::arrow::join_with_expiration::JoinWithExpirationConstructor;
+use crate::arrow::replay_source::ReplaySourceFunc;
 use crate::arrow::session_aggregating_window::SessionAggregatingWindowConstructor;
 use crate::arrow::sliding_aggregating_window::SlidingAggregatingWindowConstructor;
+use crate::arrow::stateful_udf::StatefulUdfConstructor;
 use crate::arrow::tumbling_aggregating_window::TumblingAggregateWindowConstructor;
 use crate::arrow::updating_aggregator::UpdatingAggregatingConstructor;
 use crate::arrow::watermark_generator::WatermarkGeneratorConstructor;
@@ -24,24 +29,31 @@ use crate::arrow::window_fn::WindowFunctionConstructor;
 use crate::arrow::{KeyExecutionConstructor, ValueExecutionConstructor};
 use crate::network_manager::{NetworkManager, Quad, Senders};
 use arroyo_datastream::logical::{
-    LogicalEdge, LogicalEdgeType, LogicalGraph, LogicalNode, OperatorName,
+    EdgePartitioning, LogicalEdge, LogicalEdgeType, LogicalGraph, LogicalNode, OperatorName,
+    ProgramConfig,
 };
 use arroyo_df::physical::new_registry;
-use arroyo_operator::context::{batch_bounded, ArrowContext, BatchReceiver, BatchSender};
+use arroyo_operator::clock::Clock;
+use arroyo_operator::context::{
+    batch_bounded, ArrowContext, BatchReceiver, BatchSender, Exchange, STATE_RESTORE_FAILURE,
+};
 use arroyo_operator::operator::OperatorNode;
 use arroyo_operator::operator::Registry;
+use arroyo_operator::recording::InputRecorder;
 use arroyo_operator::ErasedConstructor;
+use arroyo_rpc::api_types::pipelines::{ErrorCategory, PipelineReplay};
 use arroyo_rpc::config::config;
 use arroyo_rpc::grpc::{api, CheckpointMetadata, TaskAssignment};
-use arroyo_rpc::{ControlMessage, ControlResp};
+use arroyo_rpc::{BatchSettings, ControlMessage, ControlResp};
 use arroyo_state::{BackingStore, StateBackend};
-use arroyo_types::{range_for_server, Key, TaskInfo, WorkerId};
+use arroyo_types::{range_for_server, range_for_server_in_ranges, Key, TaskInfo, WorkerId};
 use arroyo_udf_host::LocalUdf;
 use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::visit::EdgeRef;
 use petgraph::Direction;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::Barrier;
+use tokio::task::AbortHandle;
 
 #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
 pub struct TimerValue<K: Key, T: Decode + Encode + Clone + PartialEq + Eq> {
@@ -57,6 +69,16 @@ pub struct SubtaskNode {
     pub in_schemas: Vec<ArroyoSchema>,
     pub out_schema: Option<ArroyoSchema>,
     pub projection: Option<Vec<usize>>,
+    pub batch_settings: BatchSettings,
+    pub key_range: RangeInclusive<u64>,
+    /// For sources, the rate at which the batches they emit are recorded
+    pub recording_rate: Option<f64>,
+    /// Set for sources that are replaying recorded inputs, which don't restore from the
+    /// checkpoint being replayed
+    pub replaying: bool,
+    /// Snapshot URLs to bootstrap the operator's tables from, by table name, if it's not
+    /// restoring from a checkpoint
+    pub bootstrap: HashMap<String, String>,
     pub node: OperatorNode,
 }
 
@@ -93,6 +115,10 @@ pub struct PhysicalGraphEdge {
     out_logical_idx: usize,
     schema: ArroyoSchema,
     edge: LogicalEdgeType,
+    // set for range-partitioned edges, to their boundaries if they're valid for the target (and
+    // otherwise empty, in which case keys are routed as for hash partitioning)
+    range_boundaries: Option<Vec<u64>>,
+    exchange: Exchange,
     tx: Option<BatchSender>,
     rx: Option<BatchReceiver>,
 }
@@ -107,6 +133,21 @@ impl Debug for PhysicalGraphEdge {
     }
 }
 
+/// The boundaries of the range-partitioned edge that routes keys to the node, directly or through
+/// forward edges, if they're valid for its parallelism
+fn input_range_boundaries(
+    logical: &LogicalGraph,
+    idx: NodeIndex,
+    parallelism: usize,
+) -> Option<&[u64]> {
+    logical
+        .edges_directed(idx, Direction::Incoming)
+        .find_map(|edge| match edge.weight().edge_type {
+            LogicalEdgeType::Forward => input_range_boundaries(logical, edge.source(), parallelism),
+            _ => edge.weight().range_boundaries(parallelism),
+        })
+}
+
 impl SubtaskOrQueueNode {
     pub fn take_subtask(&mut self, job_id: String) -> (SubtaskNode, Receiver<ControlMessage>) {
         let (mut qn, rx) = match self {
@@ -120,7 +161,7 @@ impl SubtaskOrQueueNode {
                         operator_id: sn.id.clone(),
                         task_index: sn.subtask_idx,
                         parallelism: sn.parallelism,
-                        key_range: range_for_server(sn.subtask_idx, sn.parallelism),
+                        key_range: sn.key_range.clone(),
                     },
                     tx,
                 });
@@ -179,7 +220,22 @@ impl Program {
         logical: &DiGraph<LogicalNode, LogicalEdge>,
         udfs: &[LocalUdf],
     ) -> Self {
-        let assignments = logical
+        let mut registry = new_registry();
+        for udf in udfs {
+            registry.add_local_udf(udf);
+        }
+        Self::from_logical(
+            name,
+            logical,
+            &Self::local_assignments(logical),
+            registry,
+            &ProgramConfig::default(),
+        )
+    }
+
+    /// Assigns every subtask of the graph to a single local worker
+    pub fn local_assignments(logical: &LogicalGraph) -> Vec<TaskAssignment> {
+        logical
             .node_weights()
             .flat_map(|weight| {
                 (0..weight.parallelism).map(|index| TaskAssignment {
@@ -189,13 +245,7 @@ impl Program {
                     worker_addr: "".into(),
                 })
             })
-            .collect();
-
-        let mut registry = new_registry();
-        for udf in udfs {
-            registry.add_local_udf(udf);
-        }
-        Self::from_logical(name, logical, &assignments, registry)
+            .collect()
     }
 
     pub fn from_logical(
@@ -203,6 +253,7 @@ impl Program {
         logical: &LogicalGraph,
         assignments: &Vec<TaskAssignment>,
         registry: Registry,
+        program_config: &ProgramConfig,
     ) -> Program {
         let mut physical = DiGraph::new();
 
@@ -235,6 +286,23 @@ impl Program {
                 warn!("no assignments for operator {}", node.operator_id);
                 &node.parallelism
             });
+            let batch_settings = program_config.batching.for_operator(&node.operator_id);
+
+            let is_source = node.operator_name == OperatorName::ConnectorSource;
+            let replay = program_config.replay.as_ref().filter(|_| is_source);
+            let recording_rate = Some(program_config.recording.sample_rate())
+                .filter(|rate| is_source && replay.is_none() && *rate > 0.0);
+
+            let bootstrap: HashMap<_, _> = program_config
+                .bootstrap
+                .iter()
+                .filter(|b| b.operator_id == node.operator_id)
+                .map(|b| (b.table.clone(), b.url.clone()))
+                .collect();
+
+            // keyed state is partitioned the same way as the edge that routes keys to the operator
+            let range_boundaries = input_range_boundaries(logical, idx, parallelism);
+
             for i in 0..parallelism {
                 physical.add_node(SubtaskOrQueueNode::SubtaskNode(SubtaskNode {
                     id: node.operator_id.clone(),
@@ -242,12 +310,26 @@ impl Program {
                     parallelism,
                     in_schemas: in_schemas.clone(),
                     out_schema: out_schema.clone(),
-                    node: construct_operator(
-                        node.operator_name,
-                        node.operator_config.clone(),
-                        registry.clone(),
-                    ),
+                    node: match replay {
+                        Some(replay) => OperatorNode::from_source(Box::new(ReplaySourceFunc::new(
+                            replay.job_id.clone(),
+                            replay.epoch,
+                        ))),
+                        None => construct_operator(
+                            node.operator_name,
+                            node.operator_config.clone(),
+                            registry.clone(),
+                        ),
+                    },
                     projection: projection.clone(),
+                    batch_settings,
+                    recording_rate,
+                    replaying: replay.is_some(),
+                    bootstrap: bootstrap.clone(),
+                    key_range: match range_boundaries {
+                        Some(boundaries) => range_for_server_in_ranges(i, boundaries),
+                        None => range_for_server(i, parallelism),
+                    },
                 }));
             }
         }
@@ -271,6 +353,21 @@ impl Program {
                 .collect();
             assert_ne!(from_nodes.len(), 0, "failed to find to nodes");
 
+            let range_boundaries = match &edge.partitioning {
+                EdgePartitioning::Range { .. } => Some(
+                    edge.range_boundaries(to_nodes.len())
+                        .map(|b| b.to_vec())
+                        .unwrap_or_default(),
+                ),
+                _ => None,
+            };
+
+            let exchange = match &edge.partitioning {
+                EdgePartitioning::Hash | EdgePartitioning::Range { .. } => Exchange::Hash,
+                EdgePartitioning::Rebalance | EdgePartitioning::Rescale => Exchange::RoundRobin,
+                EdgePartitioning::Broadcast => Exchange::Broadcast,
+            };
+
             match edge.edge_type {
                 LogicalEdgeType::Forward => {
                     if from_nodes.len() != to_nodes.len() && !from_nodes.is_empty() {
@@ -284,6 +381,8 @@ impl Program {
                             out_logical_idx: logical_out_node_idx.index(),
                             schema: edge.schema.clone(),
                             edge: edge.edge_type,
+                            range_boundaries: range_boundaries.clone(),
+                            exchange,
                             tx: Some(tx),
                             rx: Some(rx),
                         };
@@ -293,8 +392,11 @@ impl Program {
                 LogicalEdgeType::Shuffle
                 | LogicalEdgeType::LeftJoin
                 | LogicalEdgeType::RightJoin => {
-                    for f in &from_nodes {
-                        for (idx, t) in to_nodes.iter().enumerate() {
+                    for (i, f) in from_nodes.iter().enumerate() {
+                        let targets =
+                            edge.partitioning
+                                .targets(i, from_nodes.len(), to_nodes.len());
+                        for (idx, t) in to_nodes[targets].iter().enumerate() {
                             let (tx, rx) = batch_bounded(queue_size);
                             let edge = PhysicalGraphEdge {
                                 edge_idx: idx,
@@ -302,6 +404,8 @@ impl Program {
                                 out_logical_idx: logical_out_node_idx.index(),
                                 schema: edge.schema.clone(),
                                 edge: edge.edge_type,
+                                range_boundaries: range_boundaries.clone(),
+                                exchange,
                                 tx: Some(tx),
                                 rx: Some(rx),
                             };
@@ -336,19 +440,29 @@ pub struct Engine {
     job_id: String,
     network_manager: NetworkManager,
     assignments: HashMap<(String, usize), TaskAssignment>,
+    /// whether subtasks read processing time from a simulated clock rather than the system clock
+    simulated_time: bool,
 }
 
 pub struct StreamConfig {
     pub restore_epoch: Option<u32>,
+    /// When replaying a pipeline, the checkpoint to start from if not restoring one of this job's
+    pub replay: Option<PipelineReplay>,
 }
 
 pub struct RunningEngine {
     program: Program,
     assignments: HashMap<(String, usize), TaskAssignment>,
     worker_id: WorkerId,
+    task_handles: HashMap<String, Vec<AbortHandle>>,
 }
 
 impl RunningEngine {
+    /// Handles for aborting the local tasks of each operator
+    pub fn task_handles(&self) -> HashMap<String, Vec<AbortHandle>> {
+        self.task_handles.clone()
+    }
+
     pub fn source_controls(&self) -> Vec<Sender<ControlMessage>> {
         let graph = self.program.graph.read().unwrap();
         graph
@@ -433,6 +547,7 @@ impl Engine {
             run_id,
             network_manager,
             assignments,
+            simulated_time: false,
         }
     }
 
@@ -470,10 +585,31 @@ impl Engine {
             run_id: "0".to_string(),
             network_manager: NetworkManager::new(0),
             assignments,
+            simulated_time: false,
         }
     }
 
-    pub async fn start(mut self, config: StreamConfig) -> (RunningEngine, Receiver<ControlResp>) {
+    /// Runs the subtasks with simulated processing time, which follows the event time of the data
+    /// they receive; see [`Clock`]
+    pub fn with_simulated_time(mut self) -> Self {
+        self.simulated_time = true;
+        self
+    }
+
+    pub async fn start(self, config: StreamConfig) -> (RunningEngine, Receiver<ControlResp>) {
+        let (control_tx, control_rx) = channel(128);
+        (
+            self.start_with_control(config, control_tx).await,
+            control_rx,
+        )
+    }
+
+    /// Starts the local tasks of the program, which report their status on `control_tx`
+    pub async fn start_with_control(
+        mut self,
+        config: StreamConfig,
+        control_tx: Sender<ControlResp>,
+    ) -> RunningEngine {
         info!("Starting job {}", self.job_id);
 
         let checkpoint_metadata = if let Some(epoch) = config.restore_epoch {
@@ -485,16 +621,31 @@ impl Engine {
                         panic!("failed to load checkpoint metadata for epoch {}", epoch)
                     }),
             )
+        } else if let Some(replay) = &config.replay {
+            info!(
+                "Replaying job {} from checkpoint {} as job {}",
+                replay.job_id, replay.epoch, self.job_id
+            );
+            Some(
+                StateBackend::load_checkpoint_metadata(&replay.job_id, replay.epoch)
+                    .await
+                    .unwrap_or_else(|_| {
+                        panic!(
+                            "failed to load checkpoint metadata for epoch {} of job {}",
+                            replay.epoch, replay.job_id
+                        )
+                    }),
+            )
         } else {
             None
         };
 
         let node_indexes: Vec<_> = self.program.graph.read().unwrap().node_indices().collect();
 
-        let (control_tx, control_rx) = channel(128);
         let worker_id = self.worker_id;
 
         let mut senders = Senders::new();
+        let mut task_handles: HashMap<String, Vec<AbortHandle>> = HashMap::new();
 
         let ready = Arc::new(Barrier::new(self.local_task_count()));
         {
@@ -509,8 +660,11 @@ impl Engine {
                 ));
             }
 
-            while let Some(result) = futures.next().await {
+            while let Some((result, handle)) = futures.next().await {
                 senders.merge(result);
+                if let Some((operator_id, handle)) = handle {
+                    task_handles.entry(operator_id).or_default().push(handle);
+                }
             }
         }
 
@@ -521,14 +675,12 @@ impl Engine {
             n.tx = None;
         }
 
-        (
-            RunningEngine {
-                program: self.program,
-                assignments: self.assignments,
-                worker_id,
-            },
-            control_rx,
-        )
+        RunningEngine {
+            program: self.program,
+            assignments: self.assignments,
+            worker_id,
+            task_handles,
+        }
     }
 
     async fn schedule_node(
@@ -537,7 +689,7 @@ impl Engine {
         control_tx: &Sender<ControlResp>,
         idx: NodeIndex,
         ready: Arc<Barrier>,
-    ) -> Senders {
+    ) -> (Senders, Option<(String, AbortHandle)>) {
         let (node, control_rx) = self
             .program
             .graph
@@ -561,16 +713,19 @@ impl Engine {
 
         let mut senders = Senders::new();
 
-        if assignment.worker_id == self.worker_id.0 {
-            self.run_locally(
-                checkpoint_metadata,
-                control_tx,
-                idx,
-                node,
-                control_rx,
-                ready,
-            )
-            .await;
+        let handle = if assignment.worker_id == self.worker_id.0 {
+            let operator_id = node.id.clone();
+            let handle = self
+                .run_locally(
+                    checkpoint_metadata,
+                    control_tx,
+                    idx,
+                    node,
+                    control_rx,
+                    ready,
+                )
+                .await;
+            Some((operator_id, handle))
         } else {
             self.connect_to_remote_task(
                 &mut senders,
@@ -580,9 +735,10 @@ impl Engine {
                 assignment,
             )
             .await;
-        }
+            None
+        };
 
-        senders
+        (senders, handle)
     }
 
     async fn connect_to_remote_task(
@@ -659,7 +815,7 @@ impl Engine {
         node: SubtaskNode,
         control_rx: Receiver<ControlMessage>,
         ready: Arc<Barrier>,
-    ) {
+    ) -> AbortHandle {
         info!(
             "[{:?}] Scheduling {}-{}-{} ({}/{})",
             self.worker_id,
@@ -672,6 +828,8 @@ impl Engine {
 
         let mut in_qs_map: BTreeMap<(LogicalEdgeType, usize), Vec<BatchReceiver>> = BTreeMap::new();
         let mut out_qs_map: BTreeMap<usize, BTreeMap<usize, BatchSender>> = BTreeMap::new();
+        let mut out_range_boundaries: BTreeMap<usize, Option<Vec<u64>>> = BTreeMap::new();
+        let mut out_exchanges: BTreeMap<usize, Exchange> = BTreeMap::new();
         let task_info = {
             let mut graph = self.program.graph.write().unwrap();
             for edge in graph.edge_indices() {
@@ -696,6 +854,10 @@ impl Engine {
                 };
 
                 let tx = edge.weight().tx.as_ref().unwrap().clone();
+                out_range_boundaries
+                    .entry(edge.weight().out_logical_idx)
+                    .or_insert_with(|| edge.weight().range_boundaries.clone());
+                out_exchanges.insert(edge.weight().out_logical_idx, edge.weight().exchange);
                 out_qs_map
                     .entry(edge.weight().out_logical_idx)
                     .or_default()
@@ -711,9 +873,17 @@ impl Engine {
         let tables = node.node.tables();
         let in_qs: Vec<_> = in_qs_map.into_values().flatten().collect();
 
-        let ctx = ArrowContext::new(
+        let restore_from = checkpoint_metadata.clone().filter(|_| !node.replaying);
+        let restore_epoch = restore_from.as_ref().map(|m| m.epoch);
+        let bootstrap = if restore_from.is_none() {
+            node.bootstrap
+        } else {
+            HashMap::new()
+        };
+
+        let mut ctx = ArrowContext::new(
             task_info,
-            checkpoint_metadata.clone(),
+            restore_from,
             control_rx,
             control_tx.clone(),
             in_qs.len(),
@@ -725,13 +895,26 @@ impl Engine {
                 .map(|v| v.into_values().collect())
                 .collect(),
             tables,
+            bootstrap,
         )
         .await;
+        ctx.batch_settings = node.batch_settings;
+        if self.simulated_time {
+            ctx.clock = Clock::simulated();
+        }
+        ctx.recorder = node
+            .recording_rate
+            .map(|rate| InputRecorder::new(rate, restore_epoch));
+        ctx.collector
+            .set_range_partitioning(out_range_boundaries.into_values().collect());
+        ctx.collector
+            .set_exchanges(out_exchanges.into_values().collect());
 
         let operator = Box::new(node.node);
         let join_task = tokio::spawn(async move {
             operator.start(ctx, in_qs, ready).await;
         });
+        let abort_handle = join_task.abort_handle();
 
         let send_copy = control_tx.clone();
         tokio::spawn(async move {
@@ -744,16 +927,59 @@ impl Engine {
                 .await
                 .unwrap();
             if let Err(error) = join_task.await {
+                if error.is_cancelled() {
+                    // the task was aborted to restart its region, which isn't a failure
+                    return;
+                }
+
+                let (error, category) = if error.is_panic() {
+                    let payload = error.into_panic();
+                    let message = payload
+                        .downcast_ref::<String>()
+                        .cloned()
+                        .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                        .unwrap_or_else(|| "task panicked".to_string());
+                    let category = panic_category(&message);
+                    (message, category)
+                } else {
+                    (error.to_string(), ErrorCategory::Internal)
+                };
+
                 send_copy
                     .send(ControlResp::TaskFailed {
                         operator_id,
                         task_index,
-                        error: error.to_string(),
+                        error,
+                        category,
                     })
                     .await
                     .ok();
             };
         });
+
+        abort_handle
+    }
+}
+
+/// Categorizes a task failure from its panic message
+fn panic_category(message: &str) -> ErrorCategory {
+    if message.starts_with(STATE_RESTORE_FAILURE) {
+        ErrorCategory::StateRestore
+    } else if message.starts_with("panic in UDF") {
+        ErrorCategory::UserCode
+    } else if [
+        "memory allocation",
+        "out of memory",
+        "Resources exhausted",
+        "No space left on device",
+        "Too many open files",
+    ]
+    .iter()
+    .any(|s| message.contains(s))
+    {
+        ErrorCategory::ResourceExhaustion
+    } else {
+        ErrorCategory::Internal
     }
 }
 
@@ -766,9 +992,12 @@ pub fn construct_operator(
         OperatorName::ArrowValue => Box::new(ValueExecutionConstructor),
         OperatorName::ArrowKey => Box::new(KeyExecutionConstructor),
         OperatorName::AsyncUdf => Box::new(AsyncUdfConstructor),
+        OperatorName::StatefulUdf => Box::new(StatefulUdfConstructor),
+        OperatorName::CustomOperator => Box::new(CustomOperatorConstructor),
         OperatorName::TumblingWindowAggregate => Box::new(TumblingAggregateWindowConstructor),
         OperatorName::SlidingWindowAggregate => Box::new(SlidingAggregatingWindowConstructor),
         OperatorName::SessionWindowAggregate => Box::new(SessionAggregatingWindowConstructor),
+        OperatorName::CountWindowAggregate => Box::new(CountAggregatingWindowConstructor),
         OperatorName::UpdatingAggregate => Box::new(UpdatingAggregatingConstructor),
         OperatorName::ExpressionWatermark => Box::new(WatermarkGeneratorConstructor),
         OperatorName::Join => Box::new(JoinWithExpirationConstructor),
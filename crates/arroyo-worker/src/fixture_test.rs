@@ -0,0 +1,455 @@
+//! Runs a pipeline against fixture data, for testing SQL pipelines without their real sources and
+//! sinks.
+//!
+//! Each source of the pipeline is replaced by a single-file source that reads the fixture rows
+//! for its table, and each sink by a single-file sink that collects what the pipeline writes to
+//! it. Once the sources have been exhausted and the pipeline has finished, the rows collected for
+//! each sink are compared with the expected rows.
+//!
+//! Tests can run with simulated processing time, where each subtask's clock fast-forwards to the
+//! event time of the data it receives (see [`arroyo_operator::clock::Clock`]). Processing-time
+//! windows and idleness then depend only on the fixtures, and windows of hours or days close as
+//! soon as the data passes them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use arroyo_datastream::logical::{LogicalProgram, OperatorName};
+use arroyo_df::physical::new_registry;
+use arroyo_rpc::api_types::pipelines::{PipelineTestResult, SinkTestResult};
+use arroyo_rpc::formats::{Format, JsonFormat};
+use arroyo_rpc::grpc::api::ConnectorOp;
+use arroyo_rpc::{ControlResp, OperatorConfig};
+use prost::Message;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::engine::{Engine, Program, StreamConfig};
+
+/// How long a fixture test may run for before it's failed
+pub const DEFAULT_TEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct FixtureTest {
+    pub program: LogicalProgram,
+    /// The rows read by each source table
+    pub inputs: BTreeMap<String, Vec<Value>>,
+    /// The rows expected to be written to each sink table
+    pub expected: BTreeMap<String, Vec<Value>>,
+    /// Whether rows must be written to each sink in the expected order
+    pub ordered: bool,
+    pub timeout: Duration,
+    /// Whether to run with simulated processing time, which fast-forwards to the event time of
+    /// each row rather than following the system clock
+    pub simulated_time: bool,
+}
+
+/// The table that a source or sink operator reads or writes, from its operator id (which the
+/// planner constructs as `source_{table}_{index}` or `sink_{table}_{index}`)
+fn table_name<'a>(operator_id: &'a str, prefix: &str) -> Option<&'a str> {
+    operator_id
+        .strip_prefix(prefix)
+        .and_then(|s| s.rsplit_once('_'))
+        .map(|(name, _)| name)
+}
+
+/// The JSON format to read or write fixture rows with, keeping the settings of the original
+/// format if it's JSON
+fn fixture_format(config: &OperatorConfig) -> Format {
+    let mut format = match &config.format {
+        Some(Format::Json(json)) => json.clone(),
+        _ => JsonFormat::default(),
+    };
+    format.confluent_schema_registry = false;
+    format.schema_id = None;
+    // upsert sinks write updates, which need to be represented in the output
+    format.debezium |= !config.upsert_key.is_empty();
+    Format::Json(format)
+}
+
+fn single_file_op(
+    config: &OperatorConfig,
+    path: &Path,
+    table_type: &str,
+    description: String,
+) -> Vec<u8> {
+    let config = OperatorConfig {
+        connection: json!({}),
+        table: json!({
+            "path": path.to_string_lossy(),
+            "table_type": table_type,
+            "wait_for_control": false,
+        }),
+        format: Some(fixture_format(config)),
+        bad_data: config.bad_data.clone(),
+        ..Default::default()
+    };
+
+    ConnectorOp {
+        connector: "single_file".to_string(),
+        config: serde_json::to_string(&config).unwrap(),
+        description,
+    }
+    .encode_to_vec()
+}
+
+/// Replaces the sources and sinks of the program with single-file connectors in `dir`, returning
+/// the files that each sink writes to
+fn replace_connectors(
+    program: &mut LogicalProgram,
+    inputs: &BTreeMap<String, Vec<Value>>,
+    dir: &Path,
+) -> anyhow::Result<BTreeMap<String, PathBuf>> {
+    let mut sinks = BTreeMap::new();
+
+    for node in program.graph.node_weights_mut() {
+        // fixtures are read and collected by a single subtask
+        node.parallelism = 1;
+
+        let (prefix, table_type) = match node.operator_name {
+            OperatorName::ConnectorSource => ("source_", "source"),
+            OperatorName::ConnectorSink => ("sink_", "sink"),
+            _ => continue,
+        };
+
+        let name = table_name(&node.operator_id, prefix)
+            .ok_or_else(|| anyhow!("unexpected connector operator {}", node.operator_id))?
+            .to_string();
+
+        let op = ConnectorOp::decode(&node.operator_config[..])?;
+        let config: OperatorConfig = serde_json::from_str(&op.config)
+            .with_context(|| format!("invalid config for {}", node.operator_id))?;
+
+        let path = dir.join(format!("{}-{}.json", table_type, node.operator_id));
+
+        if table_type == "source" {
+            let rows = inputs
+                .get(&name)
+                .ok_or_else(|| anyhow!("no fixture for source table '{}'", name))?;
+            let mut data = String::new();
+            for row in rows {
+                data.push_str(&serde_json::to_string(row)?);
+                data.push('\n');
+            }
+            std::fs::write(&path, data)?;
+        } else if sinks.insert(name.clone(), path.clone()).is_some() {
+            bail!("multiple sinks write to table '{}'", name);
+        }
+
+        node.description = format!("{}<fixture {}>", table_type, name);
+        node.operator_config = single_file_op(&config, &path, table_type, node.description.clone());
+    }
+
+    Ok(sinks)
+}
+
+/// Serializes a value with its object keys in sorted order, so that equal values compare equal
+fn canonical(value: &Value) -> String {
+    fn sort(value: &Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), sort(v)))
+                    .collect::<BTreeMap<_, _>>()
+                    .into_iter()
+                    .collect(),
+            ),
+            Value::Array(values) => Value::Array(values.iter().map(sort).collect()),
+            v => v.clone(),
+        }
+    }
+
+    sort(value).to_string()
+}
+
+fn is_debezium(value: &Value) -> bool {
+    value.get("op").map(|op| op.is_string()).unwrap_or(false)
+}
+
+/// Collapses a stream of debezium changes into the rows that remain after applying them
+fn apply_changes(changes: &[Value]) -> anyhow::Result<Vec<Value>> {
+    let mut rows: Vec<Value> = vec![];
+
+    for change in changes {
+        let before = change.get("before").filter(|v| !v.is_null());
+        let after = change.get("after").filter(|v| !v.is_null());
+
+        if let Some(before) = before {
+            let before = canonical(before);
+            let idx = rows
+                .iter()
+                .position(|r| canonical(r) == before)
+                .ok_or_else(|| anyhow!("retraction of a row that was never written: {}", before))?;
+            rows.remove(idx);
+        }
+
+        if let Some(after) = after {
+            rows.push(after.clone());
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Compares the rows written to a sink with the expected rows
+pub fn compare(
+    name: &str,
+    actual: Vec<Value>,
+    expected: &[Value],
+    ordered: bool,
+) -> SinkTestResult {
+    let mut result = SinkTestResult {
+        name: name.to_string(),
+        passed: false,
+        actual,
+        missing: vec![],
+        unexpected: vec![],
+        error: None,
+    };
+
+    // updating sinks write changes; unless the expected rows are changes too, compare the final
+    // state of the table
+    let rows = if result.actual.first().map(is_debezium).unwrap_or(false)
+        && !expected.first().map(is_debezium).unwrap_or(false)
+    {
+        match apply_changes(&result.actual) {
+            Ok(rows) => rows,
+            Err(e) => {
+                result.error = Some(e.to_string());
+                return result;
+            }
+        }
+    } else {
+        result.actual.clone()
+    };
+
+    let mut remaining: HashMap<String, Vec<&Value>> = HashMap::new();
+    for row in expected {
+        remaining.entry(canonical(row)).or_default().push(row);
+    }
+
+    for row in &rows {
+        match remaining.get_mut(&canonical(row)) {
+            Some(matches) if !matches.is_empty() => {
+                matches.pop();
+            }
+            _ => result.unexpected.push(row.clone()),
+        }
+    }
+
+    result.missing = expected
+        .iter()
+        .filter(|row| {
+            remaining
+                .get_mut(&canonical(row))
+                .and_then(|matches| matches.pop())
+                .is_some()
+        })
+        .cloned()
+        .collect();
+
+    if result.missing.is_empty() && result.unexpected.is_empty() {
+        if ordered
+            && rows
+                .iter()
+                .zip(expected)
+                .any(|(a, e)| canonical(a) != canonical(e))
+        {
+            result.error = Some("rows were written in a different order than expected".to_string());
+        } else {
+            result.passed = true;
+        }
+    }
+
+    result
+}
+
+pub(crate) async fn run_to_completion(
+    program: LogicalProgram,
+    job_id: &str,
+    timeout: Duration,
+    simulated_time: bool,
+) -> anyhow::Result<()> {
+    let mut registry = new_registry();
+    for (udf_name, dylib_config) in &program.program_config.udf_dylibs {
+        registry
+            .load_dylib(udf_name, dylib_config)
+            .await
+            .map_err(|e| e.context(format!("loading UDF {udf_name}")))?;
+    }
+
+    for (udf_name, js_config) in &program.program_config.js_udfs {
+        registry
+            .add_js_udf(udf_name, js_config)
+            .map_err(|e| e.context(format!("loading UDF {udf_name}")))?;
+    }
+
+    for (model_name, model_config) in &program.program_config.models {
+        registry
+            .add_model(model_name, model_config)
+            .await
+            .map_err(|e| e.context(format!("loading model {model_name}")))?;
+    }
+
+    let physical = Program::from_logical(
+        job_id.to_string(),
+        &program.graph,
+        &Program::local_assignments(&program.graph),
+        registry,
+        &program.program_config,
+    );
+
+    let total_nodes = physical.total_nodes();
+    let mut engine = Engine::for_local(physical, job_id.to_string());
+    if simulated_time {
+        engine = engine.with_simulated_time();
+    }
+
+    let (_engine, mut control_rx) = engine
+        .start(StreamConfig {
+            restore_epoch: None,
+            replay: None,
+        })
+        .await;
+
+    let mut finished = 0;
+    tokio::time::timeout(timeout, async {
+        while finished < total_nodes {
+            match control_rx.recv().await {
+                Some(ControlResp::TaskFinished { .. }) => {
+                    finished += 1;
+                }
+                Some(ControlResp::TaskFailed {
+                    operator_id,
+                    task_index,
+                    error,
+                    ..
+                }) => {
+                    bail!("task {}-{} failed: {}", operator_id, task_index, error);
+                }
+                Some(_) => {}
+                None => bail!("pipeline stopped before finishing"),
+            }
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|_| anyhow!("pipeline did not finish within {:?}", timeout))?
+}
+
+/// Runs the test, returning the result for each sink
+pub async fn run(test: FixtureTest) -> anyhow::Result<PipelineTestResult> {
+    let job_id = format!("test_{:016x}", rand::random::<u64>());
+    let dir = std::env::temp_dir().join(&job_id);
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("could not create test directory {}", dir.to_string_lossy()))?;
+
+    let result: anyhow::Result<PipelineTestResult> = async {
+        let mut program = test.program;
+        let sinks = replace_connectors(&mut program, &test.inputs, &dir)?;
+
+        for name in test.expected.keys() {
+            if !sinks.contains_key(name) {
+                bail!(
+                    "expected output for '{}', which is not a sink of the query",
+                    name
+                );
+            }
+        }
+
+        info!("Running fixture test {}", job_id);
+        run_to_completion(program, &job_id, test.timeout, test.simulated_time).await?;
+
+        let mut results = vec![];
+        for (name, path) in sinks {
+            let actual = match std::fs::read_to_string(&path) {
+                Ok(data) => data
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(serde_json::from_str)
+                    .collect::<Result<Vec<Value>, _>>()?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+                Err(e) => return Err(e.into()),
+            };
+
+            results.push(match test.expected.get(&name) {
+                Some(expected) => compare(&name, actual, expected, test.ordered),
+                None => SinkTestResult {
+                    name: name.clone(),
+                    passed: false,
+                    actual,
+                    missing: vec![],
+                    unexpected: vec![],
+                    error: Some(format!("no expected output for sink '{}'", name)),
+                },
+            });
+        }
+
+        Ok(PipelineTestResult {
+            passed: results.iter().all(|r| r.passed),
+            sinks: results,
+        })
+    }
+    .await;
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_name() {
+        assert_eq!(table_name("source_orders_0", "source_"), Some("orders"));
+        assert_eq!(table_name("sink_my_output_3", "sink_"), Some("my_output"));
+        assert_eq!(table_name("value_1", "sink_"), None);
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = vec![json!({"a": 1, "b": "x"}), json!({"a": 2, "b": "y"})];
+
+        let result = compare(
+            "t",
+            vec![json!({"b": "y", "a": 2}), json!({"a": 1, "b": "x"})],
+            &expected,
+            false,
+        );
+        assert!(result.passed);
+
+        let result = compare(
+            "t",
+            vec![json!({"a": 2, "b": "y"}), json!({"a": 1, "b": "x"})],
+            &expected,
+            true,
+        );
+        assert!(!result.passed);
+        assert!(result.error.is_some());
+
+        let result = compare(
+            "t",
+            vec![json!({"a": 1, "b": "x"}), json!({"a": 3, "b": "z"})],
+            &expected,
+            false,
+        );
+        assert!(!result.passed);
+        assert_eq!(result.missing, vec![json!({"a": 2, "b": "y"})]);
+        assert_eq!(result.unexpected, vec![json!({"a": 3, "b": "z"})]);
+    }
+
+    #[test]
+    fn test_compare_updating() {
+        let changes = vec![
+            json!({"before": null, "after": {"k": 1, "count": 1}, "op": "c"}),
+            json!({"before": {"k": 1, "count": 1}, "after": null, "op": "d"}),
+            json!({"before": null, "after": {"k": 1, "count": 2}, "op": "c"}),
+        ];
+
+        let result = compare("t", changes, &[json!({"k": 1, "count": 2})], false);
+        assert!(result.passed, "{:?}", result);
+    }
+}
@@ -3,19 +3,23 @@
 
 use crate::engine::{Engine, Program, StreamConfig, SubtaskNode};
 use crate::network_manager::NetworkManager;
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
 use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::grpc::worker_grpc_server::{WorkerGrpc, WorkerGrpcServer};
 use arroyo_rpc::grpc::{
-    api, CheckpointReq, CheckpointResp, CommitReq, CommitResp, HeartbeatReq, JobFinishedReq,
-    JobFinishedResp, LoadCompactedDataReq, LoadCompactedDataRes, MetricFamily, MetricsReq,
-    MetricsResp, RegisterWorkerReq, StartExecutionReq, StartExecutionResp, StopExecutionReq,
-    StopExecutionResp, TaskCheckpointCompletedReq, TaskCheckpointEventReq, TaskFailedReq,
-    TaskFinishedReq, TaskStartedReq, WorkerErrorReq, WorkerResources,
+    api, AlignWatermarksReq, AlignWatermarksResp, CheckpointReq, CheckpointResp, CommitReq,
+    CommitResp, DrainReq, DrainResp, ErrorCategory, HeartbeatReq, InjectFaultReq, InjectFaultResp,
+    JobFinishedReq, JobFinishedResp, LoadCompactedDataReq, LoadCompactedDataRes, MetricFamily,
+    MetricsReq, MetricsResp, ProfileReq, ProfileResp, ProfileType, RegisterWorkerReq,
+    ReloadUdfsReq, ReloadUdfsResp, RestartRegionReq, RestartRegionResp, StartExecutionReq,
+    StartExecutionResp, StopExecutionReq, StopExecutionResp, StopMode, TaskAssignment,
+    TaskCheckpointCompletedReq, TaskCheckpointEventReq, TaskFailedReq, TaskFinishedReq,
+    TaskStartedReq, UpdateParametersReq, UpdateParametersResp, WorkerDrainingReq, WorkerErrorReq,
+    WorkerResources,
 };
 use arroyo_types::{
-    from_millis, to_micros, CheckpointBarrier, NodeId, WorkerId, ARROYO_PROGRAM_ENV,
+    from_micros, from_millis, to_micros, CheckpointBarrier, NodeId, WorkerId, ARROYO_PROGRAM_ENV,
     ARROYO_PROGRAM_FILE_ENV, JOB_ID_ENV, RUN_ID_ENV,
 };
 use local_ip_address::local_ip;
@@ -28,29 +32,38 @@ use std::env;
 use std::fmt::{Debug, Display, Formatter};
 use std::future::Future;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::net::TcpListener;
 use tokio::select;
-use tokio::sync::mpsc::{Receiver, Sender};
-use tokio_stream::wrappers::TcpListenerStream;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::AbortHandle;
 use tonic::{Request, Response, Status};
 use tracing::{debug, error, info, warn};
 
-use arroyo_rpc::{retry, CompactionResult, ControlMessage, ControlResp};
+use arroyo_rpc::{retry, CompactionResult, Compressed, ControlMessage, ControlResp};
 pub use ordered_float::OrderedFloat;
 use prometheus::{Encoder, ProtobufEncoder};
 use prost::Message;
 
-use arroyo_datastream::logical::{LogicalGraph, LogicalProgram, ProgramConfig};
+use arroyo_datastream::logical::{LogicalGraph, LogicalProgram, OperatorName, ProgramConfig};
 use arroyo_df::physical::new_registry;
+use arroyo_operator::models::{ModelReloader, PreparedModels};
+use arroyo_operator::operator::{PreparedUdfs, Registry, UdfReloader};
 use arroyo_rpc::config::config;
+use arroyo_rpc::fault_injection::{self, FaultSpec};
+use arroyo_rpc::secrets::{resolve_secrets, start_secret_refresher};
+use arroyo_server_common::profiling::{self, CpuProfileFormat};
 use arroyo_server_common::shutdown::ShutdownGuard;
+use arroyo_server_common::tls;
 use arroyo_server_common::wrap_start;
 
 pub mod arrow;
+pub mod benchmark;
 
 pub mod engine;
+pub mod fixture_test;
 mod network_manager;
 
 pub static TIMER_TABLE: char = '[';
@@ -96,7 +109,44 @@ struct EngineState {
     sources: Vec<Sender<ControlMessage>>,
     sinks: Vec<Sender<ControlMessage>>,
     operator_controls: HashMap<String, Vec<Sender<ControlMessage>>>, // operator_id -> vec of control tx
+    // one per registry; restarting a region creates a new registry for its operators
+    reloaders: Vec<Reloaders>,
     shutdown_guard: ShutdownGuard,
+    control_tx: Sender<ControlResp>,
+    assignments: Vec<TaskAssignment>,
+    task_handles: HashMap<String, Vec<AbortHandle>>, // operator_id -> local tasks
+    // the program config as of the last UDF reload, for constructing restarted regions
+    program_config: ProgramConfig,
+    // UDFs and models that have been loaded for a reload, waiting to be swapped in
+    prepared_reload: Option<PreparedReload>,
+    // the number of region restarts, which replace registries that a reload must cover
+    region_restarts: u64,
+}
+
+/// A UDF reload that has been prepared on every registry of the worker, but not yet committed
+struct PreparedReload {
+    program_config: ProgramConfig,
+    udfs: Vec<PreparedUdfs>,
+    models: Vec<PreparedModels>,
+}
+
+/// The reloaders of a registry, along with the operators whose tasks were built from it
+struct Reloaders {
+    operators: HashSet<String>,
+    udfs: UdfReloader,
+    models: ModelReloader,
+}
+
+/// How long the tasks of a region have to stop before they're aborted
+const REGION_STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a drained worker waits after its last task finishes before shutting down, so that the
+/// controller can finish the job's final checkpoint
+const DRAIN_SHUTDOWN_DELAY: Duration = Duration::from_secs(5);
+
+/// Whether a draining worker has finished all of its tasks and can shut down
+fn drained(draining: &AtomicBool, finished_tasks: usize, local_tasks: usize) -> bool {
+    draining.load(Ordering::SeqCst) && finished_tasks >= local_tasks
 }
 
 pub struct LocalRunner {
@@ -115,6 +165,7 @@ impl LocalRunner {
         let (_running_engine, mut control_rx) = engine
             .start(StreamConfig {
                 restore_epoch: None,
+                replay: None,
             })
             .await;
 
@@ -148,6 +199,12 @@ pub struct WorkerServer {
     program_config: ProgramConfig,
     state: Arc<Mutex<Option<EngineState>>>,
     network: Arc<Mutex<Option<NetworkManager>>>,
+    // set once the worker has been asked to drain, after which it won't accept new tasks and exits
+    // once its tasks have finished
+    draining: Arc<AtomicBool>,
+    // tasks being stopped to restart their region, whose finish and failure messages are from
+    // the old tasks and so aren't passed on to the controller
+    stopping_tasks: Arc<Mutex<HashSet<(String, usize)>>>,
     shutdown_guard: ShutdownGuard,
 }
 
@@ -224,6 +281,8 @@ impl WorkerServer {
             program_config: logical.program_config,
             state: Arc::new(Mutex::new(None)),
             network: Arc::new(Mutex::new(None)),
+            draining: Arc::new(AtomicBool::new(false)),
+            stopping_tasks: Arc::new(Mutex::new(HashSet::new())),
             shutdown_guard,
         }
     }
@@ -249,7 +308,9 @@ impl WorkerServer {
 
         info!("Started worker-rpc for {} on {}", self.name, local_addr);
         let mut client = retry!(
-            ControllerGrpcClient::connect(self.controller_addr.clone()).await,
+            tls::connect_grpc(self.controller_addr.clone())
+                .await
+                .map(|channel| ControllerGrpcClient::new(channel).compressed()),
             20,
             Duration::from_millis(100),
             Duration::from_secs(2),
@@ -263,6 +324,8 @@ impl WorkerServer {
 
         *self.network.lock().unwrap() = Some(network);
 
+        start_secret_refresher();
+
         info!(
             "Started worker data for {} on 0.0.0.0:{}",
             self.name, data_port
@@ -281,8 +344,8 @@ impl WorkerServer {
                 "worker",
                 local_addr,
                 arroyo_server_common::grpc_server()
-                    .add_service(WorkerGrpcServer::new(self))
-                    .serve_with_incoming(TcpListenerStream::new(listener)),
+                    .add_service(WorkerGrpcServer::new(self).compressed())
+                    .serve_with_incoming(tls::incoming(listener)),
             ));
 
         // ideally, get a signal when the server is started...
@@ -299,6 +362,7 @@ impl WorkerServer {
                     slots: std::thread::available_parallelism().unwrap().get() as u64,
                 }),
                 slots: config.worker.task_slots as u64,
+                resource_class: config.worker.resource_class.clone(),
             }))
             .await
             .unwrap();
@@ -306,6 +370,68 @@ impl WorkerServer {
         Ok(())
     }
 
+    /// Fetches the secrets referenced by the configs of the pipeline's connectors, so that they
+    /// are available when the connector operators are constructed
+    async fn prefetch_secrets(&self) -> Result<()> {
+        for node in self.logical_graph.node_weights() {
+            if !matches!(
+                node.operator_name,
+                OperatorName::ConnectorSource | OperatorName::ConnectorSink
+            ) {
+                continue;
+            }
+
+            let op = api::ConnectorOp::decode(&node.operator_config[..])?;
+            resolve_secrets(&op.config)
+                .await
+                .with_context(|| format!("for operator {}", node.operator_id))?;
+        }
+
+        Ok(())
+    }
+
+    async fn build_registry(&self, program_config: &ProgramConfig) -> Result<Registry, Status> {
+        let mut registry = new_registry();
+
+        for (udf_name, dylib_config) in &program_config.udf_dylibs {
+            info!("Loading UDF {}", udf_name);
+            registry
+                .load_dylib(udf_name, dylib_config)
+                .await
+                .map_err(|e| {
+                    Status::failed_precondition(
+                        e.context(format!("loading UDF {udf_name}")).to_string(),
+                    )
+                })?;
+        }
+
+        for (udf_name, js_config) in &program_config.js_udfs {
+            info!("Loading JavaScript UDF {}", udf_name);
+            registry.add_js_udf(udf_name, js_config).map_err(|e| {
+                Status::failed_precondition(
+                    e.context(format!("loading UDF {udf_name}")).to_string(),
+                )
+            })?;
+        }
+
+        for (model_name, model_config) in &program_config.models {
+            info!(
+                "Loading model {} (version {})",
+                model_name, model_config.version
+            );
+            registry
+                .add_model(model_name, model_config)
+                .await
+                .map_err(|e| {
+                    Status::failed_precondition(
+                        e.context(format!("loading model {model_name}")).to_string(),
+                    )
+                })?;
+        }
+
+        Ok(registry)
+    }
+
     #[tokio::main]
     pub async fn start(self) -> Result<()> {
         self.start_async().await
@@ -316,14 +442,19 @@ impl WorkerServer {
         mut control_rx: Receiver<ControlResp>,
         worker_id: WorkerId,
         job_id: String,
+        local_tasks: usize,
     ) -> impl Future<Output = Result<()>> {
         let addr = self.controller_addr.clone();
+        let stopping_tasks = self.stopping_tasks.clone();
+        let draining = self.draining.clone();
+        let mut finished_tasks = HashSet::new();
 
         let cancel_token = self.shutdown_guard.token();
 
         async move {
-            let mut controller = ControllerGrpcClient::connect(addr.clone())
+            let mut controller = tls::connect_grpc(addr.clone())
                 .await
+                .map(|channel| ControllerGrpcClient::new(channel).compressed())
                 .expect("Unable to connect to controller");
             let mut tick = tokio::time::interval(Duration::from_secs(5));
             tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
@@ -331,6 +462,11 @@ impl WorkerServer {
                 select! {
                     msg = control_rx.recv() => {
                         let err = match msg {
+                            Some(ControlResp::TaskFinished { operator_id, task_index } | ControlResp::TaskFailed { operator_id, task_index, .. })
+                                if stopping_tasks.lock().unwrap().contains(&(operator_id.clone(), task_index)) => {
+                                debug!(message = "Task stopped for region restart", operator_id, task_index);
+                                None
+                            }
                             Some(ControlResp::CheckpointEvent(c)) => {
                                 controller.task_checkpoint_event(Request::new(
                                     TaskCheckpointEventReq {
@@ -359,7 +495,8 @@ impl WorkerServer {
                             }
                             Some(ControlResp::TaskFinished { operator_id, task_index }) => {
                                 info!(message = "Task finished", operator_id, task_index);
-                                controller.task_finished(Request::new(
+                                finished_tasks.insert((operator_id.clone(), task_index));
+                                let err = controller.task_finished(Request::new(
                                     TaskFinishedReq {
                                         worker_id: worker_id.0,
                                         job_id: job_id.clone(),
@@ -367,9 +504,19 @@ impl WorkerServer {
                                         operator_id: operator_id.to_string(),
                                         operator_subtask: task_index as u64,
                                     }
-                                )).await.err()
+                                )).await.err();
+
+                                if drained(&draining, finished_tasks.len(), local_tasks) {
+                                    info!("[{:?}] Drained all tasks; shutting down", worker_id);
+                                    let token = cancel_token.clone();
+                                    tokio::task::spawn(async move {
+                                        tokio::time::sleep(DRAIN_SHUTDOWN_DELAY).await;
+                                        token.cancel();
+                                    });
+                                }
+                                err
                             }
-                            Some(ControlResp::TaskFailed { operator_id, task_index, error }) => {
+                            Some(ControlResp::TaskFailed { operator_id, task_index, error, category }) => {
                                 controller.task_failed(Request::new(
                                     TaskFailedReq {
                                         worker_id: worker_id.0,
@@ -378,21 +525,26 @@ impl WorkerServer {
                                         operator_id: operator_id.to_string(),
                                         operator_subtask: task_index as u64,
                                         error,
+                                        category: ErrorCategory::from(category) as i32,
                                     }
                                 )).await.err()
                             }
-                            Some(ControlResp::Error { operator_id, task_index, message, details}) => {
+                            Some(ControlResp::Error { operator_id, task_index, message, details, category }) => {
                                 controller.worker_error(Request::new(
                                     WorkerErrorReq {
                                         job_id: job_id.clone(),
                                         operator_id,
                                         task_index: task_index as u32,
                                         message,
-                                        details
+                                        details,
+                                        category: ErrorCategory::from(category) as i32,
                                     }
                                 )).await.err()
                             }
                             Some(ControlResp::TaskStarted {operator_id, task_index, start_time}) => {
+                                // messages from the old tasks of a restarted region always come
+                                // before the new tasks start
+                                stopping_tasks.lock().unwrap().remove(&(operator_id.clone(), task_index));
                                 controller.task_started(Request::new(
                                     TaskStartedReq {
                                         worker_id: worker_id.0,
@@ -432,12 +584,46 @@ impl WorkerServer {
     }
 }
 
+/// Stops a region's tasks by sending an immediate stop to its sources, which flows through the
+/// rest of its tasks like any other stop. Tasks that can't be stopped this way, like those
+/// downstream of a task that's hung, are aborted once the timeout has passed.
+async fn stop_tasks(sources: &[Sender<ControlMessage>], handles: &[AbortHandle]) {
+    for tx in sources {
+        // the control channel of a source that has failed is already closed
+        let _ = tx
+            .send(ControlMessage::Stop {
+                mode: StopMode::Immediate,
+            })
+            .await;
+    }
+
+    let start = Instant::now();
+    while handles.iter().any(|h| !h.is_finished()) {
+        if start.elapsed() > REGION_STOP_TIMEOUT {
+            warn!(
+                "Tasks did not stop within {:?}; aborting them",
+                REGION_STOP_TIMEOUT
+            );
+            for handle in handles {
+                handle.abort();
+            }
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
 #[tonic::async_trait]
 impl WorkerGrpc for WorkerServer {
     async fn start_execution(
         &self,
         request: Request<StartExecutionReq>,
     ) -> Result<Response<StartExecutionResp>, Status> {
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(Status::unavailable("Worker is draining"));
+        }
+
         {
             let state = self.state.lock().unwrap();
 
@@ -449,24 +635,17 @@ impl WorkerGrpc for WorkerServer {
         }
 
         let req = request.into_inner();
-        let mut registry = new_registry();
 
-        for (udf_name, dylib_config) in &self.program_config.udf_dylibs {
-            info!("Loading UDF {}", udf_name);
-            registry
-                .load_dylib(udf_name, dylib_config)
-                .await
-                .map_err(|e| {
-                    Status::failed_precondition(
-                        e.context(format!("loading UDF {udf_name}")).to_string(),
-                    )
-                })?;
-            if dylib_config.is_async {
-                continue;
-            }
-        }
+        self.prefetch_secrets().await.map_err(|e| {
+            Status::failed_precondition(format!("failed to resolve connector secrets: {:?}", e))
+        })?;
 
-        let (engine, control_rx) = {
+        let registry = self.build_registry(&self.program_config).await?;
+        let udf_reloader = registry.udf_reloader();
+        let model_reloader = registry.model_reloader();
+
+        let (control_tx, control_rx) = channel(128);
+        let engine = {
             let network = { self.network.lock().unwrap().take().unwrap() };
 
             let program = Program::from_logical(
@@ -474,6 +653,7 @@ impl WorkerGrpc for WorkerServer {
                 &self.logical_graph,
                 &req.tasks,
                 registry,
+                &self.program_config,
             );
 
             let engine = Engine::new(
@@ -482,18 +662,27 @@ impl WorkerGrpc for WorkerServer {
                 self.job_id.clone(),
                 self.run_id.clone(),
                 network,
-                req.tasks,
+                req.tasks.clone(),
             );
             engine
-                .start(StreamConfig {
-                    restore_epoch: req.restore_epoch,
-                })
+                .start_with_control(
+                    StreamConfig {
+                        restore_epoch: req.restore_epoch,
+                        replay: self.program_config.replay.clone(),
+                    },
+                    control_tx.clone(),
+                )
                 .await
         };
 
         self.shutdown_guard
             .child("control-thread")
-            .into_spawn_task(self.start_control_thread(control_rx, self.id, self.job_id.clone()));
+            .into_spawn_task(self.start_control_thread(
+                control_rx,
+                self.id,
+                self.job_id.clone(),
+                engine.task_handles().values().map(|h| h.len()).sum(),
+            ));
 
         let sources = engine.source_controls();
         let sinks = engine.sink_controls();
@@ -504,7 +693,22 @@ impl WorkerGrpc for WorkerServer {
             sources,
             sinks,
             operator_controls,
+            reloaders: vec![Reloaders {
+                operators: self
+                    .logical_graph
+                    .node_weights()
+                    .map(|n| n.operator_id.clone())
+                    .collect(),
+                udfs: udf_reloader,
+                models: model_reloader,
+            }],
             shutdown_guard: self.shutdown_guard.child("engine-state"),
+            control_tx,
+            assignments: req.tasks,
+            task_handles: engine.task_handles(),
+            program_config: self.program_config.clone(),
+            prepared_reload: None,
+            region_restarts: 0,
         });
 
         info!("[{:?}] Started execution", self.id);
@@ -512,6 +716,147 @@ impl WorkerGrpc for WorkerServer {
         Ok(Response::new(StartExecutionResp {}))
     }
 
+    async fn restart_region(
+        &self,
+        request: Request<RestartRegionReq>,
+    ) -> Result<Response<RestartRegionResp>, Status> {
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(Status::unavailable("Worker is draining"));
+        }
+
+        let req = request.into_inner();
+        let region: HashSet<String> = req.operator_ids.into_iter().collect();
+
+        let (control_tx, assignments, program_config) = {
+            let state = self.state.lock().unwrap();
+            let Some(state) = state.as_ref() else {
+                return Err(Status::failed_precondition(
+                    "Worker has not yet started execution",
+                ));
+            };
+
+            let assignments: Vec<_> = state
+                .assignments
+                .iter()
+                .filter(|a| region.contains(&a.operator_id))
+                .cloned()
+                .collect();
+
+            if assignments.iter().any(|a| a.worker_id != self.id.0) {
+                return Err(Status::invalid_argument(
+                    "Only regions that run entirely on one worker can be restarted",
+                ));
+            }
+
+            (
+                state.control_tx.clone(),
+                assignments,
+                state.program_config.clone(),
+            )
+        };
+
+        info!(
+            "[{:?}] Restarting region {:?} from epoch {:?}",
+            self.id, region, req.restore_epoch
+        );
+
+        let registry = self.build_registry(&program_config).await?;
+        let udf_reloader = registry.udf_reloader();
+        let model_reloader = registry.model_reloader();
+
+        let graph = self.logical_graph.filter_map(
+            |_, node| region.contains(&node.operator_id).then(|| node.clone()),
+            |_, edge| Some(edge.clone()),
+        );
+
+        let program = Program::from_logical(
+            self.name.to_string(),
+            &graph,
+            &assignments,
+            registry,
+            &program_config,
+        );
+
+        let (old_controls, old_sources, old_handles) = {
+            let state = self.state.lock().unwrap();
+            let state = state.as_ref().unwrap();
+
+            let controls: Vec<_> = region
+                .iter()
+                .filter_map(|op| state.operator_controls.get(op))
+                .flatten()
+                .cloned()
+                .collect();
+
+            let sources: Vec<_> = state
+                .sources
+                .iter()
+                .filter(|tx| controls.iter().any(|c| c.same_channel(tx)))
+                .cloned()
+                .collect();
+
+            let handles: Vec<_> = region
+                .iter()
+                .filter_map(|op| state.task_handles.get(op))
+                .flatten()
+                .cloned()
+                .collect();
+
+            (controls, sources, handles)
+        };
+
+        self.stopping_tasks.lock().unwrap().extend(
+            assignments
+                .iter()
+                .map(|a| (a.operator_id.clone(), a.operator_subtask as usize)),
+        );
+
+        stop_tasks(&old_sources, &old_handles).await;
+
+        // all of the region's tasks are local, so it doesn't need any network connections
+        let engine = Engine::new(
+            program,
+            self.id,
+            self.job_id.clone(),
+            self.run_id.clone(),
+            NetworkManager::new(0),
+            assignments,
+        )
+        .start_with_control(
+            StreamConfig {
+                restore_epoch: req.restore_epoch,
+                replay: program_config.replay.clone(),
+            },
+            control_tx,
+        )
+        .await;
+
+        let mut state = self.state.lock().unwrap();
+        let state = state.as_mut().unwrap();
+        let replaced =
+            |tx: &Sender<ControlMessage>| old_controls.iter().any(|o| o.same_channel(tx));
+        state.sources.retain(|tx| !replaced(tx));
+        state.sources.extend(engine.source_controls());
+        state.sinks.retain(|tx| !replaced(tx));
+        state.sinks.extend(engine.sink_controls());
+        state.operator_controls.extend(engine.operator_controls());
+        state.task_handles.extend(engine.task_handles());
+        // registries whose tasks have all been replaced are no longer used
+        state.reloaders.retain(|r| !r.operators.is_subset(&region));
+        state.reloaders.push(Reloaders {
+            operators: region.clone(),
+            udfs: udf_reloader,
+            models: model_reloader,
+        });
+        // a prepared reload doesn't cover the new registry, so it can no longer be committed
+        state.prepared_reload = None;
+        state.region_restarts += 1;
+
+        info!("[{:?}] Restarted region {:?}", self.id, region);
+
+        Ok(Response::new(RestartRegionResp {}))
+    }
+
     async fn checkpoint(
         &self,
         request: Request<CheckpointReq>,
@@ -635,6 +980,75 @@ impl WorkerGrpc for WorkerServer {
         return Ok(Response::new(LoadCompactedDataRes {}));
     }
 
+    async fn align_watermarks(
+        &self,
+        request: Request<AlignWatermarksReq>,
+    ) -> Result<Response<AlignWatermarksResp>, Status> {
+        let req = request.into_inner();
+
+        for (operator_id, max_watermark) in req.max_watermark_micros {
+            // operators that aren't running on this worker have nothing to align
+            let Some(nodes) = ({
+                let state = self.state.lock().unwrap();
+                state
+                    .as_ref()
+                    .and_then(|s| s.operator_controls.get(&operator_id).cloned())
+            }) else {
+                continue;
+            };
+
+            for s in nodes {
+                if let Err(e) = s
+                    .send(ControlMessage::AlignWatermark {
+                        max_watermark: from_micros(max_watermark),
+                    })
+                    .await
+                {
+                    warn!(
+                        "Failed to send AlignWatermark message to operator {}: {}",
+                        operator_id, e
+                    );
+                }
+            }
+        }
+
+        Ok(Response::new(AlignWatermarksResp {}))
+    }
+
+    async fn update_parameters(
+        &self,
+        request: Request<UpdateParametersReq>,
+    ) -> Result<Response<UpdateParametersResp>, Status> {
+        let req = request.into_inner();
+
+        // the operator may not be running on this worker
+        let nodes = {
+            let state = self.state.lock().unwrap();
+            state
+                .as_ref()
+                .and_then(|s| s.operator_controls.get(&req.operator_id).cloned())
+                .unwrap_or_default()
+        };
+
+        let mut subtasks = 0;
+        for s in nodes {
+            match s
+                .send(ControlMessage::UpdateParameters {
+                    parameters: req.parameters.clone(),
+                })
+                .await
+            {
+                Ok(()) => subtasks += 1,
+                Err(e) => warn!(
+                    "Failed to send UpdateParameters message to operator {}: {}",
+                    req.operator_id, e
+                ),
+            }
+        }
+
+        Ok(Response::new(UpdateParametersResp { subtasks }))
+    }
+
     async fn stop_execution(
         &self,
         request: Request<StopExecutionReq>,
@@ -702,4 +1116,201 @@ impl WorkerGrpc for WorkerServer {
 
         Ok(Response::new(MetricsResp { metrics }))
     }
+
+    async fn reload_udfs(
+        &self,
+        request: Request<ReloadUdfsReq>,
+    ) -> Result<Response<ReloadUdfsResp>, Status> {
+        let req = request.into_inner();
+
+        if req.commit {
+            let mut state = self.state.lock().unwrap();
+            let Some(state) = state.as_mut() else {
+                return Err(Status::failed_precondition(
+                    "Worker has not yet started execution",
+                ));
+            };
+            let Some(prepared) = state.prepared_reload.take() else {
+                return Err(Status::failed_precondition(
+                    "No UDF reload has been prepared",
+                ));
+            };
+
+            // each registry holds the same UDFs, so they all reload the same number
+            let reloaded = prepared.udfs.into_iter().map(|p| p.commit()).max();
+            let reloaded_models = prepared.models.into_iter().map(|p| p.commit()).max();
+            state.program_config.udf_dylibs = prepared.program_config.udf_dylibs;
+            state.program_config.models = prepared.program_config.models;
+
+            let (reloaded, reloaded_models) = (reloaded.unwrap_or(0), reloaded_models.unwrap_or(0));
+            info!(
+                "[{:?}] Reloaded {} UDFs and {} models",
+                self.id, reloaded, reloaded_models
+            );
+
+            return Ok(Response::new(ReloadUdfsResp {
+                reloaded: (reloaded + reloaded_models) as u32,
+            }));
+        }
+
+        let program_config: ProgramConfig =
+            api::ArrowProgramConfig::decode(&req.program_config[..])
+                .map_err(|e| Status::invalid_argument(format!("invalid program config: {:?}", e)))?
+                .into();
+
+        let (region_restarts, (reloaders, model_reloaders)): (_, (Vec<_>, Vec<_>)) = {
+            let mut state = self.state.lock().unwrap();
+            let Some(state) = state.as_mut() else {
+                return Err(Status::failed_precondition(
+                    "Worker has not yet started execution",
+                ));
+            };
+            state.prepared_reload = None;
+            (
+                state.region_restarts,
+                state
+                    .reloaders
+                    .iter()
+                    .map(|r| (r.udfs.clone(), r.models.clone()))
+                    .unzip(),
+            )
+        };
+
+        let mut udfs = vec![];
+        for reloader in reloaders {
+            udfs.push(
+                reloader
+                    .prepare(&program_config.udf_dylibs)
+                    .await
+                    .map_err(|e| {
+                        Status::failed_precondition(format!("failed to reload UDFs: {:?}", e))
+                    })?,
+            );
+        }
+
+        let mut models = vec![];
+        for reloader in model_reloaders {
+            models.push(
+                reloader
+                    .prepare(&program_config.models)
+                    .await
+                    .map_err(|e| {
+                        Status::failed_precondition(format!("failed to reload models: {:?}", e))
+                    })?,
+            );
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let Some(state) = state.as_mut() else {
+            return Err(Status::failed_precondition("Worker is no longer running"));
+        };
+        if state.region_restarts != region_restarts {
+            return Err(Status::failed_precondition(
+                "A region was restarted while UDFs were being loaded",
+            ));
+        }
+        state.prepared_reload = Some(PreparedReload {
+            program_config,
+            udfs,
+            models,
+        });
+
+        info!("[{:?}] Prepared UDF reload", self.id);
+
+        Ok(Response::new(ReloadUdfsResp { reloaded: 0 }))
+    }
+
+    async fn profile(&self, request: Request<ProfileReq>) -> Result<Response<ProfileResp>, Status> {
+        let req = request.into_inner();
+        let duration = Duration::from_micros(req.duration_micros);
+
+        let data = match req.profile_type() {
+            ProfileType::CpuFlamegraph => {
+                profiling::cpu_profile(duration, CpuProfileFormat::Flamegraph).await
+            }
+            ProfileType::CpuPprof => {
+                profiling::cpu_profile(duration, CpuProfileFormat::Pprof).await
+            }
+            ProfileType::HeapPprof => profiling::heap_profile().await,
+        }
+        .map_err(|e| Status::failed_precondition(format!("failed to collect profile: {}", e)))?;
+
+        info!(
+            "[{:?}] Collected {:?} profile ({} bytes)",
+            self.id,
+            req.profile_type(),
+            data.len()
+        );
+
+        Ok(Response::new(ProfileResp { data }))
+    }
+
+    async fn inject_fault(
+        &self,
+        request: Request<InjectFaultReq>,
+    ) -> Result<Response<InjectFaultResp>, Status> {
+        match request.into_inner().fault {
+            Some(fault) => {
+                let fault: FaultSpec = fault.into();
+                info!("[{:?}] Injecting fault {:?}", self.id, fault);
+                fault_injection::inject(fault)
+                    .map_err(|e| Status::failed_precondition(e.to_string()))?;
+            }
+            None => {
+                info!("[{:?}] Clearing injected faults", self.id);
+                fault_injection::clear();
+            }
+        }
+
+        Ok(Response::new(InjectFaultResp {}))
+    }
+
+    async fn drain(&self, _request: Request<DrainReq>) -> Result<Response<DrainResp>, Status> {
+        if self.draining.swap(true, Ordering::SeqCst) {
+            return Ok(Response::new(DrainResp {}));
+        }
+
+        info!("[{:?}] Draining worker", self.id);
+
+        // the controller stops the job with a final checkpoint and reschedules it onto new workers;
+        // this one shuts down once all of its tasks have finished
+        let mut controller = tls::connect_grpc(self.controller_addr.clone())
+            .await
+            .map(|channel| ControllerGrpcClient::new(channel).compressed())
+            .map_err(|e| {
+                self.draining.store(false, Ordering::SeqCst);
+                Status::unavailable(format!("failed to connect to controller: {}", e))
+            })?;
+
+        if let Err(e) = controller
+            .worker_draining(Request::new(WorkerDrainingReq {
+                // set by the node that started this worker
+                node_id: config().node.id,
+                worker_id: self.id.0,
+                job_id: self.job_id.clone(),
+            }))
+            .await
+        {
+            self.draining.store(false, Ordering::SeqCst);
+            return Err(e);
+        }
+
+        Ok(Response::new(DrainResp {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drained() {
+        let draining = AtomicBool::new(false);
+        // a worker that isn't draining keeps running when its tasks finish
+        assert!(!drained(&draining, 2, 2));
+
+        draining.store(true, Ordering::SeqCst);
+        assert!(!drained(&draining, 1, 2));
+        assert!(drained(&draining, 2, 2));
+    }
 }
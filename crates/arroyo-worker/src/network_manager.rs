@@ -3,17 +3,27 @@ use anyhow::{anyhow, bail};
 use arrow::buffer::MutableBuffer;
 use arrow::ipc::reader::read_record_batch;
 use arrow::ipc::writer::{DictionaryTracker, EncodedData, IpcDataGenerator, IpcWriteOptions};
-use arrow_array::RecordBatch;
+use arrow::ipc::CompressionType;
+use arrow_array::{Array, RecordBatch};
 use arrow_schema::{ArrowError, SchemaRef};
+use arroyo_rpc::config::{config as arroyo_config, NetworkCompression};
+use arroyo_rpc::fault_injection::{self, FaultKind};
 use arroyo_types::ArrowMessage;
 use bincode::config;
+use lazy_static::lazy_static;
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter_vec, Histogram, HistogramVec,
+    IntCounter, IntCounterVec,
+};
+use std::net::SocketAddr;
+use std::sync::Weak;
 use std::{collections::HashMap, mem::size_of, pin::Pin, sync::Arc, time::Duration};
 use tokio::{
-    io::{self, BufReader, BufWriter},
+    io::{self, AsyncRead, BufReader, BufWriter},
     select,
     sync::Mutex,
 };
-use tracing::warn;
+use tracing::{info, warn};
 
 use bytes::{Buf, BufMut};
 use rand::rngs::StdRng;
@@ -29,6 +39,151 @@ use tokio_stream::StreamExt;
 
 use arroyo_operator::inq_reader::InQReader;
 use arroyo_server_common::shutdown::ShutdownGuard;
+use arroyo_server_common::tls::{self, ClientStream, ServerStream};
+
+const HANDSHAKE_MAGIC: u32 = 0x4152_5931;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref NETWORK_BYTES_SENT: IntCounterVec = register_int_counter_vec!(
+        "arroyo_worker_network_bytes_sent",
+        "Bytes of record batches sent to other workers, after compression",
+        &["edge", "compression"]
+    )
+    .unwrap();
+    static ref NETWORK_BYTES_UNCOMPRESSED: IntCounterVec = register_int_counter_vec!(
+        "arroyo_worker_network_bytes_uncompressed",
+        "Bytes of record batches sent to other workers, before compression",
+        &["edge", "compression"]
+    )
+    .unwrap();
+    static ref NETWORK_COMPRESSION_RATIO: HistogramVec = register_histogram_vec!(
+        "arroyo_worker_network_compression_ratio",
+        "Ratio of uncompressed to compressed size of each batch sent to other workers",
+        &["edge", "compression"],
+        // 1x to ~38x
+        exponential_buckets(1.0, 1.5, 10).unwrap()
+    )
+    .unwrap();
+    /// The inbound sides of the network managers running in this process, by data port
+    static ref IN_PROCESS_MANAGERS: std::sync::Mutex<HashMap<u16, Weak<Mutex<InStreamsOrSenders>>>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
+fn compression_code(compression: NetworkCompression) -> u32 {
+    match compression {
+        NetworkCompression::None => 0,
+        NetworkCompression::Lz4 => 1,
+        NetworkCompression::Zstd => 2,
+    }
+}
+
+fn compression_from_code(code: u32) -> Option<NetworkCompression> {
+    match code {
+        0 => Some(NetworkCompression::None),
+        1 => Some(NetworkCompression::Lz4),
+        2 => Some(NetworkCompression::Zstd),
+        _ => None,
+    }
+}
+
+fn compression_name(compression: NetworkCompression) -> &'static str {
+    match compression {
+        NetworkCompression::None => "none",
+        NetworkCompression::Lz4 => "lz4",
+        NetworkCompression::Zstd => "zstd",
+    }
+}
+
+fn ipc_compression(compression: NetworkCompression) -> Option<CompressionType> {
+    match compression {
+        NetworkCompression::None => None,
+        NetworkCompression::Lz4 => Some(CompressionType::LZ4_FRAME),
+        NetworkCompression::Zstd => Some(CompressionType::ZSTD),
+    }
+}
+
+/// Run by the sending side of a connection before any messages are sent; proposes a compression
+/// codec and returns the one the receiving worker agreed to
+async fn request_compression<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    requested: NetworkCompression,
+) -> anyhow::Result<NetworkCompression> {
+    let mut bytes = [0u8; 8];
+    let mut buf = &mut bytes[..];
+    buf.put_u32_le(HANDSHAKE_MAGIC);
+    buf.put_u32_le(compression_code(requested));
+    stream.write_all(&bytes).await?;
+    stream.flush().await?;
+
+    let code = stream.read_u32_le().await?;
+    compression_from_code(code)
+        .ok_or_else(|| anyhow!("receiver responded with unknown compression codec {}", code))
+}
+
+/// Run by the receiving side of a connection when it's accepted. Batches are decoded according to
+/// the codec recorded in each IPC message, so any codec we know about can be accepted.
+async fn accept_compression<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+) -> anyhow::Result<NetworkCompression> {
+    let magic = stream.read_u32_le().await?;
+    if magic != HANDSHAKE_MAGIC {
+        bail!("invalid handshake from sender: {:#x}", magic);
+    }
+
+    let compression =
+        compression_from_code(stream.read_u32_le().await?).unwrap_or(NetworkCompression::None);
+
+    stream
+        .write_all(&compression_code(compression).to_le_bytes())
+        .await?;
+    stream.flush().await?;
+
+    Ok(compression)
+}
+
+/// The size of the buffers that make up the body of the IPC message for a batch, before
+/// compression
+fn uncompressed_size(batch: &RecordBatch) -> usize {
+    fn data_size(data: &arrow::array::ArrayData) -> usize {
+        data.buffers().iter().map(|b| b.len()).sum::<usize>()
+            + data.nulls().map(|n| n.buffer().len()).unwrap_or(0)
+            + data.child_data().iter().map(data_size).sum::<usize>()
+    }
+
+    batch
+        .columns()
+        .iter()
+        .map(|c| data_size(&c.to_data()))
+        .sum()
+}
+
+struct EdgeMetrics {
+    bytes_sent: IntCounter,
+    bytes_uncompressed: IntCounter,
+    compression_ratio: Histogram,
+}
+
+impl EdgeMetrics {
+    fn new(quad: Quad, compression: NetworkCompression) -> Self {
+        let edge = format!("{}-{}", quad.src_id, quad.dst_id);
+        let labels = [edge.as_str(), compression_name(compression)];
+        Self {
+            bytes_sent: NETWORK_BYTES_SENT.with_label_values(&labels),
+            bytes_uncompressed: NETWORK_BYTES_UNCOMPRESSED.with_label_values(&labels),
+            compression_ratio: NETWORK_COMPRESSION_RATIO.with_label_values(&labels),
+        }
+    }
+
+    fn record(&self, uncompressed: usize, sent: usize) {
+        self.bytes_sent.inc_by(sent as u64);
+        self.bytes_uncompressed.inc_by(uncompressed as u64);
+        if sent > 0 {
+            self.compression_ratio
+                .observe(uncompressed as f64 / sent as f64);
+        }
+    }
+}
 
 #[derive(Clone)]
 struct NetworkSender {
@@ -63,6 +218,10 @@ impl Senders {
     }
 
     async fn send(&mut self, header: Header, data: Vec<u8>) {
+        if header.message_type == MessageType::Data && drop_injected(header.as_quad()) {
+            return;
+        }
+
         let sender = self.senders.get(&header.as_quad()).unwrap();
 
         let message = match header.message_type {
@@ -76,6 +235,10 @@ impl Senders {
             ),
         };
 
+        Self::send_to(sender, message).await;
+    }
+
+    async fn send_to(sender: &NetworkSender, message: ArrowMessage) {
         if let Err(send_error) = sender.tx.send(message).await {
             if !send_error.0.is_end() {
                 panic!("{:?} not sent", send_error.0);
@@ -86,9 +249,14 @@ impl Senders {
     }
 }
 
+/// Whether an injected fault drops a data batch arriving from another worker
+fn drop_injected(quad: Quad) -> bool {
+    fault_injection::check(FaultKind::DropNetworkBatch, None, quad.dst_idx).is_some()
+}
+
 pub struct InNetworkLink {
     _source: String,
-    stream: BufReader<TcpStream>,
+    stream: BufReader<ServerStream>,
     senders: Senders,
 }
 
@@ -162,7 +330,7 @@ impl Header {
 }
 
 impl InNetworkLink {
-    pub fn new(source: String, stream: TcpStream, senders: Senders) -> Self {
+    pub fn new(source: String, stream: ServerStream, senders: Senders) -> Self {
         InNetworkLink {
             _source: source,
             stream: BufReader::new(stream),
@@ -206,25 +374,52 @@ struct NetworkReceiver {
     quad: Quad,
     rx: BatchReceiver,
     dictionary_tracker: Arc<Mutex<DictionaryTracker>>,
+    metrics: Arc<EdgeMetrics>,
 }
 
 struct OutNetworkLink {
     _dest: String,
-    stream: BufWriter<TcpStream>,
+    stream: BufWriter<ClientStream>,
+    compression: NetworkCompression,
     receivers: Vec<NetworkReceiver>,
 }
 
 impl OutNetworkLink {
+    async fn open(dest: &str, requested: NetworkCompression) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(dest).await?;
+        let host = dest.rsplit_once(':').map(|(host, _)| host).unwrap_or(dest);
+        let (stream, compression) = tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+            let mut stream = tls::connect(stream, host).await?;
+            let compression = request_compression(&mut stream, requested).await?;
+            Ok::<_, anyhow::Error>((stream, compression))
+        })
+        .await
+        .map_err(|_| anyhow!("timed out waiting for handshake"))??;
+
+        if compression != requested {
+            info!(
+                "{} declined {} compression, using {}",
+                dest,
+                compression_name(requested),
+                compression_name(compression)
+            );
+        }
+
+        Ok(Self {
+            _dest: dest.to_string(),
+            stream: BufWriter::new(stream),
+            compression,
+            receivers: vec![],
+        })
+    }
+
     pub async fn connect(dest: String) -> Self {
+        let requested = arroyo_config().worker.network_compression;
         let mut rand = StdRng::from_entropy();
         for i in 0..10 {
-            match TcpStream::connect(&dest).await {
-                Ok(stream) => {
-                    return Self {
-                        _dest: dest,
-                        stream: BufWriter::new(stream),
-                        receivers: vec![],
-                    }
+            match Self::open(&dest, requested).await {
+                Ok(link) => {
+                    return link;
                 }
                 Err(e) => {
                     warn!("Failed to connect to {dest}: {:?}", e);
@@ -243,6 +438,7 @@ impl OutNetworkLink {
             quad,
             rx,
             dictionary_tracker: Arc::new(Mutex::new(DictionaryTracker::new(true))),
+            metrics: Arc::new(EdgeMetrics::new(quad, self.compression)),
         });
     }
 
@@ -253,22 +449,25 @@ impl OutNetworkLink {
                 quad,
                 mut rx,
                 dictionary_tracker,
+                metrics,
             } in self.receivers
             {
                 let stream = async_stream::stream! {
                     while let Some(item) = rx.recv().await {
-                        yield (quad, dictionary_tracker.clone(), item);
+                        yield (quad, dictionary_tracker.clone(), metrics.clone(), item);
                     }
                 };
                 sel.push(Box::pin(stream));
             }
             let mut flush_interval: Interval = interval(Duration::from_millis(100));
 
-            let write_options = IpcWriteOptions::default();
+            let write_options = IpcWriteOptions::default()
+                .try_with_compression(ipc_compression(self.compression))
+                .expect("failed to configure IPC compression");
 
             loop {
                 select! {
-                    Some(((quad, dictionary_tracker, msg), s)) = sel.next() => {
+                    Some(((quad, dictionary_tracker, metrics, msg), s)) = sel.next() => {
                         match msg {
                             ArrowMessage::Signal(signal) => {
                                 let data = bincode::encode_to_vec(&signal, config::standard()).unwrap();
@@ -282,7 +481,8 @@ impl OutNetworkLink {
                                     IpcDataGenerator {}.encoded_batch(&data, &mut dictionary_tracker, &write_options)
                                       .expect("failed to encode batch")
                                 };
-                                write_message_and_header(&mut Pin::new(&mut self.stream), quad, encoded_message).await.unwrap();
+                                let sent = write_message_and_header(&mut Pin::new(&mut self.stream), quad, encoded_message).await.unwrap();
+                                metrics.record(uncompressed_size(&data), sent);
                             }
                         };
 
@@ -297,11 +497,52 @@ impl OutNetworkLink {
     }
 }
 
+/// An exchange with a task in another worker running in this process. Batches are moved
+/// directly from the sender's queue to the receiver's, without being serialized.
+struct InProcessLink {
+    quad: Quad,
+    rx: BatchReceiver,
+}
+
+impl InProcessLink {
+    fn start(mut self, senders: Senders) {
+        tokio::spawn(async move {
+            let sender = senders
+                .senders
+                .get(&self.quad)
+                .unwrap_or_else(|| panic!("no sender for in-process link {:?}", self.quad))
+                .clone();
+
+            while let Some(message) = self.rx.recv().await {
+                if matches!(message, ArrowMessage::Data(_)) && drop_injected(self.quad) {
+                    continue;
+                }
+                Senders::send_to(&sender, message).await;
+            }
+        });
+    }
+}
+
 enum InStreamsOrSenders {
-    InStreams(Vec<TcpStream>),
+    InStreams(Vec<ServerStream>, Vec<InProcessLink>),
     Senders(Senders),
 }
 
+/// Finds the network manager listening on `addr` if it's running in this process
+fn in_process_manager(addr: &str) -> Option<Arc<Mutex<InStreamsOrSenders>>> {
+    let addr: SocketAddr = addr.parse().ok()?;
+    let ip = addr.ip();
+    if !(ip.is_loopback() || ip.is_unspecified() || local_ip_address::local_ip().ok() == Some(ip)) {
+        return None;
+    }
+
+    IN_PROCESS_MANAGERS
+        .lock()
+        .unwrap()
+        .get(&addr.port())
+        .and_then(|m| m.upgrade())
+}
+
 pub struct NetworkManager {
     port: u16,
     in_streams: Arc<Mutex<InStreamsOrSenders>>,
@@ -312,7 +553,7 @@ impl NetworkManager {
     pub fn new(port: u16) -> Self {
         NetworkManager {
             port,
-            in_streams: Arc::new(Mutex::new(InStreamsOrSenders::InStreams(vec![]))),
+            in_streams: Arc::new(Mutex::new(InStreamsOrSenders::InStreams(vec![], vec![]))),
             out_streams: Arc::new(Mutex::new(HashMap::new())),
         }
     }
@@ -324,27 +565,51 @@ impl NetworkManager {
             .unwrap();
         let port = listener.local_addr().unwrap().port();
 
+        {
+            let mut managers = IN_PROCESS_MANAGERS.lock().unwrap();
+            managers.retain(|_, m| m.strong_count() > 0);
+            managers.insert(port, Arc::downgrade(&self.in_streams));
+        }
+
         let streams = Arc::clone(&self.in_streams);
         shutdown_guard.into_spawn_task(async move {
             loop {
-                let (stream, _) = listener.accept().await?;
-
-                let mut s = streams.lock().await;
-
-                match &mut *s {
-                    InStreamsOrSenders::InStreams(streams) => streams.push(stream),
-                    InStreamsOrSenders::Senders(ref senders) => {
-                        let senders = senders.clone();
-                        tokio::spawn(async move {
+                let (stream, addr) = listener.accept().await?;
+
+                let streams = Arc::clone(&streams);
+                tokio::spawn(async move {
+                    let handshake = async {
+                        let mut stream = tls::accept(stream).await?;
+                        accept_compression(&mut stream).await?;
+                        Ok::<_, anyhow::Error>(stream)
+                    };
+
+                    let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                        Ok(Ok(stream)) => stream,
+                        Ok(Err(e)) => {
+                            warn!("Handshake with {} failed: {:?}", addr, e);
+                            return;
+                        }
+                        Err(_) => {
+                            warn!("Timed out waiting for handshake from {}", addr);
+                            return;
+                        }
+                    };
+
+                    let mut s = streams.lock().await;
+
+                    match &mut *s {
+                        InStreamsOrSenders::InStreams(streams, _) => streams.push(stream),
+                        InStreamsOrSenders::Senders(ref senders) => {
                             InNetworkLink::new(
-                                stream.local_addr().unwrap().to_string(),
+                                stream.get_ref().local_addr().unwrap().to_string(),
                                 stream,
-                                senders,
+                                senders.clone(),
                             )
                             .start();
-                        });
+                        }
                     }
-                }
+                });
             }
             #[allow(unreachable_code)]
             Ok(())
@@ -357,14 +622,22 @@ impl NetworkManager {
         let mut sockets = self.in_streams.lock().await;
 
         match &mut *sockets {
-            InStreamsOrSenders::InStreams(ref mut in_streams) => {
+            InStreamsOrSenders::InStreams(ref mut in_streams, ref mut in_process_links) => {
                 for s in in_streams.drain(..) {
                     let senders = senders.clone();
                     tokio::spawn(async move {
-                        InNetworkLink::new(s.local_addr().unwrap().to_string(), s, senders.clone())
-                            .start();
+                        InNetworkLink::new(
+                            s.get_ref().local_addr().unwrap().to_string(),
+                            s,
+                            senders.clone(),
+                        )
+                        .start();
                     });
                 }
+
+                for link in in_process_links.drain(..) {
+                    link.start(senders.clone());
+                }
             }
             InStreamsOrSenders::Senders(_) => {
                 panic!("already started!");
@@ -380,6 +653,16 @@ impl NetworkManager {
     }
 
     pub async fn connect(&self, addr: String, quad: Quad, rx: BatchReceiver) {
+        if let Some(manager) = in_process_manager(&addr) {
+            info!("Exchanging batches for {:?} with {} in-process", quad, addr);
+            let link = InProcessLink { quad, rx };
+            match &mut *manager.lock().await {
+                InStreamsOrSenders::InStreams(_, links) => links.push(link),
+                InStreamsOrSenders::Senders(senders) => link.start(senders.clone()),
+            }
+            return;
+        }
+
         let link = OutNetworkLink::connect(addr.clone()).await;
         let mut ins = self.out_streams.lock().await;
         if let std::collections::hash_map::Entry::Vacant(e) = ins.entry(quad) {
@@ -399,12 +682,13 @@ fn pad_to_8(len: u32) -> usize {
     (((len + 7) & !7) - len) as usize
 }
 
-// Async-ified and modified version of arrow::ipc::writer::write_message
+// Async-ified and modified version of arrow::ipc::writer::write_message; returns the number of
+// bytes written, excluding the header
 pub async fn write_message_and_header<W: AsyncWrite + AsyncWriteExt>(
     writer: &mut Pin<&mut W>,
     quad: Quad,
     encoded: EncodedData,
-) -> Result<(), ArrowError> {
+) -> Result<usize, ArrowError> {
     let arrow_data_len = encoded.arrow_data.len();
     if arrow_data_len % 8 != 0 {
         return Err(ArrowError::MemoryError(
@@ -452,7 +736,7 @@ pub async fn write_message_and_header<W: AsyncWrite + AsyncWriteExt>(
         bytes_written, total_size
     );
 
-    Ok(())
+    Ok(bytes_written)
 }
 
 fn read_message(schema: SchemaRef, data: Vec<u8>) -> anyhow::Result<RecordBatch> {
@@ -490,7 +774,7 @@ fn read_message(schema: SchemaRef, data: Vec<u8>) -> anyhow::Result<RecordBatch>
 
 #[cfg(test)]
 mod test {
-    use arrow_array::{ArrayRef, RecordBatch, TimestampNanosecondArray, UInt64Array};
+    use arrow_array::{Array, ArrayRef, RecordBatch, TimestampNanosecondArray, UInt64Array};
     use arrow_schema::{Field, Schema, TimeUnit};
     use std::sync::Arc;
     use std::time::SystemTime;
@@ -503,7 +787,12 @@ mod test {
 
     use crate::network_manager::{MessageType, Quad};
 
-    use super::{Header, NetworkManager, Senders};
+    use super::{
+        accept_compression, ipc_compression, read_message, request_compression,
+        write_message_and_header, Header, NetworkManager, Senders,
+    };
+    use arrow::ipc::writer::{DictionaryTracker, IpcDataGenerator, IpcWriteOptions};
+    use arroyo_rpc::config::NetworkCompression;
 
     #[tokio::test]
     async fn test_header_serdes() {
@@ -525,6 +814,120 @@ mod test {
         assert_eq!(header, h2);
     }
 
+    #[tokio::test]
+    async fn test_compression_handshake() {
+        for requested in [
+            NetworkCompression::None,
+            NetworkCompression::Lz4,
+            NetworkCompression::Zstd,
+        ] {
+            let (mut client, mut server) = tokio::io::duplex(64);
+            let (requested_result, accepted) = tokio::join!(
+                request_compression(&mut client, requested),
+                accept_compression(&mut server)
+            );
+
+            assert_eq!(requested_result.unwrap(), requested);
+            assert_eq!(accepted.unwrap(), requested);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compressed_batches() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "id",
+            arrow_schema::DataType::UInt64,
+            false,
+        )]));
+        let columns: Vec<ArrayRef> = vec![Arc::new(UInt64Array::from(vec![7; 1000]))];
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+
+        let quad = Quad {
+            src_id: 1,
+            src_idx: 0,
+            dst_id: 2,
+            dst_idx: 0,
+        };
+
+        for compression in [NetworkCompression::Lz4, NetworkCompression::Zstd] {
+            let options = IpcWriteOptions::default()
+                .try_with_compression(ipc_compression(compression))
+                .unwrap();
+            let (_, encoded) = IpcDataGenerator {}
+                .encoded_batch(&batch, &mut DictionaryTracker::new(true), &options)
+                .unwrap();
+
+            let mut buffer = vec![];
+            let written = write_message_and_header(&mut Pin::new(&mut buffer), quad, encoded)
+                .await
+                .unwrap();
+
+            let header = Header::from_bytes(&buffer[..]);
+            assert_eq!(header.len, written);
+            assert!(written < 8000, "batch was not compressed");
+
+            let data = buffer[std::mem::size_of::<Header>()..].to_vec();
+            assert_eq!(read_message(schema.clone(), data).unwrap(), batch);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_process_exchange() {
+        let quad = Quad {
+            src_id: 3,
+            src_idx: 0,
+            dst_id: 4,
+            dst_idx: 1,
+        };
+
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "id",
+            arrow_schema::DataType::UInt64,
+            false,
+        )]));
+        let columns: Vec<ArrayRef> = vec![Arc::new(UInt64Array::from(vec![1, 2, 3]))];
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+
+        let shutdown = Shutdown::new("test");
+        let mut receiving = NetworkManager::new(0);
+        let port = receiving.open_listener(shutdown.guard("receiving")).await;
+
+        let (server_tx, mut server_rx) = batch_bounded(10);
+        let mut senders = Senders::new();
+        senders.add(quad, schema, server_tx);
+
+        let mut sending = NetworkManager::new(0);
+        sending.open_listener(shutdown.guard("sending")).await;
+
+        let (client_tx, client_rx) = batch_bounded(10);
+        sending
+            .connect(format!("127.0.0.1:{}", port), quad, client_rx)
+            .await;
+
+        sending.start(Senders::new()).await;
+        receiving.start(senders).await;
+
+        client_tx
+            .send(ArrowMessage::Data(batch.clone()))
+            .await
+            .unwrap();
+
+        let result = timeout(Duration::from_secs(1), server_rx.recv())
+            .await
+            .unwrap()
+            .expect("timed out");
+
+        let ArrowMessage::Data(result) = result else {
+            panic!("expected data");
+        };
+
+        // the batch should have been moved, not copied
+        assert_eq!(
+            result.column(0).to_data().buffers()[0].as_ptr(),
+            batch.column(0).to_data().buffers()[0].as_ptr()
+        );
+    }
+
     #[tokio::test]
     async fn test_client_server() {
         let (server_tx, mut server_rx) = batch_bounded(10);
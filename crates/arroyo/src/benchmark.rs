@@ -0,0 +1,203 @@
+//! The Nexmark benchmark, which runs the standard Nexmark queries in-process against the Nexmark
+//! source and reports the throughput and latency of each.
+//!
+//! The queries read from `person`, `auction`, and `bid` views over a bounded Nexmark source; the
+//! ones that use features Arroyo doesn't support (like over windows and bounded tables) are
+//! adapted as described in their comments.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_df::{ArroyoSchemaProvider, SqlConfig};
+use arroyo_worker::benchmark::{self, BenchmarkResult};
+use serde_json::json;
+
+pub const QUERIES: [(&str, &str); 20] = [
+    ("q1", include_str!("nexmark/q1.sql")),
+    ("q2", include_str!("nexmark/q2.sql")),
+    ("q3", include_str!("nexmark/q3.sql")),
+    ("q4", include_str!("nexmark/q4.sql")),
+    ("q5", include_str!("nexmark/q5.sql")),
+    ("q6", include_str!("nexmark/q6.sql")),
+    ("q7", include_str!("nexmark/q7.sql")),
+    ("q8", include_str!("nexmark/q8.sql")),
+    ("q9", include_str!("nexmark/q9.sql")),
+    ("q10", include_str!("nexmark/q10.sql")),
+    ("q11", include_str!("nexmark/q11.sql")),
+    ("q12", include_str!("nexmark/q12.sql")),
+    ("q13", include_str!("nexmark/q13.sql")),
+    ("q14", include_str!("nexmark/q14.sql")),
+    ("q15", include_str!("nexmark/q15.sql")),
+    ("q16", include_str!("nexmark/q16.sql")),
+    ("q17", include_str!("nexmark/q17.sql")),
+    ("q18", include_str!("nexmark/q18.sql")),
+    ("q19", include_str!("nexmark/q19.sql")),
+    ("q20", include_str!("nexmark/q20.sql")),
+];
+
+#[derive(clap::Args)]
+pub struct BenchmarkArgs {
+    /// Queries to run, like `q5`; all are run if none are given
+    queries: Vec<String>,
+
+    /// Events per second generated by the Nexmark source. To measure the maximum throughput,
+    /// this should be more than the pipeline can process.
+    #[arg(long, default_value_t = 1_000_000)]
+    event_rate: u64,
+
+    /// Seconds of events to generate for each query, at the event rate
+    #[arg(long, default_value_t = 10)]
+    runtime: u64,
+
+    /// Parallelism of every operator
+    #[arg(long, default_value_t = 1)]
+    parallelism: usize,
+
+    /// How many seconds to wait for each query to finish
+    #[arg(long, default_value_t = 600)]
+    timeout: u64,
+
+    /// Print the results as newline-delimited JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// The source table and the views of each kind of event that the queries read from
+fn prelude(event_rate: u64, runtime: u64) -> String {
+    format!(
+        "CREATE TABLE nexmark WITH (
+    connector = 'nexmark',
+    event_rate = '{event_rate}',
+    runtime = '{runtime}'
+);
+
+CREATE VIEW person AS
+SELECT person.id AS id, person.name AS name, person.email_address AS email_address,
+    person.credit_card AS credit_card, person.city AS city, person.state AS state,
+    person.datetime AS datetime, person.extra AS extra
+FROM nexmark WHERE person IS NOT NULL;
+
+CREATE VIEW auction AS
+SELECT auction.id AS id, auction.item_name AS item_name, auction.description AS description,
+    auction.initial_bid AS initial_bid, auction.reserve AS reserve,
+    auction.datetime AS datetime, auction.expires AS expires, auction.seller AS seller,
+    auction.category AS category, auction.extra AS extra
+FROM nexmark WHERE auction IS NOT NULL;
+
+CREATE VIEW bid AS
+SELECT bid.auction AS auction, bid.bidder AS bidder, bid.price AS price,
+    bid.channel AS channel, bid.url AS url, bid.datetime AS datetime, bid.extra AS extra
+FROM nexmark WHERE bid IS NOT NULL;
+
+"
+    )
+}
+
+fn format_latency(latency: Option<f64>) -> String {
+    match latency {
+        None => "-".to_string(),
+        Some(l) if l.is_infinite() => "> 33s".to_string(),
+        Some(l) if l < 1.0 => format!("<= {}ms", (l * 1000.0).round()),
+        Some(l) => format!("<= {:.1}s", l),
+    }
+}
+
+fn print_result(name: &str, result: &BenchmarkResult, as_json: bool) {
+    if as_json {
+        println!(
+            "{}",
+            json!({
+                "query": name,
+                "events": result.events,
+                "outputs": result.outputs,
+                "elapsedSecs": result.elapsed.as_secs_f64(),
+                "eventsPerSec": result.throughput,
+                "latencyP50Secs": result.latency_p50.filter(|l| l.is_finite()),
+                "latencyP99Secs": result.latency_p99.filter(|l| l.is_finite()),
+            })
+        );
+    } else {
+        println!(
+            "{:<6}{:>14}{:>12}{:>10.1}{:>16.0}{:>14}{:>14}",
+            name,
+            result.events,
+            result.outputs,
+            result.elapsed.as_secs_f64(),
+            result.throughput,
+            format_latency(result.latency_p50),
+            format_latency(result.latency_p99),
+        );
+    }
+}
+
+pub async fn run_benchmark(args: &BenchmarkArgs) -> anyhow::Result<()> {
+    let queries: Vec<_> = if args.queries.is_empty() {
+        QUERIES.to_vec()
+    } else {
+        args.queries
+            .iter()
+            .map(|name| {
+                QUERIES
+                    .iter()
+                    .find(|(q, _)| q.eq_ignore_ascii_case(name))
+                    .copied()
+                    .ok_or_else(|| anyhow!("unknown query '{}'; queries are q1 to q20", name))
+            })
+            .collect::<anyhow::Result<_>>()?
+    };
+
+    if args.parallelism == 0 {
+        bail!("parallelism must be at least 1");
+    }
+
+    if !args.json {
+        println!(
+            "{:<6}{:>14}{:>12}{:>10}{:>16}{:>14}{:>14}",
+            "query", "events", "outputs", "secs", "events/sec", "p50 latency", "p99 latency"
+        );
+    }
+
+    for (name, query) in queries {
+        let compiled = arroyo_df::parse_and_get_arrow_program(
+            format!("{}{}", prelude(args.event_rate, args.runtime), query),
+            ArroyoSchemaProvider::new(),
+            SqlConfig {
+                default_parallelism: args.parallelism,
+            },
+        )
+        .await
+        .map_err(|e| anyhow!("failed to plan {}: {}", name, e))?;
+
+        let result = benchmark::run(
+            compiled.program,
+            args.parallelism,
+            Duration::from_secs(args.timeout),
+        )
+        .await
+        .map_err(|e| e.context(format!("failed to run {}", name)))?;
+
+        print_result(name, &result, args.json);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queries_plan() {
+        for (name, query) in QUERIES {
+            if let Err(e) = arroyo_df::parse_and_get_arrow_program(
+                format!("{}{}", prelude(100, 1), query),
+                ArroyoSchemaProvider::new(),
+                SqlConfig::default(),
+            )
+            .await
+            {
+                panic!("failed to plan {}: {}", name, e);
+            }
+        }
+    }
+}
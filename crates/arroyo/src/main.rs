@@ -1,15 +1,18 @@
 use anyhow::{anyhow, bail};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use arroyo_df::{ArroyoSchemaProvider, SqlConfig};
 use arroyo_rpc::config;
 use arroyo_rpc::config::{config, DatabaseType};
 use arroyo_server_common::shutdown::Shutdown;
 use arroyo_server_common::{log_event, start_admin_server};
+use arroyo_worker::fixture_test::{self, FixtureTest};
 use arroyo_worker::WorkerServer;
 use clap::{Parser, Subcommand};
 use cornucopia_async::DatabaseSource;
-use deadpool_postgres::{ManagerConfig, Pool, RecyclingMethod};
+use deadpool_postgres::Pool;
 use serde_json::json;
 use std::process::exit;
 use std::sync::Arc;
@@ -20,6 +23,12 @@ use tokio_postgres::{Client, Connection, NoTls};
 use tracing::{debug, error, info};
 use uuid::Uuid;
 
+use crate::benchmark::BenchmarkArgs;
+use crate::remote::{ApiArgs, PipelineCommand};
+
+mod benchmark;
+mod remote;
+
 #[derive(Parser)]
 #[command(version, about)]
 struct Cli {
@@ -61,6 +70,107 @@ enum Commands {
         #[arg(long)]
         wait: Option<u32>,
     },
+
+    /// Runs a SQL pipeline against fixture data, and checks its output against expected results
+    Test {
+        /// Path to a file containing the SQL query to test
+        query: PathBuf,
+
+        /// Directory containing the fixtures; each source table reads its input from
+        /// `{table}.json` and each sink table is checked against `{table}.expected.json`, both
+        /// as JSON arrays of rows
+        #[arg(long)]
+        fixtures: PathBuf,
+
+        /// If set, rows must be written to each sink in the expected order
+        #[arg(long)]
+        ordered: bool,
+
+        /// How many seconds to wait for the pipeline to finish
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+
+        /// If set, processing time is simulated: it fast-forwards to the event time of each row
+        /// rather than following the system clock, so that pipelines with long windows can be
+        /// tested in seconds
+        #[arg(long)]
+        simulated_time: bool,
+    },
+
+    /// Runs the Nexmark benchmark queries in-process, reporting the throughput and latency of
+    /// each
+    Benchmark {
+        #[command(flatten)]
+        args: BenchmarkArgs,
+    },
+
+    /// Inspects the state in a checkpoint, reading it directly from the checkpoint storage.
+    /// Lists the checkpoint's operators and their tables, or dumps a single table.
+    State {
+        /// Id of the job the checkpoint belongs to
+        job_id: String,
+
+        /// Epoch of the checkpoint
+        epoch: u32,
+
+        /// Operator whose table should be dumped
+        #[arg(long, requires = "table")]
+        operator: Option<String>,
+
+        /// Table to dump, printed as newline-delimited JSON
+        #[arg(long, requires = "operator")]
+        table: Option<String>,
+
+        /// Only dump the rows for this key, given as the values of the table's key columns
+        /// joined with commas
+        #[arg(long, requires = "table")]
+        key: Option<String>,
+
+        /// Write the table to this path as Parquet, rather than printing it
+        #[arg(long, requires = "table")]
+        output: Option<PathBuf>,
+    },
+
+    /// Manages the pipelines of a running Arroyo cluster through its API
+    Pipeline {
+        #[command(flatten)]
+        api: ApiArgs,
+
+        #[command(subcommand)]
+        command: PipelineCommand,
+    },
+
+    /// Exports pipelines and the resources they use from an Arroyo cluster as a YAML bundle
+    Export {
+        #[command(flatten)]
+        api: ApiArgs,
+
+        /// Only export this pipeline, along with the resources it uses
+        #[arg(long)]
+        pipeline: Option<String>,
+
+        /// Write the bundle to this path, rather than printing it
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Applies a YAML bundle to an Arroyo cluster, creating, updating, and replacing pipelines
+    /// and the resources they use to match it
+    Apply {
+        /// Path to the bundle
+        file: PathBuf,
+
+        #[command(flatten)]
+        api: ApiArgs,
+
+        /// Delete pipelines that aren't in the bundle
+        #[arg(long)]
+        prune: bool,
+
+        /// Only print the changes that would be made
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -116,21 +226,136 @@ async fn main() {
         Commands::Node { .. } => {
             start_node().await;
         }
+        Commands::Test {
+            query,
+            fixtures,
+            ordered,
+            timeout,
+            simulated_time,
+        } => match run_test(
+            query,
+            fixtures,
+            *ordered,
+            Duration::from_secs(*timeout),
+            *simulated_time,
+        )
+        .await
+        {
+            Ok(true) => {}
+            Ok(false) => exit(1),
+            Err(e) => {
+                error!("{:?}", e);
+                exit(1);
+            }
+        },
+        Commands::Benchmark { args } => {
+            if let Err(e) = benchmark::run_benchmark(args).await {
+                error!("{:?}", e);
+                exit(1);
+            }
+        }
+        Commands::State {
+            job_id,
+            epoch,
+            operator,
+            table,
+            key,
+            output,
+        } => {
+            let result = match (operator, table) {
+                (Some(operator), Some(table)) => {
+                    dump_state_table(job_id, *epoch, operator, table, key.as_deref(), output).await
+                }
+                _ => list_state_tables(job_id, *epoch).await,
+            };
+
+            if let Err(e) = result {
+                error!("{:?}", e);
+                exit(1);
+            }
+        }
+        Commands::Pipeline { api, command } => {
+            if let Err(e) = remote::run_pipeline_command(api, command).await {
+                error!("{:?}", e);
+                exit(1);
+            }
+        }
+        Commands::Export {
+            api,
+            pipeline,
+            output,
+        } => {
+            if let Err(e) = remote::export_bundle(api, pipeline.as_deref(), output).await {
+                error!("{:?}", e);
+                exit(1);
+            }
+        }
+        Commands::Apply {
+            file,
+            api,
+            prune,
+            dry_run,
+        } => {
+            if let Err(e) = remote::apply_bundle(file, api, *prune, *dry_run).await {
+                error!("{:?}", e);
+                exit(1);
+            }
+        }
     };
 }
 
+async fn list_state_tables(job_id: &str, epoch: u32) -> anyhow::Result<()> {
+    for operator in arroyo_state::inspect::describe_checkpoint(job_id, epoch).await? {
+        println!("{}", operator.operator_id);
+        for table in operator.tables {
+            println!(
+                "  {} ({}, {} files): {}",
+                table.name,
+                table.table_type,
+                table.files.len(),
+                table.description
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn dump_state_table(
+    job_id: &str,
+    epoch: u32,
+    operator: &str,
+    table: &str,
+    key: Option<&str>,
+    output: &Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let batches = arroyo_state::inspect::read_table(job_id, epoch, operator, table, key).await?;
+
+    match output {
+        Some(path) => {
+            fs::write(path, arroyo_state::inspect::to_parquet(&batches)?)?;
+            info!(
+                "wrote {} rows to {}",
+                batches.iter().map(|b| b.num_rows()).sum::<usize>(),
+                path.to_string_lossy()
+            );
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            for batch in &batches {
+                std::io::Write::write_all(
+                    &mut stdout,
+                    &arroyo_state::inspect::to_json_lines(batch)?,
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 async fn pg_pool() -> Pool {
     let config = &config().database.postgres;
-    let mut cfg = deadpool_postgres::Config::new();
-    cfg.dbname = Some(config.database_name.clone());
-    cfg.host = Some(config.host.clone());
-    cfg.port = Some(config.port);
-    cfg.user = Some(config.user.clone());
-    cfg.password = Some((*config.password).clone());
-    cfg.manager = Some(ManagerConfig {
-        recycling_method: RecyclingMethod::Fast,
-    });
-    let pool = cfg
+    let pool = arroyo_api::postgres_pool_config()
         .create_pool(Some(deadpool_postgres::Runtime::Tokio1), NoTls)
         .unwrap_or_else(|e| {
             error!("Unable to connect to database {:?}: {:?}", config, e);
@@ -192,6 +417,13 @@ fn sqlite_connection() -> rusqlite::Connection {
             [&uuid],
         )
         .expect("Unable to write to sqlite database");
+    } else if let Err(e) = sqlite_migrations::migrations::runner().run(&mut conn) {
+        // apply any migrations added since the database was created
+        panic!(
+            "Failed to migrate database at {}: {}",
+            path.to_string_lossy(),
+            e
+        );
     }
 
     let mut statement = conn.prepare("select id from cluster_info").unwrap();
@@ -375,6 +607,83 @@ async fn start_worker() {
     Shutdown::handle_shutdown(shutdown.wait_for_shutdown(Duration::from_secs(30)).await);
 }
 
+fn read_fixture(path: &Path) -> anyhow::Result<Vec<serde_json::Value>> {
+    let data = fs::read_to_string(path)
+        .map_err(|e| anyhow!("could not read {}: {}", path.to_string_lossy(), e))?;
+    serde_json::from_str(&data).map_err(|e| {
+        anyhow!(
+            "{} is not a JSON array of rows: {}",
+            path.to_string_lossy(),
+            e
+        )
+    })
+}
+
+async fn run_test(
+    query: &Path,
+    fixtures: &Path,
+    ordered: bool,
+    timeout: Duration,
+    simulated_time: bool,
+) -> anyhow::Result<bool> {
+    let query = fs::read_to_string(query)
+        .map_err(|e| anyhow!("could not read query {}: {}", query.to_string_lossy(), e))?;
+
+    let mut inputs = BTreeMap::new();
+    let mut expected = BTreeMap::new();
+    for entry in fs::read_dir(fixtures)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+
+        if let Some(table) = name.strip_suffix(".expected.json") {
+            expected.insert(table.to_string(), read_fixture(&path)?);
+        } else if let Some(table) = name.strip_suffix(".json") {
+            inputs.insert(table.to_string(), read_fixture(&path)?);
+        }
+    }
+
+    let compiled = arroyo_df::parse_and_get_arrow_program(
+        query,
+        ArroyoSchemaProvider::new(),
+        SqlConfig {
+            default_parallelism: 1,
+        },
+    )
+    .await?;
+
+    let result = fixture_test::run(FixtureTest {
+        program: compiled.program,
+        inputs,
+        expected,
+        ordered,
+        timeout,
+        simulated_time,
+    })
+    .await?;
+
+    for sink in &result.sinks {
+        if sink.passed {
+            println!("PASS {}", sink.name);
+            continue;
+        }
+
+        println!("FAIL {}", sink.name);
+        if let Some(error) = &sink.error {
+            println!("  {}", error);
+        }
+        for row in &sink.missing {
+            println!("  - {}", row);
+        }
+        for row in &sink.unexpected {
+            println!("  + {}", row);
+        }
+    }
+
+    Ok(result.passed)
+}
+
 async fn start_node() {
     let shutdown = Shutdown::new("node");
     let id = arroyo_node::start_server(shutdown.guard("node")).await;
@@ -0,0 +1,3 @@
+-- Currency conversion: converts each bid's price from dollars to euros
+SELECT auction, bidder, 0.908 * price AS price, datetime, extra
+FROM bid;
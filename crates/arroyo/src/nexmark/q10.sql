@@ -0,0 +1,6 @@
+-- Log to file system: adds the date and hour that bids would be partitioned by when written to
+-- files; the benchmark discards them rather than writing them
+SELECT auction, bidder, price, datetime, extra,
+    date_trunc('day', datetime) AS dt,
+    date_part('hour', datetime) AS hm
+FROM bid;
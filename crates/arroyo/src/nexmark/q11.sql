@@ -0,0 +1,4 @@
+-- User sessions: the number of bids each bidder makes in each session of activity
+SELECT bidder, count(*) AS bid_count, session(interval '10 seconds') AS window
+FROM bid
+GROUP BY bidder, window;
@@ -0,0 +1,5 @@
+-- Processing time windows: the number of bids each bidder makes in each ten-second window of
+-- processing time
+SELECT bidder, count(*) AS bid_count, proctime_tumble(interval '10 seconds') AS window
+FROM bid
+GROUP BY bidder, window;
@@ -0,0 +1,5 @@
+-- Bounded side input join. Bounded tables to join against aren't supported, so the side input's
+-- value is derived from the key it would be looked up by.
+SELECT auction, bidder, price, datetime,
+    concat('side-input-', CAST(auction % 10000 AS TEXT)) AS value
+FROM bid;
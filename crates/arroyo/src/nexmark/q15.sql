@@ -0,0 +1,18 @@
+-- Bidding statistics report: the number of bids, bidders, and auctions by price range. Reported
+-- for ten-second windows rather than days, so that results are produced while benchmarking.
+SELECT
+    tumble(interval '10 seconds') AS window,
+    count(*) AS total_bids,
+    sum(CASE WHEN price < 10000 THEN 1 ELSE 0 END) AS rank1_bids,
+    sum(CASE WHEN price >= 10000 AND price < 1000000 THEN 1 ELSE 0 END) AS rank2_bids,
+    sum(CASE WHEN price >= 1000000 THEN 1 ELSE 0 END) AS rank3_bids,
+    count(DISTINCT bidder) AS total_bidders,
+    count(DISTINCT CASE WHEN price < 10000 THEN bidder END) AS rank1_bidders,
+    count(DISTINCT CASE WHEN price >= 10000 AND price < 1000000 THEN bidder END) AS rank2_bidders,
+    count(DISTINCT CASE WHEN price >= 1000000 THEN bidder END) AS rank3_bidders,
+    count(DISTINCT auction) AS total_auctions,
+    count(DISTINCT CASE WHEN price < 10000 THEN auction END) AS rank1_auctions,
+    count(DISTINCT CASE WHEN price >= 10000 AND price < 1000000 THEN auction END) AS rank2_auctions,
+    count(DISTINCT CASE WHEN price >= 1000000 THEN auction END) AS rank3_auctions
+FROM bid
+GROUP BY window;
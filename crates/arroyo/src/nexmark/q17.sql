@@ -0,0 +1,15 @@
+-- Auction statistics report: the number of bids on each auction by price range, and statistics
+-- of their prices, for ten-second windows rather than days
+SELECT
+    auction,
+    tumble(interval '10 seconds') AS window,
+    count(*) AS total_bids,
+    sum(CASE WHEN price < 10000 THEN 1 ELSE 0 END) AS rank1_bids,
+    sum(CASE WHEN price >= 10000 AND price < 1000000 THEN 1 ELSE 0 END) AS rank2_bids,
+    sum(CASE WHEN price >= 1000000 THEN 1 ELSE 0 END) AS rank3_bids,
+    min(price) AS min_price,
+    max(price) AS max_price,
+    avg(price) AS avg_price,
+    sum(price) AS sum_price
+FROM bid
+GROUP BY auction, window;
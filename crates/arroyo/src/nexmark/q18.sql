@@ -0,0 +1,14 @@
+-- Find last bid: the last bid each bidder made on each auction in each ten-second window, as
+-- ranking rows outside of windows isn't supported
+SELECT auction, bidder, price, datetime, window
+FROM (
+    SELECT auction, bidder, price, datetime, window, ROW_NUMBER() OVER (
+        PARTITION BY window, bidder, auction
+        ORDER BY datetime DESC) AS row_num
+    FROM (
+        SELECT auction, bidder, price, datetime, tumble(interval '10 seconds') AS window
+        FROM bid
+        GROUP BY auction, bidder, price, datetime, window
+    )
+)
+WHERE row_num <= 1;
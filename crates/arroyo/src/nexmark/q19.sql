@@ -0,0 +1,14 @@
+-- Auction top-10 price: the ten highest bids on each auction in each ten-second window, as
+-- ranking rows outside of windows isn't supported
+SELECT auction, bidder, price, row_num, window
+FROM (
+    SELECT auction, bidder, price, window, ROW_NUMBER() OVER (
+        PARTITION BY window, auction
+        ORDER BY price DESC) AS row_num
+    FROM (
+        SELECT auction, bidder, max(price) AS price, tumble(interval '10 seconds') AS window
+        FROM bid
+        GROUP BY auction, bidder, window
+    )
+)
+WHERE row_num <= 10;
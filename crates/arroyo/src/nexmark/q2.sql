@@ -0,0 +1,4 @@
+-- Selection: finds the bids on a set of auctions
+SELECT auction, price
+FROM bid
+WHERE auction % 123 = 0;
@@ -0,0 +1,7 @@
+-- Expand bid with auction: joins each bid with the auction it's for, in one category
+SELECT B.auction, B.bidder, B.price, B.channel, B.url, B.datetime,
+    A.item_name, A.description, A.initial_bid, A.reserve, A.datetime AS auction_datetime,
+    A.expires, A.seller, A.category
+FROM bid AS B
+JOIN auction AS A ON B.auction = A.id
+WHERE A.category = 10;
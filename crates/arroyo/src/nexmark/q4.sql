@@ -0,0 +1,12 @@
+-- Average price for a category: the average winning bid of the auctions in each category.
+-- Auctions are closed by ten-second windows rather than by their expiry, as aggregates of
+-- updating aggregates aren't supported.
+SELECT category, avg(final) AS avg_final, window
+FROM (
+    SELECT A.id, A.category, max(B.price) AS final, tumble(interval '10 seconds') AS window
+    FROM auction AS A
+    JOIN bid AS B ON A.id = B.auction
+    WHERE B.datetime BETWEEN A.datetime AND A.expires
+    GROUP BY A.id, A.category, window
+)
+GROUP BY category, window;
@@ -0,0 +1,13 @@
+-- Hot items: the auction with the most bids in each sliding window
+SELECT auction, num, window
+FROM (
+    SELECT auction, num, window, ROW_NUMBER() OVER (
+        PARTITION BY window
+        ORDER BY num DESC) AS row_num
+    FROM (
+        SELECT auction, count(*) AS num, hop(interval '2 seconds', interval '10 seconds') AS window
+        FROM bid
+        GROUP BY auction, window
+    )
+)
+WHERE row_num <= 1;
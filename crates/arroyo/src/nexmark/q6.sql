@@ -0,0 +1,11 @@
+-- Average selling price by seller. Over windows of the last ten auctions aren't supported, so
+-- this averages the winning bids of each seller's auctions closed in each ten-second window.
+SELECT seller, avg(final) AS avg_final, window
+FROM (
+    SELECT A.id, A.seller, max(B.price) AS final, tumble(interval '10 seconds') AS window
+    FROM auction AS A
+    JOIN bid AS B ON A.id = B.auction
+    WHERE B.datetime BETWEEN A.datetime AND A.expires
+    GROUP BY A.id, A.seller, window
+)
+GROUP BY seller, window;
@@ -0,0 +1,13 @@
+-- Highest bid: the bid with the highest price in each ten-second window
+SELECT auction, bidder, price, window
+FROM (
+    SELECT auction, bidder, price, window, ROW_NUMBER() OVER (
+        PARTITION BY window
+        ORDER BY price DESC) AS row_num
+    FROM (
+        SELECT auction, bidder, max(price) AS price, tumble(interval '10 seconds') AS window
+        FROM bid
+        GROUP BY auction, bidder, window
+    )
+)
+WHERE row_num = 1;
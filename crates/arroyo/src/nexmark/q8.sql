@@ -0,0 +1,14 @@
+-- Monitor new users: people who created an auction in the same ten-second window they
+-- registered in
+SELECT P.id, P.name, P.window
+FROM (
+    SELECT id, name, tumble(interval '10 seconds') AS window
+    FROM person
+    GROUP BY id, name, window
+) P
+JOIN (
+    SELECT seller, tumble(interval '10 seconds') AS window
+    FROM auction
+    GROUP BY seller, window
+) A
+ON P.id = A.seller AND P.window.start = A.window.start;
@@ -0,0 +1,8 @@
+-- Winning bids: the highest valid bid on each auction. Auctions are closed by ten-second windows
+-- rather than by their expiry, as ranking the rows of an unwindowed join isn't supported.
+SELECT A.id, A.seller, A.category, A.reserve, max(B.price) AS final,
+    tumble(interval '10 seconds') AS window
+FROM auction AS A
+JOIN bid AS B ON A.id = B.auction
+WHERE B.datetime BETWEEN A.datetime AND A.expires AND B.price >= A.reserve
+GROUP BY A.id, A.seller, A.category, A.reserve, window;
@@ -0,0 +1,702 @@
+//! Commands that manage a running Arroyo cluster through its REST API.
+//!
+//! The API endpoint and key are taken from flags or environment variables, or from a named
+//! profile in `$CONFIG_DIR/arroyo/cli.toml`:
+//!
+//! ```toml
+//! default-profile = "prod"
+//!
+//! [profiles.prod]
+//! endpoint = "https://arroyo.example.com"
+//! api-key = "ak_..."
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use arroyo_rpc::api_types::bundles::{
+    BundleAction, BundleApplyResult, BundleApplyStatus, BundleChange, BundleResourceType,
+};
+use arroyo_rpc::api_types::checkpoints::Checkpoint;
+use arroyo_rpc::api_types::pipelines::{
+    Job, JobLogLevel, JobLogMessage, Pipeline, PipelinePatch, PipelinePost, PipelineRestart,
+    StopType,
+};
+use arroyo_rpc::api_types::udfs::{Udf, UdfLanguage};
+use arroyo_rpc::api_types::{
+    CheckpointCollection, JobCollection, JobLogMessageCollection, OperatorMetricGroupCollection,
+    PipelineCollection,
+};
+use clap::{Subcommand, ValueEnum};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+const DEFAULT_ENDPOINT: &str = "http://localhost:8000";
+const ERRORS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const BUNDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(clap::Args)]
+pub struct ApiArgs {
+    /// Profile in the CLI config file to take the API endpoint and key from
+    #[arg(long, env = "ARROYO_PROFILE", global = true)]
+    profile: Option<String>,
+
+    /// URL of the Arroyo API server [default: http://localhost:8000]
+    #[arg(long, env = "ARROYO_ENDPOINT", global = true)]
+    endpoint: Option<String>,
+
+    /// API key to authenticate with
+    #[arg(long, env = "ARROYO_API_KEY", global = true)]
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct CliConfig {
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(rename_all = "kebab-case")]
+struct Profile {
+    endpoint: Option<String>,
+    api_key: Option<String>,
+}
+
+fn cli_config() -> anyhow::Result<CliConfig> {
+    let Some(path) = dirs::config_dir().map(|d| d.join("arroyo/cli.toml")) else {
+        return Ok(CliConfig::default());
+    };
+
+    if !path.exists() {
+        return Ok(CliConfig::default());
+    }
+
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+    toml::from_str(&contents).with_context(|| format!("invalid {}", path.to_string_lossy()))
+}
+
+impl ApiArgs {
+    pub fn client(&self) -> anyhow::Result<ApiClient> {
+        let config = cli_config()?;
+
+        let profile = match self.profile.as_ref().or(config.default_profile.as_ref()) {
+            Some(name) => config
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("no profile named '{}' in the CLI config", name))?,
+            None => Profile::default(),
+        };
+
+        Ok(ApiClient {
+            client: reqwest::Client::new(),
+            endpoint: self
+                .endpoint
+                .clone()
+                .or(profile.endpoint)
+                .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string()),
+            api_key: self.api_key.clone().or(profile.api_key),
+        })
+    }
+}
+
+pub struct ApiClient {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: Option<String>,
+}
+
+impl ApiClient {
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/api/v1/{}", self.endpoint.trim_end_matches('/'), path);
+        let request = self.client.request(method, url);
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let resp = request
+            .send()
+            .await
+            .with_context(|| format!("failed to connect to {}", self.endpoint))?;
+
+        if resp.status().is_success() {
+            Ok(resp)
+        } else {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            // errors are returned as JSON with a message
+            let message = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(String::from))
+                .unwrap_or(body);
+            bail!("request failed with {}: {}", status, message)
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        Ok(self
+            .send(self.request(reqwest::Method::GET, path))
+            .await?
+            .json()
+            .await?)
+    }
+
+    async fn post<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> anyhow::Result<T> {
+        Ok(self
+            .send(self.request(reqwest::Method::POST, path).json(body))
+            .await?
+            .json()
+            .await?)
+    }
+
+    async fn patch_pipeline(&self, id: &str, patch: &PipelinePatch) -> anyhow::Result<Pipeline> {
+        Ok(self
+            .send(
+                self.request(reqwest::Method::PATCH, &format!("pipelines/{}", id))
+                    .json(patch),
+            )
+            .await?
+            .json()
+            .await?)
+    }
+
+    /// The given job of the pipeline, or its current job
+    async fn job_id(&self, pipeline_id: &str, job_id: &Option<String>) -> anyhow::Result<String> {
+        if let Some(job_id) = job_id {
+            return Ok(job_id.clone());
+        }
+
+        let jobs: JobCollection = self.get(&format!("pipelines/{}/jobs", pipeline_id)).await?;
+        jobs.data
+            .into_iter()
+            .next()
+            .map(|j| j.id)
+            .ok_or_else(|| anyhow!("pipeline {} has no jobs", pipeline_id))
+    }
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+pub enum StopMode {
+    /// Take a final checkpoint, then stop
+    Checkpoint,
+    /// Stop sources and let the data in flight drain through the pipeline
+    Graceful,
+    /// Stop immediately, without a final checkpoint
+    Immediate,
+    /// Kill the workers
+    Force,
+}
+
+impl From<StopMode> for StopType {
+    fn from(val: StopMode) -> Self {
+        match val {
+            StopMode::Checkpoint => StopType::Checkpoint,
+            StopMode::Graceful => StopType::Graceful,
+            StopMode::Immediate => StopType::Immediate,
+            StopMode::Force => StopType::Force,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum PipelineCommand {
+    /// Creates and starts a pipeline
+    Create {
+        /// Path to a file containing the pipeline's SQL query
+        query: PathBuf,
+
+        /// Name of the pipeline
+        #[arg(long)]
+        name: String,
+
+        #[arg(long, default_value_t = 1)]
+        parallelism: u64,
+
+        /// How often the pipeline checkpoints, in seconds
+        #[arg(long)]
+        checkpoint_interval: Option<u64>,
+
+        /// Paths to UDF definitions for the pipeline; files ending in `.js` are JavaScript UDFs,
+        /// and all others Rust UDFs
+        #[arg(long)]
+        udf: Vec<PathBuf>,
+
+        #[arg(long)]
+        namespace: Option<String>,
+    },
+
+    /// Lists pipelines
+    List {},
+
+    /// Prints a pipeline as JSON
+    Get { id: String },
+
+    /// Starts a stopped pipeline
+    Start { id: String },
+
+    /// Stops a pipeline
+    Stop {
+        id: String,
+
+        #[arg(long, value_enum, default_value_t = StopMode::Checkpoint)]
+        mode: StopMode,
+    },
+
+    /// Restarts a pipeline's job
+    Restart {
+        id: String,
+
+        /// Restart without waiting for a final checkpoint
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Deletes a stopped pipeline
+    Delete { id: String },
+
+    /// Lists a pipeline's jobs
+    Jobs { id: String },
+
+    /// Lists the checkpoints of a pipeline's job
+    Checkpoints {
+        id: String,
+
+        /// Job to list the checkpoints of; defaults to the pipeline's current job
+        #[arg(long)]
+        job: Option<String>,
+    },
+
+    /// Triggers a savepoint of a running pipeline, which is retained by checkpoint cleanup
+    Savepoint {
+        id: String,
+
+        #[arg(long)]
+        job: Option<String>,
+    },
+
+    /// Prints the errors reported by a pipeline's job
+    Errors {
+        id: String,
+
+        #[arg(long)]
+        job: Option<String>,
+
+        /// Number of recent errors to print
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+
+        /// Keep printing new errors as they're reported
+        #[arg(short, long)]
+        follow: bool,
+    },
+
+    /// Prints the latest metrics of each operator of a pipeline's job
+    Metrics {
+        id: String,
+
+        #[arg(long)]
+        job: Option<String>,
+    },
+}
+
+fn format_time(micros: u64) -> String {
+    time::OffsetDateTime::from_unix_timestamp_nanos(micros as i128 * 1000)
+        .ok()
+        .and_then(|t| {
+            t.format(&time::format_description::well_known::Rfc3339)
+                .ok()
+        })
+        .unwrap_or_else(|| micros.to_string())
+}
+
+fn stop_patch(stop: StopType) -> PipelinePatch {
+    PipelinePatch {
+        parallelism: None,
+        checkpoint_interval_micros: None,
+        stop: Some(stop),
+        priority: None,
+        worker_pod: None,
+        freshness_slo: None,
+        restart_strategy: None,
+    }
+}
+
+fn print_pipeline_row(p: &Pipeline) {
+    println!(
+        "{:<24} {:<30} {:<12} {}",
+        p.id,
+        p.name,
+        p.action_text,
+        format_time(p.created_at)
+    );
+}
+
+fn print_error(e: &JobLogMessage) {
+    let level = match e.level {
+        JobLogLevel::Info => "INFO",
+        JobLogLevel::Warn => "WARN",
+        JobLogLevel::Error => "ERROR",
+    };
+    let source = match (&e.operator_id, e.task_index) {
+        (Some(op), Some(idx)) => format!(" [{}-{}]", op, idx),
+        (Some(op), None) => format!(" [{}]", op),
+        _ => String::new(),
+    };
+    println!(
+        "{} {:<5}{} {}",
+        format_time(e.created_at),
+        level,
+        source,
+        e.message
+    );
+    if !e.details.is_empty() {
+        for line in e.details.lines() {
+            println!("    {}", line);
+        }
+    }
+}
+
+pub async fn run_pipeline_command(api: &ApiArgs, command: &PipelineCommand) -> anyhow::Result<()> {
+    let client = api.client()?;
+
+    match command {
+        PipelineCommand::Create {
+            query,
+            name,
+            parallelism,
+            checkpoint_interval,
+            udf,
+            namespace,
+        } => {
+            let query = fs::read_to_string(query)
+                .with_context(|| format!("failed to read {}", query.to_string_lossy()))?;
+
+            let udfs = udf
+                .iter()
+                .map(|path| {
+                    let language = match path.extension().and_then(|e| e.to_str()) {
+                        Some("js") => UdfLanguage::JavaScript,
+                        _ => UdfLanguage::Rust,
+                    };
+                    Ok(Udf {
+                        definition: fs::read_to_string(path).with_context(|| {
+                            format!("failed to read {}", path.to_string_lossy())
+                        })?,
+                        language,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            let post = PipelinePost {
+                name: name.clone(),
+                query,
+                udfs: Some(udfs),
+                udf_versions: None,
+                preview: None,
+                parallelism: *parallelism,
+                checkpoint_interval_micros: checkpoint_interval
+                    .map(|s| Duration::from_secs(s).as_micros() as u64),
+                namespace: namespace.clone(),
+                priority: None,
+                worker_pod: None,
+                freshness_slo: None,
+                restart_strategy: None,
+                batching: None,
+                recording: None,
+                replay: None,
+                bootstrap: None,
+                resource_classes: None,
+            };
+
+            let pipeline: Pipeline = client.post("pipelines", &post).await?;
+            info!("created pipeline {}", pipeline.id);
+            println!("{}", pipeline.id);
+        }
+        PipelineCommand::List {} => {
+            let mut starting_after: Option<String> = None;
+            loop {
+                let mut path = "pipelines?limit=100".to_string();
+                if let Some(after) = &starting_after {
+                    path.push_str(&format!("&starting_after={}", after));
+                }
+
+                let page: PipelineCollection = client.get(&path).await?;
+                for p in &page.data {
+                    print_pipeline_row(p);
+                }
+
+                match page.data.last() {
+                    Some(last) if page.has_more => starting_after = Some(last.id.clone()),
+                    _ => break,
+                }
+            }
+        }
+        PipelineCommand::Get { id } => {
+            let pipeline: serde_json::Value = client.get(&format!("pipelines/{}", id)).await?;
+            println!("{}", serde_json::to_string_pretty(&pipeline)?);
+        }
+        PipelineCommand::Start { id } => {
+            client
+                .patch_pipeline(id, &stop_patch(StopType::None))
+                .await?;
+            info!("starting pipeline {}", id);
+        }
+        PipelineCommand::Stop { id, mode } => {
+            client
+                .patch_pipeline(id, &stop_patch((*mode).into()))
+                .await?;
+            info!("stopping pipeline {}", id);
+        }
+        PipelineCommand::Restart { id, force } => {
+            let _: Pipeline = client
+                .post(
+                    &format!("pipelines/{}/restart", id),
+                    &PipelineRestart {
+                        force: Some(*force),
+                    },
+                )
+                .await?;
+            info!("restarting pipeline {}", id);
+        }
+        PipelineCommand::Delete { id } => {
+            client
+                .send(client.request(reqwest::Method::DELETE, &format!("pipelines/{}", id)))
+                .await?;
+            info!("deleted pipeline {}", id);
+        }
+        PipelineCommand::Jobs { id } => {
+            let jobs: JobCollection = client.get(&format!("pipelines/{}/jobs", id)).await?;
+            for Job {
+                id,
+                state,
+                start_time,
+                failure_message,
+                ..
+            } in jobs.data
+            {
+                println!(
+                    "{:<24} {:<12} {:<30} {}",
+                    id,
+                    state,
+                    start_time.map(format_time).unwrap_or_default(),
+                    failure_message.unwrap_or_default()
+                );
+            }
+        }
+        PipelineCommand::Checkpoints { id, job } => {
+            let job_id = client.job_id(id, job).await?;
+            let checkpoints: CheckpointCollection = client
+                .get(&format!("pipelines/{}/jobs/{}/checkpoints", id, job_id))
+                .await?;
+
+            for Checkpoint {
+                epoch,
+                start_time,
+                finish_time,
+                ..
+            } in checkpoints.data
+            {
+                let duration = finish_time
+                    .map(|f| format!("{:.1}s", (f.saturating_sub(start_time)) as f64 / 1e6))
+                    .unwrap_or_else(|| "in progress".to_string());
+                println!("{:<8} {:<30} {}", epoch, format_time(start_time), duration);
+            }
+        }
+        PipelineCommand::Savepoint { id, job } => {
+            let job_id = client.job_id(id, job).await?;
+            client
+                .send(client.request(
+                    reqwest::Method::POST,
+                    &format!("pipelines/{}/jobs/{}/savepoints", id, job_id),
+                ))
+                .await?;
+            info!("triggered savepoint of job {}", job_id);
+        }
+        PipelineCommand::Errors {
+            id,
+            job,
+            limit,
+            follow,
+        } => {
+            let job_id = client.job_id(id, job).await?;
+            let path = format!("pipelines/{}/jobs/{}/errors", id, job_id);
+
+            // errors are returned newest first
+            let errors: JobLogMessageCollection =
+                client.get(&format!("{}?limit={}", path, limit)).await?;
+            for e in errors.data.iter().rev() {
+                print_error(e);
+            }
+
+            if *follow {
+                let mut last_seen = errors.data.first().map(|e| e.id.clone());
+                loop {
+                    tokio::time::sleep(ERRORS_POLL_INTERVAL).await;
+
+                    let errors: JobLogMessageCollection =
+                        client.get(&format!("{}?limit=100", path)).await?;
+                    let new: Vec<_> = errors
+                        .data
+                        .iter()
+                        .take_while(|e| last_seen.as_ref() != Some(&e.id))
+                        .collect();
+
+                    for e in new.iter().rev() {
+                        print_error(e);
+                    }
+
+                    if let Some(e) = new.first() {
+                        last_seen = Some(e.id.clone());
+                    }
+                }
+            }
+        }
+        PipelineCommand::Metrics { id, job } => {
+            let job_id = client.job_id(id, job).await?;
+            let groups: OperatorMetricGroupCollection = client
+                .get(&format!(
+                    "pipelines/{}/jobs/{}/operator_metric_groups",
+                    id, job_id
+                ))
+                .await?;
+
+            for op in groups.data {
+                println!("{}", op.operator_id);
+                for group in op.metric_groups {
+                    // sum the most recent value across subtasks
+                    let total: f64 = group
+                        .subtasks
+                        .iter()
+                        .filter_map(|s| s.metrics.last())
+                        .map(|m| m.value)
+                        .sum();
+                    let name = serde_json::to_value(group.name)?;
+                    println!("  {:<32} {:.2}", name.as_str().unwrap_or_default(), total);
+                }
+                if let Some(skew) = op.skew {
+                    println!("  {:<32} {:.2}", "skew", skew);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn export_bundle(
+    api: &ApiArgs,
+    pipeline: Option<&str>,
+    output: &Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let client = api.client()?;
+
+    let mut request = client.request(reqwest::Method::GET, "bundles/export");
+    if let Some(pipeline) = pipeline {
+        request = request.query(&[("pipeline_id", pipeline)]);
+    }
+
+    let bundle = client.send(request).await?.text().await?;
+
+    match output {
+        Some(path) => {
+            fs::write(path, bundle)?;
+            info!("wrote bundle to {}", path.to_string_lossy());
+        }
+        None => print!("{}", bundle),
+    }
+    Ok(())
+}
+
+pub async fn apply_bundle(
+    file: &Path,
+    api: &ApiArgs,
+    prune: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let client = api.client()?;
+
+    let bundle = fs::read_to_string(file)
+        .map_err(|e| anyhow!("failed to read {}: {}", file.to_string_lossy(), e))?;
+
+    let mut result: BundleApplyResult = client
+        .send(
+            client
+                .request(reqwest::Method::POST, "bundles/apply")
+                .query(&[("prune", prune), ("dry_run", dry_run)])
+                .header(reqwest::header::CONTENT_TYPE, "application/yaml")
+                .body(bundle),
+        )
+        .await?
+        .json()
+        .await?;
+
+    // changes are made in the background, so poll the apply until it finishes, printing its
+    // changes as they're made
+    let mut printed = 0;
+    loop {
+        for change in &result.changes[printed..] {
+            print_bundle_change(change);
+        }
+        printed = result.changes.len();
+
+        if result.status != BundleApplyStatus::Running {
+            break;
+        }
+        let Some(id) = &result.id else {
+            break;
+        };
+
+        tokio::time::sleep(BUNDLE_POLL_INTERVAL).await;
+        result = client.get(&format!("bundles/applies/{}", id)).await?;
+    }
+
+    if result.dry_run {
+        println!("dry run; no changes were made");
+    }
+    if result.status == BundleApplyStatus::Failed {
+        bail!(
+            "failed to apply bundle: {}",
+            result.error.as_deref().unwrap_or("unknown error")
+        );
+    }
+    Ok(())
+}
+
+fn print_bundle_change(change: &BundleChange) {
+    let action = match change.action {
+        BundleAction::Create => "create",
+        BundleAction::Update => "update",
+        BundleAction::Replace => "replace",
+        BundleAction::Delete => "delete",
+        BundleAction::Unchanged => "unchanged",
+    };
+    let resource = match change.resource_type {
+        BundleResourceType::ConnectionProfile => "connection profile",
+        BundleResourceType::ConnectionTable => "connection table",
+        BundleResourceType::Udf => "udf",
+        BundleResourceType::Pipeline => "pipeline",
+    };
+    print!("{:<10} {} '{}'", action, resource, change.name);
+    if let Some(id) = &change.id {
+        print!(" ({})", id);
+    }
+    if let Some(message) = &change.message {
+        print!(": {}", message);
+    }
+    println!();
+}